@@ -5,8 +5,27 @@ CREATE USER datawriter;
 ALTER USER datawriter WITH PASSWORD '123321';
 GRANT ALL PRIVILEGES ON DATABASE mydb TO datawriter;
 --
+-- trigram indexes for the ILIKE search filters
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+--
+-- isolated customer orgs - every user belongs to one tenant
+-- (tenant 1 is the default tenant used when TENANT_MODE=off)
+CREATE TABLE tenants (
+    id INT GENERATED BY DEFAULT AS IDENTITY,
+    slug VARCHAR(64) NOT NULL,
+    name TEXT NOT NULL,
+    state INT DEFAULT 0 NOT NULL,
+    created_at timestamp with time zone DEFAULT timezone('UTC'::text, now()) NOT NULL,
+    PRIMARY KEY(id)
+);
+ALTER TABLE tenants OWNER TO datawriter;
+ALTER TABLE ONLY tenants ADD CONSTRAINT tenants_slug_key UNIQUE (slug);
+INSERT INTO tenants (id, slug, name) VALUES (1, 'default', 'default');
+SELECT setval(pg_get_serial_sequence('tenants', 'id'), 1);
+
 CREATE TABLE users (
     id INT GENERATED ALWAYS AS IDENTITY,
+    tenant_id INT DEFAULT 1 NOT NULL,
     email TEXT NOT NULL,
     password character varying(512) NOT NULL,
     state INT DEFAULT 0 NOT NULL,
@@ -14,16 +33,39 @@ CREATE TABLE users (
     created_at timestamp with time zone DEFAULT timezone('UTC'::text, now()) NOT NULL,
     updated_at timestamp with time zone,
     role character varying(20) NOT NULL,
-    PRIMARY KEY(id)
+    -- bumped on every update for optimistic concurrency
+    version INT DEFAULT 1 NOT NULL,
+    -- max users_data bytes (NULL = USER_DATA_QUOTA_BYTES, 0 = unlimited)
+    quota_bytes BIGINT,
+    -- locale for the user's emails (like en or pt-BR)
+    locale VARCHAR(16) DEFAULT 'en' NOT NULL,
+    -- scheme of the password hash (NULL = not recorded yet)
+    password_scheme VARCHAR(128),
+    -- an admin forced a password reset (1 = logins are blocked
+    -- until the user changes their password with an otp)
+    password_reset_required INT DEFAULT 0 NOT NULL,
+    PRIMARY KEY(id),
+    CONSTRAINT fk_tenant_id
+        FOREIGN KEY(tenant_id)
+        REFERENCES tenants(id)
 );
 ALTER TABLE users OWNER TO datawriter;
-ALTER TABLE ONLY users ADD CONSTRAINT users_email_key UNIQUE (email);
+-- emails are unique per tenant
+ALTER TABLE ONLY users ADD CONSTRAINT users_tenant_id_email_key UNIQUE (tenant_id, email);
+-- emails are stored trimmed and lowercased so the unique key is case-insensitive
+ALTER TABLE ONLY users ADD CONSTRAINT users_email_normalized CHECK (email = LOWER(TRIM(email)));
 CREATE INDEX idx_users_user_id ON users(id);
 CREATE INDEX idx_users_email ON users(email);
+-- search and login indexes - existing dbs can apply
+-- ./migrations/0001_search_indexes.sql
+CREATE INDEX idx_users_email_lower ON users(LOWER(email));
+CREATE INDEX idx_users_email_trgm ON users USING GIN (email gin_trgm_ops);
+CREATE INDEX idx_users_created_at ON users(created_at);
 
 CREATE TABLE users_verified (
     id INT GENERATED ALWAYS AS IDENTITY,
     user_id INT NOT NULL,
+    tenant_id INT DEFAULT 1 NOT NULL,
     token VARCHAR(512) NOT NULL,
     email TEXT NOT NULL,
     state INT DEFAULT 0 NOT NULL,
@@ -38,16 +80,24 @@ CREATE TABLE users_verified (
 );
 ALTER TABLE users_verified OWNER TO datawriter;
 ALTER TABLE ONLY users_verified ADD CONSTRAINT users_verified_user_id_key UNIQUE (user_id);
-ALTER TABLE ONLY users_verified ADD CONSTRAINT users_verified_email_key UNIQUE (email);
+ALTER TABLE ONLY users_verified ADD CONSTRAINT users_verified_tenant_id_email_key UNIQUE (tenant_id, email);
 CREATE INDEX idx_users_verified_user_id ON users_verified(user_id);
+CREATE INDEX idx_users_verified_exp_date_active ON users_verified(exp_date) WHERE state = 0;
 
 CREATE TABLE users_tokens (
     id INT GENERATED ALWAYS AS IDENTITY,
     user_id INT,
     token VARCHAR(512) NOT NULL,
     state INT DEFAULT 0 NOT NULL,
+    user_agent VARCHAR(512),
+    ip_address VARCHAR(64),
+    device VARCHAR(256),
+    country VARCHAR(8),
+    city VARCHAR(128),
+    kid VARCHAR(128) DEFAULT 'default' NOT NULL,
     created_at timestamp with time zone DEFAULT timezone('UTC'::text, now()) NOT NULL,
     updated_at timestamp with time zone,
+    last_used_at timestamp with time zone,
     PRIMARY KEY(id),
     CONSTRAINT fk_user_id
         FOREIGN KEY(user_id)
@@ -56,34 +106,290 @@ CREATE TABLE users_tokens (
 ALTER TABLE users_tokens OWNER TO datawriter;
 CREATE INDEX idx_users_tokens_id ON users_tokens(id);
 CREATE INDEX idx_users_tokens_user_id ON users_tokens(user_id);
+CREATE INDEX idx_users_tokens_token ON users_tokens(token);
+CREATE INDEX idx_users_tokens_kid_active ON users_tokens(kid) WHERE state = 0;
 
 CREATE TABLE users_data (
     id INT GENERATED ALWAYS AS IDENTITY,
     user_id INT,
+    tenant_id INT DEFAULT 1 NOT NULL,
     filename VARCHAR(512) NOT NULL,
     size_in_bytes BIGINT NOT NULL,
     comments VARCHAR(512) NOT NULL,
     data_type VARCHAR(64) NOT NULL,
     encoding VARCHAR(64) NOT NULL,
     sloc VARCHAR(1024) NOT NULL,
+    -- uploading, pending, scanning, ready, quarantined, failed,
+    -- expired or broken
+    status VARCHAR(20) DEFAULT 'ready' NOT NULL,
+    status_updated_at timestamp with time zone,
     created_at timestamp with time zone DEFAULT timezone('UTC'::text, now()) NOT NULL,
     updated_at timestamp with time zone,
+    -- bumped on every update for optimistic concurrency
+    version INT DEFAULT 1 NOT NULL,
+    -- hex sha256 of the uploaded contents for dedupe
+    checksum VARCHAR(64),
+    -- s3 storage class (NULL = S3_STORAGE_CLASS)
+    storage_class VARCHAR(32),
+    -- the lifecycle task deletes the s3 object (or moves it
+    -- to the expire_storage_class) after expires_at
+    expires_at timestamp with time zone,
+    expire_storage_class VARCHAR(32),
+    -- upload scanner result: clean, infected or error
+    -- (NULL = not scanned) and the infected file's signature
+    scan_status VARCHAR(16),
+    scan_signature VARCHAR(256),
+    -- upload Content-Type (image/* uploads get thumbnails)
+    content_type VARCHAR(128),
+    -- thumbnail task status: pending, processing, done or failed
+    -- (NULL = no derivatives)
+    derivatives_status VARCHAR(16),
+    derivatives_updated_at timestamp with time zone,
+    -- user-defined tags and an optional folder path
+    -- (NULL = not in a folder) for organizing uploads
+    tags TEXT[] DEFAULT '{}' NOT NULL,
+    folder VARCHAR(1024),
+    -- file versions (on_conflict=overwrite uploads) - the
+    -- first version's id and the newer version's id
+    -- (NULL = the current version)
+    file_version INT DEFAULT 1 NOT NULL,
+    version_of INT,
+    replaced_by INT,
     PRIMARY KEY(id),
     CONSTRAINT fk_user_id
         FOREIGN KEY(user_id)
-        REFERENCES users(id)
+        REFERENCES users(id),
+    CONSTRAINT users_data_status
+        CHECK (status IN ('uploading', 'pending', 'scanning', 'ready', 'quarantined', 'failed', 'expired', 'broken')),
+    CONSTRAINT users_data_scan_status
+        CHECK (scan_status IN ('clean', 'infected', 'error')),
+    CONSTRAINT users_data_derivatives_status
+        CHECK (derivatives_status IN ('pending', 'processing', 'done', 'failed'))
 );
 ALTER TABLE users_data OWNER TO datawriter;
 CREATE INDEX idx_users_data_id ON users_data(id);
+CREATE INDEX idx_users_data_created_at ON users_data(created_at);
+CREATE INDEX idx_users_data_user_id_created_at ON users_data(user_id, created_at);
+CREATE INDEX idx_users_data_filename_trgm ON users_data USING GIN (filename gin_trgm_ops);
+CREATE INDEX idx_users_data_comments_trgm ON users_data USING GIN (comments gin_trgm_ops);
+CREATE INDEX idx_users_data_status_processing ON users_data(id) WHERE status IN ('pending', 'scanning');
+CREATE INDEX idx_users_data_user_id_checksum ON users_data(user_id, checksum) WHERE checksum IS NOT NULL;
+CREATE INDEX idx_users_data_expires_at ON users_data(expires_at) WHERE expires_at IS NOT NULL;
+CREATE INDEX idx_users_data_derivatives_processing ON users_data(id) WHERE derivatives_status IN ('pending', 'processing');
+CREATE INDEX idx_users_data_tags ON users_data USING GIN (tags);
+CREATE INDEX idx_users_data_folder ON users_data(folder text_pattern_ops) WHERE folder IS NOT NULL;
+CREATE INDEX idx_users_data_user_id_filename_current ON users_data(user_id, filename) WHERE replaced_by IS NULL;
+CREATE INDEX idx_users_data_version_of ON users_data(version_of) WHERE version_of IS NOT NULL;
+CREATE INDEX idx_users_data_uploading_sloc ON users_data(sloc) WHERE status = 'uploading';
+
+-- resumable uploads - the s3 multipart upload for each
+-- users_data record in the uploading status
+CREATE TABLE users_data_uploads (
+    data_id INT NOT NULL,
+    user_id INT NOT NULL,
+    bucket VARCHAR(256) NOT NULL,
+    key VARCHAR(1024) NOT NULL,
+    upload_id VARCHAR(1024) NOT NULL,
+    total_bytes BIGINT NOT NULL,
+    received_bytes BIGINT DEFAULT 0 NOT NULL,
+    -- [{"part_number": 1, "etag": "..."}, ...]
+    parts JSONB DEFAULT '[]'::jsonb NOT NULL,
+    created_at timestamp with time zone DEFAULT timezone('UTC'::text, now()) NOT NULL,
+    updated_at timestamp with time zone,
+    -- the lifecycle task aborts uploads that are not
+    -- completed before expires_at
+    expires_at timestamp with time zone NOT NULL,
+    PRIMARY KEY(data_id),
+    CONSTRAINT fk_data_id
+        FOREIGN KEY(data_id)
+        REFERENCES users_data(id)
+        ON DELETE CASCADE,
+    CONSTRAINT fk_user_id
+        FOREIGN KEY(user_id)
+        REFERENCES users(id)
+);
+ALTER TABLE users_data_uploads OWNER TO datawriter;
+CREATE INDEX idx_users_data_uploads_expires_at ON users_data_uploads(expires_at);
+
+-- thumbnails (and other files) generated from a users_data upload
+-- and stored under S3_DATA_DERIVATIVES_PREFIX. data_id has no
+-- foreign key so derivatives stay with records moved to the
+-- users_data_archive table
+CREATE TABLE users_data_derivatives (
+    id INT GENERATED ALWAYS AS IDENTITY,
+    data_id INT NOT NULL,
+    user_id INT NOT NULL,
+    -- thumbnail
+    kind VARCHAR(32) NOT NULL,
+    -- requested max width and height
+    size INT NOT NULL,
+    width INT NOT NULL,
+    height INT NOT NULL,
+    content_type VARCHAR(128) NOT NULL,
+    size_in_bytes BIGINT NOT NULL,
+    sloc VARCHAR(1024) NOT NULL,
+    created_at timestamp with time zone DEFAULT timezone('UTC'::text, now()) NOT NULL,
+    PRIMARY KEY(id),
+    CONSTRAINT fk_user_id
+        FOREIGN KEY(user_id)
+        REFERENCES users(id)
+);
+ALTER TABLE users_data_derivatives OWNER TO datawriter;
+CREATE UNIQUE INDEX idx_users_data_derivatives_data_id_kind_size ON users_data_derivatives(data_id, kind, size);
+
+CREATE TABLE users_data_acl (
+    id INT GENERATED ALWAYS AS IDENTITY,
+    data_id INT NOT NULL,
+    owner_user_id INT NOT NULL,
+    grantee_user_id INT,
+    grantee_role VARCHAR(20),
+    access VARCHAR(10) DEFAULT 'read' NOT NULL,
+    created_at timestamp with time zone DEFAULT timezone('UTC'::text, now()) NOT NULL,
+    updated_at timestamp with time zone,
+    PRIMARY KEY(id),
+    CONSTRAINT fk_data_id
+        FOREIGN KEY(data_id)
+        REFERENCES users_data(id)
+        ON DELETE CASCADE,
+    CONSTRAINT fk_owner_user_id
+        FOREIGN KEY(owner_user_id)
+        REFERENCES users(id),
+    CONSTRAINT fk_grantee_user_id
+        FOREIGN KEY(grantee_user_id)
+        REFERENCES users(id),
+    CONSTRAINT users_data_acl_one_grantee
+        CHECK ((grantee_user_id IS NULL) <> (grantee_role IS NULL)),
+    CONSTRAINT users_data_acl_access
+        CHECK (access IN ('read', 'write'))
+);
+ALTER TABLE users_data_acl OWNER TO datawriter;
+CREATE UNIQUE INDEX idx_users_data_acl_data_id_grantee_user_id ON users_data_acl(data_id, grantee_user_id) WHERE grantee_user_id IS NOT NULL;
+CREATE UNIQUE INDEX idx_users_data_acl_data_id_grantee_role ON users_data_acl(data_id, grantee_role) WHERE grantee_role IS NOT NULL;
+CREATE INDEX idx_users_data_acl_grantee_user_id ON users_data_acl(grantee_user_id);
+
+-- users_data records shared with another user or through a
+-- public download link (only the link token's hash is stored)
+CREATE TABLE users_data_shares (
+    id INT GENERATED ALWAYS AS IDENTITY,
+    data_id INT NOT NULL,
+    owner_user_id INT NOT NULL,
+    shared_with_user_id INT,
+    token VARCHAR(512),
+    expires_at timestamp with time zone,
+    created_at timestamp with time zone DEFAULT timezone('UTC'::text, now()) NOT NULL,
+    PRIMARY KEY(id),
+    CONSTRAINT fk_data_id
+        FOREIGN KEY(data_id)
+        REFERENCES users_data(id)
+        ON DELETE CASCADE,
+    CONSTRAINT fk_owner_user_id
+        FOREIGN KEY(owner_user_id)
+        REFERENCES users(id),
+    CONSTRAINT fk_shared_with_user_id
+        FOREIGN KEY(shared_with_user_id)
+        REFERENCES users(id),
+    CONSTRAINT users_data_shares_one_target
+        CHECK ((shared_with_user_id IS NULL) <> (token IS NULL))
+);
+ALTER TABLE users_data_shares OWNER TO datawriter;
+CREATE UNIQUE INDEX idx_users_data_shares_token ON users_data_shares(token) WHERE token IS NOT NULL;
+CREATE UNIQUE INDEX idx_users_data_shares_data_id_shared_with_user_id ON users_data_shares(data_id, shared_with_user_id) WHERE shared_with_user_id IS NOT NULL;
+CREATE INDEX idx_users_data_shares_shared_with_user_id ON users_data_shares(shared_with_user_id);
+
+-- one row per users_data file download (who, when and how
+-- many bytes) for per-file access stats and egress metering.
+-- rows are kept after the file is archived or deleted
+CREATE TABLE users_data_access (
+    id BIGINT GENERATED ALWAYS AS IDENTITY,
+    data_id INT NOT NULL,
+    owner_user_id INT NOT NULL,
+    user_id INT,
+    access VARCHAR(16) NOT NULL,
+    size_in_bytes BIGINT NOT NULL,
+    created_at timestamp with time zone DEFAULT timezone('UTC'::text, now()) NOT NULL,
+    PRIMARY KEY(id),
+    CONSTRAINT fk_owner_user_id
+        FOREIGN KEY(owner_user_id)
+        REFERENCES users(id),
+    CONSTRAINT fk_user_id
+        FOREIGN KEY(user_id)
+        REFERENCES users(id),
+    CONSTRAINT users_data_access_access
+        CHECK (access IN ('owner', 'acl', 'share_token'))
+);
+ALTER TABLE users_data_access OWNER TO datawriter;
+CREATE INDEX idx_users_data_access_data_id_created_at ON users_data_access(data_id, created_at);
+CREATE INDEX idx_users_data_access_owner_user_id_created_at ON users_data_access(owner_user_id, created_at);
+
+-- users_data rows older than USERS_DATA_ARCHIVE_AFTER_DAYS are moved
+-- here (with their shares) so searches over recent data stay fast
+CREATE TABLE users_data_archive (
+    id INT NOT NULL,
+    user_id INT,
+    tenant_id INT DEFAULT 1 NOT NULL,
+    filename VARCHAR(512) NOT NULL,
+    size_in_bytes BIGINT NOT NULL,
+    comments VARCHAR(512) NOT NULL,
+    data_type VARCHAR(64) NOT NULL,
+    encoding VARCHAR(64) NOT NULL,
+    sloc VARCHAR(1024) NOT NULL,
+    status VARCHAR(20) DEFAULT 'ready' NOT NULL,
+    created_at timestamp with time zone NOT NULL,
+    updated_at timestamp with time zone,
+    version INT DEFAULT 1 NOT NULL,
+    checksum VARCHAR(64),
+    storage_class VARCHAR(32),
+    expires_at timestamp with time zone,
+    expire_storage_class VARCHAR(32),
+    scan_status VARCHAR(16),
+    scan_signature VARCHAR(256),
+    content_type VARCHAR(128),
+    derivatives_status VARCHAR(16),
+    derivatives_updated_at timestamp with time zone,
+    tags TEXT[] DEFAULT '{}' NOT NULL,
+    folder VARCHAR(1024),
+    file_version INT DEFAULT 1 NOT NULL,
+    version_of INT,
+    replaced_by INT,
+    archived_at timestamp with time zone DEFAULT timezone('UTC'::text, now()) NOT NULL,
+    PRIMARY KEY(id),
+    CONSTRAINT fk_user_id
+        FOREIGN KEY(user_id)
+        REFERENCES users(id)
+);
+ALTER TABLE users_data_archive OWNER TO datawriter;
+CREATE INDEX idx_users_data_archive_user_id_created_at ON users_data_archive(user_id, created_at);
+CREATE INDEX idx_users_data_archive_version_of ON users_data_archive(version_of) WHERE version_of IS NOT NULL;
+
+CREATE TABLE users_data_acl_archive (
+    id INT NOT NULL,
+    data_id INT NOT NULL,
+    owner_user_id INT NOT NULL,
+    grantee_user_id INT,
+    grantee_role VARCHAR(20),
+    access VARCHAR(10) NOT NULL,
+    created_at timestamp with time zone NOT NULL,
+    updated_at timestamp with time zone,
+    PRIMARY KEY(id),
+    CONSTRAINT fk_data_id
+        FOREIGN KEY(data_id)
+        REFERENCES users_data_archive(id)
+        ON DELETE CASCADE
+);
+ALTER TABLE users_data_acl_archive OWNER TO datawriter;
+CREATE INDEX idx_users_data_acl_archive_data_id ON users_data_acl_archive(data_id);
+CREATE INDEX idx_users_data_acl_archive_grantee_user_id ON users_data_acl_archive(grantee_user_id);
 
 CREATE TABLE users_otp (
     id INT GENERATED ALWAYS AS IDENTITY,
     user_id INT,
+    tenant_id INT DEFAULT 1 NOT NULL,
     token VARCHAR(512) NOT NULL,
     email TEXT,
     state INT DEFAULT 0 NOT NULL,
     exp_date timestamp with time zone,
     consumed_date timestamp with time zone,
+    failed_attempts INT DEFAULT 0 NOT NULL,
     created_at timestamp with time zone DEFAULT timezone('UTC'::text, now()) NOT NULL,
     PRIMARY KEY(id),
     CONSTRAINT fk_user_id
@@ -93,3 +399,295 @@ CREATE TABLE users_otp (
 ALTER TABLE users_otp OWNER TO datawriter;
 CREATE INDEX idx_users_otp_id ON users_otp(id);
 CREATE INDEX idx_users_otp_user_id ON users_otp(user_id);
+-- at most one active otp per user (state 2 = replaced by a newer otp)
+CREATE UNIQUE INDEX idx_users_otp_user_id_active ON users_otp(user_id) WHERE state = 0;
+-- state 3 = locked after USER_OTP_MAX_ATTEMPTS wrong tokens
+CREATE INDEX idx_users_otp_exp_date_active ON users_otp(exp_date) WHERE state = 0;
+
+-- one row per consumed verification or one-time-password token
+-- hash so each link can only be used once
+CREATE TABLE users_tokens_consumed (
+    id BIGINT GENERATED ALWAYS AS IDENTITY,
+    kind VARCHAR(16) NOT NULL,
+    token VARCHAR(512) NOT NULL,
+    user_id INT NOT NULL,
+    consumed_at timestamp with time zone DEFAULT timezone('UTC'::text, now()) NOT NULL,
+    PRIMARY KEY(id),
+    CONSTRAINT users_tokens_consumed_kind
+        CHECK (kind IN ('verify', 'otp')),
+    CONSTRAINT fk_user_id
+        FOREIGN KEY(user_id)
+        REFERENCES users(id)
+);
+ALTER TABLE users_tokens_consumed OWNER TO datawriter;
+CREATE UNIQUE INDEX idx_users_tokens_consumed_kind_token ON users_tokens_consumed(kind, token);
+
+CREATE TABLE users_invites (
+    id INT GENERATED ALWAYS AS IDENTITY,
+    user_id INT NOT NULL,
+    invited_by INT NOT NULL,
+    token VARCHAR(512) NOT NULL,
+    email TEXT NOT NULL,
+    state INT DEFAULT 0 NOT NULL,
+    exp_date timestamp with time zone NOT NULL,
+    created_at timestamp with time zone DEFAULT timezone('UTC'::text, now()) NOT NULL,
+    accepted_at timestamp with time zone,
+    PRIMARY KEY(id),
+    CONSTRAINT fk_user_id
+        FOREIGN KEY(user_id)
+        REFERENCES users(id),
+    CONSTRAINT fk_invited_by
+        FOREIGN KEY(invited_by)
+        REFERENCES users(id)
+);
+ALTER TABLE users_invites OWNER TO datawriter;
+ALTER TABLE ONLY users_invites ADD CONSTRAINT users_invites_user_id_key UNIQUE (user_id);
+
+-- self-service account deletion requests (state 1 = confirmed)
+CREATE TABLE users_delete_requests (
+    id INT GENERATED ALWAYS AS IDENTITY,
+    user_id INT NOT NULL,
+    token VARCHAR(512) NOT NULL,
+    state INT DEFAULT 0 NOT NULL,
+    exp_date timestamp with time zone NOT NULL,
+    created_at timestamp with time zone DEFAULT timezone('UTC'::text, now()) NOT NULL,
+    confirmed_at timestamp with time zone,
+    purge_date timestamp with time zone,
+    PRIMARY KEY(id),
+    CONSTRAINT fk_user_id
+        FOREIGN KEY(user_id)
+        REFERENCES users(id)
+);
+ALTER TABLE users_delete_requests OWNER TO datawriter;
+ALTER TABLE ONLY users_delete_requests ADD CONSTRAINT users_delete_requests_user_id_key UNIQUE (user_id);
+
+CREATE TABLE users_passkeys (
+    id INT GENERATED ALWAYS AS IDENTITY,
+    user_id INT NOT NULL,
+    cred_id VARCHAR(1024) NOT NULL,
+    name VARCHAR(256) NOT NULL,
+    passkey TEXT NOT NULL,
+    state INT DEFAULT 0 NOT NULL,
+    created_at timestamp with time zone DEFAULT timezone('UTC'::text, now()) NOT NULL,
+    last_used_at timestamp with time zone,
+    updated_at timestamp with time zone,
+    PRIMARY KEY(id),
+    CONSTRAINT fk_user_id
+        FOREIGN KEY(user_id)
+        REFERENCES users(id)
+);
+ALTER TABLE users_passkeys OWNER TO datawriter;
+ALTER TABLE ONLY users_passkeys ADD CONSTRAINT users_passkeys_cred_id_key UNIQUE (cred_id);
+CREATE INDEX idx_users_passkeys_user_id ON users_passkeys(user_id);
+
+CREATE TABLE users_passkeys_challenges (
+    id INT GENERATED ALWAYS AS IDENTITY,
+    user_id INT NOT NULL,
+    ceremony VARCHAR(20) NOT NULL,
+    challenge_state TEXT NOT NULL,
+    exp_date timestamp with time zone NOT NULL,
+    created_at timestamp with time zone DEFAULT timezone('UTC'::text, now()) NOT NULL,
+    PRIMARY KEY(id),
+    CONSTRAINT fk_user_id
+        FOREIGN KEY(user_id)
+        REFERENCES users(id)
+);
+ALTER TABLE users_passkeys_challenges OWNER TO datawriter;
+ALTER TABLE ONLY users_passkeys_challenges ADD CONSTRAINT users_passkeys_challenges_user_id_ceremony_key UNIQUE (user_id, ceremony);
+
+CREATE TABLE users_login_throttle (
+    kind VARCHAR(20) NOT NULL,
+    key VARCHAR(512) NOT NULL,
+    failures INT DEFAULT 0 NOT NULL,
+    last_failure_at timestamp with time zone DEFAULT timezone('UTC'::text, now()) NOT NULL,
+    locked_until timestamp with time zone,
+    PRIMARY KEY(kind, key),
+    CONSTRAINT users_login_throttle_kind
+        CHECK (kind IN ('email', 'ip'))
+);
+ALTER TABLE users_login_throttle OWNER TO datawriter;
+
+-- one row per password or passkey login attempt for a known user
+CREATE TABLE users_logins (
+    id BIGINT GENERATED ALWAYS AS IDENTITY,
+    user_id INT NOT NULL,
+    method VARCHAR(16) NOT NULL,
+    result VARCHAR(32) NOT NULL,
+    user_agent VARCHAR(512),
+    ip_address VARCHAR(64),
+    device VARCHAR(256),
+    country VARCHAR(8),
+    city VARCHAR(128),
+    created_at timestamp with time zone DEFAULT timezone('UTC'::text, now()) NOT NULL,
+    PRIMARY KEY(id),
+    CONSTRAINT fk_user_id
+        FOREIGN KEY(user_id)
+        REFERENCES users(id),
+    CONSTRAINT users_logins_method
+        CHECK (method IN ('password', 'passkey'))
+);
+ALTER TABLE users_logins OWNER TO datawriter;
+CREATE INDEX idx_users_logins_user_id_created_at ON users_logins(user_id, created_at);
+
+CREATE TABLE users_admin_actions (
+    id BIGINT GENERATED ALWAYS AS IDENTITY,
+    admin_user_id INT NOT NULL,
+    user_id INT NOT NULL,
+    action VARCHAR(32) NOT NULL,
+    reason VARCHAR(512),
+    revoked_sessions BIGINT DEFAULT 0 NOT NULL,
+    created_at timestamp with time zone DEFAULT timezone('UTC'::text, now()) NOT NULL,
+    PRIMARY KEY(id),
+    CONSTRAINT fk_admin_user_id
+        FOREIGN KEY(admin_user_id)
+        REFERENCES users(id),
+    CONSTRAINT fk_user_id
+        FOREIGN KEY(user_id)
+        REFERENCES users(id),
+    CONSTRAINT users_admin_actions_action
+        CHECK (action IN ('revoke_sessions', 'force_password_reset'))
+);
+ALTER TABLE users_admin_actions OWNER TO datawriter;
+CREATE INDEX idx_users_admin_actions_user_id_created_at ON users_admin_actions(user_id, created_at);
+
+CREATE TABLE settings (
+    key VARCHAR(128) NOT NULL,
+    value TEXT NOT NULL,
+    updated_by INT,
+    updated_at timestamp with time zone DEFAULT timezone('UTC'::text, now()) NOT NULL,
+    PRIMARY KEY(key)
+);
+ALTER TABLE settings OWNER TO datawriter;
+
+-- bump users.version and users_data.version on every update so
+-- a PUT with a stale version is rejected with a 409
+CREATE FUNCTION bump_row_version() RETURNS trigger AS $$
+BEGIN
+    NEW.version := OLD.version + 1;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+ALTER FUNCTION bump_row_version() OWNER TO datawriter;
+CREATE TRIGGER users_version
+    BEFORE UPDATE ON users
+    FOR EACH ROW EXECUTE FUNCTION bump_row_version();
+CREATE TRIGGER users_data_version
+    BEFORE UPDATE ON users_data
+    FOR EACH ROW EXECUTE FUNCTION bump_row_version();
+
+-- notify every api server to reload its cached settings
+CREATE FUNCTION notify_settings_changed() RETURNS trigger AS $$
+BEGIN
+    PERFORM pg_notify('settings_changed', COALESCE(NEW.key, OLD.key));
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+ALTER FUNCTION notify_settings_changed() OWNER TO datawriter;
+CREATE TRIGGER settings_changed
+    AFTER INSERT OR UPDATE OR DELETE ON settings
+    FOR EACH ROW EXECUTE FUNCTION notify_settings_changed();
+
+-- ip allow and deny rules managed with the /admin/ip-rules apis
+CREATE TABLE ip_rules (
+    id INT GENERATED ALWAYS AS IDENTITY,
+    action VARCHAR(8) NOT NULL,
+    cidr VARCHAR(64) NOT NULL,
+    path_prefix VARCHAR(256) DEFAULT '' NOT NULL,
+    note VARCHAR(512),
+    created_by INT NOT NULL,
+    created_at timestamp with time zone DEFAULT timezone('UTC'::text, now()) NOT NULL,
+    PRIMARY KEY(id),
+    CONSTRAINT fk_created_by
+        FOREIGN KEY(created_by)
+        REFERENCES users(id),
+    CONSTRAINT ip_rules_action
+        CHECK (action IN ('allow', 'deny'))
+);
+ALTER TABLE ip_rules OWNER TO datawriter;
+CREATE UNIQUE INDEX idx_ip_rules_action_cidr_path_prefix ON ip_rules(action, cidr, path_prefix);
+
+-- notify every api server to reload its cached ip rules
+CREATE FUNCTION notify_ip_rules_changed() RETURNS trigger AS $$
+BEGIN
+    PERFORM pg_notify('ip_rules_changed', COALESCE(NEW.id, OLD.id)::text);
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+ALTER FUNCTION notify_ip_rules_changed() OWNER TO datawriter;
+CREATE TRIGGER ip_rules_changed
+    AFTER INSERT OR UPDATE OR DELETE ON ip_rules
+    FOR EACH ROW EXECUTE FUNCTION notify_ip_rules_changed();
+
+-- user events streamed by GET /user/notifications/stream
+CREATE TABLE users_notifications (
+    id BIGINT GENERATED ALWAYS AS IDENTITY,
+    user_id INT NOT NULL,
+    event VARCHAR(64) NOT NULL,
+    details TEXT DEFAULT '' NOT NULL,
+    created_at timestamp with time zone DEFAULT timezone('UTC'::text, now()) NOT NULL,
+    PRIMARY KEY(id),
+    CONSTRAINT fk_user_id
+        FOREIGN KEY(user_id)
+        REFERENCES users(id)
+);
+ALTER TABLE users_notifications OWNER TO datawriter;
+CREATE INDEX idx_users_notifications_user_id_id ON users_notifications(user_id, id);
+
+-- webhook endpoints (state 1 = deleted) and their queued
+-- deliveries for the webhook dispatcher
+CREATE TABLE webhooks (
+    id INT GENERATED ALWAYS AS IDENTITY,
+    url VARCHAR(2048) NOT NULL,
+    secret VARCHAR(512) NOT NULL,
+    event_types TEXT[] DEFAULT '{}' NOT NULL,
+    state INT DEFAULT 0 NOT NULL,
+    created_by INT NOT NULL,
+    created_at timestamp with time zone DEFAULT timezone('UTC'::text, now()) NOT NULL,
+    deleted_at timestamp with time zone,
+    PRIMARY KEY(id),
+    CONSTRAINT fk_created_by
+        FOREIGN KEY(created_by)
+        REFERENCES users(id)
+);
+ALTER TABLE webhooks OWNER TO datawriter;
+
+CREATE TABLE webhooks_deliveries (
+    id BIGINT GENERATED ALWAYS AS IDENTITY,
+    webhook_id INT NOT NULL,
+    event VARCHAR(64) NOT NULL,
+    payload TEXT NOT NULL,
+    status VARCHAR(16) DEFAULT 'pending' NOT NULL,
+    attempts INT DEFAULT 0 NOT NULL,
+    next_attempt_at timestamp with time zone DEFAULT timezone('UTC'::text, now()) NOT NULL,
+    last_status_code INT,
+    last_error TEXT,
+    created_at timestamp with time zone DEFAULT timezone('UTC'::text, now()) NOT NULL,
+    delivered_at timestamp with time zone,
+    PRIMARY KEY(id),
+    CONSTRAINT fk_webhook_id
+        FOREIGN KEY(webhook_id)
+        REFERENCES webhooks(id),
+    CONSTRAINT webhooks_deliveries_status
+        CHECK (status IN ('pending', 'delivered', 'failed'))
+);
+ALTER TABLE webhooks_deliveries OWNER TO datawriter;
+CREATE INDEX idx_webhooks_deliveries_pending ON webhooks_deliveries(next_attempt_at) WHERE status = 'pending';
+CREATE INDEX idx_webhooks_deliveries_webhook_id_id ON webhooks_deliveries(webhook_id, id);
+
+CREATE TABLE kafka_dead_letters (
+    id BIGINT GENERATED ALWAYS AS IDENTITY,
+    topic VARCHAR(256) NOT NULL,
+    partition_key VARCHAR(256) NOT NULL,
+    payload TEXT NOT NULL,
+    error TEXT NOT NULL,
+    status VARCHAR(16) DEFAULT 'dead' NOT NULL,
+    attempts INT DEFAULT 0 NOT NULL,
+    created_at timestamp with time zone DEFAULT timezone('UTC'::text, now()) NOT NULL,
+    updated_at timestamp with time zone DEFAULT timezone('UTC'::text, now()) NOT NULL,
+    requeued_at timestamp with time zone,
+    PRIMARY KEY(id),
+    CONSTRAINT kafka_dead_letters_status
+        CHECK (status IN ('dead', 'requeued'))
+);
+ALTER TABLE kafka_dead_letters OWNER TO datawriter;
+CREATE INDEX idx_kafka_dead_letters_status_id ON kafka_dead_letters(status, id);
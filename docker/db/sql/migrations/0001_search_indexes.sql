@@ -0,0 +1,19 @@
+-- indexes for the search and login queries
+--
+-- apply to an existing db with:
+-- DB_SQL_INIT_FILE=./sql/migrations/0001_search_indexes.sql ./init-db.sh
+--
+-- each index is built CONCURRENTLY so writes are not blocked,
+-- and IF NOT EXISTS makes this safe to run more than once
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+-- login and email lookups
+CREATE INDEX CONCURRENTLY IF NOT EXISTS idx_users_email_lower ON users(LOWER(email));
+-- POST /user/search - email ILIKE filter and created_at sort/range
+CREATE INDEX CONCURRENTLY IF NOT EXISTS idx_users_email_trgm ON users USING GIN (email gin_trgm_ops);
+CREATE INDEX CONCURRENTLY IF NOT EXISTS idx_users_created_at ON users(created_at);
+-- POST /user/data/search - creator filter and the archiver cutoff
+CREATE INDEX CONCURRENTLY IF NOT EXISTS idx_users_data_user_id_created_at ON users_data(user_id, created_at);
+-- POST /user/data/search - filename and comments ILIKE filters
+CREATE INDEX CONCURRENTLY IF NOT EXISTS idx_users_data_filename_trgm ON users_data USING GIN (filename gin_trgm_ops);
+CREATE INDEX CONCURRENTLY IF NOT EXISTS idx_users_data_comments_trgm ON users_data USING GIN (comments gin_trgm_ops);
@@ -0,0 +1,24 @@
+-- pending user invites for POST /admin/users/invite
+--
+-- apply to an existing db with:
+-- DB_SQL_INIT_FILE=./sql/migrations/0002_users_invites.sql ./init-db.sh
+CREATE TABLE IF NOT EXISTS users_invites (
+    id INT GENERATED ALWAYS AS IDENTITY,
+    user_id INT NOT NULL,
+    invited_by INT NOT NULL,
+    token VARCHAR(512) NOT NULL,
+    email TEXT NOT NULL,
+    state INT DEFAULT 0 NOT NULL,
+    exp_date timestamp with time zone NOT NULL,
+    created_at timestamp with time zone DEFAULT timezone('UTC'::text, now()) NOT NULL,
+    accepted_at timestamp with time zone,
+    PRIMARY KEY(id),
+    CONSTRAINT fk_user_id
+        FOREIGN KEY(user_id)
+        REFERENCES users(id),
+    CONSTRAINT fk_invited_by
+        FOREIGN KEY(invited_by)
+        REFERENCES users(id)
+);
+ALTER TABLE users_invites OWNER TO datawriter;
+CREATE UNIQUE INDEX IF NOT EXISTS users_invites_user_id_key ON users_invites(user_id);
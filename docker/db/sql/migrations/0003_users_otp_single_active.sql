@@ -0,0 +1,13 @@
+-- allow at most one active (state = 0) one-time-password per user
+-- for POST /user/password/reset
+--
+-- older unconsumed otps are marked as replaced (state = 2)
+-- before the index is created
+--
+-- apply to an existing db with:
+-- DB_SQL_INIT_FILE=./sql/migrations/0003_users_otp_single_active.sql ./init-db.sh
+UPDATE users_otp SET state = 2
+WHERE state = 0
+AND id NOT IN (
+    SELECT MAX(id) FROM users_otp WHERE state = 0 GROUP BY user_id);
+CREATE UNIQUE INDEX CONCURRENTLY IF NOT EXISTS idx_users_otp_user_id_active ON users_otp(user_id) WHERE state = 0;
@@ -0,0 +1,20 @@
+-- track the jwt kid that signed each token so admins can see
+-- how many active tokens still use a key before retiring it
+-- with GET /admin/jwt/keys and POST /admin/jwt/keys/retire
+--
+-- active tokens get the kid from their jwt header (tokens
+-- without a kid were signed with the default key)
+--
+-- apply to an existing db with:
+-- DB_SQL_INIT_FILE=./sql/migrations/0004_users_tokens_kid.sql ./init-db.sh
+ALTER TABLE users_tokens ADD COLUMN IF NOT EXISTS kid VARCHAR(128) DEFAULT 'default' NOT NULL;
+UPDATE users_tokens SET kid = COALESCE(
+    convert_from(
+        decode(
+            translate(split_part(token, '.', 1), '-_', '+/')
+            || repeat('=', (4 - length(split_part(token, '.', 1)) % 4) % 4),
+            'base64'),
+        'UTF8')::json->>'kid',
+    'default')
+WHERE state = 0;
+CREATE INDEX CONCURRENTLY IF NOT EXISTS idx_users_tokens_kid_active ON users_tokens(kid) WHERE state = 0;
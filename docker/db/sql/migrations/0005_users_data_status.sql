@@ -0,0 +1,16 @@
+-- track the upload status lifecycle of each users_data record
+-- (pending, scanning, ready, quarantined or failed) so downloads
+-- can be blocked until the upload pipeline marks a file ready
+--
+-- existing records were never scanned and stay downloadable
+-- as ready
+--
+-- apply to an existing db with:
+-- DB_SQL_INIT_FILE=./sql/migrations/0005_users_data_status.sql ./init-db.sh
+ALTER TABLE users_data ADD COLUMN IF NOT EXISTS status VARCHAR(20) DEFAULT 'ready' NOT NULL;
+ALTER TABLE users_data ADD COLUMN IF NOT EXISTS status_updated_at timestamp with time zone;
+ALTER TABLE users_data DROP CONSTRAINT IF EXISTS users_data_status;
+ALTER TABLE users_data ADD CONSTRAINT users_data_status
+    CHECK (status IN ('pending', 'scanning', 'ready', 'quarantined', 'failed'));
+ALTER TABLE users_data_archive ADD COLUMN IF NOT EXISTS status VARCHAR(20) DEFAULT 'ready' NOT NULL;
+CREATE INDEX CONCURRENTLY IF NOT EXISTS idx_users_data_status_processing ON users_data(id) WHERE status IN ('pending', 'scanning');
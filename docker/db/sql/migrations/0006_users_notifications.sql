@@ -0,0 +1,19 @@
+-- user events streamed by GET /user/notifications/stream
+-- (the id is the server-sent event id used to resume
+-- a stream with the Last-Event-ID header)
+--
+-- apply to an existing db with:
+-- DB_SQL_INIT_FILE=./sql/migrations/0006_users_notifications.sql ./init-db.sh
+CREATE TABLE IF NOT EXISTS users_notifications (
+    id BIGINT GENERATED ALWAYS AS IDENTITY,
+    user_id INT NOT NULL,
+    event VARCHAR(64) NOT NULL,
+    details TEXT DEFAULT '' NOT NULL,
+    created_at timestamp with time zone DEFAULT timezone('UTC'::text, now()) NOT NULL,
+    PRIMARY KEY(id),
+    CONSTRAINT fk_user_id
+        FOREIGN KEY(user_id)
+        REFERENCES users(id)
+);
+ALTER TABLE users_notifications OWNER TO datawriter;
+CREATE INDEX IF NOT EXISTS idx_users_notifications_user_id_id ON users_notifications(user_id, id);
@@ -0,0 +1,36 @@
+-- multi-tenancy - every user belongs to one tenant and
+-- emails are unique per tenant instead of globally
+--
+-- existing records move to the default tenant (id 1)
+--
+-- apply to an existing db with:
+-- DB_SQL_INIT_FILE=./sql/migrations/0007_tenants.sql ./init-db.sh
+CREATE TABLE IF NOT EXISTS tenants (
+    id INT GENERATED BY DEFAULT AS IDENTITY,
+    slug VARCHAR(64) NOT NULL,
+    name TEXT NOT NULL,
+    state INT DEFAULT 0 NOT NULL,
+    created_at timestamp with time zone DEFAULT timezone('UTC'::text, now()) NOT NULL,
+    PRIMARY KEY(id)
+);
+ALTER TABLE tenants OWNER TO datawriter;
+ALTER TABLE tenants DROP CONSTRAINT IF EXISTS tenants_slug_key;
+ALTER TABLE ONLY tenants ADD CONSTRAINT tenants_slug_key UNIQUE (slug);
+INSERT INTO tenants (id, slug, name) VALUES (1, 'default', 'default') ON CONFLICT DO NOTHING;
+SELECT setval(pg_get_serial_sequence('tenants', 'id'), GREATEST((SELECT MAX(id) FROM tenants), 1));
+
+ALTER TABLE users ADD COLUMN IF NOT EXISTS tenant_id INT DEFAULT 1 NOT NULL;
+ALTER TABLE users DROP CONSTRAINT IF EXISTS fk_tenant_id;
+ALTER TABLE users ADD CONSTRAINT fk_tenant_id FOREIGN KEY(tenant_id) REFERENCES tenants(id);
+ALTER TABLE users DROP CONSTRAINT IF EXISTS users_email_key;
+ALTER TABLE users DROP CONSTRAINT IF EXISTS users_tenant_id_email_key;
+ALTER TABLE users ADD CONSTRAINT users_tenant_id_email_key UNIQUE (tenant_id, email);
+
+ALTER TABLE users_verified ADD COLUMN IF NOT EXISTS tenant_id INT DEFAULT 1 NOT NULL;
+ALTER TABLE users_verified DROP CONSTRAINT IF EXISTS users_verified_email_key;
+ALTER TABLE users_verified DROP CONSTRAINT IF EXISTS users_verified_tenant_id_email_key;
+ALTER TABLE users_verified ADD CONSTRAINT users_verified_tenant_id_email_key UNIQUE (tenant_id, email);
+
+ALTER TABLE users_data ADD COLUMN IF NOT EXISTS tenant_id INT DEFAULT 1 NOT NULL;
+ALTER TABLE users_data_archive ADD COLUMN IF NOT EXISTS tenant_id INT DEFAULT 1 NOT NULL;
+ALTER TABLE users_otp ADD COLUMN IF NOT EXISTS tenant_id INT DEFAULT 1 NOT NULL;
@@ -0,0 +1,25 @@
+-- optimistic concurrency - users and users_data rows have a
+-- version that is bumped on every update, and PUT /user and
+-- PUT /user/data reject a stale version with a 409
+--
+-- apply to an existing db with:
+-- DB_SQL_INIT_FILE=./sql/migrations/0008_row_versions.sql ./init-db.sh
+ALTER TABLE users ADD COLUMN IF NOT EXISTS version INT DEFAULT 1 NOT NULL;
+ALTER TABLE users_data ADD COLUMN IF NOT EXISTS version INT DEFAULT 1 NOT NULL;
+ALTER TABLE users_data_archive ADD COLUMN IF NOT EXISTS version INT DEFAULT 1 NOT NULL;
+
+CREATE OR REPLACE FUNCTION bump_row_version() RETURNS trigger AS $$
+BEGIN
+    NEW.version := OLD.version + 1;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+ALTER FUNCTION bump_row_version() OWNER TO datawriter;
+DROP TRIGGER IF EXISTS users_version ON users;
+CREATE TRIGGER users_version
+    BEFORE UPDATE ON users
+    FOR EACH ROW EXECUTE FUNCTION bump_row_version();
+DROP TRIGGER IF EXISTS users_data_version ON users_data;
+CREATE TRIGGER users_data_version
+    BEFORE UPDATE ON users_data
+    FOR EACH ROW EXECUTE FUNCTION bump_row_version();
@@ -0,0 +1,11 @@
+-- store a sha256 checksum of each upload so POST /user/data can
+-- reuse the s3 object of an identical file the user already
+-- uploaded instead of uploading it again
+--
+-- existing records have no checksum and are never reused
+--
+-- apply to an existing db with:
+-- DB_SQL_INIT_FILE=./sql/migrations/0009_users_data_checksum.sql ./init-db.sh
+ALTER TABLE users_data ADD COLUMN IF NOT EXISTS checksum VARCHAR(64);
+ALTER TABLE users_data_archive ADD COLUMN IF NOT EXISTS checksum VARCHAR(64);
+CREATE INDEX CONCURRENTLY IF NOT EXISTS idx_users_data_user_id_checksum ON users_data(user_id, checksum) WHERE checksum IS NOT NULL;
@@ -0,0 +1,17 @@
+-- per-upload s3 storage class and expiry - the lifecycle task
+-- deletes the s3 object of each users_data record after its
+-- expires_at (and marks the record expired) or moves it to the
+-- expire_storage_class
+--
+-- apply to an existing db with:
+-- DB_SQL_INIT_FILE=./sql/migrations/0010_users_data_lifecycle.sql ./init-db.sh
+ALTER TABLE users_data ADD COLUMN IF NOT EXISTS storage_class VARCHAR(32);
+ALTER TABLE users_data ADD COLUMN IF NOT EXISTS expires_at timestamp with time zone;
+ALTER TABLE users_data ADD COLUMN IF NOT EXISTS expire_storage_class VARCHAR(32);
+ALTER TABLE users_data DROP CONSTRAINT IF EXISTS users_data_status;
+ALTER TABLE users_data ADD CONSTRAINT users_data_status
+    CHECK (status IN ('pending', 'scanning', 'ready', 'quarantined', 'failed', 'expired'));
+ALTER TABLE users_data_archive ADD COLUMN IF NOT EXISTS storage_class VARCHAR(32);
+ALTER TABLE users_data_archive ADD COLUMN IF NOT EXISTS expires_at timestamp with time zone;
+ALTER TABLE users_data_archive ADD COLUMN IF NOT EXISTS expire_storage_class VARCHAR(32);
+CREATE INDEX CONCURRENTLY IF NOT EXISTS idx_users_data_expires_at ON users_data(expires_at) WHERE expires_at IS NOT NULL;
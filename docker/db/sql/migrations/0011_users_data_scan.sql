@@ -0,0 +1,13 @@
+-- upload scanner results - uploads are scanned (clamd or a
+-- webhook) before the s3 upload and infected files are rejected
+-- or stored as quarantined with their signature
+--
+-- apply to an existing db with:
+-- DB_SQL_INIT_FILE=./sql/migrations/0011_users_data_scan.sql ./init-db.sh
+ALTER TABLE users_data ADD COLUMN IF NOT EXISTS scan_status VARCHAR(16);
+ALTER TABLE users_data ADD COLUMN IF NOT EXISTS scan_signature VARCHAR(256);
+ALTER TABLE users_data DROP CONSTRAINT IF EXISTS users_data_scan_status;
+ALTER TABLE users_data ADD CONSTRAINT users_data_scan_status
+    CHECK (scan_status IN ('clean', 'infected', 'error'));
+ALTER TABLE users_data_archive ADD COLUMN IF NOT EXISTS scan_status VARCHAR(16);
+ALTER TABLE users_data_archive ADD COLUMN IF NOT EXISTS scan_signature VARCHAR(256);
@@ -0,0 +1,35 @@
+-- image thumbnails - image/* uploads are marked with a pending
+-- derivatives_status and the thumbnail task stores each
+-- thumbnail in the users_data_derivatives table
+--
+-- apply to an existing db with:
+-- DB_SQL_INIT_FILE=./sql/migrations/0012_users_data_derivatives.sql ./init-db.sh
+ALTER TABLE users_data ADD COLUMN IF NOT EXISTS content_type VARCHAR(128);
+ALTER TABLE users_data ADD COLUMN IF NOT EXISTS derivatives_status VARCHAR(16);
+ALTER TABLE users_data ADD COLUMN IF NOT EXISTS derivatives_updated_at timestamp with time zone;
+ALTER TABLE users_data DROP CONSTRAINT IF EXISTS users_data_derivatives_status;
+ALTER TABLE users_data ADD CONSTRAINT users_data_derivatives_status
+    CHECK (derivatives_status IN ('pending', 'processing', 'done', 'failed'));
+ALTER TABLE users_data_archive ADD COLUMN IF NOT EXISTS content_type VARCHAR(128);
+ALTER TABLE users_data_archive ADD COLUMN IF NOT EXISTS derivatives_status VARCHAR(16);
+ALTER TABLE users_data_archive ADD COLUMN IF NOT EXISTS derivatives_updated_at timestamp with time zone;
+CREATE TABLE IF NOT EXISTS users_data_derivatives (
+    id INT GENERATED ALWAYS AS IDENTITY,
+    data_id INT NOT NULL,
+    user_id INT NOT NULL,
+    kind VARCHAR(32) NOT NULL,
+    size INT NOT NULL,
+    width INT NOT NULL,
+    height INT NOT NULL,
+    content_type VARCHAR(128) NOT NULL,
+    size_in_bytes BIGINT NOT NULL,
+    sloc VARCHAR(1024) NOT NULL,
+    created_at timestamp with time zone DEFAULT timezone('UTC'::text, now()) NOT NULL,
+    PRIMARY KEY(id),
+    CONSTRAINT fk_user_id
+        FOREIGN KEY(user_id)
+        REFERENCES users(id)
+);
+ALTER TABLE users_data_derivatives OWNER TO datawriter;
+CREATE UNIQUE INDEX IF NOT EXISTS idx_users_data_derivatives_data_id_kind_size ON users_data_derivatives(data_id, kind, size);
+CREATE INDEX CONCURRENTLY IF NOT EXISTS idx_users_data_derivatives_processing ON users_data(id) WHERE derivatives_status IN ('pending', 'processing');
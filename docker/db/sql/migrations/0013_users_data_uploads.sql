@@ -0,0 +1,32 @@
+-- resumable uploads - POST /user/data/uploads creates a
+-- users_data record in the new uploading status and an s3
+-- multipart upload tracked in the users_data_uploads table
+--
+-- apply to an existing db with:
+-- DB_SQL_INIT_FILE=./sql/migrations/0013_users_data_uploads.sql ./init-db.sh
+ALTER TABLE users_data DROP CONSTRAINT IF EXISTS users_data_status;
+ALTER TABLE users_data ADD CONSTRAINT users_data_status
+    CHECK (status IN ('uploading', 'pending', 'scanning', 'ready', 'quarantined', 'failed', 'expired'));
+CREATE TABLE IF NOT EXISTS users_data_uploads (
+    data_id INT NOT NULL,
+    user_id INT NOT NULL,
+    bucket VARCHAR(256) NOT NULL,
+    key VARCHAR(1024) NOT NULL,
+    upload_id VARCHAR(1024) NOT NULL,
+    total_bytes BIGINT NOT NULL,
+    received_bytes BIGINT DEFAULT 0 NOT NULL,
+    parts JSONB DEFAULT '[]'::jsonb NOT NULL,
+    created_at timestamp with time zone DEFAULT timezone('UTC'::text, now()) NOT NULL,
+    updated_at timestamp with time zone,
+    expires_at timestamp with time zone NOT NULL,
+    PRIMARY KEY(data_id),
+    CONSTRAINT fk_data_id
+        FOREIGN KEY(data_id)
+        REFERENCES users_data(id)
+        ON DELETE CASCADE,
+    CONSTRAINT fk_user_id
+        FOREIGN KEY(user_id)
+        REFERENCES users(id)
+);
+ALTER TABLE users_data_uploads OWNER TO datawriter;
+CREATE INDEX IF NOT EXISTS idx_users_data_uploads_expires_at ON users_data_uploads(expires_at);
@@ -0,0 +1,31 @@
+-- share users_data records with another user id or through a
+-- public download link with an optional expiry
+--
+-- apply to an existing db with:
+-- DB_SQL_INIT_FILE=./sql/migrations/0014_users_data_shares.sql ./init-db.sh
+CREATE TABLE IF NOT EXISTS users_data_shares (
+    id INT GENERATED ALWAYS AS IDENTITY,
+    data_id INT NOT NULL,
+    owner_user_id INT NOT NULL,
+    shared_with_user_id INT,
+    token VARCHAR(512),
+    expires_at timestamp with time zone,
+    created_at timestamp with time zone DEFAULT timezone('UTC'::text, now()) NOT NULL,
+    PRIMARY KEY(id),
+    CONSTRAINT fk_data_id
+        FOREIGN KEY(data_id)
+        REFERENCES users_data(id)
+        ON DELETE CASCADE,
+    CONSTRAINT fk_owner_user_id
+        FOREIGN KEY(owner_user_id)
+        REFERENCES users(id),
+    CONSTRAINT fk_shared_with_user_id
+        FOREIGN KEY(shared_with_user_id)
+        REFERENCES users(id),
+    CONSTRAINT users_data_shares_one_target
+        CHECK ((shared_with_user_id IS NULL) <> (token IS NULL))
+);
+ALTER TABLE users_data_shares OWNER TO datawriter;
+CREATE UNIQUE INDEX IF NOT EXISTS idx_users_data_shares_token ON users_data_shares(token) WHERE token IS NOT NULL;
+CREATE UNIQUE INDEX IF NOT EXISTS idx_users_data_shares_data_id_shared_with_user_id ON users_data_shares(data_id, shared_with_user_id) WHERE shared_with_user_id IS NOT NULL;
+CREATE INDEX IF NOT EXISTS idx_users_data_shares_shared_with_user_id ON users_data_shares(shared_with_user_id);
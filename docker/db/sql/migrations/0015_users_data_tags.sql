@@ -0,0 +1,12 @@
+-- tags and folders - users_data records get user-defined tags
+-- and an optional folder path set on upload or update and
+-- used as search filters
+--
+-- apply to an existing db with:
+-- DB_SQL_INIT_FILE=./sql/migrations/0015_users_data_tags.sql ./init-db.sh
+ALTER TABLE users_data ADD COLUMN IF NOT EXISTS tags TEXT[] DEFAULT '{}' NOT NULL;
+ALTER TABLE users_data ADD COLUMN IF NOT EXISTS folder VARCHAR(1024);
+ALTER TABLE users_data_archive ADD COLUMN IF NOT EXISTS tags TEXT[] DEFAULT '{}' NOT NULL;
+ALTER TABLE users_data_archive ADD COLUMN IF NOT EXISTS folder VARCHAR(1024);
+CREATE INDEX CONCURRENTLY IF NOT EXISTS idx_users_data_tags ON users_data USING GIN (tags);
+CREATE INDEX CONCURRENTLY IF NOT EXISTS idx_users_data_folder ON users_data(folder text_pattern_ops) WHERE folder IS NOT NULL;
@@ -0,0 +1,6 @@
+-- storage quotas - users get an optional per-user override
+-- for the USER_DATA_QUOTA_BYTES upload quota
+--
+-- apply to an existing db with:
+-- DB_SQL_INIT_FILE=./sql/migrations/0016_users_quota.sql ./init-db.sh
+ALTER TABLE users ADD COLUMN IF NOT EXISTS quota_bytes BIGINT;
@@ -0,0 +1,21 @@
+-- self-service account deletion requests for
+-- POST /user/delete/request and POST /user/delete/confirm
+--
+-- apply to an existing db with:
+-- DB_SQL_INIT_FILE=./sql/migrations/0017_users_delete_requests.sql ./init-db.sh
+CREATE TABLE IF NOT EXISTS users_delete_requests (
+    id INT GENERATED ALWAYS AS IDENTITY,
+    user_id INT NOT NULL,
+    token VARCHAR(512) NOT NULL,
+    state INT DEFAULT 0 NOT NULL,
+    exp_date timestamp with time zone NOT NULL,
+    created_at timestamp with time zone DEFAULT timezone('UTC'::text, now()) NOT NULL,
+    confirmed_at timestamp with time zone,
+    purge_date timestamp with time zone,
+    PRIMARY KEY(id),
+    CONSTRAINT fk_user_id
+        FOREIGN KEY(user_id)
+        REFERENCES users(id)
+);
+ALTER TABLE users_delete_requests OWNER TO datawriter;
+CREATE UNIQUE INDEX IF NOT EXISTS users_delete_requests_user_id_key ON users_delete_requests(user_id);
@@ -0,0 +1,22 @@
+-- brute-force resistant one-time-use tokens - count wrong
+-- tokens per otp (locked otps have state = 3), remove
+-- plaintext tokens created before tokens were hashed and
+-- index the token expiry dates
+--
+-- plaintext tokens are replaced with their sha256 so they can
+-- no longer be used (users request a new otp or verification
+-- email) and do not leak with the db
+--
+-- apply to an existing db with:
+-- DB_SQL_INIT_FILE=./sql/migrations/0018_users_otp_attempts.sql ./init-db.sh
+ALTER TABLE users_otp ADD COLUMN IF NOT EXISTS failed_attempts INT DEFAULT 0 NOT NULL;
+UPDATE users_otp SET
+    token = encode(sha256(token::bytea), 'hex'),
+    state = CASE WHEN state = 0 THEN 2 ELSE state END
+WHERE token NOT LIKE '$argon2%';
+UPDATE users_verified SET
+    token = encode(sha256(token::bytea), 'hex'),
+    exp_date = LEAST(exp_date, timezone('UTC'::text, now()))
+WHERE token NOT LIKE '$argon2%';
+CREATE INDEX CONCURRENTLY IF NOT EXISTS idx_users_otp_exp_date_active ON users_otp(exp_date) WHERE state = 0;
+CREATE INDEX CONCURRENTLY IF NOT EXISTS idx_users_verified_exp_date_active ON users_verified(exp_date) WHERE state = 0;
@@ -0,0 +1,42 @@
+-- webhook endpoints registered with POST /admin/webhooks
+-- and the queued deliveries for the webhook dispatcher
+--
+-- apply to an existing db with:
+-- DB_SQL_INIT_FILE=./sql/migrations/0019_webhooks.sql ./init-db.sh
+CREATE TABLE IF NOT EXISTS webhooks (
+    id INT GENERATED ALWAYS AS IDENTITY,
+    url VARCHAR(2048) NOT NULL,
+    secret VARCHAR(512) NOT NULL,
+    event_types TEXT[] DEFAULT '{}' NOT NULL,
+    state INT DEFAULT 0 NOT NULL,
+    created_by INT NOT NULL,
+    created_at timestamp with time zone DEFAULT timezone('UTC'::text, now()) NOT NULL,
+    deleted_at timestamp with time zone,
+    PRIMARY KEY(id),
+    CONSTRAINT fk_created_by
+        FOREIGN KEY(created_by)
+        REFERENCES users(id)
+);
+ALTER TABLE webhooks OWNER TO datawriter;
+CREATE TABLE IF NOT EXISTS webhooks_deliveries (
+    id BIGINT GENERATED ALWAYS AS IDENTITY,
+    webhook_id INT NOT NULL,
+    event VARCHAR(64) NOT NULL,
+    payload TEXT NOT NULL,
+    status VARCHAR(16) DEFAULT 'pending' NOT NULL,
+    attempts INT DEFAULT 0 NOT NULL,
+    next_attempt_at timestamp with time zone DEFAULT timezone('UTC'::text, now()) NOT NULL,
+    last_status_code INT,
+    last_error TEXT,
+    created_at timestamp with time zone DEFAULT timezone('UTC'::text, now()) NOT NULL,
+    delivered_at timestamp with time zone,
+    PRIMARY KEY(id),
+    CONSTRAINT fk_webhook_id
+        FOREIGN KEY(webhook_id)
+        REFERENCES webhooks(id),
+    CONSTRAINT webhooks_deliveries_status
+        CHECK (status IN ('pending', 'delivered', 'failed'))
+);
+ALTER TABLE webhooks_deliveries OWNER TO datawriter;
+CREATE INDEX CONCURRENTLY IF NOT EXISTS idx_webhooks_deliveries_pending ON webhooks_deliveries(next_attempt_at) WHERE status = 'pending';
+CREATE INDEX CONCURRENTLY IF NOT EXISTS idx_webhooks_deliveries_webhook_id_id ON webhooks_deliveries(webhook_id, id);
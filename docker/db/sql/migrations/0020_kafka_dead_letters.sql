@@ -0,0 +1,22 @@
+-- kafka messages that could not be handed to the kafka
+-- publisher after KAFKA_PUBLISH_MAX_RETRIES retries
+--
+-- apply to an existing db with:
+-- DB_SQL_INIT_FILE=./sql/migrations/0020_kafka_dead_letters.sql ./init-db.sh
+CREATE TABLE IF NOT EXISTS kafka_dead_letters (
+    id BIGINT GENERATED ALWAYS AS IDENTITY,
+    topic VARCHAR(256) NOT NULL,
+    partition_key VARCHAR(256) NOT NULL,
+    payload TEXT NOT NULL,
+    error TEXT NOT NULL,
+    status VARCHAR(16) DEFAULT 'dead' NOT NULL,
+    attempts INT DEFAULT 0 NOT NULL,
+    created_at timestamp with time zone DEFAULT timezone('UTC'::text, now()) NOT NULL,
+    updated_at timestamp with time zone DEFAULT timezone('UTC'::text, now()) NOT NULL,
+    requeued_at timestamp with time zone,
+    PRIMARY KEY(id),
+    CONSTRAINT kafka_dead_letters_status
+        CHECK (status IN ('dead', 'requeued'))
+);
+ALTER TABLE kafka_dead_letters OWNER TO datawriter;
+CREATE INDEX CONCURRENTLY IF NOT EXISTS idx_kafka_dead_letters_status_id ON kafka_dead_letters(status, id);
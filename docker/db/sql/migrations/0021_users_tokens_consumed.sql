@@ -0,0 +1,32 @@
+-- one row for each consumed verification and one-time-password
+-- token hash so a link can only be used once, even when
+-- concurrent requests present the same token
+--
+-- tokens that were already consumed are recorded so they
+-- cannot be replayed after upgrading
+--
+-- apply to an existing db with:
+-- DB_SQL_INIT_FILE=./sql/migrations/0021_users_tokens_consumed.sql ./init-db.sh
+CREATE TABLE IF NOT EXISTS users_tokens_consumed (
+    id BIGINT GENERATED ALWAYS AS IDENTITY,
+    kind VARCHAR(16) NOT NULL,
+    token VARCHAR(512) NOT NULL,
+    user_id INT NOT NULL,
+    consumed_at timestamp with time zone DEFAULT timezone('UTC'::text, now()) NOT NULL,
+    PRIMARY KEY(id),
+    CONSTRAINT users_tokens_consumed_kind
+        CHECK (kind IN ('verify', 'otp')),
+    CONSTRAINT fk_user_id
+        FOREIGN KEY(user_id)
+        REFERENCES users(id)
+);
+ALTER TABLE users_tokens_consumed OWNER TO datawriter;
+CREATE UNIQUE INDEX IF NOT EXISTS idx_users_tokens_consumed_kind_token ON users_tokens_consumed(kind, token);
+INSERT INTO users_tokens_consumed (kind, token, user_id)
+    SELECT 'otp', token, user_id FROM users_otp
+    WHERE state = 1 AND user_id IS NOT NULL
+ON CONFLICT DO NOTHING;
+INSERT INTO users_tokens_consumed (kind, token, user_id)
+    SELECT 'verify', token, user_id FROM users_verified
+    WHERE state = 1 AND user_id IS NOT NULL
+ON CONFLICT DO NOTHING;
@@ -0,0 +1,8 @@
+-- locale for rendering each user's verification, one-time-password
+-- and invite emails (like en or pt-BR)
+--
+-- existing users get the built-in en templates
+--
+-- apply to an existing db with:
+-- DB_SQL_INIT_FILE=./sql/migrations/0022_users_locale.sql ./init-db.sh
+ALTER TABLE users ADD COLUMN IF NOT EXISTS locale VARCHAR(16) DEFAULT 'en' NOT NULL;
@@ -0,0 +1,9 @@
+-- client country and city looked up with GEOIP_DB_PATH for each
+-- new session (login events and new login notifications use them)
+--
+-- existing sessions have no location
+--
+-- apply to an existing db with:
+-- DB_SQL_INIT_FILE=./sql/migrations/0023_users_tokens_geo.sql ./init-db.sh
+ALTER TABLE users_tokens ADD COLUMN IF NOT EXISTS country VARCHAR(8);
+ALTER TABLE users_tokens ADD COLUMN IF NOT EXISTS city VARCHAR(128);
@@ -0,0 +1,8 @@
+-- broken status - the users_data reconcile task marks records
+-- whose s3 object is missing as broken
+--
+-- apply to an existing db with:
+-- DB_SQL_INIT_FILE=./sql/migrations/0024_users_data_broken.sql ./init-db.sh
+ALTER TABLE users_data DROP CONSTRAINT IF EXISTS users_data_status;
+ALTER TABLE users_data ADD CONSTRAINT users_data_status
+    CHECK (status IN ('uploading', 'pending', 'scanning', 'ready', 'quarantined', 'failed', 'expired', 'broken'));
@@ -0,0 +1,14 @@
+-- file versions - uploads with on_conflict=overwrite keep the
+-- replaced users_data record as an older version of the file
+--
+-- apply to an existing db with:
+-- DB_SQL_INIT_FILE=./sql/migrations/0025_users_data_versions.sql ./init-db.sh
+ALTER TABLE users_data ADD COLUMN IF NOT EXISTS file_version INT DEFAULT 1 NOT NULL;
+ALTER TABLE users_data ADD COLUMN IF NOT EXISTS version_of INT;
+ALTER TABLE users_data ADD COLUMN IF NOT EXISTS replaced_by INT;
+ALTER TABLE users_data_archive ADD COLUMN IF NOT EXISTS file_version INT DEFAULT 1 NOT NULL;
+ALTER TABLE users_data_archive ADD COLUMN IF NOT EXISTS version_of INT;
+ALTER TABLE users_data_archive ADD COLUMN IF NOT EXISTS replaced_by INT;
+CREATE INDEX CONCURRENTLY IF NOT EXISTS idx_users_data_user_id_filename_current ON users_data(user_id, filename) WHERE replaced_by IS NULL;
+CREATE INDEX CONCURRENTLY IF NOT EXISTS idx_users_data_version_of ON users_data(version_of) WHERE version_of IS NOT NULL;
+CREATE INDEX CONCURRENTLY IF NOT EXISTS idx_users_data_archive_version_of ON users_data_archive(version_of) WHERE version_of IS NOT NULL;
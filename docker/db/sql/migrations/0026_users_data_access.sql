@@ -0,0 +1,26 @@
+-- download audit - one row per users_data file download for
+-- per-file access stats and egress metering
+--
+-- apply to an existing db with:
+-- DB_SQL_INIT_FILE=./sql/migrations/0026_users_data_access.sql ./init-db.sh
+CREATE TABLE IF NOT EXISTS users_data_access (
+    id BIGINT GENERATED ALWAYS AS IDENTITY,
+    data_id INT NOT NULL,
+    owner_user_id INT NOT NULL,
+    user_id INT,
+    access VARCHAR(16) NOT NULL,
+    size_in_bytes BIGINT NOT NULL,
+    created_at timestamp with time zone DEFAULT timezone('UTC'::text, now()) NOT NULL,
+    PRIMARY KEY(id),
+    CONSTRAINT fk_owner_user_id
+        FOREIGN KEY(owner_user_id)
+        REFERENCES users(id),
+    CONSTRAINT fk_user_id
+        FOREIGN KEY(user_id)
+        REFERENCES users(id),
+    CONSTRAINT users_data_access_access
+        CHECK (access IN ('owner', 'acl', 'share_token'))
+);
+ALTER TABLE users_data_access OWNER TO datawriter;
+CREATE INDEX IF NOT EXISTS idx_users_data_access_data_id_created_at ON users_data_access(data_id, created_at);
+CREATE INDEX IF NOT EXISTS idx_users_data_access_owner_user_id_created_at ON users_data_access(owner_user_id, created_at);
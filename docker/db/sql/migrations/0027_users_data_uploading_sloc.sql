@@ -0,0 +1,6 @@
+-- s3 event callbacks - find the uploading users_data record for
+-- an s3 ObjectCreated event by its sloc
+--
+-- apply to an existing db with:
+-- DB_SQL_INIT_FILE=./sql/migrations/0027_users_data_uploading_sloc.sql ./init-db.sh
+CREATE INDEX CONCURRENTLY IF NOT EXISTS idx_users_data_uploading_sloc ON users_data(sloc) WHERE status = 'uploading';
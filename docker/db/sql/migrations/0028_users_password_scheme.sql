@@ -0,0 +1,12 @@
+-- password hash migration - the scheme of each users.password hash
+-- (VARIANT$v=VERSION$PARAMS$salt=FINGERPRINT for argon2 hashes) so
+-- GET /admin/passwords/schemes can report how many users still
+-- have a legacy hash
+--
+-- existing argon2 hashes are backfilled and other hashes stay NULL
+-- until the user's next login
+--
+-- apply to an existing db with:
+-- DB_SQL_INIT_FILE=./sql/migrations/0028_users_password_scheme.sql ./init-db.sh
+ALTER TABLE users ADD COLUMN IF NOT EXISTS password_scheme VARCHAR(128);
+UPDATE users SET password_scheme = split_part(password, '$', 2) || '$' || split_part(password, '$', 3) || '$' || split_part(password, '$', 4) || '$salt=' || left(encode(sha256(convert_to(split_part(password, '$', 5), 'UTF8')), 'hex'), 8) WHERE password_scheme IS NULL AND password ~ '^\$argon2[a-z]*\$v=[0-9]+\$[^$]+\$[^$]+\$[^$]+$';
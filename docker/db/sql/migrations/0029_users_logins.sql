@@ -0,0 +1,25 @@
+-- login history - one row per password or passkey login attempt
+-- for a known user for GET /user/logins
+--
+-- apply to an existing db with:
+-- DB_SQL_INIT_FILE=./sql/migrations/0029_users_logins.sql ./init-db.sh
+CREATE TABLE IF NOT EXISTS users_logins (
+    id BIGINT GENERATED ALWAYS AS IDENTITY,
+    user_id INT NOT NULL,
+    method VARCHAR(16) NOT NULL,
+    result VARCHAR(32) NOT NULL,
+    user_agent VARCHAR(512),
+    ip_address VARCHAR(64),
+    device VARCHAR(256),
+    country VARCHAR(8),
+    city VARCHAR(128),
+    created_at timestamp with time zone DEFAULT timezone('UTC'::text, now()) NOT NULL,
+    PRIMARY KEY(id),
+    CONSTRAINT fk_user_id
+        FOREIGN KEY(user_id)
+        REFERENCES users(id),
+    CONSTRAINT users_logins_method
+        CHECK (method IN ('password', 'passkey'))
+);
+ALTER TABLE users_logins OWNER TO datawriter;
+CREATE INDEX IF NOT EXISTS idx_users_logins_user_id_created_at ON users_logins(user_id, created_at);
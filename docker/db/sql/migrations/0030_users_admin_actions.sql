@@ -0,0 +1,28 @@
+-- incident response - users.password_reset_required blocks logins
+-- until the user changes their password with a one-time-use token,
+-- and users_admin_actions audits each admin session revoke and
+-- forced password reset
+--
+-- apply to an existing db with:
+-- DB_SQL_INIT_FILE=./sql/migrations/0030_users_admin_actions.sql ./init-db.sh
+ALTER TABLE users ADD COLUMN IF NOT EXISTS password_reset_required INT DEFAULT 0 NOT NULL;
+CREATE TABLE IF NOT EXISTS users_admin_actions (
+    id BIGINT GENERATED ALWAYS AS IDENTITY,
+    admin_user_id INT NOT NULL,
+    user_id INT NOT NULL,
+    action VARCHAR(32) NOT NULL,
+    reason VARCHAR(512),
+    revoked_sessions BIGINT DEFAULT 0 NOT NULL,
+    created_at timestamp with time zone DEFAULT timezone('UTC'::text, now()) NOT NULL,
+    PRIMARY KEY(id),
+    CONSTRAINT fk_admin_user_id
+        FOREIGN KEY(admin_user_id)
+        REFERENCES users(id),
+    CONSTRAINT fk_user_id
+        FOREIGN KEY(user_id)
+        REFERENCES users(id),
+    CONSTRAINT users_admin_actions_action
+        CHECK (action IN ('revoke_sessions', 'force_password_reset'))
+);
+ALTER TABLE users_admin_actions OWNER TO datawriter;
+CREATE INDEX IF NOT EXISTS idx_users_admin_actions_user_id_created_at ON users_admin_actions(user_id, created_at);
@@ -0,0 +1,34 @@
+-- ip allow and deny rules managed with the /admin/ip-rules apis,
+-- and a notification so every api server reloads its cached rules
+--
+-- apply to an existing db with:
+-- DB_SQL_INIT_FILE=./sql/migrations/0031_ip_rules.sql ./init-db.sh
+CREATE TABLE IF NOT EXISTS ip_rules (
+    id INT GENERATED ALWAYS AS IDENTITY,
+    action VARCHAR(8) NOT NULL,
+    cidr VARCHAR(64) NOT NULL,
+    path_prefix VARCHAR(256) DEFAULT '' NOT NULL,
+    note VARCHAR(512),
+    created_by INT NOT NULL,
+    created_at timestamp with time zone DEFAULT timezone('UTC'::text, now()) NOT NULL,
+    PRIMARY KEY(id),
+    CONSTRAINT fk_created_by
+        FOREIGN KEY(created_by)
+        REFERENCES users(id),
+    CONSTRAINT ip_rules_action
+        CHECK (action IN ('allow', 'deny'))
+);
+ALTER TABLE ip_rules OWNER TO datawriter;
+CREATE UNIQUE INDEX IF NOT EXISTS idx_ip_rules_action_cidr_path_prefix ON ip_rules(action, cidr, path_prefix);
+
+CREATE OR REPLACE FUNCTION notify_ip_rules_changed() RETURNS trigger AS $$
+BEGIN
+    PERFORM pg_notify('ip_rules_changed', COALESCE(NEW.id, OLD.id)::text);
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+ALTER FUNCTION notify_ip_rules_changed() OWNER TO datawriter;
+DROP TRIGGER IF EXISTS ip_rules_changed ON ip_rules;
+CREATE TRIGGER ip_rules_changed
+    AFTER INSERT OR UPDATE OR DELETE ON ip_rules
+    FOR EACH ROW EXECUTE FUNCTION notify_ip_rules_changed();
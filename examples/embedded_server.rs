@@ -0,0 +1,107 @@
+extern crate log;
+extern crate pretty_env_logger;
+
+use std::sync::Arc;
+
+use hyper::Body;
+use hyper::Method;
+use hyper::Response;
+
+use restapi::core::server::core_http_request::CoreHttpRequest;
+use restapi::core::server::custom_route::CustomRoute;
+use restapi::core::server::custom_route::CustomRouteFuture;
+use restapi::core::server::rest_api_server::RestApiServerBuilder;
+use restapi::monitoring::log_redaction::init_logger;
+use restapi::requests::auth::login_risk::LoginRiskContext;
+use restapi::requests::auth::login_risk::LoginRiskDecision;
+use restapi::requests::auth::login_risk::LoginRiskFuture;
+use restapi::requests::auth::login_risk::LoginRiskHook;
+
+/// HelloRoute
+///
+/// Example [`CustomRoute`](restapi::core::server::custom_route::CustomRoute)
+/// that serves ``GET /hello``
+///
+struct HelloRoute {}
+
+impl CustomRoute for HelloRoute {
+    fn matches(&self, method: &Method, request_uri: &str) -> bool {
+        method == Method::GET && request_uri == "/hello"
+    }
+
+    fn handle<'a>(&'a self, data: CoreHttpRequest) -> CustomRouteFuture<'a> {
+        Box::pin(async move {
+            Response::builder()
+                .status(200)
+                .body(Body::from(format!(
+                    "{{\"msg\":\"hello from {}\"}}",
+                    data.config.label
+                )))
+                .unwrap()
+        })
+    }
+}
+
+/// UnknownDeviceRiskHook
+///
+/// Example [`LoginRiskHook`](restapi::requests::auth::login_risk::LoginRiskHook)
+/// that challenges password logins from a user agent and
+/// ``device`` header that none of the user's recent
+/// sessions used
+///
+struct UnknownDeviceRiskHook {}
+
+impl LoginRiskHook for UnknownDeviceRiskHook {
+    fn score_login<'a>(
+        &'a self,
+        _tracking_label: &'a str,
+        context: &'a LoginRiskContext,
+    ) -> LoginRiskFuture<'a> {
+        Box::pin(async move {
+            let known_device = context.history.iter().any(|session| {
+                session.user_agent == context.session.user_agent
+                    && session.device == context.session.device
+            });
+            match context.history.is_empty() || known_device {
+                true => Ok(LoginRiskDecision::Allow),
+                false => Ok(LoginRiskDecision::Challenge),
+            }
+        })
+    }
+}
+
+/// main
+///
+/// Build the server in code with a
+/// [`RestApiServerBuilder`](restapi::core::server::rest_api_server::RestApiServerBuilder)
+/// that serves an extra ``GET /hello`` route and
+/// challenges logins from unknown devices. Anything
+/// that is not set on the builder falls back to the
+/// environment variables.
+///
+#[tokio::main]
+async fn main() {
+    init_logger();
+
+    let server = match RestApiServerBuilder::new("embedded-server")
+        .api_endpoint("0.0.0.0:3000")
+        .db_name("mydb")
+        .kafka_publish_events(false)
+        .route(Arc::new(HelloRoute {}))
+        .login_risk_hook(Arc::new(UnknownDeviceRiskHook {}))
+        .build()
+        .await
+    {
+        Ok(server) => server,
+        Err(err_msg) => {
+            panic!(
+                "failed to build the server with err='{err_msg}' \
+                stopping"
+            );
+        }
+    };
+
+    if !server.serve().await {
+        std::process::exit(1);
+    }
+}
@@ -1,19 +1,20 @@
 extern crate chrono;
 extern crate log;
 extern crate pretty_env_logger;
-extern crate prometheus;
 extern crate serde;
 extern crate serde_json;
 extern crate uuid;
 
-use restapi::core::core_config::build_core_config;
-use restapi::core::core_config::CoreConfig;
-use restapi::core::server::run_server::run_server;
+use restapi::cli::run_cli::run_cli;
+use restapi::monitoring::log_redaction::init_logger;
 
 /// main
 ///
-/// Create a [`CoreConfig`](restapi::core::core_config::CoreConfig) and
-/// start the server using the configuration. There are
+/// Run a [`CliCommand`](restapi::cli::cli_command::CliCommand)
+/// (``serve``, ``migrate``, ``create-admin EMAIL``,
+/// ``rotate-jwt-keys`` or ``check-config``). With no command
+/// this creates a [`CoreConfig`](restapi::core::core_config::CoreConfig)
+/// and starts the server using the configuration. There are
 /// many supported environment variables to customize most
 /// layers of the stack.
 ///
@@ -21,21 +22,9 @@ use restapi::core::server::run_server::run_server;
 ///
 #[tokio::main]
 async fn main() {
-    pretty_env_logger::init_timed();
+    init_logger();
 
     let label = "server";
-    // create the server's config from environment variables
-    let core_config: CoreConfig = match build_core_config(&label).await {
-        Ok(core_config) => core_config,
-        Err(err_msg) => {
-            panic!(
-                "failed to build core config with err='{err_msg}' \
-                stopping"
-            );
-        }
-    };
-
-    run_server(&core_config).await;
-
-    return;
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    std::process::exit(run_cli(label, &args).await);
 }
@@ -0,0 +1,51 @@
+#!/bin/bash
+
+# usage: ./create-jwt-kid.sh KID [SERVER_PKI_DIR_JWT]
+#
+# create a rotated jwt keypair named:
+# ${SERVER_PKI_DIR_JWT}/KID.private-key-pkcs8.pem
+# ${SERVER_PKI_DIR_JWT}/KID.public-key.pem
+
+function create_jwt_kid() {
+    kid="${1}"
+    pki_dir="${2:-${SERVER_PKI_DIR_JWT:-.}}"
+    if [[ ! "${kid}" =~ ^[A-Za-z0-9._-]+$ ]]; then
+        echo "usage: ./create-jwt-kid.sh KID [SERVER_PKI_DIR_JWT] - KID must use letters, numbers, '.', '_' or '-' (i.e. $(date -u +%Y%m%d))"
+        exit 1
+    fi
+    if [[ -e "${pki_dir}/${kid}.private-key-pkcs8.pem" ]]; then
+        echo "jwt kid=${kid} already exists in ${pki_dir} - stopping"
+        exit 1
+    fi
+    echo "creating JWT kid=${kid} private and public signing keys in ${pki_dir}"
+    openssl ecparam -name prime256v1 -genkey -out "${pki_dir}/${kid}.private-key.pem"
+    lt="$?"
+    if [[ "${lt}" -ne 0 ]]; then
+        echo "failed to create ${kid}.private-key.pem - stopping"
+        exit 1
+    fi
+    # encrypt the pkcs8 key if TOKEN_ALGO_PRIVATE_KEY_PASSPHRASE is set
+    if [[ "${TOKEN_ALGO_PRIVATE_KEY_PASSPHRASE}" != "" ]]; then
+        openssl pkcs8 -topk8 -v2 aes-256-cbc -passout env:TOKEN_ALGO_PRIVATE_KEY_PASSPHRASE -in "${pki_dir}/${kid}.private-key.pem" -out "${pki_dir}/${kid}.private-key-pkcs8.pem"
+    else
+        openssl pkcs8 -topk8 -nocrypt -in "${pki_dir}/${kid}.private-key.pem" -out "${pki_dir}/${kid}.private-key-pkcs8.pem"
+    fi
+    lt="$?"
+    if [[ "${lt}" -ne 0 ]]; then
+        echo "failed to create pkcs8 from ${kid}.private-key.pem - stopping"
+        exit 1
+    fi
+    openssl ec -in "${pki_dir}/${kid}.private-key.pem" -pubout -out "${pki_dir}/${kid}.public-key.pem"
+    lt="$?"
+    if [[ "${lt}" -ne 0 ]]; then
+        echo "failed to create ${kid}.public-key.pem - stopping"
+        exit 1
+    fi
+    rm -f "${pki_dir}/${kid}.private-key.pem"
+
+    echo "done creating JWT kid=${kid} - copy both files to every api server and reload the keys with: kill -HUP <PID>"
+}
+
+create_jwt_kid "${1}" "${2}"
+
+exit 0
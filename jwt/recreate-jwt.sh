@@ -7,7 +7,12 @@ function recreate_jwt() {
     if [[ "${lt}" -ne 0 ]]; then
         echo "failed to create private-key.pem - stopping"
     fi
-    openssl pkcs8 -topk8 -nocrypt -in private-key.pem -out private-key-pkcs8.pem
+    # encrypt the pkcs8 key if TOKEN_ALGO_PRIVATE_KEY_PASSPHRASE is set
+    if [[ "${TOKEN_ALGO_PRIVATE_KEY_PASSPHRASE}" != "" ]]; then
+        openssl pkcs8 -topk8 -v2 aes-256-cbc -passout env:TOKEN_ALGO_PRIVATE_KEY_PASSPHRASE -in private-key.pem -out private-key-pkcs8.pem
+    else
+        openssl pkcs8 -topk8 -nocrypt -in private-key.pem -out private-key-pkcs8.pem
+    fi
     lt="$?"
     if [[ "${lt}" -ne 0 ]]; then
         echo "failed to create pkcs8 from private-key.pem - stopping"
@@ -17,6 +22,10 @@ function recreate_jwt() {
     if [[ "${lt}" -ne 0 ]]; then
         echo "failed to create public-key.pem - stopping"
     fi
+    # do not leave an unencrypted copy of an encrypted key
+    if [[ "${TOKEN_ALGO_PRIVATE_KEY_PASSPHRASE}" != "" ]]; then
+        rm -f private-key.pem
+    fi
 
     echo "done creating JWT private and public signing keys"
 }
@@ -0,0 +1,23 @@
+//! Move old ``users_data`` rows to the ``users_data_archive``
+//! table so searches over recent data stay fast as the
+//! table grows
+//!
+//! See
+//! [`UserDataArchiver`](crate::archive::user_data_archiver::UserDataArchiver)
+//! for the supported environment variables
+//!
+//! Uploads with an ``expire_days`` lifecycle policy are
+//! deleted or moved to another s3 storage class once they
+//! expire (see
+//! [`UserDataLifecycle`](crate::archive::user_data_lifecycle::UserDataLifecycle))
+//!
+//! Orphan s3 keys and records without an s3 key are found
+//! (and optionally cleaned up) by the
+//! [`UserDataReconciler`](crate::archive::user_data_reconciler::UserDataReconciler)
+//!
+pub mod run_user_data_archiver;
+pub mod run_user_data_lifecycle;
+pub mod run_user_data_reconciler;
+pub mod user_data_archiver;
+pub mod user_data_lifecycle;
+pub mod user_data_reconciler;
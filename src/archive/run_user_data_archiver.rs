@@ -0,0 +1,81 @@
+//! Background task that archives old ``users_data`` rows
+//!
+use std::sync::Arc;
+use std::time::Duration;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use crate::archive::user_data_archiver::UserDataArchiver;
+use crate::is3::object_store::ObjectStore;
+
+/// run_user_data_archiver
+///
+/// Archive batches with
+/// [`archive_user_data_batch`](crate::archive::user_data_archiver::UserDataArchiver::archive_user_data_batch)
+/// until there is nothing left to archive and then
+/// wait `interval_seconds` before checking again.
+/// Rows are moved by a ``DELETE ... RETURNING`` statement,
+/// so when two api servers pick the same batch only the
+/// delete that wins each row archives it (and a repeated
+/// s3 export overwrites the same batch key).
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `archiver` - [`UserDataArchiver`](crate::archive::user_data_archiver::UserDataArchiver)
+/// * `object_store` - [`ObjectStore`](crate::is3::object_store::ObjectStore) -
+///   storage for the s3 exports
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client db
+///   threadpool with required tls encryption
+///
+pub async fn run_user_data_archiver(
+    tracking_label: &str,
+    archiver: UserDataArchiver,
+    object_store: Arc<dyn ObjectStore>,
+    db_pool: Pool<PostgresConnectionManager<MakeTlsConnector>>,
+) {
+    if !archiver.enabled {
+        return;
+    }
+    info!(
+        "{tracking_label} - \
+        archiving users_data older than {} days every {}s",
+        archiver.after_days, archiver.interval_seconds
+    );
+    loop {
+        match db_pool.get().await {
+            Ok(conn) => loop {
+                match archiver
+                    .archive_user_data_batch(
+                        tracking_label,
+                        object_store.as_ref(),
+                        &conn,
+                    )
+                    .await
+                {
+                    Ok(num_archived) => {
+                        if (num_archived as i64) < archiver.batch_size {
+                            break;
+                        }
+                    }
+                    Err(err_msg) => {
+                        error!("{err_msg}");
+                        break;
+                    }
+                }
+            },
+            Err(e) => {
+                error!(
+                    "{tracking_label} - \
+                    failed to get a db connection for archiving \
+                    users_data with err='{e}'"
+                );
+            }
+        }
+        tokio::time::sleep(Duration::from_secs(archiver.interval_seconds))
+            .await;
+    }
+}
@@ -0,0 +1,107 @@
+//! Background task that expires ``users_data`` uploads
+//! and aborts expired resumable uploads
+//!
+use std::sync::Arc;
+use std::time::Duration;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use crate::archive::user_data_lifecycle::UserDataLifecycle;
+use crate::is3::object_store::ObjectStore;
+
+/// run_user_data_lifecycle
+///
+/// Expire batches with
+/// [`expire_user_data_batch`](crate::archive::user_data_lifecycle::UserDataLifecycle::expire_user_data_batch)
+/// and abort expired resumable uploads with
+/// [`abort_user_data_uploads_batch`](crate::archive::user_data_lifecycle::UserDataLifecycle::abort_user_data_uploads_batch)
+/// until there is nothing left to expire and then
+/// wait `interval_seconds` before checking again.
+/// Expired uploads are claimed by deleting their
+/// ``users_data_uploads`` rows with ``FOR UPDATE SKIP LOCKED``,
+/// and an expired record is only updated while its
+/// ``expires_at`` is set, so a second api server skips the
+/// records another server already expired.
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `lifecycle` - [`UserDataLifecycle`](crate::archive::user_data_lifecycle::UserDataLifecycle)
+/// * `object_store` - [`ObjectStore`](crate::is3::object_store::ObjectStore) -
+///   storage with the uploaded files
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client db
+///   threadpool with required tls encryption
+///
+pub async fn run_user_data_lifecycle(
+    tracking_label: &str,
+    lifecycle: UserDataLifecycle,
+    object_store: Arc<dyn ObjectStore>,
+    db_pool: Pool<PostgresConnectionManager<MakeTlsConnector>>,
+) {
+    if !lifecycle.enabled {
+        return;
+    }
+    info!(
+        "{tracking_label} - \
+        expiring users_data uploads every {}s",
+        lifecycle.interval_seconds
+    );
+    loop {
+        match db_pool.get().await {
+            Ok(conn) => {
+                loop {
+                    match lifecycle
+                        .expire_user_data_batch(
+                            tracking_label,
+                            object_store.as_ref(),
+                            &conn,
+                        )
+                        .await
+                    {
+                        Ok(num_expired) => {
+                            if (num_expired as i64) < lifecycle.batch_size {
+                                break;
+                            }
+                        }
+                        Err(err_msg) => {
+                            error!("{err_msg}");
+                            break;
+                        }
+                    }
+                }
+                loop {
+                    match lifecycle
+                        .abort_user_data_uploads_batch(
+                            tracking_label,
+                            object_store.as_ref(),
+                            &conn,
+                        )
+                        .await
+                    {
+                        Ok(num_aborted) => {
+                            if (num_aborted as i64) < lifecycle.batch_size {
+                                break;
+                            }
+                        }
+                        Err(err_msg) => {
+                            error!("{err_msg}");
+                            break;
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                error!(
+                    "{tracking_label} - \
+                    failed to get a db connection for expiring \
+                    users_data with err='{e}'"
+                );
+            }
+        }
+        tokio::time::sleep(Duration::from_secs(lifecycle.interval_seconds))
+            .await;
+    }
+}
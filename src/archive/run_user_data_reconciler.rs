@@ -0,0 +1,141 @@
+//! Background task that finds orphan s3 keys and
+//! ``users_data`` records without an s3 key
+//!
+use std::sync::Arc;
+use std::time::Duration;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use crate::archive::user_data_reconciler::UserDataReconcileResult;
+use crate::archive::user_data_reconciler::UserDataReconciler;
+use crate::archive::user_data_reconciler::USERS_DATA_RECONCILE_DRIFT_GAUGE_VEC;
+use crate::is3::object_store::ObjectStore;
+
+/// run_user_data_reconciler
+///
+/// Reconcile every user in batches with
+/// [`reconcile_user`](crate::archive::user_data_reconciler::UserDataReconciler::reconcile_user)
+/// and then wait `interval_seconds` before the next run.
+/// A postgres advisory lock lets one api server reconcile
+/// at a time, and the other servers skip the run.
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `reconciler` - [`UserDataReconciler`](crate::archive::user_data_reconciler::UserDataReconciler)
+/// * `object_store` - [`ObjectStore`](crate::is3::object_store::ObjectStore) -
+///   storage with the uploaded files
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client db
+///   threadpool with required tls encryption
+///
+pub async fn run_user_data_reconciler(
+    tracking_label: &str,
+    reconciler: UserDataReconciler,
+    object_store: Arc<dyn ObjectStore>,
+    db_pool: Pool<PostgresConnectionManager<MakeTlsConnector>>,
+) {
+    if !reconciler.enabled {
+        return;
+    }
+    info!(
+        "{tracking_label} - \
+        reconciling users_data with s3 every {}s (dry_run={})",
+        reconciler.interval_seconds, reconciler.dry_run
+    );
+    loop {
+        match db_pool.get().await {
+            Ok(conn) => {
+                if reconciler.try_lock(tracking_label, &conn).await {
+                    let mut total = UserDataReconcileResult::default();
+                    let mut num_errors: usize = 0;
+                    let mut after_user_id: i32 = 0;
+                    loop {
+                        let user_ids = match reconciler
+                            .get_user_ids_batch(
+                                tracking_label,
+                                after_user_id,
+                                &conn,
+                            )
+                            .await
+                        {
+                            Ok(user_ids) => user_ids,
+                            Err(err_msg) => {
+                                error!("{err_msg}");
+                                num_errors += 1;
+                                break;
+                            }
+                        };
+                        for user_id in user_ids.iter() {
+                            match reconciler
+                                .reconcile_user(
+                                    tracking_label,
+                                    object_store.as_ref(),
+                                    *user_id,
+                                    &conn,
+                                )
+                                .await
+                            {
+                                Ok(result) => total.add(&result),
+                                Err(err_msg) => {
+                                    error!(
+                                        "{tracking_label} - \
+                                        failed to reconcile \
+                                        user_id={user_id} \
+                                        with err='{err_msg}'"
+                                    );
+                                    num_errors += 1;
+                                }
+                            }
+                        }
+                        match user_ids.last() {
+                            Some(last_user_id)
+                                if (user_ids.len() as i64)
+                                    >= reconciler.batch_size =>
+                            {
+                                after_user_id = *last_user_id;
+                            }
+                            _ => break,
+                        }
+                    }
+                    reconciler.unlock(tracking_label, &conn).await;
+                    // keep the last complete run's drift
+                    if num_errors == 0 {
+                        USERS_DATA_RECONCILE_DRIFT_GAUGE_VEC
+                            .with_label_values(&["orphan"])
+                            .set(total.num_orphans as i64);
+                        USERS_DATA_RECONCILE_DRIFT_GAUGE_VEC
+                            .with_label_values(&["missing"])
+                            .set(total.num_missing as i64);
+                    }
+                    info!(
+                        "{tracking_label} - \
+                        reconciled {} s3 keys - found {} orphans \
+                        (deleted {}) and {} records without a key \
+                        (marked {} broken) with {num_errors} errors{}",
+                        total.num_objects,
+                        total.num_orphans,
+                        total.num_deleted,
+                        total.num_missing,
+                        total.num_broken,
+                        match reconciler.dry_run {
+                            true => " - dry run",
+                            false => "",
+                        }
+                    );
+                }
+            }
+            Err(e) => {
+                error!(
+                    "{tracking_label} - \
+                    failed to get a db connection for reconciling \
+                    users_data with err='{e}'"
+                );
+            }
+        }
+        tokio::time::sleep(Duration::from_secs(reconciler.interval_seconds))
+            .await;
+    }
+}
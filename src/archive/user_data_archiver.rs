@@ -0,0 +1,499 @@
+//! Archive ``users_data`` rows older than
+//! ``USERS_DATA_ARCHIVE_AFTER_DAYS`` in batches
+//!
+//! Each batch is moved with one sql statement that deletes
+//! the rows from ``users_data``, copies their shares into
+//! ``users_data_acl_archive`` and inserts the rows into
+//! ``users_data_archive`` (so a batch is never half-moved).
+//! With ``USERS_DATA_ARCHIVE_S3_EXPORT=1`` each batch is
+//! also exported to s3 as json lines before it is moved.
+//! Batches are keyed by their first and last ``id`` so
+//! retries overwrite the same s3 key.
+//!
+//! Rows with live ``users_data_shares`` (user shares or
+//! public links that have not expired) are not archived, so
+//! archiving never removes a share that still works.
+//!
+//! Archived rows are read-only and are only returned by
+//! [`search_user_data`](crate::requests::user::search_user_data::search_user_data)
+//! with ``include_archived``.
+//!
+use lazy_static::lazy_static;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::PooledConnection;
+use bb8_postgres::PostgresConnectionManager;
+
+use crate::is3::object_store::ObjectStore;
+use crate::monitoring::metric_types::register_int_counter_vec;
+use crate::monitoring::metric_types::IntCounterVec;
+use crate::monitoring::otel::trace_client_span;
+use crate::monitoring::otel::trace_db_query;
+use crate::requests::models::user_data::ModelUserData;
+
+lazy_static! {
+    pub static ref USERS_DATA_ARCHIVE_COUNTER_VEC: IntCounterVec =
+        register_int_counter_vec!(
+            "users_data_archive_total",
+            "Number of users_data rows archived and exported to s3 \
+            and the number of failed archive batches.",
+            &["result"]
+        )
+        .unwrap();
+}
+
+/// UserDataArchiver
+///
+/// Settings for archiving old ``users_data`` rows
+///
+/// # Supported Environment Variables
+///
+/// ```bash
+/// export USERS_DATA_ARCHIVE_ENABLED="0"
+/// export USERS_DATA_ARCHIVE_AFTER_DAYS="90"
+/// export USERS_DATA_ARCHIVE_BATCH_SIZE="1000"
+/// export USERS_DATA_ARCHIVE_INTERVAL_SECONDS="3600"
+/// export USERS_DATA_ARCHIVE_S3_EXPORT="0"
+/// # defaults to S3_DATA_BUCKET
+/// export USERS_DATA_ARCHIVE_S3_BUCKET="BUCKET_NAME"
+/// export USERS_DATA_ARCHIVE_S3_PREFIX="user/data/archive"
+/// ```
+///
+/// # Arguments
+///
+/// * `enabled` - `bool` - run the archiver on this api server
+/// * `after_days` - `i64` - archive rows created more than
+///   this many days ago
+/// * `batch_size` - `i64` - max rows moved per statement
+/// * `interval_seconds` - `u64` - seconds between archive runs
+/// * `s3_export` - `bool` - export each batch to s3 before
+///   moving it
+/// * `s3_bucket` - `String` - export bucket
+/// * `s3_prefix` - `String` - export key prefix
+///
+#[derive(Clone, Default)]
+pub struct UserDataArchiver {
+    pub enabled: bool,
+    pub after_days: i64,
+    pub batch_size: i64,
+    pub interval_seconds: u64,
+    pub s3_export: bool,
+    pub s3_bucket: String,
+    pub s3_prefix: String,
+}
+
+impl UserDataArchiver {
+    /// build_user_data_archiver
+    ///
+    /// Build a
+    /// [`UserDataArchiver`](crate::archive::user_data_archiver::UserDataArchiver)
+    /// from environment variables
+    ///
+    pub fn build_user_data_archiver() -> Self {
+        let get_env = |key: &str, default: i64| -> i64 {
+            std::env::var(key)
+                .unwrap_or_else(|_| format!("{default}"))
+                .parse::<i64>()
+                .unwrap_or(default)
+        };
+        let is_enabled = |key: &str| -> bool {
+            let value = std::env::var(key).unwrap_or_else(|_| "0".to_string());
+            value == "1" || value == "true"
+        };
+        let s3_data_bucket = std::env::var("S3_DATA_BUCKET")
+            .unwrap_or_else(|_| "BUCKET_NAME".to_string());
+        UserDataArchiver {
+            enabled: is_enabled("USERS_DATA_ARCHIVE_ENABLED"),
+            after_days: get_env("USERS_DATA_ARCHIVE_AFTER_DAYS", 90).max(1),
+            batch_size: get_env("USERS_DATA_ARCHIVE_BATCH_SIZE", 1000)
+                .clamp(1, 100000),
+            interval_seconds: get_env(
+                "USERS_DATA_ARCHIVE_INTERVAL_SECONDS",
+                3600,
+            )
+            .max(1) as u64,
+            s3_export: is_enabled("USERS_DATA_ARCHIVE_S3_EXPORT"),
+            s3_bucket: std::env::var("USERS_DATA_ARCHIVE_S3_BUCKET")
+                .unwrap_or(s3_data_bucket),
+            s3_prefix: std::env::var("USERS_DATA_ARCHIVE_S3_PREFIX")
+                .unwrap_or_else(|_| "user/data/archive".to_string()),
+        }
+    }
+
+    /// archive_user_data_batch
+    ///
+    /// Move up to `batch_size` of the oldest
+    /// ``users_data`` rows created before the
+    /// `after_days` cutoff into ``users_data_archive``
+    /// (resumable uploads still ``uploading`` and rows with
+    /// live ``users_data_shares`` are skipped)
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - caller logging label
+    /// * `object_store` - [`ObjectStore`](crate::is3::object_store::ObjectStore) -
+    ///   storage for the s3 export
+    /// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+    ///   an established db connection from the
+    ///   postgres client db threadpool
+    ///
+    /// # Returns
+    ///
+    /// Ok(num_archived: `usize`) - `0` when there is
+    /// nothing left to archive
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) - nothing is moved if the
+    /// s3 export or the db statement fails
+    ///
+    pub async fn archive_user_data_batch(
+        &self,
+        tracking_label: &str,
+        object_store: &dyn ObjectStore,
+        conn: &PooledConnection<
+            '_,
+            PostgresConnectionManager<MakeTlsConnector>,
+        >,
+    ) -> Result<usize, String> {
+        let cutoff = format!(
+            "timezone('UTC'::text, now()) - interval '{} days'",
+            self.after_days
+        );
+        // shares are deleted with their users_data row
+        let without_live_shares = "NOT EXISTS (\
+                SELECT 1 \
+                FROM \
+                    users_data_shares \
+                WHERE \
+                    users_data_shares.data_id = users_data.id \
+                    AND \
+                    (users_data_shares.expires_at IS NULL \
+                    OR \
+                    users_data_shares.expires_at > \
+                    timezone('UTC'::text, now())))";
+        let query = format!(
+            "SELECT \
+                users_data.id, \
+                users_data.user_id, \
+                users_data.filename, \
+                users_data.size_in_bytes, \
+                users_data.comments, \
+                users_data.data_type, \
+                users_data.encoding, \
+                users_data.sloc, \
+                users_data.created_at, \
+                users_data.updated_at, \
+                users_data.status, \
+                users_data.version, \
+                users_data.storage_class, \
+                users_data.expires_at, \
+                users_data.scan_status, \
+                users_data.scan_signature, \
+                users_data.content_type, \
+                users_data.derivatives_status, \
+                users_data.tags, \
+                users_data.folder, \
+                users_data.file_version, \
+                users_data.version_of, \
+                users_data.replaced_by \
+            FROM \
+                users_data \
+            WHERE \
+                users_data.created_at < {cutoff} \
+                AND \
+                users_data.status <> 'uploading' \
+                AND \
+                {without_live_shares} \
+            ORDER BY \
+                users_data.id ASC \
+            LIMIT {};",
+            self.batch_size
+        );
+        let stmt = conn.prepare(&query).await.unwrap();
+        let query_result =
+            match trace_db_query(&query, conn.query(&stmt, &[])).await {
+                Ok(query_result) => query_result,
+                Err(e) => {
+                    return Err(format!(
+                        "{tracking_label} - \
+                    failed to find users_data to archive with err='{e}'"
+                    ));
+                }
+            };
+        if query_result.is_empty() {
+            return Ok(0);
+        }
+        let mut rows: Vec<ModelUserData> =
+            Vec::with_capacity(query_result.len());
+        for row in query_result.iter() {
+            let created_at_utc: chrono::DateTime<chrono::Utc> =
+                row.try_get("created_at").unwrap();
+            let updated_at: Option<chrono::DateTime<chrono::Utc>> =
+                row.try_get("updated_at").unwrap();
+            rows.push(ModelUserData {
+                user_id: row.try_get("user_id").unwrap(),
+                data_id: row.try_get("id").unwrap(),
+                filename: row.try_get("filename").unwrap(),
+                data_type: row.try_get("data_type").unwrap(),
+                size_in_bytes: row.try_get("size_in_bytes").unwrap(),
+                comments: row.try_get("comments").unwrap(),
+                encoding: row.try_get("encoding").unwrap(),
+                sloc: row.try_get("sloc").unwrap(),
+                created_at: format!(
+                    "{}",
+                    created_at_utc.format("%Y-%m-%dT%H:%M:%SZ")
+                ),
+                updated_at: updated_at
+                    .map(|v| format!("{}", v.format("%Y-%m-%dT%H:%M:%SZ")))
+                    .unwrap_or_default(),
+                version: row.try_get("version").unwrap(),
+                archived: true,
+                status: row.try_get("status").unwrap(),
+                storage_class: row
+                    .try_get::<_, Option<String>>("storage_class")
+                    .unwrap()
+                    .unwrap_or_default(),
+                expires_at: row
+                    .try_get::<_, Option<chrono::DateTime<chrono::Utc>>>(
+                        "expires_at",
+                    )
+                    .unwrap()
+                    .map(|v| format!("{}", v.format("%Y-%m-%dT%H:%M:%SZ")))
+                    .unwrap_or_default(),
+                scan_status: row
+                    .try_get::<_, Option<String>>("scan_status")
+                    .unwrap()
+                    .unwrap_or_default(),
+                scan_signature: row
+                    .try_get::<_, Option<String>>("scan_signature")
+                    .unwrap()
+                    .unwrap_or_default(),
+                content_type: row
+                    .try_get::<_, Option<String>>("content_type")
+                    .unwrap()
+                    .unwrap_or_default(),
+                derivatives_status: row
+                    .try_get::<_, Option<String>>("derivatives_status")
+                    .unwrap()
+                    .unwrap_or_default(),
+                tags: row.try_get("tags").unwrap(),
+                folder: row
+                    .try_get::<_, Option<String>>("folder")
+                    .unwrap()
+                    .unwrap_or_default(),
+                file_version: row.try_get("file_version").unwrap(),
+                version_of: row
+                    .try_get::<_, Option<i32>>("version_of")
+                    .unwrap()
+                    .unwrap_or_default(),
+                replaced_by: row
+                    .try_get::<_, Option<i32>>("replaced_by")
+                    .unwrap()
+                    .unwrap_or_default(),
+                derivatives: Vec::new(),
+                msg: "".to_string(),
+            });
+        }
+        let first_id = rows[0].data_id;
+        let last_id = rows[rows.len() - 1].data_id;
+
+        if self.s3_export {
+            let s3_key = format!(
+                "{}/users_data_{first_id:010}_{last_id:010}.jsonl",
+                self.s3_prefix
+            );
+            let export = rows
+                .iter()
+                .map(|row| serde_json::to_string(row).unwrap())
+                .collect::<Vec<String>>()
+                .join("\n");
+            if let Err(err_msg) = trace_client_span(
+                "s3 upload",
+                vec![
+                    ("s3.bucket", self.s3_bucket.clone()),
+                    ("s3.key", s3_key.clone()),
+                    ("s3.bytes", format!("{}", export.len())),
+                ],
+                object_store.upload_buffer(
+                    tracking_label,
+                    &self.s3_bucket,
+                    &s3_key,
+                    export.as_bytes(),
+                ),
+            )
+            .await
+            {
+                USERS_DATA_ARCHIVE_COUNTER_VEC
+                    .with_label_values(&["failed"])
+                    .inc();
+                return Err(format!(
+                    "{tracking_label} - \
+                    failed to export users_data ids {first_id}-{last_id} \
+                    to s3://{}/{s3_key} with err='{err_msg}'",
+                    self.s3_bucket
+                ));
+            }
+            USERS_DATA_ARCHIVE_COUNTER_VEC
+                .with_label_values(&["exported"])
+                .inc_by(rows.len() as u64);
+        }
+
+        // rows already moved by another api server or
+        // shared since the select are skipped by the delete
+        let ids = rows
+            .iter()
+            .map(|row| format!("{}", row.data_id))
+            .collect::<Vec<String>>()
+            .join(", ");
+        let query = format!(
+            "WITH moved AS (\
+                DELETE FROM \
+                    users_data \
+                WHERE \
+                    users_data.id IN ({ids}) \
+                    AND \
+                    {without_live_shares} \
+                RETURNING \
+                    users_data.id, \
+                    users_data.user_id, \
+                    users_data.filename, \
+                    users_data.size_in_bytes, \
+                    users_data.comments, \
+                    users_data.data_type, \
+                    users_data.encoding, \
+                    users_data.sloc, \
+                    users_data.created_at, \
+                    users_data.updated_at, \
+                    users_data.status, \
+                    users_data.tenant_id, \
+                    users_data.version, \
+                    users_data.checksum, \
+                    users_data.storage_class, \
+                    users_data.expires_at, \
+                    users_data.expire_storage_class, \
+                    users_data.scan_status, \
+                    users_data.scan_signature, \
+                    users_data.content_type, \
+                    users_data.derivatives_status, \
+                    users_data.derivatives_updated_at, \
+                    users_data.tags, \
+                    users_data.folder, \
+                    users_data.file_version, \
+                    users_data.version_of, \
+                    users_data.replaced_by\
+            ), moved_acl AS (\
+                INSERT INTO \
+                    users_data_acl_archive (\
+                        id, \
+                        data_id, \
+                        owner_user_id, \
+                        grantee_user_id, \
+                        grantee_role, \
+                        access, \
+                        created_at, \
+                        updated_at) \
+                SELECT \
+                    users_data_acl.id, \
+                    users_data_acl.data_id, \
+                    users_data_acl.owner_user_id, \
+                    users_data_acl.grantee_user_id, \
+                    users_data_acl.grantee_role, \
+                    users_data_acl.access, \
+                    users_data_acl.created_at, \
+                    users_data_acl.updated_at \
+                FROM \
+                    users_data_acl \
+                WHERE \
+                    users_data_acl.data_id IN (SELECT moved.id FROM moved)\
+            ) \
+            INSERT INTO \
+                users_data_archive (\
+                    id, \
+                    user_id, \
+                    filename, \
+                    size_in_bytes, \
+                    comments, \
+                    data_type, \
+                    encoding, \
+                    sloc, \
+                    created_at, \
+                    updated_at, \
+                    status, \
+                    tenant_id, \
+                    version, \
+                    checksum, \
+                    storage_class, \
+                    expires_at, \
+                    expire_storage_class, \
+                    scan_status, \
+                    scan_signature, \
+                    content_type, \
+                    derivatives_status, \
+                    derivatives_updated_at, \
+                    tags, \
+                    folder, \
+                    file_version, \
+                    version_of, \
+                    replaced_by) \
+            SELECT \
+                moved.id, \
+                moved.user_id, \
+                moved.filename, \
+                moved.size_in_bytes, \
+                moved.comments, \
+                moved.data_type, \
+                moved.encoding, \
+                moved.sloc, \
+                moved.created_at, \
+                moved.updated_at, \
+                moved.status, \
+                moved.tenant_id, \
+                moved.version, \
+                moved.checksum, \
+                moved.storage_class, \
+                moved.expires_at, \
+                moved.expire_storage_class, \
+                moved.scan_status, \
+                moved.scan_signature, \
+                moved.content_type, \
+                moved.derivatives_status, \
+                moved.derivatives_updated_at, \
+                moved.tags, \
+                moved.folder, \
+                moved.file_version, \
+                moved.version_of, \
+                moved.replaced_by \
+            FROM \
+                moved \
+            RETURNING \
+                users_data_archive.id;"
+        );
+        let stmt = conn.prepare(&query).await.unwrap();
+        match trace_db_query(&query, conn.query(&stmt, &[])).await {
+            Ok(query_result) => {
+                let num_archived = query_result.len();
+                USERS_DATA_ARCHIVE_COUNTER_VEC
+                    .with_label_values(&["archived"])
+                    .inc_by(num_archived as u64);
+                info!(
+                    "{tracking_label} - \
+                    archived {num_archived} users_data rows \
+                    ids {first_id}-{last_id}"
+                );
+                Ok(num_archived)
+            }
+            Err(e) => {
+                USERS_DATA_ARCHIVE_COUNTER_VEC
+                    .with_label_values(&["failed"])
+                    .inc();
+                Err(format!(
+                    "{tracking_label} - \
+                    failed to archive users_data ids {first_id}-{last_id} \
+                    with err='{e}'"
+                ))
+            }
+        }
+    }
+}
@@ -0,0 +1,489 @@
+//! Expire ``users_data`` uploads after their ``expires_at``
+//!
+//! Uploads with an ``expire_days`` lifecycle policy have a
+//! ``users_data.expires_at``. Once it passes, the s3 object is
+//! deleted and the record is marked ``expired`` (so it can no
+//! longer be downloaded), or the object is moved to the
+//! upload's ``expire_storage_class`` (for example ``GLACIER``)
+//! and the record keeps its status.
+//!
+//! Only objects the server uploaded (under
+//! ``S3_DATA_BUCKET/S3_DATA_PREFIX/USER_ID/``) are deleted or
+//! transitioned, and an object that another record still
+//! references is kept. Records in the
+//! ``users_data_archive`` table never expire.
+//!
+//! The task also aborts resumable uploads (see
+//! [`resumable_upload`](crate::requests::user::resumable_upload))
+//! that were not completed before their
+//! ``users_data_uploads.expires_at`` and marks their
+//! records ``failed``.
+//!
+use lazy_static::lazy_static;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::PooledConnection;
+use bb8_postgres::PostgresConnectionManager;
+
+use crate::is3::object_store::ObjectStore;
+use crate::monitoring::metric_types::register_int_counter_vec;
+use crate::monitoring::metric_types::IntCounterVec;
+use crate::monitoring::otel::trace_client_span;
+use crate::monitoring::otel::trace_db_query;
+
+lazy_static! {
+    pub static ref USERS_DATA_LIFECYCLE_COUNTER_VEC: IntCounterVec =
+        register_int_counter_vec!(
+            "users_data_lifecycle_total",
+            "Number of expired users_data s3 objects deleted, \
+            transitioned to another storage class, skipped \
+            (shared or not uploaded by the server) or failed \
+            and expired resumable uploads aborted.",
+            &["result"]
+        )
+        .unwrap();
+}
+
+/// UserDataLifecycle
+///
+/// Settings for expiring ``users_data`` uploads
+///
+/// # Supported Environment Variables
+///
+/// ```bash
+/// export USERS_DATA_LIFECYCLE_ENABLED="0"
+/// export USERS_DATA_LIFECYCLE_BATCH_SIZE="100"
+/// export USERS_DATA_LIFECYCLE_INTERVAL_SECONDS="3600"
+/// # only objects under S3_DATA_BUCKET/S3_DATA_PREFIX/USER_ID/
+/// # are deleted or transitioned
+/// export S3_DATA_BUCKET="BUCKET_NAME"
+/// export S3_DATA_PREFIX="user/data/file"
+/// ```
+///
+/// # Arguments
+///
+/// * `enabled` - `bool` - run the lifecycle task on this
+///   api server
+/// * `batch_size` - `i64` - max expired records per batch
+/// * `interval_seconds` - `u64` - seconds between runs
+/// * `s3_bucket` - `String` - ``S3_DATA_BUCKET`` the server
+///   uploads to
+/// * `s3_prefix` - `String` - ``S3_DATA_PREFIX`` the server
+///   uploads to
+///
+#[derive(Clone, Default)]
+pub struct UserDataLifecycle {
+    pub enabled: bool,
+    pub batch_size: i64,
+    pub interval_seconds: u64,
+    pub s3_bucket: String,
+    pub s3_prefix: String,
+}
+
+impl UserDataLifecycle {
+    /// build_user_data_lifecycle
+    ///
+    /// Build a
+    /// [`UserDataLifecycle`](crate::archive::user_data_lifecycle::UserDataLifecycle)
+    /// from environment variables
+    ///
+    pub fn build_user_data_lifecycle() -> Self {
+        let get_env = |key: &str, default: i64| -> i64 {
+            std::env::var(key)
+                .unwrap_or_else(|_| format!("{default}"))
+                .parse::<i64>()
+                .unwrap_or(default)
+        };
+        let enabled_s = std::env::var("USERS_DATA_LIFECYCLE_ENABLED")
+            .unwrap_or_else(|_| "0".to_string());
+        UserDataLifecycle {
+            enabled: enabled_s == "1" || enabled_s == "true",
+            batch_size: get_env("USERS_DATA_LIFECYCLE_BATCH_SIZE", 100)
+                .clamp(1, 10000),
+            interval_seconds: get_env(
+                "USERS_DATA_LIFECYCLE_INTERVAL_SECONDS",
+                3600,
+            )
+            .max(1) as u64,
+            s3_bucket: std::env::var("S3_DATA_BUCKET")
+                .unwrap_or_else(|_| "BUCKET_NAME".to_string()),
+            s3_prefix: std::env::var("S3_DATA_PREFIX")
+                .unwrap_or_else(|_| "user/data/file".to_string()),
+        }
+    }
+
+    /// is_server_upload
+    ///
+    /// Check if an s3 location is under the user's
+    /// ``S3_DATA_BUCKET/S3_DATA_PREFIX/USER_ID/`` path (client
+    /// ``sloc`` values can point anywhere)
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - `i32` - record owner
+    /// * `bucket` - `&str` - s3 bucket
+    /// * `key` - `&str` - s3 key
+    ///
+    fn is_server_upload(&self, user_id: i32, bucket: &str, key: &str) -> bool {
+        bucket == self.s3_bucket
+            && key.starts_with(&format!("{}/{user_id}/", self.s3_prefix))
+            && !key.split('/').any(|segment| segment == "..")
+    }
+
+    /// expire_user_data_batch
+    ///
+    /// Delete or transition the s3 objects of up to
+    /// `batch_size` ``users_data`` records past their
+    /// ``expires_at`` (records that are still ``uploading``,
+    /// ``pending`` or ``scanning`` wait for the upload to
+    /// finish)
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - caller logging label
+    /// * `object_store` - [`ObjectStore`](crate::is3::object_store::ObjectStore) -
+    ///   storage with the uploaded files
+    /// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+    ///   an established db connection from the
+    ///   postgres client db threadpool
+    ///
+    /// # Returns
+    ///
+    /// Ok(num_expired: `usize`) - records that no longer
+    /// have an ``expires_at`` (failed records are retried on
+    /// the next run)
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) - the expired records could
+    /// not be queried
+    ///
+    pub async fn expire_user_data_batch(
+        &self,
+        tracking_label: &str,
+        object_store: &dyn ObjectStore,
+        conn: &PooledConnection<
+            '_,
+            PostgresConnectionManager<MakeTlsConnector>,
+        >,
+    ) -> Result<usize, String> {
+        let query = format!(
+            "SELECT \
+                users_data.id, \
+                users_data.user_id, \
+                users_data.sloc, \
+                users_data.expire_storage_class, \
+                EXISTS (\
+                    SELECT \
+                        1 \
+                    FROM \
+                        users_data AS other \
+                    WHERE \
+                        other.sloc = users_data.sloc \
+                    AND \
+                        other.id <> users_data.id \
+                    AND \
+                        other.status <> 'expired') AS shared \
+            FROM \
+                users_data \
+            WHERE \
+                users_data.expires_at <= timezone('UTC'::text, now()) \
+            AND \
+                users_data.status NOT IN ('uploading', 'pending', 'scanning') \
+            ORDER BY \
+                users_data.expires_at ASC \
+            LIMIT {};",
+            self.batch_size
+        );
+        let stmt = conn.prepare(&query).await.unwrap();
+        let query_result =
+            match trace_db_query(&query, conn.query(&stmt, &[])).await {
+                Ok(query_result) => query_result,
+                Err(e) => {
+                    return Err(format!(
+                        "{tracking_label} - \
+                        failed to find expired users_data with err='{e}'"
+                    ));
+                }
+            };
+        let mut num_expired: usize = 0;
+        for row in query_result.iter() {
+            let data_id: i32 = row.try_get("id").unwrap();
+            let user_id: i32 = row.try_get("user_id").unwrap();
+            let sloc: String = row.try_get("sloc").unwrap();
+            let expire_storage_class: Option<String> =
+                row.try_get("expire_storage_class").unwrap();
+            let shared: bool = row.try_get("shared").unwrap();
+            let s3_location =
+                get_bucket_and_key_from_sloc(&sloc).filter(|(bucket, key)| {
+                    self.is_server_upload(user_id, bucket, key)
+                });
+            let (result, object_result) = match (&s3_location, shared) {
+                (Some((bucket, key)), false) => match &expire_storage_class {
+                    Some(storage_class) => (
+                        "transitioned",
+                        trace_client_span(
+                            "s3 set storage class",
+                            vec![
+                                ("s3.bucket", bucket.clone()),
+                                ("s3.key", key.clone()),
+                                ("s3.storage_class", storage_class.clone()),
+                            ],
+                            object_store.set_storage_class(
+                                tracking_label,
+                                bucket,
+                                key,
+                                storage_class,
+                            ),
+                        )
+                        .await,
+                    ),
+                    None => (
+                        "deleted",
+                        trace_client_span(
+                            "s3 delete",
+                            vec![
+                                ("s3.bucket", bucket.clone()),
+                                ("s3.key", key.clone()),
+                            ],
+                            object_store.delete_object(
+                                tracking_label,
+                                bucket,
+                                key,
+                            ),
+                        )
+                        .await,
+                    ),
+                },
+                // keep objects the server did not upload and
+                // objects other records still use
+                _ => ("skipped", Ok("Skipped".to_string())),
+            };
+            if let Err(err_msg) = object_result {
+                USERS_DATA_LIFECYCLE_COUNTER_VEC
+                    .with_label_values(&["failed"])
+                    .inc();
+                error!(
+                    "{tracking_label} - \
+                    failed to expire users_data id={data_id} {sloc} \
+                    with err='{err_msg}'"
+                );
+                continue;
+            }
+            let update_sql = match (&expire_storage_class, result) {
+                (Some(storage_class), "transitioned") => format!(
+                    "storage_class = '{}', \
+                    expire_storage_class = NULL",
+                    storage_class.replace('\'', "''")
+                ),
+                (Some(_), _) => "expire_storage_class = NULL".to_string(),
+                (None, _) => "status = 'expired', \
+                    status_updated_at = timezone('UTC'::text, now())"
+                    .to_string(),
+            };
+            let query = format!(
+                "UPDATE \
+                    users_data \
+                SET \
+                    {update_sql}, \
+                    expires_at = NULL, \
+                    updated_at = timezone('UTC'::text, now()) \
+                WHERE \
+                    users_data.id = {data_id} \
+                AND \
+                    users_data.expires_at IS NOT NULL;"
+            );
+            let stmt = conn.prepare(&query).await.unwrap();
+            match trace_db_query(&query, conn.execute(&stmt, &[])).await {
+                Ok(_) => {
+                    USERS_DATA_LIFECYCLE_COUNTER_VEC
+                        .with_label_values(&[result])
+                        .inc();
+                    info!(
+                        "{tracking_label} - \
+                        {result} users_data id={data_id} {sloc}"
+                    );
+                    num_expired += 1;
+                }
+                Err(e) => {
+                    USERS_DATA_LIFECYCLE_COUNTER_VEC
+                        .with_label_values(&["failed"])
+                        .inc();
+                    error!(
+                        "{tracking_label} - \
+                        failed to mark users_data id={data_id} \
+                        {result} with err='{e}'"
+                    );
+                }
+            }
+        }
+        Ok(num_expired)
+    }
+
+    /// abort_user_data_uploads_batch
+    ///
+    /// Abort up to `batch_size` resumable uploads past their
+    /// ``users_data_uploads.expires_at``, delete their
+    /// uploaded parts and mark the ``uploading`` records
+    /// ``failed``
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - caller logging label
+    /// * `object_store` - [`ObjectStore`](crate::is3::object_store::ObjectStore) -
+    ///   storage with the uploaded parts
+    /// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+    ///   an established db connection from the
+    ///   postgres client db threadpool
+    ///
+    /// # Returns
+    ///
+    /// Ok(num_aborted: `usize`) - expired uploads removed
+    /// from ``users_data_uploads`` (parts that could not be
+    /// deleted are left for an s3 bucket lifecycle rule)
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) - the expired uploads could
+    /// not be claimed
+    ///
+    pub async fn abort_user_data_uploads_batch(
+        &self,
+        tracking_label: &str,
+        object_store: &dyn ObjectStore,
+        conn: &PooledConnection<
+            '_,
+            PostgresConnectionManager<MakeTlsConnector>,
+        >,
+    ) -> Result<usize, String> {
+        // deleting the rows claims them so only one api
+        // server aborts each upload
+        let query = format!(
+            "DELETE FROM \
+                users_data_uploads \
+            WHERE \
+                users_data_uploads.data_id IN (\
+                    SELECT \
+                        users_data_uploads.data_id \
+                    FROM \
+                        users_data_uploads \
+                    WHERE \
+                        users_data_uploads.expires_at \
+                            <= timezone('UTC'::text, now()) \
+                    ORDER BY \
+                        users_data_uploads.expires_at ASC \
+                    LIMIT {} \
+                    FOR UPDATE SKIP LOCKED) \
+            RETURNING \
+                users_data_uploads.data_id, \
+                users_data_uploads.bucket, \
+                users_data_uploads.key, \
+                users_data_uploads.upload_id;",
+            self.batch_size
+        );
+        let stmt = conn.prepare(&query).await.unwrap();
+        let query_result =
+            match trace_db_query(&query, conn.query(&stmt, &[])).await {
+                Ok(query_result) => query_result,
+                Err(e) => {
+                    return Err(format!(
+                        "{tracking_label} - \
+                        failed to claim expired users_data_uploads \
+                        with err='{e}'"
+                    ));
+                }
+            };
+        for row in query_result.iter() {
+            let data_id: i32 = row.try_get("data_id").unwrap();
+            let bucket: String = row.try_get("bucket").unwrap();
+            let key: String = row.try_get("key").unwrap();
+            let upload_id: String = row.try_get("upload_id").unwrap();
+            if let Err(err_msg) = trace_client_span(
+                "s3 abort multipart upload",
+                vec![("s3.bucket", bucket.clone()), ("s3.key", key.clone())],
+                object_store.abort_multipart_upload(
+                    tracking_label,
+                    &bucket,
+                    &key,
+                    &upload_id,
+                ),
+            )
+            .await
+            {
+                USERS_DATA_LIFECYCLE_COUNTER_VEC
+                    .with_label_values(&["failed"])
+                    .inc();
+                error!(
+                    "{tracking_label} - \
+                    failed to abort the upload for users_data \
+                    id={data_id} s3://{bucket}/{key} with err='{err_msg}'"
+                );
+            }
+            let query = format!(
+                "UPDATE \
+                    users_data \
+                SET \
+                    status = 'failed', \
+                    status_updated_at = timezone('UTC'::text, now()), \
+                    updated_at = timezone('UTC'::text, now()) \
+                WHERE \
+                    users_data.id = {data_id} \
+                AND \
+                    users_data.status = 'uploading';"
+            );
+            let stmt = conn.prepare(&query).await.unwrap();
+            match trace_db_query(&query, conn.execute(&stmt, &[])).await {
+                Ok(_) => {
+                    USERS_DATA_LIFECYCLE_COUNTER_VEC
+                        .with_label_values(&["aborted"])
+                        .inc();
+                    info!(
+                        "{tracking_label} - \
+                        aborted expired upload for users_data \
+                        id={data_id} s3://{bucket}/{key}"
+                    );
+                }
+                Err(e) => {
+                    error!(
+                        "{tracking_label} - \
+                        failed to mark users_data id={data_id} failed \
+                        with err='{e}'"
+                    );
+                }
+            }
+        }
+        Ok(query_result.len())
+    }
+}
+
+/// get_bucket_and_key_from_sloc
+///
+/// Split an ``s3://BUCKET/KEY`` location
+///
+/// # Arguments
+///
+/// * `sloc` - `&str` - s3 location
+///
+/// # Returns
+///
+/// ``Some((bucket, key))`` or ``None`` for other locations
+///
+/// # Examples
+///
+/// ```rust
+/// use restapi::archive::user_data_lifecycle::get_bucket_and_key_from_sloc;
+/// assert_eq!(
+///     get_bucket_and_key_from_sloc("s3://bucket/user/data/file/1/a.txt"),
+///     Some(("bucket".to_string(), "user/data/file/1/a.txt".to_string()))
+/// );
+/// assert_eq!(get_bucket_and_key_from_sloc("s3://bucket"), None);
+/// ```
+///
+pub fn get_bucket_and_key_from_sloc(sloc: &str) -> Option<(String, String)> {
+    let (bucket, key) = sloc.strip_prefix("s3://")?.split_once('/')?;
+    match bucket.is_empty() || key.is_empty() {
+        true => None,
+        false => Some((bucket.to_string(), key.to_string())),
+    }
+}
@@ -0,0 +1,555 @@
+//! Find drift between the ``users_data`` records and the
+//! keys in s3
+//!
+//! Each run lists the keys under every user's
+//! ``S3_DATA_BUCKET/S3_DATA_PREFIX/USER_ID/`` prefix and
+//! matches them with the ``users_data`` and
+//! ``users_data_archive`` records that point to them:
+//!
+//! - orphan keys (no record points to them) older than
+//!   ``USERS_DATA_RECONCILE_MIN_AGE_SECONDS`` are deleted
+//! - ``ready``, ``quarantined`` and ``failed`` records older
+//!   than ``USERS_DATA_RECONCILE_MIN_AGE_SECONDS`` without a
+//!   key are marked ``broken`` (archived records are only
+//!   counted)
+//!
+//! With ``USERS_DATA_RECONCILE_DRY_RUN=1`` (the default)
+//! nothing is deleted or marked and the drift is only logged
+//! and counted, so operators can review it before turning
+//! the dry run off. The same report for one user is
+//! available with
+//! [`list_user_data_s3_objects`](crate::requests::user::list_user_data_s3_objects::list_user_data_s3_objects).
+//!
+use std::collections::HashSet;
+
+use lazy_static::lazy_static;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::PooledConnection;
+use bb8_postgres::PostgresConnectionManager;
+
+use crate::is3::object_store::ObjectStore;
+use crate::monitoring::metric_types::register_int_counter_vec;
+use crate::monitoring::metric_types::register_int_gauge_vec;
+use crate::monitoring::metric_types::IntCounterVec;
+use crate::monitoring::metric_types::IntGaugeVec;
+use crate::monitoring::otel::trace_client_span;
+use crate::monitoring::otel::trace_db_query;
+use crate::requests::models::user_data_repo::UserDataRepo;
+
+lazy_static! {
+    pub static ref USERS_DATA_RECONCILE_COUNTER_VEC: IntCounterVec =
+        register_int_counter_vec!(
+            "users_data_reconcile_total",
+            "Number of orphan s3 keys found and deleted, users_data \
+            records without an s3 key found and marked broken and \
+            failed reconcile operations.",
+            &["result"]
+        )
+        .unwrap();
+    pub static ref USERS_DATA_RECONCILE_DRIFT_GAUGE_VEC: IntGaugeVec =
+        register_int_gauge_vec!(
+            "users_data_reconcile_drift",
+            "Orphan s3 keys and users_data records without an s3 \
+            key found by the last reconcile run.",
+            &["kind"]
+        )
+        .unwrap();
+}
+
+/// advisory lock id held during a reconcile run so only one
+/// api server reconciles at a time
+const USERS_DATA_RECONCILE_LOCK_ID: i64 = 4_242_002;
+
+/// UserDataReconcileResult
+///
+/// Drift found (and fixed) by
+/// [`reconcile_user`](crate::archive::user_data_reconciler::UserDataReconciler::reconcile_user)
+///
+/// # Arguments
+///
+/// * `num_objects` - `usize` - listed s3 keys
+/// * `num_orphans` - `usize` - keys without a record
+/// * `num_deleted` - `usize` - deleted orphan keys
+/// * `num_missing` - `usize` - records without a key
+/// * `num_broken` - `usize` - records marked ``broken``
+///
+#[derive(Clone, Debug, Default)]
+pub struct UserDataReconcileResult {
+    pub num_objects: usize,
+    pub num_orphans: usize,
+    pub num_deleted: usize,
+    pub num_missing: usize,
+    pub num_broken: usize,
+}
+
+impl UserDataReconcileResult {
+    /// add
+    ///
+    /// Add another result's counts to this one
+    ///
+    pub fn add(&mut self, other: &UserDataReconcileResult) {
+        self.num_objects += other.num_objects;
+        self.num_orphans += other.num_orphans;
+        self.num_deleted += other.num_deleted;
+        self.num_missing += other.num_missing;
+        self.num_broken += other.num_broken;
+    }
+}
+
+/// UserDataReconciler
+///
+/// Settings for reconciling ``users_data`` records with the
+/// keys in s3
+///
+/// # Supported Environment Variables
+///
+/// ```bash
+/// export USERS_DATA_RECONCILE_ENABLED="0"
+/// # log and count the drift without deleting keys or
+/// # marking records broken
+/// export USERS_DATA_RECONCILE_DRY_RUN="1"
+/// export USERS_DATA_RECONCILE_INTERVAL_SECONDS="86400"
+/// # skip keys and records newer than this (uploads in flight)
+/// export USERS_DATA_RECONCILE_MIN_AGE_SECONDS="86400"
+/// export USERS_DATA_RECONCILE_BATCH_SIZE="100"
+/// export USERS_DATA_RECONCILE_MAX_KEYS="10000"
+/// export S3_DATA_BUCKET="BUCKET_NAME"
+/// export S3_DATA_PREFIX="user/data/file"
+/// ```
+///
+/// # Arguments
+///
+/// * `enabled` - `bool` - run the reconcile task on this api
+///   server
+/// * `dry_run` - `bool` - only log and count the drift
+/// * `interval_seconds` - `u64` - seconds between runs
+/// * `min_age_seconds` - `i64` - keys and records newer than
+///   this are never changed
+/// * `batch_size` - `i64` - users per db query
+/// * `max_keys` - `usize` - most keys listed per user (users
+///   with more keys are only reconciled up to the last listed
+///   key)
+/// * `s3_bucket` - `String` - ``S3_DATA_BUCKET`` the server
+///   uploads to
+/// * `s3_prefix` - `String` - ``S3_DATA_PREFIX`` the server
+///   uploads to
+///
+#[derive(Clone, Default)]
+pub struct UserDataReconciler {
+    pub enabled: bool,
+    pub dry_run: bool,
+    pub interval_seconds: u64,
+    pub min_age_seconds: i64,
+    pub batch_size: i64,
+    pub max_keys: usize,
+    pub s3_bucket: String,
+    pub s3_prefix: String,
+}
+
+impl UserDataReconciler {
+    /// build_user_data_reconciler
+    ///
+    /// Build a
+    /// [`UserDataReconciler`](crate::archive::user_data_reconciler::UserDataReconciler)
+    /// from environment variables
+    ///
+    pub fn build_user_data_reconciler() -> Self {
+        let get_env = |key: &str, default: i64| -> i64 {
+            std::env::var(key)
+                .unwrap_or_else(|_| format!("{default}"))
+                .parse::<i64>()
+                .unwrap_or(default)
+        };
+        let enabled_s = std::env::var("USERS_DATA_RECONCILE_ENABLED")
+            .unwrap_or_else(|_| "0".to_string());
+        let dry_run_s = std::env::var("USERS_DATA_RECONCILE_DRY_RUN")
+            .unwrap_or_else(|_| "1".to_string());
+        UserDataReconciler {
+            enabled: enabled_s == "1" || enabled_s == "true",
+            dry_run: dry_run_s != "0" && dry_run_s != "false",
+            interval_seconds: get_env(
+                "USERS_DATA_RECONCILE_INTERVAL_SECONDS",
+                86400,
+            )
+            .max(1) as u64,
+            min_age_seconds: get_env(
+                "USERS_DATA_RECONCILE_MIN_AGE_SECONDS",
+                86400,
+            )
+            .max(0),
+            batch_size: get_env("USERS_DATA_RECONCILE_BATCH_SIZE", 100)
+                .clamp(1, 10000),
+            max_keys: get_env("USERS_DATA_RECONCILE_MAX_KEYS", 10000)
+                .clamp(1, 1000000) as usize,
+            s3_bucket: std::env::var("S3_DATA_BUCKET")
+                .unwrap_or_else(|_| "BUCKET_NAME".to_string()),
+            s3_prefix: std::env::var("S3_DATA_PREFIX")
+                .unwrap_or_else(|_| "user/data/file".to_string()),
+        }
+    }
+
+    /// try_lock
+    ///
+    /// Try to take the reconcile advisory lock on the
+    /// connection (released with
+    /// [`unlock`](crate::archive::user_data_reconciler::UserDataReconciler::unlock))
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - caller logging label
+    /// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+    ///   connection used for the whole run
+    ///
+    /// # Returns
+    ///
+    /// `bool` - `true` if this api server holds the lock
+    ///
+    pub async fn try_lock(
+        &self,
+        tracking_label: &str,
+        conn: &PooledConnection<
+            '_,
+            PostgresConnectionManager<MakeTlsConnector>,
+        >,
+    ) -> bool {
+        let query = format!(
+            "SELECT pg_try_advisory_lock({USERS_DATA_RECONCILE_LOCK_ID}) \
+            AS locked;"
+        );
+        match trace_db_query(&query, conn.query_one(query.as_str(), &[])).await
+        {
+            Ok(row) => row.try_get("locked").unwrap_or(false),
+            Err(e) => {
+                error!(
+                    "{tracking_label} - \
+                    failed to take the reconcile lock with err='{e}'"
+                );
+                false
+            }
+        }
+    }
+
+    /// unlock
+    ///
+    /// Release the reconcile advisory lock
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - caller logging label
+    /// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+    ///   connection that took the lock
+    ///
+    pub async fn unlock(
+        &self,
+        tracking_label: &str,
+        conn: &PooledConnection<
+            '_,
+            PostgresConnectionManager<MakeTlsConnector>,
+        >,
+    ) {
+        let query = format!(
+            "SELECT pg_advisory_unlock({USERS_DATA_RECONCILE_LOCK_ID});"
+        );
+        if let Err(e) =
+            trace_db_query(&query, conn.execute(query.as_str(), &[])).await
+        {
+            error!(
+                "{tracking_label} - \
+                failed to release the reconcile lock with err='{e}'"
+            );
+        }
+    }
+
+    /// get_user_ids_batch
+    ///
+    /// Get up to `batch_size` user ids after `after_user_id`
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - caller logging label
+    /// * `after_user_id` - `i32` - last reconciled user id
+    ///   (``0`` to start)
+    /// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+    ///   an established db connection from the
+    ///   postgres client db threadpool
+    ///
+    /// # Returns
+    ///
+    /// Ok(`Vec<i32>`) - sorted user ids
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`)
+    ///
+    pub async fn get_user_ids_batch(
+        &self,
+        tracking_label: &str,
+        after_user_id: i32,
+        conn: &PooledConnection<
+            '_,
+            PostgresConnectionManager<MakeTlsConnector>,
+        >,
+    ) -> Result<Vec<i32>, String> {
+        let query = format!(
+            "SELECT \
+                users.id \
+            FROM \
+                users \
+            WHERE \
+                users.id > {after_user_id} \
+            ORDER BY \
+                users.id ASC \
+            LIMIT {};",
+            self.batch_size
+        );
+        match trace_db_query(&query, conn.query(query.as_str(), &[])).await {
+            Ok(query_result) => Ok(query_result
+                .iter()
+                .map(|row| row.try_get("id").unwrap())
+                .collect()),
+            Err(e) => Err(format!(
+                "{tracking_label} - \
+                failed to find users to reconcile with err='{e}'"
+            )),
+        }
+    }
+
+    /// reconcile_user
+    ///
+    /// List the keys under the user's prefix, delete orphan
+    /// keys and mark records without a key ``broken`` (only
+    /// counted in a dry run)
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - caller logging label
+    /// * `object_store` - [`ObjectStore`](crate::is3::object_store::ObjectStore) -
+    ///   storage with the uploaded files
+    /// * `user_id` - `i32` - user to reconcile
+    /// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+    ///   an established db connection from the
+    ///   postgres client db threadpool
+    ///
+    /// # Returns
+    ///
+    /// Ok([`UserDataReconcileResult`](crate::archive::user_data_reconciler::UserDataReconcileResult))
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) - the keys or records could not
+    /// be listed (failed deletes and updates are counted and
+    /// retried on the next run)
+    ///
+    pub async fn reconcile_user(
+        &self,
+        tracking_label: &str,
+        object_store: &dyn ObjectStore,
+        user_id: i32,
+        conn: &PooledConnection<
+            '_,
+            PostgresConnectionManager<MakeTlsConnector>,
+        >,
+    ) -> Result<UserDataReconcileResult, String> {
+        let prefix = format!("{}/{user_id}/", self.s3_prefix);
+        let sloc_prefix = format!("s3://{}/", self.s3_bucket);
+        // list one extra key to know if there are more
+        let mut entries = trace_client_span(
+            "s3 list",
+            vec![
+                ("s3.bucket", self.s3_bucket.clone()),
+                ("s3.prefix", prefix.clone()),
+            ],
+            object_store.list_objects(
+                tracking_label,
+                &self.s3_bucket,
+                &prefix,
+                self.max_keys + 1,
+            ),
+        )
+        .await?;
+        let truncated = entries.len() > self.max_keys;
+        entries.truncate(self.max_keys);
+        let records = UserDataRepo::new(conn)
+            .find_by_sloc_prefix(
+                tracking_label,
+                &format!("{sloc_prefix}{prefix}"),
+            )
+            .await
+            .map_err(|e| format!("{e}"))?;
+
+        let min_age_time = chrono::Utc::now()
+            - chrono::Duration::seconds(self.min_age_seconds);
+        let mut result = UserDataReconcileResult {
+            num_objects: entries.len(),
+            ..Default::default()
+        };
+        let record_keys: HashSet<&str> = records
+            .iter()
+            .map(|record| {
+                record
+                    .sloc
+                    .strip_prefix(&sloc_prefix)
+                    .unwrap_or(&record.sloc)
+            })
+            .collect();
+        for entry in entries.iter() {
+            if record_keys.contains(entry.key.as_str()) {
+                continue;
+            }
+            result.num_orphans += 1;
+            USERS_DATA_RECONCILE_COUNTER_VEC
+                .with_label_values(&["orphan"])
+                .inc();
+            let is_old =
+                chrono::DateTime::parse_from_rfc3339(&entry.last_modified)
+                    .map(|last_modified| last_modified <= min_age_time)
+                    .unwrap_or(false);
+            if self.dry_run || !is_old {
+                info!(
+                    "{tracking_label} - \
+                    found orphan s3://{}/{} ({} bytes, \
+                    last_modified={}){}",
+                    self.s3_bucket,
+                    entry.key,
+                    entry.size_in_bytes,
+                    entry.last_modified,
+                    match self.dry_run {
+                        true => " - dry run",
+                        false => " - too new to delete",
+                    }
+                );
+                continue;
+            }
+            match trace_client_span(
+                "s3 delete",
+                vec![
+                    ("s3.bucket", self.s3_bucket.clone()),
+                    ("s3.key", entry.key.clone()),
+                ],
+                object_store.delete_object(
+                    tracking_label,
+                    &self.s3_bucket,
+                    &entry.key,
+                ),
+            )
+            .await
+            {
+                Ok(_) => {
+                    result.num_deleted += 1;
+                    USERS_DATA_RECONCILE_COUNTER_VEC
+                        .with_label_values(&["deleted"])
+                        .inc();
+                    info!(
+                        "{tracking_label} - \
+                        deleted orphan s3://{}/{}",
+                        self.s3_bucket, entry.key
+                    );
+                }
+                Err(err_msg) => {
+                    USERS_DATA_RECONCILE_COUNTER_VEC
+                        .with_label_values(&["failed"])
+                        .inc();
+                    error!(
+                        "{tracking_label} - \
+                        failed to delete orphan s3://{}/{} \
+                        with err='{err_msg}'",
+                        self.s3_bucket, entry.key
+                    );
+                }
+            }
+        }
+
+        // a truncated listing only covers keys up to the last one
+        let last_key = match truncated {
+            true => entries.last().map(|entry| entry.key.as_str()),
+            false => None,
+        };
+        let listed_keys: HashSet<&str> =
+            entries.iter().map(|entry| entry.key.as_str()).collect();
+        for record in records.iter() {
+            if record.status == "uploading"
+                || record.status == "expired"
+                || record.status == "broken"
+            {
+                continue;
+            }
+            let key = record
+                .sloc
+                .strip_prefix(&sloc_prefix)
+                .unwrap_or(&record.sloc);
+            if listed_keys.contains(key)
+                || last_key.map(|last_key| key > last_key).unwrap_or(false)
+            {
+                continue;
+            }
+            result.num_missing += 1;
+            USERS_DATA_RECONCILE_COUNTER_VEC
+                .with_label_values(&["missing"])
+                .inc();
+            if self.dry_run || record.archived {
+                info!(
+                    "{tracking_label} - \
+                    found users_data id={} status={} without \
+                    {}{}",
+                    record.data_id,
+                    record.status,
+                    record.sloc,
+                    match self.dry_run {
+                        true => " - dry run",
+                        false => " - archived",
+                    }
+                );
+                continue;
+            }
+            // records that are still processing or too new are
+            // left alone
+            let query = format!(
+                "UPDATE \
+                    users_data \
+                SET \
+                    status = 'broken', \
+                    status_updated_at = timezone('UTC'::text, now()), \
+                    updated_at = timezone('UTC'::text, now()) \
+                WHERE \
+                    users_data.id = {} \
+                AND \
+                    users_data.status IN ('ready', 'quarantined', 'failed') \
+                AND \
+                    users_data.created_at \
+                        <= timezone('UTC'::text, now()) \
+                        - interval '{} seconds';",
+                record.data_id, self.min_age_seconds
+            );
+            match trace_db_query(&query, conn.execute(query.as_str(), &[]))
+                .await
+            {
+                Ok(0) => {}
+                Ok(_) => {
+                    result.num_broken += 1;
+                    USERS_DATA_RECONCILE_COUNTER_VEC
+                        .with_label_values(&["broken"])
+                        .inc();
+                    warn!(
+                        "{tracking_label} - \
+                        marked users_data id={} broken - {} is missing",
+                        record.data_id, record.sloc
+                    );
+                }
+                Err(e) => {
+                    USERS_DATA_RECONCILE_COUNTER_VEC
+                        .with_label_values(&["failed"])
+                        .inc();
+                    error!(
+                        "{tracking_label} - \
+                        failed to mark users_data id={} broken \
+                        with err='{e}'",
+                        record.data_id
+                    );
+                }
+            }
+        }
+        Ok(result)
+    }
+}
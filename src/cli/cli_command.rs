@@ -0,0 +1,133 @@
+//! Parse the api server's command line arguments
+//!
+use std::fmt;
+
+/// usage for the api server binary
+pub const CLI_USAGE: &str = "\
+usage: server [COMMAND]
+
+commands:
+  serve                  start the api server (default)
+  migrate                create the db schema or apply pending migrations
+  create-admin EMAIL     create an admin user (password from ADMIN_PASSWORD
+                         or generated and printed once)
+  rotate-jwt-keys [KID]  write a new jwt key pair to SERVER_PKI_DIR_JWT
+                         (KID defaults to the utc time YYYYMMDDHHMMSS)
+  check-config           check every tls file, jwt key and environment
+                         variable and exit with a report
+  help                   show this message";
+
+/// CliCommand
+///
+/// Subcommands supported by the api server binary
+///
+/// # Variants
+///
+/// * `Serve` - start the api server
+/// * `Migrate` - create the db schema or apply the pending
+///   migrations
+/// * `CreateAdmin` - create an admin user with the `email`
+/// * `RotateJwtKeys` - write a new jwt key pair with an
+///   optional `kid`
+/// * `CheckConfig` - validate the config and exit
+/// * `Help` - print the usage
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CliCommand {
+    Serve,
+    Migrate,
+    CreateAdmin { email: String },
+    RotateJwtKeys { kid: Option<String> },
+    CheckConfig,
+    Help,
+}
+
+impl fmt::Display for CliCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliCommand::Serve => write!(f, "serve"),
+            CliCommand::Migrate => write!(f, "migrate"),
+            CliCommand::CreateAdmin { .. } => write!(f, "create-admin"),
+            CliCommand::RotateJwtKeys { .. } => write!(f, "rotate-jwt-keys"),
+            CliCommand::CheckConfig => write!(f, "check-config"),
+            CliCommand::Help => write!(f, "help"),
+        }
+    }
+}
+
+/// parse_cli_args
+///
+/// Parse the arguments after the binary name into a
+/// [`CliCommand`](crate::cli::cli_command::CliCommand).
+/// No arguments starts the server, and ``--validate-config``
+/// is the same as ``check-config``.
+///
+/// # Arguments
+///
+/// * `args` - `&[String]` - arguments without the binary name
+///
+/// # Returns
+///
+/// Ok([`CliCommand`](crate::cli::cli_command::CliCommand))
+///
+/// # Errors
+///
+/// Err(err_msg: `String`) for an unknown command or missing
+/// or extra arguments
+///
+/// # Examples
+///
+/// ```rust
+/// use restapi::cli::cli_command::parse_cli_args;
+/// use restapi::cli::cli_command::CliCommand;
+/// let args = vec!["create-admin".to_string(), "ops@email.com".to_string()];
+/// assert_eq!(
+///     parse_cli_args(&args),
+///     Ok(CliCommand::CreateAdmin {
+///         email: "ops@email.com".to_string()
+///     })
+/// );
+/// assert_eq!(parse_cli_args(&[]), Ok(CliCommand::Serve));
+/// assert!(parse_cli_args(&["create-admin".to_string()]).is_err());
+/// ```
+///
+pub fn parse_cli_args(args: &[String]) -> Result<CliCommand, String> {
+    let command = match args.first() {
+        Some(command) => command.as_str(),
+        None => return Ok(CliCommand::Serve),
+    };
+    let values = &args[1..];
+    let (cli_command, max_values) = match command {
+        "serve" => (CliCommand::Serve, 0),
+        "migrate" => (CliCommand::Migrate, 0),
+        "create-admin" => match values.first() {
+            Some(email) => (
+                CliCommand::CreateAdmin {
+                    email: email.to_string(),
+                },
+                1,
+            ),
+            None => {
+                return Err("create-admin requires an EMAIL".to_string());
+            }
+        },
+        "rotate-jwt-keys" => (
+            CliCommand::RotateJwtKeys {
+                kid: values.first().cloned(),
+            },
+            1,
+        ),
+        "check-config" | "--validate-config" => (CliCommand::CheckConfig, 0),
+        "help" | "--help" | "-h" => (CliCommand::Help, 0),
+        _ => {
+            return Err(format!("unknown command '{command}'"));
+        }
+    };
+    if values.len() > max_values {
+        return Err(format!(
+            "{cli_command} has unexpected arguments: {}",
+            values[max_values..].join(" ")
+        ));
+    }
+    Ok(cli_command)
+}
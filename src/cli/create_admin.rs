@@ -0,0 +1,117 @@
+//! Create the first admin user for a new deployment
+//!
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use crate::core::core_config::CoreConfig;
+use crate::requests::models::api_error::ApiError;
+use crate::requests::models::tenant::DEFAULT_TENANT_ID;
+use crate::requests::models::user::ModelUser;
+use crate::requests::models::user::DEFAULT_USER_LOCALE;
+use crate::requests::models::user_repo::NewUser;
+use crate::requests::models::user_repo::UserRepo;
+use crate::requests::validation::field_rules::add_field_error;
+use crate::requests::validation::field_rules::check_email;
+use crate::requests::validation::field_rules::check_password;
+use crate::requests::validation::normalize_email::normalize_email;
+
+/// create_admin
+///
+/// Create an active and verified ``admin`` user in the
+/// ``default`` tenant
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client db
+///   threadpool with required tls encryption
+/// * `email` - `&str` - admin email
+/// * `password` - `&str` - admin password
+///
+/// # Returns
+///
+/// Ok([`ModelUser`](crate::requests::models::user::ModelUser)) -
+/// the new admin
+///
+/// # Errors
+///
+/// Err(err_msg: `String`) if the email or password is
+/// invalid, the email is already registered or the db
+/// insert fails
+///
+pub async fn create_admin(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    email: &str,
+    password: &str,
+) -> Result<ModelUser, String> {
+    let email = normalize_email(email);
+    let mut errors = Vec::new();
+    check_email(&mut errors, "email", &email);
+    check_password(&mut errors, "password", password);
+    let violations = config
+        .get_settings()
+        .password_policy
+        .get_violations(password);
+    if !violations.is_empty() {
+        add_field_error(&mut errors, "password", &violations.join(", "));
+    }
+    if !errors.is_empty() {
+        return Err(errors
+            .iter()
+            .map(|e| format!("{} {}", e.field, e.msg))
+            .collect::<Vec<String>>()
+            .join(" - "));
+    }
+
+    let hash = match config
+        .password_hashing
+        .hash_password(password, &config.server_password_salt)
+    {
+        Ok(hash) => hash,
+        Err(e) => {
+            return Err(format!(
+                "{tracking_label} - \
+                failed to hash the admin password - {e}"
+            ));
+        }
+    };
+    let conn = match db_pool.get().await {
+        Ok(conn) => conn,
+        Err(e) => {
+            return Err(format!(
+                "{tracking_label} - \
+                failed to get a db connection with err='{e}'"
+            ));
+        }
+    };
+    let new_user = NewUser {
+        email: email.clone(),
+        password_hash: hash,
+        state: 0,
+        verified: 1,
+        role: "admin".to_string(),
+        tenant_id: DEFAULT_TENANT_ID,
+        locale: DEFAULT_USER_LOCALE.to_string(),
+    };
+    match UserRepo::new(&conn).insert(tracking_label, &new_user).await {
+        Ok(created_user) => {
+            info!(
+                "{tracking_label} - created admin {email} id={}",
+                created_user.id
+            );
+            Ok(created_user)
+        }
+        Err(ApiError::Conflict(_)) => {
+            Err(format!("email {email} is already registered"))
+        }
+        Err(e) => Err(format!(
+            "{tracking_label} - \
+            failed to create admin {email} with err='{e}'"
+        )),
+    }
+}
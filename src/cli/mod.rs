@@ -0,0 +1,23 @@
+//! Subcommands for the api server binary so operators can
+//! bootstrap a deployment without psql
+//!
+//! ```bash
+//! # create the db schema or apply pending migrations
+//! ./target/debug/examples/server migrate
+//! # create the first admin user
+//! ADMIN_PASSWORD="..." ./target/debug/examples/server create-admin admin@example.com
+//! # write a new jwt key pair to SERVER_PKI_DIR_JWT
+//! ./target/debug/examples/server rotate-jwt-keys
+//! # check the tls files, jwt keys and environment variables
+//! ./target/debug/examples/server check-config
+//! # start the api server (same as no command)
+//! ./target/debug/examples/server serve
+//! ```
+//!
+//! See [`run_cli`](crate::cli::run_cli::run_cli) for
+//! embedding the subcommands in another binary.
+//!
+pub mod cli_command;
+pub mod create_admin;
+pub mod rotate_jwt_keys;
+pub mod run_cli;
@@ -0,0 +1,114 @@
+//! Write a new jwt key pair for rotating the signing key
+//!
+//! The new ``<kid>.private-key-pkcs8.pem`` and
+//! ``<kid>.public-key.pem`` files are written to
+//! ``SERVER_PKI_DIR_JWT`` (see
+//! [`jwt_keys`](crate::jwt::jwt_keys)). Running api
+//! servers load them on the next ``SIGHUP`` and sign new
+//! tokens with the newest ``kid`` unless the
+//! ``jwt_signing_kid`` runtime setting pins another key.
+//!
+use openssl::ec::EcGroup;
+use openssl::ec::EcKey;
+use openssl::nid::Nid;
+use openssl::pkey::PKey;
+
+use crate::jwt::jwt_keys::JwtKeyPaths;
+use crate::jwt::jwt_keys::DEFAULT_JWT_KID;
+
+/// rotate_jwt_keys
+///
+/// Generate an ES256 key pair and write it to the jwt key
+/// directory with a new ``kid``
+///
+/// # Arguments
+///
+/// * `jwt_key_paths` - [`JwtKeyPaths`](crate::jwt::jwt_keys::JwtKeyPaths) -
+///   key directory
+/// * `kid` - `Option<&str>` - new key id (defaults to the
+///   utc time ``YYYYMMDDHHMMSS`` so newer keys sort last)
+///
+/// # Returns
+///
+/// Ok((`kid`, `private_key_path`, `public_key_path`))
+///
+/// # Errors
+///
+/// Err(err_msg: `String`) if the kid is invalid, a key file
+/// for the kid already exists or the keys cannot be written
+///
+pub fn rotate_jwt_keys(
+    jwt_key_paths: &JwtKeyPaths,
+    kid: Option<&str>,
+) -> Result<(String, String, String), String> {
+    let kid = match kid {
+        Some(kid) => kid.to_string(),
+        None => chrono::Utc::now().format("%Y%m%d%H%M%S").to_string(),
+    };
+    if kid.is_empty()
+        || kid == DEFAULT_JWT_KID
+        || !kid
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(format!(
+            "invalid jwt kid '{kid}' - use letters, numbers, '-' or '_' \
+            (and not '{DEFAULT_JWT_KID}')"
+        ));
+    }
+    let dir = &jwt_key_paths.dir;
+    let private_key_path = format!("{dir}/{kid}.private-key-pkcs8.pem");
+    let public_key_path = format!("{dir}/{kid}.public-key.pem");
+    for path in [&private_key_path, &public_key_path] {
+        if std::path::Path::new(path).exists() {
+            return Err(format!(
+                "jwt key {path} already exists - use a new kid"
+            ));
+        }
+    }
+
+    let key = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)
+        .and_then(|group| EcKey::generate(&group))
+        .and_then(PKey::from_ec_key)
+        .map_err(|e| format!("failed to generate a jwt key with err='{e}'"))?;
+    let private_key = key.private_key_to_pem_pkcs8().map_err(|e| {
+        format!("failed to encode the jwt private key with err='{e}'")
+    })?;
+    let public_key = key.public_key_to_pem().map_err(|e| {
+        format!("failed to encode the jwt public key with err='{e}'")
+    })?;
+
+    std::fs::create_dir_all(dir)
+        .map_err(|e| format!("failed to create {dir} with err='{e}'"))?;
+    // write the public key first so servers never load a
+    // private key without its public key
+    std::fs::write(&public_key_path, public_key).map_err(|e| {
+        format!("failed to write {public_key_path} with err='{e}'")
+    })?;
+    write_private_key(&private_key_path, &private_key).map_err(|e| {
+        format!("failed to write {private_key_path} with err='{e}'")
+    })?;
+    Ok((kid, private_key_path, public_key_path))
+}
+
+/// write_private_key
+///
+/// Write a private key that only the owner can read
+///
+#[cfg(unix)]
+fn write_private_key(path: &str, contents: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?
+        .write_all(contents)
+}
+
+#[cfg(not(unix))]
+fn write_private_key(path: &str, contents: &[u8]) -> std::io::Result<()> {
+    std::fs::write(path, contents)
+}
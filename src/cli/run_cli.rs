@@ -0,0 +1,166 @@
+//! Run an api server subcommand
+//!
+use crate::cli::cli_command::parse_cli_args;
+use crate::cli::cli_command::CliCommand;
+use crate::cli::cli_command::CLI_USAGE;
+use crate::cli::create_admin::create_admin;
+use crate::cli::rotate_jwt_keys::rotate_jwt_keys;
+use crate::core::core_config::build_core_config;
+use crate::core::core_config::validate_core_config;
+use crate::core::core_config::CoreConfig;
+use crate::core::server::run_server::run_server;
+use crate::core::startup_error::format_startup_report;
+use crate::jwt::jwt_keys::JwtKeyPaths;
+use crate::pools::get_db_pool::get_db_pool;
+use crate::pools::run_migrations::run_migrations;
+use crate::settings::listen_for_settings_changes::reload_settings;
+use crate::utils::get_uuid::get_uuid;
+
+/// run_cli
+///
+/// Parse the command line arguments and run the
+/// [`CliCommand`](crate::cli::cli_command::CliCommand).
+/// Every command except ``rotate-jwt-keys`` and
+/// ``check-config`` builds the
+/// [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// from the same environment variables as the server.
+///
+/// # Supported Environment Variables
+///
+/// ```bash
+/// # password for create-admin (a random password is
+/// # generated and printed once when unset)
+/// export ADMIN_PASSWORD="..."
+/// ```
+///
+/// # Arguments
+///
+/// * `label` - `&str` - logging label
+/// * `args` - `&[String]` - arguments without the binary name
+///
+/// # Returns
+///
+/// `i32` - process exit code (`0` on success, `1` when the
+/// command fails and `2` for invalid arguments)
+///
+pub async fn run_cli(label: &str, args: &[String]) -> i32 {
+    let cli_command = match parse_cli_args(args) {
+        Ok(cli_command) => cli_command,
+        Err(err_msg) => {
+            eprintln!("{err_msg}\n\n{CLI_USAGE}");
+            return 2;
+        }
+    };
+    let result = match &cli_command {
+        CliCommand::Help => {
+            println!("{CLI_USAGE}");
+            Ok(())
+        }
+        CliCommand::CheckConfig => {
+            let errors = validate_core_config(label).await;
+            println!("{}", format_startup_report(&errors));
+            match errors.is_empty() {
+                true => Ok(()),
+                false => Err("invalid config".to_string()),
+            }
+        }
+        CliCommand::RotateJwtKeys { kid } => {
+            let jwt_key_paths =
+                JwtKeyPaths::build_jwt_key_paths(None, None, None);
+            rotate_jwt_keys(&jwt_key_paths, kid.as_deref()).map(
+                |(kid, private_key_path, public_key_path)| {
+                    println!(
+                        "created jwt kid={kid}\n\
+                        - {private_key_path}\n\
+                        - {public_key_path}\n\
+                        copy both files to every api server's \
+                        SERVER_PKI_DIR_JWT and reload with: kill -HUP <PID>"
+                    );
+                },
+            )
+        }
+        CliCommand::Serve => match get_core_config(label).await {
+            Ok(core_config) => match run_server(&core_config).await {
+                true => Ok(()),
+                false => Err("server stopped with an error".to_string()),
+            },
+            Err(err_msg) => Err(err_msg),
+        },
+        CliCommand::Migrate => migrate(label).await,
+        CliCommand::CreateAdmin { email } => {
+            run_create_admin(label, email).await
+        }
+    };
+    match result {
+        Ok(_) => 0,
+        Err(err_msg) => {
+            error!("{label} - {cli_command} failed - {err_msg}");
+            1
+        }
+    }
+}
+
+/// get_core_config
+///
+/// Build the
+/// [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// from environment variables
+///
+async fn get_core_config(label: &str) -> Result<CoreConfig, String> {
+    build_core_config(label).await.map_err(|e| {
+        format!(
+            "failed to build core config with err='{e}' - run with \
+            check-config to list every error"
+        )
+    })
+}
+
+/// migrate
+///
+/// Create the db schema or apply the pending migrations
+///
+async fn migrate(label: &str) -> Result<(), String> {
+    let core_config = get_core_config(label).await?;
+    let db_pool = get_db_pool(&core_config).await;
+    core_config
+        .startup_wait
+        .wait_for_db(label, &db_pool)
+        .await?;
+    let conn = db_pool
+        .get()
+        .await
+        .map_err(|e| format!("failed to get a db connection with err='{e}'"))?;
+    let applied = run_migrations(label, &conn).await?;
+    match applied.is_empty() {
+        true => println!("db is up to date"),
+        false => println!("applied: {}", applied.join(", ")),
+    }
+    Ok(())
+}
+
+/// run_create_admin
+///
+/// Create an admin with ``ADMIN_PASSWORD`` or a generated
+/// password
+///
+async fn run_create_admin(label: &str, email: &str) -> Result<(), String> {
+    let (password, generated) = match std::env::var("ADMIN_PASSWORD") {
+        Ok(password) => (password, false),
+        // upper and lower case so it meets any password policy
+        Err(_) => (
+            format!("{}-{}", get_uuid(), get_uuid().to_uppercase()),
+            true,
+        ),
+    };
+    let core_config = get_core_config(label).await?;
+    let db_pool = get_db_pool(&core_config).await;
+    // apply the password policy overrides from the db
+    reload_settings(label, &core_config, &db_pool).await?;
+    let admin =
+        create_admin(label, &core_config, &db_pool, email, &password).await?;
+    println!("created admin {} user_id={}", admin.email, admin.id);
+    if generated {
+        println!("password (shown once): {password}");
+    }
+    Ok(())
+}
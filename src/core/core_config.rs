@@ -5,8 +5,76 @@
 //! publishing enabled, jwt keys, user password salt,
 //! and postgres db credentials
 //!
-use crate::tls::get_tls_config::get_tls_config;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::RwLock;
+
+use crate::archive::user_data_archiver::UserDataArchiver;
+use crate::archive::user_data_lifecycle::UserDataLifecycle;
+use crate::archive::user_data_reconciler::UserDataReconciler;
+use crate::core::server::access_log::AccessLog;
+use crate::core::server::admission_control::AdmissionControl;
+use crate::core::server::connection_limits::ConnectionLimits;
+use crate::core::server::custom_route::CustomRoutes;
+use crate::core::server::geo_ip::GeoIp;
+use crate::core::server::ip_access::IpAccessControl;
+use crate::core::server::request_body_limits::RequestBodyLimits;
+use crate::core::server::request_deadline::RequestDeadline;
+use crate::core::server::response_format::ResponseFormat;
+use crate::core::server::rest_api_server::RestApiServerBuilder;
+use crate::core::server::startup_wait::StartupWait;
+use crate::core::server::static_assets::StaticAssets;
+use crate::core::server::tenant_resolver::TenantResolver;
+use crate::core::server::trusted_proxies::TrustedProxies;
+use crate::core::startup_error::StartupError;
+use crate::demo::demo_mode::DemoMode;
+use crate::i18n::message_localization::MessageLocalization;
+use crate::is3::object_store::build_object_store;
+use crate::is3::object_store::CircuitBreakerObjectStore;
+use crate::is3::object_store::ObjectStore;
+use crate::jwt::jwt_keys::check_jwt_key_files;
+use crate::jwt::jwt_keys::load_jwt_keys_from_paths;
+use crate::jwt::jwt_keys::JwtKeyPaths;
+use crate::jwt::jwt_keys::JwtKeys;
+use crate::jwt::jwt_keys::DEFAULT_JWT_KID;
+use crate::jwt::token_claims::load_token_custom_claims;
+use crate::jwt::token_claims::TokenClaimsProvider;
+use crate::jwt::token_claims::TokenCustomClaims;
+use crate::kafka::event_bus::EventBus;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::monitoring::auth_alerts::AuthAlerts;
+use crate::monitoring::otel::OtelConfig;
+use crate::notifications::email_templates::EmailTemplates;
+use crate::notifications::security_notifications::SecurityNotifications;
+use crate::pools::user_cache::UserCache;
+use crate::processing::upload_scanner::UploadScan;
+use crate::processing::user_data_pipeline::UserDataPipeline;
+use crate::processing::user_data_thumbnails::UserDataThumbnails;
+use crate::requests::admin::admin_stats_cache::AdminStatsCache;
+use crate::requests::auth::login_history::LoginHistory;
+use crate::requests::auth::login_risk::LoginRisk;
+use crate::requests::auth::token_cookie::TokenCookie;
+use crate::requests::user::otp_config::OtpConfig;
+use crate::requests::user::resumable_upload::ResumableUploadConfig;
+use crate::requests::user::s3_event_callback::S3EventCallback;
+use crate::requests::user::search_export::SearchExport;
+use crate::requests::user::user_data_quota::UserDataQuota;
+use crate::requests::user::user_delete_config::UserDeleteConfig;
+use crate::requests::user::user_upload_limit::UserUploadLimit;
+use crate::secrets::secrets_provider::get_jwt_key_paths_with_secrets;
+use crate::secrets::secrets_provider::get_jwt_keys_from_secrets;
+use crate::secrets::secrets_provider::Secrets;
+use crate::secrets::secrets_provider::SECRET_SERVER_PASSWORD_SALT;
+use crate::settings::runtime_settings::RuntimeSettings;
+use crate::settings::runtime_settings::SharedRuntimeSettings;
+use crate::tls::get_tls_config::check_tls_files;
+use crate::tls::get_tls_config::get_tls_config_from_paths;
+use crate::tls::get_tls_config::get_tls_paths;
+use crate::tls::get_tls_config::TlsPaths;
 use crate::tls::tls_config::TlsConfig;
+use crate::utils::circuit_breaker::CircuitBreaker;
+use crate::utils::password_hashing::PasswordHashing;
+use crate::utils::password_migration::PasswordMigration;
 
 /// CoreConfig
 ///
@@ -51,12 +119,60 @@ use crate::tls::tls_config::TlsConfig;
 /// export DB_NAME="mydb"
 /// ```
 ///
+/// ### Route read-only queries to postgres read replicas
+///
+/// Replicas use the same credentials, database name and
+/// ``POSTGRES_TLS_CA`` as the primary
+/// (see [`DbReadPools`](crate::pools::db_read_pools::DbReadPools))
+///
+/// ```bash
+/// # comma-separated replica addresses (empty = primary only)
+/// export POSTGRES_READ_ENDPOINTS="replica-0:5432,replica-1:5432"
+/// # max wait for a replica connection before using the next one
+/// export POSTGRES_READ_TIMEOUT_MS="500"
+/// # seconds before retrying a replica that was down
+/// export POSTGRES_READ_RETRY_SECONDS="10"
+/// ```
+///
+/// ### Limit postgres query time and log slow queries
+///
+/// (see [`db_query_limits`](crate::pools::db_query_limits))
+///
+/// ```bash
+/// # postgres cancels longer statements (0 = no timeout)
+/// export POSTGRES_STATEMENT_TIMEOUT_MS="30000"
+/// # log queries slower than this (0 = disabled)
+/// export POSTGRES_SLOW_QUERY_MS="500"
+/// ```
+///
 /// ### Change the user password salt for argon2 password hashing
 ///
 /// ```bash
 /// export SERVER_PASSWORD_SALT="PLEASE_CHANGE_ME"
 /// ```
 ///
+/// ### Tune the argon2 password hashing parameters
+///
+/// (see [`PasswordHashing`](crate::utils::password_hashing::PasswordHashing))
+///
+/// ```bash
+/// export PASSWORD_HASH_MEMORY_KIB="4096"
+/// export PASSWORD_HASH_ITERATIONS="3"
+/// export PASSWORD_HASH_PARALLELISM="1"
+/// # time one hash at startup and warn outside the target
+/// export PASSWORD_HASH_BENCHMARK="1"
+/// export PASSWORD_HASH_TARGET_MIN_MS="0"
+/// export PASSWORD_HASH_TARGET_MAX_MS="1000"
+/// ```
+///
+/// ### Rehash legacy password hashes at login
+///
+/// (see [`PasswordMigration`](crate::utils::password_migration::PasswordMigration))
+///
+/// ```bash
+/// export PASSWORD_HASH_REHASH_ON_LOGIN="1"
+/// ```
+///
 /// ## JWT using the `jsonwebtokens` crate and encrypted using `Algorithm::ES256` algorithm
 ///
 /// ### Change jwt private key
@@ -65,12 +181,93 @@ use crate::tls::tls_config::TlsConfig;
 /// export TOKEN_ALGO_PRIVATE_KEY="path/private-key-pkcs8.pem"
 /// ```
 ///
+/// ### Decrypt a passphrase-protected jwt private key
+///
+/// Encrypted PKCS#8 private keys (``BEGIN ENCRYPTED PRIVATE
+/// KEY``) are decrypted at startup (see
+/// [`decrypt_jwt_private_key`](crate::jwt::jwt_keys::decrypt_jwt_private_key))
+///
+/// ```bash
+/// export TOKEN_ALGO_PRIVATE_KEY_PASSPHRASE=""
+/// ```
+///
 /// ### Change jwt public key
 ///
 /// ```bash
 /// export TOKEN_ALGO_PUBLIC_KEY="path/public-key.pem"
 /// ```
 ///
+/// ### Change the directory for rotated jwt keys
+///
+/// Rotated keys use the naming convention
+/// `<kid>.private-key-pkcs8.pem` and `<kid>.public-key.pem`
+/// (see [`jwt_keys`](crate::jwt::jwt_keys))
+///
+/// ```bash
+/// export SERVER_PKI_DIR_JWT="./jwt"
+/// ```
+///
+/// ### Pin or retire jwt keys
+///
+/// Defaults for the ``jwt_signing_kid`` and ``jwt_retired_kids``
+/// runtime settings
+/// (see [`RuntimeSettings`](crate::settings::runtime_settings::RuntimeSettings))
+///
+/// ```bash
+/// # sign with this kid (empty = newest loaded key)
+/// export TOKEN_SIGNING_KID=""
+/// # comma-separated kids that are no longer accepted
+/// export TOKEN_RETIRED_KIDS=""
+/// ```
+///
+/// ### Add custom claims to every jwt
+///
+/// Per-user claims can be added with a
+/// [`TokenClaimsProvider`](crate::jwt::token_claims::TokenClaimsProvider)
+/// (see [`token_claims`](crate::jwt::token_claims))
+///
+/// ```bash
+/// export TOKEN_CUSTOM_CLAIMS='{"tenant_id":"acme","scopes":["read"]}'
+/// ```
+///
+/// ### Return the jwt in an HttpOnly cookie for browser apps
+///
+/// (see [`TokenCookie`](crate::requests::auth::token_cookie::TokenCookie))
+///
+/// ```bash
+/// # off, both (json token and cookie) or cookie (cookie only)
+/// export TOKEN_COOKIE_MODE="off"
+/// export TOKEN_COOKIE_NAME="restapi_token"
+/// # Strict, Lax or None
+/// export TOKEN_COOKIE_SAME_SITE="Strict"
+/// export TOKEN_COOKIE_DOMAIN=""
+/// export TOKEN_COOKIE_PATH="/"
+/// # double-submit csrf cookie and header
+/// export TOKEN_COOKIE_CSRF_NAME="restapi_csrf"
+/// export TOKEN_COOKIE_CSRF_HEADER="X-CSRF-Token"
+/// ```
+///
+/// ## Secrets Manager
+///
+/// ### Fetch the postgres password, password salt and jwt keys from vault or aws secrets manager
+///
+/// (see [`Secrets`](crate::secrets::secrets_provider::Secrets))
+///
+/// ```bash
+/// # vault or aws (empty = environment variables and files)
+/// export SECRETS_PROVIDER=""
+/// # fetch the secrets again (0 = only at startup)
+/// export SECRETS_REFRESH_INTERVAL_SECONDS="300"
+/// export SECRETS_TIMEOUT_MS="5000"
+/// export VAULT_ADDR="https://vault:8200"
+/// export VAULT_TOKEN=""
+/// export VAULT_TOKEN_FILE=""
+/// export VAULT_NAMESPACE=""
+/// export SECRETS_VAULT_PATH="secret/data/restapi"
+/// export SECRETS_AWS_SECRET_ID="restapi"
+/// export SECRETS_AWS_REGION="us-east-2"
+/// ```
+///
 /// ## Tls Environment Variables
 ///
 /// ### Change the `API Server` tls certificate authority, server key and cert
@@ -87,6 +284,612 @@ use crate::tls::tls_config::TlsConfig;
 /// export DB_TLS_CA="path/api-ca.pem"
 /// ```
 ///
+/// ## Kafka
+///
+/// ### Publish user events to kafka
+///
+/// ```bash
+/// export KAFKA_PUBLISH_EVENTS="true"
+/// export KAFKA_TOPIC_USER_EVENTS="user.events"
+/// export KAFKA_EXCLUDE_EVENTS="DATA_DOWNLOADED"
+/// ```
+///
+/// ### Route user events to other topics
+///
+/// (see [`EventBus::get_event_topic`](crate::kafka::event_bus::EventBus::get_event_topic))
+///
+/// ```bash
+/// export USER_EVENTS_TOPIC_LOGIN="user.logins"
+/// export USER_EVENTS_TOPIC_DATA="user.data"
+/// ```
+///
+/// ## Login Throttling
+///
+/// ### Lock an email or ip after failed logins
+///
+/// (see [`LoginThrottle`](crate::requests::auth::login_throttle::LoginThrottle))
+///
+/// ```bash
+/// export LOGIN_THROTTLE_ENABLED="1"
+/// export LOGIN_THROTTLE_MAX_FAILURES="5"
+/// export LOGIN_THROTTLE_BASE_DELAY_SECONDS="1"
+/// export LOGIN_THROTTLE_MAX_DELAY_SECONDS="900"
+/// export LOGIN_THROTTLE_RESET_SECONDS="3600"
+/// ```
+///
+/// ## Login Risk Scoring
+///
+/// ### Allow, challenge or deny password logins with a custom risk engine
+///
+/// (see [`LoginRisk`](crate::requests::auth::login_risk::LoginRisk))
+///
+/// ```bash
+/// # decision when the hook fails: allow, challenge or deny
+/// export LOGIN_RISK_ON_ERROR="allow"
+/// export LOGIN_RISK_HISTORY_LIMIT="20"
+/// ```
+///
+/// ## Login History
+///
+/// ### Record login attempts for ``GET /user/logins``
+///
+/// (see [`LoginHistory`](crate::requests::auth::login_history::LoginHistory))
+///
+/// ```bash
+/// export USER_LOGINS_ENABLED="1"
+/// # delete a user's attempts older than this (0 = keep all)
+/// export USER_LOGINS_RETENTION_DAYS="90"
+/// ```
+///
+/// ## Auth Failure Alerts
+///
+/// ### Alert operators when auth failures cross a threshold
+///
+/// (see [`AuthAlerts`](crate::monitoring::auth_alerts::AuthAlerts))
+///
+/// ```bash
+/// # json POST for each alert (empty = log only)
+/// export AUTH_ALERT_WEBHOOK_URL="https://alerts.example.com/hooks/restapi"
+/// export AUTH_ALERT_WEBHOOK_TIMEOUT_MS="5000"
+/// export AUTH_ALERT_WINDOW_SECONDS="60"
+/// # failures in a window that trigger an alert (0 = off)
+/// export AUTH_ALERT_LOGIN_THRESHOLD="50"
+/// export AUTH_ALERT_OTP_THRESHOLD="20"
+/// export AUTH_ALERT_TOKEN_THRESHOLD="100"
+/// export AUTH_ALERT_COOLDOWN_SECONDS="300"
+/// ```
+///
+/// ## Runtime Settings
+///
+/// ### Defaults for settings admins can override at runtime
+///
+/// (see [`RuntimeSettings`](crate::settings::runtime_settings::RuntimeSettings))
+///
+/// ```bash
+/// # max user data upload size in bytes (0 = unlimited)
+/// export MAX_UPLOAD_SIZE_BYTES="0"
+/// # reject non-admin requests with a 503
+/// export MAINTENANCE_MODE="0"
+/// # Retry-After header for requests rejected in
+/// # maintenance mode
+/// export MAINTENANCE_RETRY_AFTER_SECONDS="60"
+/// # password policy for new and changed passwords
+/// export PASSWORD_MIN_LENGTH="4"
+/// export PASSWORD_REQUIRE_DIGIT="0"
+/// export PASSWORD_REQUIRE_SYMBOL="0"
+/// export PASSWORD_REQUIRE_MIXED_CASE="0"
+/// ```
+///
+/// ## User Data Archive
+///
+/// ### Move old users_data rows to the users_data_archive table
+///
+/// (see [`UserDataArchiver`](crate::archive::user_data_archiver::UserDataArchiver))
+///
+/// ```bash
+/// export USERS_DATA_ARCHIVE_ENABLED="0"
+/// export USERS_DATA_ARCHIVE_AFTER_DAYS="90"
+/// export USERS_DATA_ARCHIVE_BATCH_SIZE="1000"
+/// export USERS_DATA_ARCHIVE_INTERVAL_SECONDS="3600"
+/// export USERS_DATA_ARCHIVE_S3_EXPORT="0"
+/// export USERS_DATA_ARCHIVE_S3_BUCKET="BUCKET_NAME"
+/// export USERS_DATA_ARCHIVE_S3_PREFIX="user/data/archive"
+/// ```
+///
+/// ## User Data Lifecycle
+///
+/// ### Delete or transition uploads after their expire_days
+///
+/// (see [`UserDataLifecycle`](crate::archive::user_data_lifecycle::UserDataLifecycle))
+///
+/// ```bash
+/// export USERS_DATA_LIFECYCLE_ENABLED="0"
+/// export USERS_DATA_LIFECYCLE_BATCH_SIZE="100"
+/// export USERS_DATA_LIFECYCLE_INTERVAL_SECONDS="3600"
+/// ```
+///
+/// ## User Data Reconcile
+///
+/// ### Find orphan s3 keys and records without an s3 key
+///
+/// (see [`UserDataReconciler`](crate::archive::user_data_reconciler::UserDataReconciler))
+///
+/// ```bash
+/// export USERS_DATA_RECONCILE_ENABLED="0"
+/// export USERS_DATA_RECONCILE_DRY_RUN="1"
+/// export USERS_DATA_RECONCILE_INTERVAL_SECONDS="86400"
+/// export USERS_DATA_RECONCILE_MIN_AGE_SECONDS="86400"
+/// export USERS_DATA_RECONCILE_BATCH_SIZE="100"
+/// export USERS_DATA_RECONCILE_MAX_KEYS="10000"
+/// ```
+///
+/// ## User Data Pipeline
+///
+/// ### Scan or process uploads before they can be downloaded
+///
+/// New uploads start as ``pending`` and a
+/// [`UserDataProcessor`](crate::processing::user_data_processor::UserDataProcessor)
+/// set on ``user_data_pipeline.processor`` marks them
+/// ``ready``, ``quarantined`` or ``failed``
+/// (see [`UserDataPipeline`](crate::processing::user_data_pipeline::UserDataPipeline))
+///
+/// ```bash
+/// export USERS_DATA_PIPELINE_ENABLED="0"
+/// export USERS_DATA_PIPELINE_BATCH_SIZE="10"
+/// export USERS_DATA_PIPELINE_INTERVAL_SECONDS="5"
+/// export USERS_DATA_PIPELINE_TIMEOUT_SECONDS="300"
+/// ```
+///
+/// ## Upload Scanning
+///
+/// ### Scan uploads for viruses before they are stored
+///
+/// (see [`UploadScan`](crate::processing::upload_scanner::UploadScan))
+///
+/// ```bash
+/// # clamd host:port or a webhook url (empty = disabled)
+/// export UPLOAD_SCAN_CLAMD_ADDRESS="localhost:3310"
+/// export UPLOAD_SCAN_WEBHOOK_URL=""
+/// # reject or quarantine infected files
+/// export UPLOAD_SCAN_ACTION="reject"
+/// # reject or allow uploads that could not be scanned
+/// export UPLOAD_SCAN_ON_ERROR="reject"
+/// export UPLOAD_SCAN_TIMEOUT_MS="30000"
+/// ```
+///
+/// ## User Data Thumbnails
+///
+/// ### Create thumbnails for image uploads
+///
+/// Requires the ``thumbnails`` feature
+/// (see [`UserDataThumbnails`](crate::processing::user_data_thumbnails::UserDataThumbnails))
+///
+/// ```bash
+/// export USERS_DATA_THUMBNAILS_ENABLED="0"
+/// export USERS_DATA_THUMBNAILS_SIZES="128,512"
+/// export USERS_DATA_THUMBNAILS_BATCH_SIZE="10"
+/// export USERS_DATA_THUMBNAILS_INTERVAL_SECONDS="5"
+/// export USERS_DATA_THUMBNAILS_TIMEOUT_SECONDS="120"
+/// export USERS_DATA_THUMBNAILS_MAX_SOURCE_BYTES="26214400"
+/// export S3_DATA_DERIVATIVES_PREFIX="user/data/derivatives"
+/// ```
+///
+/// ## Resumable Uploads
+///
+/// ### Upload large files in Content-Range chunks
+///
+/// (see [`ResumableUploadConfig`](crate::requests::user::resumable_upload::ResumableUploadConfig))
+///
+/// ```bash
+/// export UPLOAD_RESUMABLE_MIN_CHUNK_BYTES="5242880"
+/// export UPLOAD_RESUMABLE_MAX_CHUNK_BYTES="67108864"
+/// export UPLOAD_RESUMABLE_MAX_BYTES="53687091200"
+/// export UPLOAD_RESUMABLE_EXPIRE_HOURS="24"
+/// ```
+///
+/// ## User Data Quotas
+///
+/// ### Limit the bytes each user can store
+///
+/// (see [`UserDataQuota`](crate::requests::user::user_data_quota::UserDataQuota))
+///
+/// ```bash
+/// export USER_DATA_QUOTA_BYTES="0"
+/// export USER_DATA_QUOTA_METRICS_INTERVAL_SECONDS="300"
+/// ```
+///
+/// ## User Upload Limits
+///
+/// ### Limit each user's concurrent uploads
+///
+/// (see [`UserUploadLimit`](crate::requests::user::user_upload_limit::UserUploadLimit))
+///
+/// ```bash
+/// export USER_DATA_MAX_CONCURRENT_UPLOADS="4"
+/// export USER_DATA_UPLOAD_RETRY_AFTER_SECONDS="1"
+/// ```
+///
+/// ## S3 Event Callbacks
+///
+/// ### Complete direct-to-s3 uploads from signed s3 event notifications
+///
+/// (see [`S3EventCallback`](crate::requests::user::s3_event_callback::S3EventCallback))
+///
+/// ```bash
+/// export S3_EVENTS_SECRET=""
+/// export S3_EVENTS_MAX_AGE_SECONDS="300"
+/// ```
+///
+/// ## User Notifications
+///
+/// ### Store user events and stream them with server-sent events
+///
+/// (see [`UserNotifications`](crate::notifications::user_notifications::UserNotifications)
+/// on ``events.notifications``)
+///
+/// ```bash
+/// export USER_NOTIFICATIONS_ENABLED="0"
+/// export USER_NOTIFICATIONS_POLL_INTERVAL_MS="1000"
+/// export USER_NOTIFICATIONS_KEEP_ALIVE_SECONDS="15"
+/// export USER_NOTIFICATIONS_MAX_STREAM_SECONDS="3600"
+/// ```
+///
+/// ## Webhooks
+///
+/// ### Send user events to admin-registered webhooks
+///
+/// (see [`WebhookDispatcher`](crate::webhooks::webhook_dispatcher::WebhookDispatcher)
+/// on ``events.webhooks``)
+///
+/// ```bash
+/// export WEBHOOKS_ENABLED="0"
+/// export WEBHOOKS_INTERVAL_MS="1000"
+/// export WEBHOOKS_BATCH_SIZE="50"
+/// export WEBHOOKS_MAX_ATTEMPTS="8"
+/// export WEBHOOKS_RETRY_SECONDS="30"
+/// export WEBHOOKS_TIMEOUT_MS="5000"
+/// export WEBHOOKS_ALLOW_HTTP="0"
+/// ```
+///
+/// ## Kafka Dead Letters
+///
+/// ### Retry rejected kafka publishes and store the failures
+///
+/// (see [`KafkaDeadLetters`](crate::kafka::kafka_dead_letters::KafkaDeadLetters)
+/// on ``events.dead_letters``)
+///
+/// ```bash
+/// export KAFKA_DEAD_LETTERS_ENABLED="1"
+/// export KAFKA_PUBLISH_MAX_RETRIES="3"
+/// export KAFKA_PUBLISH_RETRY_MS="200"
+/// export KAFKA_MAX_PENDING_MSGS="10000"
+/// ```
+///
+/// ## Kafka Schema Registry
+///
+/// ### Register the user event schema and publish framed avro or json events
+///
+/// (see [`SchemaRegistry`](crate::kafka::schema_registry::SchemaRegistry)
+/// on ``events.dead_letters.schema_registry``)
+///
+/// ```bash
+/// export KAFKA_SCHEMA_REGISTRY_URL="https://schema-registry:8081"
+/// export KAFKA_SCHEMA_REGISTRY_FORMAT="avro"
+/// export KAFKA_SCHEMA_REGISTRY_USERNAME=""
+/// export KAFKA_SCHEMA_REGISTRY_PASSWORD=""
+/// export KAFKA_SCHEMA_REGISTRY_AUTO_REGISTER="1"
+/// export KAFKA_SCHEMA_REGISTRY_TIMEOUT_MS="5000"
+/// ```
+///
+/// ## Email Templates
+///
+/// ### Publish verify, otp, invite and security emails rendered in the user's locale
+///
+/// (see [`EmailTemplates`](crate::notifications::email_templates::EmailTemplates))
+///
+/// ```bash
+/// export EMAIL_TEMPLATES_ENABLED="0"
+/// # subdirectory for each locale (empty = built-in en templates)
+/// export EMAIL_TEMPLATES_DIR="./templates/email"
+/// export EMAIL_DEFAULT_LOCALE="en"
+/// export KAFKA_TOPIC_USER_EMAILS="user.emails"
+/// export EMAIL_VERIFY_URL="https://app.example.com/verify"
+/// export EMAIL_INVITE_URL="https://app.example.com/invite"
+/// ```
+///
+/// ## Security Notifications
+///
+/// ### Notify users about password, email and passkey changes and logins from a new device or location
+///
+/// (see [`SecurityNotifications`](crate::notifications::security_notifications::SecurityNotifications))
+///
+/// ```bash
+/// export SECURITY_NOTIFICATIONS_EVENTS="1"
+/// export SECURITY_NOTIFICATIONS_EMAILS="1"
+/// export SECURITY_NOTIFICATIONS_NEW_LOGINS="1"
+/// # publish USER_SECURITY_CHANGE events to their own topic
+/// export USER_EVENTS_TOPIC_SECURITY="user.security"
+/// # publish LOGIN_UNRECOGNIZED events to the same topic
+/// export USER_EVENTS_TOPIC_UNRECOGNIZED="user.security"
+/// ```
+///
+/// ## Message Localization
+///
+/// ### Translate the response messages with Accept-Language or users.locale
+///
+/// (see [`MessageLocalization`](crate::i18n::message_localization::MessageLocalization))
+///
+/// ```bash
+/// export MESSAGE_LOCALIZATION_ENABLED="0"
+/// # {locale}.json catalogs (empty = built-in catalogs)
+/// export MESSAGE_CATALOG_DIR="./templates/messages"
+/// export MESSAGE_DEFAULT_LOCALE="en"
+/// ```
+///
+/// ## Response Formatting
+///
+/// ### Trim json responses with ?fields= and indent them with ?pretty=1
+///
+/// (see [`ResponseFormat`](crate::core::server::response_format::ResponseFormat))
+///
+/// ```bash
+/// export RESPONSE_FORMAT_ENABLED="1"
+/// # larger json responses are sent unchanged
+/// export RESPONSE_FORMAT_MAX_BODY_BYTES="4194304"
+/// ```
+///
+/// ## Search Exports
+///
+/// ### Stream every matching search row with Accept: text/csv or application/x-ndjson
+///
+/// (see [`SearchExport`](crate::requests::user::search_export::SearchExport))
+///
+/// ```bash
+/// export SEARCH_EXPORT_ENABLED="1"
+/// # rows read from the db cursor at a time
+/// export SEARCH_EXPORT_BATCH_ROWS="500"
+/// ```
+///
+/// ## Demo Mode
+///
+/// ### Seed demo users and data and store files locally
+///
+/// (see [`DemoMode`](crate::demo::demo_mode::DemoMode))
+///
+/// ```bash
+/// export DEMO_MODE="1"
+/// export DEMO_S3_DIR="./demo-s3"
+/// export DEMO_PASSWORD="demo-password"
+/// ```
+///
+/// ## Object Store
+///
+/// ### Store user files in s3 or a local directory
+///
+/// (see [`ObjectStore`](crate::is3::object_store::ObjectStore))
+///
+/// Files are stored in s3 unless ``DEMO_MODE=1`` (the
+/// ``DEMO_S3_DIR`` directory). Servers built without the
+/// ``s3`` cargo feature reject uploads outside of demo mode.
+/// Replace the ``object_store`` before starting the server
+/// for other backends.
+///
+/// ## Circuit Breakers
+///
+/// ### Fail fast while s3 or kafka keep failing
+///
+/// (see [`CircuitBreaker`](crate::utils::circuit_breaker::CircuitBreaker))
+///
+/// ```bash
+/// # open after this many consecutive failures (0 = disabled)
+/// export S3_CIRCUIT_BREAKER_FAILURE_THRESHOLD="5"
+/// # reject calls for this long before a trial call
+/// export S3_CIRCUIT_BREAKER_OPEN_SECONDS="30"
+/// export KAFKA_CIRCUIT_BREAKER_FAILURE_THRESHOLD="5"
+/// export KAFKA_CIRCUIT_BREAKER_OPEN_SECONDS="30"
+/// ```
+///
+/// ## Request Deadlines
+///
+/// ### Abandon requests after the client's timeout
+///
+/// (see [`RequestDeadline`](crate::core::server::request_deadline::RequestDeadline))
+///
+/// ```bash
+/// # deadline for requests without a timeout header (0 = none)
+/// export REQUEST_TIMEOUT_DEFAULT_MS="0"
+/// # max deadline a client can ask for
+/// export REQUEST_TIMEOUT_MAX_MS="60000"
+/// ```
+///
+/// ## Request Body Limits
+///
+/// ### Reject oversized bodies and unsupported content types
+///
+/// (see [`RequestBodyLimits`](crate::core::server::request_body_limits::RequestBodyLimits))
+///
+/// ```bash
+/// export REQUEST_ENFORCE_JSON_CONTENT_TYPE="1"
+/// export REQUEST_MAX_BODY_BYTES="262144"
+/// export REQUEST_ROUTE_MAX_BODY_BYTES="auth=16384"
+/// export UPLOAD_ALLOWED_CONTENT_TYPES="text/*,application/json,image/png"
+/// ```
+///
+/// ## Multi-Tenancy
+///
+/// ### Resolve each request's tenant from a header or subdomain
+///
+/// (see [`TenantResolver`](crate::core::server::tenant_resolver::TenantResolver))
+///
+/// ```bash
+/// # off, header or subdomain
+/// export TENANT_MODE="header"
+/// export TENANT_HEADER="X-Tenant"
+/// export TENANT_BASE_DOMAIN="api.example.com"
+/// export TENANT_DEFAULT="default"
+/// ```
+///
+/// ## Trusted Proxies
+///
+/// ### Use the client address forwarded by a load balancer
+///
+/// (see [`TrustedProxies`](crate::core::server::trusted_proxies::TrustedProxies))
+///
+/// ```bash
+/// # comma-separated proxy networks (empty = disabled)
+/// export TRUSTED_PROXY_CIDRS="10.0.0.0/8,172.16.0.0/12"
+/// ```
+///
+/// ## IP Allowlists and Denylists
+///
+/// ### Reject client addresses globally or for route prefixes
+///
+/// (see [`IpAccessControl`](crate::core::server::ip_access::IpAccessControl) -
+/// admins add more rules with ``POST /admin/ip-rules``)
+///
+/// ```bash
+/// # comma-separated networks (empty = any address)
+/// export IP_ALLOWLIST=""
+/// export IP_DENYLIST=""
+/// # comma-separated prefix=cidr|cidr pairs
+/// export IP_ROUTE_ALLOWLIST="/admin=10.0.0.0/8,/metrics=127.0.0.1"
+/// export IP_ROUTE_DENYLIST=""
+/// ```
+///
+/// ## GeoIP
+///
+/// ### Store the country and city for each new session
+///
+/// (see [`GeoIp`](crate::core::server::geo_ip::GeoIp) -
+/// requires ``--features geoip``)
+///
+/// ```bash
+/// export GEOIP_DB_PATH="/usr/share/GeoIP/GeoLite2-City.mmdb"
+/// ```
+///
+/// ## Static Assets
+///
+/// ### Serve a frontend from the same TLS listener
+///
+/// (see [`StaticAssets`](crate::core::server::static_assets::StaticAssets))
+///
+/// ```bash
+/// # directory with the frontend build (empty = disabled)
+/// export STATIC_ASSETS_DIR="./web/dist"
+/// export STATIC_ASSETS_MAX_AGE_SECONDS="3600"
+/// ```
+///
+/// ## Admission Control
+///
+/// ### Shed low priority requests when the server is overloaded
+///
+/// (see [`AdmissionControl`](crate::core::server::admission_control::AdmissionControl))
+///
+/// ```bash
+/// export ADMISSION_CONTROL_ENABLED="0"
+/// export ADMISSION_MAX_IN_FLIGHT="512"
+/// export ADMISSION_MAX_POOL_WAIT_MS="500"
+/// export ADMISSION_PROBE_INTERVAL_MS="250"
+/// export ADMISSION_SHED_LOW_AT="0.7"
+/// export ADMISSION_SHED_NORMAL_AT="0.85"
+/// export ADMISSION_SHED_HIGH_AT="1.0"
+/// export ADMISSION_RETRY_AFTER_SECONDS="1"
+/// export ADMISSION_ROUTE_PRIORITIES="auth=high,admin=high,user=normal,data=low,search=low"
+/// ```
+///
+/// ## Connection Limits
+///
+/// ### Limit open connections and tune hyper
+///
+/// (see [`ConnectionLimits`](crate::core::server::connection_limits::ConnectionLimits))
+///
+/// ```bash
+/// export API_MAX_CONNECTIONS="10000"
+/// export API_TLS_HANDSHAKE_TIMEOUT_MS="10000"
+/// export API_HTTP1_KEEP_ALIVE="1"
+/// export API_HTTP1_HEADER_READ_TIMEOUT_MS="30000"
+/// export API_HTTP2_KEEP_ALIVE_INTERVAL_SECONDS="0"
+/// export API_HTTP2_KEEP_ALIVE_TIMEOUT_SECONDS="20"
+/// export API_MAX_BUF_SIZE_BYTES="0"
+/// ```
+///
+/// ## Startup Wait
+///
+/// ### Retry postgres and kafka before starting
+///
+/// (see [`StartupWait`](crate::core::server::startup_wait::StartupWait))
+///
+/// ```bash
+/// # 0 = no checks
+/// export STARTUP_WAIT_SECONDS="0"
+/// export STARTUP_RETRY_MS="500"
+/// export STARTUP_MAX_RETRY_MS="5000"
+/// # start with kafka publishing disabled instead of failing
+/// export STARTUP_KAFKA_DEGRADED="0"
+/// ```
+///
+/// ## User One-Time-Use Passwords
+///
+/// ### Configure one-time-use password reset tokens
+///
+/// (see [`OtpConfig`](crate::requests::user::otp_config::OtpConfig))
+///
+/// ```bash
+/// export USER_OTP_EXP_IN_SECONDS="2592000"
+/// # uuid, alphanumeric, hex or numeric
+/// export USER_OTP_TOKEN_CHARSET="uuid"
+/// export USER_OTP_TOKEN_LENGTH="32"
+/// # max otps per user within the window (0 = unlimited)
+/// export USER_OTP_RATE_LIMIT_MAX="5"
+/// export USER_OTP_RATE_LIMIT_WINDOW_SECONDS="3600"
+/// # wrong tokens before the active otp is locked (0 = unlimited)
+/// export USER_OTP_MAX_ATTEMPTS="5"
+/// # response or email
+/// export USER_OTP_DELIVERY="response"
+/// # emailed reset link for /user/password/forgot
+/// export USER_OTP_RESET_URL="https://app.example.com/reset-password"
+/// ```
+///
+/// ## User Self-Service Deletion
+///
+/// ### Configure account deletion confirmation tokens
+///
+/// (see [`UserDeleteConfig`](crate::requests::user::user_delete_config::UserDeleteConfig))
+///
+/// ```bash
+/// export USER_DELETE_EXP_IN_SECONDS="86400"
+/// export USER_DELETE_PURGE_DAYS="30"
+/// # emailed confirmation link for /user/delete/request
+/// export USER_DELETE_CONFIRM_URL="https://app.example.com/delete-account"
+/// ```
+///
+/// ## Admin Stats
+///
+/// ### Cache the aggregate stats for GET /admin/stats
+///
+/// (see [`AdminStatsCache`](crate::requests::admin::admin_stats_cache::AdminStatsCache))
+///
+/// ```bash
+/// # 0 computes the stats for every request
+/// export ADMIN_STATS_CACHE_SECONDS="60"
+/// ```
+///
+/// ## Cache
+///
+/// ### Cache user lookups and token checks
+///
+/// (see [`UserCache`](crate::pools::user_cache::UserCache))
+///
+/// ```bash
+/// # none, memory or redis
+/// export CACHE_BACKEND="none"
+/// export CACHE_TTL_SECONDS="30"
+/// export CACHE_MAX_ENTRIES="10000"
+/// # requires building with --features redis
+/// export CACHE_REDIS_URL="redis://127.0.0.1:6379"
+/// export CACHE_KEY_PREFIX="restapi"
+/// ```
+///
 /// ## Logging
 ///
 /// ### Set the server name for the logs
@@ -95,6 +898,31 @@ use crate::tls::tls_config::TlsConfig;
 /// export SERVER_NAME_LABEL="my-server"
 /// ```
 ///
+/// ## Access Log
+///
+/// ### Write one json line for each request
+///
+/// (see [`AccessLog`](crate::core::server::access_log::AccessLog))
+///
+/// ```bash
+/// export ACCESS_LOG_ENABLED="0"
+/// # stdout or a file path (lines are appended)
+/// export ACCESS_LOG_PATH="stdout"
+/// ```
+///
+/// ## OpenTelemetry Tracing
+///
+/// ### Export request, db, s3 and kafka spans over OTLP
+///
+/// (see [`OtelConfig`](crate::monitoring::otel::OtelConfig),
+/// requires building with ``--features otel``)
+///
+/// ```bash
+/// export OTEL_EXPORTER_OTLP_ENDPOINT="http://localhost:4317"
+/// export OTEL_SERVICE_NAME="restapi"
+/// export OTEL_TRACES_SAMPLER_ARG="1.0"
+/// ```
+///
 /// ## Debug
 ///
 /// At startup, print a curl connectivity command
@@ -110,16 +938,131 @@ pub struct CoreConfig {
     pub label: String,
     pub server_address: String,
     pub server_password_salt: Vec<u8>,
+    /// argon2 parameters for new password hashes
+    pub password_hashing: PasswordHashing,
+    /// rehash legacy password hashes at login
+    pub password_migration: PasswordMigration,
     pub api_config: TlsConfig,
     pub db_conn_type: String,
     pub db_username: String,
     pub db_password: String,
     pub db_address: String,
+    /// read replica addresses for read-only queries
+    pub db_read_addresses: Vec<String>,
+    /// max milliseconds to wait for a replica connection
+    pub db_read_timeout_ms: u64,
+    /// seconds before retrying a replica that was down
+    pub db_read_retry_seconds: u64,
+    /// postgres ``statement_timeout`` (``0`` = no timeout)
+    pub db_statement_timeout_ms: u64,
+    /// log queries slower than this (``0`` = disabled)
+    pub db_slow_query_ms: u64,
     pub db_name: String,
     pub db_config: TlsConfig,
     pub encoding_key_bytes: Vec<u8>,
     pub decoding_key_bytes: Vec<u8>,
+    /// jwt key ring shared across connections
+    /// (reloaded on ``SIGHUP``)
+    pub jwt_keys: Arc<RwLock<JwtKeys>>,
+    /// where the jwt key ring is reloaded from
+    pub jwt_key_paths: JwtKeyPaths,
+    /// optional secrets manager for the postgres password,
+    /// password salt and jwt keys
+    pub secrets: Secrets,
+    /// custom claims added to every new jwt
+    pub token_claims: TokenCustomClaims,
+    /// optional hook for adding per-user claims
+    /// to new jwts at login time
+    pub token_claims_provider: Option<Arc<dyn TokenClaimsProvider>>,
+    /// optional risk engine for password logins
+    pub login_risk: LoginRisk,
+    /// record login attempts for ``GET /user/logins``
+    pub login_history: LoginHistory,
+    /// return and accept the jwt in an HttpOnly cookie
+    pub token_cookie: TokenCookie,
+    /// deprecated - use `events.enabled` or the
+    /// [`EventBus`](crate::kafka::event_bus::EventBus)
+    /// methods instead of checking this flag in handlers
     pub kafka_publish_events: bool,
+    pub events: EventBus,
+    /// kafka publisher started before the server (``None``
+    /// starts one from the ``KAFKA_*`` environment variables)
+    pub kafka_pool: Option<KafkaPublisher>,
+    /// runtime settings shared across connections
+    /// (reloaded when the ``settings`` table changes)
+    pub settings: SharedRuntimeSettings,
+    /// archive old ``users_data`` rows
+    pub user_data_archiver: UserDataArchiver,
+    /// expire uploads with a lifecycle policy
+    pub user_data_lifecycle: UserDataLifecycle,
+    /// reconcile ``users_data`` records with the keys in s3
+    pub user_data_reconciler: UserDataReconciler,
+    /// upload status pipeline and optional processor
+    pub user_data_pipeline: UserDataPipeline,
+    /// virus scanning for uploads
+    pub upload_scan: UploadScan,
+    /// thumbnails for image uploads
+    pub user_data_thumbnails: UserDataThumbnails,
+    /// chunk limits and expiry for resumable uploads
+    pub resumable_uploads: ResumableUploadConfig,
+    /// per-user storage quotas and usage gauges
+    pub user_data_quota: UserDataQuota,
+    /// per-user concurrent upload permits
+    pub user_upload_limit: UserUploadLimit,
+    /// hmac settings for s3 event notification callbacks
+    pub s3_event_callback: S3EventCallback,
+    /// seeded demo data and local s3 directory
+    pub demo_mode: DemoMode,
+    /// storage for uploaded files and archive exports
+    pub object_store: Arc<dyn ObjectStore>,
+    /// fail fast while s3 keeps failing
+    pub s3_circuit_breaker: CircuitBreaker,
+    /// per-request deadlines from timeout headers
+    pub request_deadline: RequestDeadline,
+    /// body size and content type limits for each route
+    pub request_body_limits: RequestBodyLimits,
+    /// resolve each request's tenant
+    pub tenants: TenantResolver,
+    /// proxies allowed to forward the client address
+    pub trusted_proxies: TrustedProxies,
+    /// ip allowlists and denylists checked before routing
+    pub ip_access: IpAccessControl,
+    /// optional country and city lookups for sessions
+    pub geo_ip: GeoIp,
+    /// optional frontend served for unmatched GET requests
+    pub static_assets: StaticAssets,
+    /// shed requests by priority class under load
+    pub admission_control: AdmissionControl,
+    /// max open connections and hyper connection tuning
+    pub connection_limits: ConnectionLimits,
+    /// wait for postgres and kafka at startup
+    pub startup_wait: StartupWait,
+    /// one-time-use password reset token settings
+    pub otp: OtpConfig,
+    /// self-service account deletion settings
+    pub user_delete: UserDeleteConfig,
+    /// per-locale verify, otp, invite and security email templates
+    pub email_templates: EmailTemplates,
+    /// password, email and passkey change notifications
+    pub security_notifications: SecurityNotifications,
+    /// translated response messages
+    pub message_localization: MessageLocalization,
+    /// ?fields= and ?pretty= json response formatting
+    pub response_format: ResponseFormat,
+    /// csv and ndjson search exports
+    pub search_export: SearchExport,
+    /// optional cache for user lookups and token checks
+    pub user_cache: UserCache,
+    /// auth failure counters and threshold alerts
+    pub auth_alerts: AuthAlerts,
+    /// cached aggregate stats for ``GET /admin/stats``
+    pub admin_stats: AdminStatsCache,
+    /// json access log line for each request
+    pub access_log: AccessLog,
+    /// OTLP trace exporter settings
+    pub otel: OtelConfig,
+    /// routes added by crates that embed the server
+    pub custom_routes: CustomRoutes,
     // more shared Send/Sync objects can go here
 }
 
@@ -132,114 +1075,290 @@ pub struct CoreConfig {
 ///
 /// * `label` - logging label
 ///
-pub async fn build_core_config(label: &str) -> Result<CoreConfig, String> {
+pub async fn build_core_config(
+    label: &str,
+) -> Result<CoreConfig, StartupError> {
+    build_core_config_from_builder(&RestApiServerBuilder::new(label)).await
+}
+
+/// build_core_config_from_builder
+///
+/// Build a [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// from the values set on a
+/// [`RestApiServerBuilder`](crate::core::server::rest_api_server::RestApiServerBuilder)
+/// and environment variables for everything else.
+///
+/// # Arguments
+///
+/// * `builder` - [`RestApiServerBuilder`](crate::core::server::rest_api_server::RestApiServerBuilder)
+///
+pub async fn build_core_config_from_builder(
+    builder: &RestApiServerBuilder,
+) -> Result<CoreConfig, StartupError> {
+    let label = builder.label.as_str();
     let tracking_label = std::env::var("SERVER_NAME_LABEL")
         .unwrap_or_else(|_| label.to_string());
-    let api_name =
-        std::env::var("SERVER_NAME_API").unwrap_or_else(|_| "api".to_string());
-    let api_address =
-        std::env::var(format!("{api_name}_ENDPOINT").to_uppercase())
-            .unwrap_or_else(|_| "0.0.0.0:3000".to_string());
+    let (api_name, api_address, api_tls_paths) = get_api_endpoint(builder);
     let api_tls_mode = "tls";
-    let db_cert_name = std::env::var("SERVER_DB_NODE_NAME")
-        .unwrap_or_else(|_| "postgres".to_string());
+    let (db_cert_name, db_address, db_tls_paths) = get_db_endpoint(builder);
     let db_conn_type =
         std::env::var(format!("{db_cert_name}_DB_CONN_TYPE").to_uppercase())
             .unwrap_or_else(|_| "postgresql".to_string());
-    let db_address =
-        std::env::var(format!("{db_cert_name}_ENDPOINT").to_uppercase())
-            .unwrap_or_else(|_| "0.0.0.0:5432".to_string());
-    let db_username =
+    let db_read_addresses: Vec<String> =
+        builder.db_read_endpoints.clone().unwrap_or_else(|| {
+            std::env::var(
+                format!("{db_cert_name}_READ_ENDPOINTS").to_uppercase(),
+            )
+            .unwrap_or_default()
+            .split(',')
+            .map(|address| address.trim().to_string())
+            .filter(|address| !address.is_empty())
+            .collect()
+        });
+    let db_read_timeout_ms =
+        std::env::var(format!("{db_cert_name}_READ_TIMEOUT_MS").to_uppercase())
+            .unwrap_or_else(|_| "500".to_string())
+            .parse::<u64>()
+            .unwrap_or(500)
+            .max(1);
+    let db_read_retry_seconds = std::env::var(
+        format!("{db_cert_name}_READ_RETRY_SECONDS").to_uppercase(),
+    )
+    .unwrap_or_else(|_| "10".to_string())
+    .parse::<u64>()
+    .unwrap_or(10);
+    let db_statement_timeout_ms = std::env::var(
+        format!("{db_cert_name}_STATEMENT_TIMEOUT_MS").to_uppercase(),
+    )
+    .unwrap_or_else(|_| "30000".to_string())
+    .parse::<u64>()
+    .unwrap_or(30000);
+    let db_slow_query_ms =
+        std::env::var(format!("{db_cert_name}_SLOW_QUERY_MS").to_uppercase())
+            .unwrap_or_else(|_| "500".to_string())
+            .parse::<u64>()
+            .unwrap_or(500);
+    let db_username = builder.db_username.clone().unwrap_or_else(|| {
         std::env::var(format!("{db_cert_name}_USERNAME").to_uppercase())
-            .unwrap_or_else(|_| "datawriter".to_string());
-    let db_password =
-        std::env::var(format!("{db_cert_name}_PASSWORD").to_uppercase())
-            .unwrap_or_else(|_| "123321".to_string());
-    let db_name =
-        std::env::var("DB_NAME").unwrap_or_else(|_| "mydb".to_string());
+            .unwrap_or_else(|_| "datawriter".to_string())
+    });
+    let mut secrets = Secrets::build_secrets(&db_cert_name)?;
+    if let Some(provider) = &builder.secrets_provider {
+        secrets.provider_name = "custom".to_string();
+        secrets.provider = Some(provider.clone());
+    }
+    let secret_values = match secrets.fetch_secrets(&tracking_label).await {
+        Ok(secret_values) => secret_values,
+        Err(err_msg) => {
+            error!("{tracking_label} - {err_msg}");
+            return Err(StartupError::Config(err_msg));
+        }
+    };
+    let db_password = builder
+        .db_password
+        .clone()
+        .or_else(|| secret_values.get(&secrets.db_password_name).cloned())
+        .unwrap_or_else(|| {
+            std::env::var(format!("{db_cert_name}_PASSWORD").to_uppercase())
+                .unwrap_or_else(|_| "123321".to_string())
+        });
+    let db_name = builder.db_name.clone().unwrap_or_else(|| {
+        std::env::var("DB_NAME").unwrap_or_else(|_| "mydb".to_string())
+    });
     let db_tls_mode = "require";
-    let server_password_salt = std::env::var("SERVER_PASSWORD_SALT")
-        .unwrap_or_else(|_| "PLEASE_CHANGE_ME".to_string());
+    let server_password_salt = builder
+        .server_password_salt
+        .clone()
+        .or_else(|| secret_values.get(SECRET_SERVER_PASSWORD_SALT).cloned())
+        .unwrap_or_else(|| {
+            std::env::var("SERVER_PASSWORD_SALT")
+                .unwrap_or_else(|_| "PLEASE_CHANGE_ME".to_string())
+        });
+    let password_hashing = PasswordHashing::build_password_hashing()?;
+    let mut password_migration = PasswordMigration::build_password_migration();
+    password_migration.legacy_hashers = builder.legacy_password_hashers.clone();
 
-    let pki_dir_jwt = std::env::var("SERVER_PKI_DIR_JWT")
-        .unwrap_or_else(|_| "./jwt".to_string());
-    let token_private_key_path = std::env::var("TOKEN_ALGO_PRIVATE_KEY")
-        .unwrap_or_else(|_| format!("{pki_dir_jwt}/private-key-pkcs8.pem"));
-    let token_public_key_path = std::env::var("TOKEN_ALGO_PUBLIC_KEY")
-        .unwrap_or_else(|_| format!("{pki_dir_jwt}/public-key.pem"));
+    let jwt_key_paths = get_jwt_key_paths_with_secrets(
+        &JwtKeyPaths::build_jwt_key_paths(
+            builder.jwt_key_dir.as_deref(),
+            builder.jwt_private_key.as_deref(),
+            builder.jwt_public_key.as_deref(),
+        ),
+        &secret_values,
+    );
 
-    let kafka_publish_events_s = std::env::var("KAFKA_PUBLISH_EVENTS")
-        .unwrap_or_else(|_| "false".to_string());
-    let mut kafka_publish_events = false;
-    if kafka_publish_events_s == "1" || kafka_publish_events_s == "true" {
-        kafka_publish_events = true;
+    let mut events = get_event_bus(builder);
+    events.validate_topic_routes(&get_kafka_topics(builder))?;
+    events.dead_letters.schema_registry.validate()?;
+    let email_templates = EmailTemplates::build_email_templates()?;
+    email_templates.validate_topic(&events, &get_kafka_topics(builder))?;
+    if email_templates.enabled {
+        // rendered emails are json, not user events
+        events
+            .dead_letters
+            .schema_registry
+            .text_topics
+            .push(email_templates.topic.clone());
+    }
+    let settings = RuntimeSettings::build_runtime_settings();
+    let user_data_archiver = UserDataArchiver::build_user_data_archiver();
+    let user_data_lifecycle = UserDataLifecycle::build_user_data_lifecycle();
+    let user_data_reconciler = UserDataReconciler::build_user_data_reconciler();
+    let user_data_pipeline = UserDataPipeline::build_user_data_pipeline();
+    let upload_scan = UploadScan::build_upload_scan()?;
+    let user_data_thumbnails =
+        UserDataThumbnails::build_user_data_thumbnails()?;
+    let resumable_uploads =
+        ResumableUploadConfig::build_resumable_upload_config()?;
+    let user_data_quota = UserDataQuota::build_user_data_quota();
+    let user_upload_limit = UserUploadLimit::build_user_upload_limit();
+    let s3_event_callback = S3EventCallback::build_s3_event_callback();
+    let demo_mode = DemoMode::build_demo_mode();
+    let request_deadline = RequestDeadline::build_request_deadline();
+    let request_body_limits = RequestBodyLimits::build_request_body_limits();
+    let tenants = TenantResolver::build_tenant_resolver()?;
+    let trusted_proxies = TrustedProxies::build_trusted_proxies()?;
+    let ip_access = IpAccessControl::build_ip_access_control()?;
+    let geo_ip = GeoIp::build_geo_ip()?;
+    let static_assets = StaticAssets::build_static_assets();
+    let admission_control = AdmissionControl::build_admission_control();
+    let connection_limits = builder
+        .connection_limits
+        .clone()
+        .unwrap_or_else(ConnectionLimits::build_connection_limits);
+    let otp = OtpConfig::build_otp_config();
+    let token_cookie = TokenCookie::build_token_cookie()?;
+    let mut login_risk = LoginRisk::build_login_risk()?;
+    login_risk.hook = builder.login_risk_hook.clone();
+    let login_history = LoginHistory::build_login_history();
+    let mut message_localization =
+        MessageLocalization::build_message_localization()?;
+    if let Some(provider) = &builder.translation_provider {
+        message_localization.provider = provider.clone();
     }
+    let user_delete = UserDeleteConfig::build_user_delete_config();
+    let user_cache = UserCache::build_user_cache()?;
+    let auth_alerts = AuthAlerts::build_auth_alerts(&tracking_label);
+    let admin_stats = AdminStatsCache::build_admin_stats_cache();
+    let access_log = AccessLog::build_access_log()?;
+    let otel = OtelConfig::build_otel_config();
 
-    let token_private_key_bytes =
-        std::fs::read_to_string(&token_private_key_path)
-            .unwrap()
-            .into_bytes();
-    let token_public_key_bytes =
-        std::fs::read_to_string(&token_public_key_path)
-            .unwrap()
-            .into_bytes();
+    let jwt_keys = match get_jwt_keys_from_secrets(
+        &tracking_label,
+        &jwt_key_paths,
+        &secret_values,
+    )? {
+        Some(jwt_keys) => jwt_keys,
+        None => load_jwt_keys_from_paths(&tracking_label, &jwt_key_paths)?,
+    };
+    let token_private_key_bytes = jwt_keys
+        .encoding_keys
+        .get(DEFAULT_JWT_KID)
+        .cloned()
+        .unwrap_or_default();
+    let token_public_key_bytes = jwt_keys
+        .decoding_keys
+        .get(DEFAULT_JWT_KID)
+        .cloned()
+        .unwrap_or_default();
+    let token_claims = load_token_custom_claims(&tracking_label)?;
 
-    let api_config = match get_tls_config(
+    let api_config = get_tls_config_from_paths(
         &tracking_label,
         &api_name,
         &api_address,
         api_tls_mode,
+        api_tls_paths,
     )
-    .await
-    {
-        Ok(api_config) => api_config,
-        Err(err_msg) => {
-            panic!(
-                "{tracking_label} - \
-                failed to build {api_name} tls config with err='{err_msg}'"
-            );
-        }
-    };
+    .await?;
+    if api_config.socket_addr.is_none() {
+        return Err(invalid_api_endpoint(&api_name, &api_address));
+    }
 
-    let db_config = match get_tls_config(
+    let db_config = get_tls_config_from_paths(
         &tracking_label,
         &db_cert_name,
         &db_address,
         db_tls_mode,
+        db_tls_paths,
     )
-    .await
-    {
-        Ok(db_config) => db_config,
-        Err(err_msg) => {
-            panic!(
-                "{label} - \
-                failed to build {db_cert_name} tls config with err='{err_msg}'"
-            );
-        }
-    };
+    .await?;
 
-    if !db_config.enabled {
-        let err_msg =
-            "{tracking_label} - invalid tls for the db - stopping".to_string();
-        error!("{err_msg}");
-        return Err(err_msg);
-    }
+    let s3_circuit_breaker = CircuitBreaker::build_circuit_breaker("s3", "S3");
 
     // config object
     let config = CoreConfig {
         label: tracking_label,
         server_address: api_address,
         server_password_salt: server_password_salt.as_bytes().to_vec(),
+        password_hashing,
+        password_migration,
         db_conn_type,
         db_username,
         db_password,
         db_address,
+        db_read_addresses,
+        db_read_timeout_ms,
+        db_read_retry_seconds,
+        db_statement_timeout_ms,
+        db_slow_query_ms,
         db_name,
         api_config,
         db_config,
         encoding_key_bytes: token_private_key_bytes.clone(),
         decoding_key_bytes: token_public_key_bytes.clone(),
-        kafka_publish_events,
+        jwt_keys: Arc::new(RwLock::new(jwt_keys)),
+        jwt_key_paths,
+        secrets,
+        token_claims,
+        token_claims_provider: builder.token_claims_provider.clone(),
+        login_risk,
+        login_history,
+        token_cookie,
+        kafka_publish_events: events.enabled,
+        events,
+        kafka_pool: None,
+        settings: Arc::new(RwLock::new(settings)),
+        user_data_archiver,
+        user_data_lifecycle,
+        user_data_reconciler,
+        user_data_pipeline,
+        upload_scan,
+        user_data_thumbnails,
+        resumable_uploads,
+        user_data_quota,
+        user_upload_limit,
+        s3_event_callback,
+        demo_mode,
+        object_store: Arc::new(CircuitBreakerObjectStore {
+            inner: build_object_store(),
+            breaker: s3_circuit_breaker.clone(),
+        }),
+        s3_circuit_breaker,
+        request_deadline,
+        request_body_limits,
+        tenants,
+        trusted_proxies,
+        ip_access,
+        geo_ip,
+        static_assets,
+        admission_control,
+        connection_limits,
+        startup_wait: StartupWait::build_startup_wait(),
+        otp,
+        user_delete,
+        email_templates,
+        security_notifications:
+            SecurityNotifications::build_security_notifications(),
+        message_localization,
+        response_format: ResponseFormat::build_response_format(),
+        search_export: SearchExport::build_search_export(),
+        user_cache,
+        auth_alerts,
+        admin_stats,
+        access_log,
+        otel,
+        custom_routes: builder.custom_routes.clone(),
     };
 
     if std::env::var("DEBUG").unwrap_or_else(|_| "0".to_string()) == *"1" {
@@ -257,17 +1376,270 @@ pub async fn build_core_config(label: &str) -> Result<CoreConfig, String> {
             openssl s_client -connect {} -starttls postgres\n\
             \n\
             token:\n\
-            - private key: {token_private_key_path}\n\
-            - public key: {token_public_key_path}\n\
+            - private key: {}\n\
+            - public key: {}\n\
             \n",
             config.server_address,
             config.api_config.ca_path,
             config.api_config.cert_path,
             config.api_config.key_path,
             config.server_address,
-            config.db_address
+            config.db_address,
+            config.jwt_key_paths.private_key,
+            config.jwt_key_paths.public_key
         );
     }
 
     Ok(config)
 }
+
+/// validate_core_config
+///
+/// Check the environment variables and files used by
+/// [`build_core_config`](crate::core::core_config::build_core_config)
+/// without starting anything
+///
+/// # Arguments
+///
+/// * `label` - logging label
+///
+/// # Returns
+///
+/// `Vec<StartupError>` - every
+/// [`StartupError`](crate::core::startup_error::StartupError)
+/// that was found (empty when the config is valid)
+///
+pub async fn validate_core_config(label: &str) -> Vec<StartupError> {
+    validate_core_config_from_builder(&RestApiServerBuilder::new(label)).await
+}
+
+/// validate_core_config_from_builder
+///
+/// Check the values set on a
+/// [`RestApiServerBuilder`](crate::core::server::rest_api_server::RestApiServerBuilder)
+/// and the environment variables for everything else. Unlike
+/// [`build_core_config_from_builder`](crate::core::core_config::build_core_config_from_builder)
+/// this does not stop at the first error.
+///
+/// # Arguments
+///
+/// * `builder` - [`RestApiServerBuilder`](crate::core::server::rest_api_server::RestApiServerBuilder)
+///
+/// # Returns
+///
+/// `Vec<StartupError>` - every
+/// [`StartupError`](crate::core::startup_error::StartupError)
+/// that was found (empty when the config is valid)
+///
+pub async fn validate_core_config_from_builder(
+    builder: &RestApiServerBuilder,
+) -> Vec<StartupError> {
+    let tracking_label = std::env::var("SERVER_NAME_LABEL")
+        .unwrap_or_else(|_| builder.label.clone());
+    let mut errors: Vec<StartupError> = Vec::new();
+
+    let (api_name, api_address, api_tls_paths) = get_api_endpoint(builder);
+    if api_address.parse::<std::net::SocketAddr>().is_err() {
+        errors.push(invalid_api_endpoint(&api_name, &api_address));
+    }
+    let (db_cert_name, db_address, db_tls_paths) = get_db_endpoint(builder);
+    for (name, address, mode, tls_paths) in [
+        (&api_name, &api_address, "tls", api_tls_paths),
+        (&db_cert_name, &db_address, "require", db_tls_paths),
+    ] {
+        let tls_errors = check_tls_files(name, &tls_paths);
+        if !tls_errors.is_empty() {
+            errors.extend(tls_errors);
+        } else if let Err(e) = get_tls_config_from_paths(
+            &tracking_label,
+            name,
+            address,
+            mode,
+            tls_paths,
+        )
+        .await
+        {
+            errors.push(e);
+        }
+    }
+
+    let jwt_key_paths = JwtKeyPaths::build_jwt_key_paths(
+        builder.jwt_key_dir.as_deref(),
+        builder.jwt_private_key.as_deref(),
+        builder.jwt_public_key.as_deref(),
+    );
+    // jwt keys from the secrets provider replace the key files
+    let secret_values = match Secrets::build_secrets(&db_cert_name) {
+        Ok(mut secrets) => {
+            if let Some(provider) = &builder.secrets_provider {
+                secrets.provider = Some(provider.clone());
+            }
+            match secrets.fetch_secrets(&tracking_label).await {
+                Ok(secret_values) => secret_values,
+                Err(err_msg) => {
+                    errors.push(StartupError::Config(err_msg));
+                    HashMap::new()
+                }
+            }
+        }
+        Err(err_msg) => {
+            errors.push(StartupError::Config(err_msg));
+            HashMap::new()
+        }
+    };
+    let jwt_key_paths =
+        get_jwt_key_paths_with_secrets(&jwt_key_paths, &secret_values);
+    match get_jwt_keys_from_secrets(
+        &tracking_label,
+        &jwt_key_paths,
+        &secret_values,
+    ) {
+        Ok(Some(_)) => {}
+        Ok(None) => errors.extend(check_jwt_key_files(&jwt_key_paths)),
+        Err(e) => errors.push(e),
+    }
+    let mut check = |result: Result<(), String>| {
+        if let Err(err_msg) = result {
+            errors.push(StartupError::Config(err_msg));
+        }
+    };
+    check(load_token_custom_claims(&tracking_label).map(|_| ()));
+    check(TokenCookie::build_token_cookie().map(|_| ()));
+    check(LoginRisk::build_login_risk().map(|_| ()));
+    check(PasswordHashing::build_password_hashing().map(|_| ()));
+    check(UploadScan::build_upload_scan().map(|_| ()));
+    check(UserDataThumbnails::build_user_data_thumbnails().map(|_| ()));
+    check(ResumableUploadConfig::build_resumable_upload_config().map(|_| ()));
+    check(TenantResolver::build_tenant_resolver().map(|_| ()));
+    check(TrustedProxies::build_trusted_proxies().map(|_| ()));
+    check(IpAccessControl::build_ip_access_control().map(|_| ()));
+    check(GeoIp::build_geo_ip().map(|_| ()));
+    check(UserCache::build_user_cache().map(|_| ()));
+    check(AccessLog::build_access_log().map(|_| ()));
+    check(MessageLocalization::build_message_localization().map(|_| ()));
+    let events = get_event_bus(builder);
+    check(events.validate_topic_routes(&get_kafka_topics(builder)));
+    let schema_registry = &events.dead_letters.schema_registry;
+    match schema_registry.validate() {
+        Ok(_) if events.enabled && schema_registry.enabled => {
+            for topic in events.get_topics().iter() {
+                check(schema_registry.check_compatibility(topic).await);
+            }
+        }
+        result => check(result),
+    }
+    check(EmailTemplates::build_email_templates().and_then(
+        |email_templates| {
+            email_templates.validate_topic(&events, &get_kafka_topics(builder))
+        },
+    ));
+    errors
+}
+
+/// get_event_bus
+///
+/// Build the
+/// [`EventBus`](crate::kafka::event_bus::EventBus) from
+/// environment variables with the builder's kafka settings
+///
+fn get_event_bus(builder: &RestApiServerBuilder) -> EventBus {
+    let mut events = EventBus::build_event_bus();
+    if let Some(enabled) = builder.kafka_publish_events {
+        events.enabled = enabled;
+    }
+    if let Some(user_topic) = &builder.kafka_user_topic {
+        events.user_topic = user_topic.clone();
+    }
+    for (event, topic) in builder.kafka_event_topics.iter() {
+        events.topic_routes.retain(|(route, _)| route != event);
+        events.topic_routes.push((event.clone(), topic.clone()));
+    }
+    events.topic_routes.sort();
+    events
+}
+
+/// get_kafka_topics
+///
+/// Get the supported kafka topics from the builder's
+/// ``KafkaClientConfig`` or ``KAFKA_TOPICS``
+///
+#[cfg_attr(not(feature = "kafka"), allow(unused_variables))]
+fn get_kafka_topics(builder: &RestApiServerBuilder) -> Vec<String> {
+    #[cfg(feature = "kafka")]
+    if let Some(kafka_client_config) = &builder.kafka_client_config {
+        return kafka_client_config.publish_topics.keys().cloned().collect();
+    }
+    std::env::var("KAFKA_TOPICS")
+        .unwrap_or_default()
+        .split(',')
+        .map(|topic| topic.trim().to_string())
+        .filter(|topic| !topic.is_empty())
+        .collect()
+}
+
+/// get_api_endpoint
+///
+/// Get the api's name (``SERVER_NAME_API``), listening
+/// address and tls paths from the builder or environment
+/// variables
+///
+fn get_api_endpoint(
+    builder: &RestApiServerBuilder,
+) -> (String, String, TlsPaths) {
+    let api_name =
+        std::env::var("SERVER_NAME_API").unwrap_or_else(|_| "api".to_string());
+    let api_address = builder.api_endpoint.clone().unwrap_or_else(|| {
+        std::env::var(format!("{api_name}_ENDPOINT").to_uppercase())
+            .unwrap_or_else(|_| "0.0.0.0:3000".to_string())
+    });
+    let api_tls_paths = builder
+        .api_tls
+        .clone()
+        .unwrap_or_else(|| get_tls_paths(&api_name));
+    (api_name, api_address, api_tls_paths)
+}
+
+/// get_db_endpoint
+///
+/// Get the db's name (``SERVER_DB_NODE_NAME``), address and
+/// tls paths from the builder or environment variables
+///
+fn get_db_endpoint(
+    builder: &RestApiServerBuilder,
+) -> (String, String, TlsPaths) {
+    let db_cert_name = std::env::var("SERVER_DB_NODE_NAME")
+        .unwrap_or_else(|_| "postgres".to_string());
+    let db_address = builder.db_endpoint.clone().unwrap_or_else(|| {
+        std::env::var(format!("{db_cert_name}_ENDPOINT").to_uppercase())
+            .unwrap_or_else(|_| "0.0.0.0:5432".to_string())
+    });
+    let db_tls_paths = builder
+        .db_tls
+        .clone()
+        .unwrap_or_else(|| get_tls_paths(&db_cert_name));
+    (db_cert_name, db_address, db_tls_paths)
+}
+
+/// invalid_api_endpoint
+///
+/// Error for an api listening address that is not an
+/// ``IP_ADDRESS:PORT``
+///
+fn invalid_api_endpoint(api_name: &str, api_address: &str) -> StartupError {
+    StartupError::InvalidEnvVar {
+        env_var: format!("{api_name}_ENDPOINT").to_uppercase(),
+        value: api_address.to_string(),
+        reason: "expected a listening address like 0.0.0.0:3000".to_string(),
+    }
+}
+
+impl CoreConfig {
+    /// get_settings
+    ///
+    /// Get a copy of the current
+    /// [`RuntimeSettings`](crate::settings::runtime_settings::RuntimeSettings)
+    ///
+    pub fn get_settings(&self) -> RuntimeSettings {
+        self.settings.read().unwrap().clone()
+    }
+}
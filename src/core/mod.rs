@@ -2,3 +2,4 @@
 //!
 pub mod core_config;
 pub mod server;
+pub mod startup_error;
@@ -0,0 +1,260 @@
+//! Write one json access log line for each request
+//!
+//! With ``ACCESS_LOG_ENABLED=1`` every request is logged
+//! after its response is built with the method, path (without
+//! the query string), status, latency, authenticated user id,
+//! remote address, request id and the body sizes. Lines go to
+//! stdout or the ``ACCESS_LOG_PATH`` file and are separate
+//! from the ``log`` crate output.
+//!
+//! The request id is the client's ``X-Request-Id`` header (or
+//! a new uuid) and it is returned in the ``X-Request-Id``
+//! response header. Handlers mark the authenticated user with
+//! [`set_access_log_user_id`](crate::core::server::access_log::set_access_log_user_id).
+//!
+use std::convert::Infallible;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::sync::atomic::AtomicI32;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use hyper::body::HttpBody;
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Response;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::core::server::core_http_request::CoreHttpRequest;
+use crate::handle_request::handle_request;
+use crate::utils::get_uuid::get_uuid;
+
+tokio::task_local! {
+    /// authenticated user id for the request being logged
+    /// (``-1`` until a handler validates a token)
+    static ACCESS_LOG_USER_ID: Arc<AtomicI32>;
+}
+
+/// AccessLogLine
+///
+/// One json access log line
+///
+/// # Arguments
+///
+/// * `timestamp` - `String` - utc time the request finished
+/// * `request_id` - `String` - ``X-Request-Id`` value
+/// * `method` - `String` - HTTP method
+/// * `path` - `String` - url path without the query string
+/// * `status` - `u16` - HTTP status code
+/// * `latency_ms` - `f64` - milliseconds to build the response
+/// * `user_id` - `Option<i32>` - authenticated user id
+/// * `remote_addr` - `String` - client socket address
+/// * `bytes_in` - `Option<u64>` - request body size
+///   (``None`` for streamed bodies without a
+///   ``Content-Length``)
+/// * `bytes_out` - `Option<u64>` - response body size
+///   (``None`` for streamed responses)
+///
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AccessLogLine {
+    pub timestamp: String,
+    pub request_id: String,
+    pub method: String,
+    pub path: String,
+    pub status: u16,
+    pub latency_ms: f64,
+    pub user_id: Option<i32>,
+    pub remote_addr: String,
+    pub bytes_in: Option<u64>,
+    pub bytes_out: Option<u64>,
+}
+
+/// AccessLog
+///
+/// Settings for the json access log
+///
+/// # Supported Environment Variables
+///
+/// ```bash
+/// export ACCESS_LOG_ENABLED="0"
+/// # stdout or a file path (lines are appended)
+/// export ACCESS_LOG_PATH="stdout"
+/// ```
+///
+/// # Arguments
+///
+/// * `enabled` - `bool` - log every request
+/// * `path` - `String` - ``stdout`` or a file path
+/// * `file` - `Option<Arc<Mutex<File>>>` - open access log
+///   file (``None`` for stdout)
+///
+#[derive(Clone, Default)]
+pub struct AccessLog {
+    pub enabled: bool,
+    pub path: String,
+    pub file: Option<Arc<Mutex<File>>>,
+}
+
+impl AccessLog {
+    /// build_access_log
+    ///
+    /// Build an
+    /// [`AccessLog`](crate::core::server::access_log::AccessLog)
+    /// from environment variables
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) if the ``ACCESS_LOG_PATH``
+    /// file cannot be opened
+    ///
+    pub fn build_access_log() -> Result<Self, String> {
+        let enabled_s = std::env::var("ACCESS_LOG_ENABLED")
+            .unwrap_or_else(|_| "0".to_string());
+        let enabled = enabled_s == "1" || enabled_s == "true";
+        let path = std::env::var("ACCESS_LOG_PATH")
+            .unwrap_or_else(|_| "stdout".to_string());
+        let file = match enabled && !path.is_empty() && path != "stdout" {
+            true => {
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&path)
+                    .map_err(|e| {
+                        format!(
+                            "failed to open ACCESS_LOG_PATH={path} \
+                            with err='{e}'"
+                        )
+                    })?;
+                Some(Arc::new(Mutex::new(file)))
+            }
+            false => None,
+        };
+        Ok(AccessLog {
+            enabled,
+            path,
+            file,
+        })
+    }
+
+    /// log_request
+    ///
+    /// Serve a request with
+    /// [`handle_request`](crate::handle_request::handle_request)
+    /// and write its access log line
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - [`CoreHttpRequest`](crate::core::server::core_http_request::CoreHttpRequest)
+    ///
+    pub async fn log_request(
+        self,
+        mut data: CoreHttpRequest,
+    ) -> std::result::Result<Response<Body>, Infallible> {
+        let start = Instant::now();
+        let request_id = get_request_id(data.request.headers());
+        // the metrics exemplars use the same request id
+        if let Ok(value) = HeaderValue::from_str(&request_id) {
+            data.request.headers_mut().insert("X-Request-Id", value);
+        }
+        let method = data.request.method().to_string();
+        let path = data.request.uri().path().to_string();
+        let remote_addr = format!("{}", data.remote_addr);
+        let bytes_in = data
+            .request
+            .headers()
+            .get("Content-Length")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok())
+            .or_else(|| data.request.body().size_hint().exact());
+        let user_id = Arc::new(AtomicI32::new(-1));
+        let mut response = match ACCESS_LOG_USER_ID
+            .scope(user_id.clone(), handle_request(data))
+            .await
+        {
+            Ok(response) => response,
+            Err(e) => match e {},
+        };
+        if let Ok(value) = HeaderValue::from_str(&request_id) {
+            response.headers_mut().insert("X-Request-Id", value);
+        }
+        let user_id = user_id.load(Ordering::Relaxed);
+        self.write_line(&AccessLogLine {
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            request_id,
+            method,
+            path,
+            status: response.status().as_u16(),
+            latency_ms: start.elapsed().as_secs_f64() * 1000.0,
+            user_id: (user_id >= 0).then_some(user_id),
+            remote_addr,
+            bytes_in,
+            bytes_out: response.body().size_hint().exact(),
+        });
+        Ok(response)
+    }
+
+    /// write_line
+    ///
+    /// Write a json line to stdout or the access log file
+    ///
+    /// # Arguments
+    ///
+    /// * `line` - [`AccessLogLine`](crate::core::server::access_log::AccessLogLine)
+    ///
+    pub fn write_line(&self, line: &AccessLogLine) {
+        let json = serde_json::to_string(line).unwrap();
+        let result = match &self.file {
+            Some(file) => writeln!(file.lock().unwrap(), "{json}"),
+            None => writeln!(std::io::stdout().lock(), "{json}"),
+        };
+        if let Err(e) = result {
+            error!(
+                "failed to write access log line to {} with err='{e}'",
+                self.path
+            );
+        }
+    }
+}
+
+/// get_request_id
+///
+/// Get a request's ``X-Request-Id`` header or a new uuid
+/// when the header is missing, empty or longer than 128
+/// characters
+///
+/// # Arguments
+///
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+///
+pub fn get_request_id(headers: &HeaderMap<HeaderValue>) -> String {
+    headers
+        .get("X-Request-Id")
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.trim())
+        .filter(|v| !v.is_empty() && v.len() <= 128)
+        .map(|v| v.to_string())
+        .unwrap_or_else(get_uuid)
+}
+
+/// set_access_log_user_id
+///
+/// Record the authenticated user for the current request's
+/// access log line (does nothing when the access log is
+/// disabled)
+///
+/// # Arguments
+///
+/// * `user_id` - `i32` - authenticated user id
+///
+pub fn set_access_log_user_id(user_id: i32) {
+    let _ = ACCESS_LOG_USER_ID.try_with(|access_log_user_id| {
+        access_log_user_id.store(user_id, Ordering::Relaxed)
+    });
+}
@@ -0,0 +1,376 @@
+//! Queue-depth aware admission control
+//!
+//! [`handle_request`](crate::handle_request::handle_request)
+//! asks [`AdmissionControl`](crate::core::server::admission_control::AdmissionControl)
+//! to admit each request before routing it. The server load is
+//! the larger of:
+//!
+//! - the in-flight request count divided by
+//!   ``ADMISSION_MAX_IN_FLIGHT``
+//! - the bb8 db pool wait time (measured by
+//!   [`run_admission_probe`](crate::core::server::run_admission_probe::run_admission_probe))
+//!   divided by ``ADMISSION_MAX_POOL_WAIT_MS``
+//!
+//! Each route group has a priority class, and the lowest
+//! priority classes are shed first as the load grows. The
+//! ``health`` routes (metrics, jwks, event schemas and favicon) are always
+//! ``critical`` and are never shed. Rejected requests get a
+//! ``503`` when the db pool is the bottleneck or a ``429``
+//! when there are too many requests in flight, both with a
+//! ``Retry-After`` header.
+//!
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use lazy_static::lazy_static;
+
+use hyper::Method;
+
+use crate::monitoring::metric_types::register_int_counter_vec;
+use crate::monitoring::metric_types::register_int_gauge;
+use crate::monitoring::metric_types::IntCounterVec;
+use crate::monitoring::metric_types::IntGauge;
+
+lazy_static! {
+    pub static ref ADMISSION_REJECTED_COUNTER_VEC: IntCounterVec =
+        register_int_counter_vec!(
+            "admission_rejected_total",
+            "Number of requests shed by admission control.",
+            &["priority", "status"]
+        )
+        .unwrap();
+    pub static ref REQUESTS_IN_FLIGHT_GAUGE: IntGauge = register_int_gauge!(
+        "http_requests_in_flight",
+        "Number of requests currently being served."
+    )
+    .unwrap();
+    pub static ref DB_POOL_WAIT_MS_GAUGE: IntGauge = register_int_gauge!(
+        "db_pool_wait_ms",
+        "Smoothed time in milliseconds to get a db pool connection."
+    )
+    .unwrap();
+}
+
+/// supported priority classes from highest to lowest
+pub const ADMISSION_PRIORITIES: [&str; 4] =
+    ["critical", "high", "normal", "low"];
+
+/// route groups with a configurable priority class
+/// (``health`` is always ``critical``)
+pub const ADMISSION_ROUTE_GROUPS: [&str; 5] =
+    ["auth", "admin", "user", "data", "search"];
+
+/// default `group=priority` list for ``ADMISSION_ROUTE_PRIORITIES``
+pub const DEFAULT_ADMISSION_ROUTE_PRIORITIES: &str =
+    "auth=high,admin=high,user=normal,data=low,search=low";
+
+/// AdmissionRejected
+///
+/// Why a request was not admitted
+///
+/// # Arguments
+///
+/// * `status` - `u16` - ``429`` or ``503``
+/// * `retry_after_seconds` - `u64` - ``Retry-After`` value
+/// * `reason` - `String` - rejection reason for the client
+///
+pub struct AdmissionRejected {
+    pub status: u16,
+    pub retry_after_seconds: u64,
+    pub reason: String,
+}
+
+/// InFlightRequest
+///
+/// Counts a request as in flight until it is dropped
+///
+pub struct InFlightRequest {
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl Drop for InFlightRequest {
+    fn drop(&mut self) {
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+        REQUESTS_IN_FLIGHT_GAUGE.dec();
+    }
+}
+
+/// AdmissionControl
+///
+/// Settings and shared load counters for shedding
+/// requests by priority class
+///
+/// # Supported Environment Variables
+///
+/// ```bash
+/// export ADMISSION_CONTROL_ENABLED="0"
+/// export ADMISSION_MAX_IN_FLIGHT="512"
+/// export ADMISSION_MAX_POOL_WAIT_MS="500"
+/// export ADMISSION_PROBE_INTERVAL_MS="250"
+/// # shed each priority class once the load reaches
+/// export ADMISSION_SHED_LOW_AT="0.7"
+/// export ADMISSION_SHED_NORMAL_AT="0.85"
+/// export ADMISSION_SHED_HIGH_AT="1.0"
+/// export ADMISSION_RETRY_AFTER_SECONDS="1"
+/// # route group priority classes (critical, high, normal or low)
+/// export ADMISSION_ROUTE_PRIORITIES="auth=high,admin=high,user=normal,data=low,search=low"
+/// ```
+///
+/// # Arguments
+///
+/// * `enabled` - `bool` - shed requests when the server is loaded
+/// * `max_in_flight` - `usize` - in-flight requests at full load
+/// * `max_pool_wait_ms` - `u64` - db pool wait at full load
+/// * `probe_interval_ms` - `u64` - how often to measure the
+///   db pool wait time
+/// * `shed_low_at` - `f64` - load for shedding ``low`` routes
+/// * `shed_normal_at` - `f64` - load for shedding ``normal`` routes
+/// * `shed_high_at` - `f64` - load for shedding ``high`` routes
+/// * `retry_after_seconds` - `u64` - ``Retry-After`` for
+///   rejected requests
+/// * `route_priorities` - `Vec<(String, String)>` - priority
+///   class for each route group
+/// * `in_flight` - `Arc<AtomicUsize>` - requests being served
+///   (shared across connections)
+/// * `pool_wait_ms` - `Arc<AtomicU64>` - smoothed db pool wait
+///   time (shared across connections)
+///
+#[derive(Clone, Default)]
+pub struct AdmissionControl {
+    pub enabled: bool,
+    pub max_in_flight: usize,
+    pub max_pool_wait_ms: u64,
+    pub probe_interval_ms: u64,
+    pub shed_low_at: f64,
+    pub shed_normal_at: f64,
+    pub shed_high_at: f64,
+    pub retry_after_seconds: u64,
+    pub route_priorities: Vec<(String, String)>,
+    pub in_flight: Arc<AtomicUsize>,
+    pub pool_wait_ms: Arc<AtomicU64>,
+}
+
+impl AdmissionControl {
+    /// build_admission_control
+    ///
+    /// Build an
+    /// [`AdmissionControl`](crate::core::server::admission_control::AdmissionControl)
+    /// from environment variables. Unsupported route groups
+    /// and priority classes are logged and ignored.
+    ///
+    pub fn build_admission_control() -> Self {
+        let get_env = |key: &str, default: &str| -> String {
+            std::env::var(key).unwrap_or_else(|_| default.to_string())
+        };
+        let get_u64 = |key: &str, default: u64| -> u64 {
+            get_env(key, &format!("{default}"))
+                .parse::<u64>()
+                .unwrap_or(default)
+        };
+        let get_f64 = |key: &str, default: f64| -> f64 {
+            match get_env(key, &format!("{default}")).parse::<f64>() {
+                Ok(v) if v > 0.0 => v,
+                _ => default,
+            }
+        };
+        let enabled_s = get_env("ADMISSION_CONTROL_ENABLED", "0");
+        AdmissionControl {
+            enabled: enabled_s == "1" || enabled_s == "true",
+            max_in_flight: get_u64("ADMISSION_MAX_IN_FLIGHT", 512).max(1)
+                as usize,
+            max_pool_wait_ms: get_u64("ADMISSION_MAX_POOL_WAIT_MS", 500).max(1),
+            probe_interval_ms: get_u64("ADMISSION_PROBE_INTERVAL_MS", 250)
+                .max(10),
+            shed_low_at: get_f64("ADMISSION_SHED_LOW_AT", 0.7),
+            shed_normal_at: get_f64("ADMISSION_SHED_NORMAL_AT", 0.85),
+            shed_high_at: get_f64("ADMISSION_SHED_HIGH_AT", 1.0),
+            retry_after_seconds: get_u64("ADMISSION_RETRY_AFTER_SECONDS", 1),
+            route_priorities: parse_route_priorities(&get_env(
+                "ADMISSION_ROUTE_PRIORITIES",
+                DEFAULT_ADMISSION_ROUTE_PRIORITIES,
+            )),
+            in_flight: Arc::new(AtomicUsize::new(0)),
+            pool_wait_ms: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// get_priority
+    ///
+    /// Get the priority class for a request
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - [`Method`](hyper::Method) - request method
+    /// * `request_uri` - `&str` - url path
+    ///
+    /// # Returns
+    ///
+    /// `&str` - one of
+    /// [`ADMISSION_PRIORITIES`](crate::core::server::admission_control::ADMISSION_PRIORITIES)
+    ///
+    pub fn get_priority(&self, method: &Method, request_uri: &str) -> &str {
+        let group = get_route_group(method, request_uri);
+        if group == "health" {
+            return "critical";
+        }
+        self.route_priorities
+            .iter()
+            .find(|(g, _)| g == group)
+            .map(|(_, p)| p.as_str())
+            .unwrap_or("normal")
+    }
+
+    /// try_admit
+    ///
+    /// Admit a request if its priority class is not being
+    /// shed at the current load
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - [`Method`](hyper::Method) - request method
+    /// * `request_uri` - `&str` - url path
+    ///
+    /// # Returns
+    ///
+    /// Ok([`InFlightRequest`](crate::core::server::admission_control::InFlightRequest))
+    /// that counts the request as in flight until it is dropped
+    ///
+    /// # Errors
+    ///
+    /// Err([`AdmissionRejected`](crate::core::server::admission_control::AdmissionRejected))
+    /// when the request is shed
+    ///
+    pub fn try_admit(
+        &self,
+        method: &Method,
+        request_uri: &str,
+    ) -> Result<InFlightRequest, AdmissionRejected> {
+        if self.enabled {
+            let priority = self.get_priority(method, request_uri);
+            let shed_at = match priority {
+                "low" => self.shed_low_at,
+                "normal" => self.shed_normal_at,
+                "high" => self.shed_high_at,
+                _ => f64::MAX,
+            };
+            let in_flight_load = self.in_flight.load(Ordering::SeqCst) as f64
+                / self.max_in_flight as f64;
+            let pool_load = self.pool_wait_ms.load(Ordering::SeqCst) as f64
+                / self.max_pool_wait_ms as f64;
+            let load = in_flight_load.max(pool_load);
+            if load >= shed_at {
+                // a slow db pool is a server-side problem (503) and
+                // too many in-flight requests is client traffic (429)
+                let status: u16 = match pool_load >= in_flight_load {
+                    true => 503,
+                    false => 429,
+                };
+                ADMISSION_REJECTED_COUNTER_VEC
+                    .with_label_values(&[priority, &format!("{status}")])
+                    .inc();
+                return Err(AdmissionRejected {
+                    status,
+                    retry_after_seconds: self.retry_after_seconds,
+                    reason: format!(
+                        "server is overloaded - shedding {priority} \
+                        priority requests at load={load:.2} - rejected \
+                        {method} {request_uri}"
+                    ),
+                });
+            }
+        }
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        REQUESTS_IN_FLIGHT_GAUGE.inc();
+        Ok(InFlightRequest {
+            in_flight: self.in_flight.clone(),
+        })
+    }
+
+    /// record_pool_wait
+    ///
+    /// Smooth a new db pool wait time sample into
+    /// `pool_wait_ms`
+    ///
+    /// # Arguments
+    ///
+    /// * `wait_ms` - `u64` - time to get a db pool connection
+    ///
+    pub fn record_pool_wait(&self, wait_ms: u64) {
+        let prev = self.pool_wait_ms.load(Ordering::SeqCst);
+        let smoothed = (prev + wait_ms) / 2;
+        self.pool_wait_ms.store(smoothed, Ordering::SeqCst);
+        DB_POOL_WAIT_MS_GAUGE.set(smoothed as i64);
+    }
+}
+
+/// get_route_group
+///
+/// Map a request to its admission route group
+///
+/// # Examples
+///
+/// ```rust
+/// use hyper::Method;
+/// use restapi::core::server::admission_control::get_route_group;
+/// assert_eq!(get_route_group(&Method::GET, "/metrics"), "health");
+/// assert_eq!(get_route_group(&Method::GET, "/health"), "health");
+/// assert_eq!(get_route_group(&Method::POST, "/login"), "auth");
+/// assert_eq!(get_route_group(&Method::POST, "/user/data/search"), "search");
+/// assert_eq!(get_route_group(&Method::POST, "/user/data"), "data");
+/// assert_eq!(get_route_group(&Method::PUT, "/user"), "user");
+/// ```
+///
+pub fn get_route_group(method: &Method, request_uri: &str) -> &'static str {
+    match (method, request_uri) {
+        (&Method::GET, "/metrics")
+        | (&Method::GET, "/health")
+        | (&Method::GET, "/.well-known/jwks.json")
+        | (&Method::GET, "/openapi/events.json")
+        | (&Method::GET, "/favicon.ico") => "health",
+        (_, "/user/search") | (_, "/user/data/search") => "search",
+        _ if request_uri.starts_with("/admin/") => "admin",
+        _ if request_uri.starts_with("/login") || request_uri == "/csrf" => {
+            "auth"
+        }
+        _ if request_uri.starts_with("/user/data") => "data",
+        _ => "user",
+    }
+}
+
+/// parse_route_priorities
+///
+/// Parse a comma-separated ``group=priority`` list
+/// on top of
+/// [`DEFAULT_ADMISSION_ROUTE_PRIORITIES`](crate::core::server::admission_control::DEFAULT_ADMISSION_ROUTE_PRIORITIES)
+///
+fn parse_route_priorities(value: &str) -> Vec<(String, String)> {
+    let mut route_priorities: Vec<(String, String)> =
+        DEFAULT_ADMISSION_ROUTE_PRIORITIES
+            .split(',')
+            .filter_map(|v| v.split_once('='))
+            .map(|(g, p)| (g.to_string(), p.to_string()))
+            .collect();
+    for pair in value.split(',').filter(|v| !v.trim().is_empty()) {
+        let (group, priority) = match pair.split_once('=') {
+            Some((g, p)) => (g.trim(), p.trim()),
+            None => {
+                warn!("ignoring invalid ADMISSION_ROUTE_PRIORITIES={pair}");
+                continue;
+            }
+        };
+        if !ADMISSION_ROUTE_GROUPS.contains(&group)
+            || !ADMISSION_PRIORITIES.contains(&priority)
+        {
+            warn!("ignoring unsupported ADMISSION_ROUTE_PRIORITIES={pair}");
+            continue;
+        }
+        for (g, p) in route_priorities.iter_mut() {
+            if g == group {
+                *p = priority.to_string();
+            }
+        }
+    }
+    route_priorities
+}
@@ -0,0 +1,139 @@
+//! Version-prefixed api routing (``/v1/...`` and ``/v2/...``)
+//!
+//! [`handle_request`](crate::handle_request::handle_request)
+//! strips a ``/vN`` prefix from the url path before admission
+//! control, maintenance mode, body limits and routing, and then
+//! serves the request with the handler set registered for that
+//! version in [`API_VERSIONS`](crate::core::server::api_version::API_VERSIONS).
+//! Unprefixed paths are served as
+//! [`ApiVersion::V1`](crate::core::server::api_version::ApiVersion::V1)
+//! so existing clients keep working. A breaking change to a
+//! request or response type ships as a new route in the next
+//! version's handler set, and every route without a breaking
+//! change falls back to the previous version's handler
+//! (see ``route_v2_request`` in
+//! [`handle_request`](crate::handle_request)).
+//!
+//! Each response has an ``API-Version`` header with the
+//! version that served it.
+//!
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::Response;
+
+/// ApiVersion
+///
+/// A supported api version
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ApiVersion {
+    V1,
+    V2,
+}
+
+/// All supported api versions. Adding a version here
+/// requires a handler set for it in
+/// [`handle_request`](crate::handle_request)
+///
+pub const API_VERSIONS: [ApiVersion; 2] = [ApiVersion::V1, ApiVersion::V2];
+
+/// The version for url paths without a version prefix
+///
+pub const API_VERSION_DEFAULT: ApiVersion = ApiVersion::V1;
+
+impl ApiVersion {
+    /// name
+    ///
+    /// The version's url path prefix without the
+    /// leading ``/`` (``v1``)
+    ///
+    pub fn name(&self) -> &'static str {
+        match self {
+            ApiVersion::V1 => "v1",
+            ApiVersion::V2 => "v2",
+        }
+    }
+
+    /// set_response_header
+    ///
+    /// Add the ``API-Version`` header to a response
+    ///
+    /// # Arguments
+    ///
+    /// * `response` - [`Response`](hyper::Response) - the
+    ///   response to tag with this version
+    ///
+    pub fn set_response_header(&self, response: &mut Response<Body>) {
+        response
+            .headers_mut()
+            .insert("API-Version", HeaderValue::from_static(self.name()));
+    }
+}
+
+/// split_api_version
+///
+/// Split a url path into its api version and the
+/// unprefixed path that the version's handler set routes on
+///
+/// # Arguments
+///
+/// * `request_uri` - `&str` - url path
+///
+/// # Returns
+///
+/// Ok((
+/// [`ApiVersion`](crate::core::server::api_version::ApiVersion),
+/// `&str` unprefixed path))
+///
+/// # Errors
+///
+/// Err(`String`) with the reason when the path has a
+/// version prefix that is not in
+/// [`API_VERSIONS`](crate::core::server::api_version::API_VERSIONS)
+///
+/// # Examples
+///
+/// ```rust
+/// use restapi::core::server::api_version::split_api_version;
+/// use restapi::core::server::api_version::ApiVersion;
+/// assert_eq!(
+///     split_api_version("/user/data"),
+///     Ok((ApiVersion::V1, "/user/data"))
+/// );
+/// assert_eq!(
+///     split_api_version("/v1/user/data"),
+///     Ok((ApiVersion::V1, "/user/data"))
+/// );
+/// assert_eq!(split_api_version("/v2/user/2"), Ok((ApiVersion::V2, "/user/2")));
+/// assert_eq!(split_api_version("/v2"), Ok((ApiVersion::V2, "/")));
+/// assert_eq!(split_api_version("/videos"), Ok((ApiVersion::V1, "/videos")));
+/// assert!(split_api_version("/v9/user").is_err());
+/// ```
+///
+pub fn split_api_version(
+    request_uri: &str,
+) -> Result<(ApiVersion, &str), String> {
+    let rest = match request_uri.strip_prefix("/v") {
+        Some(rest) => rest,
+        None => return Ok((API_VERSION_DEFAULT, request_uri)),
+    };
+    let (number, path) = match rest.find('/') {
+        Some(idx) => (&rest[..idx], &rest[idx..]),
+        None => (rest, "/"),
+    };
+    if number.is_empty() || !number.chars().all(|c| c.is_ascii_digit()) {
+        return Ok((API_VERSION_DEFAULT, request_uri));
+    }
+    let name = format!("v{number}");
+    match API_VERSIONS.iter().find(|v| v.name() == name) {
+        Some(api_version) => Ok((*api_version, path)),
+        None => Err(format!(
+            "unsupported api version {name} - supported versions: {}",
+            API_VERSIONS
+                .iter()
+                .map(|v| v.name())
+                .collect::<Vec<&str>>()
+                .join(", ")
+        )),
+    }
+}
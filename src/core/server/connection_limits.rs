@@ -0,0 +1,273 @@
+//! Limit and tune client connections
+//!
+//! [`start_core_server`](crate::core::server::start_core_server::start_core_server)
+//! takes a permit from the
+//! [`ConnectionLimits`](crate::core::server::connection_limits::ConnectionLimits)
+//! semaphore before accepting each connection, so at most
+//! ``API_MAX_CONNECTIONS`` connections are open at once and
+//! new clients wait in the listen backlog instead of using up
+//! the server's file descriptors and memory. The permit is
+//! released when the connection closes.
+//!
+//! Slow clients are closed when the tls handshake takes longer
+//! than ``API_TLS_HANDSHAKE_TIMEOUT_MS`` or a request's headers
+//! take longer than ``API_HTTP1_HEADER_READ_TIMEOUT_MS`` (this
+//! also closes http/1 keep-alive connections that stay idle for
+//! that long).
+//!
+use std::sync::Arc;
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+
+use tokio::sync::OwnedSemaphorePermit;
+use tokio::sync::Semaphore;
+
+use crate::monitoring::metric_types::register_int_counter;
+use crate::monitoring::metric_types::register_int_gauge;
+use crate::monitoring::metric_types::IntCounter;
+use crate::monitoring::metric_types::IntGauge;
+
+lazy_static! {
+    pub static ref CONNECTIONS_OPEN_GAUGE: IntGauge = register_int_gauge!(
+        "http_connections_open",
+        "Number of open client connections."
+    )
+    .unwrap();
+    pub static ref CONNECTION_LIMIT_REACHED_COUNTER: IntCounter =
+        register_int_counter!(
+            "http_connection_limit_reached_total",
+            "Number of times a connection waited for API_MAX_CONNECTIONS."
+        )
+        .unwrap();
+}
+
+/// smallest read buffer hyper supports
+const MIN_BUF_SIZE_BYTES: usize = 8192;
+
+/// OpenConnection
+///
+/// Holds a connection's permit until the connection is closed
+///
+pub struct OpenConnection {
+    _permit: OwnedSemaphorePermit,
+}
+
+impl Drop for OpenConnection {
+    fn drop(&mut self) {
+        CONNECTIONS_OPEN_GAUGE.dec();
+    }
+}
+
+/// ConnectionLimits
+///
+/// Connection limit, timeouts and hyper server tuning
+///
+/// # Supported Environment Variables
+///
+/// ```bash
+/// # max open client connections (0 = unlimited)
+/// export API_MAX_CONNECTIONS="10000"
+/// # close connections that do not finish the tls
+/// # handshake in time (0 = no timeout)
+/// export API_TLS_HANDSHAKE_TIMEOUT_MS="10000"
+/// # reuse http/1 connections for more requests
+/// export API_HTTP1_KEEP_ALIVE="1"
+/// # close connections that do not send a request's headers
+/// # in time, including idle keep-alive connections
+/// # (0 = no timeout)
+/// export API_HTTP1_HEADER_READ_TIMEOUT_MS="30000"
+/// # ping idle http/2 connections (0 = disabled)
+/// export API_HTTP2_KEEP_ALIVE_INTERVAL_SECONDS="0"
+/// # close http/2 connections that do not answer a ping
+/// export API_HTTP2_KEEP_ALIVE_TIMEOUT_SECONDS="20"
+/// # max read buffer for each connection (0 = hyper's
+/// # default, min 8192)
+/// export API_MAX_BUF_SIZE_BYTES="0"
+/// ```
+///
+/// # Arguments
+///
+/// * `max_connections` - `usize` - max open connections
+///   (0 = unlimited)
+/// * `tls_handshake_timeout_ms` - `u64` - tls handshake
+///   timeout (0 = none)
+/// * `http1_keep_alive` - `bool` - support http/1 keep-alive
+/// * `http1_header_read_timeout_ms` - `u64` - request header
+///   read timeout (0 = none)
+/// * `http2_keep_alive_interval_seconds` - `u64` - http/2 ping
+///   interval (0 = disabled)
+/// * `http2_keep_alive_timeout_seconds` - `u64` - http/2 ping
+///   timeout
+/// * `max_buf_size_bytes` - `usize` - max read buffer size
+///   (0 = hyper's default)
+/// * `semaphore` - `Arc<Semaphore>` - open connection permits
+///   (shared across connections)
+///
+#[derive(Clone)]
+pub struct ConnectionLimits {
+    pub max_connections: usize,
+    pub tls_handshake_timeout_ms: u64,
+    pub http1_keep_alive: bool,
+    pub http1_header_read_timeout_ms: u64,
+    pub http2_keep_alive_interval_seconds: u64,
+    pub http2_keep_alive_timeout_seconds: u64,
+    pub max_buf_size_bytes: usize,
+    pub semaphore: Arc<Semaphore>,
+}
+
+impl Default for ConnectionLimits {
+    fn default() -> Self {
+        ConnectionLimits::new(10000)
+    }
+}
+
+impl ConnectionLimits {
+    /// new
+    ///
+    /// Build
+    /// [`ConnectionLimits`](crate::core::server::connection_limits::ConnectionLimits)
+    /// with a connection limit and the default timeouts
+    ///
+    /// # Arguments
+    ///
+    /// * `max_connections` - `usize` - max open connections
+    ///   (0 = unlimited)
+    ///
+    pub fn new(max_connections: usize) -> Self {
+        let permits = if max_connections == 0 {
+            Semaphore::MAX_PERMITS
+        } else {
+            max_connections
+        };
+        ConnectionLimits {
+            max_connections,
+            tls_handshake_timeout_ms: 10000,
+            http1_keep_alive: true,
+            http1_header_read_timeout_ms: 30000,
+            http2_keep_alive_interval_seconds: 0,
+            http2_keep_alive_timeout_seconds: 20,
+            max_buf_size_bytes: 0,
+            semaphore: Arc::new(Semaphore::new(permits)),
+        }
+    }
+
+    /// build_connection_limits
+    ///
+    /// Build
+    /// [`ConnectionLimits`](crate::core::server::connection_limits::ConnectionLimits)
+    /// from environment variables
+    ///
+    pub fn build_connection_limits() -> Self {
+        let get_u64 = |key: &str, default_value: u64| {
+            std::env::var(key)
+                .unwrap_or_else(|_| format!("{default_value}"))
+                .parse::<u64>()
+                .unwrap_or(default_value)
+        };
+        let keep_alive_s = std::env::var("API_HTTP1_KEEP_ALIVE")
+            .unwrap_or_else(|_| "1".to_string());
+        let mut max_buf_size_bytes =
+            get_u64("API_MAX_BUF_SIZE_BYTES", 0) as usize;
+        if max_buf_size_bytes != 0 && max_buf_size_bytes < MIN_BUF_SIZE_BYTES {
+            warn!(
+                "API_MAX_BUF_SIZE_BYTES={max_buf_size_bytes} is below \
+                the minimum - using {MIN_BUF_SIZE_BYTES}"
+            );
+            max_buf_size_bytes = MIN_BUF_SIZE_BYTES;
+        }
+        ConnectionLimits {
+            tls_handshake_timeout_ms: get_u64(
+                "API_TLS_HANDSHAKE_TIMEOUT_MS",
+                10000,
+            ),
+            http1_keep_alive: keep_alive_s == "1" || keep_alive_s == "true",
+            http1_header_read_timeout_ms: get_u64(
+                "API_HTTP1_HEADER_READ_TIMEOUT_MS",
+                30000,
+            ),
+            http2_keep_alive_interval_seconds: get_u64(
+                "API_HTTP2_KEEP_ALIVE_INTERVAL_SECONDS",
+                0,
+            ),
+            http2_keep_alive_timeout_seconds: get_u64(
+                "API_HTTP2_KEEP_ALIVE_TIMEOUT_SECONDS",
+                20,
+            ),
+            max_buf_size_bytes,
+            ..ConnectionLimits::new(
+                get_u64("API_MAX_CONNECTIONS", 10000) as usize
+            )
+        }
+    }
+
+    /// build_http
+    ///
+    /// Build the hyper [`Http`](hyper::server::conn::Http)
+    /// connection builder with the keep-alive, header timeout
+    /// and buffer settings
+    ///
+    pub fn build_http(&self) -> hyper::server::conn::Http {
+        let mut http = hyper::server::conn::Http::new();
+        http.http1_keep_alive(self.http1_keep_alive);
+        if self.http1_header_read_timeout_ms > 0 {
+            http.http1_header_read_timeout(Duration::from_millis(
+                self.http1_header_read_timeout_ms,
+            ));
+        }
+        if self.http2_keep_alive_interval_seconds > 0 {
+            http.http2_keep_alive_interval(Duration::from_secs(
+                self.http2_keep_alive_interval_seconds,
+            ));
+            http.http2_keep_alive_timeout(Duration::from_secs(
+                self.http2_keep_alive_timeout_seconds,
+            ));
+        }
+        if self.max_buf_size_bytes > 0 {
+            http.max_buf_size(self.max_buf_size_bytes);
+        }
+        http
+    }
+
+    /// acquire
+    ///
+    /// Wait for a free connection permit
+    ///
+    /// # Returns
+    ///
+    /// [`OpenConnection`](crate::core::server::connection_limits::OpenConnection)
+    /// that releases the permit when it is dropped
+    ///
+    pub async fn acquire(&self) -> OpenConnection {
+        let permit = match self.semaphore.clone().try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(_) => {
+                CONNECTION_LIMIT_REACHED_COUNTER.inc();
+                warn!(
+                    "reached API_MAX_CONNECTIONS={} - waiting for a \
+                    connection to close",
+                    self.max_connections
+                );
+                self.semaphore
+                    .clone()
+                    .acquire_owned()
+                    .await
+                    .expect("connection semaphore is never closed")
+            }
+        };
+        CONNECTIONS_OPEN_GAUGE.inc();
+        OpenConnection { _permit: permit }
+    }
+
+    /// get_tls_handshake_timeout
+    ///
+    /// Get the tls handshake timeout (`None` = no timeout)
+    ///
+    pub fn get_tls_handshake_timeout(&self) -> Option<Duration> {
+        if self.tls_handshake_timeout_ms == 0 {
+            None
+        } else {
+            Some(Duration::from_millis(self.tls_handshake_timeout_ms))
+        }
+    }
+}
@@ -8,10 +8,12 @@
 //! [`config: CoreConfig`](crate::core::core_config::CoreConfig)
 //! - the postgres bb8 db threadpool in the member field:
 //! [`db_pool: Pool<PostgresConnectionManager<MakeTlsConnector>>`](bb8::Pool)
+//! - the postgres read replica pools in the member field:
+//!   [`db_read_pools: DbReadPools`](crate::pools::db_read_pools::DbReadPools)
 //! - the kafka threadpool's
-//! [`KafkaPublisher`](kafka_threadpool::KafkaPublisher)
+//! [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
 //! in the member field:
-//! [`kafka_pool: KafkaPublisher`](kafka_threadpool::KafkaPublisher)
+//! [`kafka_pool: KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
 //! - the HTTP request in the member field:
 //! [`request: Request<Body>`](hyper::Request)
 //! - the HTTP response in the member field:
@@ -26,9 +28,9 @@ use hyper::Body;
 use hyper::Request;
 use hyper::Response;
 
-use kafka_threadpool::kafka_publisher::KafkaPublisher;
-
 use crate::core::core_config::CoreConfig;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::pools::db_read_pools::DbReadPools;
 use crate::tls::tls_info::TlsInfo;
 
 /// CoreHttpRequest
@@ -57,6 +59,7 @@ use crate::tls::tls_info::TlsInfo;
 pub struct CoreHttpRequest {
     pub config: CoreConfig,
     pub db_pool: Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    pub db_read_pools: DbReadPools,
     pub kafka_pool: KafkaPublisher,
     pub local_addr: std::net::SocketAddr,
     pub remote_addr: std::net::SocketAddr,
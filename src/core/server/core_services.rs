@@ -3,6 +3,7 @@
 //! for serving HTTP traffic with a customized struct containing:
 //! - the static server configuration (``config`` member)
 //! - the bb8 postgres db threadpool (``db_pool`` member)
+//! - the postgres read replica pools (``db_read_pools`` member)
 //! - the kafka publisher threadpool (``kafka_pool`` member)
 //! - server tls information (``tls_info`` member)
 //! - tracking fields for local and remote addresses
@@ -23,11 +24,11 @@ use hyper::Body;
 use hyper::Request;
 use hyper::Response;
 
-use kafka_threadpool::kafka_publisher::KafkaPublisher;
-
 use crate::core::core_config::CoreConfig;
 use crate::core::server::core_http_request::CoreHttpRequest;
 use crate::handle_request::handle_request;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::pools::db_read_pools::DbReadPools;
 
 use crate::tls::tls_info::TlsInfo;
 
@@ -39,7 +40,7 @@ use crate::tls::tls_info::TlsInfo;
 /// [`CoreConfig`](crate::core::core_config::CoreConfig)
 /// server statics,
 /// tls information, kafka threadpool
-/// [`KafkaPublisher`](kafka_threadpool::kafka_publisher::KafkaPublisher),
+/// [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher),
 /// and postgres client db threadpool
 /// ([`Pool`](bb8::Pool))
 ///
@@ -56,17 +57,24 @@ use crate::tls::tls_info::TlsInfo;
 /// [`MakeTlsConnector`](postgres_native_tls::MakeTlsConnector)
 /// for db client tls encryption
 ///
+/// ## Postgres Read Replicas
+///
+/// [`DbReadPools`](crate::pools::db_read_pools::DbReadPools)
+/// for routing read-only queries to replicas
+///
 /// ## Kafka Threadpool
 ///
 /// The ``kafka_pool`` (
-/// [`KafkaPublisher`](kafka_threadpool::kafka_publisher::KafkaPublisher))
+/// [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher))
 /// supports HTTP requests that need to publish messages to the
 /// environment variable-configured kafka cluster.
 ///
 /// ## Socket Data
 ///
 /// `local_addr` - server address
-/// `remote_addr` - client address
+/// `remote_addr` - connection peer address (each request's
+/// ``remote_addr`` is the forwarded client address when the
+/// peer is a trusted proxy)
 ///
 /// ## TLS Info
 ///
@@ -77,6 +85,7 @@ use crate::tls::tls_info::TlsInfo;
 pub struct CoreServices {
     pub config: CoreConfig,
     pub db_pool: Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    pub db_read_pools: DbReadPools,
     pub kafka_pool: KafkaPublisher,
     pub local_addr: std::net::SocketAddr,
     pub remote_addr: std::net::SocketAddr,
@@ -138,14 +147,21 @@ impl Service<Request<Body>> for CoreServices {
         let data = CoreHttpRequest {
             config: self.config.clone(),
             db_pool: self.db_pool.clone(),
+            db_read_pools: self.db_read_pools.clone(),
             kafka_pool: self.kafka_pool.clone(),
             local_addr: self.local_addr,
-            remote_addr: self.remote_addr,
+            remote_addr: self
+                .config
+                .trusted_proxies
+                .get_client_addr(&self.remote_addr, req.headers()),
             tls_info: self.tls_info.clone(),
             request: req,
             response: Response::new("".into()),
         };
         // handle request
+        if self.config.access_log.enabled {
+            return Box::pin(self.config.access_log.clone().log_request(data));
+        }
         Box::pin(handle_request(data))
     }
 }
@@ -0,0 +1,92 @@
+//! Routes added by crates that embed the api server
+//!
+//! Implement the
+//! [`CustomRoute`](crate::core::server::custom_route::CustomRoute)
+//! trait and add it with
+//! [`RestApiServerBuilder::route`](crate::core::server::rest_api_server::RestApiServerBuilder::route)
+//! (or push it onto the
+//! [`CoreConfig`](crate::core::core_config::CoreConfig)
+//! ``custom_routes`` before starting the server).
+//!
+//! Custom routes are checked in the order they were added,
+//! after maintenance mode and the request body limits and
+//! before the built-in routes, so a custom route can add a
+//! new url or replace a built-in one. Custom routes are
+//! served under every api version with the unprefixed url
+//! path (see [`api_version`](crate::core::server::api_version)).
+//!
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use hyper::Body;
+use hyper::Method;
+use hyper::Response;
+
+use crate::core::server::core_http_request::CoreHttpRequest;
+
+/// future returned by
+/// [`CustomRoute::handle`](crate::core::server::custom_route::CustomRoute::handle)
+pub type CustomRouteFuture<'a> =
+    Pin<Box<dyn Future<Output = Response<Body>> + Send + 'a>>;
+
+/// custom routes shared by all connections
+pub type CustomRoutes = Vec<Arc<dyn CustomRoute>>;
+
+/// CustomRoute
+///
+/// Hook for serving a url that is not built into the
+/// api server
+///
+pub trait CustomRoute: Send + Sync {
+    /// matches
+    ///
+    /// Check if this route serves a request
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - [`Method`](hyper::Method) - request method
+    /// * `request_uri` - `&str` - url path without an api
+    ///   version prefix
+    ///
+    fn matches(&self, method: &Method, request_uri: &str) -> bool;
+
+    /// handle
+    ///
+    /// Serve a matched request
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - [`CoreHttpRequest`](crate::core::server::core_http_request::CoreHttpRequest) -
+    ///   the request with the server's config, db pools and
+    ///   kafka publisher
+    ///
+    /// # Returns
+    ///
+    /// hyper [`Response`](hyper::Response) sent back to the
+    /// client
+    ///
+    fn handle<'a>(&'a self, data: CoreHttpRequest) -> CustomRouteFuture<'a>;
+}
+
+/// find_custom_route
+///
+/// Find the first custom route that serves a request
+///
+/// # Arguments
+///
+/// * `custom_routes` - [`CustomRoutes`](crate::core::server::custom_route::CustomRoutes)
+/// * `method` - [`Method`](hyper::Method) - request method
+/// * `request_uri` - `&str` - url path without an api
+///   version prefix
+///
+pub fn find_custom_route(
+    custom_routes: &CustomRoutes,
+    method: &Method,
+    request_uri: &str,
+) -> Option<Arc<dyn CustomRoute>> {
+    custom_routes
+        .iter()
+        .find(|route| route.matches(method, request_uri))
+        .cloned()
+}
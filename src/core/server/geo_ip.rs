@@ -0,0 +1,240 @@
+//! Look up the country and city for a client address
+//!
+//! With ``GEOIP_DB_PATH`` set to a MaxMind GeoIP2 or
+//! GeoLite2 City (or Country) database, new sessions store
+//! the client's ``country`` and ``city`` in the
+//! ``users_tokens`` table and login events include them.
+//! Private and unknown addresses have no location.
+//!
+//! Reading the database requires building restapi with
+//! ``--features geoip``.
+//!
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+/// GeoLocation
+///
+/// Location for a client address
+///
+/// # Arguments
+///
+/// * `country` - `String` - ISO 3166-1 country code
+///   (``US``)
+/// * `city` - `String` - English city name (empty for
+///   country databases)
+///
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct GeoLocation {
+    pub country: String,
+    pub city: String,
+}
+
+/// GeoIpProvider
+///
+/// Look up the location for a client address
+///
+pub trait GeoIpProvider: Send + Sync {
+    /// lookup
+    ///
+    /// # Arguments
+    ///
+    /// * `ip_address` - [`IpAddr`](std::net::IpAddr) - client
+    ///   address
+    ///
+    /// # Returns
+    ///
+    /// `Option<`[`GeoLocation`](crate::core::server::geo_ip::GeoLocation)`>` -
+    /// `None` for addresses that are not in the database
+    ///
+    fn lookup(&self, ip_address: IpAddr) -> Option<GeoLocation>;
+}
+
+/// GeoIp
+///
+/// Settings and database for the client address lookups
+///
+/// # Supported Environment Variables
+///
+/// ```bash
+/// # MaxMind GeoIP2 or GeoLite2 mmdb file
+/// # (empty = no lookups)
+/// export GEOIP_DB_PATH=""
+/// ```
+///
+/// # Arguments
+///
+/// * `db_path` - `String` - database file
+/// * `provider` - `Option<Arc<dyn`[`GeoIpProvider`](crate::core::server::geo_ip::GeoIpProvider)`>>` -
+///   loaded database (`None` = disabled)
+///
+#[derive(Clone, Default)]
+pub struct GeoIp {
+    pub db_path: String,
+    pub provider: Option<Arc<dyn GeoIpProvider>>,
+}
+
+impl GeoIp {
+    /// build_geo_ip
+    ///
+    /// Build a
+    /// [`GeoIp`](crate::core::server::geo_ip::GeoIp)
+    /// from environment variables and load the database
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) when the database cannot be
+    /// read or restapi was built without the ``geoip``
+    /// feature
+    ///
+    pub fn build_geo_ip() -> Result<Self, String> {
+        let db_path = std::env::var("GEOIP_DB_PATH")
+            .unwrap_or_default()
+            .trim()
+            .to_string();
+        let provider = match db_path.is_empty() {
+            true => None,
+            false => Some(build_maxmind_provider(&db_path)?),
+        };
+        Ok(GeoIp { db_path, provider })
+    }
+
+    /// is_enabled
+    ///
+    /// Check if a database is loaded
+    ///
+    pub fn is_enabled(&self) -> bool {
+        self.provider.is_some()
+    }
+
+    /// get_location
+    ///
+    /// Look up the location for a session's ``ip_address``
+    ///
+    /// # Arguments
+    ///
+    /// * `ip_address` - `&str` - client address
+    ///
+    /// # Returns
+    ///
+    /// [`GeoLocation`](crate::core::server::geo_ip::GeoLocation) -
+    /// empty when disabled or the address is unknown
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use restapi::core::server::geo_ip::GeoIp;
+    /// let geo_ip = GeoIp::default();
+    /// assert_eq!(geo_ip.get_location("8.8.8.8").country, "");
+    /// ```
+    ///
+    pub fn get_location(&self, ip_address: &str) -> GeoLocation {
+        match (&self.provider, ip_address.parse::<IpAddr>()) {
+            (Some(provider), Ok(ip_address)) => {
+                provider.lookup(ip_address).unwrap_or_default()
+            }
+            _ => GeoLocation::default(),
+        }
+    }
+}
+
+/// MaxMindGeoIp
+///
+/// [`GeoIpProvider`](crate::core::server::geo_ip::GeoIpProvider)
+/// for a MaxMind mmdb file
+///
+#[cfg(feature = "geoip")]
+pub struct MaxMindGeoIp {
+    pub reader: maxminddb::Reader<Vec<u8>>,
+}
+
+#[cfg(feature = "geoip")]
+impl GeoIpProvider for MaxMindGeoIp {
+    fn lookup(&self, ip_address: IpAddr) -> Option<GeoLocation> {
+        let record: maxminddb::geoip2::City =
+            self.reader.lookup(ip_address).ok()?;
+        let country = record
+            .country
+            .and_then(|country| country.iso_code)
+            .unwrap_or_default()
+            .to_string();
+        let city = record
+            .city
+            .and_then(|city| city.names)
+            .and_then(|names| names.get("en").map(|name| name.to_string()))
+            .unwrap_or_default();
+        match country.is_empty() && city.is_empty() {
+            true => None,
+            false => Some(GeoLocation { country, city }),
+        }
+    }
+}
+
+/// build_maxmind_provider
+///
+/// Load a MaxMind mmdb file
+///
+#[cfg(feature = "geoip")]
+fn build_maxmind_provider(
+    db_path: &str,
+) -> Result<Arc<dyn GeoIpProvider>, String> {
+    let reader = maxminddb::Reader::open_readfile(db_path).map_err(|e| {
+        format!("failed to load GEOIP_DB_PATH={db_path} with err='{e}'")
+    })?;
+    Ok(Arc::new(MaxMindGeoIp { reader }))
+}
+
+/// build_maxmind_provider
+///
+/// The MaxMind database requires the ``geoip`` feature
+///
+#[cfg(not(feature = "geoip"))]
+fn build_maxmind_provider(
+    _db_path: &str,
+) -> Result<Arc<dyn GeoIpProvider>, String> {
+    Err("GEOIP_DB_PATH requires building restapi with \
+        --features geoip"
+        .to_string())
+}
+
+/// get_location_details
+///
+/// Format a location as user event details (the city is
+/// form-urlencoded because event details are separated by
+/// spaces)
+///
+/// # Arguments
+///
+/// * `country` - `&str` - country code (empty = unknown)
+/// * `city` - `&str` - city name (empty = unknown)
+///
+/// # Returns
+///
+/// `String` - ``country=XX city=NAME`` details (empty when
+/// the location is unknown)
+///
+/// # Examples
+///
+/// ```rust
+/// use restapi::core::server::geo_ip::get_location_details;
+/// assert_eq!(
+///     get_location_details("US", "San Francisco"),
+///     "country=US city=San+Francisco");
+/// assert_eq!(get_location_details("DE", ""), "country=DE");
+/// assert_eq!(get_location_details("", ""), "");
+/// ```
+///
+pub fn get_location_details(country: &str, city: &str) -> String {
+    let mut details: Vec<String> = Vec::new();
+    if !country.is_empty() {
+        details.push(format!("country={country}"));
+    }
+    if !city.is_empty() {
+        let city: String =
+            url::form_urlencoded::byte_serialize(city.as_bytes()).collect();
+        details.push(format!("city={city}"));
+    }
+    details.join(" ")
+}
@@ -0,0 +1,353 @@
+//! Allow or deny requests by client address before they
+//! are routed
+//!
+//! Rules come from environment variables and from the
+//! ``ip_rules`` table (managed with the ``/admin/ip-rules``
+//! apis). A rule applies to every route or only to the
+//! routes under its ``path_prefix`` (``/admin`` matches
+//! ``/admin`` and ``/admin/stats``). Requests are checked
+//! with the client address from
+//! [`TrustedProxies`](crate::core::server::trusted_proxies::TrustedProxies)
+//! and rejected with a ``403`` when:
+//!
+//! 1. the address is in a matching deny rule, or
+//! 2. a matching path prefix (or the global list) has allow
+//!    rules and none of them contain the address
+//!
+//! Every insert, update or delete on the ``ip_rules`` table
+//! sends an ``ip_rules_changed`` postgres notification so all
+//! api servers reload their cached rules.
+//!
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::sync::RwLock;
+
+use crate::core::server::trusted_proxies::TrustedCidr;
+
+/// supported ``ip_rules.action`` values
+pub const IP_RULE_ACTIONS: [&str; 2] = ["allow", "deny"];
+
+/// IpAccessRule
+///
+/// One allow or deny rule
+///
+/// # Arguments
+///
+/// * `allow` - `bool` - ``true`` for an allow rule and
+///   ``false`` for a deny rule
+/// * `cidr` - [`TrustedCidr`](crate::core::server::trusted_proxies::TrustedCidr) -
+///   network the rule matches
+/// * `path_prefix` - `String` - url path prefix the rule
+///   applies to (empty = every route)
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IpAccessRule {
+    pub allow: bool,
+    pub cidr: TrustedCidr,
+    pub path_prefix: String,
+}
+
+impl IpAccessRule {
+    /// parse_rule
+    ///
+    /// Build a rule from an action, a cidr and a path prefix.
+    /// Trailing ``/`` characters are removed from the path
+    /// prefix so ``/admin/`` and ``/admin`` are the same rule.
+    ///
+    /// # Arguments
+    ///
+    /// * `action` - `&str` - ``allow`` or ``deny``
+    /// * `cidr` - `&str` - network (``10.0.0.0/8``) or address
+    /// * `path_prefix` - `&str` - url path prefix (empty or
+    ///   ``/`` = every route)
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) for an unsupported action,
+    /// invalid cidr or a path prefix that does not start
+    /// with ``/``
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use restapi::core::server::ip_access::IpAccessRule;
+    /// let rule = IpAccessRule::parse_rule("allow", "10.0.0.0/8", "/admin/")
+    ///     .unwrap();
+    /// assert_eq!(rule.path_prefix, "/admin");
+    /// assert!(rule.matches_path("/admin/stats"));
+    /// assert!(!rule.matches_path("/administrator"));
+    /// assert!(IpAccessRule::parse_rule("block", "10.0.0.0/8", "").is_err());
+    /// assert!(IpAccessRule::parse_rule("deny", "10.0.0.0/8", "admin").is_err());
+    /// ```
+    ///
+    pub fn parse_rule(
+        action: &str,
+        cidr: &str,
+        path_prefix: &str,
+    ) -> Result<Self, String> {
+        let allow = match action.trim() {
+            "allow" => true,
+            "deny" => false,
+            _ => {
+                return Err(format!(
+                    "invalid ip rule action={action} must be allow or deny"
+                ))
+            }
+        };
+        let cidr = TrustedCidr::parse_cidr(cidr)
+            .map_err(|_| format!("invalid ip rule cidr={cidr}"))?;
+        let path_prefix = path_prefix.trim();
+        if !path_prefix.is_empty() && !path_prefix.starts_with('/') {
+            return Err(format!(
+                "invalid ip rule path_prefix={path_prefix} must start with /"
+            ));
+        }
+        Ok(IpAccessRule {
+            allow,
+            cidr,
+            path_prefix: path_prefix.trim_end_matches('/').to_string(),
+        })
+    }
+
+    /// get_action
+    ///
+    /// Get the ``ip_rules.action`` for the rule
+    ///
+    pub fn get_action(&self) -> &'static str {
+        match self.allow {
+            true => "allow",
+            false => "deny",
+        }
+    }
+
+    /// matches_path
+    ///
+    /// Check if the rule applies to a url path
+    ///
+    /// # Arguments
+    ///
+    /// * `request_uri` - `&str` - unprefixed url path
+    ///
+    pub fn matches_path(&self, request_uri: &str) -> bool {
+        match request_uri.strip_prefix(self.path_prefix.as_str()) {
+            Some(rest) => rest.is_empty() || rest.starts_with('/'),
+            None => false,
+        }
+    }
+}
+
+/// check_ip_rules
+///
+/// Check a client address against a list of rules for a
+/// url path
+///
+/// # Arguments
+///
+/// * `rules` - `&[IpAccessRule]` - rules to check
+/// * `ip` - `&IpAddr` - client address
+/// * `request_uri` - `&str` - unprefixed url path
+///
+/// # Errors
+///
+/// Err(err_msg: `String`) when the address is denied or
+/// missing from a matching allowlist
+///
+/// # Examples
+///
+/// ```rust
+/// use restapi::core::server::ip_access::check_ip_rules;
+/// use restapi::core::server::ip_access::IpAccessRule;
+/// let rules = vec![
+///     IpAccessRule::parse_rule("allow", "10.0.0.0/8", "/admin").unwrap(),
+///     IpAccessRule::parse_rule("deny", "10.9.0.0/16", "").unwrap(),
+/// ];
+/// let office = "10.1.2.3".parse().unwrap();
+/// let home = "203.0.113.7".parse().unwrap();
+/// let blocked = "10.9.1.1".parse().unwrap();
+/// assert!(check_ip_rules(&rules, &office, "/admin/stats").is_ok());
+/// assert!(check_ip_rules(&rules, &home, "/admin/stats").is_err());
+/// assert!(check_ip_rules(&rules, &home, "/user").is_ok());
+/// assert!(check_ip_rules(&rules, &blocked, "/user").is_err());
+/// ```
+///
+pub fn check_ip_rules(
+    rules: &[IpAccessRule],
+    ip: &IpAddr,
+    request_uri: &str,
+) -> Result<(), String> {
+    let matching: Vec<&IpAccessRule> = rules
+        .iter()
+        .filter(|rule| rule.matches_path(request_uri))
+        .collect();
+    if matching
+        .iter()
+        .any(|rule| !rule.allow && rule.cidr.contains(ip))
+    {
+        return Err(format!("ip address {ip} is denied for {request_uri}"));
+    }
+    // the global allowlist and each matching path prefix's
+    // allowlist must all contain the address
+    for scope in matching.iter().filter(|rule| rule.allow) {
+        if !matching.iter().any(|rule| {
+            rule.allow
+                && rule.path_prefix == scope.path_prefix
+                && rule.cidr.contains(ip)
+        }) {
+            return Err(format!(
+                "ip address {ip} is not allowed for {request_uri}"
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// shared ``ip_rules`` table rules on the
+/// [`IpAccessControl`](crate::core::server::ip_access::IpAccessControl)
+pub type SharedIpAccessRules = Arc<RwLock<Vec<IpAccessRule>>>;
+
+/// IpAccessControl
+///
+/// Settings for the ip allowlists and denylists
+///
+/// # Supported Environment Variables
+///
+/// ```bash
+/// # comma-separated networks allowed to reach every route
+/// # (empty = any address)
+/// export IP_ALLOWLIST=""
+/// # comma-separated networks rejected on every route
+/// export IP_DENYLIST=""
+/// # per route prefix allowlists and denylists as
+/// # comma-separated prefix=cidr|cidr pairs
+/// export IP_ROUTE_ALLOWLIST="/admin=10.0.0.0/8|127.0.0.1,/metrics=127.0.0.1"
+/// export IP_ROUTE_DENYLIST=""
+/// ```
+///
+/// # Arguments
+///
+/// * `env_rules` - `Vec<IpAccessRule>` - rules from
+///   environment variables
+/// * `db_rules` - `SharedIpAccessRules` - rules from the
+///   ``ip_rules`` table (reloaded when the table changes)
+///
+#[derive(Clone, Default)]
+pub struct IpAccessControl {
+    pub env_rules: Vec<IpAccessRule>,
+    pub db_rules: SharedIpAccessRules,
+}
+
+impl IpAccessControl {
+    /// build_ip_access_control
+    ///
+    /// Build an
+    /// [`IpAccessControl`](crate::core::server::ip_access::IpAccessControl)
+    /// from environment variables (the ``ip_rules`` table is
+    /// loaded by
+    /// [`reload_ip_rules`](crate::settings::listen_for_settings_changes::reload_ip_rules))
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) for an invalid cidr or
+    /// route entry
+    ///
+    pub fn build_ip_access_control() -> Result<Self, String> {
+        let mut env_rules: Vec<IpAccessRule> = Vec::new();
+        for (key, action) in
+            [("IP_ALLOWLIST", "allow"), ("IP_DENYLIST", "deny")]
+        {
+            for cidr in std::env::var(key)
+                .unwrap_or_default()
+                .split(',')
+                .filter(|v| !v.trim().is_empty())
+            {
+                env_rules.push(
+                    IpAccessRule::parse_rule(action, cidr, "")
+                        .map_err(|e| format!("{key} - {e}"))?,
+                );
+            }
+        }
+        for (key, action) in [
+            ("IP_ROUTE_ALLOWLIST", "allow"),
+            ("IP_ROUTE_DENYLIST", "deny"),
+        ] {
+            for pair in std::env::var(key)
+                .unwrap_or_default()
+                .split(',')
+                .filter(|v| !v.trim().is_empty())
+            {
+                let (path_prefix, cidrs) =
+                    pair.split_once('=').ok_or_else(|| {
+                        format!(
+                            "{key} - invalid entry={pair} must be prefix=cidr"
+                        )
+                    })?;
+                if path_prefix.trim().trim_end_matches('/').is_empty() {
+                    return Err(format!(
+                        "{key} - invalid entry={pair} needs a path prefix"
+                    ));
+                }
+                for cidr in cidrs.split('|').filter(|v| !v.trim().is_empty()) {
+                    env_rules.push(
+                        IpAccessRule::parse_rule(action, cidr, path_prefix)
+                            .map_err(|e| format!("{key} - {e}"))?,
+                    );
+                }
+            }
+        }
+        Ok(IpAccessControl {
+            env_rules,
+            db_rules: Arc::new(RwLock::new(Vec::new())),
+        })
+    }
+
+    /// get_rules
+    ///
+    /// Get the environment variable rules followed by the
+    /// ``ip_rules`` table rules
+    ///
+    pub fn get_rules(&self) -> Vec<IpAccessRule> {
+        let mut rules = self.env_rules.clone();
+        rules.extend(self.db_rules.read().unwrap().iter().cloned());
+        rules
+    }
+
+    /// set_db_rules
+    ///
+    /// Replace the cached ``ip_rules`` table rules
+    ///
+    /// # Arguments
+    ///
+    /// * `rules` - `Vec<IpAccessRule>` - rules from the db
+    ///
+    pub fn set_db_rules(&self, rules: Vec<IpAccessRule>) {
+        *self.db_rules.write().unwrap() = rules;
+    }
+
+    /// check_request
+    ///
+    /// Check a client address against every rule for a url path
+    /// (see
+    /// [`check_ip_rules`](crate::core::server::ip_access::check_ip_rules))
+    ///
+    /// # Arguments
+    ///
+    /// * `ip` - `&IpAddr` - client address
+    /// * `request_uri` - `&str` - unprefixed url path
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) when the request must be
+    /// rejected with a ``403``
+    ///
+    pub fn check_request(
+        &self,
+        ip: &IpAddr,
+        request_uri: &str,
+    ) -> Result<(), String> {
+        if self.env_rules.is_empty() && self.db_rules.read().unwrap().is_empty()
+        {
+            return Ok(());
+        }
+        check_ip_rules(&self.get_rules(), ip, request_uri)
+    }
+}
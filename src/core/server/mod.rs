@@ -2,7 +2,23 @@
 //! [`struct CoreHttpRequest`](crate::core::server::core_http_request::CoreHttpRequest)
 //! to all hyper worker threads when an HTTP request is received
 //!
+pub mod access_log;
+pub mod admission_control;
+pub mod api_version;
+pub mod connection_limits;
 pub mod core_http_request;
 pub mod core_services;
+pub mod custom_route;
+pub mod geo_ip;
+pub mod ip_access;
+pub mod request_body_limits;
+pub mod request_deadline;
+pub mod response_format;
+pub mod rest_api_server;
+pub mod run_admission_probe;
 pub mod run_server;
 pub mod start_core_server;
+pub mod startup_wait;
+pub mod static_assets;
+pub mod tenant_resolver;
+pub mod trusted_proxies;
@@ -0,0 +1,355 @@
+//! Enforce request body sizes and content types before a
+//! request is routed
+//!
+//! Requests with a body on the json apis must send a
+//! ``Content-Type: application/json`` header (``415``
+//! otherwise), and bodies larger than the route group's
+//! limit are rejected with a ``413`` before they are read.
+//! Raw user data uploads (``POST /user/data``) must declare
+//! a valid ``Content-Type`` that is in the optional
+//! ``UPLOAD_ALLOWED_CONTENT_TYPES`` list, and their size is
+//! limited by the ``max_upload_size_bytes`` runtime setting.
+//!
+use futures::StreamExt;
+
+use hyper::body::HttpBody;
+use hyper::header::HeaderValue;
+use hyper::header::CONTENT_LENGTH;
+use hyper::header::CONTENT_TYPE;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Method;
+
+use crate::core::server::admission_control::get_route_group;
+use crate::core::server::admission_control::ADMISSION_ROUTE_GROUPS;
+
+/// RequestBodyLimits
+///
+/// Settings for rejecting oversized bodies and unsupported
+/// content types
+///
+/// # Supported Environment Variables
+///
+/// ```bash
+/// # reject json api bodies without an application/json
+/// # Content-Type with a 415
+/// export REQUEST_ENFORCE_JSON_CONTENT_TYPE="1"
+/// # max json api body size
+/// export REQUEST_MAX_BODY_BYTES="262144"
+/// # per route group max body sizes (auth, admin, user,
+/// # data or search)
+/// export REQUEST_ROUTE_MAX_BODY_BYTES="auth=16384"
+/// # allowed Content-Type values for raw uploads
+/// # (type/* wildcards, empty = any valid media type)
+/// export UPLOAD_ALLOWED_CONTENT_TYPES="text/*,application/json,image/png"
+/// ```
+///
+/// # Arguments
+///
+/// * `enforce_json_content_type` - `bool` - require an
+///   ``application/json`` ``Content-Type`` on json apis
+/// * `max_body_bytes` - `u64` - max json api body size
+/// * `route_max_body_bytes` - `Vec<(String, u64)>` - max body
+///   size for a route group
+/// * `upload_content_types` - `Vec<String>` - allowed raw
+///   upload content types (empty = any)
+///
+#[derive(Clone, Default)]
+pub struct RequestBodyLimits {
+    pub enforce_json_content_type: bool,
+    pub max_body_bytes: u64,
+    pub route_max_body_bytes: Vec<(String, u64)>,
+    pub upload_content_types: Vec<String>,
+}
+
+impl RequestBodyLimits {
+    /// build_request_body_limits
+    ///
+    /// Build a
+    /// [`RequestBodyLimits`](crate::core::server::request_body_limits::RequestBodyLimits)
+    /// from environment variables. Unsupported route groups
+    /// and sizes are logged and ignored.
+    ///
+    pub fn build_request_body_limits() -> Self {
+        let enforce_s = std::env::var("REQUEST_ENFORCE_JSON_CONTENT_TYPE")
+            .unwrap_or_else(|_| "1".to_string());
+        RequestBodyLimits {
+            enforce_json_content_type: enforce_s == "1" || enforce_s == "true",
+            max_body_bytes: std::env::var("REQUEST_MAX_BODY_BYTES")
+                .unwrap_or_else(|_| "262144".to_string())
+                .parse::<u64>()
+                .unwrap_or(262144),
+            route_max_body_bytes: parse_route_max_body_bytes(
+                &std::env::var("REQUEST_ROUTE_MAX_BODY_BYTES")
+                    .unwrap_or_default(),
+            ),
+            upload_content_types: std::env::var("UPLOAD_ALLOWED_CONTENT_TYPES")
+                .unwrap_or_default()
+                .split(',')
+                .map(|v| v.trim().to_lowercase())
+                .filter(|v| !v.is_empty())
+                .collect(),
+        }
+    }
+
+    /// get_max_body_bytes
+    ///
+    /// Get the max body size for a request's route group
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - [`Method`](hyper::Method) - request method
+    /// * `request_uri` - `&str` - url path
+    ///
+    pub fn get_max_body_bytes(
+        &self,
+        method: &Method,
+        request_uri: &str,
+    ) -> u64 {
+        let group = get_route_group(method, request_uri);
+        self.route_max_body_bytes
+            .iter()
+            .find(|(g, _)| g == group)
+            .map(|(_, max_bytes)| *max_bytes)
+            .unwrap_or(self.max_body_bytes)
+    }
+
+    /// check_request
+    ///
+    /// Check the ``Content-Type`` and ``Content-Length`` of a
+    /// request and read json api bodies up to the route
+    /// group's limit (chunked bodies do not send a
+    /// ``Content-Length``)
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - [`Method`](hyper::Method) - request method
+    /// * `request_uri` - `&str` - url path
+    /// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+    ///   hashmap containing headers in key-value pairs
+    /// * `body` - `hyper::Body` - the request body
+    ///
+    /// # Returns
+    ///
+    /// Ok(`hyper::Body`) - the request body for the route
+    /// handler
+    ///
+    /// # Errors
+    ///
+    /// `Err((u16, String))` - HTTP status code (``413`` or
+    /// ``415``) and an error message for the client
+    ///
+    pub async fn check_request(
+        &self,
+        method: &Method,
+        request_uri: &str,
+        headers: &HeaderMap<HeaderValue>,
+        body: Body,
+    ) -> Result<Body, (u16, String)> {
+        if *method == Method::GET
+            || *method == Method::HEAD
+            || *method == Method::OPTIONS
+        {
+            return Ok(body);
+        }
+        let content_type = headers
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("")
+            .trim()
+            .to_lowercase();
+        // raw uploads are read by the handler with the
+        // max_upload_size_bytes runtime setting
+        if *method == Method::POST && request_uri == "/user/data" {
+            self.check_upload_content_type(&content_type)?;
+            return Ok(body);
+        }
+        // resumable upload chunks are read by the handler with
+        // the UPLOAD_RESUMABLE_MAX_CHUNK_BYTES limit
+        if *method == Method::PUT
+            && request_uri.starts_with("/user/data/")
+            && request_uri.ends_with("/chunks")
+        {
+            return Ok(body);
+        }
+        let content_length = headers
+            .get(CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok());
+        // http/2 bodies do not need a Content-Length or a
+        // Transfer-Encoding header
+        let has_body = content_length.unwrap_or(0) > 0
+            || (content_length.is_none() && !body.is_end_stream());
+        if !has_body {
+            return Ok(body);
+        }
+        if self.enforce_json_content_type
+            && !is_json_content_type(&content_type)
+        {
+            return Err((
+                415,
+                format!(
+                    "unsupported Content-Type '{content_type}' for \
+                    {method} {request_uri} - must be application/json"
+                ),
+            ));
+        }
+        let max_body_bytes = self.get_max_body_bytes(method, request_uri);
+        if max_body_bytes < 1 {
+            return Ok(body);
+        }
+        let too_large_msg = format!(
+            "request body for {method} {request_uri} is larger than \
+            the max size of {max_body_bytes} bytes"
+        );
+        if content_length.unwrap_or(0) > max_body_bytes {
+            return Err((413, too_large_msg));
+        }
+        let mut body = body;
+        let mut contents: Vec<u8> = Vec::new();
+        while let Some(chunk) = body.next().await {
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    return Err((
+                        400,
+                        format!(
+                            "failed to read the request body with err='{e}'"
+                        ),
+                    ));
+                }
+            };
+            if (contents.len() + chunk.len()) as u64 > max_body_bytes {
+                return Err((413, too_large_msg));
+            }
+            contents.extend_from_slice(&chunk);
+        }
+        Ok(Body::from(contents))
+    }
+
+    /// check_upload_content_type
+    ///
+    /// Require raw uploads to declare a ``type/subtype``
+    /// ``Content-Type`` that is in the
+    /// ``UPLOAD_ALLOWED_CONTENT_TYPES`` list (when set).
+    /// ``multipart/form-data`` uploads are always allowed.
+    ///
+    /// # Arguments
+    ///
+    /// * `content_type` - `&str` - lowercased ``Content-Type``
+    ///
+    /// # Errors
+    ///
+    /// `Err((415, String))` - HTTP status code
+    /// and an error message for the client
+    ///
+    fn check_upload_content_type(
+        &self,
+        content_type: &str,
+    ) -> Result<(), (u16, String)> {
+        let media_type = content_type.split(';').next().unwrap_or("").trim();
+        if media_type == "multipart/form-data" {
+            return Ok(());
+        }
+        let is_valid = match media_type.split_once('/') {
+            Some((t, s)) => {
+                !t.is_empty()
+                    && !s.is_empty()
+                    && !s.contains('/')
+                    && media_type.chars().all(|c| {
+                        c.is_ascii_alphanumeric() || "!#$&-^_.+/".contains(c)
+                    })
+            }
+            None => false,
+        };
+        if !is_valid {
+            return Err((
+                415,
+                format!(
+                    "User data upload rejected - missing or invalid \
+                    Content-Type '{content_type}'"
+                ),
+            ));
+        }
+        if self.upload_content_types.is_empty()
+            || self.upload_content_types.iter().any(|allowed| {
+                match allowed.strip_suffix("/*") {
+                    Some(prefix) => media_type
+                        .split_once('/')
+                        .map(|(t, _)| t == prefix)
+                        .unwrap_or(false),
+                    None => allowed == media_type,
+                }
+            })
+        {
+            return Ok(());
+        }
+        Err((
+            415,
+            format!(
+                "User data upload rejected - Content-Type \
+                '{media_type}' is not one of: {}",
+                self.upload_content_types.join(", ")
+            ),
+        ))
+    }
+}
+
+/// is_json_content_type
+///
+/// Check if a ``Content-Type`` is ``application/json``
+/// (or a ``+json`` media type)
+///
+/// # Arguments
+///
+/// * `content_type` - `&str` - ``Content-Type`` header value
+///
+/// # Examples
+///
+/// ```rust
+/// use restapi::core::server::request_body_limits::is_json_content_type;
+/// assert!(is_json_content_type("application/json"));
+/// assert!(is_json_content_type("application/json; charset=utf-8"));
+/// assert!(is_json_content_type("application/merge-patch+json"));
+/// assert!(!is_json_content_type("application/x-www-form-urlencoded"));
+/// assert!(!is_json_content_type(""));
+/// ```
+///
+pub fn is_json_content_type(content_type: &str) -> bool {
+    let media_type = content_type
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_lowercase();
+    media_type == "application/json"
+        || (media_type.starts_with("application/")
+            && media_type.ends_with("+json"))
+}
+
+/// parse_route_max_body_bytes
+///
+/// Parse a comma-separated ``group=bytes`` list for
+/// ``REQUEST_ROUTE_MAX_BODY_BYTES``
+///
+fn parse_route_max_body_bytes(value: &str) -> Vec<(String, u64)> {
+    let mut route_max_body_bytes: Vec<(String, u64)> = Vec::new();
+    for pair in value.split(',').filter(|v| !v.trim().is_empty()) {
+        let parsed = pair
+            .split_once('=')
+            .map(|(g, b)| (g.trim(), b.trim().parse::<u64>()));
+        match parsed {
+            Some((group, Ok(max_bytes)))
+                if ADMISSION_ROUTE_GROUPS.contains(&group) =>
+            {
+                route_max_body_bytes.push((group.to_string(), max_bytes));
+            }
+            _ => {
+                warn!(
+                    "ignoring unsupported REQUEST_ROUTE_MAX_BODY_BYTES={pair}"
+                );
+            }
+        }
+    }
+    route_max_body_bytes
+}
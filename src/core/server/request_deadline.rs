@@ -0,0 +1,201 @@
+//! Per-request deadlines from the ``X-Request-Timeout``
+//! or ``grpc-timeout`` header
+//!
+//! [`handle_request`](crate::handle_request::handle_request)
+//! runs each request under its deadline. All db queries,
+//! s3 calls and kafka publishes for a request are awaited
+//! inside the handler, so when the deadline passes the
+//! handler future is dropped and no further work is started
+//! for a client that has already given up. The client gets a
+//! ``504`` response. A db query that is already running
+//! finishes on the postgres side, but its result is ignored.
+//!
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+
+use hyper::header::HeaderValue;
+use hyper::HeaderMap;
+
+use crate::monitoring::metric_types::register_int_counter;
+use crate::monitoring::metric_types::IntCounter;
+
+lazy_static! {
+    pub static ref REQUEST_DEADLINE_EXCEEDED_COUNTER: IntCounter =
+        register_int_counter!(
+            "request_deadline_exceeded_total",
+            "Number of requests abandoned after their deadline passed."
+        )
+        .unwrap();
+}
+
+/// RequestDeadline
+///
+/// Settings for deriving a per-request deadline
+///
+/// # Supported Environment Variables
+///
+/// ```bash
+/// # deadline for requests without a timeout header (0 = none)
+/// export REQUEST_TIMEOUT_DEFAULT_MS="0"
+/// # max deadline a client can ask for
+/// export REQUEST_TIMEOUT_MAX_MS="60000"
+/// ```
+///
+/// # Supported Headers
+///
+/// * `X-Request-Timeout` - seconds (``2`` or ``0.5``)
+///   or milliseconds with a ``ms`` suffix (``500ms``)
+/// * `grpc-timeout` - up to 8 digits and a unit
+///   (``H``, ``M``, ``S``, ``m``, ``u`` or ``n``)
+///   like ``500m``
+///
+/// # Arguments
+///
+/// * `default_ms` - `u64` - deadline in milliseconds
+///   for requests without a timeout header (`0` = none)
+/// * `max_ms` - `u64` - max deadline in milliseconds
+///   (`0` = no max)
+///
+#[derive(Clone, Default)]
+pub struct RequestDeadline {
+    pub default_ms: u64,
+    pub max_ms: u64,
+}
+
+impl RequestDeadline {
+    /// build_request_deadline
+    ///
+    /// Build a
+    /// [`RequestDeadline`](crate::core::server::request_deadline::RequestDeadline)
+    /// from environment variables
+    ///
+    pub fn build_request_deadline() -> Self {
+        let get_env = |key: &str, default: u64| -> u64 {
+            std::env::var(key)
+                .unwrap_or_else(|_| format!("{default}"))
+                .parse::<u64>()
+                .unwrap_or(default)
+        };
+        RequestDeadline {
+            default_ms: get_env("REQUEST_TIMEOUT_DEFAULT_MS", 0),
+            max_ms: get_env("REQUEST_TIMEOUT_MAX_MS", 60000),
+        }
+    }
+
+    /// get_timeout
+    ///
+    /// Get the time left for a request from its headers.
+    /// Client timeouts are capped at `max_ms`.
+    ///
+    /// # Arguments
+    ///
+    /// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+    ///   request headers
+    ///
+    /// # Returns
+    ///
+    /// `Ok(None)` when the request has no deadline or
+    /// `Ok(Some(`[`Duration`](std::time::Duration)`))`
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) for an invalid timeout header
+    ///
+    pub fn get_timeout(
+        &self,
+        headers: &HeaderMap<HeaderValue>,
+    ) -> Result<Option<Duration>, String> {
+        let timeout = match (
+            headers.get("x-request-timeout"),
+            headers.get("grpc-timeout"),
+        ) {
+            (Some(v), _) => Some(parse_header(v, parse_request_timeout)?),
+            (None, Some(v)) => Some(parse_header(v, parse_grpc_timeout)?),
+            (None, None) => match self.default_ms {
+                0 => None,
+                ms => Some(Duration::from_millis(ms)),
+            },
+        };
+        Ok(timeout.map(|v| match self.max_ms {
+            0 => v,
+            ms => v.min(Duration::from_millis(ms)),
+        }))
+    }
+}
+
+/// parse_header
+///
+/// Parse a timeout header value with `parser`
+///
+fn parse_header(
+    value: &HeaderValue,
+    parser: fn(&str) -> Option<Duration>,
+) -> Result<Duration, String> {
+    let value_str = value.to_str().unwrap_or("").trim();
+    match parser(value_str) {
+        Some(v) => Ok(v),
+        None => Err(format!("invalid request timeout header={value_str}")),
+    }
+}
+
+/// parse_request_timeout
+///
+/// Parse an `X-Request-Timeout` value in seconds
+/// (``2`` or ``0.5``) or milliseconds (``500ms``)
+///
+/// # Examples
+///
+/// ```rust
+/// use std::time::Duration;
+/// use restapi::core::server::request_deadline::parse_request_timeout;
+/// assert_eq!(parse_request_timeout("0.5"), Some(Duration::from_millis(500)));
+/// assert_eq!(parse_request_timeout("250ms"), Some(Duration::from_millis(250)));
+/// assert_eq!(parse_request_timeout("-1"), None);
+/// ```
+///
+pub fn parse_request_timeout(value: &str) -> Option<Duration> {
+    match value.strip_suffix("ms") {
+        Some(ms) => ms.parse::<u64>().ok().map(Duration::from_millis),
+        None => match value.strip_suffix('s').unwrap_or(value).parse::<f64>() {
+            Ok(seconds) => Duration::try_from_secs_f64(seconds).ok(),
+            Err(_) => None,
+        },
+    }
+}
+
+/// parse_grpc_timeout
+///
+/// Parse a `grpc-timeout` value: up to 8 digits
+/// followed by a unit (``H``, ``M``, ``S``, ``m``,
+/// ``u`` or ``n``)
+///
+/// # Examples
+///
+/// ```rust
+/// use std::time::Duration;
+/// use restapi::core::server::request_deadline::parse_grpc_timeout;
+/// assert_eq!(parse_grpc_timeout("500m"), Some(Duration::from_millis(500)));
+/// assert_eq!(parse_grpc_timeout("2S"), Some(Duration::from_secs(2)));
+/// assert_eq!(parse_grpc_timeout("123456789S"), None);
+/// ```
+///
+pub fn parse_grpc_timeout(value: &str) -> Option<Duration> {
+    if value.len() < 2 || value.len() > 9 || !value.is_ascii() {
+        return None;
+    }
+    let (digits, unit) = value.split_at(value.len() - 1);
+    let amount = match digits.chars().all(|c| c.is_ascii_digit()) {
+        true => digits.parse::<u64>().ok()?,
+        false => return None,
+    };
+    match unit {
+        "H" => Some(Duration::from_secs(amount * 3600)),
+        "M" => Some(Duration::from_secs(amount * 60)),
+        "S" => Some(Duration::from_secs(amount)),
+        "m" => Some(Duration::from_millis(amount)),
+        "u" => Some(Duration::from_micros(amount)),
+        "n" => Some(Duration::from_nanos(amount)),
+        _ => None,
+    }
+}
@@ -0,0 +1,271 @@
+//! Trim and pretty-print json responses with the
+//! ``fields`` and ``pretty`` query parameters
+//!
+//! Any api that returns json accepts:
+//!
+//! - ``?fields=user_id,email`` - keep only the listed keys in
+//!   each record. Arrays of records (like the ``users`` in a
+//!   ``/user/search`` response) are trimmed row by row, and
+//!   the ``msg``, ``error_code`` and ``errors`` keys are
+//!   always kept.
+//! - ``?pretty=1`` - indent the json body
+//!
+//! Only json responses with a known length up to
+//! ``RESPONSE_FORMAT_MAX_BODY_BYTES`` are changed, so file
+//! downloads and server-sent event streams are never
+//! buffered.
+//!
+use hyper::body::HttpBody;
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::Response;
+
+/// keys that are kept by every ``fields`` filter
+pub const RESPONSE_FORMAT_ENVELOPE_KEYS: [&str; 3] =
+    ["msg", "error_code", "errors"];
+
+/// ResponseFormatQuery
+///
+/// Formatting options from a request's query string
+///
+/// # Arguments
+///
+/// * `fields` - `Vec<String>` - keys to keep in each record
+///   (empty = every key)
+/// * `pretty` - `bool` - indent the json body
+///
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ResponseFormatQuery {
+    pub fields: Vec<String>,
+    pub pretty: bool,
+}
+
+impl ResponseFormatQuery {
+    /// from_query
+    ///
+    /// Parse the ``fields`` and ``pretty`` query parameters
+    /// (repeated ``fields`` parameters are combined)
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - `Option<&str>` - url query string
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use restapi::core::server::response_format::ResponseFormatQuery;
+    /// let format = ResponseFormatQuery::from_query(
+    ///     Some("fields=user_id,%20email&pretty=1&page=2"),
+    /// );
+    /// assert_eq!(format.fields, vec!["user_id", "email"]);
+    /// assert!(format.pretty);
+    /// assert!(!ResponseFormatQuery::from_query(None).is_set());
+    /// ```
+    ///
+    pub fn from_query(query: Option<&str>) -> Self {
+        let mut format = ResponseFormatQuery::default();
+        let query = match query {
+            Some(query) => query,
+            None => return format,
+        };
+        for (key, value) in url::form_urlencoded::parse(query.as_bytes()) {
+            match key.as_ref() {
+                "fields" => format.fields.extend(
+                    value
+                        .split(',')
+                        .map(|v| v.trim().to_string())
+                        .filter(|v| !v.is_empty()),
+                ),
+                "pretty" => format.pretty = value == "1" || value == "true",
+                _ => {}
+            }
+        }
+        format
+    }
+
+    /// is_set
+    ///
+    /// Check if the response needs to be changed
+    ///
+    pub fn is_set(&self) -> bool {
+        self.pretty || !self.fields.is_empty()
+    }
+}
+
+/// filter_json_fields
+///
+/// Keep only the listed keys in a json record. Unlisted keys
+/// that hold records (objects or arrays of objects) are
+/// filtered the same way so rows are trimmed inside their
+/// response envelope, and nested objects without any kept
+/// keys are removed. Listed keys are kept with their full
+/// value.
+///
+/// # Arguments
+///
+/// * `value` - `&mut serde_json::Value` - json to filter in place
+/// * `fields` - `&[String]` - keys to keep
+///
+/// # Examples
+///
+/// ```rust
+/// use restapi::core::server::response_format::filter_json_fields;
+/// let mut value = serde_json::json!({
+///     "users": [
+///         {"user_id": 1, "email": "a@b.com", "role": "user"},
+///     ],
+///     "page": 0,
+///     "msg": "success",
+/// });
+/// let fields = vec!["user_id".to_string(), "email".to_string()];
+/// filter_json_fields(&mut value, &fields);
+/// assert_eq!(
+///     value,
+///     serde_json::json!({
+///         "users": [{"user_id": 1, "email": "a@b.com"}],
+///         "msg": "success",
+///     })
+/// );
+/// ```
+///
+pub fn filter_json_fields(value: &mut serde_json::Value, fields: &[String]) {
+    match value {
+        serde_json::Value::Array(rows) => {
+            for row in rows.iter_mut() {
+                filter_json_fields(row, fields);
+            }
+        }
+        serde_json::Value::Object(map) => {
+            map.retain(|key, value| {
+                if fields.contains(key)
+                    || RESPONSE_FORMAT_ENVELOPE_KEYS.contains(&key.as_str())
+                {
+                    return true;
+                }
+                match value {
+                    serde_json::Value::Object(_) => {
+                        filter_json_fields(value, fields);
+                        value.as_object().is_some_and(|v| !v.is_empty())
+                    }
+                    serde_json::Value::Array(rows)
+                        if rows.iter().all(|row| row.is_object()) =>
+                    {
+                        filter_json_fields(value, fields);
+                        true
+                    }
+                    _ => false,
+                }
+            });
+        }
+        _ => {}
+    }
+}
+
+/// ResponseFormat
+///
+/// Settings for the ``fields`` and ``pretty`` query
+/// parameters
+///
+/// # Supported Environment Variables
+///
+/// ```bash
+/// export RESPONSE_FORMAT_ENABLED="1"
+/// # larger json responses are sent unchanged
+/// export RESPONSE_FORMAT_MAX_BODY_BYTES="4194304"
+/// ```
+///
+/// # Arguments
+///
+/// * `enabled` - `bool` - support the query parameters
+/// * `max_body_bytes` - `u64` - largest response body that
+///   is buffered and formatted
+///
+#[derive(Clone, Default)]
+pub struct ResponseFormat {
+    pub enabled: bool,
+    pub max_body_bytes: u64,
+}
+
+impl ResponseFormat {
+    /// build_response_format
+    ///
+    /// Build a
+    /// [`ResponseFormat`](crate::core::server::response_format::ResponseFormat)
+    /// from environment variables
+    ///
+    pub fn build_response_format() -> Self {
+        let enabled_s = std::env::var("RESPONSE_FORMAT_ENABLED")
+            .unwrap_or_else(|_| "1".to_string());
+        ResponseFormat {
+            enabled: enabled_s == "1" || enabled_s == "true",
+            max_body_bytes: std::env::var("RESPONSE_FORMAT_MAX_BODY_BYTES")
+                .unwrap_or_else(|_| "4194304".to_string())
+                .parse::<u64>()
+                .unwrap_or(4194304),
+        }
+    }
+
+    /// format_response
+    ///
+    /// Apply the ``fields`` and ``pretty`` query parameters
+    /// to a json response. Non-json bodies, bodies without a
+    /// known length and bodies larger than
+    /// ``max_body_bytes`` are returned unchanged.
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - [`ResponseFormatQuery`](crate::core::server::response_format::ResponseFormatQuery) -
+    ///   options from the request's query string
+    /// * `response` - [`Response`](hyper::Response)
+    ///
+    pub async fn format_response(
+        &self,
+        format: &ResponseFormatQuery,
+        response: Response<Body>,
+    ) -> Response<Body> {
+        if !self.enabled || !format.is_set() {
+            return response;
+        }
+        let (mut parts, body) = response.into_parts();
+        let is_json = parts
+            .headers
+            .get("Content-Type")
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.contains("json"))
+            .unwrap_or(true);
+        let body_len = body.size_hint().exact();
+        if !is_json || body_len.is_none_or(|len| len > self.max_body_bytes) {
+            return Response::from_parts(parts, body);
+        }
+        let bytes = match hyper::body::to_bytes(body).await {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                error!(
+                    "failed to read response body for formatting \
+                    with err='{e}'"
+                );
+                return Response::from_parts(parts, Body::empty());
+            }
+        };
+        let mut value =
+            match serde_json::from_slice::<serde_json::Value>(&bytes) {
+                Ok(value) => value,
+                _ => return Response::from_parts(parts, Body::from(bytes)),
+            };
+        if !format.fields.is_empty() {
+            filter_json_fields(&mut value, &format.fields);
+        }
+        let body = match format.pretty {
+            true => serde_json::to_string_pretty(&value).unwrap(),
+            false => value.to_string(),
+        };
+        parts.headers.remove("Content-Length");
+        if !parts.headers.contains_key("Content-Type") {
+            parts.headers.insert(
+                "Content-Type",
+                HeaderValue::from_static("application/json"),
+            );
+        }
+        Response::from_parts(parts, Body::from(body))
+    }
+}
@@ -0,0 +1,486 @@
+//! Build and serve the api server from code
+//!
+//! Crates that embed the api server can set the db
+//! endpoints, tls paths, jwt keys, kafka options and custom
+//! routes with a
+//! [`RestApiServerBuilder`](crate::core::server::rest_api_server::RestApiServerBuilder)
+//! instead of exporting environment variables. Anything
+//! that is not set on the builder falls back to the same
+//! environment variables and defaults as
+//! [`build_core_config`](crate::core::core_config::build_core_config).
+//!
+//! ```rust,no_run
+//! use restapi::core::server::rest_api_server::RestApiServerBuilder;
+//!
+//! # async fn run() -> Result<(), String> {
+//! RestApiServerBuilder::new("my-api")
+//!     .api_endpoint("0.0.0.0:3000")
+//!     .api_tls("./tls/ca/ca.pem", "./tls/api/server.pem", "./tls/api/server-key.pem")
+//!     .db_endpoint("0.0.0.0:5432")
+//!     .db_credentials("datawriter", "123321")
+//!     .db_name("mydb")
+//!     .jwt_key_files("./jwt/private-key-pkcs8.pem", "./jwt/public-key.pem")
+//!     .build()
+//!     .await?
+//!     .serve()
+//!     .await;
+//! # Ok(())
+//! # }
+//! ```
+//!
+use std::sync::Arc;
+
+#[cfg(feature = "kafka")]
+use kafka_threadpool::config::kafka_client_config::KafkaClientConfig;
+#[cfg(feature = "kafka")]
+use kafka_threadpool::pool::start_threads_from_config::start_threads_from_config;
+
+use crate::core::core_config::build_core_config_from_builder;
+use crate::core::core_config::validate_core_config_from_builder;
+use crate::core::core_config::CoreConfig;
+use crate::core::server::connection_limits::ConnectionLimits;
+use crate::core::server::custom_route::CustomRoute;
+use crate::core::server::custom_route::CustomRoutes;
+use crate::core::server::run_server::run_server;
+use crate::core::startup_error::StartupError;
+use crate::i18n::translation_provider::TranslationProvider;
+use crate::jwt::token_claims::TokenClaimsProvider;
+use crate::requests::auth::login_risk::LoginRiskHook;
+use crate::secrets::secrets_provider::SecretsProvider;
+use crate::tls::get_tls_config::TlsPaths;
+use crate::utils::password_migration::LegacyPasswordHasher;
+
+/// RestApiServerBuilder
+///
+/// Set the api server's configuration in code. Every
+/// `None` value falls back to its environment variable.
+///
+/// # Arguments
+///
+/// * `label` - `String` - logging label (``SERVER_NAME_LABEL``
+///   still wins when it is set)
+/// * `api_endpoint` - `Option<String>` - listening address
+///   (``API_ENDPOINT``)
+/// * `api_tls` - `Option<TlsPaths>` - api tls assets
+///   (``API_TLS_CA``, ``API_TLS_CERT`` and ``API_TLS_KEY``)
+/// * `db_endpoint` - `Option<String>` - postgres address
+///   (``POSTGRES_ENDPOINT``)
+/// * `db_read_endpoints` - `Option<Vec<String>>` - postgres
+///   read replica addresses (``POSTGRES_READ_ENDPOINTS``)
+/// * `db_username` - `Option<String>` - postgres user
+///   (``POSTGRES_USERNAME``)
+/// * `db_password` - `Option<String>` - postgres password
+///   (``POSTGRES_PASSWORD``)
+/// * `db_name` - `Option<String>` - postgres database
+///   (``DB_NAME``)
+/// * `db_tls` - `Option<TlsPaths>` - postgres client tls assets
+///   (``POSTGRES_TLS_CA``, ``POSTGRES_TLS_CERT`` and
+///   ``POSTGRES_TLS_KEY``)
+/// * `server_password_salt` - `Option<String>` - argon2
+///   password salt (``SERVER_PASSWORD_SALT``)
+/// * `jwt_key_dir` - `Option<String>` - directory with the
+///   jwt key ring (``SERVER_PKI_DIR_JWT``)
+/// * `jwt_private_key` - `Option<String>` - `default` jwt
+///   private key (``TOKEN_ALGO_PRIVATE_KEY``)
+/// * `jwt_public_key` - `Option<String>` - `default` jwt
+///   public key (``TOKEN_ALGO_PUBLIC_KEY``)
+/// * `kafka_publish_events` - `Option<bool>` - publish user
+///   events (``KAFKA_PUBLISH_EVENTS``)
+/// * `kafka_user_topic` - `Option<String>` - user events topic
+///   (``KAFKA_TOPIC_USER_EVENTS``)
+/// * `kafka_event_topics` - `Vec<(String, String)>` - extra
+///   `(event name or words, topic)` routes
+///   (``USER_EVENTS_TOPIC_*``)
+/// * `kafka_client_config` - `Option<KafkaClientConfig>` -
+///   kafka threadpool config (``KAFKA_*``, requires the
+///   ``kafka`` feature)
+/// * `connection_limits` - `Option<ConnectionLimits>` - max
+///   open connections and hyper tuning (``API_MAX_CONNECTIONS``
+///   and the other
+///   [`ConnectionLimits`](crate::core::server::connection_limits::ConnectionLimits)
+///   variables)
+/// * `token_claims_provider` - `Option<Arc<dyn TokenClaimsProvider>>` -
+///   per-user jwt claims (see
+///   [`TokenClaimsProvider`](crate::jwt::token_claims::TokenClaimsProvider))
+/// * `login_risk_hook` - `Option<Arc<dyn LoginRiskHook>>` -
+///   allows, challenges or denies password logins (see
+///   [`LoginRiskHook`](crate::requests::auth::login_risk::LoginRiskHook))
+/// * `translation_provider` - `Option<Arc<dyn TranslationProvider>>` -
+///   translates the response messages instead of the json
+///   message catalogs (see
+///   [`TranslationProvider`](crate::i18n::translation_provider::TranslationProvider))
+/// * `secrets_provider` - `Option<Arc<dyn SecretsProvider>>` -
+///   fetches the postgres password, password salt and jwt
+///   keys instead of ``SECRETS_PROVIDER`` (see
+///   [`SecretsProvider`](crate::secrets::secrets_provider::SecretsProvider))
+/// * `legacy_password_hashers` - `Vec<Arc<dyn LegacyPasswordHasher>>` -
+///   verify imported password hashes that are rehashed at
+///   login (see
+///   [`LegacyPasswordHasher`](crate::utils::password_migration::LegacyPasswordHasher))
+/// * `custom_routes` - [`CustomRoutes`](crate::core::server::custom_route::CustomRoutes) -
+///   routes served before the built-in routes
+///
+#[derive(Clone, Default)]
+pub struct RestApiServerBuilder {
+    pub label: String,
+    pub api_endpoint: Option<String>,
+    pub api_tls: Option<TlsPaths>,
+    pub db_endpoint: Option<String>,
+    pub db_read_endpoints: Option<Vec<String>>,
+    pub db_username: Option<String>,
+    pub db_password: Option<String>,
+    pub db_name: Option<String>,
+    pub db_tls: Option<TlsPaths>,
+    pub server_password_salt: Option<String>,
+    pub jwt_key_dir: Option<String>,
+    pub jwt_private_key: Option<String>,
+    pub jwt_public_key: Option<String>,
+    pub kafka_publish_events: Option<bool>,
+    pub kafka_user_topic: Option<String>,
+    pub kafka_event_topics: Vec<(String, String)>,
+    #[cfg(feature = "kafka")]
+    pub kafka_client_config: Option<KafkaClientConfig>,
+    pub connection_limits: Option<ConnectionLimits>,
+    pub token_claims_provider: Option<Arc<dyn TokenClaimsProvider>>,
+    pub login_risk_hook: Option<Arc<dyn LoginRiskHook>>,
+    pub translation_provider: Option<Arc<dyn TranslationProvider>>,
+    pub secrets_provider: Option<Arc<dyn SecretsProvider>>,
+    pub legacy_password_hashers: Vec<Arc<dyn LegacyPasswordHasher>>,
+    pub custom_routes: CustomRoutes,
+}
+
+impl RestApiServerBuilder {
+    /// new
+    ///
+    /// Start a builder where everything falls back to
+    /// environment variables
+    ///
+    /// # Arguments
+    ///
+    /// * `label` - `&str` - logging label
+    ///
+    pub fn new(label: &str) -> Self {
+        RestApiServerBuilder {
+            label: label.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// api_endpoint
+    ///
+    /// Set the listening address (``0.0.0.0:3000``)
+    ///
+    pub fn api_endpoint(mut self, address: &str) -> Self {
+        self.api_endpoint = Some(address.to_string());
+        self
+    }
+
+    /// api_tls
+    ///
+    /// Set the api server's tls certificate authority,
+    /// certificate and key paths
+    ///
+    pub fn api_tls(mut self, ca: &str, cert: &str, key: &str) -> Self {
+        self.api_tls = Some(TlsPaths {
+            ca: ca.to_string(),
+            key: key.to_string(),
+            cert: cert.to_string(),
+        });
+        self
+    }
+
+    /// db_endpoint
+    ///
+    /// Set the postgres address (``0.0.0.0:5432``)
+    ///
+    pub fn db_endpoint(mut self, address: &str) -> Self {
+        self.db_endpoint = Some(address.to_string());
+        self
+    }
+
+    /// db_read_endpoints
+    ///
+    /// Set the postgres read replica addresses
+    /// (an empty list sends every query to the primary)
+    ///
+    pub fn db_read_endpoints(mut self, addresses: &[&str]) -> Self {
+        self.db_read_endpoints =
+            Some(addresses.iter().map(|v| v.to_string()).collect());
+        self
+    }
+
+    /// db_credentials
+    ///
+    /// Set the postgres username and password
+    ///
+    pub fn db_credentials(mut self, username: &str, password: &str) -> Self {
+        self.db_username = Some(username.to_string());
+        self.db_password = Some(password.to_string());
+        self
+    }
+
+    /// db_name
+    ///
+    /// Set the postgres database name
+    ///
+    pub fn db_name(mut self, db_name: &str) -> Self {
+        self.db_name = Some(db_name.to_string());
+        self
+    }
+
+    /// db_tls
+    ///
+    /// Set the postgres client tls certificate authority,
+    /// certificate and key paths
+    ///
+    pub fn db_tls(mut self, ca: &str, cert: &str, key: &str) -> Self {
+        self.db_tls = Some(TlsPaths {
+            ca: ca.to_string(),
+            key: key.to_string(),
+            cert: cert.to_string(),
+        });
+        self
+    }
+
+    /// server_password_salt
+    ///
+    /// Set the argon2 password salt
+    ///
+    pub fn server_password_salt(mut self, salt: &str) -> Self {
+        self.server_password_salt = Some(salt.to_string());
+        self
+    }
+
+    /// jwt_key_dir
+    ///
+    /// Set the directory with the jwt key ring (the
+    /// `default` key files are in this directory unless
+    /// they are set with
+    /// [`jwt_key_files`](crate::core::server::rest_api_server::RestApiServerBuilder::jwt_key_files))
+    ///
+    pub fn jwt_key_dir(mut self, dir: &str) -> Self {
+        self.jwt_key_dir = Some(dir.to_string());
+        self
+    }
+
+    /// jwt_key_files
+    ///
+    /// Set the `default` jwt private and public key paths
+    ///
+    pub fn jwt_key_files(
+        mut self,
+        private_key: &str,
+        public_key: &str,
+    ) -> Self {
+        self.jwt_private_key = Some(private_key.to_string());
+        self.jwt_public_key = Some(public_key.to_string());
+        self
+    }
+
+    /// kafka_publish_events
+    ///
+    /// Enable or disable publishing user events
+    ///
+    pub fn kafka_publish_events(mut self, enabled: bool) -> Self {
+        self.kafka_publish_events = Some(enabled);
+        self
+    }
+
+    /// kafka_user_topic
+    ///
+    /// Set the topic for user events
+    ///
+    pub fn kafka_user_topic(mut self, topic: &str) -> Self {
+        self.kafka_user_topic = Some(topic.to_string());
+        self
+    }
+
+    /// kafka_event_topic
+    ///
+    /// Publish the events matching an event name or
+    /// ``_``-separated words in event names (``LOGIN``) to
+    /// another topic (same as ``USER_EVENTS_TOPIC_LOGIN``)
+    ///
+    pub fn kafka_event_topic(mut self, event: &str, topic: &str) -> Self {
+        self.kafka_event_topics
+            .push((event.to_uppercase(), topic.to_string()));
+        self
+    }
+
+    /// kafka_client_config
+    ///
+    /// Start the kafka threadpool with a
+    /// ``KafkaClientConfig`` instead of the ``KAFKA_*``
+    /// environment variables
+    ///
+    #[cfg(feature = "kafka")]
+    pub fn kafka_client_config(
+        mut self,
+        kafka_client_config: KafkaClientConfig,
+    ) -> Self {
+        self.kafka_client_config = Some(kafka_client_config);
+        self
+    }
+
+    /// connection_limits
+    ///
+    /// Set the max open connections, timeouts and hyper
+    /// buffer sizes
+    ///
+    pub fn connection_limits(
+        mut self,
+        connection_limits: ConnectionLimits,
+    ) -> Self {
+        self.connection_limits = Some(connection_limits);
+        self
+    }
+
+    /// token_claims_provider
+    ///
+    /// Add per-user claims to new jwts
+    ///
+    pub fn token_claims_provider(
+        mut self,
+        provider: Arc<dyn TokenClaimsProvider>,
+    ) -> Self {
+        self.token_claims_provider = Some(provider);
+        self
+    }
+
+    /// login_risk_hook
+    ///
+    /// Score password logins with a custom risk engine
+    ///
+    pub fn login_risk_hook(mut self, hook: Arc<dyn LoginRiskHook>) -> Self {
+        self.login_risk_hook = Some(hook);
+        self
+    }
+
+    /// translation_provider
+    ///
+    /// Translate the response messages with a custom
+    /// provider (requires ``MESSAGE_LOCALIZATION_ENABLED=1``)
+    ///
+    pub fn translation_provider(
+        mut self,
+        provider: Arc<dyn TranslationProvider>,
+    ) -> Self {
+        self.translation_provider = Some(provider);
+        self
+    }
+
+    /// secrets_provider
+    ///
+    /// Fetch the postgres password, password salt and jwt
+    /// keys from a custom secrets manager
+    ///
+    pub fn secrets_provider(
+        mut self,
+        provider: Arc<dyn SecretsProvider>,
+    ) -> Self {
+        self.secrets_provider = Some(provider);
+        self
+    }
+
+    /// legacy_password_hasher
+    ///
+    /// Verify password hashes imported from another system
+    /// and rehash them with the current scheme at login
+    ///
+    pub fn legacy_password_hasher(
+        mut self,
+        hasher: Arc<dyn LegacyPasswordHasher>,
+    ) -> Self {
+        self.legacy_password_hashers.push(hasher);
+        self
+    }
+
+    /// route
+    ///
+    /// Add a [`CustomRoute`](crate::core::server::custom_route::CustomRoute)
+    ///
+    pub fn route(mut self, route: Arc<dyn CustomRoute>) -> Self {
+        self.custom_routes.push(route);
+        self
+    }
+
+    /// build
+    ///
+    /// Build the
+    /// [`RestApiServer`](crate::core::server::rest_api_server::RestApiServer)
+    /// with the builder's values and the environment
+    /// variables for everything else
+    ///
+    /// # Errors
+    ///
+    /// Err([`StartupError`](crate::core::startup_error::StartupError))
+    /// when the
+    /// [`CoreConfig`](crate::core::core_config::CoreConfig)
+    /// cannot be built
+    ///
+    pub async fn build(self) -> Result<RestApiServer, StartupError> {
+        let config = build_core_config_from_builder(&self).await?;
+        Ok(RestApiServer {
+            config,
+            #[cfg(feature = "kafka")]
+            kafka_client_config: self.kafka_client_config,
+        })
+    }
+
+    /// validate
+    ///
+    /// Check the builder's values and the environment
+    /// variables for everything else without building the
+    /// server
+    ///
+    /// # Returns
+    ///
+    /// `Vec<StartupError>` - every
+    /// [`StartupError`](crate::core::startup_error::StartupError)
+    /// that was found (empty when the config is valid)
+    ///
+    pub async fn validate(&self) -> Vec<StartupError> {
+        validate_core_config_from_builder(self).await
+    }
+}
+
+/// RestApiServer
+///
+/// A built api server that is ready to serve
+///
+/// # Arguments
+///
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+///   for the server
+///
+pub struct RestApiServer {
+    pub config: CoreConfig,
+    #[cfg(feature = "kafka")]
+    kafka_client_config: Option<KafkaClientConfig>,
+}
+
+impl RestApiServer {
+    /// serve
+    ///
+    /// Start the threadpools and serve requests with
+    /// [`run_server`](crate::core::server::run_server::run_server)
+    ///
+    /// Returns `false` when the server could not start
+    ///
+    pub async fn serve(self) -> bool {
+        #[allow(unused_mut)]
+        let mut config = self.config;
+        #[cfg(feature = "kafka")]
+        if let Some(kafka_client_config) = self.kafka_client_config {
+            match start_threads_from_config(kafka_client_config).await {
+                Ok(kafka_pool) => config.kafka_pool = Some(kafka_pool),
+                Err(err_msg) => panic!(
+                    "{} - failed to start the kafka threadpool \
+                    with err='{err_msg}' - stopping",
+                    config.label
+                ),
+            }
+        }
+        run_server(&config).await
+    }
+}
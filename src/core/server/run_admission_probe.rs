@@ -0,0 +1,78 @@
+//! Background task that measures the bb8 db pool wait
+//! time for admission control
+//!
+use std::time::Duration;
+use std::time::Instant;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use crate::core::server::admission_control::AdmissionControl;
+
+/// run_admission_probe
+///
+/// Every `probe_interval_ms` record how long it takes to
+/// get a db pool connection with
+/// [`record_pool_wait`](crate::core::server::admission_control::AdmissionControl::record_pool_wait).
+/// The probe only waits for a connection when the pool has
+/// no idle connections, so an idle pool records ``0`` without
+/// taking a connection from the handlers. A probe that is
+/// still waiting after 4x `max_pool_wait_ms` records that
+/// time.
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `admission` - [`AdmissionControl`](crate::core::server::admission_control::AdmissionControl)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client db
+///   threadpool with required tls encryption
+///
+pub async fn run_admission_probe(
+    tracking_label: &str,
+    admission: AdmissionControl,
+    db_pool: Pool<PostgresConnectionManager<MakeTlsConnector>>,
+) {
+    if !admission.enabled {
+        return;
+    }
+    info!(
+        "{tracking_label} - \
+        admission control enabled with max_in_flight={} \
+        max_pool_wait_ms={}",
+        admission.max_in_flight, admission.max_pool_wait_ms
+    );
+    let max_probe_ms = admission.max_pool_wait_ms * 4;
+    loop {
+        let wait_ms = match db_pool.state().idle_connections {
+            0 => {
+                let start = Instant::now();
+                match tokio::time::timeout(
+                    Duration::from_millis(max_probe_ms),
+                    db_pool.get(),
+                )
+                .await
+                {
+                    Ok(Ok(conn)) => {
+                        drop(conn);
+                        start.elapsed().as_millis() as u64
+                    }
+                    Ok(Err(e)) => {
+                        warn!(
+                            "{tracking_label} - \
+                            admission probe failed to get a db \
+                            connection with err='{e}'"
+                        );
+                        max_probe_ms
+                    }
+                    Err(_) => max_probe_ms,
+                }
+            }
+            _ => 0,
+        };
+        admission.record_pool_wait(wait_ms);
+        tokio::time::sleep(Duration::from_millis(admission.probe_interval_ms))
+            .await;
+    }
+}
@@ -13,6 +13,11 @@ use crate::core::server::start_core_server::start_core_server;
 ///
 /// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
 ///
+/// # Returns
+///
+/// `false` when the server could not start so the caller can
+/// exit with a non-zero status
+///
 pub async fn run_server(config: &CoreConfig) -> bool {
     // boot up the server
     match start_core_server(config).await {
@@ -20,14 +25,13 @@ pub async fn run_server(config: &CoreConfig) -> bool {
             info!("{} - run_server.start_core_server done", config.label);
             true
         }
-        Err(hyper_error) => {
-            let err_msg = format!("{hyper_error}");
-            panic!(
+        Err(startup_error) => {
+            error!(
                 "{} - run_server.start_core_server failed with \
-                err='{err_msg}'",
+                err='{startup_error}'",
                 config.label
             );
+            false
         }
-    };
-    false
+    }
 }
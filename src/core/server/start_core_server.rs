@@ -6,15 +6,34 @@
 //! each tokio-spawned worker thread when a new HTTP request is received
 //!
 use std::sync::Arc;
+use std::time::Duration;
 
-use kafka_threadpool::kafka_publisher::KafkaPublisher;
-use kafka_threadpool::start_threadpool::start_threadpool;
-
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::pools::check_db_indexes::check_db_indexes;
+use crate::pools::db_read_pools::get_db_read_pools;
 use crate::pools::get_db_pool::get_db_pool;
 use crate::tls::tls_info::TlsInfo;
 
+use crate::archive::run_user_data_archiver::run_user_data_archiver;
+use crate::archive::run_user_data_lifecycle::run_user_data_lifecycle;
+use crate::archive::run_user_data_reconciler::run_user_data_reconciler;
 use crate::core::core_config::CoreConfig;
 use crate::core::server::core_services::CoreServices;
+use crate::core::server::run_admission_probe::run_admission_probe;
+use crate::core::startup_error::StartupError;
+use crate::demo::seed_demo_data::seed_demo_data;
+use crate::jwt::reload_jwt_keys::reload_jwt_keys_on_sighup;
+use crate::processing::run_user_data_pipeline::run_user_data_pipeline;
+use crate::processing::run_user_data_thumbnails::run_user_data_thumbnails;
+use crate::requests::user::user_data_quota::run_user_data_quota_metrics;
+use crate::secrets::refresh_secrets::run_secrets_refresher;
+use crate::settings::listen_for_settings_changes::listen_for_settings_changes;
+use crate::webhooks::run_webhook_dispatcher::run_webhook_dispatcher;
+
+/// pause before accepting again after an accept error that
+/// is not about a single connection (like running out of
+/// file descriptors with ``EMFILE`` or ``ENFILE``)
+pub const ACCEPT_ERROR_BACKOFF: Duration = Duration::from_millis(500);
 
 /// start_core_server
 ///
@@ -23,20 +42,56 @@ use crate::core::server::core_services::CoreServices;
 /// # Tasks
 ///
 /// 1. Start threadpools based off the ``CoreConfig``
+///    - Start the OTLP trace exporter (``OTEL_EXPORTER_OTLP_ENDPOINT``)
+///      with [`OtelConfig`](crate::monitoring::otel::OtelConfig)
 ///    - Build the encrypted bb8 threadpool ([`Pool`](bb8::Pool))
-///    - Build the encrypted kafka threadpool
-///      ([`KafkaPublisher`](kafka_threadpool::KafkaPublisher))
+///      and wait for postgres with
+///      [`StartupWait`](crate::core::server::startup_wait::StartupWait)
+///    - Wait for the kafka brokers and build the encrypted kafka
+///      threadpool
+///      ([`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher))
+///      unless the config has a ``kafka_pool`` from a
+///      [`RestApiServerBuilder`](crate::core::server::rest_api_server::RestApiServerBuilder)
+///    - Register the user event schema with the kafka schema
+///      registry (``KAFKA_SCHEMA_REGISTRY_URL``) with
+///      [`SchemaRegistry`](crate::kafka::schema_registry::SchemaRegistry)
+///    - Seed the demo users and data (``DEMO_MODE=1``) with
+///      [`seed_demo_data`](crate::demo::seed_demo_data::seed_demo_data)
+///    - Warn about missing db indexes with
+///      [`check_db_indexes`](crate::pools::check_db_indexes::check_db_indexes)
+///    - Listen for runtime settings changes with
+///      [`listen_for_settings_changes`](crate::settings::listen_for_settings_changes::listen_for_settings_changes)
+///    - Archive old ``users_data`` rows with
+///      [`run_user_data_archiver`](crate::archive::run_user_data_archiver::run_user_data_archiver)
+///    - Delete or transition expired uploads with
+///      [`run_user_data_lifecycle`](crate::archive::run_user_data_lifecycle::run_user_data_lifecycle)
+///    - Find orphan s3 keys and records without an s3 key with
+///      [`run_user_data_reconciler`](crate::archive::run_user_data_reconciler::run_user_data_reconciler)
+///    - Create thumbnails for image uploads with
+///      [`run_user_data_thumbnails`](crate::processing::run_user_data_thumbnails::run_user_data_thumbnails)
+///    - Refresh the storage usage gauges with
+///      [`run_user_data_quota_metrics`](crate::requests::user::user_data_quota::run_user_data_quota_metrics)
+///    - Measure the db pool wait time for admission control with
+///      [`run_admission_probe`](crate::core::server::run_admission_probe::run_admission_probe)
 /// 1. Build the [`TcpListener`](tokio::net::TcpListener) and bind it to
 ///    the api server address
 /// 1. Create the [`Http`](hyper::server::conn::Http) server with
-///    a thread-safe `Arc` for the verifying client tls connections using
+///    the keep-alive, header timeout and buffer settings from the
+///    [`ConnectionLimits`](crate::core::server::connection_limits::ConnectionLimits)
+///    and a thread-safe `Arc` for the verifying client tls connections using
 ///    a [`TlsInfo`](crate::tls::tls_info::TlsInfo) object
 /// 1. Start the server `loop`
-/// 1. Wait for a client connection `accept` is triggered on the server socket
+/// 1. Wait for a free connection permit (``API_MAX_CONNECTIONS``)
+///    then wait for a client connection `accept` is triggered on the
+///    server socket. Accept errors are logged and the loop keeps
+///    going (after
+///    [`ACCEPT_ERROR_BACKOFF`](crate::core::server::start_core_server::ACCEPT_ERROR_BACKOFF)
+///    unless only that client's connection failed)
 /// 1. Clone all `Arc` objects to ensure thread-safety
 /// 1. Create task future
 /// 1. Start task future
 /// 1. Determine if the client connection meets the tls requirements
+///    within the ``API_TLS_HANDSHAKE_TIMEOUT_MS``
 /// 1. Extract client tls connection information
 /// 1. Build a
 ///    [`CoreServices`](crate::core::server::core_services::CoreServices)
@@ -45,7 +100,8 @@ use crate::core::server::core_services::CoreServices;
 ///    and tls client information
 /// 1. Handle serving the client
 ///    connection using the [`handle_request`](crate::handle_request::handle_request)
-///    function
+///    function and release the connection permit when the
+///    connection closes
 ///
 /// # Arguments
 ///
@@ -53,13 +109,210 @@ use crate::core::server::core_services::CoreServices;
 /// [`CoreConfig`](crate::core::core_config::CoreConfig)
 /// for static values read from environment variables
 ///
+/// # Errors
+///
+/// Returns a
+/// [`StartupError`](crate::core::startup_error::StartupError)
+/// (after logging it) when postgres or the kafka brokers do not
+/// answer before the ``STARTUP_WAIT_SECONDS`` wait runs out,
+/// the kafka schema registry cannot be used, the demo data
+/// cannot be seeded (``DEMO_MODE=1``) or the server address
+/// cannot be bound
+///
 pub async fn start_core_server(
     config: &CoreConfig,
-) -> std::result::Result<String, hyper::Error> {
+) -> std::result::Result<String, StartupError> {
+    // export traces (if enabled)
+    if let Err(err_msg) = config.otel.start_tracing(&config.label) {
+        error!("{err_msg}");
+    }
     // 1 - start threadpools
     let db_pool = get_db_pool(config).await;
-    let kafka_pool: KafkaPublisher =
-        start_threadpool(Some(&config.label)).await;
+    // wait for postgres (if STARTUP_WAIT_SECONDS is set)
+    if let Err(e) = config
+        .startup_wait
+        .wait_for_db(&config.label, &db_pool)
+        .await
+    {
+        let err_msg = format!("Server startup failed - {e} - stopping");
+        error!("{err_msg}");
+        return Err(StartupError::Config(err_msg));
+    }
+    // store user events for the notifications stream
+    let mut config = config.clone();
+    config.events.notifications.db_pool = Some(db_pool.clone());
+    // queue user events for the registered webhooks
+    config.events.webhooks.db_pool = Some(db_pool.clone());
+    // store kafka messages that fail every publish retry
+    config.events.dead_letters.db_pool = Some(db_pool.clone());
+    let kafka_pool: KafkaPublisher = match &config.kafka_pool {
+        Some(kafka_pool) => kafka_pool.clone(),
+        None => match config.startup_wait.start_kafka(&config.label).await {
+            Ok(kafka_pool) => kafka_pool,
+            Err(e) => {
+                let err_msg = format!("Server startup failed - {e} - stopping");
+                error!("{err_msg}");
+                return Err(StartupError::Config(err_msg));
+            }
+        },
+    };
+    // register the user event schema and start the producer
+    // for framed payloads
+    if config.events.enabled
+        && config.events.dead_letters.schema_registry.enabled
+    {
+        let topics = config.events.get_topics();
+        let schema_registry = &mut config.events.dead_letters.schema_registry;
+        if let Err(e) = schema_registry
+            .register_schemas(&config.label, &topics)
+            .await
+        {
+            let err_msg = format!(
+                "Server startup failed - unable to use the kafka \
+                schema registry with err='{e}' - stopping"
+            );
+            error!("{err_msg}");
+            return Err(StartupError::Config(err_msg));
+        }
+        schema_registry.start_producer(&kafka_pool);
+    }
+    let config = &config;
+    let db_read_pools = get_db_read_pools(config);
+    // reload the jwt keys on SIGHUP
+    let reload_label = config.label.clone();
+    let reload_jwt_keys = config.jwt_keys.clone();
+    let reload_jwt_key_paths = config.jwt_key_paths.clone();
+    let reload_secrets = config.secrets.clone();
+    tokio::spawn(async move {
+        reload_jwt_keys_on_sighup(
+            &reload_label,
+            reload_jwt_keys,
+            reload_jwt_key_paths,
+            reload_secrets,
+        )
+        .await
+    });
+    // fetch the secrets again (if enabled)
+    tokio::spawn(run_secrets_refresher(config.clone()));
+    // time one password hash and warn outside the target latency
+    let hash_label = config.label.clone();
+    let password_hashing = config.password_hashing.clone();
+    let hash_salt = config.server_password_salt.clone();
+    tokio::task::spawn_blocking(move || {
+        password_hashing.check_latency(&hash_label, &hash_salt)
+    });
+    // seed the demo users and data before serving requests
+    if config.demo_mode.enabled {
+        let demo_label = format!("{} - demo", config.label);
+        match seed_demo_data(&demo_label, config, &db_pool).await {
+            Ok(num_seeded_users) => info!(
+                "{demo_label} - demo mode seeded {num_seeded_users} users \
+                with password DEMO_PASSWORD and stores s3 files in {}",
+                config.demo_mode.s3_dir
+            ),
+            Err(err_msg) => {
+                let err_msg =
+                    format!("Server startup failed - {err_msg} - stopping");
+                error!("{err_msg}");
+                return Err(StartupError::Config(err_msg));
+            }
+        }
+    }
+    // warn about missing search and login indexes
+    let index_label = config.label.clone();
+    let index_db_pool = db_pool.clone();
+    tokio::spawn(
+        async move { check_db_indexes(&index_label, index_db_pool).await },
+    );
+    // reload the runtime settings when the settings table changes
+    tokio::spawn(listen_for_settings_changes(config.clone(), db_pool.clone()));
+    // archive old users_data rows (if enabled)
+    let archive_label = format!("{} - archive", config.label);
+    let archiver = config.user_data_archiver.clone();
+    let archive_object_store = config.object_store.clone();
+    let archive_db_pool = db_pool.clone();
+    tokio::spawn(async move {
+        run_user_data_archiver(
+            &archive_label,
+            archiver,
+            archive_object_store,
+            archive_db_pool,
+        )
+        .await
+    });
+    // expire users_data uploads with a lifecycle policy (if enabled)
+    let lifecycle_label = format!("{} - lifecycle", config.label);
+    let lifecycle = config.user_data_lifecycle.clone();
+    let lifecycle_object_store = config.object_store.clone();
+    let lifecycle_db_pool = db_pool.clone();
+    tokio::spawn(async move {
+        run_user_data_lifecycle(
+            &lifecycle_label,
+            lifecycle,
+            lifecycle_object_store,
+            lifecycle_db_pool,
+        )
+        .await
+    });
+    // reconcile users_data records with the keys in s3 (if enabled)
+    let reconcile_label = format!("{} - reconcile", config.label);
+    let reconciler = config.user_data_reconciler.clone();
+    let reconcile_object_store = config.object_store.clone();
+    let reconcile_db_pool = db_pool.clone();
+    tokio::spawn(async move {
+        run_user_data_reconciler(
+            &reconcile_label,
+            reconciler,
+            reconcile_object_store,
+            reconcile_db_pool,
+        )
+        .await
+    });
+    // process pending users_data uploads (if enabled)
+    let pipeline_label = format!("{} - pipeline", config.label);
+    let pipeline = config.user_data_pipeline.clone();
+    let pipeline_db_pool = db_pool.clone();
+    tokio::spawn(async move {
+        run_user_data_pipeline(&pipeline_label, pipeline, pipeline_db_pool)
+            .await
+    });
+    // create thumbnails for image uploads (if enabled)
+    let thumbnails_label = format!("{} - thumbnails", config.label);
+    let thumbnails = config.user_data_thumbnails.clone();
+    let thumbnails_object_store = config.object_store.clone();
+    let thumbnails_db_pool = db_pool.clone();
+    tokio::spawn(async move {
+        run_user_data_thumbnails(
+            &thumbnails_label,
+            thumbnails,
+            thumbnails_object_store,
+            thumbnails_db_pool,
+        )
+        .await
+    });
+    // send the queued webhook deliveries (if enabled)
+    let webhooks_label = format!("{} - webhooks", config.label);
+    let webhooks = config.events.webhooks.clone();
+    let webhooks_db_pool = db_pool.clone();
+    tokio::spawn(async move {
+        run_webhook_dispatcher(&webhooks_label, webhooks, webhooks_db_pool)
+            .await
+    });
+    // refresh the storage usage gauges (if enabled)
+    let quota_label = format!("{} - quota", config.label);
+    let quota = config.user_data_quota.clone();
+    let quota_db_pool = db_pool.clone();
+    tokio::spawn(async move {
+        run_user_data_quota_metrics(&quota_label, quota, quota_db_pool).await
+    });
+    // measure the db pool wait time (if admission control is enabled)
+    let admission_label = format!("{} - admission", config.label);
+    let admission = config.admission_control.clone();
+    let admission_db_pool = db_pool.clone();
+    tokio::spawn(async move {
+        run_admission_probe(&admission_label, admission, admission_db_pool)
+            .await
+    });
     // 2
     let listener = match tokio::net::TcpListener::bind(
         &config.api_config.socket_addr.unwrap(),
@@ -74,12 +327,14 @@ pub async fn start_core_server(
                 config.api_config.server_endpoint
             );
             error!("{err_msg}");
-            panic!("{err_msg}");
+            return Err(StartupError::Config(err_msg));
         }
     };
     let local_addr = listener.local_addr().unwrap();
     // 3
-    let http = hyper::server::conn::Http::new();
+    let connection_limits = config.connection_limits.clone();
+    let http = connection_limits.build_http();
+    let tls_handshake_timeout = connection_limits.get_tls_handshake_timeout();
     let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(
         config.api_config.server_config.clone(),
     ));
@@ -87,17 +342,60 @@ pub async fn start_core_server(
     // 4
     loop {
         // 5
-        let (conn, remote_addr) = listener.accept().await.unwrap();
+        let open_connection = connection_limits.acquire().await;
+        let (conn, remote_addr) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                // the client gave up before the connection was
+                // accepted - nothing to wait for
+                if matches!(
+                    e.kind(),
+                    std::io::ErrorKind::ConnectionAborted
+                        | std::io::ErrorKind::ConnectionRefused
+                        | std::io::ErrorKind::ConnectionReset
+                ) {
+                    debug!("hyper server accept failed with err='{e}'");
+                    continue;
+                }
+                error!(
+                    "hyper server accept failed with err='{e}' - \
+                    retrying in {}ms",
+                    ACCEPT_ERROR_BACKOFF.as_millis()
+                );
+                tokio::time::sleep(ACCEPT_ERROR_BACKOFF).await;
+                continue;
+            }
+        };
         // 6
         let acceptor = acceptor.clone();
         let http = http.clone();
         let cloned_config = config.clone();
         let cloned_db_pool = db_pool.clone();
+        let cloned_db_read_pools = db_read_pools.clone();
         let cloned_kafka_pool = kafka_pool.clone();
         // 7
         let fut = async move {
+            // release the connection permit when the task ends
+            let _open_connection = open_connection;
             // 9 determine if the client connection meets the tls requirements
-            match acceptor.accept(conn).await {
+            let accepted = match tls_handshake_timeout {
+                Some(timeout) => {
+                    match tokio::time::timeout(timeout, acceptor.accept(conn))
+                        .await
+                    {
+                        Ok(accepted) => accepted,
+                        Err(_) => {
+                            trace!(
+                                "hyper server tls handshake timed out \
+                                for {remote_addr}"
+                            );
+                            return;
+                        }
+                    }
+                }
+                None => acceptor.accept(conn).await,
+            };
+            match accepted {
                 Ok(stream) => {
                     // 10
                     let (_io, tls_connection) = stream.get_ref();
@@ -106,6 +404,7 @@ pub async fn start_core_server(
                     let supported_services = CoreServices {
                         config: cloned_config,
                         db_pool: cloned_db_pool,
+                        db_read_pools: cloned_db_read_pools,
                         kafka_pool: cloned_kafka_pool,
                         local_addr,
                         remote_addr,
@@ -0,0 +1,367 @@
+//! Wait for postgres and kafka before serving requests
+//!
+//! Pods often start before their dependencies are ready. With
+//! ``STARTUP_WAIT_SECONDS`` set,
+//! [`start_core_server`](crate::core::server::start_core_server::start_core_server)
+//! retries a postgres connection and (with ``KAFKA_ENABLED=1``)
+//! a kafka metadata request with backoff (starting at
+//! ``STARTUP_RETRY_MS`` and doubling up to
+//! ``STARTUP_MAX_RETRY_MS``) until they answer or the wait
+//! runs out. The server does not start without postgres.
+//! With ``STARTUP_KAFKA_DEGRADED=1`` the server starts with
+//! kafka publishing disabled when the brokers do not answer
+//! in time, and the ``startup_degraded`` prometheus gauge is
+//! ``1`` for ``dependency="kafka"`` until the server is
+//! restarted.
+//!
+//! The ``migrate`` command also waits for postgres.
+//!
+use std::future::Future;
+use std::time::Duration;
+use std::time::Instant;
+
+use lazy_static::lazy_static;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use crate::kafka::kafka_publisher::start_threadpool;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::monitoring::metric_types::register_int_counter_vec;
+use crate::monitoring::metric_types::register_int_gauge_vec;
+use crate::monitoring::metric_types::IntCounterVec;
+use crate::monitoring::metric_types::IntGaugeVec;
+
+lazy_static! {
+    pub static ref STARTUP_DEGRADED_GAUGE: IntGaugeVec =
+        register_int_gauge_vec!(
+            "startup_degraded",
+            "Dependencies that were not available at startup \
+            (1 = started without the dependency).",
+            &["dependency"]
+        )
+        .unwrap();
+    pub static ref STARTUP_RETRIES_COUNTER_VEC: IntCounterVec =
+        register_int_counter_vec!(
+            "startup_dependency_retries_total",
+            "Number of failed dependency checks retried at startup.",
+            &["dependency"]
+        )
+        .unwrap();
+}
+
+/// StartupWait
+///
+/// Settings for waiting on dependencies at startup
+///
+/// # Supported Environment Variables
+///
+/// ```bash
+/// # seconds to wait for postgres and kafka (0 = no checks)
+/// export STARTUP_WAIT_SECONDS="0"
+/// # first retry delay (doubles after each failed check)
+/// export STARTUP_RETRY_MS="500"
+/// export STARTUP_MAX_RETRY_MS="5000"
+/// # start with kafka publishing disabled when the brokers
+/// # do not answer in time (0 = do not start)
+/// export STARTUP_KAFKA_DEGRADED="0"
+/// ```
+///
+/// # Arguments
+///
+/// * `wait_seconds` - `u64` - how long to retry each
+///   dependency (0 = no checks)
+/// * `retry_ms` - `u64` - first retry delay
+/// * `max_retry_ms` - `u64` - longest retry delay
+/// * `kafka_degraded` - `bool` - start without kafka
+///   instead of failing
+///
+#[derive(Clone, Default)]
+pub struct StartupWait {
+    pub wait_seconds: u64,
+    pub retry_ms: u64,
+    pub max_retry_ms: u64,
+    pub kafka_degraded: bool,
+}
+
+impl StartupWait {
+    /// build_startup_wait
+    ///
+    /// Build a
+    /// [`StartupWait`](crate::core::server::startup_wait::StartupWait)
+    /// from environment variables
+    ///
+    pub fn build_startup_wait() -> Self {
+        let get_env = |key: &str, default: u64| -> u64 {
+            std::env::var(key)
+                .unwrap_or_else(|_| format!("{default}"))
+                .parse::<u64>()
+                .unwrap_or(default)
+        };
+        let kafka_degraded = std::env::var("STARTUP_KAFKA_DEGRADED")
+            .unwrap_or_else(|_| "0".to_string());
+        let retry_ms = get_env("STARTUP_RETRY_MS", 500).max(1);
+        StartupWait {
+            wait_seconds: get_env("STARTUP_WAIT_SECONDS", 0),
+            retry_ms,
+            max_retry_ms: get_env("STARTUP_MAX_RETRY_MS", 5000).max(retry_ms),
+            kafka_degraded: kafka_degraded == "1" || kafka_degraded == "true",
+        }
+    }
+
+    /// is_enabled
+    ///
+    /// Check if dependencies are checked at startup
+    ///
+    pub fn is_enabled(&self) -> bool {
+        self.wait_seconds > 0
+    }
+
+    /// get_retry_ms
+    ///
+    /// Get the delay before the next check after `attempt`
+    /// failed checks
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use restapi::core::server::startup_wait::StartupWait;
+    /// let startup_wait = StartupWait {
+    ///     wait_seconds: 60,
+    ///     retry_ms: 500,
+    ///     max_retry_ms: 5000,
+    ///     kafka_degraded: false,
+    /// };
+    /// assert_eq!(startup_wait.get_retry_ms(1), 500);
+    /// assert_eq!(startup_wait.get_retry_ms(3), 2000);
+    /// assert_eq!(startup_wait.get_retry_ms(10), 5000);
+    /// ```
+    ///
+    pub fn get_retry_ms(&self, attempt: u32) -> u64 {
+        let shift = attempt.saturating_sub(1).min(32);
+        self.retry_ms
+            .saturating_mul(1u64 << shift)
+            .min(self.max_retry_ms)
+    }
+
+    /// wait_for
+    ///
+    /// Retry a dependency check with backoff until it passes
+    /// or ``STARTUP_WAIT_SECONDS`` runs out
+    ///
+    /// # Arguments
+    ///
+    /// * `label` - `&str` - caller logging label
+    /// * `dependency` - `&str` - dependency name for logs and
+    ///   metrics
+    /// * `check` - closure returning a future that resolves to
+    ///   `Ok(())` when the dependency is available
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) with the last check error
+    ///
+    pub async fn wait_for<F, Fut>(
+        &self,
+        label: &str,
+        dependency: &str,
+        check: F,
+    ) -> Result<(), String>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<(), String>>,
+    {
+        let deadline = Instant::now() + Duration::from_secs(self.wait_seconds);
+        let mut attempt: u32 = 0;
+        loop {
+            attempt += 1;
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let err_msg = match tokio::time::timeout(
+                remaining.max(Duration::from_millis(1)),
+                check(),
+            )
+            .await
+            {
+                Ok(Ok(())) => {
+                    info!(
+                        "{label} - {dependency} is available after \
+                        {attempt} checks"
+                    );
+                    return Ok(());
+                }
+                Ok(Err(err_msg)) => err_msg,
+                Err(_) => "check timed out".to_string(),
+            };
+            let retry_ms = self.get_retry_ms(attempt);
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(format!(
+                    "{dependency} was not available after {} seconds \
+                    ({attempt} checks) with err='{err_msg}'",
+                    self.wait_seconds
+                ));
+            }
+            STARTUP_RETRIES_COUNTER_VEC
+                .with_label_values(&[dependency])
+                .inc();
+            warn!(
+                "{label} - waiting for {dependency} - check {attempt} \
+                failed with err='{err_msg}' - retrying in {retry_ms}ms"
+            );
+            tokio::time::sleep(Duration::from_millis(retry_ms).min(remaining))
+                .await;
+        }
+    }
+
+    /// wait_for_db
+    ///
+    /// Wait for a postgres connection (skipped when
+    /// ``STARTUP_WAIT_SECONDS=0``)
+    ///
+    /// # Arguments
+    ///
+    /// * `label` - `&str` - caller logging label
+    /// * `db_pool` - [`Pool`](bb8::Pool) - postgres client db
+    ///   threadpool with required tls encryption
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) when postgres did not accept a
+    /// connection in time
+    ///
+    pub async fn wait_for_db(
+        &self,
+        label: &str,
+        db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    ) -> Result<(), String> {
+        if !self.is_enabled() {
+            return Ok(());
+        }
+        self.wait_for(label, "postgres", || async {
+            db_pool
+                .dedicated_connection()
+                .await
+                .map(|_| ())
+                .map_err(|e| format!("{e}"))
+        })
+        .await
+    }
+
+    /// start_kafka
+    ///
+    /// Wait for the kafka brokers (skipped when
+    /// ``STARTUP_WAIT_SECONDS=0`` or ``KAFKA_ENABLED`` is off)
+    /// then start the kafka threadpool
+    ///
+    /// # Arguments
+    ///
+    /// * `label` - `&str` - caller logging label
+    ///
+    /// # Returns
+    ///
+    /// Ok([`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)) -
+    /// disabled when the brokers did not answer and
+    /// ``STARTUP_KAFKA_DEGRADED=1``
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) when the brokers did not answer
+    /// in time
+    ///
+    pub async fn start_kafka(
+        &self,
+        label: &str,
+    ) -> Result<KafkaPublisher, String> {
+        STARTUP_DEGRADED_GAUGE.with_label_values(&["kafka"]).set(0);
+        if self.is_enabled() && is_kafka_enabled(label) {
+            if let Err(err_msg) = self
+                .wait_for(label, "kafka", || check_kafka_brokers(label))
+                .await
+            {
+                if !self.kafka_degraded {
+                    return Err(err_msg);
+                }
+                error!(
+                    "{label} - starting in degraded mode with kafka \
+                    publishing disabled - {err_msg}"
+                );
+                STARTUP_DEGRADED_GAUGE.with_label_values(&["kafka"]).set(1);
+                return Ok(KafkaPublisher::default());
+            }
+        }
+        Ok(start_threadpool(Some(label)).await)
+    }
+}
+
+/// is_kafka_enabled
+///
+/// Check ``KAFKA_ENABLED`` with the threadpool's settings
+///
+/// # Arguments
+///
+/// * `label` - `&str` - caller logging label
+///
+#[cfg(feature = "kafka")]
+fn is_kafka_enabled(label: &str) -> bool {
+    kafka_threadpool::api::build_kafka_client_config::build_kafka_client_config(
+        label,
+    )
+    .is_enabled
+}
+
+/// is_kafka_enabled
+///
+/// Always `false` without the ``kafka`` feature
+///
+/// # Arguments
+///
+/// * `_label` - `&str` - caller logging label
+///
+#[cfg(not(feature = "kafka"))]
+fn is_kafka_enabled(_label: &str) -> bool {
+    false
+}
+
+/// check_kafka_brokers
+///
+/// Fetch the cluster metadata with the threadpool's
+/// ``KAFKA_*`` settings
+///
+/// # Arguments
+///
+/// * `label` - `&str` - caller logging label
+///
+#[cfg(feature = "kafka")]
+async fn check_kafka_brokers(label: &str) -> Result<(), String> {
+    use rdkafka::producer::Producer;
+    let kafka_config =
+        kafka_threadpool::api::build_kafka_client_config::build_kafka_client_config(
+            label,
+        );
+    tokio::task::spawn_blocking(move || {
+        kafka_threadpool::api::get_kafka_producer::get_kafka_producer(
+            &kafka_config,
+        )
+        .client()
+        .fetch_metadata(None, Duration::from_secs(5))
+        .map(|_| ())
+        .map_err(|e| format!("{e}"))
+    })
+    .await
+    .map_err(|e| format!("{e}"))?
+}
+
+/// check_kafka_brokers
+///
+/// Always `Ok` without the ``kafka`` feature
+///
+/// # Arguments
+///
+/// * `_label` - `&str` - caller logging label
+///
+#[cfg(not(feature = "kafka"))]
+async fn check_kafka_brokers(_label: &str) -> Result<(), String> {
+    Ok(())
+}
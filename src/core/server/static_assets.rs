@@ -0,0 +1,270 @@
+//! Serve a frontend (single page app) from a local directory
+//! on the same TLS listener as the api
+//!
+//! With ``STATIC_ASSETS_DIR`` set, ``GET`` and ``HEAD``
+//! requests that do not match an api route are served from
+//! the directory. Paths without a file extension that do not
+//! exist (client-side routes like ``/settings/profile``) are
+//! served the ``index.html`` so the frontend router can
+//! handle them.
+//!
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::UNIX_EPOCH;
+
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Method;
+use hyper::Response;
+
+use crate::utils::etag::is_etag_match;
+
+/// StaticAssets
+///
+/// Settings for serving static files
+///
+/// # Supported Environment Variables
+///
+/// ```bash
+/// # directory with the frontend build (empty = disabled)
+/// export STATIC_ASSETS_DIR="./web/dist"
+/// # Cache-Control max-age for files other than index.html
+/// export STATIC_ASSETS_MAX_AGE_SECONDS="3600"
+/// ```
+///
+/// # Arguments
+///
+/// * `dir` - `String` - directory to serve (empty = disabled)
+/// * `max_age_seconds` - `u64` - ``Cache-Control`` max-age
+///   for files other than ``index.html``
+///
+#[derive(Clone, Default)]
+pub struct StaticAssets {
+    pub dir: String,
+    pub max_age_seconds: u64,
+}
+
+impl StaticAssets {
+    /// build_static_assets
+    ///
+    /// Build a
+    /// [`StaticAssets`](crate::core::server::static_assets::StaticAssets)
+    /// from environment variables
+    ///
+    pub fn build_static_assets() -> Self {
+        StaticAssets {
+            dir: std::env::var("STATIC_ASSETS_DIR")
+                .unwrap_or_default()
+                .trim_end_matches('/')
+                .to_string(),
+            max_age_seconds: std::env::var("STATIC_ASSETS_MAX_AGE_SECONDS")
+                .unwrap_or_else(|_| "3600".to_string())
+                .parse::<u64>()
+                .unwrap_or(3600),
+        }
+    }
+
+    /// is_enabled
+    ///
+    /// Check if a ``STATIC_ASSETS_DIR`` is set
+    ///
+    pub fn is_enabled(&self) -> bool {
+        !self.dir.is_empty()
+    }
+
+    /// serve_static_asset
+    ///
+    /// Serve a file from the ``STATIC_ASSETS_DIR`` with its
+    /// content type, ``Cache-Control``, ``Last-Modified``
+    /// and ``ETag`` headers. ``index.html`` is never cached
+    /// so new frontend deploys are picked up right away.
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - caller logging label
+    /// * `method` - `&Method` - ``GET`` or ``HEAD``
+    /// * `headers` - `&HeaderMap<HeaderValue>` - request
+    ///   headers for ``If-None-Match``
+    /// * `request_uri` - `&str` - request path
+    ///
+    /// # Returns
+    ///
+    /// ``Response<Body>`` with a ``200``, ``304`` (unchanged)
+    /// or ``404`` (missing file or unsafe path)
+    ///
+    pub fn serve_static_asset(
+        &self,
+        tracking_label: &str,
+        method: &Method,
+        headers: &HeaderMap<HeaderValue>,
+        request_uri: &str,
+    ) -> Response<Body> {
+        let path = match self.get_asset_path(request_uri) {
+            Some(path) => path,
+            None => {
+                warn!(
+                    "{tracking_label} - static asset not found \
+                    uri={request_uri}"
+                );
+                return Response::builder()
+                    .status(404)
+                    .body(Body::from(format!(
+                        "{{\"status\":404,\
+                        \"reason\":\"not found {request_uri}\"}}"
+                    )))
+                    .unwrap();
+            }
+        };
+        let metadata = match std::fs::metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                error!(
+                    "{tracking_label} - failed to stat static asset \
+                    {path:?} with err='{e}'"
+                );
+                return Response::builder()
+                    .status(404)
+                    .body(Body::from(format!(
+                        "{{\"status\":404,\
+                        \"reason\":\"not found {request_uri}\"}}"
+                    )))
+                    .unwrap();
+            }
+        };
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|m| m.duration_since(UNIX_EPOCH).ok());
+        let etag = format!(
+            "\"{:x}-{:x}\"",
+            metadata.len(),
+            modified.map(|m| m.as_secs()).unwrap_or(0)
+        );
+        let is_index =
+            path.file_name().and_then(|n| n.to_str()) == Some("index.html");
+        let cache_control = if is_index {
+            "no-cache".to_string()
+        } else {
+            format!("public, max-age={}", self.max_age_seconds)
+        };
+        let mut builder = Response::builder()
+            .header("Content-Type", get_content_type(&path))
+            .header("Cache-Control", cache_control)
+            .header("ETag", &etag);
+        if let Some(modified) = modified {
+            builder = builder.header(
+                "Last-Modified",
+                chrono::DateTime::<chrono::Utc>::from(UNIX_EPOCH + modified)
+                    .format("%a, %d %b %Y %H:%M:%S GMT")
+                    .to_string(),
+            );
+        }
+        if is_etag_match(headers, &etag) {
+            return builder.status(304).body(Body::empty()).unwrap();
+        }
+        if *method == Method::HEAD {
+            return builder
+                .header("Content-Length", metadata.len())
+                .body(Body::empty())
+                .unwrap();
+        }
+        match std::fs::read(&path) {
+            Ok(contents) => builder.body(Body::from(contents)).unwrap(),
+            Err(e) => {
+                error!(
+                    "{tracking_label} - failed to read static asset \
+                    {path:?} with err='{e}'"
+                );
+                Response::builder()
+                    .status(500)
+                    .body(Body::from(
+                        "{\"status\":500,\
+                        \"reason\":\"failed to read static asset\"}",
+                    ))
+                    .unwrap()
+            }
+        }
+    }
+
+    /// get_asset_path
+    ///
+    /// Map a request path to a file in the ``STATIC_ASSETS_DIR``.
+    /// Missing paths without a file extension fall back to
+    /// ``index.html``.
+    ///
+    /// # Returns
+    ///
+    /// ``Some(PathBuf)`` or ``None`` for missing files and
+    /// paths that try to leave the directory
+    ///
+    fn get_asset_path(&self, request_uri: &str) -> Option<PathBuf> {
+        let relative = request_uri.trim_start_matches('/');
+        if relative
+            .split('/')
+            .any(|segment| segment == ".." || segment.contains('\\'))
+        {
+            return None;
+        }
+        let root = Path::new(&self.dir);
+        let index = root.join("index.html");
+        if relative.is_empty() {
+            return index.is_file().then_some(index);
+        }
+        let mut path = root.join(relative);
+        if path.is_dir() {
+            path = path.join("index.html");
+        }
+        if path.is_file() {
+            return Some(path);
+        }
+        let has_extension = relative
+            .rsplit('/')
+            .next()
+            .map(|name| name.contains('.'))
+            .unwrap_or(false);
+        if !has_extension && index.is_file() {
+            return Some(index);
+        }
+        None
+    }
+}
+
+/// get_content_type
+///
+/// Get the ``Content-Type`` for a file extension
+///
+/// # Arguments
+///
+/// * `path` - `&Path` - file path
+///
+pub fn get_content_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+    match extension.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "json" | "map" => "application/json",
+        "webmanifest" => "application/manifest+json",
+        "txt" => "text/plain; charset=utf-8",
+        "xml" => "application/xml",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        "ico" => "image/x-icon",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+        "wasm" => "application/wasm",
+        "pdf" => "application/pdf",
+        _ => "application/octet-stream",
+    }
+}
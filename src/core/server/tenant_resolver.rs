@@ -0,0 +1,234 @@
+//! Resolve the tenant (isolated customer org) for a request
+//! from a header or the subdomain of the ``Host`` header
+//!
+//! Every user belongs to one tenant (``users.tenant_id``) and
+//! emails are unique per tenant. Logins, user creation and
+//! user searches are scoped to the request's tenant, new
+//! tokens include a ``tenant_id`` claim, and
+//! [`validate_user_token`](crate::requests::auth::validate_user_token::validate_user_token)
+//! rejects a token used with a different tenant.
+//!
+//! With ``TENANT_MODE=off`` (the default) every request uses
+//! the ``default`` tenant without a db lookup.
+//!
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::PooledConnection;
+use bb8_postgres::PostgresConnectionManager;
+
+use hyper::header::HeaderValue;
+use hyper::HeaderMap;
+
+use crate::requests::models::tenant::get_tenant_by_slug;
+use crate::requests::models::tenant::DEFAULT_TENANT_ID;
+
+/// supported values for ``TENANT_MODE``
+pub const TENANT_MODES: [&str; 3] = ["off", "header", "subdomain"];
+
+/// TenantResolver
+///
+/// Settings for resolving the tenant of a request
+///
+/// # Supported Environment Variables
+///
+/// ```bash
+/// # off, header or subdomain
+/// export TENANT_MODE="off"
+/// # header with the tenant slug for TENANT_MODE=header
+/// export TENANT_HEADER="X-Tenant"
+/// # domain under which each tenant has a subdomain
+/// # (acme.api.example.com) for TENANT_MODE=subdomain
+/// export TENANT_BASE_DOMAIN="api.example.com"
+/// # tenant slug for requests without a tenant
+/// # (empty = reject the request)
+/// export TENANT_DEFAULT="default"
+/// ```
+///
+/// # Arguments
+///
+/// * `mode` - `String` - ``off``, ``header`` or ``subdomain``
+/// * `header` - `String` - tenant header name
+/// * `base_domain` - `String` - base domain for subdomains
+/// * `default_slug` - `String` - tenant slug for requests
+///   without a tenant (empty = reject)
+///
+#[derive(Clone, Default)]
+pub struct TenantResolver {
+    pub mode: String,
+    pub header: String,
+    pub base_domain: String,
+    pub default_slug: String,
+}
+
+impl TenantResolver {
+    /// build_tenant_resolver
+    ///
+    /// Build a
+    /// [`TenantResolver`](crate::core::server::tenant_resolver::TenantResolver)
+    /// from environment variables
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) for an unsupported ``TENANT_MODE``
+    /// or ``TENANT_MODE=subdomain`` without a
+    /// ``TENANT_BASE_DOMAIN``
+    ///
+    pub fn build_tenant_resolver() -> Result<Self, String> {
+        let mode = std::env::var("TENANT_MODE")
+            .unwrap_or_else(|_| "off".to_string())
+            .to_lowercase();
+        if !TENANT_MODES.contains(&mode.as_str()) {
+            return Err(format!(
+                "unsupported TENANT_MODE={mode} - \
+                must be one of: {}",
+                TENANT_MODES.join(", ")
+            ));
+        }
+        let base_domain = std::env::var("TENANT_BASE_DOMAIN")
+            .unwrap_or_default()
+            .trim_matches('.')
+            .to_lowercase();
+        if mode == "subdomain" && base_domain.is_empty() {
+            return Err("TENANT_MODE=subdomain requires a \
+                TENANT_BASE_DOMAIN"
+                .to_string());
+        }
+        Ok(TenantResolver {
+            mode,
+            header: std::env::var("TENANT_HEADER")
+                .unwrap_or_else(|_| "X-Tenant".to_string()),
+            base_domain,
+            default_slug: std::env::var("TENANT_DEFAULT")
+                .unwrap_or_else(|_| "default".to_string()),
+        })
+    }
+
+    /// is_enabled
+    ///
+    /// Check if requests are resolved to a tenant
+    /// (``TENANT_MODE`` is not ``off``)
+    ///
+    pub fn is_enabled(&self) -> bool {
+        !self.mode.is_empty() && self.mode != "off"
+    }
+
+    /// get_tenant_slug
+    ///
+    /// Get the tenant slug for a request from the tenant
+    /// header or the ``Host`` subdomain. Requests without a
+    /// tenant use the `default_slug`.
+    ///
+    /// ```rust
+    /// use hyper::header::HeaderValue;
+    /// use hyper::HeaderMap;
+    /// use restapi::core::server::tenant_resolver::TenantResolver;
+    ///
+    /// let resolver = TenantResolver {
+    ///     mode: "subdomain".to_string(),
+    ///     base_domain: "api.example.com".to_string(),
+    ///     default_slug: "default".to_string(),
+    ///     ..Default::default()
+    /// };
+    /// let mut headers = HeaderMap::new();
+    /// headers.insert(
+    ///     "host",
+    ///     HeaderValue::from_static("acme.api.example.com:3000"),
+    /// );
+    /// assert_eq!(resolver.get_tenant_slug(&headers).unwrap(), "acme");
+    /// headers.insert("host", HeaderValue::from_static("api.example.com"));
+    /// assert_eq!(resolver.get_tenant_slug(&headers).unwrap(), "default");
+    /// ```
+    ///
+    /// # Arguments
+    ///
+    /// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+    ///   request headers
+    ///
+    /// # Returns
+    ///
+    /// Ok(slug: `String`)
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) if the request has no tenant
+    /// and there is no `default_slug`
+    ///
+    pub fn get_tenant_slug(
+        &self,
+        headers: &HeaderMap<HeaderValue>,
+    ) -> Result<String, String> {
+        let slug = match self.mode.as_str() {
+            "header" => headers
+                .get(self.header.as_str())
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.trim().to_lowercase())
+                .unwrap_or_default(),
+            "subdomain" => {
+                let host = headers
+                    .get("host")
+                    .and_then(|v| v.to_str().ok())
+                    .unwrap_or("")
+                    .to_lowercase();
+                let host = host.split(':').next().unwrap_or("");
+                host.strip_suffix(&format!(".{}", self.base_domain))
+                    .filter(|v| !v.contains('.'))
+                    .unwrap_or("")
+                    .to_string()
+            }
+            _ => "".to_string(),
+        };
+        match (slug.is_empty(), self.default_slug.is_empty()) {
+            (false, _) => Ok(slug),
+            (true, false) => Ok(self.default_slug.clone()),
+            (true, true) => Err(format!(
+                "missing tenant - set the {} header or use a \
+                tenant subdomain",
+                self.header
+            )),
+        }
+    }
+
+    /// get_tenant_id
+    ///
+    /// Get the active tenant id for a request
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - caller logging label
+    /// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+    ///   request headers
+    /// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+    ///   an established db connection from the
+    ///   postgres client db threadpool
+    ///
+    /// # Returns
+    ///
+    /// Ok(tenant_id: `i32`) -
+    /// [`DEFAULT_TENANT_ID`](crate::requests::models::tenant::DEFAULT_TENANT_ID)
+    /// when ``TENANT_MODE=off``
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) if the request has no tenant or
+    /// the tenant does not exist or is not active
+    ///
+    pub async fn get_tenant_id(
+        &self,
+        tracking_label: &str,
+        headers: &HeaderMap<HeaderValue>,
+        conn: &PooledConnection<
+            '_,
+            PostgresConnectionManager<MakeTlsConnector>,
+        >,
+    ) -> Result<i32, String> {
+        if !self.is_enabled() {
+            return Ok(DEFAULT_TENANT_ID);
+        }
+        let slug = self.get_tenant_slug(headers)?;
+        let tenant = get_tenant_by_slug(tracking_label, &slug, conn).await?;
+        match tenant.state {
+            0 => Ok(tenant.id),
+            _ => Err(format!("{tracking_label} - tenant={slug} is not active")),
+        }
+    }
+}
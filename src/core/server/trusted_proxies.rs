@@ -0,0 +1,284 @@
+//! Resolve the real client address for requests that arrive
+//! through a load balancer or reverse proxy
+//!
+//! When the connection's peer address is inside one of the
+//! ``TRUSTED_PROXY_CIDRS``, the ``Forwarded`` (RFC 7239) or
+//! ``X-Forwarded-For`` header is walked from the closest hop
+//! back towards the client, skipping trusted proxies, and the
+//! first untrusted address becomes the request's
+//! ``remote_addr``. Logs, the access log, login throttling and
+//! the session ``ip_address`` then use the client address
+//! instead of the load balancer's.
+//!
+//! Forwarded headers from untrusted peers are ignored so
+//! clients cannot spoof their address.
+//!
+use std::net::IpAddr;
+use std::net::SocketAddr;
+
+use hyper::header::HeaderValue;
+use hyper::HeaderMap;
+
+/// TrustedCidr
+///
+/// One trusted proxy network
+///
+/// # Arguments
+///
+/// * `network` - `IpAddr` - network address
+/// * `prefix_len` - `u8` - number of leading bits that must
+///   match (``32`` or ``128`` for a single address)
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TrustedCidr {
+    pub network: IpAddr,
+    pub prefix_len: u8,
+}
+
+impl TrustedCidr {
+    /// parse_cidr
+    ///
+    /// Parse a ``10.0.0.0/8``, ``fd00::/8`` or single
+    /// address (``10.1.2.3``) network
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - `&str` - cidr or ip address
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) for an invalid address or
+    /// prefix length
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use restapi::core::server::trusted_proxies::TrustedCidr;
+    /// let cidr = TrustedCidr::parse_cidr("10.0.0.0/8").unwrap();
+    /// assert!(cidr.contains(&"10.20.30.40".parse().unwrap()));
+    /// assert!(!cidr.contains(&"11.0.0.1".parse().unwrap()));
+    /// assert!(TrustedCidr::parse_cidr("10.0.0.0/33").is_err());
+    /// ```
+    ///
+    pub fn parse_cidr(value: &str) -> Result<Self, String> {
+        let value = value.trim();
+        let (address, prefix) = match value.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (value, None),
+        };
+        let network = address
+            .parse::<IpAddr>()
+            .map_err(|_| format!("invalid trusted proxy cidr={value}"))?;
+        let max_len = match network {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        let prefix_len = match prefix {
+            Some(prefix) => match prefix.parse::<u8>() {
+                Ok(prefix_len) if prefix_len <= max_len => prefix_len,
+                _ => {
+                    return Err(format!(
+                        "invalid trusted proxy cidr={value} prefix \
+                        must be 0-{max_len}"
+                    ))
+                }
+            },
+            None => max_len,
+        };
+        Ok(TrustedCidr {
+            network,
+            prefix_len,
+        })
+    }
+
+    /// contains
+    ///
+    /// Check if an address is inside the network (ipv4-mapped
+    /// ipv6 addresses match ipv4 networks)
+    ///
+    /// # Arguments
+    ///
+    /// * `ip` - `&IpAddr` - address to check
+    ///
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl std::fmt::Display for TrustedCidr {
+    /// fmt
+    ///
+    /// Format the network as ``address/prefix_len``
+    ///
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix_len)
+    }
+}
+
+/// TrustedProxies
+///
+/// Settings for trusting forwarded client addresses
+///
+/// # Supported Environment Variables
+///
+/// ```bash
+/// # comma-separated proxy networks or addresses
+/// # (empty = never trust forwarded headers)
+/// export TRUSTED_PROXY_CIDRS="10.0.0.0/8,172.16.0.0/12"
+/// ```
+///
+/// # Arguments
+///
+/// * `cidrs` - `Vec<TrustedCidr>` - trusted proxy networks
+///
+#[derive(Clone, Default)]
+pub struct TrustedProxies {
+    pub cidrs: Vec<TrustedCidr>,
+}
+
+impl TrustedProxies {
+    /// build_trusted_proxies
+    ///
+    /// Build a
+    /// [`TrustedProxies`](crate::core::server::trusted_proxies::TrustedProxies)
+    /// from environment variables
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) for an invalid
+    /// ``TRUSTED_PROXY_CIDRS`` entry
+    ///
+    pub fn build_trusted_proxies() -> Result<Self, String> {
+        let cidrs = std::env::var("TRUSTED_PROXY_CIDRS")
+            .unwrap_or_default()
+            .split(',')
+            .filter(|v| !v.trim().is_empty())
+            .map(TrustedCidr::parse_cidr)
+            .collect::<Result<Vec<TrustedCidr>, String>>()?;
+        Ok(TrustedProxies { cidrs })
+    }
+
+    /// is_trusted
+    ///
+    /// Check if an address is a trusted proxy
+    ///
+    /// # Arguments
+    ///
+    /// * `ip` - `&IpAddr` - address to check
+    ///
+    pub fn is_trusted(&self, ip: &IpAddr) -> bool {
+        self.cidrs.iter().any(|cidr| cidr.contains(ip))
+    }
+
+    /// get_client_addr
+    ///
+    /// Resolve the client address for a request. Requests
+    /// from trusted proxies use the right-most untrusted
+    /// ``Forwarded`` ``for=`` address (or ``X-Forwarded-For``
+    /// address when there is no ``Forwarded`` header).
+    /// Forwarded addresses without a port use port ``0``.
+    ///
+    /// # Arguments
+    ///
+    /// * `remote_addr` - `&SocketAddr` - connection peer address
+    /// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+    ///   request headers
+    ///
+    /// # Returns
+    ///
+    /// ``SocketAddr`` of the client (the peer address when it
+    /// is not a trusted proxy or there are no forwarded
+    /// addresses)
+    ///
+    pub fn get_client_addr(
+        &self,
+        remote_addr: &SocketAddr,
+        headers: &HeaderMap<HeaderValue>,
+    ) -> SocketAddr {
+        let mut client_addr = *remote_addr;
+        if !self.is_trusted(&client_addr.ip()) {
+            return client_addr;
+        }
+        let hops = match headers.contains_key("forwarded") {
+            true => get_header_hops(headers, "forwarded")
+                .into_iter()
+                .filter_map(|element| get_forwarded_for(&element))
+                .collect::<Vec<String>>(),
+            false => get_header_hops(headers, "x-forwarded-for"),
+        };
+        for hop in hops.iter().rev() {
+            match parse_forwarded_addr(hop) {
+                Some(hop_addr) => {
+                    client_addr = hop_addr;
+                    if !self.is_trusted(&hop_addr.ip()) {
+                        break;
+                    }
+                }
+                // obfuscated or unknown hops end the walk
+                None => break,
+            }
+        }
+        client_addr
+    }
+}
+
+/// get_header_hops
+///
+/// Split every value of a header into its comma-separated
+/// elements (in the order they were added)
+///
+fn get_header_hops(headers: &HeaderMap<HeaderValue>, key: &str) -> Vec<String> {
+    headers
+        .get_all(key)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+        .collect()
+}
+
+/// get_forwarded_for
+///
+/// Get the ``for=`` value from a ``Forwarded`` element like
+/// ``for="[2001:db8::17]:4711";proto=https``
+///
+fn get_forwarded_for(element: &str) -> Option<String> {
+    element.split(';').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        match key.trim().eq_ignore_ascii_case("for") {
+            true => Some(value.trim().trim_matches('"').to_string()),
+            false => None,
+        }
+    })
+}
+
+/// parse_forwarded_addr
+///
+/// Parse a forwarded ``1.2.3.4``, ``1.2.3.4:80``,
+/// ``2001:db8::17`` or ``[2001:db8::17]:4711`` address
+///
+fn parse_forwarded_addr(hop: &str) -> Option<SocketAddr> {
+    if let Ok(addr) = hop.parse::<SocketAddr>() {
+        return Some(addr);
+    }
+    hop.trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse::<IpAddr>()
+        .ok()
+        .map(|ip| SocketAddr::new(ip, 0))
+}
@@ -0,0 +1,137 @@
+//! Typed errors for building the server's configuration
+//!
+//! [`build_core_config`](crate::core::core_config::build_core_config),
+//! [`get_tls_config`](crate::tls::get_tls_config::get_tls_config)
+//! and
+//! [`load_jwt_keys`](crate::jwt::jwt_keys::load_jwt_keys)
+//! return a
+//! [`StartupError`](crate::core::startup_error::StartupError)
+//! that names the environment variable and file that need to
+//! be fixed instead of panicking.
+//! [`validate_core_config`](crate::core::core_config::validate_core_config)
+//! runs every check and returns all of the errors at once
+//! (``./target/debug/examples/server check-config``).
+//!
+use std::fmt;
+
+/// StartupError
+///
+/// Why the server's configuration could not be built
+///
+/// # Variants
+///
+/// * `MissingFile` - the file set by an environment variable
+///   does not exist or cannot be read
+/// * `InvalidPem` - the file does not have a valid pem
+///   certificate or key
+/// * `InvalidTls` - the tls certificate and key cannot be
+///   used together
+/// * `InvalidEnvVar` - an environment variable has an
+///   unsupported value
+/// * `Config` - any other configuration error
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StartupError {
+    MissingFile {
+        env_var: String,
+        path: String,
+        err: String,
+    },
+    InvalidPem {
+        env_var: String,
+        path: String,
+        reason: String,
+    },
+    InvalidTls {
+        name: String,
+        err: String,
+    },
+    InvalidEnvVar {
+        env_var: String,
+        value: String,
+        reason: String,
+    },
+    Config(String),
+}
+
+impl fmt::Display for StartupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StartupError::MissingFile { env_var, path, err } => {
+                write!(
+                    f,
+                    "{env_var}={path} - failed to read file with err='{err}'"
+                )
+            }
+            StartupError::InvalidPem {
+                env_var,
+                path,
+                reason,
+            } => write!(f, "{env_var}={path} - {reason}"),
+            StartupError::InvalidTls { name, err } => {
+                write!(
+                    f,
+                    "{name} tls - invalid certificate and key with err='{err}'"
+                )
+            }
+            StartupError::InvalidEnvVar {
+                env_var,
+                value,
+                reason,
+            } => write!(f, "{env_var}={value} - {reason}"),
+            StartupError::Config(err_msg) => write!(f, "{err_msg}"),
+        }
+    }
+}
+
+impl std::error::Error for StartupError {}
+
+impl From<String> for StartupError {
+    fn from(err_msg: String) -> Self {
+        StartupError::Config(err_msg)
+    }
+}
+
+impl From<StartupError> for String {
+    fn from(e: StartupError) -> Self {
+        e.to_string()
+    }
+}
+
+/// format_startup_report
+///
+/// Build a report with one line for each
+/// [`StartupError`](crate::core::startup_error::StartupError)
+///
+/// # Arguments
+///
+/// * `errors` - `&[StartupError]` - errors from
+///   [`validate_core_config`](crate::core::core_config::validate_core_config)
+///
+/// # Examples
+///
+/// ```rust
+/// use restapi::core::startup_error::format_startup_report;
+/// use restapi::core::startup_error::StartupError;
+/// let report = format_startup_report(&[StartupError::InvalidEnvVar {
+///     env_var: "API_ENDPOINT".to_string(),
+///     value: "localhost".to_string(),
+///     reason: "expected IP_ADDRESS:PORT".to_string(),
+/// }]);
+/// assert_eq!(
+///     report,
+///     "config has 1 error(s):\n- API_ENDPOINT=localhost - expected IP_ADDRESS:PORT"
+/// );
+/// assert_eq!(format_startup_report(&[]), "config is valid");
+/// ```
+///
+pub fn format_startup_report(errors: &[StartupError]) -> String {
+    if errors.is_empty() {
+        return "config is valid".to_string();
+    }
+    let mut report = format!("config has {} error(s):", errors.len());
+    for e in errors.iter() {
+        report.push_str(&format!("\n- {e}"));
+    }
+    report
+}
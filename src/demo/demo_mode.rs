@@ -0,0 +1,52 @@
+//! Settings for evaluating the api server with seeded
+//! demo users and data
+//!
+use crate::is3::s3_mock_dir::get_s3_mock_dir;
+
+/// DemoMode
+///
+/// Settings for ``DEMO_MODE``
+///
+/// # Supported Environment Variables
+///
+/// ```bash
+/// export DEMO_MODE="1"
+/// export DEMO_S3_DIR="./demo-s3"
+/// export DEMO_PASSWORD="demo-password"
+/// ```
+///
+/// # Arguments
+///
+/// * `enabled` - `bool` - seed the demo users and data on
+///   startup and store s3 objects in `s3_dir` (postgres,
+///   the tls assets and the jwt keys are still required, see
+///   [`demo`](crate::demo))
+/// * `s3_dir` - `String` - local directory used instead
+///   of s3
+/// * `password` - `String` - password for every seeded
+///   user
+///
+#[derive(Clone, Default)]
+pub struct DemoMode {
+    pub enabled: bool,
+    pub s3_dir: String,
+    pub password: String,
+}
+
+impl DemoMode {
+    /// build_demo_mode
+    ///
+    /// Build a
+    /// [`DemoMode`](crate::demo::demo_mode::DemoMode)
+    /// from environment variables
+    ///
+    pub fn build_demo_mode() -> Self {
+        let s3_dir = get_s3_mock_dir();
+        DemoMode {
+            enabled: s3_dir.is_some(),
+            s3_dir: s3_dir.unwrap_or_default(),
+            password: std::env::var("DEMO_PASSWORD")
+                .unwrap_or_else(|_| "demo-password".to_string()),
+        }
+    }
+}
@@ -0,0 +1,29 @@
+//! Run the api server with seeded demo users and data and
+//! a local directory instead of s3 (``DEMO_MODE=1``)
+//!
+//! # Scope
+//!
+//! Demo mode does not have an in-memory or SQLite store and
+//! is not a zero-setup mode. The handlers, background tasks
+//! and startup checks query postgres directly (the
+//! [`UserRepo`](crate::requests::models::user_repo::UserRepo)
+//! and
+//! [`UserDataRepo`](crate::requests::models::user_data_repo::UserDataRepo)
+//! model layer only covers part of them), so demo mode
+//! needs the same setup as a normal server:
+//!
+//! - the tls assets (``./tls/create-tls-assets.sh``)
+//! - the jwt signing keys (``./jwt/recreate-jwt.sh``)
+//! - the postgres db (``./docker/db``)
+//!
+//! Demo mode only removes the aws setup (s3 objects are
+//! stored in ``DEMO_S3_DIR``) and seeds users and files to
+//! try the endpoints with. Startup stops with an error when
+//! the demo data cannot be seeded, for example when postgres
+//! is not running.
+//!
+//! See [`DemoMode`](crate::demo::demo_mode::DemoMode) for
+//! the supported environment variables
+//!
+pub mod demo_mode;
+pub mod seed_demo_data;
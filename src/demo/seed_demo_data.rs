@@ -0,0 +1,232 @@
+//! Seed an admin, sample users and sample ``users_data``
+//! files when the api server starts with ``DEMO_MODE=1``
+//!
+//! Seeding is skipped for any demo email that already
+//! exists, so restarting the server does not duplicate
+//! the sample data.
+//!
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use crate::core::core_config::CoreConfig;
+use crate::monitoring::otel::trace_client_span;
+use crate::monitoring::otel::trace_db_query;
+use crate::utils::password_migration::get_password_scheme;
+
+/// list of `(email, role)` seeded in demo mode
+pub const DEMO_USERS: [(&str, &str); 3] = [
+    ("admin@email.com", "admin"),
+    ("alice@email.com", "user"),
+    ("bob@email.com", "user"),
+];
+
+/// list of `(filename, data_type, comments, contents)`
+/// uploaded for each seeded user with the ``user`` role
+pub const DEMO_USER_DATA: [(&str, &str, &str, &str); 2] = [
+    (
+        "hello.txt",
+        "file",
+        "demo text file",
+        "hello from the restapi demo\n",
+    ),
+    (
+        "sample.json",
+        "json",
+        "demo json file",
+        "{\"demo\": true, \"values\": [1, 2, 3]}\n",
+    ),
+];
+
+/// seed_demo_data
+///
+/// Create the
+/// [`DEMO_USERS`](crate::demo::seed_demo_data::DEMO_USERS)
+/// (verified, with the ``DEMO_PASSWORD`` password), upload
+/// the
+/// [`DEMO_USER_DATA`](crate::demo::seed_demo_data::DEMO_USER_DATA)
+/// files for each new ``user`` to ``DEMO_S3_DIR`` and share
+/// the first user's first file read-only with the second
+/// user
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client db
+///   threadpool with required tls encryption
+///
+/// # Returns
+///
+/// Ok(num_seeded_users: `usize`) - `0` when every demo user
+/// already exists
+///
+/// # Errors
+///
+/// Err(err_msg: `String`) if a demo record cannot be created
+///
+pub async fn seed_demo_data(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+) -> Result<usize, String> {
+    let conn = match db_pool.get().await {
+        Ok(conn) => conn,
+        Err(e) => {
+            return Err(format!(
+                "{tracking_label} - \
+                unable to seed demo data - \
+                failed to get a db connection with err='{e}' - \
+                demo mode needs a running postgres db (see docker/db)"
+            ));
+        }
+    };
+    let hash = config
+        .password_hashing
+        .hash_password(
+            &config.demo_mode.password,
+            &config.server_password_salt,
+        )
+        .unwrap();
+    let password_scheme = get_password_scheme(&hash);
+    let s3_bucket = std::env::var("S3_DATA_BUCKET")
+        .unwrap_or_else(|_| "BUCKET_NAME".to_string());
+    let s3_prefix = std::env::var("S3_DATA_PREFIX")
+        .unwrap_or_else(|_| "user/data/file".to_string());
+
+    let mut num_seeded_users: usize = 0;
+    let mut seeded_user_ids: Vec<i32> = Vec::new();
+    for (email, role) in DEMO_USERS.iter() {
+        let query = format!(
+            "INSERT INTO \
+                users (\
+                    email, \
+                    password, \
+                    state, \
+                    verified, \
+                    role, \
+                    password_scheme) \
+            VALUES (\
+                '{email}', \
+                '{hash}', \
+                0, \
+                1, \
+                '{role}', \
+                '{password_scheme}') \
+            ON CONFLICT (tenant_id, email) DO NOTHING \
+            RETURNING \
+                users.id;"
+        );
+        let query_result =
+            match trace_db_query(&query, conn.query(query.as_str(), &[])).await
+            {
+                Ok(query_result) => query_result,
+                Err(e) => {
+                    return Err(format!(
+                        "{tracking_label} - \
+                    failed to seed demo user {email} with err='{e}'"
+                    ));
+                }
+            };
+        let user_id: i32 = match query_result.first() {
+            Some(row) => row.try_get("id").unwrap(),
+            None => {
+                info!(
+                    "{tracking_label} - \
+                    demo user {email} already exists - skipping"
+                );
+                continue;
+            }
+        };
+        info!("{tracking_label} - seeded demo user {email} id={user_id}");
+        num_seeded_users += 1;
+        if *role != "user" {
+            continue;
+        }
+        seeded_user_ids.push(user_id);
+        for (filename, data_type, comments, contents) in DEMO_USER_DATA.iter() {
+            let s3_key = format!("{s3_prefix}/{user_id}/demo/{filename}");
+            trace_client_span(
+                "s3 upload",
+                vec![
+                    ("s3.bucket", s3_bucket.clone()),
+                    ("s3.key", s3_key.clone()),
+                    ("s3.bytes", format!("{}", contents.len())),
+                ],
+                config.object_store.upload_buffer(
+                    tracking_label,
+                    &s3_bucket,
+                    &s3_key,
+                    contents.as_bytes(),
+                ),
+            )
+            .await?;
+            let query = format!(
+                "INSERT INTO \
+                    users_data (\
+                        user_id, \
+                        filename, \
+                        data_type, \
+                        size_in_bytes, \
+                        comments, \
+                        encoding, \
+                        sloc, \
+                        status) \
+                VALUES (\
+                    {user_id}, \
+                    '{filename}', \
+                    '{data_type}', \
+                    {}, \
+                    '{comments}', \
+                    'na', \
+                    's3://{s3_bucket}/{s3_key}', \
+                    'ready');",
+                contents.len()
+            );
+            if let Err(e) =
+                trace_db_query(&query, conn.query(query.as_str(), &[])).await
+            {
+                return Err(format!(
+                    "{tracking_label} - \
+                    failed to seed demo file {filename} \
+                    for user {user_id} with err='{e}'"
+                ));
+            }
+        }
+    }
+
+    // share the first user's first file with the second user
+    if let [owner_user_id, grantee_user_id] = seeded_user_ids[..] {
+        let query = format!(
+            "INSERT INTO \
+                users_data_acl (\
+                    data_id, \
+                    owner_user_id, \
+                    grantee_user_id, \
+                    access) \
+            SELECT \
+                users_data.id, \
+                {owner_user_id}, \
+                {grantee_user_id}, \
+                'read' \
+            FROM \
+                users_data \
+            WHERE \
+                users_data.user_id = {owner_user_id} \
+            ORDER BY \
+                users_data.id ASC \
+            LIMIT 1;"
+        );
+        if let Err(e) =
+            trace_db_query(&query, conn.query(query.as_str(), &[])).await
+        {
+            return Err(format!(
+                "{tracking_label} - \
+                failed to share demo data from user {owner_user_id} \
+                with user {grantee_user_id} with err='{e}'"
+            ));
+        }
+    }
+    Ok(num_seeded_users)
+}
@@ -0,0 +1,126 @@
+//! Build the OpenAPI document for the user events
+//! served at ``GET /openapi/events.json``
+//!
+use serde_json::json;
+use serde_json::Map;
+use serde_json::Value;
+
+use crate::events::user_event::USER_EVENT_SCHEMAS;
+use crate::kafka::event_bus::EventBus;
+
+/// build_events_openapi
+///
+/// Build an OpenAPI 3 document from
+/// [`USER_EVENT_SCHEMAS`](crate::events::user_event::USER_EVENT_SCHEMAS).
+/// ``components.schemas.UserEvent`` is the parsed
+/// [`UserEvent`](crate::events::user_event::UserEvent) and each
+/// event name has a schema for its ``details``. The default kafka
+/// topic, the topic for each event, partition key, payload
+/// format and schema registry ids (``KAFKA_SCHEMA_REGISTRY_URL``)
+/// are in ``x-kafka``.
+///
+/// # Arguments
+///
+/// * `events` - [`EventBus`](crate::kafka::event_bus::EventBus) -
+///   user event topics
+///
+/// # Returns
+///
+/// [`Value`](serde_json::Value) - the OpenAPI document
+///
+pub fn build_events_openapi(events: &EventBus) -> Value {
+    let event_names: Vec<&str> = USER_EVENT_SCHEMAS
+        .iter()
+        .map(|schema| schema.event)
+        .collect();
+    let mut event_topics = Map::new();
+    for event in event_names.iter() {
+        event_topics.insert(
+            event.to_string(),
+            Value::from(events.get_event_topic(event)),
+        );
+    }
+    let schema_registry = &events.dead_letters.schema_registry;
+    let schema_registry = match schema_registry.enabled {
+        true => json!({
+            "format": schema_registry.format,
+            "subject": "TOPIC-value",
+            "schema_ids": schema_registry.schema_ids,
+            "wire_format": "magic byte 0, 4-byte big-endian schema id, \
+                UserEvent in avro binary or json",
+        }),
+        false => Value::Null,
+    };
+    let mut schemas = Map::new();
+    schemas.insert(
+        "UserEvent".to_string(),
+        json!({
+            "type": "object",
+            "description": "user event parsed from a kafka message payload",
+            "required": ["event", "user_id", "details"],
+            "properties": {
+                "event": {
+                    "type": "string",
+                    "enum": event_names,
+                },
+                "user_id": {
+                    "type": "integer",
+                    "format": "int32",
+                },
+                "details": {
+                    "type": "object",
+                    "description": "key=value details after user=USER_ID \
+                        (see the schema named after the event)",
+                    "additionalProperties": {"type": "string"},
+                },
+            },
+        }),
+    );
+    for schema in USER_EVENT_SCHEMAS.iter() {
+        let mut properties = Map::new();
+        let mut required: Vec<&str> = Vec::new();
+        for field in schema.fields.iter() {
+            properties.insert(
+                field.name.to_string(),
+                json!({
+                    "type": "string",
+                    "description": field.description,
+                }),
+            );
+            if field.required {
+                required.push(field.name);
+            }
+        }
+        schemas.insert(
+            schema.event.to_string(),
+            json!({
+                "type": "object",
+                "description": schema.description,
+                "required": required,
+                "properties": properties,
+                "additionalProperties": schema.dynamic_fields,
+            }),
+        );
+    }
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "restapi user events",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "user events published to kafka - rust \
+                consumers can parse payloads with \
+                restapi::events::UserEvent::from_payload",
+        },
+        "paths": {},
+        "x-kafka": {
+            "topic": events.user_topic,
+            "event_topics": event_topics,
+            "partition_key": "user-USER_ID",
+            "payload_format": "EVENT_NAME user=USER_ID [key=value ...]",
+            "schema_registry": schema_registry,
+        },
+        "components": {
+            "schemas": schemas,
+        },
+    })
+}
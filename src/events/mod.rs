@@ -0,0 +1,7 @@
+//! Typed user event schemas for kafka consumers
+//!
+pub mod events_openapi;
+pub mod user_event;
+pub mod user_event_encoding;
+
+pub use crate::events::user_event::UserEvent;
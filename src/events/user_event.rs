@@ -0,0 +1,793 @@
+//! Canonical user event schemas and a typed
+//! [`UserEvent`](crate::events::user_event::UserEvent)
+//! for kafka consumers
+//!
+//! The [`EventBus`](crate::kafka::event_bus::EventBus)
+//! publishes each user event to the user events topic
+//! (``KAFKA_TOPIC_USER_EVENTS``) with the partition key
+//! ``user-USER_ID`` and a text payload:
+//!
+//! ``EVENT_NAME user=USER_ID [key=value ...]``
+//!
+//! Rust consumers can depend on this crate and parse
+//! payloads with
+//! [`UserEvent::from_payload`](crate::events::user_event::UserEvent::from_payload)
+//! instead of copying the format. Other consumers can
+//! read the same schemas from ``GET /openapi/events.json``.
+//! With ``KAFKA_SCHEMA_REGISTRY_URL`` set the payloads use
+//! the schema registry format instead (see
+//! [`user_event_encoding`](crate::events::user_event_encoding)).
+//!
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+/// UserEventField
+///
+/// A ``key=value`` detail in a user event payload
+///
+/// # Arguments
+///
+/// * `name` - `&str` - detail key
+/// * `required` - `bool` - is the key in every event
+/// * `description` - `&str` - what the value is
+///
+pub struct UserEventField {
+    pub name: &'static str,
+    pub required: bool,
+    pub description: &'static str,
+}
+
+/// UserEventSchema
+///
+/// Schema for one user event name
+///
+/// # Arguments
+///
+/// * `event` - `&str` - event name (``USER_CREATE``)
+/// * `description` - `&str` - when the event is published
+/// * `fields` - `&[UserEventField]` - detail keys after
+///   ``user=USER_ID``
+/// * `dynamic_fields` - `bool` - the event can have other
+///   detail keys (like ``ADMIN_UPDATE_SETTINGS``)
+///
+pub struct UserEventSchema {
+    pub event: &'static str,
+    pub description: &'static str,
+    pub fields: &'static [UserEventField],
+    pub dynamic_fields: bool,
+}
+
+const EMAIL_FIELD: UserEventField = UserEventField {
+    name: "email",
+    required: true,
+    description: "user email",
+};
+
+const IP_RULE_FIELDS: &[UserEventField] = &[
+    UserEventField {
+        name: "rule",
+        required: true,
+        description: "ip_rules.id",
+    },
+    UserEventField {
+        name: "action",
+        required: true,
+        description: "allow or deny",
+    },
+    UserEventField {
+        name: "cidr",
+        required: true,
+        description: "network the rule matches",
+    },
+];
+
+const ADMIN_SECURITY_FIELDS: &[UserEventField] = &[
+    UserEventField {
+        name: "admin",
+        required: true,
+        description: "admin users.id",
+    },
+    UserEventField {
+        name: "revoked",
+        required: true,
+        description: "number of revoked sessions",
+    },
+    UserEventField {
+        name: "reason",
+        required: false,
+        description: "form-urlencoded reason from the admin",
+    },
+];
+
+const COUNTRY_FIELD: UserEventField = UserEventField {
+    name: "country",
+    required: false,
+    description: "session country code (requires GEOIP_DB_PATH)",
+};
+
+const CITY_FIELD: UserEventField = UserEventField {
+    name: "city",
+    required: false,
+    description: "form-urlencoded session city (requires GEOIP_DB_PATH)",
+};
+
+const NO_FIELDS: &[UserEventField] = &[];
+
+/// all user events published by the api server
+pub const USER_EVENT_SCHEMAS: [UserEventSchema; 45] = [
+    UserEventSchema {
+        event: "USER_CREATE",
+        description: "a new user was created",
+        fields: &[EMAIL_FIELD],
+        dynamic_fields: false,
+    },
+    UserEventSchema {
+        event: "USER_GET",
+        description: "a user was read",
+        fields: NO_FIELDS,
+        dynamic_fields: false,
+    },
+    UserEventSchema {
+        event: "USER_UPDATE",
+        description: "a user was updated",
+        fields: &[EMAIL_FIELD],
+        dynamic_fields: false,
+    },
+    UserEventSchema {
+        event: "USER_DELETE",
+        description: "a user was deleted",
+        fields: NO_FIELDS,
+        dynamic_fields: false,
+    },
+    UserEventSchema {
+        event: "USER_DELETE_REQUEST",
+        description: "a user requested a token to confirm deleting \
+            their account",
+        fields: &[
+            EMAIL_FIELD,
+            UserEventField {
+                name: "token",
+                required: true,
+                description: "one-time-use token to email to the user",
+            },
+            UserEventField {
+                name: "link",
+                required: false,
+                description: "deletion confirmation link to email to the \
+                    user (only with USER_DELETE_CONFIRM_URL)",
+            },
+        ],
+        dynamic_fields: false,
+    },
+    UserEventSchema {
+        event: "USER_VERIFY",
+        description: "a user verified their email",
+        fields: &[EMAIL_FIELD],
+        dynamic_fields: false,
+    },
+    UserEventSchema {
+        event: "LOGIN",
+        description: "a user logged in with a password",
+        fields: &[EMAIL_FIELD, COUNTRY_FIELD, CITY_FIELD],
+        dynamic_fields: false,
+    },
+    UserEventSchema {
+        event: "LOGIN_PASSKEY",
+        description: "a user logged in with a passkey",
+        fields: &[EMAIL_FIELD, COUNTRY_FIELD, CITY_FIELD],
+        dynamic_fields: false,
+    },
+    UserEventSchema {
+        event: "USER_PASSKEY_REGISTER",
+        description: "a user registered a passkey",
+        fields: &[UserEventField {
+            name: "passkey",
+            required: true,
+            description: "users_passkeys.id",
+        }],
+        dynamic_fields: false,
+    },
+    UserEventSchema {
+        event: "USER_CREATE_OTP",
+        description: "a user created a one-time-use password reset token",
+        fields: &[
+            UserEventField {
+                name: "email",
+                required: false,
+                description: "user email (only with USER_OTP_DELIVERY=email \
+                    or /user/password/forgot)",
+            },
+            UserEventField {
+                name: "token",
+                required: false,
+                description: "one-time-use token to email to the user \
+                    (only with USER_OTP_DELIVERY=email \
+                    or /user/password/forgot)",
+            },
+            UserEventField {
+                name: "link",
+                required: false,
+                description: "password reset link to email to the user \
+                    (only with USER_OTP_RESET_URL)",
+            },
+        ],
+        dynamic_fields: false,
+    },
+    UserEventSchema {
+        event: "USER_CONSUME_OTP",
+        description: "a user changed their password with a one-time-use \
+            token",
+        fields: NO_FIELDS,
+        dynamic_fields: false,
+    },
+    UserEventSchema {
+        event: "USER_SECURITY_CHANGE",
+        description: "a user's password, email or passkeys changed",
+        fields: &[
+            UserEventField {
+                name: "change",
+                required: true,
+                description: "password, email or passkey",
+            },
+            UserEventField {
+                name: "email",
+                required: true,
+                description: "user email before the change",
+            },
+        ],
+        dynamic_fields: false,
+    },
+    UserEventSchema {
+        event: "ADMIN_SECURITY_REVOKE_SESSIONS",
+        description: "an admin revoked every active session for a user \
+            (user is the target user)",
+        fields: ADMIN_SECURITY_FIELDS,
+        dynamic_fields: false,
+    },
+    UserEventSchema {
+        event: "ADMIN_SECURITY_FORCE_PASSWORD_RESET",
+        description: "an admin revoked a user's sessions and blocked \
+            logins until the user changes their password with a \
+            one-time-use token (user is the target user)",
+        fields: ADMIN_SECURITY_FIELDS,
+        dynamic_fields: false,
+    },
+    UserEventSchema {
+        event: "LOGIN_CHALLENGE",
+        description: "a login risk hook challenged a password login",
+        fields: &[
+            EMAIL_FIELD,
+            UserEventField {
+                name: "token",
+                required: true,
+                description: "one-time-use password to email to the user",
+            },
+        ],
+        dynamic_fields: false,
+    },
+    UserEventSchema {
+        event: "LOGIN_UNRECOGNIZED",
+        description: "a user logged in from a new device or location",
+        fields: &[
+            EMAIL_FIELD,
+            UserEventField {
+                name: "reason",
+                required: true,
+                description: "device, location or device,location",
+            },
+            COUNTRY_FIELD,
+            CITY_FIELD,
+        ],
+        dynamic_fields: false,
+    },
+    UserEventSchema {
+        event: "USER_GET_SESSIONS",
+        description: "a user listed their active sessions",
+        fields: NO_FIELDS,
+        dynamic_fields: false,
+    },
+    UserEventSchema {
+        event: "USER_GET_LOGINS",
+        description: "a user listed their recent login attempts",
+        fields: NO_FIELDS,
+        dynamic_fields: false,
+    },
+    UserEventSchema {
+        event: "USER_GET_QUOTA",
+        description: "a user checked their storage quota and usage",
+        fields: NO_FIELDS,
+        dynamic_fields: false,
+    },
+    UserEventSchema {
+        event: "USER_LIST_S3_OBJECTS",
+        description: "a user listed the s3 keys under a user's prefix",
+        fields: NO_FIELDS,
+        dynamic_fields: false,
+    },
+    UserEventSchema {
+        event: "USER_REVOKE_SESSION",
+        description: "a user revoked one of their sessions",
+        fields: &[UserEventField {
+            name: "session",
+            required: true,
+            description: "users_tokens.id",
+        }],
+        dynamic_fields: false,
+    },
+    UserEventSchema {
+        event: "SEARCH_USERS",
+        description: "a user searched for users",
+        fields: NO_FIELDS,
+        dynamic_fields: false,
+    },
+    UserEventSchema {
+        event: "UPLOAD_USER_DATA",
+        description: "a user uploaded a file",
+        fields: &[UserEventField {
+            name: "data",
+            required: true,
+            description: "users_data.id",
+        }],
+        dynamic_fields: false,
+    },
+    UserEventSchema {
+        event: "USER_UPDATE_DATA",
+        description: "a user updated a file's metadata",
+        fields: NO_FIELDS,
+        dynamic_fields: false,
+    },
+    UserEventSchema {
+        event: "USER_BATCH_DATA",
+        description: "a user updated, moved or deleted files in a batch \
+            (or deleted a file with graphql)",
+        fields: &[
+            UserEventField {
+                name: "updated",
+                required: true,
+                description: "number of update operations",
+            },
+            UserEventField {
+                name: "moved",
+                required: true,
+                description: "number of move operations",
+            },
+            UserEventField {
+                name: "deleted",
+                required: true,
+                description: "number of delete operations",
+            },
+        ],
+        dynamic_fields: false,
+    },
+    UserEventSchema {
+        event: "SEARCH_USER_DATA",
+        description: "a user searched for files",
+        fields: NO_FIELDS,
+        dynamic_fields: false,
+    },
+    UserEventSchema {
+        event: "DATA_DOWNLOADED",
+        description: "a file was downloaded or a presigned download url \
+            was created for it",
+        fields: &[
+            UserEventField {
+                name: "data",
+                required: true,
+                description: "users_data.id",
+            },
+            UserEventField {
+                name: "bytes",
+                required: true,
+                description: "bytes sent (file size for a presigned url)",
+            },
+            UserEventField {
+                name: "access",
+                required: true,
+                description: "owner, acl or share_token",
+            },
+            UserEventField {
+                name: "range",
+                required: false,
+                description: "requested byte range (START-END)",
+            },
+        ],
+        dynamic_fields: false,
+    },
+    UserEventSchema {
+        event: "USER_GET_DATA_ACCESS",
+        description: "a user checked the download stats for a file",
+        fields: &[UserEventField {
+            name: "data",
+            required: true,
+            description: "users_data.id",
+        }],
+        dynamic_fields: false,
+    },
+    UserEventSchema {
+        event: "USER_GRANT_DATA_ACCESS",
+        description: "a user shared a file with a user or role",
+        fields: &[
+            UserEventField {
+                name: "data",
+                required: true,
+                description: "users_data.id",
+            },
+            UserEventField {
+                name: "grantee_user_id",
+                required: false,
+                description: "users.id with access (or grantee_role)",
+            },
+            UserEventField {
+                name: "grantee_role",
+                required: false,
+                description: "role with access (or grantee_user_id)",
+            },
+            UserEventField {
+                name: "access",
+                required: true,
+                description: "read or write",
+            },
+        ],
+        dynamic_fields: false,
+    },
+    UserEventSchema {
+        event: "USER_REVOKE_DATA_ACCESS",
+        description: "a user stopped sharing a file with a user or role",
+        fields: &[
+            UserEventField {
+                name: "data",
+                required: true,
+                description: "users_data.id",
+            },
+            UserEventField {
+                name: "grantee_user_id",
+                required: false,
+                description: "users.id that lost access (or grantee_role)",
+            },
+            UserEventField {
+                name: "grantee_role",
+                required: false,
+                description: "role that lost access (or grantee_user_id)",
+            },
+        ],
+        dynamic_fields: false,
+    },
+    UserEventSchema {
+        event: "USER_SHARE_DATA",
+        description: "a user shared a file with a user or created a \
+            public download link",
+        fields: &[
+            UserEventField {
+                name: "data",
+                required: true,
+                description: "users_data.id",
+            },
+            UserEventField {
+                name: "share",
+                required: true,
+                description: "users_data_shares.id",
+            },
+            UserEventField {
+                name: "shared_with_user_id",
+                required: false,
+                description: "users.id with access (not set for a link)",
+            },
+        ],
+        dynamic_fields: false,
+    },
+    UserEventSchema {
+        event: "USER_UNSHARE_DATA",
+        description: "a user removed a file share or public download link",
+        fields: &[
+            UserEventField {
+                name: "data",
+                required: true,
+                description: "users_data.id",
+            },
+            UserEventField {
+                name: "share",
+                required: true,
+                description: "users_data_shares.id",
+            },
+        ],
+        dynamic_fields: false,
+    },
+    UserEventSchema {
+        event: "USER_INVITE",
+        description: "an admin invited a new user (user is the invited \
+            user)",
+        fields: &[
+            EMAIL_FIELD,
+            UserEventField {
+                name: "invited_by",
+                required: true,
+                description: "admin users.id",
+            },
+        ],
+        dynamic_fields: false,
+    },
+    UserEventSchema {
+        event: "USER_INVITE_ACCEPT",
+        description: "an invited user accepted their invite",
+        fields: &[EMAIL_FIELD],
+        dynamic_fields: false,
+    },
+    UserEventSchema {
+        event: "ADMIN_UNLOCK_LOGIN",
+        description: "an admin cleared failed login lockouts",
+        fields: &[
+            EMAIL_FIELD,
+            UserEventField {
+                name: "ip",
+                required: true,
+                description:
+                    "unlocked ip address (empty if only the email was unlocked)",
+            },
+        ],
+        dynamic_fields: false,
+    },
+    UserEventSchema {
+        event: "ADMIN_GET_SETTINGS",
+        description: "an admin read the runtime settings",
+        fields: NO_FIELDS,
+        dynamic_fields: false,
+    },
+    UserEventSchema {
+        event: "ADMIN_UPDATE_SETTINGS",
+        description: "an admin changed runtime settings (one SETTING=value \
+            per change, null = removed)",
+        fields: NO_FIELDS,
+        dynamic_fields: true,
+    },
+    UserEventSchema {
+        event: "ADMIN_GET_JWT_KEYS",
+        description: "an admin read the jwt key migration status",
+        fields: NO_FIELDS,
+        dynamic_fields: false,
+    },
+    UserEventSchema {
+        event: "ADMIN_GET_PASSWORD_SCHEMES",
+        description: "an admin read the password hash scheme report",
+        fields: NO_FIELDS,
+        dynamic_fields: false,
+    },
+    UserEventSchema {
+        event: "ADMIN_RETIRE_JWT_KEY",
+        description: "an admin retired a jwt signing key",
+        fields: &[
+            UserEventField {
+                name: "kid",
+                required: true,
+                description: "retired jwt kid",
+            },
+            UserEventField {
+                name: "revoked",
+                required: true,
+                description: "active tokens revoked with force=true",
+            },
+        ],
+        dynamic_fields: false,
+    },
+    UserEventSchema {
+        event: "ADMIN_CREATE_WEBHOOK",
+        description: "an admin registered a webhook endpoint",
+        fields: &[UserEventField {
+            name: "webhook",
+            required: true,
+            description: "webhooks.id",
+        }],
+        dynamic_fields: false,
+    },
+    UserEventSchema {
+        event: "ADMIN_DELETE_WEBHOOK",
+        description: "an admin removed a webhook endpoint",
+        fields: &[UserEventField {
+            name: "webhook",
+            required: true,
+            description: "webhooks.id",
+        }],
+        dynamic_fields: false,
+    },
+    UserEventSchema {
+        event: "ADMIN_CREATE_IP_RULE",
+        description: "an admin added an ip allow or deny rule",
+        fields: IP_RULE_FIELDS,
+        dynamic_fields: false,
+    },
+    UserEventSchema {
+        event: "ADMIN_DELETE_IP_RULE",
+        description: "an admin removed an ip allow or deny rule",
+        fields: IP_RULE_FIELDS,
+        dynamic_fields: false,
+    },
+    UserEventSchema {
+        event: "ADMIN_REQUEUE_KAFKA_DEAD_LETTERS",
+        description: "an admin published kafka dead letters again",
+        fields: &[
+            UserEventField {
+                name: "requeued",
+                required: true,
+                description: "number of published dead letters",
+            },
+            UserEventField {
+                name: "failed",
+                required: true,
+                description: "number of dead letters that failed again",
+            },
+        ],
+        dynamic_fields: false,
+    },
+];
+
+/// get_user_event_schema
+///
+/// Find the schema for an event name
+///
+/// # Arguments
+///
+/// * `event` - `&str` - event name (``USER_CREATE``)
+///
+/// # Returns
+///
+/// `Option<&UserEventSchema>` - `None` for unknown events
+///
+pub fn get_user_event_schema(event: &str) -> Option<&'static UserEventSchema> {
+    USER_EVENT_SCHEMAS
+        .iter()
+        .find(|schema| schema.event == event)
+}
+
+/// UserEvent
+///
+/// Typed user event parsed from a kafka message payload
+///
+/// # Usage
+///
+/// ```rust
+/// use restapi::events::UserEvent;
+/// let event = UserEvent::from_payload(
+///     b"USER_INVITE user=7 email=new@email.com invited_by=1").unwrap();
+/// assert_eq!(event.event, "USER_INVITE");
+/// assert_eq!(event.user_id, 7);
+/// assert_eq!(event.get("email"), Some("new@email.com"));
+/// assert_eq!(event.get_i32("invited_by"), Some(1));
+/// assert_eq!(
+///     event.to_payload(),
+///     "USER_INVITE user=7 email=new@email.com invited_by=1");
+/// assert!(UserEvent::from_payload(b"USER_CREATE email=a@b.com").is_err());
+/// ```
+///
+/// # Arguments
+///
+/// * `event` - `String` - event name (``USER_CREATE``)
+/// * `user_id` - `i32` - user id (also in the partition key)
+/// * `details` - `BTreeMap<String, String>` - ``key=value``
+///   details after ``user=USER_ID``
+///
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct UserEvent {
+    pub event: String,
+    pub user_id: i32,
+    pub details: BTreeMap<String, String>,
+}
+
+impl UserEvent {
+    /// from_payload
+    ///
+    /// Parse a kafka message payload
+    ///
+    /// # Arguments
+    ///
+    /// * `payload` - `&[u8]` - kafka message payload
+    ///
+    /// # Returns
+    ///
+    /// Ok([`UserEvent`](crate::events::user_event::UserEvent))
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) when the payload is not utf-8
+    /// or is missing the event name or ``user=USER_ID``
+    ///
+    pub fn from_payload(payload: &[u8]) -> Result<Self, String> {
+        match std::str::from_utf8(payload) {
+            Ok(payload_str) => payload_str.parse::<UserEvent>(),
+            Err(e) => Err(format!("invalid user event payload with err='{e}'")),
+        }
+    }
+
+    /// get
+    ///
+    /// Get a detail value by key
+    ///
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.details.get(key).map(|v| v.as_str())
+    }
+
+    /// get_i32
+    ///
+    /// Get a detail value by key as an `i32` (for id
+    /// details like ``session`` or ``data``)
+    ///
+    pub fn get_i32(&self, key: &str) -> Option<i32> {
+        self.get(key).and_then(|v| v.parse::<i32>().ok())
+    }
+
+    /// get_schema
+    ///
+    /// Get the
+    /// [`UserEventSchema`](crate::events::user_event::UserEventSchema)
+    /// for this event (`None` for events published by a
+    /// newer api server)
+    ///
+    pub fn get_schema(&self) -> Option<&'static UserEventSchema> {
+        get_user_event_schema(&self.event)
+    }
+
+    /// to_payload
+    ///
+    /// Serialize the event in the kafka payload format
+    /// (details are sorted by key)
+    ///
+    pub fn to_payload(&self) -> String {
+        let mut payload = format!("{} user={}", self.event, self.user_id);
+        for (key, value) in self.details.iter() {
+            payload.push_str(&format!(" {key}={value}"));
+        }
+        payload
+    }
+}
+
+impl FromStr for UserEvent {
+    type Err = String;
+
+    fn from_str(payload: &str) -> Result<Self, Self::Err> {
+        let mut parts = payload.split_whitespace();
+        let event = match parts.next() {
+            Some(v) if !v.contains('=') => v.to_string(),
+            _ => {
+                return Err(format!(
+                    "invalid user event payload={payload} - missing event name"
+                ))
+            }
+        };
+        let user_id = match parts.next().and_then(|v| v.strip_prefix("user=")) {
+            Some(v) => match v.parse::<i32>() {
+                Ok(user_id) => user_id,
+                Err(_) => {
+                    return Err(format!(
+                        "invalid user event payload={payload} - \
+                        invalid user={v}"
+                    ))
+                }
+            },
+            None => {
+                return Err(format!(
+                    "invalid user event payload={payload} - missing user="
+                ))
+            }
+        };
+        let mut details: BTreeMap<String, String> = BTreeMap::new();
+        for part in parts {
+            match part.split_once('=') {
+                Some((key, value)) => {
+                    details.insert(key.to_string(), value.to_string());
+                }
+                None => {
+                    return Err(format!(
+                        "invalid user event payload={payload} - \
+                        detail={part} is not key=value"
+                    ))
+                }
+            }
+        }
+        Ok(UserEvent {
+            event,
+            user_id,
+            details,
+        })
+    }
+}
@@ -0,0 +1,284 @@
+//! Schema registry encodings for the
+//! [`UserEvent`](crate::events::user_event::UserEvent)
+//!
+//! With ``KAFKA_SCHEMA_REGISTRY_URL`` set the
+//! [`SchemaRegistry`](crate::kafka::schema_registry::SchemaRegistry)
+//! registers the ``UserEvent`` schema for each user event
+//! topic and publishes payloads in the Confluent wire format:
+//!
+//! - byte ``0`` - magic byte ``0``
+//! - bytes ``1-4`` - schema id (big-endian)
+//! - remaining bytes - the event in avro binary or json
+//!
+//! Rust consumers can decode payloads with
+//! [`decode_user_event`](crate::events::user_event_encoding::decode_user_event)
+//! and other consumers can use any Confluent deserializer.
+//!
+use serde_json::json;
+
+use crate::events::user_event::UserEvent;
+
+/// supported schema registry payload formats
+pub const USER_EVENT_FORMATS: [&str; 2] = ["avro", "json"];
+
+/// get_user_event_schema_type
+///
+/// Get the schema registry ``schemaType`` for a payload
+/// format (``AVRO`` or ``JSON``)
+///
+/// # Arguments
+///
+/// * `format` - `&str` - ``avro`` or ``json``
+///
+/// # Errors
+///
+/// Err(err_msg: `String`) for an unsupported format
+///
+pub fn get_user_event_schema_type(
+    format: &str,
+) -> Result<&'static str, String> {
+    match format {
+        "avro" => Ok("AVRO"),
+        "json" => Ok("JSON"),
+        _ => Err(format!(
+            "unsupported user event format={format} - use one of: {}",
+            USER_EVENT_FORMATS.join(", ")
+        )),
+    }
+}
+
+/// get_user_event_schema
+///
+/// Get the ``UserEvent`` schema registered for a payload
+/// format (an avro record or a json schema)
+///
+/// # Arguments
+///
+/// * `format` - `&str` - ``avro`` or ``json``
+///
+/// # Errors
+///
+/// Err(err_msg: `String`) for an unsupported format
+///
+pub fn get_user_event_schema(format: &str) -> Result<String, String> {
+    let schema = match get_user_event_schema_type(format)? {
+        "AVRO" => json!({
+            "type": "record",
+            "name": "UserEvent",
+            "namespace": "restapi.events",
+            "doc": "user event published by the restapi server \
+                (see GET /openapi/events.json for the details of each event)",
+            "fields": [
+                {"name": "event", "type": "string"},
+                {"name": "user_id", "type": "int"},
+                {
+                    "name": "details",
+                    "type": {"type": "map", "values": "string"},
+                },
+            ],
+        }),
+        _ => json!({
+            "$schema": "http://json-schema.org/draft-07/schema#",
+            "title": "UserEvent",
+            "description": "user event published by the restapi server \
+                (see GET /openapi/events.json for the details of each event)",
+            "type": "object",
+            "required": ["event", "user_id", "details"],
+            "properties": {
+                "event": {"type": "string"},
+                "user_id": {"type": "integer"},
+                "details": {
+                    "type": "object",
+                    "additionalProperties": {"type": "string"},
+                },
+            },
+        }),
+    };
+    Ok(schema.to_string())
+}
+
+/// encode_user_event
+///
+/// Encode a user event in the Confluent wire format
+///
+/// # Arguments
+///
+/// * `event` - [`UserEvent`](crate::events::user_event::UserEvent)
+/// * `format` - `&str` - ``avro`` or ``json``
+/// * `schema_id` - `u32` - registered schema id
+///
+/// # Errors
+///
+/// Err(err_msg: `String`) for an unsupported format
+///
+/// # Examples
+///
+/// ```rust
+/// use restapi::events::UserEvent;
+/// use restapi::events::user_event_encoding::decode_user_event;
+/// use restapi::events::user_event_encoding::encode_user_event;
+/// let event = UserEvent::from_payload(b"LOGIN user=7 email=a@b.com").unwrap();
+/// for format in ["avro", "json"] {
+///     let payload = encode_user_event(&event, format, 42).unwrap();
+///     assert_eq!(payload[..5], [0, 0, 0, 0, 42]);
+///     assert_eq!(decode_user_event(&payload, format).unwrap(), (42, event.clone()));
+/// }
+/// ```
+///
+pub fn encode_user_event(
+    event: &UserEvent,
+    format: &str,
+    schema_id: u32,
+) -> Result<Vec<u8>, String> {
+    let mut payload = vec![0_u8];
+    payload.extend_from_slice(&schema_id.to_be_bytes());
+    match get_user_event_schema_type(format)? {
+        "AVRO" => {
+            write_avro_string(&mut payload, &event.event);
+            write_avro_long(&mut payload, event.user_id as i64);
+            if !event.details.is_empty() {
+                write_avro_long(&mut payload, event.details.len() as i64);
+                for (key, value) in event.details.iter() {
+                    write_avro_string(&mut payload, key);
+                    write_avro_string(&mut payload, value);
+                }
+            }
+            write_avro_long(&mut payload, 0);
+        }
+        _ => payload.extend_from_slice(
+            serde_json::to_string(event).unwrap().as_bytes(),
+        ),
+    }
+    Ok(payload)
+}
+
+/// decode_user_event
+///
+/// Decode a user event from the Confluent wire format
+///
+/// # Arguments
+///
+/// * `payload` - `&[u8]` - kafka message payload
+/// * `format` - `&str` - ``avro`` or ``json``
+///
+/// # Returns
+///
+/// Ok((`u32`, [`UserEvent`](crate::events::user_event::UserEvent))) -
+/// the schema id and the event
+///
+/// # Errors
+///
+/// Err(err_msg: `String`) when the payload does not start
+/// with the magic byte and schema id or the event cannot be
+/// decoded
+///
+pub fn decode_user_event(
+    payload: &[u8],
+    format: &str,
+) -> Result<(u32, UserEvent), String> {
+    let schema_type = get_user_event_schema_type(format)?;
+    if payload.len() < 5 || payload[0] != 0 {
+        return Err("invalid user event payload - missing the magic \
+            byte and schema id"
+            .to_string());
+    }
+    let schema_id =
+        u32::from_be_bytes([payload[1], payload[2], payload[3], payload[4]]);
+    let body = &payload[5..];
+    let event = match schema_type {
+        "AVRO" => {
+            let mut pos = 0;
+            let event = read_avro_string(body, &mut pos)?;
+            let user_id = i32::try_from(read_avro_long(body, &mut pos)?)
+                .map_err(|_| "invalid avro user event user_id".to_string())?;
+            let mut event = UserEvent {
+                event,
+                user_id,
+                ..Default::default()
+            };
+            loop {
+                let mut count = read_avro_long(body, &mut pos)?;
+                if count == 0 {
+                    break;
+                }
+                if count < 0 {
+                    // negative counts are followed by the block size
+                    count = -count;
+                    read_avro_long(body, &mut pos)?;
+                }
+                for _ in 0..count {
+                    let key = read_avro_string(body, &mut pos)?;
+                    let value = read_avro_string(body, &mut pos)?;
+                    event.details.insert(key, value);
+                }
+            }
+            event
+        }
+        _ => serde_json::from_slice::<UserEvent>(body).map_err(|e| {
+            format!("invalid json user event payload with err='{e}'")
+        })?,
+    };
+    Ok((schema_id, event))
+}
+
+/// write_avro_long
+///
+/// Append a zig-zag varint (avro ``int`` and ``long``)
+///
+fn write_avro_long(buf: &mut Vec<u8>, value: i64) {
+    let mut n = ((value << 1) ^ (value >> 63)) as u64;
+    while n > 0x7f {
+        buf.push((n & 0x7f) as u8 | 0x80);
+        n >>= 7;
+    }
+    buf.push(n as u8);
+}
+
+/// write_avro_string
+///
+/// Append a length-prefixed utf-8 string
+///
+fn write_avro_string(buf: &mut Vec<u8>, value: &str) {
+    write_avro_long(buf, value.len() as i64);
+    buf.extend_from_slice(value.as_bytes());
+}
+
+/// read_avro_long
+///
+/// Read a zig-zag varint
+///
+fn read_avro_long(buf: &[u8], pos: &mut usize) -> Result<i64, String> {
+    let mut n: u64 = 0;
+    let mut shift = 0;
+    loop {
+        let byte = match buf.get(*pos) {
+            Some(byte) if shift < 64 => *byte,
+            _ => return Err("invalid avro user event payload".to_string()),
+        };
+        *pos += 1;
+        n |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+    }
+    Ok((n >> 1) as i64 ^ -((n & 1) as i64))
+}
+
+/// read_avro_string
+///
+/// Read a length-prefixed utf-8 string
+///
+fn read_avro_string(buf: &[u8], pos: &mut usize) -> Result<String, String> {
+    let len = usize::try_from(read_avro_long(buf, pos)?)
+        .map_err(|_| "invalid avro user event string length".to_string())?;
+    match buf.get(*pos..pos.saturating_add(len)) {
+        Some(value) => {
+            *pos += len;
+            String::from_utf8(value.to_vec()).map_err(|e| {
+                format!("invalid avro user event string with err='{e}'")
+            })
+        }
+        None => Err("invalid avro user event payload".to_string()),
+    }
+}
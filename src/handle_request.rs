@@ -11,44 +11,405 @@ use std::convert::Infallible;
 use hyper::body;
 use hyper::Body;
 use hyper::Method;
+use hyper::Request;
 use hyper::Response;
 
+use crate::monitoring::health::handle_health;
+#[cfg(feature = "metrics")]
 use crate::monitoring::metrics::handle_showing_metrics;
+#[cfg(feature = "metrics")]
+use crate::monitoring::metrics::handle_showing_openmetrics;
 use crate::monitoring::metrics::record_monitoring_metrics_api_after;
 use crate::monitoring::metrics::record_monitoring_metrics_api_before;
+#[cfg(feature = "metrics")]
+use crate::monitoring::openmetrics::is_openmetrics_accepted;
+use crate::monitoring::otel::trace_request;
+use crate::monitoring::request_metrics::scope_request_metrics;
+use crate::pools::db_query_limits::scope_db_query_timeouts;
 
+use crate::core::server::access_log::get_request_id;
+use crate::core::server::api_version::split_api_version;
+use crate::core::server::api_version::ApiVersion;
 use crate::core::server::core_http_request::CoreHttpRequest;
+use crate::core::server::custom_route::find_custom_route;
+use crate::core::server::request_deadline::REQUEST_DEADLINE_EXCEEDED_COUNTER;
+use crate::core::server::response_format::ResponseFormatQuery;
+use crate::settings::runtime_settings::RuntimeSettings;
 
 use crate::utils::get_server_address::get_server_address;
 
 // request handlers
 
+// admin requests
+use crate::requests::admin::create_ip_rule::create_ip_rule;
+use crate::requests::admin::create_webhook::create_webhook;
+use crate::requests::admin::delete_ip_rule::delete_ip_rule;
+use crate::requests::admin::delete_webhook::delete_webhook;
+use crate::requests::admin::force_password_reset::force_password_reset;
+use crate::requests::admin::get_admin_jwt_keys::get_admin_jwt_keys;
+use crate::requests::admin::get_admin_password_schemes::get_admin_password_schemes;
+use crate::requests::admin::get_admin_settings::get_admin_settings;
+use crate::requests::admin::get_admin_stats::get_admin_stats;
+use crate::requests::admin::get_ip_rules::get_ip_rules;
+use crate::requests::admin::get_webhooks::get_webhooks;
+use crate::requests::admin::invite_user::invite_user;
+use crate::requests::admin::requeue_kafka_dead_letters::requeue_kafka_dead_letters;
+use crate::requests::admin::retire_jwt_key::retire_jwt_key;
+use crate::requests::admin::revoke_user_sessions::revoke_user_sessions;
+use crate::requests::admin::search_kafka_dead_letters::search_kafka_dead_letters;
+use crate::requests::admin::search_webhook_deliveries::search_webhook_deliveries;
+use crate::requests::admin::unlock_login::unlock_login;
+use crate::requests::admin::update_admin_settings::update_admin_settings;
+
 // auth requests
+use crate::requests::auth::get_csrf_token::get_csrf_token;
+use crate::requests::auth::get_jwks::get_jwks;
 use crate::requests::auth::login_user::login_user;
+use crate::requests::auth::token_scopes::get_route_scope;
+use crate::requests::auth::token_scopes::scope_request_token_scope;
+use crate::requests::auth::webauthn::finish_passkey_login::finish_passkey_login;
+use crate::requests::auth::webauthn::finish_passkey_registration::finish_passkey_registration;
+use crate::requests::auth::webauthn::start_passkey_login::start_passkey_login;
+use crate::requests::auth::webauthn::start_passkey_registration::start_passkey_registration;
+
+// event requests
+use crate::requests::events::get_events_openapi::get_events_openapi;
+
+// graphql requests
+#[cfg(feature = "graphql")]
+use crate::requests::graphql::handle_graphql::get_graphql_schema;
+#[cfg(feature = "graphql")]
+use crate::requests::graphql::handle_graphql::handle_graphql;
 
 // user requests
+use crate::requests::user::accept_user_invite::accept_user_invite;
+use crate::requests::user::batch_user_data::batch_user_data;
+use crate::requests::user::complete_resumable_upload::complete_resumable_upload;
+use crate::requests::user::complete_s3_event_uploads::complete_s3_event_uploads;
+use crate::requests::user::confirm_user_delete::confirm_user_delete;
 use crate::requests::user::consume_user_otp::consume_user_otp;
 use crate::requests::user::create_otp::create_otp;
 use crate::requests::user::create_user::create_user;
 use crate::requests::user::delete_user::delete_user;
+use crate::requests::user::download_shared_user_data::download_shared_user_data;
+use crate::requests::user::download_user_data::download_user_data;
+use crate::requests::user::forgot_password::forgot_password;
+use crate::requests::user::get_resumable_upload::get_resumable_upload;
 use crate::requests::user::get_user::get_user;
+use crate::requests::user::get_user_data_access::get_user_data_access;
+use crate::requests::user::get_user_logins::get_user_logins;
+use crate::requests::user::get_user_quota::get_user_quota;
+use crate::requests::user::get_user_sessions::get_user_sessions;
+use crate::requests::user::grant_user_data_access::grant_user_data_access;
+use crate::requests::user::list_user_data_s3_objects::list_user_data_s3_objects;
+use crate::requests::user::request_user_delete::request_user_delete;
+use crate::requests::user::revoke_user_data_access::revoke_user_data_access;
+use crate::requests::user::revoke_user_session::revoke_user_session;
 use crate::requests::user::search_user_data::search_user_data;
 use crate::requests::user::search_users::search_users;
+use crate::requests::user::share_user_data::share_user_data;
+use crate::requests::user::start_resumable_upload::start_resumable_upload;
+use crate::requests::user::stream_user_notifications::stream_user_notifications;
+use crate::requests::user::unshare_user_data::unshare_user_data;
+use crate::requests::user::update_user::patch_user;
 use crate::requests::user::update_user::update_user;
+use crate::requests::user::update_user_data::patch_user_data;
 use crate::requests::user::update_user_data::update_user_data;
 use crate::requests::user::upload_user_data::upload_user_data;
+use crate::requests::user::upload_user_data_chunk::upload_user_data_chunk;
 use crate::requests::user::verify_user::verify_user;
 
 /// handle_request
 ///
 /// The url routing handler for all api requests.
 ///
+/// Requests with an ``X-Request-Timeout`` or ``grpc-timeout``
+/// header (or a ``REQUEST_TIMEOUT_DEFAULT_MS`` default) are
+/// abandoned with a ``504`` once their deadline passes
+/// (see [`RequestDeadline`](crate::core::server::request_deadline::RequestDeadline)).
+///
+/// Client addresses that are denied by the ip allowlists and
+/// denylists are rejected with a ``403`` before they are routed
+/// (see [`IpAccessControl`](crate::core::server::ip_access::IpAccessControl)).
+///
+/// When admission control is enabled, requests are shed with a
+/// ``429`` or ``503`` by priority class before they are routed
+/// (see [`AdmissionControl`](crate::core::server::admission_control::AdmissionControl)).
+///
+/// With an ``OTEL_EXPORTER_OTLP_ENDPOINT`` (and the ``otel``
+/// feature) each request is served in a trace span
+/// (see [`trace_request`](crate::monitoring::otel::trace_request)).
+///
+/// Each request's latency is observed with its
+/// ``X-Request-Id`` (or a new uuid) as an exemplar and the
+/// request is counted in the ``http_route_requests_in_flight``
+/// gauge while its route handler runs
+/// (see [`scope_request_metrics`](crate::monitoring::request_metrics::scope_request_metrics)).
+///
+/// Url paths with a ``/v1`` or ``/v2`` prefix are served by
+/// that version's handler set and unprefixed paths are
+/// served as ``v1``
+/// (see [`split_api_version`](crate::core::server::api_version::split_api_version)).
+///
 /// # Arguments
 ///
 /// * `data` - [`CoreHttpRequest`](crate::core::server::core_http_request::CoreHttpRequest)
 ///
 pub async fn handle_request(
     data: CoreHttpRequest,
+) -> std::result::Result<Response<Body>, Infallible> {
+    let request_id = get_request_id(data.request.headers());
+    if !data.config.otel.is_enabled() {
+        return scope_request_metrics(request_id, admit_request(data)).await;
+    }
+    let request_method = data.request.method().clone();
+    let request_uri = data.request.uri().path().to_string();
+    let headers = data.request.headers().clone();
+    scope_request_metrics(
+        request_id,
+        trace_request(
+            &request_method,
+            &request_uri,
+            &headers,
+            admit_request(data),
+        ),
+    )
+    .await
+}
+
+/// admit_request
+///
+/// Apply the ip allowlists and denylists, admission control
+/// and the request deadline before routing a request
+///
+/// # Arguments
+///
+/// * `data` - [`CoreHttpRequest`](crate::core::server::core_http_request::CoreHttpRequest)
+///
+async fn admit_request(
+    mut data: CoreHttpRequest,
+) -> std::result::Result<Response<Body>, Infallible> {
+    let tracking_label = data.config.label.to_string();
+    let request_uri = data.request.uri().path().to_string();
+    let request_method = data.request.method().clone();
+    // everything after this routes on the unprefixed path
+    let (api_version, request_uri) = match split_api_version(&request_uri) {
+        Ok((api_version, path)) => (api_version, path.to_string()),
+        Err(reason) => {
+            let err_msg = format!("{{\"status\":404,\"reason\":\"{reason}\"}}");
+            warn!("{tracking_label} - {err_msg}");
+            return Ok(Response::builder()
+                .status(404)
+                .body(Body::from(err_msg))
+                .unwrap());
+        }
+    };
+    if request_uri != data.request.uri().path() {
+        let mut uri_parts = data.request.uri().clone().into_parts();
+        let path_and_query = match data.request.uri().query() {
+            Some(query) => format!("{request_uri}?{query}"),
+            None => request_uri.clone(),
+        };
+        uri_parts.path_and_query = path_and_query.parse().ok();
+        if let Ok(uri) = hyper::Uri::from_parts(uri_parts) {
+            *data.request.uri_mut() = uri;
+        }
+    }
+    // reject denied client addresses before any other work
+    if let Err(reason) = data
+        .config
+        .ip_access
+        .check_request(&data.remote_addr.ip(), &request_uri)
+    {
+        let err_msg = format!("{{\"status\":403,\"reason\":\"{reason}\"}}");
+        warn!("{tracking_label} - {err_msg}");
+        return Ok(Response::builder()
+            .status(403)
+            .body(Body::from(err_msg))
+            .unwrap());
+    }
+    // shed the lowest priority requests first when the
+    // server is overloaded. the guard counts this request
+    // as in flight until the response is built
+    let _in_flight = match data
+        .config
+        .admission_control
+        .try_admit(&request_method, &request_uri)
+    {
+        Ok(in_flight) => in_flight,
+        Err(rejected) => {
+            let err_msg = format!(
+                "{{\"status\":{},\"reason\":\"{}\"}}",
+                rejected.status, rejected.reason
+            );
+            warn!("{tracking_label} - {err_msg}");
+            return Ok(Response::builder()
+                .status(rejected.status)
+                .header(
+                    "Retry-After",
+                    format!("{}", rejected.retry_after_seconds),
+                )
+                .body(Body::from(err_msg))
+                .unwrap());
+        }
+    };
+    let timeout = match data
+        .config
+        .request_deadline
+        .get_timeout(data.request.headers())
+    {
+        Ok(timeout) => timeout,
+        Err(err_msg) => {
+            let err_msg =
+                format!("{{\"status\":400,\"reason\":\"{err_msg}\"}}");
+            warn!("{tracking_label} - {err_msg}");
+            return Ok(Response::builder()
+                .status(400)
+                .body(Body::from(err_msg))
+                .unwrap());
+        }
+    };
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return route_request(data, api_version).await,
+    };
+    // dropping the routed future on timeout stops all
+    // pending db, s3 and kafka work for this request
+    match tokio::time::timeout(timeout, route_request(data, api_version)).await
+    {
+        Ok(processed_result) => processed_result,
+        Err(_) => {
+            REQUEST_DEADLINE_EXCEEDED_COUNTER.inc();
+            let err_msg = format!(
+                "{{\"status\":504,\"reason\":\"request deadline of \
+                {}ms exceeded - abandoned {request_method} {request_uri}\"}}",
+                timeout.as_millis()
+            );
+            warn!("{tracking_label} - {err_msg}");
+            Ok(Response::builder()
+                .status(504)
+                .body(Body::from(err_msg))
+                .unwrap())
+        }
+    }
+}
+
+/// route_request
+///
+/// Route a request to the handler set for its api version
+/// and tag the response with an ``API-Version`` header.
+/// Response messages are translated when
+/// ``MESSAGE_LOCALIZATION_ENABLED=1``
+/// (see [`MessageLocalization`](crate::i18n::message_localization::MessageLocalization)).
+/// Scoped tokens are checked against the route's scope from
+/// [`get_route_scope`](crate::requests::auth::token_scopes::get_route_scope).
+/// Json responses are trimmed and indented with the
+/// ``fields`` and ``pretty`` query parameters
+/// (see [`ResponseFormat`](crate::core::server::response_format::ResponseFormat)).
+/// Handler ``500`` responses after a postgres statement
+/// timeout are returned as ``504``
+/// (see [`scope_db_query_timeouts`](crate::pools::db_query_limits::scope_db_query_timeouts)).
+///
+/// # Arguments
+///
+/// * `data` - [`CoreHttpRequest`](crate::core::server::core_http_request::CoreHttpRequest)
+///   with an unprefixed url path
+/// * `api_version` - [`ApiVersion`](crate::core::server::api_version::ApiVersion) -
+///   version from the url path prefix
+///
+async fn route_request(
+    data: CoreHttpRequest,
+    api_version: ApiVersion,
+) -> std::result::Result<Response<Body>, Infallible> {
+    let message_localization = data.config.message_localization.clone();
+    let response_format = data.config.response_format.clone();
+    let format_query =
+        ResponseFormatQuery::from_query(data.request.uri().query());
+    let headers = data.request.headers().clone();
+    let required_scope =
+        get_route_scope(data.request.method(), data.request.uri().path());
+    let processed_result = message_localization
+        .localize_request(
+            &headers,
+            Box::pin(scope_request_token_scope(
+                required_scope,
+                scope_db_query_timeouts(async move {
+                    match api_version {
+                        ApiVersion::V1 => route_v1_request(data).await,
+                        ApiVersion::V2 => route_v2_request(data).await,
+                    }
+                }),
+            )),
+        )
+        .await;
+    let mut response = match processed_result {
+        Ok(response) => response,
+        Err(e) => match e {},
+    };
+    response = response_format
+        .format_response(&format_query, response)
+        .await;
+    api_version.set_response_header(&mut response);
+    Ok(response)
+}
+
+/// UnsupportedRoute
+///
+/// Response extension set by
+/// [`route_v1_request`](crate::handle_request) when no
+/// handler matched the method and url path
+///
+/// # Arguments
+///
+/// * `reason` - `String` - error message with the method
+///   and url
+///
+#[derive(Clone, Debug)]
+pub struct UnsupportedRoute {
+    pub reason: String,
+}
+
+/// route_v2_request
+///
+/// Route a request to its v2 api handler. Routes with a
+/// breaking change in v2 are matched here, and every other
+/// route is served by its v1 handler. The v2 breaking
+/// changes are:
+///
+/// - an unsupported method and url path gets a ``404``
+///   (v1 returns a ``200`` with a ``"status":400`` body)
+///
+/// # Arguments
+///
+/// * `data` - [`CoreHttpRequest`](crate::core::server::core_http_request::CoreHttpRequest)
+///
+async fn route_v2_request(
+    data: CoreHttpRequest,
+) -> std::result::Result<Response<Body>, Infallible> {
+    let response = route_v1_request(data).await?;
+    let reason = match response.extensions().get::<UnsupportedRoute>() {
+        Some(unsupported_route) => unsupported_route.reason.clone(),
+        None => return Ok(response),
+    };
+    Ok(Response::builder()
+        .status(404)
+        .header("Content-Type", "application/json")
+        .body(Body::from(
+            serde_json::json!({"status": 404, "reason": reason}).to_string(),
+        ))
+        .unwrap())
+}
+
+/// route_v1_request
+///
+/// Route a request to its v1 api handler
+///
+/// # Arguments
+///
+/// * `data` - [`CoreHttpRequest`](crate::core::server::core_http_request::CoreHttpRequest)
+///
+async fn route_v1_request(
+    data: CoreHttpRequest,
 ) -> std::result::Result<Response<Body>, Infallible> {
     /*
     let tracking_label = format!(
@@ -83,7 +444,98 @@ pub async fn handle_request(
         Ok(Response::new(Body::from("prep".to_string())));
     let (parts, body) = data.request.into_parts();
     let request_uri = parts.uri.path();
-    let request_method = parts.method;
+    let request_method = parts.method.clone();
+    // only logins, csrf tokens, admin apis, jwks, health
+    // checks and metrics are served in maintenance mode
+    let settings = data.config.get_settings();
+    if settings.maintenance_mode
+        && !RuntimeSettings::is_allowed_during_maintenance(
+            &request_method,
+            request_uri,
+        )
+    {
+        let err_msg = format!(
+            "{{\"status\":503,\"reason\":\"the api is in maintenance mode \
+            - rejected {request_method} {request_uri}\"}}"
+        );
+        warn!("{tracking_label} - {err_msg}");
+        return Ok(Response::builder()
+            .status(503)
+            .header(
+                "Retry-After",
+                format!("{}", settings.maintenance_retry_after_seconds),
+            )
+            .body(Body::from(err_msg))
+            .unwrap());
+    }
+    // reject oversized bodies and unsupported content types
+    // before reading the body in a route handler
+    let body = match data
+        .config
+        .request_body_limits
+        .check_request(&request_method, request_uri, &parts.headers, body)
+        .await
+    {
+        Ok(body) => body,
+        Err((status, reason)) => {
+            let err_msg =
+                format!("{{\"status\":{status},\"reason\":\"{reason}\"}}");
+            warn!("{tracking_label} - {err_msg}");
+            return Ok(Response::builder()
+                .status(status)
+                .body(Body::from(err_msg))
+                .unwrap());
+        }
+    };
+    // cookie-authenticated changes need the csrf header
+    if let Err(reason) = data
+        .config
+        .token_cookie
+        .check_csrf(&request_method, &parts.headers)
+    {
+        let err_msg = format!("{{\"status\":403,\"reason\":\"{reason}\"}}");
+        warn!("{tracking_label} - {err_msg}");
+        return Ok(Response::builder()
+            .status(403)
+            .body(Body::from(err_msg))
+            .unwrap());
+    }
+    // routes added by crates that embed the server
+    if let Some(custom_route) = find_custom_route(
+        &data.config.custom_routes,
+        &request_method,
+        request_uri,
+    ) {
+        let request_uri = request_uri.to_string();
+        let metrics_method = match request_method {
+            Method::GET | Method::HEAD => "get",
+            _ => "post",
+        };
+        record_monitoring_metrics_api_before(
+            &request_uri,
+            "unknown",
+            metrics_method,
+        );
+        processed_result = Ok(custom_route
+            .handle(CoreHttpRequest {
+                config: data.config,
+                db_pool: data.db_pool,
+                db_read_pools: data.db_read_pools,
+                kafka_pool: data.kafka_pool,
+                local_addr: data.local_addr,
+                remote_addr: data.remote_addr,
+                tls_info: data.tls_info,
+                request: Request::from_parts(parts, body),
+                response: data.response,
+            })
+            .await);
+        return record_monitoring_metrics_api_after(
+            &request_uri,
+            "unknown",
+            metrics_method,
+            processed_result,
+        );
+    }
     match (request_method.clone(), request_uri) {
         (Method::POST, "/") => {
             if false {
@@ -113,6 +565,8 @@ pub async fn handle_request(
                 &data.config,
                 &data.db_pool,
                 &data.kafka_pool,
+                &parts.headers,
+                &data.remote_addr,
                 &bytes,
             )
             .await;
@@ -147,6 +601,45 @@ pub async fn handle_request(
             )
         }
         // end user deletion
+        // self-service user deletion with an emailed confirmation token
+        (Method::POST, "/user/delete/request") => {
+            record_monitoring_metrics_api_before(request_uri, "user", "delete");
+            let bytes = body::to_bytes(body).await.unwrap();
+            processed_result = request_user_delete(
+                &tracking_label,
+                &data.config,
+                &data.db_pool,
+                &data.kafka_pool,
+                &parts.headers,
+                &bytes,
+            )
+            .await;
+            record_monitoring_metrics_api_after(
+                request_uri,
+                "user",
+                "delete",
+                processed_result,
+            )
+        }
+        (Method::POST, "/user/delete/confirm") => {
+            record_monitoring_metrics_api_before(request_uri, "user", "delete");
+            let bytes = body::to_bytes(body).await.unwrap();
+            processed_result = confirm_user_delete(
+                &tracking_label,
+                &data.config,
+                &data.db_pool,
+                &data.kafka_pool,
+                &parts.headers,
+                &bytes,
+            )
+            .await;
+            record_monitoring_metrics_api_after(
+                request_uri,
+                "user",
+                "delete",
+                processed_result,
+            )
+        }
         (Method::PUT, "/user") => {
             record_monitoring_metrics_api_before(request_uri, "user", "put");
             let bytes = body::to_bytes(body).await.unwrap();
@@ -166,7 +659,26 @@ pub async fn handle_request(
                 processed_result,
             )
         }
-        // end user deletion
+        (Method::PATCH, "/user") => {
+            record_monitoring_metrics_api_before(request_uri, "user", "patch");
+            let bytes = body::to_bytes(body).await.unwrap();
+            processed_result = patch_user(
+                &tracking_label,
+                &data.config,
+                &data.db_pool,
+                &data.kafka_pool,
+                &parts.headers,
+                &bytes,
+            )
+            .await;
+            record_monitoring_metrics_api_after(
+                request_uri,
+                "user",
+                "patch",
+                processed_result,
+            )
+        }
+        // end user patch
         (Method::POST, "/user/search") => {
             record_monitoring_metrics_api_before(request_uri, "user", "search");
             let bytes = body::to_bytes(body).await.unwrap();
@@ -174,6 +686,7 @@ pub async fn handle_request(
                 &tracking_label,
                 &data.config,
                 &data.db_pool,
+                &data.db_read_pools,
                 &data.kafka_pool,
                 &parts.headers,
                 &bytes,
@@ -209,6 +722,26 @@ pub async fn handle_request(
             )
         }
         // end user data - create
+        (Method::POST, "/user/data/uploads") => {
+            record_monitoring_metrics_api_before(request_uri, "data", "upload");
+            let bytes = body::to_bytes(body).await.unwrap();
+            processed_result = start_resumable_upload(
+                &tracking_label,
+                &data.config,
+                &data.db_pool,
+                &data.kafka_pool,
+                &parts.headers,
+                &bytes,
+            )
+            .await;
+            record_monitoring_metrics_api_after(
+                request_uri,
+                "data",
+                "upload",
+                processed_result,
+            )
+        }
+        // end user data - start resumable upload
         (Method::PUT, "/user/data") => {
             record_monitoring_metrics_api_before(request_uri, "data", "put");
             let bytes = body::to_bytes(body).await.unwrap();
@@ -228,11 +761,10 @@ pub async fn handle_request(
                 processed_result,
             )
         }
-        // end user deletion
-        (Method::POST, "/user/data/search") => {
-            record_monitoring_metrics_api_before(request_uri, "data", "search");
+        (Method::PATCH, "/user/data") => {
+            record_monitoring_metrics_api_before(request_uri, "data", "patch");
             let bytes = body::to_bytes(body).await.unwrap();
-            processed_result = search_user_data(
+            processed_result = patch_user_data(
                 &tracking_label,
                 &data.config,
                 &data.db_pool,
@@ -244,19 +776,35 @@ pub async fn handle_request(
             record_monitoring_metrics_api_after(
                 request_uri,
                 "data",
-                "search",
+                "patch",
                 processed_result,
             )
         }
-        // end user data - search via json containing optional dictionary parameters
-        (Method::POST, "/user/password/reset") => {
-            record_monitoring_metrics_api_before(
+        // end user data - patch
+        (Method::POST, "/user/data/batch") => {
+            record_monitoring_metrics_api_before(request_uri, "data", "put");
+            let bytes = body::to_bytes(body).await.unwrap();
+            processed_result = batch_user_data(
+                &tracking_label,
+                &data.config,
+                &data.db_pool,
+                &data.kafka_pool,
+                &parts.headers,
+                &bytes,
+            )
+            .await;
+            record_monitoring_metrics_api_after(
                 request_uri,
-                "user",
-                "create_otp",
-            );
+                "data",
+                "put",
+                processed_result,
+            )
+        }
+        // end user data - batch
+        (Method::POST, "/user/data/acl") => {
+            record_monitoring_metrics_api_before(request_uri, "data", "post");
             let bytes = body::to_bytes(body).await.unwrap();
-            processed_result = create_otp(
+            processed_result = grant_user_data_access(
                 &tracking_label,
                 &data.config,
                 &data.db_pool,
@@ -267,20 +815,36 @@ pub async fn handle_request(
             .await;
             record_monitoring_metrics_api_after(
                 request_uri,
-                "user",
-                "create_otp",
+                "data",
+                "post",
                 processed_result,
             )
         }
-        // end user password create a one-time-password record
-        (Method::POST, "/user/password/change") => {
-            record_monitoring_metrics_api_before(
+        // end user data - grant access
+        (Method::DELETE, "/user/data/acl") => {
+            record_monitoring_metrics_api_before(request_uri, "data", "delete");
+            let bytes = body::to_bytes(body).await.unwrap();
+            processed_result = revoke_user_data_access(
+                &tracking_label,
+                &data.config,
+                &data.db_pool,
+                &data.kafka_pool,
+                &parts.headers,
+                &bytes,
+            )
+            .await;
+            record_monitoring_metrics_api_after(
                 request_uri,
-                "user",
-                "consume_otp",
-            );
+                "data",
+                "delete",
+                processed_result,
+            )
+        }
+        // end user data - revoke access
+        (Method::POST, "/user/data/shares") => {
+            record_monitoring_metrics_api_before(request_uri, "data", "post");
             let bytes = body::to_bytes(body).await.unwrap();
-            processed_result = consume_user_otp(
+            processed_result = share_user_data(
                 &tracking_label,
                 &data.config,
                 &data.db_pool,
@@ -291,69 +855,1075 @@ pub async fn handle_request(
             .await;
             record_monitoring_metrics_api_after(
                 request_uri,
-                "user",
-                "consume_otp",
+                "data",
+                "post",
                 processed_result,
             )
         }
-        // end user password reset consuming user's one-time-password token
-        (Method::POST, "/login") => {
-            record_monitoring_metrics_api_before(request_uri, "auth", "login");
+        // end user data - share
+        (Method::DELETE, "/user/data/shares") => {
+            record_monitoring_metrics_api_before(request_uri, "data", "delete");
             let bytes = body::to_bytes(body).await.unwrap();
-            processed_result = login_user(
+            processed_result = unshare_user_data(
                 &tracking_label,
                 &data.config,
                 &data.db_pool,
                 &data.kafka_pool,
+                &parts.headers,
                 &bytes,
             )
             .await;
             record_monitoring_metrics_api_after(
                 request_uri,
-                "auth",
-                "login",
+                "data",
+                "delete",
                 processed_result,
             )
         }
-        // end user login
-        (Method::GET, "/metrics") => handle_showing_metrics(),
-        // end metrics
-        (Method::GET, "/favicon.ico") => {
-            let body = Body::from("no favicon.ico".to_string());
-            processed_result = Ok(Response::new(body));
-            processed_result
+        // end user data - unshare
+        (Method::POST, "/user/data/search") => {
+            record_monitoring_metrics_api_before(request_uri, "data", "search");
+            let bytes = body::to_bytes(body).await.unwrap();
+            processed_result = search_user_data(
+                &tracking_label,
+                &data.config,
+                &data.db_pool,
+                &data.db_read_pools,
+                &data.kafka_pool,
+                &parts.headers,
+                &bytes,
+            )
+            .await;
+            record_monitoring_metrics_api_after(
+                request_uri,
+                "data",
+                "search",
+                processed_result,
+            )
         }
-        // end of favicon.ico
-        _ => {
-            if request_method == Method::GET
-                && request_uri.contains("/user/verify")
-            {
-                record_monitoring_metrics_api_before(
-                    request_uri,
-                    "user",
-                    "consume_verify",
-                );
-                let request_query_params = parts.uri.query().unwrap_or("");
-                let full_url = format!(
-                    "https://{}{request_uri}?{request_query_params}",
-                    get_server_address("api")
-                );
-                processed_result = verify_user(
-                    &tracking_label,
-                    &data.config,
-                    &data.db_pool,
-                    &data.kafka_pool,
-                    &full_url,
-                )
-                .await;
-                record_monitoring_metrics_api_after(
-                    request_uri,
+        // end user data - search via json containing optional dictionary parameters
+        (Method::GET, "/user/sessions") => {
+            record_monitoring_metrics_api_before(request_uri, "user", "get");
+            processed_result = get_user_sessions(
+                &tracking_label,
+                &data.config,
+                &data.db_pool,
+                &data.kafka_pool,
+                &parts.headers,
+            )
+            .await;
+            record_monitoring_metrics_api_after(
+                request_uri,
+                "user",
+                "get",
+                processed_result,
+            )
+        }
+        (Method::GET, "/user/logins") => {
+            record_monitoring_metrics_api_before(request_uri, "user", "get");
+            processed_result = get_user_logins(
+                &tracking_label,
+                &data.config,
+                &data.db_pool,
+                &data.kafka_pool,
+                &parts.headers,
+                parts.uri.query().unwrap_or(""),
+            )
+            .await;
+            record_monitoring_metrics_api_after(
+                request_uri,
+                "user",
+                "get",
+                processed_result,
+            )
+        }
+        (Method::GET, "/user/quota") => {
+            record_monitoring_metrics_api_before(request_uri, "user", "get");
+            processed_result = get_user_quota(
+                &tracking_label,
+                &data.config,
+                &data.db_pool,
+                &data.kafka_pool,
+                &parts.headers,
+            )
+            .await;
+            record_monitoring_metrics_api_after(
+                request_uri,
+                "user",
+                "get",
+                processed_result,
+            )
+        }
+        (Method::POST, "/user/data/s3/events") => {
+            record_monitoring_metrics_api_before(request_uri, "data", "post");
+            let bytes = body::to_bytes(body).await.unwrap();
+            processed_result = complete_s3_event_uploads(
+                &tracking_label,
+                &data.config,
+                &data.db_pool,
+                &data.kafka_pool,
+                &parts.headers,
+                &bytes,
+            )
+            .await;
+            record_monitoring_metrics_api_after(
+                request_uri,
+                "data",
+                "post",
+                processed_result,
+            )
+        }
+        (Method::GET, "/user/data/s3/list") => {
+            record_monitoring_metrics_api_before(request_uri, "data", "get");
+            processed_result = list_user_data_s3_objects(
+                &tracking_label,
+                &data.config,
+                &data.db_pool,
+                &data.kafka_pool,
+                &parts.headers,
+                parts.uri.query().unwrap_or(""),
+            )
+            .await;
+            record_monitoring_metrics_api_after(
+                request_uri,
+                "data",
+                "get",
+                processed_result,
+            )
+        }
+        (Method::GET, "/user/notifications/stream") => {
+            record_monitoring_metrics_api_before(request_uri, "user", "get");
+            processed_result = stream_user_notifications(
+                &tracking_label,
+                &data.config,
+                &data.db_pool,
+                &parts.headers,
+            )
+            .await;
+            record_monitoring_metrics_api_after(
+                request_uri,
+                "user",
+                "get",
+                processed_result,
+            )
+        }
+        (Method::POST, "/user/password/reset") => {
+            record_monitoring_metrics_api_before(
+                request_uri,
+                "user",
+                "create_otp",
+            );
+            let bytes = body::to_bytes(body).await.unwrap();
+            processed_result = create_otp(
+                &tracking_label,
+                &data.config,
+                &data.db_pool,
+                &data.kafka_pool,
+                &parts.headers,
+                &bytes,
+            )
+            .await;
+            record_monitoring_metrics_api_after(
+                request_uri,
+                "user",
+                "create_otp",
+                processed_result,
+            )
+        }
+        // unauthenticated user password create a one-time-password record
+        (Method::POST, "/user/password/forgot") => {
+            record_monitoring_metrics_api_before(
+                request_uri,
+                "user",
+                "create_otp",
+            );
+            let bytes = body::to_bytes(body).await.unwrap();
+            processed_result = forgot_password(
+                &tracking_label,
+                &data.config,
+                &data.db_pool,
+                &data.kafka_pool,
+                &parts.headers,
+                &bytes,
+            )
+            .await;
+            record_monitoring_metrics_api_after(
+                request_uri,
+                "user",
+                "create_otp",
+                processed_result,
+            )
+        }
+        // end user password create a one-time-password record
+        (Method::POST, "/user/password/change") => {
+            record_monitoring_metrics_api_before(
+                request_uri,
+                "user",
+                "consume_otp",
+            );
+            let bytes = body::to_bytes(body).await.unwrap();
+            processed_result = consume_user_otp(
+                &tracking_label,
+                &data.config,
+                &data.db_pool,
+                &data.kafka_pool,
+                &parts.headers,
+                &bytes,
+            )
+            .await;
+            record_monitoring_metrics_api_after(
+                request_uri,
+                "user",
+                "consume_otp",
+                processed_result,
+            )
+        }
+        // end user password reset consuming user's one-time-password token
+        (Method::POST, "/login") => {
+            record_monitoring_metrics_api_before(request_uri, "auth", "login");
+            let bytes = body::to_bytes(body).await.unwrap();
+            processed_result = login_user(
+                &tracking_label,
+                &data.config,
+                &data.db_pool,
+                &data.kafka_pool,
+                &parts.headers,
+                &data.remote_addr,
+                &bytes,
+            )
+            .await;
+            record_monitoring_metrics_api_after(
+                request_uri,
+                "auth",
+                "login",
+                processed_result,
+            )
+        }
+        // end user login
+        (Method::POST, "/admin/login/unlock") => {
+            record_monitoring_metrics_api_before(
+                request_uri,
+                "admin",
+                "unlock",
+            );
+            let bytes = body::to_bytes(body).await.unwrap();
+            processed_result = unlock_login(
+                &tracking_label,
+                &data.config,
+                &data.db_pool,
+                &data.kafka_pool,
+                &parts.headers,
+                &bytes,
+            )
+            .await;
+            record_monitoring_metrics_api_after(
+                request_uri,
+                "admin",
+                "unlock",
+                processed_result,
+            )
+        }
+        // end admin unlock login
+        (Method::POST, "/admin/users/sessions/revoke") => {
+            record_monitoring_metrics_api_before(request_uri, "admin", "put");
+            let bytes = body::to_bytes(body).await.unwrap();
+            processed_result = revoke_user_sessions(
+                &tracking_label,
+                &data.config,
+                &data.db_pool,
+                &data.kafka_pool,
+                &parts.headers,
+                &bytes,
+            )
+            .await;
+            record_monitoring_metrics_api_after(
+                request_uri,
+                "admin",
+                "put",
+                processed_result,
+            )
+        }
+        // end admin revoke user sessions
+        (Method::POST, "/admin/users/password/reset") => {
+            record_monitoring_metrics_api_before(request_uri, "admin", "put");
+            let bytes = body::to_bytes(body).await.unwrap();
+            processed_result = force_password_reset(
+                &tracking_label,
+                &data.config,
+                &data.db_pool,
+                &data.kafka_pool,
+                &parts.headers,
+                &bytes,
+            )
+            .await;
+            record_monitoring_metrics_api_after(
+                request_uri,
+                "admin",
+                "put",
+                processed_result,
+            )
+        }
+        // end admin force password reset
+        (Method::GET, "/admin/settings") => {
+            record_monitoring_metrics_api_before(request_uri, "admin", "get");
+            processed_result = get_admin_settings(
+                &tracking_label,
+                &data.config,
+                &data.db_pool,
+                &data.kafka_pool,
+                &parts.headers,
+            )
+            .await;
+            record_monitoring_metrics_api_after(
+                request_uri,
+                "admin",
+                "get",
+                processed_result,
+            )
+        }
+        // end admin get settings
+        (Method::PUT, "/admin/settings") => {
+            record_monitoring_metrics_api_before(request_uri, "admin", "put");
+            let bytes = body::to_bytes(body).await.unwrap();
+            processed_result = update_admin_settings(
+                &tracking_label,
+                &data.config,
+                &data.db_pool,
+                &data.kafka_pool,
+                &parts.headers,
+                &bytes,
+            )
+            .await;
+            record_monitoring_metrics_api_after(
+                request_uri,
+                "admin",
+                "put",
+                processed_result,
+            )
+        }
+        // end admin update settings
+        (Method::POST, "/admin/users/invite") => {
+            record_monitoring_metrics_api_before(
+                request_uri,
+                "admin",
+                "invite",
+            );
+            let bytes = body::to_bytes(body).await.unwrap();
+            processed_result = invite_user(
+                &tracking_label,
+                &data.config,
+                &data.db_pool,
+                &data.kafka_pool,
+                &parts.headers,
+                &bytes,
+            )
+            .await;
+            record_monitoring_metrics_api_after(
+                request_uri,
+                "admin",
+                "invite",
+                processed_result,
+            )
+        }
+        // end admin invite user
+        (Method::GET, "/admin/jwt/keys") => {
+            record_monitoring_metrics_api_before(request_uri, "admin", "keys");
+            processed_result = get_admin_jwt_keys(
+                &tracking_label,
+                &data.config,
+                &data.db_pool,
+                &data.kafka_pool,
+                &parts.headers,
+            )
+            .await;
+            record_monitoring_metrics_api_after(
+                request_uri,
+                "admin",
+                "keys",
+                processed_result,
+            )
+        }
+        // end admin get jwt keys
+        (Method::GET, "/admin/passwords/schemes") => {
+            record_monitoring_metrics_api_before(request_uri, "admin", "get");
+            processed_result = get_admin_password_schemes(
+                &tracking_label,
+                &data.config,
+                &data.db_pool,
+                &data.kafka_pool,
+                &parts.headers,
+            )
+            .await;
+            record_monitoring_metrics_api_after(
+                request_uri,
+                "admin",
+                "get",
+                processed_result,
+            )
+        }
+        // end admin get password schemes
+        (Method::POST, "/admin/jwt/keys/retire") => {
+            record_monitoring_metrics_api_before(
+                request_uri,
+                "admin",
+                "retire",
+            );
+            let bytes = body::to_bytes(body).await.unwrap();
+            processed_result = retire_jwt_key(
+                &tracking_label,
+                &data.config,
+                &data.db_pool,
+                &data.kafka_pool,
+                &parts.headers,
+                &bytes,
+            )
+            .await;
+            record_monitoring_metrics_api_after(
+                request_uri,
+                "admin",
+                "retire",
+                processed_result,
+            )
+        }
+        // end admin retire jwt key
+        (Method::POST, "/admin/webhooks") => {
+            record_monitoring_metrics_api_before(
+                request_uri,
+                "admin",
+                "webhooks",
+            );
+            let bytes = body::to_bytes(body).await.unwrap();
+            processed_result = create_webhook(
+                &tracking_label,
+                &data.config,
+                &data.db_pool,
+                &data.kafka_pool,
+                &parts.headers,
+                &bytes,
+            )
+            .await;
+            record_monitoring_metrics_api_after(
+                request_uri,
+                "admin",
+                "webhooks",
+                processed_result,
+            )
+        }
+        // end admin create webhook
+        (Method::GET, "/admin/webhooks") => {
+            record_monitoring_metrics_api_before(
+                request_uri,
+                "admin",
+                "webhooks",
+            );
+            processed_result = get_webhooks(
+                &tracking_label,
+                &data.config,
+                &data.db_pool,
+                &data.kafka_pool,
+                &parts.headers,
+            )
+            .await;
+            record_monitoring_metrics_api_after(
+                request_uri,
+                "admin",
+                "webhooks",
+                processed_result,
+            )
+        }
+        // end admin get webhooks
+        (Method::DELETE, "/admin/webhooks") => {
+            record_monitoring_metrics_api_before(
+                request_uri,
+                "admin",
+                "webhooks",
+            );
+            let bytes = body::to_bytes(body).await.unwrap();
+            processed_result = delete_webhook(
+                &tracking_label,
+                &data.config,
+                &data.db_pool,
+                &data.kafka_pool,
+                &parts.headers,
+                &bytes,
+            )
+            .await;
+            record_monitoring_metrics_api_after(
+                request_uri,
+                "admin",
+                "webhooks",
+                processed_result,
+            )
+        }
+        // end admin delete webhook
+        (Method::POST, "/admin/ip-rules") => {
+            record_monitoring_metrics_api_before(request_uri, "admin", "put");
+            let bytes = body::to_bytes(body).await.unwrap();
+            processed_result = create_ip_rule(
+                &tracking_label,
+                &data.config,
+                &data.db_pool,
+                &data.kafka_pool,
+                &parts.headers,
+                &data.remote_addr,
+                &bytes,
+            )
+            .await;
+            record_monitoring_metrics_api_after(
+                request_uri,
+                "admin",
+                "put",
+                processed_result,
+            )
+        }
+        // end admin create ip rule
+        (Method::GET, "/admin/ip-rules") => {
+            record_monitoring_metrics_api_before(request_uri, "admin", "get");
+            processed_result = get_ip_rules(
+                &tracking_label,
+                &data.config,
+                &data.db_pool,
+                &data.kafka_pool,
+                &parts.headers,
+                &data.remote_addr,
+            )
+            .await;
+            record_monitoring_metrics_api_after(
+                request_uri,
+                "admin",
+                "get",
+                processed_result,
+            )
+        }
+        // end admin get ip rules
+        (Method::DELETE, "/admin/ip-rules") => {
+            record_monitoring_metrics_api_before(request_uri, "admin", "put");
+            let bytes = body::to_bytes(body).await.unwrap();
+            processed_result = delete_ip_rule(
+                &tracking_label,
+                &data.config,
+                &data.db_pool,
+                &data.kafka_pool,
+                &parts.headers,
+                &data.remote_addr,
+                &bytes,
+            )
+            .await;
+            record_monitoring_metrics_api_after(
+                request_uri,
+                "admin",
+                "put",
+                processed_result,
+            )
+        }
+        // end admin delete ip rule
+        (Method::POST, "/admin/webhooks/deliveries") => {
+            record_monitoring_metrics_api_before(
+                request_uri,
+                "admin",
+                "webhooks",
+            );
+            let bytes = body::to_bytes(body).await.unwrap();
+            processed_result = search_webhook_deliveries(
+                &tracking_label,
+                &data.config,
+                &data.db_pool,
+                &data.kafka_pool,
+                &parts.headers,
+                &bytes,
+            )
+            .await;
+            record_monitoring_metrics_api_after(
+                request_uri,
+                "admin",
+                "webhooks",
+                processed_result,
+            )
+        }
+        // end admin search webhook deliveries
+        (Method::POST, "/admin/kafka/dead-letters") => {
+            record_monitoring_metrics_api_before(request_uri, "admin", "kafka");
+            let bytes = body::to_bytes(body).await.unwrap();
+            processed_result = search_kafka_dead_letters(
+                &tracking_label,
+                &data.config,
+                &data.db_pool,
+                &data.kafka_pool,
+                &parts.headers,
+                &bytes,
+            )
+            .await;
+            record_monitoring_metrics_api_after(
+                request_uri,
+                "admin",
+                "kafka",
+                processed_result,
+            )
+        }
+        // end admin search kafka dead letters
+        (Method::POST, "/admin/kafka/dead-letters/requeue") => {
+            record_monitoring_metrics_api_before(request_uri, "admin", "kafka");
+            let bytes = body::to_bytes(body).await.unwrap();
+            processed_result = requeue_kafka_dead_letters(
+                &tracking_label,
+                &data.config,
+                &data.db_pool,
+                &data.kafka_pool,
+                &parts.headers,
+                &bytes,
+            )
+            .await;
+            record_monitoring_metrics_api_after(
+                request_uri,
+                "admin",
+                "kafka",
+                processed_result,
+            )
+        }
+        // end admin requeue kafka dead letters
+        (Method::GET, "/admin/stats") => {
+            record_monitoring_metrics_api_before(request_uri, "admin", "stats");
+            processed_result = get_admin_stats(
+                &tracking_label,
+                &data.config,
+                &data.db_pool,
+                &data.kafka_pool,
+                &parts.headers,
+            )
+            .await;
+            record_monitoring_metrics_api_after(
+                request_uri,
+                "admin",
+                "stats",
+                processed_result,
+            )
+        }
+        // end admin get stats
+        (Method::POST, "/user/invite/accept") => {
+            record_monitoring_metrics_api_before(request_uri, "user", "invite");
+            let bytes = body::to_bytes(body).await.unwrap();
+            processed_result = accept_user_invite(
+                &tracking_label,
+                &data.config,
+                &data.db_pool,
+                &data.kafka_pool,
+                &parts.headers,
+                &data.remote_addr,
+                &bytes,
+            )
+            .await;
+            record_monitoring_metrics_api_after(
+                request_uri,
+                "user",
+                "invite",
+                processed_result,
+            )
+        }
+        // end user accept invite
+        (Method::POST, "/user/passkey/register/start") => {
+            record_monitoring_metrics_api_before(
+                request_uri,
+                "auth",
+                "passkey",
+            );
+            let bytes = body::to_bytes(body).await.unwrap();
+            processed_result = start_passkey_registration(
+                &tracking_label,
+                &data.config,
+                &data.db_pool,
+                &data.kafka_pool,
+                &parts.headers,
+                &bytes,
+            )
+            .await;
+            record_monitoring_metrics_api_after(
+                request_uri,
+                "auth",
+                "passkey",
+                processed_result,
+            )
+        }
+        (Method::POST, "/user/passkey/register/finish") => {
+            record_monitoring_metrics_api_before(
+                request_uri,
+                "auth",
+                "passkey",
+            );
+            let bytes = body::to_bytes(body).await.unwrap();
+            processed_result = finish_passkey_registration(
+                &tracking_label,
+                &data.config,
+                &data.db_pool,
+                &data.kafka_pool,
+                &parts.headers,
+                &bytes,
+            )
+            .await;
+            record_monitoring_metrics_api_after(
+                request_uri,
+                "auth",
+                "passkey",
+                processed_result,
+            )
+        }
+        // end passkey registration
+        (Method::POST, "/login/passkey/start") => {
+            record_monitoring_metrics_api_before(
+                request_uri,
+                "auth",
+                "passkey",
+            );
+            let bytes = body::to_bytes(body).await.unwrap();
+            processed_result = start_passkey_login(
+                &tracking_label,
+                &data.config,
+                &data.db_pool,
+                &data.kafka_pool,
+                &parts.headers,
+                &bytes,
+            )
+            .await;
+            record_monitoring_metrics_api_after(
+                request_uri,
+                "auth",
+                "passkey",
+                processed_result,
+            )
+        }
+        (Method::POST, "/login/passkey/finish") => {
+            record_monitoring_metrics_api_before(
+                request_uri,
+                "auth",
+                "passkey",
+            );
+            let bytes = body::to_bytes(body).await.unwrap();
+            processed_result = finish_passkey_login(
+                &tracking_label,
+                &data.config,
+                &data.db_pool,
+                &data.kafka_pool,
+                &parts.headers,
+                &data.remote_addr,
+                &bytes,
+            )
+            .await;
+            record_monitoring_metrics_api_after(
+                request_uri,
+                "auth",
+                "passkey",
+                processed_result,
+            )
+        }
+        // end passkey login
+        (Method::GET, "/.well-known/jwks.json") => {
+            record_monitoring_metrics_api_before(request_uri, "auth", "get");
+            processed_result = get_jwks(&tracking_label, &data.config).await;
+            record_monitoring_metrics_api_after(
+                request_uri,
+                "auth",
+                "get",
+                processed_result,
+            )
+        }
+        // end jwks
+        (Method::GET, "/csrf") => {
+            record_monitoring_metrics_api_before(request_uri, "auth", "get");
+            processed_result =
+                get_csrf_token(&tracking_label, &data.config, &parts.headers)
+                    .await;
+            record_monitoring_metrics_api_after(
+                request_uri,
+                "auth",
+                "get",
+                processed_result,
+            )
+        }
+        // end csrf
+        (Method::GET, "/openapi/events.json") => {
+            record_monitoring_metrics_api_before(request_uri, "events", "get");
+            processed_result = get_events_openapi(&data.config).await;
+            record_monitoring_metrics_api_after(
+                request_uri,
+                "events",
+                "get",
+                processed_result,
+            )
+        }
+        // end events openapi
+        #[cfg(feature = "graphql")]
+        (Method::POST, "/graphql") => {
+            record_monitoring_metrics_api_before(request_uri, "user", "post");
+            let bytes = body::to_bytes(body).await.unwrap();
+            processed_result = handle_graphql(
+                &tracking_label,
+                &data.config,
+                &data.db_pool,
+                &data.db_read_pools,
+                &data.kafka_pool,
+                &parts.headers,
+                &bytes,
+            )
+            .await;
+            record_monitoring_metrics_api_after(
+                request_uri,
+                "user",
+                "post",
+                processed_result,
+            )
+        }
+        #[cfg(feature = "graphql")]
+        (Method::GET, "/graphql") => {
+            record_monitoring_metrics_api_before(request_uri, "user", "get");
+            processed_result = get_graphql_schema().await;
+            record_monitoring_metrics_api_after(
+                request_uri,
+                "user",
+                "get",
+                processed_result,
+            )
+        }
+        // end graphql
+        #[cfg(feature = "metrics")]
+        (Method::GET, "/metrics") => {
+            let accept = parts
+                .headers
+                .get("Accept")
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or("");
+            match is_openmetrics_accepted(accept) {
+                true => handle_showing_openmetrics(),
+                false => handle_showing_metrics(),
+            }
+        }
+        // end metrics
+        (Method::GET, "/health") => handle_health(&data.config),
+        // end health
+        (Method::GET, "/favicon.ico") => {
+            if data.config.static_assets.is_enabled() {
+                processed_result =
+                    Ok(data.config.static_assets.serve_static_asset(
+                        &tracking_label,
+                        &request_method,
+                        &parts.headers,
+                        request_uri,
+                    ));
+            } else {
+                let body = Body::from("no favicon.ico".to_string());
+                processed_result = Ok(Response::new(body));
+            }
+            processed_result
+        }
+        // end of favicon.ico
+        _ => {
+            if request_method == Method::GET
+                && request_uri.contains("/user/verify")
+            {
+                record_monitoring_metrics_api_before(
+                    request_uri,
+                    "user",
+                    "consume_verify",
+                );
+                let request_query_params = parts.uri.query().unwrap_or("");
+                let full_url = format!(
+                    "https://{}{request_uri}?{request_query_params}",
+                    get_server_address("api")
+                );
+                processed_result = verify_user(
+                    &tracking_label,
+                    &data.config,
+                    &data.db_pool,
+                    &data.kafka_pool,
+                    &full_url,
+                )
+                .await;
+                record_monitoring_metrics_api_after(
+                    request_uri,
                     "user",
                     "consume_verify",
                     processed_result,
                 )
             }
             // end user verification
+            else if request_method == Method::DELETE
+                && request_uri.starts_with("/user/sessions/")
+            {
+                record_monitoring_metrics_api_before(
+                    request_uri,
+                    "user",
+                    "delete",
+                );
+                processed_result = revoke_user_session(
+                    &tracking_label,
+                    &data.config,
+                    &data.db_pool,
+                    &data.kafka_pool,
+                    &parts.headers,
+                    request_uri,
+                )
+                .await;
+                record_monitoring_metrics_api_after(
+                    request_uri,
+                    "user",
+                    "delete",
+                    processed_result,
+                )
+            }
+            // end user session revoke
+            else if request_method == Method::PUT
+                && request_uri.starts_with("/user/data/")
+                && request_uri.ends_with("/chunks")
+            {
+                record_monitoring_metrics_api_before(
+                    request_uri,
+                    "data",
+                    "put",
+                );
+                processed_result = upload_user_data_chunk(
+                    &tracking_label,
+                    &data.config,
+                    &data.db_pool,
+                    &data.kafka_pool,
+                    &parts.headers,
+                    request_uri,
+                    body,
+                )
+                .await;
+                record_monitoring_metrics_api_after(
+                    request_uri,
+                    "data",
+                    "put",
+                    processed_result,
+                )
+            }
+            // end user data - upload resumable upload chunk
+            else if request_method == Method::GET
+                && request_uri.starts_with("/user/data/")
+                && request_uri.ends_with("/chunks")
+            {
+                record_monitoring_metrics_api_before(
+                    request_uri,
+                    "data",
+                    "get",
+                );
+                processed_result = get_resumable_upload(
+                    &tracking_label,
+                    &data.config,
+                    &data.db_pool,
+                    &data.kafka_pool,
+                    &parts.headers,
+                    request_uri,
+                )
+                .await;
+                record_monitoring_metrics_api_after(
+                    request_uri,
+                    "data",
+                    "get",
+                    processed_result,
+                )
+            }
+            // end user data - get resumable upload
+            else if request_method == Method::POST
+                && request_uri.starts_with("/user/data/")
+                && request_uri.ends_with("/complete")
+            {
+                record_monitoring_metrics_api_before(
+                    request_uri,
+                    "data",
+                    "post",
+                );
+                processed_result = complete_resumable_upload(
+                    &tracking_label,
+                    &data.config,
+                    &data.db_pool,
+                    &data.kafka_pool,
+                    &parts.headers,
+                    request_uri,
+                )
+                .await;
+                record_monitoring_metrics_api_after(
+                    request_uri,
+                    "data",
+                    "post",
+                    processed_result,
+                )
+            }
+            // end user data - complete resumable upload
+            else if request_method == Method::GET
+                && request_uri.starts_with("/user/data/shared/")
+            {
+                record_monitoring_metrics_api_before(
+                    request_uri,
+                    "data",
+                    "get",
+                );
+                processed_result = download_shared_user_data(
+                    &tracking_label,
+                    &data.config,
+                    &data.db_pool,
+                    &data.kafka_pool,
+                    request_uri,
+                )
+                .await;
+                record_monitoring_metrics_api_after(
+                    request_uri,
+                    "data",
+                    "get",
+                    processed_result,
+                )
+            }
+            // end user data - download with a public link
+            else if request_method == Method::GET
+                && request_uri.starts_with("/user/data/")
+                && request_uri.ends_with("/download")
+            {
+                record_monitoring_metrics_api_before(
+                    request_uri,
+                    "data",
+                    "get",
+                );
+                processed_result = download_user_data(
+                    &tracking_label,
+                    &data.config,
+                    &data.db_pool,
+                    &data.kafka_pool,
+                    &parts.headers,
+                    request_uri,
+                )
+                .await;
+                record_monitoring_metrics_api_after(
+                    request_uri,
+                    "data",
+                    "get",
+                    processed_result,
+                )
+            }
+            // end user data - download
+            else if request_method == Method::GET
+                && request_uri.starts_with("/user/data/")
+                && request_uri.ends_with("/access")
+            {
+                record_monitoring_metrics_api_before(
+                    request_uri,
+                    "data",
+                    "get",
+                );
+                processed_result = get_user_data_access(
+                    &tracking_label,
+                    &data.config,
+                    &data.db_pool,
+                    &data.kafka_pool,
+                    &parts.headers,
+                    request_uri,
+                )
+                .await;
+                record_monitoring_metrics_api_after(
+                    request_uri,
+                    "data",
+                    "get",
+                    processed_result,
+                )
+            }
+            // end user data - get download stats
             else if request_method == Method::GET
                 && request_uri.contains("/user/")
             {
@@ -366,6 +1936,7 @@ pub async fn handle_request(
                     &tracking_label,
                     &data.config,
                     &data.db_pool,
+                    &data.db_read_pools,
                     &data.kafka_pool,
                     &parts.headers,
                     request_uri,
@@ -379,6 +1950,30 @@ pub async fn handle_request(
                 )
             }
             // end user get
+            else if (request_method == Method::GET
+                || request_method == Method::HEAD)
+                && data.config.static_assets.is_enabled()
+            {
+                record_monitoring_metrics_api_before(
+                    request_uri,
+                    "unknown",
+                    "get",
+                );
+                processed_result =
+                    Ok(data.config.static_assets.serve_static_asset(
+                        &tracking_label,
+                        &request_method,
+                        &parts.headers,
+                        request_uri,
+                    ));
+                record_monitoring_metrics_api_after(
+                    request_uri,
+                    "unknown",
+                    "get",
+                    processed_result,
+                )
+            }
+            // end static assets
             else {
                 record_monitoring_metrics_api_before(
                     request_uri,
@@ -395,7 +1990,11 @@ pub async fn handle_request(
                     format!("{{\"status\":400,\"reason\":\"{}\"}}", reason);
                 error!("{}", err_msg);
                 let body = Body::from(err_msg);
-                processed_result = Ok(Response::new(body));
+                let mut response = Response::new(body);
+                response
+                    .extensions_mut()
+                    .insert(UnsupportedRoute { reason });
+                processed_result = Ok(response);
                 record_monitoring_metrics_api_after(
                     request_uri,
                     "unknown",
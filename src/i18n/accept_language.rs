@@ -0,0 +1,64 @@
+//! Parse the ``Accept-Language`` request header
+//!
+use hyper::header::HeaderValue;
+use hyper::HeaderMap;
+
+use crate::notifications::email_templates::normalize_locale;
+use crate::requests::validation::field_rules::is_valid_locale;
+
+/// max ``Accept-Language`` entries that are checked
+pub const MAX_ACCEPT_LANGUAGES: usize = 10;
+
+/// get_accept_languages
+///
+/// Get the locales in an ``Accept-Language`` header
+/// ordered by their quality (``q``) value. Wildcards,
+/// invalid locales and locales with ``q=0`` are skipped.
+///
+/// # Arguments
+///
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+///
+/// # Returns
+///
+/// `Vec<String>` - normalized locales (like ``pt-BR``)
+///
+/// # Examples
+///
+/// ```rust
+/// use hyper::HeaderMap;
+/// use restapi::i18n::accept_language::get_accept_languages;
+/// let mut headers = HeaderMap::new();
+/// headers.insert(
+///     "Accept-Language",
+///     "en;q=0.5, es-mx, fr;q=0, *;q=0.1".parse().unwrap(),
+/// );
+/// assert_eq!(get_accept_languages(&headers), vec!["es-MX", "en"]);
+/// ```
+///
+pub fn get_accept_languages(headers: &HeaderMap<HeaderValue>) -> Vec<String> {
+    let header =
+        match headers.get("Accept-Language").and_then(|v| v.to_str().ok()) {
+            Some(header) => header,
+            None => return Vec::new(),
+        };
+    let mut languages: Vec<(f32, String)> = header
+        .split(',')
+        .take(MAX_ACCEPT_LANGUAGES)
+        .filter_map(|entry| {
+            let mut parts = entry.split(';');
+            let locale = normalize_locale(parts.next().unwrap_or_default());
+            let quality = parts
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .filter_map(|q| q.trim().parse::<f32>().ok())
+                .next()
+                .unwrap_or(1.0);
+            (quality > 0.0 && is_valid_locale(&locale))
+                .then_some((quality, locale))
+        })
+        .collect();
+    // stable, so equal qualities keep the client's order
+    languages.sort_by(|a, b| b.0.total_cmp(&a.0));
+    languages.into_iter().map(|(_, locale)| locale).collect()
+}
@@ -0,0 +1,316 @@
+//! Pick each response's locale and translate its json
+//! ``msg`` fields
+//!
+//! The locale is the first supported locale (or its
+//! language) in the request's ``Accept-Language`` header,
+//! then the authenticated user's ``users.locale``, then
+//! ``MESSAGE_DEFAULT_LOCALE``. Handlers mark the
+//! authenticated user's locale with
+//! [`set_response_user_locale`](crate::i18n::message_localization::set_response_user_locale).
+//!
+//! Only json responses with a known length up to
+//! [`MAX_LOCALIZED_BODY_BYTES`](crate::i18n::message_localization::MAX_LOCALIZED_BODY_BYTES)
+//! are translated, so file downloads and server-sent event
+//! streams are never buffered. The ``success`` message is
+//! not in the built-in catalog because clients compare it.
+//!
+use std::future::Future;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use hyper::body::HttpBody;
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Response;
+
+use crate::i18n::accept_language::get_accept_languages;
+use crate::i18n::translation_provider::MessageCatalog;
+use crate::i18n::translation_provider::TranslationProvider;
+use crate::i18n::translation_provider::SOURCE_MESSAGE_LOCALE;
+use crate::notifications::email_templates::normalize_locale;
+use crate::requests::validation::field_rules::is_valid_locale;
+
+/// max response body bytes that are parsed for messages
+pub const MAX_LOCALIZED_BODY_BYTES: u64 = 1024 * 1024;
+
+tokio::task_local! {
+    /// authenticated user's ``users.locale`` for the request
+    /// being localized (empty until a handler validates a
+    /// token)
+    static RESPONSE_USER_LOCALE: Arc<Mutex<String>>;
+}
+
+/// MessageLocalization
+///
+/// Settings and translation provider for localizing the
+/// response messages
+///
+/// # Supported Environment Variables
+///
+/// ```bash
+/// export MESSAGE_LOCALIZATION_ENABLED="0"
+/// # directory with {locale}.json catalogs
+/// # (empty = only the built-in catalogs)
+/// export MESSAGE_CATALOG_DIR=""
+/// # locale for requests without a supported
+/// # Accept-Language or users.locale
+/// export MESSAGE_DEFAULT_LOCALE="en"
+/// ```
+///
+/// # Arguments
+///
+/// * `enabled` - `bool` - translate response messages
+/// * `dir` - `String` - catalog directory
+/// * `default_locale` - `String` - fallback locale
+/// * `provider` - `Arc<dyn`[`TranslationProvider`](crate::i18n::translation_provider::TranslationProvider)`>` -
+///   translates the messages (a
+///   [`MessageCatalog`](crate::i18n::translation_provider::MessageCatalog)
+///   by default)
+///
+#[derive(Clone)]
+pub struct MessageLocalization {
+    pub enabled: bool,
+    pub dir: String,
+    pub default_locale: String,
+    pub provider: Arc<dyn TranslationProvider>,
+}
+
+impl MessageLocalization {
+    /// build_message_localization
+    ///
+    /// Build a
+    /// [`MessageLocalization`](crate::i18n::message_localization::MessageLocalization)
+    /// from environment variables and load the message
+    /// catalogs
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) for an invalid
+    /// ``MESSAGE_DEFAULT_LOCALE`` or message catalog
+    ///
+    pub fn build_message_localization() -> Result<Self, String> {
+        let enabled = std::env::var("MESSAGE_LOCALIZATION_ENABLED")
+            .unwrap_or_else(|_| "0".to_string());
+        let dir = std::env::var("MESSAGE_CATALOG_DIR")
+            .unwrap_or_default()
+            .trim()
+            .to_string();
+        let default_locale = normalize_locale(
+            &std::env::var("MESSAGE_DEFAULT_LOCALE")
+                .unwrap_or_else(|_| SOURCE_MESSAGE_LOCALE.to_string()),
+        );
+        if !is_valid_locale(&default_locale) {
+            return Err(format!(
+                "invalid MESSAGE_DEFAULT_LOCALE={default_locale} \
+                must be a locale like en or pt-BR"
+            ));
+        }
+        let provider = Arc::new(MessageCatalog::build_message_catalog(&dir)?);
+        Ok(MessageLocalization {
+            enabled: enabled == "1" || enabled == "true",
+            dir,
+            default_locale,
+            provider,
+        })
+    }
+
+    /// get_locale
+    ///
+    /// Get the first supported locale from the
+    /// ``Accept-Language`` locales, the user's locale and
+    /// the ``default_locale`` (each locale is tried before
+    /// its language)
+    ///
+    /// # Arguments
+    ///
+    /// * `accept_languages` - `&[String]` - ``Accept-Language``
+    ///   locales (see
+    ///   [`get_accept_languages`](crate::i18n::accept_language::get_accept_languages))
+    /// * `user_locale` - `&str` - authenticated user's
+    ///   ``users.locale`` (empty = unknown)
+    ///
+    /// # Returns
+    ///
+    /// `String` - ``en`` when no translated locale matches
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use restapi::i18n::message_localization::MessageLocalization;
+    /// let localization = MessageLocalization::build_message_localization().unwrap();
+    /// assert_eq!(localization.get_locale(&["es-MX".to_string()], "en"), "es");
+    /// assert_eq!(localization.get_locale(&["en-US".to_string()], "es"), "en");
+    /// assert_eq!(localization.get_locale(&[], "es"), "es");
+    /// assert_eq!(localization.get_locale(&["fr".to_string()], ""), "en");
+    /// ```
+    ///
+    pub fn get_locale(
+        &self,
+        accept_languages: &[String],
+        user_locale: &str,
+    ) -> String {
+        let locales = self.provider.get_locales();
+        accept_languages
+            .iter()
+            .map(|locale| locale.as_str())
+            .chain([user_locale, self.default_locale.as_str()])
+            .filter(|locale| !locale.is_empty())
+            .flat_map(|locale| {
+                let language = locale.split('-').next().unwrap_or_default();
+                [locale.to_string(), language.to_string()]
+            })
+            .find(|locale| {
+                locale == SOURCE_MESSAGE_LOCALE || locales.contains(locale)
+            })
+            .unwrap_or_else(|| SOURCE_MESSAGE_LOCALE.to_string())
+    }
+
+    /// localize_request
+    ///
+    /// Serve a request with the user's locale tracked for
+    /// [`set_response_user_locale`](crate::i18n::message_localization::set_response_user_locale)
+    /// and translate the response's messages
+    ///
+    /// # Arguments
+    ///
+    /// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+    ///   request headers with the ``Accept-Language``
+    /// * `serve_request` - future that builds the response
+    ///
+    pub async fn localize_request<F, E>(
+        &self,
+        headers: &HeaderMap<HeaderValue>,
+        serve_request: F,
+    ) -> Result<Response<Body>, E>
+    where
+        F: Future<Output = Result<Response<Body>, E>>,
+    {
+        if !self.enabled {
+            return serve_request.await;
+        }
+        let accept_languages = get_accept_languages(headers);
+        let user_locale = Arc::new(Mutex::new(String::new()));
+        let response = RESPONSE_USER_LOCALE
+            .scope(user_locale.clone(), serve_request)
+            .await?;
+        let user_locale = user_locale.lock().unwrap().clone();
+        let locale = self.get_locale(&accept_languages, &user_locale);
+        Ok(self.localize_response(&locale, response).await)
+    }
+
+    /// localize_response
+    ///
+    /// Translate the ``msg`` and ``errors[].msg`` values in a
+    /// json response and set the ``Content-Language`` and
+    /// ``Vary`` headers
+    ///
+    /// # Arguments
+    ///
+    /// * `locale` - `&str` - response locale
+    /// * `response` - [`Response`](hyper::Response)
+    ///
+    pub async fn localize_response(
+        &self,
+        locale: &str,
+        response: Response<Body>,
+    ) -> Response<Body> {
+        let (mut parts, body) = response.into_parts();
+        parts
+            .headers
+            .append("Vary", HeaderValue::from_static("Accept-Language"));
+        let is_json = parts
+            .headers
+            .get("Content-Type")
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.contains("json"))
+            .unwrap_or(true);
+        let body_len = body.size_hint().exact();
+        if locale == SOURCE_MESSAGE_LOCALE
+            || !is_json
+            || body_len.is_none_or(|len| len > MAX_LOCALIZED_BODY_BYTES)
+        {
+            return Response::from_parts(parts, body);
+        }
+        let bytes = match hyper::body::to_bytes(body).await {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                error!("failed to read response body for localization with err='{e}'");
+                return Response::from_parts(parts, Body::empty());
+            }
+        };
+        let mut value =
+            match serde_json::from_slice::<serde_json::Value>(&bytes) {
+                Ok(value) if value.is_object() => value,
+                _ => return Response::from_parts(parts, Body::from(bytes)),
+            };
+        if !self.translate_messages(locale, &mut value) {
+            return Response::from_parts(parts, Body::from(bytes));
+        }
+        if let Ok(content_language) = HeaderValue::from_str(locale) {
+            parts.headers.insert("Content-Language", content_language);
+        }
+        parts.headers.remove("Content-Length");
+        Response::from_parts(parts, Body::from(value.to_string()))
+    }
+
+    /// translate_messages
+    ///
+    /// Translate the ``msg`` and ``errors[].msg`` string
+    /// values in a json object
+    ///
+    /// # Arguments
+    ///
+    /// * `locale` - `&str` - response locale
+    /// * `value` - `&mut serde_json::Value` - json response
+    ///
+    /// # Returns
+    ///
+    /// `bool` where `true` - a message was translated
+    ///
+    pub fn translate_messages(
+        &self,
+        locale: &str,
+        value: &mut serde_json::Value,
+    ) -> bool {
+        let mut translated = false;
+        let mut translate = |msg: &mut serde_json::Value| {
+            if let Some(translation) = msg
+                .as_str()
+                .and_then(|msg| self.provider.translate(locale, msg))
+            {
+                *msg = serde_json::Value::String(translation);
+                translated = true;
+            }
+        };
+        if let Some(msg) = value.get_mut("msg") {
+            translate(msg);
+        }
+        if let Some(errors) =
+            value.get_mut("errors").and_then(|v| v.as_array_mut())
+        {
+            for error in errors.iter_mut() {
+                if let Some(msg) = error.get_mut("msg") {
+                    translate(msg);
+                }
+            }
+        }
+        translated
+    }
+}
+
+/// set_response_user_locale
+///
+/// Record the authenticated user's ``users.locale`` for
+/// the current request's response messages (does nothing
+/// when localization is disabled)
+///
+/// # Arguments
+///
+/// * `locale` - `&str` - user's locale
+///
+pub fn set_response_user_locale(locale: &str) {
+    let _ = RESPONSE_USER_LOCALE.try_with(|user_locale| {
+        *user_locale.lock().unwrap() = locale.to_string()
+    });
+}
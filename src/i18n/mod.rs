@@ -0,0 +1,12 @@
+//! Localize the ``msg`` fields in api responses with an
+//! ``Accept-Language``-aware message catalog
+//!
+//! See
+//! [`MessageLocalization`](crate::i18n::message_localization::MessageLocalization)
+//! for the supported environment variables and
+//! [`TranslationProvider`](crate::i18n::translation_provider::TranslationProvider)
+//! for replacing the json message catalog
+//!
+pub mod accept_language;
+pub mod message_localization;
+pub mod translation_provider;
@@ -0,0 +1,299 @@
+//! Translate api response messages
+//!
+//! The default
+//! [`MessageCatalog`](crate::i18n::translation_provider::MessageCatalog)
+//! loads a json object for each locale that maps the
+//! English message to its translation. ``{}`` in a message
+//! matches any text, and the matched text replaces each
+//! ``{}`` in the translation in order:
+//!
+//! ```json
+//! {
+//!     "User login failed - invalid password": "Error al iniciar sesión - contraseña no válida",
+//!     "User email {} already registered": "El correo {} ya está registrado"
+//! }
+//! ```
+//!
+//! Messages without a translation are returned in English.
+//! Deployments with a translation service implement the
+//! [`TranslationProvider`](crate::i18n::translation_provider::TranslationProvider)
+//! trait and set it with
+//! [`RestApiServerBuilder::translation_provider`](crate::core::server::rest_api_server::RestApiServerBuilder::translation_provider).
+//!
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::notifications::email_templates::normalize_locale;
+use crate::requests::validation::field_rules::is_valid_locale;
+
+/// locale of the messages in the handlers
+pub const SOURCE_MESSAGE_LOCALE: &str = "en";
+
+/// built-in ``(locale, catalog)`` json message catalogs
+const BUILT_IN_MESSAGE_CATALOGS: [(&str, &str); 1] =
+    [("es", include_str!("../../templates/messages/es.json"))];
+
+/// TranslationProvider
+///
+/// Hook for translating the ``msg`` fields in api
+/// responses (and the ``msg`` of each invalid field)
+///
+pub trait TranslationProvider: Send + Sync {
+    /// get_locales
+    ///
+    /// Locales with translated messages (the English
+    /// source messages do not need a locale)
+    ///
+    fn get_locales(&self) -> Vec<String>;
+
+    /// translate
+    ///
+    /// Translate an English message
+    ///
+    /// # Arguments
+    ///
+    /// * `locale` - `&str` - one of the
+    ///   [`get_locales`](crate::i18n::translation_provider::TranslationProvider::get_locales)
+    /// * `msg` - `&str` - English message
+    ///
+    /// # Returns
+    ///
+    /// `Option<String>` - translated message (`None` keeps
+    /// the English message)
+    ///
+    fn translate(&self, locale: &str, msg: &str) -> Option<String>;
+}
+
+/// CatalogPattern
+///
+/// A catalog message with ``{}`` placeholders
+///
+/// # Arguments
+///
+/// * `parts` - `Vec<String>` - text between the placeholders
+/// * `translation` - `String` - translation with ``{}``
+///   placeholders
+///
+#[derive(Clone, Debug, Default)]
+pub struct CatalogPattern {
+    pub parts: Vec<String>,
+    pub translation: String,
+}
+
+impl CatalogPattern {
+    /// translate
+    ///
+    /// Match a message and fill the translation's
+    /// placeholders with the matched text
+    ///
+    /// # Arguments
+    ///
+    /// * `msg` - `&str` - English message
+    ///
+    /// # Returns
+    ///
+    /// `Option<String>` - `None` when the message does not
+    /// match
+    ///
+    pub fn translate(&self, msg: &str) -> Option<String> {
+        let first = self.parts.first()?;
+        let last = self.parts.last()?;
+        let mut rest = msg.strip_prefix(first.as_str())?;
+        let mut values = Vec::new();
+        for part in self.parts[1..self.parts.len() - 1].iter() {
+            let index = rest.find(part.as_str())?;
+            values.push(&rest[..index]);
+            rest = &rest[index + part.len()..];
+        }
+        values.push(rest.strip_suffix(last.as_str())?);
+        let mut values = values.into_iter();
+        let mut translated = String::new();
+        let mut translation_parts = self.translation.split("{}");
+        translated.push_str(translation_parts.next().unwrap_or_default());
+        for translation_part in translation_parts {
+            translated.push_str(values.next().unwrap_or_default());
+            translated.push_str(translation_part);
+        }
+        Some(translated)
+    }
+}
+
+/// MessageCatalog
+///
+/// Default
+/// [`TranslationProvider`](crate::i18n::translation_provider::TranslationProvider)
+/// with json message catalogs for each locale
+///
+/// # Arguments
+///
+/// * `messages` - `HashMap<String, HashMap<String, String>>` -
+///   exact translations by locale and English message
+/// * `patterns` - `HashMap<String, Vec<CatalogPattern>>` -
+///   translations with ``{}`` placeholders by locale (most
+///   specific first)
+///
+#[derive(Clone, Debug, Default)]
+pub struct MessageCatalog {
+    pub messages: HashMap<String, HashMap<String, String>>,
+    pub patterns: HashMap<String, Vec<CatalogPattern>>,
+}
+
+impl MessageCatalog {
+    /// build_message_catalog
+    ///
+    /// Load the built-in catalogs and the ``{locale}.json``
+    /// files in a directory (a directory catalog replaces
+    /// the built-in translations for the same messages)
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - `&str` - catalog directory (empty = only
+    ///   the built-in catalogs)
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) for a missing directory, a file
+    /// that is not named after a locale or a catalog that is
+    /// not a json object of strings
+    ///
+    pub fn build_message_catalog(dir: &str) -> Result<Self, String> {
+        let mut catalogs: BTreeMap<String, BTreeMap<String, String>> =
+            BTreeMap::new();
+        for (locale, source) in BUILT_IN_MESSAGE_CATALOGS.iter() {
+            catalogs
+                .entry(locale.to_string())
+                .or_default()
+                .extend(parse_message_catalog(locale, source)?);
+        }
+        if !dir.is_empty() {
+            let entries = std::fs::read_dir(dir).map_err(|e| {
+                format!(
+                    "failed to read MESSAGE_CATALOG_DIR={dir} with err='{e}'"
+                )
+            })?;
+            for entry in entries {
+                let path = entry
+                    .map_err(|e| {
+                        format!("failed to read {dir} with err='{e}'")
+                    })?
+                    .path();
+                let stem = match path.extension().and_then(|ext| ext.to_str()) {
+                    Some("json") => path
+                        .file_stem()
+                        .map(|stem| stem.to_string_lossy().to_string())
+                        .unwrap_or_default(),
+                    _ => continue,
+                };
+                let locale = normalize_locale(&stem);
+                if !is_valid_locale(&locale) {
+                    return Err(format!(
+                        "invalid message catalog={} must be named \
+                        {{locale}}.json like es.json or pt-BR.json",
+                        path.display()
+                    ));
+                }
+                let source = read_message_catalog(&path)?;
+                catalogs
+                    .entry(locale.clone())
+                    .or_default()
+                    .extend(parse_message_catalog(&locale, &source)?);
+            }
+        }
+        let mut catalog = MessageCatalog::default();
+        for (locale, entries) in catalogs.into_iter() {
+            let mut messages = HashMap::new();
+            let mut patterns = Vec::new();
+            for (msg, translation) in entries.into_iter() {
+                match msg.contains("{}") {
+                    true => patterns.push(CatalogPattern {
+                        parts: msg.split("{}").map(|p| p.to_string()).collect(),
+                        translation,
+                    }),
+                    false => {
+                        messages.insert(msg, translation);
+                    }
+                }
+            }
+            // patterns with more fixed text are more specific
+            patterns.sort_by_key(|pattern: &CatalogPattern| {
+                std::cmp::Reverse(
+                    pattern.parts.iter().map(|p| p.len()).sum::<usize>(),
+                )
+            });
+            catalog.messages.insert(locale.clone(), messages);
+            catalog.patterns.insert(locale, patterns);
+        }
+        Ok(catalog)
+    }
+}
+
+impl TranslationProvider for MessageCatalog {
+    fn get_locales(&self) -> Vec<String> {
+        let mut locales: Vec<String> = self.messages.keys().cloned().collect();
+        locales.sort();
+        locales
+    }
+
+    fn translate(&self, locale: &str, msg: &str) -> Option<String> {
+        if let Some(translation) =
+            self.messages.get(locale).and_then(|m| m.get(msg))
+        {
+            return Some(translation.clone());
+        }
+        self.patterns
+            .get(locale)?
+            .iter()
+            .find_map(|pattern| pattern.translate(msg))
+    }
+}
+
+/// read_message_catalog
+///
+/// Read a catalog file
+///
+fn read_message_catalog(path: &Path) -> Result<String, String> {
+    std::fs::read_to_string(path).map_err(|e| {
+        format!(
+            "failed to read message catalog={} with err='{e}'",
+            path.display()
+        )
+    })
+}
+
+/// parse_message_catalog
+///
+/// Parse a json object of English messages and their
+/// translations
+///
+/// # Arguments
+///
+/// * `locale` - `&str` - catalog locale (for errors)
+/// * `source` - `&str` - json catalog
+///
+/// # Errors
+///
+/// Err(err_msg: `String`) when the catalog is not a json
+/// object of strings
+///
+/// # Examples
+///
+/// ```rust
+/// use restapi::i18n::translation_provider::parse_message_catalog;
+/// let catalog = parse_message_catalog("es", r#"{"success": "éxito"}"#).unwrap();
+/// assert_eq!(catalog["success"], "éxito");
+/// assert!(parse_message_catalog("es", r#"{"success": 1}"#).is_err());
+/// ```
+///
+pub fn parse_message_catalog(
+    locale: &str,
+    source: &str,
+) -> Result<BTreeMap<String, String>, String> {
+    serde_json::from_str::<BTreeMap<String, String>>(source).map_err(|e| {
+        format!(
+            "invalid message catalog for locale={locale} - \
+            must be a json object of English messages and \
+            their translations with err='{e}'"
+        )
+    })
+}
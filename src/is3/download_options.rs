@@ -0,0 +1,131 @@
+//! Options for streaming large downloads to disk with
+//! [`ObjectStore::download_to_file`](crate::is3::object_store::ObjectStore::download_to_file)
+//! (for batch export jobs built on this crate)
+//!
+use std::sync::Arc;
+
+/// default bytes read from s3 and written to disk at a time
+pub const DOWNLOAD_DEFAULT_CHUNK_BYTES: usize = 8 * 1024 * 1024;
+
+/// callback for download progress
+pub type DownloadProgressFn = Arc<dyn Fn(&DownloadProgress) + Send + Sync>;
+
+/// DownloadProgress
+///
+/// Progress passed to the
+/// [`DownloadOptions`](crate::is3::download_options::DownloadOptions)
+/// ``progress`` callback after each chunk is written
+///
+/// # Arguments
+///
+/// * `bucket` - `String` - source bucket
+/// * `key` - `String` - source key location
+/// * `downloaded_bytes` - `u64` - bytes written so far
+/// * `total_bytes` - `Option<u64>` - object size when known
+/// * `elapsed_ms` - `u64` - time since the download started
+///
+#[derive(Clone, Debug, Default)]
+pub struct DownloadProgress {
+    pub bucket: String,
+    pub key: String,
+    pub downloaded_bytes: u64,
+    pub total_bytes: Option<u64>,
+    pub elapsed_ms: u64,
+}
+
+/// DownloadOptions
+///
+/// How to stream an object to disk
+///
+/// # Arguments
+///
+/// * `chunk_bytes` - `usize` - bytes read and written at a
+///   time (``0`` =
+///   [`DOWNLOAD_DEFAULT_CHUNK_BYTES`](crate::is3::download_options::DOWNLOAD_DEFAULT_CHUNK_BYTES))
+/// * `max_bytes_per_second` - `u64` - bandwidth cap
+///   (``0`` = unlimited)
+/// * `progress` - `Option<`[`DownloadProgressFn`](crate::is3::download_options::DownloadProgressFn)`>` -
+///   called after each chunk is written
+///
+/// # Examples
+///
+/// ```rust
+/// use std::sync::Arc;
+/// use restapi::is3::download_options::DownloadOptions;
+/// let options = DownloadOptions {
+///     chunk_bytes: 1024 * 1024,
+///     max_bytes_per_second: 50 * 1024 * 1024,
+///     progress: Some(Arc::new(|progress| {
+///         println!(
+///             "{}/{:?} bytes",
+///             progress.downloaded_bytes, progress.total_bytes
+///         );
+///     })),
+/// };
+/// assert_eq!(options.get_chunk_bytes(), 1024 * 1024);
+/// assert_eq!(options.get_throttle_ms(50 * 1024 * 1024, 500), 500);
+/// ```
+///
+#[derive(Clone, Default)]
+pub struct DownloadOptions {
+    pub chunk_bytes: usize,
+    pub max_bytes_per_second: u64,
+    pub progress: Option<DownloadProgressFn>,
+}
+
+impl DownloadOptions {
+    /// get_chunk_bytes
+    ///
+    /// Get the chunk size (``chunk_bytes`` or
+    /// [`DOWNLOAD_DEFAULT_CHUNK_BYTES`](crate::is3::download_options::DOWNLOAD_DEFAULT_CHUNK_BYTES))
+    ///
+    pub fn get_chunk_bytes(&self) -> usize {
+        match self.chunk_bytes {
+            0 => DOWNLOAD_DEFAULT_CHUNK_BYTES,
+            chunk_bytes => chunk_bytes,
+        }
+    }
+
+    /// get_throttle_ms
+    ///
+    /// Get how long to pause so the download stays under
+    /// ``max_bytes_per_second``
+    ///
+    /// # Arguments
+    ///
+    /// * `downloaded_bytes` - `u64` - bytes downloaded since
+    ///   the download started
+    /// * `elapsed_ms` - `u64` - time since the download started
+    ///
+    /// # Returns
+    ///
+    /// `u64` - milliseconds to sleep (``0`` = keep going)
+    ///
+    pub fn get_throttle_ms(
+        &self,
+        downloaded_bytes: u64,
+        elapsed_ms: u64,
+    ) -> u64 {
+        if self.max_bytes_per_second == 0 {
+            return 0;
+        }
+        let expected_ms = (downloaded_bytes as u128 * 1000
+            / self.max_bytes_per_second as u128)
+            .min(u64::MAX as u128) as u64;
+        expected_ms.saturating_sub(elapsed_ms)
+    }
+
+    /// report
+    ///
+    /// Call the ``progress`` callback (if set)
+    ///
+    /// # Arguments
+    ///
+    /// * `progress` - [`DownloadProgress`](crate::is3::download_options::DownloadProgress)
+    ///
+    pub fn report(&self, progress: &DownloadProgress) {
+        if let Some(progress_fn) = &self.progress {
+            progress_fn(progress);
+        }
+    }
+}
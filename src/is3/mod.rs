@@ -1,6 +1,23 @@
 //! APIs for downloading and uploading to the configured S3 endpoint
 //!
+pub mod download_options;
+pub mod object_store;
+#[cfg(feature = "s3")]
+pub mod s3_delete_object;
+#[cfg(feature = "s3")]
 pub mod s3_download_to_file;
+#[cfg(feature = "s3")]
 pub mod s3_download_to_memory;
+#[cfg(feature = "s3")]
+pub mod s3_list_objects;
+pub mod s3_mock_dir;
+#[cfg(feature = "s3")]
+pub mod s3_multipart_upload;
+#[cfg(feature = "s3")]
+pub mod s3_retry;
+#[cfg(feature = "s3")]
+pub mod s3_set_storage_class;
+#[cfg(feature = "s3")]
 pub mod s3_upload_buffer;
+#[cfg(feature = "s3")]
 pub mod s3_upload_file;
@@ -0,0 +1,1260 @@
+//! Store and load user files with the
+//! [`ObjectStore`](crate::is3::object_store::ObjectStore)
+//! set on the
+//! [`CoreConfig`](crate::core::core_config::CoreConfig)
+//! ``object_store``
+//!
+//! [`build_object_store`](crate::is3::object_store::build_object_store)
+//! picks the backend:
+//!
+//! - ``DEMO_MODE=1`` - files are stored in ``DEMO_S3_DIR/BUCKET/KEY``
+//!   ([`LocalDirObjectStore`](crate::is3::object_store::LocalDirObjectStore))
+//! - built with the ``s3`` feature (the default) - files are
+//!   stored in s3 (``S3ObjectStore``)
+//! - built without the ``s3`` feature - uploads and downloads
+//!   fail ([`DisabledObjectStore`](crate::is3::object_store::DisabledObjectStore))
+//!
+//! The
+//! [`CoreConfig`](crate::core::core_config::CoreConfig)
+//! wraps the backend in a
+//! [`CircuitBreakerObjectStore`](crate::is3::object_store::CircuitBreakerObjectStore)
+//! so repeated failures stop new calls for a while.
+//!
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use crate::is3::download_options::DownloadOptions;
+use crate::is3::download_options::DownloadProgress;
+use crate::is3::s3_mock_dir::get_s3_mock_dir;
+use crate::is3::s3_mock_dir::resolve_s3_mock_path;
+use crate::utils::circuit_breaker::CircuitBreaker;
+
+/// future returned by
+/// [`ObjectStore::upload_buffer`](crate::is3::object_store::ObjectStore::upload_buffer)
+pub type ObjectStoreUploadFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<String, String>> + Send + 'a>>;
+
+/// supported s3 storage classes for
+/// [`ObjectStore::upload_buffer_with_storage_class`](crate::is3::object_store::ObjectStore::upload_buffer_with_storage_class)
+/// and
+/// [`ObjectStore::set_storage_class`](crate::is3::object_store::ObjectStore::set_storage_class)
+pub const S3_STORAGE_CLASSES: [&str; 8] = [
+    "STANDARD",
+    "STANDARD_IA",
+    "ONEZONE_IA",
+    "INTELLIGENT_TIERING",
+    "GLACIER_IR",
+    "GLACIER",
+    "DEEP_ARCHIVE",
+    "REDUCED_REDUNDANCY",
+];
+
+/// future returned by
+/// [`ObjectStore::download_to_memory`](crate::is3::object_store::ObjectStore::download_to_memory)
+pub type ObjectStoreDownloadFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<Vec<u8>, String>> + Send + 'a>>;
+
+/// ObjectStoreEntry
+///
+/// A key returned by
+/// [`ObjectStore::list_objects`](crate::is3::object_store::ObjectStore::list_objects)
+///
+/// # Arguments
+///
+/// * `key` - `String` - key location
+/// * `size_in_bytes` - `i64` - object size
+/// * `last_modified` - `String` - last modified time
+///   (rfc3339)
+/// * `storage_class` - `String` - s3 storage class
+///   (``STANDARD`` for local files)
+///
+#[derive(Clone, Debug, Default)]
+pub struct ObjectStoreEntry {
+    pub key: String,
+    pub size_in_bytes: i64,
+    pub last_modified: String,
+    pub storage_class: String,
+}
+
+/// future returned by
+/// [`ObjectStore::list_objects`](crate::is3::object_store::ObjectStore::list_objects)
+pub type ObjectStoreListFuture<'a> = Pin<
+    Box<dyn Future<Output = Result<Vec<ObjectStoreEntry>, String>> + Send + 'a>,
+>;
+
+/// ObjectStore
+///
+/// Backend for storing user files by bucket and key
+///
+pub trait ObjectStore: Send + Sync {
+    /// upload_buffer
+    ///
+    /// Store the bytes in a single key
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - logging label for caller
+    /// * `bucket` - `&str` - destination bucket
+    /// * `key` - `&str` - destination key location
+    /// * `bytes` - `&[u8]` - contents to store
+    ///
+    /// # Returns
+    ///
+    /// Ok(success_msg: `String`)
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`)
+    ///
+    fn upload_buffer<'a>(
+        &'a self,
+        tracking_label: &'a str,
+        bucket: &'a str,
+        key: &'a str,
+        bytes: &'a [u8],
+    ) -> ObjectStoreUploadFuture<'a>;
+
+    /// download_to_memory
+    ///
+    /// Load the contents of a key
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket` - `&str` - source bucket
+    /// * `key` - `&str` - source key location
+    ///
+    /// # Returns
+    ///
+    /// Ok(``Vec<u8>``)
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`)
+    ///
+    fn download_to_memory<'a>(
+        &'a self,
+        bucket: &'a str,
+        key: &'a str,
+    ) -> ObjectStoreDownloadFuture<'a>;
+
+    /// download_to_file
+    ///
+    /// Save the contents of a key to a file. The default
+    /// implementation loads the whole key with
+    /// [`ObjectStore::download_to_memory`](crate::is3::object_store::ObjectStore::download_to_memory)
+    /// and reports progress once. The s3 backend streams the
+    /// key to disk in chunks.
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - logging label for caller
+    /// * `bucket` - `&str` - source bucket
+    /// * `key` - `&str` - source key location
+    /// * `file_path` - `&str` - save to this file path on disk
+    /// * `options` - [`DownloadOptions`](crate::is3::download_options::DownloadOptions) -
+    ///   chunk size, bandwidth cap and progress callback
+    ///
+    /// # Returns
+    ///
+    /// Ok(file_path: `String`)
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`)
+    ///
+    fn download_to_file<'a>(
+        &'a self,
+        tracking_label: &'a str,
+        bucket: &'a str,
+        key: &'a str,
+        file_path: &'a str,
+        options: &'a DownloadOptions,
+    ) -> ObjectStoreUploadFuture<'a> {
+        Box::pin(async move {
+            let start_time = std::time::Instant::now();
+            let contents = self.download_to_memory(bucket, key).await?;
+            tokio::fs::write(file_path, &contents).await.map_err(|e| {
+                format!(
+                    "{tracking_label} - download_to_file - \
+                    failed to write {file_path} with err='{e}'"
+                )
+            })?;
+            options.report(&DownloadProgress {
+                bucket: bucket.to_string(),
+                key: key.to_string(),
+                downloaded_bytes: contents.len() as u64,
+                total_bytes: Some(contents.len() as u64),
+                elapsed_ms: start_time.elapsed().as_millis() as u64,
+            });
+            Ok(file_path.to_string())
+        })
+    }
+
+    /// upload_buffer_with_storage_class
+    ///
+    /// Store the bytes in a single key with an s3 storage
+    /// class (backends without storage classes ignore it)
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - logging label for caller
+    /// * `bucket` - `&str` - destination bucket
+    /// * `key` - `&str` - destination key location
+    /// * `bytes` - `&[u8]` - contents to store
+    /// * `storage_class` - `&str` - s3 storage class
+    ///
+    /// # Returns
+    ///
+    /// Ok(success_msg: `String`)
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`)
+    ///
+    fn upload_buffer_with_storage_class<'a>(
+        &'a self,
+        tracking_label: &'a str,
+        bucket: &'a str,
+        key: &'a str,
+        bytes: &'a [u8],
+        storage_class: &'a str,
+    ) -> ObjectStoreUploadFuture<'a> {
+        let _ = storage_class;
+        self.upload_buffer(tracking_label, bucket, key, bytes)
+    }
+
+    /// delete_object
+    ///
+    /// Delete a key
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - logging label for caller
+    /// * `bucket` - `&str` - bucket
+    /// * `key` - `&str` - key location
+    ///
+    /// # Returns
+    ///
+    /// Ok(success_msg: `String`)
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) - the default implementation
+    /// does not support deletes
+    ///
+    fn delete_object<'a>(
+        &'a self,
+        tracking_label: &'a str,
+        bucket: &'a str,
+        key: &'a str,
+    ) -> ObjectStoreUploadFuture<'a> {
+        Box::pin(async move {
+            Err(format!(
+                "{tracking_label} - delete_object - \
+                s3://{bucket}/{key} is not supported by this object store"
+            ))
+        })
+    }
+
+    /// set_storage_class
+    ///
+    /// Transition a key to another s3 storage class
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - logging label for caller
+    /// * `bucket` - `&str` - bucket
+    /// * `key` - `&str` - key location
+    /// * `storage_class` - `&str` - new s3 storage class
+    ///
+    /// # Returns
+    ///
+    /// Ok(success_msg: `String`)
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) - the default implementation
+    /// does not support storage classes
+    ///
+    fn set_storage_class<'a>(
+        &'a self,
+        tracking_label: &'a str,
+        bucket: &'a str,
+        key: &'a str,
+        storage_class: &'a str,
+    ) -> ObjectStoreUploadFuture<'a> {
+        Box::pin(async move {
+            Err(format!(
+                "{tracking_label} - set_storage_class - \
+                sc={storage_class} on s3://{bucket}/{key} is not \
+                supported by this object store"
+            ))
+        })
+    }
+
+    /// create_multipart_upload
+    ///
+    /// Start a multipart upload that receives its parts in
+    /// separate calls to
+    /// [`ObjectStore::upload_part`](crate::is3::object_store::ObjectStore::upload_part)
+    /// (used for resumable uploads)
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - logging label for caller
+    /// * `bucket` - `&str` - destination bucket
+    /// * `key` - `&str` - destination key location
+    /// * `storage_class` - `Option<&str>` - s3 storage class
+    ///   (``None`` = ``S3_STORAGE_CLASS``)
+    ///
+    /// # Returns
+    ///
+    /// Ok(upload_id: `String`)
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) - the default implementation
+    /// does not support multipart uploads
+    ///
+    fn create_multipart_upload<'a>(
+        &'a self,
+        tracking_label: &'a str,
+        bucket: &'a str,
+        key: &'a str,
+        storage_class: Option<&'a str>,
+    ) -> ObjectStoreUploadFuture<'a> {
+        let _ = storage_class;
+        Box::pin(async move {
+            Err(format!(
+                "{tracking_label} - create_multipart_upload - \
+                s3://{bucket}/{key} is not supported by this object store"
+            ))
+        })
+    }
+
+    /// upload_part
+    ///
+    /// Store one part of a multipart upload
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - logging label for caller
+    /// * `bucket` - `&str` - destination bucket
+    /// * `key` - `&str` - destination key location
+    /// * `upload_id` - `&str` - id from
+    ///   [`ObjectStore::create_multipart_upload`](crate::is3::object_store::ObjectStore::create_multipart_upload)
+    /// * `part_number` - `i64` - part number (``1`` to ``10000``)
+    /// * `bytes` - `&[u8]` - part contents
+    ///
+    /// # Returns
+    ///
+    /// Ok(etag: `String`)
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) - the default implementation
+    /// does not support multipart uploads
+    ///
+    fn upload_part<'a>(
+        &'a self,
+        tracking_label: &'a str,
+        bucket: &'a str,
+        key: &'a str,
+        upload_id: &'a str,
+        part_number: i64,
+        bytes: &'a [u8],
+    ) -> ObjectStoreUploadFuture<'a> {
+        let _ = (upload_id, bytes);
+        Box::pin(async move {
+            Err(format!(
+                "{tracking_label} - upload_part - part={part_number} \
+                for s3://{bucket}/{key} is not supported by this \
+                object store"
+            ))
+        })
+    }
+
+    /// complete_multipart_upload
+    ///
+    /// Combine the uploaded parts into the key
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - logging label for caller
+    /// * `bucket` - `&str` - destination bucket
+    /// * `key` - `&str` - destination key location
+    /// * `upload_id` - `&str` - multipart upload id
+    /// * `parts` - `&[(i64, String)]` - uploaded
+    ///   ``(part_number, etag)`` values in order
+    ///
+    /// # Returns
+    ///
+    /// Ok(success_msg: `String`)
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) - the default implementation
+    /// does not support multipart uploads
+    ///
+    fn complete_multipart_upload<'a>(
+        &'a self,
+        tracking_label: &'a str,
+        bucket: &'a str,
+        key: &'a str,
+        upload_id: &'a str,
+        parts: &'a [(i64, String)],
+    ) -> ObjectStoreUploadFuture<'a> {
+        let _ = (upload_id, parts);
+        Box::pin(async move {
+            Err(format!(
+                "{tracking_label} - complete_multipart_upload - \
+                s3://{bucket}/{key} is not supported by this object store"
+            ))
+        })
+    }
+
+    /// abort_multipart_upload
+    ///
+    /// Abort a multipart upload and delete its parts
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - logging label for caller
+    /// * `bucket` - `&str` - destination bucket
+    /// * `key` - `&str` - destination key location
+    /// * `upload_id` - `&str` - multipart upload id
+    ///
+    /// # Returns
+    ///
+    /// Ok(success_msg: `String`)
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) - the default implementation
+    /// does not support multipart uploads
+    ///
+    fn abort_multipart_upload<'a>(
+        &'a self,
+        tracking_label: &'a str,
+        bucket: &'a str,
+        key: &'a str,
+        upload_id: &'a str,
+    ) -> ObjectStoreUploadFuture<'a> {
+        let _ = upload_id;
+        Box::pin(async move {
+            Err(format!(
+                "{tracking_label} - abort_multipart_upload - \
+                s3://{bucket}/{key} is not supported by this object store"
+            ))
+        })
+    }
+
+    /// list_objects
+    ///
+    /// List the keys under a prefix sorted by key
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - logging label for caller
+    /// * `bucket` - `&str` - bucket
+    /// * `prefix` - `&str` - key prefix
+    /// * `max_keys` - `usize` - stop after this many keys
+    ///
+    /// # Returns
+    ///
+    /// Ok(`Vec<`[`ObjectStoreEntry`](crate::is3::object_store::ObjectStoreEntry)`>`)
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) - the default implementation
+    /// does not support listing keys
+    ///
+    fn list_objects<'a>(
+        &'a self,
+        tracking_label: &'a str,
+        bucket: &'a str,
+        prefix: &'a str,
+        max_keys: usize,
+    ) -> ObjectStoreListFuture<'a> {
+        let _ = max_keys;
+        Box::pin(async move {
+            Err(format!(
+                "{tracking_label} - list_objects - \
+                s3://{bucket}/{prefix} is not supported by this object store"
+            ))
+        })
+    }
+}
+
+/// build_object_store
+///
+/// Build the
+/// [`ObjectStore`](crate::is3::object_store::ObjectStore)
+/// for the ``DEMO_MODE`` and the enabled cargo features
+///
+pub fn build_object_store() -> Arc<dyn ObjectStore> {
+    match get_s3_mock_dir() {
+        Some(dir) => Arc::new(LocalDirObjectStore { dir }),
+        None => build_s3_object_store(),
+    }
+}
+
+/// build_s3_object_store
+///
+/// Create the s3 backend
+///
+#[cfg(feature = "s3")]
+fn build_s3_object_store() -> Arc<dyn ObjectStore> {
+    Arc::new(S3ObjectStore {})
+}
+
+/// build_s3_object_store
+///
+/// The s3 backend requires the ``s3`` feature
+///
+#[cfg(not(feature = "s3"))]
+fn build_s3_object_store() -> Arc<dyn ObjectStore> {
+    Arc::new(DisabledObjectStore {})
+}
+
+/// S3ObjectStore
+///
+/// Store files in s3 with
+/// [`s3_upload_buffer`](crate::is3::s3_upload_buffer::s3_upload_buffer)
+/// and
+/// [`s3_download_to_memory`](crate::is3::s3_download_to_memory::s3_download_to_memory)
+///
+#[cfg(feature = "s3")]
+pub struct S3ObjectStore {}
+
+#[cfg(feature = "s3")]
+impl ObjectStore for S3ObjectStore {
+    fn upload_buffer<'a>(
+        &'a self,
+        tracking_label: &'a str,
+        bucket: &'a str,
+        key: &'a str,
+        bytes: &'a [u8],
+    ) -> ObjectStoreUploadFuture<'a> {
+        Box::pin(crate::is3::s3_upload_buffer::s3_upload_buffer(
+            tracking_label,
+            bucket,
+            key,
+            bytes,
+            None,
+        ))
+    }
+
+    fn download_to_memory<'a>(
+        &'a self,
+        bucket: &'a str,
+        key: &'a str,
+    ) -> ObjectStoreDownloadFuture<'a> {
+        Box::pin(crate::is3::s3_download_to_memory::s3_download_to_memory(
+            bucket, key,
+        ))
+    }
+
+    fn download_to_file<'a>(
+        &'a self,
+        tracking_label: &'a str,
+        bucket: &'a str,
+        key: &'a str,
+        file_path: &'a str,
+        options: &'a DownloadOptions,
+    ) -> ObjectStoreUploadFuture<'a> {
+        Box::pin(
+            crate::is3::s3_download_to_file::s3_download_to_file_with_options(
+                tracking_label,
+                file_path,
+                bucket,
+                key,
+                options,
+            ),
+        )
+    }
+
+    fn upload_buffer_with_storage_class<'a>(
+        &'a self,
+        tracking_label: &'a str,
+        bucket: &'a str,
+        key: &'a str,
+        bytes: &'a [u8],
+        storage_class: &'a str,
+    ) -> ObjectStoreUploadFuture<'a> {
+        Box::pin(crate::is3::s3_upload_buffer::s3_upload_buffer(
+            tracking_label,
+            bucket,
+            key,
+            bytes,
+            Some(storage_class),
+        ))
+    }
+
+    fn delete_object<'a>(
+        &'a self,
+        tracking_label: &'a str,
+        bucket: &'a str,
+        key: &'a str,
+    ) -> ObjectStoreUploadFuture<'a> {
+        Box::pin(crate::is3::s3_delete_object::s3_delete_object(
+            tracking_label,
+            bucket,
+            key,
+        ))
+    }
+
+    fn set_storage_class<'a>(
+        &'a self,
+        tracking_label: &'a str,
+        bucket: &'a str,
+        key: &'a str,
+        storage_class: &'a str,
+    ) -> ObjectStoreUploadFuture<'a> {
+        Box::pin(crate::is3::s3_set_storage_class::s3_set_storage_class(
+            tracking_label,
+            bucket,
+            key,
+            storage_class,
+        ))
+    }
+
+    fn create_multipart_upload<'a>(
+        &'a self,
+        tracking_label: &'a str,
+        bucket: &'a str,
+        key: &'a str,
+        storage_class: Option<&'a str>,
+    ) -> ObjectStoreUploadFuture<'a> {
+        Box::pin(crate::is3::s3_multipart_upload::s3_create_multipart_upload(
+            tracking_label,
+            bucket,
+            key,
+            storage_class,
+        ))
+    }
+
+    fn upload_part<'a>(
+        &'a self,
+        tracking_label: &'a str,
+        bucket: &'a str,
+        key: &'a str,
+        upload_id: &'a str,
+        part_number: i64,
+        bytes: &'a [u8],
+    ) -> ObjectStoreUploadFuture<'a> {
+        Box::pin(crate::is3::s3_multipart_upload::s3_upload_part(
+            tracking_label,
+            bucket,
+            key,
+            upload_id,
+            part_number,
+            bytes,
+        ))
+    }
+
+    fn complete_multipart_upload<'a>(
+        &'a self,
+        tracking_label: &'a str,
+        bucket: &'a str,
+        key: &'a str,
+        upload_id: &'a str,
+        parts: &'a [(i64, String)],
+    ) -> ObjectStoreUploadFuture<'a> {
+        Box::pin(
+            crate::is3::s3_multipart_upload::s3_complete_multipart_upload(
+                tracking_label,
+                bucket,
+                key,
+                upload_id,
+                parts,
+            ),
+        )
+    }
+
+    fn abort_multipart_upload<'a>(
+        &'a self,
+        tracking_label: &'a str,
+        bucket: &'a str,
+        key: &'a str,
+        upload_id: &'a str,
+    ) -> ObjectStoreUploadFuture<'a> {
+        Box::pin(crate::is3::s3_multipart_upload::s3_abort_multipart_upload(
+            tracking_label,
+            bucket,
+            key,
+            upload_id,
+        ))
+    }
+
+    fn list_objects<'a>(
+        &'a self,
+        tracking_label: &'a str,
+        bucket: &'a str,
+        prefix: &'a str,
+        max_keys: usize,
+    ) -> ObjectStoreListFuture<'a> {
+        Box::pin(crate::is3::s3_list_objects::s3_list_objects(
+            tracking_label,
+            bucket,
+            prefix,
+            max_keys,
+        ))
+    }
+}
+
+/// LocalDirObjectStore
+///
+/// Store files in ``dir/BUCKET/KEY`` (see
+/// [`resolve_s3_mock_path`](crate::is3::s3_mock_dir::resolve_s3_mock_path))
+/// so the server runs without aws credentials. Keys that
+/// resolve outside of ``dir`` are rejected.
+///
+/// # Arguments
+///
+/// * `dir` - `String` - local directory
+///
+pub struct LocalDirObjectStore {
+    pub dir: String,
+}
+
+impl LocalDirObjectStore {
+    /// get_multipart_dir
+    ///
+    /// Local directory holding the parts of a multipart
+    /// upload (``dir/.multipart/UPLOAD_ID``)
+    ///
+    /// # Arguments
+    ///
+    /// * `upload_id` - `&str` - multipart upload id
+    ///
+    /// # Returns
+    ///
+    /// `String` - directory path (characters outside
+    /// ``[a-zA-Z0-9-]`` are dropped from the ``upload_id``)
+    ///
+    pub fn get_multipart_dir(&self, upload_id: &str) -> String {
+        format!(
+            "{}/.multipart/{}",
+            self.dir.trim_end_matches('/'),
+            upload_id
+                .chars()
+                .filter(|c| c.is_ascii_alphanumeric() || *c == '-')
+                .collect::<String>()
+        )
+    }
+}
+
+impl ObjectStore for LocalDirObjectStore {
+    fn upload_buffer<'a>(
+        &'a self,
+        tracking_label: &'a str,
+        bucket: &'a str,
+        key: &'a str,
+        bytes: &'a [u8],
+    ) -> ObjectStoreUploadFuture<'a> {
+        Box::pin(async move {
+            let path =
+                resolve_s3_mock_path(&self.dir, bucket, key).await.map_err(
+                    |e| format!("{tracking_label} - upload_buffer - {e}"),
+                )?;
+            if let Some(parent) = std::path::Path::new(&path).parent() {
+                if let Err(e) = tokio::fs::create_dir_all(parent).await {
+                    return Err(format!(
+                        "{tracking_label} - upload_buffer - \
+                        failed to create mock s3 dir for {path} \
+                        with err='{e}'"
+                    ));
+                }
+            }
+            match tokio::fs::write(&path, bytes).await {
+                Ok(_) => {
+                    info!(
+                        "{tracking_label} - upload_buffer - done - \
+                        s3://{bucket}/{key} stored in {path}"
+                    );
+                    Ok("Success".to_string())
+                }
+                Err(e) => Err(format!(
+                    "{tracking_label} - upload_buffer - \
+                    failed to write mock s3 file {path} with err='{e}'"
+                )),
+            }
+        })
+    }
+
+    fn download_to_memory<'a>(
+        &'a self,
+        bucket: &'a str,
+        key: &'a str,
+    ) -> ObjectStoreDownloadFuture<'a> {
+        Box::pin(async move {
+            let path = resolve_s3_mock_path(&self.dir, bucket, key).await?;
+            info!("download_to_memory s3://{bucket}/{key} from {path}");
+            tokio::fs::read(&path).await.map_err(|e| {
+                format!("failed to download s3://{bucket}/{key} with err='{e}'")
+            })
+        })
+    }
+
+    fn delete_object<'a>(
+        &'a self,
+        tracking_label: &'a str,
+        bucket: &'a str,
+        key: &'a str,
+    ) -> ObjectStoreUploadFuture<'a> {
+        Box::pin(async move {
+            let path =
+                resolve_s3_mock_path(&self.dir, bucket, key).await.map_err(
+                    |e| format!("{tracking_label} - delete_object - {e}"),
+                )?;
+            match tokio::fs::remove_file(&path).await {
+                Ok(_) => {
+                    info!(
+                        "{tracking_label} - delete_object - done - \
+                        s3://{bucket}/{key} removed {path}"
+                    );
+                    Ok("Success".to_string())
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    Ok("Success".to_string())
+                }
+                Err(e) => Err(format!(
+                    "{tracking_label} - delete_object - \
+                    failed to remove mock s3 file {path} with err='{e}'"
+                )),
+            }
+        })
+    }
+
+    fn set_storage_class<'a>(
+        &'a self,
+        tracking_label: &'a str,
+        bucket: &'a str,
+        key: &'a str,
+        storage_class: &'a str,
+    ) -> ObjectStoreUploadFuture<'a> {
+        // local files do not have storage classes
+        Box::pin(async move {
+            info!(
+                "{tracking_label} - set_storage_class - \
+                s3://{bucket}/{key} sc={storage_class} (ignored in \
+                demo mode)"
+            );
+            Ok("Success".to_string())
+        })
+    }
+
+    fn create_multipart_upload<'a>(
+        &'a self,
+        tracking_label: &'a str,
+        bucket: &'a str,
+        key: &'a str,
+        _storage_class: Option<&'a str>,
+    ) -> ObjectStoreUploadFuture<'a> {
+        Box::pin(async move {
+            resolve_s3_mock_path(&self.dir, bucket, key).await.map_err(
+                |e| format!("{tracking_label} - create_multipart_upload - {e}"),
+            )?;
+            let upload_id = uuid::Uuid::new_v4().to_string();
+            let path = self.get_multipart_dir(&upload_id);
+            match tokio::fs::create_dir_all(&path).await {
+                Ok(_) => {
+                    info!(
+                        "{tracking_label} - create_multipart_upload - \
+                        done - s3://{bucket}/{key} parts in {path}"
+                    );
+                    Ok(upload_id)
+                }
+                Err(e) => Err(format!(
+                    "{tracking_label} - create_multipart_upload - \
+                    failed to create mock s3 dir {path} with err='{e}'"
+                )),
+            }
+        })
+    }
+
+    fn upload_part<'a>(
+        &'a self,
+        tracking_label: &'a str,
+        _bucket: &'a str,
+        _key: &'a str,
+        upload_id: &'a str,
+        part_number: i64,
+        bytes: &'a [u8],
+    ) -> ObjectStoreUploadFuture<'a> {
+        Box::pin(async move {
+            let path = format!(
+                "{}/{part_number:05}",
+                self.get_multipart_dir(upload_id)
+            );
+            match tokio::fs::write(&path, bytes).await {
+                Ok(_) => Ok(format!("{upload_id}-{part_number}")),
+                Err(e) => Err(format!(
+                    "{tracking_label} - upload_part - \
+                    failed to write mock s3 part {path} with err='{e}'"
+                )),
+            }
+        })
+    }
+
+    fn complete_multipart_upload<'a>(
+        &'a self,
+        tracking_label: &'a str,
+        bucket: &'a str,
+        key: &'a str,
+        upload_id: &'a str,
+        parts: &'a [(i64, String)],
+    ) -> ObjectStoreUploadFuture<'a> {
+        Box::pin(async move {
+            let parts_dir = self.get_multipart_dir(upload_id);
+            let mut contents: Vec<u8> = Vec::new();
+            for (part_number, _) in parts.iter() {
+                let path = format!("{parts_dir}/{part_number:05}");
+                match tokio::fs::read(&path).await {
+                    Ok(part) => contents.extend_from_slice(&part),
+                    Err(e) => {
+                        return Err(format!(
+                            "{tracking_label} - complete_multipart_upload \
+                            - failed to read mock s3 part {path} \
+                            with err='{e}'"
+                        ));
+                    }
+                }
+            }
+            self.upload_buffer(tracking_label, bucket, key, &contents)
+                .await?;
+            let _ = tokio::fs::remove_dir_all(&parts_dir).await;
+            Ok("Success".to_string())
+        })
+    }
+
+    fn abort_multipart_upload<'a>(
+        &'a self,
+        tracking_label: &'a str,
+        bucket: &'a str,
+        key: &'a str,
+        upload_id: &'a str,
+    ) -> ObjectStoreUploadFuture<'a> {
+        Box::pin(async move {
+            let path = self.get_multipart_dir(upload_id);
+            match tokio::fs::remove_dir_all(&path).await {
+                Ok(_) => {
+                    info!(
+                        "{tracking_label} - abort_multipart_upload - \
+                        done - s3://{bucket}/{key} removed {path}"
+                    );
+                    Ok("Success".to_string())
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    Ok("Success".to_string())
+                }
+                Err(e) => Err(format!(
+                    "{tracking_label} - abort_multipart_upload - \
+                    failed to remove mock s3 dir {path} with err='{e}'"
+                )),
+            }
+        })
+    }
+
+    fn list_objects<'a>(
+        &'a self,
+        tracking_label: &'a str,
+        bucket: &'a str,
+        prefix: &'a str,
+        max_keys: usize,
+    ) -> ObjectStoreListFuture<'a> {
+        Box::pin(async move {
+            let bucket_dir =
+                resolve_s3_mock_path(&self.dir, bucket, "").await.map_err(
+                    |e| format!("{tracking_label} - list_objects - {e}"),
+                )?;
+            let mut entries: Vec<ObjectStoreEntry> = Vec::new();
+            let mut dirs = vec![bucket_dir.trim_end_matches('/').to_string()];
+            while let Some(dir) = dirs.pop() {
+                let mut read_dir = match tokio::fs::read_dir(&dir).await {
+                    Ok(read_dir) => read_dir,
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                        continue;
+                    }
+                    Err(e) => {
+                        return Err(format!(
+                            "{tracking_label} - list_objects - \
+                            failed to read mock s3 dir {dir} with err='{e}'"
+                        ));
+                    }
+                };
+                while let Ok(Some(dir_entry)) = read_dir.next_entry().await {
+                    let path = dir_entry.path();
+                    let metadata = match dir_entry.metadata().await {
+                        Ok(metadata) => metadata,
+                        Err(_) => continue,
+                    };
+                    if metadata.is_dir() {
+                        dirs.push(path.to_string_lossy().to_string());
+                        continue;
+                    }
+                    let key = match path.strip_prefix(&bucket_dir) {
+                        Ok(key) => key.to_string_lossy().to_string(),
+                        Err(_) => continue,
+                    };
+                    if !key.starts_with(prefix.trim_start_matches('/')) {
+                        continue;
+                    }
+                    let last_modified = metadata
+                        .modified()
+                        .map(|modified| {
+                            chrono::DateTime::<chrono::Utc>::from(modified)
+                                .to_rfc3339_opts(
+                                    chrono::SecondsFormat::Millis,
+                                    true,
+                                )
+                        })
+                        .unwrap_or_default();
+                    entries.push(ObjectStoreEntry {
+                        key,
+                        size_in_bytes: metadata.len() as i64,
+                        last_modified,
+                        storage_class: "STANDARD".to_string(),
+                    });
+                }
+            }
+            entries.sort_by(|a, b| a.key.cmp(&b.key));
+            entries.truncate(max_keys);
+            Ok(entries)
+        })
+    }
+}
+
+/// DisabledObjectStore
+///
+/// Rejects every upload and download when restapi is
+/// built without the ``s3`` feature
+///
+pub struct DisabledObjectStore {}
+
+impl ObjectStore for DisabledObjectStore {
+    fn upload_buffer<'a>(
+        &'a self,
+        tracking_label: &'a str,
+        bucket: &'a str,
+        key: &'a str,
+        _bytes: &'a [u8],
+    ) -> ObjectStoreUploadFuture<'a> {
+        Box::pin(async move {
+            Err(format!(
+                "{tracking_label} - upload_buffer - \
+                s3://{bucket}/{key} requires building restapi \
+                with --features s3"
+            ))
+        })
+    }
+
+    fn download_to_memory<'a>(
+        &'a self,
+        bucket: &'a str,
+        key: &'a str,
+    ) -> ObjectStoreDownloadFuture<'a> {
+        Box::pin(async move {
+            Err(format!(
+                "download_to_memory - s3://{bucket}/{key} requires \
+                building restapi with --features s3"
+            ))
+        })
+    }
+}
+
+/// CircuitBreakerObjectStore
+///
+/// Run every call to another
+/// [`ObjectStore`](crate::is3::object_store::ObjectStore)
+/// through a
+/// [`CircuitBreaker`](crate::utils::circuit_breaker::CircuitBreaker)
+/// so uploads and downloads fail fast while s3 is down
+///
+/// # Arguments
+///
+/// * `inner` - `Arc<dyn ObjectStore>` - wrapped backend
+/// * `breaker` - [`CircuitBreaker`](crate::utils::circuit_breaker::CircuitBreaker) -
+///   shared with the
+///   [`CoreConfig`](crate::core::core_config::CoreConfig)
+///   ``s3_circuit_breaker``
+///
+pub struct CircuitBreakerObjectStore {
+    pub inner: Arc<dyn ObjectStore>,
+    pub breaker: CircuitBreaker,
+}
+
+impl ObjectStore for CircuitBreakerObjectStore {
+    fn upload_buffer<'a>(
+        &'a self,
+        tracking_label: &'a str,
+        bucket: &'a str,
+        key: &'a str,
+        bytes: &'a [u8],
+    ) -> ObjectStoreUploadFuture<'a> {
+        Box::pin(self.breaker.call(self.inner.upload_buffer(
+            tracking_label,
+            bucket,
+            key,
+            bytes,
+        )))
+    }
+
+    fn download_to_memory<'a>(
+        &'a self,
+        bucket: &'a str,
+        key: &'a str,
+    ) -> ObjectStoreDownloadFuture<'a> {
+        Box::pin(
+            self.breaker
+                .call(self.inner.download_to_memory(bucket, key)),
+        )
+    }
+
+    fn download_to_file<'a>(
+        &'a self,
+        tracking_label: &'a str,
+        bucket: &'a str,
+        key: &'a str,
+        file_path: &'a str,
+        options: &'a DownloadOptions,
+    ) -> ObjectStoreUploadFuture<'a> {
+        Box::pin(self.breaker.call(self.inner.download_to_file(
+            tracking_label,
+            bucket,
+            key,
+            file_path,
+            options,
+        )))
+    }
+
+    fn upload_buffer_with_storage_class<'a>(
+        &'a self,
+        tracking_label: &'a str,
+        bucket: &'a str,
+        key: &'a str,
+        bytes: &'a [u8],
+        storage_class: &'a str,
+    ) -> ObjectStoreUploadFuture<'a> {
+        Box::pin(self.breaker.call(
+            self.inner.upload_buffer_with_storage_class(
+                tracking_label,
+                bucket,
+                key,
+                bytes,
+                storage_class,
+            ),
+        ))
+    }
+
+    fn delete_object<'a>(
+        &'a self,
+        tracking_label: &'a str,
+        bucket: &'a str,
+        key: &'a str,
+    ) -> ObjectStoreUploadFuture<'a> {
+        Box::pin(self.breaker.call(self.inner.delete_object(
+            tracking_label,
+            bucket,
+            key,
+        )))
+    }
+
+    fn set_storage_class<'a>(
+        &'a self,
+        tracking_label: &'a str,
+        bucket: &'a str,
+        key: &'a str,
+        storage_class: &'a str,
+    ) -> ObjectStoreUploadFuture<'a> {
+        Box::pin(self.breaker.call(self.inner.set_storage_class(
+            tracking_label,
+            bucket,
+            key,
+            storage_class,
+        )))
+    }
+
+    fn create_multipart_upload<'a>(
+        &'a self,
+        tracking_label: &'a str,
+        bucket: &'a str,
+        key: &'a str,
+        storage_class: Option<&'a str>,
+    ) -> ObjectStoreUploadFuture<'a> {
+        Box::pin(self.breaker.call(self.inner.create_multipart_upload(
+            tracking_label,
+            bucket,
+            key,
+            storage_class,
+        )))
+    }
+
+    fn upload_part<'a>(
+        &'a self,
+        tracking_label: &'a str,
+        bucket: &'a str,
+        key: &'a str,
+        upload_id: &'a str,
+        part_number: i64,
+        bytes: &'a [u8],
+    ) -> ObjectStoreUploadFuture<'a> {
+        Box::pin(self.breaker.call(self.inner.upload_part(
+            tracking_label,
+            bucket,
+            key,
+            upload_id,
+            part_number,
+            bytes,
+        )))
+    }
+
+    fn complete_multipart_upload<'a>(
+        &'a self,
+        tracking_label: &'a str,
+        bucket: &'a str,
+        key: &'a str,
+        upload_id: &'a str,
+        parts: &'a [(i64, String)],
+    ) -> ObjectStoreUploadFuture<'a> {
+        Box::pin(self.breaker.call(self.inner.complete_multipart_upload(
+            tracking_label,
+            bucket,
+            key,
+            upload_id,
+            parts,
+        )))
+    }
+
+    fn abort_multipart_upload<'a>(
+        &'a self,
+        tracking_label: &'a str,
+        bucket: &'a str,
+        key: &'a str,
+        upload_id: &'a str,
+    ) -> ObjectStoreUploadFuture<'a> {
+        Box::pin(self.breaker.call(self.inner.abort_multipart_upload(
+            tracking_label,
+            bucket,
+            key,
+            upload_id,
+        )))
+    }
+
+    fn list_objects<'a>(
+        &'a self,
+        tracking_label: &'a str,
+        bucket: &'a str,
+        prefix: &'a str,
+        max_keys: usize,
+    ) -> ObjectStoreListFuture<'a> {
+        Box::pin(self.breaker.call(self.inner.list_objects(
+            tracking_label,
+            bucket,
+            prefix,
+            max_keys,
+        )))
+    }
+}
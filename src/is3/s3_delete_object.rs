@@ -0,0 +1,54 @@
+//! Delete an s3 key with the function:
+//! ``s3_delete_object()``
+//!
+use rusoto_core::Region;
+use rusoto_s3::DeleteObjectRequest;
+use rusoto_s3::S3Client;
+use rusoto_s3::S3;
+
+/// s3_delete_object
+///
+/// Delete an s3 key (deleting a missing key succeeds)
+///
+/// # Arguments
+///
+/// * `tracking_label` - &str - logging label for the caller
+/// * `bucket` - &str - bucket
+/// * `key` - &str - key location to delete
+///
+/// # Returns
+///
+/// Ok(success_msg: `String`)
+///
+/// # Errors
+///
+/// ``String`` error messages can be returned for many reasons
+/// (connectivity, aws credentials, mfa timeouts, etc.)
+///
+/// Err(err_msg: ``String``)
+///
+pub async fn s3_delete_object(
+    tracking_label: &str,
+    bucket: &str,
+    key: &str,
+) -> Result<String, String> {
+    let client = S3Client::new(Region::UsEast2);
+    let delete_req = DeleteObjectRequest {
+        bucket: String::from(bucket),
+        key: String::from(key),
+        ..Default::default()
+    };
+    match client.delete_object(delete_req).await {
+        Ok(_) => {
+            info!(
+                "{tracking_label} - s3_delete_object - done - \
+                s3://{bucket}/{key}"
+            );
+            Ok("Success".to_string())
+        }
+        Err(e) => Err(format!(
+            "{tracking_label} - s3_delete_object - \
+            failed to delete s3://{bucket}/{key} with err='{e}'"
+        )),
+    }
+}
@@ -1,8 +1,32 @@
 //! Download a file from s3 using the
 //! ``s3_download_to_file()`` function
 //!
-use crate::is3::s3_download_to_memory::s3_download_to_memory;
-use std::fs;
+//! Objects are streamed to disk in chunks so multi-GB
+//! objects are never buffered in memory. Use
+//! ``s3_download_to_file_with_options()`` for a progress
+//! callback and a bandwidth cap.
+//!
+use std::time::Duration;
+use std::time::Instant;
+
+use rusoto_core::request::HttpDispatchError;
+use rusoto_core::Region;
+use rusoto_core::RusotoError;
+use rusoto_s3::GetObjectError;
+use rusoto_s3::GetObjectRequest;
+use rusoto_s3::S3Client;
+use rusoto_s3::S3;
+
+use tokio::io::AsyncRead;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+
+use crate::is3::download_options::DownloadOptions;
+use crate::is3::download_options::DownloadProgress;
+use crate::is3::s3_retry::is_retryable_s3_error;
+use crate::is3::s3_retry::S3_ATTEMPTS_HISTO_VEC;
+use crate::is3::s3_retry::S3_RETRIES_COUNTER_VEC;
+use crate::is3::s3_retry::S3_RETRY_POLICY;
 
 /// s3_download_to_file
 ///
@@ -30,25 +54,253 @@ pub async fn s3_download_to_file(
     bucket: &str,
     key: &str,
 ) -> Result<String, String> {
-    match s3_download_to_memory(bucket, key).await {
-        Ok(s3_contents) => {
-            if false {
-                info!(
-                    "s3_download_to_file - saving - \
-                    s3://{bucket}/{key} \
-                    at {file_path}"
-                );
-            }
-            fs::write(file_path, s3_contents).unwrap();
-            if false {
-                info!(
-                    "s3_download_to_file - saved - \
-                    s3://{bucket}/{key} \
-                    at {file_path}"
-                );
+    s3_download_to_file_with_options(
+        "s3_download_to_file",
+        file_path,
+        bucket,
+        key,
+        &DownloadOptions::default(),
+    )
+    .await
+}
+
+/// s3_download_to_file_with_options
+///
+/// Stream a key from s3 to a file in
+/// ``options.chunk_bytes`` chunks. The object is written to
+/// ``file_path.part`` and renamed to ``file_path`` once it
+/// is complete. Transient errors are retried with the
+/// [`S3_RETRY_POLICY`](crate::is3::s3_retry::S3_RETRY_POLICY)
+/// and resume from the last written byte with a ``Range``
+/// request.
+///
+/// # Arguments
+///
+/// * `tracking_label` - &str - logging label for the caller
+/// * `file_path` - &str - save to this file path on disk
+/// * `bucket` - &str - source bucket
+/// * `key` - &str - key location
+/// * `options` - [`DownloadOptions`](crate::is3::download_options::DownloadOptions) -
+///   chunk size, bandwidth cap and progress callback
+///
+/// # Returns
+///
+/// Ok(file_path: ``String``)
+///
+/// # Errors
+///
+/// Err(err_msg: ``String``) - the partial file is removed
+///
+pub async fn s3_download_to_file_with_options(
+    tracking_label: &str,
+    file_path: &str,
+    bucket: &str,
+    key: &str,
+    options: &DownloadOptions,
+) -> Result<String, String> {
+    let part_path = format!("{file_path}.part");
+    let res =
+        stream_to_file(tracking_label, &part_path, bucket, key, options).await;
+    let res = match res {
+        Ok(downloaded_bytes) => {
+            match tokio::fs::rename(&part_path, file_path).await {
+                Ok(_) => Ok(downloaded_bytes),
+                Err(e) => Err(format!(
+                    "{tracking_label} - s3_download_to_file - \
+                    failed to rename {part_path} to {file_path} \
+                    with err='{e}'"
+                )),
             }
+        }
+        Err(err_msg) => Err(err_msg),
+    };
+    match res {
+        Ok(downloaded_bytes) => {
+            info!(
+                "{tracking_label} - s3_download_to_file - saved \
+                s3://{bucket}/{key} ({downloaded_bytes} bytes) \
+                at {file_path}"
+            );
             Ok(file_path.to_string())
         }
-        Err(emsg) => Err(emsg),
+        Err(err_msg) => {
+            let _ = tokio::fs::remove_file(&part_path).await;
+            Err(err_msg)
+        }
+    }
+}
+
+/// stream_to_file
+///
+/// Write the object to ``part_path`` and resume with a
+/// ``Range`` request after transient errors
+///
+/// # Arguments
+///
+/// * `tracking_label` - &str - logging label for the caller
+/// * `part_path` - &str - partial file path on disk
+/// * `bucket` - &str - source bucket
+/// * `key` - &str - key location
+/// * `options` - [`DownloadOptions`](crate::is3::download_options::DownloadOptions)
+///
+/// # Returns
+///
+/// Ok(downloaded_bytes: `u64`)
+///
+/// # Errors
+///
+/// Err(err_msg: ``String``)
+///
+async fn stream_to_file(
+    tracking_label: &str,
+    part_path: &str,
+    bucket: &str,
+    key: &str,
+    options: &DownloadOptions,
+) -> Result<u64, String> {
+    let policy = &*S3_RETRY_POLICY;
+    let client = S3Client::new(Region::UsEast2);
+    let mut file = tokio::fs::File::create(part_path).await.map_err(|e| {
+        format!(
+            "{tracking_label} - s3_download_to_file - \
+            failed to create {part_path} with err='{e}'"
+        )
+    })?;
+    let mut buf = vec![0u8; options.get_chunk_bytes()];
+    let start_time = Instant::now();
+    let mut progress = DownloadProgress {
+        bucket: bucket.to_string(),
+        key: key.to_string(),
+        ..Default::default()
+    };
+    // attempts since the last written chunk and all attempts
+    // for the histogram
+    let mut attempt: u32 = 0;
+    let mut num_attempts: u32 = 0;
+    info!("{tracking_label} - s3_download_to_file - start s3://{bucket}/{key}");
+    loop {
+        attempt += 1;
+        num_attempts += 1;
+        let get_req = GetObjectRequest {
+            bucket: String::from(bucket),
+            key: String::from(key),
+            range: match progress.downloaded_bytes {
+                0 => None,
+                offset => Some(format!("bytes={offset}-")),
+            },
+            ..Default::default()
+        };
+        let err: RusotoError<GetObjectError> = match client
+            .get_object(get_req)
+            .await
+        {
+            Ok(res) => {
+                if progress.total_bytes.is_none() {
+                    progress.total_bytes = res
+                        .content_length
+                        .map(|v| v.max(0) as u64 + progress.downloaded_bytes);
+                }
+                let mut stream = match res.body {
+                    Some(body) => body.into_async_read(),
+                    None => break,
+                };
+                let read_err = loop {
+                    let num_bytes =
+                        match read_chunk(&mut stream, &mut buf).await {
+                            Ok(0) => break None,
+                            Ok(num_bytes) => num_bytes,
+                            Err(e) => break Some(e),
+                        };
+                    file.write_all(&buf[..num_bytes]).await.map_err(|e| {
+                        format!(
+                            "{tracking_label} - s3_download_to_file - \
+                                    failed to write {part_path} with err='{e}'"
+                        )
+                    })?;
+                    attempt = 1;
+                    progress.downloaded_bytes += num_bytes as u64;
+                    progress.elapsed_ms =
+                        start_time.elapsed().as_millis() as u64;
+                    options.report(&progress);
+                    let throttle_ms = options.get_throttle_ms(
+                        progress.downloaded_bytes,
+                        progress.elapsed_ms,
+                    );
+                    if throttle_ms > 0 {
+                        tokio::time::sleep(Duration::from_millis(throttle_ms))
+                            .await;
+                    }
+                };
+                match read_err {
+                    None => break,
+                    Some(e) => {
+                        RusotoError::HttpDispatch(HttpDispatchError::new(
+                            format!("failed to read the body with err='{e}'"),
+                        ))
+                    }
+                }
+            }
+            Err(e) => e,
+        };
+        if attempt >= policy.max_attempts || !is_retryable_s3_error(&err) {
+            S3_ATTEMPTS_HISTO_VEC
+                .with_label_values(&["download_to_file", "error"])
+                .observe(num_attempts as f64);
+            return Err(format!(
+                "{tracking_label} - s3_download_to_file - \
+                failed to download s3://{bucket}/{key} after \
+                {} bytes with err='{err}'",
+                progress.downloaded_bytes
+            ));
+        }
+        let delay_ms = policy.get_delay_ms(attempt);
+        warn!(
+            "{tracking_label} - s3 download_to_file attempt \
+            {attempt}/{} failed at byte {} with err='{err}' - \
+            resuming in {delay_ms}ms",
+            policy.max_attempts, progress.downloaded_bytes
+        );
+        S3_RETRIES_COUNTER_VEC
+            .with_label_values(&["download_to_file"])
+            .inc();
+        tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+    }
+    file.sync_all().await.map_err(|e| {
+        format!(
+            "{tracking_label} - s3_download_to_file - \
+            failed to sync {part_path} with err='{e}'"
+        )
+    })?;
+    S3_ATTEMPTS_HISTO_VEC
+        .with_label_values(&["download_to_file", "ok"])
+        .observe(num_attempts as f64);
+    Ok(progress.downloaded_bytes)
+}
+
+/// read_chunk
+///
+/// Fill ``buf`` from the stream (only the last chunk is
+/// shorter)
+///
+/// # Arguments
+///
+/// * `stream` - s3 object body
+/// * `buf` - `&mut [u8]` - chunk buffer
+///
+/// # Returns
+///
+/// Ok(num_bytes: `usize`) - ``0`` at the end of the object
+///
+async fn read_chunk<R: AsyncRead + Unpin>(
+    stream: &mut R,
+    buf: &mut [u8],
+) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match stream.read(&mut buf[filled..]).await? {
+            0 => break,
+            num_bytes => filled += num_bytes,
+        }
     }
+    Ok(filled)
 }
@@ -2,17 +2,24 @@
 //! in a buffer (``Vec<u8>``) with the
 //! ``s3_download_to_memory()`` function
 //!
+use rusoto_core::request::HttpDispatchError;
 use rusoto_core::Region;
+use rusoto_core::RusotoError;
 use rusoto_s3::GetObjectRequest;
 use rusoto_s3::S3Client;
 use rusoto_s3::S3;
 
 use tokio::io::AsyncReadExt;
 
+use crate::is3::s3_retry::retry_s3;
+
 /// s3_download_to_memory
 ///
 /// download an s3 key and return it as ``Vec[u8]``
 ///
+/// Transient errors are retried with the
+/// [`S3_RETRY_POLICY`](crate::is3::s3_retry::S3_RETRY_POLICY)
+///
 /// credit to source:
 /// <https://github.com/rusoto/rusoto/blob/master/integration_tests/tests/s3.rs#L903-L920>
 ///
@@ -37,32 +44,34 @@ pub async fn s3_download_to_memory(
     key: &str,
 ) -> Result<Vec<u8>, String> {
     let client = S3Client::new(Region::UsEast2);
-    let get_req = GetObjectRequest {
-        bucket: String::from(bucket),
-        key: String::from(key),
-        ..Default::default()
-    };
 
     info!("s3_download_to_memory s3://{bucket}/{key}");
-    let down_res = match client.get_object(get_req).await {
-        Ok(success_res) => success_res,
-        Err(_) => {
-            return Err(format!("failed to download s3://{bucket}/{key}"));
-        }
-    };
+    // retry the download and the body read together so a
+    // connection dropped mid-stream starts over
+    retry_s3("s3_download_to_memory", "get_object", || async {
+        let get_req = GetObjectRequest {
+            bucket: String::from(bucket),
+            key: String::from(key),
+            ..Default::default()
+        };
+        let down_res = client.get_object(get_req).await?;
 
-    if false {
-        trace!(
-            "s3_download_to_memory(\
-            {bucket}, {key}) => object down_res: {:#?}",
-            down_res
-        );
-    }
-
-    // https://github.com/rusoto/rusoto/blob/master/integration_tests/tests/s3.rs#L922-L940
-    let mut stream = down_res.body.unwrap().into_async_read();
-    let mut s3_contents = Vec::new();
-    stream.read_to_end(&mut s3_contents).await.unwrap();
-
-    Ok(s3_contents)
+        // https://github.com/rusoto/rusoto/blob/master/integration_tests/tests/s3.rs#L922-L940
+        let mut s3_contents = Vec::new();
+        if let Some(body) = down_res.body {
+            body.into_async_read()
+                .read_to_end(&mut s3_contents)
+                .await
+                .map_err(|e| {
+                    RusotoError::HttpDispatch(HttpDispatchError::new(format!(
+                        "failed to read the body with err='{e}'"
+                    )))
+                })?;
+        }
+        Ok(s3_contents)
+    })
+    .await
+    .map_err(|e| {
+        format!("failed to download s3://{bucket}/{key} with err='{e}'")
+    })
 }
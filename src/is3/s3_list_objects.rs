@@ -0,0 +1,88 @@
+//! List the keys under an s3 prefix with the
+//! ``s3_list_objects()`` function
+//!
+use rusoto_core::Region;
+use rusoto_s3::ListObjectsV2Request;
+use rusoto_s3::S3Client;
+use rusoto_s3::S3;
+
+use crate::is3::object_store::ObjectStoreEntry;
+use crate::is3::s3_retry::retry_s3;
+
+/// s3_list_objects
+///
+/// List the keys under a prefix (sorted by key) and follow
+/// the continuation token until ``max_keys`` keys are found
+/// or there are no more pages
+///
+/// Transient errors are retried with the
+/// [`S3_RETRY_POLICY`](crate::is3::s3_retry::S3_RETRY_POLICY)
+///
+/// # Arguments
+///
+/// * `tracking_label` - &str - logging label for the caller
+/// * `bucket` - &str - source bucket
+/// * `prefix` - &str - key prefix
+/// * `max_keys` - usize - stop after this many keys
+///
+/// # Returns
+///
+/// Ok(``Vec<``[`ObjectStoreEntry`](crate::is3::object_store::ObjectStoreEntry)``>``)
+///
+/// # Errors
+///
+/// ``String`` error messages can be returned for many reasons
+/// (connectivity, aws credentials, mfa timeouts, etc.)
+///
+/// Err(err_msg: ``String``)
+///
+pub async fn s3_list_objects(
+    tracking_label: &str,
+    bucket: &str,
+    prefix: &str,
+    max_keys: usize,
+) -> Result<Vec<ObjectStoreEntry>, String> {
+    let client = S3Client::new(Region::UsEast2);
+    let mut entries: Vec<ObjectStoreEntry> = Vec::new();
+    let mut continuation_token: Option<String> = None;
+    info!("{tracking_label} - s3_list_objects s3://{bucket}/{prefix}");
+    while entries.len() < max_keys {
+        // s3 returns at most 1000 keys per page
+        let page_keys = (max_keys - entries.len()).min(1000) as i64;
+        let res = retry_s3(tracking_label, "list_objects", || {
+            client.list_objects_v2(ListObjectsV2Request {
+                bucket: String::from(bucket),
+                prefix: Some(String::from(prefix)),
+                max_keys: Some(page_keys),
+                continuation_token: continuation_token.clone(),
+                ..Default::default()
+            })
+        })
+        .await
+        .map_err(|e| {
+            format!(
+                "{tracking_label} - s3_list_objects - \
+                failed to list s3://{bucket}/{prefix} with err='{e}'"
+            )
+        })?;
+        for object in res.contents.unwrap_or_default() {
+            entries.push(ObjectStoreEntry {
+                key: object.key.unwrap_or_default(),
+                size_in_bytes: object.size.unwrap_or(0),
+                last_modified: object.last_modified.unwrap_or_default(),
+                storage_class: object
+                    .storage_class
+                    .unwrap_or_else(|| "STANDARD".to_string()),
+            });
+        }
+        continuation_token = match res.is_truncated.unwrap_or(false) {
+            true => res.next_continuation_token,
+            false => None,
+        };
+        if continuation_token.is_none() {
+            break;
+        }
+    }
+    entries.truncate(max_keys);
+    Ok(entries)
+}
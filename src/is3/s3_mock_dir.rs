@@ -0,0 +1,144 @@
+//! Store s3 objects in a local directory instead of s3
+//! when ``DEMO_MODE=1`` with the ``get_s3_mock_dir()``
+//! function
+//!
+//! The
+//! [`LocalDirObjectStore`](crate::is3::object_store::LocalDirObjectStore)
+//! reads and writes ``DEMO_S3_DIR/BUCKET/KEY`` so the server
+//! runs without aws credentials. Keys come from upload
+//! filenames and ``sloc`` values, so every path is checked
+//! with
+//! [`resolve_s3_mock_path`](crate::is3::s3_mock_dir::resolve_s3_mock_path)
+//! and keys that would leave ``DEMO_S3_DIR`` are rejected.
+//!
+
+/// get_s3_mock_dir
+///
+/// Get the local directory used instead of s3
+///
+/// # Supported Environment Variables
+///
+/// ```bash
+/// export DEMO_MODE="1"
+/// export DEMO_S3_DIR="./demo-s3"
+/// ```
+///
+/// # Returns
+///
+/// `Option<String>` - `None` unless ``DEMO_MODE`` is enabled
+///
+pub fn get_s3_mock_dir() -> Option<String> {
+    let demo_mode =
+        std::env::var("DEMO_MODE").unwrap_or_else(|_| "0".to_string());
+    match demo_mode == "1" || demo_mode == "true" {
+        true => Some(
+            std::env::var("DEMO_S3_DIR")
+                .unwrap_or_else(|_| "./demo-s3".to_string()),
+        ),
+        false => None,
+    }
+}
+
+/// get_s3_mock_path
+///
+/// Local file path for an s3 object. The bucket must be a
+/// single path segment and the key cannot have ``.`` or
+/// ``..`` segments, backslashes or nul characters, so the
+/// path always stays inside ``mock_dir``.
+///
+/// # Arguments
+///
+/// * `mock_dir` - `&str` - directory from
+///   [`get_s3_mock_dir`](crate::is3::s3_mock_dir::get_s3_mock_dir)
+/// * `bucket` - `&str` - s3 bucket
+/// * `key` - `&str` - s3 key
+///
+/// # Returns
+///
+/// Ok(path: `String`) - ``mock_dir/bucket/key``
+///
+/// # Errors
+///
+/// Err(err_msg: `String`) if the bucket or key could leave
+/// ``mock_dir``
+///
+/// # Examples
+///
+/// ```rust
+/// use restapi::is3::s3_mock_dir::get_s3_mock_path;
+/// assert_eq!(
+///     get_s3_mock_path("./demo-s3/", "bucket", "/user/1/a.txt").unwrap(),
+///     "./demo-s3/bucket/user/1/a.txt"
+/// );
+/// assert!(get_s3_mock_path("./demo-s3", "bucket", "user/../../etc/passwd").is_err());
+/// assert!(get_s3_mock_path("./demo-s3", "..", "a.txt").is_err());
+/// ```
+///
+pub fn get_s3_mock_path(
+    mock_dir: &str,
+    bucket: &str,
+    key: &str,
+) -> Result<String, String> {
+    let is_unsafe_segment = |segment: &str| {
+        segment == "." || segment == ".." || segment.contains(['\\', '\0'])
+    };
+    if bucket.is_empty() || bucket.contains('/') || is_unsafe_segment(bucket) {
+        return Err(format!("invalid mock s3 bucket={bucket}"));
+    }
+    let key = key.trim_start_matches('/');
+    if key.split('/').any(is_unsafe_segment) {
+        return Err(format!("invalid mock s3 key={key}"));
+    }
+    Ok(format!("{}/{bucket}/{key}", mock_dir.trim_end_matches('/')))
+}
+
+/// resolve_s3_mock_path
+///
+/// Build the local file path for an s3 object with
+/// [`get_s3_mock_path`](crate::is3::s3_mock_dir::get_s3_mock_path)
+/// and canonicalize the deepest existing directory on the
+/// path so a symlink inside ``mock_dir`` cannot point the
+/// file somewhere else. Creates ``mock_dir`` if it does not
+/// exist.
+///
+/// # Arguments
+///
+/// * `mock_dir` - `&str` - directory from
+///   [`get_s3_mock_dir`](crate::is3::s3_mock_dir::get_s3_mock_dir)
+/// * `bucket` - `&str` - s3 bucket
+/// * `key` - `&str` - s3 key
+///
+/// # Returns
+///
+/// Ok(path: `String`) - ``mock_dir/bucket/key``
+///
+/// # Errors
+///
+/// Err(err_msg: `String`) if the path is invalid or resolves
+/// outside of ``mock_dir``
+///
+pub async fn resolve_s3_mock_path(
+    mock_dir: &str,
+    bucket: &str,
+    key: &str,
+) -> Result<String, String> {
+    let path = get_s3_mock_path(mock_dir, bucket, key)?;
+    tokio::fs::create_dir_all(mock_dir).await.map_err(|e| {
+        format!("failed to create mock s3 dir {mock_dir} with err='{e}'")
+    })?;
+    let root = tokio::fs::canonicalize(mock_dir).await.map_err(|e| {
+        format!("failed to resolve mock s3 dir {mock_dir} with err='{e}'")
+    })?;
+    for ancestor in std::path::Path::new(&path).ancestors() {
+        if let Ok(resolved) = tokio::fs::canonicalize(ancestor).await {
+            return match resolved.starts_with(&root) {
+                true => Ok(path),
+                false => Err(format!(
+                    "mock s3 path for s3://{bucket}/{key} resolves \
+                    outside of {mock_dir}"
+                )),
+            };
+        }
+    }
+    Err(format!("failed to resolve mock s3 path {path}"))
+}
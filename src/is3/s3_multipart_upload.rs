@@ -0,0 +1,271 @@
+//! Manage an s3 multipart upload one part at a time (for
+//! resumable uploads) with the functions:
+//!
+//! - ``s3_create_multipart_upload()``
+//! - ``s3_upload_part()``
+//! - ``s3_complete_multipart_upload()``
+//! - ``s3_abort_multipart_upload()``
+//!
+//! Creating the upload, uploading a part and completing the
+//! upload are retried with the
+//! [`S3_RETRY_POLICY`](crate::is3::s3_retry::S3_RETRY_POLICY)
+//! when they fail with a transient error.
+//!
+use rusoto_core::Region;
+use rusoto_s3::AbortMultipartUploadRequest;
+use rusoto_s3::CompleteMultipartUploadRequest;
+use rusoto_s3::CompletedMultipartUpload;
+use rusoto_s3::CompletedPart;
+use rusoto_s3::CreateMultipartUploadRequest;
+use rusoto_s3::S3Client;
+use rusoto_s3::UploadPartRequest;
+use rusoto_s3::S3;
+
+use crate::is3::s3_retry::retry_s3;
+
+/// s3_create_multipart_upload
+///
+/// Start an s3 multipart upload with server-side encryption
+///
+/// # Usage
+///
+/// Change the default s3 storage class (used when
+/// ``storage_class`` is ``None``) with:
+///
+/// ```bash
+/// export S3_STORAGE_CLASS=STANDARD
+/// ```
+///
+/// # Arguments
+///
+/// * `tracking_label` - &str - logging label for the caller
+/// * `bucket` - &str - destination bucket
+/// * `key` - &str - destination key location
+/// * `storage_class` - Option<&str> - s3 storage class for
+///   the key (``None`` = ``S3_STORAGE_CLASS``)
+///
+/// # Returns
+///
+/// Ok(upload_id: `String`)
+///
+/// # Errors
+///
+/// ``String`` error messages can be returned for many reasons
+/// (connectivity, aws credentials, mfa timeouts, etc.)
+///
+/// Err(err_msg: ``String``)
+///
+pub async fn s3_create_multipart_upload(
+    tracking_label: &str,
+    bucket: &str,
+    key: &str,
+    storage_class: Option<&str>,
+) -> Result<String, String> {
+    let storage_class = match storage_class {
+        Some(storage_class) => storage_class.to_string(),
+        None => std::env::var("S3_STORAGE_CLASS")
+            .unwrap_or_else(|_| "STANDARD".to_string()),
+    };
+    let client = S3Client::new(Region::UsEast2);
+    let create_req = CreateMultipartUploadRequest {
+        bucket: String::from(bucket),
+        key: String::from(key),
+        server_side_encryption: Some("AES256".to_string()),
+        storage_class: Some(storage_class.clone()),
+        ..Default::default()
+    };
+    match retry_s3(tracking_label, "create_multipart_upload", || {
+        client.create_multipart_upload(create_req.clone())
+    })
+    .await
+    {
+        Ok(res) => match res.upload_id {
+            Some(upload_id) => {
+                info!(
+                    "{tracking_label} - s3_create_multipart_upload - \
+                    done - s3://{bucket}/{key} sc={storage_class}"
+                );
+                Ok(upload_id)
+            }
+            None => Err(format!(
+                "{tracking_label} - s3_create_multipart_upload - \
+                s3://{bucket}/{key} did not return an upload_id"
+            )),
+        },
+        Err(e) => Err(format!(
+            "{tracking_label} - s3_create_multipart_upload - \
+            failed to create s3://{bucket}/{key} with err='{e}'"
+        )),
+    }
+}
+
+/// s3_upload_part
+///
+/// Upload one part of an s3 multipart upload
+///
+/// # Arguments
+///
+/// * `tracking_label` - &str - logging label for the caller
+/// * `bucket` - &str - destination bucket
+/// * `key` - &str - destination key location
+/// * `upload_id` - &str - multipart upload id from
+///   [`s3_create_multipart_upload`](crate::is3::s3_multipart_upload::s3_create_multipart_upload)
+/// * `part_number` - i64 - part number (``1`` to ``10000``)
+/// * `bytes` - &[u8] - part contents
+///
+/// # Returns
+///
+/// Ok(etag: `String`)
+///
+/// # Errors
+///
+/// Err(err_msg: ``String``)
+///
+pub async fn s3_upload_part(
+    tracking_label: &str,
+    bucket: &str,
+    key: &str,
+    upload_id: &str,
+    part_number: i64,
+    bytes: &[u8],
+) -> Result<String, String> {
+    let client = S3Client::new(Region::UsEast2);
+    match retry_s3(tracking_label, "upload_part", || {
+        client.upload_part(UploadPartRequest {
+            body: Some(bytes.to_vec().into()),
+            bucket: String::from(bucket),
+            key: String::from(key),
+            upload_id: String::from(upload_id),
+            part_number,
+            ..Default::default()
+        })
+    })
+    .await
+    {
+        Ok(res) => match res.e_tag {
+            Some(etag) => Ok(etag),
+            None => Err(format!(
+                "{tracking_label} - s3_upload_part - \
+                part={part_number} for s3://{bucket}/{key} did not \
+                return an etag"
+            )),
+        },
+        Err(e) => Err(format!(
+            "{tracking_label} - s3_upload_part - \
+            failed to upload part={part_number} for \
+            s3://{bucket}/{key} with err='{e}'"
+        )),
+    }
+}
+
+/// s3_complete_multipart_upload
+///
+/// Combine the uploaded parts into the s3 key
+///
+/// # Arguments
+///
+/// * `tracking_label` - &str - logging label for the caller
+/// * `bucket` - &str - destination bucket
+/// * `key` - &str - destination key location
+/// * `upload_id` - &str - multipart upload id
+/// * `parts` - &[(i64, String)] - uploaded
+///   ``(part_number, etag)`` values in order
+///
+/// # Returns
+///
+/// Ok(success_msg: `String`)
+///
+/// # Errors
+///
+/// Err(err_msg: ``String``)
+///
+pub async fn s3_complete_multipart_upload(
+    tracking_label: &str,
+    bucket: &str,
+    key: &str,
+    upload_id: &str,
+    parts: &[(i64, String)],
+) -> Result<String, String> {
+    let client = S3Client::new(Region::UsEast2);
+    let completed_parts: Vec<CompletedPart> = parts
+        .iter()
+        .map(|(part_number, etag)| CompletedPart {
+            e_tag: Some(etag.clone()),
+            part_number: Some(*part_number),
+        })
+        .collect();
+    let complete_req = CompleteMultipartUploadRequest {
+        bucket: String::from(bucket),
+        key: String::from(key),
+        upload_id: String::from(upload_id),
+        multipart_upload: Some(CompletedMultipartUpload {
+            parts: Some(completed_parts),
+        }),
+        ..Default::default()
+    };
+    match retry_s3(tracking_label, "complete_multipart_upload", || {
+        client.complete_multipart_upload(complete_req.clone())
+    })
+    .await
+    {
+        Ok(_) => {
+            info!(
+                "{tracking_label} - s3_complete_multipart_upload - \
+                done - s3://{bucket}/{key} parts={}",
+                parts.len()
+            );
+            Ok("Success".to_string())
+        }
+        Err(e) => Err(format!(
+            "{tracking_label} - s3_complete_multipart_upload - \
+            failed to complete s3://{bucket}/{key} with err='{e}'"
+        )),
+    }
+}
+
+/// s3_abort_multipart_upload
+///
+/// Abort a multipart upload and delete its uploaded parts
+///
+/// # Arguments
+///
+/// * `tracking_label` - &str - logging label for the caller
+/// * `bucket` - &str - destination bucket
+/// * `key` - &str - destination key location
+/// * `upload_id` - &str - multipart upload id
+///
+/// # Returns
+///
+/// Ok(success_msg: `String`)
+///
+/// # Errors
+///
+/// Err(err_msg: ``String``)
+///
+pub async fn s3_abort_multipart_upload(
+    tracking_label: &str,
+    bucket: &str,
+    key: &str,
+    upload_id: &str,
+) -> Result<String, String> {
+    let client = S3Client::new(Region::UsEast2);
+    let abort_req = AbortMultipartUploadRequest {
+        bucket: String::from(bucket),
+        key: String::from(key),
+        upload_id: String::from(upload_id),
+        ..Default::default()
+    };
+    match client.abort_multipart_upload(abort_req).await {
+        Ok(_) => {
+            info!(
+                "{tracking_label} - s3_abort_multipart_upload - \
+                done - s3://{bucket}/{key}"
+            );
+            Ok("Success".to_string())
+        }
+        Err(e) => Err(format!(
+            "{tracking_label} - s3_abort_multipart_upload - \
+            failed to abort s3://{bucket}/{key} with err='{e}'"
+        )),
+    }
+}
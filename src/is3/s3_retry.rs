@@ -0,0 +1,248 @@
+//! Retry s3 requests that fail with a transient error
+//!
+//! Connection errors, ``5xx`` responses and throttling
+//! errors (``429``, ``SlowDown``, ``RequestTimeout``) are
+//! retried up to ``S3_RETRY_MAX_ATTEMPTS`` times with an
+//! exponential backoff starting at ``S3_RETRY_BASE_DELAY_MS``
+//! (doubled after each attempt up to
+//! ``S3_RETRY_MAX_DELAY_MS``) and a random jitter, so
+//! concurrent requests do not retry at the same time. Other
+//! errors (like access denied or a missing key) fail right
+//! away.
+//!
+//! Retries are counted in the ``s3_retries_total`` prometheus
+//! metric and the ``s3_request_attempts`` histogram records
+//! how many attempts each request needed.
+//!
+use std::future::Future;
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+
+use rusoto_core::RusotoError;
+
+use crate::monitoring::metric_types::register_histogram_vec;
+use crate::monitoring::metric_types::register_int_counter_vec;
+use crate::monitoring::metric_types::HistogramVec;
+use crate::monitoring::metric_types::IntCounterVec;
+
+lazy_static! {
+    pub static ref S3_RETRY_POLICY: S3RetryPolicy =
+        S3RetryPolicy::build_s3_retry_policy();
+    pub static ref S3_RETRIES_COUNTER_VEC: IntCounterVec =
+        register_int_counter_vec!(
+            "s3_retries_total",
+            "Number of s3 requests retried after a transient error.",
+            &["operation"]
+        )
+        .unwrap();
+    pub static ref S3_ATTEMPTS_HISTO_VEC: HistogramVec =
+        register_histogram_vec!(
+            "s3_request_attempts",
+            "Attempts per s3 request by operation and result.",
+            &["operation", "result"],
+            vec![1.0, 2.0, 3.0, 4.0, 5.0, 7.0, 10.0]
+        )
+        .unwrap();
+}
+
+/// S3RetryPolicy
+///
+/// Retry settings for transient s3 errors
+///
+/// # Supported Environment Variables
+///
+/// ```bash
+/// # attempts per s3 request (1 = no retries)
+/// export S3_RETRY_MAX_ATTEMPTS="3"
+/// export S3_RETRY_BASE_DELAY_MS="100"
+/// export S3_RETRY_MAX_DELAY_MS="5000"
+/// ```
+///
+/// # Arguments
+///
+/// * `max_attempts` - `u32` - attempts per request
+///   including the first one (``1`` to ``10``)
+/// * `base_delay_ms` - `u64` - backoff before the first retry
+/// * `max_delay_ms` - `u64` - longest backoff
+///
+#[derive(Clone, Debug)]
+pub struct S3RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay_ms: u64,
+    pub max_delay_ms: u64,
+}
+
+impl S3RetryPolicy {
+    /// build_s3_retry_policy
+    ///
+    /// Build an
+    /// [`S3RetryPolicy`](crate::is3::s3_retry::S3RetryPolicy)
+    /// from environment variables
+    ///
+    pub fn build_s3_retry_policy() -> Self {
+        let get_env = |key: &str, default: u64| -> u64 {
+            std::env::var(key)
+                .unwrap_or_else(|_| format!("{default}"))
+                .parse::<u64>()
+                .unwrap_or(default)
+        };
+        let base_delay_ms =
+            get_env("S3_RETRY_BASE_DELAY_MS", 100).clamp(1, 60000);
+        S3RetryPolicy {
+            max_attempts: get_env("S3_RETRY_MAX_ATTEMPTS", 3).clamp(1, 10)
+                as u32,
+            base_delay_ms,
+            max_delay_ms: get_env("S3_RETRY_MAX_DELAY_MS", 5000)
+                .clamp(base_delay_ms, 300000),
+        }
+    }
+
+    /// get_backoff_ms
+    ///
+    /// Get the backoff before the next attempt after
+    /// `attempt` failed attempts (without the jitter)
+    ///
+    /// # Arguments
+    ///
+    /// * `attempt` - `u32` - failed attempts starting at ``1``
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use restapi::is3::s3_retry::S3RetryPolicy;
+    /// let policy = S3RetryPolicy {
+    ///     max_attempts: 5,
+    ///     base_delay_ms: 100,
+    ///     max_delay_ms: 500,
+    /// };
+    /// assert_eq!(policy.get_backoff_ms(1), 100);
+    /// assert_eq!(policy.get_backoff_ms(3), 400);
+    /// assert_eq!(policy.get_backoff_ms(4), 500);
+    /// ```
+    ///
+    pub fn get_backoff_ms(&self, attempt: u32) -> u64 {
+        let shift = attempt.saturating_sub(1).min(32);
+        self.base_delay_ms
+            .saturating_mul(1u64 << shift)
+            .min(self.max_delay_ms)
+    }
+
+    /// get_delay_ms
+    ///
+    /// Get the backoff with a random jitter between half and
+    /// all of
+    /// [`get_backoff_ms`](crate::is3::s3_retry::S3RetryPolicy::get_backoff_ms)
+    ///
+    /// # Arguments
+    ///
+    /// * `attempt` - `u32` - failed attempts starting at ``1``
+    ///
+    pub fn get_delay_ms(&self, attempt: u32) -> u64 {
+        let backoff_ms = self.get_backoff_ms(attempt);
+        let mut buf = [0u8; 8];
+        openssl::rand::rand_bytes(&mut buf).unwrap();
+        let half = backoff_ms / 2;
+        half + u64::from_le_bytes(buf) % (backoff_ms - half + 1)
+    }
+}
+
+/// is_retryable_s3_error
+///
+/// Check if an s3 error is transient: a connection error, a
+/// ``5xx`` or ``429`` response, or a ``SlowDown``,
+/// ``Throttling`` or ``RequestTimeout`` error code
+///
+/// # Arguments
+///
+/// * `e` - [`RusotoError`](rusoto_core::RusotoError) - s3 error
+///
+pub fn is_retryable_s3_error<E>(e: &RusotoError<E>) -> bool {
+    match e {
+        RusotoError::HttpDispatch(_) => true,
+        RusotoError::Unknown(res) => {
+            let status = res.status.as_u16();
+            let body = String::from_utf8_lossy(&res.body);
+            status >= 500
+                || status == 429
+                || body.contains("<Code>SlowDown</Code>")
+                || body.contains("<Code>RequestTimeout</Code>")
+                || body.contains("Throttl")
+        }
+        _ => false,
+    }
+}
+
+/// retry_s3
+///
+/// Run an s3 request and retry it with the
+/// [`S3_RETRY_POLICY`](crate::is3::s3_retry::S3_RETRY_POLICY)
+/// while it fails with a transient error
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - logging label for the caller
+/// * `operation` - `&str` - s3 operation for logs and
+///   metrics (``upload_part``)
+/// * `request` - closure that sends a new request for each
+///   attempt
+///
+/// # Errors
+///
+/// The last [`RusotoError`](rusoto_core::RusotoError) when
+/// the error is not transient or every attempt failed
+///
+pub async fn retry_s3<T, E, F, Fut>(
+    tracking_label: &str,
+    operation: &str,
+    mut request: F,
+) -> Result<T, RusotoError<E>>
+where
+    E: std::error::Error + 'static,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, RusotoError<E>>>,
+{
+    let policy = &*S3_RETRY_POLICY;
+    let mut attempt: u32 = 0;
+    loop {
+        attempt += 1;
+        match request().await {
+            Ok(res) => {
+                if attempt > 1 {
+                    info!(
+                        "{tracking_label} - s3 {operation} succeeded \
+                        after {attempt} attempts"
+                    );
+                }
+                S3_ATTEMPTS_HISTO_VEC
+                    .with_label_values(&[operation, "ok"])
+                    .observe(attempt as f64);
+                return Ok(res);
+            }
+            Err(e) => {
+                if attempt >= policy.max_attempts || !is_retryable_s3_error(&e)
+                {
+                    if attempt > 1 {
+                        warn!(
+                            "{tracking_label} - s3 {operation} failed \
+                            after {attempt} attempts with err='{e}'"
+                        );
+                    }
+                    S3_ATTEMPTS_HISTO_VEC
+                        .with_label_values(&[operation, "error"])
+                        .observe(attempt as f64);
+                    return Err(e);
+                }
+                let delay_ms = policy.get_delay_ms(attempt);
+                warn!(
+                    "{tracking_label} - s3 {operation} attempt \
+                    {attempt}/{} failed with err='{e}' - retrying in \
+                    {delay_ms}ms",
+                    policy.max_attempts
+                );
+                S3_RETRIES_COUNTER_VEC.with_label_values(&[operation]).inc();
+                tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+            }
+        }
+    }
+}
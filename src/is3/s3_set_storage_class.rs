@@ -0,0 +1,82 @@
+//! Transition an s3 key to another storage class with the
+//! function: ``s3_set_storage_class()``
+//!
+use rusoto_core::Region;
+use rusoto_s3::CopyObjectRequest;
+use rusoto_s3::S3Client;
+use rusoto_s3::S3;
+
+/// s3_set_storage_class
+///
+/// Change the storage class of an s3 key by copying the
+/// key onto itself (keeps the metadata and server-side
+/// encryption). Keys over 5GB cannot be copied in one
+/// request and must use a bucket lifecycle rule instead.
+///
+/// # Arguments
+///
+/// * `tracking_label` - &str - logging label for the caller
+/// * `bucket` - &str - bucket
+/// * `key` - &str - key location
+/// * `storage_class` - &str - new s3 storage class
+///   (for example ``GLACIER``)
+///
+/// # Returns
+///
+/// Ok(success_msg: `String`)
+///
+/// # Errors
+///
+/// ``String`` error messages can be returned for many reasons
+/// (connectivity, aws credentials, mfa timeouts, etc.)
+///
+/// Err(err_msg: ``String``)
+///
+pub async fn s3_set_storage_class(
+    tracking_label: &str,
+    bucket: &str,
+    key: &str,
+    storage_class: &str,
+) -> Result<String, String> {
+    let client = S3Client::new(Region::UsEast2);
+    // the copy source must be url-encoded
+    let copy_source = format!(
+        "{bucket}/{}",
+        key.bytes()
+            .map(|b| match b {
+                b'A'..=b'Z'
+                | b'a'..=b'z'
+                | b'0'..=b'9'
+                | b'-'
+                | b'_'
+                | b'.'
+                | b'~'
+                | b'/' => (b as char).to_string(),
+                _ => format!("%{b:02X}"),
+            })
+            .collect::<String>()
+    );
+    let copy_req = CopyObjectRequest {
+        bucket: String::from(bucket),
+        key: String::from(key),
+        copy_source,
+        metadata_directive: Some("COPY".to_string()),
+        server_side_encryption: Some("AES256".to_string()),
+        storage_class: Some(storage_class.to_string()),
+        ..Default::default()
+    };
+    match client.copy_object(copy_req).await {
+        Ok(_) => {
+            info!(
+                "{tracking_label} - s3_set_storage_class - done - \
+                s3://{bucket}/{key} sc={storage_class}"
+            );
+            Ok("Success".to_string())
+        }
+        Err(e) => Err(format!(
+            "{tracking_label} - s3_set_storage_class - \
+            failed to set sc={storage_class} on s3://{bucket}/{key} \
+            with err='{e}'"
+        )),
+    }
+}
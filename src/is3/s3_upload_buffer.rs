@@ -6,6 +6,7 @@ use std::sync::Arc;
 use std::sync::Mutex;
 
 use rusoto_core::Region;
+use rusoto_s3::AbortMultipartUploadRequest;
 use rusoto_s3::CompleteMultipartUploadRequest;
 use rusoto_s3::CompletedMultipartUpload;
 use rusoto_s3::CompletedPart;
@@ -14,6 +15,8 @@ use rusoto_s3::S3Client;
 use rusoto_s3::UploadPartRequest;
 use rusoto_s3::S3;
 
+use crate::is3::s3_retry::retry_s3;
+
 /// s3_upload_buffer
 ///
 /// An async upload an in-memory buffer (``&[u8]``)
@@ -25,9 +28,15 @@ use rusoto_s3::S3;
 /// ``multipart`` ``futures`` that are processed asynchronously.
 /// Once the ``futures`` are done, the file is done uploading to s3.
 ///
+/// Each s3 request is retried with the
+/// [`S3_RETRY_POLICY`](crate::is3::s3_retry::S3_RETRY_POLICY)
+/// when it fails with a transient error, and the multipart
+/// upload is aborted when a part still fails.
+///
 /// # Usage
 ///
-/// Change the default s3 storage class with:
+/// Change the default s3 storage class (used when
+/// ``storage_class`` is ``None``) with:
 ///
 /// ```bash
 /// export S3_STORAGE_CLASS=STANDARD
@@ -44,6 +53,8 @@ use rusoto_s3::S3;
 /// * `bucket` - &str - destination bucket
 /// * `key` - &str - destination key location
 /// * `bytes` - &[u8] - buffer to upload into s3
+/// * `storage_class` - Option<&str> - s3 storage class for
+///   the key (``None`` = ``S3_STORAGE_CLASS``)
 ///
 /// # Returns
 ///
@@ -58,29 +69,37 @@ use rusoto_s3::S3;
 ///
 /// # Examples
 ///
-/// ```
-/// use crate::is3::is3::s3_upload_buffer;
-/// let bytes = format!("test-s3-upload-buffer")
-///     .as_bytes()
-///     .to_vec();
-/// match s3_upload_buffer(
+/// ```rust,no_run
+/// use restapi::is3::s3_upload_buffer::s3_upload_buffer;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let bytes = "test-s3-upload-buffer".as_bytes().to_vec();
+///     match s3_upload_buffer(
 ///         "test-s3-upload-buffer",
 ///         "BUCKET",
 ///         "PATH_TO_KEY",
-///         &bytes).await {
-///     Ok(good_msg) => {
-///         info!("{good_msg} - done uploading to s3://{s3_bucket}/{s3_key_dst}")
-///     },
-///     Err(emsg) => {
-///         info!("{emsg} - failed uploading to s3://{s3_bucket}/{s3_key_dst}")
+///         &bytes,
+///         Some("STANDARD_IA"),
+///     )
+///     .await
+///     {
+///         Ok(good_msg) => {
+///             println!("{good_msg} - done uploading to s3://BUCKET/PATH_TO_KEY")
+///         }
+///         Err(emsg) => {
+///             println!("{emsg} - failed uploading to s3://BUCKET/PATH_TO_KEY")
+///         }
 ///     }
 /// }
 /// ```
+///
 pub async fn s3_upload_buffer(
     tracking_label: &str,
     bucket: &str,
     key: &str,
     bytes: &[u8],
+    storage_class: Option<&str>,
 ) -> Result<String, String> {
     // let now = Instant::now();
     let s3_bucket = String::from(bucket);
@@ -88,8 +107,11 @@ pub async fn s3_upload_buffer(
     let s3_bucket_copy = String::from(bucket);
     let s3_key_copy = String::from(key);
     let server_side_encryption = "AES256";
-    let storage_class = std::env::var("S3_STORAGE_CLASS")
-        .unwrap_or_else(|_| "STANDARD".to_string());
+    let storage_class = match storage_class {
+        Some(storage_class) => storage_class.to_string(),
+        None => std::env::var("S3_STORAGE_CLASS")
+            .unwrap_or_else(|_| "STANDARD".to_string()),
+    };
 
     let upload_size_in_bytes = bytes.len();
     let upload_size_in_mb: f32 = upload_size_in_bytes as f32 / 1024.0 / 1024.0;
@@ -121,8 +143,10 @@ pub async fn s3_upload_buffer(
     };
 
     // Start the multipart upload and note the upload_id generated
-    let create_response_result = client
-        .create_multipart_upload(create_multipart_request)
+    let create_response_result =
+        retry_s3(tracking_label, "create_multipart_upload", || {
+            client.create_multipart_upload(create_multipart_request.clone())
+        })
         .await;
 
     info!(
@@ -195,26 +219,57 @@ pub async fn s3_upload_buffer(
         let data_to_send: Vec<u8> = buffer.to_vec();
         let completed_parts_cloned = completed_parts.clone();
         let create_upload_part_arc_cloned = create_upload_part_arc.clone();
+        let part_label = tracking_label.to_string();
         let send_part_task_future = tokio::task::spawn(async move {
-            let part = create_upload_part_arc_cloned(data_to_send, part_number);
-            {
-                let part_number = part.part_number;
-                let internal_loop_client = S3Client::new(Region::UsEast2);
-                let response = internal_loop_client.upload_part(part).await;
-                completed_parts_cloned.lock().unwrap().push(CompletedPart {
-                    e_tag: response
-                        .expect("Couldn't complete multipart upload")
-                        .e_tag,
-                    part_number: Some(part_number),
-                });
-            }
+            let internal_loop_client = S3Client::new(Region::UsEast2);
+            let response = retry_s3(&part_label, "upload_part", || {
+                let part = create_upload_part_arc_cloned(
+                    data_to_send.clone(),
+                    part_number,
+                );
+                internal_loop_client.upload_part(part)
+            })
+            .await
+            .map_err(|e| format!("part={part_number} failed with err='{e}'"))?;
+            completed_parts_cloned.lock().unwrap().push(CompletedPart {
+                e_tag: response.e_tag,
+                part_number: Some(part_number),
+            });
+            Ok::<(), String>(())
         });
         multiple_parts_futures.push(send_part_task_future);
         part_number += 1;
     }
     let final_client = S3Client::new(Region::UsEast2);
     // println!("waiting for futures");
-    let _results = futures::future::join_all(multiple_parts_futures).await;
+    let results = futures::future::join_all(multiple_parts_futures).await;
+    let part_errors: Vec<String> = results
+        .into_iter()
+        .filter_map(|res| match res {
+            Ok(Ok(())) => None,
+            Ok(Err(err_msg)) => Some(err_msg),
+            Err(e) => Some(format!("{e}")),
+        })
+        .collect();
+    if !part_errors.is_empty() {
+        let abort_req = AbortMultipartUploadRequest {
+            bucket: String::from(bucket),
+            key: String::from(key),
+            upload_id: upload_id.to_owned(),
+            ..Default::default()
+        };
+        if let Err(e) = final_client.abort_multipart_upload(abort_req).await {
+            warn!(
+                "{tracking_label} - s3_upload_buffer - failed to abort \
+                the multipart upload for s3://{bucket}/{key} with err='{e}'"
+            );
+        }
+        return Err(format!(
+            "{tracking_label} - s3_upload_buffer - \
+            failed to upload s3://{bucket}/{key} with err='{}'",
+            part_errors.join(", ")
+        ));
+    }
 
     let mut completed_parts_vector = completed_parts.lock().unwrap().to_vec();
     completed_parts_vector.sort_by_key(|part| part.part_number);
@@ -244,10 +299,17 @@ pub async fn s3_upload_buffer(
             chunk_size);
     }
     */
-    final_client
-        .complete_multipart_upload(complete_req)
+    if let Err(e) =
+        retry_s3(tracking_label, "complete_multipart_upload", || {
+            final_client.complete_multipart_upload(complete_req.clone())
+        })
         .await
-        .expect("Couldn't complete multipart upload");
+    {
+        return Err(format!(
+            "{tracking_label} - s3_upload_buffer - \
+            failed to complete s3://{bucket}/{key} with err='{e}'"
+        ));
+    }
 
     info!(
         "{tracking_label} - s3_upload_buffer - done - \
@@ -45,6 +45,12 @@
 //! export TOKEN_ALGO_PUBLIC_KEY="${TOKEN_ALGO_KEY_DIR}/public-key.pem"
 //! ```
 //!
+//! ### JWT Key Rotation
+//!
+//! Rotated keys are loaded by key id (``kid``) from
+//! ``SERVER_PKI_DIR_JWT`` - see
+//! [`jwt_keys`](crate::jwt::jwt_keys)
+//!
 //! generate your own jwt keys with these commands (bash)
 //!
 //! ```bash
@@ -61,6 +67,7 @@ use std::time::SystemTime;
 use std::time::UNIX_EPOCH;
 
 use jsonwebtoken::decode;
+use jsonwebtoken::decode_header;
 use jsonwebtoken::encode;
 use jsonwebtoken::errors::ErrorKind;
 use jsonwebtoken::Algorithm;
@@ -70,6 +77,13 @@ use jsonwebtoken::Header;
 use jsonwebtoken::TokenData;
 use jsonwebtoken::Validation;
 
+use crate::jwt::jwt_keys::JwtKeys;
+use crate::jwt::token_claims::remove_reserved_token_claims;
+use crate::jwt::token_claims::TokenCustomClaims;
+
+/// end of the validation error for an expired jwt
+pub const TOKEN_EXPIRED_ERR: &str = "token expired - need to refresh";
+
 /// TokenClaim
 ///
 /// custom claim contained in the signed jwt
@@ -82,12 +96,21 @@ use jsonwebtoken::Validation;
 /// * `sub` - String - custom, unique identifier
 /// * `org` - String - custom, unique org identifier
 /// * `exp` - usize - epoch time when the token expires
+/// * `scope` - String - space-separated token scopes (empty =
+///   an unscoped token, see
+///   [`token_scopes`](crate::requests::auth::token_scopes))
+/// * `claims` - [`TokenCustomClaims`](crate::jwt::token_claims::TokenCustomClaims) -
+///   custom claims (see [`token_claims`](crate::jwt::token_claims))
 ///
 #[derive(Debug, Serialize, Deserialize, Default)]
 pub struct TokenClaim {
     pub sub: String,
     pub org: String,
     pub exp: usize,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub scope: String,
+    #[serde(flatten)]
+    pub claims: TokenCustomClaims,
 }
 
 /// validate_token
@@ -162,9 +185,7 @@ pub async fn validate_token(
                 return Err(format!("{label} - token issuer is invalid"));
             }
             ErrorKind::ExpiredSignature => {
-                return Err(format!(
-                    "{label} - token expired - need to refresh"
-                ));
+                return Err(format!("{label} - {TOKEN_EXPIRED_ERR}"));
             }
             _ => {
                 return Err(format!(
@@ -177,6 +198,60 @@ pub async fn validate_token(
     Ok(token_data)
 }
 
+/// validate_token_with_keys
+///
+/// validate a decoded jwt token using the public key
+/// that matches the ``kid`` in the token's header
+/// from the
+/// [`JwtKeys`](crate::jwt::jwt_keys::JwtKeys)
+/// key ring (tokens without a ``kid`` use the
+/// ``default`` key)
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `token` - `&str` - the client's jwt
+/// * `uid` - `&str` - unique identifier the token must be for
+/// * `jwt_keys` - [`JwtKeys`](crate::jwt::jwt_keys::JwtKeys) -
+///   loaded public keys by kid
+///
+/// # Returns
+///
+/// ## validate_token_with_keys on Success Returns
+///
+/// Ok([`TokenData`](jsonwebtoken::TokenData))
+///
+/// # Errors
+///
+/// ## validate_token_with_keys on Failure Returns
+///
+/// Err(err_msg: `String`) for an invalid token or an unknown ``kid``
+///
+pub async fn validate_token_with_keys(
+    tracking_label: &str,
+    token: &str,
+    uid: &str,
+    jwt_keys: &JwtKeys,
+) -> Result<TokenData<TokenClaim>, String> {
+    let header = match decode_header(token) {
+        Ok(header) => header,
+        Err(_) => {
+            return Err(format!("{tracking_label} - token was invalid"));
+        }
+    };
+    let decoding_key_bytes =
+        match jwt_keys.get_decoding_key_bytes(header.kid.as_deref()) {
+            Some(decoding_key_bytes) => decoding_key_bytes,
+            None => {
+                return Err(format!(
+                    "{tracking_label} - token kid={:?} is not a known key",
+                    header.kid
+                ));
+            }
+        };
+    validate_token(tracking_label, token, uid, decoding_key_bytes).await
+}
+
 /// get_current_timestamp
 ///
 /// get the current unix epoch time as a ``usize``
@@ -275,6 +350,138 @@ pub async fn create_token(
     tracking_label: &str,
     uid: &str,
     encoding_key_bytes: &[u8],
+) -> Result<String, String> {
+    create_token_with_kid(tracking_label, uid, None, encoding_key_bytes).await
+}
+
+/// create_token_with_kid
+///
+/// create a
+/// [`TokenClaim`](crate::jwt::api::TokenClaim)
+/// and sign it using the algorithm:
+/// [`ES256`](jsonwebtoken::Algorithm)
+/// with the ``kid`` set in the jwt header so the
+/// token can be validated after the signing key rotates
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - logging label for the caller
+/// * `uid` - `&str` - unique identifier for this application
+/// * `kid` - `Option<&str>` - key id for the `encoding_key_bytes`
+/// * `encoding_key_bytes` - `&[u8]` - jwt key
+///   contents in bytes
+///
+/// # Returns
+///
+/// Ok(token: `String`)
+///
+/// # Errors
+///
+/// ## create_token_with_kid on Failure Returns
+///
+/// Err(err_msg: `String`)
+///
+pub async fn create_token_with_kid(
+    tracking_label: &str,
+    uid: &str,
+    kid: Option<&str>,
+    encoding_key_bytes: &[u8],
+) -> Result<String, String> {
+    create_token_with_claims(
+        tracking_label,
+        uid,
+        kid,
+        TokenCustomClaims::new(),
+        encoding_key_bytes,
+    )
+    .await
+}
+
+/// create_token_with_claims
+///
+/// create a
+/// [`TokenClaim`](crate::jwt::api::TokenClaim)
+/// with custom claims and sign it using the algorithm:
+/// [`ES256`](jsonwebtoken::Algorithm)
+/// with the ``kid`` set in the jwt header
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - logging label for the caller
+/// * `uid` - `&str` - unique identifier for this application
+/// * `kid` - `Option<&str>` - key id for the `encoding_key_bytes`
+/// * `claims` - [`TokenCustomClaims`](crate::jwt::token_claims::TokenCustomClaims) -
+///   custom claims to add (reserved claims are ignored)
+/// * `encoding_key_bytes` - `&[u8]` - jwt key
+///   contents in bytes
+///
+/// # Returns
+///
+/// Ok(token: `String`)
+///
+/// # Errors
+///
+/// ## create_token_with_claims on Failure Returns
+///
+/// Err(err_msg: `String`)
+///
+pub async fn create_token_with_claims(
+    tracking_label: &str,
+    uid: &str,
+    kid: Option<&str>,
+    claims: TokenCustomClaims,
+    encoding_key_bytes: &[u8],
+) -> Result<String, String> {
+    create_token_with_scope(
+        tracking_label,
+        uid,
+        kid,
+        claims,
+        "",
+        encoding_key_bytes,
+    )
+    .await
+}
+
+/// create_token_with_scope
+///
+/// create a
+/// [`TokenClaim`](crate::jwt::api::TokenClaim)
+/// with custom claims and a ``scope`` claim and sign it
+/// using the algorithm:
+/// [`ES256`](jsonwebtoken::Algorithm)
+/// with the ``kid`` set in the jwt header
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - logging label for the caller
+/// * `uid` - `&str` - unique identifier for this application
+/// * `kid` - `Option<&str>` - key id for the `encoding_key_bytes`
+/// * `claims` - [`TokenCustomClaims`](crate::jwt::token_claims::TokenCustomClaims) -
+///   custom claims to add (reserved claims are ignored)
+/// * `scope` - `&str` - space-separated token scopes from
+///   [`build_scope_claim`](crate::requests::auth::token_scopes::build_scope_claim)
+///   (empty = an unscoped token)
+/// * `encoding_key_bytes` - `&[u8]` - jwt key
+///   contents in bytes
+///
+/// # Returns
+///
+/// Ok(token: `String`)
+///
+/// # Errors
+///
+/// ## create_token_with_scope on Failure Returns
+///
+/// Err(err_msg: `String`)
+///
+pub async fn create_token_with_scope(
+    tracking_label: &str,
+    uid: &str,
+    kid: Option<&str>,
+    claims: TokenCustomClaims,
+    scope: &str,
+    encoding_key_bytes: &[u8],
 ) -> Result<String, String> {
     // env vars for these
     let token_org = get_token_org();
@@ -285,10 +492,14 @@ pub async fn create_token(
         sub: uid.to_string(),
         org: token_org,
         exp: token_expiration,
+        scope: scope.to_string(),
+        claims: remove_reserved_token_claims(tracking_label, claims),
     };
 
+    let mut header = Header::new(Algorithm::ES256);
+    header.kid = kid.map(|kid| kid.to_string());
     let token = match encode(
-        &Header::new(Algorithm::ES256),
+        &header,
         &access_claim,
         &EncodingKey::from_ec_pem(encoding_key_bytes).unwrap(),
     ) {
@@ -0,0 +1,97 @@
+//! Convert the loaded jwt public keys into a
+//! JSON Web Key Set (JWKS)
+//!
+use jsonwebtoken::jwk::AlgorithmParameters;
+use jsonwebtoken::jwk::CommonParameters;
+use jsonwebtoken::jwk::EllipticCurve;
+use jsonwebtoken::jwk::EllipticCurveKeyParameters;
+use jsonwebtoken::jwk::EllipticCurveKeyType;
+use jsonwebtoken::jwk::Jwk;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::jwk::PublicKeyUse;
+use jsonwebtoken::Algorithm;
+
+use openssl::bn::BigNum;
+use openssl::bn::BigNumContext;
+use openssl::ec::EcKey;
+
+use crate::jwt::jwt_keys::JwtKeys;
+
+/// build_jwks
+///
+/// Build a
+/// [`JwkSet`](jsonwebtoken::jwk::JwkSet)
+/// with one ``ES256`` (``P-256``) key per ``kid`` in the
+/// [`JwtKeys`](crate::jwt::jwt_keys::JwtKeys)
+/// key ring. Keys are sorted by ``kid``.
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `jwt_keys` - [`JwtKeys`](crate::jwt::jwt_keys::JwtKeys) -
+///   loaded public keys by kid
+///
+/// # Returns
+///
+/// Ok([`JwkSet`](jsonwebtoken::jwk::JwkSet))
+///
+/// # Errors
+///
+/// Err(err_msg: `String`) if a public key is not a valid
+/// ec pem
+///
+pub fn build_jwks(
+    tracking_label: &str,
+    jwt_keys: &JwtKeys,
+) -> Result<JwkSet, String> {
+    let mut kids: Vec<&String> = jwt_keys.decoding_keys.keys().collect();
+    kids.sort();
+    let mut keys: Vec<Jwk> = Vec::with_capacity(kids.len());
+    for kid in kids {
+        let public_key_pem = &jwt_keys.decoding_keys[kid];
+        let ec_key = match EcKey::public_key_from_pem(public_key_pem) {
+            Ok(ec_key) => ec_key,
+            Err(e) => {
+                return Err(format!(
+                    "{tracking_label} - \
+                    failed to parse jwt public key kid={kid} with err='{e}'"
+                ));
+            }
+        };
+        let mut ctx = BigNumContext::new().unwrap();
+        let mut x = BigNum::new().unwrap();
+        let mut y = BigNum::new().unwrap();
+        if let Err(e) = ec_key.public_key().affine_coordinates_gfp(
+            ec_key.group(),
+            &mut x,
+            &mut y,
+            &mut ctx,
+        ) {
+            return Err(format!(
+                "{tracking_label} - \
+                failed to get jwt public key coordinates kid={kid} \
+                with err='{e}'"
+            ));
+        }
+        // P-256 coordinates are always 32 bytes
+        let x_bytes = x.to_vec_padded(32).unwrap();
+        let y_bytes = y.to_vec_padded(32).unwrap();
+        keys.push(Jwk {
+            common: CommonParameters {
+                public_key_use: Some(PublicKeyUse::Signature),
+                algorithm: Some(Algorithm::ES256),
+                key_id: Some(kid.to_string()),
+                ..Default::default()
+            },
+            algorithm: AlgorithmParameters::EllipticCurve(
+                EllipticCurveKeyParameters {
+                    key_type: EllipticCurveKeyType::EC,
+                    curve: EllipticCurve::P256,
+                    x: base64::encode_config(x_bytes, base64::URL_SAFE_NO_PAD),
+                    y: base64::encode_config(y_bytes, base64::URL_SAFE_NO_PAD),
+                },
+            ),
+        });
+    }
+    Ok(JwkSet { keys })
+}
@@ -0,0 +1,678 @@
+//! # JWT key ring for signing and validating tokens by key id (kid)
+//!
+//! Tokens are signed with the newest private key and
+//! include its ``kid`` in the jwt header. Tokens are
+//! validated with the public key matching their ``kid``
+//! so previously-issued tokens keep working until they
+//! expire after a new key is rolled out.
+//!
+//! ## Key Layout
+//!
+//! Rotated keys are loaded from ``SERVER_PKI_DIR_JWT``
+//! using the file naming convention:
+//!
+//! ```bash
+//! ${SERVER_PKI_DIR_JWT}/<kid>.private-key-pkcs8.pem
+//! ${SERVER_PKI_DIR_JWT}/<kid>.public-key.pem
+//! ```
+//!
+//! The newest signing key is the private key with the
+//! greatest ``kid`` when sorted (use sortable ids like
+//! ``20221018``). Sorting by name (instead of file
+//! timestamps) keeps every api server in a cluster
+//! signing with the same key.
+//!
+//! The original ``TOKEN_ALGO_PRIVATE_KEY`` and
+//! ``TOKEN_ALGO_PUBLIC_KEY`` files are always loaded with
+//! the ``kid`` of ``default``. Tokens without a ``kid`` are
+//! validated with the ``default`` public key.
+//!
+//! ## Pin the signing key for a blue/green migration
+//!
+//! Admins can pin the signing ``kid`` cluster-wide with the
+//! ``jwt_signing_kid`` runtime setting (or the
+//! ``TOKEN_SIGNING_KID`` environment variable) so a new key
+//! can be deployed to every api server for validation
+//! before any server signs with it. Keys listed in the
+//! ``jwt_retired_kids`` runtime setting (or
+//! ``TOKEN_RETIRED_KIDS``) are no longer accepted or
+//! published in the jwks (see
+//! [`RuntimeSettings`](crate::settings::runtime_settings::RuntimeSettings)).
+//!
+//! ## Encrypted private keys
+//!
+//! Private keys can be passphrase-protected PKCS#8 pem
+//! files (``BEGIN ENCRYPTED PRIVATE KEY``) so the signing
+//! key is not stored unencrypted on disk. Set the
+//! passphrase with ``TOKEN_ALGO_PRIVATE_KEY_PASSPHRASE``
+//! (or the secrets provider key with the same name, see
+//! [`secrets_provider`](crate::secrets::secrets_provider)).
+//! The passphrase is used for the `default` and rotated
+//! private keys:
+//!
+//! ```bash
+//! openssl pkcs8 -topk8 -v2 aes-256-cbc \
+//!     -passout env:TOKEN_ALGO_PRIVATE_KEY_PASSPHRASE \
+//!     -in private-key.pem -out private-key-pkcs8.pem
+//! ```
+//!
+//! ## Reload keys without downtime
+//!
+//! Send a ``SIGHUP`` to the server process to reload the
+//! keys (see
+//! [`reload_jwt_keys_on_sighup`](crate::jwt::reload_jwt_keys::reload_jwt_keys_on_sighup)):
+//!
+//! ```bash
+//! kill -HUP <PID>
+//! ```
+//!
+use std::collections::HashMap;
+
+use jsonwebtoken::DecodingKey;
+use jsonwebtoken::EncodingKey;
+
+use crate::core::startup_error::StartupError;
+
+/// the kid for the ``TOKEN_ALGO_PRIVATE_KEY`` and
+/// ``TOKEN_ALGO_PUBLIC_KEY`` files
+pub const DEFAULT_JWT_KID: &str = "default";
+
+/// JwtKeys
+///
+/// Key ring for signing new tokens and validating
+/// tokens signed by any loaded key
+///
+/// # Arguments
+///
+/// * `signing_kid` - `String` - kid for the newest private key
+/// * `encoding_key_bytes` - `Vec<u8>` - newest private key contents
+/// * `encoding_keys` - `HashMap<String, Vec<u8>>` - private key
+///   contents by kid (only kids with a matching public key)
+/// * `decoding_keys` - `HashMap<String, Vec<u8>>` - public key
+///   contents by kid
+///
+#[derive(Clone, Default)]
+pub struct JwtKeys {
+    pub signing_kid: String,
+    pub encoding_key_bytes: Vec<u8>,
+    pub encoding_keys: HashMap<String, Vec<u8>>,
+    pub decoding_keys: HashMap<String, Vec<u8>>,
+}
+
+impl JwtKeys {
+    /// get_decoding_key_bytes
+    ///
+    /// Get the public key contents for a token's kid
+    /// (tokens without a kid use the `default` key)
+    ///
+    /// # Arguments
+    ///
+    /// * `kid` - `Option<&str>` - kid from the jwt header
+    ///
+    /// # Returns
+    ///
+    /// `Option<&Vec<u8>>` - `None` if the kid is unknown
+    ///
+    pub fn get_decoding_key_bytes(
+        &self,
+        kid: Option<&str>,
+    ) -> Option<&Vec<u8>> {
+        self.decoding_keys.get(kid.unwrap_or(DEFAULT_JWT_KID))
+    }
+
+    /// get_signing_key
+    ///
+    /// Get the kid and private key contents for signing
+    /// new tokens. A pinned kid without a loaded private
+    /// key falls back to the newest key.
+    ///
+    /// # Arguments
+    ///
+    /// * `pinned_kid` - `&str` - kid from the ``jwt_signing_kid``
+    ///   runtime setting (empty uses the newest key)
+    ///
+    /// # Returns
+    ///
+    /// `(String, Vec<u8>)` - signing kid and private key contents
+    ///
+    pub fn get_signing_key(&self, pinned_kid: &str) -> (String, Vec<u8>) {
+        match self.encoding_keys.get(pinned_kid) {
+            Some(encoding_key_bytes) => {
+                (pinned_kid.to_string(), encoding_key_bytes.clone())
+            }
+            None => {
+                if !pinned_kid.is_empty() {
+                    warn!(
+                        "jwt signing kid={pinned_kid} is not loaded - \
+                        signing with kid={}",
+                        self.signing_kid
+                    );
+                }
+                (self.signing_kid.clone(), self.encoding_key_bytes.clone())
+            }
+        }
+    }
+
+    /// without_retired_kids
+    ///
+    /// Copy the key ring without the public keys for
+    /// retired kids so their tokens are rejected
+    ///
+    /// # Arguments
+    ///
+    /// * `retired_kids` - `&[String]` - kids from the
+    ///   ``jwt_retired_kids`` runtime setting
+    ///
+    /// # Returns
+    ///
+    /// [`JwtKeys`](crate::jwt::jwt_keys::JwtKeys)
+    ///
+    pub fn without_retired_kids(&self, retired_kids: &[String]) -> JwtKeys {
+        let mut jwt_keys = self.clone();
+        for kid in retired_kids.iter() {
+            jwt_keys.encoding_keys.remove(kid);
+            jwt_keys.decoding_keys.remove(kid);
+        }
+        jwt_keys
+    }
+}
+
+/// JwtKeyPaths
+///
+/// Where to load the jwt key ring from
+///
+/// # Arguments
+///
+/// * `dir` - `String` - directory with rotated
+///   `<kid>.` prefixed keys (``SERVER_PKI_DIR_JWT``)
+/// * `private_key` - `String` - `default` private key
+///   path (``TOKEN_ALGO_PRIVATE_KEY``)
+/// * `public_key` - `String` - `default` public key
+///   path (``TOKEN_ALGO_PUBLIC_KEY``)
+/// * `private_key_passphrase` - `String` - passphrase for
+///   encrypted private keys
+///   (``TOKEN_ALGO_PRIVATE_KEY_PASSPHRASE``, redacted in
+///   debug output)
+///
+#[derive(Clone, Default)]
+pub struct JwtKeyPaths {
+    pub dir: String,
+    pub private_key: String,
+    pub public_key: String,
+    pub private_key_passphrase: String,
+}
+
+impl std::fmt::Debug for JwtKeyPaths {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JwtKeyPaths")
+            .field("dir", &self.dir)
+            .field("private_key", &self.private_key)
+            .field("public_key", &self.public_key)
+            .field(
+                "private_key_passphrase",
+                &match self.private_key_passphrase.is_empty() {
+                    true => "",
+                    false => "REDACTED",
+                },
+            )
+            .finish()
+    }
+}
+
+impl JwtKeyPaths {
+    /// build_jwt_key_paths
+    ///
+    /// Build the
+    /// [`JwtKeyPaths`](crate::jwt::jwt_keys::JwtKeyPaths)
+    /// from the arguments and fall back to the
+    /// environment variables for any `None` argument
+    ///
+    /// ```bash
+    /// export SERVER_PKI_DIR_JWT="./jwt"
+    /// export TOKEN_ALGO_PRIVATE_KEY="${SERVER_PKI_DIR_JWT}/private-key-pkcs8.pem"
+    /// export TOKEN_ALGO_PUBLIC_KEY="${SERVER_PKI_DIR_JWT}/public-key.pem"
+    /// # only for encrypted private keys
+    /// export TOKEN_ALGO_PRIVATE_KEY_PASSPHRASE=""
+    /// ```
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - `Option<&str>` - key directory
+    /// * `private_key` - `Option<&str>` - `default` private key path
+    /// * `public_key` - `Option<&str>` - `default` public key path
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use restapi::jwt::jwt_keys::JwtKeyPaths;
+    /// let jwt_key_paths =
+    ///     JwtKeyPaths::build_jwt_key_paths(Some("/keys"), None, None);
+    /// assert_eq!(jwt_key_paths.dir, "/keys");
+    /// ```
+    ///
+    pub fn build_jwt_key_paths(
+        dir: Option<&str>,
+        private_key: Option<&str>,
+        public_key: Option<&str>,
+    ) -> Self {
+        let dir = dir.map(|v| v.to_string()).unwrap_or_else(|| {
+            std::env::var("SERVER_PKI_DIR_JWT")
+                .unwrap_or_else(|_| "./jwt".to_string())
+        });
+        let private_key =
+            private_key.map(|v| v.to_string()).unwrap_or_else(|| {
+                std::env::var("TOKEN_ALGO_PRIVATE_KEY")
+                    .unwrap_or_else(|_| format!("{dir}/private-key-pkcs8.pem"))
+            });
+        let public_key =
+            public_key.map(|v| v.to_string()).unwrap_or_else(|| {
+                std::env::var("TOKEN_ALGO_PUBLIC_KEY")
+                    .unwrap_or_else(|_| format!("{dir}/public-key.pem"))
+            });
+        JwtKeyPaths {
+            dir,
+            private_key,
+            public_key,
+            private_key_passphrase: std::env::var(
+                "TOKEN_ALGO_PRIVATE_KEY_PASSPHRASE",
+            )
+            .unwrap_or_default(),
+        }
+    }
+}
+
+/// load_jwt_keys
+///
+/// Load the `default` jwt keys and any rotated
+/// `<kid>.` prefixed keys in ``SERVER_PKI_DIR_JWT``
+///
+/// # Environment Variables
+///
+/// ```bash
+/// export SERVER_PKI_DIR_JWT="./jwt"
+/// export TOKEN_ALGO_PRIVATE_KEY="${SERVER_PKI_DIR_JWT}/private-key-pkcs8.pem"
+/// export TOKEN_ALGO_PUBLIC_KEY="${SERVER_PKI_DIR_JWT}/public-key.pem"
+/// ```
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+///
+/// # Returns
+///
+/// Ok([`JwtKeys`](crate::jwt::jwt_keys::JwtKeys))
+///
+/// # Errors
+///
+/// Err([`StartupError`](crate::core::startup_error::StartupError))
+/// if the `default` keys cannot be read or are not valid
+/// ES256 pem keys
+///
+pub fn load_jwt_keys(tracking_label: &str) -> Result<JwtKeys, StartupError> {
+    load_jwt_keys_from_paths(
+        tracking_label,
+        &JwtKeyPaths::build_jwt_key_paths(None, None, None),
+    )
+}
+
+/// load_jwt_keys_from_paths
+///
+/// Load the `default` jwt keys and any rotated
+/// `<kid>.` prefixed keys from the
+/// [`JwtKeyPaths`](crate::jwt::jwt_keys::JwtKeyPaths)
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `jwt_key_paths` - [`JwtKeyPaths`](crate::jwt::jwt_keys::JwtKeyPaths) -
+///   key directory and `default` key paths
+///
+/// # Returns
+///
+/// Ok([`JwtKeys`](crate::jwt::jwt_keys::JwtKeys))
+///
+/// # Errors
+///
+/// Err([`StartupError`](crate::core::startup_error::StartupError))
+/// if the `default` keys cannot be read or are not valid
+/// ES256 pem keys
+///
+pub fn load_jwt_keys_from_paths(
+    tracking_label: &str,
+    jwt_key_paths: &JwtKeyPaths,
+) -> Result<JwtKeys, StartupError> {
+    let (default_private_key, default_public_key) =
+        match read_default_jwt_keys(jwt_key_paths) {
+            Ok(keys) => keys,
+            Err(mut errors) => {
+                let e = errors.remove(0);
+                error!("{tracking_label} - {e}");
+                return Err(e);
+            }
+        };
+    load_jwt_keys_with_default_keys(
+        tracking_label,
+        jwt_key_paths,
+        default_private_key,
+        default_public_key,
+    )
+}
+
+/// load_jwt_keys_with_default_keys
+///
+/// Load any rotated `<kid>.` prefixed keys from the
+/// [`JwtKeyPaths`](crate::jwt::jwt_keys::JwtKeyPaths)
+/// directory with `default` keys that were already read
+/// (for example from a
+/// [`SecretsProvider`](crate::secrets::secrets_provider::SecretsProvider))
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `jwt_key_paths` - [`JwtKeyPaths`](crate::jwt::jwt_keys::JwtKeyPaths) -
+///   key directory (the `default` key paths are not read)
+/// * `default_private_key` - `Vec<u8>` - `default` private
+///   key contents
+/// * `default_public_key` - `Vec<u8>` - `default` public
+///   key contents
+///
+/// # Returns
+///
+/// Ok([`JwtKeys`](crate::jwt::jwt_keys::JwtKeys))
+///
+/// # Errors
+///
+/// Err([`StartupError`](crate::core::startup_error::StartupError))
+/// if the `default` keys are not valid ES256 pem keys
+///
+pub fn load_jwt_keys_with_default_keys(
+    tracking_label: &str,
+    jwt_key_paths: &JwtKeyPaths,
+    default_private_key: Vec<u8>,
+    default_public_key: Vec<u8>,
+) -> Result<JwtKeys, StartupError> {
+    let pki_dir_jwt = &jwt_key_paths.dir;
+    let passphrase = &jwt_key_paths.private_key_passphrase;
+    let default_private_key =
+        match decrypt_jwt_private_key(&default_private_key, passphrase) {
+            Ok(key) => key,
+            Err(reason) => {
+                let e = StartupError::InvalidPem {
+                    env_var: "TOKEN_ALGO_PRIVATE_KEY".to_string(),
+                    path: "secret".to_string(),
+                    reason,
+                };
+                error!("{tracking_label} - {e}");
+                return Err(e);
+            }
+        };
+    for (env_var, key, is_private) in [
+        ("TOKEN_ALGO_PRIVATE_KEY", &default_private_key, true),
+        ("TOKEN_ALGO_PUBLIC_KEY", &default_public_key, false),
+    ] {
+        if let Err(reason) = check_jwt_key_pem(key, is_private) {
+            let e = StartupError::InvalidPem {
+                env_var: env_var.to_string(),
+                path: "secret".to_string(),
+                reason,
+            };
+            error!("{tracking_label} - {e}");
+            return Err(e);
+        }
+    }
+
+    let mut jwt_keys = JwtKeys {
+        signing_kid: DEFAULT_JWT_KID.to_string(),
+        encoding_key_bytes: default_private_key.clone(),
+        encoding_keys: HashMap::new(),
+        decoding_keys: HashMap::new(),
+    };
+    jwt_keys
+        .encoding_keys
+        .insert(DEFAULT_JWT_KID.to_string(), default_private_key);
+    jwt_keys
+        .decoding_keys
+        .insert(DEFAULT_JWT_KID.to_string(), default_public_key);
+
+    // load any rotated <kid>.private-key-pkcs8.pem
+    // and <kid>.public-key.pem files
+    let mut private_keys: Vec<(String, Vec<u8>)> = Vec::new();
+    if let Ok(entries) = std::fs::read_dir(pki_dir_jwt) {
+        for entry in entries.flatten() {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            let file_path = entry.path();
+            if let Some(kid) = file_name.strip_suffix(".public-key.pem") {
+                match std::fs::read_to_string(&file_path) {
+                    Ok(v) => {
+                        jwt_keys
+                            .decoding_keys
+                            .insert(kid.to_string(), v.into_bytes());
+                    }
+                    Err(e) => {
+                        error!(
+                            "{tracking_label} - \
+                            failed to read jwt public key={file_path:?} \
+                            with err='{e}'"
+                        );
+                    }
+                }
+            } else if let Some(kid) =
+                file_name.strip_suffix(".private-key-pkcs8.pem")
+            {
+                match std::fs::read(&file_path)
+                    .map_err(|e| format!("{e}"))
+                    .and_then(|v| decrypt_jwt_private_key(&v, passphrase))
+                {
+                    Ok(v) => private_keys.push((kid.to_string(), v)),
+                    Err(e) => {
+                        error!(
+                            "{tracking_label} - \
+                            failed to read jwt private key={file_path:?} \
+                            with err='{e}'"
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    // sign with the newest private key that has a public key
+    private_keys.sort_by(|a, b| a.0.cmp(&b.0));
+    for (kid, private_key) in private_keys.into_iter() {
+        if !jwt_keys.decoding_keys.contains_key(&kid) {
+            error!(
+                "{tracking_label} - \
+                ignoring jwt private key kid={kid} \
+                without a matching {kid}.public-key.pem"
+            );
+            continue;
+        }
+        jwt_keys.signing_kid = kid.clone();
+        jwt_keys.encoding_key_bytes = private_key.clone();
+        jwt_keys.encoding_keys.insert(kid, private_key);
+    }
+
+    info!(
+        "{tracking_label} - loaded {} jwt public keys \
+        signing with kid={}",
+        jwt_keys.decoding_keys.len(),
+        jwt_keys.signing_kid
+    );
+    Ok(jwt_keys)
+}
+
+/// check_jwt_key_files
+///
+/// Check the `default` jwt private and public keys exist
+/// and are valid ES256 pem keys
+///
+/// # Arguments
+///
+/// * `jwt_key_paths` - [`JwtKeyPaths`](crate::jwt::jwt_keys::JwtKeyPaths) -
+///   key directory and `default` key paths
+///
+/// # Returns
+///
+/// `Vec<StartupError>` - one
+/// [`StartupError`](crate::core::startup_error::StartupError)
+/// for each invalid key (empty when both keys are valid)
+///
+pub fn check_jwt_key_files(jwt_key_paths: &JwtKeyPaths) -> Vec<StartupError> {
+    match read_default_jwt_keys(jwt_key_paths) {
+        Ok(_) => Vec::new(),
+        Err(errors) => errors,
+    }
+}
+
+/// read_default_jwt_keys
+///
+/// Read and parse the `default` jwt private and public keys
+///
+fn read_default_jwt_keys(
+    jwt_key_paths: &JwtKeyPaths,
+) -> Result<(Vec<u8>, Vec<u8>), Vec<StartupError>> {
+    let mut errors = Vec::new();
+    let mut read_key = |env_var: &str, path: &str, is_private: bool| {
+        let key = match std::fs::read_to_string(path) {
+            Ok(v) => v.into_bytes(),
+            Err(e) => {
+                errors.push(StartupError::MissingFile {
+                    env_var: env_var.to_string(),
+                    path: path.to_string(),
+                    err: format!("{e}"),
+                });
+                return None;
+            }
+        };
+        let key = match is_private {
+            true => match decrypt_jwt_private_key(
+                &key,
+                &jwt_key_paths.private_key_passphrase,
+            ) {
+                Ok(key) => key,
+                Err(reason) => {
+                    errors.push(StartupError::InvalidPem {
+                        env_var: env_var.to_string(),
+                        path: path.to_string(),
+                        reason,
+                    });
+                    return None;
+                }
+            },
+            false => key,
+        };
+        match check_jwt_key_pem(&key, is_private) {
+            Ok(_) => Some(key),
+            Err(reason) => {
+                errors.push(StartupError::InvalidPem {
+                    env_var: env_var.to_string(),
+                    path: path.to_string(),
+                    reason,
+                });
+                None
+            }
+        }
+    };
+    let private_key =
+        read_key("TOKEN_ALGO_PRIVATE_KEY", &jwt_key_paths.private_key, true);
+    let public_key =
+        read_key("TOKEN_ALGO_PUBLIC_KEY", &jwt_key_paths.public_key, false);
+    match (private_key, public_key) {
+        (Some(private_key), Some(public_key)) => Ok((private_key, public_key)),
+        _ => Err(errors),
+    }
+}
+
+/// check_jwt_key_pem
+///
+/// Check the contents of a jwt key are a valid ES256 pem
+/// private or public key
+///
+/// # Arguments
+///
+/// * `key` - `&[u8]` - pem key contents
+/// * `is_private` - `bool` - check for a private key
+///
+/// # Errors
+///
+/// Err(reason: `String`) if the key cannot be parsed
+///
+pub fn check_jwt_key_pem(key: &[u8], is_private: bool) -> Result<(), String> {
+    let parsed = match is_private {
+        true => EncodingKey::from_ec_pem(key).map(|_| ()),
+        false => DecodingKey::from_ec_pem(key).map(|_| ()),
+    };
+    parsed.map_err(|e| {
+        format!(
+            "not a valid ES256 pem {} key with err='{e}'",
+            if is_private { "private" } else { "public" }
+        )
+    })
+}
+
+/// decrypt_jwt_private_key
+///
+/// Decrypt a passphrase-protected PKCS#8 pem private key
+/// (``BEGIN ENCRYPTED PRIVATE KEY``) into an unencrypted
+/// PKCS#8 pem key. Unencrypted keys are returned as-is.
+///
+/// # Arguments
+///
+/// * `key` - `&[u8]` - pem private key contents
+/// * `passphrase` - `&str` - passphrase for an encrypted key
+///
+/// # Returns
+///
+/// Ok(`Vec<u8>`) - unencrypted PKCS#8 pem key contents
+///
+/// # Errors
+///
+/// Err(reason: `String`) if the key is encrypted and the
+/// passphrase is missing or wrong
+///
+/// # Examples
+///
+/// ```rust
+/// use openssl::ec::EcGroup;
+/// use openssl::ec::EcKey;
+/// use openssl::nid::Nid;
+/// use openssl::pkey::PKey;
+/// use openssl::symm::Cipher;
+/// use restapi::jwt::jwt_keys::check_jwt_key_pem;
+/// use restapi::jwt::jwt_keys::decrypt_jwt_private_key;
+/// let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+/// let key = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+/// let encrypted = key
+///     .private_key_to_pem_pkcs8_passphrase(Cipher::aes_256_cbc(), b"secret")
+///     .unwrap();
+/// assert!(check_jwt_key_pem(&encrypted, true).is_err());
+/// let decrypted = decrypt_jwt_private_key(&encrypted, "secret").unwrap();
+/// assert!(check_jwt_key_pem(&decrypted, true).is_ok());
+/// assert!(decrypt_jwt_private_key(&encrypted, "wrong").is_err());
+/// assert!(decrypt_jwt_private_key(&encrypted, "").is_err());
+/// assert_eq!(decrypt_jwt_private_key(&decrypted, "").unwrap(), decrypted);
+/// ```
+///
+pub fn decrypt_jwt_private_key(
+    key: &[u8],
+    passphrase: &str,
+) -> Result<Vec<u8>, String> {
+    if !String::from_utf8_lossy(key).contains("BEGIN ENCRYPTED PRIVATE KEY") {
+        return Ok(key.to_vec());
+    }
+    if passphrase.is_empty() {
+        return Err("encrypted private key requires \
+            TOKEN_ALGO_PRIVATE_KEY_PASSPHRASE"
+            .to_string());
+    }
+    openssl::pkey::PKey::private_key_from_pem_passphrase(
+        key,
+        passphrase.as_bytes(),
+    )
+    .and_then(|pkey| pkey.private_key_to_pem_pkcs8())
+    .map_err(|_| {
+        "failed to decrypt the private key with \
+        TOKEN_ALGO_PRIVATE_KEY_PASSPHRASE"
+            .to_string()
+    })
+}
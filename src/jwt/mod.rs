@@ -1,3 +1,7 @@
 //! API for managing user JSON web tokens (JWTs)
 //!
 pub mod api;
+pub mod build_jwks;
+pub mod jwt_keys;
+pub mod reload_jwt_keys;
+pub mod token_claims;
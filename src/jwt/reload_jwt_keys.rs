@@ -0,0 +1,83 @@
+//! Reload the jwt key ring when the server
+//! receives a ``SIGHUP``
+//!
+use std::sync::Arc;
+use std::sync::RwLock;
+
+use crate::jwt::jwt_keys::JwtKeyPaths;
+use crate::jwt::jwt_keys::JwtKeys;
+use crate::secrets::secrets_provider::Secrets;
+
+/// reload_jwt_keys_on_sighup
+///
+/// Wait for ``SIGHUP`` signals and reload the
+/// [`JwtKeys`](crate::jwt::jwt_keys::JwtKeys)
+/// shared by all connections. The `default` keys are
+/// fetched again from the secrets provider (if enabled)
+/// and the current keys are kept if the reload fails.
+///
+/// This is a no-op on non-unix platforms.
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `jwt_keys` - `Arc<RwLock<JwtKeys>>` - shared key ring on the
+///   [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `jwt_key_paths` - [`JwtKeyPaths`](crate::jwt::jwt_keys::JwtKeyPaths) -
+///   where to reload the keys from
+/// * `secrets` - [`Secrets`](crate::secrets::secrets_provider::Secrets) -
+///   optional secrets provider with the `default` keys
+///
+#[cfg(unix)]
+pub async fn reload_jwt_keys_on_sighup(
+    tracking_label: &str,
+    jwt_keys: Arc<RwLock<JwtKeys>>,
+    jwt_key_paths: JwtKeyPaths,
+    secrets: Secrets,
+) {
+    use tokio::signal::unix::signal;
+    use tokio::signal::unix::SignalKind;
+
+    use crate::secrets::refresh_secrets::load_jwt_keys_from_secrets_or_paths;
+
+    let mut sighup = match signal(SignalKind::hangup()) {
+        Ok(sighup) => sighup,
+        Err(e) => {
+            error!(
+                "{tracking_label} - \
+                unable to listen for SIGHUP jwt key reloads with err='{e}'"
+            );
+            return;
+        }
+    };
+    while sighup.recv().await.is_some() {
+        info!("{tracking_label} - received SIGHUP - reloading jwt keys");
+        match load_jwt_keys_from_secrets_or_paths(
+            tracking_label,
+            &secrets,
+            &jwt_key_paths,
+        )
+        .await
+        {
+            Ok(new_jwt_keys) => {
+                *jwt_keys.write().unwrap() = new_jwt_keys;
+            }
+            Err(err_msg) => {
+                error!(
+                    "{tracking_label} - \
+                    keeping current jwt keys - reload failed with \
+                    err='{err_msg}'"
+                );
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub async fn reload_jwt_keys_on_sighup(
+    _tracking_label: &str,
+    _jwt_keys: Arc<RwLock<JwtKeys>>,
+    _jwt_key_paths: JwtKeyPaths,
+    _secrets: Secrets,
+) {
+}
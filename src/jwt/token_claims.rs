@@ -0,0 +1,166 @@
+//! # Custom claims for issued JWTs
+//!
+//! Add extra claims (``roles``, ``tenant_id``, ``scopes``, etc.)
+//! to every token created with
+//! [`create_user_token`](crate::requests::auth::create_user_token::create_user_token).
+//!
+//! ## Static claims for every token
+//!
+//! Set a json object with the claims to add:
+//!
+//! ```bash
+//! export TOKEN_CUSTOM_CLAIMS='{"tenant_id":"acme","scopes":["read","write"]}'
+//! ```
+//!
+//! ## Per-user claims computed at login time
+//!
+//! Implement the
+//! [`TokenClaimsProvider`](crate::jwt::token_claims::TokenClaimsProvider)
+//! trait and set it on the
+//! [`CoreConfig`](crate::core::core_config::CoreConfig)
+//! ``token_claims_provider`` before starting the server.
+//! Per-user claims replace static claims with the same name.
+//!
+//! The reserved claims ``sub``, ``org``, ``exp`` and
+//! ``scope`` cannot be changed and are ignored (``scope``
+//! is set from the login's ``scopes``, see
+//! [`token_scopes`](crate::requests::auth::token_scopes)). With multi-tenancy enabled
+//! (``TENANT_MODE``) the server sets the ``tenant_id`` claim
+//! to the user's tenant id and it replaces any custom
+//! ``tenant_id`` claim.
+//!
+use std::future::Future;
+use std::pin::Pin;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::PooledConnection;
+use bb8_postgres::PostgresConnectionManager;
+
+/// claims set by the server that custom claims cannot replace
+pub const RESERVED_TOKEN_CLAIMS: [&str; 4] = ["sub", "org", "exp", "scope"];
+
+/// custom claims by claim name
+pub type TokenCustomClaims = serde_json::Map<String, serde_json::Value>;
+
+/// future returned by
+/// [`TokenClaimsProvider::get_user_claims`](crate::jwt::token_claims::TokenClaimsProvider::get_user_claims)
+pub type TokenClaimsFuture<'a> = Pin<
+    Box<dyn Future<Output = Result<TokenCustomClaims, String>> + Send + 'a>,
+>;
+
+/// TokenClaimsProvider
+///
+/// Hook for adding per-user claims to a new jwt.
+/// Claims are computed with the db connection used
+/// to create the token.
+///
+/// Returning an `Err` stops the token from being
+/// created and the user's login fails.
+///
+pub trait TokenClaimsProvider: Send + Sync {
+    /// get_user_claims
+    ///
+    /// Build the custom claims for a user's new jwt
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - logging label for caller
+    /// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+    ///   established db connection from the threadpool
+    /// * `user_email` - `&str` - user's email
+    /// * `user_id` - `i32` - user's database id
+    ///
+    /// # Returns
+    ///
+    /// Ok([`TokenCustomClaims`](crate::jwt::token_claims::TokenCustomClaims))
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`)
+    ///
+    fn get_user_claims<'a>(
+        &'a self,
+        tracking_label: &'a str,
+        conn: &'a PooledConnection<
+            '_,
+            PostgresConnectionManager<MakeTlsConnector>,
+        >,
+        user_email: &'a str,
+        user_id: i32,
+    ) -> TokenClaimsFuture<'a>;
+}
+
+/// load_token_custom_claims
+///
+/// Load the static custom claims added to every
+/// new jwt from the environment variable
+/// ``TOKEN_CUSTOM_CLAIMS`` (a json object)
+///
+/// ## Roadmap: should move into CoreConfig
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+///
+/// # Returns
+///
+/// Ok([`TokenCustomClaims`](crate::jwt::token_claims::TokenCustomClaims)) -
+/// empty if ``TOKEN_CUSTOM_CLAIMS`` is not set
+///
+/// # Errors
+///
+/// Err(err_msg: `String`) if ``TOKEN_CUSTOM_CLAIMS`` is not a
+/// json object
+///
+pub fn load_token_custom_claims(
+    tracking_label: &str,
+) -> Result<TokenCustomClaims, String> {
+    let claims_str = match std::env::var("TOKEN_CUSTOM_CLAIMS") {
+        Ok(v) => v,
+        Err(_) => return Ok(TokenCustomClaims::new()),
+    };
+    if claims_str.trim().is_empty() {
+        return Ok(TokenCustomClaims::new());
+    }
+    let claims: TokenCustomClaims = match serde_json::from_str(&claims_str) {
+        Ok(claims) => claims,
+        Err(e) => {
+            return Err(format!(
+                "{tracking_label} - \
+                TOKEN_CUSTOM_CLAIMS must be a json object with err='{e}'"
+            ));
+        }
+    };
+    Ok(remove_reserved_token_claims(tracking_label, claims))
+}
+
+/// remove_reserved_token_claims
+///
+/// Drop any
+/// [`RESERVED_TOKEN_CLAIMS`](crate::jwt::token_claims::RESERVED_TOKEN_CLAIMS)
+/// from custom claims
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `claims` - [`TokenCustomClaims`](crate::jwt::token_claims::TokenCustomClaims)
+///
+/// # Returns
+///
+/// [`TokenCustomClaims`](crate::jwt::token_claims::TokenCustomClaims)
+///
+pub fn remove_reserved_token_claims(
+    tracking_label: &str,
+    mut claims: TokenCustomClaims,
+) -> TokenCustomClaims {
+    for reserved in RESERVED_TOKEN_CLAIMS.iter() {
+        if claims.remove(*reserved).is_some() {
+            warn!(
+                "{tracking_label} - \
+                ignoring reserved custom jwt claim={reserved}"
+            );
+        }
+    }
+    claims
+}
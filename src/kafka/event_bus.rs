@@ -0,0 +1,632 @@
+//! Publish user events to kafka through the
+//! [`EventBus`](crate::kafka::event_bus::EventBus)
+//! stored on the
+//! [`CoreConfig`](crate::core::core_config::CoreConfig)
+//!
+//! The event bus owns the enabled/disabled check, topic
+//! routing (``USER_EVENTS_TOPIC_*``), payload serialization
+//! and the
+//! ``kafka_events_total`` prometheus counter so handlers
+//! only need to call a method like
+//! ``config.events.user_updated(...)``
+//!
+use lazy_static::lazy_static;
+
+use crate::core::server::geo_ip::get_location_details;
+use crate::events::user_event::USER_EVENT_SCHEMAS;
+use crate::kafka::kafka_dead_letters::KafkaDeadLetters;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::monitoring::metric_labels::get_metric_label;
+use crate::monitoring::metric_types::register_int_counter_vec;
+use crate::monitoring::metric_types::IntCounterVec;
+use crate::notifications::user_notifications::UserNotifications;
+use crate::requests::models::user_session::ModelUserSessionMetadata;
+use crate::webhooks::webhook_dispatcher::WebhookDispatcher;
+
+lazy_static! {
+    pub static ref KAFKA_EVENTS_COUNTER_VEC: IntCounterVec =
+        register_int_counter_vec!(
+            "kafka_events_total",
+            "Number of events sent to the kafka publisher.",
+            &["topic", "event"]
+        )
+        .unwrap();
+}
+
+/// prefix for the environment variables that route user
+/// events to a kafka topic (``USER_EVENTS_TOPIC_LOGIN``)
+pub const USER_EVENTS_TOPIC_PREFIX: &str = "USER_EVENTS_TOPIC_";
+
+/// EventBus
+///
+/// Routes user events to kafka when
+/// publishing is enabled
+///
+/// # Supported Environment Variables
+///
+/// ## Enable publishing events to kafka
+///
+/// ```bash
+/// export KAFKA_PUBLISH_EVENTS="true"
+/// ```
+///
+/// ## Change the kafka topic for user events
+///
+/// ```bash
+/// export KAFKA_TOPIC_USER_EVENTS="user.events"
+/// ```
+///
+/// ## Route events to other topics
+///
+/// The suffix is an event name or ``_``-separated words in
+/// event names (``LOGIN`` routes ``LOGIN`` and
+/// ``LOGIN_PASSKEY``). The longest matching suffix wins and
+/// unmatched events use ``KAFKA_TOPIC_USER_EVENTS``.
+///
+/// ```bash
+/// export USER_EVENTS_TOPIC_LOGIN="user.logins"
+/// export USER_EVENTS_TOPIC_DATA="user.data"
+/// ```
+///
+/// ## Skip publishing noisy events (comma-separated event names)
+///
+/// ```bash
+/// export KAFKA_EXCLUDE_EVENTS="DATA_DOWNLOADED,USER_GET"
+/// ```
+///
+/// # Arguments
+///
+/// * `enabled` - `bool` - publish events to kafka
+/// * `user_topic` - `String` - default kafka topic for user events
+/// * `topic_routes` - `Vec<(String, String)>` - sorted
+///   `(event name or words, topic)` routes
+/// * `excluded_events` - `Vec<String>` - event names that
+///   are never published
+/// * `notifications` - [`UserNotifications`](crate::notifications::user_notifications::UserNotifications) -
+///   stores events for the user notifications stream
+/// * `webhooks` - [`WebhookDispatcher`](crate::webhooks::webhook_dispatcher::WebhookDispatcher) -
+///   queues events for the registered webhooks
+/// * `dead_letters` - [`KafkaDeadLetters`](crate::kafka::kafka_dead_letters::KafkaDeadLetters) -
+///   retries rejected publishes and stores the events that
+///   fail every retry
+///
+#[derive(Clone, Default)]
+pub struct EventBus {
+    pub enabled: bool,
+    pub user_topic: String,
+    pub topic_routes: Vec<(String, String)>,
+    pub excluded_events: Vec<String>,
+    pub notifications: UserNotifications,
+    pub webhooks: WebhookDispatcher,
+    pub dead_letters: KafkaDeadLetters,
+}
+
+impl EventBus {
+    /// build_event_bus
+    ///
+    /// Build an
+    /// [`EventBus`](crate::kafka::event_bus::EventBus)
+    /// from environment variables
+    ///
+    pub fn build_event_bus() -> Self {
+        let enabled_s = std::env::var("KAFKA_PUBLISH_EVENTS")
+            .unwrap_or_else(|_| "false".to_string());
+        let user_topic = std::env::var("KAFKA_TOPIC_USER_EVENTS")
+            .unwrap_or_else(|_| "user.events".to_string());
+        let excluded_events = std::env::var("KAFKA_EXCLUDE_EVENTS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|event| event.trim().to_uppercase())
+            .filter(|event| !event.is_empty())
+            .collect();
+        let mut topic_routes: Vec<(String, String)> = std::env::vars()
+            .filter_map(|(key, topic)| {
+                key.strip_prefix(USER_EVENTS_TOPIC_PREFIX).map(|route| {
+                    (route.to_uppercase(), topic.trim().to_string())
+                })
+            })
+            .collect();
+        topic_routes.sort();
+        EventBus {
+            enabled: enabled_s == "1" || enabled_s == "true",
+            user_topic,
+            topic_routes,
+            excluded_events,
+            notifications: UserNotifications::build_user_notifications(),
+            webhooks: WebhookDispatcher::build_webhook_dispatcher(),
+            dead_letters: KafkaDeadLetters::build_kafka_dead_letters(),
+        }
+    }
+
+    /// get_event_topic
+    ///
+    /// Get the kafka topic for an event. Routes match the
+    /// event name or ``_``-separated words in it, the route
+    /// with the most words wins (ties go to the first route
+    /// alphabetically) and unmatched events use `user_topic`.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - `&str` - event name (``LOGIN_PASSKEY``)
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use restapi::kafka::event_bus::EventBus;
+    /// let events = EventBus {
+    ///     user_topic: "user.events".to_string(),
+    ///     topic_routes: vec![
+    ///         ("DATA".to_string(), "user.data".to_string()),
+    ///         ("LOGIN".to_string(), "user.logins".to_string()),
+    ///         ("USER_UPDATE_DATA".to_string(), "user.updates".to_string()),
+    ///     ],
+    ///     ..Default::default()
+    /// };
+    /// assert_eq!(events.get_event_topic("LOGIN_PASSKEY"), "user.logins");
+    /// assert_eq!(events.get_event_topic("DATA_DOWNLOADED"), "user.data");
+    /// assert_eq!(events.get_event_topic("USER_UPDATE_DATA"), "user.updates");
+    /// assert_eq!(events.get_event_topic("USER_CREATE"), "user.events");
+    /// ```
+    ///
+    pub fn get_event_topic(&self, event: &str) -> &str {
+        let event_words = format!("_{event}_");
+        self.topic_routes
+            .iter()
+            .filter(|(route, _)| event_words.contains(&format!("_{route}_")))
+            .max_by(|(a, _), (b, _)| {
+                a.split('_')
+                    .count()
+                    .cmp(&b.split('_').count())
+                    .then(b.cmp(a))
+            })
+            .map(|(_, topic)| topic.as_str())
+            .unwrap_or(&self.user_topic)
+    }
+
+    /// get_topics
+    ///
+    /// Get every kafka topic the bus publishes to (sorted
+    /// without duplicates)
+    ///
+    pub fn get_topics(&self) -> Vec<String> {
+        let mut topics: Vec<String> = self
+            .topic_routes
+            .iter()
+            .map(|(_, topic)| topic.clone())
+            .collect();
+        topics.push(self.user_topic.clone());
+        topics.sort();
+        topics.dedup();
+        topics
+    }
+
+    /// validate_topic_routes
+    ///
+    /// Check the ``USER_EVENTS_TOPIC_*`` routes when the api
+    /// server starts
+    ///
+    /// # Arguments
+    ///
+    /// * `kafka_topics` - `&[String]` - supported topics
+    ///   (``KAFKA_TOPICS``) - every topic the bus publishes to
+    ///   must be in the list when publishing is enabled and
+    ///   the list is not empty
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) for a route without a topic, a
+    /// route that does not match any event or an unsupported
+    /// topic
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use restapi::kafka::event_bus::EventBus;
+    /// let events = EventBus {
+    ///     enabled: true,
+    ///     user_topic: "user.events".to_string(),
+    ///     topic_routes: vec![("LOGIN".to_string(), "user.logins".to_string())],
+    ///     ..Default::default()
+    /// };
+    /// let kafka_topics = vec!["user.events".to_string()];
+    /// assert!(events.validate_topic_routes(&kafka_topics).is_err());
+    /// let kafka_topics = vec!["user.events".to_string(), "user.logins".to_string()];
+    /// assert!(events.validate_topic_routes(&kafka_topics).is_ok());
+    /// ```
+    ///
+    pub fn validate_topic_routes(
+        &self,
+        kafka_topics: &[String],
+    ) -> Result<(), String> {
+        for (route, topic) in self.topic_routes.iter() {
+            if topic.is_empty() {
+                return Err(format!(
+                    "{USER_EVENTS_TOPIC_PREFIX}{route} must be a kafka topic"
+                ));
+            }
+            let route_words = format!("_{route}_");
+            if !USER_EVENT_SCHEMAS.iter().any(|schema| {
+                format!("_{}_", schema.event).contains(&route_words)
+            }) {
+                return Err(format!(
+                    "{USER_EVENTS_TOPIC_PREFIX}{route} does not match \
+                    any user event name"
+                ));
+            }
+        }
+        if !self.enabled || kafka_topics.is_empty() {
+            return Ok(());
+        }
+        let missing_topics: Vec<String> = self
+            .get_topics()
+            .into_iter()
+            .filter(|topic| !kafka_topics.contains(topic))
+            .collect();
+        match missing_topics.is_empty() {
+            true => Ok(()),
+            false => Err(format!(
+                "user event topics={} are not in KAFKA_TOPICS={}",
+                missing_topics.join(","),
+                kafka_topics.join(",")
+            )),
+        }
+    }
+
+    /// is_event_enabled
+    ///
+    /// Check if an event name is published (the bus is
+    /// enabled and the event is not in
+    /// ``KAFKA_EXCLUDE_EVENTS``)
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - `&str` - event name (``DATA_DOWNLOADED``)
+    ///
+    pub fn is_event_enabled(&self, event: &str) -> bool {
+        self.enabled && !self.excluded_events.iter().any(|v| v == event)
+    }
+
+    /// publish_user_event
+    ///
+    /// Publish a user event to the event's topic (see
+    /// [`get_event_topic`](crate::kafka::event_bus::EventBus::get_event_topic))
+    /// using the user's id as the partition key. The
+    /// payload is serialized as:
+    /// ``EVENT_NAME user=USER_ID [DETAILS]``
+    ///
+    /// Nothing is published when the bus is disabled or the
+    /// event is in ``KAFKA_EXCLUDE_EVENTS``. Events that are
+    /// not excluded are also stored for the user
+    /// notifications stream when ``USER_NOTIFICATIONS_ENABLED=1``
+    /// and queued for the subscribed webhooks when
+    /// ``WEBHOOKS_ENABLED=1`` (even if kafka is disabled).
+    /// Events the kafka publisher rejects are retried and
+    /// then stored in the ``kafka_dead_letters`` table.
+    ///
+    /// # Arguments
+    ///
+    /// * `kafka_pool` - initialized [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+    /// * `user_id` - `i32` - user id for the event
+    /// * `event` - `&str` - event name (``USER_UPDATE``)
+    /// * `details` - `&str` - optional space-separated
+    ///   ``key=value`` pairs (use ``""`` for none)
+    ///
+    pub async fn publish_user_event(
+        &self,
+        kafka_pool: &KafkaPublisher,
+        user_id: i32,
+        event: &str,
+        details: &str,
+    ) {
+        if self.excluded_events.iter().any(|v| v == event) {
+            return;
+        }
+        self.notifications
+            .store_user_notification(user_id, event, details);
+        self.webhooks.queue_user_event(user_id, event, details);
+        if !self.is_event_enabled(event) {
+            return;
+        }
+        let payload = match details.is_empty() {
+            true => format!("{event} user={user_id}"),
+            false => format!("{event} user={user_id} {details}"),
+        };
+        let topic = self.get_event_topic(event);
+        KAFKA_EVENTS_COUNTER_VEC
+            .with_label_values(&[
+                &get_metric_label("kafka_events_total", topic),
+                &get_metric_label("kafka_events_total", event),
+            ])
+            .inc();
+        self.dead_letters
+            .publish(
+                kafka_pool,
+                // topic
+                topic,
+                // partition key
+                &format!("user-{user_id}"),
+                // payload in the message
+                &payload,
+            )
+            .await;
+    }
+
+    /// user_created
+    ///
+    /// Publish a ``USER_CREATE`` event
+    ///
+    /// # Arguments
+    ///
+    /// * `kafka_pool` - initialized [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+    /// * `user_id` - `i32` - user id
+    /// * `email` - `&str` - user email
+    ///
+    pub async fn user_created(
+        &self,
+        kafka_pool: &KafkaPublisher,
+        user_id: i32,
+        email: &str,
+    ) {
+        self.publish_user_event(
+            kafka_pool,
+            user_id,
+            "USER_CREATE",
+            &format!("email={email}"),
+        )
+        .await
+    }
+
+    /// user_updated
+    ///
+    /// Publish a ``USER_UPDATE`` event
+    ///
+    /// # Arguments
+    ///
+    /// * `kafka_pool` - initialized [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+    /// * `user_id` - `i32` - user id
+    /// * `email` - `&str` - user email
+    ///
+    pub async fn user_updated(
+        &self,
+        kafka_pool: &KafkaPublisher,
+        user_id: i32,
+        email: &str,
+    ) {
+        self.publish_user_event(
+            kafka_pool,
+            user_id,
+            "USER_UPDATE",
+            &format!("email={email}"),
+        )
+        .await
+    }
+
+    /// user_deleted
+    ///
+    /// Publish a ``USER_DELETE`` event
+    ///
+    /// # Arguments
+    ///
+    /// * `kafka_pool` - initialized [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+    /// * `user_id` - `i32` - user id
+    ///
+    pub async fn user_deleted(
+        &self,
+        kafka_pool: &KafkaPublisher,
+        user_id: i32,
+    ) {
+        self.publish_user_event(kafka_pool, user_id, "USER_DELETE", "")
+            .await
+    }
+
+    /// user_verified
+    ///
+    /// Publish a ``USER_VERIFY`` event
+    ///
+    /// # Arguments
+    ///
+    /// * `kafka_pool` - initialized [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+    /// * `user_id` - `i32` - user id
+    /// * `email` - `&str` - user email
+    ///
+    pub async fn user_verified(
+        &self,
+        kafka_pool: &KafkaPublisher,
+        user_id: i32,
+        email: &str,
+    ) {
+        self.publish_user_event(
+            kafka_pool,
+            user_id,
+            "USER_VERIFY",
+            &format!("email={email}"),
+        )
+        .await
+    }
+
+    /// user_logged_in
+    ///
+    /// Publish a login event (``LOGIN`` or ``LOGIN_PASSKEY``)
+    /// with the session's ``country`` and ``city`` when
+    /// ``GEOIP_DB_PATH`` found them
+    ///
+    /// # Arguments
+    ///
+    /// * `kafka_pool` - initialized [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+    /// * `user_id` - `i32` - user id
+    /// * `email` - `&str` - user email
+    /// * `event` - `&str` - login event name
+    /// * `session` - [`ModelUserSessionMetadata`](crate::requests::models::user_session::ModelUserSessionMetadata) -
+    ///   new session's client details
+    ///
+    pub async fn user_logged_in(
+        &self,
+        kafka_pool: &KafkaPublisher,
+        user_id: i32,
+        email: &str,
+        event: &str,
+        session: &ModelUserSessionMetadata,
+    ) {
+        let location = get_location_details(&session.country, &session.city);
+        let details = match location.is_empty() {
+            true => format!("email={email}"),
+            false => format!("email={email} {location}"),
+        };
+        self.publish_user_event(kafka_pool, user_id, event, &details)
+            .await
+    }
+
+    /// user_security_changed
+    ///
+    /// Publish a ``USER_SECURITY_CHANGE`` event when a user's
+    /// password, email or passkeys change
+    ///
+    /// # Arguments
+    ///
+    /// * `kafka_pool` - initialized [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+    /// * `user_id` - `i32` - user id
+    /// * `change` - `&str` - ``password``, ``email`` or
+    ///   ``passkey``
+    /// * `email` - `&str` - user email before the change
+    ///
+    pub async fn user_security_changed(
+        &self,
+        kafka_pool: &KafkaPublisher,
+        user_id: i32,
+        change: &str,
+        email: &str,
+    ) {
+        self.publish_user_event(
+            kafka_pool,
+            user_id,
+            "USER_SECURITY_CHANGE",
+            &format!("change={change} email={email}"),
+        )
+        .await
+    }
+
+    /// admin_security_action
+    ///
+    /// Publish an admin incident response event
+    /// (``ADMIN_SECURITY_REVOKE_SESSIONS`` or
+    /// ``ADMIN_SECURITY_FORCE_PASSWORD_RESET``) for the
+    /// target user with the admin, the number of revoked
+    /// sessions and the form-urlencoded reason (when set)
+    ///
+    /// # Arguments
+    ///
+    /// * `kafka_pool` - initialized [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+    /// * `user_id` - `i32` - target user id
+    /// * `event` - `&str` - admin security event name
+    /// * `admin_user_id` - `i32` - admin user id
+    /// * `revoked_sessions` - `i64` - number of revoked
+    ///   sessions
+    /// * `reason` - `&str` - optional reason (empty = none)
+    ///
+    pub async fn admin_security_action(
+        &self,
+        kafka_pool: &KafkaPublisher,
+        user_id: i32,
+        event: &str,
+        admin_user_id: i32,
+        revoked_sessions: i64,
+        reason: &str,
+    ) {
+        let details = match reason.is_empty() {
+            true => format!("admin={admin_user_id} revoked={revoked_sessions}"),
+            false => {
+                let reason: String =
+                    url::form_urlencoded::byte_serialize(reason.as_bytes())
+                        .collect();
+                format!(
+                    "admin={admin_user_id} revoked={revoked_sessions} \
+                    reason={reason}"
+                )
+            }
+        };
+        self.publish_user_event(kafka_pool, user_id, event, &details)
+            .await
+    }
+
+    /// login_unrecognized
+    ///
+    /// Publish a ``LOGIN_UNRECOGNIZED`` event when a user logs
+    /// in from a device or location that none of their other
+    /// sessions used
+    ///
+    /// # Arguments
+    ///
+    /// * `kafka_pool` - initialized [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+    /// * `user_id` - `i32` - user id
+    /// * `email` - `&str` - user email
+    /// * `reason` - `&str` - ``device``, ``location`` or
+    ///   ``device,location``
+    /// * `session` - [`ModelUserSessionMetadata`](crate::requests::models::user_session::ModelUserSessionMetadata) -
+    ///   new session's client details
+    ///
+    pub async fn login_unrecognized(
+        &self,
+        kafka_pool: &KafkaPublisher,
+        user_id: i32,
+        email: &str,
+        reason: &str,
+        session: &ModelUserSessionMetadata,
+    ) {
+        let location = get_location_details(&session.country, &session.city);
+        let details = match location.is_empty() {
+            true => format!("email={email} reason={reason}"),
+            false => format!("email={email} reason={reason} {location}"),
+        };
+        self.publish_user_event(
+            kafka_pool,
+            user_id,
+            "LOGIN_UNRECOGNIZED",
+            &details,
+        )
+        .await
+    }
+
+    /// data_downloaded
+    ///
+    /// Publish a ``DATA_DOWNLOADED`` audit event when a
+    /// ``users_data`` file is downloaded or a presigned
+    /// download url is created for it
+    ///
+    /// # Arguments
+    ///
+    /// * `kafka_pool` - initialized [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+    /// * `user_id` - `i32` - user downloading the file (the
+    ///   owner's id for ``share_token`` downloads)
+    /// * `data_id` - `i32` - ``users_data.id``
+    /// * `bytes` - `i64` - bytes sent (or the file size for a
+    ///   presigned url)
+    /// * `range` - `&str` - requested byte range
+    ///   (``START-END``) or ``""`` for the whole file
+    /// * `access` - `&str` - how access was granted:
+    ///   ``owner``, ``acl`` or ``share_token``
+    ///
+    pub async fn data_downloaded(
+        &self,
+        kafka_pool: &KafkaPublisher,
+        user_id: i32,
+        data_id: i32,
+        bytes: i64,
+        range: &str,
+        access: &str,
+    ) {
+        let details = match range.is_empty() {
+            true => format!("data={data_id} bytes={bytes} access={access}"),
+            false => format!(
+                "data={data_id} bytes={bytes} access={access} \
+                range={range}"
+            ),
+        };
+        self.publish_user_event(
+            kafka_pool,
+            user_id,
+            "DATA_DOWNLOADED",
+            &details,
+        )
+        .await
+    }
+}
@@ -0,0 +1,429 @@
+//! Retry kafka publishes and store the messages that still
+//! fail in the ``kafka_dead_letters`` table
+//!
+//! The kafka threadpool retries each accepted message until
+//! the brokers store it, so messages are lost when the
+//! publisher rejects them or its work vec keeps growing
+//! while the brokers are degraded. The
+//! [`EventBus`](crate::kafka::event_bus::EventBus) publishes
+//! through [`KafkaDeadLetters`] which:
+//!
+//! - rejects new messages when the publisher already has
+//!   ``KAFKA_MAX_PENDING_MSGS`` messages waiting
+//! - retries rejected messages in a background task with an
+//!   exponential backoff so handlers are not slowed down
+//! - stores the messages that fail every retry with the last
+//!   error so admins can list and requeue them
+//!
+//! After ``KAFKA_CIRCUIT_BREAKER_FAILURE_THRESHOLD``
+//! consecutive rejected publishes the kafka
+//! [`CircuitBreaker`](crate::utils::circuit_breaker::CircuitBreaker)
+//! opens, and new messages skip the publisher and their
+//! retries and are stored as dead letters right away until
+//! a trial publish succeeds.
+//!
+//! Errors inside the threadpool's own retry loop (after a
+//! message was accepted) are not reported back to the api
+//! server and only show up as a growing
+//! ``kafka_publish_pending_msgs`` gauge.
+//!
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use crate::kafka::kafka_publisher::get_pending_msgs;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::kafka::publish_msg::try_publish_msg;
+use crate::kafka::schema_registry::SchemaRegistry;
+use crate::monitoring::metric_labels::get_metric_label;
+use crate::monitoring::metric_types::register_int_counter_vec;
+use crate::monitoring::metric_types::register_int_gauge;
+use crate::monitoring::metric_types::IntCounterVec;
+use crate::monitoring::metric_types::IntGauge;
+use crate::requests::models::kafka_dead_letter::insert_kafka_dead_letter;
+use crate::utils::circuit_breaker::CircuitBreaker;
+
+lazy_static! {
+    pub static ref KAFKA_PUBLISH_FAILURES_COUNTER_VEC: IntCounterVec =
+        register_int_counter_vec!(
+            "kafka_publish_failures_total",
+            "Number of kafka publish attempts rejected by the publisher.",
+            &["topic"]
+        )
+        .unwrap();
+    pub static ref KAFKA_PUBLISH_RETRIES_COUNTER_VEC: IntCounterVec =
+        register_int_counter_vec!(
+            "kafka_publish_retries_total",
+            "Number of kafka publish retries.",
+            &["topic"]
+        )
+        .unwrap();
+    pub static ref KAFKA_DEAD_LETTERS_COUNTER_VEC: IntCounterVec =
+        register_int_counter_vec!(
+            "kafka_dead_letters_total",
+            "Number of kafka messages that failed every retry by result.",
+            &["topic", "result"]
+        )
+        .unwrap();
+    pub static ref KAFKA_DEAD_LETTERS_REQUEUED_COUNTER_VEC: IntCounterVec =
+        register_int_counter_vec!(
+            "kafka_dead_letters_requeued_total",
+            "Number of requeued kafka dead letters by result.",
+            &["topic", "result"]
+        )
+        .unwrap();
+    pub static ref KAFKA_PUBLISH_PENDING_GAUGE: IntGauge = register_int_gauge!(
+        "kafka_publish_pending_msgs",
+        "Number of messages waiting in the kafka publisher."
+    )
+    .unwrap();
+}
+
+/// KafkaDeadLetters
+///
+/// Settings for retrying kafka publishes and storing the
+/// messages that fail every retry
+///
+/// # Supported Environment Variables
+///
+/// ```bash
+/// # store messages that fail every retry
+/// export KAFKA_DEAD_LETTERS_ENABLED="1"
+/// # rejected messages are retried after 200ms, 400ms, 800ms
+/// export KAFKA_PUBLISH_MAX_RETRIES="3"
+/// export KAFKA_PUBLISH_RETRY_MS="200"
+/// # reject new messages while the publisher has this many
+/// # messages waiting for the brokers (0 = unlimited)
+/// export KAFKA_MAX_PENDING_MSGS="10000"
+/// # stop publishing after this many consecutive rejected
+/// # publishes (0 = disabled)
+/// export KAFKA_CIRCUIT_BREAKER_FAILURE_THRESHOLD="5"
+/// export KAFKA_CIRCUIT_BREAKER_OPEN_SECONDS="30"
+/// ```
+///
+/// # Arguments
+///
+/// * `enabled` - `bool` - store messages that fail every retry
+/// * `max_retries` - `u32` - retries after the first
+///   rejected publish
+/// * `retry_ms` - `u64` - wait before the first retry
+///   (doubled after each retry)
+/// * `max_pending_msgs` - `usize` - reject messages while the
+///   publisher has this many waiting (``0`` = unlimited)
+/// * `db_pool` - `Option<`[`Pool`](bb8::Pool)`>` - postgres
+///   client db threadpool for storing dead letters (set
+///   when the api server starts)
+/// * `schema_registry` - [`SchemaRegistry`](crate::kafka::schema_registry::SchemaRegistry) -
+///   publishes user events in the schema registry format
+///   when ``KAFKA_SCHEMA_REGISTRY_URL`` is set
+/// * `circuit_breaker` - [`CircuitBreaker`](crate::utils::circuit_breaker::CircuitBreaker) -
+///   fails publishes fast while the brokers keep rejecting
+///   them
+///
+#[derive(Clone, Default)]
+pub struct KafkaDeadLetters {
+    pub enabled: bool,
+    pub max_retries: u32,
+    pub retry_ms: u64,
+    pub max_pending_msgs: usize,
+    pub db_pool: Option<Pool<PostgresConnectionManager<MakeTlsConnector>>>,
+    pub schema_registry: SchemaRegistry,
+    pub circuit_breaker: CircuitBreaker,
+}
+
+impl KafkaDeadLetters {
+    /// build_kafka_dead_letters
+    ///
+    /// Build a
+    /// [`KafkaDeadLetters`](crate::kafka::kafka_dead_letters::KafkaDeadLetters)
+    /// from environment variables (without a db pool)
+    ///
+    pub fn build_kafka_dead_letters() -> Self {
+        let get_env = |key: &str, default: u64| -> u64 {
+            std::env::var(key)
+                .unwrap_or_else(|_| format!("{default}"))
+                .parse::<u64>()
+                .unwrap_or(default)
+        };
+        let enabled = std::env::var("KAFKA_DEAD_LETTERS_ENABLED")
+            .unwrap_or_else(|_| "1".to_string());
+        KafkaDeadLetters {
+            enabled: enabled == "1" || enabled == "true",
+            max_retries: get_env("KAFKA_PUBLISH_MAX_RETRIES", 3).min(20) as u32,
+            retry_ms: get_env("KAFKA_PUBLISH_RETRY_MS", 200).clamp(1, 60000),
+            max_pending_msgs: get_env("KAFKA_MAX_PENDING_MSGS", 10000) as usize,
+            db_pool: None,
+            schema_registry: SchemaRegistry::build_schema_registry(),
+            circuit_breaker: CircuitBreaker::build_circuit_breaker(
+                "kafka", "KAFKA",
+            ),
+        }
+    }
+
+    /// get_retry_ms
+    ///
+    /// Get the wait before a retry (``KAFKA_PUBLISH_RETRY_MS``
+    /// doubled after each retry)
+    ///
+    /// # Arguments
+    ///
+    /// * `retry` - `u32` - retry number starting at ``1``
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use restapi::kafka::kafka_dead_letters::KafkaDeadLetters;
+    /// let dead_letters = KafkaDeadLetters {
+    ///     retry_ms: 200,
+    ///     ..Default::default()
+    /// };
+    /// assert_eq!(dead_letters.get_retry_ms(1), 200);
+    /// assert_eq!(dead_letters.get_retry_ms(3), 800);
+    /// ```
+    ///
+    pub fn get_retry_ms(&self, retry: u32) -> u64 {
+        self.retry_ms
+            .saturating_mul(2_u64.saturating_pow(retry.saturating_sub(1)))
+    }
+
+    /// try_publish
+    ///
+    /// Hand a message to the kafka publisher unless it
+    /// already has ``KAFKA_MAX_PENDING_MSGS`` messages waiting.
+    /// With a schema registry the message is encoded and
+    /// this waits for the brokers to acknowledge it.
+    ///
+    /// # Arguments
+    ///
+    /// * `kafka_pool` - initialized [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+    /// * `topic` - `&str` - kafka topic
+    /// * `key` - `&str` - kafka partition key
+    /// * `payload` - `&str` - kafka message payload
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) when the publisher is full,
+    /// rejects the message or the kafka circuit breaker is
+    /// open
+    ///
+    pub async fn try_publish(
+        &self,
+        kafka_pool: &KafkaPublisher,
+        topic: &str,
+        key: &str,
+        payload: &str,
+    ) -> Result<(), String> {
+        let pending_msgs = get_pending_msgs(kafka_pool);
+        KAFKA_PUBLISH_PENDING_GAUGE.set(pending_msgs as i64);
+        let res = self
+            .circuit_breaker
+            .call(async {
+                match self.max_pending_msgs > 0
+                    && pending_msgs >= self.max_pending_msgs
+                {
+                    true => Err(format!(
+                        "kafka publisher has {pending_msgs} pending msgs \
+                        (KAFKA_MAX_PENDING_MSGS={})",
+                        self.max_pending_msgs
+                    )),
+                    false => match self.schema_registry.is_framed(topic) {
+                        true => {
+                            self.schema_registry
+                                .try_publish(topic, key, payload)
+                                .await
+                        }
+                        false => try_publish_msg(
+                            kafka_pool, topic, key, None, payload,
+                        )
+                        .await
+                        .map(|_| ()),
+                    },
+                }
+            })
+            .await;
+        if res.is_err() {
+            KAFKA_PUBLISH_FAILURES_COUNTER_VEC
+                .with_label_values(&[&get_metric_label(
+                    "kafka_publish_failures_total",
+                    topic,
+                )])
+                .inc();
+        }
+        res
+    }
+
+    /// publish
+    ///
+    /// Publish a message when ``KAFKA_ENABLED`` is ``true`` or
+    /// ``1``. A rejected message is retried
+    /// ``KAFKA_PUBLISH_MAX_RETRIES`` times in a background
+    /// task and then stored as a dead letter. Schema registry
+    /// publishes wait for the brokers, so the first attempt
+    /// also runs in the background task. Retries stop while
+    /// the kafka circuit breaker is open.
+    ///
+    /// # Arguments
+    ///
+    /// * `kafka_pool` - initialized [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+    /// * `topic` - `&str` - kafka topic
+    /// * `key` - `&str` - kafka partition key
+    /// * `payload` - `&str` - kafka message payload
+    ///
+    pub async fn publish(
+        &self,
+        kafka_pool: &KafkaPublisher,
+        topic: &str,
+        key: &str,
+        payload: &str,
+    ) {
+        if !kafka_pool.is_enabled() {
+            return;
+        }
+        let first_err_msg = match self.schema_registry.is_framed(topic) {
+            true => None,
+            false => {
+                match self.try_publish(kafka_pool, topic, key, payload).await {
+                    Ok(_) => return,
+                    Err(err_msg) => Some(err_msg),
+                }
+            }
+        };
+        let dead_letters = self.clone();
+        let kafka_pool = kafka_pool.clone();
+        let topic = topic.to_string();
+        let key = key.to_string();
+        let payload = payload.to_string();
+        tokio::spawn(async move {
+            let mut err_msg = match first_err_msg {
+                Some(err_msg) => err_msg,
+                None => match dead_letters
+                    .try_publish(&kafka_pool, &topic, &key, &payload)
+                    .await
+                {
+                    Ok(_) => return,
+                    Err(err_msg) => err_msg,
+                },
+            };
+            warn!(
+                "kafka publish failed topic={topic} key={key} \
+                retries={} with err={err_msg}",
+                dead_letters.max_retries
+            );
+            for retry in 1..=dead_letters.max_retries {
+                // skip the retries while the brokers keep failing
+                if dead_letters.circuit_breaker.is_open() {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(
+                    dead_letters.get_retry_ms(retry),
+                ))
+                .await;
+                KAFKA_PUBLISH_RETRIES_COUNTER_VEC
+                    .with_label_values(&[&get_metric_label(
+                        "kafka_publish_retries_total",
+                        &topic,
+                    )])
+                    .inc();
+                match dead_letters
+                    .try_publish(&kafka_pool, &topic, &key, &payload)
+                    .await
+                {
+                    Ok(_) => {
+                        info!(
+                            "kafka publish succeeded topic={topic} \
+                            key={key} after retry={retry}"
+                        );
+                        return;
+                    }
+                    Err(retry_err_msg) => err_msg = retry_err_msg,
+                }
+            }
+            dead_letters
+                .store_dead_letter(&topic, &key, &payload, &err_msg)
+                .await;
+        });
+    }
+
+    /// store_dead_letter
+    ///
+    /// Store a message that failed every retry when dead
+    /// letters are enabled and the api server set the
+    /// `db_pool` (otherwise the message is logged and dropped)
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - `&str` - kafka topic
+    /// * `key` - `&str` - kafka partition key
+    /// * `payload` - `&str` - kafka message payload
+    /// * `err_msg` - `&str` - error for the last retry
+    ///
+    pub async fn store_dead_letter(
+        &self,
+        topic: &str,
+        key: &str,
+        payload: &str,
+        err_msg: &str,
+    ) {
+        let tracking_label = "kafka dead letters";
+        let attempts = self.max_retries as i32 + 1;
+        let stored = match (self.enabled, &self.db_pool) {
+            (true, Some(db_pool)) => match db_pool.get().await {
+                Ok(conn) => insert_kafka_dead_letter(
+                    tracking_label,
+                    topic,
+                    key,
+                    payload,
+                    err_msg,
+                    attempts,
+                    &conn,
+                )
+                .await
+                .map_err(|e| error!("{e}"))
+                .ok(),
+                Err(e) => {
+                    error!(
+                        "{tracking_label} - \
+                        unable to get a db connection with err='{e}'"
+                    );
+                    None
+                }
+            },
+            _ => None,
+        };
+        match stored {
+            Some(dead_letter_id) => {
+                KAFKA_DEAD_LETTERS_COUNTER_VEC
+                    .with_label_values(&[
+                        &get_metric_label("kafka_dead_letters_total", topic),
+                        "stored",
+                    ])
+                    .inc();
+                error!(
+                    "{tracking_label} - \
+                    stored kafka dead letter={dead_letter_id} \
+                    topic={topic} key={key} after \
+                    attempts={attempts} with err={err_msg}"
+                );
+            }
+            None => {
+                KAFKA_DEAD_LETTERS_COUNTER_VEC
+                    .with_label_values(&[
+                        &get_metric_label("kafka_dead_letters_total", topic),
+                        "dropped",
+                    ])
+                    .inc();
+                error!(
+                    "{tracking_label} - \
+                    dropped kafka message topic={topic} key={key} \
+                    after attempts={attempts} with err={err_msg} \
+                    payload={payload}"
+                );
+            }
+        }
+    }
+}
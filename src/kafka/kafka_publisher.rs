@@ -0,0 +1,111 @@
+//! The kafka publisher passed to every handler
+//!
+//! Built with the ``kafka`` feature (the default) this is the
+//! [kafka_threadpool](https://crates.io/crates/kafka-threadpool)
+//! ``KafkaPublisher``. Without the ``kafka`` feature this is a
+//! no-op publisher that is never enabled, so the server does
+//! not compile ``rdkafka`` and
+//! [`publish_msg`](crate::kafka::publish_msg::publish_msg)
+//! drops every message.
+//!
+#[cfg(feature = "kafka")]
+pub use kafka_threadpool::kafka_publisher::KafkaPublisher;
+#[cfg(feature = "kafka")]
+pub use kafka_threadpool::start_threadpool::start_threadpool;
+
+#[cfg(not(feature = "kafka"))]
+use std::collections::HashMap;
+
+/// get_pending_msgs
+///
+/// Get the number of messages waiting in the
+/// [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+/// work vec. The threadpool retries each message until the
+/// brokers accept it, so this grows while the brokers are
+/// degraded.
+///
+/// # Arguments
+///
+/// * `kafka_pool` - initialized [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+///
+#[cfg(feature = "kafka")]
+pub fn get_pending_msgs(kafka_pool: &KafkaPublisher) -> usize {
+    match kafka_pool.publish_msgs.lock() {
+        Ok(publish_msgs) => publish_msgs.len(),
+        Err(_) => 0,
+    }
+}
+
+/// get_pending_msgs
+///
+/// Always `0` without the ``kafka`` feature
+///
+/// # Arguments
+///
+/// * `_kafka_pool` - no-op [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+///
+#[cfg(not(feature = "kafka"))]
+pub fn get_pending_msgs(_kafka_pool: &KafkaPublisher) -> usize {
+    0
+}
+
+/// KafkaPublisher
+///
+/// No-op publisher used when restapi is built without
+/// the ``kafka`` feature
+///
+#[cfg(not(feature = "kafka"))]
+#[derive(Clone, Default)]
+pub struct KafkaPublisher {}
+
+#[cfg(not(feature = "kafka"))]
+impl KafkaPublisher {
+    /// is_enabled
+    ///
+    /// Always `false` without the ``kafka`` feature
+    ///
+    pub fn is_enabled(&self) -> bool {
+        false
+    }
+
+    /// add_data_msg
+    ///
+    /// Drop the message
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) - always
+    ///
+    pub async fn add_data_msg(
+        &self,
+        topic: &str,
+        key: &str,
+        _headers: Option<HashMap<String, String>>,
+        _payload: &str,
+    ) -> Result<usize, String> {
+        Err(format!(
+            "kafka publishing topic={topic} key={key} requires \
+            building restapi with --features kafka"
+        ))
+    }
+}
+
+/// start_threadpool
+///
+/// Build the no-op
+/// [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+/// when restapi is built without the ``kafka`` feature
+///
+/// # Arguments
+///
+/// * `label` - `Option<&str>` - logging label
+///
+#[cfg(not(feature = "kafka"))]
+pub async fn start_threadpool(label: Option<&str>) -> KafkaPublisher {
+    info!(
+        "{} - kafka publishing disabled - \
+        restapi was built without --features kafka",
+        label.unwrap_or("ktp")
+    );
+    KafkaPublisher::default()
+}
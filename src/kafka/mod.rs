@@ -1,3 +1,7 @@
 //! Kafka helper methods wrapping the kafka_threadpool APIs
 //!
+pub mod event_bus;
+pub mod kafka_dead_letters;
+pub mod kafka_publisher;
 pub mod publish_msg;
+pub mod schema_registry;
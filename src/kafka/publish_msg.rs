@@ -2,17 +2,18 @@
 //!
 use std::collections::HashMap;
 
-use kafka_threadpool::kafka_publisher::KafkaPublisher;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::monitoring::otel::trace_client_span;
 
 /// publish_msg
 ///
 /// Wrapper for
-/// [`kafka_threadpool::kafka_publisher::KafkaPublisher::add_data_msg()`](kafka_threadpool::kafka_publisher::KafkaPublisher::add_data_msg)
+/// [`KafkaPublisher::add_data_msg()`](crate::kafka::kafka_publisher::KafkaPublisher::add_data_msg)
 /// that will only publish to kafka if the environment variable ``KAFKA_ENABLED`` is ``true`` or ``1``
 ///
 /// # Arguments
 ///
-/// * `kafka_pool` - initialized [`KafkaPublisher`](kafka_threadpool::kafka_publisher::KafkaPublisher)
+/// * `kafka_pool` - initialized [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
 /// that can publish messages to the configured kafka cluster
 /// * `topic` - kafka topic to publish the message into
 /// * `key` - kafka partition key
@@ -28,19 +29,62 @@ pub async fn publish_msg(
 ) {
     // if enabled, publish the event to kafka
     if kafka_pool.is_enabled() {
-        match kafka_pool.add_data_msg(topic, key, headers, payload).await {
-            Ok(res_str) => {
-                trace!(
-                    "kafka publisher: res={res_str} \
-                    topic={topic} key={key}"
-                )
-            }
-            Err(err_str) => {
-                error!(
-                    "failed to publish login to \
-                    kafka with err={err_str}"
-                )
-            }
+        if let Err(err_str) =
+            try_publish_msg(kafka_pool, topic, key, headers, payload).await
+        {
+            error!(
+                "failed to publish login to \
+                kafka with err={err_str}"
+            )
         }
     }
 }
+
+/// try_publish_msg
+///
+/// Hand a message to the kafka publisher and return the
+/// result so callers can retry it (the caller checks
+/// ``KAFKA_ENABLED`` with
+/// [`KafkaPublisher::is_enabled()`](crate::kafka::kafka_publisher::KafkaPublisher::is_enabled))
+///
+/// # Arguments
+///
+/// * `kafka_pool` - initialized [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+///   that can publish messages to the configured kafka cluster
+/// * `topic` - kafka topic to publish the message into
+/// * `key` - kafka partition key
+/// * `headers` - optional - headers for the kafka message
+/// * `payload` - data within the kafka message
+///
+/// # Returns
+///
+/// Ok(`usize`) - number of messages waiting in the publisher
+///
+/// # Errors
+///
+/// Err(err_msg: `String`) when the publisher rejects the message
+///
+pub async fn try_publish_msg(
+    kafka_pool: &KafkaPublisher,
+    topic: &str,
+    key: &str,
+    headers: Option<HashMap<String, String>>,
+    payload: &str,
+) -> Result<usize, String> {
+    let res = trace_client_span(
+        "kafka publish",
+        vec![
+            ("messaging.system", "kafka".to_string()),
+            ("messaging.destination.name", topic.to_string()),
+        ],
+        kafka_pool.add_data_msg(topic, key, headers, payload),
+    )
+    .await;
+    if let Ok(res_str) = &res {
+        trace!(
+            "kafka publisher: res={res_str} \
+            topic={topic} key={key}"
+        )
+    }
+    res
+}
@@ -0,0 +1,507 @@
+//! Register the ``UserEvent`` schema with a Confluent Schema
+//! Registry and publish user events in the Confluent wire
+//! format for strict kafka consumers
+//!
+//! When ``KAFKA_SCHEMA_REGISTRY_URL`` is set (and
+//! ``KAFKA_PUBLISH_EVENTS`` is enabled) the server:
+//!
+//! - checks the ``UserEvent`` schema is compatible with the
+//!   latest version of the ``TOPIC-value`` subject for each
+//!   user event topic and does not start when it is not
+//! - registers the schema (or looks up the registered id with
+//!   ``KAFKA_SCHEMA_REGISTRY_AUTO_REGISTER=0``)
+//! - publishes each user event encoded with
+//!   [`encode_user_event`](crate::events::user_event_encoding::encode_user_event)
+//!
+//! The kafka threadpool only publishes utf-8 payloads, so
+//! framed payloads are published with a kafka producer
+//! built from the threadpool's ``KafkaClientConfig``
+//! that waits for the brokers to acknowledge each message.
+//! Messages that fail are retried and stored as dead letters
+//! in the text payload format and encoded again when they
+//! are requeued.
+//!
+use std::collections::HashMap;
+use std::time::Duration;
+
+use hyper::body::to_bytes;
+use hyper::Body;
+use hyper::Client;
+use hyper::Method;
+use hyper::Request;
+
+use hyper_tls::HttpsConnector;
+
+use serde_json::json;
+use serde_json::Value;
+
+use crate::events::user_event::UserEvent;
+use crate::events::user_event_encoding::encode_user_event;
+use crate::events::user_event_encoding::get_user_event_schema;
+use crate::events::user_event_encoding::get_user_event_schema_type;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+
+/// SchemaRegistryProducer
+///
+/// Kafka producer for framed payloads
+///
+#[cfg(feature = "kafka")]
+pub type SchemaRegistryProducer = rdkafka::producer::FutureProducer;
+
+/// SchemaRegistryProducer
+///
+/// No-op producer used when restapi is built without the
+/// ``kafka`` feature
+///
+#[cfg(not(feature = "kafka"))]
+#[derive(Clone)]
+pub struct SchemaRegistryProducer {}
+
+/// SchemaRegistry
+///
+/// Settings for registering the ``UserEvent`` schema and
+/// encoding user events
+///
+/// # Supported Environment Variables
+///
+/// ```bash
+/// # schema registry url (empty = publish text payloads)
+/// export KAFKA_SCHEMA_REGISTRY_URL="https://schema-registry:8081"
+/// # avro or json (json schema)
+/// export KAFKA_SCHEMA_REGISTRY_FORMAT="avro"
+/// # optional basic auth
+/// export KAFKA_SCHEMA_REGISTRY_USERNAME=""
+/// export KAFKA_SCHEMA_REGISTRY_PASSWORD=""
+/// # 0 = only use a schema an operator already registered
+/// export KAFKA_SCHEMA_REGISTRY_AUTO_REGISTER="1"
+/// export KAFKA_SCHEMA_REGISTRY_TIMEOUT_MS="5000"
+/// ```
+///
+/// # Arguments
+///
+/// * `enabled` - `bool` - encode user events for the schema
+///   registry (``KAFKA_SCHEMA_REGISTRY_URL`` is set)
+/// * `url` - `String` - schema registry url
+/// * `format` - `String` - ``avro`` or ``json``
+/// * `username` - `String` - basic auth username
+///   (empty = no auth)
+/// * `password` - `String` - basic auth password
+/// * `auto_register` - `bool` - register the schema when
+///   the server starts
+/// * `timeout_ms` - `u64` - max milliseconds for each
+///   schema registry request
+/// * `schema_ids` - `HashMap<String, u32>` - registered
+///   schema id for each user event topic (set when the
+///   server starts)
+/// * `producer` - `Option<`[`SchemaRegistryProducer`](crate::kafka::schema_registry::SchemaRegistryProducer)`>` -
+///   kafka producer for framed payloads (set when the api
+///   server starts)
+/// * `text_topics` - `Vec<String>` - topics for payloads
+///   that are not user events (like the rendered user
+///   emails) which are always published as text
+///
+#[derive(Clone, Default)]
+pub struct SchemaRegistry {
+    pub enabled: bool,
+    pub url: String,
+    pub format: String,
+    pub username: String,
+    pub password: String,
+    pub auto_register: bool,
+    pub timeout_ms: u64,
+    pub schema_ids: HashMap<String, u32>,
+    pub producer: Option<SchemaRegistryProducer>,
+    pub text_topics: Vec<String>,
+}
+
+impl SchemaRegistry {
+    /// build_schema_registry
+    ///
+    /// Build a
+    /// [`SchemaRegistry`](crate::kafka::schema_registry::SchemaRegistry)
+    /// from environment variables (without schema ids or a
+    /// producer)
+    ///
+    pub fn build_schema_registry() -> Self {
+        let url = std::env::var("KAFKA_SCHEMA_REGISTRY_URL")
+            .unwrap_or_default()
+            .trim()
+            .trim_end_matches('/')
+            .to_string();
+        let auto_register =
+            std::env::var("KAFKA_SCHEMA_REGISTRY_AUTO_REGISTER")
+                .unwrap_or_else(|_| "1".to_string());
+        SchemaRegistry {
+            enabled: !url.is_empty(),
+            url,
+            format: std::env::var("KAFKA_SCHEMA_REGISTRY_FORMAT")
+                .unwrap_or_else(|_| "avro".to_string())
+                .trim()
+                .to_lowercase(),
+            username: std::env::var("KAFKA_SCHEMA_REGISTRY_USERNAME")
+                .unwrap_or_default(),
+            password: std::env::var("KAFKA_SCHEMA_REGISTRY_PASSWORD")
+                .unwrap_or_default(),
+            auto_register: auto_register == "1" || auto_register == "true",
+            timeout_ms: std::env::var("KAFKA_SCHEMA_REGISTRY_TIMEOUT_MS")
+                .unwrap_or_else(|_| "5000".to_string())
+                .parse::<u64>()
+                .unwrap_or(5000)
+                .clamp(100, 60000),
+            schema_ids: HashMap::new(),
+            producer: None,
+            text_topics: Vec::new(),
+        }
+    }
+
+    /// is_framed
+    ///
+    /// Check if a topic's payloads are user events encoded
+    /// for the schema registry
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - `&str` - kafka topic
+    ///
+    /// # Returns
+    ///
+    /// `bool` where `true` - the registry is enabled and the
+    /// topic is not in the `text_topics`
+    ///
+    pub fn is_framed(&self, topic: &str) -> bool {
+        self.enabled && !self.text_topics.iter().any(|t| t == topic)
+    }
+
+    /// validate
+    ///
+    /// Check the url and format when the registry is enabled
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) for an unsupported url or format
+    ///
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.enabled {
+            return Ok(());
+        }
+        if !self.url.starts_with("http://") && !self.url.starts_with("https://")
+        {
+            return Err(format!(
+                "KAFKA_SCHEMA_REGISTRY_URL={} must be an http or https url",
+                self.url
+            ));
+        }
+        get_user_event_schema_type(&self.format)
+            .map(|_| ())
+            .map_err(|e| format!("KAFKA_SCHEMA_REGISTRY_FORMAT - {e}"))
+    }
+
+    /// get_subject
+    ///
+    /// Get the schema registry subject for a kafka topic
+    /// (``TOPIC-value``)
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - `&str` - kafka topic
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use restapi::kafka::schema_registry::SchemaRegistry;
+    /// assert_eq!(SchemaRegistry::get_subject("user.events"), "user.events-value");
+    /// ```
+    ///
+    pub fn get_subject(topic: &str) -> String {
+        format!("{topic}-value")
+    }
+
+    /// post_schema
+    ///
+    /// ``POST`` the ``UserEvent`` schema to a schema registry
+    /// path and return the status code and json response
+    ///
+    async fn post_schema(&self, path: &str) -> Result<(u16, Value), String> {
+        let body = json!({
+            "schemaType": get_user_event_schema_type(&self.format)?,
+            "schema": get_user_event_schema(&self.format)?,
+        });
+        let mut req = Request::builder()
+            .method(Method::POST)
+            .uri(format!("{}{path}", self.url))
+            .header("Content-Type", "application/vnd.schemaregistry.v1+json");
+        if !self.username.is_empty() {
+            req = req.header(
+                "Authorization",
+                format!(
+                    "Basic {}",
+                    base64::encode(format!(
+                        "{}:{}",
+                        self.username, self.password
+                    ))
+                ),
+            );
+        }
+        let req = req.body(Body::from(body.to_string())).map_err(|e| {
+            format!("invalid KAFKA_SCHEMA_REGISTRY_URL with err='{e}'")
+        })?;
+        let client = Client::builder().build(HttpsConnector::new());
+        let res = match tokio::time::timeout(
+            Duration::from_millis(self.timeout_ms),
+            client.request(req),
+        )
+        .await
+        {
+            Ok(Ok(res)) => res,
+            Ok(Err(e)) => {
+                return Err(format!(
+                    "schema registry request {path} failed with err='{e}'"
+                ))
+            }
+            Err(_) => {
+                return Err(format!(
+                    "schema registry request {path} timed out after {}ms",
+                    self.timeout_ms
+                ))
+            }
+        };
+        let status = res.status().as_u16();
+        let bytes = to_bytes(res.into_body()).await.map_err(|e| {
+            format!(
+                "schema registry request {path} failed to read the \
+                response with err='{e}'"
+            )
+        })?;
+        Ok((
+            status,
+            serde_json::from_slice::<Value>(&bytes).unwrap_or(Value::Null),
+        ))
+    }
+
+    /// check_compatibility
+    ///
+    /// Check the ``UserEvent`` schema is compatible with the
+    /// latest registered version of a topic's subject (a
+    /// subject without versions is compatible)
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - `&str` - kafka topic
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) when the schema is not compatible
+    /// or the schema registry request fails
+    ///
+    pub async fn check_compatibility(&self, topic: &str) -> Result<(), String> {
+        let subject = SchemaRegistry::get_subject(topic);
+        let (status, res) = self
+            .post_schema(&format!(
+                "/compatibility/subjects/{subject}/versions/latest?verbose=true"
+            ))
+            .await?;
+        match (status, res["is_compatible"].as_bool()) {
+            (200, Some(true)) | (404, _) => Ok(()),
+            (200, Some(false)) => Err(format!(
+                "UserEvent {} schema is not compatible with the latest \
+                version of subject={subject} messages={}",
+                self.format, res["messages"]
+            )),
+            _ => Err(format!(
+                "schema registry compatibility check for \
+                subject={subject} failed status={status} response={res}"
+            )),
+        }
+    }
+
+    /// get_schema_id
+    ///
+    /// Register the ``UserEvent`` schema for a topic's
+    /// subject, or look up its id when
+    /// ``KAFKA_SCHEMA_REGISTRY_AUTO_REGISTER=0``
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - `&str` - kafka topic
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) when the schema cannot be
+    /// registered or is not registered
+    ///
+    pub async fn get_schema_id(&self, topic: &str) -> Result<u32, String> {
+        let subject = SchemaRegistry::get_subject(topic);
+        let path = match self.auto_register {
+            true => format!("/subjects/{subject}/versions"),
+            false => format!("/subjects/{subject}"),
+        };
+        let (status, res) = self.post_schema(&path).await?;
+        match (status, res["id"].as_u64()) {
+            (200, Some(schema_id)) => u32::try_from(schema_id).map_err(|_| {
+                format!("invalid schema id={schema_id} for subject={subject}")
+            }),
+            (404, _) if !self.auto_register => Err(format!(
+                "UserEvent {} schema is not registered for \
+                subject={subject} (KAFKA_SCHEMA_REGISTRY_AUTO_REGISTER=0)",
+                self.format
+            )),
+            _ => Err(format!(
+                "schema registry failed to register subject={subject} \
+                status={status} response={res}"
+            )),
+        }
+    }
+
+    /// register_schemas
+    ///
+    /// Check compatibility and store the schema id for each
+    /// user event topic when the server starts
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - caller logging label
+    /// * `topics` - `&[String]` - user event topics
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) for an invalid config, an
+    /// incompatible schema or a failed request
+    ///
+    pub async fn register_schemas(
+        &mut self,
+        tracking_label: &str,
+        topics: &[String],
+    ) -> Result<(), String> {
+        self.validate()?;
+        for topic in topics.iter() {
+            self.check_compatibility(topic).await?;
+            let schema_id = self.get_schema_id(topic).await?;
+            info!(
+                "{tracking_label} - \
+                user events topic={topic} use {} schema id={schema_id}",
+                self.format
+            );
+            self.schema_ids.insert(topic.clone(), schema_id);
+        }
+        Ok(())
+    }
+
+    /// start_producer
+    ///
+    /// Build the kafka producer for framed payloads from the
+    /// kafka threadpool's config
+    ///
+    /// # Arguments
+    ///
+    /// * `kafka_pool` - initialized [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+    ///
+    #[cfg(feature = "kafka")]
+    pub fn start_producer(&mut self, kafka_pool: &KafkaPublisher) {
+        if self.enabled && kafka_pool.is_enabled() {
+            self.producer = Some(
+                kafka_threadpool::api::get_kafka_producer::get_kafka_producer(
+                    &kafka_pool.config,
+                ),
+            );
+        }
+    }
+
+    /// start_producer
+    ///
+    /// No-op without the ``kafka`` feature
+    ///
+    /// # Arguments
+    ///
+    /// * `_kafka_pool` - no-op [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+    ///
+    #[cfg(not(feature = "kafka"))]
+    pub fn start_producer(&mut self, _kafka_pool: &KafkaPublisher) {}
+
+    /// try_publish
+    ///
+    /// Encode a text user event payload and wait for the
+    /// brokers to acknowledge it
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - `&str` - kafka topic
+    /// * `key` - `&str` - kafka partition key
+    /// * `payload` - `&str` - user event payload
+    ///   (``EVENT_NAME user=USER_ID [DETAILS]``)
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) when the topic has no schema id,
+    /// the payload is not a user event or the brokers reject
+    /// the message
+    ///
+    pub async fn try_publish(
+        &self,
+        topic: &str,
+        key: &str,
+        payload: &str,
+    ) -> Result<(), String> {
+        let schema_id = match self.schema_ids.get(topic) {
+            Some(schema_id) => *schema_id,
+            None => {
+                return Err(format!(
+                    "no {} schema id for topic={topic}",
+                    self.format
+                ))
+            }
+        };
+        let event = payload.parse::<UserEvent>()?;
+        let framed = encode_user_event(&event, &self.format, schema_id)?;
+        self.send(topic, key, &framed).await
+    }
+
+    /// send
+    ///
+    /// Publish framed bytes and wait for the brokers
+    ///
+    #[cfg(feature = "kafka")]
+    async fn send(
+        &self,
+        topic: &str,
+        key: &str,
+        framed: &[u8],
+    ) -> Result<(), String> {
+        let producer = match &self.producer {
+            Some(producer) => producer,
+            None => {
+                return Err("schema registry kafka producer was not \
+                    started"
+                    .to_string())
+            }
+        };
+        producer
+            .send(
+                rdkafka::producer::FutureRecord::to(topic)
+                    .key(key)
+                    .payload(framed),
+                Duration::from_secs(0),
+            )
+            .await
+            .map(|_| ())
+            .map_err(|(e, _)| {
+                format!("kafka rejected topic={topic} key={key} with err='{e}'")
+            })
+    }
+
+    /// send
+    ///
+    /// Drop the message without the ``kafka`` feature
+    ///
+    #[cfg(not(feature = "kafka"))]
+    async fn send(
+        &self,
+        topic: &str,
+        key: &str,
+        _framed: &[u8],
+    ) -> Result<(), String> {
+        Err(format!(
+            "kafka publishing topic={topic} key={key} requires \
+            building restapi with --features kafka"
+        ))
+    }
+}
@@ -18,14 +18,22 @@
 //!
 //! ### User
 //!
-//! - User password reset and user email change support using one-time-use tokens that are stored in postgres.
+//! - User password reset and user email change support using one-time-use tokens that are stored in postgres as argon2 hashes (like passwords).
 //! - Users can upload and manage files stored on AWS S3 (assuming valid credentials are loaded outside this rust project).
 //! - User passwords are hashed using [argon2](https://docs.rs/argon2/latest/argon2/).
+//! - Users can stream their events as server-sent events (``GET /user/notifications/stream``) with ``Last-Event-ID`` resume support.
+//! - User and user data search responses include an ``ETag`` and return an empty ``304`` for an unchanged ``If-None-Match``, so polling clients do not download unchanged records.
+//! - User and user data updates require the record's ``version`` (optimistic concurrency), so a concurrent ``PUT`` gets a ``409`` instead of overwriting another update. Existing dbs can add the versions with the ``0008_row_versions.sql`` migration.
+//! - Optional multi-tenancy that isolates users, their data and their tokens by tenant (resolved from a header or subdomain).
+//! - Optional static file serving (``STATIC_ASSETS_DIR``) with an ``index.html`` fallback for deploying a single page app on the same tls listener.
 //!
 //! ### Auth
 //!
 //! - User authentication enabled by default
 //! - Default JWT signing keys included with [documentation for building new keys as needed](https://github.com/jay-johnson/restapi/tree/main/jwt).
+//! - Passwordless login with passkeys ([webauthn](https://docs.rs/webauthn-rs/latest/webauthn_rs/)) that issue the same JWT as the password login
+//! - Users can list and revoke their active login sessions (issued JWTs)
+//! - Failed logins are throttled by email and ip address with exponential backoff
 //!
 //! ### Database
 //!
@@ -88,12 +96,124 @@
 //! cargo build --example server
 //! ```
 //!
+//! ### Build Only the User-Management Core
+//!
+//! Kafka publishing (``kafka``), s3 file storage (``s3``), email templates (``email``) and prometheus metrics (``metrics``) are default cargo features. Build without them to skip compiling ``rdkafka``, ``rusoto``, ``handlebars`` and ``prometheus``:
+//!
+//! ```bash
+//! cargo build --example server --no-default-features
+//! ```
+//!
+//! Without the ``kafka`` feature the server never publishes user events (``KAFKA_ENABLED`` is ignored). Without the ``s3`` feature file uploads and archive exports fail unless ``DEMO_MODE=1`` stores them in a local directory, or a custom ``ObjectStore`` is set on the ``CoreConfig`` ``object_store`` before starting the server. Without the ``email`` feature no emails are rendered or published (``EMAIL_TEMPLATES_ENABLED`` is ignored). Without the ``metrics`` feature every metric is a no-op and ``GET /metrics`` is not routed. Add single features back with ``--features kafka``, ``--features s3``, ``--features email`` or ``--features metrics``.
+//!
 //! ### Run API Server
 //!
 //! ```bash
 //! export RUST_BACKTRACE=1 && export RUST_LOG=info,kafka_threadpool=info && ./target/debug/examples/server
 //! ```
 //!
+//! ### API Server Commands
+//!
+//! The example server binary has subcommands for bootstrapping a deployment without psql. Every command uses the same environment variables as the server:
+//!
+//! ```bash
+//! # create the db schema on an empty db or apply the pending
+//! # docker/db/sql/migrations (tracked in the schema_migrations table)
+//! ./target/debug/examples/server migrate
+//! # create the first admin (a random password is printed once
+//! # when ADMIN_PASSWORD is not set)
+//! ADMIN_PASSWORD="change-me" ./target/debug/examples/server create-admin admin@example.com
+//! # write a new <kid>.private-key-pkcs8.pem and <kid>.public-key.pem
+//! # to SERVER_PKI_DIR_JWT then reload the servers with: kill -HUP <PID>
+//! ./target/debug/examples/server rotate-jwt-keys
+//! # start the server (same as no command)
+//! ./target/debug/examples/server serve
+//! ```
+//!
+//! ### Validate the API Server Config
+//!
+//! Check every tls file, jwt key and environment variable the server needs and exit with a report (exit code ``1`` when anything is wrong) instead of starting the server. Each error names the environment variable and file to fix:
+//!
+//! ```bash
+//! ./target/debug/examples/server check-config
+//! ```
+//!
+//! ```text
+//! config has 2 error(s):
+//! - API_TLS_CERT=./certs/tls/api/server.pem - failed to read file with err='No such file or directory (os error 2)'
+//! - TOKEN_ALGO_PUBLIC_KEY=./jwt/public-key.pem - not a valid ES256 pem public key with err='InvalidKeyFormat'
+//! ```
+//!
+//! ### Run API Server in Demo Mode
+//!
+//! Demo mode seeds an admin (``admin@email.com``), two users (``alice@email.com`` and ``bob@email.com``) and sample files, and stores uploaded files under ``./demo-s3`` instead of s3, so every endpoint can be tried without aws credentials. Demo mode does not have an in-memory or SQLite store: every handler queries postgres directly, so it needs the same tls assets, jwt keys and postgres db as a normal server (see the steps above), and the server stops if the demo data cannot be seeded.
+//!
+//! ```bash
+//! export DEMO_MODE=1 && export RUST_LOG=info && cargo run --example server
+//! ```
+//!
+//! ### Embed the API Server in Another Binary
+//!
+//! Set the db endpoints, tls paths, jwt keys, kafka options and extra routes in code with a [`RestApiServerBuilder`](crate::core::server::rest_api_server::RestApiServerBuilder) instead of exporting environment variables. Anything that is not set on the builder falls back to its environment variable (``SERVER_NAME_LABEL`` still replaces the builder's label when it is set). Extra routes implement the [`CustomRoute`](crate::core::server::custom_route::CustomRoute) trait and are checked before the built-in routes, so they can add new urls or replace built-in ones (see the ``embedded_server`` example for a ``GET /hello`` route).
+//!
+//! ```rust,no_run
+//! use restapi::core::server::rest_api_server::RestApiServerBuilder;
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), String> {
+//!     RestApiServerBuilder::new("my-api")
+//!         .api_endpoint("0.0.0.0:3000")
+//!         .api_tls("./tls/ca/ca.pem", "./tls/api/server.pem", "./tls/api/server-key.pem")
+//!         .db_endpoint("0.0.0.0:5432")
+//!         .db_credentials("datawriter", "123321")
+//!         .db_name("mydb")
+//!         .jwt_key_files("./jwt/private-key-pkcs8.pem", "./jwt/public-key.pem")
+//!         .kafka_publish_events(false)
+//!         .build()
+//!         .await?
+//!         .serve()
+//!         .await;
+//!     Ok(())
+//! }
+//! ```
+//!
+//! ```bash
+//! cargo run --example embedded_server
+//! ```
+//!
+//! ### Stream Large S3 Downloads to Disk
+//!
+//! Batch export jobs built on this crate can save multi-GB objects with [`s3_download_to_file_with_options`](crate::is3::s3_download_to_file::s3_download_to_file_with_options) (or [`ObjectStore::download_to_file`](crate::is3::object_store::ObjectStore::download_to_file) on ``config.object_store``). Objects are streamed to ``FILE_PATH.part`` in ``chunk_bytes`` chunks (default 8 MiB) instead of being buffered in memory, and the file is renamed to ``FILE_PATH`` once it is complete. [`DownloadOptions`](crate::is3::download_options::DownloadOptions) sets an optional bandwidth cap (``max_bytes_per_second``) and a ``progress`` callback that runs after each chunk. Transient errors are retried with the s3 retry policy and resume from the last written byte with a ``Range`` request, and the partial file is removed if the download fails.
+//!
+//! ```rust,no_run
+//! use std::sync::Arc;
+//! use restapi::is3::download_options::DownloadOptions;
+//! use restapi::is3::s3_download_to_file::s3_download_to_file_with_options;
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), String> {
+//!     let options = DownloadOptions {
+//!         chunk_bytes: 16 * 1024 * 1024,
+//!         max_bytes_per_second: 50 * 1024 * 1024,
+//!         progress: Some(Arc::new(|progress| {
+//!             println!(
+//!                 "{}: {}/{:?} bytes",
+//!                 progress.key, progress.downloaded_bytes, progress.total_bytes
+//!             );
+//!         })),
+//!     };
+//!     s3_download_to_file_with_options(
+//!         "export",
+//!         "/data/export.tar",
+//!         "YOUR_BUCKET",
+//!         "exports/export.tar",
+//!         &options,
+//!     )
+//!     .await?;
+//!     Ok(())
+//! }
+//! ```
+//!
 //! ## Environment Variables
 //!
 //! ### Rest API
@@ -115,26 +235,106 @@
 //! USER_EMAIL_VERIFICATION_REQUIRED       | "0"
 //! USER_EMAIL_VERIFICATION_ENABLED        | "1"
 //! USER_EMAIL_VERIFICATION_EXP_IN_SECONDS | "2592000"
+//! USER_EMAIL_CHECK_DOMAIN                | "0"
+//! USER_EMAIL_CHECK_DOMAIN_TIMEOUT_MS     | "2000"
+//!
+//! Only hashes of the verification and one-time-use password tokens are stored in the db. The verification url (with the token) is only logged when ``DEBUG=1``.
 //!
 //! ### User One-Time-Use Token Expiration for Password Recovery
 //!
-//! Environment Variable    | Default
-//! ----------------------- | -------
-//! USER_OTP_EXP_IN_SECONDS | "2592000"
+//! Environment Variable               | Default
+//! ---------------------------------- | -------
+//! USER_OTP_EXP_IN_SECONDS            | "2592000"
+//! USER_OTP_TOKEN_CHARSET             | "uuid"
+//! USER_OTP_TOKEN_LENGTH              | "32"
+//! USER_OTP_RATE_LIMIT_MAX            | "5"
+//! USER_OTP_RATE_LIMIT_WINDOW_SECONDS | "3600"
+//! USER_OTP_MAX_ATTEMPTS              | "5"
+//! USER_OTP_DELIVERY                  | "response"
+//! USER_OTP_RESET_URL                 | ""
+//!
+//! Each user has at most one active one-time-use token. Creating a new token marks the user's unconsumed tokens as replaced (``users_otp.state = 2``), and users that create more than ``USER_OTP_RATE_LIMIT_MAX`` tokens within ``USER_OTP_RATE_LIMIT_WINDOW_SECONDS`` get a ``429`` with a ``Retry-After`` header (``0`` disables the limit). Only argon2 hashes of the tokens (with ``SERVER_PASSWORD_SALT``) are stored, the user's tokens are found by ``user_id`` and ``email`` and their hashes are compared in constant time (never in sql), and a token is locked (``users_otp.state = 3``) after ``USER_OTP_MAX_ATTEMPTS`` wrong tokens for the user (``0`` disables the lock), so short ``numeric`` tokens cannot be brute-forced. Dbs with tokens created before tokens were hashed should apply ``0018_users_otp_attempts.sql``, which removes the plaintext tokens. ``USER_OTP_TOKEN_CHARSET`` supports ``uuid`` (two uuids), ``alphanumeric``, ``hex`` and ``numeric`` tokens with ``USER_OTP_TOKEN_LENGTH`` characters (``6`` to ``256``). With ``USER_OTP_DELIVERY=email`` the token is not returned to the client and is only published in the ``USER_CREATE_OTP`` user event (``email=EMAIL token=TOKEN``) for a mail service, so ``KAFKA_PUBLISH_EVENTS`` must be enabled. When ``USER_OTP_RESET_URL`` is set (for example ``https://app.example.com/reset``) the event also has a ``link=URL?user_id=ID&email=EMAIL&token=TOKEN`` for the email.
+//!
+//! ### User Self-Service Deletion
+//!
+//! Environment Variable       | Default
+//! -------------------------- | -------
+//! USER_DELETE_EXP_IN_SECONDS | "86400"
+//! USER_DELETE_PURGE_DAYS     | "30"
+//! USER_DELETE_CONFIRM_URL    | ""
+//!
+//! Users delete their own account in two steps. ``POST /user/delete/request`` publishes a one-time-use confirmation token in a ``USER_DELETE_REQUEST`` user event (``email=EMAIL token=TOKEN`` plus ``link=URL?user_id=ID&token=TOKEN`` when ``USER_DELETE_CONFIRM_URL`` is set) for a mail service, so ``KAFKA_PUBLISH_EVENTS`` must be enabled. ``POST /user/delete/confirm`` consumes the token within ``USER_DELETE_EXP_IN_SECONDS``, sets ``users.state = 1``, revokes all of the user's tokens and sets ``users_data.expires_at`` to ``USER_DELETE_PURGE_DAYS`` days later, so the upload lifecycle task (``USERS_DATA_LIFECYCLE_ENABLED=1``) deletes the user's files from s3. Existing dbs need the ``0017_users_delete_requests.sql`` migration.
+//!
+//! ### User Invites
+//!
+//! Environment Variable       | Default
+//! -------------------------- | -------
+//! USER_INVITE_EXP_IN_SECONDS | "604800"
 //!
 //! ### Postgres Database
 //!
 //! Environment Variable  | Default
-//! --------------------- | -------
-//! DB_NAME               | mydb
-//! POSTGRES_USERNAME     | datawriter
-//! POSTGRES_PASSWORD     | "123321"
-//! POSTGRES_ENDPOINT     | 0.0.0.0:5432
-//! POSTGRES_TLS_DIR      | ./tls/postgres
-//! POSTGRES_TLS_CA       | ./tls/ca/ca.pem
-//! POSTGRES_TLS_CERT     | ./tls/postgres/client.pem
-//! POSTGRES_TLS_KEY      | ./tls/postgres/client-key.pem
-//! POSTGRES_DB_CONN_TYPE | postgresql
+//! ----------------------------- | -------
+//! DB_NAME                       | mydb
+//! POSTGRES_USERNAME             | datawriter
+//! POSTGRES_PASSWORD             | "123321"
+//! POSTGRES_ENDPOINT             | 0.0.0.0:5432
+//! POSTGRES_TLS_DIR              | ./tls/postgres
+//! POSTGRES_TLS_CA               | ./tls/ca/ca.pem
+//! POSTGRES_TLS_CERT             | ./tls/postgres/client.pem
+//! POSTGRES_TLS_KEY              | ./tls/postgres/client-key.pem
+//! POSTGRES_DB_CONN_TYPE         | postgresql
+//! POSTGRES_READ_ENDPOINTS       | "" (comma-separated replicas)
+//! POSTGRES_READ_TIMEOUT_MS      | "500"
+//! POSTGRES_READ_RETRY_SECONDS   | "10"
+//! POSTGRES_STATEMENT_TIMEOUT_MS | "30000"
+//! POSTGRES_SLOW_QUERY_MS        | "500"
+//!
+//! Set ``POSTGRES_READ_ENDPOINTS`` (for example ``replica-0:5432,replica-1:5432``) to send the read-only ``GET /user/ID``, ``POST /user/search`` and ``POST /user/data/search`` queries to postgres read replicas in round robin. Replicas use the primary's credentials, ``DB_NAME`` and ``POSTGRES_TLS_CA``. Writes, token validation and admin checks always use the primary. A replica that does not return a connection within ``POSTGRES_READ_TIMEOUT_MS`` is skipped for ``POSTGRES_READ_RETRY_SECONDS``, and reads fall back to the primary when every replica is down. Replicas can lag behind the primary, so a read right after a write may return the previous row. Reads are counted in the ``db_read_pool_total`` prometheus metric by ``target`` (``replica`` or ``primary``).
+//!
+//! Every postgres connection (primary and replicas) runs with a ``statement_timeout`` of ``POSTGRES_STATEMENT_TIMEOUT_MS`` (``0`` disables it), so postgres cancels a runaway query instead of holding its connection. A handler that fails because its query was canceled returns a ``504`` with ``error_code`` ``QUERY_TIMEOUT`` instead of a ``500``. Every query's latency is observed in the ``db_query_duration_seconds`` prometheus histogram by sql ``operation`` and the route's ``resource`` and ``method`` labels (``background`` for tasks outside of a request), and queries slower than ``POSTGRES_SLOW_QUERY_MS`` (``0`` disables it) are logged as warnings with their duration, handler and request id and counted in ``db_slow_queries_total``. The sql statement is never logged because queries contain user values (see [db_query_limits](crate::pools::db_query_limits)).
+//!
+//! The api server warns at startup about any missing search, login, one-time-use token, jwt key report, upload pipeline and notifications indexes (``lower(email)``, ``users_data(user_id, created_at)``, trigram indexes for the ``ILIKE`` filters, the one active token per user and token expiry indexes on ``users_otp`` and ``users_verified``, the consumed tokens index on ``users_tokens_consumed``, the active tokens by ``kid`` index on ``users_tokens``, the pending uploads index on ``users_data``, the ``users_notifications(user_id, id)`` index and the upload checksum, expiry and pending thumbnails indexes on ``users_data`` the resumable upload expiry index on ``users_data_uploads`` the shared user index on ``users_data_shares`` and the tag and folder indexes on ``users_data``). New dbs get them from ``docker/db/sql/init.sql``. Existing dbs can build them without blocking writes with the migrations in ``docker/db/sql/migrations``:
+//!
+//! ```bash
+//! cd docker/db
+//! DB_SQL_INIT_FILE=./sql/migrations/0001_search_indexes.sql ./init-db.sh
+//! DB_SQL_INIT_FILE=./sql/migrations/0003_users_otp_single_active.sql ./init-db.sh
+//! DB_SQL_INIT_FILE=./sql/migrations/0004_users_tokens_kid.sql ./init-db.sh
+//! DB_SQL_INIT_FILE=./sql/migrations/0005_users_data_status.sql ./init-db.sh
+//! DB_SQL_INIT_FILE=./sql/migrations/0006_users_notifications.sql ./init-db.sh
+//! DB_SQL_INIT_FILE=./sql/migrations/0007_tenants.sql ./init-db.sh
+//! DB_SQL_INIT_FILE=./sql/migrations/0008_row_versions.sql ./init-db.sh
+//! DB_SQL_INIT_FILE=./sql/migrations/0009_users_data_checksum.sql ./init-db.sh
+//! DB_SQL_INIT_FILE=./sql/migrations/0010_users_data_lifecycle.sql ./init-db.sh
+//! DB_SQL_INIT_FILE=./sql/migrations/0011_users_data_scan.sql ./init-db.sh
+//! DB_SQL_INIT_FILE=./sql/migrations/0012_users_data_derivatives.sql ./init-db.sh
+//! DB_SQL_INIT_FILE=./sql/migrations/0013_users_data_uploads.sql ./init-db.sh
+//! DB_SQL_INIT_FILE=./sql/migrations/0014_users_data_shares.sql ./init-db.sh
+//! DB_SQL_INIT_FILE=./sql/migrations/0015_users_data_tags.sql ./init-db.sh
+//! DB_SQL_INIT_FILE=./sql/migrations/0016_users_quota.sql ./init-db.sh
+//! DB_SQL_INIT_FILE=./sql/migrations/0017_users_delete_requests.sql ./init-db.sh
+//! DB_SQL_INIT_FILE=./sql/migrations/0018_users_otp_attempts.sql ./init-db.sh
+//! DB_SQL_INIT_FILE=./sql/migrations/0019_webhooks.sql ./init-db.sh
+//! DB_SQL_INIT_FILE=./sql/migrations/0020_kafka_dead_letters.sql ./init-db.sh
+//! DB_SQL_INIT_FILE=./sql/migrations/0021_users_tokens_consumed.sql ./init-db.sh
+//! DB_SQL_INIT_FILE=./sql/migrations/0022_users_locale.sql ./init-db.sh
+//! DB_SQL_INIT_FILE=./sql/migrations/0023_users_tokens_geo.sql ./init-db.sh
+//! DB_SQL_INIT_FILE=./sql/migrations/0024_users_data_broken.sql ./init-db.sh
+//! DB_SQL_INIT_FILE=./sql/migrations/0025_users_data_versions.sql ./init-db.sh
+//! DB_SQL_INIT_FILE=./sql/migrations/0026_users_data_access.sql ./init-db.sh
+//! DB_SQL_INIT_FILE=./sql/migrations/0027_users_data_uploading_sloc.sql ./init-db.sh
+//! DB_SQL_INIT_FILE=./sql/migrations/0028_users_password_scheme.sql ./init-db.sh
+//! DB_SQL_INIT_FILE=./sql/migrations/0029_users_logins.sql ./init-db.sh
+//! DB_SQL_INIT_FILE=./sql/migrations/0030_users_admin_actions.sql ./init-db.sh
+//! DB_SQL_INIT_FILE=./sql/migrations/0031_ip_rules.sql ./init-db.sh
+//! ```
+//!
+//! Or apply every pending migration with the api server's ``migrate`` command (see [API Server Commands](#api-server-commands)):
+//!
+//! ```bash
+//! ./target/debug/examples/server migrate
+//! ```
 //!
 //! ### Kafka Cluster
 //!
@@ -143,10 +343,13 @@
 //! Environment Variable             | Purpose / Value
 //! -------------------------------- | ---------------
 //! KAFKA_PUBLISH_EVENTS             | if set to ``true`` or ``1`` publish all user events to kafka
+//! KAFKA_TOPIC_USER_EVENTS          | kafka topic for user events (default ``user.events``)
+//! USER_EVENTS_TOPIC_*              | route matching user events to another topic (``USER_EVENTS_TOPIC_LOGIN=user.logins`` for ``LOGIN`` and ``LOGIN_PASSKEY``)
+//! KAFKA_EXCLUDE_EVENTS             | comma-delimited list of user event names to never publish (``DATA_DOWNLOADED,USER_GET``)
 //! KAFKA_ENABLED                    | toggle the kafka_threadpool on with: ``true`` or ``1`` anything else disables the threadpool
 //! KAFKA_LOG_LABEL                  | tracking label that shows up in all crate logs
 //! KAFKA_BROKERS                    | comma-delimited list of brokers (``host1:port,host2:port,host3:port``)
-//! KAFKA_TOPICS                     | comma-delimited list of supported topics
+//! KAFKA_TOPICS                     | comma-delimited list of supported topics (must include every user event topic when set)
 //! KAFKA_PUBLISH_RETRY_INTERVAL_SEC | number of seconds to sleep before each publish retry
 //! KAFKA_PUBLISH_IDLE_INTERVAL_SEC  | number of seconds to sleep if there are no message to process
 //! KAFKA_NUM_THREADS                | number of threads for the threadpool
@@ -172,14 +375,42 @@
 //! export KAFKA_METADATA_COUNT_MSG_OFFSETS="true"
 //! ```
 //!
+//! #### Route user events to topics
+//!
+//! User events go to ``KAFKA_TOPIC_USER_EVENTS`` unless a ``USER_EVENTS_TOPIC_<NAME>`` variable matches the event name or ``_``-separated words in it, for example ``USER_EVENTS_TOPIC_LOGIN=user.logins`` routes ``LOGIN`` and ``LOGIN_PASSKEY`` and ``USER_EVENTS_TOPIC_DATA=user.data`` routes every event with ``DATA`` in its name. The route with the most words wins, so ``USER_EVENTS_TOPIC_USER_UPDATE_DATA`` overrides ``USER_EVENTS_TOPIC_DATA``. Servers built with ``RestApiServerBuilder`` can add routes with ``.kafka_event_topic("LOGIN", "user.logins")``. The server does not start (and ``check-config`` fails) when a route does not match any user event or, with ``KAFKA_PUBLISH_EVENTS`` enabled, a user event topic is missing from a non-empty ``KAFKA_TOPICS``. ``GET /openapi/events.json`` lists the topic for each event.
+//!
+//! #### Consume user events in Rust
+//!
+//! Kafka consumers can depend on this crate and parse user event payloads (``EVENT_NAME user=USER_ID [key=value ...]``) with ``restapi::events::UserEvent``. The schema for every event is served at ``GET /openapi/events.json``. Download handlers publish ``DATA_DOWNLOADED`` audit events (``data=DATA_ID bytes=BYTES access=owner|acl|share_token [range=START-END]``) with ``config.events.data_downloaded(...)``, and high-volume events like this one can be skipped with ``KAFKA_EXCLUDE_EVENTS``.
+//!
+//! ```rust
+//! use restapi::events::UserEvent;
+//!
+//! let event = UserEvent::from_payload(b"USER_CREATE user=1 email=user@email.com").unwrap();
+//! assert_eq!(event.event, "USER_CREATE");
+//! assert_eq!(event.get("email"), Some("user@email.com"));
+//! ```
+//!
 //! ### S3
 //!
-//! Environment Variable | Default
-//! -------------------- | -------
-//! S3_DATA_BUCKET       | YOUR_BUCKET
-//! S3_DATA_PREFIX       | /rust-restapi/tests
-//! S3_STORAGE_CLASS     | STANDARD
-//! S3_DATA_UPLOAD_TO_S3 | "0"
+//! Environment Variable        | Default
+//! --------------------------- | -------
+//! S3_DATA_BUCKET              | YOUR_BUCKET
+//! S3_DATA_PREFIX              | /rust-restapi/tests
+//! S3_STORAGE_CLASS            | STANDARD
+//! S3_DATA_UPLOAD_TO_S3        | "0"
+//! S3_DATA_DEDUPE              | "1"
+//! USER_DATA_FILENAME_CONFLICT | "allow"
+//! UPLOAD_MAX_HEADER_BYTES     | 8192
+//! S3_RETRY_MAX_ATTEMPTS       | "3"
+//! S3_RETRY_BASE_DELAY_MS      | "100"
+//! S3_RETRY_MAX_DELAY_MS       | "5000"
+//!
+//! Each upload's sha256 checksum is stored in ``users_data.checksum``. When a user uploads the same contents again (and the upload does not set its own ``sloc``), the new record reuses the ``sloc`` of the user's newest ``pending``, ``scanning`` or ``ready`` record with that checksum, the s3 upload is skipped and the response has ``"deduplicated": true``. Set ``S3_DATA_DEDUPE=0`` to always upload.
+//!
+//! Uploads to ``POST /user/data`` pick what happens when the user already has a file with the same ``filename`` in the same ``folder`` with an ``on_conflict`` header (or metadata value), or the ``USER_DATA_FILENAME_CONFLICT`` default: ``allow`` stores another record with the filename, ``reject`` fails the upload with a ``409``, ``suffix`` stores the upload with the first free version suffix like ``report-v2.txt``, and ``overwrite`` stores the upload as the next ``file_version`` of the file. Overwritten records keep their s3 object (and count against the storage quota) as older versions with a ``replaced_by`` data id. Searches only return current versions unless they set ``data_id``, ``"include_versions": true`` or ``"versions_of": DATA_ID`` (every version of that file, which can be downloaded by its ``data_id``). Existing dbs need the ``0025_users_data_versions.sql`` migration.
+//!
+//! s3 uploads, downloads and resumable upload parts retry connection errors, ``5xx`` responses and throttling errors (``429``, ``SlowDown`` and ``RequestTimeout``) up to ``S3_RETRY_MAX_ATTEMPTS`` attempts (``1`` disables retries) with an exponential backoff from ``S3_RETRY_BASE_DELAY_MS`` up to ``S3_RETRY_MAX_DELAY_MS`` and a random jitter (see [S3RetryPolicy](crate::is3::s3_retry::S3RetryPolicy)), so a single transient error does not fail the upload. Other errors like access denied fail right away, and multipart uploads with a part that still fails are aborted. Each retry is logged with its attempt number and counted in the ``s3_retries_total{operation}`` prometheus metric, and the ``s3_request_attempts{operation,result}`` histogram records how many attempts each s3 request needed.
 //!
 //! ### JWT
 //!
@@ -189,10 +420,562 @@
 //! TOKEN_ORG                            | example.org
 //! TOKEN_HEADER                         | Bearer
 //! TOKEN_ALGO_PRIVATE_KEY               | ./jwt/private-key-pkcs8.pem
+//! TOKEN_ALGO_PRIVATE_KEY_PASSPHRASE    | "" (encrypted private keys)
 //! TOKEN_ALGO_PUBLIC_KEY                | ./jwt/public-key.pem
 //! SERVER_PKI_DIR_JWT                   | ./jwt
+//! TOKEN_CUSTOM_CLAIMS                  | "" (json object)
+//! TOKEN_SIGNING_KID                    | "" (newest loaded key)
+//! TOKEN_RETIRED_KIDS                   | "" (comma-separated kids)
+//! TOKEN_COOKIE_MODE                    | "off" (off, both or cookie)
+//! TOKEN_COOKIE_NAME                    | restapi_token
+//! TOKEN_COOKIE_SAME_SITE               | Strict
+//! TOKEN_COOKIE_DOMAIN                  | "" (api host only)
+//! TOKEN_COOKIE_PATH                    | /
+//! TOKEN_COOKIE_CSRF_NAME               | restapi_csrf
+//! TOKEN_COOKIE_CSRF_HEADER             | X-CSRF-Token
 //! SERVER_PASSWORD_SALT                 | 78197b60-c950-4339-a52c-053165a04764
 //!
+//! #### JWT Key Rotation
+//!
+//! Rotated jwt keys are loaded by key id (``kid``) from ``SERVER_PKI_DIR_JWT`` using the files ``<kid>.private-key-pkcs8.pem`` and ``<kid>.public-key.pem``. New tokens are signed with the greatest (sorted) ``kid`` (or the pinned ``jwt_signing_kid``) and existing tokens are validated with the key matching their ``kid`` until they expire. Send a ``SIGHUP`` to the server to reload the keys without downtime.
+//!
+//! #### Encrypted JWT Private Keys
+//!
+//! Keep the signing key encrypted on disk with a passphrase-protected PKCS#8 private key (``BEGIN ENCRYPTED PRIVATE KEY``) and set ``TOKEN_ALGO_PRIVATE_KEY_PASSPHRASE`` (or store it in the secrets manager with the same key, see ``SECRETS_PROVIDER``). The passphrase decrypts the ``default`` and rotated ``<kid>.private-key-pkcs8.pem`` keys in memory when the keys are loaded or reloaded, and the server does not start if an encrypted key has a missing or wrong passphrase. Set ``TOKEN_ALGO_PRIVATE_KEY_PASSPHRASE`` before running ``./jwt/recreate-jwt.sh`` or ``./jwt/create-jwt-kid.sh`` to create encrypted keys, or encrypt an existing key with ``openssl pkcs8 -topk8 -v2 aes-256-cbc -passout env:TOKEN_ALGO_PRIVATE_KEY_PASSPHRASE -in private-key.pem -out private-key-pkcs8.pem``.
+//!
+//! #### Blue/Green JWT Key Migration
+//!
+//! Move every api server to a new key without a global logout. Each token's signing ``kid`` is stored in ``users_tokens.kid``, and the ``jwt_signing_kid`` and ``jwt_retired_kids`` runtime settings (defaults ``TOKEN_SIGNING_KID`` and ``TOKEN_RETIRED_KIDS``) apply to the whole cluster:
+//!
+//! 1. Pin the current ``signing_kid`` (from ``GET /admin/jwt/keys``) so the new key is only used for validation: ``PUT /admin/settings`` with ``{"jwt_signing_kid":"CURRENT_KID"}``
+//! 2. Create the new keypair on every api server and send a ``SIGHUP``: ``./jwt/create-jwt-kid.sh 20261018``
+//! 3. Switch signing to the new key: ``PUT /admin/settings`` with ``{"jwt_signing_kid":"20261018"}``. Tokens signed with the old key keep working.
+//! 4. Watch ``active_tokens`` for the old key drop as tokens expire and users log in again with ``GET /admin/jwt/keys``
+//! 5. Retire the old key with ``POST /admin/jwt/keys/retire``. This returns a ``409`` while the old key still has active tokens. Set ``force`` to ``true`` to revoke only those tokens.
+//! 6. Remove the old key files after the last api server has the retired setting
+//!
+//! #### Custom JWT Claims
+//!
+//! Add extra claims (``roles``, ``tenant_id``, ``scopes``) to every new jwt by setting ``TOKEN_CUSTOM_CLAIMS`` to a json object (i.e. ``'{"tenant_id":"acme"}'``). Per-user claims can be computed from the db at login time by setting a ``TokenClaimsProvider`` on the ``CoreConfig.token_claims_provider`` before starting the server. The reserved claims ``sub``, ``org``, ``exp`` and ``scope`` cannot be changed.
+//!
+//! #### JWT Cookies for Browser Apps
+//!
+//! Browser apps that cannot safely keep the jwt in javascript can set ``TOKEN_COOKIE_MODE=both`` (json ``token`` and cookie) or ``TOKEN_COOKIE_MODE=cookie`` (cookie only, the json ``token`` is empty). Login, create user, accept invite and passkey login responses then set the jwt in a ``Secure``, ``HttpOnly`` and ``SameSite`` cookie named ``TOKEN_COOKIE_NAME`` that expires with the jwt. Requests are authenticated with the ``TOKEN_HEADER`` header first and then the cookie. Browsers send the cookie with every request, so cookie-authenticated ``POST``, ``PUT``, ``PATCH`` and ``DELETE`` requests are rejected with a ``403`` unless the ``TOKEN_COOKIE_CSRF_HEADER`` header matches the ``TOKEN_COOKIE_CSRF_NAME`` cookie (double-submit cookie). Logins set the csrf cookie (readable by scripts) next to the jwt cookie and ``GET /csrf`` returns the current csrf token. Requests with the ``TOKEN_HEADER`` header are not checked. See [TokenCookie](crate::requests::auth::token_cookie::TokenCookie).
+//!
+//! #### Scoped Tokens
+//!
+//! Machine integrations can log in with ``scopes`` (i.e. ``{"email":"...","password":"...","scopes":["data:read"]}``) to get a jwt with a space-separated ``scope`` claim that only works on the matching routes. The supported scopes are ``user:read``, ``user:write``, ``data:read``, ``data:write`` and ``admin``, and a ``write`` scope also allows the matching ``read`` scope. ``/admin/*`` routes need ``admin``, ``/user/data*`` routes need ``data:read`` (``GET`` and ``POST /user/data/search``) or ``data:write``, and the other routes need ``user:read`` (``GET`` and ``POST /user/search``) or ``user:write``. ``/graphql`` has no route scope and each GraphQL resolver checks its own (``user:read`` for ``me`` and ``userQuota``, ``data:read`` for ``userData`` and ``data:write`` for the mutations). Scoped tokens without the route's scope are rejected with ``INSUFFICIENT_SCOPE``. Tokens without a ``scope`` claim (logins without ``scopes``) work on every route, and the ``admin`` scope does not make a user an admin. See [token_scopes](crate::requests::auth::token_scopes).
+//!
+//! ### Secrets Manager
+//!
+//! Environment Variable             | Default
+//! -------------------------------- | -------
+//! SECRETS_PROVIDER                 | "" (vault or aws)
+//! SECRETS_REFRESH_INTERVAL_SECONDS | "300"
+//! SECRETS_TIMEOUT_MS               | "5000"
+//! VAULT_ADDR                       | ""
+//! VAULT_TOKEN                      | ""
+//! VAULT_TOKEN_FILE                 | ""
+//! VAULT_NAMESPACE                  | ""
+//! SECRETS_VAULT_PATH               | secret/data/restapi
+//! SECRETS_AWS_SECRET_ID            | restapi
+//! SECRETS_AWS_REGION               | us-east-2
+//!
+//! Fetch the ``POSTGRES_PASSWORD``, ``SERVER_PASSWORD_SALT``, ``TOKEN_ALGO_PRIVATE_KEY``, ``TOKEN_ALGO_PUBLIC_KEY`` and ``TOKEN_ALGO_PRIVATE_KEY_PASSPHRASE`` values from a secrets manager at startup instead of environment variables and files on disk. ``SECRETS_PROVIDER=vault`` reads a HashiCorp Vault kv secret (version 1 or 2) at ``SECRETS_VAULT_PATH`` with ``VAULT_TOKEN`` (or a ``VAULT_TOKEN_FILE`` renewed by a vault agent), for example ``vault kv put secret/restapi POSTGRES_PASSWORD=... TOKEN_ALGO_PRIVATE_KEY=@./jwt/private-key-pkcs8.pem TOKEN_ALGO_PUBLIC_KEY=@./jwt/public-key.pem``. ``SECRETS_PROVIDER=aws`` reads the json ``SecretString`` of the AWS Secrets Manager secret ``SECRETS_AWS_SECRET_ID`` with the same aws credentials as s3 (requires the ``s3`` feature). The jwt keys are the pem contents and must be set together. Keys missing from the secret fall back to the environment variables and files, and the server does not start if the secrets cannot be fetched within ``SECRETS_TIMEOUT_MS``. The secrets are fetched again every ``SECRETS_REFRESH_INTERVAL_SECONDS`` (``0`` = only at startup) and on ``SIGHUP``. New jwt keys replace the ``default`` keys without downtime. A changed postgres password or password salt is logged as a warning until the server is restarted. Fetches are counted in the ``secrets_fetches_total{result="ok"|"error"}`` prometheus counter. Other secrets managers can be used with a [`SecretsProvider`](crate::secrets::secrets_provider::SecretsProvider) set with ``RestApiServerBuilder::secrets_provider``.
+//!
+//! ### Password Hashing
+//!
+//! Environment Variable          | Default
+//! ----------------------------- | -------
+//! PASSWORD_HASH_MEMORY_KIB      | "4096"
+//! PASSWORD_HASH_ITERATIONS      | "3"
+//! PASSWORD_HASH_PARALLELISM     | "1"
+//! PASSWORD_HASH_BENCHMARK       | "1"
+//! PASSWORD_HASH_TARGET_MIN_MS   | "0" (disabled)
+//! PASSWORD_HASH_TARGET_MAX_MS   | "1000"
+//! PASSWORD_HASH_REHASH_ON_LOGIN | "1"
+//!
+//! User passwords are hashed with argon2 using ``PASSWORD_HASH_MEMORY_KIB``, ``PASSWORD_HASH_ITERATIONS`` and ``PASSWORD_HASH_PARALLELISM``, and the server does not start if argon2 does not support them (memory must be at least 8 KiB per lane) or if a ``PASSWORD_HASH_*`` number is not a whole number. The parameters are stored in each hash, so passwords hashed before a change still work and are rehashed with the new parameters on the user's next successful login. With ``PASSWORD_HASH_BENCHMARK=1`` the server times one hash at startup (in a blocking task, so serving requests is not delayed) and logs a warning when it is faster than ``PASSWORD_HASH_TARGET_MIN_MS`` or slower than ``PASSWORD_HASH_TARGET_MAX_MS`` (``0`` disables either check). See [`PasswordHashing`](crate::utils::password_hashing::PasswordHashing).
+//!
+//! Each ``users.password_scheme`` records the scheme of the user's hash (the argon2 parameters and a fingerprint of the password salt), so changing the ``PASSWORD_HASH_*`` parameters or ``SERVER_PASSWORD_SALT`` turns the existing hashes into legacy hashes. With ``PASSWORD_HASH_REHASH_ON_LOGIN=1`` a legacy hash is replaced with a hash using the current scheme after the user's next successful login, and ``GET /admin/passwords/schemes`` reports how many users are left on each scheme. Rehashes are counted in the ``password_hash_migrations_total{result="rehashed"|"failed"}`` prometheus counter. Hashes imported from another system (like bcrypt) can be verified and migrated the same way with a [`LegacyPasswordHasher`](crate::utils::password_migration::LegacyPasswordHasher) set with ``RestApiServerBuilder::legacy_password_hasher``. Existing dbs need the ``0028_users_password_scheme.sql`` migration, which records the scheme of the existing argon2 hashes.
+//!
+//! ### Passkeys (WebAuthn)
+//!
+//! Environment Variable              | Default
+//! --------------------------------- | -------
+//! WEBAUTHN_RP_ID                    | localhost
+//! WEBAUTHN_RP_ORIGIN                | https://localhost:3000
+//! WEBAUTHN_RP_NAME                  | restapi
+//! WEBAUTHN_CHALLENGE_EXP_IN_SECONDS | "300"
+//!
+//! ### Login Throttling
+//!
+//! Environment Variable              | Default
+//! --------------------------------- | -------
+//! LOGIN_THROTTLE_ENABLED            | "1"
+//! LOGIN_THROTTLE_MAX_FAILURES       | "5"
+//! LOGIN_THROTTLE_BASE_DELAY_SECONDS | "1"
+//! LOGIN_THROTTLE_MAX_DELAY_SECONDS  | "900"
+//! LOGIN_THROTTLE_RESET_SECONDS      | "3600"
+//!
+//! Failed logins are counted per target email and per client ip (the remote address or the client address forwarded by a trusted proxy). After ``LOGIN_THROTTLE_MAX_FAILURES`` failures the email or ip is locked for ``LOGIN_THROTTLE_BASE_DELAY_SECONDS`` doubling on each additional failure (up to ``LOGIN_THROTTLE_MAX_DELAY_SECONDS``), and locked logins get a ``429`` with a ``Retry-After`` header. A successful login resets the email's count. Counters are exported as the ``login_throttle_total`` prometheus metric, and an admin can unlock an email or ip with ``POST /admin/login/unlock``.
+//!
+//! ### Login Risk Scoring
+//!
+//! Environment Variable     | Default
+//! ------------------------ | -------
+//! LOGIN_RISK_ON_ERROR      | "allow"
+//! LOGIN_RISK_HISTORY_LIMIT | "20"
+//!
+//! Wire in a risk engine by implementing a [LoginRiskHook](crate::requests::auth::login_risk::LoginRiskHook) and setting it with ``RestApiServerBuilder::login_risk_hook`` (see ``examples/embedded_server.rs``). After a user's password is verified, ``POST /login`` calls the hook with the user, the new session's ip address, user agent, ``device`` header and GeoIP location, and the user's ``LOGIN_RISK_HISTORY_LIMIT`` most recent sessions. The hook returns ``allow``, ``challenge`` or ``deny`` (``LOGIN_RISK_ON_ERROR`` is used when the hook fails):
+//!
+//! - ``allow`` creates the token.
+//! - ``deny`` returns a ``403`` with the ``LOGIN_DENIED`` error code.
+//! - ``challenge`` returns a ``401`` with the ``LOGIN_CHALLENGE_REQUIRED`` error code and the started second factors in ``challenge``. With ``KAFKA_PUBLISH_EVENTS=1`` a one-time-use password is emailed (a ``LOGIN_CHALLENGE`` user event and the ``otp`` email with ``purpose`` set to ``login``), and the client repeats the login with it as the ``otp`` field. A login with a valid ``otp`` is not scored again. Users with passkeys get ``passkey`` and can finish a passkey login instead. Challenged users without a second factor are denied.
+//!
+//! Passkey logins are not scored. Decisions are counted in the ``login_risk_decisions_total`` prometheus metric.
+//!
+//! ### Login History
+//!
+//! Environment Variable       | Default
+//! -------------------------- | -------
+//! USER_LOGINS_ENABLED        | "1"
+//! USER_LOGINS_RETENTION_DAYS | "90"
+//!
+//! Password and passkey login attempts for existing users are stored in the ``users_logins`` table with the result (``success``, ``invalid_password``, ``invalid_otp``, ``invalid_passkey``, ``unverified``, ``challenged``, ``denied`` or ``reset_required``), ip address, user agent, ``device`` header and GeoIP location. Each new attempt deletes the user's attempts older than ``USER_LOGINS_RETENTION_DAYS`` (``0`` keeps every attempt). Users list their attempts with ``GET /user/logins``. Existing dbs need the ``0029_users_logins.sql`` migration.
+//!
+//! ### Auth Failure Alerts
+//!
+//! Environment Variable          | Default
+//! ----------------------------- | -------
+//! AUTH_ALERT_WEBHOOK_URL        | ""
+//! AUTH_ALERT_WEBHOOK_TIMEOUT_MS | "5000"
+//! AUTH_ALERT_WINDOW_SECONDS     | "60"
+//! AUTH_ALERT_LOGIN_THRESHOLD    | "50"
+//! AUTH_ALERT_OTP_THRESHOLD      | "20"
+//! AUTH_ALERT_TOKEN_THRESHOLD    | "100"
+//! AUTH_ALERT_COOLDOWN_SECONDS   | "300"
+//!
+//! Failed logins (including passkey logins), one-time-password failures and token validation failures are exported as the ``auth_failures_total`` prometheus metric with ``kind`` (``login``, ``otp`` or ``token``) and ``reason`` labels (for example ``invalid_password``, ``unknown_user``, ``throttled``, ``token_mismatch``, ``expired``, ``invalid_jwt`` or ``revoked_session``). When an api server sees a kind's threshold of failures (``0`` = off) within ``AUTH_ALERT_WINDOW_SECONDS``, it logs an alert and sends it as a json ``POST`` to the ``AUTH_ALERT_WEBHOOK_URL`` (at most once per ``AUTH_ALERT_COOLDOWN_SECONDS`` for each kind). Alerts are counted in ``auth_alerts_total``, and a custom ``AuthAlertHook`` can replace the webhook on the ``CoreConfig``.
+//!
+//! ### Runtime Settings
+//!
+//! Environment Variable            | Default
+//! ------------------------------- | -------
+//! MAX_UPLOAD_SIZE_BYTES           | "0"
+//! MAINTENANCE_MODE                | "0"
+//! MAINTENANCE_RETRY_AFTER_SECONDS | "60"
+//! PASSWORD_MIN_LENGTH             | "4"
+//! PASSWORD_REQUIRE_DIGIT          | "0"
+//! PASSWORD_REQUIRE_SYMBOL         | "0"
+//! PASSWORD_REQUIRE_MIXED_CASE     | "0"
+//!
+//! Admins can override ``login_throttle_enabled``, ``login_throttle_max_failures``, ``login_throttle_base_delay_seconds``, ``login_throttle_max_delay_seconds``, ``login_throttle_reset_seconds``, ``verification_required`` (defaults to ``USER_EMAIL_VERIFICATION_REQUIRED``), ``max_upload_size_bytes``, ``maintenance_mode``, ``maintenance_retry_after_seconds``, ``jwt_signing_kid``, ``jwt_retired_kids``, ``password_min_length``, ``password_require_digit``, ``password_require_symbol`` and ``password_require_mixed_case`` at runtime with ``PUT /admin/settings``. Overrides are stored in the ``settings`` table, and a trigger sends a postgres ``settings_changed`` notification so every api server reloads its cached settings without a restart. Uploads over ``max_upload_size_bytes`` (``0`` = unlimited) get a ``413``. In maintenance mode (for example during planned db migrations) every request except ``/login``, ``/csrf``, ``/admin/*``, ``/health``, ``/metrics``, ``/.well-known/jwks.json`` and ``/openapi/events.json`` gets a ``503`` with a ``Retry-After: maintenance_retry_after_seconds`` header. ``GET /health`` needs no token and does not use the db, so load balancers and kubernetes probes keep the api servers in rotation, and it returns ``{"status":"maintenance","maintenance_mode":true,"degraded":[]}`` while maintenance mode is on. New and changed passwords (``POST /user``, ``PUT /user``, ``POST /user/password/change``, ``POST /user/invite/accept`` and the ``create-admin`` command) that do not meet the password policy get a ``422`` listing every unmet rule, and existing passwords keep working until they are changed.
+//!
+//! ### User Data Archive
+//!
+//! Environment Variable                | Default
+//! ----------------------------------- | -------
+//! USERS_DATA_ARCHIVE_ENABLED          | "0"
+//! USERS_DATA_ARCHIVE_AFTER_DAYS       | "90"
+//! USERS_DATA_ARCHIVE_BATCH_SIZE       | "1000"
+//! USERS_DATA_ARCHIVE_INTERVAL_SECONDS | "3600"
+//! USERS_DATA_ARCHIVE_S3_EXPORT        | "0"
+//! USERS_DATA_ARCHIVE_S3_BUCKET        | S3_DATA_BUCKET
+//! USERS_DATA_ARCHIVE_S3_PREFIX        | user/data/archive
+//!
+//! When enabled, each api server moves ``users_data`` rows created more than ``USERS_DATA_ARCHIVE_AFTER_DAYS`` ago into the ``users_data_archive`` table (and their shares into ``users_data_acl_archive``) in batches of ``USERS_DATA_ARCHIVE_BATCH_SIZE`` (rows with unexpired user shares or public links in ``users_data_shares`` are skipped), so searches over recent data stay fast. Each batch is moved with a single sql statement. With ``USERS_DATA_ARCHIVE_S3_EXPORT=1``, each batch is first exported to ``s3://USERS_DATA_ARCHIVE_S3_BUCKET/USERS_DATA_ARCHIVE_S3_PREFIX/users_data_<first id>_<last id>.jsonl``, and the batch stays in ``users_data`` if the export fails. Archived rows are read-only and are returned by ``POST /user/data/search`` with ``"include_archived": true``. Counters are exported as the ``users_data_archive_total`` prometheus metric.
+//!
+//! ### User Data Lifecycle
+//!
+//! Environment Variable                  | Default
+//! ------------------------------------- | -------
+//! USERS_DATA_LIFECYCLE_ENABLED          | "0"
+//! USERS_DATA_LIFECYCLE_BATCH_SIZE       | "100"
+//! USERS_DATA_LIFECYCLE_INTERVAL_SECONDS | "3600"
+//!
+//! Uploads can set their s3 storage class with a ``storage_class`` header (for example ``STANDARD_IA`` or ``GLACIER_IR``, defaults to ``S3_STORAGE_CLASS``) and a lifecycle policy with an ``expire_days`` header. When enabled, each api server finds ``users_data`` records past their ``expires_at`` in batches of ``USERS_DATA_LIFECYCLE_BATCH_SIZE``. The s3 object is deleted and the record is marked ``expired``, or, when the upload also set an ``expire_storage_class`` header (for example ``GLACIER``), the object is moved to that storage class and keeps its status. Only objects the server uploaded under ``s3://S3_DATA_BUCKET/S3_DATA_PREFIX/USER_ID/`` are deleted or transitioned, and objects other records still use are kept. Results are counted in the ``users_data_lifecycle_total`` prometheus metric.
+//!
+//! ### User Data Reconcile
+//!
+//! Environment Variable                  | Default
+//! ------------------------------------- | -------
+//! USERS_DATA_RECONCILE_ENABLED          | "0"
+//! USERS_DATA_RECONCILE_DRY_RUN          | "1"
+//! USERS_DATA_RECONCILE_INTERVAL_SECONDS | "86400"
+//! USERS_DATA_RECONCILE_MIN_AGE_SECONDS  | "86400"
+//! USERS_DATA_RECONCILE_BATCH_SIZE       | "100"
+//! USERS_DATA_RECONCILE_MAX_KEYS         | "10000"
+//!
+//! When enabled, one api server at a time (a postgres advisory lock skips the run on the others) lists every user's keys under ``s3://S3_DATA_BUCKET/S3_DATA_PREFIX/USER_ID/`` (up to ``USERS_DATA_RECONCILE_MAX_KEYS`` per user, in batches of ``USERS_DATA_RECONCILE_BATCH_SIZE`` users) and matches them with the ``users_data`` and ``users_data_archive`` records every ``USERS_DATA_RECONCILE_INTERVAL_SECONDS``. Keys without a record are orphans and are deleted, and ``ready``, ``quarantined`` and ``failed`` records without a key are marked ``broken`` (broken records cannot be downloaded, do not count against the quota and can be deleted by their owner). Only keys and records older than ``USERS_DATA_RECONCILE_MIN_AGE_SECONDS`` are changed so in-flight uploads are left alone. With ``USERS_DATA_RECONCILE_DRY_RUN=1`` (the default) nothing is deleted or marked and the drift is only logged and counted. Results are counted in the ``users_data_reconcile_total`` prometheus metric (``result`` label ``orphan``, ``deleted``, ``missing``, ``broken`` or ``failed``) and the last complete run's drift is set in the ``users_data_reconcile_drift`` gauge (``kind`` label ``orphan`` or ``missing``). Existing dbs need the ``0024_users_data_broken.sql`` migration.
+//!
+//! ### User Data Upload Pipeline
+//!
+//! Environment Variable                 | Default
+//! ------------------------------------ | -------
+//! USERS_DATA_PIPELINE_ENABLED          | "0"
+//! USERS_DATA_PIPELINE_BATCH_SIZE       | "10"
+//! USERS_DATA_PIPELINE_INTERVAL_SECONDS | "5"
+//! USERS_DATA_PIPELINE_TIMEOUT_SECONDS  | "300"
+//!
+//! Each ``users_data`` record has a ``status``: ``pending``, ``scanning``, ``ready``, ``quarantined``, ``failed``, ``expired`` (see User Data Lifecycle) or ``broken`` (see User Data Reconcile). With the pipeline disabled, uploads are created as ``ready``. When enabled, uploads are created as ``pending``, and a ``UserDataProcessor`` (for example a virus scanner) set on the ``CoreConfig`` ``user_data_pipeline.processor`` claims them in batches as ``scanning`` and stores the ``ready``, ``quarantined`` or ``failed`` result. A processor error or a run longer than ``USERS_DATA_PIPELINE_TIMEOUT_SECONDS`` marks the record ``failed``. Without a processor, ``pending`` records are left for an external pipeline to update ``users_data.status``. ``POST /user/data/search`` returns each record's ``status`` and accepts a ``status`` filter, and only ``ready`` records can be downloaded. Results are counted in the ``users_data_pipeline_total`` prometheus metric.
+//!
+//! ### Upload Scanning
+//!
+//! Environment Variable      | Default
+//! ------------------------- | -------
+//! UPLOAD_SCAN_CLAMD_ADDRESS | ""
+//! UPLOAD_SCAN_WEBHOOK_URL   | ""
+//! UPLOAD_SCAN_ACTION        | reject
+//! UPLOAD_SCAN_ON_ERROR      | reject
+//! UPLOAD_SCAN_TIMEOUT_MS    | "30000"
+//!
+//! Set ``UPLOAD_SCAN_CLAMD_ADDRESS`` (a ClamAV ``clamd`` ``host:port``) or ``UPLOAD_SCAN_WEBHOOK_URL`` to scan each ``POST /user/data`` upload before it is stored. The webhook gets the file as an ``application/octet-stream`` ``POST`` and must reply with json like ``{"status": "clean"}`` or ``{"status": "infected", "signature": "Eicar-Test-Signature"}``. Infected files are rejected with a ``422`` (``UPLOAD_SCAN_ACTION=reject``) or stored as ``quarantined`` records that cannot be downloaded (``UPLOAD_SCAN_ACTION=quarantine``). Uploads that could not be scanned are rejected with a ``503`` unless ``UPLOAD_SCAN_ON_ERROR=allow``. The result is stored in ``users_data.scan_status`` (``clean``, ``infected`` or ``error``) and ``users_data.scan_signature``, and counted in the ``upload_scans_total`` prometheus metric. Other scanners can implement the ``UploadScanner`` trait and be set on the ``CoreConfig`` ``upload_scan.scanner``.
+//!
+//! ### User Data Thumbnails
+//!
+//! Environment Variable                     | Default
+//! ---------------------------------------- | -------
+//! USERS_DATA_THUMBNAILS_ENABLED            | "0"
+//! USERS_DATA_THUMBNAILS_SIZES              | "128,512"
+//! USERS_DATA_THUMBNAILS_BATCH_SIZE         | "10"
+//! USERS_DATA_THUMBNAILS_INTERVAL_SECONDS   | "5"
+//! USERS_DATA_THUMBNAILS_TIMEOUT_SECONDS    | "120"
+//! USERS_DATA_THUMBNAILS_MAX_SOURCE_BYTES   | "26214400"
+//! S3_DATA_DERIVATIVES_PREFIX               | user/data/derivatives
+//!
+//! Thumbnails require building with ``cargo build --features thumbnails``. Each upload stores its ``Content-Type`` (the raw body's header or the multipart ``file`` part's type, or a ``content_type`` metadata field) in ``users_data.content_type``. When enabled, ``image/png``, ``image/jpeg``, ``image/gif`` and ``image/webp`` uploads are created with a ``pending`` ``derivatives_status``. Once an upload is ``ready``, each api server claims them in batches, creates one thumbnail for each of the ``USERS_DATA_THUMBNAILS_SIZES`` (max width and height, keeping the aspect ratio and never upscaling), stores it at ``s3://S3_DATA_BUCKET/S3_DATA_DERIVATIVES_PREFIX/USER_ID/DATA_ID/thumbnail_SIZE.(png|jpg)`` and records it in the ``users_data_derivatives`` table before setting the ``derivatives_status`` to ``done``. Images that cannot be decoded, are larger than ``USERS_DATA_THUMBNAILS_MAX_SOURCE_BYTES`` or take longer than ``USERS_DATA_THUMBNAILS_TIMEOUT_SECONDS`` are marked ``failed``. ``POST /user/data/search`` returns each record's ``content_type``, ``derivatives_status`` and ``derivatives`` (``kind``, ``size``, ``width``, ``height``, ``content_type``, ``size_in_bytes`` and ``sloc``). Results are counted in the ``users_data_thumbnails_total`` prometheus metric.
+//!
+//! ### Resumable Uploads
+//!
+//! Environment Variable               | Default
+//! ---------------------------------- | -------
+//! UPLOAD_RESUMABLE_MIN_CHUNK_BYTES   | "5242880"
+//! UPLOAD_RESUMABLE_MAX_CHUNK_BYTES   | "67108864"
+//! UPLOAD_RESUMABLE_MAX_BYTES         | "53687091200"
+//! UPLOAD_RESUMABLE_EXPIRE_HOURS      | "24"
+//!
+//! Large files can be uploaded in ``Content-Range`` chunks that are stored as the parts of an s3 multipart upload. ``POST /user/data/uploads`` creates a ``users_data`` record in the ``uploading`` status (which cannot be downloaded) and a ``users_data_uploads`` record for the multipart upload. Each ``PUT /user/data/DATAID/chunks`` with a ``Content-Range: bytes START-END/TOTAL`` header must start at the upload's ``received_bytes`` (``409`` otherwise) and every chunk except the last one must be at least ``UPLOAD_RESUMABLE_MIN_CHUNK_BYTES`` (s3 requires 5 MiB parts). An interrupted client gets the offset to resume from with ``GET /user/data/DATAID/chunks``. ``POST /user/data/DATAID/complete`` combines the parts once all bytes were received and sets the record to ``pending`` (or ``ready`` without the upload pipeline). Uploads that are not completed within ``UPLOAD_RESUMABLE_EXPIRE_HOURS`` are rejected with a ``410`` and aborted by the lifecycle task (``USERS_DATA_LIFECYCLE_ENABLED=1``), which marks their records ``failed``. Resumable uploads cannot be scanned before they are stored, so they are rejected when an upload scanner is set unless ``USERS_DATA_PIPELINE_ENABLED=1``.
+//!
+//! ### User Data Quotas
+//!
+//! Environment Variable                      | Default
+//! ----------------------------------------- | -------
+//! USER_DATA_QUOTA_BYTES                     | "0"
+//! USER_DATA_QUOTA_METRICS_INTERVAL_SECONDS  | "300"
+//!
+//! Each user can store up to ``USER_DATA_QUOTA_BYTES`` (``0`` = unlimited). Set a per-user override with ``UPDATE users SET quota_bytes = BYTES WHERE id = USERID;`` (``0`` = unlimited, ``NULL`` uses the default). Usage is the ``size_in_bytes`` of all the user's ``users_data`` and ``users_data_archive`` records except ``failed``, ``expired`` and ``broken`` ones. ``POST /user/data`` and ``POST /user/data/uploads`` reject files larger than the whole quota with a ``413`` and files that do not fit in the remaining quota with a ``507``. Users get their quota and usage with ``GET /user/quota``. Every ``USER_DATA_QUOTA_METRICS_INTERVAL_SECONDS`` (``0`` = disabled) each api server sets the ``user_data_used_bytes``, ``user_data_files`` and ``user_data_users_over_quota`` prometheus gauges.
+//!
+//! ### User Upload Limits
+//!
+//! Environment Variable                  | Default
+//! ------------------------------------- | -------
+//! USER_DATA_MAX_CONCURRENT_UPLOADS      | "4"
+//! USER_DATA_UPLOAD_RETRY_AFTER_SECONDS  | "1"
+//!
+//! Each user can run up to ``USER_DATA_MAX_CONCURRENT_UPLOADS`` ``POST /user/data`` uploads, resumable upload chunks (``PUT /user/data/DATAID/chunks``) and completions (``POST /user/data/DATAID/complete``) at once on each api server (``0`` = unlimited), so one user cannot saturate the s3 uplink or the db pool with parallel uploads. Uploads over the limit are rejected with a ``429`` and a ``Retry-After: USER_DATA_UPLOAD_RETRY_AFTER_SECONDS`` header after the user's token is validated, and are counted in the ``user_data_uploads_limited_total`` prometheus metric.
+//!
+//! ### S3 Event Callbacks
+//!
+//! Environment Variable        | Default
+//! --------------------------- | -------
+//! S3_EVENTS_SECRET            | ""
+//! S3_EVENTS_MAX_AGE_SECONDS   | "300"
+//!
+//! Direct-to-s3 uploads (like a client ``PUT`` to a presigned url for an ``uploading`` ``users_data`` record) can be completed without the client reporting completion. Point the bucket's ``ObjectCreated`` event notifications at an SQS queue (or a lambda) and have the poller forward each s3 event json body to ``POST /user/data/s3/events`` with a ``Content-Type: application/json`` header, an ``X-S3-Event-Timestamp`` header (unix seconds) and an ``X-S3-Event-Signature: v1=HEX`` header, where ``HEX`` is the hex-encoded ``HMAC-SHA256`` of ``TIMESTAMP.BODY`` with ``S3_EVENTS_SECRET`` (the same scheme as the outbound webhook signatures). Each ``ObjectCreated:*`` record moves the ``uploading`` record with the matching ``sloc`` (``s3://BUCKET/KEY``) to ``pending`` (or ``ready`` if the upload pipeline is disabled), stores the object's size and publishes an ``UPLOAD_USER_DATA`` event. Other records and records that already left ``uploading`` are skipped, so redelivered events are safe. The callback returns a ``404`` until ``S3_EVENTS_SECRET`` is set, a ``401`` for a missing or invalid signature or a timestamp more than ``S3_EVENTS_MAX_AGE_SECONDS`` from the server's clock, and a ``500`` if a record could not be updated (so the poller should not delete the message). Results are counted in the ``user_data_s3_events_total{result="completed"|"skipped"|"rejected"}`` prometheus counter. Existing dbs need the ``0027_users_data_uploading_sloc.sql`` migration.
+//!
+//! ### Email Templates
+//!
+//! Environment Variable    | Default
+//! ----------------------- | -------
+//! EMAIL_TEMPLATES_ENABLED | "0"
+//! EMAIL_TEMPLATES_DIR     | ""
+//! EMAIL_DEFAULT_LOCALE    | "en"
+//! KAFKA_TOPIC_USER_EMAILS | "user.emails"
+//! EMAIL_VERIFY_URL        | "https://API_ENDPOINT/user/verify"
+//! EMAIL_INVITE_URL        | ""
+//!
+//! With ``EMAIL_TEMPLATES_ENABLED=1`` (and ``KAFKA_PUBLISH_EVENTS=1``) the verification, one-time-use password (``USER_OTP_DELIVERY=email``), invite, security notification and new login emails are rendered from handlebars templates and published as json (``template``, ``locale``, ``to``, ``subject``, ``text`` and ``html``) to the ``KAFKA_TOPIC_USER_EMAILS`` topic for a mail service. The built-in English templates are in ``templates/email/en``. To brand or translate the emails, copy ``templates/email`` and set ``EMAIL_TEMPLATES_DIR`` to a directory with a subdirectory for each locale (like ``es`` or ``pt-BR``) holding ``{verify,otp,invite,security,new_login}.{subject,text,html}.hbs`` files. Each email uses the user's ``users.locale`` (set with ``locale`` when creating, updating or inviting a user), then its language (``pt`` for ``pt-BR``), then ``EMAIL_DEFAULT_LOCALE`` and then the built-in templates. Templates can use ``email``, ``locale``, ``link``, ``token``, ``exp_date``, ``change``, ``new_email``, ``changed_at``, ``device``, ``ip_address``, ``country``, ``city``, ``new_device``, ``new_location``, ``login_at`` and ``purpose`` (``login`` for a login challenge's one-time-use password). Invalid templates stop the server from starting, rendered emails are counted in the ``user_emails_total`` prometheus metric and existing dbs need the ``0022_users_locale.sql`` migration. Rendering requires the ``email`` cargo feature (a default feature).
+//!
+//! ### Security Notifications
+//!
+//! Environment Variable              | Default
+//! --------------------------------- | -------
+//! SECURITY_NOTIFICATIONS_EVENTS     | "1"
+//! SECURITY_NOTIFICATIONS_EMAILS     | "1"
+//! SECURITY_NOTIFICATIONS_NEW_LOGINS | "1"
+//!
+//! When a user's password changes (``PUT /user`` or ``POST /user/password/change``), their email changes (``PUT /user``) or they register a passkey, the api publishes a ``USER_SECURITY_CHANGE`` user event (``change=password|email|passkey email=PREVIOUS_EMAIL``) and sends the ``security`` email (requires ``EMAIL_TEMPLATES_ENABLED=1``) so users notice changes they did not make. Email changes are sent to the previous email address. Set ``USER_EVENTS_TOPIC_SECURITY`` to publish the events to a dedicated kafka topic, and turn off the events or emails with ``SECURITY_NOTIFICATIONS_EVENTS=0`` or ``SECURITY_NOTIFICATIONS_EMAILS=0``. Each login also compares the new session's device (``User-Agent`` and ``device`` headers) and location (with ``GEOIP_DB_PATH``) with the user's other sessions. A login from a new device or location publishes a ``LOGIN_UNRECOGNIZED`` user event (``email=EMAIL reason=device|location|device,location`` with the ``country`` and ``city``) and sends the ``new_login`` email (route these events with ``USER_EVENTS_TOPIC_UNRECOGNIZED``). A user's first login is not reported. Turn off these checks with ``SECURITY_NOTIFICATIONS_NEW_LOGINS=0``.
+//!
+//! ### Message Localization
+//!
+//! Environment Variable         | Default
+//! ---------------------------- | -------
+//! MESSAGE_LOCALIZATION_ENABLED | "0"
+//! MESSAGE_CATALOG_DIR          | ""
+//! MESSAGE_DEFAULT_LOCALE       | "en"
+//!
+//! With ``MESSAGE_LOCALIZATION_ENABLED=1`` the ``msg`` field (and each ``errors[].msg``) in json responses is translated into the first supported locale from the request's ``Accept-Language`` header, then the authenticated user's ``users.locale`` and then ``MESSAGE_DEFAULT_LOCALE`` (each locale is tried before its language). Translated responses have a ``Content-Language`` header and every response has ``Vary: Accept-Language``. The built-in Spanish catalog is in ``templates/messages/es.json``: a json object mapping each English message to its translation where ``{}`` matches any text. Set ``MESSAGE_CATALOG_DIR`` to a directory of ``{locale}.json`` catalogs to add locales or override the built-in translations, or translate with another service by setting a [TranslationProvider](crate::i18n::translation_provider::TranslationProvider) with ``RestApiServerBuilder::translation_provider``. Messages without a translation stay in English and ``success`` is never translated.
+//!
+//! ### Response Formatting
+//!
+//! Environment Variable           | Default
+//! ------------------------------ | ---------
+//! RESPONSE_FORMAT_ENABLED        | "1"
+//! RESPONSE_FORMAT_MAX_BODY_BYTES | "4194304"
+//!
+//! Every json api accepts ``?fields=user_id,email`` to keep only the listed keys in each record and ``?pretty=1`` to indent the response. Arrays of records are trimmed row by row (like the ``users`` in a ``/user/search`` response or the ``data`` in a ``/user/data/search`` response), other unlisted keys are removed (list ``page`` and ``has_more`` to keep the paging values) and ``msg``, ``error_code`` and ``errors`` are always kept. Json responses larger than ``RESPONSE_FORMAT_MAX_BODY_BYTES``, file downloads and event streams are sent unchanged (see [ResponseFormat](crate::core::server::response_format::ResponseFormat)).
+//!
+//! ### Search Exports
+//!
+//! Environment Variable     | Default
+//! ------------------------ | -------
+//! SEARCH_EXPORT_ENABLED    | "1"
+//! SEARCH_EXPORT_BATCH_ROWS | "500"
+//!
+//! ``POST /user/search`` and ``POST /user/data/search`` requests with an ``Accept: text/csv`` or ``Accept: application/x-ndjson`` header stream every matching row (without the ``page_size`` or 100 record limits) as a ``users.csv``, ``users.ndjson``, ``user_data.csv`` or ``user_data.ndjson`` download. Rows are read ``SEARCH_EXPORT_BATCH_ROWS`` at a time with a db cursor (on a read replica when ``POSTGRES_READ_ENDPOINTS`` is set), and a db failure during an export aborts the response so clients see an incomplete transfer. Csv values that start with ``=``, ``+``, ``-``, ``@``, a tab or a carriage return are prefixed with ``'`` so spreadsheets do not run them as formulas. User exports never include password hashes (see [SearchExport](crate::requests::user::search_export::SearchExport)).
+//!
+//! ### Error Codes
+//!
+//! Failed json responses include an ``error_code`` next to the human-readable ``msg`` so clients can branch on the failure without parsing (or translating) the message. Successful responses omit ``error_code``. The codes are stable ``SCREAMING_SNAKE_CASE`` strings from [ApiErrorCode](crate::requests::models::api_error::ApiErrorCode) like ``VALIDATION_FAILED``, ``INVALID_TOKEN``, ``TOKEN_EXPIRED``, ``SESSION_REVOKED``, ``INSUFFICIENT_SCOPE``, ``INVALID_CREDENTIALS``, ``LOGIN_CHALLENGE_REQUIRED``, ``USER_NOT_FOUND``, ``EMAIL_IN_USE``, ``VERSION_CONFLICT``, ``OTP_EXPIRED``, ``QUOTA_EXCEEDED``, ``SERVICE_UNAVAILABLE``, ``QUERY_TIMEOUT`` and ``INTERNAL_ERROR``:
+//!
+//! ```json
+//! {"user_id":-1,"email":"","state":-1,"role":"","token":"","msg":"User email user@email.com already registered","error_code":"EMAIL_IN_USE"}
+//! ```
+//!
+//! ### User Notifications
+//!
+//! Environment Variable                  | Default
+//! ------------------------------------- | -------
+//! USER_NOTIFICATIONS_ENABLED            | "0"
+//! USER_NOTIFICATIONS_POLL_INTERVAL_MS   | "1000"
+//! USER_NOTIFICATIONS_KEEP_ALIVE_SECONDS | "15"
+//! USER_NOTIFICATIONS_MAX_STREAM_SECONDS | "3600"
+//!
+//! With ``USER_NOTIFICATIONS_ENABLED=1`` every user event that is not in ``KAFKA_EXCLUDE_EVENTS`` is also stored in the ``users_notifications`` table (even when kafka publishing is disabled) and streamed to the user with ``GET /user/notifications/stream``. Each stream polls the table every ``USER_NOTIFICATIONS_POLL_INTERVAL_MS``, sends a keep-alive comment after ``USER_NOTIFICATIONS_KEEP_ALIVE_SECONDS`` without events and closes after ``USER_NOTIFICATIONS_MAX_STREAM_SECONDS`` so clients reconnect with ``Last-Event-ID``. Open streams are counted in the ``user_notification_streams`` prometheus gauge.
+//!
+//! ### Webhooks
+//!
+//! Environment Variable   | Default
+//! ---------------------- | -------
+//! WEBHOOKS_ENABLED       | "0"
+//! WEBHOOKS_INTERVAL_MS   | "1000"
+//! WEBHOOKS_BATCH_SIZE    | "50"
+//! WEBHOOKS_MAX_ATTEMPTS  | "8"
+//! WEBHOOKS_RETRY_SECONDS | "30"
+//! WEBHOOKS_TIMEOUT_MS    | "5000"
+//! WEBHOOKS_ALLOW_HTTP    | "0"
+//!
+//! Admins register webhook endpoints with ``POST /admin/webhooks`` for the ``user.created``, ``user.verified`` and ``data.uploaded`` event types. With ``WEBHOOKS_ENABLED=1`` every matching user event that is not in ``KAFKA_EXCLUDE_EVENTS`` is queued in the ``webhooks_deliveries`` table for each subscribed endpoint (even when kafka publishing is disabled), and every api server sends the due deliveries as a json ``POST`` every ``WEBHOOKS_INTERVAL_MS``. Each request has an ``X-Webhook-Signature: v1=HEX`` header with the ``HMAC-SHA256`` of ``X-Webhook-Timestamp`` + ``.`` + the body using the endpoint's secret, and an ``X-Webhook-Id`` that stays the same across retries. Responses other than ``2xx`` and timeouts after ``WEBHOOKS_TIMEOUT_MS`` are retried after ``WEBHOOKS_RETRY_SECONDS`` (doubled after each attempt) until ``WEBHOOKS_MAX_ATTEMPTS``, and the status of each delivery is returned by ``POST /admin/webhooks/deliveries``. Webhook urls must use ``https`` unless ``WEBHOOKS_ALLOW_HTTP=1``. Attempts are counted in the ``webhook_deliveries_total`` prometheus metric and existing dbs need the ``0019_webhooks.sql`` migration.
+//!
+//! ### Kafka Dead Letters
+//!
+//! Environment Variable       | Default
+//! -------------------------- | -------
+//! KAFKA_DEAD_LETTERS_ENABLED | "1"
+//! KAFKA_PUBLISH_MAX_RETRIES  | "3"
+//! KAFKA_PUBLISH_RETRY_MS     | "200"
+//! KAFKA_MAX_PENDING_MSGS     | "10000"
+//!
+//! When ``KAFKA_ENABLED=1`` and the kafka publisher rejects a user event, or already has ``KAFKA_MAX_PENDING_MSGS`` messages waiting while the brokers are degraded (``0`` disables the limit), the event is retried ``KAFKA_PUBLISH_MAX_RETRIES`` times in a background task after ``KAFKA_PUBLISH_RETRY_MS`` (doubled after each retry). Events that fail every retry are stored with the last error in the ``kafka_dead_letters`` table (or logged and dropped with ``KAFKA_DEAD_LETTERS_ENABLED=0``), listed with ``POST /admin/kafka/dead-letters`` and published again with ``POST /admin/kafka/dead-letters/requeue``. Messages the publisher accepted are retried by the kafka threadpool every ``KAFKA_PUBLISH_RETRY_INTERVAL_SEC`` until the brokers store them and only show up in the ``kafka_publish_pending_msgs`` prometheus gauge. Rejected publishes, retries, dead letters and requeues are counted in the ``kafka_publish_failures_total``, ``kafka_publish_retries_total``, ``kafka_dead_letters_total`` and ``kafka_dead_letters_requeued_total`` prometheus metrics and existing dbs need the ``0020_kafka_dead_letters.sql`` migration.
+//!
+//! ### Kafka Schema Registry
+//!
+//! Environment Variable                | Default
+//! ----------------------------------- | -------
+//! KAFKA_SCHEMA_REGISTRY_URL           | ""
+//! KAFKA_SCHEMA_REGISTRY_FORMAT        | "avro"
+//! KAFKA_SCHEMA_REGISTRY_USERNAME      | ""
+//! KAFKA_SCHEMA_REGISTRY_PASSWORD      | ""
+//! KAFKA_SCHEMA_REGISTRY_AUTO_REGISTER | "1"
+//! KAFKA_SCHEMA_REGISTRY_TIMEOUT_MS    | "5000"
+//!
+//! With ``KAFKA_SCHEMA_REGISTRY_URL`` set and ``KAFKA_PUBLISH_EVENTS`` enabled, the api server checks the ``UserEvent`` schema (``avro`` record or ``json`` schema with ``event``, ``user_id`` and ``details``) is compatible with the latest version of the ``TOPIC-value`` subject for every user event topic, registers it (or only looks up the registered id with ``KAFKA_SCHEMA_REGISTRY_AUTO_REGISTER=0``) and does not start when either step fails. ``check-config`` runs the compatibility checks without registering. User events are then published in the Confluent wire format (magic byte ``0``, the 4-byte schema id and the encoded event) so strict consumers can use any Confluent deserializer, and Rust consumers can use [`decode_user_event`](crate::events::user_event_encoding::decode_user_event). Framed events are published with a kafka producer built from the threadpool's ``KAFKA_*`` settings that waits for the brokers, so failed events are retried and stored as text ``kafka_dead_letters`` that are encoded again when they are requeued. The schema ids are listed in ``x-kafka.schema_registry`` on ``GET /openapi/events.json``.
+//!
+//! ### Admin Stats
+//!
+//! Environment Variable      | Default
+//! ------------------------- | -------
+//! ADMIN_STATS_CACHE_SECONDS | "60"
+//!
+//! ``GET /admin/stats`` computes the dashboard stats with three aggregate queries and each api server reuses the result for ``ADMIN_STATS_CACHE_SECONDS`` (``0`` runs the queries for every request).
+//!
+//! ### Demo Mode
+//!
+//! Environment Variable | Default
+//! -------------------- | -------
+//! DEMO_MODE            | "0"
+//! DEMO_S3_DIR          | "./demo-s3"
+//! DEMO_PASSWORD        | "demo-password"
+//!
+//! With ``DEMO_MODE=1`` the api server creates the verified demo users (every user's password is ``DEMO_PASSWORD``) on startup, uploads two ``ready`` files for each ``user`` and shares ``alice@email.com``'s first file read-only with ``bob@email.com``. Demo emails that already exist are skipped, so restarts do not duplicate the data. S3 uploads and downloads read and write ``DEMO_S3_DIR/BUCKET/KEY`` instead of s3, and keys that would resolve outside of ``DEMO_S3_DIR`` are rejected. There is no in-memory or SQLite store, so demo mode uses the configured postgres db (every handler queries postgres directly) and the server does not start when the demo data cannot be seeded.
+//!
+//! ### Request Deadlines
+//!
+//! Environment Variable       | Default
+//! -------------------------- | -------
+//! REQUEST_TIMEOUT_DEFAULT_MS | "0"
+//! REQUEST_TIMEOUT_MAX_MS     | "60000"
+//!
+//! Clients can set a deadline with an ``X-Request-Timeout`` header in seconds (``2`` or ``0.5``) or milliseconds (``500ms``), or with a grpc-style ``grpc-timeout`` header (``500m``). Requests without a header use ``REQUEST_TIMEOUT_DEFAULT_MS`` (``0`` = no deadline), and every deadline is capped at ``REQUEST_TIMEOUT_MAX_MS``. Once the deadline passes, the request's pending db queries, s3 calls and kafka publishes are dropped and the client gets a ``504``. An invalid timeout header gets a ``400``. Abandoned requests are counted in the ``request_deadline_exceeded_total`` prometheus metric.
+//!
+//! ### Request Body Limits
+//!
+//! Environment Variable              | Default
+//! --------------------------------- | -------
+//! REQUEST_ENFORCE_JSON_CONTENT_TYPE | "1"
+//! REQUEST_MAX_BODY_BYTES            | "262144"
+//! REQUEST_ROUTE_MAX_BODY_BYTES      | ""
+//! UPLOAD_ALLOWED_CONTENT_TYPES      | ""
+//!
+//! Requests with a body on the json apis must send a ``Content-Type: application/json`` header (or a ``+json`` media type), otherwise they are rejected with a ``415``. Set ``REQUEST_ENFORCE_JSON_CONTENT_TYPE=0`` for older clients that do not send the header. Json bodies over ``REQUEST_MAX_BODY_BYTES`` get a ``413`` before the body is read (chunked bodies are cut off at the limit), and ``REQUEST_ROUTE_MAX_BODY_BYTES`` sets ``group=bytes`` limits for the ``auth``, ``admin``, ``user``, ``data`` and ``search`` route groups (``0`` = unlimited). User data uploads (``POST /user/data``) must declare a ``type/subtype`` ``Content-Type`` (or ``multipart/form-data``) that is in the comma-separated ``UPLOAD_ALLOWED_CONTENT_TYPES`` list (``text/*`` wildcards are supported, empty = any), and their size is limited by the ``max_upload_size_bytes`` runtime setting.
+//!
+//! ### Multi-Tenancy
+//!
+//! Environment Variable | Default
+//! -------------------- | -------
+//! TENANT_MODE          | "off"
+//! TENANT_HEADER        | "X-Tenant"
+//! TENANT_BASE_DOMAIN   | ""
+//! TENANT_DEFAULT       | "default"
+//!
+//! Every user belongs to a row in the ``tenants`` table, and emails are unique per tenant. Tenants are added with sql (``INSERT INTO tenants (slug, name) VALUES ('acme', 'Acme');``). With ``TENANT_MODE=header`` each request's tenant is the ``tenants.slug`` in the ``TENANT_HEADER`` header, and with ``TENANT_MODE=subdomain`` it is the subdomain of the ``Host`` header under ``TENANT_BASE_DOMAIN`` (``acme.api.example.com``). Requests without a tenant use ``TENANT_DEFAULT`` (empty = rejected), and unknown or inactive tenants get a ``400``. Logins, user creation, user searches and role-based data shares are scoped to the tenant, new tokens include a ``tenant_id`` claim, and a token used with a different tenant is rejected. Server-wide admin apis are limited to admins in the ``default`` tenant. With ``TENANT_MODE=off`` every user is in the ``default`` tenant. Existing dbs can add the tenants with the ``0007_tenants.sql`` migration.
+//!
+//! ### Trusted Proxies
+//!
+//! Environment Variable | Default
+//! -------------------- | -------
+//! TRUSTED_PROXY_CIDRS  | ""
+//!
+//! Behind a load balancer or reverse proxy, set ``TRUSTED_PROXY_CIDRS`` to the comma-separated proxy networks (``10.0.0.0/8,fd00::/8``) or addresses. When a connection comes from a trusted proxy, the client address is the right-most untrusted address in the ``Forwarded`` (``for=``) header, or the ``X-Forwarded-For`` header when there is no ``Forwarded`` header. The client address is used for logs, the access log, login throttling and the session ``ip_address``. Forwarded headers from untrusted peers are ignored so clients cannot spoof their address.
+//!
+//! ### IP Allowlists and Denylists
+//!
+//! Environment Variable | Default
+//! -------------------- | -------
+//! IP_ALLOWLIST         | ""
+//! IP_DENYLIST          | ""
+//! IP_ROUTE_ALLOWLIST   | ""
+//! IP_ROUTE_DENYLIST    | ""
+//!
+//! Reject client addresses with a ``403`` before a request is routed. ``IP_ALLOWLIST`` and ``IP_DENYLIST`` are comma-separated networks (``10.0.0.0/8``) or addresses for every route, and ``IP_ROUTE_ALLOWLIST`` and ``IP_ROUTE_DENYLIST`` are comma-separated ``prefix=cidr|cidr`` pairs for the routes under a url path prefix (``/admin=10.0.0.0/8|127.0.0.1,/metrics=127.0.0.1``). A request is rejected when its address is in a matching denylist, or when the global allowlist or a matching route prefix's allowlist is set and does not contain the address. Admins add more rules with ``POST /admin/ip-rules`` (stored in the ``ip_rules`` table), and every api server reloads them when the table changes. Rules use the client address from ``TRUSTED_PROXY_CIDRS``, so set it when running behind a load balancer. Existing dbs need the ``0031_ip_rules.sql`` migration.
+//!
+//! ### GeoIP
+//!
+//! Environment Variable | Default
+//! -------------------- | -------
+//! GEOIP_DB_PATH        | ""
+//!
+//! Set ``GEOIP_DB_PATH`` to a MaxMind GeoIP2 or GeoLite2 City (or Country) ``mmdb`` file to look up the country and city for each new session's client address. The lookups require building with ``cargo build --features geoip`` and the server does not start when the database cannot be read. The ``country`` (ISO code like ``US``) and English ``city`` name are stored in the ``users_tokens`` table, returned by ``GET /user/sessions`` and added to the ``LOGIN`` and ``LOGIN_PASSKEY`` user events (``country=US city=San+Francisco`` with a form-urlencoded city). Private and unknown addresses have no location. Locations are also compared with the user's other sessions to report logins from a new location (see Security Notifications). Existing dbs need the ``0023_users_tokens_geo.sql`` migration.
+//!
+//! ### Static Assets
+//!
+//! Environment Variable          | Default
+//! ----------------------------- | -------
+//! STATIC_ASSETS_DIR             | ""
+//! STATIC_ASSETS_MAX_AGE_SECONDS | "3600"
+//!
+//! Set ``STATIC_ASSETS_DIR`` to a frontend build directory to serve it from the same tls listener as the api. ``GET`` and ``HEAD`` requests that do not match an api route are served from the directory with a ``Content-Type`` for the file extension, an ``ETag`` (``If-None-Match`` gets a ``304``) and a ``Cache-Control`` max-age of ``STATIC_ASSETS_MAX_AGE_SECONDS``. ``index.html`` is sent with ``Cache-Control: no-cache``, and missing paths without a file extension (client-side routes like ``/settings/profile``) are served the ``index.html``. Paths with ``..`` are rejected with a ``404``.
+//!
+//! ### Admission Control
+//!
+//! Environment Variable          | Default
+//! ----------------------------- | -------
+//! ADMISSION_CONTROL_ENABLED     | "0"
+//! ADMISSION_MAX_IN_FLIGHT       | "512"
+//! ADMISSION_MAX_POOL_WAIT_MS    | "500"
+//! ADMISSION_PROBE_INTERVAL_MS   | "250"
+//! ADMISSION_SHED_LOW_AT         | "0.7"
+//! ADMISSION_SHED_NORMAL_AT      | "0.85"
+//! ADMISSION_SHED_HIGH_AT        | "1.0"
+//! ADMISSION_RETRY_AFTER_SECONDS | "1"
+//! ADMISSION_ROUTE_PRIORITIES    | "auth=high,admin=high,user=normal,data=low,search=low"
+//!
+//! When enabled, the server load is the larger of the in-flight request count over ``ADMISSION_MAX_IN_FLIGHT`` and the smoothed bb8 db pool wait time over ``ADMISSION_MAX_POOL_WAIT_MS`` (the pool wait is measured every ``ADMISSION_PROBE_INTERVAL_MS``). Each route group has a priority class (``critical``, ``high``, ``normal`` or ``low``), and a class is shed once the load reaches its ``ADMISSION_SHED_*_AT`` threshold, so ``low`` routes are shed first. The route groups are ``auth`` (``/login*`` and ``/csrf``), ``admin`` (``/admin/*``), ``search`` (``/user/search`` and ``/user/data/search``), ``data`` (``/user/data*``) and ``user`` (everything else). The ``/health``, ``/metrics``, ``/.well-known/jwks.json``, ``/openapi/events.json`` and ``/favicon.ico`` routes are always ``critical`` and are never shed. Shed requests get a ``503`` when the db pool is the bottleneck or a ``429`` when there are too many requests in flight, both with a ``Retry-After`` header. The ``admission_rejected_total``, ``http_requests_in_flight`` and ``db_pool_wait_ms`` prometheus metrics track the shedding.
+//!
+//! ### Connection Limits
+//!
+//! Environment Variable                  | Default
+//! ------------------------------------- | -------
+//! API_MAX_CONNECTIONS                   | "10000"
+//! API_TLS_HANDSHAKE_TIMEOUT_MS          | "10000"
+//! API_HTTP1_KEEP_ALIVE                  | "1"
+//! API_HTTP1_HEADER_READ_TIMEOUT_MS      | "30000"
+//! API_HTTP2_KEEP_ALIVE_INTERVAL_SECONDS | "0"
+//! API_HTTP2_KEEP_ALIVE_TIMEOUT_SECONDS  | "20"
+//! API_MAX_BUF_SIZE_BYTES                | "0"
+//!
+//! The server stops accepting new connections while ``API_MAX_CONNECTIONS`` are open (``0`` is unlimited), so new clients wait in the listen backlog until a connection closes. Connections are closed when the tls handshake takes longer than ``API_TLS_HANDSHAKE_TIMEOUT_MS`` or a request's headers take longer than ``API_HTTP1_HEADER_READ_TIMEOUT_MS``, which also closes http/1 keep-alive connections that stay idle for that long (``0`` disables either timeout). ``API_HTTP1_KEEP_ALIVE=0`` closes each http/1 connection after one response. ``API_HTTP2_KEEP_ALIVE_INTERVAL_SECONDS`` pings idle http/2 connections and closes them when a ping is not answered within ``API_HTTP2_KEEP_ALIVE_TIMEOUT_SECONDS``. ``API_MAX_BUF_SIZE_BYTES`` caps each connection's read buffer (``0`` keeps hyper's default, the minimum is ``8192``). The ``http_connections_open`` and ``http_connection_limit_reached_total`` prometheus metrics track the limit.
+//!
+//! ### Startup Wait
+//!
+//! Environment Variable   | Default
+//! ---------------------- | -------
+//! STARTUP_WAIT_SECONDS   | "0"
+//! STARTUP_RETRY_MS       | "500"
+//! STARTUP_MAX_RETRY_MS   | "5000"
+//! STARTUP_KAFKA_DEGRADED | "0"
+//!
+//! Set ``STARTUP_WAIT_SECONDS`` so an api server that starts before its dependencies retries a postgres connection and, with ``KAFKA_ENABLED=1``, a kafka metadata request until they answer instead of failing right away. Failed checks are retried after ``STARTUP_RETRY_MS``, doubling up to ``STARTUP_MAX_RETRY_MS``, and counted in the ``startup_dependency_retries_total`` prometheus metric. The server does not start when postgres or kafka is still unavailable after ``STARTUP_WAIT_SECONDS``. With ``STARTUP_KAFKA_DEGRADED=1`` it starts with kafka publishing disabled instead, and the ``startup_degraded{dependency="kafka"}`` gauge is ``1`` until the server is restarted. The ``migrate`` command also waits for postgres.
+//!
+//! ### Circuit Breakers
+//!
+//! Environment Variable                    | Default
+//! --------------------------------------- | -------
+//! S3_CIRCUIT_BREAKER_FAILURE_THRESHOLD    | "5"
+//! S3_CIRCUIT_BREAKER_OPEN_SECONDS         | "30"
+//! KAFKA_CIRCUIT_BREAKER_FAILURE_THRESHOLD | "5"
+//! KAFKA_CIRCUIT_BREAKER_OPEN_SECONDS      | "30"
+//!
+//! s3 calls (uploads, downloads, resumable upload parts and the archive, lifecycle and thumbnail tasks) and kafka publishes each go through a [CircuitBreaker](crate::utils::circuit_breaker::CircuitBreaker). After ``*_CIRCUIT_BREAKER_FAILURE_THRESHOLD`` consecutive failures (``0`` disables the breaker) the breaker opens for ``*_CIRCUIT_BREAKER_OPEN_SECONDS``, and calls fail fast instead of waiting on the degraded service. While the s3 breaker is open, ``POST /user/data``, ``GET /user/data/DATAID/download``, ``POST /user/data/uploads``, ``PUT /user/data/DATAID/chunks`` and ``POST /user/data/DATAID/complete`` get a ``503`` with ``error_code`` ``SERVICE_UNAVAILABLE`` and a ``Retry-After`` header, and uploads are rejected before their body is read. While the kafka breaker is open, user events skip the publisher and their retries and are stored as ``kafka_dead_letters`` right away. After ``*_CIRCUIT_BREAKER_OPEN_SECONDS`` one trial call is let through: a success closes the breaker and a failure opens it again. The ``circuit_breaker_state{name="s3"|"kafka"}`` prometheus gauge is ``0`` (closed), ``1`` (open) or ``2`` (half open), ``circuit_breaker_total`` counts the ``opened``, ``closed`` and ``rejected`` results, and ``GET /health`` lists the breakers that are not closed in ``degraded`` with a ``degraded`` status (still a ``200``).
+//!
+//! ### Cache
+//!
+//! Environment Variable | Default
+//! -------------------- | -------
+//! CACHE_BACKEND        | "none"
+//! CACHE_TTL_SECONDS    | "30"
+//! CACHE_MAX_ENTRIES    | "10000"
+//! CACHE_REDIS_URL      | "redis://127.0.0.1:6379"
+//! CACHE_KEY_PREFIX     | "restapi"
+//!
+//! With ``CACHE_BACKEND=memory`` (an in-process LRU cache holding up to ``CACHE_MAX_ENTRIES`` users) or ``CACHE_BACKEND=redis`` (shared by every api server), each authenticated request reads the user and the user's active token hashes from the cache for up to ``CACHE_TTL_SECONDS`` instead of querying postgres. Handlers that update, verify or delete a user, change a password, or revoke tokens drop the user's cached entry, and cache errors fall back to postgres. The redis backend requires building with ``cargo build --features redis``. Lookups are counted in the ``cache_requests_total`` prometheus metric.
+//!
+//! ### Access Log
+//!
+//! Environment Variable | Default
+//! -------------------- | -------
+//! ACCESS_LOG_ENABLED   | "0"
+//! ACCESS_LOG_PATH      | stdout
+//!
+//! With ``ACCESS_LOG_ENABLED=1`` the server writes one json line for each request to stdout or appends it to the ``ACCESS_LOG_PATH`` file, separate from the ``RUST_LOG`` logs. Each line has the ``timestamp``, ``request_id``, ``method``, ``path`` (without the query string), ``status``, ``latency_ms``, ``user_id`` (``null`` unless the request logged in or sent a valid token), ``remote_addr``, ``bytes_in`` and ``bytes_out`` (``null`` for streamed bodies). The ``request_id`` is the client's ``X-Request-Id`` header or a new uuid, and it is returned in the ``X-Request-Id`` response header.
+//!
+//! ### OpenTelemetry Tracing
+//!
+//! Environment Variable        | Default
+//! --------------------------- | -------
+//! OTEL_EXPORTER_OTLP_ENDPOINT | ""
+//! OTEL_SERVICE_NAME           | restapi
+//! OTEL_TRACES_SAMPLER_ARG     | "1.0"
+//!
+//! Tracing requires building with ``cargo build --features otel``. With an ``OTEL_EXPORTER_OTLP_ENDPOINT`` (an OTLP grpc collector like ``http://localhost:4317`` for Jaeger or Tempo) each request is exported as a server span named by its method and route (numeric ids are replaced with ``{id}``) with child spans for each postgres query (``postgres SELECT``, ``postgres UPDATE``, ...), s3 upload and kafka publish. A W3C ``traceparent`` request header continues the caller's trace. ``OTEL_TRACES_SAMPLER_ARG`` is the ratio of new traces to sample. Sql statements are not exported because they contain user values.
+//!
+//! ### Prometheus Metrics
+//!
+//! Environment Variable     | Default
+//! ------------------------ | -------
+//! METRICS_MAX_LABEL_VALUES | "100"
+//!
+//! The http metrics only use each route's static ``resource`` and ``method`` labels, so request values (user ids, emails, paths and request ids) never become prometheus label values. Labels that come from the environment or the db (kafka topics and event names) are recorded as ``other`` once a metric has ``METRICS_MAX_LABEL_VALUES`` distinct values, or when a value is longer than 64 characters or has characters outside of ``a-z``, ``A-Z``, ``0-9``, ``_``, ``-``, ``.`` and ``:``, and the ``metric_label_values_rejected_total`` metric counts them. ``http_request_duration_seconds`` observes each route's latency and ``http_route_requests_in_flight`` counts the requests in each route handler.
+//!
+//! ### Log Redaction
+//!
+//! Environment Variable | Default
+//! -------------------- | -------
+//! LOG_REDACTION        | "full"
+//!
+//! The example servers log with [`init_logger`](crate::monitoring::log_redaction::init_logger) which still filters with ``RUST_LOG`` and redacts each log message before it is written. With ``full`` (the default and the value to use in production) emails become ``[redacted-email]``, json web tokens become ``[redacted-token]`` and the values of ``password``, ``token``, ``secret``, ``authorization``, ``otp``, ``cookie`` and ``api_key`` keys (including keys like ``reset_token``) become ``[redacted]``. ``partial`` keeps the first character and domain of each email (``a***@email.com``) for debugging, and ``off`` logs everything for local development. Unsupported values use ``full``.
+//!
 //! ### Rust
 //!
 //! Environment Variable | Default
@@ -272,10 +1055,35 @@
 //!     - dev-api.dev.svc.cluster.local:3000
 //! ```
 //!
+//! Scrapers that send an ``Accept: application/openmetrics-text`` header get the OpenMetrics format with the newest ``X-Request-Id`` (or the generated request id) in each ``http_request_duration_seconds`` bucket as a ``request_id`` exemplar, so a slow bucket links to its access log line and trace. Prometheus asks for this format and stores the exemplars when it runs with ``--enable-feature=exemplar-storage``.
+//!
+//! ### Download Egress Metering
+//!
+//! Every file download (``GET /user/data/DATAID/download`` and public link downloads) is stored in the ``users_data_access`` table with the file's ``owner_user_id``, the downloading ``user_id`` (``NULL`` for a public link), the ``access`` (``owner``, ``acl`` or ``share_token``), the ``size_in_bytes`` sent and the time. Rows are kept after the file is archived or deleted, so deployments can bill or cap bandwidth with queries like ``SELECT owner_user_id, SUM(size_in_bytes) FROM users_data_access WHERE created_at > now() - interval '30 days' GROUP BY owner_user_id;``. Each download also adds to the ``user_data_downloads_total`` and ``user_data_egress_bytes_total`` prometheus counters with the file owner's ``user_id`` and the ``access`` as labels. Owner ids past the ``METRICS_MAX_LABEL_VALUES`` limit are recorded as ``other``, so use the table for exact per-user totals on large deployments. Owners get a file's download stats with ``GET /user/data/DATAID/access``. Existing dbs need the ``0026_users_data_access.sql`` migration.
+//!
 //! ## Supported APIs
 //!
 //! Here are the supported json contracts for each ``Request`` and ``Response`` based off the url. Each client request is handled by the [`handle_requests`](crate::handle_request::handle_request) and returned as a response back to the client (serialization using ``serde_json``)
 //!
+//! ### Request Validation
+//!
+//! Every json request is validated after it is deserialized (email format, value lengths, numeric ranges and supported values like ``state``, ``role`` and ``access``). Invalid requests get a ``422`` HTTP status code and an [`ApiResValidationErrors`](crate::requests::validation::validate_api_req::ApiResValidationErrors) body listing every invalid field:
+//!
+//! ```json
+//! {"errors":[{"field":"email","msg":"must be a valid email address"}],"msg":"Request validation failed for fields: email"}
+//! ```
+//!
+//! Emails are trimmed and lowercased before they are validated or looked up (``create_user``, ``update_user``, ``login_user``, ``create_otp``, ``consume_user_otp``, ``delete_user``, passkey logins and admin unlocks), so ``"User@Email.com "`` and ``"user@email.com"`` map to the same account. With ``USER_EMAIL_CHECK_DOMAIN=1``, new and changed emails must also have a domain that resolves (lookups slower than ``USER_EMAIL_CHECK_DOMAIN_TIMEOUT_MS`` are allowed). Existing databases need their emails normalized before adding the ``users_email_normalized`` constraint from ``docker/db/sql/init.sql``:
+//!
+//! ```sql
+//! UPDATE users SET email = LOWER(TRIM(email));
+//! ALTER TABLE ONLY users ADD CONSTRAINT users_email_normalized CHECK (email = LOWER(TRIM(email)));
+//! ```
+//!
+//! ### API Versions
+//!
+//! Every url path can be prefixed with an api version (``/v1/user/data/search`` or ``/v2/user/data/search``). Unprefixed paths are served as ``v1``, so existing clients keep working, and breaking changes to a request or response type ship in the ``v2`` handler set without changing ``v1``. Routes without a breaking change serve the same handler under every version. In ``v2`` an unsupported method and url path gets a ``404`` (``v1`` keeps returning a ``200`` with a ``"status":400`` body). Every response has an ``API-Version`` header with the version that served it, and an unsupported version prefix (``/v9/user/2``) gets a ``404``.
+//!
 //! ### User APIs
 //!
 //! #### Create User
@@ -298,6 +1106,16 @@
 //! - Request: [`ApiReqUserUpdate`](crate::requests::user::update_user::ApiReqUserUpdate)
 //! - Response: [`ApiResUserUpdate`](crate::requests::user::update_user::ApiResUserUpdate)
 //!
+//! #### Patch User
+//!
+//! Update ``users`` fields with a [JSON Merge Patch](https://www.rfc-editor.org/rfc/rfc7396) (``Content-Type: application/merge-patch+json``). Missing fields are not changed and a ``null`` ``locale`` resets it to the ``EMAIL_DEFAULT_LOCALE``. ``email``, ``password``, ``state``, ``verified`` and ``role`` cannot be ``null`` (``422``).
+//!
+//! - URL path: ``/user``
+//! - Method: ``PATCH``
+//! - Handler: [`patch_user`](crate::requests::user::update_user::patch_user)
+//! - Request: [`ApiReqUserUpdate`](crate::requests::user::update_user::ApiReqUserUpdate)
+//! - Response: [`ApiResUserUpdate`](crate::requests::user::update_user::ApiResUserUpdate)
+//!
 //! #### Get User
 //!
 //! Get a single user by ``users.id`` - by default, a user can only get their own account details
@@ -318,9 +1136,31 @@
 //! - Request: [`ApiReqUserDelete`](crate::requests::user::delete_user::ApiReqUserDelete)
 //! - Response: [`ApiResUserDelete`](crate::requests::user::delete_user::ApiResUserDelete)
 //!
+//! #### Request Self-Service User Deletion
+//!
+//! Email a one-time-use confirmation token for deleting the account of the user that owns the request's token. The account is not changed until the token is confirmed. Requires ``KAFKA_PUBLISH_EVENTS=1`` (``503`` otherwise).
+//!
+//! - URL path: ``/user/delete/request``
+//! - Method: ``POST``
+//! - Handler: [`request_user_delete`](crate::requests::user::request_user_delete::request_user_delete)
+//! - Request: [`ApiReqUserDeleteRequest`](crate::requests::user::request_user_delete::ApiReqUserDeleteRequest)
+//! - Response: [`ApiResUserDeleteRequest`](crate::requests::user::request_user_delete::ApiResUserDeleteRequest)
+//!
+//! #### Confirm Self-Service User Deletion
+//!
+//! Consume the emailed confirmation token to soft-delete the user (``users.state = 1``), revoke all of the user's tokens and schedule the user's uploads for deletion after ``USER_DELETE_PURGE_DAYS``. Unlike ``DELETE /user``, the user must confirm with the token from their email first.
+//!
+//! - URL path: ``/user/delete/confirm``
+//! - Method: ``POST``
+//! - Handler: [`confirm_user_delete`](crate::requests::user::confirm_user_delete::confirm_user_delete)
+//! - Request: [`ApiReqUserDeleteConfirm`](crate::requests::user::confirm_user_delete::ApiReqUserDeleteConfirm)
+//! - Response: [`ApiResUserDeleteConfirm`](crate::requests::user::confirm_user_delete::ApiResUserDeleteConfirm)
+//!
 //! #### Search Users in the db
 //!
-//! Search for matching ``users`` records in the db
+//! Search for matching ``users`` records in the db by ``email``, ``role``, ``state``, ``verified`` and ``created_at`` range with paging. Only users with the ``admin`` role can search across all users. Other users only match their own record.
+//!
+//! Trim the returned rows with ``?fields=`` (see [Response Formatting](#response-formatting)) or export every matching row with an ``Accept: text/csv`` or ``Accept: application/x-ndjson`` header (see [Search Exports](#search-exports)).
 //!
 //! - URL path: ``/user/search``
 //! - Method: ``POST``
@@ -338,9 +1178,19 @@
 //! - Request: [`ApiReqUserCreateOtp`](crate::requests::user::create_otp::ApiReqUserCreateOtp)
 //! - Response: [`ApiResUserCreateOtp`](crate::requests::user::create_otp::ApiResUserCreateOtp)
 //!
+//! #### Forgot Password - Email a One-Time-Use Password Reset Token (OTP)
+//!
+//! Email a one-time-use password reset token to a user that cannot log in. The response is always the same ``202`` for registered and unknown emails. The token (and a ``USER_OTP_RESET_URL`` link) is only published in the ``USER_CREATE_OTP`` user event for a mail service, so ``KAFKA_PUBLISH_EVENTS`` must be enabled (``503`` otherwise). The user sends the token to ``/user/password/change`` without a Bearer token.
+//!
+//! - URL path: ``/user/password/forgot``
+//! - Method: ``POST``
+//! - Handler: [`forgot_password`](crate::requests::user::forgot_password::forgot_password)
+//! - Request: [`ApiReqUserForgotPassword`](crate::requests::user::forgot_password::ApiReqUserForgotPassword)
+//! - Response: [`ApiResUserForgotPassword`](crate::requests::user::forgot_password::ApiResUserForgotPassword)
+//!
 //! #### Consume a One-Time-Use Password Reset Token (OTP)
 //!
-//! Consume a one-time-use password and change the user's ``users.password`` value to the new argon2-hashed password
+//! Consume a one-time-use password and change the user's ``users.password`` value to the new argon2-hashed password. Logged-in users send their Bearer token, and users from ``/user/password/forgot`` send only the token. Each token can only be used once, and only the first of several concurrent requests with the same token changes the password (existing dbs need the ``0021_users_tokens_consumed.sql`` migration).
 //!
 //! - URL path: ``/user/password/change``
 //! - Method: ``POST``
@@ -350,7 +1200,7 @@
 //!
 //! #### Verify a User's email
 //!
-//! Consume a one-time-use verification token and change the user's ``users.verified`` value verified (``1``)
+//! Consume a one-time-use verification token and change the user's ``users.verified`` value verified (``1``). Each verify link works once - replaying the link or sending it in concurrent requests returns a ``400`` after the first success (existing dbs need the ``0021_users_tokens_consumed.sql`` migration).
 //!
 //! - URL path: ``/user/verify``
 //! - Method: ``GET``
@@ -358,18 +1208,115 @@
 //! - Request: [`ApiReqUserVerify`](crate::requests::user::verify_user::ApiReqUserVerify)
 //! - Response: [`ApiResUserVerify`](crate::requests::user::verify_user::ApiResUserVerify)
 //!
+//! #### Get User Sessions
+//!
+//! List the active sessions (issued tokens) for the user that owns the request's token with the user agent, ip address, ``device`` header and GeoIP ``country`` and ``city`` from login
+//!
+//! - URL path: ``/user/sessions``
+//! - Method: ``GET``
+//! - Handler: [`get_user_sessions`](crate::requests::user::get_user_sessions::get_user_sessions)
+//! - Response: [`ApiResUserGetSessions`](crate::requests::user::get_user_sessions::ApiResUserGetSessions)
+//!
+//! #### Get User Logins
+//!
+//! List the most recent successful and failed password and passkey login attempts for the user that owns the request's token with the time, result, ip address, user agent, ``device`` header and GeoIP ``country`` and ``city``. Use the ``limit`` query parameter (``1`` to ``100``, default ``20``) to change the number of attempts and ``failed=1`` to only list failed attempts.
+//!
+//! - URL path: ``/user/logins``
+//! - Method: ``GET``
+//! - Handler: [`get_user_logins`](crate::requests::user::get_user_logins::get_user_logins)
+//! - Response: [`ApiResUserGetLogins`](crate::requests::user::get_user_logins::ApiResUserGetLogins)
+//!
+//! #### Get User Quota
+//!
+//! Get the storage quota, used bytes and available bytes (``-1`` when unlimited) for the user that owns the request's token
+//!
+//! - URL path: ``/user/quota``
+//! - Method: ``GET``
+//! - Handler: [`get_user_quota`](crate::requests::user::get_user_quota::get_user_quota)
+//! - Response: [`ApiResUserGetQuota`](crate::requests::user::get_user_quota::ApiResUserGetQuota)
+//!
+//! #### Stream User Notifications
+//!
+//! Stream the events for the user that owns the request's token as server-sent events (``text/event-stream``) for clients that cannot use websockets. Each event's ``id`` is the ``users_notifications.id``, so reconnecting with the ``Last-Event-ID`` header resumes after the last received event. Without the header only new events are sent. Requires ``USER_NOTIFICATIONS_ENABLED=1``.
+//!
+//! - URL path: ``/user/notifications/stream``
+//! - Method: ``GET``
+//! - Handler: [stream_user_notifications](https://docs.rs/restapi/latest/restapi/requests/user/stream_user_notifications/fn.stream_user_notifications.html)
+//! - Response: ``text/event-stream`` or [ApiResUserNotificationsStream](https://docs.rs/restapi/latest/restapi/requests/user/stream_user_notifications/struct.ApiResUserNotificationsStream.html) on failure
+//!
+//! #### Revoke a User Session
+//!
+//! Revoke one of the user's active sessions - requests using a revoked session's token are rejected even if the jwt has not expired
+//!
+//! - URL path: ``/user/sessions/SESSIONID``
+//! - Method: ``DELETE``
+//! - Handler: [`revoke_user_session`](crate::requests::user::revoke_user_session::revoke_user_session)
+//! - Response: [`ApiResUserRevokeSession`](crate::requests::user::revoke_user_session::ApiResUserRevokeSession)
+//!
+//! #### Accept a User Invite
+//!
+//! Consume a one-time-use invite token, set the user's ``users.password``, activate the account and mark the email as verified. Returns a new jwt for the user.
+//!
+//! - URL path: ``/user/invite/accept``
+//! - Method: ``POST``
+//! - Handler: [`accept_user_invite`](crate::requests::user::accept_user_invite::accept_user_invite)
+//! - Request: [`ApiReqUserAcceptInvite`](crate::requests::user::accept_user_invite::ApiReqUserAcceptInvite)
+//! - Response: [`ApiResUserLogin`](crate::requests::auth::login_user::ApiResUserLogin)
+//!
 //! ### User S3 APIs
 //!
 //! #### Upload a file asynchronously to AWS S3 and store a tracking record in the db
 //!
 //! Upload a local file on disk to AWS S3 asynchronously and store a tracking record in the ``users_data`` table. The documentation refers to this as a ``user data`` or ``user data file`` record.
 //!
+//! The file metadata can be sent in custom headers (``user_id``, ``filename``, etc.) with the file as the raw body, or as a ``multipart/form-data`` body with a json ``metadata`` part and a ``file`` part.
+//!
 //! - URL path: ``/user/data``
 //! - Method: ``POST``
 //! - Handler: [`upload_user_data`](crate::requests::user::upload_user_data::upload_user_data)
 //! - Request: [`ApiReqUserUploadData`](crate::requests::user::upload_user_data::ApiReqUserUploadData)
 //! - Response: [`ApiResUserUploadData`](crate::requests::user::upload_user_data::ApiResUserUploadData)
 //!
+//! #### Start a resumable upload
+//!
+//! Create a ``users_data`` record in the ``uploading`` status and an s3 multipart upload for a large file that is sent in ``Content-Range`` chunks
+//!
+//! - URL path: ``/user/data/uploads``
+//! - Method: ``POST``
+//! - Handler: [`start_resumable_upload`](crate::requests::user::start_resumable_upload::start_resumable_upload)
+//! - Request: [`ApiReqUserStartResumableUpload`](crate::requests::user::start_resumable_upload::ApiReqUserStartResumableUpload)
+//! - Response: [`ApiResUserResumableUpload`](crate::requests::user::resumable_upload::ApiResUserResumableUpload)
+//!
+//! #### Upload a resumable upload chunk
+//!
+//! Store the PUT-ed body as the next part of a resumable upload. The ``Content-Range`` header must start at the upload's ``received_bytes``.
+//!
+//! - URL path: ``/user/data/DATAID/chunks``
+//! - Method: ``PUT``
+//! - Handler: [`upload_user_data_chunk`](crate::requests::user::upload_user_data_chunk::upload_user_data_chunk)
+//! - Request: the chunk contents as the raw body with a ``Content-Range: bytes START-END/TOTAL`` header
+//! - Response: [`ApiResUserResumableUpload`](crate::requests::user::resumable_upload::ApiResUserResumableUpload)
+//!
+//! #### Get a resumable upload
+//!
+//! Get the ``received_bytes`` offset to resume an interrupted upload from
+//!
+//! - URL path: ``/user/data/DATAID/chunks``
+//! - Method: ``GET``
+//! - Handler: [`get_resumable_upload`](crate::requests::user::get_resumable_upload::get_resumable_upload)
+//! - Request: none (uses the token header)
+//! - Response: [`ApiResUserResumableUpload`](crate::requests::user::resumable_upload::ApiResUserResumableUpload)
+//!
+//! #### Complete a resumable upload
+//!
+//! Combine the received chunks into the s3 object and move the ``users_data`` record out of the ``uploading`` status
+//!
+//! - URL path: ``/user/data/DATAID/complete``
+//! - Method: ``POST``
+//! - Handler: [`complete_resumable_upload`](crate::requests::user::complete_resumable_upload::complete_resumable_upload)
+//! - Request: none (uses the token header)
+//! - Response: [`ApiResUserResumableUpload`](crate::requests::user::resumable_upload::ApiResUserResumableUpload)
+//!
 //! #### Update an existing user data file record for a file stored in AWS S3
 //!
 //! Update the ``users_data`` tracking record for a file that exists in AWS S3
@@ -380,16 +1327,130 @@
 //! - Request: [`ApiReqUserUpdateData`](crate::requests::user::update_user_data::ApiReqUserUpdateData)
 //! - Response: [`ApiResUserUpdateData`](crate::requests::user::update_user_data::ApiResUserUpdateData)
 //!
+//! #### Patch an existing user data file record
+//!
+//! Update the ``users_data`` tracking record with a [JSON Merge Patch](https://www.rfc-editor.org/rfc/rfc7396) (``Content-Type: application/merge-patch+json``). Missing fields are not changed and fields set to ``null`` are cleared: ``comments``, ``data_type`` and ``encoding`` become empty, ``tags`` are removed and the record is moved out of its ``folder``. ``filename`` and ``sloc`` cannot be ``null`` (``422``).
+//!
+//! - URL path: ``/user/data``
+//! - Method: ``PATCH``
+//! - Handler: [`patch_user_data`](crate::requests::user::update_user_data::patch_user_data)
+//! - Request: [`ApiReqUserUpdateData`](crate::requests::user::update_user_data::ApiReqUserUpdateData)
+//! - Response: [`ApiResUserUpdateData`](crate::requests::user::update_user_data::ApiResUserUpdateData)
+//!
+//! #### Update, move and delete user data file records in a batch
+//!
+//! Run up to 100 ``update`` (metadata), ``move`` (``folder``) and ``delete`` operations on ``users_data`` records in one db transaction with a ``status_code`` for each operation. Every operation needs the record's ``version``. The first failed operation rolls back the whole batch and the other operations get a ``424``. Only the owner can delete a ``ready``, ``quarantined``, ``failed`` or ``broken`` record. Deleted records are marked ``expired`` and the lifecycle task (``USERS_DATA_LIFECYCLE_ENABLED=1``) deletes their s3 objects.
+//!
+//! - URL path: ``/user/data/batch``
+//! - Method: ``POST``
+//! - Handler: [`batch_user_data`](crate::requests::user::batch_user_data::batch_user_data)
+//! - Request: [`ApiReqUserDataBatch`](crate::requests::user::batch_user_data::ApiReqUserDataBatch)
+//! - Response: [`ApiResUserDataBatch`](crate::requests::user::batch_user_data::ApiResUserDataBatch)
+//!
 //! #### Search for existing user data files from the db
 //!
 //! Search for matching records in the ``users_data`` db based off the request's values
 //!
+//! Uploads (``tags`` and ``folder`` headers or metadata values) and updates can organize records with up to 20 tags and a folder path like ``/projects/2022``. Searches with ``tags`` return records with every tag, and a ``folder`` filter returns the records in that folder (and its subfolders with ``"include_subfolders": true``). Older versions of overwritten files are returned with ``"versions_of": DATA_ID`` or ``"include_versions": true``.
+//!
+//! Trim the returned rows with ``?fields=`` (see [Response Formatting](#response-formatting)) or export every matching row with an ``Accept: text/csv`` or ``Accept: application/x-ndjson`` header (see [Search Exports](#search-exports)).
+//!
 //! - URL path: ``/user/data/search``
 //! - Method: ``POST``
 //! - Handler: [`search_user_data`](crate::requests::user::search_user_data::search_user_data)
 //! - Request: [`ApiReqUserSearchData`](crate::requests::user::search_user_data::ApiReqUserSearchData)
 //! - Response: [`ApiResUserSearchData`](crate::requests::user::search_user_data::ApiResUserSearchData)
 //!
+//! #### List the user's s3 objects and reconcile them with the db
+//!
+//! List the keys under the user's ``S3_DATA_PREFIX/USER_ID/`` prefix and match each key with the ``users_data`` and ``users_data_archive`` records that point to it, so operators can find drift between the db and the bucket. Keys without a record have ``"orphan": true`` and records without a key are returned in ``missing`` (``uploading`` and ``expired`` records are skipped). Up to ``max_keys`` keys are listed (``1`` to ``10000``, default ``1000``) and ``"truncated": true`` means the prefix has more keys. Admins in the ``default`` tenant can list another user's prefix with ``?user_id=USERID``. Returns a ``503`` with a ``Retry-After`` header while the s3 circuit breaker is open.
+//!
+//! - URL path: ``/user/data/s3/list``
+//! - Method: ``GET``
+//! - Handler: [`list_user_data_s3_objects`](crate::requests::user::list_user_data_s3_objects::list_user_data_s3_objects)
+//! - Request: optional ``user_id`` and ``max_keys`` query parameters
+//! - Response: [`ApiResUserListS3Objects`](crate::requests::user::list_user_data_s3_objects::ApiResUserListS3Objects)
+//!
+//! #### Grant access to a user data file record
+//!
+//! Share a ``users_data`` record with another user id or role using ``read`` (search) or ``write`` (search and update) access. Only the record owner can grant access.
+//!
+//! - URL path: ``/user/data/acl``
+//! - Method: ``POST``
+//! - Handler: [`grant_user_data_access`](crate::requests::user::grant_user_data_access::grant_user_data_access)
+//! - Request: [`ApiReqUserGrantDataAccess`](crate::requests::user::grant_user_data_access::ApiReqUserGrantDataAccess)
+//! - Response: [`ApiResUserGrantDataAccess`](crate::requests::user::grant_user_data_access::ApiResUserGrantDataAccess)
+//!
+//! #### Revoke access to a user data file record
+//!
+//! Remove a ``users_data_acl`` share for a user id or role from a ``users_data`` record
+//!
+//! - URL path: ``/user/data/acl``
+//! - Method: ``DELETE``
+//! - Handler: [`revoke_user_data_access`](crate::requests::user::revoke_user_data_access::revoke_user_data_access)
+//! - Request: [`ApiReqUserRevokeDataAccess`](crate::requests::user::revoke_user_data_access::ApiReqUserRevokeDataAccess)
+//! - Response: [`ApiResUserRevokeDataAccess`](crate::requests::user::revoke_user_data_access::ApiResUserRevokeDataAccess)
+//!
+//! #### Share a user data file record
+//!
+//! Share a ``users_data`` record with another user id in the same tenant (search and download access) or create a public download link when ``shared_with_user_id`` is not set. Links expire after ``expire_hours`` (default 24) and the link token is only returned in this response. Records with shares or links that have not expired are not archived.
+//!
+//! - URL path: ``/user/data/shares``
+//! - Method: ``POST``
+//! - Handler: [`share_user_data`](crate::requests::user::share_user_data::share_user_data)
+//! - Request: [`ApiReqUserShareData`](crate::requests::user::share_user_data::ApiReqUserShareData)
+//! - Response: [`ApiResUserShareData`](crate::requests::user::share_user_data::ApiResUserShareData)
+//!
+//! #### Unshare a user data file record
+//!
+//! Remove a ``users_data_shares`` user share or public download link by its ``share_id``
+//!
+//! - URL path: ``/user/data/shares``
+//! - Method: ``DELETE``
+//! - Handler: [`unshare_user_data`](crate::requests::user::unshare_user_data::unshare_user_data)
+//! - Request: [`ApiReqUserUnshareData`](crate::requests::user::unshare_user_data::ApiReqUserUnshareData)
+//! - Response: [`ApiResUserUnshareData`](crate::requests::user::unshare_user_data::ApiResUserUnshareData)
+//!
+//! #### Download a user data file
+//!
+//! Download the file for a ``ready`` ``users_data`` record the user owns or can read through a ``users_data_acl`` share or ``users_data_shares`` user share. Each download is stored in ``users_data_access`` and publishes a ``DATA_DOWNLOADED`` event.
+//!
+//! - URL path: ``/user/data/DATAID/download``
+//! - Method: ``GET``
+//! - Handler: [`download_user_data`](crate::requests::user::download_user_data::download_user_data)
+//! - Request: none (uses the token header)
+//! - Response: the file contents (errors return an [`ApiResUserDownloadData`](crate::requests::user::download_user_data::ApiResUserDownloadData))
+//!
+//! #### Download a shared user data file
+//!
+//! Download the file for a ``ready`` ``users_data`` record with the token from an unexpired public download link (no login required)
+//!
+//! - URL path: ``/user/data/shared/TOKEN``
+//! - Method: ``GET``
+//! - Handler: [`download_shared_user_data`](crate::requests::user::download_shared_user_data::download_shared_user_data)
+//! - Request: none (the link token is in the url path)
+//! - Response: the file contents (errors return an [`ApiResUserDownloadData`](crate::requests::user::download_user_data::ApiResUserDownloadData))
+//!
+//! #### Get user data file download stats
+//!
+//! Get the number of downloads, distinct users, public link downloads, total bytes sent and the last 100 downloads from ``users_data_access`` for a ``users_data`` (or archived) record the user owns
+//!
+//! - URL path: ``/user/data/DATAID/access``
+//! - Method: ``GET``
+//! - Handler: [`get_user_data_access`](crate::requests::user::get_user_data_access::get_user_data_access)
+//! - Request: none (uses the token header)
+//! - Response: [`ApiResUserGetDataAccess`](crate::requests::user::get_user_data_access::ApiResUserGetDataAccess)
+//!
+//! #### Complete uploads from s3 event notifications
+//!
+//! Mark ``uploading`` ``users_data`` records as uploaded from a forwarded s3 ``ObjectCreated`` event notification (no login required, the request must be signed with ``S3_EVENTS_SECRET``, see [S3 Event Callbacks](#s3-event-callbacks))
+//!
+//! - URL path: ``/user/data/s3/events``
+//! - Method: ``POST``
+//! - Handler: [`complete_s3_event_uploads`](crate::requests::user::complete_s3_event_uploads::complete_s3_event_uploads)
+//! - Request: [`ApiReqS3EventNotification`](crate::requests::user::complete_s3_event_uploads::ApiReqS3EventNotification)
+//! - Response: [`ApiResS3EventNotification`](crate::requests::user::complete_s3_event_uploads::ApiResS3EventNotification)
+//!
 //! ### User Authentication APIs
 //!
 //! #### User Login
@@ -402,12 +1463,316 @@
 //! - Request: [`ApiReqUserLogin`](crate::requests::auth::login_user::ApiReqUserLogin)
 //! - Response: [`ApiResUserLogin`](crate::requests::auth::login_user::ApiResUserLogin)
 //!
+//! #### Get a CSRF Token
+//!
+//! Get the csrf token that cookie-authenticated ``POST``, ``PUT``, ``PATCH`` and ``DELETE`` requests send in the ``TOKEN_COOKIE_CSRF_HEADER`` header (requires ``TOKEN_COOKIE_MODE=both`` or ``cookie``). The token is also set in the ``TOKEN_COOKIE_CSRF_NAME`` cookie.
+//!
+//! - URL path: ``/csrf``
+//! - Method: ``GET``
+//! - Handler: [`get_csrf_token`](crate::requests::auth::get_csrf_token::get_csrf_token)
+//! - Response: [`ApiResCsrfToken`](crate::requests::auth::get_csrf_token::ApiResCsrfToken)
+//!
+//! #### Get the JSON Web Key Set (JWKS)
+//!
+//! Get the public jwt verification keys in JWKS format so other services can validate tokens issued by this api. Each key's ``kid`` matches the ``kid`` in the jwt header.
+//!
+//! - URL path: ``/.well-known/jwks.json``
+//! - Method: ``GET``
+//! - Handler: [`get_jwks`](crate::requests::auth::get_jwks::get_jwks)
+//!
+//! #### Get the User Event Schemas
+//!
+//! Get an OpenAPI document describing every user event published to kafka (``KAFKA_TOPIC_USER_EVENTS``), including the ``EVENT_NAME user=USER_ID [key=value ...]`` payload format and the details for each event name. Rust consumers can depend on this crate and parse payloads with ``restapi::events::UserEvent::from_payload`` instead of copying the event structs.
+//!
+//! - URL path: ``/openapi/events.json``
+//! - Method: ``GET``
+//! - Handler: [`get_events_openapi`](crate::requests::events::get_events_openapi::get_events_openapi)
+//!
+//! ### GraphQL API
+//!
+//! Build with ``cargo build --example server --features graphql`` to add a GraphQL facade over the user and user data models. Requests run as the user that owns the token header and reuse the REST api's validation, access rules and user events. The schema has the ``me``, ``userData(filter: UserDataFilter)`` and ``userQuota`` queries and the ``updateUserData(input: UserDataUpdate!)`` and ``deleteUserData(dataId: Int!, version: Int!)`` mutations. Scoped tokens need the resolver's scope (``INSUFFICIENT_SCOPE`` with a ``403`` ``extensions.status`` otherwise). Resolver errors return in the response's ``errors`` with the REST api's HTTP status code in ``extensions.status``. Queries are limited to a depth of 8 and a complexity of 500. User changes like passwords and emails stay on ``PUT /user``.
+//!
+//! #### Run a GraphQL query or mutation
+//!
+//! - URL path: ``/graphql``
+//! - Method: ``POST``
+//! - Handler: ``restapi::requests::graphql::handle_graphql::handle_graphql``
+//! - Request: json with a ``query`` and optional ``variables`` and ``operationName``
+//! - Response: json with ``data`` and ``errors``
+//!
+//! #### Get the GraphQL schema
+//!
+//! Get the schema in the GraphQL schema definition language for client code generators (no login required)
+//!
+//! - URL path: ``/graphql``
+//! - Method: ``GET``
+//! - Handler: ``restapi::requests::graphql::handle_graphql::get_graphql_schema``
+//!
+//! ### Passkey (WebAuthn) APIs
+//!
+//! #### Start Passkey Registration
+//!
+//! Create a webauthn registration challenge for a logged-in user to pass to ``navigator.credentials.create()``
+//!
+//! - URL path: ``/user/passkey/register/start``
+//! - Method: ``POST``
+//! - Handler: [`start_passkey_registration`](crate::requests::auth::webauthn::start_passkey_registration::start_passkey_registration)
+//! - Request: [`ApiReqUserStartPasskeyRegistration`](crate::requests::auth::webauthn::start_passkey_registration::ApiReqUserStartPasskeyRegistration)
+//! - Response: [`ApiResUserStartPasskeyRegistration`](crate::requests::auth::webauthn::start_passkey_registration::ApiResUserStartPasskeyRegistration)
+//!
+//! #### Finish Passkey Registration
+//!
+//! Verify the signed registration response and store the new passkey in the ``users_passkeys`` table
+//!
+//! - URL path: ``/user/passkey/register/finish``
+//! - Method: ``POST``
+//! - Handler: [`finish_passkey_registration`](crate::requests::auth::webauthn::finish_passkey_registration::finish_passkey_registration)
+//! - Request: [`ApiReqUserFinishPasskeyRegistration`](crate::requests::auth::webauthn::finish_passkey_registration::ApiReqUserFinishPasskeyRegistration)
+//! - Response: [`ApiResUserFinishPasskeyRegistration`](crate::requests::auth::webauthn::finish_passkey_registration::ApiResUserFinishPasskeyRegistration)
+//!
+//! #### Start Passkey Login
+//!
+//! Create a webauthn authentication challenge for a user's registered passkeys to pass to ``navigator.credentials.get()``
+//!
+//! - URL path: ``/login/passkey/start``
+//! - Method: ``POST``
+//! - Handler: [`start_passkey_login`](crate::requests::auth::webauthn::start_passkey_login::start_passkey_login)
+//! - Request: [`ApiReqUserStartPasskeyLogin`](crate::requests::auth::webauthn::start_passkey_login::ApiReqUserStartPasskeyLogin)
+//! - Response: [`ApiResUserStartPasskeyLogin`](crate::requests::auth::webauthn::start_passkey_login::ApiResUserStartPasskeyLogin)
+//!
+//! #### Finish Passkey Login
+//!
+//! Verify the signed authentication response and get a json web token (jwt) back (same response as ``/login``). Unknown emails, unverified users, missing challenges and invalid credentials all get the same ``400`` ``INVALID_CREDENTIALS`` response.
+//!
+//! - URL path: ``/login/passkey/finish``
+//! - Method: ``POST``
+//! - Handler: [`finish_passkey_login`](crate::requests::auth::webauthn::finish_passkey_login::finish_passkey_login)
+//! - Request: [`ApiReqUserFinishPasskeyLogin`](crate::requests::auth::webauthn::finish_passkey_login::ApiReqUserFinishPasskeyLogin)
+//! - Response: [`ApiResUserLogin`](crate::requests::auth::login_user::ApiResUserLogin)
+//!
+//! ### Admin APIs
+//!
+//! #### Unlock a Throttled Login
+//!
+//! Clear the failed login counts and lock for an email and/or an ip address. The requesting user must have the ``admin`` role.
+//!
+//! - URL path: ``/admin/login/unlock``
+//! - Method: ``POST``
+//! - Handler: [`unlock_login`](crate::requests::admin::unlock_login::unlock_login)
+//! - Request: [`ApiReqAdminUnlockLogin`](crate::requests::admin::unlock_login::ApiReqAdminUnlockLogin)
+//! - Response: [`ApiResAdminUnlockLogin`](crate::requests::admin::unlock_login::ApiResAdminUnlockLogin)
+//!
+//! #### Revoke Every Session for a User
+//!
+//! Log a user out of every device during incident response by revoking all of the user's active sessions. The action is stored in the ``users_admin_actions`` audit table with the admin and the optional ``reason`` and published as an ``ADMIN_SECURITY_REVOKE_SESSIONS`` user event for the target user. The requesting user must have the ``admin`` role.
+//!
+//! - URL path: ``/admin/users/sessions/revoke``
+//! - Method: ``POST``
+//! - Handler: [`revoke_user_sessions`](crate::requests::admin::revoke_user_sessions::revoke_user_sessions)
+//! - Request: [`ApiReqAdminRevokeUserSessions`](crate::requests::admin::revoke_user_sessions::ApiReqAdminRevokeUserSessions)
+//! - Response: [`ApiResAdminRevokeUserSessions`](crate::requests::admin::revoke_user_sessions::ApiResAdminRevokeUserSessions)
+//!
+//! #### Force a Password Reset
+//!
+//! Revoke all of a user's active sessions and block the user's password and passkey logins with a ``403`` and the ``PASSWORD_RESET_REQUIRED`` error code until the user changes their password with a one-time-use token (``POST /user/password/forgot`` and ``POST /user/password/change``). With ``send_otp`` (default ``true``) and ``KAFKA_PUBLISH_EVENTS=1`` the token is emailed right away. The action is audited in the ``users_admin_actions`` table and published as an ``ADMIN_SECURITY_FORCE_PASSWORD_RESET`` user event for the target user. The requesting user must have the ``admin`` role.
+//!
+//! - URL path: ``/admin/users/password/reset``
+//! - Method: ``POST``
+//! - Handler: [`force_password_reset`](crate::requests::admin::force_password_reset::force_password_reset)
+//! - Request: [`ApiReqAdminForcePasswordReset`](crate::requests::admin::force_password_reset::ApiReqAdminForcePasswordReset)
+//! - Response: [`ApiResAdminForcePasswordReset`](crate::requests::admin::force_password_reset::ApiResAdminForcePasswordReset)
+//!
+//! #### Get Runtime Settings
+//!
+//! Get the effective runtime settings and the overrides stored in the ``settings`` table. The requesting user must have the ``admin`` role.
+//!
+//! - URL path: ``/admin/settings``
+//! - Method: ``GET``
+//! - Handler: [`get_admin_settings`](crate::requests::admin::get_admin_settings::get_admin_settings)
+//! - Response: [`ApiResAdminSettings`](crate::requests::admin::get_admin_settings::ApiResAdminSettings)
+//!
+//! #### Update Runtime Settings
+//!
+//! Set or remove (``null``) runtime setting overrides for every api server in the cluster. Unsupported settings or invalid values are rejected with a ``422``. The requesting user must have the ``admin`` role.
+//!
+//! - URL path: ``/admin/settings``
+//! - Method: ``PUT``
+//! - Handler: [`update_admin_settings`](crate::requests::admin::update_admin_settings::update_admin_settings)
+//! - Request: [`ApiReqAdminUpdateSettings`](crate::requests::admin::update_admin_settings::ApiReqAdminUpdateSettings)
+//! - Response: [`ApiResAdminSettings`](crate::requests::admin::get_admin_settings::ApiResAdminSettings)
+//!
+//! #### Invite a User
+//!
+//! Create a pending user (``users.state = 1``) with a one-time-use invite token stored in the ``users_invites`` table for closed-signup deployments. A ``USER_INVITE`` event is published so a mail service can send the invite, and the token is returned to the admin. The requesting user must have the ``admin`` role.
+//!
+//! - URL path: ``/admin/users/invite``
+//! - Method: ``POST``
+//! - Handler: [`invite_user`](crate::requests::admin::invite_user::invite_user)
+//! - Request: [`ApiReqAdminInviteUser`](crate::requests::admin::invite_user::ApiReqAdminInviteUser)
+//! - Response: [`ApiResAdminInviteUser`](crate::requests::admin::invite_user::ApiResAdminInviteUser)
+//!
+//! #### Get JWT Key Migration Status
+//!
+//! Report the jwt keys loaded on the api server, the ``kid`` signing new tokens, the retired keys and how many active tokens still use each key. The requesting user must have the ``admin`` role.
+//!
+//! - URL path: ``/admin/jwt/keys``
+//! - Method: ``GET``
+//! - Handler: [`get_admin_jwt_keys`](crate::requests::admin::get_admin_jwt_keys::get_admin_jwt_keys)
+//! - Response: [`ApiResAdminJwtKeys`](crate::requests::admin::get_admin_jwt_keys::ApiResAdminJwtKeys)
+//!
+//! #### Retire a JWT Key
+//!
+//! Add a ``kid`` to the ``jwt_retired_kids`` runtime setting so every api server rejects its tokens and leaves it out of the jwks. Returns a ``409`` while active tokens still use the key unless ``force`` is ``true``, which revokes only those tokens. The key signing new tokens cannot be retired (``422``). The requesting user must have the ``admin`` role.
+//!
+//! - URL path: ``/admin/jwt/keys/retire``
+//! - Method: ``POST``
+//! - Handler: [`retire_jwt_key`](crate::requests::admin::retire_jwt_key::retire_jwt_key)
+//! - Request: [`ApiReqAdminRetireJwtKey`](crate::requests::admin::retire_jwt_key::ApiReqAdminRetireJwtKey)
+//! - Response: [`ApiResAdminJwtKeys`](crate::requests::admin::get_admin_jwt_keys::ApiResAdminJwtKeys)
+//!
+//! #### Get Password Hash Migration Status
+//!
+//! Report how many users have a password hash with each scheme (argon2 parameters and password salt), the scheme of new hashes and how many users still have a legacy hash. The requesting user must have the ``admin`` role.
+//!
+//! - URL path: ``/admin/passwords/schemes``
+//! - Method: ``GET``
+//! - Handler: [`get_admin_password_schemes`](crate::requests::admin::get_admin_password_schemes::get_admin_password_schemes)
+//! - Response: [`ApiResAdminPasswordSchemes`](crate::requests::admin::get_admin_password_schemes::ApiResAdminPasswordSchemes)
+//!
+//! #### Register a Webhook
+//!
+//! Register an endpoint that receives a signed json ``POST`` for each selected event type (``user.created``, ``user.verified`` and ``data.uploaded``) when ``WEBHOOKS_ENABLED=1``. The ``secret`` signs the deliveries and is never returned. The requesting user must have the ``admin`` role.
+//!
+//! - URL path: ``/admin/webhooks``
+//! - Method: ``POST``
+//! - Handler: [`create_webhook`](crate::requests::admin::create_webhook::create_webhook)
+//! - Request: [`ApiReqAdminCreateWebhook`](crate::requests::admin::create_webhook::ApiReqAdminCreateWebhook)
+//! - Response: [`ApiResAdminCreateWebhook`](crate::requests::admin::create_webhook::ApiResAdminCreateWebhook)
+//!
+//! #### Get Webhooks
+//!
+//! List the active webhook endpoints (without their secrets) and the supported event types. The requesting user must have the ``admin`` role.
+//!
+//! - URL path: ``/admin/webhooks``
+//! - Method: ``GET``
+//! - Handler: [`get_webhooks`](crate::requests::admin::get_webhooks::get_webhooks)
+//! - Response: [`ApiResAdminWebhooks`](crate::requests::admin::get_webhooks::ApiResAdminWebhooks)
+//!
+//! #### Delete a Webhook
+//!
+//! Stop sending deliveries to a webhook endpoint. Its ``pending`` deliveries are marked ``failed`` and its delivery records are kept. The requesting user must have the ``admin`` role.
+//!
+//! - URL path: ``/admin/webhooks``
+//! - Method: ``DELETE``
+//! - Handler: [`delete_webhook`](crate::requests::admin::delete_webhook::delete_webhook)
+//! - Request: [`ApiReqAdminDeleteWebhook`](crate::requests::admin::delete_webhook::ApiReqAdminDeleteWebhook)
+//! - Response: [`ApiResAdminDeleteWebhook`](crate::requests::admin::delete_webhook::ApiResAdminDeleteWebhook)
+//!
+//! #### Create an IP Rule
+//!
+//! Add an ``allow`` or ``deny`` rule for a network or address to every route or to the routes under a ``path_prefix`` (like ``/admin`` or ``/metrics``). Every api server reloads its rules when the ``ip_rules`` table changes. Rules that would block the requesting admin's own address from the ``/admin/ip-rules`` apis are rejected with a ``409``. The requesting user must have the ``admin`` role.
+//!
+//! - URL path: ``/admin/ip-rules``
+//! - Method: ``POST``
+//! - Handler: [`create_ip_rule`](crate::requests::admin::create_ip_rule::create_ip_rule)
+//! - Request: [`ApiReqAdminCreateIpRule`](crate::requests::admin::create_ip_rule::ApiReqAdminCreateIpRule)
+//! - Response: [`ApiResAdminCreateIpRule`](crate::requests::admin::create_ip_rule::ApiResAdminCreateIpRule)
+//!
+//! #### Get IP Rules
+//!
+//! List the rules from the environment variables and the ``ip_rules`` table with the client address the api server sees for the requesting admin. The requesting user must have the ``admin`` role.
+//!
+//! - URL path: ``/admin/ip-rules``
+//! - Method: ``GET``
+//! - Handler: [`get_ip_rules`](crate::requests::admin::get_ip_rules::get_ip_rules)
+//! - Response: [`ApiResAdminIpRules`](crate::requests::admin::get_ip_rules::ApiResAdminIpRules)
+//!
+//! #### Delete an IP Rule
+//!
+//! Remove a rule from the ``ip_rules`` table. Removing a rule that would block the requesting admin's own address from the ``/admin/ip-rules`` apis is rejected with a ``409``. The requesting user must have the ``admin`` role.
+//!
+//! - URL path: ``/admin/ip-rules``
+//! - Method: ``DELETE``
+//! - Handler: [`delete_ip_rule`](crate::requests::admin::delete_ip_rule::delete_ip_rule)
+//! - Request: [`ApiReqAdminDeleteIpRule`](crate::requests::admin::delete_ip_rule::ApiReqAdminDeleteIpRule)
+//! - Response: [`ApiResAdminDeleteIpRule`](crate::requests::admin::delete_ip_rule::ApiResAdminDeleteIpRule)
+//!
+//! #### Search Webhook Deliveries
+//!
+//! Get the newest webhook deliveries with their status (``pending``, ``delivered`` or ``failed``), attempts, last HTTP status code and last error, filtered by ``webhook_id`` and/or ``status``. The requesting user must have the ``admin`` role.
+//!
+//! - URL path: ``/admin/webhooks/deliveries``
+//! - Method: ``POST``
+//! - Handler: [`search_webhook_deliveries`](crate::requests::admin::search_webhook_deliveries::search_webhook_deliveries)
+//! - Request: [`ApiReqAdminSearchWebhookDeliveries`](crate::requests::admin::search_webhook_deliveries::ApiReqAdminSearchWebhookDeliveries)
+//! - Response: [`ApiResAdminSearchWebhookDeliveries`](crate::requests::admin::search_webhook_deliveries::ApiResAdminSearchWebhookDeliveries)
+//!
+//! #### Search Kafka Dead Letters
+//!
+//! Get the newest kafka dead letters with their topic, partition key, payload, last error, status (``dead`` or ``requeued``) and attempts, filtered by ``status`` and/or ``topic``, plus the number of messages waiting in the api server's kafka publisher. The requesting user must have the ``admin`` role.
+//!
+//! - URL path: ``/admin/kafka/dead-letters``
+//! - Method: ``POST``
+//! - Handler: [`search_kafka_dead_letters`](crate::requests::admin::search_kafka_dead_letters::search_kafka_dead_letters)
+//! - Request: [`ApiReqAdminSearchKafkaDeadLetters`](crate::requests::admin::search_kafka_dead_letters::ApiReqAdminSearchKafkaDeadLetters)
+//! - Response: [`ApiResAdminSearchKafkaDeadLetters`](crate::requests::admin::search_kafka_dead_letters::ApiResAdminSearchKafkaDeadLetters)
+//!
+//! #### Requeue Kafka Dead Letters
+//!
+//! Publish ``dead`` letters to kafka again. Published dead letters are marked ``requeued``, dead letters that fail again keep the new error and ids that are not ``dead`` are skipped. Returns a ``503`` when kafka publishing is disabled. The requesting user must have the ``admin`` role.
+//!
+//! - URL path: ``/admin/kafka/dead-letters/requeue``
+//! - Method: ``POST``
+//! - Handler: [`requeue_kafka_dead_letters`](crate::requests::admin::requeue_kafka_dead_letters::requeue_kafka_dead_letters)
+//! - Request: [`ApiReqAdminRequeueKafkaDeadLetters`](crate::requests::admin::requeue_kafka_dead_letters::ApiReqAdminRequeueKafkaDeadLetters)
+//! - Response: [`ApiResAdminRequeueKafkaDeadLetters`](crate::requests::admin::requeue_kafka_dead_letters::ApiResAdminRequeueKafkaDeadLetters)
+//!
+//! #### Get Admin Stats
+//!
+//! Get aggregate json stats for lightweight dashboards that do not run PromQL: total, active and verified users (with the verified percent of active users), uploads in the last 24 hours, total stored bytes and the logins (new sessions) in each of the last 24 hours for every tenant. Results are cached for ``ADMIN_STATS_CACHE_SECONDS``. The requesting user must have the ``admin`` role.
+//!
+//! - URL path: ``/admin/stats``
+//! - Method: ``GET``
+//! - Handler: [`get_admin_stats`](crate::requests::admin::get_admin_stats::get_admin_stats)
+//! - Request: none (uses the token header)
+//! - Response: [`ApiResAdminStats`](crate::requests::admin::get_admin_stats::ApiResAdminStats)
+//!
 //! # Interation Test Guide
 //!
 //! This project focused on integration tests for v1 instead of only rust tests (specifically everything has been tested with **curl**):
 //!
 //! Please refer to the latest [Integration Tests Using curl Guide](https://github.com/jay-johnson/restapi/blob/tests/integration-using-curl.md)
 //!
+//! ## Rust End-to-End Tests
+//!
+//! Build with ``--features test-support`` to write end-to-end tests in rust. [`TestServer::start`](https://docs.rs/restapi/latest/restapi/test_support/test_server/struct.TestServer.html) creates a throwaway certificate authority, api and postgres tls certificates and ES256 jwt keys in a temp directory, starts a ``postgres:14.5-alpine`` container with [testcontainers](https://docs.rs/testcontainers) (requires a docker daemon), applies ``docker/db/sql/init.sql`` and serves the api on a random ``127.0.0.1`` port. Use ``TestServer::start_with`` to pass a ``RestApiServerBuilder`` (custom routes, claims providers) and extra migration sql. The container, temp directory and server stop when the ``TestServer`` is dropped.
+//!
+//! ```rust,ignore
+//! use hyper::Method;
+//! use restapi::test_support::test_server::TestServer;
+//!
+//! #[tokio::test]
+//! async fn create_and_get_user() {
+//!     let server = TestServer::start().await.unwrap();
+//!     let (user_id, token) = server
+//!         .create_user("user@email.com", "12345")
+//!         .await
+//!         .unwrap();
+//!     let (status, user) = server
+//!         .send_json(Method::GET, &format!("/user/{user_id}"), Some(&token), None)
+//!         .await
+//!         .unwrap();
+//!     assert_eq!(status, 200);
+//!     assert_eq!(user["email"], "user@email.com");
+//! }
+//! ```
+//!
+//! The create user and login tests in [tests/test_server.rs](https://github.com/jay-johnson/restapi/blob/main/tests/test_server.rs) run with:
+//!
+//! ```bash
+//! cargo test --features test-support --test test_server
+//! ```
+//!
 //! ## Build and run the example server
 //!
 //! ```bash
@@ -433,6 +1798,7 @@
 //! curl -s ${TLS_ARGS} \
 //!     "https://0.0.0.0:3000/login" \
 //!     -XPOST \
+//!     -H "Content-Type: application/json" \
 //!     -d '{"email":"user@email.com","password":"12345"}' | jq
 //! ```
 //!
@@ -442,6 +1808,7 @@
 //! curl -s ${TLS_ARGS} \
 //!     "https://0.0.0.0:3000/user" \
 //!     -XPOST \
+//!     -H "Content-Type: application/json" \
 //!     -d '{"email":"user@email.com","password":"12345"}' | jq
 //! ```
 //!
@@ -451,6 +1818,7 @@
 //! export TOKEN=$(curl -s ${TLS_ARGS} \
 //!     "https://0.0.0.0:3000/login" \
 //!     -XPOST \
+//!     -H "Content-Type: application/json" \
 //!     -d '{"email":"user@email.com","password":"12345"}' | jq -r '.token')
 //! ```
 //!
@@ -465,12 +1833,15 @@
 //!
 //! ### Update user
 //!
+//! Updates require the ``version`` from the last get, search or update response (``USER_VERSION``). A stale version is rejected with a ``409``.
+//!
 //! ```bash
 //! curl -s ${TLS_ARGS} \
 //!     "https://0.0.0.0:3000/user" \
 //!     -H "Bearer: ${TOKEN}" \
 //!     -XPUT \
-//!     -d '{"user_id":1,"email":"somenewemail@gmail.com","password":"321123","state":0}'
+//!     -H "Content-Type: application/json" \
+//!     -d '{"user_id":1,"email":"somenewemail@gmail.com","password":"321123","state":0,"version":USER_VERSION}'
 //! ```
 //!
 //! ### Change user password
@@ -482,7 +1853,8 @@
 //!     "https://0.0.0.0:3000/user" \
 //!     -H "Bearer: ${TOKEN}" \
 //!     -XPUT \
-//!     -d '{"user_id":1,"password":"12345a"}' | jq
+//!     -H "Content-Type: application/json" \
+//!     -d '{"user_id":1,"password":"12345a","version":USER_VERSION}' | jq
 //! ```
 //!
 //! #### Change password back to the original
@@ -492,7 +1864,8 @@
 //!     "https://0.0.0.0:3000/user" \
 //!     -H "Bearer: ${TOKEN}" \
 //!     -XPUT \
-//!     -d '{"user_id":1,"password":"12345"}' | jq
+//!     -H "Content-Type: application/json" \
+//!     -d '{"user_id":1,"password":"12345","version":USER_VERSION}' | jq
 //! ```
 //!
 //! ### Create a one-time-use-password (otp) allowing a user to reset their users.password from the users.email
@@ -502,6 +1875,7 @@
 //!     "https://0.0.0.0:3000/user/password/reset" \
 //!     -H "Bearer: ${TOKEN}" \
 //!     -XPOST \
+//!     -H "Content-Type: application/json" \
 //!     -d '{"user_id":1,"email":"user@email.com"}' | jq
 //! ```
 //!
@@ -512,6 +1886,7 @@
 //!     "https://0.0.0.0:3000/user/password/change" \
 //!     -H "Bearer: ${TOKEN}" \
 //!     -XPOST \
+//!     -H "Content-Type: application/json" \
 //!     -d '{"user_id":1,"email":"user@email.com"}' | jq
 //! ```
 //!
@@ -522,7 +1897,8 @@
 //!     "https://0.0.0.0:3000/user" \
 //!     -H "Bearer: ${TOKEN}" \
 //!     -XPUT \
-//!     -d '{"user_id":1,"email":"unique@gmail.com"}' | jq
+//!     -H "Content-Type: application/json" \
+//!     -d '{"user_id":1,"email":"unique@gmail.com","version":USER_VERSION}' | jq
 //! ```
 //!
 //! ### Verify user email
@@ -539,6 +1915,7 @@
 //!     "https://0.0.0.0:3000/user/search" \
 //!     -XPOST \
 //!     -H "Bearer: ${TOKEN}" \
+//!     -H "Content-Type: application/json" \
 //!     -d '{"email":"user","user_id":1}' | jq
 //! ```
 //!
@@ -548,6 +1925,7 @@
 //! curl -s ${TLS_ARGS} \
 //!     "https://0.0.0.0:3000/user" \
 //!     -XDELETE \
+//!     -H "Content-Type: application/json" \
 //!     -d '{"email":"user@email.com","user_id":1}' \
 //!     -H "Content-type: application/json" \
 //!     -H "Bearer: ${TOKEN}" | jq
@@ -640,17 +2018,21 @@
 //!     "https://0.0.0.0:3000/user/data/search" \
 //!     -XPOST \
 //!     -H "Bearer: ${TOKEN}" \
+//!     -H "Content-Type: application/json" \
 //!     -d '{"user_id":1}' | jq
 //! ```
 //!
 //! ### Update a single user data record (token must be for the PUT user id)
 //!
+//! Data updates require the record's ``version`` from the last search or update response (``DATA_VERSION``). A stale version is rejected with a ``409``.
+//!
 //! ```bash
 //! curl -s ${TLS_ARGS} \
 //!     "https://0.0.0.0:3000/user/data" \
 //!     -XPUT \
 //!     -H "Bearer: ${TOKEN}" \
-//!     -d '{"user_id":1,"data_id":1,"comments":"updated comment using curl"}' | jq
+//!     -H "Content-Type: application/json" \
+//!     -d '{"user_id":1,"data_id":1,"comments":"updated comment using curl","version":DATA_VERSION}' | jq
 //! ```
 //!
 //! ### Login and save the token as an env variable
@@ -659,6 +2041,7 @@
 //! export TOKEN=$(curl -s ${TLS_ARGS} \
 //!     "https://0.0.0.0:3000/login" \
 //!     -XPOST \
+//!     -H "Content-Type: application/json" \
 //!     -d '{"email":"user@email.com","password":"12345"}' | jq -r '.token')
 //! ```
 //!
@@ -707,13 +2090,25 @@ extern crate serde_json;
 extern crate uuid;
 
 // include files and sub directories
+pub mod archive;
+pub mod cli;
 pub mod core;
+pub mod demo;
+pub mod events;
 pub mod handle_request;
+pub mod i18n;
 pub mod is3;
 pub mod jwt;
 pub mod kafka;
 pub mod monitoring;
+pub mod notifications;
 pub mod pools;
+pub mod processing;
 pub mod requests;
+pub mod secrets;
+pub mod settings;
+#[cfg(feature = "test-support")]
+pub mod test_support;
 pub mod tls;
 pub mod utils;
+pub mod webhooks;
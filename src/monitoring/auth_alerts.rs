@@ -0,0 +1,415 @@
+//! Count failed logins, one-time-password (otp) failures
+//! and token validation failures by reason and alert
+//! operators when the failures cross a threshold
+//!
+//! Every failure increments the ``auth_failures_total``
+//! prometheus counter with ``kind`` (``login``, ``otp`` or
+//! ``token``) and ``reason`` labels. Each api server also
+//! counts the failures for each kind in a fixed window
+//! (``AUTH_ALERT_WINDOW_SECONDS``), and when a kind reaches
+//! its threshold the
+//! [`AuthAlertHook`](crate::monitoring::auth_alerts::AuthAlertHook)
+//! is called in a background task (at most once per
+//! ``AUTH_ALERT_COOLDOWN_SECONDS`` for each kind).
+//!
+//! With ``AUTH_ALERT_WEBHOOK_URL`` set, alerts are sent as
+//! a json ``POST`` to the url
+//! ([`WebhookAuthAlertHook`](crate::monitoring::auth_alerts::WebhookAuthAlertHook)).
+//! Set the ``hook`` on the
+//! [`CoreConfig`](crate::core::core_config::CoreConfig)
+//! ``auth_alerts`` before starting the server for other
+//! destinations. Alerts are always logged and counted in
+//! ``auth_alerts_total``.
+//!
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use lazy_static::lazy_static;
+
+use hyper::client::HttpConnector;
+use hyper::Body;
+use hyper::Client;
+use hyper::Method;
+use hyper::Request;
+
+use hyper_tls::HttpsConnector;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::monitoring::metric_types::register_int_counter_vec;
+use crate::monitoring::metric_types::IntCounterVec;
+
+lazy_static! {
+    pub static ref AUTH_FAILURE_COUNTER_VEC: IntCounterVec =
+        register_int_counter_vec!(
+            "auth_failures_total",
+            "Number of failed logins, one-time-password failures \
+            and token validation failures by reason.",
+            &["kind", "reason"]
+        )
+        .unwrap();
+    pub static ref AUTH_ALERT_COUNTER_VEC: IntCounterVec =
+        register_int_counter_vec!(
+            "auth_alerts_total",
+            "Number of auth failure alerts by kind and hook result.",
+            &["kind", "result"]
+        )
+        .unwrap();
+}
+
+/// AuthAlert
+///
+/// Sent to the
+/// [`AuthAlertHook`](crate::monitoring::auth_alerts::AuthAlertHook)
+/// when a kind of auth failure crosses its threshold
+///
+/// # Arguments
+///
+/// * `server` - `String` - api server name
+///   (``SERVER_NAME_LABEL``)
+/// * `kind` - `String` - ``login``, ``otp`` or ``token``
+/// * `reason` - `String` - reason for the failure that
+///   crossed the threshold
+/// * `failures` - `u64` - failures in the current window
+/// * `threshold` - `u64` - failures that trigger an alert
+/// * `window_seconds` - `u64` - length of the window
+/// * `created_at` - `String` - utc timestamp for the alert
+///
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AuthAlert {
+    pub server: String,
+    pub kind: String,
+    pub reason: String,
+    pub failures: u64,
+    pub threshold: u64,
+    pub window_seconds: u64,
+    pub created_at: String,
+}
+
+/// future returned by
+/// [`AuthAlertHook::send_alert`](crate::monitoring::auth_alerts::AuthAlertHook::send_alert)
+pub type AuthAlertFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<(), String>> + Send + 'a>>;
+
+/// AuthAlertHook
+///
+/// Destination for auth failure alerts
+///
+pub trait AuthAlertHook: Send + Sync {
+    /// send_alert
+    ///
+    /// Deliver an alert (called in a background task)
+    ///
+    /// # Arguments
+    ///
+    /// * `alert` - [`AuthAlert`](crate::monitoring::auth_alerts::AuthAlert) -
+    ///   the alert
+    ///
+    /// # Returns
+    ///
+    /// Ok(())
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`)
+    ///
+    fn send_alert<'a>(&'a self, alert: &'a AuthAlert) -> AuthAlertFuture<'a>;
+}
+
+/// WebhookAuthAlertHook
+///
+/// ``POST`` each alert as json to a webhook url
+///
+/// # Arguments
+///
+/// * `url` - `String` - ``http`` or ``https`` webhook url
+/// * `timeout_ms` - `u64` - max milliseconds for each
+///   webhook request
+///
+pub struct WebhookAuthAlertHook {
+    pub url: String,
+    pub timeout_ms: u64,
+    client: Client<HttpsConnector<HttpConnector>>,
+}
+
+impl WebhookAuthAlertHook {
+    /// new
+    ///
+    /// Build a webhook hook for a url
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - `&str` - ``http`` or ``https`` webhook url
+    /// * `timeout_ms` - `u64` - max milliseconds for each
+    ///   webhook request
+    ///
+    pub fn new(url: &str, timeout_ms: u64) -> Self {
+        WebhookAuthAlertHook {
+            url: url.to_string(),
+            timeout_ms,
+            client: Client::builder().build(HttpsConnector::new()),
+        }
+    }
+}
+
+impl AuthAlertHook for WebhookAuthAlertHook {
+    fn send_alert<'a>(&'a self, alert: &'a AuthAlert) -> AuthAlertFuture<'a> {
+        Box::pin(async move {
+            let req = Request::builder()
+                .method(Method::POST)
+                .uri(&self.url)
+                .header("Content-Type", "application/json")
+                .body(Body::from(serde_json::to_string(alert).unwrap()))
+                .map_err(|e| {
+                    format!("invalid AUTH_ALERT_WEBHOOK_URL with err='{e}'")
+                })?;
+            match tokio::time::timeout(
+                Duration::from_millis(self.timeout_ms),
+                self.client.request(req),
+            )
+            .await
+            {
+                Ok(Ok(res)) if res.status().is_success() => Ok(()),
+                Ok(Ok(res)) => Err(format!(
+                    "auth alert webhook returned status={}",
+                    res.status()
+                )),
+                Ok(Err(e)) => {
+                    Err(format!("auth alert webhook failed with err='{e}'"))
+                }
+                Err(_) => Err(format!(
+                    "auth alert webhook timed out after {}ms",
+                    self.timeout_ms
+                )),
+            }
+        })
+    }
+}
+
+/// AuthFailureWindow
+///
+/// Failures for one kind in the current window
+///
+#[derive(Clone, Debug)]
+struct AuthFailureWindow {
+    started_at: Instant,
+    failures: u64,
+    last_alert_at: Option<Instant>,
+}
+
+/// AuthAlerts
+///
+/// Settings for counting auth failures and alerting when
+/// they cross a threshold
+///
+/// # Supported Environment Variables
+///
+/// ```bash
+/// # json POST for each alert (empty = log only)
+/// export AUTH_ALERT_WEBHOOK_URL="https://alerts.example.com/hooks/restapi"
+/// export AUTH_ALERT_WEBHOOK_TIMEOUT_MS="5000"
+/// export AUTH_ALERT_WINDOW_SECONDS="60"
+/// # failures in a window that trigger an alert (0 = off)
+/// export AUTH_ALERT_LOGIN_THRESHOLD="50"
+/// export AUTH_ALERT_OTP_THRESHOLD="20"
+/// export AUTH_ALERT_TOKEN_THRESHOLD="100"
+/// # min seconds between alerts for the same kind
+/// export AUTH_ALERT_COOLDOWN_SECONDS="300"
+/// ```
+///
+/// # Arguments
+///
+/// * `server` - `String` - api server name for alerts
+/// * `window_seconds` - `u64` - length of each window
+/// * `login_threshold` - `u64` - failed logins in a window
+///   that trigger an alert (``0`` = off)
+/// * `otp_threshold` - `u64` - otp failures in a window
+///   that trigger an alert (``0`` = off)
+/// * `token_threshold` - `u64` - token validation failures
+///   in a window that trigger an alert (``0`` = off)
+/// * `cooldown_seconds` - `u64` - min seconds between alerts
+///   for the same kind
+/// * `hook` - `Option<Arc<dyn`
+///   [`AuthAlertHook`](crate::monitoring::auth_alerts::AuthAlertHook)`>>` -
+///   optional alert destination
+///
+#[derive(Clone, Default)]
+pub struct AuthAlerts {
+    pub server: String,
+    pub window_seconds: u64,
+    pub login_threshold: u64,
+    pub otp_threshold: u64,
+    pub token_threshold: u64,
+    pub cooldown_seconds: u64,
+    pub hook: Option<Arc<dyn AuthAlertHook>>,
+    windows: Arc<Mutex<HashMap<String, AuthFailureWindow>>>,
+}
+
+impl AuthAlerts {
+    /// build_auth_alerts
+    ///
+    /// Build an
+    /// [`AuthAlerts`](crate::monitoring::auth_alerts::AuthAlerts)
+    /// from environment variables
+    ///
+    /// # Arguments
+    ///
+    /// * `server` - `&str` - api server name for alerts
+    ///
+    pub fn build_auth_alerts(server: &str) -> Self {
+        let get_env = |key: &str, default: u64| -> u64 {
+            std::env::var(key)
+                .unwrap_or_else(|_| format!("{default}"))
+                .parse::<u64>()
+                .unwrap_or(default)
+        };
+        let webhook_url =
+            std::env::var("AUTH_ALERT_WEBHOOK_URL").unwrap_or_default();
+        let hook: Option<Arc<dyn AuthAlertHook>> = match webhook_url.is_empty()
+        {
+            true => None,
+            false => Some(Arc::new(WebhookAuthAlertHook::new(
+                &webhook_url,
+                get_env("AUTH_ALERT_WEBHOOK_TIMEOUT_MS", 5000).max(1),
+            ))),
+        };
+        AuthAlerts {
+            server: server.to_string(),
+            window_seconds: get_env("AUTH_ALERT_WINDOW_SECONDS", 60).max(1),
+            login_threshold: get_env("AUTH_ALERT_LOGIN_THRESHOLD", 50),
+            otp_threshold: get_env("AUTH_ALERT_OTP_THRESHOLD", 20),
+            token_threshold: get_env("AUTH_ALERT_TOKEN_THRESHOLD", 100),
+            cooldown_seconds: get_env("AUTH_ALERT_COOLDOWN_SECONDS", 300),
+            hook,
+            windows: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// get_threshold
+    ///
+    /// Get the alert threshold for a kind of failure
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - `&str` - ``login``, ``otp`` or ``token``
+    ///
+    /// # Returns
+    ///
+    /// `u64` - failures that trigger an alert (``0`` = off)
+    ///
+    pub fn get_threshold(&self, kind: &str) -> u64 {
+        match kind {
+            "login" => self.login_threshold,
+            "otp" => self.otp_threshold,
+            "token" => self.token_threshold,
+            _ => 0,
+        }
+    }
+
+    /// record_failure
+    ///
+    /// Count an auth failure and send an alert when
+    /// the kind's failures in the current window reach
+    /// its threshold
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - caller logging label
+    /// * `kind` - `&str` - ``login``, ``otp`` or ``token``
+    /// * `reason` - `&str` - short reason label
+    ///   (for example ``invalid_password``)
+    ///
+    pub fn record_failure(
+        &self,
+        tracking_label: &str,
+        kind: &str,
+        reason: &str,
+    ) {
+        AUTH_FAILURE_COUNTER_VEC
+            .with_label_values(&[kind, reason])
+            .inc();
+        let threshold = self.get_threshold(kind);
+        if threshold == 0 {
+            return;
+        }
+        let failures = {
+            let mut windows = self.windows.lock().unwrap();
+            let now = Instant::now();
+            let window =
+                windows
+                    .entry(kind.to_string())
+                    .or_insert(AuthFailureWindow {
+                        started_at: now,
+                        failures: 0,
+                        last_alert_at: None,
+                    });
+            if now.duration_since(window.started_at).as_secs()
+                >= self.window_seconds
+            {
+                window.started_at = now;
+                window.failures = 0;
+            }
+            window.failures += 1;
+            let cooling_down = window
+                .last_alert_at
+                .map(|last_alert_at| {
+                    now.duration_since(last_alert_at).as_secs()
+                        < self.cooldown_seconds
+                })
+                .unwrap_or(false);
+            if window.failures < threshold || cooling_down {
+                return;
+            }
+            window.last_alert_at = Some(now);
+            window.failures
+        };
+        let alert = AuthAlert {
+            server: self.server.clone(),
+            kind: kind.to_string(),
+            reason: reason.to_string(),
+            failures,
+            threshold,
+            window_seconds: self.window_seconds,
+            created_at: chrono::Utc::now().to_rfc3339(),
+        };
+        warn!(
+            "{tracking_label} - auth failure alert - \
+            {failures} {kind} failures in {}s (threshold={threshold}) \
+            last reason={reason}",
+            self.window_seconds
+        );
+        let hook = match &self.hook {
+            Some(hook) => hook.clone(),
+            None => {
+                AUTH_ALERT_COUNTER_VEC
+                    .with_label_values(&[kind, "logged"])
+                    .inc();
+                return;
+            }
+        };
+        let tracking_label = tracking_label.to_string();
+        tokio::spawn(async move {
+            let result = match hook.send_alert(&alert).await {
+                Ok(_) => "sent",
+                Err(err_msg) => {
+                    error!(
+                        "{tracking_label} - \
+                        failed to send {} auth failure alert with \
+                        err='{err_msg}'",
+                        alert.kind
+                    );
+                    "failed"
+                }
+            };
+            AUTH_ALERT_COUNTER_VEC
+                .with_label_values(&[&alert.kind, result])
+                .inc();
+        });
+    }
+}
@@ -0,0 +1,81 @@
+//! Health check for load balancers and kubernetes probes
+//!
+//! ``GET /health`` does not need a token, does not touch the
+//! db and is served in maintenance mode (with a
+//! ``maintenance`` status) so planned db migrations do not
+//! take the api servers out of rotation.
+//! Open s3 and kafka circuit breakers are listed in
+//! ``degraded`` (with a ``degraded`` status) without failing
+//! the check, because restarting the pod does not fix a
+//! downstream outage.
+//!
+use std::convert::Infallible;
+
+use hyper::Body;
+use hyper::Response;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::core::core_config::CoreConfig;
+use crate::utils::circuit_breaker::CircuitState;
+
+/// ApiResHealth
+///
+/// # Response type for handle_health
+///
+/// # Arguments
+///
+/// * `status` - `String` - ``ok``, ``degraded`` or
+///   ``maintenance``
+/// * `maintenance_mode` - `bool` - non-admin requests are
+///   rejected with a `503`
+/// * `degraded` - `Vec<String>` - dependencies with an open
+///   circuit breaker (``s3`` or ``kafka``)
+///
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct ApiResHealth {
+    pub status: String,
+    pub maintenance_mode: bool,
+    pub degraded: Vec<String>,
+}
+
+/// handle_health
+///
+/// Report that the api server is up with a `200` HTTP
+/// status code
+///
+/// # Arguments
+///
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+///
+pub fn handle_health(
+    config: &CoreConfig,
+) -> std::result::Result<Response<Body>, Infallible> {
+    let maintenance_mode = config.get_settings().maintenance_mode;
+    let degraded: Vec<String> = [
+        &config.s3_circuit_breaker,
+        &config.events.dead_letters.circuit_breaker,
+    ]
+    .iter()
+    .filter(|breaker| breaker.get_state() != CircuitState::Closed)
+    .map(|breaker| breaker.name.clone())
+    .collect();
+    let status = match (maintenance_mode, degraded.is_empty()) {
+        (true, _) => "maintenance",
+        (false, false) => "degraded",
+        (false, true) => "ok",
+    };
+    Ok(Response::builder()
+        .status(200)
+        .header("Content-Type", "application/json")
+        .body(Body::from(
+            serde_json::to_string(&ApiResHealth {
+                status: status.to_string(),
+                maintenance_mode,
+                degraded,
+            })
+            .unwrap(),
+        ))
+        .unwrap())
+}
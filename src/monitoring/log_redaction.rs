@@ -0,0 +1,398 @@
+//! Redact emails, tokens and passwords from the log output
+//!
+//! [`init_logger`](crate::monitoring::log_redaction::init_logger)
+//! wraps the ``pretty_env_logger`` logger (still filtered by
+//! ``RUST_LOG``) with a
+//! [`RedactingLogger`](crate::monitoring::log_redaction::RedactingLogger)
+//! that rewrites each formatted log message before it is
+//! written:
+//!
+//! - emails - ``[redacted-email]`` (``a***@email.com`` with
+//!   ``LOG_REDACTION=partial``)
+//! - json web tokens (``eyJ...``) - ``[redacted-token]``
+//! - values for ``password``, ``token``, ``secret``,
+//!   ``authorization``, ``bearer``, ``otp``, ``cookie`` and
+//!   ``api_key`` keys (including keys ending with
+//!   ``_token`` or ``_password``) in ``key=value``,
+//!   ``key: value`` and json formats - ``[redacted]``
+//!
+//! ## Supported Environment Variables
+//!
+//! ```bash
+//! # full (default), partial (mask emails) or off (dev only)
+//! export LOG_REDACTION="full"
+//! ```
+//!
+use log::LevelFilter;
+use log::Log;
+use log::Metadata;
+use log::Record;
+
+use pretty_env_logger::env_logger::Logger;
+
+/// supported ``LOG_REDACTION`` values
+pub const LOG_REDACTION_MODES: [&str; 3] = ["full", "partial", "off"];
+
+/// key names (or the last ``_``-separated part of a key
+/// name) with values that are always redacted
+pub const SENSITIVE_LOG_KEYS: [&str; 11] = [
+    "password",
+    "passwd",
+    "secret",
+    "token",
+    "authorization",
+    "bearer",
+    "otp",
+    "cookie",
+    "credentials",
+    "api_key",
+    "private_key",
+];
+
+/// RedactingLogger
+///
+/// [`Log`](log::Log) implementation that redacts each
+/// message before the wrapped ``env_logger`` writes it
+///
+/// # Arguments
+///
+/// * `inner` - ``env_logger`` [`Logger`](pretty_env_logger::env_logger::Logger)
+///   filtered by ``RUST_LOG``
+/// * `mode` - `String` - ``full``, ``partial`` or ``off``
+///
+pub struct RedactingLogger {
+    pub inner: Logger,
+    pub mode: String,
+}
+
+impl Log for RedactingLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.inner.matches(record) {
+            return;
+        }
+        if self.mode == "off" {
+            self.inner.log(record);
+            return;
+        }
+        let msg = redact_log_message(&record.args().to_string(), &self.mode);
+        self.inner.log(
+            &Record::builder()
+                .args(format_args!("{msg}"))
+                .metadata(record.metadata().clone())
+                .module_path(record.module_path())
+                .file(record.file())
+                .line(record.line())
+                .build(),
+        );
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// get_log_redaction_mode
+///
+/// Get the ``LOG_REDACTION`` mode (unsupported values use
+/// ``full``)
+///
+/// # Returns
+///
+/// `String` - ``full``, ``partial`` or ``off``
+///
+pub fn get_log_redaction_mode() -> String {
+    let mode = std::env::var("LOG_REDACTION")
+        .unwrap_or_else(|_| "full".to_string())
+        .trim()
+        .to_lowercase();
+    match LOG_REDACTION_MODES.contains(&mode.as_str()) {
+        true => mode,
+        false => "full".to_string(),
+    }
+}
+
+/// init_logger
+///
+/// Initialize the global logger with a timed
+/// ``pretty_env_logger`` that is filtered by ``RUST_LOG`` and
+/// redacts sensitive values based off ``LOG_REDACTION``
+///
+/// # Panics
+///
+/// If the global logger was already set
+///
+pub fn init_logger() {
+    try_init_logger().unwrap();
+}
+
+/// try_init_logger
+///
+/// Initialize the global logger with a
+/// [`RedactingLogger`](crate::monitoring::log_redaction::RedactingLogger)
+///
+/// # Errors
+///
+/// Err([`SetLoggerError`](log::SetLoggerError)) if the
+/// global logger was already set
+///
+pub fn try_init_logger() -> Result<(), log::SetLoggerError> {
+    let mut builder = pretty_env_logger::formatted_timed_builder();
+    if let Ok(filters) = std::env::var("RUST_LOG") {
+        builder.parse_filters(&filters);
+    }
+    let inner = builder.build();
+    let max_level: LevelFilter = inner.filter();
+    let mode = get_log_redaction_mode();
+    log::set_boxed_logger(Box::new(RedactingLogger { inner, mode }))?;
+    log::set_max_level(max_level);
+    let configured = std::env::var("LOG_REDACTION").unwrap_or_default();
+    if !configured.is_empty()
+        && !LOG_REDACTION_MODES
+            .contains(&configured.trim().to_lowercase().as_str())
+    {
+        warn!(
+            "unsupported LOG_REDACTION={configured} - using full \
+            redaction (use one of: {})",
+            LOG_REDACTION_MODES.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// redact_log_message
+///
+/// Redact emails, json web tokens and the values of
+/// sensitive keys in a log message
+///
+/// # Arguments
+///
+/// * `msg` - `&str` - formatted log message
+/// * `mode` - `&str` - ``full``, ``partial`` or ``off``
+///
+/// # Examples
+///
+/// ```rust
+/// use restapi::monitoring::log_redaction::redact_log_message;
+/// assert_eq!(
+///     redact_log_message("login user=1 email=Alice@email.com", "full"),
+///     "login user=1 email=[redacted-email]");
+/// assert_eq!(
+///     redact_log_message("login user=1 email=Alice@email.com", "partial"),
+///     "login user=1 email=A***@email.com");
+/// assert_eq!(
+///     redact_log_message(
+///         "{\"password\": \"hunter2\", \"user_id\": 1} reset_token=abc123",
+///         "full"),
+///     "{\"password\": \"[redacted]\", \"user_id\": 1} reset_token=[redacted]");
+/// assert_eq!(
+///     redact_log_message("Bearer: eyJhbGciOi.eyJzdWIiOi.c2lnbmF0dXJl", "off"),
+///     "Bearer: eyJhbGciOi.eyJzdWIiOi.c2lnbmF0dXJl");
+/// assert_eq!(
+///     redact_log_message("jwt eyJhbGciOi.eyJzdWIiOi.c2lnbmF0dXJl expired", "full"),
+///     "jwt [redacted-token] expired");
+/// ```
+///
+pub fn redact_log_message(msg: &str, mode: &str) -> String {
+    if mode == "off" {
+        return msg.to_string();
+    }
+    let redacted = redact_sensitive_values(msg);
+    let redacted = redact_tokens(&redacted);
+    redact_emails(&redacted, mode == "partial")
+}
+
+/// is_email_local_char
+///
+/// Characters allowed before the ``@`` in an email
+///
+fn is_email_local_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "._%+-".contains(c)
+}
+
+/// is_email_domain_char
+///
+/// Characters allowed after the ``@`` in an email
+///
+fn is_email_domain_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '.' || c == '-'
+}
+
+/// redact_emails
+///
+/// Replace emails with ``[redacted-email]`` or mask all but
+/// the first character before the ``@``
+///
+fn redact_emails(msg: &str, partial: bool) -> String {
+    let chars: Vec<char> = msg.chars().collect();
+    let mut out = String::with_capacity(msg.len());
+    let mut copied = 0;
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] != '@' {
+            i += 1;
+            continue;
+        }
+        let mut start = i;
+        while start > copied && is_email_local_char(chars[start - 1]) {
+            start -= 1;
+        }
+        let mut end = i + 1;
+        while end < chars.len() && is_email_domain_char(chars[end]) {
+            end += 1;
+        }
+        // trailing dots end a sentence, not the domain
+        while end > i + 1 && chars[end - 1] == '.' {
+            end -= 1;
+        }
+        let domain: String = chars[i + 1..end].iter().collect();
+        let is_email = start < i
+            && domain.contains('.')
+            && !domain.starts_with('.')
+            && !domain.ends_with('.');
+        if !is_email {
+            i += 1;
+            continue;
+        }
+        out.extend(&chars[copied..start]);
+        match partial {
+            true => out.push_str(&format!("{}***@{domain}", chars[start])),
+            false => out.push_str("[redacted-email]"),
+        }
+        copied = end;
+        i = end;
+    }
+    out.extend(&chars[copied..]);
+    out
+}
+
+/// redact_tokens
+///
+/// Replace json web tokens (three base64url parts starting
+/// with ``eyJ``) with ``[redacted-token]``
+///
+fn redact_tokens(msg: &str) -> String {
+    let is_token_char =
+        |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+    let chars: Vec<char> = msg.chars().collect();
+    let mut out = String::with_capacity(msg.len());
+    let mut i = 0;
+    while i < chars.len() {
+        let at_word_start = i == 0 || !is_token_char(chars[i - 1]);
+        if at_word_start && chars[i..].starts_with(&['e', 'y', 'J']) {
+            let mut end = i;
+            let mut dots = 0;
+            while end < chars.len()
+                && (is_token_char(chars[end]) || chars[end] == '.')
+            {
+                if chars[end] == '.' {
+                    dots += 1;
+                }
+                end += 1;
+            }
+            if dots >= 2 {
+                out.push_str("[redacted-token]");
+                i = end;
+                continue;
+            }
+        }
+        out.push(chars[i]);
+        i += 1;
+    }
+    out
+}
+
+/// is_sensitive_key
+///
+/// Check if a key name (or its last ``_``-separated part)
+/// is a [`SENSITIVE_LOG_KEYS`](crate::monitoring::log_redaction::SENSITIVE_LOG_KEYS)
+/// value
+///
+fn is_sensitive_key(key: &str) -> bool {
+    let key = key.to_lowercase();
+    let last = key.rsplit(['_', '-', '.']).next().unwrap_or("");
+    SENSITIVE_LOG_KEYS.contains(&key.as_str())
+        || SENSITIVE_LOG_KEYS.contains(&last)
+}
+
+/// redact_sensitive_values
+///
+/// Replace the values after sensitive keys in
+/// ``key=value``, ``key: value`` and ``"key": "value"``
+/// pairs with ``[redacted]``
+///
+fn redact_sensitive_values(msg: &str) -> String {
+    let is_key_char = |c: char| c.is_ascii_alphanumeric() || "_-.".contains(c);
+    let chars: Vec<char> = msg.chars().collect();
+    let mut out = String::with_capacity(msg.len());
+    let mut copied = 0;
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] != '=' && chars[i] != ':' {
+            i += 1;
+            continue;
+        }
+        // find the key before the separator
+        let mut key_end = i;
+        if key_end > 0
+            && (chars[key_end - 1] == '"' || chars[key_end - 1] == '\'')
+        {
+            key_end -= 1;
+        }
+        let mut key_start = key_end;
+        while key_start > 0 && is_key_char(chars[key_start - 1]) {
+            key_start -= 1;
+        }
+        let key: String = chars[key_start..key_end].iter().collect();
+        if key.is_empty() || !is_sensitive_key(&key) {
+            i += 1;
+            continue;
+        }
+        // find the value after the separator
+        let mut value_start = i + 1;
+        while value_start < chars.len() && chars[value_start] == ' ' {
+            value_start += 1;
+        }
+        let quote = match chars.get(value_start) {
+            Some('"') => Some('"'),
+            Some('\'') => Some('\''),
+            _ => None,
+        };
+        let value_end = match quote {
+            Some(quote) => {
+                value_start += 1;
+                let mut end = value_start;
+                while end < chars.len() && chars[end] != quote {
+                    if chars[end] == '\\' {
+                        end += 1;
+                    }
+                    end += 1;
+                }
+                end.min(chars.len())
+            }
+            None => {
+                let mut end = value_start;
+                while end < chars.len()
+                    && !chars[end].is_whitespace()
+                    && !",;&)]}".contains(chars[end])
+                {
+                    end += 1;
+                }
+                end
+            }
+        };
+        if value_end == value_start {
+            i += 1;
+            continue;
+        }
+        out.extend(&chars[copied..value_start]);
+        out.push_str("[redacted]");
+        copied = value_end;
+        i = value_end;
+    }
+    out.extend(&chars[copied..]);
+    out
+}
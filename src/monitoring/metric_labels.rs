@@ -0,0 +1,106 @@
+//! Keep prometheus label values from growing without bound
+//!
+//! Every prometheus label value creates a new time series,
+//! so request data (user ids, emails, paths, request ids)
+//! must never become a label value. The http metrics only
+//! use the static route labels, and values that come from
+//! the environment or the db (like kafka topics) pass through
+//! [`get_metric_label`](crate::monitoring::metric_labels::get_metric_label)
+//! which caps the distinct values for each metric. The only
+//! user ids recorded as label values are the file owners on
+//! the download egress counters, which also pass through the
+//! guard (the exact per-user totals are in the
+//! ``users_data_access`` table).
+//!
+//! ## Supported Environment Variables
+//!
+//! ```bash
+//! # distinct values for each guarded label before new
+//! # values are recorded as "other"
+//! export METRICS_MAX_LABEL_VALUES="100"
+//! ```
+//!
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+
+use crate::monitoring::metric_types::register_int_counter_vec;
+use crate::monitoring::metric_types::IntCounterVec;
+
+/// label value used for values the guard rejects
+pub const METRIC_LABEL_OTHER: &str = "other";
+
+/// max characters in a guarded label value
+pub const METRIC_LABEL_MAX_LEN: usize = 64;
+
+lazy_static! {
+    pub static ref METRIC_LABELS_REJECTED_COUNTER_VEC: IntCounterVec =
+        register_int_counter_vec!(
+            "metric_label_values_rejected_total",
+            "Number of label values recorded as \"other\" by metric \
+            and reason.",
+            &["metric", "reason"]
+        )
+        .unwrap();
+    static ref METRIC_MAX_LABEL_VALUES: usize =
+        std::env::var("METRICS_MAX_LABEL_VALUES")
+            .unwrap_or_else(|_| "100".to_string())
+            .parse::<usize>()
+            .unwrap_or(100);
+    static ref METRIC_LABEL_VALUES: Mutex<HashMap<&'static str, HashSet<String>>> =
+        Mutex::new(HashMap::new());
+}
+
+/// get_metric_label
+///
+/// Get a safe label value for a metric. Values longer than
+/// ``METRIC_LABEL_MAX_LEN``, values with characters outside
+/// of ``a-z``, ``A-Z``, ``0-9``, ``_``, ``-``, ``.`` and ``:``
+/// and new values once the metric has
+/// ``METRICS_MAX_LABEL_VALUES`` distinct values are recorded
+/// as ``other``.
+///
+/// # Arguments
+///
+/// * `metric` - `&'static str` - metric name
+/// * `value` - `&str` - label value
+///
+/// # Returns
+///
+/// `String` - ``value`` or ``other``
+///
+/// # Examples
+///
+/// ```rust
+/// use restapi::monitoring::metric_labels::get_metric_label;
+/// assert_eq!(get_metric_label("doc_test_total", "user.events"), "user.events");
+/// assert_eq!(get_metric_label("doc_test_total", "a@b.com"), "other");
+/// ```
+///
+pub fn get_metric_label(metric: &'static str, value: &str) -> String {
+    let reason = if value.is_empty() || value.len() > METRIC_LABEL_MAX_LEN {
+        "length"
+    } else if !value
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "_-.:".contains(c))
+    {
+        "charset"
+    } else {
+        let mut label_values = METRIC_LABEL_VALUES.lock().unwrap();
+        let values = label_values.entry(metric).or_default();
+        if values.contains(value) {
+            return value.to_string();
+        }
+        if values.len() < *METRIC_MAX_LABEL_VALUES {
+            values.insert(value.to_string());
+            return value.to_string();
+        }
+        "cardinality"
+    };
+    METRIC_LABELS_REJECTED_COUNTER_VEC
+        .with_label_values(&[metric, reason])
+        .inc();
+    METRIC_LABEL_OTHER.to_string()
+}
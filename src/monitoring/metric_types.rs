@@ -0,0 +1,289 @@
+//! The prometheus metric types used by every module
+//!
+//! Built with the ``metrics`` feature (the default) these are
+//! the [prometheus](https://crates.io/crates/prometheus)
+//! types and registration macros. Without the ``metrics``
+//! feature these are no-op metrics that record nothing, so
+//! the server does not compile ``prometheus`` and
+//! ``GET /metrics`` is not routed.
+//!
+//! Modules import the metric types and ``register_*``
+//! macros from here instead of from ``prometheus``:
+//!
+//! ```rust
+//! use lazy_static::lazy_static;
+//! use restapi::monitoring::metric_types::IntCounterVec;
+//!
+//! lazy_static! {
+//!     static ref DOC_TEST_COUNTER_VEC: IntCounterVec =
+//!         restapi::monitoring::metric_types::register_int_counter_vec!(
+//!             "doc_test_metric_types_total",
+//!             "Number of doc tests by result.",
+//!             &["result"]
+//!         )
+//!         .unwrap();
+//! }
+//! DOC_TEST_COUNTER_VEC.with_label_values(&["ok"]).inc();
+//! ```
+//!
+#[cfg(feature = "metrics")]
+pub use prometheus::register_histogram_vec;
+#[cfg(feature = "metrics")]
+pub use prometheus::register_int_counter;
+#[cfg(feature = "metrics")]
+pub use prometheus::register_int_counter_vec;
+#[cfg(feature = "metrics")]
+pub use prometheus::register_int_gauge;
+#[cfg(feature = "metrics")]
+pub use prometheus::register_int_gauge_vec;
+#[cfg(feature = "metrics")]
+pub use prometheus::HistogramVec;
+#[cfg(feature = "metrics")]
+pub use prometheus::IntCounter;
+#[cfg(feature = "metrics")]
+pub use prometheus::IntCounterVec;
+#[cfg(feature = "metrics")]
+pub use prometheus::IntGauge;
+#[cfg(feature = "metrics")]
+pub use prometheus::IntGaugeVec;
+#[cfg(feature = "metrics")]
+pub use prometheus::DEFAULT_BUCKETS;
+
+/// default histogram buckets in seconds (the same buckets as
+/// ``prometheus::DEFAULT_BUCKETS``)
+#[cfg(not(feature = "metrics"))]
+pub const DEFAULT_BUCKETS: &[f64; 11] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// IntCounter
+///
+/// No-op counter used when restapi is built without the
+/// ``metrics`` feature
+///
+#[cfg(not(feature = "metrics"))]
+#[derive(Clone, Debug, Default)]
+pub struct IntCounter {}
+
+#[cfg(not(feature = "metrics"))]
+impl IntCounter {
+    /// inc
+    ///
+    /// Record nothing
+    ///
+    pub fn inc(&self) {}
+
+    /// inc_by
+    ///
+    /// Record nothing
+    ///
+    pub fn inc_by(&self, _v: u64) {}
+}
+
+/// IntGauge
+///
+/// No-op gauge used when restapi is built without the
+/// ``metrics`` feature
+///
+#[cfg(not(feature = "metrics"))]
+#[derive(Clone, Debug, Default)]
+pub struct IntGauge {}
+
+#[cfg(not(feature = "metrics"))]
+impl IntGauge {
+    /// inc
+    ///
+    /// Record nothing
+    ///
+    pub fn inc(&self) {}
+
+    /// dec
+    ///
+    /// Record nothing
+    ///
+    pub fn dec(&self) {}
+
+    /// set
+    ///
+    /// Record nothing
+    ///
+    pub fn set(&self, _v: i64) {}
+}
+
+/// Histogram
+///
+/// No-op histogram used when restapi is built without the
+/// ``metrics`` feature
+///
+#[cfg(not(feature = "metrics"))]
+#[derive(Clone, Debug, Default)]
+pub struct Histogram {}
+
+#[cfg(not(feature = "metrics"))]
+impl Histogram {
+    /// observe
+    ///
+    /// Record nothing
+    ///
+    pub fn observe(&self, _v: f64) {}
+}
+
+/// IntCounterVec
+///
+/// No-op labeled counters used when restapi is built without
+/// the ``metrics`` feature
+///
+#[cfg(not(feature = "metrics"))]
+#[derive(Clone, Debug, Default)]
+pub struct IntCounterVec {}
+
+#[cfg(not(feature = "metrics"))]
+impl IntCounterVec {
+    /// with_label_values
+    ///
+    /// Get a no-op [`IntCounter`](crate::monitoring::metric_types::IntCounter)
+    ///
+    pub fn with_label_values(&self, _vals: &[&str]) -> IntCounter {
+        IntCounter {}
+    }
+}
+
+/// IntGaugeVec
+///
+/// No-op labeled gauges used when restapi is built without
+/// the ``metrics`` feature
+///
+#[cfg(not(feature = "metrics"))]
+#[derive(Clone, Debug, Default)]
+pub struct IntGaugeVec {}
+
+#[cfg(not(feature = "metrics"))]
+impl IntGaugeVec {
+    /// with_label_values
+    ///
+    /// Get a no-op [`IntGauge`](crate::monitoring::metric_types::IntGauge)
+    ///
+    pub fn with_label_values(&self, _vals: &[&str]) -> IntGauge {
+        IntGauge {}
+    }
+}
+
+/// HistogramVec
+///
+/// No-op labeled histograms used when restapi is built
+/// without the ``metrics`` feature
+///
+#[cfg(not(feature = "metrics"))]
+#[derive(Clone, Debug, Default)]
+pub struct HistogramVec {}
+
+#[cfg(not(feature = "metrics"))]
+impl HistogramVec {
+    /// with_label_values
+    ///
+    /// Get a no-op [`Histogram`](crate::monitoring::metric_types::Histogram)
+    ///
+    pub fn with_label_values(&self, _vals: &[&str]) -> Histogram {
+        Histogram {}
+    }
+}
+
+/// register_noop_metric
+///
+/// Build a no-op metric for the ``register_*`` macros when
+/// restapi is built without the ``metrics`` feature
+///
+/// # Returns
+///
+/// Ok(`T`) - always
+///
+/// # Errors
+///
+/// Never fails (the `Result` matches the ``prometheus``
+/// macros so callers can ``unwrap()``)
+///
+#[cfg(not(feature = "metrics"))]
+pub fn register_noop_metric<T: Default>() -> Result<T, String> {
+    Ok(T::default())
+}
+
+/// register_int_counter
+///
+/// Build a no-op [`IntCounter`](crate::monitoring::metric_types::IntCounter)
+///
+#[cfg(not(feature = "metrics"))]
+#[macro_export]
+macro_rules! register_int_counter {
+    ($($arg:tt)*) => {
+        $crate::monitoring::metric_types::register_noop_metric::<
+            $crate::monitoring::metric_types::IntCounter,
+        >()
+    };
+}
+
+/// register_int_counter_vec
+///
+/// Build a no-op [`IntCounterVec`](crate::monitoring::metric_types::IntCounterVec)
+///
+#[cfg(not(feature = "metrics"))]
+#[macro_export]
+macro_rules! register_int_counter_vec {
+    ($($arg:tt)*) => {
+        $crate::monitoring::metric_types::register_noop_metric::<
+            $crate::monitoring::metric_types::IntCounterVec,
+        >()
+    };
+}
+
+/// register_int_gauge
+///
+/// Build a no-op [`IntGauge`](crate::monitoring::metric_types::IntGauge)
+///
+#[cfg(not(feature = "metrics"))]
+#[macro_export]
+macro_rules! register_int_gauge {
+    ($($arg:tt)*) => {
+        $crate::monitoring::metric_types::register_noop_metric::<
+            $crate::monitoring::metric_types::IntGauge,
+        >()
+    };
+}
+
+/// register_int_gauge_vec
+///
+/// Build a no-op [`IntGaugeVec`](crate::monitoring::metric_types::IntGaugeVec)
+///
+#[cfg(not(feature = "metrics"))]
+#[macro_export]
+macro_rules! register_int_gauge_vec {
+    ($($arg:tt)*) => {
+        $crate::monitoring::metric_types::register_noop_metric::<
+            $crate::monitoring::metric_types::IntGaugeVec,
+        >()
+    };
+}
+
+/// register_histogram_vec
+///
+/// Build a no-op [`HistogramVec`](crate::monitoring::metric_types::HistogramVec)
+///
+#[cfg(not(feature = "metrics"))]
+#[macro_export]
+macro_rules! register_histogram_vec {
+    ($($arg:tt)*) => {
+        $crate::monitoring::metric_types::register_noop_metric::<
+            $crate::monitoring::metric_types::HistogramVec,
+        >()
+    };
+}
+
+#[cfg(not(feature = "metrics"))]
+pub use crate::register_histogram_vec;
+#[cfg(not(feature = "metrics"))]
+pub use crate::register_int_counter;
+#[cfg(not(feature = "metrics"))]
+pub use crate::register_int_counter_vec;
+#[cfg(not(feature = "metrics"))]
+pub use crate::register_int_gauge;
+#[cfg(not(feature = "metrics"))]
+pub use crate::register_int_gauge_vec;
@@ -1,47 +1,39 @@
 //! Monitor the hyper server with custom prometheus metrics
 //!
+//! Each route records its static ``resource`` and ``method``
+//! labels before and after its handler runs. The request
+//! latency, in-flight gauge and request id exemplars are
+//! tracked in
+//! [`request_metrics`](crate::monitoring::request_metrics).
+//!
+//! Without the ``metrics`` feature the route counters are
+//! not compiled, ``GET /metrics`` is not routed and the
+//! hooks only track the request's route labels (see
+//! [`metric_types`](crate::monitoring::metric_types)).
+//!
 use std::convert::Infallible;
 
 use lazy_static::lazy_static;
+#[cfg(feature = "metrics")]
 use prometheus::*;
+#[cfg(feature = "metrics")]
 use prometheus_static_metric::auto_flush_from;
+#[cfg(feature = "metrics")]
 use prometheus_static_metric::make_auto_flush_static_metric;
 
 use hyper::Body;
 use hyper::Response;
+#[cfg(feature = "metrics")]
 use hyper::StatusCode;
 
-make_auto_flush_static_metric! {
-
-    pub label_enum HistogramLabelsAPI {
-        user,
-        auth,
-        data,
-        unknown,
-        unsupported,
-    }
-
-    pub label_enum HistogramMethodsAPI {
-        post,
-        get,
-        put,
-        delete,
-        search,
-        login,
-        create_verify,
-        consume_verify,
-        create_otp,
-        consume_otp,
-        upload,
-        unknown,
-        unsupported,
-    }
-
-    pub struct LhrsHistogram: LocalHistogram {
-        "resource" => HistogramLabelsAPI,
-        "method" => HistogramMethodsAPI,
-    }
-}
+use crate::monitoring::metric_types::register_histogram_vec;
+use crate::monitoring::metric_types::HistogramVec;
+#[cfg(feature = "metrics")]
+use crate::monitoring::openmetrics::encode_openmetrics;
+#[cfg(feature = "metrics")]
+use crate::monitoring::openmetrics::OPENMETRICS_CONTENT_TYPE;
+use crate::monitoring::request_metrics::finish_request_metrics;
+use crate::monitoring::request_metrics::start_request_metrics;
 
 lazy_static! {
     pub static ref HTTP_HISTO_VEC: HistogramVec =
@@ -55,24 +47,17 @@ lazy_static! {
         ).unwrap();
 }
 
-lazy_static! {
-    // You can also use default flush duration which is 60 seconds.
-    // pub static ref TLS_HTTP_HISTOGRAM: Lhrs = auto_flush_from!(HTTP_HISTO_VEC, Lhrs);
-    pub static ref TLS_HTTP_HISTOGRAM: LhrsHistogram =
-        auto_flush_from!(
-            HTTP_HISTO_VEC,
-            LhrsHistogram,
-            std::time::Duration::from_secs(60));
-}
-
 // Counter for Requests
 
+#[cfg(feature = "metrics")]
 make_auto_flush_static_metric! {
 
     pub label_enum CounterLabelsAPI {
         user,
         auth,
         data,
+        admin,
+        events,
         unknown,
     }
 
@@ -80,6 +65,7 @@ make_auto_flush_static_metric! {
         post,
         get,
         put,
+        patch,
         delete,
         search,
         login,
@@ -88,6 +74,14 @@ make_auto_flush_static_metric! {
         create_otp,
         consume_otp,
         upload,
+        passkey,
+        unlock,
+        invite,
+        keys,
+        retire,
+        webhooks,
+        kafka,
+        stats,
         unknown,
     }
 
@@ -97,6 +91,7 @@ make_auto_flush_static_metric! {
     }
 }
 
+#[cfg(feature = "metrics")]
 lazy_static! {
     pub static ref HTTP_COUNTER_VEC: IntCounterVec =
         register_int_counter_vec ! (
@@ -109,6 +104,7 @@ lazy_static! {
         ).unwrap();
 }
 
+#[cfg(feature = "metrics")]
 lazy_static! {
     // You can also use default flush duration which is 60 seconds.
     // pub static ref TLS_HTTP_COUNTER: Lhrs = auto_flush_from!(HTTP_COUNTER_VEC, Lhrs);
@@ -120,12 +116,15 @@ lazy_static! {
 
 // Counter for Requests by Success/Failure/StatusCode
 
+#[cfg(feature = "metrics")]
 make_auto_flush_static_metric! {
 
     pub label_enum CounterLabelsAPIStatusCode {
         user,
         auth,
         data,
+        admin,
+        events,
         unknown,
         unsupported,
     }
@@ -134,6 +133,7 @@ make_auto_flush_static_metric! {
         post,
         get,
         put,
+        patch,
         delete,
         search,
         login,
@@ -142,6 +142,14 @@ make_auto_flush_static_metric! {
         create_otp,
         consume_otp,
         upload,
+        passkey,
+        unlock,
+        invite,
+        keys,
+        retire,
+        webhooks,
+        kafka,
+        stats,
         unknown,
         unsupported,
     }
@@ -175,6 +183,7 @@ make_auto_flush_static_metric! {
     }
 }
 
+#[cfg(feature = "metrics")]
 lazy_static! {
     pub static ref HTTP_COUNTER_VEC_STATUS_CODE: IntCounterVec =
         register_int_counter_vec ! (
@@ -188,6 +197,7 @@ lazy_static! {
         ).unwrap();
 }
 
+#[cfg(feature = "metrics")]
 lazy_static! {
     // You can also use default flush duration which is 60 seconds.
     // pub static ref TLS_HTTP_COUNTER_STATUS_CODE: LhrsIntCounterStatusCode = auto_flush_from!(HTTP_COUNTER_VEC, Lhrs);
@@ -209,6 +219,7 @@ lazy_static! {
 /// use crate::monitoring::metrics::handle_showing_metrics;
 /// handle_showing_metrics();
 /// ```
+#[cfg(feature = "metrics")]
 pub fn handle_showing_metrics(
 ) -> std::result::Result<Response<Body>, Infallible> {
     let encoder = TextEncoder::new();
@@ -224,12 +235,33 @@ pub fn handle_showing_metrics(
     Ok(Response::new(Body::from(response)))
 }
 
+/// handle_showing_openmetrics
+///
+/// Host all the collected metrics in the OpenMetrics format
+/// with request id exemplars on the
+/// ``http_request_duration_seconds`` buckets
+/// (see [`openmetrics`](crate::monitoring::openmetrics)).
+/// ``GET /metrics`` uses this format when the scraper's
+/// ``Accept`` header asks for ``application/openmetrics-text``.
+///
+#[cfg(feature = "metrics")]
+pub fn handle_showing_openmetrics(
+) -> std::result::Result<Response<Body>, Infallible> {
+    Ok(Response::builder()
+        .header("Content-Type", OPENMETRICS_CONTENT_TYPE)
+        .body(Body::from(encode_openmetrics(&prometheus::gather())))
+        .unwrap())
+}
+
 /// record_monitoring_metrics_api_before
 ///
 /// This method records tracked metrics using
 /// [Prometheus](https://docs.rs/prometheus/latest/prometheus/)
 /// before the internal service handlers start processing the
-/// request.
+/// request. Supported routes also count the request in the
+/// ``http_route_requests_in_flight`` gauge and start its latency
+/// timer. Unsupported ``resource`` and ``method`` values are
+/// logged and never become label values.
 ///
 /// # Arguments
 ///
@@ -250,6 +282,7 @@ pub fn handle_showing_metrics(
 ///     "user",
 ///     "post");
 /// ```
+#[cfg(feature = "metrics")]
 pub fn record_monitoring_metrics_api_before(
     uri: &str,
     resource: &str,
@@ -264,73 +297,133 @@ pub fn record_monitoring_metrics_api_before(
     match (resource, method) {
         ("auth", "login") => {
             TLS_HTTP_COUNTER.auth.login.inc();
-            TLS_HTTP_HISTOGRAM.auth.login.observe(1.0);
+            start_request_metrics("auth", "login");
         }
         ("user", "post") => {
             TLS_HTTP_COUNTER.user.post.inc();
-            TLS_HTTP_HISTOGRAM.user.post.observe(1.0);
+            start_request_metrics("user", "post");
         }
         ("user", "delete") => {
             TLS_HTTP_COUNTER.user.delete.inc();
-            TLS_HTTP_HISTOGRAM.user.delete.observe(1.0);
+            start_request_metrics("user", "delete");
         }
         ("user", "put") => {
             TLS_HTTP_COUNTER.user.put.inc();
-            TLS_HTTP_HISTOGRAM.user.put.observe(1.0);
+            start_request_metrics("user", "put");
+        }
+        ("user", "patch") => {
+            TLS_HTTP_COUNTER.user.patch.inc();
+            start_request_metrics("user", "patch");
         }
         ("user", "get") => {
             TLS_HTTP_COUNTER.user.get.inc();
-            TLS_HTTP_HISTOGRAM.user.get.observe(1.0);
+            start_request_metrics("user", "get");
         }
         ("user", "search") => {
             TLS_HTTP_COUNTER.user.search.inc();
-            TLS_HTTP_HISTOGRAM.user.search.observe(1.0);
+            start_request_metrics("user", "search");
         }
         ("user", "create_otp") => {
             TLS_HTTP_COUNTER.user.create_otp.inc();
-            TLS_HTTP_HISTOGRAM.user.create_otp.observe(1.0);
+            start_request_metrics("user", "create_otp");
         }
         ("user", "consume_otp") => {
             TLS_HTTP_COUNTER.user.consume_otp.inc();
-            TLS_HTTP_HISTOGRAM.user.consume_otp.observe(1.0);
+            start_request_metrics("user", "consume_otp");
         }
         ("user", "consume_verify") => {
             TLS_HTTP_COUNTER.user.consume_verify.inc();
-            TLS_HTTP_HISTOGRAM.user.consume_verify.observe(1.0);
+            start_request_metrics("user", "consume_verify");
         }
         // end of user
         ("data", "post") => {
             TLS_HTTP_COUNTER.data.post.inc();
-            TLS_HTTP_HISTOGRAM.data.post.observe(1.0);
+            start_request_metrics("data", "post");
         }
         ("data", "delete") => {
             TLS_HTTP_COUNTER.data.delete.inc();
-            TLS_HTTP_HISTOGRAM.data.delete.observe(1.0);
+            start_request_metrics("data", "delete");
         }
         ("data", "put") => {
             TLS_HTTP_COUNTER.data.put.inc();
-            TLS_HTTP_HISTOGRAM.data.put.observe(1.0);
+            start_request_metrics("data", "put");
+        }
+        ("data", "patch") => {
+            TLS_HTTP_COUNTER.data.patch.inc();
+            start_request_metrics("data", "patch");
         }
         ("data", "get") => {
             TLS_HTTP_COUNTER.data.get.inc();
-            TLS_HTTP_HISTOGRAM.data.get.observe(1.0);
+            start_request_metrics("data", "get");
         }
         ("data", "search") => {
             TLS_HTTP_COUNTER.data.search.inc();
-            TLS_HTTP_HISTOGRAM.data.search.observe(1.0);
+            start_request_metrics("data", "search");
         }
         ("data", "upload") => {
             TLS_HTTP_COUNTER.data.upload.inc();
-            TLS_HTTP_HISTOGRAM.data.upload.observe(1.0);
+            start_request_metrics("data", "upload");
         }
         // end of data
+        ("auth", "passkey") => {
+            TLS_HTTP_COUNTER.auth.passkey.inc();
+            start_request_metrics("auth", "passkey");
+        }
+        ("auth", "get") => {
+            TLS_HTTP_COUNTER.auth.get.inc();
+            start_request_metrics("auth", "get");
+        }
+        ("admin", "unlock") => {
+            TLS_HTTP_COUNTER.admin.unlock.inc();
+            start_request_metrics("admin", "unlock");
+        }
+        ("admin", "get") => {
+            TLS_HTTP_COUNTER.admin.get.inc();
+            start_request_metrics("admin", "get");
+        }
+        ("admin", "put") => {
+            TLS_HTTP_COUNTER.admin.put.inc();
+            start_request_metrics("admin", "put");
+        }
+        ("admin", "invite") => {
+            TLS_HTTP_COUNTER.admin.invite.inc();
+            start_request_metrics("admin", "invite");
+        }
+        ("user", "invite") => {
+            TLS_HTTP_COUNTER.user.invite.inc();
+            start_request_metrics("user", "invite");
+        }
+        ("events", "get") => {
+            TLS_HTTP_COUNTER.events.get.inc();
+            start_request_metrics("events", "get");
+        }
+        ("admin", "keys") => {
+            TLS_HTTP_COUNTER.admin.keys.inc();
+            start_request_metrics("admin", "keys");
+        }
+        ("admin", "retire") => {
+            TLS_HTTP_COUNTER.admin.retire.inc();
+            start_request_metrics("admin", "retire");
+        }
+        ("admin", "webhooks") => {
+            TLS_HTTP_COUNTER.admin.webhooks.inc();
+            start_request_metrics("admin", "webhooks");
+        }
+        ("admin", "kafka") => {
+            TLS_HTTP_COUNTER.admin.kafka.inc();
+            start_request_metrics("admin", "kafka");
+        }
+        ("admin", "stats") => {
+            TLS_HTTP_COUNTER.admin.stats.inc();
+            start_request_metrics("admin", "stats");
+        }
         ("unknown", "get") => {
             TLS_HTTP_COUNTER.unknown.get.inc();
-            TLS_HTTP_HISTOGRAM.unknown.get.observe(1.0);
+            start_request_metrics("unknown", "get");
         }
         ("unknown", "post") => {
             TLS_HTTP_COUNTER.unknown.post.inc();
-            TLS_HTTP_HISTOGRAM.unknown.post.observe(1.0);
+            start_request_metrics("unknown", "post");
         }
         // end of unknown
         (_, _) => {
@@ -349,7 +442,9 @@ pub fn record_monitoring_metrics_api_before(
 /// [Prometheus](https://docs.rs/prometheus/latest/prometheus/)
 /// after the internal service handlers processed the
 /// request. This allows for tracking latency and status codes
-/// for each resource and each method.
+/// for each resource and each method. The request leaves the
+/// ``http_route_requests_in_flight`` gauge and its latency is
+/// observed with a request id exemplar.
 ///
 /// # Arguments
 ///
@@ -380,12 +475,14 @@ pub fn record_monitoring_metrics_api_before(
 ///     "post",
 ///     processed_result);
 /// ```
+#[cfg(feature = "metrics")]
 pub fn record_monitoring_metrics_api_after(
     uri: &str,
     resource: &str,
     method: &str,
     processed_response: std::result::Result<Response<Body>, Infallible>,
 ) -> std::result::Result<Response<Body>, Infallible> {
+    finish_request_metrics();
     match processed_response {
         Ok(resp) => {
             match (resource, method) {
@@ -485,7 +582,7 @@ pub fn record_monitoring_metrics_api_after(
                                 .inc();
                         }
                     }
-                    TLS_HTTP_HISTOGRAM.auth.login.observe(1.0);
+                    start_request_metrics("auth", "login");
                 }
                 ("user", "post") => {
                     match resp.status() {
@@ -583,7 +680,7 @@ pub fn record_monitoring_metrics_api_after(
                                 .inc();
                         }
                     }
-                    TLS_HTTP_HISTOGRAM.user.post.observe(1.0);
+                    start_request_metrics("user", "post");
                 }
                 ("user", "delete") => {
                     match resp.status() {
@@ -601,6 +698,13 @@ pub fn record_monitoring_metrics_api_after(
                                 .http_201
                                 .inc();
                         }
+                        StatusCode::ACCEPTED => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .user
+                                .delete
+                                .http_202
+                                .inc();
+                        }
                         StatusCode::BAD_REQUEST => {
                             TLS_HTTP_COUNTER_STATUS_CODE
                                 .user
@@ -681,7 +785,7 @@ pub fn record_monitoring_metrics_api_after(
                                 .inc();
                         }
                     }
-                    TLS_HTTP_HISTOGRAM.user.delete.observe(1.0);
+                    start_request_metrics("user", "delete");
                 }
                 ("user", "put") => {
                     match resp.status() {
@@ -779,7 +883,105 @@ pub fn record_monitoring_metrics_api_after(
                                 .inc();
                         }
                     }
-                    TLS_HTTP_HISTOGRAM.user.put.observe(1.0);
+                    start_request_metrics("user", "put");
+                }
+                ("user", "patch") => {
+                    match resp.status() {
+                        StatusCode::OK => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .user
+                                .patch
+                                .http_200
+                                .inc();
+                        }
+                        StatusCode::CREATED => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .user
+                                .patch
+                                .http_201
+                                .inc();
+                        }
+                        StatusCode::BAD_REQUEST => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .user
+                                .patch
+                                .http_400
+                                .inc();
+                        }
+                        StatusCode::UNAUTHORIZED => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .user
+                                .patch
+                                .http_401
+                                .inc();
+                        }
+                        StatusCode::FORBIDDEN => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .user
+                                .patch
+                                .http_403
+                                .inc();
+                        }
+                        StatusCode::NOT_FOUND => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .user
+                                .patch
+                                .http_404
+                                .inc();
+                        }
+                        StatusCode::INTERNAL_SERVER_ERROR => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .user
+                                .patch
+                                .http_500
+                                .inc();
+                        }
+                        StatusCode::NOT_IMPLEMENTED => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .user
+                                .patch
+                                .http_501
+                                .inc();
+                        }
+                        StatusCode::BAD_GATEWAY => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .user
+                                .patch
+                                .http_502
+                                .inc();
+                        }
+                        StatusCode::SERVICE_UNAVAILABLE => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .user
+                                .patch
+                                .http_503
+                                .inc();
+                        }
+                        StatusCode::GATEWAY_TIMEOUT => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .user
+                                .patch
+                                .http_504
+                                .inc();
+                        }
+                        _ => {
+                            error!(
+                                "unsupported metric \
+                                resource={resource} \
+                                method={method} \
+                                result={:?} \
+                                status_code={:?}",
+                                resp,
+                                resp.status()
+                            );
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .user
+                                .patch
+                                .unsupported
+                                .inc();
+                        }
+                    }
+                    start_request_metrics("user", "patch");
                 }
                 ("user", "get") => {
                     match resp.status() {
@@ -877,7 +1079,7 @@ pub fn record_monitoring_metrics_api_after(
                                 .inc();
                         }
                     }
-                    TLS_HTTP_HISTOGRAM.user.get.observe(1.0);
+                    start_request_metrics("user", "get");
                 }
                 ("user", "search") => {
                     match resp.status() {
@@ -975,7 +1177,7 @@ pub fn record_monitoring_metrics_api_after(
                                 .inc();
                         }
                     }
-                    TLS_HTTP_HISTOGRAM.user.search.observe(1.0);
+                    start_request_metrics("user", "search");
                 }
                 ("user", "create_otp") => {
                     match resp.status() {
@@ -993,6 +1195,13 @@ pub fn record_monitoring_metrics_api_after(
                                 .http_201
                                 .inc();
                         }
+                        StatusCode::ACCEPTED => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .user
+                                .create_otp
+                                .http_202
+                                .inc();
+                        }
                         StatusCode::BAD_REQUEST => {
                             TLS_HTTP_COUNTER_STATUS_CODE
                                 .user
@@ -1073,7 +1282,7 @@ pub fn record_monitoring_metrics_api_after(
                                 .inc();
                         }
                     }
-                    TLS_HTTP_HISTOGRAM.user.create_otp.observe(1.0);
+                    start_request_metrics("user", "create_otp");
                 }
                 ("user", "consume_otp") => {
                     match resp.status() {
@@ -1171,7 +1380,7 @@ pub fn record_monitoring_metrics_api_after(
                                 .inc();
                         }
                     }
-                    TLS_HTTP_HISTOGRAM.user.consume_otp.observe(1.0);
+                    start_request_metrics("user", "consume_otp");
                 }
                 ("user", "consume_verify") => {
                     match resp.status() {
@@ -1269,7 +1478,7 @@ pub fn record_monitoring_metrics_api_after(
                                 .inc();
                         }
                     }
-                    TLS_HTTP_HISTOGRAM.user.consume_verify.observe(1.0);
+                    start_request_metrics("user", "consume_verify");
                 }
                 // end of user
                 ("data", "post") => {
@@ -1369,7 +1578,7 @@ pub fn record_monitoring_metrics_api_after(
                                 .inc();
                         }
                     }
-                    TLS_HTTP_HISTOGRAM.data.post.observe(1.0);
+                    start_request_metrics("data", "post");
                 }
                 ("data", "delete") => {
                     match resp.status() {
@@ -1467,7 +1676,7 @@ pub fn record_monitoring_metrics_api_after(
                                 .inc();
                         }
                     }
-                    TLS_HTTP_HISTOGRAM.data.delete.observe(1.0);
+                    start_request_metrics("data", "delete");
                 }
                 ("data", "put") => {
                     match resp.status() {
@@ -1565,7 +1774,105 @@ pub fn record_monitoring_metrics_api_after(
                                 .inc();
                         }
                     }
-                    TLS_HTTP_HISTOGRAM.data.put.observe(1.0);
+                    start_request_metrics("data", "put");
+                }
+                ("data", "patch") => {
+                    match resp.status() {
+                        StatusCode::OK => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .data
+                                .patch
+                                .http_200
+                                .inc();
+                        }
+                        StatusCode::CREATED => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .data
+                                .patch
+                                .http_201
+                                .inc();
+                        }
+                        StatusCode::BAD_REQUEST => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .data
+                                .patch
+                                .http_400
+                                .inc();
+                        }
+                        StatusCode::UNAUTHORIZED => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .data
+                                .patch
+                                .http_401
+                                .inc();
+                        }
+                        StatusCode::FORBIDDEN => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .data
+                                .patch
+                                .http_403
+                                .inc();
+                        }
+                        StatusCode::NOT_FOUND => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .data
+                                .patch
+                                .http_404
+                                .inc();
+                        }
+                        StatusCode::INTERNAL_SERVER_ERROR => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .data
+                                .patch
+                                .http_500
+                                .inc();
+                        }
+                        StatusCode::NOT_IMPLEMENTED => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .data
+                                .patch
+                                .http_501
+                                .inc();
+                        }
+                        StatusCode::BAD_GATEWAY => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .data
+                                .patch
+                                .http_502
+                                .inc();
+                        }
+                        StatusCode::SERVICE_UNAVAILABLE => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .data
+                                .patch
+                                .http_503
+                                .inc();
+                        }
+                        StatusCode::GATEWAY_TIMEOUT => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .data
+                                .patch
+                                .http_504
+                                .inc();
+                        }
+                        _ => {
+                            error!(
+                                "unsupported metric \
+                                resource={resource} \
+                                method={method} \
+                                result={:?} \
+                                status_code={:?}",
+                                resp,
+                                resp.status()
+                            );
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .data
+                                .patch
+                                .unsupported
+                                .inc();
+                        }
+                    }
+                    start_request_metrics("data", "patch");
                 }
                 ("data", "get") => {
                     match resp.status() {
@@ -1663,7 +1970,7 @@ pub fn record_monitoring_metrics_api_after(
                                 .inc();
                         }
                     }
-                    TLS_HTTP_HISTOGRAM.data.get.observe(1.0);
+                    start_request_metrics("data", "get");
                 }
                 ("data", "search") => {
                     match resp.status() {
@@ -1761,7 +2068,7 @@ pub fn record_monitoring_metrics_api_after(
                                 .inc();
                         }
                     }
-                    TLS_HTTP_HISTOGRAM.data.search.observe(1.0);
+                    start_request_metrics("data", "search");
                 }
                 ("data", "upload") => {
                     match resp.status() {
@@ -1859,70 +2166,1344 @@ pub fn record_monitoring_metrics_api_after(
                                 .inc();
                         }
                     }
-                    TLS_HTTP_HISTOGRAM.data.upload.observe(1.0);
+                    start_request_metrics("data", "upload");
                 }
                 // end of data
-                ("unknown", "get") => {
+                ("auth", "passkey") => {
                     match resp.status() {
                         StatusCode::OK => {
                             TLS_HTTP_COUNTER_STATUS_CODE
-                                .unknown
-                                .get
+                                .auth
+                                .passkey
                                 .http_200
                                 .inc();
                         }
                         StatusCode::CREATED => {
                             TLS_HTTP_COUNTER_STATUS_CODE
-                                .unknown
-                                .get
+                                .auth
+                                .passkey
                                 .http_201
                                 .inc();
                         }
                         StatusCode::BAD_REQUEST => {
                             TLS_HTTP_COUNTER_STATUS_CODE
-                                .unknown
-                                .get
+                                .auth
+                                .passkey
                                 .http_400
                                 .inc();
                         }
                         StatusCode::UNAUTHORIZED => {
                             TLS_HTTP_COUNTER_STATUS_CODE
-                                .unknown
-                                .get
+                                .auth
+                                .passkey
                                 .http_401
                                 .inc();
                         }
                         StatusCode::FORBIDDEN => {
                             TLS_HTTP_COUNTER_STATUS_CODE
-                                .unknown
-                                .get
+                                .auth
+                                .passkey
                                 .http_403
                                 .inc();
                         }
                         StatusCode::NOT_FOUND => {
                             TLS_HTTP_COUNTER_STATUS_CODE
-                                .unknown
-                                .get
+                                .auth
+                                .passkey
                                 .http_404
                                 .inc();
                         }
                         StatusCode::INTERNAL_SERVER_ERROR => {
                             TLS_HTTP_COUNTER_STATUS_CODE
-                                .unknown
-                                .get
+                                .auth
+                                .passkey
                                 .http_500
                                 .inc();
                         }
                         StatusCode::NOT_IMPLEMENTED => {
                             TLS_HTTP_COUNTER_STATUS_CODE
-                                .unknown
-                                .get
+                                .auth
+                                .passkey
                                 .http_501
                                 .inc();
                         }
                         StatusCode::BAD_GATEWAY => {
                             TLS_HTTP_COUNTER_STATUS_CODE
-                                .unknown
+                                .auth
+                                .passkey
+                                .http_502
+                                .inc();
+                        }
+                        StatusCode::SERVICE_UNAVAILABLE => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .auth
+                                .passkey
+                                .http_503
+                                .inc();
+                        }
+                        StatusCode::GATEWAY_TIMEOUT => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .auth
+                                .passkey
+                                .http_504
+                                .inc();
+                        }
+                        _ => {
+                            error!(
+                                "unsupported metric \
+                                resource={resource} \
+                                method={method} \
+                                result={:?} \
+                                status_code={:?}",
+                                resp,
+                                resp.status()
+                            );
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .auth
+                                .passkey
+                                .unsupported
+                                .inc();
+                        }
+                    }
+                    start_request_metrics("auth", "passkey");
+                }
+                ("auth", "get") => {
+                    match resp.status() {
+                        StatusCode::OK => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .auth
+                                .get
+                                .http_200
+                                .inc();
+                        }
+                        StatusCode::CREATED => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .auth
+                                .get
+                                .http_201
+                                .inc();
+                        }
+                        StatusCode::BAD_REQUEST => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .auth
+                                .get
+                                .http_400
+                                .inc();
+                        }
+                        StatusCode::UNAUTHORIZED => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .auth
+                                .get
+                                .http_401
+                                .inc();
+                        }
+                        StatusCode::FORBIDDEN => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .auth
+                                .get
+                                .http_403
+                                .inc();
+                        }
+                        StatusCode::NOT_FOUND => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .auth
+                                .get
+                                .http_404
+                                .inc();
+                        }
+                        StatusCode::INTERNAL_SERVER_ERROR => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .auth
+                                .get
+                                .http_500
+                                .inc();
+                        }
+                        StatusCode::NOT_IMPLEMENTED => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .auth
+                                .get
+                                .http_501
+                                .inc();
+                        }
+                        StatusCode::BAD_GATEWAY => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .auth
+                                .get
+                                .http_502
+                                .inc();
+                        }
+                        StatusCode::SERVICE_UNAVAILABLE => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .auth
+                                .get
+                                .http_503
+                                .inc();
+                        }
+                        StatusCode::GATEWAY_TIMEOUT => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .auth
+                                .get
+                                .http_504
+                                .inc();
+                        }
+                        _ => {
+                            error!(
+                                "unsupported metric \
+                                resource={resource} \
+                                method={method} \
+                                result={:?} \
+                                status_code={:?}",
+                                resp,
+                                resp.status()
+                            );
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .auth
+                                .get
+                                .unsupported
+                                .inc();
+                        }
+                    }
+                    start_request_metrics("auth", "get");
+                }
+                ("admin", "unlock") => {
+                    match resp.status() {
+                        StatusCode::OK => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .unlock
+                                .http_200
+                                .inc();
+                        }
+                        StatusCode::CREATED => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .unlock
+                                .http_201
+                                .inc();
+                        }
+                        StatusCode::BAD_REQUEST => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .unlock
+                                .http_400
+                                .inc();
+                        }
+                        StatusCode::UNAUTHORIZED => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .unlock
+                                .http_401
+                                .inc();
+                        }
+                        StatusCode::FORBIDDEN => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .unlock
+                                .http_403
+                                .inc();
+                        }
+                        StatusCode::NOT_FOUND => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .unlock
+                                .http_404
+                                .inc();
+                        }
+                        StatusCode::INTERNAL_SERVER_ERROR => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .unlock
+                                .http_500
+                                .inc();
+                        }
+                        StatusCode::NOT_IMPLEMENTED => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .unlock
+                                .http_501
+                                .inc();
+                        }
+                        StatusCode::BAD_GATEWAY => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .unlock
+                                .http_502
+                                .inc();
+                        }
+                        StatusCode::SERVICE_UNAVAILABLE => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .unlock
+                                .http_503
+                                .inc();
+                        }
+                        StatusCode::GATEWAY_TIMEOUT => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .unlock
+                                .http_504
+                                .inc();
+                        }
+                        _ => {
+                            error!(
+                                "unsupported metric \
+                                resource={resource} \
+                                method={method} \
+                                result={:?} \
+                                status_code={:?}",
+                                resp,
+                                resp.status()
+                            );
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .unlock
+                                .unsupported
+                                .inc();
+                        }
+                    }
+                    start_request_metrics("admin", "unlock");
+                }
+                ("admin", "get") => {
+                    match resp.status() {
+                        StatusCode::OK => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .get
+                                .http_200
+                                .inc();
+                        }
+                        StatusCode::CREATED => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .get
+                                .http_201
+                                .inc();
+                        }
+                        StatusCode::BAD_REQUEST => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .get
+                                .http_400
+                                .inc();
+                        }
+                        StatusCode::UNAUTHORIZED => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .get
+                                .http_401
+                                .inc();
+                        }
+                        StatusCode::FORBIDDEN => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .get
+                                .http_403
+                                .inc();
+                        }
+                        StatusCode::NOT_FOUND => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .get
+                                .http_404
+                                .inc();
+                        }
+                        StatusCode::INTERNAL_SERVER_ERROR => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .get
+                                .http_500
+                                .inc();
+                        }
+                        StatusCode::NOT_IMPLEMENTED => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .get
+                                .http_501
+                                .inc();
+                        }
+                        StatusCode::BAD_GATEWAY => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .get
+                                .http_502
+                                .inc();
+                        }
+                        StatusCode::SERVICE_UNAVAILABLE => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .get
+                                .http_503
+                                .inc();
+                        }
+                        StatusCode::GATEWAY_TIMEOUT => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .get
+                                .http_504
+                                .inc();
+                        }
+                        _ => {
+                            error!(
+                                "unsupported metric \
+                                resource={resource} \
+                                method={method} \
+                                result={:?} \
+                                status_code={:?}",
+                                resp,
+                                resp.status()
+                            );
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .get
+                                .unsupported
+                                .inc();
+                        }
+                    }
+                    start_request_metrics("admin", "get");
+                }
+                ("admin", "put") => {
+                    match resp.status() {
+                        StatusCode::OK => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .put
+                                .http_200
+                                .inc();
+                        }
+                        StatusCode::CREATED => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .put
+                                .http_201
+                                .inc();
+                        }
+                        StatusCode::BAD_REQUEST => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .put
+                                .http_400
+                                .inc();
+                        }
+                        StatusCode::UNAUTHORIZED => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .put
+                                .http_401
+                                .inc();
+                        }
+                        StatusCode::FORBIDDEN => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .put
+                                .http_403
+                                .inc();
+                        }
+                        StatusCode::NOT_FOUND => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .put
+                                .http_404
+                                .inc();
+                        }
+                        StatusCode::INTERNAL_SERVER_ERROR => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .put
+                                .http_500
+                                .inc();
+                        }
+                        StatusCode::NOT_IMPLEMENTED => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .put
+                                .http_501
+                                .inc();
+                        }
+                        StatusCode::BAD_GATEWAY => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .put
+                                .http_502
+                                .inc();
+                        }
+                        StatusCode::SERVICE_UNAVAILABLE => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .put
+                                .http_503
+                                .inc();
+                        }
+                        StatusCode::GATEWAY_TIMEOUT => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .put
+                                .http_504
+                                .inc();
+                        }
+                        _ => {
+                            error!(
+                                "unsupported metric \
+                                resource={resource} \
+                                method={method} \
+                                result={:?} \
+                                status_code={:?}",
+                                resp,
+                                resp.status()
+                            );
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .put
+                                .unsupported
+                                .inc();
+                        }
+                    }
+                    start_request_metrics("admin", "put");
+                }
+                ("admin", "invite") => {
+                    match resp.status() {
+                        StatusCode::OK => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .invite
+                                .http_200
+                                .inc();
+                        }
+                        StatusCode::CREATED => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .invite
+                                .http_201
+                                .inc();
+                        }
+                        StatusCode::BAD_REQUEST => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .invite
+                                .http_400
+                                .inc();
+                        }
+                        StatusCode::UNAUTHORIZED => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .invite
+                                .http_401
+                                .inc();
+                        }
+                        StatusCode::FORBIDDEN => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .invite
+                                .http_403
+                                .inc();
+                        }
+                        StatusCode::NOT_FOUND => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .invite
+                                .http_404
+                                .inc();
+                        }
+                        StatusCode::INTERNAL_SERVER_ERROR => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .invite
+                                .http_500
+                                .inc();
+                        }
+                        StatusCode::NOT_IMPLEMENTED => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .invite
+                                .http_501
+                                .inc();
+                        }
+                        StatusCode::BAD_GATEWAY => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .invite
+                                .http_502
+                                .inc();
+                        }
+                        StatusCode::SERVICE_UNAVAILABLE => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .invite
+                                .http_503
+                                .inc();
+                        }
+                        StatusCode::GATEWAY_TIMEOUT => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .invite
+                                .http_504
+                                .inc();
+                        }
+                        _ => {
+                            error!(
+                                "unsupported metric \
+                                resource={resource} \
+                                method={method} \
+                                result={:?} \
+                                status_code={:?}",
+                                resp,
+                                resp.status()
+                            );
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .invite
+                                .unsupported
+                                .inc();
+                        }
+                    }
+                    start_request_metrics("admin", "invite");
+                }
+                ("user", "invite") => {
+                    match resp.status() {
+                        StatusCode::OK => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .user
+                                .invite
+                                .http_200
+                                .inc();
+                        }
+                        StatusCode::CREATED => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .user
+                                .invite
+                                .http_201
+                                .inc();
+                        }
+                        StatusCode::BAD_REQUEST => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .user
+                                .invite
+                                .http_400
+                                .inc();
+                        }
+                        StatusCode::UNAUTHORIZED => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .user
+                                .invite
+                                .http_401
+                                .inc();
+                        }
+                        StatusCode::FORBIDDEN => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .user
+                                .invite
+                                .http_403
+                                .inc();
+                        }
+                        StatusCode::NOT_FOUND => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .user
+                                .invite
+                                .http_404
+                                .inc();
+                        }
+                        StatusCode::INTERNAL_SERVER_ERROR => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .user
+                                .invite
+                                .http_500
+                                .inc();
+                        }
+                        StatusCode::NOT_IMPLEMENTED => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .user
+                                .invite
+                                .http_501
+                                .inc();
+                        }
+                        StatusCode::BAD_GATEWAY => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .user
+                                .invite
+                                .http_502
+                                .inc();
+                        }
+                        StatusCode::SERVICE_UNAVAILABLE => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .user
+                                .invite
+                                .http_503
+                                .inc();
+                        }
+                        StatusCode::GATEWAY_TIMEOUT => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .user
+                                .invite
+                                .http_504
+                                .inc();
+                        }
+                        _ => {
+                            error!(
+                                "unsupported metric \
+                                resource={resource} \
+                                method={method} \
+                                result={:?} \
+                                status_code={:?}",
+                                resp,
+                                resp.status()
+                            );
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .user
+                                .invite
+                                .unsupported
+                                .inc();
+                        }
+                    }
+                    start_request_metrics("user", "invite");
+                }
+                ("events", "get") => {
+                    match resp.status() {
+                        StatusCode::OK => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .events
+                                .get
+                                .http_200
+                                .inc();
+                        }
+                        StatusCode::CREATED => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .events
+                                .get
+                                .http_201
+                                .inc();
+                        }
+                        StatusCode::BAD_REQUEST => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .events
+                                .get
+                                .http_400
+                                .inc();
+                        }
+                        StatusCode::UNAUTHORIZED => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .events
+                                .get
+                                .http_401
+                                .inc();
+                        }
+                        StatusCode::FORBIDDEN => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .events
+                                .get
+                                .http_403
+                                .inc();
+                        }
+                        StatusCode::NOT_FOUND => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .events
+                                .get
+                                .http_404
+                                .inc();
+                        }
+                        StatusCode::INTERNAL_SERVER_ERROR => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .events
+                                .get
+                                .http_500
+                                .inc();
+                        }
+                        StatusCode::NOT_IMPLEMENTED => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .events
+                                .get
+                                .http_501
+                                .inc();
+                        }
+                        StatusCode::BAD_GATEWAY => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .events
+                                .get
+                                .http_502
+                                .inc();
+                        }
+                        StatusCode::SERVICE_UNAVAILABLE => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .events
+                                .get
+                                .http_503
+                                .inc();
+                        }
+                        StatusCode::GATEWAY_TIMEOUT => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .events
+                                .get
+                                .http_504
+                                .inc();
+                        }
+                        _ => {
+                            error!(
+                                "unsupported metric \
+                                resource={resource} \
+                                method={method} \
+                                result={:?} \
+                                status_code={:?}",
+                                resp,
+                                resp.status()
+                            );
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .events
+                                .get
+                                .unsupported
+                                .inc();
+                        }
+                    }
+                    start_request_metrics("events", "get");
+                }
+                ("admin", "keys") => {
+                    match resp.status() {
+                        StatusCode::OK => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .keys
+                                .http_200
+                                .inc();
+                        }
+                        StatusCode::CREATED => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .keys
+                                .http_201
+                                .inc();
+                        }
+                        StatusCode::BAD_REQUEST => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .keys
+                                .http_400
+                                .inc();
+                        }
+                        StatusCode::UNAUTHORIZED => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .keys
+                                .http_401
+                                .inc();
+                        }
+                        StatusCode::FORBIDDEN => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .keys
+                                .http_403
+                                .inc();
+                        }
+                        StatusCode::NOT_FOUND => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .keys
+                                .http_404
+                                .inc();
+                        }
+                        StatusCode::INTERNAL_SERVER_ERROR => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .keys
+                                .http_500
+                                .inc();
+                        }
+                        StatusCode::NOT_IMPLEMENTED => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .keys
+                                .http_501
+                                .inc();
+                        }
+                        StatusCode::BAD_GATEWAY => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .keys
+                                .http_502
+                                .inc();
+                        }
+                        StatusCode::SERVICE_UNAVAILABLE => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .keys
+                                .http_503
+                                .inc();
+                        }
+                        StatusCode::GATEWAY_TIMEOUT => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .keys
+                                .http_504
+                                .inc();
+                        }
+                        _ => {
+                            error!(
+                                "unsupported metric \
+                                resource={resource} \
+                                method={method} \
+                                result={:?} \
+                                status_code={:?}",
+                                resp,
+                                resp.status()
+                            );
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .keys
+                                .unsupported
+                                .inc();
+                        }
+                    }
+                    start_request_metrics("admin", "keys");
+                }
+                ("admin", "retire") => {
+                    match resp.status() {
+                        StatusCode::OK => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .retire
+                                .http_200
+                                .inc();
+                        }
+                        StatusCode::CREATED => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .retire
+                                .http_201
+                                .inc();
+                        }
+                        StatusCode::BAD_REQUEST => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .retire
+                                .http_400
+                                .inc();
+                        }
+                        StatusCode::UNAUTHORIZED => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .retire
+                                .http_401
+                                .inc();
+                        }
+                        StatusCode::FORBIDDEN => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .retire
+                                .http_403
+                                .inc();
+                        }
+                        StatusCode::NOT_FOUND => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .retire
+                                .http_404
+                                .inc();
+                        }
+                        StatusCode::INTERNAL_SERVER_ERROR => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .retire
+                                .http_500
+                                .inc();
+                        }
+                        StatusCode::NOT_IMPLEMENTED => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .retire
+                                .http_501
+                                .inc();
+                        }
+                        StatusCode::BAD_GATEWAY => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .retire
+                                .http_502
+                                .inc();
+                        }
+                        StatusCode::SERVICE_UNAVAILABLE => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .retire
+                                .http_503
+                                .inc();
+                        }
+                        StatusCode::GATEWAY_TIMEOUT => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .retire
+                                .http_504
+                                .inc();
+                        }
+                        _ => {
+                            error!(
+                                "unsupported metric \
+                                resource={resource} \
+                                method={method} \
+                                result={:?} \
+                                status_code={:?}",
+                                resp,
+                                resp.status()
+                            );
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .retire
+                                .unsupported
+                                .inc();
+                        }
+                    }
+                    start_request_metrics("admin", "retire");
+                }
+                ("admin", "webhooks") => {
+                    match resp.status() {
+                        StatusCode::OK => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .webhooks
+                                .http_200
+                                .inc();
+                        }
+                        StatusCode::CREATED => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .webhooks
+                                .http_201
+                                .inc();
+                        }
+                        StatusCode::BAD_REQUEST => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .webhooks
+                                .http_400
+                                .inc();
+                        }
+                        StatusCode::UNAUTHORIZED => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .webhooks
+                                .http_401
+                                .inc();
+                        }
+                        StatusCode::FORBIDDEN => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .webhooks
+                                .http_403
+                                .inc();
+                        }
+                        StatusCode::NOT_FOUND => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .webhooks
+                                .http_404
+                                .inc();
+                        }
+                        StatusCode::INTERNAL_SERVER_ERROR => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .webhooks
+                                .http_500
+                                .inc();
+                        }
+                        StatusCode::NOT_IMPLEMENTED => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .webhooks
+                                .http_501
+                                .inc();
+                        }
+                        StatusCode::BAD_GATEWAY => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .webhooks
+                                .http_502
+                                .inc();
+                        }
+                        StatusCode::SERVICE_UNAVAILABLE => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .webhooks
+                                .http_503
+                                .inc();
+                        }
+                        StatusCode::GATEWAY_TIMEOUT => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .webhooks
+                                .http_504
+                                .inc();
+                        }
+                        _ => {
+                            error!(
+                                "unsupported metric \
+                                resource={resource} \
+                                method={method} \
+                                result={:?} \
+                                status_code={:?}",
+                                resp,
+                                resp.status()
+                            );
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .webhooks
+                                .unsupported
+                                .inc();
+                        }
+                    }
+                    start_request_metrics("admin", "webhooks");
+                }
+                ("admin", "kafka") => {
+                    match resp.status() {
+                        StatusCode::OK => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .kafka
+                                .http_200
+                                .inc();
+                        }
+                        StatusCode::CREATED => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .kafka
+                                .http_201
+                                .inc();
+                        }
+                        StatusCode::BAD_REQUEST => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .kafka
+                                .http_400
+                                .inc();
+                        }
+                        StatusCode::UNAUTHORIZED => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .kafka
+                                .http_401
+                                .inc();
+                        }
+                        StatusCode::FORBIDDEN => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .kafka
+                                .http_403
+                                .inc();
+                        }
+                        StatusCode::NOT_FOUND => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .kafka
+                                .http_404
+                                .inc();
+                        }
+                        StatusCode::INTERNAL_SERVER_ERROR => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .kafka
+                                .http_500
+                                .inc();
+                        }
+                        StatusCode::NOT_IMPLEMENTED => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .kafka
+                                .http_501
+                                .inc();
+                        }
+                        StatusCode::BAD_GATEWAY => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .kafka
+                                .http_502
+                                .inc();
+                        }
+                        StatusCode::SERVICE_UNAVAILABLE => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .kafka
+                                .http_503
+                                .inc();
+                        }
+                        StatusCode::GATEWAY_TIMEOUT => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .kafka
+                                .http_504
+                                .inc();
+                        }
+                        _ => {
+                            error!(
+                                "unsupported metric \
+                                resource={resource} \
+                                method={method} \
+                                result={:?} \
+                                status_code={:?}",
+                                resp,
+                                resp.status()
+                            );
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .kafka
+                                .unsupported
+                                .inc();
+                        }
+                    }
+                    start_request_metrics("admin", "kafka");
+                }
+                ("admin", "stats") => {
+                    match resp.status() {
+                        StatusCode::OK => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .stats
+                                .http_200
+                                .inc();
+                        }
+                        StatusCode::CREATED => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .stats
+                                .http_201
+                                .inc();
+                        }
+                        StatusCode::BAD_REQUEST => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .stats
+                                .http_400
+                                .inc();
+                        }
+                        StatusCode::UNAUTHORIZED => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .stats
+                                .http_401
+                                .inc();
+                        }
+                        StatusCode::FORBIDDEN => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .stats
+                                .http_403
+                                .inc();
+                        }
+                        StatusCode::NOT_FOUND => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .stats
+                                .http_404
+                                .inc();
+                        }
+                        StatusCode::INTERNAL_SERVER_ERROR => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .stats
+                                .http_500
+                                .inc();
+                        }
+                        StatusCode::NOT_IMPLEMENTED => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .stats
+                                .http_501
+                                .inc();
+                        }
+                        StatusCode::BAD_GATEWAY => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .stats
+                                .http_502
+                                .inc();
+                        }
+                        StatusCode::SERVICE_UNAVAILABLE => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .stats
+                                .http_503
+                                .inc();
+                        }
+                        StatusCode::GATEWAY_TIMEOUT => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .stats
+                                .http_504
+                                .inc();
+                        }
+                        _ => {
+                            error!(
+                                "unsupported metric \
+                                resource={resource} \
+                                method={method} \
+                                result={:?} \
+                                status_code={:?}",
+                                resp,
+                                resp.status()
+                            );
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .admin
+                                .stats
+                                .unsupported
+                                .inc();
+                        }
+                    }
+                    start_request_metrics("admin", "stats");
+                }
+                ("unknown", "get") => {
+                    match resp.status() {
+                        StatusCode::OK => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .unknown
+                                .get
+                                .http_200
+                                .inc();
+                        }
+                        StatusCode::CREATED => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .unknown
+                                .get
+                                .http_201
+                                .inc();
+                        }
+                        StatusCode::BAD_REQUEST => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .unknown
+                                .get
+                                .http_400
+                                .inc();
+                        }
+                        StatusCode::UNAUTHORIZED => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .unknown
+                                .get
+                                .http_401
+                                .inc();
+                        }
+                        StatusCode::FORBIDDEN => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .unknown
+                                .get
+                                .http_403
+                                .inc();
+                        }
+                        StatusCode::NOT_FOUND => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .unknown
+                                .get
+                                .http_404
+                                .inc();
+                        }
+                        StatusCode::INTERNAL_SERVER_ERROR => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .unknown
+                                .get
+                                .http_500
+                                .inc();
+                        }
+                        StatusCode::NOT_IMPLEMENTED => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .unknown
+                                .get
+                                .http_501
+                                .inc();
+                        }
+                        StatusCode::BAD_GATEWAY => {
+                            TLS_HTTP_COUNTER_STATUS_CODE
+                                .unknown
                                 .get
                                 .http_502
                                 .inc();
@@ -1958,7 +3539,7 @@ pub fn record_monitoring_metrics_api_after(
                                 .inc();
                         }
                     }
-                    TLS_HTTP_HISTOGRAM.unknown.get.observe(1.0);
+                    start_request_metrics("unknown", "get");
                 }
                 ("unknown", "post") => {
                     match resp.status() {
@@ -2056,7 +3637,7 @@ pub fn record_monitoring_metrics_api_after(
                                 .inc();
                         }
                     }
-                    TLS_HTTP_HISTOGRAM.unknown.post.observe(1.0);
+                    start_request_metrics("unknown", "post");
                 }
                 // end of unknown
                 (_, _) => {
@@ -2160,7 +3741,7 @@ pub fn record_monitoring_metrics_api_after(
                                 .inc();
                         }
                     }
-                    TLS_HTTP_HISTOGRAM.unknown.unsupported.observe(1.0);
+                    start_request_metrics("unknown", "unsupported");
                 }
             }
             Ok(resp)
@@ -2168,3 +3749,100 @@ pub fn record_monitoring_metrics_api_after(
         Err(e) => Err(e),
     }
 }
+
+/// route ``(resource, method)`` labels tracked by
+/// [`record_monitoring_metrics_api_before`](crate::monitoring::metrics::record_monitoring_metrics_api_before)
+/// when restapi is built without the ``metrics`` feature
+#[cfg(not(feature = "metrics"))]
+pub const HTTP_ROUTE_LABELS: [(&str, &str); 32] = [
+    ("auth", "login"),
+    ("user", "post"),
+    ("user", "delete"),
+    ("user", "put"),
+    ("user", "patch"),
+    ("user", "get"),
+    ("user", "search"),
+    ("user", "create_otp"),
+    ("user", "consume_otp"),
+    ("user", "consume_verify"),
+    ("data", "post"),
+    ("data", "delete"),
+    ("data", "put"),
+    ("data", "patch"),
+    ("data", "get"),
+    ("data", "search"),
+    ("data", "upload"),
+    ("auth", "passkey"),
+    ("auth", "get"),
+    ("admin", "unlock"),
+    ("admin", "get"),
+    ("admin", "put"),
+    ("admin", "invite"),
+    ("user", "invite"),
+    ("events", "get"),
+    ("admin", "keys"),
+    ("admin", "retire"),
+    ("admin", "webhooks"),
+    ("admin", "kafka"),
+    ("admin", "stats"),
+    ("unknown", "get"),
+    ("unknown", "post"),
+];
+
+/// record_monitoring_metrics_api_before
+///
+/// Without the ``metrics`` feature nothing is counted. A
+/// supported route (see
+/// [`HTTP_ROUTE_LABELS`](crate::monitoring::metrics::HTTP_ROUTE_LABELS))
+/// still starts the request's timer so
+/// [`get_request_route`](crate::monitoring::request_metrics::get_request_route)
+/// returns its labels for logging.
+///
+/// # Arguments
+///
+/// * `uri` - `str&` - url sub path without the hosting fqdn address
+/// * `resource` - `str&` - HTTP resource (`user`, `data`, `auth`, etc.)
+/// * `method` - `str&` - HTTP method used (`get`, `post`, `put`, `delete`, etc.)
+///
+#[cfg(not(feature = "metrics"))]
+pub fn record_monitoring_metrics_api_before(
+    uri: &str,
+    resource: &str,
+    method: &str,
+) {
+    match HTTP_ROUTE_LABELS
+        .iter()
+        .find(|(r, m)| *r == resource && *m == method)
+    {
+        Some((resource, method)) => start_request_metrics(resource, method),
+        None => warn!(
+            "metrics - before - unsupported - uri={uri} \
+            resource={resource} \
+            method={method}"
+        ),
+    }
+}
+
+/// record_monitoring_metrics_api_after
+///
+/// Without the ``metrics`` feature nothing is counted and
+/// the handler's response is returned as is
+///
+/// # Arguments
+///
+/// * `_uri` - `str&` - url sub path without the hosting fqdn address
+/// * `_resource` - `str&` - HTTP resource
+/// * `_method` - `str&` - HTTP method
+/// * `processed_response` - existing [`Response`](hyper::Response)
+///   from the internal service handler
+///
+#[cfg(not(feature = "metrics"))]
+pub fn record_monitoring_metrics_api_after(
+    _uri: &str,
+    _resource: &str,
+    _method: &str,
+    processed_response: std::result::Result<Response<Body>, Infallible>,
+) -> std::result::Result<Response<Body>, Infallible> {
+    finish_request_metrics();
+    processed_response
+}
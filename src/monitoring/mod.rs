@@ -1,3 +1,12 @@
 //! Module for monitoring metrics (currently only supports Prometheus)
 //!
+pub mod auth_alerts;
+pub mod health;
+pub mod log_redaction;
+pub mod metric_labels;
+pub mod metric_types;
 pub mod metrics;
+#[cfg(feature = "metrics")]
+pub mod openmetrics;
+pub mod otel;
+pub mod request_metrics;
@@ -0,0 +1,285 @@
+//! Encode the prometheus metrics in the OpenMetrics text
+//! format with request id exemplars
+//!
+//! ``GET /metrics`` returns the prometheus text format by
+//! default. Scrapers that send an
+//! ``Accept: application/openmetrics-text`` header (prometheus
+//! does when exemplar storage is enabled) get the OpenMetrics
+//! format, which adds the newest
+//! [`RequestExemplar`](crate::monitoring::request_metrics::RequestExemplar)
+//! to each ``http_request_duration_seconds`` bucket.
+//!
+use prometheus::proto::LabelPair;
+use prometheus::proto::MetricFamily;
+use prometheus::proto::MetricType;
+use prometheus::DEFAULT_BUCKETS;
+
+use crate::monitoring::request_metrics::get_latency_exemplar;
+use crate::monitoring::request_metrics::RequestExemplar;
+
+/// OpenMetrics response content type
+pub const OPENMETRICS_CONTENT_TYPE: &str =
+    "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// is_openmetrics_accepted
+///
+/// Check if a scraper's ``Accept`` header asks for the
+/// OpenMetrics format
+///
+/// # Arguments
+///
+/// * `accept` - `&str` - ``Accept`` header value
+///
+/// # Examples
+///
+/// ```rust
+/// use restapi::monitoring::openmetrics::is_openmetrics_accepted;
+/// assert!(is_openmetrics_accepted(
+///     "application/openmetrics-text;version=1.0.0,text/plain;q=0.5"));
+/// assert!(!is_openmetrics_accepted("text/plain"));
+/// ```
+///
+pub fn is_openmetrics_accepted(accept: &str) -> bool {
+    accept.split(',').any(|media_type| {
+        media_type.split(';').next().unwrap_or("").trim()
+            == "application/openmetrics-text"
+    })
+}
+
+/// encode_openmetrics
+///
+/// Encode metric families in the OpenMetrics text format
+///
+/// # Arguments
+///
+/// * `metric_families` - `&[MetricFamily]` - gathered
+///   prometheus metrics
+///
+/// # Returns
+///
+/// `String` ending with ``# EOF``
+///
+pub fn encode_openmetrics(metric_families: &[MetricFamily]) -> String {
+    let mut buf = String::new();
+    for mf in metric_families {
+        let name = mf.get_name();
+        let metric_type = mf.get_field_type();
+        // openmetrics counter families are named without the
+        // _total suffix that every counter sample has
+        let family = match metric_type {
+            MetricType::COUNTER => name.strip_suffix("_total").unwrap_or(name),
+            _ => name,
+        };
+        let type_name = match metric_type {
+            MetricType::COUNTER => "counter",
+            MetricType::GAUGE => "gauge",
+            MetricType::HISTOGRAM => "histogram",
+            MetricType::SUMMARY => "summary",
+            MetricType::UNTYPED => "unknown",
+        };
+        buf.push_str(&format!("# TYPE {family} {type_name}\n"));
+        if !mf.get_help().is_empty() {
+            buf.push_str(&format!(
+                "# HELP {family} {}\n",
+                escape_value(mf.get_help())
+            ));
+        }
+        for m in mf.get_metric() {
+            let labels = m.get_label();
+            match metric_type {
+                MetricType::COUNTER => write_sample(
+                    &mut buf,
+                    &format!("{family}_total"),
+                    labels,
+                    None,
+                    m.get_counter().get_value(),
+                    None,
+                ),
+                MetricType::GAUGE => write_sample(
+                    &mut buf,
+                    family,
+                    labels,
+                    None,
+                    m.get_gauge().get_value(),
+                    None,
+                ),
+                MetricType::UNTYPED => write_sample(
+                    &mut buf,
+                    family,
+                    labels,
+                    None,
+                    m.get_untyped().get_value(),
+                    None,
+                ),
+                MetricType::HISTOGRAM => {
+                    let h = m.get_histogram();
+                    let has_exemplars =
+                        family == "http_request_duration_seconds";
+                    let resource = get_label(labels, "resource");
+                    let method = get_label(labels, "method");
+                    let mut bounds: Vec<(f64, u64)> = h
+                        .get_bucket()
+                        .iter()
+                        .map(|b| {
+                            (b.get_upper_bound(), b.get_cumulative_count())
+                        })
+                        .collect();
+                    if !bounds
+                        .last()
+                        .map(|(b, _)| b.is_infinite())
+                        .unwrap_or(false)
+                    {
+                        bounds.push((f64::INFINITY, h.get_sample_count()));
+                    }
+                    for (upper_bound, count) in bounds {
+                        let exemplar = match has_exemplars {
+                            true => DEFAULT_BUCKETS
+                                .iter()
+                                .chain([f64::INFINITY].iter())
+                                .position(|b| *b == upper_bound)
+                                .and_then(|bucket| {
+                                    get_latency_exemplar(
+                                        resource, method, bucket,
+                                    )
+                                }),
+                            false => None,
+                        };
+                        write_sample(
+                            &mut buf,
+                            &format!("{family}_bucket"),
+                            labels,
+                            Some(("le", &format_float(upper_bound))),
+                            count as f64,
+                            exemplar.as_ref(),
+                        );
+                    }
+                    write_sample(
+                        &mut buf,
+                        &format!("{family}_sum"),
+                        labels,
+                        None,
+                        h.get_sample_sum(),
+                        None,
+                    );
+                    write_sample(
+                        &mut buf,
+                        &format!("{family}_count"),
+                        labels,
+                        None,
+                        h.get_sample_count() as f64,
+                        None,
+                    );
+                }
+                MetricType::SUMMARY => {
+                    let s = m.get_summary();
+                    for q in s.get_quantile() {
+                        write_sample(
+                            &mut buf,
+                            family,
+                            labels,
+                            Some(("quantile", &format_float(q.get_quantile()))),
+                            q.get_value(),
+                            None,
+                        );
+                    }
+                    write_sample(
+                        &mut buf,
+                        &format!("{family}_sum"),
+                        labels,
+                        None,
+                        s.get_sample_sum(),
+                        None,
+                    );
+                    write_sample(
+                        &mut buf,
+                        &format!("{family}_count"),
+                        labels,
+                        None,
+                        s.get_sample_count() as f64,
+                        None,
+                    );
+                }
+            }
+        }
+    }
+    buf.push_str("# EOF\n");
+    buf
+}
+
+/// write_sample
+///
+/// Append one sample line with an optional exemplar
+///
+fn write_sample(
+    buf: &mut String,
+    name: &str,
+    labels: &[LabelPair],
+    extra_label: Option<(&str, &str)>,
+    value: f64,
+    exemplar: Option<&RequestExemplar>,
+) {
+    buf.push_str(name);
+    let mut pairs: Vec<String> = labels
+        .iter()
+        .map(|lp| {
+            format!("{}=\"{}\"", lp.get_name(), escape_value(lp.get_value()))
+        })
+        .collect();
+    if let Some((label, label_value)) = extra_label {
+        pairs.push(format!("{label}=\"{}\"", escape_value(label_value)));
+    }
+    if !pairs.is_empty() {
+        buf.push_str(&format!("{{{}}}", pairs.join(",")));
+    }
+    buf.push_str(&format!(" {}", format_float(value)));
+    if let Some(exemplar) = exemplar {
+        buf.push_str(&format!(
+            " # {{request_id=\"{}\"}} {} {:.3}",
+            escape_value(&exemplar.request_id),
+            format_float(exemplar.value),
+            exemplar.timestamp
+        ));
+    }
+    buf.push('\n');
+}
+
+/// get_label
+///
+/// Get a label value from a sample's label pairs
+///
+fn get_label<'a>(labels: &'a [LabelPair], name: &str) -> &'a str {
+    labels
+        .iter()
+        .find(|lp| lp.get_name() == name)
+        .map(|lp| lp.get_value())
+        .unwrap_or("")
+}
+
+/// escape_value
+///
+/// Escape backslashes, double quotes and newlines
+///
+fn escape_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// format_float
+///
+/// Format a float with the OpenMetrics spellings for
+/// infinity and nan
+///
+fn format_float(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() {
+        match value.is_sign_positive() {
+            true => "+Inf".to_string(),
+            false => "-Inf".to_string(),
+        }
+    } else {
+        format!("{value}")
+    }
+}
@@ -0,0 +1,420 @@
+//! Export OpenTelemetry traces to an OTLP collector
+//! (Jaeger, Tempo or an OpenTelemetry collector)
+//!
+//! Built with the ``otel`` feature and started with an
+//! ``OTEL_EXPORTER_OTLP_ENDPOINT``, every request gets a
+//! server span (continuing the caller's W3C ``traceparent``
+//! header) with child client spans for postgres queries,
+//! object store (s3) uploads and kafka publishes.
+//!
+//! Without the ``otel`` feature the
+//! [`trace_request`](crate::monitoring::otel::trace_request),
+//! [`trace_db_query`](crate::monitoring::otel::trace_db_query) and
+//! [`trace_client_span`](crate::monitoring::otel::trace_client_span)
+//! helpers only await their future (``trace_db_query``
+//! still records the db latency metrics).
+//!
+use std::convert::Infallible;
+use std::fmt::Display;
+use std::future::Future;
+use std::time::Instant;
+
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Method;
+use hyper::Response;
+
+#[cfg(feature = "otel")]
+use opentelemetry::global;
+#[cfg(feature = "otel")]
+use opentelemetry::propagation::Extractor;
+#[cfg(feature = "otel")]
+use opentelemetry::trace::FutureExt;
+#[cfg(feature = "otel")]
+use opentelemetry::trace::SpanKind;
+#[cfg(feature = "otel")]
+use opentelemetry::trace::Status;
+#[cfg(feature = "otel")]
+use opentelemetry::trace::TraceContextExt;
+#[cfg(feature = "otel")]
+use opentelemetry::trace::Tracer;
+#[cfg(feature = "otel")]
+use opentelemetry::Context;
+#[cfg(feature = "otel")]
+use opentelemetry::KeyValue;
+#[cfg(feature = "otel")]
+use opentelemetry_otlp::WithExportConfig;
+
+use crate::pools::db_query_limits::observe_db_query;
+
+/// OtelConfig
+///
+/// Settings for exporting traces over OTLP (grpc)
+///
+/// # Supported Environment Variables
+///
+/// ```bash
+/// # OTLP grpc collector (empty = tracing disabled)
+/// export OTEL_EXPORTER_OTLP_ENDPOINT="http://localhost:4317"
+/// export OTEL_SERVICE_NAME="restapi"
+/// # ratio of new traces to sample (0.0 - 1.0)
+/// export OTEL_TRACES_SAMPLER_ARG="1.0"
+/// ```
+///
+/// # Arguments
+///
+/// * `endpoint` - `String` - OTLP grpc collector
+///   (empty = disabled)
+/// * `service_name` - `String` - ``service.name`` resource
+/// * `sample_ratio` - `f64` - ratio of new traces to sample
+///   (requests with a sampled ``traceparent`` are always
+///   sampled)
+///
+#[derive(Clone, Default)]
+pub struct OtelConfig {
+    pub endpoint: String,
+    pub service_name: String,
+    pub sample_ratio: f64,
+}
+
+impl OtelConfig {
+    /// build_otel_config
+    ///
+    /// Build an
+    /// [`OtelConfig`](crate::monitoring::otel::OtelConfig)
+    /// from environment variables
+    ///
+    pub fn build_otel_config() -> Self {
+        OtelConfig {
+            endpoint: std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT")
+                .unwrap_or_default()
+                .trim()
+                .to_string(),
+            service_name: std::env::var("OTEL_SERVICE_NAME")
+                .unwrap_or_else(|_| "restapi".to_string()),
+            sample_ratio: std::env::var("OTEL_TRACES_SAMPLER_ARG")
+                .unwrap_or_else(|_| "1.0".to_string())
+                .parse::<f64>()
+                .unwrap_or(1.0)
+                .clamp(0.0, 1.0),
+        }
+    }
+
+    /// is_enabled
+    ///
+    /// Check if an ``OTEL_EXPORTER_OTLP_ENDPOINT`` is set
+    ///
+    pub fn is_enabled(&self) -> bool {
+        !self.endpoint.is_empty()
+    }
+
+    /// start_tracing
+    ///
+    /// Install the global OTLP batch exporter and the W3C
+    /// ``traceparent`` propagator (must be called from
+    /// within the tokio runtime)
+    ///
+    /// # Arguments
+    ///
+    /// * `label` - `&str` - logging label
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) if the exporter cannot be built
+    ///
+    #[cfg(feature = "otel")]
+    pub fn start_tracing(&self, label: &str) -> Result<(), String> {
+        if !self.is_enabled() {
+            return Ok(());
+        }
+        global::set_text_map_propagator(
+            opentelemetry_sdk::propagation::TraceContextPropagator::new(),
+        );
+        let sampler = opentelemetry_sdk::trace::Sampler::ParentBased(Box::new(
+            opentelemetry_sdk::trace::Sampler::TraceIdRatioBased(
+                self.sample_ratio,
+            ),
+        ));
+        opentelemetry_otlp::new_pipeline()
+            .tracing()
+            .with_exporter(
+                opentelemetry_otlp::new_exporter()
+                    .tonic()
+                    .with_endpoint(&self.endpoint),
+            )
+            .with_trace_config(
+                opentelemetry_sdk::trace::config()
+                    .with_sampler(sampler)
+                    .with_resource(opentelemetry_sdk::Resource::new(vec![
+                        KeyValue::new(
+                            "service.name",
+                            self.service_name.clone(),
+                        ),
+                    ])),
+            )
+            .install_batch(opentelemetry_sdk::runtime::Tokio)
+            .map_err(|e| {
+                format!(
+                    "{label} - failed to start the OTLP trace exporter \
+                    for {} with err='{e}'",
+                    self.endpoint
+                )
+            })?;
+        info!(
+            "{label} - exporting traces to {} as service={} \
+            sample_ratio={}",
+            self.endpoint, self.service_name, self.sample_ratio
+        );
+        Ok(())
+    }
+
+    /// start_tracing
+    ///
+    /// Tracing requires the ``otel`` feature
+    ///
+    /// # Arguments
+    ///
+    /// * `label` - `&str` - logging label
+    ///
+    #[cfg(not(feature = "otel"))]
+    pub fn start_tracing(&self, label: &str) -> Result<(), String> {
+        if self.is_enabled() {
+            warn!(
+                "{label} - ignoring OTEL_EXPORTER_OTLP_ENDPOINT={} - \
+                restapi was built without --features otel",
+                self.endpoint
+            );
+        }
+        Ok(())
+    }
+}
+
+/// get_span_route
+///
+/// Replace numeric path segments with ``{id}`` so request
+/// span names do not include user or record ids
+///
+/// # Arguments
+///
+/// * `request_uri` - `&str` - url path
+///
+/// # Examples
+///
+/// ```rust
+/// use restapi::monitoring::otel::get_span_route;
+/// assert_eq!(get_span_route("/user/42"), "/user/{id}");
+/// assert_eq!(get_span_route("/user/data/search"), "/user/data/search");
+/// ```
+///
+pub fn get_span_route(request_uri: &str) -> String {
+    request_uri
+        .split('/')
+        .map(|segment| match segment.parse::<i64>() {
+            Ok(_) => "{id}",
+            Err(_) => segment,
+        })
+        .collect::<Vec<&str>>()
+        .join("/")
+}
+
+/// HeaderExtractor
+///
+/// Read the ``traceparent`` and ``tracestate`` headers
+///
+#[cfg(feature = "otel")]
+struct HeaderExtractor<'a>(&'a HeaderMap<HeaderValue>);
+
+#[cfg(feature = "otel")]
+impl<'a> Extractor for HeaderExtractor<'a> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|v| v.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|k| k.as_str()).collect()
+    }
+}
+
+/// trace_request
+///
+/// Serve a request inside a server span that continues
+/// the caller's ``traceparent`` (spans started while the
+/// request is served are its children)
+///
+/// # Arguments
+///
+/// * `method` - [`Method`](hyper::Method) - request method
+/// * `request_uri` - `&str` - url path
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   request headers with the optional ``traceparent``
+/// * `fut` - the request handler future
+///
+#[cfg(feature = "otel")]
+pub async fn trace_request<F>(
+    method: &Method,
+    request_uri: &str,
+    headers: &HeaderMap<HeaderValue>,
+    fut: F,
+) -> std::result::Result<Response<Body>, Infallible>
+where
+    F: Future<Output = std::result::Result<Response<Body>, Infallible>>,
+{
+    let parent_cx = global::get_text_map_propagator(|propagator| {
+        propagator.extract(&HeaderExtractor(headers))
+    });
+    let tracer = global::tracer("restapi");
+    let span = tracer
+        .span_builder(format!("{method} {}", get_span_route(request_uri)))
+        .with_kind(SpanKind::Server)
+        .with_attributes(vec![
+            KeyValue::new("http.method", method.to_string()),
+            KeyValue::new("http.target", request_uri.to_string()),
+        ])
+        .start_with_context(&tracer, &parent_cx);
+    let cx = parent_cx.with_span(span);
+    let result = fut.with_context(cx.clone()).await;
+    if let Ok(response) = &result {
+        let status = response.status().as_u16();
+        cx.span()
+            .set_attribute(KeyValue::new("http.status_code", status as i64));
+        if status >= 500 {
+            cx.span()
+                .set_status(Status::error(format!("HTTP {status}")));
+        }
+    }
+    cx.span().end();
+    result
+}
+
+/// trace_request
+///
+/// Serve a request (tracing requires the ``otel`` feature)
+///
+/// # Arguments
+///
+/// * `method` - [`Method`](hyper::Method) - request method
+/// * `request_uri` - `&str` - url path
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   request headers
+/// * `fut` - the request handler future
+///
+#[cfg(not(feature = "otel"))]
+pub async fn trace_request<F>(
+    _method: &Method,
+    _request_uri: &str,
+    _headers: &HeaderMap<HeaderValue>,
+    fut: F,
+) -> std::result::Result<Response<Body>, Infallible>
+where
+    F: Future<Output = std::result::Result<Response<Body>, Infallible>>,
+{
+    fut.await
+}
+
+/// trace_client_span
+///
+/// Run a call to another service (postgres, s3 or kafka)
+/// inside a client span that is a child of the current
+/// request's span. An `Err` marks the span as failed.
+///
+/// # Arguments
+///
+/// * `name` - `&str` - span name
+/// * `attributes` - `Vec<(&'static str, String)>` - span
+///   attributes
+/// * `fut` - the call's future
+///
+#[cfg(feature = "otel")]
+pub async fn trace_client_span<T, E, F>(
+    name: &str,
+    attributes: Vec<(&'static str, String)>,
+    fut: F,
+) -> Result<T, E>
+where
+    E: Display,
+    F: Future<Output = Result<T, E>>,
+{
+    let tracer = global::tracer("restapi");
+    let parent_cx = Context::current();
+    let span = tracer
+        .span_builder(name.to_string())
+        .with_kind(SpanKind::Client)
+        .with_attributes(
+            attributes
+                .into_iter()
+                .map(|(key, value)| KeyValue::new(key, value))
+                .collect::<Vec<KeyValue>>(),
+        )
+        .start_with_context(&tracer, &parent_cx);
+    let cx = parent_cx.with_span(span);
+    let result = fut.with_context(cx.clone()).await;
+    if let Err(e) = &result {
+        cx.span().set_status(Status::error(format!("{e}")));
+    }
+    cx.span().end();
+    result
+}
+
+/// trace_client_span
+///
+/// Run a call to another service (tracing requires the
+/// ``otel`` feature)
+///
+/// # Arguments
+///
+/// * `name` - `&str` - span name
+/// * `attributes` - `Vec<(&'static str, String)>` - span
+///   attributes
+/// * `fut` - the call's future
+///
+#[cfg(not(feature = "otel"))]
+pub async fn trace_client_span<T, E, F>(
+    _name: &str,
+    _attributes: Vec<(&'static str, String)>,
+    fut: F,
+) -> Result<T, E>
+where
+    E: Display,
+    F: Future<Output = Result<T, E>>,
+{
+    fut.await
+}
+
+/// trace_db_query
+///
+/// Run a postgres query inside a client span named by the
+/// sql operation (the statement is not exported because
+/// queries contain user values). The query's latency is
+/// observed and slow or timed out queries are logged with
+/// [`observe_db_query`](crate::pools::db_query_limits::observe_db_query).
+///
+/// # Arguments
+///
+/// * `query` - `&str` - sql statement for the operation name
+/// * `fut` - the query's future
+///
+pub async fn trace_db_query<T, E, F>(query: &str, fut: F) -> Result<T, E>
+where
+    E: Display + 'static,
+    F: Future<Output = Result<T, E>>,
+{
+    let operation = query
+        .split_whitespace()
+        .next()
+        .unwrap_or("QUERY")
+        .trim_end_matches(';')
+        .to_uppercase();
+    let started = Instant::now();
+    let result = trace_client_span(
+        &format!("postgres {operation}"),
+        vec![
+            ("db.system", "postgresql".to_string()),
+            ("db.operation", operation.clone()),
+        ],
+        fut,
+    )
+    .await;
+    observe_db_query(&operation, started.elapsed(), result.as_ref().err());
+    result
+}
@@ -0,0 +1,257 @@
+//! Track in-flight requests and tag request latencies with
+//! request id exemplars
+//!
+//! [`handle_request`](crate::handle_request::handle_request)
+//! serves each request inside
+//! [`scope_request_metrics`](crate::monitoring::request_metrics::scope_request_metrics)
+//! with the request's ``X-Request-Id`` (or a new uuid).
+//! [`record_monitoring_metrics_api_before`](crate::monitoring::metrics::record_monitoring_metrics_api_before)
+//! starts the request's timer and increments the route's
+//! ``http_route_requests_in_flight`` gauge, and
+//! [`record_monitoring_metrics_api_after`](crate::monitoring::metrics::record_monitoring_metrics_api_after)
+//! decrements the gauge and observes the latency in the
+//! ``http_request_duration_seconds`` histogram. Requests
+//! that are dropped before the after hook (client
+//! disconnects and request deadlines) are removed from the
+//! gauge when the scope ends.
+//!
+//! The newest observation in each histogram bucket is kept
+//! as an exemplar with the request id so a slow bucket can
+//! be traced back to its access log line and trace. The
+//! exemplars are only served in the OpenMetrics format
+//! (see [`encode_openmetrics`](crate::monitoring::openmetrics::encode_openmetrics)).
+//!
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use lazy_static::lazy_static;
+
+use crate::monitoring::metric_types::register_int_gauge_vec;
+use crate::monitoring::metric_types::IntGaugeVec;
+use crate::monitoring::metric_types::DEFAULT_BUCKETS;
+use crate::monitoring::metrics::HTTP_HISTO_VEC;
+
+/// max characters of a request id stored in an exemplar
+/// (OpenMetrics limits exemplar labels to 128 characters)
+pub const EXEMPLAR_REQUEST_ID_MAX_LEN: usize = 64;
+
+/// (resource, method, bucket index) for a latency exemplar
+type ExemplarKey = (&'static str, &'static str, usize);
+
+/// (start time, resource, method) for a request in flight
+type RequestTimer = Option<(Instant, &'static str, &'static str)>;
+
+lazy_static! {
+    pub static ref HTTP_ROUTE_REQUESTS_IN_FLIGHT_GAUGE_VEC: IntGaugeVec =
+        register_int_gauge_vec!(
+            "http_route_requests_in_flight",
+            "Number of HTTP requests being processed by each route handler.",
+            &["resource", "method"]
+        )
+        .unwrap();
+    static ref HTTP_LATENCY_EXEMPLARS: Mutex<HashMap<ExemplarKey, RequestExemplar>> =
+        Mutex::new(HashMap::new());
+}
+
+tokio::task_local! {
+    /// request id and timer for the request being served
+    static REQUEST_METRICS: RequestMetricsContext;
+}
+
+/// RequestExemplar
+///
+/// Newest latency observation in a histogram bucket
+///
+/// # Arguments
+///
+/// * `request_id` - `String` - sanitized ``X-Request-Id``
+/// * `value` - `f64` - observed latency in seconds
+/// * `timestamp` - `f64` - unix time of the observation
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct RequestExemplar {
+    pub request_id: String,
+    pub value: f64,
+    pub timestamp: f64,
+}
+
+/// RequestMetricsContext
+///
+/// Request id and the timer started by the before hook
+///
+struct RequestMetricsContext {
+    request_id: String,
+    timer: Arc<Mutex<RequestTimer>>,
+}
+
+/// InFlightGuard
+///
+/// Remove a request from the in-flight gauge if it is
+/// dropped before the after hook
+///
+struct InFlightGuard(Arc<Mutex<RequestTimer>>);
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        // never panic in drop while a panicking handler unwinds
+        let mut timer = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((_, resource, method)) = timer.take() {
+            HTTP_ROUTE_REQUESTS_IN_FLIGHT_GAUGE_VEC
+                .with_label_values(&[resource, method])
+                .dec();
+        }
+    }
+}
+
+/// scope_request_metrics
+///
+/// Serve a request with its id available to the metrics hooks
+///
+/// # Arguments
+///
+/// * `request_id` - `String` - ``X-Request-Id`` value
+/// * `request` - `Future` - request handler
+///
+pub async fn scope_request_metrics<F: Future>(
+    request_id: String,
+    request: F,
+) -> F::Output {
+    let timer = Arc::new(Mutex::new(None));
+    let _in_flight = InFlightGuard(timer.clone());
+    // boxed so the large handler futures are not copied
+    // onto the stack again
+    REQUEST_METRICS
+        .scope(
+            RequestMetricsContext { request_id, timer },
+            Box::pin(request),
+        )
+        .await
+}
+
+/// start_request_metrics
+///
+/// Count the current request as in flight and start its
+/// latency timer (requests served outside of
+/// [`scope_request_metrics`](crate::monitoring::request_metrics::scope_request_metrics)
+/// are not tracked). The labels are only ever the static
+/// route labels from the before hook, so request values
+/// never become label values.
+///
+/// # Arguments
+///
+/// * `resource` - `&'static str` - route resource label
+/// * `method` - `&'static str` - route method label
+///
+pub fn start_request_metrics(resource: &'static str, method: &'static str) {
+    let _ = REQUEST_METRICS.try_with(|context| {
+        let mut timer = context.timer.lock().unwrap();
+        if let Some((_, resource, method)) =
+            timer.replace((Instant::now(), resource, method))
+        {
+            HTTP_ROUTE_REQUESTS_IN_FLIGHT_GAUGE_VEC
+                .with_label_values(&[resource, method])
+                .dec();
+        }
+        HTTP_ROUTE_REQUESTS_IN_FLIGHT_GAUGE_VEC
+            .with_label_values(&[resource, method])
+            .inc();
+    });
+}
+
+/// finish_request_metrics
+///
+/// Remove the current request from the in-flight gauge and
+/// observe its latency with a request id exemplar
+///
+pub fn finish_request_metrics() {
+    let _ = REQUEST_METRICS.try_with(|context| {
+        let (started, resource, method) =
+            match context.timer.lock().unwrap().take() {
+                Some(timer) => timer,
+                None => return,
+            };
+        HTTP_ROUTE_REQUESTS_IN_FLIGHT_GAUGE_VEC
+            .with_label_values(&[resource, method])
+            .dec();
+        let latency = started.elapsed().as_secs_f64();
+        HTTP_HISTO_VEC
+            .with_label_values(&[resource, method])
+            .observe(latency);
+        let bucket = DEFAULT_BUCKETS
+            .iter()
+            .position(|upper_bound| latency <= *upper_bound)
+            .unwrap_or(DEFAULT_BUCKETS.len());
+        let request_id: String = context
+            .request_id
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric() || "-_.:".contains(*c))
+            .take(EXEMPLAR_REQUEST_ID_MAX_LEN)
+            .collect();
+        if request_id.is_empty() {
+            return;
+        }
+        HTTP_LATENCY_EXEMPLARS.lock().unwrap().insert(
+            (resource, method, bucket),
+            RequestExemplar {
+                request_id,
+                value: latency,
+                timestamp: chrono::Utc::now().timestamp_millis() as f64
+                    / 1000.0,
+            },
+        );
+    });
+}
+
+/// get_request_route
+///
+/// Get the route labels and request id of the request being
+/// served (for logging and labeling work done by its
+/// handler)
+///
+/// # Returns
+///
+/// `Option<(&'static str, &'static str, String)>` -
+/// (resource, method, request id) once the before hook ran
+///
+pub fn get_request_route() -> Option<(&'static str, &'static str, String)> {
+    REQUEST_METRICS
+        .try_with(|context| {
+            context.timer.lock().unwrap().map(|(_, resource, method)| {
+                (resource, method, context.request_id.clone())
+            })
+        })
+        .ok()
+        .flatten()
+}
+
+/// get_latency_exemplar
+///
+/// Get the newest exemplar in a
+/// ``http_request_duration_seconds`` bucket
+///
+/// # Arguments
+///
+/// * `resource` - `&str` - ``resource`` label value
+/// * `method` - `&str` - ``method`` label value
+/// * `bucket` - `usize` - bucket index
+///   (``DEFAULT_BUCKETS.len()`` for ``+Inf``)
+///
+/// # Returns
+///
+/// `Option<`[`RequestExemplar`](crate::monitoring::request_metrics::RequestExemplar)`>`
+///
+pub fn get_latency_exemplar(
+    resource: &str,
+    method: &str,
+    bucket: usize,
+) -> Option<RequestExemplar> {
+    HTTP_LATENCY_EXEMPLARS
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|((r, m, b), _)| *r == resource && *m == method && *b == bucket)
+        .map(|(_, exemplar)| exemplar.clone())
+}
@@ -0,0 +1,849 @@
+//! Render the verification, one-time-password, invite,
+//! security notification and new login emails from
+//! per-locale handlebars templates and publish them to
+//! kafka for a mail service
+//!
+//! Each template has a ``subject``, ``text`` and optional
+//! ``html`` part. The built-in English templates (in
+//! ``templates/email/en``) are always loaded, and a
+//! deployment can brand or translate them with a
+//! ``EMAIL_TEMPLATES_DIR`` that has a directory for each
+//! locale:
+//!
+//! ```bash
+//! templates/email/
+//! ├── en
+//! │   ├── invite.html.hbs
+//! │   ├── invite.subject.hbs
+//! │   ├── invite.text.hbs
+//! │   ├── new_login.html.hbs
+//! │   ├── new_login.subject.hbs
+//! │   ├── new_login.text.hbs
+//! │   ├── otp.html.hbs
+//! │   ├── otp.subject.hbs
+//! │   ├── otp.text.hbs
+//! │   ├── security.html.hbs
+//! │   ├── security.subject.hbs
+//! │   ├── security.text.hbs
+//! │   ├── verify.html.hbs
+//! │   ├── verify.subject.hbs
+//! │   └── verify.text.hbs
+//! └── es
+//!     └── ...
+//! ```
+//!
+//! Templates are picked with the user's ``users.locale``
+//! (``pt-BR``), then its language (``pt``), then
+//! ``EMAIL_DEFAULT_LOCALE`` and then the built-in ``en``
+//! templates. A locale's template is only used when it has
+//! a ``subject`` and a ``text`` part.
+//!
+//! Rendering requires the ``email`` feature (the default).
+//! Without it ``handlebars`` is not compiled, no templates
+//! are loaded and ``EMAIL_TEMPLATES_ENABLED`` is ignored, so
+//! no emails are published.
+//!
+#[cfg(feature = "email")]
+use std::collections::BTreeSet;
+#[cfg(feature = "email")]
+use std::path::Path;
+#[cfg(feature = "email")]
+use std::sync::Arc;
+
+#[cfg(feature = "email")]
+use handlebars::Handlebars;
+
+use lazy_static::lazy_static;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::kafka::event_bus::EventBus;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::monitoring::metric_labels::get_metric_label;
+use crate::monitoring::metric_types::register_int_counter_vec;
+use crate::monitoring::metric_types::IntCounterVec;
+use crate::requests::validation::field_rules::is_valid_locale;
+use crate::utils::get_server_address::get_server_address;
+
+lazy_static! {
+    pub static ref USER_EMAILS_COUNTER_VEC: IntCounterVec =
+        register_int_counter_vec!(
+            "user_emails_total",
+            "Number of rendered user emails by template, locale and status.",
+            &["template", "locale", "status"]
+        )
+        .unwrap();
+}
+
+/// supported email templates
+pub const EMAIL_TEMPLATE_NAMES: [&str; 5] =
+    ["verify", "otp", "invite", "security", "new_login"];
+
+/// parts of each email template
+/// (``{template}.{part}.hbs`` files)
+pub const EMAIL_TEMPLATE_PARTS: [&str; 3] = ["subject", "text", "html"];
+
+/// locale of the built-in templates
+pub const BUILT_IN_EMAIL_LOCALE: &str = "en";
+
+/// built-in ``(template, part, source)`` templates
+#[cfg(feature = "email")]
+const BUILT_IN_EMAIL_TEMPLATES: [(&str, &str, &str); 15] = [
+    (
+        "verify",
+        "subject",
+        include_str!("../../templates/email/en/verify.subject.hbs"),
+    ),
+    (
+        "verify",
+        "text",
+        include_str!("../../templates/email/en/verify.text.hbs"),
+    ),
+    (
+        "verify",
+        "html",
+        include_str!("../../templates/email/en/verify.html.hbs"),
+    ),
+    (
+        "otp",
+        "subject",
+        include_str!("../../templates/email/en/otp.subject.hbs"),
+    ),
+    (
+        "otp",
+        "text",
+        include_str!("../../templates/email/en/otp.text.hbs"),
+    ),
+    (
+        "otp",
+        "html",
+        include_str!("../../templates/email/en/otp.html.hbs"),
+    ),
+    (
+        "invite",
+        "subject",
+        include_str!("../../templates/email/en/invite.subject.hbs"),
+    ),
+    (
+        "invite",
+        "text",
+        include_str!("../../templates/email/en/invite.text.hbs"),
+    ),
+    (
+        "invite",
+        "html",
+        include_str!("../../templates/email/en/invite.html.hbs"),
+    ),
+    (
+        "security",
+        "subject",
+        include_str!("../../templates/email/en/security.subject.hbs"),
+    ),
+    (
+        "security",
+        "text",
+        include_str!("../../templates/email/en/security.text.hbs"),
+    ),
+    (
+        "security",
+        "html",
+        include_str!("../../templates/email/en/security.html.hbs"),
+    ),
+    (
+        "new_login",
+        "subject",
+        include_str!("../../templates/email/en/new_login.subject.hbs"),
+    ),
+    (
+        "new_login",
+        "text",
+        include_str!("../../templates/email/en/new_login.text.hbs"),
+    ),
+    (
+        "new_login",
+        "html",
+        include_str!("../../templates/email/en/new_login.html.hbs"),
+    ),
+];
+
+/// UserEmail
+///
+/// Rendered email published to ``KAFKA_TOPIC_USER_EMAILS``
+/// as json
+///
+/// # Arguments
+///
+/// * `template` - `String` - ``verify``, ``otp``, ``invite``,
+///   ``security`` or ``new_login``
+/// * `locale` - `String` - locale of the rendered template
+/// * `to` - `String` - recipient email
+/// * `subject` - `String` - email subject
+/// * `text` - `String` - plain text body
+/// * `html` - `String` - html body (empty when the locale
+///   has no html template)
+///
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct UserEmail {
+    pub template: String,
+    pub locale: String,
+    pub to: String,
+    pub subject: String,
+    pub text: String,
+    pub html: String,
+}
+
+/// EmailTemplates
+///
+/// Settings and loaded templates for the user emails
+///
+/// # Supported Environment Variables
+///
+/// ```bash
+/// # publish rendered emails (requires KAFKA_PUBLISH_EVENTS)
+/// export EMAIL_TEMPLATES_ENABLED="0"
+/// # directory with a subdirectory for each locale
+/// # (empty = only the built-in en templates)
+/// export EMAIL_TEMPLATES_DIR=""
+/// export EMAIL_DEFAULT_LOCALE="en"
+/// export KAFKA_TOPIC_USER_EMAILS="user.emails"
+/// # verify link (the u and t query params are added -
+/// # empty = https://API_ENDPOINT/user/verify)
+/// export EMAIL_VERIFY_URL=""
+/// # invite link (the u and t query params are added -
+/// # empty = the email only has the invite token)
+/// export EMAIL_INVITE_URL=""
+/// ```
+///
+/// # Arguments
+///
+/// * `enabled` - `bool` - render and publish user emails
+/// * `dir` - `String` - templates directory (empty = only
+///   the built-in templates)
+/// * `default_locale` - `String` - locale used when the
+///   user's locale has no templates
+/// * `topic` - `String` - kafka topic for rendered emails
+/// * `verify_url` - `String` - page for the verify link
+/// * `invite_url` - `String` - page for the invite link
+///   (empty = no link)
+/// * `locales` - `Vec<String>` - locales with templates
+///   (sorted)
+/// * `text_templates` - `Arc<Handlebars>` - ``subject`` and
+///   ``text`` templates (not html-escaped, ``email`` feature)
+/// * `html_templates` - `Arc<Handlebars>` - ``html``
+///   templates (values are html-escaped, ``email`` feature)
+///
+#[derive(Clone, Default)]
+pub struct EmailTemplates {
+    pub enabled: bool,
+    pub dir: String,
+    pub default_locale: String,
+    pub topic: String,
+    pub verify_url: String,
+    pub invite_url: String,
+    pub locales: Vec<String>,
+    #[cfg(feature = "email")]
+    pub text_templates: Arc<Handlebars<'static>>,
+    #[cfg(feature = "email")]
+    pub html_templates: Arc<Handlebars<'static>>,
+}
+
+impl EmailTemplates {
+    /// build_email_templates
+    ///
+    /// Build an
+    /// [`EmailTemplates`](crate::notifications::email_templates::EmailTemplates)
+    /// from environment variables and load the built-in and
+    /// ``EMAIL_TEMPLATES_DIR`` templates
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) for an invalid
+    /// ``EMAIL_DEFAULT_LOCALE``, a missing
+    /// ``EMAIL_TEMPLATES_DIR``, an unsupported locale
+    /// directory or template file name or a template that
+    /// does not compile
+    ///
+    pub fn build_email_templates() -> Result<Self, String> {
+        let enabled = std::env::var("EMAIL_TEMPLATES_ENABLED")
+            .unwrap_or_else(|_| "0".to_string());
+        let dir = std::env::var("EMAIL_TEMPLATES_DIR")
+            .unwrap_or_default()
+            .trim()
+            .to_string();
+        let default_locale = normalize_locale(
+            &std::env::var("EMAIL_DEFAULT_LOCALE")
+                .unwrap_or_else(|_| BUILT_IN_EMAIL_LOCALE.to_string()),
+        );
+        if !is_valid_locale(&default_locale) {
+            return Err(format!(
+                "invalid EMAIL_DEFAULT_LOCALE={default_locale} \
+                must be a locale like en or pt-BR"
+            ));
+        }
+        let verify_url = match std::env::var("EMAIL_VERIFY_URL")
+            .unwrap_or_default()
+            .trim()
+        {
+            "" => format!("https://{}/user/verify", get_server_address("api")),
+            verify_url => verify_url.to_string(),
+        };
+
+        #[cfg(feature = "email")]
+        let (locales, text_templates, html_templates) =
+            load_email_templates(&dir)?;
+        #[cfg(not(feature = "email"))]
+        let locales = vec![BUILT_IN_EMAIL_LOCALE.to_string()];
+        let enabled = enabled == "1" || enabled == "true";
+        #[cfg(not(feature = "email"))]
+        if enabled {
+            warn!(
+                "EMAIL_TEMPLATES_ENABLED is ignored - \
+                restapi was built without --features email"
+            );
+        }
+
+        Ok(EmailTemplates {
+            enabled: cfg!(feature = "email") && enabled,
+            dir,
+            default_locale,
+            topic: std::env::var("KAFKA_TOPIC_USER_EMAILS")
+                .unwrap_or_else(|_| "user.emails".to_string()),
+            verify_url,
+            invite_url: std::env::var("EMAIL_INVITE_URL")
+                .unwrap_or_default()
+                .trim()
+                .to_string(),
+            locales,
+            #[cfg(feature = "email")]
+            text_templates: Arc::new(text_templates),
+            #[cfg(feature = "email")]
+            html_templates: Arc::new(html_templates),
+        })
+    }
+
+    /// validate_topic
+    ///
+    /// Check the rendered emails topic is in ``KAFKA_TOPICS``
+    /// when emails and kafka publishing are enabled and the
+    /// list is not empty
+    ///
+    /// # Arguments
+    ///
+    /// * `events` - [`EventBus`](crate::kafka::event_bus::EventBus)
+    /// * `kafka_topics` - `&[String]` - supported topics
+    ///   (``KAFKA_TOPICS``)
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) when the topic is not supported
+    ///
+    pub fn validate_topic(
+        &self,
+        events: &EventBus,
+        kafka_topics: &[String],
+    ) -> Result<(), String> {
+        if !self.enabled
+            || !events.enabled
+            || kafka_topics.is_empty()
+            || kafka_topics.contains(&self.topic)
+        {
+            return Ok(());
+        }
+        Err(format!(
+            "KAFKA_TOPIC_USER_EMAILS={} is not in KAFKA_TOPICS={}",
+            self.topic,
+            kafka_topics.join(",")
+        ))
+    }
+
+    /// get_email_locale
+    ///
+    /// Get the first locale with a ``subject`` and ``text``
+    /// part for a template from the user's locale, its
+    /// language, the ``default_locale`` and the built-in
+    /// locale
+    ///
+    /// # Arguments
+    ///
+    /// * `template` - `&str` - template name (``verify``)
+    /// * `locale` - `&str` - user's locale (``pt-BR``)
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use restapi::notifications::email_templates::EmailTemplates;
+    /// let email_templates = EmailTemplates::build_email_templates().unwrap();
+    /// assert_eq!(email_templates.get_email_locale("otp", "pt-BR"), "en");
+    /// ```
+    ///
+    pub fn get_email_locale(&self, template: &str, locale: &str) -> String {
+        let locale = normalize_locale(locale);
+        let mut candidates = vec![locale.clone()];
+        if let Some((language, _)) = locale.split_once('-') {
+            candidates.push(language.to_string());
+        }
+        candidates.push(self.default_locale.clone());
+        if let Some((language, _)) = self.default_locale.split_once('-') {
+            candidates.push(language.to_string());
+        }
+        candidates
+            .into_iter()
+            .find(|candidate| {
+                ["subject", "text"].iter().all(|part| {
+                    self.has_text_template(&get_template_name(
+                        candidate, template, part,
+                    ))
+                })
+            })
+            .unwrap_or_else(|| BUILT_IN_EMAIL_LOCALE.to_string())
+    }
+
+    /// render_user_email
+    ///
+    /// Render a template in the best locale for the user
+    /// (see
+    /// [`get_email_locale`](crate::notifications::email_templates::EmailTemplates::get_email_locale))
+    ///
+    /// # Arguments
+    ///
+    /// * `template` - `&str` - ``verify``, ``otp``, ``invite``,
+    ///   ``security`` or ``new_login``
+    /// * `locale` - `&str` - user's locale
+    /// * `to` - `&str` - recipient email
+    /// * `data` - `&serde_json::Value` - template values (the
+    ///   ``email`` and ``locale`` values are added)
+    ///
+    /// # Returns
+    ///
+    /// Ok([`UserEmail`](crate::notifications::email_templates::UserEmail))
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) when a template part fails to
+    /// render
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use restapi::notifications::email_templates::EmailTemplates;
+    /// let email_templates = EmailTemplates::build_email_templates().unwrap();
+    /// let email = email_templates
+    ///     .render_user_email(
+    ///         "otp",
+    ///         "en-US",
+    ///         "user@email.com",
+    ///         &serde_json::json!({"token": "123456", "exp_date": "2030-01-01"}),
+    ///     )
+    ///     .unwrap();
+    /// assert_eq!(email.subject, "Your password reset code");
+    /// assert!(email.text.contains("123456"));
+    /// assert!(email.html.contains("<strong>123456</strong>"));
+    /// ```
+    ///
+    #[cfg(feature = "email")]
+    pub fn render_user_email(
+        &self,
+        template: &str,
+        locale: &str,
+        to: &str,
+        data: &serde_json::Value,
+    ) -> Result<UserEmail, String> {
+        let email_locale = self.get_email_locale(template, locale);
+        let mut values = match data {
+            serde_json::Value::Object(values) => values.clone(),
+            _ => serde_json::Map::new(),
+        };
+        values.insert("email".to_string(), serde_json::json!(to));
+        values.insert("locale".to_string(), serde_json::json!(email_locale));
+        let values = serde_json::Value::Object(values);
+        let render = |templates: &Handlebars, part: &str| {
+            let name = get_template_name(&email_locale, template, part);
+            match templates.has_template(&name) {
+                true => templates.render(&name, &values).map_err(|e| {
+                    format!(
+                        "failed to render email template={name} with err='{e}'"
+                    )
+                }),
+                false => Ok("".to_string()),
+            }
+        };
+        Ok(UserEmail {
+            template: template.to_string(),
+            locale: email_locale.clone(),
+            to: to.to_string(),
+            subject: render(&self.text_templates, "subject")?
+                .trim()
+                .to_string(),
+            text: render(&self.text_templates, "text")?,
+            html: render(&self.html_templates, "html")?,
+        })
+    }
+
+    /// render_user_email
+    ///
+    /// Rendering requires the ``email`` feature
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) - always
+    ///
+    #[cfg(not(feature = "email"))]
+    pub fn render_user_email(
+        &self,
+        template: &str,
+        _locale: &str,
+        _to: &str,
+        _data: &serde_json::Value,
+    ) -> Result<UserEmail, String> {
+        Err(format!(
+            "rendering the {template} email requires building \
+            restapi with --features email"
+        ))
+    }
+
+    /// has_text_template
+    ///
+    /// Check a ``subject`` or ``text`` template part is
+    /// loaded (always `false` without the ``email`` feature)
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - `&str` - ``{locale}/{template}.{part}``
+    ///
+    #[cfg(feature = "email")]
+    fn has_text_template(&self, name: &str) -> bool {
+        self.text_templates.has_template(name)
+    }
+
+    /// has_text_template
+    ///
+    /// Always `false` without the ``email`` feature
+    ///
+    /// # Arguments
+    ///
+    /// * `_name` - `&str` - ``{locale}/{template}.{part}``
+    ///
+    #[cfg(not(feature = "email"))]
+    fn has_text_template(&self, _name: &str) -> bool {
+        false
+    }
+
+    /// send_user_email
+    ///
+    /// Render a user email and publish it as json to
+    /// ``KAFKA_TOPIC_USER_EMAILS`` using the user's id as the
+    /// partition key. Nothing is rendered when emails or
+    /// kafka publishing (``KAFKA_PUBLISH_EVENTS``) are
+    /// disabled.
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - caller logging label
+    /// * `events` - [`EventBus`](crate::kafka::event_bus::EventBus) -
+    ///   publishes the email with retries and dead letters
+    /// * `kafka_pool` - initialized [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+    /// * `user_id` - `i32` - recipient user id
+    /// * `template` - `&str` - ``verify``, ``otp``, ``invite``,
+    ///   ``security`` or ``new_login``
+    /// * `locale` - `&str` - user's locale
+    /// * `to` - `&str` - recipient email
+    /// * `data` - `serde_json::Value` - template values
+    ///
+    #[allow(clippy::too_many_arguments)]
+    pub async fn send_user_email(
+        &self,
+        tracking_label: &str,
+        events: &EventBus,
+        kafka_pool: &KafkaPublisher,
+        user_id: i32,
+        template: &str,
+        locale: &str,
+        to: &str,
+        data: serde_json::Value,
+    ) {
+        if !self.enabled || !events.enabled {
+            return;
+        }
+        let email = match self.render_user_email(template, locale, to, &data) {
+            Ok(email) => email,
+            Err(err_msg) => {
+                error!(
+                    "{tracking_label} - \
+                    failed to send {template} email to user {user_id} \
+                    with err='{err_msg}'"
+                );
+                USER_EMAILS_COUNTER_VEC
+                    .with_label_values(&[template, "unknown", "failed"])
+                    .inc();
+                return;
+            }
+        };
+        USER_EMAILS_COUNTER_VEC
+            .with_label_values(&[
+                template,
+                &get_metric_label("user_emails_total", &email.locale),
+                "rendered",
+            ])
+            .inc();
+        events
+            .dead_letters
+            .publish(
+                kafka_pool,
+                &self.topic,
+                &format!("user-{user_id}"),
+                &serde_json::to_string(&email).unwrap(),
+            )
+            .await;
+    }
+
+    /// get_link
+    ///
+    /// Add the ``u`` (user id) and ``t`` (token) query params
+    /// to a link
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - `&str` - page url (empty = no link)
+    /// * `user_id` - `i32` - user id
+    /// * `token` - `&str` - one-time-use token
+    ///
+    /// # Returns
+    ///
+    /// `String` - link (empty when `url` is empty)
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use restapi::notifications::email_templates::EmailTemplates;
+    /// assert_eq!(
+    ///     EmailTemplates::get_link("https://app.example.com/invite?src=email", 2, "abc"),
+    ///     "https://app.example.com/invite?src=email&u=2&t=abc");
+    /// assert_eq!(EmailTemplates::get_link("", 2, "abc"), "");
+    /// ```
+    ///
+    pub fn get_link(url: &str, user_id: i32, token: &str) -> String {
+        match url::Url::parse(url) {
+            Ok(mut link) => {
+                link.query_pairs_mut()
+                    .append_pair("u", &user_id.to_string())
+                    .append_pair("t", token);
+                link.to_string()
+            }
+            Err(_) => "".to_string(),
+        }
+    }
+}
+
+/// normalize_locale
+///
+/// Trim a locale and use ``-`` separators with a lowercase
+/// language and uppercase region (``pt_br`` - ``pt-BR``)
+///
+/// # Arguments
+///
+/// * `locale` - `&str` - locale
+///
+/// # Examples
+///
+/// ```rust
+/// use restapi::notifications::email_templates::normalize_locale;
+/// assert_eq!(normalize_locale(" pt_br "), "pt-BR");
+/// assert_eq!(normalize_locale("EN"), "en");
+/// assert_eq!(normalize_locale("zh-Hant"), "zh-Hant");
+/// ```
+///
+pub fn normalize_locale(locale: &str) -> String {
+    locale
+        .trim()
+        .replace('_', "-")
+        .split('-')
+        .enumerate()
+        .map(|(i, part)| match (i, part.len()) {
+            (0, _) => part.to_lowercase(),
+            (_, 2) => part.to_uppercase(),
+            _ => part.to_string(),
+        })
+        .collect::<Vec<String>>()
+        .join("-")
+}
+
+/// get_template_name
+///
+/// Registered name for a template part
+/// (``{locale}/{template}.{part}``)
+///
+fn get_template_name(locale: &str, template: &str, part: &str) -> String {
+    format!("{locale}/{template}.{part}")
+}
+
+/// load_email_templates
+///
+/// Compile the built-in templates and the templates in
+/// ``EMAIL_TEMPLATES_DIR``
+///
+/// # Arguments
+///
+/// * `dir` - `&str` - templates directory (empty = only the
+///   built-in templates)
+///
+/// # Returns
+///
+/// Ok((locales, text templates, html templates)) - locales
+/// are sorted
+///
+#[cfg(feature = "email")]
+fn load_email_templates(
+    dir: &str,
+) -> Result<(Vec<String>, Handlebars<'static>, Handlebars<'static>), String> {
+    let mut text_templates = Handlebars::new();
+    text_templates.register_escape_fn(handlebars::no_escape);
+    let mut html_templates = Handlebars::new();
+    let mut locales = BTreeSet::new();
+    locales.insert(BUILT_IN_EMAIL_LOCALE.to_string());
+    for (template, part, source) in BUILT_IN_EMAIL_TEMPLATES.iter() {
+        register_email_template(
+            &mut text_templates,
+            &mut html_templates,
+            BUILT_IN_EMAIL_LOCALE,
+            template,
+            part,
+            source,
+        )?;
+    }
+    if !dir.is_empty() {
+        locales.extend(load_email_templates_dir(
+            dir,
+            &mut text_templates,
+            &mut html_templates,
+        )?);
+    }
+    Ok((
+        locales.into_iter().collect(),
+        text_templates,
+        html_templates,
+    ))
+}
+
+/// register_email_template
+///
+/// Compile a template part into the text (``subject`` and
+/// ``text``) or html templates
+///
+#[cfg(feature = "email")]
+fn register_email_template(
+    text_templates: &mut Handlebars<'static>,
+    html_templates: &mut Handlebars<'static>,
+    locale: &str,
+    template: &str,
+    part: &str,
+    source: &str,
+) -> Result<(), String> {
+    let name = get_template_name(locale, template, part);
+    let templates = match part {
+        "html" => html_templates,
+        _ => text_templates,
+    };
+    templates
+        .register_template_string(&name, source)
+        .map_err(|e| format!("invalid email template={name} with err='{e}'"))
+}
+
+/// load_email_templates_dir
+///
+/// Load the ``{locale}/{template}.{part}.hbs`` files in a
+/// templates directory (other files are ignored)
+///
+/// # Returns
+///
+/// Ok(`Vec<String>`) - locales in the directory
+///
+#[cfg(feature = "email")]
+fn load_email_templates_dir(
+    dir: &str,
+    text_templates: &mut Handlebars<'static>,
+    html_templates: &mut Handlebars<'static>,
+) -> Result<Vec<String>, String> {
+    let read_dir = |path: &Path| {
+        std::fs::read_dir(path).map_err(|e| {
+            format!(
+                "failed to read EMAIL_TEMPLATES_DIR={} with err='{e}'",
+                path.display()
+            )
+        })
+    };
+    let mut locales = Vec::new();
+    for locale_entry in read_dir(Path::new(dir))? {
+        let locale_path = match locale_entry {
+            Ok(locale_entry) => locale_entry.path(),
+            Err(e) => {
+                return Err(format!("failed to read {dir} with err='{e}'"))
+            }
+        };
+        if !locale_path.is_dir() {
+            continue;
+        }
+        let dir_name = locale_path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let locale = normalize_locale(&dir_name);
+        if !is_valid_locale(&locale) {
+            return Err(format!(
+                "invalid email templates locale directory={} \
+                must be a locale like en or pt-BR",
+                locale_path.display()
+            ));
+        }
+        for template_entry in read_dir(&locale_path)? {
+            let template_path = match template_entry {
+                Ok(template_entry) => template_entry.path(),
+                Err(e) => {
+                    return Err(format!(
+                        "failed to read {} with err='{e}'",
+                        locale_path.display()
+                    ))
+                }
+            };
+            let file_name = template_path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let (template, part) = match file_name
+                .strip_suffix(".hbs")
+                .and_then(|stem| stem.split_once('.'))
+            {
+                Some(template_part) => template_part,
+                None => continue,
+            };
+            if !EMAIL_TEMPLATE_NAMES.contains(&template)
+                || !EMAIL_TEMPLATE_PARTS.contains(&part)
+            {
+                return Err(format!(
+                    "unsupported email template={} must be \
+                    {{{}}}.{{{}}}.hbs",
+                    template_path.display(),
+                    EMAIL_TEMPLATE_NAMES.join(","),
+                    EMAIL_TEMPLATE_PARTS.join(",")
+                ));
+            }
+            let source =
+                std::fs::read_to_string(&template_path).map_err(|e| {
+                    format!(
+                        "failed to read email template={} with err='{e}'",
+                        template_path.display()
+                    )
+                })?;
+            register_email_template(
+                text_templates,
+                html_templates,
+                &locale,
+                template,
+                part,
+                &source,
+            )?;
+        }
+        locales.push(locale);
+    }
+    Ok(locales)
+}
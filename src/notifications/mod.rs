@@ -0,0 +1,19 @@
+//! Store user events as notifications and stream them to
+//! clients with server-sent events on
+//! ``GET /user/notifications/stream``
+//!
+//! See
+//! [`UserNotifications`](crate::notifications::user_notifications::UserNotifications)
+//! for the supported environment variables
+//!
+//! Render the verification, one-time-password, invite and
+//! security emails from per-locale templates with
+//! [`EmailTemplates`](crate::notifications::email_templates::EmailTemplates)
+//!
+//! Notify users about password, email and passkey changes
+//! with
+//! [`SecurityNotifications`](crate::notifications::security_notifications::SecurityNotifications)
+//!
+pub mod email_templates;
+pub mod security_notifications;
+pub mod user_notifications;
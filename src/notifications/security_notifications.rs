@@ -0,0 +1,281 @@
+//! Notify users when their account credentials change
+//!
+//! Changing a user's password (``PUT /user`` or a consumed
+//! one-time-use password), email or adding a passkey
+//! publishes a ``USER_SECURITY_CHANGE`` user event and
+//! sends the ``security`` email so a user notices changes
+//! they did not make. An email change is sent to the
+//! previous email address.
+//!
+//! A login from a device (``User-Agent`` and ``device``
+//! header) or location (``GEOIP_DB_PATH``) that none of the
+//! user's other sessions used publishes a
+//! ``LOGIN_UNRECOGNIZED`` user event and sends the
+//! ``new_login`` email. A user's first login is not
+//! reported.
+//!
+//! Route the events to a dedicated kafka topic with
+//! ``USER_EVENTS_TOPIC_SECURITY`` and
+//! ``USER_EVENTS_TOPIC_UNRECOGNIZED`` (see
+//! [`EventBus`](crate::kafka::event_bus::EventBus)).
+//!
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::PooledConnection;
+use bb8_postgres::PostgresConnectionManager;
+
+use crate::core::core_config::CoreConfig;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::monitoring::otel::trace_db_query;
+use crate::requests::models::user::ModelUser;
+use crate::requests::models::user_session::ModelUserSessionMetadata;
+
+/// supported credential changes
+pub const SECURITY_CHANGES: [&str; 3] = ["password", "email", "passkey"];
+
+/// SecurityNotifications
+///
+/// Settings for the credential change notifications
+///
+/// # Supported Environment Variables
+///
+/// ```bash
+/// # publish USER_SECURITY_CHANGE events
+/// export SECURITY_NOTIFICATIONS_EVENTS="1"
+/// # send the security email (requires
+/// # EMAIL_TEMPLATES_ENABLED=1 and KAFKA_PUBLISH_EVENTS=1)
+/// export SECURITY_NOTIFICATIONS_EMAILS="1"
+/// # report logins from a new device or location
+/// export SECURITY_NOTIFICATIONS_NEW_LOGINS="1"
+/// ```
+///
+/// # Arguments
+///
+/// * `events_enabled` - `bool` - publish
+///   ``USER_SECURITY_CHANGE`` and ``LOGIN_UNRECOGNIZED``
+///   events
+/// * `emails_enabled` - `bool` - send the ``security`` and
+///   ``new_login`` emails
+/// * `new_logins_enabled` - `bool` - compare each login with
+///   the user's other sessions
+///
+#[derive(Clone, Default)]
+pub struct SecurityNotifications {
+    pub events_enabled: bool,
+    pub emails_enabled: bool,
+    pub new_logins_enabled: bool,
+}
+
+impl SecurityNotifications {
+    /// build_security_notifications
+    ///
+    /// Build a
+    /// [`SecurityNotifications`](crate::notifications::security_notifications::SecurityNotifications)
+    /// from environment variables
+    ///
+    pub fn build_security_notifications() -> Self {
+        let get_flag = |key: &str| -> bool {
+            let value = std::env::var(key).unwrap_or_else(|_| "1".to_string());
+            value == "1" || value == "true"
+        };
+        SecurityNotifications {
+            events_enabled: get_flag("SECURITY_NOTIFICATIONS_EVENTS"),
+            emails_enabled: get_flag("SECURITY_NOTIFICATIONS_EMAILS"),
+            new_logins_enabled: get_flag("SECURITY_NOTIFICATIONS_NEW_LOGINS"),
+        }
+    }
+}
+
+/// send_security_notification
+///
+/// Publish a ``USER_SECURITY_CHANGE`` event and send the
+/// ``security`` email for a credential change
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `kafka_pool` - initialized [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+/// * `user` - [`ModelUser`](crate::requests::models::user::ModelUser) -
+///   user before the change (the email is sent to this
+///   user's email and locale)
+/// * `change` - `&str` - one of the
+///   [`SECURITY_CHANGES`](crate::notifications::security_notifications::SECURITY_CHANGES)
+/// * `new_email` - `&str` - new email for an ``email``
+///   change (use ``""`` for none)
+///
+pub async fn send_security_notification(
+    tracking_label: &str,
+    config: &CoreConfig,
+    kafka_pool: &KafkaPublisher,
+    user: &ModelUser,
+    change: &str,
+    new_email: &str,
+) {
+    let security_notifications = &config.security_notifications;
+    info!(
+        "{tracking_label} - user {} security change={change}",
+        user.id
+    );
+    if security_notifications.events_enabled {
+        config
+            .events
+            .user_security_changed(kafka_pool, user.id, change, &user.email)
+            .await;
+    }
+    if security_notifications.emails_enabled {
+        let changed_at = chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC");
+        config
+            .email_templates
+            .send_user_email(
+                tracking_label,
+                &config.events,
+                kafka_pool,
+                user.id,
+                "security",
+                &user.locale,
+                &user.email,
+                serde_json::json!({
+                    "change": change,
+                    "new_email": new_email,
+                    "changed_at": changed_at.to_string(),
+                }),
+            )
+            .await;
+    }
+}
+
+/// send_new_login_notification
+///
+/// Compare a new session with the user's other sessions and
+/// publish a ``LOGIN_UNRECOGNIZED`` event and send the
+/// ``new_login`` email when the device or location was not
+/// seen before. The location is only compared when
+/// ``GEOIP_DB_PATH`` found a country for the session.
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `kafka_pool` - initialized [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+/// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+///   an established db connection from the
+///   postgres client db threadpool
+/// * `user_id` - `i32` - user id
+/// * `email` - `&str` - user email
+/// * `locale` - `&str` - user's locale
+/// * `session` - [`ModelUserSessionMetadata`](crate::requests::models::user_session::ModelUserSessionMetadata) -
+///   new session's client details
+/// * `token` - `&str` - new session's token (excluded from
+///   the comparison)
+///
+#[allow(clippy::too_many_arguments)]
+pub async fn send_new_login_notification(
+    tracking_label: &str,
+    config: &CoreConfig,
+    kafka_pool: &KafkaPublisher,
+    conn: &PooledConnection<'_, PostgresConnectionManager<MakeTlsConnector>>,
+    user_id: i32,
+    email: &str,
+    locale: &str,
+    session: &ModelUserSessionMetadata,
+    token: &str,
+) {
+    let security_notifications = &config.security_notifications;
+    if !security_notifications.new_logins_enabled
+        || (!security_notifications.events_enabled
+            && !security_notifications.emails_enabled)
+    {
+        return;
+    }
+    let query = format!(
+        "SELECT \
+            COUNT(*) AS num_sessions, \
+            COUNT(*) FILTER (WHERE \
+                COALESCE(users_tokens.user_agent, '') = '{}' \
+                AND \
+                COALESCE(users_tokens.device, '') = '{}') \
+                AS num_same_device, \
+            COUNT(*) FILTER (WHERE \
+                COALESCE(users_tokens.country, '') = '{}' \
+                AND \
+                COALESCE(users_tokens.city, '') = '{}') \
+                AS num_same_location \
+        FROM \
+            users_tokens \
+        WHERE \
+            users_tokens.user_id = {user_id} \
+            AND \
+            users_tokens.token != '{token}';",
+        session.user_agent.replace('\'', "''"),
+        session.device.replace('\'', "''"),
+        session.country.replace('\'', "''"),
+        session.city.replace('\'', "''")
+    );
+    let stmt = conn.prepare(&query).await.unwrap();
+    let row = match trace_db_query(&query, conn.query_one(&stmt, &[])).await {
+        Ok(row) => row,
+        Err(e) => {
+            error!(
+                "{tracking_label} - \
+                failed to compare sessions for user_id={user_id} \
+                with err='{e}'"
+            );
+            return;
+        }
+    };
+    let num_sessions: i64 = row.try_get("num_sessions").unwrap();
+    let num_same_device: i64 = row.try_get("num_same_device").unwrap();
+    let num_same_location: i64 = row.try_get("num_same_location").unwrap();
+    if num_sessions == 0 {
+        return;
+    }
+    let new_device = num_same_device == 0;
+    let new_location = config.geo_ip.is_enabled()
+        && !session.country.is_empty()
+        && num_same_location == 0;
+    let reason = match (new_device, new_location) {
+        (true, true) => "device,location",
+        (true, false) => "device",
+        (false, true) => "location",
+        (false, false) => return,
+    };
+    info!(
+        "{tracking_label} - user {user_id} unrecognized login \
+        reason={reason}"
+    );
+    if security_notifications.events_enabled {
+        config
+            .events
+            .login_unrecognized(kafka_pool, user_id, email, reason, session)
+            .await;
+    }
+    if security_notifications.emails_enabled {
+        let login_at = chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC");
+        let device = match session.device.is_empty() {
+            true => &session.user_agent,
+            false => &session.device,
+        };
+        config
+            .email_templates
+            .send_user_email(
+                tracking_label,
+                &config.events,
+                kafka_pool,
+                user_id,
+                "new_login",
+                locale,
+                email,
+                serde_json::json!({
+                    "device": device,
+                    "ip_address": session.ip_address,
+                    "country": session.country,
+                    "city": session.city,
+                    "new_device": new_device,
+                    "new_location": new_location,
+                    "login_at": login_at.to_string(),
+                }),
+            )
+            .await;
+    }
+}
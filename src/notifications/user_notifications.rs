@@ -0,0 +1,144 @@
+//! Settings for storing user events in the
+//! ``users_notifications`` table and streaming them with
+//! server-sent events
+//!
+//! The api server stores each event in a background task so
+//! handlers are not slowed down by the extra insert. Each
+//! stream polls the table for the user's new notifications,
+//! so every api server can serve a stream for events stored
+//! by any other api server.
+//!
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use crate::requests::models::user_notification::insert_user_notification;
+
+/// UserNotifications
+///
+/// Settings for user notifications and the
+/// ``GET /user/notifications/stream`` server-sent events
+/// endpoint
+///
+/// # Supported Environment Variables
+///
+/// ```bash
+/// export USER_NOTIFICATIONS_ENABLED="0"
+/// export USER_NOTIFICATIONS_POLL_INTERVAL_MS="1000"
+/// export USER_NOTIFICATIONS_KEEP_ALIVE_SECONDS="15"
+/// export USER_NOTIFICATIONS_MAX_STREAM_SECONDS="3600"
+/// ```
+///
+/// # Arguments
+///
+/// * `enabled` - `bool` - store user events and allow streams
+/// * `poll_interval_ms` - `u64` - milliseconds between
+///   checks for new notifications on each stream
+/// * `keep_alive_seconds` - `u64` - send a keep-alive comment
+///   after this many seconds without a notification
+/// * `max_stream_seconds` - `u64` - close a stream after this
+///   many seconds (clients reconnect with ``Last-Event-ID``)
+/// * `db_pool` - `Option<`[`Pool`](bb8::Pool)`>` - postgres
+///   client db threadpool for storing notifications (set
+///   when the api server starts)
+///
+#[derive(Clone, Default)]
+pub struct UserNotifications {
+    pub enabled: bool,
+    pub poll_interval_ms: u64,
+    pub keep_alive_seconds: u64,
+    pub max_stream_seconds: u64,
+    pub db_pool: Option<Pool<PostgresConnectionManager<MakeTlsConnector>>>,
+}
+
+impl UserNotifications {
+    /// build_user_notifications
+    ///
+    /// Build a
+    /// [`UserNotifications`](crate::notifications::user_notifications::UserNotifications)
+    /// from environment variables (without a db pool)
+    ///
+    pub fn build_user_notifications() -> Self {
+        let get_env = |key: &str, default: u64| -> u64 {
+            std::env::var(key)
+                .unwrap_or_else(|_| format!("{default}"))
+                .parse::<u64>()
+                .unwrap_or(default)
+        };
+        let enabled = std::env::var("USER_NOTIFICATIONS_ENABLED")
+            .unwrap_or_else(|_| "0".to_string());
+        UserNotifications {
+            enabled: enabled == "1" || enabled == "true",
+            poll_interval_ms: get_env(
+                "USER_NOTIFICATIONS_POLL_INTERVAL_MS",
+                1000,
+            )
+            .max(100),
+            keep_alive_seconds: get_env(
+                "USER_NOTIFICATIONS_KEEP_ALIVE_SECONDS",
+                15,
+            )
+            .max(1),
+            max_stream_seconds: get_env(
+                "USER_NOTIFICATIONS_MAX_STREAM_SECONDS",
+                3600,
+            )
+            .max(1),
+            db_pool: None,
+        }
+    }
+
+    /// store_user_notification
+    ///
+    /// Store a user event in the background when
+    /// notifications are enabled and the api server set
+    /// the `db_pool`
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - `i32` - user id for the event
+    /// * `event` - `&str` - event name (``USER_UPDATE``)
+    /// * `details` - `&str` - space-separated ``key=value``
+    ///   pairs (use ``""`` for none)
+    ///
+    pub fn store_user_notification(
+        &self,
+        user_id: i32,
+        event: &str,
+        details: &str,
+    ) {
+        let db_pool = match (self.enabled, &self.db_pool) {
+            (true, Some(db_pool)) => db_pool.clone(),
+            _ => return,
+        };
+        let event = event.to_string();
+        let details = details.to_string();
+        tokio::spawn(async move {
+            let tracking_label = "notifications";
+            let conn = match db_pool.get().await {
+                Ok(conn) => conn,
+                Err(e) => {
+                    error!(
+                        "{tracking_label} - \
+                        failed to store {event} notification \
+                        for user={user_id} - \
+                        unable to get a db connection with err='{e}'"
+                    );
+                    return;
+                }
+            };
+            if let Err(err_msg) = insert_user_notification(
+                tracking_label,
+                user_id,
+                &event,
+                &details,
+                &conn,
+            )
+            .await
+            {
+                error!("{err_msg}");
+            }
+        });
+    }
+}
@@ -0,0 +1,75 @@
+//! # Cache for hot lookups
+//!
+//! The [`Cache`](crate::pools::cache::Cache) trait stores
+//! short-lived string values by key so authenticated requests
+//! can skip postgres round trips (see
+//! [`UserCache`](crate::pools::user_cache::UserCache)).
+//!
+//! ## Supported Backends
+//!
+//! - ``memory`` - [`MemoryCache`](crate::pools::memory_cache::MemoryCache)
+//!   in-process LRU cache (each api server has its own cache)
+//! - ``redis`` - ``RedisCache`` shared by every api server
+//!   (requires building with ``--features redis``)
+//!
+//! Implement the trait to use another cache and set it on the
+//! [`UserCache`](crate::pools::user_cache::UserCache) before
+//! starting the server.
+//!
+//! Cache errors are logged and treated as a cache miss so the
+//! api keeps working from postgres if the cache is down.
+//!
+use std::future::Future;
+use std::pin::Pin;
+
+/// future returned by the
+/// [`Cache`](crate::pools::cache::Cache) methods
+pub type CacheFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Cache
+///
+/// Key-value cache with per-key expiration
+///
+pub trait Cache: Send + Sync {
+    /// get
+    ///
+    /// Get a cached value
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - `&str` - cache key
+    ///
+    /// # Returns
+    ///
+    /// `Option<String>` - `None` if the key is missing,
+    /// expired or the cache failed
+    ///
+    fn get<'a>(&'a self, key: &'a str) -> CacheFuture<'a, Option<String>>;
+
+    /// set
+    ///
+    /// Store a value that expires after `ttl_seconds`
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - `&str` - cache key
+    /// * `value` - `&str` - value to store
+    /// * `ttl_seconds` - `u64` - seconds until the value expires
+    ///
+    fn set<'a>(
+        &'a self,
+        key: &'a str,
+        value: &'a str,
+        ttl_seconds: u64,
+    ) -> CacheFuture<'a, ()>;
+
+    /// delete
+    ///
+    /// Remove a cached value
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - `&str` - cache key
+    ///
+    fn delete<'a>(&'a self, key: &'a str) -> CacheFuture<'a, ()>;
+}
@@ -0,0 +1,226 @@
+//! Startup check for the db indexes the search, login,
+//! one-time-password, jwt key report, upload pipeline,
+//! file version and notifications queries need
+//!
+//! Existing dbs can create missing indexes with the
+//! ``docker/db/sql/migrations`` sql files
+//!
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use crate::monitoring::otel::trace_db_query;
+
+/// list of `(table, index, migration)` the api server expects
+pub const EXPECTED_DB_INDEXES: [(&str, &str, &str); 30] = [
+    ("users", "idx_users_email_lower", "0001_search_indexes.sql"),
+    ("users", "idx_users_email_trgm", "0001_search_indexes.sql"),
+    ("users", "idx_users_created_at", "0001_search_indexes.sql"),
+    (
+        "users_data",
+        "idx_users_data_user_id_created_at",
+        "0001_search_indexes.sql",
+    ),
+    (
+        "users_data",
+        "idx_users_data_filename_trgm",
+        "0001_search_indexes.sql",
+    ),
+    (
+        "users_data",
+        "idx_users_data_comments_trgm",
+        "0001_search_indexes.sql",
+    ),
+    (
+        "users_otp",
+        "idx_users_otp_user_id_active",
+        "0003_users_otp_single_active.sql",
+    ),
+    (
+        "users_tokens",
+        "idx_users_tokens_kid_active",
+        "0004_users_tokens_kid.sql",
+    ),
+    (
+        "users_data",
+        "idx_users_data_status_processing",
+        "0005_users_data_status.sql",
+    ),
+    (
+        "users_notifications",
+        "idx_users_notifications_user_id_id",
+        "0006_users_notifications.sql",
+    ),
+    (
+        "users_data",
+        "idx_users_data_user_id_checksum",
+        "0009_users_data_checksum.sql",
+    ),
+    (
+        "users_data",
+        "idx_users_data_expires_at",
+        "0010_users_data_lifecycle.sql",
+    ),
+    (
+        "users_data",
+        "idx_users_data_derivatives_processing",
+        "0012_users_data_derivatives.sql",
+    ),
+    (
+        "users_data_uploads",
+        "idx_users_data_uploads_expires_at",
+        "0013_users_data_uploads.sql",
+    ),
+    (
+        "users_data_shares",
+        "idx_users_data_shares_shared_with_user_id",
+        "0014_users_data_shares.sql",
+    ),
+    (
+        "users_data",
+        "idx_users_data_tags",
+        "0015_users_data_tags.sql",
+    ),
+    (
+        "users_data",
+        "idx_users_data_folder",
+        "0015_users_data_tags.sql",
+    ),
+    (
+        "users_otp",
+        "idx_users_otp_exp_date_active",
+        "0018_users_otp_attempts.sql",
+    ),
+    (
+        "users_verified",
+        "idx_users_verified_exp_date_active",
+        "0018_users_otp_attempts.sql",
+    ),
+    (
+        "webhooks_deliveries",
+        "idx_webhooks_deliveries_pending",
+        "0019_webhooks.sql",
+    ),
+    (
+        "kafka_dead_letters",
+        "idx_kafka_dead_letters_status_id",
+        "0020_kafka_dead_letters.sql",
+    ),
+    (
+        "users_tokens_consumed",
+        "idx_users_tokens_consumed_kind_token",
+        "0021_users_tokens_consumed.sql",
+    ),
+    (
+        "users_data",
+        "idx_users_data_user_id_filename_current",
+        "0025_users_data_versions.sql",
+    ),
+    (
+        "users_data",
+        "idx_users_data_version_of",
+        "0025_users_data_versions.sql",
+    ),
+    (
+        "users_data_access",
+        "idx_users_data_access_data_id_created_at",
+        "0026_users_data_access.sql",
+    ),
+    (
+        "users_data_access",
+        "idx_users_data_access_owner_user_id_created_at",
+        "0026_users_data_access.sql",
+    ),
+    (
+        "users_data",
+        "idx_users_data_uploading_sloc",
+        "0027_users_data_uploading_sloc.sql",
+    ),
+    (
+        "users_logins",
+        "idx_users_logins_user_id_created_at",
+        "0029_users_logins.sql",
+    ),
+    (
+        "users_admin_actions",
+        "idx_users_admin_actions_user_id_created_at",
+        "0030_users_admin_actions.sql",
+    ),
+    (
+        "ip_rules",
+        "idx_ip_rules_action_cidr_path_prefix",
+        "0031_ip_rules.sql",
+    ),
+];
+
+/// check_db_indexes
+///
+/// Log a warning for each index in
+/// [`EXPECTED_DB_INDEXES`](crate::pools::check_db_indexes::EXPECTED_DB_INDEXES)
+/// that is missing from the db. Missing indexes do not
+/// stop the api server, but searches and logins will
+/// be slower on large tables.
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client db
+///   threadpool with required tls encryption
+///
+/// # Returns
+///
+/// `Vec<String>` - names of the missing indexes
+///
+pub async fn check_db_indexes(
+    tracking_label: &str,
+    db_pool: Pool<PostgresConnectionManager<MakeTlsConnector>>,
+) -> Vec<String> {
+    let conn = match db_pool.get().await {
+        Ok(conn) => conn,
+        Err(e) => {
+            warn!(
+                "{tracking_label} - \
+                unable to check db indexes - \
+                failed to get a db connection with err='{e}'"
+            );
+            return Vec::new();
+        }
+    };
+    let query = "SELECT \
+            pg_indexes.indexname \
+        FROM \
+            pg_indexes \
+        WHERE \
+            pg_indexes.schemaname = 'public';";
+    let query_result = match trace_db_query(query, conn.query(query, &[])).await
+    {
+        Ok(query_result) => query_result,
+        Err(e) => {
+            warn!(
+                "{tracking_label} - \
+                unable to check db indexes with err='{e}'"
+            );
+            return Vec::new();
+        }
+    };
+    let found: Vec<String> = query_result
+        .iter()
+        .map(|row| row.try_get("indexname").unwrap())
+        .collect();
+    let mut missing: Vec<String> = Vec::new();
+    for (table, index, migration) in EXPECTED_DB_INDEXES.iter() {
+        if !found.iter().any(|v| v == index) {
+            warn!(
+                "{tracking_label} - \
+                missing db index {index} on {table} - \
+                please apply docker/db/sql/migrations/{migration}"
+            );
+            missing.push(index.to_string());
+        }
+    }
+    if missing.is_empty() {
+        info!("{tracking_label} - found all expected db indexes");
+    }
+    missing
+}
@@ -0,0 +1,295 @@
+//! Postgres statement timeouts, db latency metrics and slow
+//! query logging
+//!
+//! Every pooled connection (the primary and any read
+//! replicas) is opened with a ``statement_timeout`` of
+//! ``POSTGRES_STATEMENT_TIMEOUT_MS`` so postgres cancels a
+//! runaway query instead of holding the connection and its
+//! locks (see
+//! [`get_statement_timeout_options`](crate::pools::db_query_limits::get_statement_timeout_options)).
+//!
+//! Queries run with
+//! [`trace_db_query`](crate::monitoring::otel::trace_db_query)
+//! are observed in the ``db_query_duration_seconds``
+//! histogram by sql operation and the route labels of the
+//! request that ran them, and queries slower than
+//! ``POSTGRES_SLOW_QUERY_MS`` are logged with their duration,
+//! handler and request id. The sql statement is never logged
+//! because queries contain user values.
+//!
+//! A query canceled by the timeout fails the handler's db
+//! call, and
+//! [`scope_db_query_timeouts`](crate::pools::db_query_limits::scope_db_query_timeouts)
+//! turns the handler's ``500`` response into a ``504`` with
+//! the ``QUERY_TIMEOUT`` error code.
+//!
+//! ## Supported Environment Variables
+//!
+//! ```bash
+//! # 0 = no statement timeout
+//! export POSTGRES_STATEMENT_TIMEOUT_MS="30000"
+//! # 0 = do not log slow queries
+//! export POSTGRES_SLOW_QUERY_MS="500"
+//! ```
+//!
+use std::any::Any;
+use std::future::Future;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
+use hyper::body::HttpBody;
+use hyper::Body;
+use hyper::Response;
+
+use lazy_static::lazy_static;
+
+use tokio_postgres::error::SqlState;
+
+use crate::monitoring::metric_types::register_histogram_vec;
+use crate::monitoring::metric_types::register_int_counter_vec;
+use crate::monitoring::metric_types::HistogramVec;
+use crate::monitoring::metric_types::IntCounterVec;
+use crate::monitoring::request_metrics::get_request_route;
+use crate::requests::models::api_error::ApiErrorCode;
+
+/// largest ``500`` response body that is rewritten with the
+/// ``QUERY_TIMEOUT`` error code
+pub const DB_TIMEOUT_MAX_BODY_BYTES: u64 = 1048576;
+
+/// slow query threshold in milliseconds (set from
+/// ``POSTGRES_SLOW_QUERY_MS`` when the db pool is built)
+static DB_SLOW_QUERY_MS: AtomicU64 = AtomicU64::new(500);
+
+lazy_static! {
+    pub static ref DB_QUERY_HISTO_VEC: HistogramVec = register_histogram_vec!(
+        "db_query_duration_seconds",
+        "Postgres query latencies in seconds by sql operation \
+        and route.",
+        &["operation", "resource", "method"],
+        vec![
+            0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5,
+            5.0, 10.0, 30.0
+        ]
+    )
+    .unwrap();
+    pub static ref DB_SLOW_QUERY_COUNTER_VEC: IntCounterVec =
+        register_int_counter_vec!(
+            "db_slow_queries_total",
+            "Number of postgres queries slower than \
+            POSTGRES_SLOW_QUERY_MS by sql operation and result \
+            (ok, error or timeout).",
+            &["operation", "result"]
+        )
+        .unwrap();
+}
+
+tokio::task_local! {
+    /// set when a query for the request being served timed out
+    static REQUEST_DB_TIMEOUT: Arc<AtomicBool>;
+}
+
+/// get_statement_timeout_options
+///
+/// Postgres connection string parameter that sets the
+/// ``statement_timeout`` for every statement on the
+/// connection
+///
+/// # Arguments
+///
+/// * `statement_timeout_ms` - `u64` - timeout in
+///   milliseconds (``0`` = no timeout)
+///
+/// # Returns
+///
+/// `String` - ``&options=...`` or an empty string
+///
+/// # Examples
+///
+/// ```rust
+/// use restapi::pools::db_query_limits::get_statement_timeout_options;
+/// assert_eq!(
+///     get_statement_timeout_options(30000),
+///     "&options=-c%20statement_timeout%3D30000"
+/// );
+/// assert_eq!(get_statement_timeout_options(0), "");
+/// ```
+///
+pub fn get_statement_timeout_options(statement_timeout_ms: u64) -> String {
+    match statement_timeout_ms {
+        0 => String::new(),
+        ms => format!("&options=-c%20statement_timeout%3D{ms}"),
+    }
+}
+
+/// set_slow_query_ms
+///
+/// Change the slow query logging threshold
+///
+/// # Arguments
+///
+/// * `slow_query_ms` - `u64` - threshold in milliseconds
+///   (``0`` = do not log slow queries)
+///
+pub fn set_slow_query_ms(slow_query_ms: u64) {
+    DB_SLOW_QUERY_MS.store(slow_query_ms, Ordering::Relaxed);
+}
+
+/// get_db_operation_label
+///
+/// Bounded ``operation`` label value for a sql operation
+///
+/// # Arguments
+///
+/// * `operation` - `&str` - upper case first sql keyword
+///
+/// # Examples
+///
+/// ```rust
+/// use restapi::pools::db_query_limits::get_db_operation_label;
+/// assert_eq!(get_db_operation_label("SELECT"), "select");
+/// assert_eq!(get_db_operation_label("DECLARE"), "other");
+/// ```
+///
+pub fn get_db_operation_label(operation: &str) -> &'static str {
+    match operation {
+        "SELECT" => "select",
+        "INSERT" => "insert",
+        "UPDATE" => "update",
+        "DELETE" => "delete",
+        "WITH" => "with",
+        _ => "other",
+    }
+}
+
+/// is_statement_timeout
+///
+/// Check if a query failed because postgres canceled it
+/// (``statement_timeout`` or a manual cancel)
+///
+/// # Arguments
+///
+/// * `e` - query error
+///
+pub fn is_statement_timeout<E: 'static>(e: &E) -> bool {
+    (e as &dyn Any)
+        .downcast_ref::<tokio_postgres::Error>()
+        .and_then(|e| e.code())
+        .is_some_and(|code| *code == SqlState::QUERY_CANCELED)
+}
+
+/// observe_db_query
+///
+/// Record a finished query's latency, log it if it was slow
+/// and flag the current request if it timed out
+///
+/// # Arguments
+///
+/// * `operation` - `&str` - upper case first sql keyword
+/// * `elapsed` - [`Duration`](std::time::Duration) - query
+///   latency
+/// * `err` - `Option<&E>` - query error
+///
+pub fn observe_db_query<E: 'static>(
+    operation: &str,
+    elapsed: Duration,
+    err: Option<&E>,
+) {
+    let operation = get_db_operation_label(operation);
+    let (resource, method, request_id) =
+        get_request_route().unwrap_or(("background", "none", String::new()));
+    DB_QUERY_HISTO_VEC
+        .with_label_values(&[operation, resource, method])
+        .observe(elapsed.as_secs_f64());
+    let timed_out = err.is_some_and(is_statement_timeout);
+    if timed_out {
+        let _ = REQUEST_DB_TIMEOUT
+            .try_with(|flag| flag.store(true, Ordering::Relaxed));
+    }
+    let slow_query_ms = DB_SLOW_QUERY_MS.load(Ordering::Relaxed);
+    let elapsed_ms = elapsed.as_millis() as u64;
+    if slow_query_ms == 0 || elapsed_ms < slow_query_ms {
+        return;
+    }
+    let result = match (timed_out, err.is_some()) {
+        (true, _) => "timeout",
+        (false, true) => "error",
+        (false, false) => "ok",
+    };
+    DB_SLOW_QUERY_COUNTER_VEC
+        .with_label_values(&[operation, result])
+        .inc();
+    warn!(
+        "slow db query - {operation} took {elapsed_ms}ms \
+        (threshold={slow_query_ms}ms result={result}) \
+        handler={resource}:{method} request_id={request_id}"
+    );
+}
+
+/// scope_db_query_timeouts
+///
+/// Serve a request and return a ``504`` with the
+/// ``QUERY_TIMEOUT`` error code if the handler returned a
+/// ``500`` after one of its queries timed out
+///
+/// # Arguments
+///
+/// * `serve_request` - future that builds the response
+///
+pub async fn scope_db_query_timeouts<F, E>(
+    serve_request: F,
+) -> Result<Response<Body>, E>
+where
+    F: Future<Output = Result<Response<Body>, E>>,
+{
+    let timed_out = Arc::new(AtomicBool::new(false));
+    let response = REQUEST_DB_TIMEOUT
+        .scope(timed_out.clone(), serve_request)
+        .await?;
+    if !timed_out.load(Ordering::Relaxed) || response.status() != 500 {
+        return Ok(response);
+    }
+    Ok(get_db_timeout_response(response).await)
+}
+
+/// get_db_timeout_response
+///
+/// Change a ``500`` response into a ``504`` and replace the
+/// json ``error_code`` with ``QUERY_TIMEOUT`` (the rest of
+/// the handler's response is kept)
+///
+/// # Arguments
+///
+/// * `response` - [`Response`](hyper::Response)
+///
+async fn get_db_timeout_response(response: Response<Body>) -> Response<Body> {
+    let (mut parts, body) = response.into_parts();
+    parts.status = hyper::StatusCode::GATEWAY_TIMEOUT;
+    if body
+        .size_hint()
+        .exact()
+        .is_none_or(|len| len > DB_TIMEOUT_MAX_BODY_BYTES)
+    {
+        return Response::from_parts(parts, body);
+    }
+    let bytes = match hyper::body::to_bytes(body).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            error!(
+                "failed to read response body for a db timeout \
+                with err='{e}'"
+            );
+            return Response::from_parts(parts, Body::empty());
+        }
+    };
+    let mut value = match serde_json::from_slice::<serde_json::Value>(&bytes) {
+        Ok(value) if value.is_object() => value,
+        _ => return Response::from_parts(parts, Body::from(bytes)),
+    };
+    value["error_code"] =
+        serde_json::Value::from(ApiErrorCode::QueryTimeout.as_str());
+    parts.headers.remove("Content-Length");
+    Response::from_parts(parts, Body::from(value.to_string()))
+}
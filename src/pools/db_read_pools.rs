@@ -0,0 +1,209 @@
+//! Route read-only queries to postgres read replicas
+//!
+//! Set ``POSTGRES_READ_ENDPOINTS`` to a comma-separated list of
+//! replica addresses to send the ``get_user``, ``search_users``
+//! and ``search_user_data`` queries to a bb8 pool per replica
+//! (round robin). Writes, token validation and admin checks
+//! always use the primary pool.
+//!
+//! A replica that does not hand out a connection within
+//! ``POSTGRES_READ_TIMEOUT_MS`` is skipped for
+//! ``POSTGRES_READ_RETRY_SECONDS`` and the next replica is
+//! used. When every replica is down, the read falls back to
+//! the primary.
+//!
+//! Replicas are asynchronous, so a read right after a write
+//! can return the previous row until the replica catches up.
+//!
+use std::sync::atomic::AtomicI64;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8::PooledConnection;
+use bb8_postgres::PostgresConnectionManager;
+
+use lazy_static::lazy_static;
+
+use crate::core::core_config::CoreConfig;
+use crate::monitoring::metric_types::register_int_counter_vec;
+use crate::monitoring::metric_types::IntCounterVec;
+use crate::pools::get_db_pool::get_db_conn_str_for_address;
+use crate::pools::get_db_pool::get_db_tls_connector;
+
+lazy_static! {
+    pub static ref DB_READ_POOL_COUNTER_VEC: IntCounterVec =
+        register_int_counter_vec!(
+            "db_read_pool_total",
+            "Number of read-only queries by target \
+            (replica or primary fallback).",
+            &["target"]
+        )
+        .unwrap();
+}
+
+/// pooled connection to a read replica
+type ReadConn<'a> =
+    PooledConnection<'a, PostgresConnectionManager<MakeTlsConnector>>;
+
+/// DbReadReplica
+///
+/// bb8 pool for one postgres read replica
+///
+/// # Arguments
+///
+/// * `address` - `String` - replica ``host:port``
+/// * `pool` - [`Pool`](bb8::Pool) - replica client db
+///   threadpool with required tls encryption
+/// * `down_until` - `Arc<AtomicI64>` - unix time in
+///   milliseconds before the replica is retried
+///
+#[derive(Clone)]
+pub struct DbReadReplica {
+    pub address: String,
+    pub pool: Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    pub down_until: Arc<AtomicI64>,
+}
+
+/// DbReadPools
+///
+/// Read replica pools shared by every HTTP request
+/// (empty when ``POSTGRES_READ_ENDPOINTS`` is not set)
+///
+/// # Arguments
+///
+/// * `replicas` - `Vec<`[`DbReadReplica`](crate::pools::db_read_pools::DbReadReplica)`>` -
+///   one pool per replica
+/// * `retry_seconds` - `u64` - seconds before retrying
+///   a replica that was down
+/// * `next` - `Arc<AtomicUsize>` - round robin counter
+///
+#[derive(Clone, Default)]
+pub struct DbReadPools {
+    pub replicas: Vec<DbReadReplica>,
+    pub retry_seconds: u64,
+    pub next: Arc<AtomicUsize>,
+}
+
+impl DbReadPools {
+    /// get_read_conn
+    ///
+    /// Get a connection for a read-only query from the next
+    /// available replica
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - caller logging label
+    ///
+    /// # Returns
+    ///
+    /// `Option<`[`PooledConnection`](bb8::PooledConnection)`>` -
+    /// `None` when no replicas are configured or every replica
+    /// is down, so the caller uses its primary connection
+    ///
+    /// # Examples
+    ///
+    /// ```rust,ignore
+    /// let read_conn = db_read_pools.get_read_conn(tracking_label).await;
+    /// let read_conn = read_conn.as_ref().unwrap_or(&conn);
+    /// ```
+    ///
+    pub async fn get_read_conn(
+        &self,
+        tracking_label: &str,
+    ) -> Option<ReadConn<'_>> {
+        let num_replicas = self.replicas.len();
+        if num_replicas == 0 {
+            return None;
+        }
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        for offset in 0..num_replicas {
+            let replica = &self.replicas[(start + offset) % num_replicas];
+            let now = chrono::Utc::now().timestamp_millis();
+            if replica.down_until.load(Ordering::Relaxed) > now {
+                continue;
+            }
+            match replica.pool.get().await {
+                Ok(conn) => {
+                    DB_READ_POOL_COUNTER_VEC
+                        .with_label_values(&["replica"])
+                        .inc();
+                    return Some(conn);
+                }
+                Err(e) => {
+                    warn!(
+                        "{tracking_label} - \
+                        read replica {} is down - retrying in {}s \
+                        with err='{e}'",
+                        replica.address, self.retry_seconds
+                    );
+                    replica.down_until.store(
+                        now + (self.retry_seconds * 1000) as i64,
+                        Ordering::Relaxed,
+                    );
+                }
+            }
+        }
+        DB_READ_POOL_COUNTER_VEC
+            .with_label_values(&["primary"])
+            .inc();
+        None
+    }
+}
+
+/// get_db_read_pools
+///
+/// Build a bb8 threadpool ([`Pool`](bb8::Pool)) for each
+/// read replica in ``POSTGRES_READ_ENDPOINTS`` with the
+/// primary's credentials, database name and
+/// [`MakeTlsConnector`](postgres_native_tls::MakeTlsConnector)
+///
+/// Replica pools connect on first use so the server starts
+/// while a replica is down.
+///
+/// # Arguments
+///
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+///
+/// # Returns
+///
+/// [`DbReadPools`](crate::pools::db_read_pools::DbReadPools)
+///
+pub fn get_db_read_pools(config: &CoreConfig) -> DbReadPools {
+    let replicas = config
+        .db_read_addresses
+        .iter()
+        .map(|db_address| {
+            let (db_conn_str, db_conn_no_password) =
+                get_db_conn_str_for_address(config, db_address);
+            info!(
+                "using postgres read replica: {db_conn_no_password} \
+                with db_tls_ca={}",
+                config.db_config.ca_path
+            );
+            let pg_mgr = PostgresConnectionManager::new_from_stringlike(
+                db_conn_str,
+                get_db_tls_connector(config),
+            )
+            .unwrap();
+            DbReadReplica {
+                address: db_address.clone(),
+                pool: Pool::builder()
+                    .connection_timeout(Duration::from_millis(
+                        config.db_read_timeout_ms,
+                    ))
+                    .build_unchecked(pg_mgr),
+                down_until: Arc::new(AtomicI64::new(0)),
+            }
+        })
+        .collect();
+    DbReadPools {
+        replicas,
+        retry_seconds: config.db_read_retry_seconds,
+        next: Arc::new(AtomicUsize::new(0)),
+    }
+}
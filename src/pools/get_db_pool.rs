@@ -9,6 +9,85 @@ use bb8::Pool;
 use bb8_postgres::PostgresConnectionManager;
 
 use crate::core::core_config::CoreConfig;
+use crate::pools::db_query_limits::get_statement_timeout_options;
+use crate::pools::db_query_limits::set_slow_query_ms;
+
+/// get_db_tls_connector
+///
+/// Build a
+/// [`MakeTlsConnector`](postgres_native_tls::MakeTlsConnector)
+/// that trusts the postgres certificate authority
+/// (``DB_TLS_CA``)
+///
+/// # Arguments
+///
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+///
+pub fn get_db_tls_connector(config: &CoreConfig) -> MakeTlsConnector {
+    let ca_bytes = std::fs::read(&config.db_config.ca_path).unwrap();
+    let db_tls_ca = native_tls_cert::from_pem(&ca_bytes).unwrap();
+    // use the certificate authority file
+    let connector = TlsConnector::builder()
+        .add_root_certificate(db_tls_ca)
+        .build()
+        .unwrap();
+    MakeTlsConnector::new(connector)
+}
+
+/// get_db_conn_str
+///
+/// Build the postgres connection string (with the
+/// ``statement_timeout`` from ``POSTGRES_STATEMENT_TIMEOUT_MS``)
+///
+/// # Arguments
+///
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+///
+/// # Returns
+///
+/// (db_conn_str: `String`, db_conn_no_password: `String`) -
+/// the connection string and a redacted copy for logging
+///
+pub fn get_db_conn_str(config: &CoreConfig) -> (String, String) {
+    get_db_conn_str_for_address(config, &config.db_address)
+}
+
+/// get_db_conn_str_for_address
+///
+/// Build the postgres connection string for another
+/// postgres address (like a read replica) with the
+/// primary's credentials and database name
+///
+/// # Arguments
+///
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_address` - `&str` - postgres ``host:port``
+///
+/// # Returns
+///
+/// (db_conn_str: `String`, db_conn_no_password: `String`) -
+/// the connection string and a redacted copy for logging
+///
+pub fn get_db_conn_str_for_address(
+    config: &CoreConfig,
+    db_address: &str,
+) -> (String, String) {
+    let options = get_statement_timeout_options(config.db_statement_timeout_ms);
+    let db_conn_no_password = format!(
+        "{}://{}:REDACTED@{db_address}/{}?\
+        sslmode=require{options}",
+        config.db_conn_type, config.db_username, config.db_name
+    );
+    let db_conn_str = format!(
+        "{}://{}:{}@{db_address}/{}?\
+        sslmode=require{options}",
+        config.db_conn_type,
+        config.db_username,
+        config.db_password,
+        config.db_name
+    );
+    (db_conn_str, db_conn_no_password)
+}
 
 /// get_db_pool
 ///
@@ -16,6 +95,8 @@ use crate::core::core_config::CoreConfig;
 /// [`PostgresConnectionManager`](bb8_postgres::PostgresConnectionManager)
 /// client with tls encryption implemented using
 /// [`MakeTlsConnector`](postgres_native_tls::MakeTlsConnector)
+/// and set the slow query logging threshold from
+/// ``POSTGRES_SLOW_QUERY_MS``
 ///
 /// # Arguments
 ///
@@ -27,7 +108,7 @@ use crate::core::core_config::CoreConfig;
 ///
 /// # Examples
 ///
-/// ```rust
+/// ```rust,no_run
 /// use restapi::core::core_config::build_core_config;
 /// use restapi::pools::get_db_pool::get_db_pool;
 /// let config = tokio_test::block_on(
@@ -41,36 +122,14 @@ use crate::core::core_config::CoreConfig;
 pub async fn get_db_pool(
     config: &CoreConfig,
 ) -> Pool<PostgresConnectionManager<MakeTlsConnector>> {
-    let ca_bytes = std::fs::read(&config.db_config.ca_path).unwrap();
-    let db_tls_ca = native_tls_cert::from_pem(&ca_bytes).unwrap();
-    // use the certificate authority file
-    let connector = TlsConnector::builder()
-        .add_root_certificate(db_tls_ca)
-        .build()
-        .unwrap();
-    let connector = MakeTlsConnector::new(connector);
-    let db_conn_no_password = format!(
-        "{}://{}:REDACTED@{}/{}?\
-        sslmode=require",
-        config.db_conn_type,
-        config.db_username,
-        config.db_address,
-        config.db_name
-    );
-    let db_conn_str = format!(
-        "{}://{}:{}@{}/{}?\
-        sslmode=require",
-        config.db_conn_type,
-        config.db_username,
-        config.db_password,
-        config.db_address,
-        config.db_name
-    );
+    let connector = get_db_tls_connector(config);
+    let (db_conn_str, db_conn_no_password) = get_db_conn_str(config);
     info!(
         "connecting to postgres: {db_conn_no_password} \
         with db_tls_ca={}",
         config.db_config.ca_path
     );
+    set_slow_query_ms(config.db_slow_query_ms);
     let pg_mgr =
         PostgresConnectionManager::new_from_stringlike(db_conn_str, connector)
             .unwrap();
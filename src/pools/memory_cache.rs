@@ -0,0 +1,96 @@
+//! In-process LRU implementation of the
+//! [`Cache`](crate::pools::cache::Cache) trait
+//!
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use lru::LruCache;
+
+use crate::pools::cache::Cache;
+use crate::pools::cache::CacheFuture;
+
+/// MemoryCache
+///
+/// LRU cache that evicts the least recently used key once
+/// it holds `max_entries` keys. Expired keys are removed
+/// when they are read.
+///
+/// # Usage
+///
+/// ```rust
+/// use restapi::pools::cache::Cache;
+/// use restapi::pools::memory_cache::MemoryCache;
+/// # tokio_test::block_on(async {
+/// let cache = MemoryCache::new(2);
+/// cache.set("a", "1", 30).await;
+/// cache.set("b", "2", 30).await;
+/// cache.set("c", "3", 30).await;
+/// assert_eq!(cache.get("a").await, None);
+/// assert_eq!(cache.get("c").await, Some("3".to_string()));
+/// cache.delete("c").await;
+/// assert_eq!(cache.get("c").await, None);
+/// # });
+/// ```
+///
+pub struct MemoryCache {
+    entries: Mutex<LruCache<String, (String, Instant)>>,
+}
+
+impl MemoryCache {
+    /// new
+    ///
+    /// Create an empty cache
+    ///
+    /// # Arguments
+    ///
+    /// * `max_entries` - `usize` - max keys before evicting
+    ///   the least recently used key (minimum `1`)
+    ///
+    pub fn new(max_entries: usize) -> Self {
+        let max_entries = NonZeroUsize::new(max_entries.max(1)).unwrap();
+        MemoryCache {
+            entries: Mutex::new(LruCache::new(max_entries)),
+        }
+    }
+}
+
+impl Cache for MemoryCache {
+    fn get<'a>(&'a self, key: &'a str) -> CacheFuture<'a, Option<String>> {
+        Box::pin(async move {
+            let mut entries = self.entries.lock().unwrap();
+            match entries.get(key) {
+                Some((value, expires_at)) if *expires_at > Instant::now() => {
+                    Some(value.clone())
+                }
+                Some(_) => {
+                    entries.pop(key);
+                    None
+                }
+                None => None,
+            }
+        })
+    }
+
+    fn set<'a>(
+        &'a self,
+        key: &'a str,
+        value: &'a str,
+        ttl_seconds: u64,
+    ) -> CacheFuture<'a, ()> {
+        Box::pin(async move {
+            let expires_at = Instant::now() + Duration::from_secs(ttl_seconds);
+            self.entries
+                .lock()
+                .unwrap()
+                .put(key.to_string(), (value.to_string(), expires_at));
+        })
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> CacheFuture<'a, ()> {
+        Box::pin(async move {
+            self.entries.lock().unwrap().pop(key);
+        })
+    }
+}
@@ -1,3 +1,15 @@
 //! Wrapper for starting up the bb8 postgres threadpool
+//! (and optional read replica pools), migrating the schema,
+//! checking the expected db indexes exist, timing queries
+//! and caching hot lookups
 //!
+pub mod cache;
+pub mod check_db_indexes;
+pub mod db_query_limits;
+pub mod db_read_pools;
 pub mod get_db_pool;
+pub mod memory_cache;
+#[cfg(feature = "redis")]
+pub mod redis;
+pub mod run_migrations;
+pub mod user_cache;
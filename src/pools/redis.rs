@@ -0,0 +1,134 @@
+//! Redis implementation of the
+//! [`Cache`](crate::pools::cache::Cache) trait
+//! shared by every api server in the cluster
+//!
+//! Build with the ``redis`` feature:
+//!
+//! ```bash
+//! cargo build --features redis
+//! ```
+//!
+use std::sync::Mutex;
+
+use redis::aio::MultiplexedConnection;
+use redis::AsyncCommands;
+use redis::Client;
+
+use crate::pools::cache::Cache;
+use crate::pools::cache::CacheFuture;
+
+/// RedisCache
+///
+/// [`Cache`](crate::pools::cache::Cache) backed by redis.
+/// The multiplexed connection is opened on first use and
+/// reopened after an error, so the api server starts
+/// (and serves requests from postgres) while redis is down.
+///
+pub struct RedisCache {
+    client: Client,
+    conn: Mutex<Option<MultiplexedConnection>>,
+}
+
+impl RedisCache {
+    /// new
+    ///
+    /// Create a redis cache without connecting
+    ///
+    /// # Arguments
+    ///
+    /// * `redis_url` - `&str` - redis url
+    ///   (``redis://127.0.0.1:6379`` or ``rediss://`` for tls)
+    ///
+    /// # Returns
+    ///
+    /// Ok([`RedisCache`](crate::pools::redis::RedisCache))
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) for an invalid `redis_url`
+    ///
+    pub fn new(redis_url: &str) -> Result<Self, String> {
+        match Client::open(redis_url) {
+            Ok(client) => Ok(RedisCache {
+                client,
+                conn: Mutex::new(None),
+            }),
+            Err(e) => Err(format!(
+                "invalid CACHE_REDIS_URL={redis_url} with err='{e}'"
+            )),
+        }
+    }
+
+    /// get_conn
+    ///
+    /// Clone the shared connection or open a new one
+    ///
+    async fn get_conn(&self) -> Option<MultiplexedConnection> {
+        if let Some(conn) = self.conn.lock().unwrap().as_ref() {
+            return Some(conn.clone());
+        }
+        match self.client.get_multiplexed_tokio_connection().await {
+            Ok(conn) => {
+                *self.conn.lock().unwrap() = Some(conn.clone());
+                Some(conn)
+            }
+            Err(e) => {
+                error!("failed to connect to redis cache with err='{e}'");
+                None
+            }
+        }
+    }
+
+    /// reset_conn
+    ///
+    /// Drop the shared connection after an error so the
+    /// next request reconnects
+    ///
+    fn reset_conn(&self, action: &str, key: &str, e: redis::RedisError) {
+        error!("redis cache {action} key={key} failed with err='{e}'");
+        *self.conn.lock().unwrap() = None;
+    }
+}
+
+impl Cache for RedisCache {
+    fn get<'a>(&'a self, key: &'a str) -> CacheFuture<'a, Option<String>> {
+        Box::pin(async move {
+            let mut conn = self.get_conn().await?;
+            match conn.get::<_, Option<String>>(key).await {
+                Ok(value) => value,
+                Err(e) => {
+                    self.reset_conn("get", key, e);
+                    None
+                }
+            }
+        })
+    }
+
+    fn set<'a>(
+        &'a self,
+        key: &'a str,
+        value: &'a str,
+        ttl_seconds: u64,
+    ) -> CacheFuture<'a, ()> {
+        Box::pin(async move {
+            if let Some(mut conn) = self.get_conn().await {
+                if let Err(e) = conn
+                    .set_ex::<_, _, ()>(key, value, ttl_seconds.max(1) as usize)
+                    .await
+                {
+                    self.reset_conn("set", key, e);
+                }
+            }
+        })
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> CacheFuture<'a, ()> {
+        Box::pin(async move {
+            if let Some(mut conn) = self.get_conn().await {
+                if let Err(e) = conn.del::<_, ()>(key).await {
+                    self.reset_conn("delete", key, e);
+                }
+            }
+        })
+    }
+}
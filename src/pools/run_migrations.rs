@@ -0,0 +1,435 @@
+//! Apply the db schema and the ``docker/db/sql/migrations``
+//! sql files without psql
+//!
+//! [`run_migrations`](crate::pools::run_migrations::run_migrations)
+//! creates every table from ``docker/db/sql/init.sql`` on an
+//! empty db and otherwise applies each migration that is not
+//! recorded in the ``schema_migrations`` table yet. Every
+//! migration only adds missing tables, columns and indexes, so
+//! dbs created before ``schema_migrations`` existed are safe to
+//! migrate. The db and the api's db user must already exist
+//! (``docker/db`` creates both).
+//!
+use crate::monitoring::otel::trace_db_query;
+
+/// the schema for an empty db
+pub const DB_SCHEMA: &str = include_str!("../../docker/db/sql/init.sql");
+
+/// list of `(name, sql)` migrations in the order they are applied
+pub const DB_MIGRATIONS: [(&str, &str); 31] = [
+    (
+        "0001_search_indexes",
+        include_str!("../../docker/db/sql/migrations/0001_search_indexes.sql"),
+    ),
+    (
+        "0002_users_invites",
+        include_str!("../../docker/db/sql/migrations/0002_users_invites.sql"),
+    ),
+    (
+        "0003_users_otp_single_active",
+        include_str!(
+            "../../docker/db/sql/migrations/0003_users_otp_single_active.sql"
+        ),
+    ),
+    (
+        "0004_users_tokens_kid",
+        include_str!(
+            "../../docker/db/sql/migrations/0004_users_tokens_kid.sql"
+        ),
+    ),
+    (
+        "0005_users_data_status",
+        include_str!(
+            "../../docker/db/sql/migrations/0005_users_data_status.sql"
+        ),
+    ),
+    (
+        "0006_users_notifications",
+        include_str!(
+            "../../docker/db/sql/migrations/0006_users_notifications.sql"
+        ),
+    ),
+    (
+        "0007_tenants",
+        include_str!("../../docker/db/sql/migrations/0007_tenants.sql"),
+    ),
+    (
+        "0008_row_versions",
+        include_str!("../../docker/db/sql/migrations/0008_row_versions.sql"),
+    ),
+    (
+        "0009_users_data_checksum",
+        include_str!(
+            "../../docker/db/sql/migrations/0009_users_data_checksum.sql"
+        ),
+    ),
+    (
+        "0010_users_data_lifecycle",
+        include_str!(
+            "../../docker/db/sql/migrations/0010_users_data_lifecycle.sql"
+        ),
+    ),
+    (
+        "0011_users_data_scan",
+        include_str!("../../docker/db/sql/migrations/0011_users_data_scan.sql"),
+    ),
+    (
+        "0012_users_data_derivatives",
+        include_str!(
+            "../../docker/db/sql/migrations/0012_users_data_derivatives.sql"
+        ),
+    ),
+    (
+        "0013_users_data_uploads",
+        include_str!(
+            "../../docker/db/sql/migrations/0013_users_data_uploads.sql"
+        ),
+    ),
+    (
+        "0014_users_data_shares",
+        include_str!(
+            "../../docker/db/sql/migrations/0014_users_data_shares.sql"
+        ),
+    ),
+    (
+        "0015_users_data_tags",
+        include_str!("../../docker/db/sql/migrations/0015_users_data_tags.sql"),
+    ),
+    (
+        "0016_users_quota",
+        include_str!("../../docker/db/sql/migrations/0016_users_quota.sql"),
+    ),
+    (
+        "0017_users_delete_requests",
+        include_str!(
+            "../../docker/db/sql/migrations/0017_users_delete_requests.sql"
+        ),
+    ),
+    (
+        "0018_users_otp_attempts",
+        include_str!(
+            "../../docker/db/sql/migrations/0018_users_otp_attempts.sql"
+        ),
+    ),
+    (
+        "0019_webhooks",
+        include_str!("../../docker/db/sql/migrations/0019_webhooks.sql"),
+    ),
+    (
+        "0020_kafka_dead_letters",
+        include_str!(
+            "../../docker/db/sql/migrations/0020_kafka_dead_letters.sql"
+        ),
+    ),
+    (
+        "0021_users_tokens_consumed",
+        include_str!(
+            "../../docker/db/sql/migrations/0021_users_tokens_consumed.sql"
+        ),
+    ),
+    (
+        "0022_users_locale",
+        include_str!("../../docker/db/sql/migrations/0022_users_locale.sql"),
+    ),
+    (
+        "0023_users_tokens_geo",
+        include_str!(
+            "../../docker/db/sql/migrations/0023_users_tokens_geo.sql"
+        ),
+    ),
+    (
+        "0024_users_data_broken",
+        include_str!(
+            "../../docker/db/sql/migrations/0024_users_data_broken.sql"
+        ),
+    ),
+    (
+        "0025_users_data_versions",
+        include_str!(
+            "../../docker/db/sql/migrations/0025_users_data_versions.sql"
+        ),
+    ),
+    (
+        "0026_users_data_access",
+        include_str!(
+            "../../docker/db/sql/migrations/0026_users_data_access.sql"
+        ),
+    ),
+    (
+        "0027_users_data_uploading_sloc",
+        include_str!(
+            "../../docker/db/sql/migrations/0027_users_data_uploading_sloc.sql"
+        ),
+    ),
+    (
+        "0028_users_password_scheme",
+        include_str!(
+            "../../docker/db/sql/migrations/0028_users_password_scheme.sql"
+        ),
+    ),
+    (
+        "0029_users_logins",
+        include_str!("../../docker/db/sql/migrations/0029_users_logins.sql"),
+    ),
+    (
+        "0030_users_admin_actions",
+        include_str!(
+            "../../docker/db/sql/migrations/0030_users_admin_actions.sql"
+        ),
+    ),
+    (
+        "0031_ip_rules",
+        include_str!("../../docker/db/sql/migrations/0031_ip_rules.sql"),
+    ),
+];
+
+/// advisory lock id held while migrating so only one api
+/// server (or operator) migrates the db at a time
+const DB_MIGRATIONS_LOCK_ID: i64 = 4_242_001;
+
+/// run_migrations
+///
+/// Create the schema on an empty db or apply the pending
+/// [`DB_MIGRATIONS`](crate::pools::run_migrations::DB_MIGRATIONS)
+/// and record them in the ``schema_migrations`` table.
+/// Statements that create the db or its users are skipped.
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `client` - [`Client`](tokio_postgres::Client) - db
+///   connection (a pooled connection works too)
+///
+/// # Returns
+///
+/// Ok(`Vec<String>`) - names of the applied migrations
+/// (``init`` when the schema was created)
+///
+/// # Errors
+///
+/// Err(err_msg: `String`) when a sql statement fails
+///
+pub async fn run_migrations(
+    tracking_label: &str,
+    client: &tokio_postgres::Client,
+) -> Result<Vec<String>, String> {
+    execute_statement(
+        tracking_label,
+        client,
+        &format!("SELECT pg_advisory_lock({DB_MIGRATIONS_LOCK_ID})"),
+    )
+    .await?;
+    let result = apply_pending_migrations(tracking_label, client).await;
+    execute_statement(
+        tracking_label,
+        client,
+        &format!("SELECT pg_advisory_unlock({DB_MIGRATIONS_LOCK_ID})"),
+    )
+    .await?;
+    result
+}
+
+/// apply_pending_migrations
+///
+/// Apply the schema or pending migrations while holding the
+/// migrations lock
+///
+async fn apply_pending_migrations(
+    tracking_label: &str,
+    client: &tokio_postgres::Client,
+) -> Result<Vec<String>, String> {
+    let query = "SELECT to_regclass('public.users') IS NOT NULL AS exists";
+    let has_schema: bool =
+        match trace_db_query(query, client.query_one(query, &[])).await {
+            Ok(row) => row.try_get("exists").unwrap_or(false),
+            Err(e) => {
+                return Err(format!(
+                    "{tracking_label} - \
+                    failed to check for an existing schema with err='{e}'"
+                ));
+            }
+        };
+    execute_statement(
+        tracking_label,
+        client,
+        "CREATE TABLE IF NOT EXISTS schema_migrations (\
+            name VARCHAR(256) NOT NULL, \
+            applied_at timestamp with time zone \
+                DEFAULT timezone('UTC'::text, now()) NOT NULL, \
+            PRIMARY KEY(name))",
+    )
+    .await?;
+
+    let mut applied: Vec<String> = Vec::new();
+    if !has_schema {
+        info!("{tracking_label} - creating the db schema");
+        apply_sql(tracking_label, client, "init", DB_SCHEMA).await?;
+        applied.push("init".to_string());
+    }
+
+    let query = "SELECT name FROM schema_migrations";
+    let recorded: Vec<String> =
+        match trace_db_query(query, client.query(query, &[])).await {
+            Ok(rows) => rows
+                .iter()
+                .map(|row| row.try_get("name").unwrap_or_default())
+                .collect(),
+            Err(e) => {
+                return Err(format!(
+                    "{tracking_label} - \
+                    failed to get the applied migrations with err='{e}'"
+                ));
+            }
+        };
+    for (name, sql) in DB_MIGRATIONS.iter() {
+        if recorded.iter().any(|v| v == name) {
+            continue;
+        }
+        // the schema already has every migration
+        if has_schema {
+            info!("{tracking_label} - applying migration {name}");
+            apply_sql(tracking_label, client, name, sql).await?;
+            applied.push(name.to_string());
+        }
+        execute_statement(
+            tracking_label,
+            client,
+            &format!(
+                "INSERT INTO schema_migrations (name) VALUES ('{name}') \
+                ON CONFLICT (name) DO NOTHING"
+            ),
+        )
+        .await?;
+    }
+    Ok(applied)
+}
+
+/// apply_sql
+///
+/// Run each statement in a sql file one at a time
+///
+async fn apply_sql(
+    tracking_label: &str,
+    client: &tokio_postgres::Client,
+    name: &str,
+    sql: &str,
+) -> Result<(), String> {
+    for statement in split_sql_statements(sql) {
+        if is_db_bootstrap_statement(&statement) {
+            continue;
+        }
+        if let Err(e) = client.batch_execute(&statement).await {
+            return Err(format!(
+                "{tracking_label} - \
+                migration {name} failed on statement='{statement}' \
+                with err='{e}'"
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// execute_statement
+///
+/// Run one sql statement
+///
+async fn execute_statement(
+    tracking_label: &str,
+    client: &tokio_postgres::Client,
+    statement: &str,
+) -> Result<(), String> {
+    match trace_db_query(statement, client.batch_execute(statement)).await {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!(
+            "{tracking_label} - \
+            failed to run statement='{statement}' with err='{e}'"
+        )),
+    }
+}
+
+/// is_db_bootstrap_statement
+///
+/// Check if a statement creates the db or its users (which
+/// need a superuser and already happened before the api can
+/// connect)
+///
+/// # Arguments
+///
+/// * `statement` - `&str` - sql statement
+///
+/// # Examples
+///
+/// ```rust
+/// use restapi::pools::run_migrations::is_db_bootstrap_statement;
+/// assert!(is_db_bootstrap_statement("CREATE DATABASE mydb"));
+/// assert!(!is_db_bootstrap_statement("CREATE TABLE users (id INT)"));
+/// ```
+///
+pub fn is_db_bootstrap_statement(statement: &str) -> bool {
+    let statement = statement.to_uppercase();
+    ["CREATE DATABASE", "CREATE USER", "ALTER USER", "GRANT "]
+        .iter()
+        .any(|prefix| statement.starts_with(prefix))
+}
+
+/// split_sql_statements
+///
+/// Split a sql file into statements that can be run one at
+/// a time (so ``CREATE INDEX CONCURRENTLY`` is not run in a
+/// transaction). ``--`` comments are removed and semicolons
+/// in quoted strings or ``$$`` function bodies are kept.
+///
+/// # Arguments
+///
+/// * `sql` - `&str` - sql file contents
+///
+/// # Examples
+///
+/// ```rust
+/// use restapi::pools::run_migrations::split_sql_statements;
+/// let statements = split_sql_statements(
+///     "-- comment\nSELECT 'a;b';\nCREATE FUNCTION f() AS $$ x; $$;",
+/// );
+/// assert_eq!(
+///     statements,
+///     vec!["SELECT 'a;b'", "CREATE FUNCTION f() AS $$ x; $$"]
+/// );
+/// ```
+///
+pub fn split_sql_statements(sql: &str) -> Vec<String> {
+    let mut statements = Vec::new();
+    let mut cur = String::new();
+    let mut in_quote = false;
+    let mut in_dollar = false;
+    for line in sql.lines() {
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            if !in_quote && !in_dollar && c == '-' && chars.peek() == Some(&'-')
+            {
+                break;
+            }
+            if !in_dollar && c == '\'' {
+                in_quote = !in_quote;
+            } else if !in_quote && c == '$' && chars.peek() == Some(&'$') {
+                chars.next();
+                cur.push_str("$$");
+                in_dollar = !in_dollar;
+                continue;
+            } else if !in_quote && !in_dollar && c == ';' {
+                let statement = cur.trim().to_string();
+                if !statement.is_empty() {
+                    statements.push(statement);
+                }
+                cur.clear();
+                continue;
+            }
+            cur.push(c);
+        }
+        cur.push('\n');
+    }
+    let statement = cur.trim().to_string();
+    if !statement.is_empty() {
+        statements.push(statement);
+    }
+    statements
+}
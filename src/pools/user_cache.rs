@@ -0,0 +1,356 @@
+//! Cache the user lookup and token check done on
+//! every authenticated request
+//!
+//! [`validate_user_token`](crate::requests::auth::validate_user_token::validate_user_token)
+//! reads the user and touches the user's session in postgres
+//! on every request. With a cache enabled, the user record
+//! and the hashes of the tokens that were found active are
+//! cached under one key per user for ``CACHE_TTL_SECONDS``,
+//! so a token's ``users_tokens.last_used_at`` is updated at
+//! most once per ttl.
+//!
+//! Handlers that change a user, or revoke a user's tokens,
+//! must call
+//! [`invalidate_user`](crate::pools::user_cache::UserCache::invalidate_user)
+//! after the change.
+//!
+use std::sync::Arc;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::PooledConnection;
+use bb8_postgres::PostgresConnectionManager;
+
+use lazy_static::lazy_static;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::monitoring::metric_types::register_int_counter_vec;
+use crate::monitoring::metric_types::IntCounterVec;
+use crate::pools::cache::Cache;
+use crate::pools::memory_cache::MemoryCache;
+use crate::requests::models::user::get_user_by_id;
+use crate::requests::models::user::ModelUser;
+
+lazy_static! {
+    pub static ref CACHE_REQUESTS_COUNTER_VEC: IntCounterVec =
+        register_int_counter_vec!(
+            "cache_requests_total",
+            "Number of user cache lookups by kind and result.",
+            &["kind", "result"]
+        )
+        .unwrap();
+}
+
+/// supported ``CACHE_BACKEND`` values
+pub const CACHE_BACKENDS: [&str; 3] = ["none", "memory", "redis"];
+
+/// max token hashes cached per user
+pub const MAX_CACHED_TOKENS_PER_USER: usize = 16;
+
+/// CachedUser
+///
+/// Value stored in the cache for each user
+///
+/// # Arguments
+///
+/// * `user` - [`ModelUser`](crate::requests::models::user::ModelUser) -
+///   user record without the ``password`` hash
+/// * `token_hashes` - `Vec<String>` - sha256 hashes of tokens
+///   that were found active in ``users_tokens``
+/// * `cached_at` - `i64` - unix time the user was read from
+///   postgres
+///
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CachedUser {
+    pub user: ModelUser,
+    pub token_hashes: Vec<String>,
+    pub cached_at: i64,
+}
+
+impl CachedUser {
+    /// has_token
+    ///
+    /// Check if the token was cached as active
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - `&str` - the client's jwt
+    ///
+    pub fn has_token(&self, token: &str) -> bool {
+        self.token_hashes.contains(&hash_token(token))
+    }
+}
+
+/// hash_token
+///
+/// Hex-encoded sha256 of a token so raw tokens are
+/// never stored in the cache
+///
+fn hash_token(token: &str) -> String {
+    openssl::sha::sha256(token.as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// UserCache
+///
+/// Optional cache for user lookups and token checks
+///
+/// # Supported Environment Variables
+///
+/// ```bash
+/// # none, memory or redis
+/// export CACHE_BACKEND="none"
+/// export CACHE_TTL_SECONDS="30"
+/// # max users in the memory cache
+/// export CACHE_MAX_ENTRIES="10000"
+/// # requires building with --features redis
+/// export CACHE_REDIS_URL="redis://127.0.0.1:6379"
+/// export CACHE_KEY_PREFIX="restapi"
+/// ```
+///
+/// # Arguments
+///
+/// * `backend` - `String` - ``none``, ``memory`` or ``redis``
+/// * `ttl_seconds` - `i64` - seconds a user stays cached
+/// * `key_prefix` - `String` - prefix for cache keys
+/// * `cache` - `Option<Arc<dyn `[`Cache`](crate::pools::cache::Cache)`>>` -
+///   cache backend (`None` reads postgres every time)
+///
+#[derive(Clone, Default)]
+pub struct UserCache {
+    pub backend: String,
+    pub ttl_seconds: i64,
+    pub key_prefix: String,
+    pub cache: Option<Arc<dyn Cache>>,
+}
+
+impl UserCache {
+    /// build_user_cache
+    ///
+    /// Build the
+    /// [`UserCache`](crate::pools::user_cache::UserCache)
+    /// from environment variables
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) for an unsupported ``CACHE_BACKEND``,
+    /// ``redis`` without the ``redis`` feature or an invalid
+    /// ``CACHE_REDIS_URL``
+    ///
+    pub fn build_user_cache() -> Result<Self, String> {
+        let get_env = |key: &str, default: &str| {
+            std::env::var(key).unwrap_or_else(|_| default.to_string())
+        };
+        let backend = get_env("CACHE_BACKEND", "none").trim().to_lowercase();
+        let ttl_seconds = get_env("CACHE_TTL_SECONDS", "30")
+            .parse::<i64>()
+            .unwrap_or(30)
+            .max(1);
+        let max_entries = get_env("CACHE_MAX_ENTRIES", "10000")
+            .parse::<usize>()
+            .unwrap_or(10000);
+        let redis_url = get_env("CACHE_REDIS_URL", "redis://127.0.0.1:6379");
+        let cache: Option<Arc<dyn Cache>> = match backend.as_str() {
+            "none" => None,
+            "memory" => Some(Arc::new(MemoryCache::new(max_entries))),
+            "redis" => Some(build_redis_cache(&redis_url)?),
+            _ => {
+                return Err(format!(
+                    "unsupported CACHE_BACKEND={backend} - supported: {}",
+                    CACHE_BACKENDS.join(", ")
+                ));
+            }
+        };
+        Ok(UserCache {
+            backend,
+            ttl_seconds,
+            key_prefix: get_env("CACHE_KEY_PREFIX", "restapi"),
+            cache,
+        })
+    }
+
+    /// get_user_key
+    ///
+    /// Cache key for a user
+    ///
+    fn get_user_key(&self, user_id: i32) -> String {
+        format!("{}:user:{user_id}", self.key_prefix)
+    }
+
+    /// get_user
+    ///
+    /// Get a user from the cache or from postgres with
+    /// [`get_user_by_id`](crate::requests::models::user::get_user_by_id)
+    /// (and cache it)
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - caller logging label
+    /// * `user_id` - `i32` - user id
+    /// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+    ///   an established db connection from the
+    ///   postgres client db threadpool
+    ///
+    /// # Returns
+    ///
+    /// Ok([`CachedUser`](crate::pools::user_cache::CachedUser))
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) from
+    /// [`get_user_by_id`](crate::requests::models::user::get_user_by_id)
+    ///
+    pub async fn get_user(
+        &self,
+        tracking_label: &str,
+        user_id: i32,
+        conn: &PooledConnection<
+            '_,
+            PostgresConnectionManager<MakeTlsConnector>,
+        >,
+    ) -> Result<CachedUser, String> {
+        let now = chrono::Utc::now().timestamp();
+        let cache = match &self.cache {
+            Some(cache) => cache,
+            None => {
+                return Ok(CachedUser {
+                    user: get_user_by_id(tracking_label, user_id, conn).await?,
+                    token_hashes: Vec::new(),
+                    cached_at: now,
+                });
+            }
+        };
+        let key = self.get_user_key(user_id);
+        if let Some(value) = cache.get(&key).await {
+            match serde_json::from_str::<CachedUser>(&value) {
+                Ok(cached_user)
+                    if now - cached_user.cached_at < self.ttl_seconds =>
+                {
+                    CACHE_REQUESTS_COUNTER_VEC
+                        .with_label_values(&["user", "hit"])
+                        .inc();
+                    return Ok(cached_user);
+                }
+                _ => cache.delete(&key).await,
+            }
+        }
+        CACHE_REQUESTS_COUNTER_VEC
+            .with_label_values(&["user", "miss"])
+            .inc();
+        let mut user = get_user_by_id(tracking_label, user_id, conn).await?;
+        // password hashes are never cached
+        user.password = String::new();
+        let cached_user = CachedUser {
+            user,
+            token_hashes: Vec::new(),
+            cached_at: now,
+        };
+        self.set_user(&cached_user).await;
+        Ok(cached_user)
+    }
+
+    /// set_user
+    ///
+    /// Store a user until ``cached_at`` + ``ttl_seconds``
+    ///
+    async fn set_user(&self, cached_user: &CachedUser) {
+        if let Some(cache) = &self.cache {
+            let ttl_seconds = self.ttl_seconds
+                - (chrono::Utc::now().timestamp() - cached_user.cached_at);
+            if ttl_seconds > 0 {
+                cache
+                    .set(
+                        &self.get_user_key(cached_user.user.id),
+                        &serde_json::to_string(cached_user).unwrap(),
+                        ttl_seconds as u64,
+                    )
+                    .await;
+            }
+        }
+    }
+
+    /// check_token
+    ///
+    /// Check if a token was cached as active for the user
+    /// and record the hit or miss
+    ///
+    /// # Arguments
+    ///
+    /// * `cached_user` - [`CachedUser`](crate::pools::user_cache::CachedUser)
+    /// * `token` - `&str` - the client's jwt
+    ///
+    pub fn check_token(&self, cached_user: &CachedUser, token: &str) -> bool {
+        if self.cache.is_none() {
+            return false;
+        }
+        let found = cached_user.has_token(token);
+        CACHE_REQUESTS_COUNTER_VEC
+            .with_label_values(&["token", if found { "hit" } else { "miss" }])
+            .inc();
+        found
+    }
+
+    /// add_token
+    ///
+    /// Cache a token that was found active in ``users_tokens``
+    /// (keeps the newest
+    /// [`MAX_CACHED_TOKENS_PER_USER`](crate::pools::user_cache::MAX_CACHED_TOKENS_PER_USER)
+    /// tokens)
+    ///
+    /// # Arguments
+    ///
+    /// * `cached_user` - [`CachedUser`](crate::pools::user_cache::CachedUser)
+    /// * `token` - `&str` - the client's jwt
+    ///
+    pub async fn add_token(&self, mut cached_user: CachedUser, token: &str) {
+        if self.cache.is_none() {
+            return;
+        }
+        cached_user.token_hashes.push(hash_token(token));
+        let num_tokens = cached_user.token_hashes.len();
+        if num_tokens > MAX_CACHED_TOKENS_PER_USER {
+            cached_user
+                .token_hashes
+                .drain(..num_tokens - MAX_CACHED_TOKENS_PER_USER);
+        }
+        self.set_user(&cached_user).await;
+    }
+
+    /// invalidate_user
+    ///
+    /// Remove a cached user and the user's cached tokens
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - `i32` - user id
+    ///
+    pub async fn invalidate_user(&self, user_id: i32) {
+        if let Some(cache) = &self.cache {
+            cache.delete(&self.get_user_key(user_id)).await;
+        }
+    }
+}
+
+/// build_redis_cache
+///
+/// Create the ``redis`` backend
+///
+#[cfg(feature = "redis")]
+fn build_redis_cache(redis_url: &str) -> Result<Arc<dyn Cache>, String> {
+    Ok(Arc::new(crate::pools::redis::RedisCache::new(redis_url)?))
+}
+
+/// build_redis_cache
+///
+/// The ``redis`` backend requires the ``redis`` feature
+///
+#[cfg(not(feature = "redis"))]
+fn build_redis_cache(_redis_url: &str) -> Result<Arc<dyn Cache>, String> {
+    Err("CACHE_BACKEND=redis requires building restapi with \
+        --features redis"
+        .to_string())
+}
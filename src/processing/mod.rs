@@ -0,0 +1,22 @@
+//! Move uploaded ``users_data`` records through the
+//! ``pending``, ``scanning`` and ``ready``, ``quarantined``
+//! or ``failed`` statuses with a pluggable processor
+//!
+//! See
+//! [`UserDataPipeline`](crate::processing::user_data_pipeline::UserDataPipeline)
+//! for the supported environment variables
+//!
+//! Uploads can also be scanned for viruses before they are
+//! stored (see
+//! [`UploadScan`](crate::processing::upload_scanner::UploadScan))
+//!
+//! Image uploads can get thumbnails from a background task
+//! (see
+//! [`UserDataThumbnails`](crate::processing::user_data_thumbnails::UserDataThumbnails))
+//!
+pub mod run_user_data_pipeline;
+pub mod run_user_data_thumbnails;
+pub mod upload_scanner;
+pub mod user_data_pipeline;
+pub mod user_data_processor;
+pub mod user_data_thumbnails;
@@ -0,0 +1,81 @@
+//! Background task that processes ``pending`` ``users_data``
+//! uploads
+//!
+use std::time::Duration;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use crate::processing::user_data_pipeline::UserDataPipeline;
+
+/// run_user_data_pipeline
+///
+/// Process batches with
+/// [`process_user_data_batch`](crate::processing::user_data_pipeline::UserDataPipeline::process_user_data_batch)
+/// until there is nothing left to process and then
+/// wait `interval_seconds` before checking again.
+/// Each batch moves its records to ``scanning`` in the same
+/// ``FOR UPDATE SKIP LOCKED`` statement that selects them,
+/// so api servers running this loop never process the same
+/// record at once.
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `pipeline` - [`UserDataPipeline`](crate::processing::user_data_pipeline::UserDataPipeline)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client db
+///   threadpool with required tls encryption
+///
+pub async fn run_user_data_pipeline(
+    tracking_label: &str,
+    pipeline: UserDataPipeline,
+    db_pool: Pool<PostgresConnectionManager<MakeTlsConnector>>,
+) {
+    if !pipeline.enabled {
+        return;
+    }
+    if pipeline.processor.is_none() {
+        info!(
+            "{tracking_label} - \
+            no users_data processor on this api server - \
+            pending uploads are left for an external pipeline"
+        );
+        return;
+    }
+    info!(
+        "{tracking_label} - \
+        processing pending users_data every {}s",
+        pipeline.interval_seconds
+    );
+    loop {
+        match db_pool.get().await {
+            Ok(conn) => loop {
+                match pipeline
+                    .process_user_data_batch(tracking_label, &conn)
+                    .await
+                {
+                    Ok(num_processed) => {
+                        if (num_processed as i64) < pipeline.batch_size {
+                            break;
+                        }
+                    }
+                    Err(err_msg) => {
+                        error!("{err_msg}");
+                        break;
+                    }
+                }
+            },
+            Err(e) => {
+                error!(
+                    "{tracking_label} - \
+                    failed to get a db connection for processing \
+                    users_data with err='{e}'"
+                );
+            }
+        }
+        tokio::time::sleep(Duration::from_secs(pipeline.interval_seconds))
+            .await;
+    }
+}
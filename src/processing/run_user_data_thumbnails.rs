@@ -0,0 +1,90 @@
+//! Background task that creates thumbnails for image
+//! ``users_data`` uploads
+//!
+use std::sync::Arc;
+use std::time::Duration;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use crate::is3::object_store::ObjectStore;
+use crate::processing::user_data_thumbnails::UserDataThumbnails;
+
+/// run_user_data_thumbnails
+///
+/// Create thumbnails in batches with
+/// [`create_thumbnails_batch`](crate::processing::user_data_thumbnails::UserDataThumbnails::create_thumbnails_batch)
+/// until there is nothing left to process and then
+/// wait `interval_seconds` before checking again.
+/// Each batch sets ``derivatives_status`` to ``processing``
+/// in the same ``FOR UPDATE SKIP LOCKED`` statement that
+/// selects its records, so two api servers never create the
+/// same thumbnails at once.
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `thumbnails` - [`UserDataThumbnails`](crate::processing::user_data_thumbnails::UserDataThumbnails)
+/// * `object_store` - [`ObjectStore`](crate::is3::object_store::ObjectStore) -
+///   storage with the uploaded files
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client db
+///   threadpool with required tls encryption
+///
+pub async fn run_user_data_thumbnails(
+    tracking_label: &str,
+    thumbnails: UserDataThumbnails,
+    object_store: Arc<dyn ObjectStore>,
+    db_pool: Pool<PostgresConnectionManager<MakeTlsConnector>>,
+) {
+    if !thumbnails.enabled {
+        return;
+    }
+    if !cfg!(feature = "thumbnails") {
+        warn!(
+            "{tracking_label} - \
+            not creating thumbnails - USERS_DATA_THUMBNAILS_ENABLED \
+            requires building restapi with the thumbnails feature"
+        );
+        return;
+    }
+    info!(
+        "{tracking_label} - \
+        creating users_data thumbnails sizes={:?} every {}s",
+        thumbnails.sizes, thumbnails.interval_seconds
+    );
+    loop {
+        match db_pool.get().await {
+            Ok(conn) => loop {
+                match thumbnails
+                    .create_thumbnails_batch(
+                        tracking_label,
+                        object_store.as_ref(),
+                        &conn,
+                    )
+                    .await
+                {
+                    Ok(num_processed) => {
+                        if (num_processed as i64) < thumbnails.batch_size {
+                            break;
+                        }
+                    }
+                    Err(err_msg) => {
+                        error!("{err_msg}");
+                        break;
+                    }
+                }
+            },
+            Err(e) => {
+                error!(
+                    "{tracking_label} - \
+                    failed to get a db connection for creating \
+                    users_data thumbnails with err='{e}'"
+                );
+            }
+        }
+        tokio::time::sleep(Duration::from_secs(thumbnails.interval_seconds))
+            .await;
+    }
+}
@@ -0,0 +1,517 @@
+//! Scan uploaded files for viruses and malware before they
+//! are stored
+//!
+//! [`upload_user_data`](crate::requests::user::upload_user_data::upload_user_data)
+//! sends the file contents to the
+//! [`UploadScanner`](crate::processing::upload_scanner::UploadScanner)
+//! before the s3 upload. Infected files are rejected with a
+//! ``422`` (``UPLOAD_SCAN_ACTION=reject``) or stored as
+//! ``quarantined`` records that cannot be downloaded
+//! (``UPLOAD_SCAN_ACTION=quarantine``). The result is stored
+//! in ``users_data.scan_status`` and ``scan_signature``.
+//!
+//! Built-in scanners:
+//!
+//! - ``UPLOAD_SCAN_CLAMD_ADDRESS`` - a ClamAV daemon over tcp
+//!   ([`ClamdUploadScanner`](crate::processing::upload_scanner::ClamdUploadScanner))
+//! - ``UPLOAD_SCAN_WEBHOOK_URL`` - an http scanning service
+//!   ([`WebhookUploadScanner`](crate::processing::upload_scanner::WebhookUploadScanner))
+//!
+//! Set the ``scanner`` on the
+//! [`CoreConfig`](crate::core::core_config::CoreConfig)
+//! ``upload_scan`` before starting the server for other
+//! scanners. Scans run after the upload is read and before
+//! any
+//! [`UserDataProcessor`](crate::processing::user_data_processor::UserDataProcessor)
+//! in the upload pipeline.
+//!
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+
+use hyper::client::HttpConnector;
+use hyper::Body;
+use hyper::Client;
+use hyper::Method;
+use hyper::Request;
+
+use hyper_tls::HttpsConnector;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+
+use crate::monitoring::metric_types::register_int_counter_vec;
+use crate::monitoring::metric_types::IntCounterVec;
+use crate::monitoring::otel::trace_client_span;
+
+lazy_static! {
+    pub static ref UPLOAD_SCAN_COUNTER_VEC: IntCounterVec =
+        register_int_counter_vec!(
+            "upload_scans_total",
+            "Number of uploads scanned by result (clean, infected \
+            or error).",
+            &["result"]
+        )
+        .unwrap();
+}
+
+/// supported ``users_data.scan_status`` values
+pub const UPLOAD_SCAN_STATUSES: [&str; 3] = ["clean", "infected", "error"];
+
+/// UploadScanResult
+///
+/// Result of scanning one upload
+///
+/// # Arguments
+///
+/// * `status` - `String` - ``clean``, ``infected`` or
+///   ``error`` (the scanner failed and
+///   ``UPLOAD_SCAN_ON_ERROR=allow``)
+/// * `signature` - `String` - virus or malware signature
+///   found in an ``infected`` file
+///
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct UploadScanResult {
+    pub status: String,
+    #[serde(default)]
+    pub signature: String,
+}
+
+impl UploadScanResult {
+    /// is_infected
+    ///
+    /// Check if the scanner found a virus or malware
+    ///
+    pub fn is_infected(&self) -> bool {
+        self.status == "infected"
+    }
+}
+
+/// future returned by
+/// [`UploadScanner::scan_upload`](crate::processing::upload_scanner::UploadScanner::scan_upload)
+pub type UploadScannerFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<UploadScanResult, String>> + Send + 'a>>;
+
+/// UploadScanner
+///
+/// Hook for scanning an upload's contents before they are
+/// stored
+///
+pub trait UploadScanner: Send + Sync {
+    /// scan_upload
+    ///
+    /// Scan one uploaded file
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - logging label for caller
+    /// * `file_name` - `&str` - uploaded file name
+    /// * `contents` - `&[u8]` - file contents
+    ///
+    /// # Returns
+    ///
+    /// Ok([`UploadScanResult`](crate::processing::upload_scanner::UploadScanResult)) -
+    /// with a ``clean`` or ``infected`` status
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) - the file could not be scanned
+    ///
+    fn scan_upload<'a>(
+        &'a self,
+        tracking_label: &'a str,
+        file_name: &'a str,
+        contents: &'a [u8],
+    ) -> UploadScannerFuture<'a>;
+}
+
+/// ClamdUploadScanner
+///
+/// Scan uploads with a ClamAV daemon (``clamd``) using the
+/// ``INSTREAM`` command over tcp
+///
+/// # Arguments
+///
+/// * `address` - `String` - clamd ``host:port``
+/// * `chunk_bytes` - `usize` - max bytes sent in each
+///   ``INSTREAM`` chunk
+///
+pub struct ClamdUploadScanner {
+    pub address: String,
+    pub chunk_bytes: usize,
+}
+
+impl ClamdUploadScanner {
+    /// new
+    ///
+    /// Build a clamd scanner for an address
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - `&str` - clamd ``host:port``
+    ///
+    pub fn new(address: &str) -> Self {
+        ClamdUploadScanner {
+            address: address.to_string(),
+            chunk_bytes: 64 * 1024,
+        }
+    }
+}
+
+impl UploadScanner for ClamdUploadScanner {
+    fn scan_upload<'a>(
+        &'a self,
+        _tracking_label: &'a str,
+        _file_name: &'a str,
+        contents: &'a [u8],
+    ) -> UploadScannerFuture<'a> {
+        Box::pin(async move {
+            let map_err = |e: std::io::Error| {
+                format!("clamd scan failed on {} with err='{e}'", self.address)
+            };
+            let mut stream = tokio::net::TcpStream::connect(&self.address)
+                .await
+                .map_err(map_err)?;
+            stream.write_all(b"zINSTREAM\0").await.map_err(map_err)?;
+            for chunk in contents.chunks(self.chunk_bytes.max(1)) {
+                stream
+                    .write_all(&(chunk.len() as u32).to_be_bytes())
+                    .await
+                    .map_err(map_err)?;
+                stream.write_all(chunk).await.map_err(map_err)?;
+            }
+            stream.write_all(&[0, 0, 0, 0]).await.map_err(map_err)?;
+            let mut reply = Vec::new();
+            stream.read_to_end(&mut reply).await.map_err(map_err)?;
+            parse_clamd_reply(&String::from_utf8_lossy(&reply))
+        })
+    }
+}
+
+/// parse_clamd_reply
+///
+/// Parse a clamd ``INSTREAM`` reply like ``stream: OK`` or
+/// ``stream: Eicar-Signature FOUND``
+///
+/// # Arguments
+///
+/// * `reply` - `&str` - clamd reply
+///
+/// # Errors
+///
+/// Err(err_msg: `String`) for clamd errors (for example
+/// ``INSTREAM size limit exceeded. ERROR``)
+///
+/// # Examples
+///
+/// ```rust
+/// use restapi::processing::upload_scanner::parse_clamd_reply;
+/// assert_eq!(parse_clamd_reply("stream: OK\0").unwrap().status, "clean");
+/// let result = parse_clamd_reply("stream: Eicar-Signature FOUND\0").unwrap();
+/// assert_eq!(result.status, "infected");
+/// assert_eq!(result.signature, "Eicar-Signature");
+/// assert!(parse_clamd_reply("INSTREAM size limit exceeded. ERROR").is_err());
+/// ```
+///
+pub fn parse_clamd_reply(reply: &str) -> Result<UploadScanResult, String> {
+    let reply = reply.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+    let result = reply.strip_prefix("stream:").unwrap_or(reply).trim();
+    if result == "OK" {
+        return Ok(UploadScanResult {
+            status: "clean".to_string(),
+            signature: "".to_string(),
+        });
+    }
+    match result.strip_suffix(" FOUND") {
+        Some(signature) => Ok(UploadScanResult {
+            status: "infected".to_string(),
+            signature: signature.trim().to_string(),
+        }),
+        None => Err(format!("clamd scan failed with reply='{reply}'")),
+    }
+}
+
+/// WebhookUploadScanner
+///
+/// ``POST`` each upload's contents to a scanning service.
+/// The service must reply with a ``2xx`` and a json
+/// [`UploadScanResult`](crate::processing::upload_scanner::UploadScanResult)
+/// like ``{"status": "infected", "signature": "Eicar"}``.
+///
+/// # Arguments
+///
+/// * `url` - `String` - ``http`` or ``https`` scanning url
+///
+pub struct WebhookUploadScanner {
+    pub url: String,
+    client: Client<HttpsConnector<HttpConnector>>,
+}
+
+impl WebhookUploadScanner {
+    /// new
+    ///
+    /// Build a webhook scanner for a url
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - `&str` - ``http`` or ``https`` scanning url
+    ///
+    pub fn new(url: &str) -> Self {
+        WebhookUploadScanner {
+            url: url.to_string(),
+            client: Client::builder().build(HttpsConnector::new()),
+        }
+    }
+}
+
+impl UploadScanner for WebhookUploadScanner {
+    fn scan_upload<'a>(
+        &'a self,
+        _tracking_label: &'a str,
+        file_name: &'a str,
+        contents: &'a [u8],
+    ) -> UploadScannerFuture<'a> {
+        Box::pin(async move {
+            let req = Request::builder()
+                .method(Method::POST)
+                .uri(&self.url)
+                .header("Content-Type", "application/octet-stream")
+                .header("filename", file_name)
+                .body(Body::from(contents.to_vec()))
+                .map_err(|e| {
+                    format!("invalid UPLOAD_SCAN_WEBHOOK_URL with err='{e}'")
+                })?;
+            let res = self.client.request(req).await.map_err(|e| {
+                format!("upload scan webhook failed with err='{e}'")
+            })?;
+            if !res.status().is_success() {
+                return Err(format!(
+                    "upload scan webhook returned status={}",
+                    res.status()
+                ));
+            }
+            let bytes =
+                hyper::body::to_bytes(res.into_body()).await.map_err(|e| {
+                    format!("upload scan webhook failed with err='{e}'")
+                })?;
+            let result: UploadScanResult = serde_json::from_slice(&bytes)
+                .map_err(|e| {
+                    format!(
+                        "upload scan webhook returned invalid json \
+                        with err='{e}'"
+                    )
+                })?;
+            match result.status.as_str() {
+                "clean" | "infected" => Ok(result),
+                status => Err(format!(
+                    "upload scan webhook returned unsupported \
+                    status={status}"
+                )),
+            }
+        })
+    }
+}
+
+/// UploadScan
+///
+/// Settings for scanning uploads
+///
+/// # Supported Environment Variables
+///
+/// ```bash
+/// # scan with clamd or a webhook (empty = no scanning)
+/// export UPLOAD_SCAN_CLAMD_ADDRESS="localhost:3310"
+/// export UPLOAD_SCAN_WEBHOOK_URL=""
+/// # reject or quarantine infected files
+/// export UPLOAD_SCAN_ACTION="reject"
+/// # reject or allow uploads the scanner could not scan
+/// export UPLOAD_SCAN_ON_ERROR="reject"
+/// export UPLOAD_SCAN_TIMEOUT_MS="30000"
+/// ```
+///
+/// # Arguments
+///
+/// * `action` - `String` - ``reject`` infected files with a
+///   ``422`` or store them as ``quarantine``-d records
+/// * `on_error` - `String` - ``reject`` uploads with a
+///   ``503`` or ``allow`` them (with a ``scan_status`` of
+///   ``error``) when the scanner fails or times out
+/// * `timeout_ms` - `u64` - max milliseconds for each scan
+/// * `scanner` - `Option<Arc<dyn `[`UploadScanner`](crate::processing::upload_scanner::UploadScanner)`>>` -
+///   scanner run on this api server (`None` = uploads are
+///   not scanned)
+///
+#[derive(Clone, Default)]
+pub struct UploadScan {
+    pub action: String,
+    pub on_error: String,
+    pub timeout_ms: u64,
+    pub scanner: Option<Arc<dyn UploadScanner>>,
+}
+
+impl UploadScan {
+    /// build_upload_scan
+    ///
+    /// Build an
+    /// [`UploadScan`](crate::processing::upload_scanner::UploadScan)
+    /// from environment variables
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) for an unsupported
+    /// ``UPLOAD_SCAN_ACTION`` or ``UPLOAD_SCAN_ON_ERROR`` or
+    /// if both a clamd address and a webhook url are set
+    ///
+    pub fn build_upload_scan() -> Result<Self, String> {
+        let clamd_address = std::env::var("UPLOAD_SCAN_CLAMD_ADDRESS")
+            .unwrap_or_default()
+            .trim()
+            .to_string();
+        let webhook_url = std::env::var("UPLOAD_SCAN_WEBHOOK_URL")
+            .unwrap_or_default()
+            .trim()
+            .to_string();
+        let scanner: Option<Arc<dyn UploadScanner>> =
+            match (clamd_address.is_empty(), webhook_url.is_empty()) {
+                (true, true) => None,
+                (false, true) => {
+                    Some(Arc::new(ClamdUploadScanner::new(&clamd_address)))
+                }
+                (true, false) => {
+                    Some(Arc::new(WebhookUploadScanner::new(&webhook_url)))
+                }
+                (false, false) => {
+                    return Err("set only one of UPLOAD_SCAN_CLAMD_ADDRESS \
+                        or UPLOAD_SCAN_WEBHOOK_URL"
+                        .to_string())
+                }
+            };
+        let action = std::env::var("UPLOAD_SCAN_ACTION")
+            .unwrap_or_else(|_| "reject".to_string());
+        if action != "reject" && action != "quarantine" {
+            return Err(format!(
+                "invalid UPLOAD_SCAN_ACTION={action} \
+                must be reject or quarantine"
+            ));
+        }
+        let on_error = std::env::var("UPLOAD_SCAN_ON_ERROR")
+            .unwrap_or_else(|_| "reject".to_string());
+        if on_error != "reject" && on_error != "allow" {
+            return Err(format!(
+                "invalid UPLOAD_SCAN_ON_ERROR={on_error} \
+                must be reject or allow"
+            ));
+        }
+        Ok(UploadScan {
+            action,
+            on_error,
+            timeout_ms: std::env::var("UPLOAD_SCAN_TIMEOUT_MS")
+                .unwrap_or_else(|_| "30000".to_string())
+                .parse::<u64>()
+                .unwrap_or(30000)
+                .max(1),
+            scanner,
+        })
+    }
+
+    /// should_reject
+    ///
+    /// Check if an upload must be rejected for its scan
+    /// result
+    ///
+    /// # Arguments
+    ///
+    /// * `result` - [`UploadScanResult`](crate::processing::upload_scanner::UploadScanResult)
+    ///
+    pub fn should_reject(&self, result: &UploadScanResult) -> bool {
+        result.is_infected() && self.action == "reject"
+    }
+
+    /// scan_upload
+    ///
+    /// Scan an upload with the configured scanner
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - logging label for caller
+    /// * `file_name` - `&str` - uploaded file name
+    /// * `contents` - `&[u8]` - file contents
+    ///
+    /// # Returns
+    ///
+    /// Ok(`Option<`[`UploadScanResult`](crate::processing::upload_scanner::UploadScanResult)`>`) -
+    /// `None` without a scanner and an ``error`` status for
+    /// failed scans with ``UPLOAD_SCAN_ON_ERROR=allow``
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) - the scan failed or timed out
+    /// and ``UPLOAD_SCAN_ON_ERROR=reject``
+    ///
+    pub async fn scan_upload(
+        &self,
+        tracking_label: &str,
+        file_name: &str,
+        contents: &[u8],
+    ) -> Result<Option<UploadScanResult>, String> {
+        let scanner = match &self.scanner {
+            Some(scanner) => scanner,
+            None => return Ok(None),
+        };
+        let scan_result = match tokio::time::timeout(
+            Duration::from_millis(self.timeout_ms),
+            trace_client_span(
+                "upload scan",
+                vec![("scan.bytes", format!("{}", contents.len()))],
+                scanner.scan_upload(tracking_label, file_name, contents),
+            ),
+        )
+        .await
+        {
+            Ok(scan_result) => scan_result,
+            Err(_) => Err(format!(
+                "upload scan timed out after {}ms",
+                self.timeout_ms
+            )),
+        };
+        match scan_result {
+            Ok(mut result) => {
+                // users_data.scan_signature is a VARCHAR(256)
+                result.signature = result.signature.chars().take(256).collect();
+                UPLOAD_SCAN_COUNTER_VEC
+                    .with_label_values(&[result.status.as_str()])
+                    .inc();
+                if result.is_infected() {
+                    warn!(
+                        "{tracking_label} - upload scan found \
+                        signature={} in name={file_name}",
+                        result.signature
+                    );
+                }
+                Ok(Some(result))
+            }
+            Err(err_msg) => {
+                UPLOAD_SCAN_COUNTER_VEC.with_label_values(&["error"]).inc();
+                match self.on_error.as_str() {
+                    "allow" => {
+                        warn!(
+                            "{tracking_label} - allowing unscanned \
+                            name={file_name} with err='{err_msg}'"
+                        );
+                        Ok(Some(UploadScanResult {
+                            status: "error".to_string(),
+                            signature: "".to_string(),
+                        }))
+                    }
+                    _ => Err(err_msg),
+                }
+            }
+        }
+    }
+}
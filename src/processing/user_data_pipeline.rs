@@ -0,0 +1,359 @@
+//! Process ``pending`` ``users_data`` uploads in batches
+//!
+//! With ``USERS_DATA_PIPELINE_ENABLED=1`` new uploads are
+//! created as ``pending`` instead of ``ready``. Each batch
+//! claims up to ``USERS_DATA_PIPELINE_BATCH_SIZE`` pending
+//! records by setting them to ``scanning`` (with
+//! ``FOR UPDATE SKIP LOCKED`` so every api server can run
+//! the pipeline), calls the
+//! [`UserDataProcessor`](crate::processing::user_data_processor::UserDataProcessor)
+//! for each record and stores the returned status.
+//!
+//! Records left in ``scanning`` by a stopped api server are
+//! claimed again after twice ``USERS_DATA_PIPELINE_TIMEOUT_SECONDS``.
+//!
+//! Without a processor on this api server, ``pending``
+//! records are left for an external pipeline that updates
+//! ``users_data.status`` in the db.
+//!
+use std::sync::Arc;
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::PooledConnection;
+use bb8_postgres::PostgresConnectionManager;
+
+use crate::monitoring::metric_types::register_int_counter_vec;
+use crate::monitoring::metric_types::IntCounterVec;
+use crate::monitoring::otel::trace_db_query;
+use crate::processing::user_data_processor::UserDataProcessor;
+use crate::requests::models::user_data::ModelUserData;
+use crate::requests::models::user_data::USER_DATA_PROCESSED_STATUSES;
+
+lazy_static! {
+    pub static ref USERS_DATA_PIPELINE_COUNTER_VEC: IntCounterVec =
+        register_int_counter_vec!(
+            "users_data_pipeline_total",
+            "Number of users_data uploads processed by result status.",
+            &["status"]
+        )
+        .unwrap();
+}
+
+/// UserDataPipeline
+///
+/// Settings for processing uploaded ``users_data`` records
+///
+/// # Supported Environment Variables
+///
+/// ```bash
+/// export USERS_DATA_PIPELINE_ENABLED="0"
+/// export USERS_DATA_PIPELINE_BATCH_SIZE="10"
+/// export USERS_DATA_PIPELINE_INTERVAL_SECONDS="5"
+/// export USERS_DATA_PIPELINE_TIMEOUT_SECONDS="300"
+/// ```
+///
+/// # Arguments
+///
+/// * `enabled` - `bool` - new uploads start as ``pending``
+/// * `batch_size` - `i64` - max records claimed per batch
+/// * `interval_seconds` - `u64` - seconds between checks for
+///   ``pending`` records
+/// * `timeout_seconds` - `u64` - max seconds for the
+///   processor to handle one record before it is ``failed``
+/// * `processor` - `Option<Arc<dyn `[`UserDataProcessor`](crate::processing::user_data_processor::UserDataProcessor)`>>` -
+///   processor run on this api server (set before starting
+///   the server)
+///
+#[derive(Clone, Default)]
+pub struct UserDataPipeline {
+    pub enabled: bool,
+    pub batch_size: i64,
+    pub interval_seconds: u64,
+    pub timeout_seconds: u64,
+    pub processor: Option<Arc<dyn UserDataProcessor>>,
+}
+
+impl UserDataPipeline {
+    /// build_user_data_pipeline
+    ///
+    /// Build a
+    /// [`UserDataPipeline`](crate::processing::user_data_pipeline::UserDataPipeline)
+    /// from environment variables (without a processor)
+    ///
+    pub fn build_user_data_pipeline() -> Self {
+        let get_env = |key: &str, default: i64| -> i64 {
+            std::env::var(key)
+                .unwrap_or_else(|_| format!("{default}"))
+                .parse::<i64>()
+                .unwrap_or(default)
+        };
+        let enabled = std::env::var("USERS_DATA_PIPELINE_ENABLED")
+            .unwrap_or_else(|_| "0".to_string());
+        UserDataPipeline {
+            enabled: enabled == "1" || enabled == "true",
+            batch_size: get_env("USERS_DATA_PIPELINE_BATCH_SIZE", 10)
+                .clamp(1, 1000),
+            interval_seconds: get_env("USERS_DATA_PIPELINE_INTERVAL_SECONDS", 5)
+                .max(1) as u64,
+            timeout_seconds: get_env("USERS_DATA_PIPELINE_TIMEOUT_SECONDS", 300)
+                .max(1) as u64,
+            processor: None,
+        }
+    }
+
+    /// get_upload_status
+    ///
+    /// Status for a new upload
+    ///
+    /// # Returns
+    ///
+    /// `&str` - ``pending`` when the pipeline is enabled
+    /// otherwise ``ready``
+    ///
+    pub fn get_upload_status(&self) -> &str {
+        match self.enabled {
+            true => "pending",
+            false => "ready",
+        }
+    }
+
+    /// process_user_data_batch
+    ///
+    /// Claim up to `batch_size` ``pending`` records, run
+    /// the processor on each one and store the result
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - caller logging label
+    /// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+    ///   an established db connection from the
+    ///   postgres client db threadpool
+    ///
+    /// # Returns
+    ///
+    /// Ok(num_processed: `usize`) - `0` when there is
+    /// nothing left to process or there is no processor
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) if the records cannot be
+    /// claimed or a status cannot be stored
+    ///
+    pub async fn process_user_data_batch(
+        &self,
+        tracking_label: &str,
+        conn: &PooledConnection<
+            '_,
+            PostgresConnectionManager<MakeTlsConnector>,
+        >,
+    ) -> Result<usize, String> {
+        let processor = match &self.processor {
+            Some(processor) => processor,
+            None => return Ok(0),
+        };
+        let query = format!(
+            "UPDATE \
+                users_data \
+            SET \
+                status = 'scanning', \
+                status_updated_at = timezone('UTC'::text, now()) \
+            WHERE \
+                users_data.id IN (\
+                    SELECT \
+                        users_data.id \
+                    FROM \
+                        users_data \
+                    WHERE \
+                        users_data.status = 'pending' \
+                        OR (\
+                            users_data.status = 'scanning' \
+                            AND \
+                            users_data.status_updated_at < \
+                                timezone('UTC'::text, now()) \
+                                - interval '{} seconds') \
+                    ORDER BY \
+                        users_data.id ASC \
+                    LIMIT {} \
+                    FOR UPDATE SKIP LOCKED) \
+            RETURNING \
+                users_data.id, \
+                users_data.user_id, \
+                users_data.filename, \
+                users_data.size_in_bytes, \
+                users_data.comments, \
+                users_data.data_type, \
+                users_data.encoding, \
+                users_data.sloc, \
+                users_data.created_at, \
+                users_data.updated_at, \
+                users_data.version, \
+                users_data.storage_class, \
+                users_data.expires_at, \
+                users_data.scan_status, \
+                users_data.scan_signature, \
+                users_data.content_type, \
+                users_data.derivatives_status, \
+                users_data.tags, \
+                users_data.folder, \
+                users_data.file_version, \
+                users_data.version_of, \
+                users_data.replaced_by;",
+            self.timeout_seconds * 2,
+            self.batch_size
+        );
+        let stmt = conn.prepare(&query).await.unwrap();
+        let query_result =
+            match trace_db_query(&query, conn.query(&stmt, &[])).await {
+                Ok(query_result) => query_result,
+                Err(e) => {
+                    return Err(format!(
+                        "{tracking_label} - \
+                    failed to claim pending users_data with err='{e}'"
+                    ));
+                }
+            };
+        for row in query_result.iter() {
+            let created_at_utc: chrono::DateTime<chrono::Utc> =
+                row.try_get("created_at").unwrap();
+            let updated_at: Option<chrono::DateTime<chrono::Utc>> =
+                row.try_get("updated_at").unwrap();
+            let user_data = ModelUserData {
+                user_id: row.try_get("user_id").unwrap(),
+                data_id: row.try_get("id").unwrap(),
+                filename: row.try_get("filename").unwrap(),
+                data_type: row.try_get("data_type").unwrap(),
+                size_in_bytes: row.try_get("size_in_bytes").unwrap(),
+                comments: row.try_get("comments").unwrap(),
+                encoding: row.try_get("encoding").unwrap(),
+                sloc: row.try_get("sloc").unwrap(),
+                created_at: format!(
+                    "{}",
+                    created_at_utc.format("%Y-%m-%dT%H:%M:%SZ")
+                ),
+                updated_at: updated_at
+                    .map(|v| format!("{}", v.format("%Y-%m-%dT%H:%M:%SZ")))
+                    .unwrap_or_default(),
+                version: row.try_get("version").unwrap(),
+                archived: false,
+                status: "scanning".to_string(),
+                storage_class: row
+                    .try_get::<_, Option<String>>("storage_class")
+                    .unwrap()
+                    .unwrap_or_default(),
+                expires_at: row
+                    .try_get::<_, Option<chrono::DateTime<chrono::Utc>>>(
+                        "expires_at",
+                    )
+                    .unwrap()
+                    .map(|v| format!("{}", v.format("%Y-%m-%dT%H:%M:%SZ")))
+                    .unwrap_or_default(),
+                scan_status: row
+                    .try_get::<_, Option<String>>("scan_status")
+                    .unwrap()
+                    .unwrap_or_default(),
+                scan_signature: row
+                    .try_get::<_, Option<String>>("scan_signature")
+                    .unwrap()
+                    .unwrap_or_default(),
+                content_type: row
+                    .try_get::<_, Option<String>>("content_type")
+                    .unwrap()
+                    .unwrap_or_default(),
+                derivatives_status: row
+                    .try_get::<_, Option<String>>("derivatives_status")
+                    .unwrap()
+                    .unwrap_or_default(),
+                tags: row.try_get("tags").unwrap(),
+                folder: row
+                    .try_get::<_, Option<String>>("folder")
+                    .unwrap()
+                    .unwrap_or_default(),
+                file_version: row.try_get("file_version").unwrap(),
+                version_of: row
+                    .try_get::<_, Option<i32>>("version_of")
+                    .unwrap()
+                    .unwrap_or_default(),
+                replaced_by: row
+                    .try_get::<_, Option<i32>>("replaced_by")
+                    .unwrap()
+                    .unwrap_or_default(),
+                derivatives: Vec::new(),
+                msg: "".to_string(),
+            };
+            let data_id = user_data.data_id;
+            let status = match tokio::time::timeout(
+                Duration::from_secs(self.timeout_seconds),
+                processor.process_user_data(tracking_label, &user_data),
+            )
+            .await
+            {
+                Ok(Ok(status))
+                    if USER_DATA_PROCESSED_STATUSES
+                        .contains(&status.as_str()) =>
+                {
+                    status
+                }
+                Ok(Ok(status)) => {
+                    error!(
+                        "{tracking_label} - \
+                        processor returned unsupported status={status} \
+                        for users_data {data_id}"
+                    );
+                    "failed".to_string()
+                }
+                Ok(Err(err_msg)) => {
+                    error!(
+                        "{tracking_label} - \
+                        failed to process users_data {data_id} \
+                        with err='{err_msg}'"
+                    );
+                    "failed".to_string()
+                }
+                Err(_) => {
+                    error!(
+                        "{tracking_label} - \
+                        processing users_data {data_id} timed out \
+                        after {}s",
+                        self.timeout_seconds
+                    );
+                    "failed".to_string()
+                }
+            };
+            // skip records claimed again by another api server
+            let query = format!(
+                "UPDATE \
+                    users_data \
+                SET \
+                    status = '{status}', \
+                    status_updated_at = timezone('UTC'::text, now()) \
+                WHERE \
+                    users_data.id = {data_id} \
+                    AND \
+                    users_data.status = 'scanning' \
+                RETURNING \
+                    users_data.id;"
+            );
+            let stmt = conn.prepare(&query).await.unwrap();
+            if let Err(e) = trace_db_query(&query, conn.query(&stmt, &[])).await
+            {
+                return Err(format!(
+                    "{tracking_label} - \
+                    failed to set users_data {data_id} status={status} \
+                    with err='{e}'"
+                ));
+            }
+            USERS_DATA_PIPELINE_COUNTER_VEC
+                .with_label_values(&[&status])
+                .inc();
+            info!(
+                "{tracking_label} - \
+                processed users_data {data_id} status={status}"
+            );
+        }
+        Ok(query_result.len())
+    }
+}
@@ -0,0 +1,56 @@
+//! # Scan or process uploaded user data files
+//!
+//! Implement the
+//! [`UserDataProcessor`](crate::processing::user_data_processor::UserDataProcessor)
+//! trait (virus scan, format checks, transcoding, etc.) and set
+//! it on the
+//! [`CoreConfig`](crate::core::core_config::CoreConfig)
+//! ``user_data_pipeline.processor`` before starting the server.
+//! The
+//! [`run_user_data_pipeline`](crate::processing::run_user_data_pipeline::run_user_data_pipeline)
+//! background task calls it for each ``pending`` upload and
+//! stores the returned status.
+//!
+use std::future::Future;
+use std::pin::Pin;
+
+use crate::requests::models::user_data::ModelUserData;
+
+/// future returned by
+/// [`UserDataProcessor::process_user_data`](crate::processing::user_data_processor::UserDataProcessor::process_user_data)
+pub type UserDataProcessorFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<String, String>> + Send + 'a>>;
+
+/// UserDataProcessor
+///
+/// Hook for scanning or processing an uploaded file
+/// after its ``users_data`` record is created.
+///
+/// Returning an `Err` marks the record ``failed``.
+///
+pub trait UserDataProcessor: Send + Sync {
+    /// process_user_data
+    ///
+    /// Scan or process one uploaded file
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - logging label for caller
+    /// * `user_data` - [`ModelUserData`](crate::requests::models::user_data::ModelUserData) -
+    ///   the ``users_data`` record (the file is at `sloc`)
+    ///
+    /// # Returns
+    ///
+    /// Ok(status: `String`) - ``ready``, ``quarantined`` or
+    /// ``failed`` (any other value is stored as ``failed``)
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`)
+    ///
+    fn process_user_data<'a>(
+        &'a self,
+        tracking_label: &'a str,
+        user_data: &'a ModelUserData,
+    ) -> UserDataProcessorFuture<'a>;
+}
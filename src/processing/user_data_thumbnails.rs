@@ -0,0 +1,628 @@
+//! Create thumbnails for image ``users_data`` uploads
+//!
+//! Uploads with one of the
+//! [`THUMBNAIL_CONTENT_TYPES`](crate::requests::models::user_data_derivative::THUMBNAIL_CONTENT_TYPES)
+//! are created with a ``pending`` ``derivatives_status``.
+//! Once the upload is ``ready``, each batch claims up to
+//! ``USERS_DATA_THUMBNAILS_BATCH_SIZE`` records by setting
+//! them to ``processing`` (with ``FOR UPDATE SKIP LOCKED`` so
+//! every api server can run the task), downloads the image,
+//! stores one thumbnail per ``USERS_DATA_THUMBNAILS_SIZES``
+//! under:
+//!
+//! ``S3_DATA_BUCKET/S3_DATA_DERIVATIVES_PREFIX/USER_ID/DATA_ID/thumbnail_SIZE.(png|jpg)``
+//!
+//! and records each one in the ``users_data_derivatives``
+//! table before setting the status to ``done`` (or
+//! ``failed``).
+//!
+//! Thumbnails keep the image's aspect ratio and are never
+//! larger than the original. Images with an alpha channel
+//! are stored as png and the rest as jpeg.
+//!
+//! Records left in ``processing`` by a stopped api server are
+//! claimed again after twice
+//! ``USERS_DATA_THUMBNAILS_TIMEOUT_SECONDS``.
+//!
+//! Decoding images requires the ``thumbnails`` feature.
+//!
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::PooledConnection;
+use bb8_postgres::PostgresConnectionManager;
+
+use crate::archive::user_data_lifecycle::get_bucket_and_key_from_sloc;
+use crate::is3::object_store::ObjectStore;
+use crate::monitoring::metric_types::register_int_counter_vec;
+use crate::monitoring::metric_types::IntCounterVec;
+use crate::monitoring::otel::trace_client_span;
+use crate::monitoring::otel::trace_db_query;
+use crate::requests::models::user_data_derivative::is_thumbnail_content_type;
+use crate::requests::models::user_data_derivative::ModelUserDataDerivative;
+
+lazy_static! {
+    pub static ref USERS_DATA_THUMBNAILS_COUNTER_VEC: IntCounterVec =
+        register_int_counter_vec!(
+            "users_data_thumbnails_total",
+            "Number of image users_data uploads with thumbnails \
+            done or failed.",
+            &["result"]
+        )
+        .unwrap();
+}
+
+/// max width and height of a decoded source image
+#[cfg(feature = "thumbnails")]
+const THUMBNAIL_MAX_SOURCE_DIMENSION: u32 = 16384;
+
+/// Thumbnail
+///
+/// One generated thumbnail
+///
+/// # Arguments
+///
+/// * `size` - `u32` - requested max width and height
+/// * `width` - `u32` - thumbnail width
+/// * `height` - `u32` - thumbnail height
+/// * `content_type` - `&str` - ``image/png`` or
+///   ``image/jpeg``
+/// * `extension` - `&str` - ``png`` or ``jpg``
+/// * `contents` - `Vec<u8>` - encoded thumbnail
+///
+#[derive(Clone, Default)]
+pub struct Thumbnail {
+    pub size: u32,
+    pub width: u32,
+    pub height: u32,
+    pub content_type: &'static str,
+    pub extension: &'static str,
+    pub contents: Vec<u8>,
+}
+
+/// UserDataThumbnails
+///
+/// Settings for creating thumbnails of image ``users_data``
+/// uploads
+///
+/// # Supported Environment Variables
+///
+/// ```bash
+/// export USERS_DATA_THUMBNAILS_ENABLED="0"
+/// # max width and height of each thumbnail (16 to 4096)
+/// export USERS_DATA_THUMBNAILS_SIZES="128,512"
+/// export USERS_DATA_THUMBNAILS_BATCH_SIZE="10"
+/// export USERS_DATA_THUMBNAILS_INTERVAL_SECONDS="5"
+/// export USERS_DATA_THUMBNAILS_TIMEOUT_SECONDS="120"
+/// export USERS_DATA_THUMBNAILS_MAX_SOURCE_BYTES="26214400"
+/// # only images under S3_DATA_BUCKET/S3_DATA_PREFIX/USER_ID/
+/// # are read
+/// export S3_DATA_BUCKET="BUCKET_NAME"
+/// export S3_DATA_PREFIX="user/data/file"
+/// export S3_DATA_DERIVATIVES_PREFIX="user/data/derivatives"
+/// ```
+///
+/// # Arguments
+///
+/// * `enabled` - `bool` - mark image uploads ``pending``
+///   and run the thumbnail task on this api server
+/// * `sizes` - `Vec<u32>` - max width and height of each
+///   thumbnail
+/// * `batch_size` - `i64` - max records claimed per batch
+/// * `interval_seconds` - `u64` - seconds between checks for
+///   ``pending`` records
+/// * `timeout_seconds` - `u64` - max seconds to create the
+///   thumbnails for one record before it is ``failed``
+/// * `max_source_bytes` - `i64` - larger images are
+///   ``failed`` without downloading them
+/// * `s3_bucket` - `String` - ``S3_DATA_BUCKET`` with the
+///   uploads and thumbnails
+/// * `s3_data_prefix` - `String` - ``S3_DATA_PREFIX`` the
+///   server uploads to
+/// * `s3_prefix` - `String` - ``S3_DATA_DERIVATIVES_PREFIX``
+///   for the thumbnails
+///
+#[derive(Clone, Default)]
+pub struct UserDataThumbnails {
+    pub enabled: bool,
+    pub sizes: Vec<u32>,
+    pub batch_size: i64,
+    pub interval_seconds: u64,
+    pub timeout_seconds: u64,
+    pub max_source_bytes: i64,
+    pub s3_bucket: String,
+    pub s3_data_prefix: String,
+    pub s3_prefix: String,
+}
+
+impl UserDataThumbnails {
+    /// build_user_data_thumbnails
+    ///
+    /// Build a
+    /// [`UserDataThumbnails`](crate::processing::user_data_thumbnails::UserDataThumbnails)
+    /// from environment variables
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) - ``USERS_DATA_THUMBNAILS_SIZES``
+    /// is not a comma-separated list of sizes between 16 and
+    /// 4096
+    ///
+    pub fn build_user_data_thumbnails() -> Result<Self, String> {
+        let get_env = |key: &str, default: i64| -> i64 {
+            std::env::var(key)
+                .unwrap_or_else(|_| format!("{default}"))
+                .parse::<i64>()
+                .unwrap_or(default)
+        };
+        let enabled_s = std::env::var("USERS_DATA_THUMBNAILS_ENABLED")
+            .unwrap_or_else(|_| "0".to_string());
+        let sizes_s = std::env::var("USERS_DATA_THUMBNAILS_SIZES")
+            .unwrap_or_else(|_| "128,512".to_string());
+        let mut sizes: Vec<u32> = Vec::new();
+        for size_s in sizes_s.split(',').map(|v| v.trim()) {
+            match size_s.parse::<u32>() {
+                Ok(size) if (16..=4096).contains(&size) => {
+                    if !sizes.contains(&size) {
+                        sizes.push(size);
+                    }
+                }
+                _ => {
+                    return Err(format!(
+                        "invalid USERS_DATA_THUMBNAILS_SIZES={sizes_s} - \
+                        each size must be between 16 and 4096"
+                    ));
+                }
+            }
+        }
+        sizes.sort_unstable();
+        Ok(UserDataThumbnails {
+            enabled: enabled_s == "1" || enabled_s == "true",
+            sizes,
+            batch_size: get_env("USERS_DATA_THUMBNAILS_BATCH_SIZE", 10)
+                .clamp(1, 1000),
+            interval_seconds: get_env(
+                "USERS_DATA_THUMBNAILS_INTERVAL_SECONDS",
+                5,
+            )
+            .max(1) as u64,
+            timeout_seconds: get_env(
+                "USERS_DATA_THUMBNAILS_TIMEOUT_SECONDS",
+                120,
+            )
+            .max(1) as u64,
+            max_source_bytes: get_env(
+                "USERS_DATA_THUMBNAILS_MAX_SOURCE_BYTES",
+                26214400,
+            )
+            .max(1),
+            s3_bucket: std::env::var("S3_DATA_BUCKET")
+                .unwrap_or_else(|_| "BUCKET_NAME".to_string()),
+            s3_data_prefix: std::env::var("S3_DATA_PREFIX")
+                .unwrap_or_else(|_| "user/data/file".to_string()),
+            s3_prefix: std::env::var("S3_DATA_DERIVATIVES_PREFIX")
+                .unwrap_or_else(|_| "user/data/derivatives".to_string()),
+        })
+    }
+
+    /// get_upload_derivatives_status
+    ///
+    /// ``derivatives_status`` for a new upload
+    ///
+    /// # Arguments
+    ///
+    /// * `content_type` - `Option<&str>` - upload content type
+    ///
+    /// # Returns
+    ///
+    /// `Option<String>` - ``pending`` for image uploads when
+    /// the task is enabled (`None` = no thumbnails)
+    ///
+    pub fn get_upload_derivatives_status(
+        &self,
+        content_type: Option<&str>,
+    ) -> Option<String> {
+        match (self.enabled, content_type) {
+            (true, Some(v)) if is_thumbnail_content_type(v) => {
+                Some("pending".to_string())
+            }
+            _ => None,
+        }
+    }
+
+    /// create_user_data_thumbnails
+    ///
+    /// Download one image, create its thumbnails and upload
+    /// them to s3
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - caller logging label
+    /// * `object_store` - [`ObjectStore`](crate::is3::object_store::ObjectStore) -
+    ///   storage with the uploaded files
+    /// * `user_id` - `i32` - record owner
+    /// * `data_id` - `i32` - ``users_data.id``
+    /// * `sloc` - `&str` - image s3 location
+    ///
+    /// # Returns
+    ///
+    /// Ok(`Vec<`[`ModelUserDataDerivative`](crate::requests::models::user_data_derivative::ModelUserDataDerivative)`>`)
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) - the image was not uploaded by
+    /// the server, could not be decoded or a thumbnail could
+    /// not be uploaded
+    ///
+    async fn create_user_data_thumbnails(
+        &self,
+        tracking_label: &str,
+        object_store: &dyn ObjectStore,
+        user_id: i32,
+        data_id: i32,
+        sloc: &str,
+    ) -> Result<Vec<ModelUserDataDerivative>, String> {
+        // client sloc values can point anywhere
+        let (bucket, key) = get_bucket_and_key_from_sloc(sloc)
+            .filter(|(bucket, key)| {
+                *bucket == self.s3_bucket
+                    && key.starts_with(&format!(
+                        "{}/{user_id}/",
+                        self.s3_data_prefix
+                    ))
+                    && !key.split('/').any(|segment| segment == "..")
+            })
+            .ok_or_else(|| format!("not a server upload {sloc}"))?;
+        let contents = trace_client_span(
+            "s3 download",
+            vec![("s3.bucket", bucket.clone()), ("s3.key", key.clone())],
+            object_store.download_to_memory(&bucket, &key),
+        )
+        .await?;
+        let sizes = self.sizes.clone();
+        let thumbnails = tokio::task::spawn_blocking(move || {
+            create_thumbnails(&contents, &sizes)
+        })
+        .await
+        .map_err(|e| format!("thumbnail task failed with err='{e}'"))??;
+        let mut derivatives: Vec<ModelUserDataDerivative> =
+            Vec::with_capacity(thumbnails.len());
+        for thumbnail in thumbnails.iter() {
+            let s3_key = format!(
+                "{}/{user_id}/{data_id}/thumbnail_{}.{}",
+                self.s3_prefix, thumbnail.size, thumbnail.extension
+            );
+            trace_client_span(
+                "s3 upload",
+                vec![
+                    ("s3.bucket", self.s3_bucket.clone()),
+                    ("s3.key", s3_key.clone()),
+                    ("s3.bytes", format!("{}", thumbnail.contents.len())),
+                ],
+                object_store.upload_buffer(
+                    tracking_label,
+                    &self.s3_bucket,
+                    &s3_key,
+                    &thumbnail.contents,
+                ),
+            )
+            .await?;
+            derivatives.push(ModelUserDataDerivative {
+                kind: "thumbnail".to_string(),
+                size: thumbnail.size as i32,
+                width: thumbnail.width as i32,
+                height: thumbnail.height as i32,
+                content_type: thumbnail.content_type.to_string(),
+                size_in_bytes: thumbnail.contents.len() as i64,
+                sloc: format!("s3://{}/{s3_key}", self.s3_bucket),
+                created_at: "".to_string(),
+            });
+        }
+        Ok(derivatives)
+    }
+
+    /// create_thumbnails_batch
+    ///
+    /// Claim up to `batch_size` ``ready`` image records with
+    /// ``pending`` thumbnails, create and store the
+    /// thumbnails and set the ``derivatives_status``
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - caller logging label
+    /// * `object_store` - [`ObjectStore`](crate::is3::object_store::ObjectStore) -
+    ///   storage with the uploaded files
+    /// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+    ///   an established db connection from the
+    ///   postgres client db threadpool
+    ///
+    /// # Returns
+    ///
+    /// Ok(num_processed: `usize`) - `0` when there is
+    /// nothing left to process
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) if the records cannot be
+    /// claimed or a status cannot be stored
+    ///
+    pub async fn create_thumbnails_batch(
+        &self,
+        tracking_label: &str,
+        object_store: &dyn ObjectStore,
+        conn: &PooledConnection<
+            '_,
+            PostgresConnectionManager<MakeTlsConnector>,
+        >,
+    ) -> Result<usize, String> {
+        let query = format!(
+            "UPDATE \
+                users_data \
+            SET \
+                derivatives_status = 'processing', \
+                derivatives_updated_at = timezone('UTC'::text, now()) \
+            WHERE \
+                users_data.id IN (\
+                    SELECT \
+                        users_data.id \
+                    FROM \
+                        users_data \
+                    WHERE \
+                        users_data.status = 'ready' \
+                        AND (\
+                            users_data.derivatives_status = 'pending' \
+                            OR (\
+                                users_data.derivatives_status = 'processing' \
+                                AND \
+                                users_data.derivatives_updated_at < \
+                                    timezone('UTC'::text, now()) \
+                                    - interval '{} seconds')) \
+                    ORDER BY \
+                        users_data.id ASC \
+                    LIMIT {} \
+                    FOR UPDATE SKIP LOCKED) \
+            RETURNING \
+                users_data.id, \
+                users_data.user_id, \
+                users_data.size_in_bytes, \
+                users_data.sloc;",
+            self.timeout_seconds * 2,
+            self.batch_size
+        );
+        let stmt = conn.prepare(&query).await.unwrap();
+        let query_result =
+            match trace_db_query(&query, conn.query(&stmt, &[])).await {
+                Ok(query_result) => query_result,
+                Err(e) => {
+                    return Err(format!(
+                        "{tracking_label} - \
+                        failed to claim pending users_data thumbnails \
+                        with err='{e}'"
+                    ));
+                }
+            };
+        for row in query_result.iter() {
+            let data_id: i32 = row.try_get("id").unwrap();
+            let user_id: i32 = row.try_get("user_id").unwrap();
+            let size_in_bytes: i64 = row.try_get("size_in_bytes").unwrap();
+            let sloc: String = row.try_get("sloc").unwrap();
+            let derivatives = match size_in_bytes > self.max_source_bytes {
+                true => Err(format!(
+                    "image size {size_in_bytes} is larger than \
+                    USERS_DATA_THUMBNAILS_MAX_SOURCE_BYTES={}",
+                    self.max_source_bytes
+                )),
+                false => match tokio::time::timeout(
+                    Duration::from_secs(self.timeout_seconds),
+                    self.create_user_data_thumbnails(
+                        tracking_label,
+                        object_store,
+                        user_id,
+                        data_id,
+                        &sloc,
+                    ),
+                )
+                .await
+                {
+                    Ok(result) => result,
+                    Err(_) => Err(format!(
+                        "timed out after {}s",
+                        self.timeout_seconds
+                    )),
+                },
+            };
+            let status = match derivatives {
+                Ok(derivatives) => {
+                    let mut status = "done";
+                    for derivative in derivatives.iter() {
+                        let query = format!(
+                            "INSERT INTO \
+                                users_data_derivatives (\
+                                    data_id, \
+                                    user_id, \
+                                    kind, \
+                                    size, \
+                                    width, \
+                                    height, \
+                                    content_type, \
+                                    size_in_bytes, \
+                                    sloc) \
+                            VALUES (\
+                                {data_id}, \
+                                {user_id}, \
+                                '{}', \
+                                {}, \
+                                {}, \
+                                {}, \
+                                '{}', \
+                                {}, \
+                                '{}') \
+                            ON CONFLICT (data_id, kind, size) DO UPDATE SET \
+                                width = EXCLUDED.width, \
+                                height = EXCLUDED.height, \
+                                content_type = EXCLUDED.content_type, \
+                                size_in_bytes = EXCLUDED.size_in_bytes, \
+                                sloc = EXCLUDED.sloc, \
+                                created_at = timezone('UTC'::text, now());",
+                            derivative.kind,
+                            derivative.size,
+                            derivative.width,
+                            derivative.height,
+                            derivative.content_type,
+                            derivative.size_in_bytes,
+                            derivative.sloc.replace('\'', "''")
+                        );
+                        let stmt = conn.prepare(&query).await.unwrap();
+                        if let Err(e) =
+                            trace_db_query(&query, conn.execute(&stmt, &[]))
+                                .await
+                        {
+                            error!(
+                                "{tracking_label} - \
+                                failed to store users_data {data_id} \
+                                thumbnail size={} with err='{e}'",
+                                derivative.size
+                            );
+                            status = "failed";
+                            break;
+                        }
+                    }
+                    status
+                }
+                Err(err_msg) => {
+                    error!(
+                        "{tracking_label} - \
+                        failed to create thumbnails for users_data \
+                        {data_id} with err='{err_msg}'"
+                    );
+                    "failed"
+                }
+            };
+            // skip records claimed again by another api server
+            let query = format!(
+                "UPDATE \
+                    users_data \
+                SET \
+                    derivatives_status = '{status}', \
+                    derivatives_updated_at = timezone('UTC'::text, now()) \
+                WHERE \
+                    users_data.id = {data_id} \
+                    AND \
+                    users_data.derivatives_status = 'processing';"
+            );
+            let stmt = conn.prepare(&query).await.unwrap();
+            if let Err(e) =
+                trace_db_query(&query, conn.execute(&stmt, &[])).await
+            {
+                return Err(format!(
+                    "{tracking_label} - \
+                    failed to set users_data {data_id} \
+                    derivatives_status={status} with err='{e}'"
+                ));
+            }
+            USERS_DATA_THUMBNAILS_COUNTER_VEC
+                .with_label_values(&[status])
+                .inc();
+            info!(
+                "{tracking_label} - \
+                users_data {data_id} thumbnails status={status}"
+            );
+        }
+        Ok(query_result.len())
+    }
+}
+
+/// create_thumbnails
+///
+/// Decode an image and encode one thumbnail per size
+///
+/// # Arguments
+///
+/// * `contents` - `&[u8]` - png, jpeg, gif or webp image
+/// * `sizes` - `&[u32]` - max width and height of each
+///   thumbnail
+///
+/// # Returns
+///
+/// Ok(`Vec<`[`Thumbnail`](crate::processing::user_data_thumbnails::Thumbnail)`>`)
+///
+/// # Errors
+///
+/// Err(err_msg: `String`) - the image could not be decoded
+/// or encoded
+///
+#[cfg(feature = "thumbnails")]
+pub fn create_thumbnails(
+    contents: &[u8],
+    sizes: &[u32],
+) -> Result<Vec<Thumbnail>, String> {
+    use std::io::Cursor;
+
+    use image::io::Limits;
+    use image::io::Reader;
+    use image::DynamicImage;
+    use image::ImageOutputFormat;
+
+    let mut reader =
+        Reader::new(Cursor::new(contents))
+            .with_guessed_format()
+            .map_err(|e| format!("failed to read image with err='{e}'"))?;
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(THUMBNAIL_MAX_SOURCE_DIMENSION);
+    limits.max_image_height = Some(THUMBNAIL_MAX_SOURCE_DIMENSION);
+    reader.limits(limits);
+    let image = reader
+        .decode()
+        .map_err(|e| format!("failed to decode image with err='{e}'"))?;
+    let has_alpha = image.color().has_alpha();
+    let mut thumbnails: Vec<Thumbnail> = Vec::with_capacity(sizes.len());
+    for size in sizes.iter() {
+        // never upscale
+        let resized = match image.width() > *size || image.height() > *size {
+            true => image.thumbnail(*size, *size),
+            false => image.clone(),
+        };
+        let mut encoded = Cursor::new(Vec::new());
+        let (content_type, extension, result) = match has_alpha {
+            true => (
+                "image/png",
+                "png",
+                DynamicImage::ImageRgba8(resized.to_rgba8())
+                    .write_to(&mut encoded, ImageOutputFormat::Png),
+            ),
+            false => (
+                "image/jpeg",
+                "jpg",
+                DynamicImage::ImageRgb8(resized.to_rgb8())
+                    .write_to(&mut encoded, ImageOutputFormat::Jpeg(85)),
+            ),
+        };
+        result.map_err(|e| {
+            format!("failed to encode thumbnail size={size} with err='{e}'")
+        })?;
+        thumbnails.push(Thumbnail {
+            size: *size,
+            width: resized.width(),
+            height: resized.height(),
+            content_type,
+            extension,
+            contents: encoded.into_inner(),
+        });
+    }
+    Ok(thumbnails)
+}
+
+/// create_thumbnails
+///
+/// Always fails without the ``thumbnails`` feature
+///
+#[cfg(not(feature = "thumbnails"))]
+pub fn create_thumbnails(
+    _contents: &[u8],
+    _sizes: &[u32],
+) -> Result<Vec<Thumbnail>, String> {
+    Err("restapi was built without the thumbnails feature".to_string())
+}
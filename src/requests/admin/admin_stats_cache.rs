@@ -0,0 +1,86 @@
+//! Cache the admin dashboard stats so dashboards that poll
+//! ``GET /admin/stats`` do not run the aggregate queries on
+//! every request
+//!
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use crate::requests::models::admin_stats::ModelAdminStats;
+
+/// AdminStatsCache
+///
+/// Settings and the cached value for ``GET /admin/stats``
+///
+/// # Supported Environment Variables
+///
+/// ```bash
+/// # seconds the stats are reused (0 = query every request)
+/// export ADMIN_STATS_CACHE_SECONDS="60"
+/// ```
+///
+/// # Arguments
+///
+/// * `cache_seconds` - `i64` - seconds the stats are reused
+///   on this api server (``0`` = no cache)
+///
+#[derive(Clone, Default)]
+pub struct AdminStatsCache {
+    pub cache_seconds: i64,
+    cached: Arc<Mutex<Option<(i64, ModelAdminStats)>>>,
+}
+
+impl AdminStatsCache {
+    /// build_admin_stats_cache
+    ///
+    /// Build an
+    /// [`AdminStatsCache`](crate::requests::admin::admin_stats_cache::AdminStatsCache)
+    /// from environment variables
+    ///
+    pub fn build_admin_stats_cache() -> Self {
+        AdminStatsCache {
+            cache_seconds: std::env::var("ADMIN_STATS_CACHE_SECONDS")
+                .unwrap_or_else(|_| "60".to_string())
+                .parse::<i64>()
+                .unwrap_or(60)
+                .max(0),
+            cached: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// get_stats
+    ///
+    /// Get the cached stats when they are newer than
+    /// ``ADMIN_STATS_CACHE_SECONDS``
+    ///
+    /// # Returns
+    ///
+    /// `Option<`[`ModelAdminStats`](crate::requests::models::admin_stats::ModelAdminStats)`>`
+    ///
+    pub fn get_stats(&self) -> Option<ModelAdminStats> {
+        let now = chrono::Utc::now().timestamp();
+        match self.cached.lock() {
+            Ok(cached) => cached
+                .as_ref()
+                .filter(|(cached_at, _)| now - cached_at < self.cache_seconds)
+                .map(|(_, stats)| stats.clone()),
+            Err(_) => None,
+        }
+    }
+
+    /// set_stats
+    ///
+    /// Cache newly computed stats
+    ///
+    /// # Arguments
+    ///
+    /// * `stats` - [`ModelAdminStats`](crate::requests::models::admin_stats::ModelAdminStats)
+    ///
+    pub fn set_stats(&self, stats: &ModelAdminStats) {
+        if self.cache_seconds == 0 {
+            return;
+        }
+        if let Ok(mut cached) = self.cached.lock() {
+            *cached = Some((chrono::Utc::now().timestamp(), stats.clone()));
+        }
+    }
+}
@@ -0,0 +1,348 @@
+//! Module for adding an ip allow or deny rule
+//!
+//! ## Admin Create IP Rule
+//!
+//! Add an ``allow`` or ``deny`` rule for a network (``10.0.0.0/8``) or address to the ``ip_rules`` table. Rules apply to every route or only to the routes under the ``path_prefix`` (like ``/admin`` or ``/metrics``), and every api server in the cluster reloads its rules when the table changes (see [`IpAccessControl`](crate::core::server::ip_access::IpAccessControl)). Rules that would block the requesting admin's own address from the ``/admin/ip-rules`` apis are rejected with a ``409``. The requesting user must have the ``admin`` role.
+//!
+//! - URL path: ``/admin/ip-rules``
+//! - Method: ``POST``
+//! - Handler: [`create_ip_rule`](crate::requests::admin::create_ip_rule::create_ip_rule)
+//! - Request: [`ApiReqAdminCreateIpRule`](crate::requests::admin::create_ip_rule::ApiReqAdminCreateIpRule)
+//! - Response: [`ApiResAdminCreateIpRule`](crate::requests::admin::create_ip_rule::ApiResAdminCreateIpRule)
+//!
+use std::convert::Infallible;
+use std::net::SocketAddr;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Response;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::core::core_config::CoreConfig;
+use crate::core::server::ip_access::check_ip_rules;
+use crate::core::server::ip_access::IpAccessRule;
+use crate::core::server::ip_access::IP_RULE_ACTIONS;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::requests::admin::is_admin_user::is_admin_user;
+use crate::requests::auth::validate_user_token::validate_user_token;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::models::ip_rule::insert_ip_rule;
+use crate::requests::models::ip_rule::ModelIpRule;
+use crate::requests::validation::field_rules::add_field_error;
+use crate::requests::validation::field_rules::check_id;
+use crate::requests::validation::field_rules::check_length;
+use crate::requests::validation::field_rules::check_one_of;
+use crate::requests::validation::validate_api_req::validate_api_req;
+use crate::requests::validation::validate_api_req::ApiFieldError;
+use crate::requests::validation::validate_api_req::ApiReqValidate;
+use crate::settings::listen_for_settings_changes::reload_ip_rules;
+
+/// ApiReqAdminCreateIpRule
+///
+/// # Request Type For create_ip_rule
+///
+/// Add an ip allow or deny rule
+///
+/// This type is the deserialized input for:
+/// [`create_ip_rule`](crate::requests::admin::create_ip_rule::create_ip_rule)
+///
+/// # Arguments
+///
+/// * `user_id` - `i32` - admin user id
+/// * `action` - `String` - ``allow`` or ``deny``
+/// * `cidr` - `String` - network (``10.0.0.0/8``) or address
+/// * `path_prefix` - `Option<String>` - url path prefix the
+///   rule applies to (default = every route)
+/// * `note` - `Option<String>` - why the rule was added
+///
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApiReqAdminCreateIpRule {
+    pub user_id: i32,
+    pub action: String,
+    pub cidr: String,
+    pub path_prefix: Option<String>,
+    pub note: Option<String>,
+}
+
+impl ApiReqValidate for ApiReqAdminCreateIpRule {
+    /// validate
+    ///
+    /// Require a positive `user_id`, a supported `action`,
+    /// a valid `cidr` and an optional `path_prefix` that
+    /// starts with ``/``
+    ///
+    fn validate(&self) -> Vec<ApiFieldError> {
+        let mut errors = Vec::new();
+        check_id(&mut errors, "user_id", self.user_id);
+        check_one_of(
+            &mut errors,
+            "action",
+            self.action.as_str(),
+            &IP_RULE_ACTIONS,
+        );
+        if let Err(err_msg) = IpAccessRule::parse_rule("allow", &self.cidr, "")
+        {
+            add_field_error(&mut errors, "cidr", &err_msg);
+        }
+        if let Some(path_prefix) = &self.path_prefix {
+            check_length(&mut errors, "path_prefix", path_prefix, 0, 256);
+            if let Err(err_msg) =
+                IpAccessRule::parse_rule("allow", "0.0.0.0/0", path_prefix)
+            {
+                add_field_error(&mut errors, "path_prefix", &err_msg);
+            }
+        }
+        if let Some(note) = &self.note {
+            check_length(&mut errors, "note", note, 0, 512);
+        }
+        errors
+    }
+}
+
+/// ApiResAdminCreateIpRule
+///
+/// # Response type for create_ip_rule
+///
+/// Return the new rule
+///
+/// # Arguments
+///
+/// * `rule` - [`ModelIpRule`](crate::requests::models::ip_rule::ModelIpRule)
+/// * `msg` - `String` - help message
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   machine-readable failure code (omitted on success)
+///
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ApiResAdminCreateIpRule {
+    pub rule: ModelIpRule,
+    pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ApiErrorCode>,
+}
+
+/// create_ip_rule
+///
+/// Handler for adding an ip allow or deny rule
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
+///   db threadpool with required tls encryption
+/// * `kafka_pool` -
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+///   for asynchronously publishing messages to the connected kafka cluster
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+/// * `remote_addr` - `&SocketAddr` - client address of the
+///   requesting admin
+/// * `bytes` - `&[u8]` - received bytes from the hyper
+///   [`Request`](hyper::Request)'s [`Body`](hyper::Body)
+///
+/// # Returns
+///
+/// ## create_ip_rule on Success Returns
+///
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResAdminCreateIpRule`](crate::requests::admin::create_ip_rule::ApiResAdminCreateIpRule)
+/// dictionary within the
+/// [`Body`](hyper::Body) and a
+/// `201` HTTP status code
+///
+/// Ok([`Response`](hyper::Response))
+///
+/// # Errors
+///
+/// `400` for an invalid request or token, `403` if the user
+/// is not an ``admin``, `409` if the rule already exists or
+/// would block the requesting admin's address and `500` when
+/// the db insert fails
+///
+/// Err([`Response`](hyper::Response))
+///
+pub async fn create_ip_rule(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    kafka_pool: &KafkaPublisher,
+    headers: &HeaderMap<HeaderValue>,
+    remote_addr: &SocketAddr,
+    bytes: &[u8],
+) -> std::result::Result<Response<Body>, Infallible> {
+    let req_object: ApiReqAdminCreateIpRule =
+        match serde_json::from_slice(bytes) {
+            Ok(uo) => uo,
+            Err(_) => {
+                return Ok(get_create_ip_rule_response(
+                    400,
+                    "Admin create ip rule failed - please ensure \
+                    user_id, action and cidr were set \
+                    correctly in the request",
+                    ApiErrorCode::InvalidRequest,
+                ));
+            }
+        };
+
+    if let Some(response) = validate_api_req(tracking_label, &req_object) {
+        return Ok(response);
+    }
+
+    let user_id = req_object.user_id;
+    let conn = db_pool.get().await.unwrap();
+    if let Err(error_code) =
+        validate_user_token(tracking_label, config, &conn, headers, user_id)
+            .await
+    {
+        return Ok(get_create_ip_rule_response(
+            400,
+            "Admin create ip rule failed due to invalid token",
+            error_code,
+        ));
+    }
+
+    if !is_admin_user(tracking_label, user_id, &conn).await {
+        warn!(
+            "{tracking_label} - \
+            rejected create ip rule from non-admin user {user_id}"
+        );
+        return Ok(get_create_ip_rule_response(
+            403,
+            "Admin create ip rule failed - user is not an admin",
+            ApiErrorCode::Forbidden,
+        ));
+    }
+
+    // validated above
+    let rule = IpAccessRule::parse_rule(
+        &req_object.action,
+        &req_object.cidr,
+        req_object.path_prefix.as_deref().unwrap_or(""),
+    )
+    .unwrap();
+    // an admin cannot lock their own address out
+    // of the ip rule apis
+    let mut rules = config.ip_access.get_rules();
+    rules.push(rule.clone());
+    if let Err(err_msg) =
+        check_ip_rules(&rules, &remote_addr.ip(), "/admin/ip-rules")
+    {
+        return Ok(get_create_ip_rule_response(
+            409,
+            &format!(
+                "Admin create ip rule failed - the rule would block \
+                the /admin/ip-rules apis for your address - {err_msg}"
+            ),
+            ApiErrorCode::Conflict,
+        ));
+    }
+
+    let ip_rule = match insert_ip_rule(
+        tracking_label,
+        &ModelIpRule {
+            action: rule.get_action().to_string(),
+            cidr: rule.cidr.to_string(),
+            path_prefix: rule.path_prefix.clone(),
+            note: req_object.note.clone().unwrap_or_default(),
+            created_by: user_id,
+            ..Default::default()
+        },
+        &conn,
+    )
+    .await
+    {
+        Ok(Some(ip_rule)) => ip_rule,
+        Ok(None) => {
+            return Ok(get_create_ip_rule_response(
+                409,
+                &format!(
+                    "Admin create ip rule failed - {} {} for \
+                    path_prefix='{}' already exists",
+                    rule.get_action(),
+                    rule.cidr,
+                    rule.path_prefix
+                ),
+                ApiErrorCode::Conflict,
+            ));
+        }
+        Err(err_msg) => {
+            error!("{err_msg}");
+            return Ok(get_create_ip_rule_response(
+                500,
+                "Admin create ip rule failed",
+                ApiErrorCode::InternalError,
+            ));
+        }
+    };
+
+    // apply the change here without waiting
+    // for the ip_rules_changed notification
+    if let Err(err_msg) = reload_ip_rules(tracking_label, config, db_pool).await
+    {
+        error!("{err_msg}");
+    }
+
+    info!(
+        "{tracking_label} - \
+        admin {user_id} created ip rule={} action={} cidr={} \
+        path_prefix='{}'",
+        ip_rule.id, ip_rule.action, ip_rule.cidr, ip_rule.path_prefix
+    );
+    config
+        .events
+        .publish_user_event(
+            kafka_pool,
+            user_id,
+            "ADMIN_CREATE_IP_RULE",
+            &format!(
+                "rule={} action={} cidr={}",
+                ip_rule.id, ip_rule.action, ip_rule.cidr
+            ),
+        )
+        .await;
+
+    let response = Response::builder()
+        .status(201)
+        .body(Body::from(
+            serde_json::to_string(&ApiResAdminCreateIpRule {
+                rule: ip_rule,
+                msg: "success".to_string(),
+                error_code: None,
+            })
+            .unwrap(),
+        ))
+        .unwrap();
+    Ok(response)
+}
+
+/// get_create_ip_rule_response
+///
+/// Build an error response for
+/// [`create_ip_rule`](crate::requests::admin::create_ip_rule::create_ip_rule)
+///
+fn get_create_ip_rule_response(
+    status: u16,
+    msg: &str,
+    error_code: ApiErrorCode,
+) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::from(
+            serde_json::to_string(&ApiResAdminCreateIpRule {
+                msg: msg.to_string(),
+                error_code: Some(error_code),
+                ..Default::default()
+            })
+            .unwrap(),
+        ))
+        .unwrap()
+}
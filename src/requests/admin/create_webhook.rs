@@ -0,0 +1,300 @@
+//! Module for registering webhook endpoints
+//!
+//! ## Admin Create Webhook
+//!
+//! Register an endpoint that receives a signed json ``POST`` for each selected event type (``user.created``, ``user.verified`` and ``data.uploaded``) when ``WEBHOOKS_ENABLED=1``. Deliveries are signed with the ``secret`` (see [`get_webhook_signature`](crate::webhooks::webhook_signature::get_webhook_signature)), which is never returned by the api. The requesting user must have the ``admin`` role.
+//!
+//! - URL path: ``/admin/webhooks``
+//! - Method: ``POST``
+//! - Handler: [`create_webhook`](crate::requests::admin::create_webhook::create_webhook)
+//! - Request: [`ApiReqAdminCreateWebhook`](crate::requests::admin::create_webhook::ApiReqAdminCreateWebhook)
+//! - Response: [`ApiResAdminCreateWebhook`](crate::requests::admin::create_webhook::ApiResAdminCreateWebhook)
+//!
+use std::convert::Infallible;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Response;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::core::core_config::CoreConfig;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::requests::admin::is_admin_user::is_admin_user;
+use crate::requests::auth::validate_user_token::validate_user_token;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::models::webhook::insert_webhook;
+use crate::requests::models::webhook::ModelWebhook;
+use crate::requests::validation::field_rules::add_field_error;
+use crate::requests::validation::field_rules::check_id;
+use crate::requests::validation::field_rules::check_length;
+use crate::requests::validation::field_rules::check_one_of;
+use crate::requests::validation::validate_api_req::validate_api_req;
+use crate::requests::validation::validate_api_req::ApiFieldError;
+use crate::requests::validation::validate_api_req::ApiReqValidate;
+use crate::webhooks::webhook_dispatcher::WEBHOOK_EVENT_TYPES;
+
+/// ApiReqAdminCreateWebhook
+///
+/// # Request Type For create_webhook
+///
+/// Register a webhook endpoint
+///
+/// This type is the deserialized input for:
+/// [`create_webhook`](crate::requests::admin::create_webhook::create_webhook)
+///
+/// # Arguments
+///
+/// * `user_id` - `i32` - admin user id
+/// * `url` - `String` - ``https`` endpoint url
+/// * `secret` - `String` - shared secret for signing
+///   deliveries (16 to 512 characters)
+/// * `events` - `Vec<String>` - webhook event types
+///   (``user.created``, ``user.verified``, ``data.uploaded``)
+///
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApiReqAdminCreateWebhook {
+    pub user_id: i32,
+    pub url: String,
+    pub secret: String,
+    pub events: Vec<String>,
+}
+
+impl ApiReqValidate for ApiReqAdminCreateWebhook {
+    /// validate
+    ///
+    /// Require a positive `user_id`, a `url`, a `secret`
+    /// and at least one supported event type
+    ///
+    fn validate(&self) -> Vec<ApiFieldError> {
+        let mut errors = Vec::new();
+        check_id(&mut errors, "user_id", self.user_id);
+        check_length(&mut errors, "url", &self.url, 1, 2048);
+        check_length(&mut errors, "secret", &self.secret, 16, 512);
+        if self.events.is_empty() {
+            add_field_error(
+                &mut errors,
+                "events",
+                "at least one event type is required",
+            );
+        }
+        let event_types: Vec<&str> =
+            WEBHOOK_EVENT_TYPES.iter().map(|(v, _)| *v).collect();
+        for event in self.events.iter() {
+            check_one_of(&mut errors, "events", event.as_str(), &event_types);
+        }
+        errors
+    }
+}
+
+/// ApiResAdminCreateWebhook
+///
+/// # Response type for create_webhook
+///
+/// Return the registered webhook (without the secret)
+///
+/// # Arguments
+///
+/// * `webhook` - [`ModelWebhook`](crate::requests::models::webhook::ModelWebhook)
+/// * `msg` - `String` - help message
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   machine-readable failure code (omitted on success)
+///
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ApiResAdminCreateWebhook {
+    pub webhook: ModelWebhook,
+    pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ApiErrorCode>,
+}
+
+/// create_webhook
+///
+/// Handler for registering a webhook endpoint
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
+///   db threadpool with required tls encryption
+/// * `kafka_pool` -
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+///   for asynchronously publishing messages to the connected kafka cluster
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+/// * `bytes` - `&[u8]` - received bytes from the hyper
+///   [`Request`](hyper::Request)'s [`Body`](hyper::Body)
+///
+/// # Returns
+///
+/// ## create_webhook on Success Returns
+///
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResAdminCreateWebhook`](crate::requests::admin::create_webhook::ApiResAdminCreateWebhook)
+/// dictionary within the
+/// [`Body`](hyper::Body) and a
+/// `201` HTTP status code
+///
+/// Ok([`Response`](hyper::Response))
+///
+/// # Errors
+///
+/// `400` for an invalid request, token or url, `403` if
+/// the user is not an ``admin`` and `500` when the db
+/// insert fails
+///
+/// Err([`Response`](hyper::Response))
+///
+pub async fn create_webhook(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    kafka_pool: &KafkaPublisher,
+    headers: &HeaderMap<HeaderValue>,
+    bytes: &[u8],
+) -> std::result::Result<Response<Body>, Infallible> {
+    let req_object: ApiReqAdminCreateWebhook =
+        match serde_json::from_slice(bytes) {
+            Ok(uo) => uo,
+            Err(_) => {
+                return Ok(get_create_webhook_response(
+                    400,
+                    "Admin create webhook failed - please ensure \
+                    user_id, url, secret and events were set \
+                    correctly in the request",
+                    ApiErrorCode::InvalidRequest,
+                ));
+            }
+        };
+
+    if let Some(response) = validate_api_req(tracking_label, &req_object) {
+        return Ok(response);
+    }
+
+    let user_id = req_object.user_id;
+    let conn = db_pool.get().await.unwrap();
+    if let Err(error_code) =
+        validate_user_token(tracking_label, config, &conn, headers, user_id)
+            .await
+    {
+        return Ok(get_create_webhook_response(
+            400,
+            "Admin create webhook failed due to invalid token",
+            error_code,
+        ));
+    }
+
+    if !is_admin_user(tracking_label, user_id, &conn).await {
+        warn!(
+            "{tracking_label} - \
+            rejected create webhook from non-admin user {user_id}"
+        );
+        return Ok(get_create_webhook_response(
+            403,
+            "Admin create webhook failed - user is not an admin",
+            ApiErrorCode::Forbidden,
+        ));
+    }
+
+    if let Err(err_msg) =
+        config.events.webhooks.validate_webhook_url(&req_object.url)
+    {
+        return Ok(get_create_webhook_response(
+            400,
+            &format!("Admin create webhook failed - {err_msg}"),
+            ApiErrorCode::InvalidRequest,
+        ));
+    }
+
+    let mut event_types = req_object.events.clone();
+    event_types.sort();
+    event_types.dedup();
+    let webhook = match insert_webhook(
+        tracking_label,
+        &req_object.url,
+        &req_object.secret,
+        &event_types,
+        user_id,
+        &conn,
+    )
+    .await
+    {
+        Ok(webhook) => webhook,
+        Err(err_msg) => {
+            error!("{err_msg}");
+            return Ok(get_create_webhook_response(
+                500,
+                "Admin create webhook failed",
+                ApiErrorCode::InternalError,
+            ));
+        }
+    };
+
+    info!(
+        "{tracking_label} - \
+        admin {user_id} created webhook={} url={} events={}",
+        webhook.id,
+        webhook.url,
+        webhook.event_types.join(",")
+    );
+    config
+        .events
+        .publish_user_event(
+            kafka_pool,
+            user_id,
+            "ADMIN_CREATE_WEBHOOK",
+            &format!("webhook={}", webhook.id),
+        )
+        .await;
+
+    let msg = match config.events.webhooks.enabled {
+        true => "success".to_string(),
+        false => "success - deliveries start after \
+            WEBHOOKS_ENABLED=1 is set"
+            .to_string(),
+    };
+    let response = Response::builder()
+        .status(201)
+        .body(Body::from(
+            serde_json::to_string(&ApiResAdminCreateWebhook {
+                webhook,
+                msg,
+                error_code: None,
+            })
+            .unwrap(),
+        ))
+        .unwrap();
+    Ok(response)
+}
+
+/// get_create_webhook_response
+///
+/// Build an error response for
+/// [`create_webhook`](crate::requests::admin::create_webhook::create_webhook)
+///
+fn get_create_webhook_response(
+    status: u16,
+    msg: &str,
+    error_code: ApiErrorCode,
+) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::from(
+            serde_json::to_string(&ApiResAdminCreateWebhook {
+                msg: msg.to_string(),
+                error_code: Some(error_code),
+                ..Default::default()
+            })
+            .unwrap(),
+        ))
+        .unwrap()
+}
@@ -0,0 +1,329 @@
+//! Module for removing an ip allow or deny rule
+//!
+//! ## Admin Delete IP Rule
+//!
+//! Remove a rule from the ``ip_rules`` table. Every api server in the cluster reloads its rules when the table changes. Removing a rule that would block the requesting admin's own address from the ``/admin/ip-rules`` apis is rejected with a ``409``. Rules from environment variables cannot be removed with the api. The requesting user must have the ``admin`` role.
+//!
+//! - URL path: ``/admin/ip-rules``
+//! - Method: ``DELETE``
+//! - Handler: [`delete_ip_rule`](crate::requests::admin::delete_ip_rule::delete_ip_rule)
+//! - Request: [`ApiReqAdminDeleteIpRule`](crate::requests::admin::delete_ip_rule::ApiReqAdminDeleteIpRule)
+//! - Response: [`ApiResAdminDeleteIpRule`](crate::requests::admin::delete_ip_rule::ApiResAdminDeleteIpRule)
+//!
+use std::convert::Infallible;
+use std::net::SocketAddr;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Response;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::core::core_config::CoreConfig;
+use crate::core::server::ip_access::check_ip_rules;
+use crate::core::server::ip_access::IpAccessRule;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::requests::admin::is_admin_user::is_admin_user;
+use crate::requests::auth::validate_user_token::validate_user_token;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::models::ip_rule::delete_ip_rule as delete_db_ip_rule;
+use crate::requests::models::ip_rule::get_ip_rules;
+use crate::requests::validation::field_rules::check_id;
+use crate::requests::validation::validate_api_req::validate_api_req;
+use crate::requests::validation::validate_api_req::ApiFieldError;
+use crate::requests::validation::validate_api_req::ApiReqValidate;
+use crate::settings::listen_for_settings_changes::reload_ip_rules;
+
+/// ApiReqAdminDeleteIpRule
+///
+/// # Request Type For delete_ip_rule
+///
+/// Remove an ip allow or deny rule
+///
+/// This type is the deserialized input for:
+/// [`delete_ip_rule`](crate::requests::admin::delete_ip_rule::delete_ip_rule)
+///
+/// # Arguments
+///
+/// * `user_id` - `i32` - admin user id
+/// * `rule_id` - `i32` - ``ip_rules.id``
+///
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApiReqAdminDeleteIpRule {
+    pub user_id: i32,
+    pub rule_id: i32,
+}
+
+impl ApiReqValidate for ApiReqAdminDeleteIpRule {
+    /// validate
+    ///
+    /// Require a positive `user_id` and `rule_id`
+    ///
+    fn validate(&self) -> Vec<ApiFieldError> {
+        let mut errors = Vec::new();
+        check_id(&mut errors, "user_id", self.user_id);
+        check_id(&mut errors, "rule_id", self.rule_id);
+        errors
+    }
+}
+
+/// ApiResAdminDeleteIpRule
+///
+/// # Response type for delete_ip_rule
+///
+/// Notify the client that the rule was removed
+///
+/// # Arguments
+///
+/// * `rule_id` - `i32` - ``ip_rules.id``
+/// * `msg` - `String` - help message
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   machine-readable failure code (omitted on success)
+///
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ApiResAdminDeleteIpRule {
+    pub rule_id: i32,
+    pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ApiErrorCode>,
+}
+
+/// delete_ip_rule
+///
+/// Handler for removing an ip allow or deny rule
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
+///   db threadpool with required tls encryption
+/// * `kafka_pool` -
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+///   for asynchronously publishing messages to the connected kafka cluster
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+/// * `remote_addr` - `&SocketAddr` - client address of the
+///   requesting admin
+/// * `bytes` - `&[u8]` - received bytes from the hyper
+///   [`Request`](hyper::Request)'s [`Body`](hyper::Body)
+///
+/// # Returns
+///
+/// ## delete_ip_rule on Success Returns
+///
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResAdminDeleteIpRule`](crate::requests::admin::delete_ip_rule::ApiResAdminDeleteIpRule)
+/// dictionary within the
+/// [`Body`](hyper::Body) and a
+/// `200` HTTP status code
+///
+/// Ok([`Response`](hyper::Response))
+///
+/// # Errors
+///
+/// `400` for an invalid request or token, `403` if the
+/// user is not an ``admin``, `404` when there is no rule
+/// with the id, `409` if removing the rule would block the
+/// requesting admin's address and `500` when the db
+/// delete fails
+///
+/// Err([`Response`](hyper::Response))
+///
+pub async fn delete_ip_rule(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    kafka_pool: &KafkaPublisher,
+    headers: &HeaderMap<HeaderValue>,
+    remote_addr: &SocketAddr,
+    bytes: &[u8],
+) -> std::result::Result<Response<Body>, Infallible> {
+    let req_object: ApiReqAdminDeleteIpRule =
+        match serde_json::from_slice(bytes) {
+            Ok(uo) => uo,
+            Err(_) => {
+                return Ok(get_delete_ip_rule_response(
+                    400,
+                    -1,
+                    "Admin delete ip rule failed - please ensure \
+                    user_id and rule_id were set correctly \
+                    in the request",
+                    ApiErrorCode::InvalidRequest,
+                ));
+            }
+        };
+
+    if let Some(response) = validate_api_req(tracking_label, &req_object) {
+        return Ok(response);
+    }
+
+    let user_id = req_object.user_id;
+    let rule_id = req_object.rule_id;
+    let conn = db_pool.get().await.unwrap();
+    if let Err(error_code) =
+        validate_user_token(tracking_label, config, &conn, headers, user_id)
+            .await
+    {
+        return Ok(get_delete_ip_rule_response(
+            400,
+            rule_id,
+            "Admin delete ip rule failed due to invalid token",
+            error_code,
+        ));
+    }
+
+    if !is_admin_user(tracking_label, user_id, &conn).await {
+        warn!(
+            "{tracking_label} - \
+            rejected delete ip rule from non-admin user {user_id}"
+        );
+        return Ok(get_delete_ip_rule_response(
+            403,
+            rule_id,
+            "Admin delete ip rule failed - user is not an admin",
+            ApiErrorCode::Forbidden,
+        ));
+    }
+
+    let db_rules = match get_ip_rules(tracking_label, &conn).await {
+        Ok(db_rules) => db_rules,
+        Err(err_msg) => {
+            error!("{err_msg}");
+            return Ok(get_delete_ip_rule_response(
+                500,
+                rule_id,
+                "Admin delete ip rule failed",
+                ApiErrorCode::InternalError,
+            ));
+        }
+    };
+    let ip_rule = match db_rules.iter().find(|v| v.id == rule_id) {
+        Some(ip_rule) => ip_rule.clone(),
+        None => {
+            return Ok(get_delete_ip_rule_response(
+                404,
+                rule_id,
+                &format!(
+                    "Admin delete ip rule failed - \
+                    unable to find ip rule with id: {rule_id}"
+                ),
+                ApiErrorCode::NotFound,
+            ));
+        }
+    };
+    // an admin cannot lock their own address out
+    // of the ip rule apis
+    let mut rules = config.ip_access.env_rules.clone();
+    rules.extend(db_rules.iter().filter(|v| v.id != rule_id).filter_map(|v| {
+        IpAccessRule::parse_rule(&v.action, &v.cidr, &v.path_prefix).ok()
+    }));
+    if let Err(err_msg) =
+        check_ip_rules(&rules, &remote_addr.ip(), "/admin/ip-rules")
+    {
+        return Ok(get_delete_ip_rule_response(
+            409,
+            rule_id,
+            &format!(
+                "Admin delete ip rule failed - removing the rule would \
+                block the /admin/ip-rules apis for your address - {err_msg}"
+            ),
+            ApiErrorCode::Conflict,
+        ));
+    }
+
+    match delete_db_ip_rule(tracking_label, rule_id, &conn).await {
+        Ok(true) => {}
+        Ok(false) => {
+            return Ok(get_delete_ip_rule_response(
+                404,
+                rule_id,
+                &format!(
+                    "Admin delete ip rule failed - \
+                    unable to find ip rule with id: {rule_id}"
+                ),
+                ApiErrorCode::NotFound,
+            ));
+        }
+        Err(err_msg) => {
+            error!("{err_msg}");
+            return Ok(get_delete_ip_rule_response(
+                500,
+                rule_id,
+                "Admin delete ip rule failed",
+                ApiErrorCode::InternalError,
+            ));
+        }
+    }
+
+    // apply the change here without waiting
+    // for the ip_rules_changed notification
+    if let Err(err_msg) = reload_ip_rules(tracking_label, config, db_pool).await
+    {
+        error!("{err_msg}");
+    }
+
+    info!(
+        "{tracking_label} - \
+        admin {user_id} deleted ip rule={rule_id} action={} cidr={} \
+        path_prefix='{}'",
+        ip_rule.action, ip_rule.cidr, ip_rule.path_prefix
+    );
+    config
+        .events
+        .publish_user_event(
+            kafka_pool,
+            user_id,
+            "ADMIN_DELETE_IP_RULE",
+            &format!(
+                "rule={rule_id} action={} cidr={}",
+                ip_rule.action, ip_rule.cidr
+            ),
+        )
+        .await;
+
+    let response = Response::builder()
+        .status(200)
+        .body(Body::from(
+            serde_json::to_string(&ApiResAdminDeleteIpRule {
+                rule_id,
+                msg: "success".to_string(),
+                error_code: None,
+            })
+            .unwrap(),
+        ))
+        .unwrap();
+    Ok(response)
+}
+
+/// get_delete_ip_rule_response
+///
+/// Build an error response for
+/// [`delete_ip_rule`](crate::requests::admin::delete_ip_rule::delete_ip_rule)
+///
+fn get_delete_ip_rule_response(
+    status: u16,
+    rule_id: i32,
+    msg: &str,
+    error_code: ApiErrorCode,
+) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::from(
+            serde_json::to_string(&ApiResAdminDeleteIpRule {
+                rule_id,
+                msg: msg.to_string(),
+                error_code: Some(error_code),
+            })
+            .unwrap(),
+        ))
+        .unwrap()
+}
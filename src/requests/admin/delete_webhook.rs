@@ -0,0 +1,268 @@
+//! Module for removing webhook endpoints
+//!
+//! ## Admin Delete Webhook
+//!
+//! Stop sending deliveries to a webhook endpoint. The webhook is soft-deleted (``webhooks.state = 1``), its ``pending`` deliveries are marked ``failed`` and its delivery records stay queryable with ``/admin/webhooks/deliveries``. The requesting user must have the ``admin`` role.
+//!
+//! - URL path: ``/admin/webhooks``
+//! - Method: ``DELETE``
+//! - Handler: [`delete_webhook`](crate::requests::admin::delete_webhook::delete_webhook)
+//! - Request: [`ApiReqAdminDeleteWebhook`](crate::requests::admin::delete_webhook::ApiReqAdminDeleteWebhook)
+//! - Response: [`ApiResAdminDeleteWebhook`](crate::requests::admin::delete_webhook::ApiResAdminDeleteWebhook)
+//!
+use std::convert::Infallible;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Response;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::core::core_config::CoreConfig;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::requests::admin::is_admin_user::is_admin_user;
+use crate::requests::auth::validate_user_token::validate_user_token;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::models::webhook::delete_webhook as delete_db_webhook;
+use crate::requests::validation::field_rules::check_id;
+use crate::requests::validation::validate_api_req::validate_api_req;
+use crate::requests::validation::validate_api_req::ApiFieldError;
+use crate::requests::validation::validate_api_req::ApiReqValidate;
+
+/// ApiReqAdminDeleteWebhook
+///
+/// # Request Type For delete_webhook
+///
+/// Remove a webhook endpoint
+///
+/// This type is the deserialized input for:
+/// [`delete_webhook`](crate::requests::admin::delete_webhook::delete_webhook)
+///
+/// # Arguments
+///
+/// * `user_id` - `i32` - admin user id
+/// * `webhook_id` - `i32` - ``webhooks.id``
+///
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApiReqAdminDeleteWebhook {
+    pub user_id: i32,
+    pub webhook_id: i32,
+}
+
+impl ApiReqValidate for ApiReqAdminDeleteWebhook {
+    /// validate
+    ///
+    /// Require a positive `user_id` and `webhook_id`
+    ///
+    fn validate(&self) -> Vec<ApiFieldError> {
+        let mut errors = Vec::new();
+        check_id(&mut errors, "user_id", self.user_id);
+        check_id(&mut errors, "webhook_id", self.webhook_id);
+        errors
+    }
+}
+
+/// ApiResAdminDeleteWebhook
+///
+/// # Response type for delete_webhook
+///
+/// Notify the client that the webhook was removed
+///
+/// # Arguments
+///
+/// * `webhook_id` - `i32` - ``webhooks.id``
+/// * `cancelled_deliveries` - `i64` - ``pending`` deliveries
+///   that were marked ``failed``
+/// * `msg` - `String` - help message
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   machine-readable failure code (omitted on success)
+///
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ApiResAdminDeleteWebhook {
+    pub webhook_id: i32,
+    pub cancelled_deliveries: i64,
+    pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ApiErrorCode>,
+}
+
+/// delete_webhook
+///
+/// Handler for removing a webhook endpoint
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
+///   db threadpool with required tls encryption
+/// * `kafka_pool` -
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+///   for asynchronously publishing messages to the connected kafka cluster
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+/// * `bytes` - `&[u8]` - received bytes from the hyper
+///   [`Request`](hyper::Request)'s [`Body`](hyper::Body)
+///
+/// # Returns
+///
+/// ## delete_webhook on Success Returns
+///
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResAdminDeleteWebhook`](crate::requests::admin::delete_webhook::ApiResAdminDeleteWebhook)
+/// dictionary within the
+/// [`Body`](hyper::Body) and a
+/// `200` HTTP status code
+///
+/// Ok([`Response`](hyper::Response))
+///
+/// # Errors
+///
+/// `400` for an invalid request or token, `403` if the
+/// user is not an ``admin``, `404` when there is no active
+/// webhook with the id and `500` when the db update fails
+///
+/// Err([`Response`](hyper::Response))
+///
+pub async fn delete_webhook(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    kafka_pool: &KafkaPublisher,
+    headers: &HeaderMap<HeaderValue>,
+    bytes: &[u8],
+) -> std::result::Result<Response<Body>, Infallible> {
+    let req_object: ApiReqAdminDeleteWebhook =
+        match serde_json::from_slice(bytes) {
+            Ok(uo) => uo,
+            Err(_) => {
+                return Ok(get_delete_webhook_response(
+                    400,
+                    -1,
+                    "Admin delete webhook failed - please ensure \
+                    user_id and webhook_id were set correctly \
+                    in the request",
+                    ApiErrorCode::InvalidRequest,
+                ));
+            }
+        };
+
+    if let Some(response) = validate_api_req(tracking_label, &req_object) {
+        return Ok(response);
+    }
+
+    let user_id = req_object.user_id;
+    let webhook_id = req_object.webhook_id;
+    let conn = db_pool.get().await.unwrap();
+    if let Err(error_code) =
+        validate_user_token(tracking_label, config, &conn, headers, user_id)
+            .await
+    {
+        return Ok(get_delete_webhook_response(
+            400,
+            webhook_id,
+            "Admin delete webhook failed due to invalid token",
+            error_code,
+        ));
+    }
+
+    if !is_admin_user(tracking_label, user_id, &conn).await {
+        warn!(
+            "{tracking_label} - \
+            rejected delete webhook from non-admin user {user_id}"
+        );
+        return Ok(get_delete_webhook_response(
+            403,
+            webhook_id,
+            "Admin delete webhook failed - user is not an admin",
+            ApiErrorCode::Forbidden,
+        ));
+    }
+
+    let cancelled_deliveries =
+        match delete_db_webhook(tracking_label, webhook_id, &conn).await {
+            Ok(Some(cancelled_deliveries)) => cancelled_deliveries,
+            Ok(None) => {
+                return Ok(get_delete_webhook_response(
+                    404,
+                    webhook_id,
+                    &format!(
+                        "Admin delete webhook failed - \
+                        unable to find webhook with id: {webhook_id}"
+                    ),
+                    ApiErrorCode::NotFound,
+                ));
+            }
+            Err(err_msg) => {
+                error!("{err_msg}");
+                return Ok(get_delete_webhook_response(
+                    500,
+                    webhook_id,
+                    "Admin delete webhook failed",
+                    ApiErrorCode::InternalError,
+                ));
+            }
+        };
+
+    info!(
+        "{tracking_label} - \
+        admin {user_id} deleted webhook={webhook_id} \
+        cancelled_deliveries={cancelled_deliveries}"
+    );
+    config
+        .events
+        .publish_user_event(
+            kafka_pool,
+            user_id,
+            "ADMIN_DELETE_WEBHOOK",
+            &format!("webhook={webhook_id}"),
+        )
+        .await;
+
+    let response = Response::builder()
+        .status(200)
+        .body(Body::from(
+            serde_json::to_string(&ApiResAdminDeleteWebhook {
+                webhook_id,
+                cancelled_deliveries,
+                msg: "success".to_string(),
+                error_code: None,
+            })
+            .unwrap(),
+        ))
+        .unwrap();
+    Ok(response)
+}
+
+/// get_delete_webhook_response
+///
+/// Build an error response for
+/// [`delete_webhook`](crate::requests::admin::delete_webhook::delete_webhook)
+///
+fn get_delete_webhook_response(
+    status: u16,
+    webhook_id: i32,
+    msg: &str,
+    error_code: ApiErrorCode,
+) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::from(
+            serde_json::to_string(&ApiResAdminDeleteWebhook {
+                webhook_id,
+                cancelled_deliveries: 0,
+                msg: msg.to_string(),
+                error_code: Some(error_code),
+            })
+            .unwrap(),
+        ))
+        .unwrap()
+}
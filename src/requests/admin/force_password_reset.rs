@@ -0,0 +1,401 @@
+//! Module for forcing a user to reset their password
+//!
+//! ## Admin Force Password Reset
+//!
+//! Revoke every active session for a user and block the user's password and passkey logins (``403`` with the ``PASSWORD_RESET_REQUIRED`` error code) until the user changes their password with a one-time-use token from ``/user/password/forgot`` and ``/user/password/change``. With ``send_otp`` (the default) and ``KAFKA_PUBLISH_EVENTS=1`` the token is emailed right away. The action is stored in the ``users_admin_actions`` audit table and published as an ``ADMIN_SECURITY_FORCE_PASSWORD_RESET`` user event for the target user. The requesting user must have the ``admin`` role.
+//!
+//! - URL path: ``/admin/users/password/reset``
+//! - Method: ``POST``
+//! - Handler: [`force_password_reset`](crate::requests::admin::force_password_reset::force_password_reset)
+//! - Request: [`ApiReqAdminForcePasswordReset`](crate::requests::admin::force_password_reset::ApiReqAdminForcePasswordReset)
+//! - Response: [`ApiResAdminForcePasswordReset`](crate::requests::admin::force_password_reset::ApiResAdminForcePasswordReset)
+//!
+use std::convert::Infallible;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Response;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::core::core_config::CoreConfig;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::requests::admin::is_admin_user::is_admin_user;
+use crate::requests::auth::validate_user_token::validate_user_token;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::models::user::get_user_by_id;
+use crate::requests::models::user_admin_action::run_user_admin_action;
+use crate::requests::models::user_admin_action::ADMIN_ACTION_FORCE_PASSWORD_RESET;
+use crate::requests::models::user_admin_action::ADMIN_ACTION_MAX_REASON_LEN;
+use crate::requests::user::create_otp::get_otp_email_details;
+use crate::requests::user::create_otp::insert_user_otp;
+use crate::requests::user::create_otp::send_otp_email;
+use crate::requests::validation::field_rules::check_id;
+use crate::requests::validation::field_rules::check_length;
+use crate::requests::validation::validate_api_req::validate_api_req;
+use crate::requests::validation::validate_api_req::ApiFieldError;
+use crate::requests::validation::validate_api_req::ApiReqValidate;
+
+/// ApiReqAdminForcePasswordReset
+///
+/// # Request Type For force_password_reset
+///
+/// Log a user out and require a password change before
+/// the next login
+///
+/// This type is the deserialized input for:
+/// [`force_password_reset`](crate::requests::admin::force_password_reset::force_password_reset)
+///
+/// # Usage
+///
+/// This type is constructed from the deserialized
+/// `bytes` (`&[u8]`) argument
+/// on the
+/// [`force_password_reset`](crate::requests::admin::force_password_reset::force_password_reset)
+/// function.
+///
+/// # Arguments
+///
+/// * `user_id` - `i32` - admin user id
+/// * `target_user_id` - `i32` - user that must reset
+///   their password
+/// * `reason` - `Option<String>` - reason stored in the
+///   audit record and the event
+/// * `send_otp` - `Option<bool>` - email the user a
+///   one-time-use password reset token (default `true`)
+///
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApiReqAdminForcePasswordReset {
+    pub user_id: i32,
+    pub target_user_id: i32,
+    pub reason: Option<String>,
+    pub send_otp: Option<bool>,
+}
+
+impl ApiReqValidate for ApiReqAdminForcePasswordReset {
+    /// validate
+    ///
+    /// Require a positive `user_id` and `target_user_id`
+    /// with an optional `reason` up to 512 characters
+    ///
+    fn validate(&self) -> Vec<ApiFieldError> {
+        let mut errors = Vec::new();
+        check_id(&mut errors, "user_id", self.user_id);
+        check_id(&mut errors, "target_user_id", self.target_user_id);
+        if let Some(reason) = &self.reason {
+            check_length(
+                &mut errors,
+                "reason",
+                reason,
+                1,
+                ADMIN_ACTION_MAX_REASON_LEN,
+            );
+        }
+        errors
+    }
+}
+
+/// ApiResAdminForcePasswordReset
+///
+/// # Response type for force_password_reset
+///
+/// Notify the client the user must reset their password
+///
+/// # Usage
+///
+/// This type is the serialized output for the function:
+/// [`force_password_reset`](crate::requests::admin::force_password_reset::force_password_reset)
+/// and contained within the
+/// hyper [`Body`](hyper::Body)
+/// of the
+/// hyper [`Response`](hyper::Response)
+/// sent back to the client.
+///
+/// # Arguments
+///
+/// * `target_user_id` - `i32` - user that must reset
+///   their password
+/// * `revoked_sessions` - `i64` - number of revoked sessions
+/// * `otp_sent` - `bool` - a one-time-use password reset
+///   token was published for the mail service
+/// * `msg` - `String` - help message
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   machine-readable failure code (omitted on success)
+///
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ApiResAdminForcePasswordReset {
+    pub target_user_id: i32,
+    pub revoked_sessions: i64,
+    pub otp_sent: bool,
+    pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ApiErrorCode>,
+}
+
+/// force_password_reset
+///
+/// Handler for revoking a user's sessions and blocking
+/// the user's logins until they change their password
+/// with a one-time-use token (see
+/// [`consume_user_otp`](crate::requests::user::consume_user_otp::consume_user_otp))
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
+///   db threadpool with required tls encryption
+/// * `kafka_pool` -
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+///   for asynchronously publishing messages to the connected kafka cluster
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+/// * `bytes` - `&[u8]` - received bytes from the hyper
+///   [`Request`](hyper::Request)'s [`Body`](hyper::Body)
+///
+/// # Returns
+///
+/// ## force_password_reset on Success Returns
+///
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResAdminForcePasswordReset`](crate::requests::admin::force_password_reset::ApiResAdminForcePasswordReset)
+/// dictionary within the
+/// [`Body`](hyper::Body) and a
+/// `200` HTTP status code
+///
+/// Ok([`Response`](hyper::Response))
+///
+/// # Errors
+///
+/// ## force_password_reset on Failure Returns
+///
+/// All errors return as a
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResAdminForcePasswordReset`](crate::requests::admin::force_password_reset::ApiResAdminForcePasswordReset)
+/// dictionary with a
+/// `non-200` HTTP status code (`403` if the user
+/// is not an ``admin`` and `404` if the target user
+/// does not exist)
+///
+/// Err([`Response`](hyper::Response))
+///
+pub async fn force_password_reset(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    kafka_pool: &KafkaPublisher,
+    headers: &HeaderMap<HeaderValue>,
+    bytes: &[u8],
+) -> std::result::Result<Response<Body>, Infallible> {
+    let req_object: ApiReqAdminForcePasswordReset =
+        match serde_json::from_slice(bytes) {
+            Ok(uo) => uo,
+            Err(_) => {
+                return Ok(get_force_password_reset_response(
+                    400,
+                    -1,
+                    "Admin force password reset failed - please ensure \
+                    user_id and target_user_id are set in the request",
+                    ApiErrorCode::InvalidRequest,
+                ));
+            }
+        };
+    if let Some(response) = validate_api_req(tracking_label, &req_object) {
+        return Ok(response);
+    }
+    let user_id = req_object.user_id;
+    let target_user_id = req_object.target_user_id;
+    let reason = req_object.reason.clone().unwrap_or_default();
+    let send_otp = req_object.send_otp.unwrap_or(true);
+    let conn = db_pool.get().await.unwrap();
+    if let Err(error_code) =
+        validate_user_token(tracking_label, config, &conn, headers, user_id)
+            .await
+    {
+        return Ok(get_force_password_reset_response(
+            400,
+            target_user_id,
+            "Admin force password reset failed due to invalid token",
+            error_code,
+        ));
+    }
+    if !is_admin_user(tracking_label, user_id, &conn).await {
+        warn!(
+            "{tracking_label} - \
+            rejected password reset for user {target_user_id} \
+            from non-admin user {user_id}"
+        );
+        return Ok(get_force_password_reset_response(
+            403,
+            target_user_id,
+            "Admin force password reset failed - user is not an admin",
+            ApiErrorCode::Forbidden,
+        ));
+    }
+
+    let admin_action = match run_user_admin_action(
+        tracking_label,
+        user_id,
+        target_user_id,
+        ADMIN_ACTION_FORCE_PASSWORD_RESET,
+        &reason,
+        &conn,
+    )
+    .await
+    {
+        Ok(Some(admin_action)) => admin_action,
+        Ok(None) => {
+            return Ok(get_force_password_reset_response(
+                404,
+                target_user_id,
+                &format!(
+                    "Admin force password reset failed - \
+                    no user with id={target_user_id}"
+                ),
+                ApiErrorCode::UserNotFound,
+            ));
+        }
+        Err(err_msg) => {
+            error!("{err_msg}");
+            return Ok(get_force_password_reset_response(
+                500,
+                target_user_id,
+                &format!(
+                    "Admin force password reset failed \
+                    for user_id={target_user_id}"
+                ),
+                ApiErrorCode::InternalError,
+            ));
+        }
+    };
+    // the revoked tokens may be cached as active
+    config.user_cache.invalidate_user(target_user_id).await;
+
+    warn!(
+        "{tracking_label} - \
+        admin {user_id} forced a password reset for user {target_user_id} \
+        revoked {} sessions audit={}",
+        admin_action.revoked_sessions, admin_action.action_id
+    );
+    config
+        .events
+        .admin_security_action(
+            kafka_pool,
+            target_user_id,
+            "ADMIN_SECURITY_FORCE_PASSWORD_RESET",
+            user_id,
+            admin_action.revoked_sessions,
+            &reason,
+        )
+        .await;
+
+    // the token is only delivered by the mail service
+    let mut otp_sent = false;
+    if send_otp && config.events.enabled {
+        match get_user_by_id(tracking_label, target_user_id, &conn).await {
+            Ok(user_model) => {
+                match insert_user_otp(
+                    tracking_label,
+                    config,
+                    &conn,
+                    target_user_id,
+                    &user_model.email,
+                )
+                .await
+                {
+                    Ok(user_otp) => {
+                        config
+                            .events
+                            .publish_user_event(
+                                kafka_pool,
+                                target_user_id,
+                                "USER_CREATE_OTP",
+                                &get_otp_email_details(
+                                    config,
+                                    target_user_id,
+                                    &user_model.email,
+                                    &user_otp.token,
+                                ),
+                            )
+                            .await;
+                        send_otp_email(
+                            tracking_label,
+                            config,
+                            kafka_pool,
+                            &user_model,
+                            &user_otp,
+                        )
+                        .await;
+                        otp_sent = true;
+                    }
+                    Err(e) => {
+                        warn!(
+                            "{tracking_label} - \
+                            forced password reset for user \
+                            {target_user_id} did not email an otp \
+                            status={} - {}",
+                            e.status, e.msg
+                        );
+                    }
+                }
+            }
+            Err(e) => {
+                warn!(
+                    "{tracking_label} - \
+                    forced password reset for user {target_user_id} \
+                    did not email an otp - {e}"
+                );
+            }
+        }
+    }
+
+    let response = Response::builder()
+        .status(200)
+        .body(Body::from(
+            serde_json::to_string(&ApiResAdminForcePasswordReset {
+                target_user_id,
+                revoked_sessions: admin_action.revoked_sessions,
+                otp_sent,
+                msg: "success".to_string(),
+                error_code: None,
+            })
+            .unwrap(),
+        ))
+        .unwrap();
+    Ok(response)
+}
+
+/// get_force_password_reset_response
+///
+/// Build a failed
+/// [`ApiResAdminForcePasswordReset`](crate::requests::admin::force_password_reset::ApiResAdminForcePasswordReset)
+/// response
+///
+fn get_force_password_reset_response(
+    status: u16,
+    target_user_id: i32,
+    msg: &str,
+    error_code: ApiErrorCode,
+) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::from(
+            serde_json::to_string(&ApiResAdminForcePasswordReset {
+                target_user_id,
+                msg: msg.to_string(),
+                error_code: Some(error_code),
+                ..Default::default()
+            })
+            .unwrap(),
+        ))
+        .unwrap()
+}
@@ -0,0 +1,305 @@
+//! Module for reporting the jwt signing keys
+//!
+//! ## Admin Get JWT Keys
+//!
+//! Report the jwt keys loaded on this api server, which ``kid`` signs new tokens, which keys are retired and how many active tokens still use each key. Use this during a blue/green key migration to know when an old key can be retired without logging out its users. The requesting user must have the ``admin`` role.
+//!
+//! - URL path: ``/admin/jwt/keys``
+//! - Method: ``GET``
+//! - Handler: [`get_admin_jwt_keys`](crate::requests::admin::get_admin_jwt_keys::get_admin_jwt_keys)
+//! - Request: none (uses the token header)
+//! - Response: [`ApiResAdminJwtKeys`](crate::requests::admin::get_admin_jwt_keys::ApiResAdminJwtKeys)
+//!
+use std::convert::Infallible;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Response;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::core::core_config::CoreConfig;
+use crate::jwt::api::get_token_expiration_in_seconds;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::requests::admin::is_admin_user::is_admin_user;
+use crate::requests::auth::validate_user_token::validate_user_token;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::models::user_session::get_active_token_counts_by_kid;
+use crate::requests::models::user_session::get_user_session_by_token;
+use crate::requests::models::user_session::ModelJwtKidUsage;
+
+/// ApiResAdminJwtKey
+///
+/// Status for one jwt kid
+///
+/// # Arguments
+///
+/// * `kid` - `String` - key id in the jwt header
+/// * `loaded` - `bool` - this api server has the public key
+/// * `can_sign` - `bool` - this api server has the private key
+/// * `signing` - `bool` - new tokens are signed with this key
+/// * `retired` - `bool` - kid is in ``jwt_retired_kids``
+///   so its tokens are rejected
+/// * `active_tokens` - `i64` - active tokens signed with this
+///   key that have not expired
+/// * `last_used_at` - `String` - most recent request with one
+///   of the active tokens
+///
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ApiResAdminJwtKey {
+    pub kid: String,
+    pub loaded: bool,
+    pub can_sign: bool,
+    pub signing: bool,
+    pub retired: bool,
+    pub active_tokens: i64,
+    pub last_used_at: String,
+}
+
+/// ApiResAdminJwtKeys
+///
+/// # Response type for get_admin_jwt_keys and retire_jwt_key
+///
+/// Return the jwt key migration status
+///
+/// # Usage
+///
+/// This type is the serialized output for the functions:
+/// [`get_admin_jwt_keys`](crate::requests::admin::get_admin_jwt_keys::get_admin_jwt_keys)
+/// and
+/// [`retire_jwt_key`](crate::requests::admin::retire_jwt_key::retire_jwt_key)
+/// and contained within the
+/// hyper [`Body`](hyper::Body)
+/// of the
+/// hyper [`Response`](hyper::Response)
+/// sent back to the client.
+///
+/// # Arguments
+///
+/// * `signing_kid` - `String` - kid signing new tokens
+///   on this api server
+/// * `pinned_kid` - `String` - ``jwt_signing_kid`` runtime
+///   setting (empty = newest loaded key)
+/// * `keys` - `Vec<`[`ApiResAdminJwtKey`](crate::requests::admin::get_admin_jwt_keys::ApiResAdminJwtKey)`>` -
+///   loaded, retired and in-use kids sorted by kid
+/// * `revoked_tokens` - `u64` - tokens revoked by a
+///   forced retire
+/// * `msg` - `String` - help message
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   machine-readable failure code (omitted on success)
+///
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ApiResAdminJwtKeys {
+    pub signing_kid: String,
+    pub pinned_kid: String,
+    pub keys: Vec<ApiResAdminJwtKey>,
+    pub revoked_tokens: u64,
+    pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ApiErrorCode>,
+}
+
+/// build_jwt_keys_report
+///
+/// Combine this api server's
+/// [`JwtKeys`](crate::jwt::jwt_keys::JwtKeys),
+/// the jwt runtime settings and the active token
+/// counts by kid
+///
+/// # Arguments
+///
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `usage` - `&[`[`ModelJwtKidUsage`](crate::requests::models::user_session::ModelJwtKidUsage)`]` -
+///   active token counts by kid
+///
+/// # Returns
+///
+/// [`ApiResAdminJwtKeys`](crate::requests::admin::get_admin_jwt_keys::ApiResAdminJwtKeys)
+///
+pub fn build_jwt_keys_report(
+    config: &CoreConfig,
+    usage: &[ModelJwtKidUsage],
+) -> ApiResAdminJwtKeys {
+    let settings = config.get_settings();
+    let jwt_keys = config.jwt_keys.read().unwrap().clone();
+    let (signing_kid, _) = jwt_keys.get_signing_key(&settings.jwt_signing_kid);
+    let mut kids: Vec<String> =
+        jwt_keys.decoding_keys.keys().cloned().collect();
+    kids.extend(settings.jwt_retired_kids.iter().cloned());
+    kids.extend(usage.iter().map(|u| u.kid.clone()));
+    kids.sort();
+    kids.dedup();
+    let keys = kids
+        .into_iter()
+        .map(|kid| {
+            let kid_usage = usage.iter().find(|u| u.kid == kid);
+            ApiResAdminJwtKey {
+                loaded: jwt_keys.decoding_keys.contains_key(&kid),
+                can_sign: jwt_keys.encoding_keys.contains_key(&kid),
+                signing: kid == signing_kid,
+                retired: settings.jwt_retired_kids.contains(&kid),
+                active_tokens: kid_usage.map_or(0, |u| u.active_tokens),
+                last_used_at: kid_usage
+                    .map(|u| u.last_used_at.clone())
+                    .unwrap_or_default(),
+                kid,
+            }
+        })
+        .collect();
+    ApiResAdminJwtKeys {
+        signing_kid,
+        pinned_kid: settings.jwt_signing_kid,
+        keys,
+        ..Default::default()
+    }
+}
+
+/// get_admin_jwt_keys
+///
+/// Handler for reporting the jwt keys and how many
+/// active tokens still use each key
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
+///   db threadpool with required tls encryption
+/// * `kafka_pool` -
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+///   for asynchronously publishing messages to the connected kafka cluster
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+///
+/// # Returns
+///
+/// ## get_admin_jwt_keys on Success Returns
+///
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResAdminJwtKeys`](crate::requests::admin::get_admin_jwt_keys::ApiResAdminJwtKeys)
+/// dictionary within the
+/// [`Body`](hyper::Body) and a
+/// `200` HTTP status code
+///
+/// Ok([`Response`](hyper::Response))
+///
+/// # Errors
+///
+/// ## get_admin_jwt_keys on Failure Returns
+///
+/// All errors return as a
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResAdminJwtKeys`](crate::requests::admin::get_admin_jwt_keys::ApiResAdminJwtKeys)
+/// dictionary with a
+/// `non-200` HTTP status code (`403` if the user
+/// is not an ``admin``)
+///
+/// Err([`Response`](hyper::Response))
+///
+pub async fn get_admin_jwt_keys(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    kafka_pool: &KafkaPublisher,
+    headers: &HeaderMap<HeaderValue>,
+) -> std::result::Result<Response<Body>, Infallible> {
+    let token = &config.token_cookie.get_request_token(headers);
+
+    let conn = db_pool.get().await.unwrap();
+    let (_, user_id) = get_user_session_by_token(tracking_label, token, &conn)
+        .await
+        .unwrap_or((-1, -1));
+    let valid_token = match user_id > 0 {
+        true => {
+            validate_user_token(tracking_label, config, &conn, headers, user_id)
+                .await
+        }
+        false => Err(ApiErrorCode::InvalidToken),
+    };
+    if let Err(error_code) = valid_token {
+        let response = Response::builder()
+            .status(400)
+            .body(Body::from(
+                serde_json::to_string(&ApiResAdminJwtKeys {
+                    msg: ("Admin get jwt keys failed due to invalid token")
+                        .to_string(),
+                    error_code: Some(error_code),
+                    ..Default::default()
+                })
+                .unwrap(),
+            ))
+            .unwrap();
+        return Ok(response);
+    }
+    if !is_admin_user(tracking_label, user_id, &conn).await {
+        warn!(
+            "{tracking_label} - \
+            rejected get jwt keys from non-admin user {user_id}"
+        );
+        let response = Response::builder()
+            .status(403)
+            .body(Body::from(
+                serde_json::to_string(&ApiResAdminJwtKeys {
+                    msg: ("Admin get jwt keys failed - user is not an admin")
+                        .to_string(),
+                    error_code: Some(ApiErrorCode::Forbidden),
+                    ..Default::default()
+                })
+                .unwrap(),
+            ))
+            .unwrap();
+        return Ok(response);
+    }
+
+    match get_active_token_counts_by_kid(
+        tracking_label,
+        get_token_expiration_in_seconds(),
+        &conn,
+    )
+    .await
+    {
+        Ok(usage) => {
+            config
+                .events
+                .publish_user_event(
+                    kafka_pool,
+                    user_id,
+                    "ADMIN_GET_JWT_KEYS",
+                    "",
+                )
+                .await;
+
+            let mut res = build_jwt_keys_report(config, &usage);
+            res.msg = "success".to_string();
+            let response = Response::builder()
+                .status(200)
+                .body(Body::from(serde_json::to_string(&res).unwrap()))
+                .unwrap();
+            Ok(response)
+        }
+        Err(err_msg) => {
+            error!("{err_msg}");
+            let response = Response::builder()
+                .status(500)
+                .body(Body::from(
+                    serde_json::to_string(&ApiResAdminJwtKeys {
+                        msg: ("Admin get jwt keys failed").to_string(),
+                        error_code: Some(ApiErrorCode::InternalError),
+                        ..Default::default()
+                    })
+                    .unwrap(),
+                ))
+                .unwrap();
+            Ok(response)
+        }
+    }
+}
@@ -0,0 +1,300 @@
+//! Module for reporting the password hash migration
+//!
+//! ## Admin Get Password Schemes
+//!
+//! Report how many users have a password hash with each scheme (see [`password_migration`](crate::utils::password_migration)) and which scheme new hashes use. Users on a legacy scheme are rehashed with the current scheme at their next successful login, so watch ``legacy_users`` drop after changing the ``PASSWORD_HASH_*`` parameters or ``SERVER_PASSWORD_SALT``. The requesting user must have the ``admin`` role.
+//!
+//! - URL path: ``/admin/passwords/schemes``
+//! - Method: ``GET``
+//! - Handler: [`get_admin_password_schemes`](crate::requests::admin::get_admin_password_schemes::get_admin_password_schemes)
+//! - Request: none (uses the token header)
+//! - Response: [`ApiResAdminPasswordSchemes`](crate::requests::admin::get_admin_password_schemes::ApiResAdminPasswordSchemes)
+//!
+use std::convert::Infallible;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Response;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::core::core_config::CoreConfig;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::requests::admin::is_admin_user::is_admin_user;
+use crate::requests::auth::validate_user_token::validate_user_token;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::models::user_repo::PasswordSchemeCount;
+use crate::requests::models::user_repo::UserRepo;
+use crate::requests::models::user_session::get_user_session_by_token;
+use crate::utils::password_migration::get_password_scheme;
+
+/// ApiResAdminPasswordScheme
+///
+/// Users with one password hash scheme
+///
+/// # Arguments
+///
+/// * `scheme` - `String` - ``users.password_scheme``
+///   (``unknown`` for hashes that are not recorded yet)
+/// * `current` - `bool` - new hashes use this scheme
+/// * `users` - `i64` - users with the scheme
+///
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ApiResAdminPasswordScheme {
+    pub scheme: String,
+    pub current: bool,
+    pub users: i64,
+}
+
+/// ApiResAdminPasswordSchemes
+///
+/// # Response type for get_admin_password_schemes
+///
+/// Return the password hash migration progress
+///
+/// # Usage
+///
+/// This type is the serialized output for the function:
+/// [`get_admin_password_schemes`](crate::requests::admin::get_admin_password_schemes::get_admin_password_schemes)
+/// and contained within the
+/// hyper [`Body`](hyper::Body)
+/// of the
+/// hyper [`Response`](hyper::Response)
+/// sent back to the client.
+///
+/// # Arguments
+///
+/// * `current_scheme` - `String` - scheme of new password
+///   hashes on this api server
+/// * `total_users` - `i64` - users in the db
+/// * `legacy_users` - `i64` - users that still have a hash
+///   with another scheme
+/// * `schemes` - `Vec<`[`ApiResAdminPasswordScheme`](crate::requests::admin::get_admin_password_schemes::ApiResAdminPasswordScheme)`>` -
+///   users per scheme (most users first)
+/// * `msg` - `String` - help message
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   machine-readable failure code (omitted on success)
+///
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ApiResAdminPasswordSchemes {
+    pub current_scheme: String,
+    pub total_users: i64,
+    pub legacy_users: i64,
+    pub schemes: Vec<ApiResAdminPasswordScheme>,
+    pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ApiErrorCode>,
+}
+
+/// build_password_schemes_report
+///
+/// Compare the users per scheme with this api server's
+/// current password hash scheme
+///
+/// # Arguments
+///
+/// * `current_scheme` - `&str` - scheme of new password hashes
+/// * `counts` - `&[`[`PasswordSchemeCount`](crate::requests::models::user_repo::PasswordSchemeCount)`]` -
+///   users per scheme
+///
+/// # Returns
+///
+/// [`ApiResAdminPasswordSchemes`](crate::requests::admin::get_admin_password_schemes::ApiResAdminPasswordSchemes)
+///
+/// # Examples
+///
+/// ```rust
+/// use restapi::requests::admin::get_admin_password_schemes::build_password_schemes_report;
+/// use restapi::requests::models::user_repo::PasswordSchemeCount;
+/// let counts = vec![
+///     PasswordSchemeCount { scheme: "new".to_string(), users: 3 },
+///     PasswordSchemeCount { scheme: "old".to_string(), users: 2 },
+/// ];
+/// let report = build_password_schemes_report("new", &counts);
+/// assert_eq!(report.total_users, 5);
+/// assert_eq!(report.legacy_users, 2);
+/// assert!(report.schemes[0].current && !report.schemes[1].current);
+/// ```
+///
+pub fn build_password_schemes_report(
+    current_scheme: &str,
+    counts: &[PasswordSchemeCount],
+) -> ApiResAdminPasswordSchemes {
+    let schemes: Vec<ApiResAdminPasswordScheme> = counts
+        .iter()
+        .map(|count| ApiResAdminPasswordScheme {
+            scheme: count.scheme.clone(),
+            current: count.scheme == current_scheme,
+            users: count.users,
+        })
+        .collect();
+    ApiResAdminPasswordSchemes {
+        current_scheme: current_scheme.to_string(),
+        total_users: schemes.iter().map(|s| s.users).sum(),
+        legacy_users: schemes
+            .iter()
+            .filter(|s| !s.current)
+            .map(|s| s.users)
+            .sum(),
+        schemes,
+        ..Default::default()
+    }
+}
+
+/// get_admin_password_schemes
+///
+/// Handler for reporting how many users have a password
+/// hash with each scheme
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
+///   db threadpool with required tls encryption
+/// * `kafka_pool` -
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+///   for asynchronously publishing messages to the connected kafka cluster
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+///
+/// # Returns
+///
+/// ## get_admin_password_schemes on Success Returns
+///
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResAdminPasswordSchemes`](crate::requests::admin::get_admin_password_schemes::ApiResAdminPasswordSchemes)
+/// dictionary within the
+/// [`Body`](hyper::Body) and a
+/// `200` HTTP status code
+///
+/// Ok([`Response`](hyper::Response))
+///
+/// # Errors
+///
+/// ## get_admin_password_schemes on Failure Returns
+///
+/// All errors return as a
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResAdminPasswordSchemes`](crate::requests::admin::get_admin_password_schemes::ApiResAdminPasswordSchemes)
+/// dictionary with a
+/// `non-200` HTTP status code (`403` if the user
+/// is not an ``admin``)
+///
+/// Err([`Response`](hyper::Response))
+///
+pub async fn get_admin_password_schemes(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    kafka_pool: &KafkaPublisher,
+    headers: &HeaderMap<HeaderValue>,
+) -> std::result::Result<Response<Body>, Infallible> {
+    let token = &config.token_cookie.get_request_token(headers);
+
+    let conn = db_pool.get().await.unwrap();
+    let (_, user_id) = get_user_session_by_token(tracking_label, token, &conn)
+        .await
+        .unwrap_or((-1, -1));
+    let valid_token = match user_id > 0 {
+        true => {
+            validate_user_token(tracking_label, config, &conn, headers, user_id)
+                .await
+        }
+        false => Err(ApiErrorCode::InvalidToken),
+    };
+    if let Err(error_code) = valid_token {
+        let response = Response::builder()
+            .status(400)
+            .body(Body::from(
+                serde_json::to_string(&ApiResAdminPasswordSchemes {
+                    msg: ("Admin get password schemes failed due to \
+                        invalid token")
+                        .to_string(),
+                    error_code: Some(error_code),
+                    ..Default::default()
+                })
+                .unwrap(),
+            ))
+            .unwrap();
+        return Ok(response);
+    }
+    if !is_admin_user(tracking_label, user_id, &conn).await {
+        warn!(
+            "{tracking_label} - \
+            rejected get password schemes from non-admin user {user_id}"
+        );
+        let response = Response::builder()
+            .status(403)
+            .body(Body::from(
+                serde_json::to_string(&ApiResAdminPasswordSchemes {
+                    msg: ("Admin get password schemes failed - \
+                        user is not an admin")
+                        .to_string(),
+                    error_code: Some(ApiErrorCode::Forbidden),
+                    ..Default::default()
+                })
+                .unwrap(),
+            ))
+            .unwrap();
+        return Ok(response);
+    }
+
+    match UserRepo::new(&conn)
+        .count_by_password_scheme(tracking_label)
+        .await
+    {
+        Ok(counts) => {
+            config
+                .events
+                .publish_user_event(
+                    kafka_pool,
+                    user_id,
+                    "ADMIN_GET_PASSWORD_SCHEMES",
+                    "",
+                )
+                .await;
+
+            // the current scheme depends on the parameters and
+            // salt so hash a throwaway value to get it
+            let current_hash = config
+                .password_hashing
+                .hash_password("password-scheme", &config.server_password_salt)
+                .unwrap_or_default();
+            let mut res = build_password_schemes_report(
+                &get_password_scheme(&current_hash),
+                &counts,
+            );
+            res.msg = "success".to_string();
+            let response = Response::builder()
+                .status(200)
+                .body(Body::from(serde_json::to_string(&res).unwrap()))
+                .unwrap();
+            Ok(response)
+        }
+        Err(e) => {
+            error!("{e}");
+            let response = Response::builder()
+                .status(500)
+                .body(Body::from(
+                    serde_json::to_string(&ApiResAdminPasswordSchemes {
+                        msg: ("Admin get password schemes failed").to_string(),
+                        error_code: Some(ApiErrorCode::InternalError),
+                        ..Default::default()
+                    })
+                    .unwrap(),
+                ))
+                .unwrap();
+            Ok(response)
+        }
+    }
+}
@@ -0,0 +1,216 @@
+//! Module for getting the runtime settings
+//!
+//! ## Admin Get Settings
+//!
+//! Get the effective runtime settings on this api server and the overrides stored in the ``settings`` table. The requesting user must have the ``admin`` role.
+//!
+//! - URL path: ``/admin/settings``
+//! - Method: ``GET``
+//! - Handler: [`get_admin_settings`](crate::requests::admin::get_admin_settings::get_admin_settings)
+//! - Request: none (uses the token header)
+//! - Response: [`ApiResAdminSettings`](crate::requests::admin::get_admin_settings::ApiResAdminSettings)
+//!
+use std::convert::Infallible;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Response;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::core::core_config::CoreConfig;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::requests::admin::is_admin_user::is_admin_user;
+use crate::requests::auth::validate_user_token::validate_user_token;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::models::setting::get_settings;
+use crate::requests::models::setting::ModelSetting;
+use crate::requests::models::user_session::get_user_session_by_token;
+use crate::settings::runtime_settings::RuntimeSettings;
+
+/// ApiResAdminSettings
+///
+/// # Response type for get_admin_settings and update_admin_settings
+///
+/// Return the effective runtime settings and the db overrides
+///
+/// # Usage
+///
+/// This type is the serialized output for the functions:
+/// [`get_admin_settings`](crate::requests::admin::get_admin_settings::get_admin_settings)
+/// and
+/// [`update_admin_settings`](crate::requests::admin::update_admin_settings::update_admin_settings)
+/// and contained within the
+/// hyper [`Body`](hyper::Body)
+/// of the
+/// hyper [`Response`](hyper::Response)
+/// sent back to the client.
+///
+/// # Arguments
+///
+/// * `settings` - [`RuntimeSettings`](crate::settings::runtime_settings::RuntimeSettings) -
+///   effective settings on this api server
+/// * `overrides` - `Vec<`[`ModelSetting`](crate::requests::models::setting::ModelSetting)`>` -
+///   overrides stored in the ``settings`` table
+/// * `msg` - `String` - help message
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   machine-readable failure code (omitted on success)
+///
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ApiResAdminSettings {
+    pub settings: RuntimeSettings,
+    pub overrides: Vec<ModelSetting>,
+    pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ApiErrorCode>,
+}
+
+/// get_admin_settings
+///
+/// Handler for getting the effective runtime settings
+/// and the db overrides
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
+///   db threadpool with required tls encryption
+/// * `kafka_pool` -
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+///   for asynchronously publishing messages to the connected kafka cluster
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+///
+/// # Returns
+///
+/// ## get_admin_settings on Success Returns
+///
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResAdminSettings`](crate::requests::admin::get_admin_settings::ApiResAdminSettings)
+/// dictionary within the
+/// [`Body`](hyper::Body) and a
+/// `200` HTTP status code
+///
+/// Ok([`Response`](hyper::Response))
+///
+/// # Errors
+///
+/// ## get_admin_settings on Failure Returns
+///
+/// All errors return as a
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResAdminSettings`](crate::requests::admin::get_admin_settings::ApiResAdminSettings)
+/// dictionary with a
+/// `non-200` HTTP status code (`403` if the user
+/// is not an ``admin``)
+///
+/// Err([`Response`](hyper::Response))
+///
+pub async fn get_admin_settings(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    kafka_pool: &KafkaPublisher,
+    headers: &HeaderMap<HeaderValue>,
+) -> std::result::Result<Response<Body>, Infallible> {
+    let token = &config.token_cookie.get_request_token(headers);
+
+    let conn = db_pool.get().await.unwrap();
+    let (_, user_id) = get_user_session_by_token(tracking_label, token, &conn)
+        .await
+        .unwrap_or((-1, -1));
+    let valid_token = match user_id > 0 {
+        true => {
+            validate_user_token(tracking_label, config, &conn, headers, user_id)
+                .await
+        }
+        false => Err(ApiErrorCode::InvalidToken),
+    };
+    if let Err(error_code) = valid_token {
+        let response = Response::builder()
+            .status(400)
+            .body(Body::from(
+                serde_json::to_string(&ApiResAdminSettings {
+                    msg: ("Admin get settings failed due to invalid token")
+                        .to_string(),
+                    error_code: Some(error_code),
+                    ..Default::default()
+                })
+                .unwrap(),
+            ))
+            .unwrap();
+        return Ok(response);
+    }
+    if !is_admin_user(tracking_label, user_id, &conn).await {
+        warn!(
+            "{tracking_label} - \
+            rejected get settings from non-admin user {user_id}"
+        );
+        let response = Response::builder()
+            .status(403)
+            .body(Body::from(
+                serde_json::to_string(&ApiResAdminSettings {
+                    msg: ("Admin get settings failed - user is not an admin")
+                        .to_string(),
+                    error_code: Some(ApiErrorCode::Forbidden),
+                    ..Default::default()
+                })
+                .unwrap(),
+            ))
+            .unwrap();
+        return Ok(response);
+    }
+
+    match get_settings(tracking_label, &conn).await {
+        Ok(overrides) => {
+            config
+                .events
+                .publish_user_event(
+                    kafka_pool,
+                    user_id,
+                    "ADMIN_GET_SETTINGS",
+                    "",
+                )
+                .await;
+
+            let response = Response::builder()
+                .status(200)
+                .body(Body::from(
+                    serde_json::to_string(&ApiResAdminSettings {
+                        settings: config.get_settings(),
+                        overrides,
+                        msg: "success".to_string(),
+                        error_code: None,
+                    })
+                    .unwrap(),
+                ))
+                .unwrap();
+            Ok(response)
+        }
+        Err(err_msg) => {
+            error!("{err_msg}");
+            let response = Response::builder()
+                .status(500)
+                .body(Body::from(
+                    serde_json::to_string(&ApiResAdminSettings {
+                        msg: ("Admin get settings failed").to_string(),
+                        error_code: Some(ApiErrorCode::InternalError),
+                        ..Default::default()
+                    })
+                    .unwrap(),
+                ))
+                .unwrap();
+            Ok(response)
+        }
+    }
+}
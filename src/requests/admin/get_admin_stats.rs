@@ -0,0 +1,192 @@
+//! Module for the admin dashboard stats
+//!
+//! ## Admin Get Stats
+//!
+//! Get aggregate json stats for lightweight dashboards that do not run PromQL: total, active and verified users (with the verified percent of active users), uploads in the last 24 hours, total stored bytes and the logins in each of the last 24 hours. The stats are computed from the db for every tenant and reused for ``ADMIN_STATS_CACHE_SECONDS`` on each api server (``cached`` is ``true`` for a reused result). The requesting user must have the ``admin`` role.
+//!
+//! - URL path: ``/admin/stats``
+//! - Method: ``GET``
+//! - Handler: [`get_admin_stats`](crate::requests::admin::get_admin_stats::get_admin_stats)
+//! - Request: none (uses the token header)
+//! - Response: [`ApiResAdminStats`](crate::requests::admin::get_admin_stats::ApiResAdminStats)
+//!
+use std::convert::Infallible;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Response;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::core::core_config::CoreConfig;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::requests::admin::is_admin_user::is_admin_user;
+use crate::requests::auth::validate_user_token::validate_user_token;
+use crate::requests::models::admin_stats::get_admin_stats as get_db_admin_stats;
+use crate::requests::models::admin_stats::ModelAdminStats;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::models::user_session::get_user_session_by_token;
+
+/// ApiResAdminStats
+///
+/// # Response type for get_admin_stats
+///
+/// Return the aggregate stats
+///
+/// # Arguments
+///
+/// * `stats` - [`ModelAdminStats`](crate::requests::models::admin_stats::ModelAdminStats)
+/// * `cached` - `bool` - the stats were reused from an
+///   earlier request (see ``stats.computed_at``)
+/// * `cache_seconds` - `i64` - ``ADMIN_STATS_CACHE_SECONDS``
+/// * `msg` - `String` - help message
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   machine-readable failure code (omitted on success)
+///
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ApiResAdminStats {
+    pub stats: ModelAdminStats,
+    pub cached: bool,
+    pub cache_seconds: i64,
+    pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ApiErrorCode>,
+}
+
+/// get_admin_stats
+///
+/// Handler for the admin dashboard stats
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
+///   db threadpool with required tls encryption
+/// * `kafka_pool` -
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+///   for asynchronously publishing messages to the connected kafka cluster
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+///
+/// # Returns
+///
+/// ## get_admin_stats on Success Returns
+///
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResAdminStats`](crate::requests::admin::get_admin_stats::ApiResAdminStats)
+/// dictionary within the
+/// [`Body`](hyper::Body) and a
+/// `200` HTTP status code
+///
+/// Ok([`Response`](hyper::Response))
+///
+/// # Errors
+///
+/// `400` for an invalid token, `403` if the user is not
+/// an ``admin`` and `500` when a db query fails
+///
+/// Err([`Response`](hyper::Response))
+///
+pub async fn get_admin_stats(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    _kafka_pool: &KafkaPublisher,
+    headers: &HeaderMap<HeaderValue>,
+) -> std::result::Result<Response<Body>, Infallible> {
+    let token = &config.token_cookie.get_request_token(headers);
+
+    let conn = db_pool.get().await.unwrap();
+    let (_, user_id) = get_user_session_by_token(tracking_label, token, &conn)
+        .await
+        .unwrap_or((-1, -1));
+    let valid_token = match user_id > 0 {
+        true => {
+            validate_user_token(tracking_label, config, &conn, headers, user_id)
+                .await
+        }
+        false => Err(ApiErrorCode::InvalidToken),
+    };
+    if let Err(error_code) = valid_token {
+        return Ok(get_admin_stats_response(
+            400,
+            "Admin get stats failed due to invalid token",
+            error_code,
+        ));
+    }
+    if !is_admin_user(tracking_label, user_id, &conn).await {
+        warn!(
+            "{tracking_label} - \
+            rejected get stats from non-admin user {user_id}"
+        );
+        return Ok(get_admin_stats_response(
+            403,
+            "Admin get stats failed - user is not an admin",
+            ApiErrorCode::Forbidden,
+        ));
+    }
+
+    let (stats, cached) = match config.admin_stats.get_stats() {
+        Some(stats) => (stats, true),
+        None => match get_db_admin_stats(tracking_label, &conn).await {
+            Ok(stats) => {
+                config.admin_stats.set_stats(&stats);
+                (stats, false)
+            }
+            Err(err_msg) => {
+                error!("{err_msg}");
+                return Ok(get_admin_stats_response(
+                    500,
+                    "Admin get stats failed",
+                    ApiErrorCode::InternalError,
+                ));
+            }
+        },
+    };
+    let response = Response::builder()
+        .status(200)
+        .body(Body::from(
+            serde_json::to_string(&ApiResAdminStats {
+                stats,
+                cached,
+                cache_seconds: config.admin_stats.cache_seconds,
+                msg: "success".to_string(),
+                error_code: None,
+            })
+            .unwrap(),
+        ))
+        .unwrap();
+    Ok(response)
+}
+
+/// get_admin_stats_response
+///
+/// Build an error response for
+/// [`get_admin_stats`](crate::requests::admin::get_admin_stats::get_admin_stats)
+///
+fn get_admin_stats_response(
+    status: u16,
+    msg: &str,
+    error_code: ApiErrorCode,
+) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::from(
+            serde_json::to_string(&ApiResAdminStats {
+                msg: msg.to_string(),
+                error_code: Some(error_code),
+                ..Default::default()
+            })
+            .unwrap(),
+        ))
+        .unwrap()
+}
@@ -0,0 +1,205 @@
+//! Module for listing the ip allow and deny rules
+//!
+//! ## Admin Get IP Rules
+//!
+//! List the rules from the ``IP_ALLOWLIST``, ``IP_DENYLIST``, ``IP_ROUTE_ALLOWLIST`` and ``IP_ROUTE_DENYLIST`` environment variables and the ``ip_rules`` table with the client address this api server sees for the requesting admin (see [`IpAccessControl`](crate::core::server::ip_access::IpAccessControl)). The requesting user must have the ``admin`` role.
+//!
+//! - URL path: ``/admin/ip-rules``
+//! - Method: ``GET``
+//! - Handler: [`get_ip_rules`](crate::requests::admin::get_ip_rules::get_ip_rules)
+//! - Request: none (uses the token header)
+//! - Response: [`ApiResAdminIpRules`](crate::requests::admin::get_ip_rules::ApiResAdminIpRules)
+//!
+use std::convert::Infallible;
+use std::net::SocketAddr;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Response;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::core::core_config::CoreConfig;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::requests::admin::is_admin_user::is_admin_user;
+use crate::requests::auth::validate_user_token::validate_user_token;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::models::ip_rule::get_ip_rules as get_db_ip_rules;
+use crate::requests::models::ip_rule::ModelIpRule;
+use crate::requests::models::user_session::get_user_session_by_token;
+
+/// ApiResAdminIpRules
+///
+/// # Response type for get_ip_rules
+///
+/// Return the ip allow and deny rules
+///
+/// # Arguments
+///
+/// * `client_ip` - `String` - requesting admin's address
+///   as seen by this api server
+/// * `env_rules` - `Vec<`[`ModelIpRule`](crate::requests::models::ip_rule::ModelIpRule)`>` -
+///   environment variable rules (``id`` is ``0``)
+/// * `rules` - `Vec<`[`ModelIpRule`](crate::requests::models::ip_rule::ModelIpRule)`>` -
+///   ``ip_rules`` table rules
+/// * `msg` - `String` - help message
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   machine-readable failure code (omitted on success)
+///
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ApiResAdminIpRules {
+    pub client_ip: String,
+    pub env_rules: Vec<ModelIpRule>,
+    pub rules: Vec<ModelIpRule>,
+    pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ApiErrorCode>,
+}
+
+/// get_ip_rules
+///
+/// Handler for listing the ip allow and deny rules
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
+///   db threadpool with required tls encryption
+/// * `kafka_pool` -
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+///   for asynchronously publishing messages to the connected kafka cluster
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+/// * `remote_addr` - `&SocketAddr` - client address of the
+///   requesting admin
+///
+/// # Returns
+///
+/// ## get_ip_rules on Success Returns
+///
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResAdminIpRules`](crate::requests::admin::get_ip_rules::ApiResAdminIpRules)
+/// dictionary within the
+/// [`Body`](hyper::Body) and a
+/// `200` HTTP status code
+///
+/// Ok([`Response`](hyper::Response))
+///
+/// # Errors
+///
+/// `400` for an invalid token, `403` if the user is not an
+/// ``admin`` and `500` when the db query fails
+///
+/// Err([`Response`](hyper::Response))
+///
+pub async fn get_ip_rules(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    _kafka_pool: &KafkaPublisher,
+    headers: &HeaderMap<HeaderValue>,
+    remote_addr: &SocketAddr,
+) -> std::result::Result<Response<Body>, Infallible> {
+    let token = &config.token_cookie.get_request_token(headers);
+
+    let conn = db_pool.get().await.unwrap();
+    let (_, user_id) = get_user_session_by_token(tracking_label, token, &conn)
+        .await
+        .unwrap_or((-1, -1));
+    let valid_token = match user_id > 0 {
+        true => {
+            validate_user_token(tracking_label, config, &conn, headers, user_id)
+                .await
+        }
+        false => Err(ApiErrorCode::InvalidToken),
+    };
+    if let Err(error_code) = valid_token {
+        return Ok(get_ip_rules_response(
+            400,
+            "Admin get ip rules failed due to invalid token",
+            error_code,
+        ));
+    }
+    if !is_admin_user(tracking_label, user_id, &conn).await {
+        warn!(
+            "{tracking_label} - \
+            rejected get ip rules from non-admin user {user_id}"
+        );
+        return Ok(get_ip_rules_response(
+            403,
+            "Admin get ip rules failed - user is not an admin",
+            ApiErrorCode::Forbidden,
+        ));
+    }
+
+    match get_db_ip_rules(tracking_label, &conn).await {
+        Ok(rules) => {
+            let env_rules = config
+                .ip_access
+                .env_rules
+                .iter()
+                .map(|rule| ModelIpRule {
+                    action: rule.get_action().to_string(),
+                    cidr: rule.cidr.to_string(),
+                    path_prefix: rule.path_prefix.clone(),
+                    created_by: -1,
+                    ..Default::default()
+                })
+                .collect();
+            let response = Response::builder()
+                .status(200)
+                .body(Body::from(
+                    serde_json::to_string(&ApiResAdminIpRules {
+                        client_ip: remote_addr.ip().to_string(),
+                        env_rules,
+                        rules,
+                        msg: "success".to_string(),
+                        error_code: None,
+                    })
+                    .unwrap(),
+                ))
+                .unwrap();
+            Ok(response)
+        }
+        Err(err_msg) => {
+            error!("{err_msg}");
+            Ok(get_ip_rules_response(
+                500,
+                "Admin get ip rules failed",
+                ApiErrorCode::InternalError,
+            ))
+        }
+    }
+}
+
+/// get_ip_rules_response
+///
+/// Build an error response for
+/// [`get_ip_rules`](crate::requests::admin::get_ip_rules::get_ip_rules)
+///
+fn get_ip_rules_response(
+    status: u16,
+    msg: &str,
+    error_code: ApiErrorCode,
+) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::from(
+            serde_json::to_string(&ApiResAdminIpRules {
+                msg: msg.to_string(),
+                error_code: Some(error_code),
+                ..Default::default()
+            })
+            .unwrap(),
+        ))
+        .unwrap()
+}
@@ -0,0 +1,190 @@
+//! Module for listing the registered webhook endpoints
+//!
+//! ## Admin Get Webhooks
+//!
+//! List the active webhook endpoints (without their secrets), the supported event types and if this api server sends deliveries (``WEBHOOKS_ENABLED``). The requesting user must have the ``admin`` role.
+//!
+//! - URL path: ``/admin/webhooks``
+//! - Method: ``GET``
+//! - Handler: [`get_webhooks`](crate::requests::admin::get_webhooks::get_webhooks)
+//! - Request: none (uses the token header)
+//! - Response: [`ApiResAdminWebhooks`](crate::requests::admin::get_webhooks::ApiResAdminWebhooks)
+//!
+use std::convert::Infallible;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Response;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::core::core_config::CoreConfig;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::requests::admin::is_admin_user::is_admin_user;
+use crate::requests::auth::validate_user_token::validate_user_token;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::models::user_session::get_user_session_by_token;
+use crate::requests::models::webhook::get_webhooks as get_db_webhooks;
+use crate::requests::models::webhook::ModelWebhook;
+use crate::webhooks::webhook_dispatcher::WEBHOOK_EVENT_TYPES;
+
+/// ApiResAdminWebhooks
+///
+/// # Response type for get_webhooks
+///
+/// Return the active webhook endpoints
+///
+/// # Arguments
+///
+/// * `enabled` - `bool` - ``WEBHOOKS_ENABLED`` on this api server
+/// * `event_types` - `Vec<String>` - supported webhook event types
+/// * `webhooks` - `Vec<`[`ModelWebhook`](crate::requests::models::webhook::ModelWebhook)`>`
+/// * `msg` - `String` - help message
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   machine-readable failure code (omitted on success)
+///
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ApiResAdminWebhooks {
+    pub enabled: bool,
+    pub event_types: Vec<String>,
+    pub webhooks: Vec<ModelWebhook>,
+    pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ApiErrorCode>,
+}
+
+/// get_webhooks
+///
+/// Handler for listing the active webhook endpoints
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
+///   db threadpool with required tls encryption
+/// * `kafka_pool` -
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+///   for asynchronously publishing messages to the connected kafka cluster
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+///
+/// # Returns
+///
+/// ## get_webhooks on Success Returns
+///
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResAdminWebhooks`](crate::requests::admin::get_webhooks::ApiResAdminWebhooks)
+/// dictionary within the
+/// [`Body`](hyper::Body) and a
+/// `200` HTTP status code
+///
+/// Ok([`Response`](hyper::Response))
+///
+/// # Errors
+///
+/// `400` for an invalid token, `403` if the user is not
+/// an ``admin`` and `500` when the db query fails
+///
+/// Err([`Response`](hyper::Response))
+///
+pub async fn get_webhooks(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    _kafka_pool: &KafkaPublisher,
+    headers: &HeaderMap<HeaderValue>,
+) -> std::result::Result<Response<Body>, Infallible> {
+    let token = &config.token_cookie.get_request_token(headers);
+
+    let conn = db_pool.get().await.unwrap();
+    let (_, user_id) = get_user_session_by_token(tracking_label, token, &conn)
+        .await
+        .unwrap_or((-1, -1));
+    let valid_token = match user_id > 0 {
+        true => {
+            validate_user_token(tracking_label, config, &conn, headers, user_id)
+                .await
+        }
+        false => Err(ApiErrorCode::InvalidToken),
+    };
+    if let Err(error_code) = valid_token {
+        return Ok(get_webhooks_response(
+            400,
+            "Admin get webhooks failed due to invalid token",
+            error_code,
+        ));
+    }
+    if !is_admin_user(tracking_label, user_id, &conn).await {
+        warn!(
+            "{tracking_label} - \
+            rejected get webhooks from non-admin user {user_id}"
+        );
+        return Ok(get_webhooks_response(
+            403,
+            "Admin get webhooks failed - user is not an admin",
+            ApiErrorCode::Forbidden,
+        ));
+    }
+
+    match get_db_webhooks(tracking_label, &conn).await {
+        Ok(webhooks) => {
+            let response = Response::builder()
+                .status(200)
+                .body(Body::from(
+                    serde_json::to_string(&ApiResAdminWebhooks {
+                        enabled: config.events.webhooks.enabled,
+                        event_types: WEBHOOK_EVENT_TYPES
+                            .iter()
+                            .map(|(v, _)| v.to_string())
+                            .collect(),
+                        webhooks,
+                        msg: "success".to_string(),
+                        error_code: None,
+                    })
+                    .unwrap(),
+                ))
+                .unwrap();
+            Ok(response)
+        }
+        Err(err_msg) => {
+            error!("{err_msg}");
+            Ok(get_webhooks_response(
+                500,
+                "Admin get webhooks failed",
+                ApiErrorCode::InternalError,
+            ))
+        }
+    }
+}
+
+/// get_webhooks_response
+///
+/// Build an error response for
+/// [`get_webhooks`](crate::requests::admin::get_webhooks::get_webhooks)
+///
+fn get_webhooks_response(
+    status: u16,
+    msg: &str,
+    error_code: ApiErrorCode,
+) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::from(
+            serde_json::to_string(&ApiResAdminWebhooks {
+                msg: msg.to_string(),
+                error_code: Some(error_code),
+                ..Default::default()
+            })
+            .unwrap(),
+        ))
+        .unwrap()
+}
@@ -0,0 +1,463 @@
+//! Module for inviting a new user
+//!
+//! ## Admin Invite User
+//!
+//! Create a pending ``users`` record (``users.state = 1``) and a ``users_invites`` record with a one-time-use invite token for closed-signup deployments. A ``USER_INVITE`` event is published so a mail service can send the invite. The invited user accepts with [`accept_user_invite`](crate::requests::user::accept_user_invite::accept_user_invite). The requesting user must have the ``admin`` role.
+//!
+//! - URL path: ``/admin/users/invite``
+//! - Method: ``POST``
+//! - Handler: [`invite_user`](crate::requests::admin::invite_user::invite_user)
+//! - Request: [`ApiReqAdminInviteUser`](crate::requests::admin::invite_user::ApiReqAdminInviteUser)
+//! - Response: [`ApiResAdminInviteUser`](crate::requests::admin::invite_user::ApiResAdminInviteUser)
+//!
+use std::convert::Infallible;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Response;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use chrono::Duration;
+use chrono::Utc;
+
+use crate::core::core_config::CoreConfig;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::monitoring::otel::trace_db_query;
+use crate::notifications::email_templates::normalize_locale;
+use crate::notifications::email_templates::EmailTemplates;
+use crate::requests::admin::is_admin_user::is_admin_user;
+use crate::requests::auth::validate_user_token::validate_user_token;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::models::user::DEFAULT_USER_LOCALE;
+use crate::requests::validation::field_rules::check_email;
+use crate::requests::validation::field_rules::check_id;
+use crate::requests::validation::field_rules::check_locale;
+use crate::requests::validation::field_rules::check_one_of;
+use crate::requests::validation::field_rules::USER_ROLES;
+use crate::requests::validation::normalize_email::normalize_email;
+use crate::requests::validation::validate_api_req::validate_api_req;
+use crate::requests::validation::validate_api_req::ApiFieldError;
+use crate::requests::validation::validate_api_req::ApiReqValidate;
+use crate::utils::get_server_address::get_server_address;
+use crate::utils::get_uuid::get_uuid;
+use crate::utils::hash_token::hash_token;
+use crate::utils::password_migration::get_password_scheme;
+
+/// ApiReqAdminInviteUser
+///
+/// # Request Type For invite_user
+///
+/// Invite a new user by email
+///
+/// This type is the deserialized input for:
+/// [`invite_user`](crate::requests::admin::invite_user::invite_user)
+///
+/// # Usage
+///
+/// This type is constructed from the deserialized
+/// `bytes` (`&[u8]`) argument
+/// on the
+/// [`invite_user`](crate::requests::admin::invite_user::invite_user)
+/// function.
+///
+/// # Arguments
+///
+/// * `user_id` - `i32` - admin user id
+/// * `email` - `String` - email to invite
+/// * `role` - `Option<String>` - role for the new user
+///   (default `user`)
+/// * `locale` - `Option<String>` - locale for the new
+///   user's emails (default ``en``)
+///
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApiReqAdminInviteUser {
+    pub user_id: i32,
+    pub email: String,
+    pub role: Option<String>,
+    #[serde(default)]
+    pub locale: Option<String>,
+}
+
+impl ApiReqValidate for ApiReqAdminInviteUser {
+    /// validate
+    ///
+    /// Require a positive `user_id`, a valid `email`,
+    /// a supported `role` and a valid `locale`
+    ///
+    fn validate(&self) -> Vec<ApiFieldError> {
+        let mut errors = Vec::new();
+        check_id(&mut errors, "user_id", self.user_id);
+        check_email(&mut errors, "email", &self.email);
+        if let Some(v) = &self.role {
+            check_one_of(&mut errors, "role", v.as_str(), &USER_ROLES);
+        }
+        if let Some(v) = &self.locale {
+            check_locale(&mut errors, "locale", v);
+        }
+        errors
+    }
+}
+
+/// ApiResAdminInviteUser
+///
+/// # Response type for invite_user
+///
+/// Return the invited user and the one-time-use
+/// invite token
+///
+/// # Usage
+///
+/// This type is the serialized output for the function:
+/// [`invite_user`](crate::requests::admin::invite_user::invite_user)
+/// and contained within the
+/// hyper [`Body`](hyper::Body)
+/// of the
+/// hyper [`Response`](hyper::Response)
+/// sent back to the client.
+///
+/// # Arguments
+///
+/// * `user_id` - `i32` - invited user id
+/// * `email` - `String` - invited email
+/// * `role` - `String` - invited user role
+/// * `token` - `String` - one-time-use invite token
+///   (only the hash is stored in the db)
+/// * `exp_date` - `String` - invite expiration date
+/// * `msg` - `String` - help message
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   machine-readable failure code (omitted on success)
+///
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ApiResAdminInviteUser {
+    pub user_id: i32,
+    pub email: String,
+    pub role: String,
+    pub token: String,
+    pub exp_date: String,
+    pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ApiErrorCode>,
+}
+
+/// invite_user
+///
+/// Handler for inviting a new user. The pending user
+/// and invite are created with one sql statement. The
+/// invite expires after ``USER_INVITE_EXP_IN_SECONDS``.
+///
+/// # Usage
+///
+/// ## Environment Variables
+///
+/// ```bash
+/// # 7 days
+/// export USER_INVITE_EXP_IN_SECONDS=604800
+/// ```
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
+///   db threadpool with required tls encryption
+/// * `kafka_pool` -
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+///   for asynchronously publishing messages to the connected kafka cluster
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+/// * `bytes` - `&[u8]` - received bytes from the hyper
+///   [`Request`](hyper::Request)'s [`Body`](hyper::Body)
+///
+/// # Returns
+///
+/// ## invite_user on Success Returns
+///
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResAdminInviteUser`](crate::requests::admin::invite_user::ApiResAdminInviteUser)
+/// dictionary within the
+/// [`Body`](hyper::Body) and a
+/// `201` HTTP status code
+///
+/// Ok([`Response`](hyper::Response))
+///
+/// # Errors
+///
+/// ## invite_user on Failure Returns
+///
+/// All errors return as a
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResAdminInviteUser`](crate::requests::admin::invite_user::ApiResAdminInviteUser)
+/// dictionary with a
+/// `non-200` HTTP status code (`403` if the user
+/// is not an ``admin``)
+///
+/// Err([`Response`](hyper::Response))
+///
+pub async fn invite_user(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    kafka_pool: &KafkaPublisher,
+    headers: &HeaderMap<HeaderValue>,
+    bytes: &[u8],
+) -> std::result::Result<Response<Body>, Infallible> {
+    let mut user_object: ApiReqAdminInviteUser =
+        match serde_json::from_slice(bytes) {
+            Ok(uo) => uo,
+            Err(_) => {
+                let response = Response::builder()
+                    .status(400)
+                    .body(Body::from(
+                        serde_json::to_string(&ApiResAdminInviteUser {
+                            msg: ("Admin invite user failed - please ensure \
+                                user_id and email are set in the request")
+                                .to_string(),
+                            error_code: Some(ApiErrorCode::InvalidRequest),
+                            ..Default::default()
+                        })
+                        .unwrap(),
+                    ))
+                    .unwrap();
+                return Ok(response);
+            }
+        };
+
+    user_object.email = normalize_email(&user_object.email);
+    user_object.locale = user_object.locale.as_deref().map(normalize_locale);
+    if let Some(response) = validate_api_req(tracking_label, &user_object) {
+        return Ok(response);
+    }
+    let user_id = user_object.user_id;
+    let email = user_object.email.clone();
+    let role = user_object
+        .role
+        .clone()
+        .unwrap_or_else(|| "user".to_string());
+    let locale = user_object
+        .locale
+        .clone()
+        .unwrap_or_else(|| DEFAULT_USER_LOCALE.to_string());
+    let conn = db_pool.get().await.unwrap();
+    if let Err(error_code) =
+        validate_user_token(tracking_label, config, &conn, headers, user_id)
+            .await
+    {
+        let response = Response::builder()
+            .status(400)
+            .body(Body::from(
+                serde_json::to_string(&ApiResAdminInviteUser {
+                    msg: ("Admin invite user failed due to invalid token")
+                        .to_string(),
+                    error_code: Some(error_code),
+                    ..Default::default()
+                })
+                .unwrap(),
+            ))
+            .unwrap();
+        return Ok(response);
+    }
+
+    if !is_admin_user(tracking_label, user_id, &conn).await {
+        warn!(
+            "{tracking_label} - \
+            rejected user invite from non-admin user {user_id}"
+        );
+        let response = Response::builder()
+            .status(403)
+            .body(Body::from(
+                serde_json::to_string(&ApiResAdminInviteUser {
+                    msg: ("Admin invite user failed - \
+                        user is not an admin")
+                        .to_string(),
+                    error_code: Some(ApiErrorCode::Forbidden),
+                    ..Default::default()
+                })
+                .unwrap(),
+            ))
+            .unwrap();
+        return Ok(response);
+    }
+
+    // only the token hash is stored in the db and the
+    // token is only returned to the admin in this response
+    let invite_token = format!("{}{}", get_uuid(), get_uuid());
+    let invite_token_hash =
+        hash_token(&invite_token, &config.server_password_salt);
+    // pending users can not login until they accept the
+    // invite and set their own password
+    let unusable_password = config
+        .password_hashing
+        .hash_password(&get_uuid(), &config.server_password_salt)
+        .unwrap();
+    let invite_exp_in_seconds: i64 =
+        std::env::var("USER_INVITE_EXP_IN_SECONDS")
+            .unwrap_or_else(|_| "604800".to_string())
+            .parse::<i64>()
+            .unwrap_or(604800);
+    let exp_date = Utc::now() + Duration::seconds(invite_exp_in_seconds);
+
+    let query = format!(
+        "WITH invited_user AS (\
+            INSERT INTO \
+                users (\
+                    email, \
+                    password, \
+                    state, \
+                    verified, \
+                    role, \
+                    tenant_id, \
+                    locale, \
+                    password_scheme) \
+            VALUES (\
+                '{}', \
+                '{unusable_password}', \
+                1, \
+                0, \
+                '{}', \
+                (SELECT users.tenant_id FROM users \
+                    WHERE users.id = {user_id}), \
+                '{}', \
+                '{}') \
+            RETURNING \
+                users.id, \
+                users.email) \
+        INSERT INTO \
+            users_invites (\
+                user_id, \
+                invited_by, \
+                token, \
+                email, \
+                exp_date) \
+        SELECT \
+            invited_user.id, \
+            {user_id}, \
+            '{invite_token_hash}', \
+            invited_user.email, \
+            '{exp_date}' \
+        FROM \
+            invited_user \
+        RETURNING \
+            users_invites.user_id;",
+        email.replace('\'', "''"),
+        role.replace('\'', "''"),
+        locale.replace('\'', "''"),
+        get_password_scheme(&unusable_password)
+    );
+    let stmt = conn.prepare(&query).await.unwrap();
+    let query_result =
+        match trace_db_query(&query, conn.query(&stmt, &[])).await {
+            Ok(query_result) => query_result,
+            Err(e) => {
+                let err_msg = format!("{e}");
+                let (msg, error_code) =
+                    match err_msg.contains("duplicate key value violates") {
+                        true => (
+                            format!("User email {email} already registered"),
+                            ApiErrorCode::EmailInUse,
+                        ),
+                        false => {
+                            error!(
+                                "{tracking_label} - \
+                            failed to invite {email} with err='{err_msg}'"
+                            );
+                            (
+                        format!("Admin invite user failed for email={email}"),
+                        ApiErrorCode::InternalError,
+                    )
+                        }
+                    };
+                let response = Response::builder()
+                    .status(400)
+                    .body(Body::from(
+                        serde_json::to_string(&ApiResAdminInviteUser {
+                            msg,
+                            error_code: Some(error_code),
+                            ..Default::default()
+                        })
+                        .unwrap(),
+                    ))
+                    .unwrap();
+                return Ok(response);
+            }
+        };
+    let invited_user_id: i32 = query_result[0].try_get("user_id").unwrap();
+
+    if std::env::var("DEBUG").unwrap_or_else(|_| "0".to_string()) == *"1" {
+        info!(
+            "{tracking_label} - admin {user_id} invited \
+            user={invited_user_id} {email} - accept with:\
+            curl -ks \
+            \"https://{}/user/invite/accept\" \
+            -XPOST \
+            -H \"Content-Type: application/json\" \
+            -d '{{\"user_id\":{invited_user_id},\
+            \"token\":\"{invite_token}\",\
+            \"password\":\"PASSWORD\"}}' \
+            | jq",
+            get_server_address("api")
+        );
+    } else {
+        info!(
+            "{tracking_label} - admin {user_id} invited \
+            user={invited_user_id} {email}"
+        );
+    }
+    config
+        .events
+        .publish_user_event(
+            kafka_pool,
+            invited_user_id,
+            "USER_INVITE",
+            &format!("email={email} invited_by={user_id}"),
+        )
+        .await;
+    let exp_date = format!("{}", exp_date.format("%Y-%m-%dT%H:%M:%SZ"));
+    config
+        .email_templates
+        .send_user_email(
+            tracking_label,
+            &config.events,
+            kafka_pool,
+            invited_user_id,
+            "invite",
+            &locale,
+            &email,
+            serde_json::json!({
+                "token": invite_token,
+                "exp_date": exp_date,
+                "link": EmailTemplates::get_link(
+                    &config.email_templates.invite_url,
+                    invited_user_id,
+                    &invite_token
+                ),
+            }),
+        )
+        .await;
+
+    let response = Response::builder()
+        .status(201)
+        .body(Body::from(
+            serde_json::to_string(&ApiResAdminInviteUser {
+                user_id: invited_user_id,
+                email,
+                role,
+                token: invite_token,
+                exp_date,
+                msg: "success".to_string(),
+                error_code: None,
+            })
+            .unwrap(),
+        ))
+        .unwrap();
+    Ok(response)
+}
@@ -0,0 +1,43 @@
+//! Check if a user has the ``admin`` role
+//!
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::PooledConnection;
+use bb8_postgres::PostgresConnectionManager;
+
+use crate::requests::models::tenant::DEFAULT_TENANT_ID;
+use crate::requests::models::user::get_user_by_id;
+
+/// is_admin_user
+///
+/// Check if the user has the ``admin`` role in the
+/// ``default`` tenant (admins in other tenants cannot change
+/// server-wide settings). Call this after validating the
+/// user's token.
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `user_id` - `i32` - user id
+/// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+///   an established db connection from the
+///   postgres client db threadpool
+///
+/// # Returns
+///
+/// `bool` - `true` if the user exists and is an ``admin``
+/// in the ``default`` tenant
+///
+pub async fn is_admin_user(
+    tracking_label: &str,
+    user_id: i32,
+    conn: &PooledConnection<'_, PostgresConnectionManager<MakeTlsConnector>>,
+) -> bool {
+    match get_user_by_id(tracking_label, user_id, conn).await {
+        Ok(user_model) => {
+            user_model.role == "admin"
+                && user_model.tenant_id == DEFAULT_TENANT_ID
+        }
+        Err(_) => false,
+    }
+}
@@ -0,0 +1,23 @@
+//! Supported admin modules
+//!
+pub mod admin_stats_cache;
+pub mod create_ip_rule;
+pub mod create_webhook;
+pub mod delete_ip_rule;
+pub mod delete_webhook;
+pub mod force_password_reset;
+pub mod get_admin_jwt_keys;
+pub mod get_admin_password_schemes;
+pub mod get_admin_settings;
+pub mod get_admin_stats;
+pub mod get_ip_rules;
+pub mod get_webhooks;
+pub mod invite_user;
+pub mod is_admin_user;
+pub mod requeue_kafka_dead_letters;
+pub mod retire_jwt_key;
+pub mod revoke_user_sessions;
+pub mod search_kafka_dead_letters;
+pub mod search_webhook_deliveries;
+pub mod unlock_login;
+pub mod update_admin_settings;
@@ -0,0 +1,346 @@
+//! Module for publishing kafka dead letters again
+//!
+//! ## Admin Requeue Kafka Dead Letters
+//!
+//! Publish ``dead`` letters to their kafka topic again once the brokers recover. Each published dead letter is marked ``requeued``, dead letters that fail again stay ``dead`` with the new error, and ids that are not ``dead`` (already requeued or missing) are skipped. The requesting user must have the ``admin`` role.
+//!
+//! - URL path: ``/admin/kafka/dead-letters/requeue``
+//! - Method: ``POST``
+//! - Handler: [`requeue_kafka_dead_letters`](crate::requests::admin::requeue_kafka_dead_letters::requeue_kafka_dead_letters)
+//! - Request: [`ApiReqAdminRequeueKafkaDeadLetters`](crate::requests::admin::requeue_kafka_dead_letters::ApiReqAdminRequeueKafkaDeadLetters)
+//! - Response: [`ApiResAdminRequeueKafkaDeadLetters`](crate::requests::admin::requeue_kafka_dead_letters::ApiResAdminRequeueKafkaDeadLetters)
+//!
+use std::convert::Infallible;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Response;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::core::core_config::CoreConfig;
+use crate::kafka::kafka_dead_letters::KAFKA_DEAD_LETTERS_REQUEUED_COUNTER_VEC;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::monitoring::metric_labels::get_metric_label;
+use crate::requests::admin::is_admin_user::is_admin_user;
+use crate::requests::auth::validate_user_token::validate_user_token;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::models::kafka_dead_letter::claim_kafka_dead_letters;
+use crate::requests::models::kafka_dead_letter::release_kafka_dead_letter;
+use crate::requests::validation::field_rules::add_field_error;
+use crate::requests::validation::field_rules::check_id;
+use crate::requests::validation::field_rules::check_range;
+use crate::requests::validation::validate_api_req::validate_api_req;
+use crate::requests::validation::validate_api_req::ApiFieldError;
+use crate::requests::validation::validate_api_req::ApiReqValidate;
+
+/// ApiReqAdminRequeueKafkaDeadLetters
+///
+/// # Request Type For requeue_kafka_dead_letters
+///
+/// Publish dead letters again
+///
+/// This type is the deserialized input for:
+/// [`requeue_kafka_dead_letters`](crate::requests::admin::requeue_kafka_dead_letters::requeue_kafka_dead_letters)
+///
+/// # Arguments
+///
+/// * `user_id` - `i32` - admin user id
+/// * `dead_letter_ids` - `Vec<i64>` - ``kafka_dead_letters.id``
+///   values (``1`` to ``1000`` ids)
+///
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApiReqAdminRequeueKafkaDeadLetters {
+    pub user_id: i32,
+    pub dead_letter_ids: Vec<i64>,
+}
+
+impl ApiReqValidate for ApiReqAdminRequeueKafkaDeadLetters {
+    /// validate
+    ///
+    /// Require a positive `user_id` and 1 to 1000 positive
+    /// `dead_letter_ids`
+    ///
+    fn validate(&self) -> Vec<ApiFieldError> {
+        let mut errors = Vec::new();
+        check_id(&mut errors, "user_id", self.user_id);
+        if self.dead_letter_ids.is_empty() || self.dead_letter_ids.len() > 1000
+        {
+            add_field_error(
+                &mut errors,
+                "dead_letter_ids",
+                "must have 1 to 1000 ids",
+            );
+        }
+        for dead_letter_id in self.dead_letter_ids.iter() {
+            check_range(
+                &mut errors,
+                "dead_letter_ids",
+                *dead_letter_id,
+                1,
+                i64::MAX,
+            );
+        }
+        errors
+    }
+}
+
+/// ApiResAdminRequeueKafkaDeadLetters
+///
+/// # Response type for requeue_kafka_dead_letters
+///
+/// Return the result for each dead letter id
+///
+/// # Arguments
+///
+/// * `requeued` - `Vec<i64>` - published dead letter ids
+/// * `failed` - `Vec<i64>` - dead letter ids that failed again
+/// * `skipped` - `Vec<i64>` - ids that are not ``dead``
+/// * `msg` - `String` - help message
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   machine-readable failure code (omitted on success)
+///
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ApiResAdminRequeueKafkaDeadLetters {
+    pub requeued: Vec<i64>,
+    pub failed: Vec<i64>,
+    pub skipped: Vec<i64>,
+    pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ApiErrorCode>,
+}
+
+/// requeue_kafka_dead_letters
+///
+/// Handler for publishing kafka dead letters again
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
+///   db threadpool with required tls encryption
+/// * `kafka_pool` -
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+///   for asynchronously publishing messages to the connected kafka cluster
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+/// * `bytes` - `&[u8]` - received bytes from the hyper
+///   [`Request`](hyper::Request)'s [`Body`](hyper::Body)
+///
+/// # Returns
+///
+/// ## requeue_kafka_dead_letters on Success Returns
+///
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResAdminRequeueKafkaDeadLetters`](crate::requests::admin::requeue_kafka_dead_letters::ApiResAdminRequeueKafkaDeadLetters)
+/// dictionary within the
+/// [`Body`](hyper::Body) and a
+/// `200` HTTP status code
+///
+/// Ok([`Response`](hyper::Response))
+///
+/// # Errors
+///
+/// `400` for an invalid request or token, `403` if the
+/// user is not an ``admin``, `500` when the db update fails
+/// and `503` when kafka publishing is disabled
+/// (``KAFKA_ENABLED``)
+///
+/// Err([`Response`](hyper::Response))
+///
+pub async fn requeue_kafka_dead_letters(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    kafka_pool: &KafkaPublisher,
+    headers: &HeaderMap<HeaderValue>,
+    bytes: &[u8],
+) -> std::result::Result<Response<Body>, Infallible> {
+    let req_object: ApiReqAdminRequeueKafkaDeadLetters =
+        match serde_json::from_slice(bytes) {
+            Ok(uo) => uo,
+            Err(_) => {
+                return Ok(get_requeue_dead_letters_response(
+                    400,
+                    "Admin requeue kafka dead letters failed - please \
+                    ensure user_id and dead_letter_ids were set \
+                    correctly in the request",
+                    ApiErrorCode::InvalidRequest,
+                ));
+            }
+        };
+
+    if let Some(response) = validate_api_req(tracking_label, &req_object) {
+        return Ok(response);
+    }
+
+    let user_id = req_object.user_id;
+    let conn = db_pool.get().await.unwrap();
+    if let Err(error_code) =
+        validate_user_token(tracking_label, config, &conn, headers, user_id)
+            .await
+    {
+        return Ok(get_requeue_dead_letters_response(
+            400,
+            "Admin requeue kafka dead letters failed due to invalid token",
+            error_code,
+        ));
+    }
+
+    if !is_admin_user(tracking_label, user_id, &conn).await {
+        warn!(
+            "{tracking_label} - \
+            rejected requeue kafka dead letters from non-admin \
+            user {user_id}"
+        );
+        return Ok(get_requeue_dead_letters_response(
+            403,
+            "Admin requeue kafka dead letters failed - \
+            user is not an admin",
+            ApiErrorCode::Forbidden,
+        ));
+    }
+
+    if !kafka_pool.is_enabled() {
+        return Ok(get_requeue_dead_letters_response(
+            503,
+            "Admin requeue kafka dead letters failed - \
+            kafka publishing is disabled on this api server",
+            ApiErrorCode::FeatureDisabled,
+        ));
+    }
+
+    let dead_letters = match claim_kafka_dead_letters(
+        tracking_label,
+        &req_object.dead_letter_ids,
+        &conn,
+    )
+    .await
+    {
+        Ok(dead_letters) => dead_letters,
+        Err(err_msg) => {
+            error!("{err_msg}");
+            return Ok(get_requeue_dead_letters_response(
+                500,
+                "Admin requeue kafka dead letters failed",
+                ApiErrorCode::InternalError,
+            ));
+        }
+    };
+
+    let mut requeued: Vec<i64> = Vec::new();
+    let mut failed: Vec<i64> = Vec::new();
+    for dead_letter in dead_letters.iter() {
+        let topic_label = get_metric_label(
+            "kafka_dead_letters_requeued_total",
+            &dead_letter.topic,
+        );
+        match config
+            .events
+            .dead_letters
+            .try_publish(
+                kafka_pool,
+                &dead_letter.topic,
+                &dead_letter.partition_key,
+                &dead_letter.payload,
+            )
+            .await
+        {
+            Ok(_) => {
+                KAFKA_DEAD_LETTERS_REQUEUED_COUNTER_VEC
+                    .with_label_values(&[&topic_label, "requeued"])
+                    .inc();
+                requeued.push(dead_letter.id);
+            }
+            Err(publish_err_msg) => {
+                KAFKA_DEAD_LETTERS_REQUEUED_COUNTER_VEC
+                    .with_label_values(&[&topic_label, "failed"])
+                    .inc();
+                if let Err(err_msg) = release_kafka_dead_letter(
+                    tracking_label,
+                    dead_letter.id,
+                    &publish_err_msg,
+                    &conn,
+                )
+                .await
+                {
+                    error!("{err_msg}");
+                }
+                failed.push(dead_letter.id);
+            }
+        }
+    }
+    let mut skipped: Vec<i64> = req_object
+        .dead_letter_ids
+        .iter()
+        .filter(|id| !dead_letters.iter().any(|v| v.id == **id))
+        .copied()
+        .collect();
+    skipped.sort();
+    skipped.dedup();
+
+    info!(
+        "{tracking_label} - \
+        admin {user_id} requeued kafka dead letters \
+        requeued={} failed={} skipped={}",
+        requeued.len(),
+        failed.len(),
+        skipped.len()
+    );
+    config
+        .events
+        .publish_user_event(
+            kafka_pool,
+            user_id,
+            "ADMIN_REQUEUE_KAFKA_DEAD_LETTERS",
+            &format!("requeued={} failed={}", requeued.len(), failed.len()),
+        )
+        .await;
+
+    let response = Response::builder()
+        .status(200)
+        .body(Body::from(
+            serde_json::to_string(&ApiResAdminRequeueKafkaDeadLetters {
+                requeued,
+                failed,
+                skipped,
+                msg: "success".to_string(),
+                error_code: None,
+            })
+            .unwrap(),
+        ))
+        .unwrap();
+    Ok(response)
+}
+
+/// get_requeue_dead_letters_response
+///
+/// Build an error response for
+/// [`requeue_kafka_dead_letters`](crate::requests::admin::requeue_kafka_dead_letters::requeue_kafka_dead_letters)
+///
+fn get_requeue_dead_letters_response(
+    status: u16,
+    msg: &str,
+    error_code: ApiErrorCode,
+) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::from(
+            serde_json::to_string(&ApiResAdminRequeueKafkaDeadLetters {
+                msg: msg.to_string(),
+                error_code: Some(error_code),
+                ..Default::default()
+            })
+            .unwrap(),
+        ))
+        .unwrap()
+}
@@ -0,0 +1,390 @@
+//! Module for retiring a jwt signing key
+//!
+//! ## Admin Retire JWT Key
+//!
+//! Add a jwt ``kid`` to the ``jwt_retired_kids`` runtime setting so every api server in the cluster rejects tokens signed with it and leaves it out of the jwks. The request is rejected with a ``409`` while active tokens still use the key unless ``force`` is ``true``, which revokes only those tokens. The key signing new tokens cannot be retired. The requesting user must have the ``admin`` role.
+//!
+//! - URL path: ``/admin/jwt/keys/retire``
+//! - Method: ``POST``
+//! - Handler: [`retire_jwt_key`](crate::requests::admin::retire_jwt_key::retire_jwt_key)
+//! - Request: [`ApiReqAdminRetireJwtKey`](crate::requests::admin::retire_jwt_key::ApiReqAdminRetireJwtKey)
+//! - Response: [`ApiResAdminJwtKeys`](crate::requests::admin::get_admin_jwt_keys::ApiResAdminJwtKeys)
+//!
+use std::convert::Infallible;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Response;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::core::core_config::CoreConfig;
+use crate::jwt::api::get_token_expiration_in_seconds;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::requests::admin::get_admin_jwt_keys::build_jwt_keys_report;
+use crate::requests::admin::get_admin_jwt_keys::ApiResAdminJwtKeys;
+use crate::requests::admin::is_admin_user::is_admin_user;
+use crate::requests::auth::validate_user_token::validate_user_token;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::models::setting::upsert_setting;
+use crate::requests::models::user_session::get_active_token_counts_by_kid;
+use crate::requests::models::user_session::revoke_tokens_by_kid;
+use crate::requests::validation::field_rules::add_field_error;
+use crate::requests::validation::field_rules::check_id;
+use crate::requests::validation::validate_api_req::validate_api_req;
+use crate::requests::validation::validate_api_req::ApiFieldError;
+use crate::requests::validation::validate_api_req::ApiReqValidate;
+use crate::settings::listen_for_settings_changes::reload_settings;
+use crate::settings::runtime_settings::RuntimeSettings;
+
+/// ApiReqAdminRetireJwtKey
+///
+/// # Request Type For retire_jwt_key
+///
+/// Retire a jwt kid after a key migration
+///
+/// This type is the deserialized input for:
+/// [`retire_jwt_key`](crate::requests::admin::retire_jwt_key::retire_jwt_key)
+///
+/// # Usage
+///
+/// This type is constructed from the deserialized
+/// `bytes` (`&[u8]`) argument
+/// on the
+/// [`retire_jwt_key`](crate::requests::admin::retire_jwt_key::retire_jwt_key)
+/// function.
+///
+/// # Arguments
+///
+/// * `user_id` - `i32` - admin user id
+/// * `kid` - `String` - jwt kid to retire
+/// * `force` - `Option<bool>` - revoke the active tokens
+///   still signed with the kid (default `false`)
+///
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApiReqAdminRetireJwtKey {
+    pub user_id: i32,
+    pub kid: String,
+    pub force: Option<bool>,
+}
+
+impl ApiReqValidate for ApiReqAdminRetireJwtKey {
+    /// validate
+    ///
+    /// Require a positive `user_id` and a valid `kid`
+    ///
+    fn validate(&self) -> Vec<ApiFieldError> {
+        let mut errors = Vec::new();
+        check_id(&mut errors, "user_id", self.user_id);
+        if self.kid.trim().is_empty() {
+            add_field_error(&mut errors, "kid", "kid is required");
+        } else if let Err(err_msg) = RuntimeSettings::default()
+            .apply_setting("jwt_signing_kid", &self.kid)
+        {
+            add_field_error(&mut errors, "kid", &err_msg);
+        }
+        errors
+    }
+}
+
+/// retire_jwt_key
+///
+/// Handler for retiring a jwt kid once no active
+/// tokens use it (or revoking them with `force`)
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
+///   db threadpool with required tls encryption
+/// * `kafka_pool` -
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+///   for asynchronously publishing messages to the connected kafka cluster
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+/// * `bytes` - `&[u8]` - received bytes from the hyper
+///   [`Request`](hyper::Request)'s [`Body`](hyper::Body)
+///
+/// # Returns
+///
+/// ## retire_jwt_key on Success Returns
+///
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResAdminJwtKeys`](crate::requests::admin::get_admin_jwt_keys::ApiResAdminJwtKeys)
+/// dictionary within the
+/// [`Body`](hyper::Body) and a
+/// `200` HTTP status code
+///
+/// Ok([`Response`](hyper::Response))
+///
+/// # Errors
+///
+/// ## retire_jwt_key on Failure Returns
+///
+/// All errors return as a
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResAdminJwtKeys`](crate::requests::admin::get_admin_jwt_keys::ApiResAdminJwtKeys)
+/// dictionary with a
+/// `non-200` HTTP status code (`403` if the user
+/// is not an ``admin``, `409` if active tokens still
+/// use the kid without `force` and `422` for the kid
+/// that signs new tokens)
+///
+/// Err([`Response`](hyper::Response))
+///
+pub async fn retire_jwt_key(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    kafka_pool: &KafkaPublisher,
+    headers: &HeaderMap<HeaderValue>,
+    bytes: &[u8],
+) -> std::result::Result<Response<Body>, Infallible> {
+    let user_object: ApiReqAdminRetireJwtKey =
+        match serde_json::from_slice(bytes) {
+            Ok(uo) => uo,
+            Err(_) => {
+                let response = Response::builder()
+                    .status(400)
+                    .body(Body::from(
+                        serde_json::to_string(&ApiResAdminJwtKeys {
+                            msg: ("Admin retire jwt key failed - please \
+                                ensure user_id and kid are set \
+                                in the request")
+                                .to_string(),
+                            error_code: Some(ApiErrorCode::InvalidRequest),
+                            ..Default::default()
+                        })
+                        .unwrap(),
+                    ))
+                    .unwrap();
+                return Ok(response);
+            }
+        };
+
+    if let Some(response) = validate_api_req(tracking_label, &user_object) {
+        return Ok(response);
+    }
+    let user_id = user_object.user_id;
+    let kid = user_object.kid.trim().to_string();
+    let force = user_object.force.unwrap_or(false);
+    let conn = db_pool.get().await.unwrap();
+    if let Err(error_code) =
+        validate_user_token(tracking_label, config, &conn, headers, user_id)
+            .await
+    {
+        let response = Response::builder()
+            .status(400)
+            .body(Body::from(
+                serde_json::to_string(&ApiResAdminJwtKeys {
+                    msg: ("Admin retire jwt key failed due to invalid token")
+                        .to_string(),
+                    error_code: Some(error_code),
+                    ..Default::default()
+                })
+                .unwrap(),
+            ))
+            .unwrap();
+        return Ok(response);
+    }
+    if !is_admin_user(tracking_label, user_id, &conn).await {
+        warn!(
+            "{tracking_label} - \
+            rejected jwt key retire from non-admin user {user_id}"
+        );
+        let response = Response::builder()
+            .status(403)
+            .body(Body::from(
+                serde_json::to_string(&ApiResAdminJwtKeys {
+                    msg: ("Admin retire jwt key failed - \
+                        user is not an admin")
+                        .to_string(),
+                    error_code: Some(ApiErrorCode::Forbidden),
+                    ..Default::default()
+                })
+                .unwrap(),
+            ))
+            .unwrap();
+        return Ok(response);
+    }
+
+    let mut settings = config.get_settings();
+    let (signing_kid, _) = config
+        .jwt_keys
+        .read()
+        .unwrap()
+        .get_signing_key(&settings.jwt_signing_kid);
+    if kid == signing_kid {
+        let response = Response::builder()
+            .status(422)
+            .body(Body::from(
+                serde_json::to_string(&ApiResAdminJwtKeys {
+                    msg: format!(
+                        "Admin retire jwt key failed - kid={kid} signs \
+                        new tokens - set jwt_signing_kid to the new kid \
+                        before retiring it"
+                    ),
+                    error_code: Some(ApiErrorCode::Conflict),
+                    ..Default::default()
+                })
+                .unwrap(),
+            ))
+            .unwrap();
+        return Ok(response);
+    }
+
+    let expiration_seconds = get_token_expiration_in_seconds();
+    let usage = match get_active_token_counts_by_kid(
+        tracking_label,
+        expiration_seconds,
+        &conn,
+    )
+    .await
+    {
+        Ok(usage) => usage,
+        Err(err_msg) => {
+            error!("{err_msg}");
+            let response = Response::builder()
+                .status(500)
+                .body(Body::from(
+                    serde_json::to_string(&ApiResAdminJwtKeys {
+                        msg: format!("Admin retire jwt key failed for {kid}"),
+                        error_code: Some(ApiErrorCode::InternalError),
+                        ..Default::default()
+                    })
+                    .unwrap(),
+                ))
+                .unwrap();
+            return Ok(response);
+        }
+    };
+    let active_tokens = usage
+        .iter()
+        .find(|u| u.kid == kid)
+        .map_or(0, |u| u.active_tokens);
+    if active_tokens > 0 && !force {
+        let mut res = build_jwt_keys_report(config, &usage);
+        res.msg = format!(
+            "Admin retire jwt key failed - {active_tokens} active tokens \
+            still use kid={kid} - wait for them to expire or retry \
+            with force=true to revoke them"
+        );
+        let response = Response::builder()
+            .status(409)
+            .body(Body::from(serde_json::to_string(&res).unwrap()))
+            .unwrap();
+        return Ok(response);
+    }
+
+    // only the sessions signed with this kid are logged out
+    let revoked_tokens = match force {
+        true => match revoke_tokens_by_kid(tracking_label, &kid, &conn).await {
+            Ok(mut user_ids) => {
+                let revoked_tokens = user_ids.len() as u64;
+                user_ids.sort_unstable();
+                user_ids.dedup();
+                for user_id in user_ids {
+                    config.user_cache.invalidate_user(user_id).await;
+                }
+                revoked_tokens
+            }
+            Err(err_msg) => {
+                error!("{err_msg}");
+                let response = Response::builder()
+                    .status(500)
+                    .body(Body::from(
+                        serde_json::to_string(&ApiResAdminJwtKeys {
+                            msg: format!(
+                                "Admin retire jwt key failed to revoke \
+                                tokens for {kid}"
+                            ),
+                            error_code: Some(ApiErrorCode::InternalError),
+                            ..Default::default()
+                        })
+                        .unwrap(),
+                    ))
+                    .unwrap();
+                return Ok(response);
+            }
+        },
+        false => 0,
+    };
+
+    let mut retired_kids = settings.jwt_retired_kids.clone();
+    retired_kids.push(kid.clone());
+    let value = settings
+        .apply_setting("jwt_retired_kids", &retired_kids.join(","))
+        .unwrap();
+    if let Err(err_msg) = upsert_setting(
+        tracking_label,
+        &conn,
+        "jwt_retired_kids",
+        &value,
+        user_id,
+    )
+    .await
+    {
+        error!("{err_msg}");
+        let response = Response::builder()
+            .status(500)
+            .body(Body::from(
+                serde_json::to_string(&ApiResAdminJwtKeys {
+                    msg: format!("Admin retire jwt key failed for {kid}"),
+                    revoked_tokens,
+                    error_code: Some(ApiErrorCode::InternalError),
+                    ..Default::default()
+                })
+                .unwrap(),
+            ))
+            .unwrap();
+        return Ok(response);
+    }
+
+    // apply the change here without waiting
+    // for the settings_changed notification
+    if let Err(err_msg) = reload_settings(tracking_label, config, db_pool).await
+    {
+        error!("{err_msg}");
+    }
+
+    info!(
+        "{tracking_label} - \
+        admin {user_id} retired jwt kid={kid} \
+        revoked_tokens={revoked_tokens}"
+    );
+    config
+        .events
+        .publish_user_event(
+            kafka_pool,
+            user_id,
+            "ADMIN_RETIRE_JWT_KEY",
+            &format!("kid={kid} revoked={revoked_tokens}"),
+        )
+        .await;
+
+    let usage = get_active_token_counts_by_kid(
+        tracking_label,
+        expiration_seconds,
+        &conn,
+    )
+    .await
+    .unwrap_or(usage);
+    let mut res = build_jwt_keys_report(config, &usage);
+    res.revoked_tokens = revoked_tokens;
+    res.msg = "success".to_string();
+    let response = Response::builder()
+        .status(200)
+        .body(Body::from(serde_json::to_string(&res).unwrap()))
+        .unwrap();
+    Ok(response)
+}
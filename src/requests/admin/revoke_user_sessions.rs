@@ -0,0 +1,323 @@
+//! Module for revoking every session for a user
+//!
+//! ## Admin Revoke User Sessions
+//!
+//! Revoke every active session (issued token) for a user during incident response. Requests with the revoked tokens are rejected even if the jwts have not expired. The action is stored in the ``users_admin_actions`` audit table and published as an ``ADMIN_SECURITY_REVOKE_SESSIONS`` user event for the target user. The requesting user must have the ``admin`` role.
+//!
+//! - URL path: ``/admin/users/sessions/revoke``
+//! - Method: ``POST``
+//! - Handler: [`revoke_user_sessions`](crate::requests::admin::revoke_user_sessions::revoke_user_sessions)
+//! - Request: [`ApiReqAdminRevokeUserSessions`](crate::requests::admin::revoke_user_sessions::ApiReqAdminRevokeUserSessions)
+//! - Response: [`ApiResAdminRevokeUserSessions`](crate::requests::admin::revoke_user_sessions::ApiResAdminRevokeUserSessions)
+//!
+use std::convert::Infallible;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Response;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::core::core_config::CoreConfig;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::requests::admin::is_admin_user::is_admin_user;
+use crate::requests::auth::validate_user_token::validate_user_token;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::models::user_admin_action::run_user_admin_action;
+use crate::requests::models::user_admin_action::ADMIN_ACTION_MAX_REASON_LEN;
+use crate::requests::models::user_admin_action::ADMIN_ACTION_REVOKE_SESSIONS;
+use crate::requests::validation::field_rules::check_id;
+use crate::requests::validation::field_rules::check_length;
+use crate::requests::validation::validate_api_req::validate_api_req;
+use crate::requests::validation::validate_api_req::ApiFieldError;
+use crate::requests::validation::validate_api_req::ApiReqValidate;
+
+/// ApiReqAdminRevokeUserSessions
+///
+/// # Request Type For revoke_user_sessions
+///
+/// Revoke every active session for a user
+///
+/// This type is the deserialized input for:
+/// [`revoke_user_sessions`](crate::requests::admin::revoke_user_sessions::revoke_user_sessions)
+///
+/// # Usage
+///
+/// This type is constructed from the deserialized
+/// `bytes` (`&[u8]`) argument
+/// on the
+/// [`revoke_user_sessions`](crate::requests::admin::revoke_user_sessions::revoke_user_sessions)
+/// function.
+///
+/// # Arguments
+///
+/// * `user_id` - `i32` - admin user id
+/// * `target_user_id` - `i32` - user to log out
+/// * `reason` - `Option<String>` - reason stored in the
+///   audit record and the event
+///
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApiReqAdminRevokeUserSessions {
+    pub user_id: i32,
+    pub target_user_id: i32,
+    pub reason: Option<String>,
+}
+
+impl ApiReqValidate for ApiReqAdminRevokeUserSessions {
+    /// validate
+    ///
+    /// Require a positive `user_id` and `target_user_id`
+    /// with an optional `reason` up to 512 characters
+    ///
+    fn validate(&self) -> Vec<ApiFieldError> {
+        let mut errors = Vec::new();
+        check_id(&mut errors, "user_id", self.user_id);
+        check_id(&mut errors, "target_user_id", self.target_user_id);
+        if let Some(reason) = &self.reason {
+            check_length(
+                &mut errors,
+                "reason",
+                reason,
+                1,
+                ADMIN_ACTION_MAX_REASON_LEN,
+            );
+        }
+        errors
+    }
+}
+
+/// ApiResAdminRevokeUserSessions
+///
+/// # Response type for revoke_user_sessions
+///
+/// Notify the client how many sessions were revoked
+///
+/// # Usage
+///
+/// This type is the serialized output for the function:
+/// [`revoke_user_sessions`](crate::requests::admin::revoke_user_sessions::revoke_user_sessions)
+/// and contained within the
+/// hyper [`Body`](hyper::Body)
+/// of the
+/// hyper [`Response`](hyper::Response)
+/// sent back to the client.
+///
+/// # Arguments
+///
+/// * `target_user_id` - `i32` - logged out user
+/// * `revoked_sessions` - `i64` - number of revoked sessions
+/// * `msg` - `String` - help message
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   machine-readable failure code (omitted on success)
+///
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ApiResAdminRevokeUserSessions {
+    pub target_user_id: i32,
+    pub revoked_sessions: i64,
+    pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ApiErrorCode>,
+}
+
+/// revoke_user_sessions
+///
+/// Handler for revoking every active session for a user
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
+///   db threadpool with required tls encryption
+/// * `kafka_pool` -
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+///   for asynchronously publishing messages to the connected kafka cluster
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+/// * `bytes` - `&[u8]` - received bytes from the hyper
+///   [`Request`](hyper::Request)'s [`Body`](hyper::Body)
+///
+/// # Returns
+///
+/// ## revoke_user_sessions on Success Returns
+///
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResAdminRevokeUserSessions`](crate::requests::admin::revoke_user_sessions::ApiResAdminRevokeUserSessions)
+/// dictionary within the
+/// [`Body`](hyper::Body) and a
+/// `200` HTTP status code
+///
+/// Ok([`Response`](hyper::Response))
+///
+/// # Errors
+///
+/// ## revoke_user_sessions on Failure Returns
+///
+/// All errors return as a
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResAdminRevokeUserSessions`](crate::requests::admin::revoke_user_sessions::ApiResAdminRevokeUserSessions)
+/// dictionary with a
+/// `non-200` HTTP status code (`403` if the user
+/// is not an ``admin`` and `404` if the target user
+/// does not exist)
+///
+/// Err([`Response`](hyper::Response))
+///
+pub async fn revoke_user_sessions(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    kafka_pool: &KafkaPublisher,
+    headers: &HeaderMap<HeaderValue>,
+    bytes: &[u8],
+) -> std::result::Result<Response<Body>, Infallible> {
+    let req_object: ApiReqAdminRevokeUserSessions =
+        match serde_json::from_slice(bytes) {
+            Ok(uo) => uo,
+            Err(_) => {
+                return Ok(get_revoke_user_sessions_response(
+                    400,
+                    -1,
+                    "Admin revoke user sessions failed - please ensure \
+                    user_id and target_user_id are set in the request",
+                    ApiErrorCode::InvalidRequest,
+                ));
+            }
+        };
+    if let Some(response) = validate_api_req(tracking_label, &req_object) {
+        return Ok(response);
+    }
+    let user_id = req_object.user_id;
+    let target_user_id = req_object.target_user_id;
+    let reason = req_object.reason.clone().unwrap_or_default();
+    let conn = db_pool.get().await.unwrap();
+    if let Err(error_code) =
+        validate_user_token(tracking_label, config, &conn, headers, user_id)
+            .await
+    {
+        return Ok(get_revoke_user_sessions_response(
+            400,
+            target_user_id,
+            "Admin revoke user sessions failed due to invalid token",
+            error_code,
+        ));
+    }
+    if !is_admin_user(tracking_label, user_id, &conn).await {
+        warn!(
+            "{tracking_label} - \
+            rejected session revoke for user {target_user_id} \
+            from non-admin user {user_id}"
+        );
+        return Ok(get_revoke_user_sessions_response(
+            403,
+            target_user_id,
+            "Admin revoke user sessions failed - user is not an admin",
+            ApiErrorCode::Forbidden,
+        ));
+    }
+
+    let admin_action = match run_user_admin_action(
+        tracking_label,
+        user_id,
+        target_user_id,
+        ADMIN_ACTION_REVOKE_SESSIONS,
+        &reason,
+        &conn,
+    )
+    .await
+    {
+        Ok(Some(admin_action)) => admin_action,
+        Ok(None) => {
+            return Ok(get_revoke_user_sessions_response(
+                404,
+                target_user_id,
+                &format!(
+                    "Admin revoke user sessions failed - \
+                    no user with id={target_user_id}"
+                ),
+                ApiErrorCode::UserNotFound,
+            ));
+        }
+        Err(err_msg) => {
+            error!("{err_msg}");
+            return Ok(get_revoke_user_sessions_response(
+                500,
+                target_user_id,
+                &format!(
+                    "Admin revoke user sessions failed \
+                    for user_id={target_user_id}"
+                ),
+                ApiErrorCode::InternalError,
+            ));
+        }
+    };
+    // the revoked tokens may be cached as active
+    config.user_cache.invalidate_user(target_user_id).await;
+
+    warn!(
+        "{tracking_label} - \
+        admin {user_id} revoked {} sessions for user {target_user_id} \
+        audit={}",
+        admin_action.revoked_sessions, admin_action.action_id
+    );
+    config
+        .events
+        .admin_security_action(
+            kafka_pool,
+            target_user_id,
+            "ADMIN_SECURITY_REVOKE_SESSIONS",
+            user_id,
+            admin_action.revoked_sessions,
+            &reason,
+        )
+        .await;
+
+    let response = Response::builder()
+        .status(200)
+        .body(Body::from(
+            serde_json::to_string(&ApiResAdminRevokeUserSessions {
+                target_user_id,
+                revoked_sessions: admin_action.revoked_sessions,
+                msg: "success".to_string(),
+                error_code: None,
+            })
+            .unwrap(),
+        ))
+        .unwrap();
+    Ok(response)
+}
+
+/// get_revoke_user_sessions_response
+///
+/// Build a failed
+/// [`ApiResAdminRevokeUserSessions`](crate::requests::admin::revoke_user_sessions::ApiResAdminRevokeUserSessions)
+/// response
+///
+fn get_revoke_user_sessions_response(
+    status: u16,
+    target_user_id: i32,
+    msg: &str,
+    error_code: ApiErrorCode,
+) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::from(
+            serde_json::to_string(&ApiResAdminRevokeUserSessions {
+                target_user_id,
+                revoked_sessions: 0,
+                msg: msg.to_string(),
+                error_code: Some(error_code),
+            })
+            .unwrap(),
+        ))
+        .unwrap()
+}
@@ -0,0 +1,272 @@
+//! Module for listing kafka messages that failed every publish retry
+//!
+//! ## Admin Search Kafka Dead Letters
+//!
+//! Get the newest kafka dead letters with their topic, partition key, payload, last error, status (``dead`` or ``requeued``) and number of publish attempts, plus the number of messages waiting in this api server's kafka publisher. Filter by ``status`` and/or ``topic``. The requesting user must have the ``admin`` role.
+//!
+//! - URL path: ``/admin/kafka/dead-letters``
+//! - Method: ``POST``
+//! - Handler: [`search_kafka_dead_letters`](crate::requests::admin::search_kafka_dead_letters::search_kafka_dead_letters)
+//! - Request: [`ApiReqAdminSearchKafkaDeadLetters`](crate::requests::admin::search_kafka_dead_letters::ApiReqAdminSearchKafkaDeadLetters)
+//! - Response: [`ApiResAdminSearchKafkaDeadLetters`](crate::requests::admin::search_kafka_dead_letters::ApiResAdminSearchKafkaDeadLetters)
+//!
+use std::convert::Infallible;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Response;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::core::core_config::CoreConfig;
+use crate::kafka::kafka_publisher::get_pending_msgs;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::requests::admin::is_admin_user::is_admin_user;
+use crate::requests::auth::validate_user_token::validate_user_token;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::models::kafka_dead_letter::search_kafka_dead_letters as search_db_kafka_dead_letters;
+use crate::requests::models::kafka_dead_letter::ModelKafkaDeadLetter;
+use crate::requests::validation::field_rules::check_id;
+use crate::requests::validation::field_rules::check_length;
+use crate::requests::validation::field_rules::check_one_of;
+use crate::requests::validation::field_rules::check_range;
+use crate::requests::validation::validate_api_req::validate_api_req;
+use crate::requests::validation::validate_api_req::ApiFieldError;
+use crate::requests::validation::validate_api_req::ApiReqValidate;
+
+/// supported kafka dead letter statuses
+pub const KAFKA_DEAD_LETTER_STATUSES: [&str; 2] = ["dead", "requeued"];
+
+/// ApiReqAdminSearchKafkaDeadLetters
+///
+/// # Request Type For search_kafka_dead_letters
+///
+/// Filter the kafka dead letters
+///
+/// This type is the deserialized input for:
+/// [`search_kafka_dead_letters`](crate::requests::admin::search_kafka_dead_letters::search_kafka_dead_letters)
+///
+/// # Arguments
+///
+/// * `user_id` - `i32` - admin user id
+/// * `status` - `Option<String>` - only ``dead`` or
+///   ``requeued`` dead letters
+/// * `topic` - `Option<String>` - only this kafka topic
+/// * `limit` - `Option<i64>` - max dead letters
+///   (``1`` to ``1000``, default ``100``)
+///
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApiReqAdminSearchKafkaDeadLetters {
+    pub user_id: i32,
+    pub status: Option<String>,
+    pub topic: Option<String>,
+    pub limit: Option<i64>,
+}
+
+impl ApiReqValidate for ApiReqAdminSearchKafkaDeadLetters {
+    /// validate
+    ///
+    /// Require a positive `user_id` with an optional
+    /// supported `status`, a `topic` and a `limit` between
+    /// 1 and 1000
+    ///
+    fn validate(&self) -> Vec<ApiFieldError> {
+        let mut errors = Vec::new();
+        check_id(&mut errors, "user_id", self.user_id);
+        if let Some(status) = &self.status {
+            check_one_of(
+                &mut errors,
+                "status",
+                status.as_str(),
+                &KAFKA_DEAD_LETTER_STATUSES,
+            );
+        }
+        if let Some(topic) = &self.topic {
+            check_length(&mut errors, "topic", topic, 1, 256);
+        }
+        if let Some(limit) = self.limit {
+            check_range(&mut errors, "limit", limit, 1, 1000);
+        }
+        errors
+    }
+}
+
+/// ApiResAdminSearchKafkaDeadLetters
+///
+/// # Response type for search_kafka_dead_letters
+///
+/// Return the matching dead letters (newest first)
+///
+/// # Arguments
+///
+/// * `pending_msgs` - `usize` - messages waiting in this api
+///   server's kafka publisher
+/// * `dead_letters` - `Vec<`[`ModelKafkaDeadLetter`](crate::requests::models::kafka_dead_letter::ModelKafkaDeadLetter)`>`
+/// * `msg` - `String` - help message
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   machine-readable failure code (omitted on success)
+///
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ApiResAdminSearchKafkaDeadLetters {
+    pub pending_msgs: usize,
+    pub dead_letters: Vec<ModelKafkaDeadLetter>,
+    pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ApiErrorCode>,
+}
+
+/// search_kafka_dead_letters
+///
+/// Handler for listing the kafka dead letters
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
+///   db threadpool with required tls encryption
+/// * `kafka_pool` -
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+///   for asynchronously publishing messages to the connected kafka cluster
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+/// * `bytes` - `&[u8]` - received bytes from the hyper
+///   [`Request`](hyper::Request)'s [`Body`](hyper::Body)
+///
+/// # Returns
+///
+/// ## search_kafka_dead_letters on Success Returns
+///
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResAdminSearchKafkaDeadLetters`](crate::requests::admin::search_kafka_dead_letters::ApiResAdminSearchKafkaDeadLetters)
+/// dictionary within the
+/// [`Body`](hyper::Body) and a
+/// `200` HTTP status code
+///
+/// Ok([`Response`](hyper::Response))
+///
+/// # Errors
+///
+/// `400` for an invalid request or token, `403` if the
+/// user is not an ``admin`` and `500` when the db query
+/// fails
+///
+/// Err([`Response`](hyper::Response))
+///
+pub async fn search_kafka_dead_letters(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    kafka_pool: &KafkaPublisher,
+    headers: &HeaderMap<HeaderValue>,
+    bytes: &[u8],
+) -> std::result::Result<Response<Body>, Infallible> {
+    let req_object: ApiReqAdminSearchKafkaDeadLetters =
+        match serde_json::from_slice(bytes) {
+            Ok(uo) => uo,
+            Err(_) => {
+                return Ok(get_search_dead_letters_response(
+                    400,
+                    "Admin search kafka dead letters failed - please \
+                    ensure user_id was set correctly in the request",
+                    ApiErrorCode::InvalidRequest,
+                ));
+            }
+        };
+
+    if let Some(response) = validate_api_req(tracking_label, &req_object) {
+        return Ok(response);
+    }
+
+    let user_id = req_object.user_id;
+    let conn = db_pool.get().await.unwrap();
+    if let Err(error_code) =
+        validate_user_token(tracking_label, config, &conn, headers, user_id)
+            .await
+    {
+        return Ok(get_search_dead_letters_response(
+            400,
+            "Admin search kafka dead letters failed due to invalid token",
+            error_code,
+        ));
+    }
+
+    if !is_admin_user(tracking_label, user_id, &conn).await {
+        warn!(
+            "{tracking_label} - \
+            rejected search kafka dead letters from non-admin \
+            user {user_id}"
+        );
+        return Ok(get_search_dead_letters_response(
+            403,
+            "Admin search kafka dead letters failed - \
+            user is not an admin",
+            ApiErrorCode::Forbidden,
+        ));
+    }
+
+    match search_db_kafka_dead_letters(
+        tracking_label,
+        req_object.status.as_deref(),
+        req_object.topic.as_deref(),
+        req_object.limit.unwrap_or(100),
+        &conn,
+    )
+    .await
+    {
+        Ok(dead_letters) => {
+            let response = Response::builder()
+                .status(200)
+                .body(Body::from(
+                    serde_json::to_string(&ApiResAdminSearchKafkaDeadLetters {
+                        pending_msgs: get_pending_msgs(kafka_pool),
+                        dead_letters,
+                        msg: "success".to_string(),
+                        error_code: None,
+                    })
+                    .unwrap(),
+                ))
+                .unwrap();
+            Ok(response)
+        }
+        Err(err_msg) => {
+            error!("{err_msg}");
+            Ok(get_search_dead_letters_response(
+                500,
+                "Admin search kafka dead letters failed",
+                ApiErrorCode::InternalError,
+            ))
+        }
+    }
+}
+
+/// get_search_dead_letters_response
+///
+/// Build an error response for
+/// [`search_kafka_dead_letters`](crate::requests::admin::search_kafka_dead_letters::search_kafka_dead_letters)
+///
+fn get_search_dead_letters_response(
+    status: u16,
+    msg: &str,
+    error_code: ApiErrorCode,
+) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::from(
+            serde_json::to_string(&ApiResAdminSearchKafkaDeadLetters {
+                msg: msg.to_string(),
+                error_code: Some(error_code),
+                ..Default::default()
+            })
+            .unwrap(),
+        ))
+        .unwrap()
+}
@@ -0,0 +1,269 @@
+//! Module for checking webhook delivery status
+//!
+//! ## Admin Search Webhook Deliveries
+//!
+//! Get the newest webhook deliveries with their status (``pending``, ``delivered`` or ``failed``), number of attempts, last HTTP status code and last error. Filter by ``webhook_id`` and/or ``status``. The requesting user must have the ``admin`` role.
+//!
+//! - URL path: ``/admin/webhooks/deliveries``
+//! - Method: ``POST``
+//! - Handler: [`search_webhook_deliveries`](crate::requests::admin::search_webhook_deliveries::search_webhook_deliveries)
+//! - Request: [`ApiReqAdminSearchWebhookDeliveries`](crate::requests::admin::search_webhook_deliveries::ApiReqAdminSearchWebhookDeliveries)
+//! - Response: [`ApiResAdminSearchWebhookDeliveries`](crate::requests::admin::search_webhook_deliveries::ApiResAdminSearchWebhookDeliveries)
+//!
+use std::convert::Infallible;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Response;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::core::core_config::CoreConfig;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::requests::admin::is_admin_user::is_admin_user;
+use crate::requests::auth::validate_user_token::validate_user_token;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::models::webhook::search_webhook_deliveries as search_db_webhook_deliveries;
+use crate::requests::models::webhook::ModelWebhookDelivery;
+use crate::requests::validation::field_rules::check_id;
+use crate::requests::validation::field_rules::check_one_of;
+use crate::requests::validation::field_rules::check_range;
+use crate::requests::validation::validate_api_req::validate_api_req;
+use crate::requests::validation::validate_api_req::ApiFieldError;
+use crate::requests::validation::validate_api_req::ApiReqValidate;
+
+/// supported webhook delivery statuses
+pub const WEBHOOK_DELIVERY_STATUSES: [&str; 3] =
+    ["pending", "delivered", "failed"];
+
+/// ApiReqAdminSearchWebhookDeliveries
+///
+/// # Request Type For search_webhook_deliveries
+///
+/// Filter the webhook deliveries
+///
+/// This type is the deserialized input for:
+/// [`search_webhook_deliveries`](crate::requests::admin::search_webhook_deliveries::search_webhook_deliveries)
+///
+/// # Arguments
+///
+/// * `user_id` - `i32` - admin user id
+/// * `webhook_id` - `Option<i32>` - only this ``webhooks.id``
+/// * `status` - `Option<String>` - only ``pending``,
+///   ``delivered`` or ``failed`` deliveries
+/// * `limit` - `Option<i64>` - max deliveries
+///   (``1`` to ``1000``, default ``100``)
+///
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApiReqAdminSearchWebhookDeliveries {
+    pub user_id: i32,
+    pub webhook_id: Option<i32>,
+    pub status: Option<String>,
+    pub limit: Option<i64>,
+}
+
+impl ApiReqValidate for ApiReqAdminSearchWebhookDeliveries {
+    /// validate
+    ///
+    /// Require a positive `user_id` with an optional positive
+    /// `webhook_id`, supported `status` and a `limit`
+    /// between 1 and 1000
+    ///
+    fn validate(&self) -> Vec<ApiFieldError> {
+        let mut errors = Vec::new();
+        check_id(&mut errors, "user_id", self.user_id);
+        if let Some(webhook_id) = self.webhook_id {
+            check_id(&mut errors, "webhook_id", webhook_id);
+        }
+        if let Some(status) = &self.status {
+            check_one_of(
+                &mut errors,
+                "status",
+                status.as_str(),
+                &WEBHOOK_DELIVERY_STATUSES,
+            );
+        }
+        if let Some(limit) = self.limit {
+            check_range(&mut errors, "limit", limit, 1, 1000);
+        }
+        errors
+    }
+}
+
+/// ApiResAdminSearchWebhookDeliveries
+///
+/// # Response type for search_webhook_deliveries
+///
+/// Return the matching deliveries (newest first)
+///
+/// # Arguments
+///
+/// * `deliveries` - `Vec<`[`ModelWebhookDelivery`](crate::requests::models::webhook::ModelWebhookDelivery)`>`
+/// * `msg` - `String` - help message
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   machine-readable failure code (omitted on success)
+///
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ApiResAdminSearchWebhookDeliveries {
+    pub deliveries: Vec<ModelWebhookDelivery>,
+    pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ApiErrorCode>,
+}
+
+/// search_webhook_deliveries
+///
+/// Handler for checking webhook delivery status
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
+///   db threadpool with required tls encryption
+/// * `kafka_pool` -
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+///   for asynchronously publishing messages to the connected kafka cluster
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+/// * `bytes` - `&[u8]` - received bytes from the hyper
+///   [`Request`](hyper::Request)'s [`Body`](hyper::Body)
+///
+/// # Returns
+///
+/// ## search_webhook_deliveries on Success Returns
+///
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResAdminSearchWebhookDeliveries`](crate::requests::admin::search_webhook_deliveries::ApiResAdminSearchWebhookDeliveries)
+/// dictionary within the
+/// [`Body`](hyper::Body) and a
+/// `200` HTTP status code
+///
+/// Ok([`Response`](hyper::Response))
+///
+/// # Errors
+///
+/// `400` for an invalid request or token, `403` if the
+/// user is not an ``admin`` and `500` when the db query
+/// fails
+///
+/// Err([`Response`](hyper::Response))
+///
+pub async fn search_webhook_deliveries(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    _kafka_pool: &KafkaPublisher,
+    headers: &HeaderMap<HeaderValue>,
+    bytes: &[u8],
+) -> std::result::Result<Response<Body>, Infallible> {
+    let req_object: ApiReqAdminSearchWebhookDeliveries =
+        match serde_json::from_slice(bytes) {
+            Ok(uo) => uo,
+            Err(_) => {
+                return Ok(get_search_deliveries_response(
+                    400,
+                    "Admin search webhook deliveries failed - please \
+                    ensure user_id was set correctly in the request",
+                    ApiErrorCode::InvalidRequest,
+                ));
+            }
+        };
+
+    if let Some(response) = validate_api_req(tracking_label, &req_object) {
+        return Ok(response);
+    }
+
+    let user_id = req_object.user_id;
+    let conn = db_pool.get().await.unwrap();
+    if let Err(error_code) =
+        validate_user_token(tracking_label, config, &conn, headers, user_id)
+            .await
+    {
+        return Ok(get_search_deliveries_response(
+            400,
+            "Admin search webhook deliveries failed due to invalid token",
+            error_code,
+        ));
+    }
+
+    if !is_admin_user(tracking_label, user_id, &conn).await {
+        warn!(
+            "{tracking_label} - \
+            rejected search webhook deliveries from non-admin \
+            user {user_id}"
+        );
+        return Ok(get_search_deliveries_response(
+            403,
+            "Admin search webhook deliveries failed - \
+            user is not an admin",
+            ApiErrorCode::Forbidden,
+        ));
+    }
+
+    match search_db_webhook_deliveries(
+        tracking_label,
+        req_object.webhook_id,
+        req_object.status.as_deref(),
+        req_object.limit.unwrap_or(100),
+        &conn,
+    )
+    .await
+    {
+        Ok(deliveries) => {
+            let response = Response::builder()
+                .status(200)
+                .body(Body::from(
+                    serde_json::to_string(
+                        &ApiResAdminSearchWebhookDeliveries {
+                            deliveries,
+                            msg: "success".to_string(),
+                            error_code: None,
+                        },
+                    )
+                    .unwrap(),
+                ))
+                .unwrap();
+            Ok(response)
+        }
+        Err(err_msg) => {
+            error!("{err_msg}");
+            Ok(get_search_deliveries_response(
+                500,
+                "Admin search webhook deliveries failed",
+                ApiErrorCode::InternalError,
+            ))
+        }
+    }
+}
+
+/// get_search_deliveries_response
+///
+/// Build an error response for
+/// [`search_webhook_deliveries`](crate::requests::admin::search_webhook_deliveries::search_webhook_deliveries)
+///
+fn get_search_deliveries_response(
+    status: u16,
+    msg: &str,
+    error_code: ApiErrorCode,
+) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::from(
+            serde_json::to_string(&ApiResAdminSearchWebhookDeliveries {
+                deliveries: Vec::new(),
+                msg: msg.to_string(),
+                error_code: Some(error_code),
+            })
+            .unwrap(),
+        ))
+        .unwrap()
+}
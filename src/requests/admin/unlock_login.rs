@@ -0,0 +1,322 @@
+//! Module for unlocking throttled logins
+//!
+//! ## Admin Unlock Login
+//!
+//! Clear the failed login counts and lock for an email and/or an ip address throttled by the [`LoginThrottle`](crate::requests::auth::login_throttle::LoginThrottle). The requesting user must have the ``admin`` role.
+//!
+//! - URL path: ``/admin/login/unlock``
+//! - Method: ``POST``
+//! - Handler: [`unlock_login`](crate::requests::admin::unlock_login::unlock_login)
+//! - Request: [`ApiReqAdminUnlockLogin`](crate::requests::admin::unlock_login::ApiReqAdminUnlockLogin)
+//! - Response: [`ApiResAdminUnlockLogin`](crate::requests::admin::unlock_login::ApiResAdminUnlockLogin)
+//!
+use std::convert::Infallible;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Response;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::core::core_config::CoreConfig;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::requests::admin::is_admin_user::is_admin_user;
+use crate::requests::auth::validate_user_token::validate_user_token;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::validation::field_rules::add_field_error;
+use crate::requests::validation::field_rules::check_email;
+use crate::requests::validation::field_rules::check_id;
+use crate::requests::validation::field_rules::check_length;
+use crate::requests::validation::normalize_email::normalize_email;
+use crate::requests::validation::validate_api_req::validate_api_req;
+use crate::requests::validation::validate_api_req::ApiFieldError;
+use crate::requests::validation::validate_api_req::ApiReqValidate;
+
+/// ApiReqAdminUnlockLogin
+///
+/// # Request Type For unlock_login
+///
+/// Unlock a throttled email and/or ip address
+///
+/// This type is the deserialized input for:
+/// [`unlock_login`](crate::requests::admin::unlock_login::unlock_login]
+///
+/// # Usage
+///
+/// This type is constructed from the deserialized
+/// `bytes` (`&[u8]`) argument
+/// on the
+/// [`unlock_login`](crate::requests::admin::unlock_login::unlock_login)
+/// function.
+///
+/// # Arguments
+///
+/// * `user_id` - `i32` - admin user id
+/// * `email` - `Option<String>` - email to unlock
+/// * `ip_address` - `Option<String>` - ip address to unlock
+///
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApiReqAdminUnlockLogin {
+    pub user_id: i32,
+    pub email: Option<String>,
+    pub ip_address: Option<String>,
+}
+
+impl ApiReqValidate for ApiReqAdminUnlockLogin {
+    /// validate
+    ///
+    /// Require a positive `user_id` with a valid `email`
+    /// and/or an `ip_address`
+    ///
+    fn validate(&self) -> Vec<ApiFieldError> {
+        let mut errors = Vec::new();
+        check_id(&mut errors, "user_id", self.user_id);
+        if self.email.is_none() && self.ip_address.is_none() {
+            add_field_error(
+                &mut errors,
+                "email",
+                "an email and/or an ip_address is required",
+            );
+        }
+        if let Some(email) = &self.email {
+            check_email(&mut errors, "email", email);
+        }
+        if let Some(ip_address) = &self.ip_address {
+            check_length(&mut errors, "ip_address", ip_address, 1, 64);
+        }
+        errors
+    }
+}
+
+/// ApiResAdminUnlockLogin
+///
+/// # Response type for unlock_login
+///
+/// Notify the client which keys were unlocked
+///
+/// # Usage
+///
+/// This type is the serialized output for the function:
+/// [`unlock_login`](crate::requests::admin::unlock_login::unlock_login]
+/// and contained within the
+/// hyper [`Body`](hyper::Body)
+/// of the
+/// hyper [`Response`](hyper::Response)
+/// sent back to the client.
+///
+/// # Arguments
+///
+/// * `email_unlocked` - `bool` - email had failures
+///   that were removed
+/// * `ip_unlocked` - `bool` - ip address had failures
+///   that were removed
+/// * `msg` - `String` - help message
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   machine-readable failure code (omitted on success)
+///
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ApiResAdminUnlockLogin {
+    pub email_unlocked: bool,
+    pub ip_unlocked: bool,
+    pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ApiErrorCode>,
+}
+
+/// unlock_login
+///
+/// Handler for removing the login throttle failures
+/// and lock for an email and/or ip address
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
+///   db threadpool with required tls encryption
+/// * `kafka_pool` -
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+///   for asynchronously publishing messages to the connected kafka cluster
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+/// * `bytes` - `&[u8]` - received bytes from the hyper
+///   [`Request`](hyper::Request)'s [`Body`](hyper::Body)
+///
+/// # Returns
+///
+/// ## unlock_login on Success Returns
+///
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResAdminUnlockLogin`](crate::requests::admin::unlock_login::ApiResAdminUnlockLogin)
+/// dictionary within the
+/// [`Body`](hyper::Body) and a
+/// `200` HTTP status code
+///
+/// Ok([`Response`](hyper::Response))
+///
+/// # Errors
+///
+/// ## unlock_login on Failure Returns
+///
+/// All errors return as a
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResAdminUnlockLogin`](crate::requests::admin::unlock_login::ApiResAdminUnlockLogin)
+/// dictionary with a
+/// `non-200` HTTP status code (`403` if the user
+/// is not an ``admin``)
+///
+/// Err([`Response`](hyper::Response))
+///
+pub async fn unlock_login(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    kafka_pool: &KafkaPublisher,
+    headers: &HeaderMap<HeaderValue>,
+    bytes: &[u8],
+) -> std::result::Result<Response<Body>, Infallible> {
+    let mut user_object: ApiReqAdminUnlockLogin =
+        match serde_json::from_slice(bytes) {
+            Ok(uo) => uo,
+            Err(_) => {
+                let response = Response::builder()
+                    .status(400)
+                    .body(Body::from(
+                        serde_json::to_string(&ApiResAdminUnlockLogin {
+                            email_unlocked: false,
+                            ip_unlocked: false,
+                            msg: ("Admin unlock login failed - please ensure \
+                                user_id is set with an email \
+                                and/or an ip_address \
+                                in the request")
+                                .to_string(),
+                            error_code: Some(ApiErrorCode::InvalidRequest),
+                        })
+                        .unwrap(),
+                    ))
+                    .unwrap();
+                return Ok(response);
+            }
+        };
+
+    user_object.email = user_object.email.as_deref().map(normalize_email);
+    if let Some(response) = validate_api_req(tracking_label, &user_object) {
+        return Ok(response);
+    }
+    let email = user_object.email.clone().unwrap_or_default();
+    let ip_address = user_object.ip_address.clone().unwrap_or_default();
+    let user_id = user_object.user_id;
+    let settings = config.get_settings();
+    let conn = db_pool.get().await.unwrap();
+    if let Err(error_code) =
+        validate_user_token(tracking_label, config, &conn, headers, user_id)
+            .await
+    {
+        let response = Response::builder()
+            .status(400)
+            .body(Body::from(
+                serde_json::to_string(&ApiResAdminUnlockLogin {
+                    email_unlocked: false,
+                    ip_unlocked: false,
+                    msg: ("Admin unlock login failed due to invalid token")
+                        .to_string(),
+                    error_code: Some(error_code),
+                })
+                .unwrap(),
+            ))
+            .unwrap();
+        return Ok(response);
+    }
+
+    if !is_admin_user(tracking_label, user_id, &conn).await {
+        warn!(
+            "{tracking_label} - \
+            rejected login unlock from non-admin user {user_id}"
+        );
+        let response = Response::builder()
+            .status(403)
+            .body(Body::from(
+                serde_json::to_string(&ApiResAdminUnlockLogin {
+                    email_unlocked: false,
+                    ip_unlocked: false,
+                    msg: ("Admin unlock login failed - \
+                        user is not an admin")
+                        .to_string(),
+                    error_code: Some(ApiErrorCode::Forbidden),
+                })
+                .unwrap(),
+            ))
+            .unwrap();
+        return Ok(response);
+    }
+
+    let mut res = ApiResAdminUnlockLogin::default();
+    for (kind, key) in [("email", &email), ("ip", &ip_address)] {
+        if key.is_empty() {
+            continue;
+        }
+        match settings
+            .login_throttle
+            .reset_login_throttle(tracking_label, &conn, kind, key, "unlock")
+            .await
+        {
+            Ok(removed) => {
+                if kind == "email" {
+                    res.email_unlocked = removed;
+                } else {
+                    res.ip_unlocked = removed;
+                }
+            }
+            Err(err_msg) => {
+                error!("{err_msg}");
+                let response = Response::builder()
+                    .status(500)
+                    .body(Body::from(
+                        serde_json::to_string(&ApiResAdminUnlockLogin {
+                            email_unlocked: res.email_unlocked,
+                            ip_unlocked: false,
+                            msg: format!(
+                                "Admin unlock login failed for {kind}={key}"
+                            ),
+                            error_code: Some(ApiErrorCode::InternalError),
+                        })
+                        .unwrap(),
+                    ))
+                    .unwrap();
+                return Ok(response);
+            }
+        }
+    }
+
+    info!(
+        "{tracking_label} - \
+        admin {user_id} unlocked login email={email} ip={ip_address} \
+        email_unlocked={} ip_unlocked={}",
+        res.email_unlocked, res.ip_unlocked
+    );
+    config
+        .events
+        .publish_user_event(
+            kafka_pool,
+            user_id,
+            "ADMIN_UNLOCK_LOGIN",
+            &format!("email={email} ip={ip_address}"),
+        )
+        .await;
+
+    res.msg = "success".to_string();
+    let response = Response::builder()
+        .status(200)
+        .body(Body::from(serde_json::to_string(&res).unwrap()))
+        .unwrap();
+    Ok(response)
+}
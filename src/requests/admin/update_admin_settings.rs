@@ -0,0 +1,369 @@
+//! Module for changing the runtime settings
+//!
+//! ## Admin Update Settings
+//!
+//! Set or remove runtime setting overrides in the ``settings`` table. A ``null`` value removes the override so the environment variable (or default) is used. Every api server in the cluster reloads its settings when the table changes. The requesting user must have the ``admin`` role.
+//!
+//! - URL path: ``/admin/settings``
+//! - Method: ``PUT``
+//! - Handler: [`update_admin_settings`](crate::requests::admin::update_admin_settings::update_admin_settings)
+//! - Request: [`ApiReqAdminUpdateSettings`](crate::requests::admin::update_admin_settings::ApiReqAdminUpdateSettings)
+//! - Response: [`ApiResAdminSettings`](crate::requests::admin::get_admin_settings::ApiResAdminSettings)
+//!
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Response;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::core::core_config::CoreConfig;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::requests::admin::get_admin_settings::ApiResAdminSettings;
+use crate::requests::admin::is_admin_user::is_admin_user;
+use crate::requests::auth::validate_user_token::validate_user_token;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::models::setting::delete_setting;
+use crate::requests::models::setting::get_settings;
+use crate::requests::models::setting::upsert_setting;
+use crate::requests::validation::field_rules::add_field_error;
+use crate::requests::validation::field_rules::check_id;
+use crate::requests::validation::validate_api_req::validate_api_req;
+use crate::requests::validation::validate_api_req::ApiFieldError;
+use crate::requests::validation::validate_api_req::ApiReqValidate;
+use crate::settings::listen_for_settings_changes::reload_settings;
+use crate::settings::runtime_settings::RuntimeSettings;
+use crate::settings::runtime_settings::SUPPORTED_SETTINGS;
+
+/// ApiReqAdminUpdateSettings
+///
+/// # Request Type For update_admin_settings
+///
+/// Set or remove runtime setting overrides
+///
+/// This type is the deserialized input for:
+/// [`update_admin_settings`](crate::requests::admin::update_admin_settings::update_admin_settings)
+///
+/// # Usage
+///
+/// This type is constructed from the deserialized
+/// `bytes` (`&[u8]`) argument
+/// on the
+/// [`update_admin_settings`](crate::requests::admin::update_admin_settings::update_admin_settings)
+/// function.
+///
+/// # Arguments
+///
+/// * `user_id` - `i32` - admin user id
+/// * `settings` - `BTreeMap<String, Option<serde_json::Value>>` -
+///   setting names from
+///   [`SUPPORTED_SETTINGS`](crate::settings::runtime_settings::SUPPORTED_SETTINGS)
+///   with the new value (`null` removes the override)
+///
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApiReqAdminUpdateSettings {
+    pub user_id: i32,
+    pub settings: BTreeMap<String, Option<serde_json::Value>>,
+}
+
+/// get_setting_value_str
+///
+/// Convert a json setting value to the string
+/// stored in the ``settings`` table
+///
+fn get_setting_value_str(value: &serde_json::Value) -> String {
+    match value.as_str() {
+        Some(value) => value.to_string(),
+        None => value.to_string(),
+    }
+}
+
+impl ApiReqValidate for ApiReqAdminUpdateSettings {
+    /// validate
+    ///
+    /// Require a positive `user_id` and at least one supported
+    /// setting with a valid value (or `null`)
+    ///
+    fn validate(&self) -> Vec<ApiFieldError> {
+        let mut errors = Vec::new();
+        check_id(&mut errors, "user_id", self.user_id);
+        if self.settings.is_empty() {
+            add_field_error(
+                &mut errors,
+                "settings",
+                "at least one setting is required",
+            );
+        }
+        let mut settings = RuntimeSettings::default();
+        for (key, value) in self.settings.iter() {
+            let field = format!("settings.{key}");
+            match value {
+                Some(value) => {
+                    if let Err(err_msg) = settings
+                        .apply_setting(key, &get_setting_value_str(value))
+                    {
+                        add_field_error(&mut errors, &field, &err_msg);
+                    }
+                }
+                None => {
+                    if !SUPPORTED_SETTINGS.contains(&key.as_str()) {
+                        add_field_error(
+                            &mut errors,
+                            &field,
+                            "unsupported setting",
+                        );
+                    }
+                }
+            }
+        }
+        errors
+    }
+}
+
+/// update_admin_settings
+///
+/// Handler for setting or removing runtime
+/// setting overrides
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
+///   db threadpool with required tls encryption
+/// * `kafka_pool` -
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+///   for asynchronously publishing messages to the connected kafka cluster
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+/// * `bytes` - `&[u8]` - received bytes from the hyper
+///   [`Request`](hyper::Request)'s [`Body`](hyper::Body)
+///
+/// # Returns
+///
+/// ## update_admin_settings on Success Returns
+///
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResAdminSettings`](crate::requests::admin::get_admin_settings::ApiResAdminSettings)
+/// dictionary within the
+/// [`Body`](hyper::Body) and a
+/// `200` HTTP status code
+///
+/// Ok([`Response`](hyper::Response))
+///
+/// # Errors
+///
+/// ## update_admin_settings on Failure Returns
+///
+/// All errors return as a
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResAdminSettings`](crate::requests::admin::get_admin_settings::ApiResAdminSettings)
+/// dictionary with a
+/// `non-200` HTTP status code (`403` if the user
+/// is not an ``admin`` and `422` for unsupported
+/// settings, invalid values or retiring the jwt kid
+/// that signs new tokens)
+///
+/// Err([`Response`](hyper::Response))
+///
+pub async fn update_admin_settings(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    kafka_pool: &KafkaPublisher,
+    headers: &HeaderMap<HeaderValue>,
+    bytes: &[u8],
+) -> std::result::Result<Response<Body>, Infallible> {
+    let user_object: ApiReqAdminUpdateSettings =
+        match serde_json::from_slice(bytes) {
+            Ok(uo) => uo,
+            Err(_) => {
+                let response = Response::builder()
+                    .status(400)
+                    .body(Body::from(
+                        serde_json::to_string(&ApiResAdminSettings {
+                            msg: ("Admin update settings failed - please \
+                                ensure user_id and settings are set \
+                                in the request")
+                                .to_string(),
+                            error_code: Some(ApiErrorCode::InvalidRequest),
+                            ..Default::default()
+                        })
+                        .unwrap(),
+                    ))
+                    .unwrap();
+                return Ok(response);
+            }
+        };
+
+    if let Some(response) = validate_api_req(tracking_label, &user_object) {
+        return Ok(response);
+    }
+    let user_id = user_object.user_id;
+    let conn = db_pool.get().await.unwrap();
+    if let Err(error_code) =
+        validate_user_token(tracking_label, config, &conn, headers, user_id)
+            .await
+    {
+        let response = Response::builder()
+            .status(400)
+            .body(Body::from(
+                serde_json::to_string(&ApiResAdminSettings {
+                    msg: ("Admin update settings failed due to invalid token")
+                        .to_string(),
+                    error_code: Some(error_code),
+                    ..Default::default()
+                })
+                .unwrap(),
+            ))
+            .unwrap();
+        return Ok(response);
+    }
+    if !is_admin_user(tracking_label, user_id, &conn).await {
+        warn!(
+            "{tracking_label} - \
+            rejected settings update from non-admin user {user_id}"
+        );
+        let response = Response::builder()
+            .status(403)
+            .body(Body::from(
+                serde_json::to_string(&ApiResAdminSettings {
+                    msg: ("Admin update settings failed - \
+                        user is not an admin")
+                        .to_string(),
+                    error_code: Some(ApiErrorCode::Forbidden),
+                    ..Default::default()
+                })
+                .unwrap(),
+            ))
+            .unwrap();
+        return Ok(response);
+    }
+
+    // retiring the key that signs new tokens would
+    // log out every user on their next request
+    let mut new_settings = config.get_settings();
+    for (key, value) in user_object.settings.iter() {
+        let value = match value {
+            Some(value) => get_setting_value_str(value),
+            None => match key.as_str() {
+                "jwt_signing_kid" => {
+                    std::env::var("TOKEN_SIGNING_KID").unwrap_or_default()
+                }
+                "jwt_retired_kids" => {
+                    std::env::var("TOKEN_RETIRED_KIDS").unwrap_or_default()
+                }
+                _ => continue,
+            },
+        };
+        let _ = new_settings.apply_setting(key, &value);
+    }
+    let (signing_kid, _) = config
+        .jwt_keys
+        .read()
+        .unwrap()
+        .get_signing_key(&new_settings.jwt_signing_kid);
+    if new_settings.jwt_retired_kids.contains(&signing_kid) {
+        let response = Response::builder()
+            .status(422)
+            .body(Body::from(
+                serde_json::to_string(&ApiResAdminSettings {
+                    msg: format!(
+                        "Admin update settings failed - jwt kid={signing_kid} \
+                        signs new tokens and cannot be retired"
+                    ),
+                    error_code: Some(ApiErrorCode::Conflict),
+                    ..Default::default()
+                })
+                .unwrap(),
+            ))
+            .unwrap();
+        return Ok(response);
+    }
+
+    // store the normalized values so every api server
+    // parses them the same way
+    let mut settings = RuntimeSettings::default();
+    let mut changes: Vec<String> = Vec::new();
+    for (key, value) in user_object.settings.iter() {
+        let result = match value {
+            Some(value) => {
+                let value = settings
+                    .apply_setting(key, &get_setting_value_str(value))
+                    .unwrap();
+                changes.push(format!("{key}={value}"));
+                upsert_setting(tracking_label, &conn, key, &value, user_id)
+                    .await
+            }
+            None => {
+                changes.push(format!("{key}=null"));
+                delete_setting(tracking_label, &conn, key).await
+            }
+        };
+        if let Err(err_msg) = result {
+            error!("{err_msg}");
+            let response = Response::builder()
+                .status(500)
+                .body(Body::from(
+                    serde_json::to_string(&ApiResAdminSettings {
+                        msg: format!("Admin update settings failed for {key}"),
+                        error_code: Some(ApiErrorCode::InternalError),
+                        ..Default::default()
+                    })
+                    .unwrap(),
+                ))
+                .unwrap();
+            return Ok(response);
+        }
+    }
+    let changes = changes.join(" ");
+
+    // apply the change here without waiting
+    // for the settings_changed notification
+    if let Err(err_msg) = reload_settings(tracking_label, config, db_pool).await
+    {
+        error!("{err_msg}");
+    }
+    let overrides = get_settings(tracking_label, &conn)
+        .await
+        .unwrap_or_default();
+
+    info!(
+        "{tracking_label} - \
+        admin {user_id} updated settings {changes}"
+    );
+    config
+        .events
+        .publish_user_event(
+            kafka_pool,
+            user_id,
+            "ADMIN_UPDATE_SETTINGS",
+            &changes,
+        )
+        .await;
+
+    let response = Response::builder()
+        .status(200)
+        .body(Body::from(
+            serde_json::to_string(&ApiResAdminSettings {
+                settings: config.get_settings(),
+                overrides,
+                msg: "success".to_string(),
+                error_code: None,
+            })
+            .unwrap(),
+        ))
+        .unwrap();
+    Ok(response)
+}
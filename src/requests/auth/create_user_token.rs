@@ -8,11 +8,25 @@ use bb8_postgres::PostgresConnectionManager;
 use crate::jwt::api as jwt_api;
 
 use crate::core::core_config::CoreConfig;
+use crate::monitoring::otel::trace_db_query;
+use crate::requests::models::user::get_user_by_id;
+use crate::requests::models::user_session::ModelUserSessionMetadata;
 
 /// create_user_token
 ///
 /// Create a signed jwt for the ``user_id`` and ``user_email``
-/// and store it in postgres with an expiration date
+/// and store it in postgres as a new user session
+/// with the client's ``session`` details and the
+/// signing ``kid`` (for reporting which tokens still
+/// use a key during a key migration).
+///
+/// The jwt includes the static ``token_claims`` and any
+/// per-user claims from the ``token_claims_provider`` on the
+/// [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// (see [`token_claims`](crate::jwt::token_claims)) and the
+/// user's ``tenant_id`` when multi-tenancy is enabled.
+/// A non-empty ``scope`` restricts the routes the jwt can
+/// use (see [`token_scopes`](crate::requests::auth::token_scopes)).
 ///
 /// # Arguments
 ///
@@ -23,6 +37,10 @@ use crate::core::core_config::CoreConfig;
 ///   established db connection from the threadpool
 /// * `user_email` - `&str` - user's email
 /// * `user_id` - `i32` - user's database id
+/// * `session` - [`ModelUserSessionMetadata`](crate::requests::models::user_session::ModelUserSessionMetadata) -
+///   client details for listing the user's sessions
+/// * `scope` - `&str` - space-separated token scopes
+///   (empty = an unscoped token)
 ///
 /// # Returns
 ///
@@ -40,12 +58,57 @@ pub async fn create_user_token(
     conn: &PooledConnection<'_, PostgresConnectionManager<MakeTlsConnector>>,
     user_email: &str,
     user_id: i32,
+    session: &ModelUserSessionMetadata,
+    scope: &str,
 ) -> Result<String, String> {
     info!("{tracking_label} creating user {user_id} token");
-    let new_token = match jwt_api::create_token(
+    // sign with the pinned jwt_signing_kid or the
+    // newest key in the key ring
+    let pinned_kid = config.get_settings().jwt_signing_kid;
+    let (signing_kid, encoding_key_bytes) =
+        config.jwt_keys.read().unwrap().get_signing_key(&pinned_kid);
+    // per-user claims replace static claims with the same name
+    let mut claims = config.token_claims.clone();
+    if let Some(provider) = &config.token_claims_provider {
+        match provider
+            .get_user_claims(tracking_label, conn, user_email, user_id)
+            .await
+        {
+            Ok(user_claims) => claims.extend(user_claims),
+            Err(err_msg) => {
+                error!(
+                    "{tracking_label} failed to get custom claims for \
+                    user {user_id} {user_email} - err_msg='{err_msg}'"
+                );
+                return Err("INVALID".to_string());
+            }
+        }
+    }
+    // the user's tenant replaces any custom tenant_id claim
+    if config.tenants.is_enabled() {
+        match get_user_by_id(tracking_label, user_id, conn).await {
+            Ok(user_model) => {
+                claims.insert(
+                    "tenant_id".to_string(),
+                    serde_json::json!(user_model.tenant_id),
+                );
+            }
+            Err(err_msg) => {
+                error!(
+                    "{tracking_label} failed to get the tenant for \
+                    user {user_id} {user_email} - err_msg='{err_msg}'"
+                );
+                return Err("INVALID".to_string());
+            }
+        }
+    }
+    let new_token = match jwt_api::create_token_with_scope(
         tracking_label,
         user_email,
-        &config.encoding_key_bytes,
+        Some(&signing_kid),
+        claims,
+        scope,
+        &encoding_key_bytes,
     )
     .await
     {
@@ -63,14 +126,32 @@ pub async fn create_user_token(
             users_tokens (\
                 user_id, \
                 token, \
-                state) \
+                state, \
+                user_agent, \
+                ip_address, \
+                device, \
+                country, \
+                city, \
+                kid) \
         VALUES (\
             {user_id}, \
             '{new_token}', \
-            0)"
+            0, \
+            '{}', \
+            '{}', \
+            '{}', \
+            '{}', \
+            '{}', \
+            '{}')",
+        session.user_agent.replace('\'', "''"),
+        session.ip_address.replace('\'', "''"),
+        session.device.replace('\'', "''"),
+        session.country.replace('\'', "''"),
+        session.city.replace('\'', "''"),
+        signing_kid.replace('\'', "''")
     );
     let stmt = conn.prepare(&insert_query).await.unwrap();
-    let _ = match conn.query(&stmt, &[]).await {
+    let _ = match trace_db_query(&insert_query, conn.query(&stmt, &[])).await {
         Ok(_query_result) => _query_result,
         Err(e) => {
             let err_msg = format!("{e}");
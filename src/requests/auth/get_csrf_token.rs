@@ -0,0 +1,130 @@
+//! Module for issuing csrf tokens for cookie-authenticated
+//! browser apps
+//!
+//! ## Get a CSRF Token
+//!
+//! Get the csrf token that cookie-authenticated ``POST``, ``PUT``, ``PATCH`` and ``DELETE`` requests must send in the ``TOKEN_COOKIE_CSRF_HEADER`` header (requires ``TOKEN_COOKIE_MODE=both`` or ``cookie``). The token is also set in the ``TOKEN_COOKIE_CSRF_NAME`` cookie, and an existing csrf cookie is reused so every tab keeps working.
+//!
+//! - URL path: ``/csrf``
+//! - Method: ``GET``
+//! - Handler: [`get_csrf_token`](crate::requests::auth::get_csrf_token::get_csrf_token)
+//! - Request: none
+//! - Response: [`ApiResCsrfToken`](crate::requests::auth::get_csrf_token::ApiResCsrfToken)
+//!
+use std::convert::Infallible;
+
+use hyper::header::HeaderValue;
+use hyper::header::SET_COOKIE;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Response;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::core::core_config::CoreConfig;
+use crate::requests::models::api_error::ApiErrorCode;
+
+/// ApiResCsrfToken
+///
+/// # Response type for get_csrf_token
+///
+/// Return the csrf token and the header it is sent in
+///
+/// # Arguments
+///
+/// * `csrf_token` - `String` - csrf token
+/// * `header` - `String` - header name for the csrf token
+/// * `msg` - `String` - help message
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   machine-readable failure code (omitted on success)
+///
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ApiResCsrfToken {
+    pub csrf_token: String,
+    pub header: String,
+    pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ApiErrorCode>,
+}
+
+/// get_csrf_token
+///
+/// Handler for returning the request's csrf token (or a
+/// new one in a new csrf cookie)
+///
+/// This api does not require a token.
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) - HTTP
+///   headers as a map with the cookies
+///
+/// # Returns
+///
+/// ## get_csrf_token on Success Returns
+///
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResCsrfToken`](crate::requests::auth::get_csrf_token::ApiResCsrfToken)
+/// dictionary within the
+/// [`Body`](hyper::Body) and a
+/// `200` HTTP status code
+///
+/// Ok([`Response`](hyper::Response))
+///
+/// # Errors
+///
+/// ## get_csrf_token on Failure Returns
+///
+/// A `400` HTTP status code with ``FEATURE_DISABLED`` when
+/// ``TOKEN_COOKIE_MODE=off``
+///
+/// Err([`Response`](hyper::Response))
+///
+pub async fn get_csrf_token(
+    tracking_label: &str,
+    config: &CoreConfig,
+    headers: &HeaderMap<HeaderValue>,
+) -> std::result::Result<Response<Body>, Infallible> {
+    let token_cookie = &config.token_cookie;
+    if !token_cookie.is_enabled() {
+        let response = Response::builder()
+            .status(400)
+            .body(Body::from(
+                serde_json::to_string(&ApiResCsrfToken {
+                    msg: ("CSRF tokens are only used with \
+                        TOKEN_COOKIE_MODE=both or cookie")
+                        .to_string(),
+                    error_code: Some(ApiErrorCode::FeatureDisabled),
+                    ..Default::default()
+                })
+                .unwrap(),
+            ))
+            .unwrap();
+        return Ok(response);
+    }
+    let (csrf_token, is_new) = token_cookie.get_csrf_token(headers);
+    let mut response = Response::builder()
+        .status(200)
+        .header("Cache-Control", "no-store");
+    if is_new {
+        info!("{tracking_label} - created a new csrf token");
+        response = response
+            .header(SET_COOKIE, token_cookie.get_csrf_set_cookie(&csrf_token));
+    }
+    let response = response
+        .body(Body::from(
+            serde_json::to_string(&ApiResCsrfToken {
+                csrf_token,
+                header: token_cookie.csrf_header.clone(),
+                msg: "success".to_string(),
+                error_code: None,
+            })
+            .unwrap(),
+        ))
+        .unwrap();
+    Ok(response)
+}
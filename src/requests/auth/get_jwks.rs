@@ -0,0 +1,90 @@
+//! Module for publishing the jwt public keys
+//!
+//! ## Get the JSON Web Key Set (JWKS)
+//!
+//! Get the public jwt verification keys in JWKS format so other services can validate tokens issued by this api. Each key's ``kid`` matches the ``kid`` in the jwt header.
+//!
+//! - URL path: ``/.well-known/jwks.json``
+//! - Method: ``GET``
+//! - Handler: [`get_jwks`](crate::requests::auth::get_jwks::get_jwks)
+//! - Request: none
+//! - Response: [`JwkSet`](jsonwebtoken::jwk::JwkSet)
+//!
+use std::convert::Infallible;
+
+use hyper::Body;
+use hyper::Response;
+
+use crate::core::core_config::CoreConfig;
+use crate::jwt::build_jwks::build_jwks;
+
+/// get_jwks
+///
+/// Handler for returning the public jwt keys from the
+/// [`JwtKeys`](crate::jwt::jwt_keys::JwtKeys)
+/// key ring as a json-serialized
+/// [`JwkSet`](jsonwebtoken::jwk::JwkSet).
+/// Rotated keys show up after the keys are reloaded
+/// and retired keys (``jwt_retired_kids``) are left out.
+///
+/// This api does not require a token.
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+///
+/// # Returns
+///
+/// ## get_jwks on Success Returns
+///
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`JwkSet`](jsonwebtoken::jwk::JwkSet)
+/// within the
+/// [`Body`](hyper::Body) and a
+/// `200` HTTP status code
+///
+/// Ok([`Response`](hyper::Response))
+///
+/// # Errors
+///
+/// ## get_jwks on Failure Returns
+///
+/// A `500` HTTP status code with an empty
+/// [`JwkSet`](jsonwebtoken::jwk::JwkSet)
+/// if a public key is invalid
+///
+/// Err([`Response`](hyper::Response))
+///
+pub async fn get_jwks(
+    tracking_label: &str,
+    config: &CoreConfig,
+) -> std::result::Result<Response<Body>, Infallible> {
+    let retired_kids = config.get_settings().jwt_retired_kids;
+    let jwt_keys = config
+        .jwt_keys
+        .read()
+        .unwrap()
+        .without_retired_kids(&retired_kids);
+    match build_jwks(tracking_label, &jwt_keys) {
+        Ok(jwks) => {
+            let response = Response::builder()
+                .status(200)
+                .header("Content-Type", "application/json")
+                .header("Cache-Control", "public, max-age=300")
+                .body(Body::from(serde_json::to_string(&jwks).unwrap()))
+                .unwrap();
+            Ok(response)
+        }
+        Err(err_msg) => {
+            error!("{err_msg}");
+            let response = Response::builder()
+                .status(500)
+                .header("Content-Type", "application/json")
+                .body(Body::from("{\"keys\":[]}"))
+                .unwrap();
+            Ok(response)
+        }
+    }
+}
@@ -0,0 +1,114 @@
+//! Record password and passkey login attempts for
+//! known users
+//!
+//! Each attempt is stored in the ``users_logins`` table
+//! with the client details from the session metadata so
+//! users can review their recent activity with
+//! [`get_user_logins`](crate::requests::user::get_user_logins::get_user_logins).
+//!
+//! Attempts for emails that do not match a user are not
+//! recorded (there is no user to show them to). Recording
+//! errors are logged and never fail the login.
+//!
+use postgres_native_tls::MakeTlsConnector;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use bb8::PooledConnection;
+use bb8_postgres::PostgresConnectionManager;
+
+use crate::requests::models::user_login::insert_user_login;
+use crate::requests::models::user_session::ModelUserSessionMetadata;
+
+/// LoginHistory
+///
+/// Settings for recording login attempts
+///
+/// # Supported Environment Variables
+///
+/// ```bash
+/// export USER_LOGINS_ENABLED="1"
+/// # delete a user's attempts older than this (0 = keep all)
+/// export USER_LOGINS_RETENTION_DAYS="90"
+/// ```
+///
+/// # Arguments
+///
+/// * `enabled` - `bool` - record login attempts
+/// * `retention_days` - `i64` - days to keep a
+///   user's attempts (`0` = keep every attempt)
+///
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct LoginHistory {
+    pub enabled: bool,
+    pub retention_days: i64,
+}
+
+impl LoginHistory {
+    /// build_login_history
+    ///
+    /// Build a
+    /// [`LoginHistory`](crate::requests::auth::login_history::LoginHistory)
+    /// from environment variables
+    ///
+    pub fn build_login_history() -> Self {
+        let enabled_s = std::env::var("USER_LOGINS_ENABLED")
+            .unwrap_or_else(|_| "1".to_string());
+        let retention_days = std::env::var("USER_LOGINS_RETENTION_DAYS")
+            .unwrap_or_else(|_| "90".to_string())
+            .parse::<i64>()
+            .unwrap_or(90)
+            .max(0);
+        LoginHistory {
+            enabled: enabled_s == "1" || enabled_s == "true",
+            retention_days,
+        }
+    }
+
+    /// record_login
+    ///
+    /// Record one login attempt for a known user
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - caller logging label
+    /// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+    ///   an established db connection from the
+    ///   postgres client db threadpool
+    /// * `user_id` - `i32` - user id in the db
+    /// * `method` - `&str` - ``password`` or ``passkey``
+    /// * `result` - `&str` - ``success`` or the failure reason
+    /// * `session` - [`ModelUserSessionMetadata`](crate::requests::models::user_session::ModelUserSessionMetadata) -
+    ///   client details for the attempt
+    ///
+    pub async fn record_login(
+        &self,
+        tracking_label: &str,
+        conn: &PooledConnection<
+            '_,
+            PostgresConnectionManager<MakeTlsConnector>,
+        >,
+        user_id: i32,
+        method: &str,
+        result: &str,
+        session: &ModelUserSessionMetadata,
+    ) {
+        if !self.enabled {
+            return;
+        }
+        if let Err(err_msg) = insert_user_login(
+            tracking_label,
+            user_id,
+            method,
+            result,
+            session,
+            self.retention_days,
+            conn,
+        )
+        .await
+        {
+            error!("{err_msg}");
+        }
+    }
+}
@@ -0,0 +1,538 @@
+//! Score password logins with an operator's risk engine
+//!
+//! Implement the
+//! [`LoginRiskHook`](crate::requests::auth::login_risk::LoginRiskHook)
+//! trait and set it with
+//! ``RestApiServerBuilder::login_risk_hook`` (or on the
+//! [`CoreConfig`](crate::core::core_config::CoreConfig)
+//! ``login_risk.hook``) before starting the server. After a
+//! user's password is verified, ``POST /login`` calls the
+//! hook with the user, the new session's client details
+//! (ip address, user agent, ``device`` header and GeoIP
+//! location) and the user's recent sessions. The hook
+//! returns a
+//! [`LoginRiskDecision`](crate::requests::auth::login_risk::LoginRiskDecision):
+//!
+//! - ``allow`` - create the token
+//! - ``challenge`` - reject the login with
+//!   ``LOGIN_CHALLENGE_REQUIRED`` and start a second
+//!   factor. Users with passkeys can finish a passkey
+//!   login (``POST /login/passkey/start``), and with
+//!   ``KAFKA_PUBLISH_EVENTS=1`` a one-time-password is
+//!   emailed that the client sends back as the ``otp``
+//!   in the next ``POST /login``. A login with a valid
+//!   ``otp`` is not scored again.
+//! - ``deny`` - reject the login with ``LOGIN_DENIED``
+//!
+//! Passkey logins are not scored because they are the
+//! second factor.
+//!
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use lazy_static::lazy_static;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use bb8::PooledConnection;
+use bb8_postgres::PostgresConnectionManager;
+
+use crate::core::core_config::CoreConfig;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::monitoring::metric_types::register_int_counter_vec;
+use crate::monitoring::metric_types::IntCounterVec;
+use crate::monitoring::otel::trace_db_query;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::models::user::get_user_by_id;
+use crate::requests::models::user_passkey::get_user_passkeys;
+use crate::requests::models::user_session::get_user_login_history;
+use crate::requests::models::user_session::ModelUserSession;
+use crate::requests::models::user_session::ModelUserSessionMetadata;
+use crate::requests::user::consume_user_otp::record_failed_otp_attempt;
+use crate::requests::user::create_otp::insert_user_otp;
+use crate::utils::hash_token::hash_token;
+
+lazy_static! {
+    pub static ref LOGIN_RISK_COUNTER_VEC: IntCounterVec =
+        register_int_counter_vec!(
+            "login_risk_decisions_total",
+            "Number of scored logins by decision and source.",
+            &["decision", "source"]
+        )
+        .unwrap();
+}
+
+/// LoginRiskDecision
+///
+/// Result of scoring a login
+///
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LoginRiskDecision {
+    Allow,
+    Challenge,
+    Deny,
+}
+
+impl LoginRiskDecision {
+    /// as_str
+    ///
+    /// Serialized decision for logs and metric labels
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use restapi::requests::auth::login_risk::LoginRiskDecision;
+    /// assert_eq!(LoginRiskDecision::Challenge.as_str(), "challenge");
+    /// ```
+    ///
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LoginRiskDecision::Allow => "allow",
+            LoginRiskDecision::Challenge => "challenge",
+            LoginRiskDecision::Deny => "deny",
+        }
+    }
+}
+
+/// LoginRiskContext
+///
+/// Login details passed to a
+/// [`LoginRiskHook`](crate::requests::auth::login_risk::LoginRiskHook)
+///
+/// # Arguments
+///
+/// * `user_id` - `i32` - user id
+/// * `email` - `String` - user email
+/// * `role` - `String` - user role
+/// * `session` - [`ModelUserSessionMetadata`](crate::requests::models::user_session::ModelUserSessionMetadata) -
+///   client details for the new session
+/// * `history` - `Vec<`[`ModelUserSession`](crate::requests::models::user_session::ModelUserSession)`>` -
+///   the user's most recent sessions (active, expired and
+///   revoked, newest first, up to
+///   ``LOGIN_RISK_HISTORY_LIMIT``)
+///
+#[derive(Serialize, Deserialize, Clone)]
+pub struct LoginRiskContext {
+    pub user_id: i32,
+    pub email: String,
+    pub role: String,
+    pub session: ModelUserSessionMetadata,
+    pub history: Vec<ModelUserSession>,
+}
+
+/// future returned by
+/// [`LoginRiskHook::score_login`](crate::requests::auth::login_risk::LoginRiskHook::score_login)
+pub type LoginRiskFuture<'a> = Pin<
+    Box<dyn Future<Output = Result<LoginRiskDecision, String>> + Send + 'a>,
+>;
+
+/// LoginRiskHook
+///
+/// Risk engine called after a user's password is verified
+///
+pub trait LoginRiskHook: Send + Sync {
+    /// score_login
+    ///
+    /// Decide if a login is allowed, needs a second factor
+    /// or is denied
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - logging label for caller
+    /// * `context` - [`LoginRiskContext`](crate::requests::auth::login_risk::LoginRiskContext) -
+    ///   the login
+    ///
+    /// # Returns
+    ///
+    /// Ok([`LoginRiskDecision`](crate::requests::auth::login_risk::LoginRiskDecision))
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) - the login uses the
+    /// ``LOGIN_RISK_ON_ERROR`` decision
+    ///
+    fn score_login<'a>(
+        &'a self,
+        tracking_label: &'a str,
+        context: &'a LoginRiskContext,
+    ) -> LoginRiskFuture<'a>;
+}
+
+/// LoginRisk
+///
+/// Settings for scoring password logins
+///
+/// # Supported Environment Variables
+///
+/// ```bash
+/// # decision when the hook fails: allow, challenge or deny
+/// export LOGIN_RISK_ON_ERROR="allow"
+/// # recent sessions passed to the hook
+/// export LOGIN_RISK_HISTORY_LIMIT="20"
+/// ```
+///
+/// # Arguments
+///
+/// * `hook` - `Option<Arc<dyn`[`LoginRiskHook`](crate::requests::auth::login_risk::LoginRiskHook)`>>` -
+///   risk engine (`None` = every login is allowed)
+/// * `on_error` - [`LoginRiskDecision`](crate::requests::auth::login_risk::LoginRiskDecision) -
+///   decision when the hook returns an `Err`
+/// * `history_limit` - `i64` - max sessions in the
+///   [`LoginRiskContext`](crate::requests::auth::login_risk::LoginRiskContext)
+///   ``history``
+///
+#[derive(Clone)]
+pub struct LoginRisk {
+    pub hook: Option<Arc<dyn LoginRiskHook>>,
+    pub on_error: LoginRiskDecision,
+    pub history_limit: i64,
+}
+
+impl Default for LoginRisk {
+    fn default() -> Self {
+        LoginRisk {
+            hook: None,
+            on_error: LoginRiskDecision::Allow,
+            history_limit: 20,
+        }
+    }
+}
+
+impl LoginRisk {
+    /// build_login_risk
+    ///
+    /// Build a
+    /// [`LoginRisk`](crate::requests::auth::login_risk::LoginRisk)
+    /// from environment variables (the hook is set by the
+    /// ``RestApiServerBuilder``)
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) when ``LOGIN_RISK_ON_ERROR`` is
+    /// not ``allow``, ``challenge`` or ``deny``
+    ///
+    pub fn build_login_risk() -> Result<Self, String> {
+        let on_error_str = std::env::var("LOGIN_RISK_ON_ERROR")
+            .unwrap_or_else(|_| "allow".to_string());
+        let on_error = match on_error_str.trim() {
+            "allow" => LoginRiskDecision::Allow,
+            "challenge" => LoginRiskDecision::Challenge,
+            "deny" => LoginRiskDecision::Deny,
+            _ => {
+                return Err(format!(
+                    "LOGIN_RISK_ON_ERROR={on_error_str} must be \
+                    allow, challenge or deny"
+                ))
+            }
+        };
+        let history_limit = std::env::var("LOGIN_RISK_HISTORY_LIMIT")
+            .unwrap_or_else(|_| "20".to_string())
+            .parse::<i64>()
+            .unwrap_or(20)
+            .max(0);
+        Ok(LoginRisk {
+            hook: None,
+            on_error,
+            history_limit,
+        })
+    }
+
+    /// is_enabled
+    ///
+    /// Check if a hook is set
+    ///
+    pub fn is_enabled(&self) -> bool {
+        self.hook.is_some()
+    }
+
+    /// score_login
+    ///
+    /// Load the user's recent sessions and call the hook
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - caller logging label
+    /// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+    ///   an established db connection from the
+    ///   postgres client db threadpool
+    /// * `user_id` - `i32` - user id
+    /// * `email` - `&str` - user email
+    /// * `role` - `&str` - user role
+    /// * `session` - [`ModelUserSessionMetadata`](crate::requests::models::user_session::ModelUserSessionMetadata) -
+    ///   client details for the new session
+    ///
+    /// # Returns
+    ///
+    /// [`LoginRiskDecision`](crate::requests::auth::login_risk::LoginRiskDecision) -
+    /// ``allow`` without a hook and ``on_error`` when the
+    /// hook fails
+    ///
+    pub async fn score_login(
+        &self,
+        tracking_label: &str,
+        conn: &PooledConnection<
+            '_,
+            PostgresConnectionManager<MakeTlsConnector>,
+        >,
+        user_id: i32,
+        email: &str,
+        role: &str,
+        session: &ModelUserSessionMetadata,
+    ) -> LoginRiskDecision {
+        let hook = match &self.hook {
+            Some(hook) => hook,
+            None => return LoginRiskDecision::Allow,
+        };
+        let history = match get_user_login_history(
+            tracking_label,
+            user_id,
+            self.history_limit,
+            conn,
+        )
+        .await
+        {
+            Ok(history) => history,
+            Err(err_msg) => {
+                error!("{err_msg}");
+                Vec::new()
+            }
+        };
+        let context = LoginRiskContext {
+            user_id,
+            email: email.to_string(),
+            role: role.to_string(),
+            session: session.clone(),
+            history,
+        };
+        let (decision, source) =
+            match hook.score_login(tracking_label, &context).await {
+                Ok(decision) => (decision, "hook"),
+                Err(err_msg) => {
+                    error!(
+                        "{tracking_label} - \
+                        login risk hook failed for user {user_id} \
+                        using LOGIN_RISK_ON_ERROR={} with err='{err_msg}'",
+                        self.on_error.as_str()
+                    );
+                    (self.on_error, "error")
+                }
+            };
+        info!(
+            "{tracking_label} - user {user_id} login risk \
+            decision={} source={source}",
+            decision.as_str()
+        );
+        LOGIN_RISK_COUNTER_VEC
+            .with_label_values(&[decision.as_str(), source])
+            .inc();
+        decision
+    }
+}
+
+/// start_login_challenge
+///
+/// Start the second factors for a challenged login. A
+/// user with passkeys can finish a passkey login, and with
+/// ``KAFKA_PUBLISH_EVENTS=1`` a one-time-password is
+/// created and emailed (``LOGIN_CHALLENGE`` user event and
+/// the ``otp`` email with ``purpose=login``).
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `kafka_pool` - initialized [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+/// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+///   an established db connection from the
+///   postgres client db threadpool
+/// * `user_id` - `i32` - user id
+/// * `email` - `&str` - user email
+///
+/// # Returns
+///
+/// `Vec<String>` - started second factors (``otp`` and
+/// ``passkey``). Empty when the user has no second factor
+/// and the login must be denied.
+///
+pub async fn start_login_challenge(
+    tracking_label: &str,
+    config: &CoreConfig,
+    kafka_pool: &KafkaPublisher,
+    conn: &PooledConnection<'_, PostgresConnectionManager<MakeTlsConnector>>,
+    user_id: i32,
+    email: &str,
+) -> Vec<String> {
+    let mut methods: Vec<String> = Vec::new();
+    // the token is only delivered by the mail service
+    if config.events.enabled {
+        match insert_user_otp(tracking_label, config, conn, user_id, email)
+            .await
+        {
+            Ok(user_otp) => {
+                config
+                    .events
+                    .publish_user_event(
+                        kafka_pool,
+                        user_id,
+                        "LOGIN_CHALLENGE",
+                        &format!("email={email} token={}", user_otp.token),
+                    )
+                    .await;
+                if let Ok(user_model) =
+                    get_user_by_id(tracking_label, user_id, conn).await
+                {
+                    config
+                        .email_templates
+                        .send_user_email(
+                            tracking_label,
+                            &config.events,
+                            kafka_pool,
+                            user_id,
+                            "otp",
+                            &user_model.locale,
+                            email,
+                            serde_json::json!({
+                                "purpose": "login",
+                                "token": user_otp.token,
+                                "exp_date": user_otp.exp_date,
+                            }),
+                        )
+                        .await;
+                }
+                methods.push("otp".to_string());
+            }
+            Err(e) => {
+                warn!(
+                    "{tracking_label} - \
+                    login challenge one-time-password for user \
+                    {user_id} failed status={} - {}",
+                    e.status, e.msg
+                );
+            }
+        }
+    }
+    match get_user_passkeys(tracking_label, user_id, conn).await {
+        Ok(passkeys) if !passkeys.is_empty() => {
+            methods.push("passkey".to_string());
+        }
+        Ok(_) => {}
+        Err(err_msg) => error!("{err_msg}"),
+    }
+    methods
+}
+
+/// consume_login_otp
+///
+/// Consume the one-time-password from a login challenge
+/// (the user's password is not changed). Wrong tokens count
+/// against ``USER_OTP_MAX_ATTEMPTS``.
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+///   an established db connection from the
+///   postgres client db threadpool
+/// * `user_id` - `i32` - user id
+/// * `email` - `&str` - user email
+/// * `token` - `&str` - one-time-password from the email
+///
+/// # Returns
+///
+/// Ok(())
+///
+/// # Errors
+///
+/// Err([`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)) -
+/// ``OTP_INVALID`` for a wrong, used or expired token and
+/// ``LOGIN_LOCKED`` after too many wrong tokens
+///
+pub async fn consume_login_otp(
+    tracking_label: &str,
+    config: &CoreConfig,
+    conn: &PooledConnection<'_, PostgresConnectionManager<MakeTlsConnector>>,
+    user_id: i32,
+    email: &str,
+    token: &str,
+) -> Result<(), ApiErrorCode> {
+    let now = chrono::Utc::now();
+    let token_hash_sql =
+        hash_token(token, &config.server_password_salt).replace('\'', "''");
+    // same single statement as consume_user_otp without
+    // the password change
+    let query = format!(
+        "WITH consumed_token AS (\
+            INSERT INTO \
+                users_tokens_consumed (kind, token, user_id) \
+            VALUES \
+                ('otp', '{token_hash_sql}', {user_id}) \
+            ON CONFLICT (kind, token) DO NOTHING \
+            RETURNING \
+                users_tokens_consumed.id) \
+        UPDATE \
+            users_otp \
+        SET \
+            state = 1, \
+            consumed_date = '{now}' \
+        WHERE \
+            users_otp.user_id = {user_id} \
+            AND \
+            users_otp.state = 0 \
+            AND \
+            EXISTS (SELECT 1 FROM consumed_token) \
+            AND \
+            users_otp.token = '{token_hash_sql}' \
+            AND \
+            users_otp.email = '{}' \
+            AND \
+            users_otp.exp_date > '{now}' \
+        RETURNING \
+            users_otp.id;",
+        email.replace('\'', "''")
+    );
+    let stmt = conn.prepare(&query).await.unwrap();
+    match trace_db_query(&query, conn.query(&stmt, &[])).await {
+        Ok(query_result) if !query_result.is_empty() => {
+            info!(
+                "{tracking_label} - \
+                user {user_id} passed the login challenge"
+            );
+            LOGIN_RISK_COUNTER_VEC
+                .with_label_values(&["allow", "otp"])
+                .inc();
+            Ok(())
+        }
+        Ok(_) => {
+            config.auth_alerts.record_failure(
+                tracking_label,
+                "otp",
+                "login_challenge",
+            );
+            match record_failed_otp_attempt(
+                tracking_label,
+                config,
+                conn,
+                user_id,
+            )
+            .await
+            {
+                true => Err(ApiErrorCode::LoginLocked),
+                false => Err(ApiErrorCode::OtpInvalid),
+            }
+        }
+        Err(e) => {
+            error!(
+                "{tracking_label} - \
+                failed to consume the login one-time-password for \
+                user {user_id} with err='{e}'"
+            );
+            Err(ApiErrorCode::InternalError)
+        }
+    }
+}
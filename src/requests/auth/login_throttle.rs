@@ -0,0 +1,386 @@
+//! Throttle failed logins by target email and by
+//! client ip address with exponential backoff
+//!
+//! Failures are tracked in the ``users_login_throttle``
+//! table so every api server in a cluster shares the
+//! same counts. Tracking the target email (not just the
+//! source ip) slows down credential-stuffing attacks
+//! that spread attempts across many ips.
+//!
+//! After ``LOGIN_THROTTLE_MAX_FAILURES`` failures in a row,
+//! a key is locked for
+//! ``LOGIN_THROTTLE_BASE_DELAY_SECONDS * 2^(failures - max_failures)``
+//! seconds (up to ``LOGIN_THROTTLE_MAX_DELAY_SECONDS``).
+//! A successful login resets the email's failures.
+//! Failures older than ``LOGIN_THROTTLE_RESET_SECONDS``
+//! are forgotten on the next failure.
+//!
+//! Admins can unlock an email or ip with
+//! [`unlock_login`](crate::requests::admin::unlock_login::unlock_login).
+//!
+use lazy_static::lazy_static;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use bb8::PooledConnection;
+use bb8_postgres::PostgresConnectionManager;
+
+use crate::monitoring::metric_types::register_int_counter_vec;
+use crate::monitoring::metric_types::IntCounterVec;
+use crate::monitoring::otel::trace_db_query;
+
+lazy_static! {
+    pub static ref LOGIN_THROTTLE_COUNTER_VEC: IntCounterVec =
+        register_int_counter_vec!(
+            "login_throttle_total",
+            "Number of login throttle failures, blocked logins, \
+            resets and admin unlocks.",
+            &["kind", "result"]
+        )
+        .unwrap();
+}
+
+/// LoginThrottle
+///
+/// Settings for throttling failed logins by email and ip
+///
+/// # Supported Environment Variables
+///
+/// ```bash
+/// export LOGIN_THROTTLE_ENABLED="1"
+/// export LOGIN_THROTTLE_MAX_FAILURES="5"
+/// export LOGIN_THROTTLE_BASE_DELAY_SECONDS="1"
+/// export LOGIN_THROTTLE_MAX_DELAY_SECONDS="900"
+/// export LOGIN_THROTTLE_RESET_SECONDS="3600"
+/// ```
+///
+/// # Arguments
+///
+/// * `enabled` - `bool` - throttle failed logins
+/// * `max_failures` - `i32` - failures allowed before
+///   locking a key
+/// * `base_delay_seconds` - `i64` - first lock duration
+/// * `max_delay_seconds` - `i64` - longest lock duration
+/// * `reset_seconds` - `i64` - forget failures older
+///   than this
+///
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct LoginThrottle {
+    pub enabled: bool,
+    pub max_failures: i32,
+    pub base_delay_seconds: i64,
+    pub max_delay_seconds: i64,
+    pub reset_seconds: i64,
+}
+
+impl LoginThrottle {
+    /// build_login_throttle
+    ///
+    /// Build a
+    /// [`LoginThrottle`](crate::requests::auth::login_throttle::LoginThrottle)
+    /// from environment variables
+    ///
+    pub fn build_login_throttle() -> Self {
+        let get_env = |key: &str, default: i64| -> i64 {
+            std::env::var(key)
+                .unwrap_or_else(|_| format!("{default}"))
+                .parse::<i64>()
+                .unwrap_or(default)
+        };
+        let enabled_s = std::env::var("LOGIN_THROTTLE_ENABLED")
+            .unwrap_or_else(|_| "1".to_string());
+        LoginThrottle {
+            enabled: enabled_s == "1" || enabled_s == "true",
+            max_failures: get_env("LOGIN_THROTTLE_MAX_FAILURES", 5).max(1)
+                as i32,
+            base_delay_seconds: get_env("LOGIN_THROTTLE_BASE_DELAY_SECONDS", 1)
+                .max(1),
+            max_delay_seconds: get_env("LOGIN_THROTTLE_MAX_DELAY_SECONDS", 900)
+                .max(1),
+            reset_seconds: get_env("LOGIN_THROTTLE_RESET_SECONDS", 3600).max(1),
+        }
+    }
+
+    /// get_lock_seconds
+    ///
+    /// Get the lock duration for a key with `failures`
+    /// failures in a row
+    ///
+    /// # Arguments
+    ///
+    /// * `failures` - `i32` - failures in a row
+    ///
+    /// # Returns
+    ///
+    /// `i64` - seconds to lock the key (`0` if not locked)
+    ///
+    pub fn get_lock_seconds(&self, failures: i32) -> i64 {
+        if failures < self.max_failures {
+            return 0;
+        }
+        let exponent = (failures - self.max_failures).min(30) as u32;
+        self.base_delay_seconds
+            .saturating_mul(2_i64.pow(exponent))
+            .min(self.max_delay_seconds)
+    }
+
+    /// check_login
+    ///
+    /// Check if the email or the ip is locked
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - caller logging label
+    /// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+    ///   an established db connection from the
+    ///   postgres client db threadpool
+    /// * `email` - `&str` - login email
+    /// * `ip_address` - `&str` - client ip address
+    ///
+    /// # Returns
+    ///
+    /// Ok(())
+    ///
+    /// # Errors
+    ///
+    /// Err(retry_after_seconds: `i64`) if a key is locked
+    ///
+    pub async fn check_login(
+        &self,
+        tracking_label: &str,
+        conn: &PooledConnection<
+            '_,
+            PostgresConnectionManager<MakeTlsConnector>,
+        >,
+        email: &str,
+        ip_address: &str,
+    ) -> Result<(), i64> {
+        if !self.enabled {
+            return Ok(());
+        }
+        let query = format!(
+            "SELECT \
+                users_login_throttle.kind, \
+                CEIL(EXTRACT(EPOCH FROM ( \
+                    users_login_throttle.locked_until - now())))::BIGINT \
+                    AS retry_after \
+            FROM \
+                users_login_throttle \
+            WHERE \
+                ((users_login_throttle.kind = 'email' \
+                    AND users_login_throttle.key = '{}') \
+                OR \
+                (users_login_throttle.kind = 'ip' \
+                    AND users_login_throttle.key = '{}')) \
+                AND \
+                users_login_throttle.locked_until > now();",
+            email.replace('\'', "''"),
+            ip_address.replace('\'', "''")
+        );
+        let stmt = conn.prepare(&query).await.unwrap();
+        let query_result =
+            match trace_db_query(&query, conn.query(&stmt, &[])).await {
+                Ok(query_result) => query_result,
+                Err(e) => {
+                    // do not lock users out if the throttle table is unavailable
+                    error!(
+                        "{tracking_label} - \
+                    failed to check login throttle with err='{e}'"
+                    );
+                    return Ok(());
+                }
+            };
+        let mut retry_after: i64 = 0;
+        for row in query_result.iter() {
+            let kind: String = row.try_get("kind").unwrap();
+            let row_retry_after: i64 = row.try_get("retry_after").unwrap();
+            LOGIN_THROTTLE_COUNTER_VEC
+                .with_label_values(&[&kind, "blocked"])
+                .inc();
+            retry_after = retry_after.max(row_retry_after.max(1));
+        }
+        if retry_after > 0 {
+            warn!(
+                "{tracking_label} - \
+                login throttled email={email} ip={ip_address} \
+                retry_after={retry_after}s"
+            );
+            return Err(retry_after);
+        }
+        Ok(())
+    }
+
+    /// record_login_failure
+    ///
+    /// Count a failed login for the email and the ip
+    /// and lock either key that reached
+    /// `max_failures`
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - caller logging label
+    /// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+    ///   an established db connection from the
+    ///   postgres client db threadpool
+    /// * `email` - `&str` - login email
+    /// * `ip_address` - `&str` - client ip address
+    ///
+    pub async fn record_login_failure(
+        &self,
+        tracking_label: &str,
+        conn: &PooledConnection<
+            '_,
+            PostgresConnectionManager<MakeTlsConnector>,
+        >,
+        email: &str,
+        ip_address: &str,
+    ) {
+        if !self.enabled {
+            return;
+        }
+        for (kind, key) in [("email", email), ("ip", ip_address)] {
+            if key.is_empty() {
+                continue;
+            }
+            let key = key.replace('\'', "''");
+            let reset_seconds = self.reset_seconds;
+            let query = format!(
+                "INSERT INTO \
+                    users_login_throttle (\
+                        kind, \
+                        key, \
+                        failures, \
+                        last_failure_at) \
+                VALUES (\
+                    '{kind}', \
+                    '{key}', \
+                    1, \
+                    now()) \
+                ON CONFLICT (kind, key) DO UPDATE SET \
+                    failures = CASE \
+                        WHEN users_login_throttle.last_failure_at < \
+                            now() - interval '{reset_seconds} seconds' \
+                        THEN 1 \
+                        ELSE users_login_throttle.failures + 1 END, \
+                    last_failure_at = now() \
+                RETURNING \
+                    users_login_throttle.failures;"
+            );
+            let stmt = conn.prepare(&query).await.unwrap();
+            let failures: i32 =
+                match trace_db_query(&query, conn.query(&stmt, &[])).await {
+                    Ok(query_result) => match query_result.first() {
+                        Some(row) => row.try_get("failures").unwrap(),
+                        None => continue,
+                    },
+                    Err(e) => {
+                        error!(
+                            "{tracking_label} - \
+                        failed to record login failure {kind}={key} \
+                        with err='{e}'"
+                        );
+                        continue;
+                    }
+                };
+            LOGIN_THROTTLE_COUNTER_VEC
+                .with_label_values(&[kind, "failure"])
+                .inc();
+            let lock_seconds = self.get_lock_seconds(failures);
+            if lock_seconds > 0 {
+                warn!(
+                    "{tracking_label} - \
+                    locking login {kind}={key} for {lock_seconds}s \
+                    after {failures} failures"
+                );
+                let query = format!(
+                    "UPDATE \
+                        users_login_throttle \
+                    SET \
+                        locked_until = now() \
+                            + interval '{lock_seconds} seconds' \
+                    WHERE \
+                        users_login_throttle.kind = '{kind}' \
+                        AND \
+                        users_login_throttle.key = '{key}';"
+                );
+                let stmt = conn.prepare(&query).await.unwrap();
+                if let Err(e) =
+                    trace_db_query(&query, conn.query(&stmt, &[])).await
+                {
+                    error!(
+                        "{tracking_label} - \
+                        failed to lock login {kind}={key} with err='{e}'"
+                    );
+                }
+            }
+        }
+    }
+
+    /// reset_login_throttle
+    ///
+    /// Remove the failures and lock for a key
+    /// (after a successful login or an admin unlock)
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - caller logging label
+    /// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+    ///   an established db connection from the
+    ///   postgres client db threadpool
+    /// * `kind` - `&str` - `email` or `ip`
+    /// * `key` - `&str` - email or ip address
+    /// * `result` - `&str` - counter label (`reset` or `unlock`)
+    ///
+    /// # Returns
+    ///
+    /// Ok(removed: `bool`)
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`)
+    ///
+    pub async fn reset_login_throttle(
+        &self,
+        tracking_label: &str,
+        conn: &PooledConnection<
+            '_,
+            PostgresConnectionManager<MakeTlsConnector>,
+        >,
+        kind: &str,
+        key: &str,
+        result: &str,
+    ) -> Result<bool, String> {
+        let query = format!(
+            "DELETE FROM \
+                users_login_throttle \
+            WHERE \
+                users_login_throttle.kind = '{}' \
+                AND \
+                users_login_throttle.key = '{}' \
+            RETURNING \
+                users_login_throttle.failures;",
+            kind.replace('\'', "''"),
+            key.replace('\'', "''")
+        );
+        let stmt = conn.prepare(&query).await.unwrap();
+        match trace_db_query(&query, conn.query(&stmt, &[])).await {
+            Ok(query_result) => {
+                let removed = !query_result.is_empty();
+                if removed {
+                    LOGIN_THROTTLE_COUNTER_VEC
+                        .with_label_values(&[kind, result])
+                        .inc();
+                }
+                Ok(removed)
+            }
+            Err(e) => Err(format!(
+                "{tracking_label} - \
+                failed to reset login throttle {kind}={key} \
+                with err='{e}'"
+            )),
+        }
+    }
+}
@@ -18,21 +18,36 @@ use postgres_native_tls::MakeTlsConnector;
 use bb8::Pool;
 use bb8_postgres::PostgresConnectionManager;
 
+use hyper::header::HeaderValue;
 use hyper::Body;
+use hyper::HeaderMap;
 use hyper::Response;
 
 use serde::Deserialize;
 use serde::Serialize;
 
-use argon2::hash_encoded as argon_hash_encoded;
-use argon2::Config as argon_config;
-
-use kafka_threadpool::kafka_publisher::KafkaPublisher;
-
 use crate::core::core_config::CoreConfig;
-use crate::kafka::publish_msg::publish_msg;
+use crate::core::server::access_log::set_access_log_user_id;
+use crate::i18n::message_localization::set_response_user_locale;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::monitoring::otel::trace_db_query;
+use crate::notifications::security_notifications::send_new_login_notification;
 use crate::requests::auth::create_user_token::create_user_token;
-use crate::requests::user::is_verification_required::is_verification_required;
+use crate::requests::auth::login_risk::consume_login_otp;
+use crate::requests::auth::login_risk::start_login_challenge;
+use crate::requests::auth::login_risk::LoginRiskDecision;
+use crate::requests::auth::token_scopes::build_scope_claim;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::models::tenant::DEFAULT_TENANT_ID;
+use crate::requests::models::user_session::get_user_session_metadata;
+use crate::requests::validation::field_rules::add_field_error;
+use crate::requests::validation::field_rules::check_email;
+use crate::requests::validation::field_rules::check_length;
+use crate::requests::validation::field_rules::MAX_PASSWORD_LEN;
+use crate::requests::validation::normalize_email::normalize_email;
+use crate::requests::validation::validate_api_req::validate_api_req;
+use crate::requests::validation::validate_api_req::ApiFieldError;
+use crate::requests::validation::validate_api_req::ApiReqValidate;
 
 /// ApiReqUserLogin
 ///
@@ -55,11 +70,47 @@ use crate::requests::user::is_verification_required::is_verification_required;
 ///
 /// * `email` - `String` - unique user email
 /// * `password` - `String` - user password
+/// * `scopes` - `Vec<String>` - optional
+///   [`TOKEN_SCOPES`](crate::requests::auth::token_scopes::TOKEN_SCOPES)
+///   to restrict the new jwt to (empty = an unscoped token)
+/// * `otp` - `String` - optional one-time-use password
+///   emailed for a ``LOGIN_CHALLENGE_REQUIRED`` login (see
+///   [`login_risk`](crate::requests::auth::login_risk))
 ///
 #[derive(Serialize, Deserialize, Clone)]
 pub struct ApiReqUserLogin {
     pub email: String,
     pub password: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub scopes: Vec<String>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub otp: String,
+}
+
+impl ApiReqValidate for ApiReqUserLogin {
+    /// validate
+    ///
+    /// Require a valid `email`, a non-empty `password`,
+    /// supported `scopes` and an `otp` up to 256 characters
+    ///
+    fn validate(&self) -> Vec<ApiFieldError> {
+        let mut errors = Vec::new();
+        check_email(&mut errors, "email", &self.email);
+        check_length(
+            &mut errors,
+            "password",
+            &self.password,
+            1,
+            MAX_PASSWORD_LEN,
+        );
+        if let Err(err_msg) = build_scope_claim(&self.scopes) {
+            add_field_error(&mut errors, "scopes", &err_msg);
+        }
+        if !self.otp.is_empty() {
+            check_length(&mut errors, "otp", &self.otp, 1, 256);
+        }
+        errors
+    }
 }
 
 /// ApiResUserLogin
@@ -88,6 +139,12 @@ pub struct ApiReqUserLogin {
 /// * `role` - `String` - user role
 /// * `token` - `String` - encrypted jwt
 /// * `msg` - `String` - error message
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   machine-readable failure code (omitted on success)
+/// * `challenge` - `Vec<String>` - second factors started for
+///   a ``LOGIN_CHALLENGE_REQUIRED`` login (``otp`` and
+///   ``passkey``, see
+///   [`login_risk`](crate::requests::auth::login_risk))
 ///
 #[derive(Serialize, Deserialize, Clone)]
 pub struct ApiResUserLogin {
@@ -98,6 +155,10 @@ pub struct ApiResUserLogin {
     pub role: String,
     pub token: String,
     pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ApiErrorCode>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub challenge: Vec<String>,
 }
 
 /// login_user
@@ -107,6 +168,22 @@ pub struct ApiResUserLogin {
 /// Validates the user credentials with `argon2`
 /// and creates a new, encrypted jwt for the user.
 ///
+/// ## login_user throttles failed logins
+///
+/// Failed logins are counted for the email and the
+/// client ip by the
+/// [`LoginThrottle`](crate::requests::auth::login_throttle::LoginThrottle).
+/// Locked emails or ips get a `429` HTTP status code with
+/// a ``Retry-After`` header.
+///
+/// ## login_user blocks users that must reset their password
+///
+/// Users with a password reset forced by
+/// [`force_password_reset`](crate::requests::admin::force_password_reset::force_password_reset)
+/// get a `403` HTTP status code with the
+/// ``PASSWORD_RESET_REQUIRED`` error code until they change
+/// their password with a one-time-use token.
+///
 /// ## login_user restriction enforcing user must be active
 ///
 /// The db `users.state` field for the user must
@@ -119,8 +196,13 @@ pub struct ApiResUserLogin {
 /// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
 ///   db threadpool with required tls encryption
 /// * `kafka_pool` -
-///   [`KafkaPublisher`](kafka_threadpool::kafka_publisher::KafkaPublisher)
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
 ///   for asynchronously publishing messages to the connected kafka cluster
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+///   (``User-Agent`` and ``device`` are stored with the session)
+/// * `remote_addr` - `&std::net::SocketAddr` - client address
+///   stored with the session
 /// * `bytes` - `&[u8]` - bytes received from the hyper server
 ///
 /// # Returns
@@ -151,10 +233,12 @@ pub async fn login_user(
     config: &CoreConfig,
     db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
     kafka_pool: &KafkaPublisher,
+    headers: &HeaderMap<HeaderValue>,
+    remote_addr: &std::net::SocketAddr,
     bytes: &[u8],
 ) -> std::result::Result<Response<Body>, Infallible> {
     // deserialize into a type
-    let user_object: ApiReqUserLogin = match serde_json::from_slice(bytes) {
+    let mut user_object: ApiReqUserLogin = match serde_json::from_slice(bytes) {
         Ok(uo) => uo,
         Err(_) => {
             let response = Response::builder()
@@ -171,6 +255,53 @@ pub async fn login_user(
                             email and password \
                             were set correctly in the request")
                             .to_string(),
+                        error_code: Some(ApiErrorCode::InvalidRequest),
+                        challenge: Vec::new(),
+                    })
+                    .unwrap(),
+                ))
+                .unwrap();
+            return Ok(response);
+        }
+    };
+
+    user_object.email = normalize_email(&user_object.email);
+    if let Some(response) = validate_api_req(tracking_label, &user_object) {
+        return Ok(response);
+    }
+
+    let settings = config.get_settings();
+    let conn = db_pool.get().await.unwrap();
+    let session =
+        get_user_session_metadata(headers, remote_addr, &config.geo_ip);
+    let scope_claim =
+        build_scope_claim(&user_object.scopes).unwrap_or_default();
+    let tenant_id = match config
+        .tenants
+        .get_tenant_id(tracking_label, headers, &conn)
+        .await
+    {
+        Ok(tenant_id) => tenant_id,
+        Err(err_msg) => {
+            error!("{err_msg}");
+            config.auth_alerts.record_failure(
+                tracking_label,
+                "login",
+                "unknown_tenant",
+            );
+            let response = Response::builder()
+                .status(400)
+                .body(Body::from(
+                    serde_json::to_string(&ApiResUserLogin {
+                        user_id: -1,
+                        email: String::from(""),
+                        state: -1,
+                        verified: -1,
+                        role: String::from(""),
+                        token: String::from(""),
+                        msg: ("User login failed - unknown tenant").to_string(),
+                        error_code: Some(ApiErrorCode::UnknownTenant),
+                        challenge: Vec::new(),
                     })
                     .unwrap(),
                 ))
@@ -178,15 +309,53 @@ pub async fn login_user(
             return Ok(response);
         }
     };
+    // the same email can exist in more than one tenant
+    let throttle_email = match tenant_id {
+        DEFAULT_TENANT_ID => user_object.email.clone(),
+        _ => format!("{tenant_id}:{}", user_object.email),
+    };
+    if let Err(retry_after) = settings
+        .login_throttle
+        .check_login(
+            tracking_label,
+            &conn,
+            &throttle_email,
+            &session.ip_address,
+        )
+        .await
+    {
+        config
+            .auth_alerts
+            .record_failure(tracking_label, "login", "throttled");
+        let response = Response::builder()
+            .status(429)
+            .header("Retry-After", format!("{retry_after}"))
+            .body(Body::from(
+                serde_json::to_string(&ApiResUserLogin {
+                    user_id: -1,
+                    email: String::from(""),
+                    state: -1,
+                    verified: -1,
+                    role: String::from(""),
+                    token: String::from(""),
+                    msg: format!(
+                        "User login failed - too many failed logins \
+                        please retry in {retry_after} seconds"
+                    ),
+                    error_code: Some(ApiErrorCode::LoginLocked),
+                    challenge: Vec::new(),
+                })
+                .unwrap(),
+            ))
+            .unwrap();
+        return Ok(response);
+    }
 
     // salt the password
-    let argon_config = argon_config::default();
-    let hash = argon_hash_encoded(
-        user_object.password.as_bytes(),
-        &config.server_password_salt,
-        &argon_config,
-    )
-    .unwrap();
+    let hash = config
+        .password_hashing
+        .hash_password(&user_object.password, &config.server_password_salt)
+        .unwrap();
 
     // find all user by email and an active state where state == 0
     let query = format!(
@@ -196,19 +365,24 @@ pub async fn login_user(
             users.password, \
             users.state, \
             users.verified, \
-            users.role \
+            users.role, \
+            users.locale, \
+            users.password_reset_required \
         FROM \
             users \
         WHERE \
             users.email = '{}' \
+        AND \
+            users.tenant_id = {tenant_id} \
         AND \
             users.state = 0 \
         LIMIT 1;",
-        &user_object.email
+        user_object.email.replace('\'', "''")
     );
-    let conn = db_pool.get().await.unwrap();
     let stmt = conn.prepare(&query).await.unwrap();
-    let query_result = match conn.query(&stmt, &[]).await {
+    let query_result = match trace_db_query(&query, conn.query(&stmt, &[]))
+        .await
+    {
         Ok(query_result) => query_result,
         Err(e) => {
             let err_msg = format!("{e}");
@@ -224,7 +398,9 @@ pub async fn login_user(
                             role: String::from(""),
                             token: String::from(""),
                             msg: format!("User login failed for email={} with err='{err_msg}'",
-                                user_object.email)
+                                user_object.email),
+                            error_code: Some(ApiErrorCode::InternalError),
+                            challenge: Vec::new(),
                         }
                     ).unwrap()))
                 .unwrap();
@@ -233,12 +409,42 @@ pub async fn login_user(
     };
     let mut row_list: Vec<(i32, String, String, i32, i32, String)> =
         Vec::with_capacity(1);
+    let mut login_locale = String::from("");
     for row in query_result.iter() {
         let id: i32 = row.try_get("id").unwrap();
         let email: String = row.try_get("email").unwrap();
         let password: String = row.try_get("password").unwrap();
-        if password != hash {
-            // error!("{tracking_label} - BAD LOGIN:\n{password}\n!=\n{hash}");
+        let password_check = config.password_migration.check_password(
+            &user_object.password,
+            &hash,
+            &password,
+        );
+        if !password_check.matched {
+            settings
+                .login_throttle
+                .record_login_failure(
+                    tracking_label,
+                    &conn,
+                    &throttle_email,
+                    &session.ip_address,
+                )
+                .await;
+            config.auth_alerts.record_failure(
+                tracking_label,
+                "login",
+                "invalid_password",
+            );
+            config
+                .login_history
+                .record_login(
+                    tracking_label,
+                    &conn,
+                    id,
+                    "password",
+                    "invalid_password",
+                    &session,
+                )
+                .await;
             let response = Response::builder()
                 .status(400)
                 .body(Body::from(
@@ -250,23 +456,90 @@ pub async fn login_user(
                         role: String::from(""),
                         token: String::from(""),
                         msg: "User login failed - invalid password".to_string(),
+                        error_code: Some(ApiErrorCode::InvalidCredentials),
+                        challenge: Vec::new(),
                     })
                     .unwrap(),
                 ))
                 .unwrap();
             return Ok(response);
         }
+        // an admin forced a password reset so the password
+        // must be changed with a one-time-use token first
+        let password_reset_required: i32 =
+            row.try_get("password_reset_required").unwrap();
+        if password_reset_required == 1 {
+            warn!(
+                "{tracking_label} - \
+                User login rejected - user {id} must reset their password"
+            );
+            config
+                .login_history
+                .record_login(
+                    tracking_label,
+                    &conn,
+                    id,
+                    "password",
+                    "reset_required",
+                    &session,
+                )
+                .await;
+            let response = Response::builder()
+                .status(403)
+                .body(Body::from(
+                    serde_json::to_string(&ApiResUserLogin {
+                        user_id: -1,
+                        email: String::from(""),
+                        state: -1,
+                        verified: -1,
+                        role: String::from(""),
+                        token: String::from(""),
+                        msg: "User login failed - a password reset is \
+                            required"
+                            .to_string(),
+                        error_code: Some(ApiErrorCode::PasswordResetRequired),
+                        challenge: Vec::new(),
+                    })
+                    .unwrap(),
+                ))
+                .unwrap();
+            return Ok(response);
+        }
+        // rehash passwords stored with a legacy scheme
+        config
+            .password_migration
+            .migrate_password(tracking_label, &conn, id, &password_check, &hash)
+            .await;
         let user_state: i32 = row.try_get("state").unwrap();
         let user_verified: i32 = row.try_get("verified").unwrap();
+        let user_locale: String = row.try_get("locale").unwrap();
+        set_response_user_locale(&user_locale);
+        login_locale = user_locale;
 
         // if user verification is enabled and the user
         // has not verified - reject the auth
-        if is_verification_required() && user_verified != 1 {
+        if settings.verification_required && user_verified != 1 {
             let err_msg = format!(
                 "User login rejected - the email address: {email} \
                 is not verified"
             );
             error!("{tracking_label} - {err_msg}");
+            config.auth_alerts.record_failure(
+                tracking_label,
+                "login",
+                "unverified",
+            );
+            config
+                .login_history
+                .record_login(
+                    tracking_label,
+                    &conn,
+                    id,
+                    "password",
+                    "unverified",
+                    &session,
+                )
+                .await;
             let response = Response::builder()
                 .status(401)
                 .body(Body::from(
@@ -278,6 +551,8 @@ pub async fn login_user(
                         role: String::from(""),
                         token: String::from(""),
                         msg: err_msg,
+                        error_code: Some(ApiErrorCode::UserNotVerified),
+                        challenge: Vec::new(),
                     })
                     .unwrap(),
                 ))
@@ -289,6 +564,20 @@ pub async fn login_user(
         row_list.push((id, email, password, user_state, user_verified, role))
     }
     if row_list.is_empty() {
+        settings
+            .login_throttle
+            .record_login_failure(
+                tracking_label,
+                &conn,
+                &throttle_email,
+                &session.ip_address,
+            )
+            .await;
+        config.auth_alerts.record_failure(
+            tracking_label,
+            "login",
+            "unknown_user",
+        );
         let response = Response::builder()
             .status(400)
             .body(Body::from(
@@ -303,6 +592,8 @@ pub async fn login_user(
                         "User login failed - user does not exist with email={}",
                         user_object.email
                     ),
+                    error_code: Some(ApiErrorCode::UserNotFound),
+                    challenge: Vec::new(),
                 })
                 .unwrap(),
             ))
@@ -311,12 +602,161 @@ pub async fn login_user(
     } else {
         let user_id = row_list[0].0;
         let user_email = row_list[0].1.to_string();
+
+        // a challenge's one-time-password is the second
+        // factor so the login is not scored again
+        if !user_object.otp.is_empty() {
+            if let Err(error_code) = consume_login_otp(
+                tracking_label,
+                config,
+                &conn,
+                user_id,
+                &user_email,
+                &user_object.otp,
+            )
+            .await
+            {
+                config
+                    .login_history
+                    .record_login(
+                        tracking_label,
+                        &conn,
+                        user_id,
+                        "password",
+                        "invalid_otp",
+                        &session,
+                    )
+                    .await;
+                let response = Response::builder()
+                    .status(401)
+                    .body(Body::from(
+                        serde_json::to_string(&ApiResUserLogin {
+                            user_id: -1,
+                            email: String::from(""),
+                            state: -1,
+                            verified: -1,
+                            role: String::from(""),
+                            token: String::from(""),
+                            msg: "User login failed - \
+                                invalid one-time-password"
+                                .to_string(),
+                            error_code: Some(error_code),
+                            challenge: Vec::new(),
+                        })
+                        .unwrap(),
+                    ))
+                    .unwrap();
+                return Ok(response);
+            }
+        } else if config.login_risk.is_enabled() {
+            let decision = config
+                .login_risk
+                .score_login(
+                    tracking_label,
+                    &conn,
+                    user_id,
+                    &user_email,
+                    &row_list[0].5,
+                    &session,
+                )
+                .await;
+            let challenge = match decision {
+                LoginRiskDecision::Allow => Vec::new(),
+                LoginRiskDecision::Challenge => {
+                    start_login_challenge(
+                        tracking_label,
+                        config,
+                        kafka_pool,
+                        &conn,
+                        user_id,
+                        &user_email,
+                    )
+                    .await
+                }
+                LoginRiskDecision::Deny => Vec::new(),
+            };
+            if decision == LoginRiskDecision::Challenge && !challenge.is_empty()
+            {
+                config
+                    .login_history
+                    .record_login(
+                        tracking_label,
+                        &conn,
+                        user_id,
+                        "password",
+                        "challenged",
+                        &session,
+                    )
+                    .await;
+                let response = Response::builder()
+                    .status(401)
+                    .body(Body::from(
+                        serde_json::to_string(&ApiResUserLogin {
+                            user_id: -1,
+                            email: String::from(""),
+                            state: -1,
+                            verified: -1,
+                            role: String::from(""),
+                            token: String::from(""),
+                            msg: "User login requires a second factor"
+                                .to_string(),
+                            error_code: Some(
+                                ApiErrorCode::LoginChallengeRequired,
+                            ),
+                            challenge,
+                        })
+                        .unwrap(),
+                    ))
+                    .unwrap();
+                return Ok(response);
+            }
+            // a challenge without a second factor is denied
+            if decision != LoginRiskDecision::Allow {
+                config.auth_alerts.record_failure(
+                    tracking_label,
+                    "login",
+                    "risk_denied",
+                );
+                config
+                    .login_history
+                    .record_login(
+                        tracking_label,
+                        &conn,
+                        user_id,
+                        "password",
+                        "denied",
+                        &session,
+                    )
+                    .await;
+                let response = Response::builder()
+                    .status(403)
+                    .body(Body::from(
+                        serde_json::to_string(&ApiResUserLogin {
+                            user_id: -1,
+                            email: String::from(""),
+                            state: -1,
+                            verified: -1,
+                            role: String::from(""),
+                            token: String::from(""),
+                            msg: "User login denied".to_string(),
+                            error_code: Some(ApiErrorCode::LoginDenied),
+                            challenge: Vec::new(),
+                        })
+                        .unwrap(),
+                    ))
+                    .unwrap();
+                return Ok(response);
+            }
+        }
+
         let user_token = match create_user_token(
             tracking_label,
             config,
             &conn,
             &user_email,
             user_id,
+            &session,
+            &scope_claim,
         )
         .await
         {
@@ -334,7 +774,9 @@ pub async fn login_user(
                                 role: String::from(""),
                                 token: String::from(""),
                                 msg: format!("User login failed - unable to create user token for user_id={user_id} email={}",
-                                    user_object.email)
+                                    user_object.email),
+                                error_code: Some(ApiErrorCode::InternalError),
+                                challenge: Vec::new(),
                             }
                         ).unwrap()))
                     .unwrap();
@@ -342,24 +784,56 @@ pub async fn login_user(
             }
         };
 
-        // if enabled, publish to kafka
-        if config.kafka_publish_events {
-            publish_msg(
-                kafka_pool,
-                // topic
-                "user.events",
-                // partition key
-                &format!("user-{}", user_id),
-                // optional headers stored in: Option<HashMap<String, String>>
-                None,
-                // payload in the message
-                &format!("LOGIN user={user_id} email={user_email}"),
+        // a successful login clears the email's failures
+        // but not the ip's (shared ips can still be stuffing)
+        if settings.login_throttle.enabled {
+            if let Err(err_msg) = settings
+                .login_throttle
+                .reset_login_throttle(
+                    tracking_label,
+                    &conn,
+                    "email",
+                    &throttle_email,
+                    "reset",
+                )
+                .await
+            {
+                error!("{err_msg}");
+            }
+        }
+
+        config
+            .login_history
+            .record_login(
+                tracking_label,
+                &conn,
+                user_id,
+                "password",
+                "success",
+                &session,
             )
             .await;
-        }
+        config
+            .events
+            .user_logged_in(kafka_pool, user_id, &user_email, "LOGIN", &session)
+            .await;
+        send_new_login_notification(
+            tracking_label,
+            config,
+            kafka_pool,
+            &conn,
+            user_id,
+            &user_email,
+            &login_locale,
+            &session,
+            &user_token,
+        )
+        .await;
+        set_access_log_user_id(user_id);
 
-        let response = Response::builder()
-            .status(201)
+        let response = config
+            .token_cookie
+            .set_token_cookie(Response::builder().status(201), &user_token)
             .body(Body::from(
                 serde_json::to_string(&ApiResUserLogin {
                     user_id,
@@ -367,8 +841,10 @@ pub async fn login_user(
                     state: row_list[0].3,
                     verified: row_list[0].4,
                     role: row_list[0].5.to_string(),
-                    token: user_token,
+                    token: config.token_cookie.get_response_token(user_token),
                     msg: "success".to_string(),
+                    error_code: None,
+                    challenge: Vec::new(),
                 })
                 .unwrap(),
             ))
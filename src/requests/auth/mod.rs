@@ -1,5 +1,13 @@
 //! Supported auth modules
 //!
 pub mod create_user_token;
+pub mod get_csrf_token;
+pub mod get_jwks;
+pub mod login_history;
+pub mod login_risk;
+pub mod login_throttle;
 pub mod login_user;
+pub mod token_cookie;
+pub mod token_scopes;
 pub mod validate_user_token;
+pub mod webauthn;
@@ -0,0 +1,504 @@
+//! Return the login jwt in a ``Secure``, ``HttpOnly``
+//! cookie for browser apps
+//!
+//! With ``TOKEN_COOKIE_MODE=both`` or ``cookie`` the
+//! login, create user, accept invite and passkey login
+//! responses set the jwt in a cookie that scripts cannot
+//! read. ``cookie`` also removes the ``token`` from the
+//! json response. Requests are authenticated with the
+//! ``TOKEN_HEADER`` header first and then the cookie (see
+//! [`get_request_token`](crate::requests::auth::token_cookie::TokenCookie::get_request_token)).
+//!
+//! Browsers send cookies with every request to the api,
+//! so cookie-authenticated ``POST``, ``PUT``, ``PATCH`` and
+//! ``DELETE`` requests must also send the csrf cookie's
+//! value in the ``TOKEN_COOKIE_CSRF_HEADER`` header
+//! (double-submit cookie, see
+//! [`check_csrf`](crate::requests::auth::token_cookie::TokenCookie::check_csrf)).
+//! Logins set the csrf cookie next to the jwt cookie and
+//! ``GET /csrf`` returns the current csrf token. Requests
+//! with the ``TOKEN_HEADER`` header are not checked because
+//! other sites cannot set it.
+//!
+use hyper::header::HeaderValue;
+use hyper::header::COOKIE;
+use hyper::header::SET_COOKIE;
+use hyper::http::response::Builder;
+use hyper::HeaderMap;
+use hyper::Method;
+
+use crate::jwt::api::get_token_expiration_in_seconds;
+use crate::utils::get_uuid::get_uuid;
+
+/// supported ``TOKEN_COOKIE_MODE`` values
+pub const TOKEN_COOKIE_MODES: [&str; 3] = ["off", "both", "cookie"];
+
+/// supported ``TOKEN_COOKIE_SAME_SITE`` values
+pub const TOKEN_COOKIE_SAME_SITE_VALUES: [&str; 3] = ["Strict", "Lax", "None"];
+
+/// TokenCookie
+///
+/// Settings for returning and accepting the jwt in a
+/// cookie
+///
+/// # Supported Environment Variables
+///
+/// ```bash
+/// # off, both (json token and cookie) or cookie (cookie only)
+/// export TOKEN_COOKIE_MODE="off"
+/// export TOKEN_COOKIE_NAME="restapi_token"
+/// # Strict, Lax or None
+/// export TOKEN_COOKIE_SAME_SITE="Strict"
+/// # empty = only the api's host
+/// export TOKEN_COOKIE_DOMAIN=""
+/// export TOKEN_COOKIE_PATH="/"
+/// # double-submit csrf cookie and header
+/// export TOKEN_COOKIE_CSRF_NAME="restapi_csrf"
+/// export TOKEN_COOKIE_CSRF_HEADER="X-CSRF-Token"
+/// ```
+///
+/// # Arguments
+///
+/// * `mode` - `String` - `off`, `both` or `cookie`
+/// * `name` - `String` - cookie name
+/// * `same_site` - `String` - cookie ``SameSite`` attribute
+/// * `domain` - `String` - cookie ``Domain`` attribute
+///   (empty = not set)
+/// * `path` - `String` - cookie ``Path`` attribute
+/// * `max_age_seconds` - `usize` - cookie ``Max-Age``
+///   (``TOKEN_EXPIRATION_SECONDS_INTO_FUTURE``)
+/// * `csrf_name` - `String` - csrf cookie name (readable
+///   by scripts)
+/// * `csrf_header` - `String` - header with the csrf
+///   cookie's value
+///
+#[derive(Clone, Debug, Default)]
+pub struct TokenCookie {
+    pub mode: String,
+    pub name: String,
+    pub same_site: String,
+    pub domain: String,
+    pub path: String,
+    pub max_age_seconds: usize,
+    pub csrf_name: String,
+    pub csrf_header: String,
+}
+
+impl TokenCookie {
+    /// build_token_cookie
+    ///
+    /// Build a
+    /// [`TokenCookie`](crate::requests::auth::token_cookie::TokenCookie)
+    /// from environment variables
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) for an unsupported mode or
+    /// ``SameSite`` value, or a name, domain or path that
+    /// is not valid in a cookie
+    ///
+    pub fn build_token_cookie() -> Result<Self, String> {
+        let mode = std::env::var("TOKEN_COOKIE_MODE")
+            .unwrap_or_else(|_| "off".to_string())
+            .to_lowercase();
+        if !TOKEN_COOKIE_MODES.contains(&mode.as_str()) {
+            return Err(format!(
+                "invalid TOKEN_COOKIE_MODE={mode} must be one of: {}",
+                TOKEN_COOKIE_MODES.join(", ")
+            ));
+        }
+        let get_name = |key: &str, default: &str| -> Result<String, String> {
+            let name =
+                std::env::var(key).unwrap_or_else(|_| default.to_string());
+            match !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+            {
+                true => Ok(name),
+                false => Err(format!(
+                    "invalid {key}={name} must only use \
+                    letters, numbers, '-', '_' and '.'"
+                )),
+            }
+        };
+        let name = get_name("TOKEN_COOKIE_NAME", "restapi_token")?;
+        let csrf_name = get_name("TOKEN_COOKIE_CSRF_NAME", "restapi_csrf")?;
+        if csrf_name == name {
+            return Err(format!(
+                "invalid TOKEN_COOKIE_CSRF_NAME={csrf_name} must not be \
+                the TOKEN_COOKIE_NAME"
+            ));
+        }
+        let csrf_header = get_name("TOKEN_COOKIE_CSRF_HEADER", "X-CSRF-Token")?;
+        let same_site = std::env::var("TOKEN_COOKIE_SAME_SITE")
+            .unwrap_or_else(|_| "Strict".to_string());
+        let same_site = match TOKEN_COOKIE_SAME_SITE_VALUES
+            .iter()
+            .find(|v| v.eq_ignore_ascii_case(&same_site))
+        {
+            Some(v) => v.to_string(),
+            None => {
+                return Err(format!(
+                    "invalid TOKEN_COOKIE_SAME_SITE={same_site} \
+                    must be one of: {}",
+                    TOKEN_COOKIE_SAME_SITE_VALUES.join(", ")
+                ));
+            }
+        };
+        let domain = std::env::var("TOKEN_COOKIE_DOMAIN").unwrap_or_default();
+        if !domain
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-.".contains(c))
+        {
+            return Err(format!(
+                "invalid TOKEN_COOKIE_DOMAIN={domain} must be a host name"
+            ));
+        }
+        let path = std::env::var("TOKEN_COOKIE_PATH")
+            .unwrap_or_else(|_| "/".to_string());
+        if !path.starts_with('/')
+            || path.chars().any(|c| c == ';' || c.is_ascii_control())
+        {
+            return Err(format!(
+                "invalid TOKEN_COOKIE_PATH={path} must start with '/' \
+                and not contain ';'"
+            ));
+        }
+        Ok(TokenCookie {
+            mode,
+            name,
+            same_site,
+            domain,
+            path,
+            max_age_seconds: get_token_expiration_in_seconds(),
+            csrf_name,
+            csrf_header,
+        })
+    }
+
+    /// is_enabled
+    ///
+    /// Are logins setting the jwt cookie
+    ///
+    pub fn is_enabled(&self) -> bool {
+        self.mode == "both" || self.mode == "cookie"
+    }
+
+    /// get_response_token
+    ///
+    /// Get the ``token`` for a login's json response
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - `String` - new jwt
+    ///
+    /// # Returns
+    ///
+    /// `String` - empty in ``cookie`` mode so scripts never
+    /// see the jwt
+    ///
+    pub fn get_response_token(&self, token: String) -> String {
+        match self.mode.as_str() {
+            "cookie" => "".to_string(),
+            _ => token,
+        }
+    }
+
+    /// get_set_cookie
+    ///
+    /// Build the ``Set-Cookie`` header value for a jwt
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - `&str` - new jwt
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use restapi::requests::auth::token_cookie::TokenCookie;
+    /// let token_cookie = TokenCookie {
+    ///     mode: "both".to_string(),
+    ///     name: "restapi_token".to_string(),
+    ///     same_site: "Strict".to_string(),
+    ///     domain: "".to_string(),
+    ///     path: "/".to_string(),
+    ///     max_age_seconds: 60,
+    ///     ..TokenCookie::default()
+    /// };
+    /// assert_eq!(
+    ///     token_cookie.get_set_cookie("abc"),
+    ///     "restapi_token=abc; Max-Age=60; Path=/; Secure; HttpOnly; SameSite=Strict"
+    /// );
+    /// ```
+    ///
+    pub fn get_set_cookie(&self, token: &str) -> String {
+        self.build_set_cookie(&self.name, token, true)
+    }
+
+    /// get_csrf_set_cookie
+    ///
+    /// Build the ``Set-Cookie`` header value for a csrf
+    /// token (without ``HttpOnly`` so the app's scripts can
+    /// copy it into the ``csrf_header``)
+    ///
+    /// # Arguments
+    ///
+    /// * `csrf_token` - `&str` - csrf token
+    ///
+    pub fn get_csrf_set_cookie(&self, csrf_token: &str) -> String {
+        self.build_set_cookie(&self.csrf_name, csrf_token, false)
+    }
+
+    /// build_set_cookie
+    ///
+    /// Build a ``Set-Cookie`` header value with the
+    /// configured attributes
+    ///
+    fn build_set_cookie(
+        &self,
+        name: &str,
+        value: &str,
+        http_only: bool,
+    ) -> String {
+        let mut set_cookie = format!(
+            "{name}={value}; Max-Age={}; Path={}; Secure",
+            self.max_age_seconds, self.path
+        );
+        if http_only {
+            set_cookie.push_str("; HttpOnly");
+        }
+        set_cookie.push_str(&format!("; SameSite={}", self.same_site));
+        if !self.domain.is_empty() {
+            set_cookie.push_str(&format!("; Domain={}", self.domain));
+        }
+        set_cookie
+    }
+
+    /// set_token_cookie
+    ///
+    /// Add the jwt and csrf ``Set-Cookie`` headers to a
+    /// login response (does nothing when the mode is ``off``)
+    ///
+    /// # Arguments
+    ///
+    /// * `builder` - [`Builder`](hyper::http::response::Builder) -
+    ///   login response
+    /// * `token` - `&str` - new jwt
+    ///
+    pub fn set_token_cookie(&self, builder: Builder, token: &str) -> Builder {
+        match self.is_enabled() {
+            true => builder
+                .header(SET_COOKIE, self.get_set_cookie(token))
+                .header(
+                    SET_COOKIE,
+                    self.get_csrf_set_cookie(&self.create_csrf_token()),
+                ),
+            false => builder,
+        }
+    }
+
+    /// get_request_token
+    ///
+    /// Get the client's jwt from the header token key
+    /// (controlled by env var ``TOKEN_HEADER=Bearer`` as the
+    /// default) or, when the mode is not ``off``, the jwt
+    /// cookie
+    ///
+    /// # Arguments
+    ///
+    /// * `headers` - [`HeaderMap`](hyper::HeaderMap) - HTTP
+    ///   headers as a map with the jwt
+    ///
+    /// # Returns
+    ///
+    /// `String` - empty when the request has no jwt
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use hyper::HeaderMap;
+    /// use restapi::requests::auth::token_cookie::TokenCookie;
+    /// let token_cookie = TokenCookie {
+    ///     mode: "cookie".to_string(),
+    ///     name: "restapi_token".to_string(),
+    ///     ..TokenCookie::default()
+    /// };
+    /// let mut headers = HeaderMap::new();
+    /// headers.insert("Cookie", "theme=dark; restapi_token=abc".parse().unwrap());
+    /// assert_eq!(token_cookie.get_request_token(&headers), "abc");
+    /// assert_eq!(TokenCookie::default().get_request_token(&headers), "");
+    /// ```
+    ///
+    pub fn get_request_token(
+        &self,
+        headers: &HeaderMap<HeaderValue>,
+    ) -> String {
+        let token_header_key = std::env::var("TOKEN_HEADER")
+            .unwrap_or_else(|_| "Bearer".to_string());
+        if let Some(token) = headers.get(&token_header_key) {
+            return token.to_str().unwrap_or("").to_string();
+        }
+        if !self.is_enabled() {
+            return "".to_string();
+        }
+        get_request_cookie(headers, &self.name).unwrap_or_default()
+    }
+
+    /// create_csrf_token
+    ///
+    /// Create a new random csrf token
+    ///
+    pub fn create_csrf_token(&self) -> String {
+        format!("{}{}", get_uuid(), get_uuid())
+    }
+
+    /// get_csrf_token
+    ///
+    /// Get the request's csrf cookie value (so every tab
+    /// keeps using the same token) or create a new one
+    ///
+    /// # Arguments
+    ///
+    /// * `headers` - [`HeaderMap`](hyper::HeaderMap) - HTTP
+    ///   headers as a map with the cookies
+    ///
+    /// # Returns
+    ///
+    /// `(csrf_token: String, is_new: bool)`
+    ///
+    pub fn get_csrf_token(
+        &self,
+        headers: &HeaderMap<HeaderValue>,
+    ) -> (String, bool) {
+        match get_request_cookie(headers, &self.csrf_name) {
+            Some(csrf_token) if !csrf_token.is_empty() => (csrf_token, false),
+            _ => (self.create_csrf_token(), true),
+        }
+    }
+
+    /// check_csrf
+    ///
+    /// Reject cookie-authenticated ``POST``, ``PUT``,
+    /// ``PATCH`` and ``DELETE`` requests unless the
+    /// ``csrf_header`` matches the csrf cookie. Requests
+    /// with the ``TOKEN_HEADER`` header or without the jwt
+    /// cookie are not checked.
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - [`Method`](hyper::Method) - request method
+    /// * `headers` - [`HeaderMap`](hyper::HeaderMap) - HTTP
+    ///   headers as a map with the cookies
+    ///
+    /// # Errors
+    ///
+    /// Err(reason: `String`) when the csrf header is missing
+    /// or does not match the csrf cookie
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use hyper::HeaderMap;
+    /// use hyper::Method;
+    /// use restapi::requests::auth::token_cookie::TokenCookie;
+    /// let token_cookie = TokenCookie {
+    ///     mode: "cookie".to_string(),
+    ///     name: "restapi_token".to_string(),
+    ///     csrf_name: "restapi_csrf".to_string(),
+    ///     csrf_header: "X-CSRF-Token".to_string(),
+    ///     ..TokenCookie::default()
+    /// };
+    /// let mut headers = HeaderMap::new();
+    /// headers.insert("Cookie", "restapi_token=abc; restapi_csrf=xyz".parse().unwrap());
+    /// assert!(token_cookie.check_csrf(&Method::GET, &headers).is_ok());
+    /// assert!(token_cookie.check_csrf(&Method::POST, &headers).is_err());
+    /// headers.insert("X-CSRF-Token", "xyz".parse().unwrap());
+    /// assert!(token_cookie.check_csrf(&Method::POST, &headers).is_ok());
+    /// headers.insert("X-CSRF-Token", "xyy".parse().unwrap());
+    /// assert!(token_cookie.check_csrf(&Method::DELETE, &headers).is_err());
+    /// ```
+    ///
+    pub fn check_csrf(
+        &self,
+        method: &Method,
+        headers: &HeaderMap<HeaderValue>,
+    ) -> Result<(), String> {
+        if !self.is_enabled()
+            || !matches!(
+                *method,
+                Method::POST | Method::PUT | Method::PATCH | Method::DELETE
+            )
+        {
+            return Ok(());
+        }
+        let token_header_key = std::env::var("TOKEN_HEADER")
+            .unwrap_or_else(|_| "Bearer".to_string());
+        if headers.contains_key(&token_header_key)
+            || get_request_cookie(headers, &self.name).is_none()
+        {
+            return Ok(());
+        }
+        let csrf_cookie =
+            get_request_cookie(headers, &self.csrf_name).unwrap_or_default();
+        let csrf_header = headers
+            .get(&self.csrf_header)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("");
+        // constant time compare so the response time does
+        // not leak how much of the token matched
+        match !csrf_cookie.is_empty()
+            && csrf_cookie.len() == csrf_header.len()
+            && openssl::memcmp::eq(
+                csrf_cookie.as_bytes(),
+                csrf_header.as_bytes(),
+            ) {
+            true => Ok(()),
+            false => Err(format!(
+                "csrf check failed - cookie-authenticated {method} \
+                requests must set the {} header to the {} cookie \
+                from GET /csrf",
+                self.csrf_header, self.csrf_name
+            )),
+        }
+    }
+}
+
+/// get_request_cookie
+///
+/// Get a cookie's value from the ``Cookie`` headers
+///
+/// # Arguments
+///
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) - HTTP
+///   headers as a map with the cookies
+/// * `name` - `&str` - cookie name
+///
+/// # Returns
+///
+/// `Option<String>` - `None` when the cookie is not set
+///
+/// # Examples
+///
+/// ```rust
+/// use hyper::HeaderMap;
+/// use restapi::requests::auth::token_cookie::get_request_cookie;
+/// let mut headers = HeaderMap::new();
+/// headers.insert("Cookie", "a=1; b=2".parse().unwrap());
+/// assert_eq!(get_request_cookie(&headers, "b"), Some("2".to_string()));
+/// assert_eq!(get_request_cookie(&headers, "c"), None);
+/// ```
+///
+pub fn get_request_cookie(
+    headers: &HeaderMap<HeaderValue>,
+    name: &str,
+) -> Option<String> {
+    headers
+        .get_all(COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .filter_map(|cookie| cookie.trim().split_once('='))
+        .find(|(cookie_name, _)| *cookie_name == name)
+        .map(|(_, value)| value.to_string())
+}
@@ -0,0 +1,186 @@
+//! Restrict a jwt to the routes a machine integration needs
+//!
+//! A login with ``scopes`` (like ``["data:read"]``) creates
+//! a jwt with a space-separated ``scope`` claim. Tokens
+//! without a ``scope`` claim can use every route.
+//!
+//! Each request needs the scope for its route (see
+//! [`get_route_scope`](crate::requests::auth::token_scopes::get_route_scope)),
+//! and
+//! [`validate_user_token`](crate::requests::auth::validate_user_token::validate_user_token)
+//! rejects scoped tokens without it with
+//! ``INSUFFICIENT_SCOPE``. A ``write`` scope also allows
+//! the matching ``read`` scope. The ``admin`` scope does not
+//! make a user an admin, it only lets an admin's token use
+//! the ``/admin/*`` routes.
+//!
+use std::future::Future;
+
+use hyper::Method;
+
+/// supported token scopes
+pub const TOKEN_SCOPES: [&str; 5] = [
+    "user:read",
+    "user:write",
+    "data:read",
+    "data:write",
+    "admin",
+];
+
+tokio::task_local! {
+    /// scope the current request's route needs
+    static REQUIRED_TOKEN_SCOPE: &'static str;
+}
+
+/// get_route_scope
+///
+/// Map a request to the token scope it needs. ``/graphql``
+/// needs no route scope because each GraphQL resolver
+/// checks the scope for the model it reads or changes (see
+/// [`GraphqlContext::check_scope`](crate::requests::graphql::graphql_schema::GraphqlContext::check_scope)).
+///
+/// # Arguments
+///
+/// * `method` - [`Method`](hyper::Method) - request method
+/// * `request_uri` - `&str` - unprefixed url path
+///
+/// # Examples
+///
+/// ```rust
+/// use hyper::Method;
+/// use restapi::requests::auth::token_scopes::get_route_scope;
+/// assert_eq!(get_route_scope(&Method::GET, "/user/1"), "user:read");
+/// assert_eq!(get_route_scope(&Method::PUT, "/user"), "user:write");
+/// assert_eq!(get_route_scope(&Method::POST, "/user/data/search"), "data:read");
+/// assert_eq!(get_route_scope(&Method::POST, "/user/data"), "data:write");
+/// assert_eq!(get_route_scope(&Method::GET, "/admin/stats"), "admin");
+/// assert_eq!(get_route_scope(&Method::POST, "/graphql"), "");
+/// ```
+///
+pub fn get_route_scope(method: &Method, request_uri: &str) -> &'static str {
+    let is_read = matches!(*method, Method::GET | Method::HEAD)
+        || request_uri == "/user/search"
+        || request_uri == "/user/data/search";
+    match (request_uri, is_read) {
+        _ if request_uri == "/graphql" => "",
+        _ if request_uri.starts_with("/admin/") => "admin",
+        _ if request_uri.starts_with("/user/data") => match is_read {
+            true => "data:read",
+            false => "data:write",
+        },
+        (_, true) => "user:read",
+        (_, false) => "user:write",
+    }
+}
+
+/// build_scope_claim
+///
+/// Build the ``scope`` claim for a login's ``scopes``
+///
+/// # Arguments
+///
+/// * `scopes` - `&[String]` - requested scopes (empty =
+///   an unscoped token)
+///
+/// # Returns
+///
+/// `String` - sorted, space-separated scopes
+///
+/// # Errors
+///
+/// Err(err_msg: `String`) for a scope that is not one of the
+/// [`TOKEN_SCOPES`](crate::requests::auth::token_scopes::TOKEN_SCOPES)
+///
+/// # Examples
+///
+/// ```rust
+/// use restapi::requests::auth::token_scopes::build_scope_claim;
+/// let scopes = vec!["user:read".to_string(), "data:read".to_string()];
+/// assert_eq!(build_scope_claim(&scopes).unwrap(), "data:read user:read");
+/// assert_eq!(build_scope_claim(&[]).unwrap(), "");
+/// assert!(build_scope_claim(&["data:delete".to_string()]).is_err());
+/// ```
+///
+pub fn build_scope_claim(scopes: &[String]) -> Result<String, String> {
+    let mut scope_claim: Vec<&str> = Vec::new();
+    for scope in scopes.iter() {
+        if !TOKEN_SCOPES.contains(&scope.as_str()) {
+            return Err(format!(
+                "invalid scope={scope} must be one of: {}",
+                TOKEN_SCOPES.join(", ")
+            ));
+        }
+        scope_claim.push(scope);
+    }
+    scope_claim.sort();
+    scope_claim.dedup();
+    Ok(scope_claim.join(" "))
+}
+
+/// is_scope_allowed
+///
+/// Check a token's ``scope`` claim allows a route's scope
+///
+/// # Arguments
+///
+/// * `scope_claim` - `&str` - space-separated token scopes
+///   (empty = an unscoped token)
+/// * `required_scope` - `&str` - route scope from
+///   [`get_route_scope`](crate::requests::auth::token_scopes::get_route_scope)
+///   (empty = no scope needed)
+///
+/// # Examples
+///
+/// ```rust
+/// use restapi::requests::auth::token_scopes::is_scope_allowed;
+/// assert!(is_scope_allowed("", "admin"));
+/// assert!(is_scope_allowed("data:read", "data:read"));
+/// assert!(is_scope_allowed("data:write", "data:read"));
+/// assert!(!is_scope_allowed("data:read", "data:write"));
+/// assert!(!is_scope_allowed("data:read user:read", "user:write"));
+/// ```
+///
+pub fn is_scope_allowed(scope_claim: &str, required_scope: &str) -> bool {
+    if scope_claim.is_empty() || required_scope.is_empty() {
+        return true;
+    }
+    let write_scope = required_scope.replace(":read", ":write");
+    scope_claim
+        .split(' ')
+        .any(|scope| scope == required_scope || scope == write_scope)
+}
+
+/// scope_request_token_scope
+///
+/// Serve a request with the scope its route needs for
+/// [`get_required_token_scope`](crate::requests::auth::token_scopes::get_required_token_scope)
+///
+/// # Arguments
+///
+/// * `required_scope` - `&'static str` - route scope from
+///   [`get_route_scope`](crate::requests::auth::token_scopes::get_route_scope)
+/// * `serve_request` - future that builds the response
+///
+pub async fn scope_request_token_scope<F>(
+    required_scope: &'static str,
+    serve_request: F,
+) -> F::Output
+where
+    F: Future,
+{
+    REQUIRED_TOKEN_SCOPE
+        .scope(required_scope, serve_request)
+        .await
+}
+
+/// get_required_token_scope
+///
+/// Get the scope the current request's route needs
+///
+/// # Returns
+///
+/// `&'static str` - empty outside of a routed request
+///
+pub fn get_required_token_scope() -> &'static str {
+    REQUIRED_TOKEN_SCOPE.try_with(|scope| *scope).unwrap_or("")
+}
@@ -9,13 +9,21 @@ use hyper::header::HeaderValue;
 use hyper::HeaderMap;
 
 use crate::core::core_config::CoreConfig;
+use crate::core::server::access_log::set_access_log_user_id;
+use crate::i18n::message_localization::set_response_user_locale;
 use crate::jwt::api as jwt_api;
-use crate::requests::models::user::get_user_by_id;
+use crate::jwt::api::TOKEN_EXPIRED_ERR;
+use crate::requests::auth::token_scopes::get_required_token_scope;
+use crate::requests::auth::token_scopes::is_scope_allowed;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::models::user_session::touch_user_session;
 
 /// validate_user_token
 ///
 /// Confirm the client's jwt from the header token key
 /// (controlled by env var `TOKEN_HEADER=Bearer` as the default)
+/// or the
+/// [`TokenCookie`](crate::requests::auth::token_cookie::TokenCookie)
 /// is valid with the following additional restriction(s):
 ///
 /// ## validate_user_token restriction enforcing user must be active
@@ -23,6 +31,29 @@ use crate::requests::models::user::get_user_by_id;
 /// The db `users.state` field for the user must
 /// be *active* (`0`) to login.
 ///
+/// ## validate_user_token restriction enforcing the session is active
+///
+/// The token must be an active (`0`) `users_tokens` record
+/// for the user. Revoked sessions are rejected even if the
+/// jwt has not expired.
+///
+/// ## validate_user_token restriction enforcing the tenant
+///
+/// With multi-tenancy enabled (``TENANT_MODE``) the request's
+/// tenant must be the user's `users.tenant_id`.
+///
+/// ## validate_user_token restriction enforcing token scopes
+///
+/// A jwt with a ``scope`` claim must include the scope for
+/// the request's route (see
+/// [`token_scopes`](crate::requests::auth::token_scopes)).
+///
+/// ## validate_user_token cache
+///
+/// With a ``CACHE_BACKEND`` the user and the active token
+/// checks are cached by the
+/// [`UserCache`](crate::pools::user_cache::UserCache)
+///
 /// # Arguments
 ///
 /// * `tracking_label` - `*&str` - caller logging label
@@ -43,7 +74,13 @@ use crate::requests::models::user::get_user_by_id;
 ///
 /// ## validate_user_token on Failure Returns
 ///
-/// Err(err_msg: `String`)
+/// Err([`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode))
+/// with ``TOKEN_EXPIRED`` for an expired jwt,
+/// ``SESSION_REVOKED`` for a revoked session,
+/// ``INSUFFICIENT_SCOPE`` for a scoped jwt without the
+/// route's scope and
+/// ``INVALID_TOKEN`` for everything else (so other users'
+/// ids and states are not leaked)
 ///
 pub async fn validate_user_token(
     tracking_label: &str,
@@ -51,71 +88,182 @@ pub async fn validate_user_token(
     conn: &PooledConnection<'_, PostgresConnectionManager<MakeTlsConnector>>,
     headers: &HeaderMap<HeaderValue>,
     user_id: i32,
-) -> Result<String, String> {
-    let token_header_key =
-        std::env::var("TOKEN_HEADER").unwrap_or_else(|_| "Bearer".to_string());
-    let (valid_user, user_model) =
-        match get_user_by_id(tracking_label, user_id, conn).await {
-            Ok(user_model) => {
-                match user_model.state {
-                    // only active users are allowed
-                    // users.state = 0 (active)
-                    0 => (true, user_model),
-                    // users.state != 0 (inactive/invalid)
-                    _ => {
-                        let err_msg = format!(
-                            "{tracking_label} user_id={user_id} \
+) -> Result<String, ApiErrorCode> {
+    let token = config.token_cookie.get_request_token(headers);
+    let (valid_user, cached_user) = match config
+        .user_cache
+        .get_user(tracking_label, user_id, conn)
+        .await
+    {
+        Ok(cached_user) => {
+            match cached_user.user.state {
+                // only active users are allowed
+                // users.state = 0 (active)
+                0 => (true, cached_user),
+                // users.state != 0 (inactive/invalid)
+                _ => {
+                    let err_msg = format!(
+                        "{tracking_label} user_id={user_id} \
                             is not active"
-                        );
-                        error!("{err_msg}");
-                        return Err("INVALID".to_string());
-                    }
+                    );
+                    error!("{err_msg}");
+                    config.auth_alerts.record_failure(
+                        tracking_label,
+                        "token",
+                        "inactive_user",
+                    );
+                    return Err(ApiErrorCode::InvalidToken);
                 }
             }
-            Err(err_msg) => {
-                return Err(err_msg);
-            }
-        };
+        }
+        Err(err_msg) => {
+            error!("{err_msg}");
+            config.auth_alerts.record_failure(
+                tracking_label,
+                "token",
+                "unknown_user",
+            );
+            return Err(ApiErrorCode::InvalidToken);
+        }
+    };
     if !valid_user {
         let err_msg = format!(
             "{tracking_label} token validation failed - user_id={user_id} \
             is not valid"
         );
         error!("{err_msg}");
-        return Err("INVALID".to_string());
+        config.auth_alerts.record_failure(
+            tracking_label,
+            "token",
+            "invalid_user",
+        );
+        return Err(ApiErrorCode::InvalidToken);
     }
-    if headers.contains_key(&token_header_key) {
-        let user_email = user_model.email.clone();
-        let token = headers.get(&token_header_key).unwrap().to_str().unwrap();
+    // tokens only work for their user's tenant
+    if config.tenants.is_enabled() {
+        match config
+            .tenants
+            .get_tenant_id(tracking_label, headers, conn)
+            .await
+        {
+            Ok(tenant_id) if tenant_id == cached_user.user.tenant_id => (),
+            Ok(tenant_id) => {
+                error!(
+                    "{tracking_label} token validation failed - \
+                    user_id={user_id} is not in tenant_id={tenant_id}"
+                );
+                config.auth_alerts.record_failure(
+                    tracking_label,
+                    "token",
+                    "wrong_tenant",
+                );
+                return Err(ApiErrorCode::InvalidToken);
+            }
+            Err(err_msg) => {
+                error!("{err_msg}");
+                config.auth_alerts.record_failure(
+                    tracking_label,
+                    "token",
+                    "unknown_tenant",
+                );
+                return Err(ApiErrorCode::InvalidToken);
+            }
+        }
+    }
+    if !token.is_empty() {
+        let user_email = cached_user.user.email.clone();
+        let token = token.as_str();
         /*
         info!("{tracking_label} validating user {user_id} \
             token={token}");
         */
-        match jwt_api::validate_token(
+        // tokens signed with a retired kid are rejected
+        let retired_kids = config.get_settings().jwt_retired_kids;
+        let jwt_keys = config
+            .jwt_keys
+            .read()
+            .unwrap()
+            .without_retired_kids(&retired_kids);
+        match jwt_api::validate_token_with_keys(
             tracking_label,
             token,
             &user_email,
-            &config.decoding_key_bytes,
+            &jwt_keys,
         )
         .await
         {
-            Ok(_) => Ok(token.to_string()),
+            Ok(token_data) => {
+                // scoped tokens only work on their routes
+                let required_scope = get_required_token_scope();
+                if !is_scope_allowed(&token_data.claims.scope, required_scope) {
+                    error!(
+                        "{tracking_label} token validation failed for \
+                        {user_email} - missing scope={required_scope}"
+                    );
+                    config.auth_alerts.record_failure(
+                        tracking_label,
+                        "token",
+                        "insufficient_scope",
+                    );
+                    return Err(ApiErrorCode::InsufficientScope);
+                }
+                // skip the db session check for cached active tokens
+                set_response_user_locale(&cached_user.user.locale);
+                if config.user_cache.check_token(&cached_user, token) {
+                    set_access_log_user_id(user_id);
+                    return Ok(token.to_string());
+                }
+                match touch_user_session(tracking_label, user_id, token, conn)
+                    .await
+                {
+                    Ok(_) => {
+                        config.user_cache.add_token(cached_user, token).await;
+                        set_access_log_user_id(user_id);
+                        Ok(token.to_string())
+                    }
+                    Err(err_msg) => {
+                        error!(
+                            "{tracking_label} token validation failed for \
+                            {user_email} err={err_msg}"
+                        );
+                        config.auth_alerts.record_failure(
+                            tracking_label,
+                            "token",
+                            "revoked_session",
+                        );
+                        Err(ApiErrorCode::SessionRevoked)
+                    }
+                }
+            }
             Err(e) => {
                 let err_msg = format!(
                     "{tracking_label} token validation failed for {user_email} \
                     err={e}"
                 );
                 error!("{err_msg}");
-                Err("INVALID".to_string())
+                config.auth_alerts.record_failure(
+                    tracking_label,
+                    "token",
+                    "invalid_jwt",
+                );
+                match e.ends_with(TOKEN_EXPIRED_ERR) {
+                    true => Err(ApiErrorCode::TokenExpired),
+                    false => Err(ApiErrorCode::InvalidToken),
+                }
             }
         }
     } else {
         let err_msg = format!(
             "{tracking_label} \
-            token validation failed missing header key={token_header_key} \
+            token validation failed missing token header or cookie \
             for {user_id} request"
         );
         error!("{err_msg}");
-        Err("INVALID".to_string())
+        config.auth_alerts.record_failure(
+            tracking_label,
+            "token",
+            "missing_header",
+        );
+        Err(ApiErrorCode::InvalidToken)
     }
 }
@@ -0,0 +1,94 @@
+//! Module for consuming a user's in-flight passkey challenge state
+//!
+//! The ``finish`` registration and login apis call
+//! [`consume_passkey_challenge`](crate::requests::auth::webauthn::consume_passkey_challenge::consume_passkey_challenge)
+//! to take the ceremony state that the matching ``start``
+//! api stored in the ``users_passkeys_challenges`` table.
+//! The row is deleted by the same statement that reads it,
+//! so a replayed ``finish`` call never finds a challenge,
+//! and expired challenges are rejected (the client has to
+//! start a new ceremony).
+//!
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::PooledConnection;
+use bb8_postgres::PostgresConnectionManager;
+
+use crate::monitoring::otel::trace_db_query;
+
+/// consume_passkey_challenge
+///
+/// Delete and return the user's stored webauthn ceremony state
+/// in a single statement so a challenge can only be
+/// used once (even with concurrent ``finish`` calls).
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `user_id` - `i32` - user id in the db
+/// * `ceremony` - `&str` - `register` or `login`
+/// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+///   an established db connection from the
+///   postgres client db threadpool
+///
+/// # Returns
+///
+/// ## consume_passkey_challenge on Success Returns
+///
+/// `Ok(challenge_state: String)` - json-serialized ceremony state
+/// stored by
+/// [`upsert_passkey_challenge`](crate::requests::auth::webauthn::upsert_passkey_challenge::upsert_passkey_challenge)
+///
+/// # Errors
+///
+/// `Err(String)` if there is no challenge, the challenge
+/// expired or the db query fails
+///
+pub async fn consume_passkey_challenge(
+    tracking_label: &str,
+    user_id: i32,
+    ceremony: &str,
+    conn: &PooledConnection<'_, PostgresConnectionManager<MakeTlsConnector>>,
+) -> Result<String, String> {
+    let cur_query = format!(
+        "DELETE FROM \
+            users_passkeys_challenges \
+        WHERE \
+            users_passkeys_challenges.user_id = {user_id} \
+        AND \
+            users_passkeys_challenges.ceremony = '{ceremony}' \
+        RETURNING \
+            users_passkeys_challenges.challenge_state, \
+            users_passkeys_challenges.exp_date;"
+    );
+    let stmt = conn.prepare(&cur_query).await.unwrap();
+    let query_result =
+        match trace_db_query(&cur_query, conn.query(&stmt, &[])).await {
+            Ok(query_result) => query_result,
+            Err(e) => {
+                return Err(format!(
+                    "{tracking_label} - \
+                failed to consume passkey {ceremony} challenge \
+                for user_id={user_id} with err='{e}'"
+                ));
+            }
+        };
+    if let Some(row) = query_result.first() {
+        let exp_date: chrono::DateTime<chrono::Utc> =
+            row.try_get("exp_date").unwrap();
+        if exp_date < chrono::Utc::now() {
+            return Err(format!(
+                "{tracking_label} - \
+                passkey {ceremony} challenge for user_id={user_id} \
+                expired on {}",
+                exp_date.format("%Y-%m-%dT%H:%M:%SZ")
+            ));
+        }
+        let challenge_state: String = row.try_get("challenge_state").unwrap();
+        return Ok(challenge_state);
+    }
+    Err(format!(
+        "{tracking_label} - \
+        no passkey {ceremony} challenge found for user_id={user_id}"
+    ))
+}
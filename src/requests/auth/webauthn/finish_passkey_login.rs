@@ -0,0 +1,562 @@
+//! Module for finishing a passkey (webauthn) login
+//!
+//! ## Finish Passkey Login
+//!
+//! Verify the signed ``navigator.credentials.get()`` response and get a json web token (jwt) back for authentication on subsequent client requests
+//!
+//! - URL path: ``/login/passkey/finish``
+//! - Method: ``POST``
+//! - Handler: [`finish_passkey_login`](crate::requests::auth::webauthn::finish_passkey_login::finish_passkey_login)
+//! - Request: [`ApiReqUserFinishPasskeyLogin`](crate::requests::auth::webauthn::finish_passkey_login::ApiReqUserFinishPasskeyLogin)
+//! - Response: [`ApiResUserLogin`](crate::requests::auth::login_user::ApiResUserLogin)
+//!
+
+use std::convert::Infallible;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Response;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use webauthn_rs::prelude::PasskeyAuthentication;
+use webauthn_rs::prelude::PublicKeyCredential;
+
+use crate::core::core_config::CoreConfig;
+use crate::core::server::access_log::set_access_log_user_id;
+use crate::i18n::message_localization::set_response_user_locale;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::monitoring::otel::trace_db_query;
+use crate::notifications::security_notifications::send_new_login_notification;
+use crate::requests::auth::create_user_token::create_user_token;
+use crate::requests::auth::login_user::ApiResUserLogin;
+use crate::requests::auth::webauthn::consume_passkey_challenge::consume_passkey_challenge;
+use crate::requests::auth::webauthn::get_webauthn::get_webauthn;
+use crate::requests::auth::webauthn::start_passkey_login::PASSKEY_LOGIN_FAILED_MSG;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::models::user::get_active_user_by_email;
+use crate::requests::models::user_admin_action::is_password_reset_required;
+use crate::requests::models::user_passkey::get_user_passkeys;
+use crate::requests::models::user_session::get_user_session_metadata;
+use crate::requests::validation::field_rules::check_email;
+use crate::requests::validation::normalize_email::normalize_email;
+use crate::requests::validation::validate_api_req::validate_api_req;
+use crate::requests::validation::validate_api_req::ApiFieldError;
+use crate::requests::validation::validate_api_req::ApiReqValidate;
+
+/// ApiReqUserFinishPasskeyLogin
+///
+/// # Request Type For finish_passkey_login
+///
+/// Finish a passkey login for a user
+///
+/// This type is the deserialized input for:
+/// [`finish_passkey_login`](crate::requests::auth::webauthn::finish_passkey_login::finish_passkey_login)
+///
+/// # Usage
+///
+/// This type is constructed from the deserialized
+/// `bytes` (`&[u8]`) argument
+/// on the
+/// [`finish_passkey_login`](crate::requests::auth::webauthn::finish_passkey_login::finish_passkey_login)
+/// function.
+///
+/// # Arguments
+///
+/// * `email` - `String` - unique user email
+/// * `credential` -
+///   [`PublicKeyCredential`](webauthn_rs::prelude::PublicKeyCredential) -
+///   the browser's ``navigator.credentials.get()`` response
+///
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApiReqUserFinishPasskeyLogin {
+    pub email: String,
+    pub credential: PublicKeyCredential,
+}
+
+impl ApiReqValidate for ApiReqUserFinishPasskeyLogin {
+    /// validate
+    ///
+    /// Require a valid `email`
+    /// (the `credential` is verified by webauthn)
+    ///
+    fn validate(&self) -> Vec<ApiFieldError> {
+        let mut errors = Vec::new();
+        check_email(&mut errors, "email", &self.email);
+        errors
+    }
+}
+
+/// get_passkey_login_failed_response
+///
+/// Build the one response for every passkey login failure
+/// that could tell a client whether an email has an account
+/// (unknown emails, unverified users, missing challenges and
+/// invalid credentials). The specific reason is only logged.
+///
+fn get_passkey_login_failed_response() -> Response<Body> {
+    Response::builder()
+        .status(400)
+        .body(Body::from(
+            serde_json::to_string(&ApiResUserLogin {
+                user_id: -1,
+                email: String::from(""),
+                state: -1,
+                verified: -1,
+                role: String::from(""),
+                token: String::from(""),
+                msg: PASSKEY_LOGIN_FAILED_MSG.to_string(),
+                error_code: Some(ApiErrorCode::InvalidCredentials),
+                challenge: Vec::new(),
+            })
+            .unwrap(),
+        ))
+        .unwrap()
+}
+
+/// finish_passkey_login
+///
+/// Handler for verifying a passkey login response
+/// against the challenge created by
+/// [`start_passkey_login`](crate::requests::auth::webauthn::start_passkey_login::start_passkey_login)
+/// and creating a new, encrypted jwt for the user (same as
+/// [`login_user`](crate::requests::auth::login_user::login_user)).
+///
+/// ## finish_passkey_login restriction enforcing user must be active
+///
+/// The db `users.state` field for the user must
+/// be *active* (`0`) to login.
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
+///   db threadpool with required tls encryption
+/// * `kafka_pool` -
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+///   for asynchronously publishing messages to the connected kafka cluster
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+///   (``User-Agent`` and ``device`` are stored with the session)
+/// * `remote_addr` - `&std::net::SocketAddr` - client address
+///   stored with the session
+/// * `bytes` - `&[u8]` - received bytes from the hyper
+///   [`Request`](hyper::Request)'s [`Body`](hyper::Body)
+///
+/// # Returns
+///
+/// ## finish_passkey_login on Success Returns
+///
+/// HTTP status code `201` with
+/// [`ApiResUserLogin`](crate::requests::auth::login_user::ApiResUserLogin)
+/// in the hyper [`Response`](hyper::Response)
+///
+/// Ok([`Response`](hyper::Response))
+///
+/// # Errors
+///
+/// ## finish_passkey_login on Failure Returns
+///
+/// `non-201` HTTP status code with
+/// [`ApiResUserLogin`](crate::requests::auth::login_user::ApiResUserLogin)
+/// in the hyper [`Response`](hyper::Response)
+///
+/// Unknown emails, unverified users, missing or expired
+/// challenges and invalid credentials all return the same
+/// `400` with ``INVALID_CREDENTIALS`` and
+/// [`PASSKEY_LOGIN_FAILED_MSG`](crate::requests::auth::webauthn::start_passkey_login::PASSKEY_LOGIN_FAILED_MSG)
+/// so the response does not reveal which accounts exist
+///
+/// Err([`Infallible`](std::convert::Infallible))
+///
+pub async fn finish_passkey_login(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    kafka_pool: &KafkaPublisher,
+    headers: &HeaderMap<HeaderValue>,
+    remote_addr: &std::net::SocketAddr,
+    bytes: &[u8],
+) -> std::result::Result<Response<Body>, Infallible> {
+    let mut req_object: ApiReqUserFinishPasskeyLogin =
+        match serde_json::from_slice(bytes) {
+            Ok(uo) => uo,
+            Err(_) => {
+                let response = Response::builder()
+                    .status(400)
+                    .body(Body::from(
+                        serde_json::to_string(&ApiResUserLogin {
+                            user_id: -1,
+                            email: String::from(""),
+                            state: -1,
+                            verified: -1,
+                            role: String::from(""),
+                            token: String::from(""),
+                            msg: ("Passkey login failed - please ensure \
+                                email and credential \
+                                were set correctly in the request")
+                                .to_string(),
+                            error_code: Some(ApiErrorCode::InvalidRequest),
+                            challenge: Vec::new(),
+                        })
+                        .unwrap(),
+                    ))
+                    .unwrap();
+                return Ok(response);
+            }
+        };
+
+    req_object.email = normalize_email(&req_object.email);
+    if let Some(response) = validate_api_req(tracking_label, &req_object) {
+        return Ok(response);
+    }
+
+    // check the server config before looking up the user so
+    // a misconfigured server answers every email the same way
+    let webauthn = match get_webauthn(tracking_label) {
+        Ok(webauthn) => webauthn,
+        Err(err_msg) => {
+            error!("{err_msg}");
+            let response = Response::builder()
+                .status(500)
+                .body(Body::from(
+                    serde_json::to_string(&ApiResUserLogin {
+                        user_id: -1,
+                        email: String::from(""),
+                        state: -1,
+                        verified: -1,
+                        role: String::from(""),
+                        token: String::from(""),
+                        msg: ("Passkey login failed - \
+                            passkeys are not configured on the server")
+                            .to_string(),
+                        error_code: Some(ApiErrorCode::FeatureDisabled),
+                        challenge: Vec::new(),
+                    })
+                    .unwrap(),
+                ))
+                .unwrap();
+            return Ok(response);
+        }
+    };
+
+    let settings = config.get_settings();
+    let conn = db_pool.get().await.unwrap();
+    let tenant_id = match config
+        .tenants
+        .get_tenant_id(tracking_label, headers, &conn)
+        .await
+    {
+        Ok(tenant_id) => tenant_id,
+        Err(err_msg) => {
+            error!("{err_msg}");
+            let response = Response::builder()
+                .status(400)
+                .body(Body::from(
+                    serde_json::to_string(&ApiResUserLogin {
+                        user_id: -1,
+                        email: String::from(""),
+                        state: -1,
+                        verified: -1,
+                        role: String::from(""),
+                        token: String::from(""),
+                        msg: ("Passkey login failed - unknown tenant")
+                            .to_string(),
+                        error_code: Some(ApiErrorCode::UnknownTenant),
+                        challenge: Vec::new(),
+                    })
+                    .unwrap(),
+                ))
+                .unwrap();
+            return Ok(response);
+        }
+    };
+    let user_model = match get_active_user_by_email(
+        tracking_label,
+        tenant_id,
+        &req_object.email,
+        &conn,
+    )
+    .await
+    {
+        Ok(user_model) => user_model,
+        Err(err_msg) => {
+            error!("{err_msg}");
+            config.auth_alerts.record_failure(
+                tracking_label,
+                "login",
+                "passkey_unknown_user",
+            );
+            return Ok(get_passkey_login_failed_response());
+        }
+    };
+    let user_id = user_model.id;
+    let user_email = user_model.email.clone();
+    let session =
+        get_user_session_metadata(headers, remote_addr, &config.geo_ip);
+
+    // if user verification is enabled and the user
+    // has not verified - reject the auth
+    if settings.verification_required && user_model.verified != 1 {
+        error!(
+            "{tracking_label} - passkey login rejected - \
+            the email address: {user_email} is not verified"
+        );
+        config
+            .login_history
+            .record_login(
+                tracking_label,
+                &conn,
+                user_id,
+                "passkey",
+                "unverified",
+                &session,
+            )
+            .await;
+        return Ok(get_passkey_login_failed_response());
+    }
+
+    let authentication_state: PasskeyAuthentication =
+        match consume_passkey_challenge(tracking_label, user_id, "login", &conn)
+            .await
+            .and_then(|challenge_state| {
+                serde_json::from_str(&challenge_state).map_err(|e| {
+                    format!(
+                        "{tracking_label} - \
+                        invalid passkey login challenge state \
+                        for user_id={user_id} with err='{e}'"
+                    )
+                })
+            }) {
+            Ok(authentication_state) => authentication_state,
+            Err(err_msg) => {
+                error!("{err_msg}");
+                config.auth_alerts.record_failure(
+                    tracking_label,
+                    "login",
+                    "passkey_challenge",
+                );
+                return Ok(get_passkey_login_failed_response());
+            }
+        };
+
+    let auth_result = match webauthn.finish_passkey_authentication(
+        &req_object.credential,
+        &authentication_state,
+    ) {
+        Ok(auth_result) => auth_result,
+        Err(e) => {
+            error!(
+                "{tracking_label} - \
+                passkey login verification failed for user {user_id} \
+                with err='{e}'"
+            );
+            config.auth_alerts.record_failure(
+                tracking_label,
+                "login",
+                "invalid_passkey",
+            );
+            config
+                .login_history
+                .record_login(
+                    tracking_label,
+                    &conn,
+                    user_id,
+                    "passkey",
+                    "invalid_passkey",
+                    &session,
+                )
+                .await;
+            return Ok(get_passkey_login_failed_response());
+        }
+    };
+
+    // persist the signature counter and backup state so
+    // cloned authenticators are detected on the next login
+    let used_cred_id = format!("{}", auth_result.cred_id());
+    if let Ok(passkeys) =
+        get_user_passkeys(tracking_label, user_id, &conn).await
+    {
+        for mut model_passkey in passkeys {
+            if model_passkey.cred_id != used_cred_id {
+                continue;
+            }
+            let passkey_changed =
+                model_passkey.passkey.update_credential(&auth_result);
+            let passkey_update = match passkey_changed {
+                Some(true) => format!(
+                    "passkey = '{}', ",
+                    serde_json::to_string(&model_passkey.passkey)
+                        .unwrap()
+                        .replace('\'', "''")
+                ),
+                _ => "".to_string(),
+            };
+            let update_query = format!(
+                "UPDATE \
+                    users_passkeys \
+                SET \
+                    {passkey_update}\
+                    last_used_at = timezone('UTC'::text, now()), \
+                    updated_at = timezone('UTC'::text, now()) \
+                WHERE \
+                    users_passkeys.id = {};",
+                model_passkey.id
+            );
+            let stmt = conn.prepare(&update_query).await.unwrap();
+            if let Err(e) =
+                trace_db_query(&update_query, conn.query(&stmt, &[])).await
+            {
+                error!(
+                    "{tracking_label} - \
+                    failed to update passkey={} for user {user_id} \
+                    with err='{e}'",
+                    model_passkey.id
+                );
+            }
+        }
+    }
+
+    // an admin forced a password reset so the password
+    // must be changed with a one-time-use token first
+    if is_password_reset_required(tracking_label, user_id, &conn).await {
+        warn!(
+            "{tracking_label} - \
+            Passkey login rejected - user {user_id} must reset \
+            their password"
+        );
+        config
+            .login_history
+            .record_login(
+                tracking_label,
+                &conn,
+                user_id,
+                "passkey",
+                "reset_required",
+                &session,
+            )
+            .await;
+        let response = Response::builder()
+            .status(403)
+            .body(Body::from(
+                serde_json::to_string(&ApiResUserLogin {
+                    user_id: -1,
+                    email: String::from(""),
+                    state: -1,
+                    verified: -1,
+                    role: String::from(""),
+                    token: String::from(""),
+                    msg: "Passkey login failed - a password reset is \
+                        required"
+                        .to_string(),
+                    error_code: Some(ApiErrorCode::PasswordResetRequired),
+                    challenge: Vec::new(),
+                })
+                .unwrap(),
+            ))
+            .unwrap();
+        return Ok(response);
+    }
+
+    let user_token = match create_user_token(
+        tracking_label,
+        config,
+        &conn,
+        &user_email,
+        user_id,
+        &session,
+        "",
+    )
+    .await
+    {
+        Ok(user_token) => user_token,
+        Err(_) => {
+            let response = Response::builder()
+                .status(400)
+                .body(Body::from(
+                    serde_json::to_string(&ApiResUserLogin {
+                        user_id: -1,
+                        email: String::from(""),
+                        state: -1,
+                        verified: -1,
+                        role: String::from(""),
+                        token: String::from(""),
+                        msg: format!(
+                            "Passkey login failed - \
+                            unable to create user token for \
+                            user_id={user_id} email={user_email}"
+                        ),
+                        error_code: Some(ApiErrorCode::InternalError),
+                        challenge: Vec::new(),
+                    })
+                    .unwrap(),
+                ))
+                .unwrap();
+            return Ok(response);
+        }
+    };
+
+    config
+        .login_history
+        .record_login(
+            tracking_label,
+            &conn,
+            user_id,
+            "passkey",
+            "success",
+            &session,
+        )
+        .await;
+    config
+        .events
+        .user_logged_in(
+            kafka_pool,
+            user_id,
+            &user_email,
+            "LOGIN_PASSKEY",
+            &session,
+        )
+        .await;
+    send_new_login_notification(
+        tracking_label,
+        config,
+        kafka_pool,
+        &conn,
+        user_id,
+        &user_email,
+        &user_model.locale,
+        &session,
+        &user_token,
+    )
+    .await;
+    set_access_log_user_id(user_id);
+    set_response_user_locale(&user_model.locale);
+
+    let response = config
+        .token_cookie
+        .set_token_cookie(Response::builder().status(201), &user_token)
+        .body(Body::from(
+            serde_json::to_string(&ApiResUserLogin {
+                user_id,
+                email: user_email,
+                state: user_model.state,
+                verified: user_model.verified,
+                role: user_model.role,
+                token: config.token_cookie.get_response_token(user_token),
+                msg: "success".to_string(),
+                error_code: None,
+                challenge: Vec::new(),
+            })
+            .unwrap(),
+        ))
+        .unwrap();
+    Ok(response)
+}
@@ -0,0 +1,493 @@
+//! Module for finishing a passkey (webauthn) registration
+//!
+//! ## Finish Passkey Registration
+//!
+//! Verify the signed ``navigator.credentials.create()`` response and store the new passkey in the ``users_passkeys`` table. A new passkey sends a security notification to the user (see [`security_notifications`](crate::notifications::security_notifications)).
+//!
+//! - URL path: ``/user/passkey/register/finish``
+//! - Method: ``POST``
+//! - Handler: [`finish_passkey_registration`](crate::requests::auth::webauthn::finish_passkey_registration::finish_passkey_registration)
+//! - Request: [`ApiReqUserFinishPasskeyRegistration`](crate::requests::auth::webauthn::finish_passkey_registration::ApiReqUserFinishPasskeyRegistration)
+//! - Response: [`ApiResUserFinishPasskeyRegistration`](crate::requests::auth::webauthn::finish_passkey_registration::ApiResUserFinishPasskeyRegistration)
+//!
+
+use std::convert::Infallible;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Response;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use webauthn_rs::prelude::PasskeyRegistration;
+use webauthn_rs::prelude::RegisterPublicKeyCredential;
+
+use crate::core::core_config::CoreConfig;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::monitoring::otel::trace_db_query;
+use crate::notifications::security_notifications::send_security_notification;
+use crate::requests::auth::validate_user_token::validate_user_token;
+use crate::requests::auth::webauthn::consume_passkey_challenge::consume_passkey_challenge;
+use crate::requests::auth::webauthn::get_webauthn::get_webauthn;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::validation::field_rules::check_id;
+use crate::requests::validation::field_rules::check_length;
+use crate::requests::validation::validate_api_req::validate_api_req;
+use crate::requests::validation::validate_api_req::ApiFieldError;
+use crate::requests::validation::validate_api_req::ApiReqValidate;
+
+/// ApiReqUserFinishPasskeyRegistration
+///
+/// # Request Type For finish_passkey_registration
+///
+/// Finish registering a new passkey for an existing user
+///
+/// This type is the deserialized input for:
+/// [`finish_passkey_registration`](crate::requests::auth::webauthn::finish_passkey_registration::finish_passkey_registration)
+///
+/// # Usage
+///
+/// This type is constructed from the deserialized
+/// `bytes` (`&[u8]`) argument
+/// on the
+/// [`finish_passkey_registration`](crate::requests::auth::webauthn::finish_passkey_registration::finish_passkey_registration)
+/// function.
+///
+/// # Arguments
+///
+/// * `user_id` - `i32` - user id
+/// * `name` - `Option<String>` - user-friendly name for the
+///   passkey (default is `passkey`)
+/// * `credential` -
+///   [`RegisterPublicKeyCredential`](webauthn_rs::prelude::RegisterPublicKeyCredential) -
+///   the browser's ``navigator.credentials.create()`` response
+///
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApiReqUserFinishPasskeyRegistration {
+    pub user_id: i32,
+    pub name: Option<String>,
+    pub credential: RegisterPublicKeyCredential,
+}
+
+impl ApiReqValidate for ApiReqUserFinishPasskeyRegistration {
+    /// validate
+    ///
+    /// Require a positive `user_id` and an optional `name`
+    /// between 1 and 255 characters
+    /// (the `credential` is verified by webauthn)
+    ///
+    fn validate(&self) -> Vec<ApiFieldError> {
+        let mut errors = Vec::new();
+        check_id(&mut errors, "user_id", self.user_id);
+        if let Some(name) = &self.name {
+            check_length(&mut errors, "name", name, 1, 255);
+        }
+        errors
+    }
+}
+
+/// ApiResUserFinishPasskeyRegistration
+///
+/// # Response type for finish_passkey_registration
+///
+/// Return the newly-stored passkey record
+///
+/// # Usage
+///
+/// This type is the serialized output for the function:
+/// [`finish_passkey_registration`](crate::requests::auth::webauthn::finish_passkey_registration::finish_passkey_registration)
+/// and contained within the
+/// hyper [`Body`](hyper::Body)
+/// of the
+/// hyper [`Response`](hyper::Response)
+/// sent back to the client.
+///
+/// # Arguments
+///
+/// * `user_id` - `i32` - user id
+/// * `passkey_id` - `i32` - `users_passkeys.id` in the db
+/// * `cred_id` - `String` - base64 url-safe encoded
+///   webauthn credential id
+/// * `name` - `String` - user-friendly name for the passkey
+/// * `msg` - `String` - help message
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   machine-readable failure code (omitted on success)
+///
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApiResUserFinishPasskeyRegistration {
+    pub user_id: i32,
+    pub passkey_id: i32,
+    pub cred_id: String,
+    pub name: String,
+    pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ApiErrorCode>,
+}
+
+/// finish_passkey_registration
+///
+/// Handler for verifying a passkey registration response
+/// against the challenge created by
+/// [`start_passkey_registration`](crate::requests::auth::webauthn::start_passkey_registration::start_passkey_registration)
+/// and storing the passkey.
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
+///   db threadpool with required tls encryption
+/// * `kafka_pool` -
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+///   for asynchronously publishing messages to the connected kafka cluster
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+/// * `bytes` - `&[u8]` - received bytes from the hyper
+///   [`Request`](hyper::Request)'s [`Body`](hyper::Body)
+///
+/// # Returns
+///
+/// ## finish_passkey_registration on Success Returns
+///
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResUserFinishPasskeyRegistration`](crate::requests::auth::webauthn::finish_passkey_registration::ApiResUserFinishPasskeyRegistration)
+/// dictionary within the
+/// [`Body`](hyper::Body) and a
+/// `201` HTTP status code
+///
+/// Ok([`Response`](hyper::Response))
+///
+/// # Errors
+///
+/// ## finish_passkey_registration on Failure Returns
+///
+/// All errors return as a
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResUserFinishPasskeyRegistration`](crate::requests::auth::webauthn::finish_passkey_registration::ApiResUserFinishPasskeyRegistration)
+/// dictionary with a
+/// `non-201` HTTP status code
+///
+/// Err([`Response`](hyper::Response))
+///
+pub async fn finish_passkey_registration(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    kafka_pool: &KafkaPublisher,
+    headers: &HeaderMap<HeaderValue>,
+    bytes: &[u8],
+) -> std::result::Result<Response<Body>, Infallible> {
+    let req_object: ApiReqUserFinishPasskeyRegistration =
+        match serde_json::from_slice(bytes) {
+            Ok(uo) => uo,
+            Err(_) => {
+                let response = Response::builder()
+                    .status(400)
+                    .body(Body::from(
+                        serde_json::to_string(
+                            &ApiResUserFinishPasskeyRegistration {
+                                user_id: -1,
+                                passkey_id: -1,
+                                cred_id: "".to_string(),
+                                name: "".to_string(),
+                                msg: ("Finish passkey registration failed - \
+                                please ensure \
+                                user_id and credential \
+                                were set correctly in the request")
+                                    .to_string(),
+                                error_code: Some(ApiErrorCode::InvalidRequest),
+                            },
+                        )
+                        .unwrap(),
+                    ))
+                    .unwrap();
+                return Ok(response);
+            }
+        };
+
+    if let Some(response) = validate_api_req(tracking_label, &req_object) {
+        return Ok(response);
+    }
+
+    let user_id = req_object.user_id;
+    let passkey_name = req_object
+        .name
+        .clone()
+        .unwrap_or_else(|| "passkey".to_string());
+    if passkey_name.is_empty() || passkey_name.len() > 255 {
+        let response = Response::builder()
+            .status(400)
+            .body(Body::from(
+                serde_json::to_string(&ApiResUserFinishPasskeyRegistration {
+                    user_id,
+                    passkey_id: -1,
+                    cred_id: "".to_string(),
+                    name: "".to_string(),
+                    msg: ("Finish passkey registration failed - \
+                        please ensure the passkey name \
+                        is between 1 and 255 characters")
+                        .to_string(),
+                    error_code: Some(ApiErrorCode::InvalidRequest),
+                })
+                .unwrap(),
+            ))
+            .unwrap();
+        return Ok(response);
+    }
+
+    let conn = db_pool.get().await.unwrap();
+    let _token = match validate_user_token(
+        tracking_label,
+        config,
+        &conn,
+        headers,
+        user_id,
+    )
+    .await
+    {
+        Ok(_token) => _token,
+        Err(error_code) => {
+            let response = Response::builder()
+                .status(400)
+                .body(Body::from(
+                    serde_json::to_string(
+                        &ApiResUserFinishPasskeyRegistration {
+                            user_id,
+                            passkey_id: -1,
+                            cred_id: "".to_string(),
+                            name: "".to_string(),
+                            msg: ("Finish passkey registration failed \
+                            due to invalid token")
+                                .to_string(),
+                            error_code: Some(error_code),
+                        },
+                    )
+                    .unwrap(),
+                ))
+                .unwrap();
+            return Ok(response);
+        }
+    };
+
+    let registration_state: PasskeyRegistration =
+        match consume_passkey_challenge(
+            tracking_label,
+            user_id,
+            "register",
+            &conn,
+        )
+        .await
+        {
+            Ok(challenge_state) => {
+                serde_json::from_str(&challenge_state).unwrap()
+            }
+            Err(err_msg) => {
+                error!("{err_msg}");
+                let response = Response::builder()
+                    .status(400)
+                    .body(Body::from(
+                        serde_json::to_string(
+                            &ApiResUserFinishPasskeyRegistration {
+                                user_id,
+                                passkey_id: -1,
+                                cred_id: "".to_string(),
+                                name: "".to_string(),
+                                msg: ("Finish passkey registration failed - \
+                                no valid registration challenge found \
+                                please start a new registration")
+                                    .to_string(),
+                                error_code: Some(ApiErrorCode::InvalidRequest),
+                            },
+                        )
+                        .unwrap(),
+                    ))
+                    .unwrap();
+                return Ok(response);
+            }
+        };
+
+    let webauthn = match get_webauthn(tracking_label) {
+        Ok(webauthn) => webauthn,
+        Err(err_msg) => {
+            error!("{err_msg}");
+            let response = Response::builder()
+                .status(500)
+                .body(Body::from(
+                    serde_json::to_string(
+                        &ApiResUserFinishPasskeyRegistration {
+                            user_id,
+                            passkey_id: -1,
+                            cred_id: "".to_string(),
+                            name: "".to_string(),
+                            msg: ("Finish passkey registration failed - \
+                            passkeys are not configured on the server")
+                                .to_string(),
+                            error_code: Some(ApiErrorCode::FeatureDisabled),
+                        },
+                    )
+                    .unwrap(),
+                ))
+                .unwrap();
+            return Ok(response);
+        }
+    };
+
+    let passkey = match webauthn.finish_passkey_registration(
+        &req_object.credential,
+        &registration_state,
+    ) {
+        Ok(passkey) => passkey,
+        Err(e) => {
+            error!(
+                "{tracking_label} - \
+                failed to verify passkey registration for user {user_id} \
+                with err='{e}'"
+            );
+            let response = Response::builder()
+                .status(400)
+                .body(Body::from(
+                    serde_json::to_string(
+                        &ApiResUserFinishPasskeyRegistration {
+                            user_id,
+                            passkey_id: -1,
+                            cred_id: "".to_string(),
+                            name: "".to_string(),
+                            msg: format!(
+                                "Finish passkey registration failed - \
+                            unable to verify the credential with err='{e}'"
+                            ),
+                            error_code: Some(ApiErrorCode::InvalidCredentials),
+                        },
+                    )
+                    .unwrap(),
+                ))
+                .unwrap();
+            return Ok(response);
+        }
+    };
+
+    let cred_id = format!("{}", passkey.cred_id());
+    let passkey_str = serde_json::to_string(&passkey).unwrap();
+    let cur_query = format!(
+        "INSERT INTO \
+            users_passkeys (\
+                user_id, \
+                cred_id, \
+                name, \
+                passkey, \
+                state) \
+        VALUES (\
+            {user_id}, \
+            '{cred_id}', \
+            '{}', \
+            '{}', \
+            0) \
+        RETURNING \
+            users_passkeys.id, \
+            users_passkeys.user_id, \
+            users_passkeys.cred_id, \
+            users_passkeys.name;",
+        passkey_name.replace('\'', "''"),
+        passkey_str.replace('\'', "''")
+    );
+    let stmt = conn.prepare(&cur_query).await.unwrap();
+    let query_result =
+        match trace_db_query(&cur_query, conn.query(&stmt, &[])).await {
+            Ok(query_result) => query_result,
+            Err(e) => {
+                let response = Response::builder()
+                    .status(400)
+                    .body(Body::from(
+                        serde_json::to_string(
+                            &ApiResUserFinishPasskeyRegistration {
+                                user_id,
+                                passkey_id: -1,
+                                cred_id: "".to_string(),
+                                name: "".to_string(),
+                                msg: format!(
+                                    "Finish passkey registration failed \
+                            for user_id={user_id} with err='{e}'"
+                                ),
+                                error_code: Some(ApiErrorCode::InternalError),
+                            },
+                        )
+                        .unwrap(),
+                    ))
+                    .unwrap();
+                return Ok(response);
+            }
+        };
+
+    // must match up with RETURNING
+    if let Some(row) = query_result.first() {
+        let passkey_id: i32 = row.try_get("id").unwrap();
+        let stored_name: String = row.try_get("name").unwrap();
+
+        config
+            .events
+            .publish_user_event(
+                kafka_pool,
+                user_id,
+                "USER_PASSKEY_REGISTER",
+                &format!("passkey={passkey_id}"),
+            )
+            .await;
+        if let Ok(cached_user) = config
+            .user_cache
+            .get_user(tracking_label, user_id, &conn)
+            .await
+        {
+            send_security_notification(
+                tracking_label,
+                config,
+                kafka_pool,
+                &cached_user.user,
+                "passkey",
+                "",
+            )
+            .await;
+        }
+
+        let response = Response::builder()
+            .status(201)
+            .body(Body::from(
+                serde_json::to_string(&ApiResUserFinishPasskeyRegistration {
+                    user_id,
+                    passkey_id,
+                    cred_id,
+                    name: stored_name,
+                    msg: "success".to_string(),
+                    error_code: None,
+                })
+                .unwrap(),
+            ))
+            .unwrap();
+        return Ok(response);
+    }
+    let response = Response::builder()
+        .status(400)
+        .body(Body::from(
+            serde_json::to_string(&ApiResUserFinishPasskeyRegistration {
+                user_id,
+                passkey_id: -1,
+                cred_id: "".to_string(),
+                name: "".to_string(),
+                msg: ("Finish passkey registration failed - \
+                    no records found in db")
+                    .to_string(),
+                error_code: Some(ApiErrorCode::InternalError),
+            })
+            .unwrap(),
+        ))
+        .unwrap();
+    Ok(response)
+}
@@ -0,0 +1,100 @@
+//! Module for building the webauthn relying party from
+//! environment variables
+//!
+//! Every passkey ``start`` and ``finish`` api builds the
+//! relying party with
+//! [`get_webauthn`](crate::requests::auth::webauthn::get_webauthn::get_webauthn).
+//! A misconfigured ``WEBAUTHN_RP_ID`` or ``WEBAUTHN_RP_ORIGIN``
+//! fails those apis with a ``FEATURE_DISABLED`` error code
+//! instead of stopping the server, so password logins keep
+//! working.
+//!
+use webauthn_rs::prelude::Url;
+use webauthn_rs::Webauthn;
+use webauthn_rs::WebauthnBuilder;
+
+/// get_webauthn
+///
+/// Build the [`Webauthn`](webauthn_rs::Webauthn) relying party
+/// used for all passkey registration and login ceremonies.
+///
+/// The relying party id must be the domain (without scheme or port)
+/// that browsers use to reach the api, and the origin must be the
+/// full url the browser sees.
+///
+/// ## Roadmap
+///
+/// This should move into the
+/// [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// server statics.
+///
+/// # Usage
+///
+/// ## Environment variables with default values (bash):
+///
+/// ```bash
+/// export WEBAUTHN_RP_ID="localhost"
+/// export WEBAUTHN_RP_ORIGIN="https://localhost:3000"
+/// export WEBAUTHN_RP_NAME="restapi"
+/// ```
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+///
+/// # Returns
+///
+/// ## get_webauthn on Success Returns
+///
+/// [`Webauthn`](webauthn_rs::Webauthn)
+///
+/// # Errors
+///
+/// `Err(String)` if the origin is not a valid url or
+/// the relying party id is not a valid domain for the origin
+///
+/// # Examples
+///
+/// ```rust
+/// use restapi::requests::auth::webauthn::get_webauthn::get_webauthn;
+/// // the defaults are a relying party for https://localhost:3000
+/// assert!(get_webauthn("doc-test").is_ok());
+/// ```
+///
+pub fn get_webauthn(tracking_label: &str) -> Result<Webauthn, String> {
+    let rp_id = std::env::var("WEBAUTHN_RP_ID")
+        .unwrap_or_else(|_| "localhost".to_string());
+    let rp_origin_str = std::env::var("WEBAUTHN_RP_ORIGIN")
+        .unwrap_or_else(|_| "https://localhost:3000".to_string());
+    let rp_name = std::env::var("WEBAUTHN_RP_NAME")
+        .unwrap_or_else(|_| "restapi".to_string());
+    let rp_origin = match Url::parse(&rp_origin_str) {
+        Ok(rp_origin) => rp_origin,
+        Err(e) => {
+            return Err(format!(
+                "{tracking_label} - \
+                invalid WEBAUTHN_RP_ORIGIN={rp_origin_str} \
+                with err='{e}'"
+            ));
+        }
+    };
+    let builder = match WebauthnBuilder::new(&rp_id, &rp_origin) {
+        Ok(builder) => builder,
+        Err(e) => {
+            return Err(format!(
+                "{tracking_label} - \
+                invalid WEBAUTHN_RP_ID={rp_id} for \
+                WEBAUTHN_RP_ORIGIN={rp_origin_str} \
+                with err='{e}'"
+            ));
+        }
+    };
+    match builder.rp_name(&rp_name).build() {
+        Ok(webauthn) => Ok(webauthn),
+        Err(e) => Err(format!(
+            "{tracking_label} - \
+            failed to build webauthn relying party \
+            rp_id={rp_id} with err='{e}'"
+        )),
+    }
+}
@@ -0,0 +1,19 @@
+//! Modules for passkey (webauthn) registration and login
+//!
+//! Passkeys let a user log in without a password by signing
+//! a server-issued challenge with a device-bound credential
+//! (see [webauthn-rs](https://docs.rs/webauthn-rs/latest/webauthn_rs/)).
+//!
+//! Each ceremony is split into a ``start`` call that returns
+//! a challenge for the browser's ``navigator.credentials`` api
+//! and a ``finish`` call that verifies the signed response. The
+//! in-flight challenge state is stored in the
+//! ``users_passkeys_challenges`` table between the two calls.
+//!
+pub mod consume_passkey_challenge;
+pub mod finish_passkey_login;
+pub mod finish_passkey_registration;
+pub mod get_webauthn;
+pub mod start_passkey_login;
+pub mod start_passkey_registration;
+pub mod upsert_passkey_challenge;
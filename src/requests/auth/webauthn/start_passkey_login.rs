@@ -0,0 +1,394 @@
+//! Module for starting a passkey (webauthn) login
+//!
+//! ## Start Passkey Login
+//!
+//! Create a webauthn authentication challenge for a user's registered passkeys. The client passes the returned ``challenge`` to ``navigator.credentials.get()`` and sends the result to the finish api.
+//!
+//! - URL path: ``/login/passkey/start``
+//! - Method: ``POST``
+//! - Handler: [`start_passkey_login`](crate::requests::auth::webauthn::start_passkey_login::start_passkey_login)
+//! - Request: [`ApiReqUserStartPasskeyLogin`](crate::requests::auth::webauthn::start_passkey_login::ApiReqUserStartPasskeyLogin)
+//! - Response: [`ApiResUserStartPasskeyLogin`](crate::requests::auth::webauthn::start_passkey_login::ApiResUserStartPasskeyLogin)
+//!
+
+use std::convert::Infallible;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Response;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use webauthn_rs::prelude::Passkey;
+
+use crate::core::core_config::CoreConfig;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::requests::auth::webauthn::get_webauthn::get_webauthn;
+use crate::requests::auth::webauthn::upsert_passkey_challenge::upsert_passkey_challenge;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::models::user::get_active_user_by_email;
+use crate::requests::models::user_passkey::get_user_passkeys;
+use crate::requests::validation::field_rules::check_email;
+use crate::requests::validation::normalize_email::normalize_email;
+use crate::requests::validation::validate_api_req::validate_api_req;
+use crate::requests::validation::validate_api_req::ApiFieldError;
+use crate::requests::validation::validate_api_req::ApiReqValidate;
+
+/// failure message for unknown emails and users without
+/// passkeys (the same response for both so the start and
+/// finish apis do not reveal which accounts exist)
+pub const PASSKEY_LOGIN_FAILED_MSG: &str =
+    "Passkey login failed - invalid email or passkey";
+
+/// ApiReqUserStartPasskeyLogin
+///
+/// # Request Type For start_passkey_login
+///
+/// Start a passkey login for a user
+///
+/// This type is the deserialized input for:
+/// [`start_passkey_login`](crate::requests::auth::webauthn::start_passkey_login::start_passkey_login)
+///
+/// # Usage
+///
+/// This type is constructed from the deserialized
+/// `bytes` (`&[u8]`) argument
+/// on the
+/// [`start_passkey_login`](crate::requests::auth::webauthn::start_passkey_login::start_passkey_login)
+/// function.
+///
+/// # Arguments
+///
+/// * `email` - `String` - unique user email
+///
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApiReqUserStartPasskeyLogin {
+    pub email: String,
+}
+
+impl ApiReqValidate for ApiReqUserStartPasskeyLogin {
+    /// validate
+    ///
+    /// Require a valid `email`
+    ///
+    fn validate(&self) -> Vec<ApiFieldError> {
+        let mut errors = Vec::new();
+        check_email(&mut errors, "email", &self.email);
+        errors
+    }
+}
+
+/// ApiResUserStartPasskeyLogin
+///
+/// # Response type for start_passkey_login
+///
+/// Return the webauthn authentication challenge for the client
+///
+/// # Usage
+///
+/// This type is the serialized output for the function:
+/// [`start_passkey_login`](crate::requests::auth::webauthn::start_passkey_login::start_passkey_login)
+/// and contained within the
+/// hyper [`Body`](hyper::Body)
+/// of the
+/// hyper [`Response`](hyper::Response)
+/// sent back to the client.
+///
+/// # Arguments
+///
+/// * `email` - `String` - unique user email
+/// * `challenge` - `serde_json::Value` - webauthn
+///   ``RequestChallengeResponse`` for
+///   ``navigator.credentials.get()`` (``null`` on failure)
+/// * `exp_date` - `String` - UTC-formatted date time string when
+///   the `challenge` expires
+/// * `msg` - `String` - help message
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   machine-readable failure code (omitted on success)
+///
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApiResUserStartPasskeyLogin {
+    pub email: String,
+    pub challenge: serde_json::Value,
+    pub exp_date: String,
+    pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ApiErrorCode>,
+}
+
+/// start_passkey_login
+///
+/// Handler for starting a passkey login. The user must be
+/// active (`users.state = 0`) and have at least one
+/// registered passkey. Unknown emails and users without
+/// passkeys get the same ``400`` with the
+/// ``INVALID_CREDENTIALS`` error code.
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
+///   db threadpool with required tls encryption
+/// * `_kafka_pool` -
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+///   for asynchronously publishing messages to the connected kafka cluster
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+/// * `bytes` - `&[u8]` - received bytes from the hyper
+///   [`Request`](hyper::Request)'s [`Body`](hyper::Body)
+///
+/// # Returns
+///
+/// ## start_passkey_login on Success Returns
+///
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResUserStartPasskeyLogin`](crate::requests::auth::webauthn::start_passkey_login::ApiResUserStartPasskeyLogin)
+/// dictionary within the
+/// [`Body`](hyper::Body) and a
+/// `201` HTTP status code
+///
+/// Ok([`Response`](hyper::Response))
+///
+/// # Errors
+///
+/// ## start_passkey_login on Failure Returns
+///
+/// All errors return as a
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResUserStartPasskeyLogin`](crate::requests::auth::webauthn::start_passkey_login::ApiResUserStartPasskeyLogin)
+/// dictionary with a
+/// `non-201` HTTP status code
+///
+/// Err([`Response`](hyper::Response))
+///
+pub async fn start_passkey_login(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    _kafka_pool: &KafkaPublisher,
+    headers: &HeaderMap<HeaderValue>,
+    bytes: &[u8],
+) -> std::result::Result<Response<Body>, Infallible> {
+    let mut req_object: ApiReqUserStartPasskeyLogin =
+        match serde_json::from_slice(bytes) {
+            Ok(uo) => uo,
+            Err(_) => {
+                let response = Response::builder()
+                    .status(400)
+                    .body(Body::from(
+                        serde_json::to_string(&ApiResUserStartPasskeyLogin {
+                            email: "".to_string(),
+                            challenge: serde_json::Value::Null,
+                            exp_date: "".to_string(),
+                            msg: ("Passkey login failed - please ensure \
+                                email was set correctly in the request")
+                                .to_string(),
+                            error_code: Some(ApiErrorCode::InvalidRequest),
+                        })
+                        .unwrap(),
+                    ))
+                    .unwrap();
+                return Ok(response);
+            }
+        };
+
+    req_object.email = normalize_email(&req_object.email);
+    if let Some(response) = validate_api_req(tracking_label, &req_object) {
+        return Ok(response);
+    }
+
+    let conn = db_pool.get().await.unwrap();
+    let tenant_id = match config
+        .tenants
+        .get_tenant_id(tracking_label, headers, &conn)
+        .await
+    {
+        Ok(tenant_id) => tenant_id,
+        Err(err_msg) => {
+            error!("{err_msg}");
+            let response = Response::builder()
+                .status(400)
+                .body(Body::from(
+                    serde_json::to_string(&ApiResUserStartPasskeyLogin {
+                        email: req_object.email.clone(),
+                        challenge: serde_json::Value::Null,
+                        exp_date: "".to_string(),
+                        msg: ("Passkey login failed - unknown tenant")
+                            .to_string(),
+                        error_code: Some(ApiErrorCode::UnknownTenant),
+                    })
+                    .unwrap(),
+                ))
+                .unwrap();
+            return Ok(response);
+        }
+    };
+    let user_model = match get_active_user_by_email(
+        tracking_label,
+        tenant_id,
+        &req_object.email,
+        &conn,
+    )
+    .await
+    {
+        Ok(user_model) => user_model,
+        Err(err_msg) => {
+            error!("{err_msg}");
+            let response = Response::builder()
+                .status(400)
+                .body(Body::from(
+                    serde_json::to_string(&ApiResUserStartPasskeyLogin {
+                        email: req_object.email.clone(),
+                        challenge: serde_json::Value::Null,
+                        exp_date: "".to_string(),
+                        msg: PASSKEY_LOGIN_FAILED_MSG.to_string(),
+                        error_code: Some(ApiErrorCode::InvalidCredentials),
+                    })
+                    .unwrap(),
+                ))
+                .unwrap();
+            return Ok(response);
+        }
+    };
+    let user_id = user_model.id;
+
+    let passkeys: Vec<Passkey> =
+        match get_user_passkeys(tracking_label, user_id, &conn).await {
+            Ok(passkeys) => passkeys.into_iter().map(|pk| pk.passkey).collect(),
+            Err(err_msg) => {
+                error!("{err_msg}");
+                Vec::new()
+            }
+        };
+    if passkeys.is_empty() {
+        error!(
+            "{tracking_label} - \
+            no passkeys are registered for user {user_id}"
+        );
+        let response = Response::builder()
+            .status(400)
+            .body(Body::from(
+                serde_json::to_string(&ApiResUserStartPasskeyLogin {
+                    email: req_object.email.clone(),
+                    challenge: serde_json::Value::Null,
+                    exp_date: "".to_string(),
+                    msg: PASSKEY_LOGIN_FAILED_MSG.to_string(),
+                    error_code: Some(ApiErrorCode::InvalidCredentials),
+                })
+                .unwrap(),
+            ))
+            .unwrap();
+        return Ok(response);
+    }
+
+    let webauthn = match get_webauthn(tracking_label) {
+        Ok(webauthn) => webauthn,
+        Err(err_msg) => {
+            error!("{err_msg}");
+            let response = Response::builder()
+                .status(500)
+                .body(Body::from(
+                    serde_json::to_string(&ApiResUserStartPasskeyLogin {
+                        email: req_object.email.clone(),
+                        challenge: serde_json::Value::Null,
+                        exp_date: "".to_string(),
+                        msg: ("Passkey login failed - \
+                            passkeys are not configured on the server")
+                            .to_string(),
+                        error_code: Some(ApiErrorCode::FeatureDisabled),
+                    })
+                    .unwrap(),
+                ))
+                .unwrap();
+            return Ok(response);
+        }
+    };
+
+    let (challenge, authentication_state) =
+        match webauthn.start_passkey_authentication(&passkeys) {
+            Ok(challenge_and_state) => challenge_and_state,
+            Err(e) => {
+                error!(
+                    "{tracking_label} - \
+                    failed to start passkey login for user {user_id} \
+                    with err='{e}'"
+                );
+                let response = Response::builder()
+                    .status(500)
+                    .body(Body::from(
+                        serde_json::to_string(&ApiResUserStartPasskeyLogin {
+                            email: req_object.email.clone(),
+                            challenge: serde_json::Value::Null,
+                            exp_date: "".to_string(),
+                            msg: format!(
+                                "Passkey login failed for email={} \
+                                with err='{e}'",
+                                req_object.email
+                            ),
+                            error_code: Some(ApiErrorCode::InternalError),
+                        })
+                        .unwrap(),
+                    ))
+                    .unwrap();
+                return Ok(response);
+            }
+        };
+
+    let exp_date = match upsert_passkey_challenge(
+        tracking_label,
+        user_id,
+        "login",
+        &serde_json::to_string(&authentication_state).unwrap(),
+        &conn,
+    )
+    .await
+    {
+        Ok(exp_date) => exp_date,
+        Err(err_msg) => {
+            error!("{err_msg}");
+            let response = Response::builder()
+                .status(500)
+                .body(Body::from(
+                    serde_json::to_string(&ApiResUserStartPasskeyLogin {
+                        email: req_object.email.clone(),
+                        challenge: serde_json::Value::Null,
+                        exp_date: "".to_string(),
+                        msg: format!(
+                            "Passkey login failed - \
+                            unable to store challenge for email={}",
+                            req_object.email
+                        ),
+                        error_code: Some(ApiErrorCode::InternalError),
+                    })
+                    .unwrap(),
+                ))
+                .unwrap();
+            return Ok(response);
+        }
+    };
+
+    let response = Response::builder()
+        .status(201)
+        .body(Body::from(
+            serde_json::to_string(&ApiResUserStartPasskeyLogin {
+                email: user_model.email,
+                challenge: serde_json::to_value(&challenge).unwrap(),
+                exp_date,
+                msg: "success".to_string(),
+                error_code: None,
+            })
+            .unwrap(),
+        ))
+        .unwrap();
+    Ok(response)
+}
@@ -0,0 +1,399 @@
+//! Module for starting a passkey (webauthn) registration
+//!
+//! ## Start Passkey Registration
+//!
+//! Create a webauthn registration challenge for a logged-in user. The client passes the returned ``challenge`` to ``navigator.credentials.create()`` and sends the result to the finish api.
+//!
+//! - URL path: ``/user/passkey/register/start``
+//! - Method: ``POST``
+//! - Handler: [`start_passkey_registration`](crate::requests::auth::webauthn::start_passkey_registration::start_passkey_registration)
+//! - Request: [`ApiReqUserStartPasskeyRegistration`](crate::requests::auth::webauthn::start_passkey_registration::ApiReqUserStartPasskeyRegistration)
+//! - Response: [`ApiResUserStartPasskeyRegistration`](crate::requests::auth::webauthn::start_passkey_registration::ApiResUserStartPasskeyRegistration)
+//!
+
+use std::convert::Infallible;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Response;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use webauthn_rs::prelude::CredentialID;
+use webauthn_rs::prelude::Uuid;
+
+use crate::core::core_config::CoreConfig;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::requests::auth::validate_user_token::validate_user_token;
+use crate::requests::auth::webauthn::get_webauthn::get_webauthn;
+use crate::requests::auth::webauthn::upsert_passkey_challenge::upsert_passkey_challenge;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::models::user::get_user_by_id;
+use crate::requests::models::user_passkey::get_user_passkeys;
+use crate::requests::validation::field_rules::check_id;
+use crate::requests::validation::validate_api_req::validate_api_req;
+use crate::requests::validation::validate_api_req::ApiFieldError;
+use crate::requests::validation::validate_api_req::ApiReqValidate;
+
+/// ApiReqUserStartPasskeyRegistration
+///
+/// # Request Type For start_passkey_registration
+///
+/// Start registering a new passkey for an existing user
+///
+/// This type is the deserialized input for:
+/// [`start_passkey_registration`](crate::requests::auth::webauthn::start_passkey_registration::start_passkey_registration)
+///
+/// # Usage
+///
+/// This type is constructed from the deserialized
+/// `bytes` (`&[u8]`) argument
+/// on the
+/// [`start_passkey_registration`](crate::requests::auth::webauthn::start_passkey_registration::start_passkey_registration)
+/// function.
+///
+/// # Arguments
+///
+/// * `user_id` - `i32` - user id
+///
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApiReqUserStartPasskeyRegistration {
+    pub user_id: i32,
+}
+
+impl ApiReqValidate for ApiReqUserStartPasskeyRegistration {
+    /// validate
+    ///
+    /// Require a positive `user_id`
+    ///
+    fn validate(&self) -> Vec<ApiFieldError> {
+        let mut errors = Vec::new();
+        check_id(&mut errors, "user_id", self.user_id);
+        errors
+    }
+}
+
+/// ApiResUserStartPasskeyRegistration
+///
+/// # Response type for start_passkey_registration
+///
+/// Return the webauthn registration challenge for the client
+///
+/// # Usage
+///
+/// This type is the serialized output for the function:
+/// [`start_passkey_registration`](crate::requests::auth::webauthn::start_passkey_registration::start_passkey_registration)
+/// and contained within the
+/// hyper [`Body`](hyper::Body)
+/// of the
+/// hyper [`Response`](hyper::Response)
+/// sent back to the client.
+///
+/// # Arguments
+///
+/// * `user_id` - `i32` - user id
+/// * `challenge` - `serde_json::Value` - webauthn
+///   ``CreationChallengeResponse`` for
+///   ``navigator.credentials.create()`` (``null`` on failure)
+/// * `exp_date` - `String` - UTC-formatted date time string when
+///   the `challenge` expires
+/// * `msg` - `String` - help message
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   machine-readable failure code (omitted on success)
+///
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApiResUserStartPasskeyRegistration {
+    pub user_id: i32,
+    pub challenge: serde_json::Value,
+    pub exp_date: String,
+    pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ApiErrorCode>,
+}
+
+/// start_passkey_registration
+///
+/// Handler for starting a passkey registration for a
+/// logged-in user. Passkeys the user already registered
+/// are excluded so the same authenticator is not
+/// registered twice.
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
+///   db threadpool with required tls encryption
+/// * `_kafka_pool` -
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+///   for asynchronously publishing messages to the connected kafka cluster
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+/// * `bytes` - `&[u8]` - received bytes from the hyper
+///   [`Request`](hyper::Request)'s [`Body`](hyper::Body)
+///
+/// # Returns
+///
+/// ## start_passkey_registration on Success Returns
+///
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResUserStartPasskeyRegistration`](crate::requests::auth::webauthn::start_passkey_registration::ApiResUserStartPasskeyRegistration)
+/// dictionary within the
+/// [`Body`](hyper::Body) and a
+/// `201` HTTP status code
+///
+/// Ok([`Response`](hyper::Response))
+///
+/// # Errors
+///
+/// ## start_passkey_registration on Failure Returns
+///
+/// All errors return as a
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResUserStartPasskeyRegistration`](crate::requests::auth::webauthn::start_passkey_registration::ApiResUserStartPasskeyRegistration)
+/// dictionary with a
+/// `non-201` HTTP status code
+///
+/// Err([`Response`](hyper::Response))
+///
+pub async fn start_passkey_registration(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    _kafka_pool: &KafkaPublisher,
+    headers: &HeaderMap<HeaderValue>,
+    bytes: &[u8],
+) -> std::result::Result<Response<Body>, Infallible> {
+    let req_object: ApiReqUserStartPasskeyRegistration =
+        match serde_json::from_slice(bytes) {
+            Ok(uo) => uo,
+            Err(_) => {
+                let response = Response::builder()
+                    .status(400)
+                    .body(Body::from(
+                        serde_json::to_string(
+                            &ApiResUserStartPasskeyRegistration {
+                                user_id: -1,
+                                challenge: serde_json::Value::Null,
+                                exp_date: "".to_string(),
+                                msg: ("Start passkey registration failed - \
+                                please ensure \
+                                user_id was set correctly in the request")
+                                    .to_string(),
+                                error_code: Some(ApiErrorCode::InvalidRequest),
+                            },
+                        )
+                        .unwrap(),
+                    ))
+                    .unwrap();
+                return Ok(response);
+            }
+        };
+
+    if let Some(response) = validate_api_req(tracking_label, &req_object) {
+        return Ok(response);
+    }
+
+    let user_id = req_object.user_id;
+    let conn = db_pool.get().await.unwrap();
+    let _token = match validate_user_token(
+        tracking_label,
+        config,
+        &conn,
+        headers,
+        user_id,
+    )
+    .await
+    {
+        Ok(_token) => _token,
+        Err(error_code) => {
+            let response = Response::builder()
+                .status(400)
+                .body(Body::from(
+                    serde_json::to_string(
+                        &ApiResUserStartPasskeyRegistration {
+                            user_id,
+                            challenge: serde_json::Value::Null,
+                            exp_date: "".to_string(),
+                            msg: ("Start passkey registration failed \
+                            due to invalid token")
+                                .to_string(),
+                            error_code: Some(error_code),
+                        },
+                    )
+                    .unwrap(),
+                ))
+                .unwrap();
+            return Ok(response);
+        }
+    };
+
+    let user_model = match get_user_by_id(tracking_label, user_id, &conn).await
+    {
+        Ok(user_model) => user_model,
+        Err(err_msg) => {
+            error!(
+                "{tracking_label} - \
+                failed to start passkey registration for user {user_id} \
+                with err='{err_msg}'"
+            );
+            let response = Response::builder()
+                .status(400)
+                .body(Body::from(
+                    serde_json::to_string(
+                        &ApiResUserStartPasskeyRegistration {
+                            user_id,
+                            challenge: serde_json::Value::Null,
+                            exp_date: "".to_string(),
+                            msg: format!(
+                                "Start passkey registration failed - \
+                            unable to find user with id: {user_id}"
+                            ),
+                            error_code: Some(ApiErrorCode::UserNotFound),
+                        },
+                    )
+                    .unwrap(),
+                ))
+                .unwrap();
+            return Ok(response);
+        }
+    };
+
+    let webauthn = match get_webauthn(tracking_label) {
+        Ok(webauthn) => webauthn,
+        Err(err_msg) => {
+            error!("{err_msg}");
+            let response = Response::builder()
+                .status(500)
+                .body(Body::from(
+                    serde_json::to_string(
+                        &ApiResUserStartPasskeyRegistration {
+                            user_id,
+                            challenge: serde_json::Value::Null,
+                            exp_date: "".to_string(),
+                            msg: ("Start passkey registration failed - \
+                            passkeys are not configured on the server")
+                                .to_string(),
+                            error_code: Some(ApiErrorCode::FeatureDisabled),
+                        },
+                    )
+                    .unwrap(),
+                ))
+                .unwrap();
+            return Ok(response);
+        }
+    };
+
+    // exclude any passkeys the user already registered
+    let exclude_credentials: Vec<CredentialID> =
+        match get_user_passkeys(tracking_label, user_id, &conn).await {
+            Ok(passkeys) => passkeys
+                .iter()
+                .map(|pk| pk.passkey.cred_id().clone())
+                .collect(),
+            Err(err_msg) => {
+                error!("{err_msg}");
+                Vec::new()
+            }
+        };
+
+    // the webauthn user handle is derived from the user id so
+    // it stays stable without storing another identifier
+    let user_handle = Uuid::new_v5(
+        &Uuid::NAMESPACE_OID,
+        format!("user-{user_id}").as_bytes(),
+    );
+    let (challenge, registration_state) = match webauthn
+        .start_passkey_registration(
+            user_handle,
+            &user_model.email,
+            &user_model.email,
+            Some(exclude_credentials),
+        ) {
+        Ok(challenge_and_state) => challenge_and_state,
+        Err(e) => {
+            error!(
+                "{tracking_label} - \
+                failed to start passkey registration for user {user_id} \
+                with err='{e}'"
+            );
+            let response = Response::builder()
+                .status(500)
+                .body(Body::from(
+                    serde_json::to_string(
+                        &ApiResUserStartPasskeyRegistration {
+                            user_id,
+                            challenge: serde_json::Value::Null,
+                            exp_date: "".to_string(),
+                            msg: format!(
+                                "Start passkey registration failed \
+                            for user_id={user_id} with err='{e}'"
+                            ),
+                            error_code: Some(ApiErrorCode::InternalError),
+                        },
+                    )
+                    .unwrap(),
+                ))
+                .unwrap();
+            return Ok(response);
+        }
+    };
+
+    let exp_date = match upsert_passkey_challenge(
+        tracking_label,
+        user_id,
+        "register",
+        &serde_json::to_string(&registration_state).unwrap(),
+        &conn,
+    )
+    .await
+    {
+        Ok(exp_date) => exp_date,
+        Err(err_msg) => {
+            error!("{err_msg}");
+            let response = Response::builder()
+                .status(500)
+                .body(Body::from(
+                    serde_json::to_string(
+                        &ApiResUserStartPasskeyRegistration {
+                            user_id,
+                            challenge: serde_json::Value::Null,
+                            exp_date: "".to_string(),
+                            msg: format!(
+                                "Start passkey registration failed - \
+                            unable to store challenge for user_id={user_id}"
+                            ),
+                            error_code: Some(ApiErrorCode::InternalError),
+                        },
+                    )
+                    .unwrap(),
+                ))
+                .unwrap();
+            return Ok(response);
+        }
+    };
+
+    let response = Response::builder()
+        .status(201)
+        .body(Body::from(
+            serde_json::to_string(&ApiResUserStartPasskeyRegistration {
+                user_id,
+                challenge: serde_json::to_value(&challenge).unwrap(),
+                exp_date,
+                msg: "success".to_string(),
+                error_code: None,
+            })
+            .unwrap(),
+        ))
+        .unwrap();
+    Ok(response)
+}
@@ -0,0 +1,108 @@
+//! Module for storing a user's in-flight passkey challenge state
+//!
+//! The ``start`` registration and login apis call
+//! [`upsert_passkey_challenge`](crate::requests::auth::webauthn::upsert_passkey_challenge::upsert_passkey_challenge)
+//! to keep the server side of a webauthn ceremony in the
+//! ``users_passkeys_challenges`` table until the browser
+//! sends the signed response to the ``finish`` api. The table
+//! holds one row per user and ceremony (``register`` or
+//! ``login``) with an expiration date from
+//! ``WEBAUTHN_CHALLENGE_EXP_IN_SECONDS``.
+//!
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::PooledConnection;
+use bb8_postgres::PostgresConnectionManager;
+
+use crate::monitoring::otel::trace_db_query;
+
+/// upsert_passkey_challenge
+///
+/// Store the serialized webauthn ceremony state for a user
+/// between the ``start`` and ``finish`` calls. Each user
+/// has at most one in-flight challenge per ``ceremony``, so
+/// starting a new ceremony replaces any older challenge.
+///
+/// # Usage
+///
+/// ## Environment variables with default values (bash):
+///
+/// ```bash
+/// # number of seconds a passkey challenge is valid
+/// export WEBAUTHN_CHALLENGE_EXP_IN_SECONDS=300
+/// ```
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `user_id` - `i32` - user id in the db
+/// * `ceremony` - `&str` - `register` or `login`
+/// * `challenge_state` - `&str` - json-serialized
+///   [`PasskeyRegistration`](webauthn_rs::prelude::PasskeyRegistration)
+///   or
+///   [`PasskeyAuthentication`](webauthn_rs::prelude::PasskeyAuthentication)
+/// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+///   an established db connection from the
+///   postgres client db threadpool
+///
+/// # Returns
+///
+/// ## upsert_passkey_challenge on Success Returns
+///
+/// `Ok(exp_date: String)` - UTC-formatted date time string
+/// when the challenge expires
+///
+/// # Errors
+///
+/// `Err(String)` if the db insert fails
+///
+pub async fn upsert_passkey_challenge(
+    tracking_label: &str,
+    user_id: i32,
+    ceremony: &str,
+    challenge_state: &str,
+    conn: &PooledConnection<'_, PostgresConnectionManager<MakeTlsConnector>>,
+) -> Result<String, String> {
+    let challenge_expiration_in_seconds_str =
+        std::env::var("WEBAUTHN_CHALLENGE_EXP_IN_SECONDS")
+            .unwrap_or_else(|_| "300".to_string());
+    let challenge_expiration_in_seconds: i64 =
+        challenge_expiration_in_seconds_str
+            .parse::<i64>()
+            .unwrap_or(300);
+    let now = chrono::Utc::now();
+    let challenge_expiration_timestamp =
+        now + chrono::Duration::seconds(challenge_expiration_in_seconds);
+    let escaped_challenge_state = challenge_state.replace('\'', "''");
+
+    let cur_query = format!(
+        "INSERT INTO \
+            users_passkeys_challenges (\
+                user_id, \
+                ceremony, \
+                challenge_state, \
+                exp_date) \
+        VALUES (\
+            {user_id}, \
+            '{ceremony}', \
+            '{escaped_challenge_state}', \
+            '{challenge_expiration_timestamp}') \
+        ON CONFLICT (user_id, ceremony) \
+        DO UPDATE SET \
+            challenge_state = EXCLUDED.challenge_state, \
+            exp_date = EXCLUDED.exp_date, \
+            created_at = timezone('UTC'::text, now());"
+    );
+    let stmt = conn.prepare(&cur_query).await.unwrap();
+    match trace_db_query(&cur_query, conn.query(&stmt, &[])).await {
+        Ok(_) => Ok(format!(
+            "{}",
+            challenge_expiration_timestamp.format("%Y-%m-%dT%H:%M:%SZ")
+        )),
+        Err(e) => Err(format!(
+            "{tracking_label} - \
+            failed to store passkey {ceremony} challenge \
+            for user_id={user_id} with err='{e}'"
+        )),
+    }
+}
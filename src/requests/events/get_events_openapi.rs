@@ -0,0 +1,56 @@
+//! Module for publishing the user event schemas
+//!
+//! ## Get the User Event Schemas
+//!
+//! Get an OpenAPI document describing every user event published to kafka (``KAFKA_TOPIC_USER_EVENTS`` or the ``USER_EVENTS_TOPIC_*`` topic for the event), including the payload format and the ``key=value`` details for each event name. Rust consumers can parse payloads with [`UserEvent`](crate::events::user_event::UserEvent) instead of copying the schemas.
+//!
+//! - URL path: ``/openapi/events.json``
+//! - Method: ``GET``
+//! - Handler: [`get_events_openapi`](crate::requests::events::get_events_openapi::get_events_openapi)
+//! - Request: none
+//! - Response: [`build_events_openapi`](crate::events::events_openapi::build_events_openapi)
+//!
+use std::convert::Infallible;
+
+use hyper::Body;
+use hyper::Response;
+
+use crate::core::core_config::CoreConfig;
+use crate::events::events_openapi::build_events_openapi;
+
+/// get_events_openapi
+///
+/// Handler for returning the user event schemas as an
+/// OpenAPI document built with
+/// [`build_events_openapi`](crate::events::events_openapi::build_events_openapi).
+///
+/// This api does not require a token.
+///
+/// # Arguments
+///
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+///
+/// # Returns
+///
+/// ## get_events_openapi on Success Returns
+///
+/// hyper [`Response`](hyper::Response)
+/// containing the json-serialized OpenAPI document
+/// within the
+/// [`Body`](hyper::Body) and a
+/// `200` HTTP status code
+///
+/// Ok([`Response`](hyper::Response))
+///
+pub async fn get_events_openapi(
+    config: &CoreConfig,
+) -> std::result::Result<Response<Body>, Infallible> {
+    let doc = build_events_openapi(&config.events);
+    let response = Response::builder()
+        .status(200)
+        .header("Content-Type", "application/json")
+        .header("Cache-Control", "public, max-age=300")
+        .body(Body::from(serde_json::to_string(&doc).unwrap()))
+        .unwrap();
+    Ok(response)
+}
@@ -0,0 +1,3 @@
+//! Modules for documenting the published user events
+//!
+pub mod get_events_openapi;
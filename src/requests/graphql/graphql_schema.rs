@@ -0,0 +1,451 @@
+//! GraphQL schema for the user and user data models
+//!
+//! The queries and mutations reuse the REST handlers'
+//! request validation and the
+//! [`UserDataRepo`](crate::requests::models::user_data_repo::UserDataRepo)
+//! model layer, so they follow the same access rules. Every
+//! resolver runs as the user that owns the request's token
+//! (see
+//! [`handle_graphql`](crate::requests::graphql::handle_graphql::handle_graphql)).
+//! Scoped tokens (see
+//! [`token_scopes`](crate::requests::auth::token_scopes))
+//! need ``user:read`` for ``me`` and ``userQuota``,
+//! ``data:read`` for ``userData`` and ``data:write`` for the
+//! mutations.
+//!
+//! ```graphql
+//! type Query {
+//!   me: User!
+//!   userData(filter: UserDataFilter): [UserData!]!
+//!   userQuota: UserDataQuota!
+//! }
+//!
+//! type Mutation {
+//!   updateUserData(input: UserDataUpdate!): UserData!
+//!   deleteUserData(dataId: Int!, version: Int!): UserData!
+//! }
+//! ```
+//!
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use async_graphql::Context;
+use async_graphql::EmptySubscription;
+use async_graphql::Error;
+use async_graphql::ErrorExtensions;
+use async_graphql::InputObject;
+use async_graphql::Object;
+use async_graphql::Schema;
+
+use lazy_static::lazy_static;
+
+use crate::core::core_config::CoreConfig;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::pools::db_read_pools::DbReadPools;
+use crate::requests::auth::token_scopes::is_scope_allowed;
+use crate::requests::models::api_error::ApiError;
+use crate::requests::models::user::get_user_by_id;
+use crate::requests::models::user::ModelUser;
+use crate::requests::models::user_data::ModelUserData;
+use crate::requests::models::user_data_quota::get_user_data_quota;
+use crate::requests::models::user_data_quota::ModelUserDataQuota;
+use crate::requests::models::user_data_repo::UserDataRepo;
+use crate::requests::user::search_user_data::ApiReqUserSearchData;
+use crate::requests::user::update_user_data::ApiReqUserUpdateData;
+use crate::requests::validation::field_rules::check_id;
+use crate::requests::validation::validate_api_req::ApiFieldError;
+use crate::requests::validation::validate_api_req::ApiReqValidate;
+
+/// max nested selections in one GraphQL query
+pub const GRAPHQL_MAX_DEPTH: usize = 8;
+
+/// max fields (complexity) in one GraphQL query
+pub const GRAPHQL_MAX_COMPLEXITY: usize = 500;
+
+/// the server's GraphQL schema
+pub type ApiGraphqlSchema = Schema<QueryRoot, MutationRoot, EmptySubscription>;
+
+lazy_static! {
+    pub static ref GRAPHQL_SCHEMA: ApiGraphqlSchema =
+        Schema::build(QueryRoot, MutationRoot, EmptySubscription)
+            .limit_depth(GRAPHQL_MAX_DEPTH)
+            .limit_complexity(GRAPHQL_MAX_COMPLEXITY)
+            .finish();
+}
+
+/// GraphqlContext
+///
+/// Request data every resolver reads with
+/// [`Context::data`](async_graphql::Context::data)
+///
+/// # Arguments
+///
+/// * `tracking_label` - `String` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
+///   db threadpool with required tls encryption
+/// * `db_read_pools` - [`DbReadPools`](crate::pools::db_read_pools::DbReadPools) -
+///   read replicas for the queries
+/// * `kafka_pool` -
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+///   for asynchronously publishing messages to the connected kafka cluster
+/// * `user_id` - `i32` - user that owns the request's token
+/// * `role` - `String` - the user's role
+/// * `scope_claim` - `String` - space-separated ``scope``
+///   claim from the request's token (empty = an unscoped
+///   token)
+///
+pub struct GraphqlContext {
+    pub tracking_label: String,
+    pub config: CoreConfig,
+    pub db_pool: Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    pub db_read_pools: DbReadPools,
+    pub kafka_pool: KafkaPublisher,
+    pub user_id: i32,
+    pub role: String,
+    pub scope_claim: String,
+}
+
+impl GraphqlContext {
+    /// check_scope
+    ///
+    /// Check the token's ``scope`` claim allows a resolver's
+    /// scope with
+    /// [`is_scope_allowed`](crate::requests::auth::token_scopes::is_scope_allowed)
+    ///
+    /// # Arguments
+    ///
+    /// * `required_scope` - `&str` - scope the resolver needs
+    ///
+    /// # Errors
+    ///
+    /// A GraphQL error with the ``INSUFFICIENT_SCOPE`` code
+    /// and a ``403`` ``status`` extension
+    ///
+    pub fn check_scope(&self, required_scope: &str) -> Result<(), Error> {
+        if is_scope_allowed(&self.scope_claim, required_scope) {
+            return Ok(());
+        }
+        error!(
+            "{} - graphql request for user_id={} \
+            missing scope={required_scope}",
+            self.tracking_label, self.user_id
+        );
+        self.config.auth_alerts.record_failure(
+            &self.tracking_label,
+            "token",
+            "insufficient_scope",
+        );
+        Err(
+            Error::new(format!("token is missing the {required_scope} scope"))
+                .extend_with(|_, ext| {
+                    ext.set("code", "INSUFFICIENT_SCOPE");
+                    ext.set("status", 403);
+                }),
+        )
+    }
+}
+
+/// get_graphql_error
+///
+/// Convert an
+/// [`ApiError`](crate::requests::models::api_error::ApiError)
+/// into a GraphQL error with the REST api's HTTP status code
+/// in the ``status`` extension
+///
+/// # Arguments
+///
+/// * `e` - [`ApiError`](crate::requests::models::api_error::ApiError)
+///
+pub fn get_graphql_error(e: ApiError) -> Error {
+    let code = match e {
+        ApiError::NotFound(_) => "NOT_FOUND",
+        ApiError::Conflict(_) => "CONFLICT",
+        ApiError::VersionConflict(_) => "VERSION_CONFLICT",
+        ApiError::Timeout(_) => "QUERY_TIMEOUT",
+        ApiError::Db(_) => "INTERNAL_SERVER_ERROR",
+    };
+    let status = e.status_code();
+    Error::new(format!("{e}")).extend_with(|_, ext| {
+        ext.set("code", code);
+        ext.set("status", status);
+    })
+}
+
+/// get_graphql_validation_error
+///
+/// Convert the invalid fields from
+/// [`ApiReqValidate`](crate::requests::validation::validate_api_req::ApiReqValidate)
+/// into a GraphQL error with a ``422`` ``status`` extension
+///
+/// # Arguments
+///
+/// * `errors` - `Vec<`[`ApiFieldError`](crate::requests::validation::validate_api_req::ApiFieldError)`>` -
+///   invalid fields
+///
+pub fn get_graphql_validation_error(errors: Vec<ApiFieldError>) -> Error {
+    let msg = errors
+        .iter()
+        .map(|e| format!("{} {}", e.field, e.msg))
+        .collect::<Vec<String>>()
+        .join(", ");
+    Error::new(format!("Request validation failed - {msg}")).extend_with(
+        |_, ext| {
+            ext.set("code", "BAD_USER_INPUT");
+            ext.set("status", 422);
+        },
+    )
+}
+
+/// UserDataFilter
+///
+/// Filters for the `userData` query (the same filters as
+/// [`ApiReqUserSearchData`](crate::requests::user::search_user_data::ApiReqUserSearchData))
+///
+#[derive(InputObject, Default)]
+pub struct UserDataFilter {
+    pub creator_user_id: Option<i32>,
+    pub data_id: Option<i32>,
+    pub filename: Option<String>,
+    pub data_type: Option<String>,
+    pub above_bytes: Option<i64>,
+    pub below_bytes: Option<i64>,
+    pub comments: Option<String>,
+    pub encoding: Option<String>,
+    pub sloc: Option<String>,
+    pub status: Option<String>,
+    pub tags: Option<Vec<String>>,
+    pub folder: Option<String>,
+    pub include_subfolders: Option<bool>,
+    pub include_archived: Option<bool>,
+    pub versions_of: Option<i32>,
+    pub include_versions: Option<bool>,
+}
+
+/// UserDataUpdate
+///
+/// Changes for the `updateUserData` mutation (the same
+/// fields as
+/// [`ApiReqUserUpdateData`](crate::requests::user::update_user_data::ApiReqUserUpdateData))
+///
+#[derive(InputObject)]
+pub struct UserDataUpdate {
+    pub data_id: i32,
+    pub filename: Option<String>,
+    pub data_type: Option<String>,
+    pub comments: Option<String>,
+    pub encoding: Option<String>,
+    pub sloc: Option<String>,
+    pub tags: Option<Vec<String>>,
+    pub folder: Option<String>,
+    pub version: i32,
+}
+
+/// QueryRoot
+///
+/// GraphQL queries for the token's user
+///
+pub struct QueryRoot;
+
+#[Object(name = "Query")]
+impl QueryRoot {
+    /// the user that owns the request's token
+    async fn me(&self, ctx: &Context<'_>) -> Result<ModelUser, Error> {
+        let gql = ctx.data::<GraphqlContext>()?;
+        gql.check_scope("user:read")?;
+        let conn = gql.db_pool.get().await?;
+        let read_conn =
+            gql.db_read_pools.get_read_conn(&gql.tracking_label).await;
+        let read_conn = read_conn.as_ref().unwrap_or(&conn);
+        let user_model =
+            get_user_by_id(&gql.tracking_label, gql.user_id, read_conn)
+                .await
+                .map_err(|err_msg| {
+                    get_graphql_error(ApiError::NotFound(err_msg))
+                })?;
+        gql.config
+            .events
+            .publish_user_event(&gql.kafka_pool, gql.user_id, "USER_GET", "")
+            .await;
+        Ok(user_model)
+    }
+
+    /// up to 100 user data records the user can read
+    /// (newest first)
+    async fn user_data(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<UserDataFilter>,
+    ) -> Result<Vec<ModelUserData>, Error> {
+        let gql = ctx.data::<GraphqlContext>()?;
+        gql.check_scope("data:read")?;
+        let filter = filter.unwrap_or_default();
+        let search = ApiReqUserSearchData {
+            user_id: gql.user_id,
+            creator_user_id: filter.creator_user_id,
+            data_id: filter.data_id,
+            filename: filter.filename,
+            data_type: filter.data_type,
+            above_bytes: filter.above_bytes,
+            below_bytes: filter.below_bytes,
+            comments: filter.comments,
+            encoding: filter.encoding,
+            sloc: filter.sloc,
+            status: filter.status,
+            tags: filter.tags,
+            folder: filter.folder,
+            include_subfolders: filter.include_subfolders,
+            include_archived: filter.include_archived,
+            versions_of: filter.versions_of,
+            include_versions: filter.include_versions,
+        };
+        let errors = search.validate();
+        if !errors.is_empty() {
+            return Err(get_graphql_validation_error(errors));
+        }
+        let conn = gql.db_pool.get().await?;
+        let read_conn =
+            gql.db_read_pools.get_read_conn(&gql.tracking_label).await;
+        let read_conn = read_conn.as_ref().unwrap_or(&conn);
+        let row_list = UserDataRepo::new(read_conn)
+            .search(
+                &gql.tracking_label,
+                gql.user_id,
+                &gql.role,
+                &search.get_search(),
+            )
+            .await
+            .map_err(get_graphql_error)?;
+        gql.config
+            .events
+            .publish_user_event(
+                &gql.kafka_pool,
+                gql.user_id,
+                "SEARCH_USER_DATA",
+                "",
+            )
+            .await;
+        Ok(row_list)
+    }
+
+    /// the user's storage quota and usage
+    async fn user_quota(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<ModelUserDataQuota, Error> {
+        let gql = ctx.data::<GraphqlContext>()?;
+        gql.check_scope("user:read")?;
+        let conn = gql.db_pool.get().await?;
+        let quota = get_user_data_quota(
+            &gql.tracking_label,
+            gql.user_id,
+            gql.config.user_data_quota.default_quota_bytes,
+            &conn,
+        )
+        .await
+        .map_err(get_graphql_error)?;
+        gql.config
+            .events
+            .publish_user_event(
+                &gql.kafka_pool,
+                gql.user_id,
+                "USER_GET_QUOTA",
+                "",
+            )
+            .await;
+        Ok(quota)
+    }
+}
+
+/// MutationRoot
+///
+/// GraphQL mutations for the token's user
+///
+pub struct MutationRoot;
+
+#[Object(name = "Mutation")]
+impl MutationRoot {
+    /// update a user data record's metadata (requires the
+    /// record's current version)
+    async fn update_user_data(
+        &self,
+        ctx: &Context<'_>,
+        input: UserDataUpdate,
+    ) -> Result<ModelUserData, Error> {
+        let gql = ctx.data::<GraphqlContext>()?;
+        gql.check_scope("data:write")?;
+        let update = ApiReqUserUpdateData {
+            user_id: gql.user_id,
+            data_id: input.data_id,
+            filename: input.filename,
+            data_type: input.data_type,
+            comments: input.comments,
+            encoding: input.encoding,
+            sloc: input.sloc,
+            tags: input.tags,
+            folder: input.folder,
+            version: Some(input.version),
+        };
+        let errors = update.validate();
+        if !errors.is_empty() {
+            return Err(get_graphql_validation_error(errors));
+        }
+        let conn = gql.db_pool.get().await?;
+        let updated_data = UserDataRepo::new(&conn)
+            .update(
+                &gql.tracking_label,
+                gql.user_id,
+                &gql.role,
+                update.data_id,
+                &update.get_changes(),
+            )
+            .await
+            .map_err(get_graphql_error)?;
+        gql.config
+            .events
+            .publish_user_event(
+                &gql.kafka_pool,
+                gql.user_id,
+                "USER_UPDATE_DATA",
+                "",
+            )
+            .await;
+        Ok(updated_data)
+    }
+
+    /// delete a ``ready``, ``quarantined`` or ``failed`` user
+    /// data record the user owns (requires the record's
+    /// current version)
+    async fn delete_user_data(
+        &self,
+        ctx: &Context<'_>,
+        data_id: i32,
+        version: i32,
+    ) -> Result<ModelUserData, Error> {
+        let gql = ctx.data::<GraphqlContext>()?;
+        gql.check_scope("data:write")?;
+        let mut errors = Vec::new();
+        check_id(&mut errors, "data_id", data_id);
+        check_id(&mut errors, "version", version);
+        if !errors.is_empty() {
+            return Err(get_graphql_validation_error(errors));
+        }
+        let conn = gql.db_pool.get().await?;
+        let deleted_data = UserDataRepo::new(&conn)
+            .delete(&gql.tracking_label, gql.user_id, data_id, Some(version))
+            .await
+            .map_err(get_graphql_error)?;
+        gql.config
+            .events
+            .publish_user_event(
+                &gql.kafka_pool,
+                gql.user_id,
+                "USER_BATCH_DATA",
+                "updated=0 moved=0 deleted=1",
+            )
+            .await;
+        Ok(deleted_data)
+    }
+}
@@ -0,0 +1,249 @@
+//! Module for serving the GraphQL facade
+//!
+//! ## Run a GraphQL query or mutation
+//!
+//! Run a GraphQL request against the [`GRAPHQL_SCHEMA`](crate::requests::graphql::graphql_schema::GRAPHQL_SCHEMA) as the user that owns the request's token (requires building with ``--features graphql``)
+//!
+//! - URL path: ``/graphql``
+//! - Method: ``POST``
+//! - Handler: [`handle_graphql`](crate::requests::graphql::handle_graphql::handle_graphql)
+//! - Request: a json GraphQL request (``query``, optional ``variables`` and ``operationName``)
+//! - Response: a json GraphQL response (``data`` and ``errors``)
+//!
+//! ## Get the GraphQL schema
+//!
+//! Get the schema in the GraphQL schema definition language (no login required)
+//!
+//! - URL path: ``/graphql``
+//! - Method: ``GET``
+//! - Handler: [`get_graphql_schema`](crate::requests::graphql::handle_graphql::get_graphql_schema)
+//! - Request: none
+//! - Response: ``text/plain`` schema
+//!
+use std::convert::Infallible;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Response;
+
+use crate::core::core_config::CoreConfig;
+use crate::jwt::api as jwt_api;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::pools::db_read_pools::DbReadPools;
+use crate::requests::auth::validate_user_token::validate_user_token;
+use crate::requests::graphql::graphql_schema::GraphqlContext;
+use crate::requests::graphql::graphql_schema::GRAPHQL_SCHEMA;
+use crate::requests::models::user::get_user_by_id;
+use crate::requests::models::user_session::get_user_session_by_token;
+
+/// get_graphql_error_response
+///
+/// Build a GraphQL-shaped error response for requests that
+/// fail before the schema runs
+///
+/// # Arguments
+///
+/// * `status` - `u16` - HTTP status code
+/// * `msg` - `&str` - error message
+///
+fn get_graphql_error_response(status: u16, msg: &str) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(Body::from(
+            serde_json::json!({
+                "data": null,
+                "errors": [{"message": msg}],
+            })
+            .to_string(),
+        ))
+        .unwrap()
+}
+
+/// get_graphql_schema
+///
+/// Return the
+/// [`GRAPHQL_SCHEMA`](crate::requests::graphql::graphql_schema::GRAPHQL_SCHEMA)
+/// in the GraphQL schema definition language for client
+/// code generators
+///
+/// # Returns
+///
+/// hyper [`Response`](hyper::Response)
+/// containing the ``text/plain`` schema and a
+/// `200` HTTP status code
+///
+pub async fn get_graphql_schema(
+) -> std::result::Result<Response<Body>, Infallible> {
+    Ok(Response::builder()
+        .status(200)
+        .header("Content-Type", "text/plain")
+        .body(Body::from(GRAPHQL_SCHEMA.sdl()))
+        .unwrap())
+}
+
+/// handle_graphql
+///
+/// Handles running a GraphQL query or mutation from the
+/// POST-ed hyper [`Request`](hyper::Request)'s
+/// [`Body`](hyper::Body) as the user that owns the token
+/// header (see
+/// [`graphql_schema`](crate::requests::graphql::graphql_schema)).
+/// The token's ``scope`` claim is passed to the resolvers,
+/// which check the scope for the model they read or change.
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
+///   db threadpool with required tls encryption
+/// * `db_read_pools` - [`DbReadPools`](crate::pools::db_read_pools::DbReadPools) -
+///   read replicas for the queries
+/// * `kafka_pool` -
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+///   for asynchronously publishing messages to the connected kafka cluster
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+/// * `bytes` - `&[u8]` - received bytes from the hyper
+///   [`Request`](hyper::Request)'s [`Body`](hyper::Body)
+///
+/// # Returns
+///
+/// ## handle_graphql on Success Returns
+///
+/// hyper [`Response`](hyper::Response)
+/// containing a json GraphQL response within the
+/// [`Body`](hyper::Body) and a
+/// `200` HTTP status code (resolver errors are in the
+/// response's ``errors`` with the REST api's HTTP status
+/// code in each error's ``extensions.status``)
+///
+/// Ok([`Response`](hyper::Response))
+///
+/// # Errors
+///
+/// ## handle_graphql on Failure Returns
+///
+/// A json GraphQL response with only ``errors`` and a
+/// `400` HTTP status code for an invalid request body or
+/// token
+///
+/// Err([`Response`](hyper::Response))
+///
+pub async fn handle_graphql(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    db_read_pools: &DbReadPools,
+    kafka_pool: &KafkaPublisher,
+    headers: &HeaderMap<HeaderValue>,
+    bytes: &[u8],
+) -> std::result::Result<Response<Body>, Infallible> {
+    let gql_request: async_graphql::Request =
+        match serde_json::from_slice(bytes) {
+            Ok(gql_request) => gql_request,
+            Err(_) => {
+                return Ok(get_graphql_error_response(
+                    400,
+                    "GraphQL request failed - please ensure the body is \
+                    json with a query and optional variables and \
+                    operationName",
+                ));
+            }
+        };
+
+    let token = &config.token_cookie.get_request_token(headers);
+    let conn = db_pool.get().await.unwrap();
+    let user_id =
+        match get_user_session_by_token(tracking_label, token, &conn).await {
+            Ok((_, user_id)) => user_id,
+            Err(_) => -1,
+        };
+    let valid_token = user_id > 0
+        && validate_user_token(tracking_label, config, &conn, headers, user_id)
+            .await
+            .is_ok();
+    if !valid_token {
+        return Ok(get_graphql_error_response(
+            400,
+            "GraphQL request failed due to invalid token",
+        ));
+    }
+    let user_model = match get_user_by_id(tracking_label, user_id, &conn).await
+    {
+        Ok(user_model) => user_model,
+        Err(err_msg) => {
+            error!(
+                "{tracking_label} - \
+                failed to find user {user_id} for graphql \
+                with err='{err_msg}'"
+            );
+            return Ok(get_graphql_error_response(
+                400,
+                &format!(
+                    "GraphQL request failed - \
+                    unable to find user with id: {user_id}"
+                ),
+            ));
+        }
+    };
+    // the resolvers get their own connections
+    drop(conn);
+
+    // each resolver checks the token's scope claim
+    let retired_kids = config.get_settings().jwt_retired_kids;
+    let jwt_keys = config
+        .jwt_keys
+        .read()
+        .unwrap()
+        .without_retired_kids(&retired_kids);
+    let scope_claim = match jwt_api::validate_token_with_keys(
+        tracking_label,
+        token,
+        &user_model.email,
+        &jwt_keys,
+    )
+    .await
+    {
+        Ok(token_data) => token_data.claims.scope,
+        Err(err_msg) => {
+            error!("{err_msg}");
+            return Ok(get_graphql_error_response(
+                400,
+                "GraphQL request failed due to invalid token",
+            ));
+        }
+    };
+
+    let gql_response = GRAPHQL_SCHEMA
+        .execute(gql_request.data(GraphqlContext {
+            tracking_label: tracking_label.to_string(),
+            config: config.clone(),
+            db_pool: db_pool.clone(),
+            db_read_pools: db_read_pools.clone(),
+            kafka_pool: kafka_pool.clone(),
+            user_id,
+            role: user_model.role,
+            scope_claim,
+        }))
+        .await;
+    if gql_response.is_err() {
+        info!(
+            "{tracking_label} - graphql request for user_id={user_id} \
+            returned {} errors",
+            gql_response.errors.len()
+        );
+    }
+    Ok(Response::builder()
+        .status(200)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string(&gql_response).unwrap()))
+        .unwrap())
+}
@@ -0,0 +1,5 @@
+//! Optional GraphQL facade over the user and user data
+//! models (requires building with ``--features graphql``)
+//!
+pub mod graphql_schema;
+pub mod handle_graphql;
@@ -1,5 +1,10 @@
 //! Modules for supported HTTP API requests
 //!
+pub mod admin;
 pub mod auth;
+pub mod events;
+#[cfg(feature = "graphql")]
+pub mod graphql;
 pub mod models;
 pub mod user;
+pub mod validation;
@@ -0,0 +1,219 @@
+//! Model for the aggregate stats on the admin dashboard
+//!
+//! [`get_admin_stats`](crate::requests::models::admin_stats::get_admin_stats)
+//! counts every tenant's users, uploads and logins with
+//! three aggregate queries. Logins are the ``users_tokens``
+//! sessions created in each hour (password and passkey
+//! logins, new users and accepted invites).
+//!
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::PooledConnection;
+use bb8_postgres::PostgresConnectionManager;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::monitoring::otel::trace_db_query;
+use crate::requests::models::user_data_quota::USER_DATA_QUOTA_EXCLUDED_STATUSES;
+
+/// ModelAdminStatsHour
+///
+/// Logins in one hour
+///
+/// # Arguments
+///
+/// * `hour` - `String` - utc start of the hour
+/// * `logins` - `i64` - sessions created in the hour
+///
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ModelAdminStatsHour {
+    pub hour: String,
+    pub logins: i64,
+}
+
+/// ModelAdminStats
+///
+/// Aggregate user, upload and login stats
+///
+/// # Arguments
+///
+/// * `total_users` - `i64` - every user record
+/// * `active_users` - `i64` - users with ``users.state = 0``
+/// * `verified_users` - `i64` - active users with a verified
+///   email
+/// * `verified_percent` - `f64` - ``verified_users`` out of
+///   ``active_users`` (``0`` to ``100``)
+/// * `uploads_last_24h` - `i64` - ``users_data`` records
+///   created in the last 24 hours
+/// * `total_stored_bytes` - `i64` - bytes stored in
+///   ``users_data`` and ``users_data_archive`` (without
+///   ``failed``, ``expired`` and ``broken`` records)
+/// * `logins_per_hour` - `Vec<`[`ModelAdminStatsHour`](crate::requests::models::admin_stats::ModelAdminStatsHour)`>` -
+///   logins in each of the last 24 hours (oldest first,
+///   including the current hour)
+/// * `computed_at` - `String` - utc timestamp for the queries
+///
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ModelAdminStats {
+    pub total_users: i64,
+    pub active_users: i64,
+    pub verified_users: i64,
+    pub verified_percent: f64,
+    pub uploads_last_24h: i64,
+    pub total_stored_bytes: i64,
+    pub logins_per_hour: Vec<ModelAdminStatsHour>,
+    pub computed_at: String,
+}
+
+/// get_admin_stats
+///
+/// Compute the admin dashboard stats from the db
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+///   an established db connection from the
+///   postgres client db threadpool
+///
+/// # Returns
+///
+/// Ok([`ModelAdminStats`](crate::requests::models::admin_stats::ModelAdminStats))
+///
+/// # Errors
+///
+/// Err(err_msg: `String`) if a query fails
+///
+pub async fn get_admin_stats(
+    tracking_label: &str,
+    conn: &PooledConnection<'_, PostgresConnectionManager<MakeTlsConnector>>,
+) -> Result<ModelAdminStats, String> {
+    let now = chrono::Utc::now();
+    let users_query = "SELECT \
+            COUNT(*) AS total_users, \
+            COUNT(*) FILTER (WHERE users.state = 0) AS active_users, \
+            COUNT(*) FILTER (\
+                WHERE users.state = 0 AND users.verified = 1) \
+                AS verified_users \
+        FROM \
+            users;";
+    let users_row =
+        match trace_db_query(users_query, conn.query_one(users_query, &[]))
+            .await
+        {
+            Ok(row) => row,
+            Err(e) => {
+                return Err(format!(
+                    "{tracking_label} - \
+                    failed to count users for admin stats with err='{e}'"
+                ))
+            }
+        };
+    let data_query = format!(
+        "SELECT \
+            (SELECT \
+                COUNT(*) \
+            FROM \
+                users_data \
+            WHERE \
+                users_data.created_at >= \
+                    timezone('UTC'::text, now()) - interval '24 hours') \
+                AS uploads_last_24h, \
+            (SELECT \
+                COALESCE(SUM(users_data.size_in_bytes), 0) \
+            FROM \
+                users_data \
+            WHERE \
+                users_data.status NOT IN ({USER_DATA_QUOTA_EXCLUDED_STATUSES}))::BIGINT \
+            + (SELECT \
+                COALESCE(SUM(users_data_archive.size_in_bytes), 0) \
+            FROM \
+                users_data_archive \
+            WHERE \
+                users_data_archive.status NOT IN ({USER_DATA_QUOTA_EXCLUDED_STATUSES}))::BIGINT \
+                AS total_stored_bytes;"
+    );
+    let data_row = match trace_db_query(
+        &data_query,
+        conn.query_one(data_query.as_str(), &[]),
+    )
+    .await
+    {
+        Ok(row) => row,
+        Err(e) => {
+            return Err(format!(
+                "{tracking_label} - \
+                failed to count uploads for admin stats with err='{e}'"
+            ))
+        }
+    };
+    let logins_query = "SELECT \
+            date_trunc('hour', users_tokens.created_at, 'UTC') AS hour, \
+            COUNT(*) AS logins \
+        FROM \
+            users_tokens \
+        WHERE \
+            users_tokens.created_at >= \
+                date_trunc('hour', now(), 'UTC') - interval '23 hours' \
+        GROUP BY \
+            1;";
+    let logins_rows =
+        match trace_db_query(logins_query, conn.query(logins_query, &[])).await
+        {
+            Ok(rows) => rows,
+            Err(e) => {
+                return Err(format!(
+                    "{tracking_label} - \
+                    failed to count logins for admin stats with err='{e}'"
+                ))
+            }
+        };
+    let format_hour = |hour: chrono::DateTime<chrono::Utc>| {
+        format!("{}", hour.format("%Y-%m-%dT%H:00:00Z"))
+    };
+    let logins: Vec<(String, i64)> = logins_rows
+        .iter()
+        .map(|row| {
+            (
+                format_hour(row.try_get("hour").unwrap()),
+                row.try_get("logins").unwrap(),
+            )
+        })
+        .collect();
+    // every hour is returned so charts do not need to fill gaps
+    let logins_per_hour = (0..24)
+        .rev()
+        .map(|hours_ago| {
+            let hour = format_hour(now - chrono::Duration::hours(hours_ago));
+            ModelAdminStatsHour {
+                logins: logins
+                    .iter()
+                    .find(|(v, _)| *v == hour)
+                    .map(|(_, v)| *v)
+                    .unwrap_or(0),
+                hour,
+            }
+        })
+        .collect();
+
+    let active_users: i64 = users_row.try_get("active_users").unwrap();
+    let verified_users: i64 = users_row.try_get("verified_users").unwrap();
+    let verified_percent = match active_users {
+        0 => 0.0,
+        _ => {
+            (verified_users as f64 * 10000.0 / active_users as f64).round()
+                / 100.0
+        }
+    };
+    Ok(ModelAdminStats {
+        total_users: users_row.try_get("total_users").unwrap(),
+        active_users,
+        verified_users,
+        verified_percent,
+        uploads_last_24h: data_row.try_get("uploads_last_24h").unwrap(),
+        total_stored_bytes: data_row.try_get("total_stored_bytes").unwrap(),
+        logins_per_hour,
+        computed_at: format!("{}", now.format("%Y-%m-%dT%H:%M:%SZ")),
+    })
+}
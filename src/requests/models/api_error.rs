@@ -0,0 +1,324 @@
+//! Typed errors returned by the
+//! [`UserRepo`](crate::requests::models::user_repo::UserRepo)
+//! and
+//! [`UserDataRepo`](crate::requests::models::user_data_repo::UserDataRepo)
+//! methods and the machine-readable
+//! [`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)
+//! in error responses
+//!
+use std::fmt;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use tokio_postgres::error::SqlState;
+
+/// ApiErrorCode
+///
+/// Stable ``error_code`` in every ``ApiRes*`` error response
+/// so clients can branch on the failure without parsing the
+/// human-readable (and translated) ``msg``. Codes serialize
+/// as ``SCREAMING_SNAKE_CASE`` strings like
+/// ``USER_NOT_FOUND`` and are never renamed once released.
+///
+/// # Variants
+///
+/// * `ValidationFailed` - request fields failed validation
+///   (``422`` with the invalid ``errors``)
+/// * `InvalidRequest` - the body, path or query params could
+///   not be parsed
+/// * `InvalidToken` - missing, invalid or unknown jwt
+/// * `TokenExpired` - the jwt expired (login again)
+/// * `SessionRevoked` - the jwt's session was revoked
+/// * `InsufficientScope` - the jwt's ``scope`` claim does not
+///   allow the route
+/// * `Forbidden` - the user cannot access the resource
+///   (like a non-admin calling an admin api)
+/// * `UnknownTenant` - the request's tenant does not exist
+/// * `InvalidCredentials` - wrong email, password or passkey
+/// * `LoginLocked` - too many failed logins or one-time-use
+///   password attempts
+/// * `LoginChallengeRequired` - the login needs a second
+///   factor (a passkey login or the emailed one-time-use
+///   password)
+/// * `LoginDenied` - the login risk hook denied the login
+/// * `PasswordResetRequired` - an admin forced a password
+///   reset (change the password with a one-time-use
+///   password before logging in)
+/// * `UserNotVerified` - the user's email is not verified
+/// * `UserInactive` - the user is deactivated
+/// * `UserNotFound` - the user does not exist
+/// * `EmailInUse` - the email is already registered
+/// * `NotFound` - the record does not exist
+/// * `Conflict` - the record already exists
+/// * `VersionConflict` - the record changed since the
+///   client read its version
+/// * `NoChanges` - the update did not change anything
+/// * `OtpInvalid` - the one-time-use password does not match
+///   or was replaced
+/// * `OtpExpired` - the one-time-use password expired or was
+///   already used
+/// * `VerifyInvalid` - the verify or confirmation token does
+///   not match or was already used
+/// * `VerifyExpired` - the verify token expired
+/// * `AlreadyVerified` - the user is already verified
+/// * `QuotaExceeded` - the user's storage quota is full
+/// * `PayloadTooLarge` - the request body is too large
+/// * `FileRejected` - the malware scan rejected the uploaded file
+/// * `TooManyRequests` - the user hit a concurrency or rate
+///   limit (retry later)
+/// * `FeatureDisabled` - the api is turned off on this server
+/// * `ServiceUnavailable` - a dependency like s3 keeps
+///   failing and the request was rejected without calling
+///   it (retry later)
+/// * `QueryTimeout` - a db query ran longer than
+///   ``POSTGRES_STATEMENT_TIMEOUT_MS`` (``504``)
+/// * `RolledBack` - a batch operation was rolled back or not
+///   run because another operation failed
+/// * `InternalError` - a db, s3 or other server error
+///
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ApiErrorCode {
+    ValidationFailed,
+    InvalidRequest,
+    InvalidToken,
+    TokenExpired,
+    SessionRevoked,
+    InsufficientScope,
+    Forbidden,
+    UnknownTenant,
+    InvalidCredentials,
+    LoginLocked,
+    LoginChallengeRequired,
+    LoginDenied,
+    PasswordResetRequired,
+    UserNotVerified,
+    UserInactive,
+    UserNotFound,
+    EmailInUse,
+    NotFound,
+    Conflict,
+    VersionConflict,
+    NoChanges,
+    OtpInvalid,
+    OtpExpired,
+    VerifyInvalid,
+    VerifyExpired,
+    AlreadyVerified,
+    QuotaExceeded,
+    PayloadTooLarge,
+    FileRejected,
+    TooManyRequests,
+    FeatureDisabled,
+    ServiceUnavailable,
+    QueryTimeout,
+    RolledBack,
+    InternalError,
+}
+
+impl ApiErrorCode {
+    /// as_str
+    ///
+    /// Serialized code for logs and metric labels
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use restapi::requests::models::api_error::ApiErrorCode;
+    /// assert_eq!(ApiErrorCode::UserNotFound.as_str(), "USER_NOT_FOUND");
+    /// assert_eq!(
+    ///     serde_json::to_string(&ApiErrorCode::TokenExpired).unwrap(),
+    ///     "\"TOKEN_EXPIRED\""
+    /// );
+    /// ```
+    ///
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiErrorCode::ValidationFailed => "VALIDATION_FAILED",
+            ApiErrorCode::InvalidRequest => "INVALID_REQUEST",
+            ApiErrorCode::InvalidToken => "INVALID_TOKEN",
+            ApiErrorCode::TokenExpired => "TOKEN_EXPIRED",
+            ApiErrorCode::SessionRevoked => "SESSION_REVOKED",
+            ApiErrorCode::InsufficientScope => "INSUFFICIENT_SCOPE",
+            ApiErrorCode::Forbidden => "FORBIDDEN",
+            ApiErrorCode::UnknownTenant => "UNKNOWN_TENANT",
+            ApiErrorCode::InvalidCredentials => "INVALID_CREDENTIALS",
+            ApiErrorCode::LoginLocked => "LOGIN_LOCKED",
+            ApiErrorCode::LoginChallengeRequired => "LOGIN_CHALLENGE_REQUIRED",
+            ApiErrorCode::LoginDenied => "LOGIN_DENIED",
+            ApiErrorCode::PasswordResetRequired => "PASSWORD_RESET_REQUIRED",
+            ApiErrorCode::UserNotVerified => "USER_NOT_VERIFIED",
+            ApiErrorCode::UserInactive => "USER_INACTIVE",
+            ApiErrorCode::UserNotFound => "USER_NOT_FOUND",
+            ApiErrorCode::EmailInUse => "EMAIL_IN_USE",
+            ApiErrorCode::NotFound => "NOT_FOUND",
+            ApiErrorCode::Conflict => "CONFLICT",
+            ApiErrorCode::VersionConflict => "VERSION_CONFLICT",
+            ApiErrorCode::NoChanges => "NO_CHANGES",
+            ApiErrorCode::OtpInvalid => "OTP_INVALID",
+            ApiErrorCode::OtpExpired => "OTP_EXPIRED",
+            ApiErrorCode::VerifyInvalid => "VERIFY_INVALID",
+            ApiErrorCode::VerifyExpired => "VERIFY_EXPIRED",
+            ApiErrorCode::AlreadyVerified => "ALREADY_VERIFIED",
+            ApiErrorCode::QuotaExceeded => "QUOTA_EXCEEDED",
+            ApiErrorCode::PayloadTooLarge => "PAYLOAD_TOO_LARGE",
+            ApiErrorCode::FileRejected => "FILE_REJECTED",
+            ApiErrorCode::TooManyRequests => "TOO_MANY_REQUESTS",
+            ApiErrorCode::FeatureDisabled => "FEATURE_DISABLED",
+            ApiErrorCode::ServiceUnavailable => "SERVICE_UNAVAILABLE",
+            ApiErrorCode::QueryTimeout => "QUERY_TIMEOUT",
+            ApiErrorCode::RolledBack => "ROLLED_BACK",
+            ApiErrorCode::InternalError => "INTERNAL_ERROR",
+        }
+    }
+
+    /// from_status
+    ///
+    /// Code for helpers that only return an HTTP status code
+    /// and a message (like the upload body and header checks)
+    ///
+    /// # Arguments
+    ///
+    /// * `status` - `u16` - HTTP status code
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use restapi::requests::models::api_error::ApiErrorCode;
+    /// assert_eq!(ApiErrorCode::from_status(413), ApiErrorCode::PayloadTooLarge);
+    /// assert_eq!(ApiErrorCode::from_status(503), ApiErrorCode::InternalError);
+    /// ```
+    ///
+    pub fn from_status(status: u16) -> Self {
+        match status {
+            401 => ApiErrorCode::InvalidToken,
+            403 => ApiErrorCode::Forbidden,
+            404 => ApiErrorCode::NotFound,
+            409 => ApiErrorCode::Conflict,
+            413 | 431 => ApiErrorCode::PayloadTooLarge,
+            422 => ApiErrorCode::ValidationFailed,
+            429 => ApiErrorCode::TooManyRequests,
+            507 => ApiErrorCode::QuotaExceeded,
+            400..=499 => ApiErrorCode::InvalidRequest,
+            _ => ApiErrorCode::InternalError,
+        }
+    }
+}
+
+impl fmt::Display for ApiErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// ApiError
+///
+/// Error from a repository method that handlers can map
+/// to an HTTP status code with
+/// [`status_code`](crate::requests::models::api_error::ApiError::status_code)
+///
+/// # Variants
+///
+/// * `NotFound` - no record matched (``404``)
+/// * `Conflict` - a unique constraint was violated (``409``)
+/// * `VersionConflict` - the record changed since the client
+///   read its version (``409``)
+/// * `Timeout` - postgres canceled the query after
+///   ``POSTGRES_STATEMENT_TIMEOUT_MS`` (``504``)
+/// * `Db` - any other db error (``500``)
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ApiError {
+    NotFound(String),
+    Conflict(String),
+    VersionConflict(String),
+    Timeout(String),
+    Db(String),
+}
+
+impl ApiError {
+    /// from_db_error
+    ///
+    /// Convert a postgres error into an
+    /// [`ApiError`](crate::requests::models::api_error::ApiError)
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - caller logging label
+    /// * `action` - `&str` - what failed (``insert user``)
+    /// * `e` - [`Error`](tokio_postgres::Error) - postgres error
+    ///
+    pub fn from_db_error(
+        tracking_label: &str,
+        action: &str,
+        e: &tokio_postgres::Error,
+    ) -> Self {
+        let err_msg = format!(
+            "{tracking_label} - \
+            failed to {action} with err='{e}'"
+        );
+        match e.code() {
+            Some(code) if *code == SqlState::UNIQUE_VIOLATION => {
+                ApiError::Conflict(err_msg)
+            }
+            Some(code) if *code == SqlState::QUERY_CANCELED => {
+                ApiError::Timeout(err_msg)
+            }
+            _ => ApiError::Db(err_msg),
+        }
+    }
+
+    /// status_code
+    ///
+    /// HTTP status code for the error
+    ///
+    /// # Returns
+    ///
+    /// `u16` - ``404``, ``409``, ``500`` or ``504``
+    ///
+    pub fn status_code(&self) -> u16 {
+        match self {
+            ApiError::NotFound(_) => 404,
+            ApiError::Conflict(_) | ApiError::VersionConflict(_) => 409,
+            ApiError::Db(_) => 500,
+            ApiError::Timeout(_) => 504,
+        }
+    }
+
+    /// error_code
+    ///
+    /// Machine-readable code for the error response
+    ///
+    /// # Returns
+    ///
+    /// [`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)
+    ///
+    pub fn error_code(&self) -> ApiErrorCode {
+        match self {
+            ApiError::NotFound(_) => ApiErrorCode::NotFound,
+            ApiError::Conflict(_) => ApiErrorCode::Conflict,
+            ApiError::VersionConflict(_) => ApiErrorCode::VersionConflict,
+            ApiError::Timeout(_) => ApiErrorCode::QueryTimeout,
+            ApiError::Db(_) => ApiErrorCode::InternalError,
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::NotFound(err_msg)
+            | ApiError::Conflict(err_msg)
+            | ApiError::VersionConflict(err_msg)
+            | ApiError::Timeout(err_msg)
+            | ApiError::Db(err_msg) => write!(f, "{err_msg}"),
+        }
+    }
+}
+
+impl From<ApiError> for String {
+    fn from(e: ApiError) -> Self {
+        e.to_string()
+    }
+}
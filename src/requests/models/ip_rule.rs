@@ -0,0 +1,232 @@
+//! Model for the ip allow and deny rules in the
+//! ``ip_rules`` table
+//!
+//! Every insert, update or delete on the ``ip_rules``
+//! table sends an ``ip_rules_changed`` postgres notification
+//! so all api servers reload their cached
+//! [`IpAccessControl`](crate::core::server::ip_access::IpAccessControl)
+//! rules
+//!
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::PooledConnection;
+use bb8_postgres::PostgresConnectionManager;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::monitoring::otel::trace_db_query;
+
+/// ModelIpRule
+///
+/// An ip allow or deny rule stored in the db
+///
+/// # DB table
+///
+/// `ip_rules`
+///
+/// # Arguments
+///
+/// * `id` - `i32` - rule id (``0`` for environment
+///   variable rules)
+/// * `action` - `String` - ``allow`` or ``deny``
+/// * `cidr` - `String` - normalized network
+///   (``10.0.0.0/8``)
+/// * `path_prefix` - `String` - url path prefix the rule
+///   applies to (empty = every route)
+/// * `note` - `String` - optional admin note
+/// * `created_by` - `i32` - admin ``users.id`` (``-1`` for
+///   environment variable rules)
+/// * `created_at` - `String` - utc timestamp
+///
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ModelIpRule {
+    pub id: i32,
+    pub action: String,
+    pub cidr: String,
+    pub path_prefix: String,
+    pub note: String,
+    pub created_by: i32,
+    pub created_at: String,
+}
+
+/// get_ip_rules
+///
+/// Get all ip rules (oldest first)
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+///   an established db connection from the
+///   postgres client db threadpool
+///
+/// # Returns
+///
+/// Ok(`Vec<`[`ModelIpRule`](crate::requests::models::ip_rule::ModelIpRule)`>`)
+///
+/// # Errors
+///
+/// Err(err_msg: `String`) if the query fails
+///
+pub async fn get_ip_rules(
+    tracking_label: &str,
+    conn: &PooledConnection<'_, PostgresConnectionManager<MakeTlsConnector>>,
+) -> Result<Vec<ModelIpRule>, String> {
+    let query = "SELECT \
+            ip_rules.id, \
+            ip_rules.action, \
+            ip_rules.cidr, \
+            ip_rules.path_prefix, \
+            ip_rules.note, \
+            ip_rules.created_by, \
+            ip_rules.created_at \
+        FROM \
+            ip_rules \
+        ORDER BY \
+            ip_rules.id ASC;";
+    match trace_db_query(query, conn.query(query, &[])).await {
+        Ok(query_result) => Ok(query_result
+            .iter()
+            .map(|row| {
+                let note: Option<String> = row.try_get("note").unwrap();
+                let created_at_utc: chrono::DateTime<chrono::Utc> =
+                    row.try_get("created_at").unwrap();
+                ModelIpRule {
+                    id: row.try_get("id").unwrap(),
+                    action: row.try_get("action").unwrap(),
+                    cidr: row.try_get("cidr").unwrap(),
+                    path_prefix: row.try_get("path_prefix").unwrap(),
+                    note: note.unwrap_or_default(),
+                    created_by: row.try_get("created_by").unwrap(),
+                    created_at: format!(
+                        "{}",
+                        created_at_utc.format("%Y-%m-%dT%H:%M:%SZ")
+                    ),
+                }
+            })
+            .collect()),
+        Err(e) => Err(format!(
+            "{tracking_label} - failed to get ip rules with err='{e}'"
+        )),
+    }
+}
+
+/// insert_ip_rule
+///
+/// Create an ip allow or deny rule
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `rule` - [`ModelIpRule`](crate::requests::models::ip_rule::ModelIpRule) -
+///   validated rule with a normalized ``cidr`` and
+///   ``path_prefix`` (the ``id`` and ``created_at`` are
+///   ignored)
+/// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+///   an established db connection from the
+///   postgres client db threadpool
+///
+/// # Returns
+///
+/// Ok(`Some(`[`ModelIpRule`](crate::requests::models::ip_rule::ModelIpRule)`)`)
+/// or Ok(`None`) if the same action, cidr and path prefix
+/// already exist
+///
+/// # Errors
+///
+/// Err(err_msg: `String`) if the record cannot be created
+///
+pub async fn insert_ip_rule(
+    tracking_label: &str,
+    rule: &ModelIpRule,
+    conn: &PooledConnection<'_, PostgresConnectionManager<MakeTlsConnector>>,
+) -> Result<Option<ModelIpRule>, String> {
+    let note_sql = match rule.note.is_empty() {
+        true => "NULL".to_string(),
+        false => format!("'{}'", rule.note.replace('\'', "''")),
+    };
+    let query = format!(
+        "INSERT INTO \
+            ip_rules (\
+                action, \
+                cidr, \
+                path_prefix, \
+                note, \
+                created_by) \
+        VALUES (\
+            '{}', \
+            '{}', \
+            '{}', \
+            {note_sql}, \
+            {}) \
+        ON CONFLICT (action, cidr, path_prefix) DO NOTHING \
+        RETURNING \
+            ip_rules.id, \
+            ip_rules.created_at;",
+        rule.action.replace('\'', "''"),
+        rule.cidr.replace('\'', "''"),
+        rule.path_prefix.replace('\'', "''"),
+        rule.created_by
+    );
+    match trace_db_query(&query, conn.query(query.as_str(), &[])).await {
+        Ok(query_result) => Ok(query_result.first().map(|row| {
+            let created_at_utc: chrono::DateTime<chrono::Utc> =
+                row.try_get("created_at").unwrap();
+            ModelIpRule {
+                id: row.try_get("id").unwrap(),
+                created_at: format!(
+                    "{}",
+                    created_at_utc.format("%Y-%m-%dT%H:%M:%SZ")
+                ),
+                ..rule.clone()
+            }
+        })),
+        Err(e) => Err(format!(
+            "{tracking_label} - \
+            failed to create ip rule action={} cidr={} path_prefix={} \
+            with err='{e}'",
+            rule.action, rule.cidr, rule.path_prefix
+        )),
+    }
+}
+
+/// delete_ip_rule
+///
+/// Remove an ip rule
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `rule_id` - `i32` - ``ip_rules.id``
+/// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+///   an established db connection from the
+///   postgres client db threadpool
+///
+/// # Returns
+///
+/// Ok(`bool`) - ``false`` if there is no rule with the id
+///
+/// # Errors
+///
+/// Err(err_msg: `String`) if the query fails
+///
+pub async fn delete_ip_rule(
+    tracking_label: &str,
+    rule_id: i32,
+    conn: &PooledConnection<'_, PostgresConnectionManager<MakeTlsConnector>>,
+) -> Result<bool, String> {
+    let query = format!(
+        "DELETE FROM \
+            ip_rules \
+        WHERE \
+            ip_rules.id = {rule_id};"
+    );
+    match trace_db_query(&query, conn.execute(query.as_str(), &[])).await {
+        Ok(num_deleted) => Ok(num_deleted > 0),
+        Err(e) => Err(format!(
+            "{tracking_label} - \
+            failed to delete ip rule={rule_id} with err='{e}'"
+        )),
+    }
+}
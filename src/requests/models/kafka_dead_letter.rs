@@ -0,0 +1,343 @@
+//! Module for kafka messages that could not be published
+//!
+//! The [`KafkaDeadLetters`](crate::kafka::kafka_dead_letters::KafkaDeadLetters)
+//! stores a ``kafka_dead_letters`` record for each message
+//! the kafka publisher did not accept after all retries, and
+//! admins requeue them with
+//! ``POST /admin/kafka/dead-letters/requeue``
+//!
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::PooledConnection;
+use bb8_postgres::PostgresConnectionManager;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::monitoring::otel::trace_db_query;
+
+/// ModelKafkaDeadLetter
+///
+/// Representation of an unpublished kafka message in the db
+///
+/// # DB table
+///
+/// `kafka_dead_letters`
+///
+/// # Arguments
+///
+/// * `id` - `i64` - dead letter id
+/// * `topic` - `String` - kafka topic
+/// * `partition_key` - `String` - kafka partition key
+/// * `payload` - `String` - kafka message payload
+/// * `error` - `String` - error for the last failed publish
+/// * `status` - `String` - ``dead`` or ``requeued``
+/// * `attempts` - `i32` - publish attempts including retries
+///   and requeues
+/// * `created_at` - `String` - utc timestamp
+/// * `updated_at` - `String` - utc timestamp
+/// * `requeued_at` - `String` - utc timestamp (empty until
+///   ``requeued``)
+///
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ModelKafkaDeadLetter {
+    pub id: i64,
+    pub topic: String,
+    pub partition_key: String,
+    pub payload: String,
+    pub error: String,
+    pub status: String,
+    pub attempts: i32,
+    pub created_at: String,
+    pub updated_at: String,
+    pub requeued_at: String,
+}
+
+/// format_dead_letter_ts
+///
+/// Format an optional timestamp column (empty for `NULL`)
+///
+fn format_dead_letter_ts(
+    value: Option<chrono::DateTime<chrono::Utc>>,
+) -> String {
+    match value {
+        Some(value) => format!("{}", value.format("%Y-%m-%dT%H:%M:%SZ")),
+        None => "".to_string(),
+    }
+}
+
+/// get_dead_letter_from_row
+///
+/// Convert a ``kafka_dead_letters`` row
+///
+fn get_dead_letter_from_row(row: &tokio_postgres::Row) -> ModelKafkaDeadLetter {
+    ModelKafkaDeadLetter {
+        id: row.try_get("id").unwrap(),
+        topic: row.try_get("topic").unwrap(),
+        partition_key: row.try_get("partition_key").unwrap(),
+        payload: row.try_get("payload").unwrap(),
+        error: row.try_get("error").unwrap(),
+        status: row.try_get("status").unwrap(),
+        attempts: row.try_get("attempts").unwrap(),
+        created_at: format_dead_letter_ts(row.try_get("created_at").unwrap()),
+        updated_at: format_dead_letter_ts(row.try_get("updated_at").unwrap()),
+        requeued_at: format_dead_letter_ts(row.try_get("requeued_at").unwrap()),
+    }
+}
+
+/// insert_kafka_dead_letter
+///
+/// Store a kafka message that could not be published
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `topic` - `&str` - kafka topic
+/// * `partition_key` - `&str` - kafka partition key
+/// * `payload` - `&str` - kafka message payload
+/// * `error` - `&str` - error for the last failed publish
+/// * `attempts` - `i32` - publish attempts
+/// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+///   an established db connection from the
+///   postgres client db threadpool
+///
+/// # Returns
+///
+/// Ok(`i64`) - the new ``kafka_dead_letters.id``
+///
+/// # Errors
+///
+/// Err(err_msg: `String`) if the record cannot be created
+///
+pub async fn insert_kafka_dead_letter(
+    tracking_label: &str,
+    topic: &str,
+    partition_key: &str,
+    payload: &str,
+    error: &str,
+    attempts: i32,
+    conn: &PooledConnection<'_, PostgresConnectionManager<MakeTlsConnector>>,
+) -> Result<i64, String> {
+    let query = format!(
+        "INSERT INTO \
+            kafka_dead_letters (\
+                topic, \
+                partition_key, \
+                payload, \
+                error, \
+                attempts) \
+        VALUES (\
+            '{}', \
+            '{}', \
+            '{}', \
+            '{}', \
+            {attempts}) \
+        RETURNING \
+            kafka_dead_letters.id;",
+        topic.replace('\'', "''"),
+        partition_key.replace('\'', "''"),
+        payload.replace('\'', "''"),
+        error.replace('\'', "''")
+    );
+    match trace_db_query(&query, conn.query(query.as_str(), &[])).await {
+        Ok(query_result) => match query_result.first() {
+            Some(row) => Ok(row.try_get("id").unwrap()),
+            None => Err(format!(
+                "{tracking_label} - \
+                failed to store kafka dead letter topic={topic} \
+                key={partition_key}"
+            )),
+        },
+        Err(e) => Err(format!(
+            "{tracking_label} - \
+            failed to store kafka dead letter topic={topic} \
+            key={partition_key} with err='{e}'"
+        )),
+    }
+}
+
+/// search_kafka_dead_letters
+///
+/// Get the newest dead letters (newest first) filtered by
+/// status and/or topic
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `status` - `Option<&str>` - only this validated status
+/// * `topic` - `Option<&str>` - only this kafka topic
+/// * `limit` - `i64` - max dead letters to return
+/// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+///   an established db connection from the
+///   postgres client db threadpool
+///
+/// # Returns
+///
+/// Ok(`Vec<`[`ModelKafkaDeadLetter`](crate::requests::models::kafka_dead_letter::ModelKafkaDeadLetter)`>`)
+///
+/// # Errors
+///
+/// Err(err_msg: `String`) if the query fails
+///
+pub async fn search_kafka_dead_letters(
+    tracking_label: &str,
+    status: Option<&str>,
+    topic: Option<&str>,
+    limit: i64,
+    conn: &PooledConnection<'_, PostgresConnectionManager<MakeTlsConnector>>,
+) -> Result<Vec<ModelKafkaDeadLetter>, String> {
+    let mut filters = vec!["TRUE".to_string()];
+    if let Some(status) = status {
+        filters.push(format!(
+            "kafka_dead_letters.status = '{}'",
+            status.replace('\'', "''")
+        ));
+    }
+    if let Some(topic) = topic {
+        filters.push(format!(
+            "kafka_dead_letters.topic = '{}'",
+            topic.replace('\'', "''")
+        ));
+    }
+    let query = format!(
+        "SELECT \
+            kafka_dead_letters.id, \
+            kafka_dead_letters.topic, \
+            kafka_dead_letters.partition_key, \
+            kafka_dead_letters.payload, \
+            kafka_dead_letters.error, \
+            kafka_dead_letters.status, \
+            kafka_dead_letters.attempts, \
+            kafka_dead_letters.created_at, \
+            kafka_dead_letters.updated_at, \
+            kafka_dead_letters.requeued_at \
+        FROM \
+            kafka_dead_letters \
+        WHERE \
+            {} \
+        ORDER BY \
+            kafka_dead_letters.id DESC \
+        LIMIT {limit};",
+        filters.join(" AND ")
+    );
+    match trace_db_query(&query, conn.query(query.as_str(), &[])).await {
+        Ok(query_result) => {
+            Ok(query_result.iter().map(get_dead_letter_from_row).collect())
+        }
+        Err(e) => Err(format!(
+            "{tracking_label} - \
+            failed to search kafka dead letters with err='{e}'"
+        )),
+    }
+}
+
+/// claim_kafka_dead_letters
+///
+/// Mark ``dead`` letters as ``requeued`` and count an
+/// attempt before publishing them again. Only ``dead``
+/// letters are claimed, so concurrent requeues never
+/// publish the same message twice.
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `ids` - `&[i64]` - ``kafka_dead_letters.id`` values
+/// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+///   an established db connection from the
+///   postgres client db threadpool
+///
+/// # Returns
+///
+/// Ok(`Vec<`[`ModelKafkaDeadLetter`](crate::requests::models::kafka_dead_letter::ModelKafkaDeadLetter)`>`) -
+/// the claimed dead letters (oldest first)
+///
+/// # Errors
+///
+/// Err(err_msg: `String`) if the query fails
+///
+pub async fn claim_kafka_dead_letters(
+    tracking_label: &str,
+    ids: &[i64],
+    conn: &PooledConnection<'_, PostgresConnectionManager<MakeTlsConnector>>,
+) -> Result<Vec<ModelKafkaDeadLetter>, String> {
+    let query = "UPDATE \
+            kafka_dead_letters \
+        SET \
+            status = 'requeued', \
+            attempts = kafka_dead_letters.attempts + 1, \
+            updated_at = timezone('UTC'::text, now()), \
+            requeued_at = timezone('UTC'::text, now()) \
+        WHERE \
+            kafka_dead_letters.id = ANY($1) \
+            AND \
+            kafka_dead_letters.status = 'dead' \
+        RETURNING \
+            kafka_dead_letters.id, \
+            kafka_dead_letters.topic, \
+            kafka_dead_letters.partition_key, \
+            kafka_dead_letters.payload, \
+            kafka_dead_letters.error, \
+            kafka_dead_letters.status, \
+            kafka_dead_letters.attempts, \
+            kafka_dead_letters.created_at, \
+            kafka_dead_letters.updated_at, \
+            kafka_dead_letters.requeued_at;";
+    match trace_db_query(query, conn.query(query, &[&ids])).await {
+        Ok(query_result) => {
+            let mut dead_letters: Vec<ModelKafkaDeadLetter> =
+                query_result.iter().map(get_dead_letter_from_row).collect();
+            dead_letters.sort_by_key(|dead_letter| dead_letter.id);
+            Ok(dead_letters)
+        }
+        Err(e) => Err(format!(
+            "{tracking_label} - \
+            failed to claim kafka dead letters with err='{e}'"
+        )),
+    }
+}
+
+/// release_kafka_dead_letter
+///
+/// Mark a claimed dead letter as ``dead`` again after the
+/// requeued publish failed
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `dead_letter_id` - `i64` - ``kafka_dead_letters.id``
+/// * `error` - `&str` - error for the failed publish
+/// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+///   an established db connection from the
+///   postgres client db threadpool
+///
+/// # Errors
+///
+/// Err(err_msg: `String`) if the query fails
+///
+pub async fn release_kafka_dead_letter(
+    tracking_label: &str,
+    dead_letter_id: i64,
+    error: &str,
+    conn: &PooledConnection<'_, PostgresConnectionManager<MakeTlsConnector>>,
+) -> Result<(), String> {
+    let query = format!(
+        "UPDATE \
+            kafka_dead_letters \
+        SET \
+            status = 'dead', \
+            error = '{}', \
+            updated_at = timezone('UTC'::text, now()), \
+            requeued_at = NULL \
+        WHERE \
+            kafka_dead_letters.id = {dead_letter_id};",
+        error.replace('\'', "''")
+    );
+    match trace_db_query(&query, conn.execute(query.as_str(), &[])).await {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!(
+            "{tracking_label} - \
+            failed to release kafka dead letter={dead_letter_id} \
+            with err='{e}'"
+        )),
+    }
+}
@@ -99,7 +99,28 @@
 //! psql --set=sslmode=require -h 0.0.0.0 -p 5432 -U postgres -d mydb -c "\dt"
 //! ```
 //!
+pub mod admin_stats;
+pub mod api_error;
+pub mod ip_rule;
+pub mod kafka_dead_letter;
+pub mod setting;
+pub mod tenant;
 pub mod user;
+pub mod user_admin_action;
 pub mod user_data;
+pub mod user_data_access;
+pub mod user_data_acl;
+pub mod user_data_conflict;
+pub mod user_data_derivative;
+pub mod user_data_quota;
+pub mod user_data_repo;
+pub mod user_data_share;
+pub mod user_data_upload;
+pub mod user_login;
+pub mod user_notification;
 pub mod user_otp;
+pub mod user_passkey;
+pub mod user_repo;
+pub mod user_session;
 pub mod user_verify;
+pub mod webhook;
@@ -0,0 +1,188 @@
+//! Model for the cluster-wide runtime setting overrides
+//! in the ``settings`` table
+//!
+//! Every insert, update or delete on the ``settings``
+//! table sends a ``settings_changed`` postgres notification
+//! so all api servers reload their cached
+//! [`RuntimeSettings`](crate::settings::runtime_settings::RuntimeSettings)
+//!
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::PooledConnection;
+use bb8_postgres::PostgresConnectionManager;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::monitoring::otel::trace_db_query;
+
+/// ModelSetting
+///
+/// A runtime setting override stored in the db
+///
+/// # Arguments
+///
+/// * `key` - `String` - setting name
+/// * `value` - `String` - setting value
+/// * `updated_by` - `i32` - admin user id that set the value
+/// * `updated_at` - `String` - last change time
+///
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct ModelSetting {
+    pub key: String,
+    pub value: String,
+    pub updated_by: i32,
+    pub updated_at: String,
+}
+
+/// get_settings
+///
+/// Get all runtime setting overrides from the db
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+///   an established db connection from the
+///   postgres client db threadpool
+///
+/// # Returns
+///
+/// Ok(`Vec<`[`ModelSetting`](crate::requests::models::setting::ModelSetting)`>`)
+///
+/// # Errors
+///
+/// Err(err_msg: `String`)
+///
+pub async fn get_settings(
+    tracking_label: &str,
+    conn: &PooledConnection<'_, PostgresConnectionManager<MakeTlsConnector>>,
+) -> Result<Vec<ModelSetting>, String> {
+    let query = "SELECT \
+            settings.key, \
+            settings.value, \
+            settings.updated_by, \
+            settings.updated_at \
+        FROM \
+            settings \
+        ORDER BY \
+            settings.key ASC;";
+    let stmt = conn.prepare(query).await.unwrap();
+    let query_result = match trace_db_query(query, conn.query(&stmt, &[])).await
+    {
+        Ok(query_result) => query_result,
+        Err(e) => {
+            return Err(format!(
+                "{tracking_label} - failed to get settings with err='{e}'"
+            ));
+        }
+    };
+    let mut settings: Vec<ModelSetting> =
+        Vec::with_capacity(query_result.len());
+    for row in query_result.iter() {
+        let updated_by: Option<i32> = row.try_get("updated_by").unwrap();
+        let updated_at_utc: chrono::DateTime<chrono::Utc> =
+            row.try_get("updated_at").unwrap();
+        settings.push(ModelSetting {
+            key: row.try_get("key").unwrap(),
+            value: row.try_get("value").unwrap(),
+            updated_by: updated_by.unwrap_or(-1),
+            updated_at: format!(
+                "{}",
+                updated_at_utc.format("%Y-%m-%dT%H:%M:%SZ")
+            ),
+        });
+    }
+    Ok(settings)
+}
+
+/// upsert_setting
+///
+/// Create or change a runtime setting override
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+///   an established db connection from the
+///   postgres client db threadpool
+/// * `key` - `&str` - setting name
+/// * `value` - `&str` - setting value
+/// * `user_id` - `i32` - admin user id making the change
+///
+/// # Errors
+///
+/// Err(err_msg: `String`)
+///
+pub async fn upsert_setting(
+    tracking_label: &str,
+    conn: &PooledConnection<'_, PostgresConnectionManager<MakeTlsConnector>>,
+    key: &str,
+    value: &str,
+    user_id: i32,
+) -> Result<(), String> {
+    let query = format!(
+        "INSERT INTO \
+            settings (\
+                key, \
+                value, \
+                updated_by, \
+                updated_at) \
+        VALUES (\
+            '{}', \
+            '{}', \
+            {user_id}, \
+            timezone('UTC'::text, now())) \
+        ON CONFLICT (key) DO UPDATE SET \
+            value = EXCLUDED.value, \
+            updated_by = EXCLUDED.updated_by, \
+            updated_at = EXCLUDED.updated_at;",
+        key.replace('\'', "''"),
+        value.replace('\'', "''")
+    );
+    let stmt = conn.prepare(&query).await.unwrap();
+    match trace_db_query(&query, conn.query(&stmt, &[])).await {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!(
+            "{tracking_label} - failed to set setting {key} with err='{e}'"
+        )),
+    }
+}
+
+/// delete_setting
+///
+/// Remove a runtime setting override so the
+/// environment variable (or default) value is used
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+///   an established db connection from the
+///   postgres client db threadpool
+/// * `key` - `&str` - setting name
+///
+/// # Errors
+///
+/// Err(err_msg: `String`)
+///
+pub async fn delete_setting(
+    tracking_label: &str,
+    conn: &PooledConnection<'_, PostgresConnectionManager<MakeTlsConnector>>,
+    key: &str,
+) -> Result<(), String> {
+    let query = format!(
+        "DELETE FROM \
+            settings \
+        WHERE \
+            settings.key = '{}';",
+        key.replace('\'', "''")
+    );
+    let stmt = conn.prepare(&query).await.unwrap();
+    match trace_db_query(&query, conn.query(&stmt, &[])).await {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!(
+            "{tracking_label} - failed to delete setting {key} with err='{e}'"
+        )),
+    }
+}
@@ -0,0 +1,98 @@
+//! Module for a tenant (an isolated customer org)
+//!
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::PooledConnection;
+use bb8_postgres::PostgresConnectionManager;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::monitoring::otel::trace_db_query;
+
+/// id for the ``default`` tenant that owns every user
+/// when ``TENANT_MODE=off``
+pub const DEFAULT_TENANT_ID: i32 = 1;
+
+/// ModelTenant
+///
+/// Representation of the tenants table in the db
+///
+/// # DB table
+///
+/// `tenants`
+///
+/// # Arguments
+///
+/// * `id` - `i32` - tenant id
+/// * `slug` - `String` - unique name used in the tenant
+///   header or subdomain
+/// * `name` - `String` - display name
+/// * `state` - `i32` - is the tenant
+///   active (`0`) or inactive (`1`)
+///
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ModelTenant {
+    pub id: i32,
+    pub slug: String,
+    pub name: String,
+    pub state: i32,
+}
+
+/// get_tenant_by_slug
+///
+/// Get a tenant from the database by `tenants.slug`
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `slug` - `&str` - tenant slug
+/// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+///   an established db connection from the
+///   postgres client db threadpool
+///
+/// # Returns
+///
+/// Ok([`ModelTenant`](crate::requests::models::tenant::ModelTenant))
+///
+/// # Errors
+///
+/// Err(err_msg: `String`) if there is no tenant with the
+/// slug or the query fails
+///
+pub async fn get_tenant_by_slug(
+    tracking_label: &str,
+    slug: &str,
+    conn: &PooledConnection<'_, PostgresConnectionManager<MakeTlsConnector>>,
+) -> Result<ModelTenant, String> {
+    let query = format!(
+        "SELECT \
+            tenants.id, \
+            tenants.slug, \
+            tenants.name, \
+            tenants.state \
+        FROM \
+            tenants \
+        WHERE \
+            tenants.slug = '{}' \
+        LIMIT 1;",
+        slug.replace('\'', "''")
+    );
+    match trace_db_query(&query, conn.query(query.as_str(), &[])).await {
+        Ok(query_result) => match query_result.first() {
+            Some(row) => Ok(ModelTenant {
+                id: row.try_get("id").unwrap(),
+                slug: row.try_get("slug").unwrap(),
+                name: row.try_get("name").unwrap(),
+                state: row.try_get("state").unwrap(),
+            }),
+            None => {
+                Err(format!("{tracking_label} - failed to find tenant={slug}"))
+            }
+        },
+        Err(e) => Err(format!(
+            "{tracking_label} - \
+            failed to find tenant={slug} with err='{e}'"
+        )),
+    }
+}
@@ -8,6 +8,12 @@ use bb8_postgres::PostgresConnectionManager;
 use serde::Deserialize;
 use serde::Serialize;
 
+use crate::requests::models::tenant::DEFAULT_TENANT_ID;
+use crate::requests::models::user_repo::UserRepo;
+
+/// ``users.locale`` for users created without a locale
+pub const DEFAULT_USER_LOCALE: &str = "en";
+
 /// ModelUser
 ///
 /// Representation of the users table in the db
@@ -26,20 +32,49 @@ use serde::Serialize;
 /// * `verified` - `i32` - is the user email
 ///   unverified (`0`) or verified (`1`)
 /// * `role` - `String` - user's role
+/// * `tenant_id` - `i32` - tenant that owns the user
+/// * `version` - `i32` - row version bumped on every update
+/// * `locale` - `String` - locale for the user's emails
+///   (like ``en`` or ``pt-BR``)
 ///
 #[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(
+    feature = "graphql",
+    derive(async_graphql::SimpleObject),
+    graphql(name = "User")
+)]
 pub struct ModelUser {
     pub id: i32,
     pub email: String,
+    #[cfg_attr(feature = "graphql", graphql(skip))]
     pub password: String,
     pub state: i32,
     pub verified: i32,
     pub role: String,
+    #[serde(default = "default_tenant_id")]
+    pub tenant_id: i32,
+    #[serde(default)]
+    pub version: i32,
+    #[serde(default = "default_locale")]
+    pub locale: String,
+}
+
+/// users cached before multi-tenancy belong to the
+/// ``default`` tenant
+fn default_tenant_id() -> i32 {
+    DEFAULT_TENANT_ID
+}
+
+/// users cached before email templates use the ``en``
+/// locale
+fn default_locale() -> String {
+    DEFAULT_USER_LOCALE.to_string()
 }
 
 /// get_user_by_id
 ///
-/// Get a user from the database by `user_id`
+/// Get a user from the database by `user_id` with
+/// [`UserRepo::find_by_id`](crate::requests::models::user_repo::UserRepo::find_by_id)
 ///
 /// # Arguments
 ///
@@ -51,7 +86,7 @@ pub struct ModelUser {
 ///
 /// # Returns
 ///
-/// ## get_user_otp on Success Returns
+/// ## get_user_by_id on Success Returns
 ///
 /// [`ModelUser`](crate::requests::models::user)
 ///
@@ -65,50 +100,42 @@ pub async fn get_user_by_id(
     id: i32,
     conn: &PooledConnection<'_, PostgresConnectionManager<MakeTlsConnector>>,
 ) -> Result<ModelUser, String> {
-    // find all user by email and an active state where state == 0
-    let query = format!(
-        "SELECT \
-            users.id, \
-            users.email, \
-            users.password, \
-            users.state, \
-            users.verified, \
-            users.role \
-        FROM \
-            users \
-        WHERE \
-            users.id = {id} \
-        LIMIT 1;"
-    );
-    let stmt = conn.prepare(&query).await.unwrap();
-    match conn.query(&stmt, &[]).await {
-        Ok(query_result) => {
-            // get just the first element
-            if let Some(row) = query_result.first() {
-                let id: i32 = row.try_get("id").unwrap();
-                let email: String = row.try_get("email").unwrap();
-                let password: String = row.try_get("email").unwrap();
-                let state: i32 = row.try_get("state").unwrap();
-                let verified: i32 = row.try_get("verified").unwrap();
-                let role: String = row.try_get("role").unwrap();
-                return Ok(ModelUser {
-                    id,
-                    email,
-                    password,
-                    state,
-                    verified,
-                    role,
-                });
-            }
-            Err(format!(
-                "{tracking_label} - \
-                failed to find any user with id={id}"
-            ))
-        }
-        Err(e) => Err(format!(
-            "{tracking_label} - \
-                failed to find user by id={id} \
-                with err='{e}'"
-        )),
-    }
+    Ok(UserRepo::new(conn).find_by_id(tracking_label, id).await?)
+}
+
+/// get_active_user_by_email
+///
+/// Get an active (`users.state = 0`) user from the
+/// database by `tenant_id` and `email` with
+/// [`UserRepo::find_active_by_email`](crate::requests::models::user_repo::UserRepo::find_active_by_email)
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `tenant_id` - `i32` - tenant that owns the user
+/// * `email` - `&str` - user email
+/// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+///   an established db connection from the
+///   postgres client db threadpool
+///
+/// # Returns
+///
+/// ## get_active_user_by_email on Success Returns
+///
+/// [`ModelUser`](crate::requests::models::user)
+///
+/// # Errors
+///
+/// Various `Err(String)` can be returned depending
+/// on what breaks
+///
+pub async fn get_active_user_by_email(
+    tracking_label: &str,
+    tenant_id: i32,
+    email: &str,
+    conn: &PooledConnection<'_, PostgresConnectionManager<MakeTlsConnector>>,
+) -> Result<ModelUser, String> {
+    Ok(UserRepo::new(conn)
+        .find_active_by_email(tracking_label, tenant_id, email)
+        .await?)
 }
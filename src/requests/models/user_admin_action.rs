@@ -0,0 +1,214 @@
+//! Model for the ``users_admin_actions`` audit of admin
+//! incident response actions
+//!
+//! Each admin session revoke and forced password reset
+//! stores the admin, the target user, the optional reason
+//! and how many sessions were revoked in the same sql
+//! statement as the action (see
+//! [`revoke_user_sessions`](crate::requests::admin::revoke_user_sessions::revoke_user_sessions)
+//! and
+//! [`force_password_reset`](crate::requests::admin::force_password_reset::force_password_reset)).
+//!
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::PooledConnection;
+use bb8_postgres::PostgresConnectionManager;
+
+use crate::monitoring::otel::trace_db_query;
+
+/// ``users_admin_actions.action`` for revoking every
+/// active session for a user
+pub const ADMIN_ACTION_REVOKE_SESSIONS: &str = "revoke_sessions";
+
+/// ``users_admin_actions.action`` for revoking every
+/// active session and blocking logins until the user
+/// changes their password
+pub const ADMIN_ACTION_FORCE_PASSWORD_RESET: &str = "force_password_reset";
+
+/// max characters stored for an admin action's reason
+pub const ADMIN_ACTION_MAX_REASON_LEN: usize = 512;
+
+/// ModelUserAdminAction
+///
+/// Result of an audited admin action
+///
+/// # DB table
+///
+/// `users_admin_actions`
+///
+/// # Arguments
+///
+/// * `action_id` - `i64` - `users_admin_actions.id`
+/// * `revoked_sessions` - `i64` - number of revoked
+///   `users_tokens` records
+///
+#[derive(Clone, Default)]
+pub struct ModelUserAdminAction {
+    pub action_id: i64,
+    pub revoked_sessions: i64,
+}
+
+/// run_user_admin_action
+///
+/// Revoke every active session for a user and store the
+/// audit record in 1 sql statement. A
+/// [`ADMIN_ACTION_FORCE_PASSWORD_RESET`](crate::requests::models::user_admin_action::ADMIN_ACTION_FORCE_PASSWORD_RESET)
+/// also sets ``users.password_reset_required = 1``.
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `admin_user_id` - `i32` - admin `users.id`
+/// * `user_id` - `i32` - target `users.id`
+/// * `action` - `&str` - ``revoke_sessions`` or
+///   ``force_password_reset``
+/// * `reason` - `&str` - optional reason (empty = none)
+/// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+///   an established db connection from the
+///   postgres client db threadpool
+///
+/// # Returns
+///
+/// Ok(`Some(`[`ModelUserAdminAction`](crate::requests::models::user_admin_action::ModelUserAdminAction)`)`)
+/// or Ok(`None`) if the user does not exist
+///
+/// # Errors
+///
+/// Err(err_msg: `String`)
+///
+pub async fn run_user_admin_action(
+    tracking_label: &str,
+    admin_user_id: i32,
+    user_id: i32,
+    action: &str,
+    reason: &str,
+    conn: &PooledConnection<'_, PostgresConnectionManager<MakeTlsConnector>>,
+) -> Result<Option<ModelUserAdminAction>, String> {
+    let target_sql = match action == ADMIN_ACTION_FORCE_PASSWORD_RESET {
+        true => format!(
+            "UPDATE \
+                users \
+            SET \
+                password_reset_required = 1, \
+                updated_at = timezone('UTC'::text, now()) \
+            WHERE \
+                users.id = {user_id} \
+            RETURNING \
+                users.id"
+        ),
+        false => format!(
+            "SELECT \
+                users.id \
+            FROM \
+                users \
+            WHERE \
+                users.id = {user_id}"
+        ),
+    };
+    let reason_sql = match reason.is_empty() {
+        true => "NULL".to_string(),
+        false => format!("'{}'", reason.replace('\'', "''")),
+    };
+    let query = format!(
+        "WITH target AS ({target_sql}), \
+        revoked AS (\
+            UPDATE \
+                users_tokens \
+            SET \
+                state = 1, \
+                updated_at = timezone('UTC'::text, now()) \
+            WHERE \
+                users_tokens.user_id IN (SELECT id FROM target) \
+                AND \
+                users_tokens.state = 0 \
+            RETURNING \
+                users_tokens.id), \
+        audit AS (\
+            INSERT INTO \
+                users_admin_actions (\
+                    admin_user_id, \
+                    user_id, \
+                    action, \
+                    reason, \
+                    revoked_sessions) \
+            SELECT \
+                {admin_user_id}, \
+                target.id, \
+                '{}', \
+                {reason_sql}, \
+                (SELECT COUNT(*) FROM revoked) \
+            FROM \
+                target \
+            RETURNING \
+                users_admin_actions.id, \
+                users_admin_actions.revoked_sessions) \
+        SELECT \
+            audit.id, \
+            audit.revoked_sessions \
+        FROM \
+            audit;",
+        action.replace('\'', "''")
+    );
+    let stmt = conn.prepare(&query).await.unwrap();
+    match trace_db_query(&query, conn.query(&stmt, &[])).await {
+        Ok(query_result) => {
+            Ok(query_result.first().map(|row| ModelUserAdminAction {
+                action_id: row.try_get("id").unwrap(),
+                revoked_sessions: row.try_get("revoked_sessions").unwrap(),
+            }))
+        }
+        Err(e) => Err(format!(
+            "{tracking_label} - \
+            failed to run admin action={action} by admin {admin_user_id} \
+            for user_id={user_id} with err='{e}'"
+        )),
+    }
+}
+
+/// is_password_reset_required
+///
+/// Check if an admin forced a password reset for a user
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `user_id` - `i32` - user id in the db
+/// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+///   an established db connection from the
+///   postgres client db threadpool
+///
+/// # Returns
+///
+/// `bool` - `true` if the user must change their password
+/// before logging in (`false` on db errors)
+///
+pub async fn is_password_reset_required(
+    tracking_label: &str,
+    user_id: i32,
+    conn: &PooledConnection<'_, PostgresConnectionManager<MakeTlsConnector>>,
+) -> bool {
+    let query = format!(
+        "SELECT \
+            users.password_reset_required \
+        FROM \
+            users \
+        WHERE \
+            users.id = {user_id} \
+        LIMIT 1;"
+    );
+    let stmt = conn.prepare(&query).await.unwrap();
+    match trace_db_query(&query, conn.query(&stmt, &[])).await {
+        Ok(query_result) => query_result.first().is_some_and(|row| {
+            let required: i32 = row.try_get("password_reset_required").unwrap();
+            required == 1
+        }),
+        Err(e) => {
+            error!(
+                "{tracking_label} - \
+                failed to check the password reset for user_id={user_id} \
+                with err='{e}'"
+            );
+            false
+        }
+    }
+}
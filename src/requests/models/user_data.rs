@@ -1,8 +1,92 @@
 //! Model for tracking user-uploaded s3 keys
 //!
+//! ## Upload status lifecycle
+//!
+//! Each ``users_data`` record has a ``status``:
+//!
+//! - ``uploading`` - a resumable upload that is still
+//!   receiving chunks (see
+//!   [`start_resumable_upload`](crate::requests::user::start_resumable_upload::start_resumable_upload))
+//! - ``pending`` - uploaded and waiting for the
+//!   [`UserDataPipeline`](crate::processing::user_data_pipeline::UserDataPipeline)
+//! - ``scanning`` - claimed by the pipeline
+//! - ``ready`` - processed (or uploaded while the pipeline
+//!   is disabled) and safe to download
+//! - ``quarantined`` - the upload scanner or the processor
+//!   flagged the file
+//! - ``failed`` - the processor could not process the file
+//! - ``expired`` - the
+//!   [`UserDataLifecycle`](crate::archive::user_data_lifecycle::UserDataLifecycle)
+//!   deleted the s3 object after the upload's ``expires_at``
+//!   (records deleted with
+//!   [`batch_user_data`](crate::requests::user::batch_user_data::batch_user_data)
+//!   are ``expired`` right away)
+//! - ``broken`` - the
+//!   [`UserDataReconciler`](crate::archive::user_data_reconciler::UserDataReconciler)
+//!   found no s3 object for the record
+//!
+//! Only ``ready`` records can be downloaded (see
+//! [`is_user_data_downloadable`](crate::requests::models::user_data::is_user_data_downloadable)).
+//!
 use serde::Deserialize;
 use serde::Serialize;
 
+use crate::requests::models::user_data_derivative::ModelUserDataDerivative;
+
+/// supported ``users_data.status`` values
+pub const USER_DATA_STATUSES: [&str; 8] = [
+    "uploading",
+    "pending",
+    "scanning",
+    "ready",
+    "quarantined",
+    "failed",
+    "expired",
+    "broken",
+];
+
+/// final ``users_data.status`` values a
+/// [`UserDataProcessor`](crate::processing::user_data_processor::UserDataProcessor)
+/// can return
+pub const USER_DATA_PROCESSED_STATUSES: [&str; 3] =
+    ["ready", "quarantined", "failed"];
+
+/// is_valid_user_data_status
+///
+/// Check if a status is a supported ``users_data.status``
+///
+/// # Arguments
+///
+/// * `status` - `&str` - status to check
+///
+/// # Returns
+///
+/// `bool` - `true` for a value in
+/// [`USER_DATA_STATUSES`](crate::requests::models::user_data::USER_DATA_STATUSES)
+///
+pub fn is_valid_user_data_status(status: &str) -> bool {
+    USER_DATA_STATUSES.contains(&status)
+}
+
+/// is_user_data_downloadable
+///
+/// Check if a ``users_data`` record can be downloaded.
+/// Download handlers must reject records that are not
+/// ``ready`` (still processing, quarantined, failed or
+/// expired).
+///
+/// # Arguments
+///
+/// * `status` - `&str` - ``users_data.status``
+///
+/// # Returns
+///
+/// `bool` - `true` only for ``ready`` records
+///
+pub fn is_user_data_downloadable(status: &str) -> bool {
+    status == "ready"
+}
+
 /// ModelUserData
 ///
 /// Representation in the db for a
@@ -28,10 +112,49 @@ use serde::Serialize;
 /// * `sloc` - `String` - full s3 location path
 /// * `created_at` - `String` - original upload time
 /// * `updated_at` - `String` - most recent update time
+/// * `version` - `i32` - row version bumped on every update
+///   (send it back in
+///   [`ApiReqUserUpdateData`](crate::requests::user::update_user_data::ApiReqUserUpdateData))
+/// * `archived` - `bool` - record was moved to the
+///   `users_data_archive` table (read-only)
+/// * `status` - `String` - upload status (``uploading``,
+///   ``pending``, ``scanning``, ``ready``, ``quarantined``,
+///   ``failed``, ``expired`` or ``broken``)
+/// * `storage_class` - `String` - s3 storage class (empty =
+///   the server's ``S3_STORAGE_CLASS``)
+/// * `expires_at` - `String` - time the lifecycle task
+///   deletes or transitions the s3 object (empty = never)
+/// * `scan_status` - `String` - upload scanner result
+///   (``clean``, ``infected`` or ``error``, empty = not
+///   scanned)
+/// * `scan_signature` - `String` - signature the scanner
+///   found in an ``infected`` file
+/// * `content_type` - `String` - upload ``Content-Type``
+///   (empty = unknown)
+/// * `derivatives_status` - `String` - thumbnail task status
+///   (``pending``, ``processing``, ``done`` or ``failed``,
+///   empty = no thumbnails)
+/// * `tags` - `Vec<String>` - user-defined tags
+/// * `folder` - `String` - folder path like
+///   ``/projects/2022`` (empty = not in a folder)
+/// * `file_version` - `i32` - version of the file with this
+///   `filename` in this `folder` (uploads with
+///   ``on_conflict: overwrite`` create the next version)
+/// * `version_of` - `i32` - `data_id` of the file's first
+///   version (``0`` = this is the first version)
+/// * `replaced_by` - `i32` - `data_id` of the newer version
+///   (``0`` = this is the current version)
+/// * `derivatives` - `Vec<`[`ModelUserDataDerivative`](crate::requests::models::user_data_derivative::ModelUserDataDerivative)`>` -
+///   generated thumbnails (only set by data searches)
 /// * `msg` - `String` - message for
 ///   helping debug from the client
 ///
 #[derive(Serialize, Deserialize, Default, Clone)]
+#[cfg_attr(
+    feature = "graphql",
+    derive(async_graphql::SimpleObject),
+    graphql(name = "UserData")
+)]
 pub struct ModelUserData {
     pub user_id: i32,
     pub data_id: i32,
@@ -47,5 +170,36 @@ pub struct ModelUserData {
     // chrono::DateTime<chrono::Utc>
     pub created_at: String,
     pub updated_at: String,
+    #[serde(default)]
+    pub version: i32,
+    #[serde(default)]
+    pub archived: bool,
+    #[serde(default)]
+    pub status: String,
+    #[serde(default)]
+    pub storage_class: String,
+    #[serde(default)]
+    pub expires_at: String,
+    #[serde(default)]
+    pub scan_status: String,
+    #[serde(default)]
+    pub scan_signature: String,
+    #[serde(default)]
+    pub content_type: String,
+    #[serde(default)]
+    pub derivatives_status: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub folder: String,
+    #[serde(default)]
+    pub file_version: i32,
+    #[serde(default)]
+    pub version_of: i32,
+    #[serde(default)]
+    pub replaced_by: i32,
+    #[serde(default)]
+    pub derivatives: Vec<ModelUserDataDerivative>,
+    #[cfg_attr(feature = "graphql", graphql(skip))]
     pub msg: String,
 }
@@ -0,0 +1,297 @@
+//! Model for the ``users_data_access`` download audit
+//!
+//! Every successful download of a ``users_data`` file stores
+//! who downloaded it, how access was granted and how many
+//! bytes were sent (see
+//! [`get_user_data_download_response`](crate::requests::user::download_user_data::get_user_data_download_response)).
+//! Rows are kept after the file is archived or deleted so
+//! the owner's egress can still be billed, and owners get the
+//! stats for a file with
+//! [`get_user_data_access`](crate::requests::user::get_user_data_access::get_user_data_access).
+//!
+use tokio_postgres::Client;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::monitoring::otel::trace_db_query;
+use crate::requests::models::api_error::ApiError;
+
+/// max ``users_data_access`` rows returned with a file's
+/// access stats
+pub const USER_DATA_ACCESS_RECENT_LIMIT: i64 = 100;
+
+/// columns returned for a
+/// [`ModelUserDataAccess`](crate::requests::models::user_data_access::ModelUserDataAccess)
+pub const USER_DATA_ACCESS_COLUMNS: &str = "\
+    users_data_access.id, \
+    users_data_access.data_id, \
+    users_data_access.owner_user_id, \
+    users_data_access.user_id, \
+    users_data_access.access, \
+    users_data_access.size_in_bytes, \
+    users_data_access.created_at";
+
+/// ModelUserDataAccess
+///
+/// Representation in the db for one download of a
+/// `users_data` file
+///
+/// # DB table
+///
+/// `users_data_access`
+///
+/// # Arguments
+///
+/// * `access_id` - `i64` - `users_data_access.id` in the db
+/// * `data_id` - `i32` - `users_data.id` that was downloaded
+/// * `owner_user_id` - `i32` - `users.id` that owns the file
+/// * `user_id` - `Option<i32>` - `users.id` that downloaded
+///   the file (`None` for a public link download)
+/// * `access` - `String` - how access was granted:
+///   ``owner``, ``acl`` or ``share_token``
+/// * `size_in_bytes` - `i64` - bytes sent
+/// * `created_at` - `String` - download time
+///
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ModelUserDataAccess {
+    pub access_id: i64,
+    pub data_id: i32,
+    pub owner_user_id: i32,
+    pub user_id: Option<i32>,
+    pub access: String,
+    pub size_in_bytes: i64,
+    pub created_at: String,
+}
+
+/// ModelUserDataAccessStats
+///
+/// Download totals for a `users_data` file
+///
+/// # Arguments
+///
+/// * `data_id` - `i32` - `users_data.id`
+/// * `owner_user_id` - `i32` - `users.id` that owns the file
+/// * `num_downloads` - `i64` - number of downloads
+/// * `num_users` - `i64` - distinct users that downloaded
+///   the file (public link downloads are not counted)
+/// * `num_link_downloads` - `i64` - downloads with a public
+///   link
+/// * `total_bytes` - `i64` - bytes sent for all downloads
+/// * `last_downloaded_at` - `String` - latest download time
+///   (empty if the file was never downloaded)
+///
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ModelUserDataAccessStats {
+    pub data_id: i32,
+    pub owner_user_id: i32,
+    pub num_downloads: i64,
+    pub num_users: i64,
+    pub num_link_downloads: i64,
+    pub total_bytes: i64,
+    pub last_downloaded_at: String,
+}
+
+/// get_user_data_access_from_row
+///
+/// Convert a row with the
+/// [`USER_DATA_ACCESS_COLUMNS`](crate::requests::models::user_data_access::USER_DATA_ACCESS_COLUMNS)
+/// into a
+/// [`ModelUserDataAccess`](crate::requests::models::user_data_access::ModelUserDataAccess)
+///
+/// # Arguments
+///
+/// * `row` - [`Row`](tokio_postgres::Row) - db row
+///
+/// # Returns
+///
+/// [`ModelUserDataAccess`](crate::requests::models::user_data_access::ModelUserDataAccess)
+///
+pub fn get_user_data_access_from_row(
+    row: &tokio_postgres::Row,
+) -> ModelUserDataAccess {
+    let created_at_utc: chrono::DateTime<chrono::Utc> =
+        row.try_get("created_at").unwrap();
+    ModelUserDataAccess {
+        access_id: row.try_get("id").unwrap(),
+        data_id: row.try_get("data_id").unwrap(),
+        owner_user_id: row.try_get("owner_user_id").unwrap(),
+        user_id: row.try_get("user_id").unwrap(),
+        access: row.try_get("access").unwrap(),
+        size_in_bytes: row.try_get("size_in_bytes").unwrap(),
+        created_at: format!("{}", created_at_utc.format("%Y-%m-%dT%H:%M:%SZ")),
+    }
+}
+
+/// insert_user_data_access
+///
+/// Store one download of a `users_data` file
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `data_id` - `i32` - `users_data.id` that was downloaded
+/// * `owner_user_id` - `i32` - `users.id` that owns the file
+/// * `user_id` - `Option<i32>` - `users.id` that downloaded
+///   the file (`None` for a public link download)
+/// * `access` - `&str` - ``owner``, ``acl`` or ``share_token``
+/// * `size_in_bytes` - `i64` - bytes sent
+/// * `client` - [`Client`](tokio_postgres::Client) - db
+///   connection
+///
+/// # Errors
+///
+/// Err([`ApiError`](crate::requests::models::api_error::ApiError))
+///
+pub async fn insert_user_data_access(
+    tracking_label: &str,
+    data_id: i32,
+    owner_user_id: i32,
+    user_id: Option<i32>,
+    access: &str,
+    size_in_bytes: i64,
+    client: &Client,
+) -> Result<(), ApiError> {
+    let query = "INSERT INTO \
+            users_data_access (\
+                data_id, \
+                owner_user_id, \
+                user_id, \
+                access, \
+                size_in_bytes) \
+        VALUES ($1, $2, $3, $4, $5);";
+    let action = format!("insert users_data_access for data_id={data_id}");
+    match trace_db_query(
+        query,
+        client.execute(
+            query,
+            &[&data_id, &owner_user_id, &user_id, &access, &size_in_bytes],
+        ),
+    )
+    .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => Err(ApiError::from_db_error(tracking_label, &action, &e)),
+    }
+}
+
+/// get_user_data_access_stats
+///
+/// Get the download totals and the most recent downloads
+/// for a `users_data` or `users_data_archive` file
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `data_id` - `i32` - `users_data.id`
+/// * `owner_user_id` - `i32` - only return the stats if
+///   this user owns the file
+/// * `client` - [`Client`](tokio_postgres::Client) - db
+///   connection
+///
+/// # Returns
+///
+/// Ok(([`ModelUserDataAccessStats`](crate::requests::models::user_data_access::ModelUserDataAccessStats),
+/// `Vec<`[`ModelUserDataAccess`](crate::requests::models::user_data_access::ModelUserDataAccess)`>`))
+/// with up to
+/// [`USER_DATA_ACCESS_RECENT_LIMIT`](crate::requests::models::user_data_access::USER_DATA_ACCESS_RECENT_LIMIT)
+/// downloads sorted by newest first
+///
+/// # Errors
+///
+/// Err([`ApiError`](crate::requests::models::api_error::ApiError)) -
+/// `NotFound` if the file does not exist or is owned by
+/// another user
+///
+pub async fn get_user_data_access_stats(
+    tracking_label: &str,
+    data_id: i32,
+    owner_user_id: i32,
+    client: &Client,
+) -> Result<(ModelUserDataAccessStats, Vec<ModelUserDataAccess>), ApiError> {
+    let query = format!(
+        "SELECT \
+            d.user_id AS owner_user_id, \
+            COUNT(users_data_access.id) AS num_downloads, \
+            COUNT(DISTINCT users_data_access.user_id) AS num_users, \
+            COUNT(users_data_access.id) FILTER (\
+                WHERE users_data_access.user_id IS NULL) \
+                AS num_link_downloads, \
+            COALESCE(SUM(users_data_access.size_in_bytes), 0)::BIGINT \
+                AS total_bytes, \
+            MAX(users_data_access.created_at) AS last_downloaded_at \
+        FROM (\
+            SELECT users_data.user_id FROM users_data \
+            WHERE users_data.id = {data_id} \
+            UNION ALL \
+            SELECT users_data_archive.user_id FROM users_data_archive \
+            WHERE users_data_archive.id = {data_id} \
+            LIMIT 1\
+        ) AS d \
+        LEFT JOIN \
+            users_data_access \
+        ON \
+            users_data_access.data_id = {data_id} \
+        WHERE \
+            d.user_id = {owner_user_id} \
+        GROUP BY \
+            d.user_id;"
+    );
+    let action = format!("get users_data_access stats for data_id={data_id}");
+    let stats = match trace_db_query(&query, client.query(query.as_str(), &[]))
+        .await
+    {
+        Ok(query_result) => match query_result.first() {
+            Some(row) => {
+                let last_downloaded_at: Option<chrono::DateTime<chrono::Utc>> =
+                    row.try_get("last_downloaded_at").unwrap();
+                ModelUserDataAccessStats {
+                    data_id,
+                    owner_user_id: row.try_get("owner_user_id").unwrap(),
+                    num_downloads: row.try_get("num_downloads").unwrap(),
+                    num_users: row.try_get("num_users").unwrap(),
+                    num_link_downloads: row
+                        .try_get("num_link_downloads")
+                        .unwrap(),
+                    total_bytes: row.try_get("total_bytes").unwrap(),
+                    last_downloaded_at: last_downloaded_at
+                        .map(|v| format!("{}", v.format("%Y-%m-%dT%H:%M:%SZ")))
+                        .unwrap_or_default(),
+                }
+            }
+            None => {
+                return Err(ApiError::NotFound(format!(
+                    "{tracking_label} - \
+                        failed to {action} - no user data found"
+                )));
+            }
+        },
+        Err(e) => {
+            return Err(ApiError::from_db_error(tracking_label, &action, &e));
+        }
+    };
+
+    let query = format!(
+        "SELECT \
+            {USER_DATA_ACCESS_COLUMNS} \
+        FROM \
+            users_data_access \
+        WHERE \
+            users_data_access.data_id = {data_id} \
+        ORDER BY \
+            users_data_access.created_at DESC, \
+            users_data_access.id DESC \
+        LIMIT {USER_DATA_ACCESS_RECENT_LIMIT};"
+    );
+    let action = format!("get users_data_access rows for data_id={data_id}");
+    match trace_db_query(&query, client.query(query.as_str(), &[])).await {
+        Ok(query_result) => Ok((
+            stats,
+            query_result
+                .iter()
+                .map(get_user_data_access_from_row)
+                .collect(),
+        )),
+        Err(e) => Err(ApiError::from_db_error(tracking_label, &action, &e)),
+    }
+}
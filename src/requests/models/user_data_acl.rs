@@ -0,0 +1,170 @@
+//! Model for sharing user-uploaded s3 keys with other users
+//! or roles
+//!
+use serde::Deserialize;
+use serde::Serialize;
+
+/// ModelUserDataAcl
+///
+/// Representation in the db for a
+/// share (access control entry) on a `users_data` record
+///
+/// Each `users_data` record can be shared with many users
+/// (by `users.id`) or roles (by `users.role`)
+///
+/// # DB table
+///
+/// `users_data_acl`
+///
+/// # Arguments
+///
+/// * `acl_id` - `i32` - `users_data_acl.id` in the db
+/// * `data_id` - `i32` - `users_data.id` being shared
+/// * `owner_user_id` - `i32` - `users.id` that owns the
+///   `users_data` record
+/// * `grantee_user_id` - `Option<i32>` - `users.id` the
+///   record is shared with
+/// * `grantee_role` - `Option<String>` - `users.role` the
+///   record is shared with
+/// * `access` - `String` - `read` (search only) or
+///   `write` (search and update)
+/// * `created_at` - `String` - share creation time
+/// * `updated_at` - `String` - most recent update time
+///
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ModelUserDataAcl {
+    pub acl_id: i32,
+    pub data_id: i32,
+    pub owner_user_id: i32,
+    pub grantee_user_id: Option<i32>,
+    pub grantee_role: Option<String>,
+    pub access: String,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// is_valid_user_data_access
+///
+/// Check the `access` value is a supported
+/// `users_data_acl.access` level
+///
+/// # Arguments
+///
+/// * `access` - `&str` - requested access level
+///
+/// # Returns
+///
+/// `bool` where `true` - `access` is `read` or `write`
+///
+pub fn is_valid_user_data_access(access: &str) -> bool {
+    access == "read" || access == "write"
+}
+
+/// get_user_data_access_sql
+///
+/// Build the sql condition for filtering `users_data`
+/// records to the ones a user owns or was granted
+/// access to by user id or role. Read access also includes
+/// records shared with the user through an unexpired
+/// `users_data_shares` share.
+///
+/// # Arguments
+///
+/// * `user_id` - `i32` - `users.id` requesting access
+/// * `role` - `&str` - `users.role` requesting access
+/// * `write_access` - `bool` - `true` if the user needs
+///   `write` access (update), `false` for `read` or `write`
+///   access (search)
+///
+/// # Returns
+///
+/// `String` - sql condition on the `users_data` table
+/// wrapped in parentheses
+///
+pub fn get_user_data_access_sql(
+    user_id: i32,
+    role: &str,
+    write_access: bool,
+) -> String {
+    get_access_sql(user_id, role, write_access, "users_data_acl", !write_access)
+}
+
+/// get_user_data_archive_access_sql
+///
+/// Build the sql condition for filtering archived
+/// `users_data_archive` records (queried with the
+/// `users_data` alias) to the ones a user owns or was
+/// granted access to before the record was archived.
+/// Archived records are read-only.
+///
+/// # Arguments
+///
+/// * `user_id` - `i32` - `users.id` requesting access
+/// * `role` - `&str` - `users.role` requesting access
+///
+/// # Returns
+///
+/// `String` - sql condition on the `users_data` alias
+/// wrapped in parentheses
+///
+pub fn get_user_data_archive_access_sql(user_id: i32, role: &str) -> String {
+    get_access_sql(
+        user_id,
+        role,
+        false,
+        "users_data_acl_archive AS users_data_acl",
+        false,
+    )
+}
+
+/// get_access_sql
+///
+/// Build the access condition against the
+/// `acl_from` shares table and optionally the
+/// `users_data_shares` user shares
+///
+fn get_access_sql(
+    user_id: i32,
+    role: &str,
+    write_access: bool,
+    acl_from: &str,
+    include_user_shares: bool,
+) -> String {
+    let access_filter = match write_access {
+        true => "AND users_data_acl.access = 'write' ",
+        false => "",
+    };
+    let user_shares_sql = match include_user_shares {
+        true => format!(
+            "OR EXISTS (\
+                SELECT 1 FROM \
+                    users_data_shares \
+                WHERE \
+                    users_data_shares.data_id = users_data.id \
+                AND \
+                    users_data_shares.shared_with_user_id = {user_id} \
+                AND (\
+                    users_data_shares.expires_at IS NULL \
+                    OR users_data_shares.expires_at \
+                        > timezone('UTC'::text, now()))) "
+        ),
+        false => "".to_string(),
+    };
+    format!(
+        "(users_data.user_id = {user_id} \
+        {user_shares_sql}\
+        OR EXISTS (\
+            SELECT 1 FROM \
+                {acl_from} \
+            WHERE \
+                users_data_acl.data_id = users_data.id \
+            {access_filter}\
+            AND (\
+                users_data_acl.grantee_user_id = {user_id} \
+                OR (users_data_acl.grantee_role = '{}' \
+                    AND users_data.tenant_id = (\
+                        SELECT users.tenant_id FROM users \
+                        WHERE users.id = {user_id})))))",
+        role.replace('\'', "''")
+    )
+}
@@ -0,0 +1,86 @@
+//! Filename collision policies for ``users_data`` uploads
+//!
+//! An upload collides with the user's current record that has
+//! the same ``filename`` in the same ``folder`` (older versions
+//! and ``expired`` records do not collide). The policy comes
+//! from the upload's ``on_conflict`` header (or metadata value)
+//! or the ``USER_DATA_FILENAME_CONFLICT`` default:
+//!
+//! - ``allow`` - store another record with the same filename
+//!   (the default)
+//! - ``reject`` - fail the upload with a ``409``
+//! - ``suffix`` - store the upload with a version suffix like
+//!   ``report-v2.txt``
+//! - ``overwrite`` - store the upload as the next
+//!   ``file_version`` of the current record, which is kept as
+//!   an older version (see the ``versions_of`` filter in
+//!   [`ApiReqUserSearchData`](crate::requests::user::search_user_data::ApiReqUserSearchData))
+//!
+
+/// supported filename collision policies
+pub const USER_DATA_CONFLICT_POLICIES: [&str; 4] =
+    ["allow", "reject", "suffix", "overwrite"];
+
+/// get_user_data_conflict_policy
+///
+/// Get the upload's filename collision policy or the
+/// ``USER_DATA_FILENAME_CONFLICT`` default (``allow``)
+///
+/// # Arguments
+///
+/// * `on_conflict` - `&Option<String>` - policy requested
+///   with the upload
+///
+/// # Returns
+///
+/// `String` - one of the
+/// [`USER_DATA_CONFLICT_POLICIES`](crate::requests::models::user_data_conflict::USER_DATA_CONFLICT_POLICIES)
+///
+pub fn get_user_data_conflict_policy(on_conflict: &Option<String>) -> String {
+    let policy = match on_conflict {
+        Some(v) => v.to_lowercase(),
+        None => std::env::var("USER_DATA_FILENAME_CONFLICT")
+            .unwrap_or_else(|_| "allow".to_string())
+            .to_lowercase(),
+    };
+    match USER_DATA_CONFLICT_POLICIES.contains(&policy.as_str()) {
+        true => policy,
+        false => {
+            warn!(
+                "unsupported USER_DATA_FILENAME_CONFLICT={policy} - \
+                allowing duplicate filenames"
+            );
+            "allow".to_string()
+        }
+    }
+}
+
+/// get_versioned_filename
+///
+/// Add a version suffix before the file extension
+///
+/// # Arguments
+///
+/// * `filename` - `&str` - uploaded filename
+/// * `file_version` - `i32` - version number for the suffix
+///
+/// # Returns
+///
+/// `String` - filename with a ``-vN`` suffix
+///
+/// ```rust
+/// use restapi::requests::models::user_data_conflict::get_versioned_filename;
+/// assert_eq!(get_versioned_filename("report.txt", 2), "report-v2.txt");
+/// assert_eq!(get_versioned_filename("logs.tar.gz", 3), "logs.tar-v3.gz");
+/// assert_eq!(get_versioned_filename("README", 2), "README-v2");
+/// assert_eq!(get_versioned_filename(".env", 2), ".env-v2");
+/// ```
+///
+pub fn get_versioned_filename(filename: &str, file_version: i32) -> String {
+    match filename.rfind('.') {
+        Some(idx) if idx > 0 => {
+            format!("{}-v{file_version}{}", &filename[..idx], &filename[idx..])
+        }
+        _ => format!("{filename}-v{file_version}"),
+    }
+}
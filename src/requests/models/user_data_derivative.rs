@@ -0,0 +1,69 @@
+//! Model for files generated from a user-uploaded s3 key
+//! (image thumbnails)
+//!
+use serde::Deserialize;
+use serde::Serialize;
+
+/// upload content types the
+/// [`UserDataThumbnails`](crate::processing::user_data_thumbnails::UserDataThumbnails)
+/// task can create thumbnails for
+pub const THUMBNAIL_CONTENT_TYPES: [&str; 4] =
+    ["image/png", "image/jpeg", "image/gif", "image/webp"];
+
+/// is_thumbnail_content_type
+///
+/// Check if an upload's content type gets thumbnails
+///
+/// # Arguments
+///
+/// * `content_type` - `&str` - upload ``Content-Type``
+///   (lowercase without parameters)
+///
+/// # Returns
+///
+/// `bool` - `true` for a value in
+/// [`THUMBNAIL_CONTENT_TYPES`](crate::requests::models::user_data_derivative::THUMBNAIL_CONTENT_TYPES)
+///
+pub fn is_thumbnail_content_type(content_type: &str) -> bool {
+    THUMBNAIL_CONTENT_TYPES.contains(&content_type)
+}
+
+/// ModelUserDataDerivative
+///
+/// Representation in the db for a file generated from a
+/// `users_data` record
+///
+/// Each `users_data` record can have many derivatives
+/// (one per `kind` and `size`)
+///
+/// # DB table
+///
+/// `users_data_derivatives`
+///
+/// # Arguments
+///
+/// * `kind` - `String` - derivative type (``thumbnail``)
+/// * `size` - `i32` - requested max width and height
+/// * `width` - `i32` - generated image width
+/// * `height` - `i32` - generated image height
+/// * `content_type` - `String` - generated file content type
+/// * `size_in_bytes` - `i64` - generated file size
+/// * `sloc` - `String` - full s3 location path
+/// * `created_at` - `String` - generation time
+///
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[cfg_attr(
+    feature = "graphql",
+    derive(async_graphql::SimpleObject),
+    graphql(name = "UserDataDerivative")
+)]
+pub struct ModelUserDataDerivative {
+    pub kind: String,
+    pub size: i32,
+    pub width: i32,
+    pub height: i32,
+    pub content_type: String,
+    pub size_in_bytes: i64,
+    pub sloc: String,
+    pub created_at: String,
+}
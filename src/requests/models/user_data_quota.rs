@@ -0,0 +1,146 @@
+//! Model for a user's ``users_data`` storage quota and usage
+//!
+//! A user's quota is ``users.quota_bytes`` or the
+//! ``USER_DATA_QUOTA_BYTES`` default when it is ``NULL``
+//! (``0`` = unlimited). Usage is the ``size_in_bytes`` of
+//! every ``users_data`` and ``users_data_archive`` record
+//! the user owns except ``failed``, ``expired`` and
+//! ``broken`` records
+//! (see
+//! [`UserDataQuota`](crate::requests::user::user_data_quota::UserDataQuota)).
+//!
+use tokio_postgres::Client;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::monitoring::otel::trace_db_query;
+use crate::requests::models::api_error::ApiError;
+
+/// ``users_data.status`` values that do not count
+/// towards a user's quota
+pub const USER_DATA_QUOTA_EXCLUDED_STATUSES: &str =
+    "'failed', 'expired', 'broken'";
+
+/// ModelUserDataQuota
+///
+/// A user's storage quota and current usage
+///
+/// # Arguments
+///
+/// * `user_id` - `i32` - user id in the db
+/// * `quota_bytes` - `i64` - max bytes the user can store
+///   (`0` = unlimited)
+/// * `used_bytes` - `i64` - bytes stored by the user's
+///   records
+/// * `available_bytes` - `i64` - bytes left before the
+///   quota is reached (`-1` = unlimited)
+/// * `num_files` - `i64` - number of records counted in
+///   `used_bytes`
+///
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[cfg_attr(
+    feature = "graphql",
+    derive(async_graphql::SimpleObject),
+    graphql(name = "UserDataQuota")
+)]
+pub struct ModelUserDataQuota {
+    pub user_id: i32,
+    pub quota_bytes: i64,
+    pub used_bytes: i64,
+    pub available_bytes: i64,
+    pub num_files: i64,
+}
+
+/// get_user_data_quota
+///
+/// Get a user's quota (``users.quota_bytes`` or
+/// `default_quota_bytes`) and usage
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `user_id` - `i32` - user id
+/// * `default_quota_bytes` - `i64` - quota for users without
+///   a ``users.quota_bytes`` override (`0` = unlimited)
+/// * `client` - [`Client`](tokio_postgres::Client) - db
+///   connection
+///
+/// # Returns
+///
+/// Ok([`ModelUserDataQuota`](crate::requests::models::user_data_quota::ModelUserDataQuota))
+///
+/// # Errors
+///
+/// Err([`ApiError`](crate::requests::models::api_error::ApiError)) -
+/// `NotFound` if the user does not exist
+///
+pub async fn get_user_data_quota(
+    tracking_label: &str,
+    user_id: i32,
+    default_quota_bytes: i64,
+    client: &Client,
+) -> Result<ModelUserDataQuota, ApiError> {
+    let query = format!(
+        "SELECT \
+            COALESCE(users.quota_bytes, {default_quota_bytes}) \
+                AS quota_bytes, \
+            usage.used_bytes, \
+            usage.num_files \
+        FROM \
+            users, \
+            LATERAL (\
+                SELECT \
+                    COALESCE(SUM(d.size_in_bytes), 0)::BIGINT \
+                        AS used_bytes, \
+                    COUNT(*) AS num_files \
+                FROM (\
+                    SELECT \
+                        users_data.size_in_bytes \
+                    FROM \
+                        users_data \
+                    WHERE \
+                        users_data.user_id = {user_id} \
+                    AND \
+                        users_data.status NOT IN \
+                            ({USER_DATA_QUOTA_EXCLUDED_STATUSES}) \
+                    UNION ALL \
+                    SELECT \
+                        users_data_archive.size_in_bytes \
+                    FROM \
+                        users_data_archive \
+                    WHERE \
+                        users_data_archive.user_id = {user_id} \
+                    AND \
+                        users_data_archive.status NOT IN \
+                            ({USER_DATA_QUOTA_EXCLUDED_STATUSES})\
+                ) AS d\
+            ) AS usage \
+        WHERE \
+            users.id = {user_id};"
+    );
+    let action = format!("get user data quota for user_id={user_id}");
+    match trace_db_query(&query, client.query(query.as_str(), &[])).await {
+        Ok(query_result) => match query_result.first() {
+            Some(row) => {
+                let quota_bytes: i64 = row.try_get("quota_bytes").unwrap();
+                let used_bytes: i64 = row.try_get("used_bytes").unwrap();
+                Ok(ModelUserDataQuota {
+                    user_id,
+                    quota_bytes,
+                    used_bytes,
+                    available_bytes: match quota_bytes {
+                        0 => -1,
+                        _ => (quota_bytes - used_bytes).max(0),
+                    },
+                    num_files: row.try_get("num_files").unwrap(),
+                })
+            }
+            None => Err(ApiError::NotFound(format!(
+                "{tracking_label} - \
+                failed to {action} - no user found"
+            ))),
+        },
+        Err(e) => Err(ApiError::from_db_error(tracking_label, &action, &e)),
+    }
+}
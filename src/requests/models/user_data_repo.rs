@@ -0,0 +1,1577 @@
+//! Typed queries for the ``users_data`` table
+//!
+//! Handlers use a
+//! [`UserDataRepo`](crate::requests::models::user_data_repo::UserDataRepo)
+//! instead of building sql strings and parsing rows. Every
+//! query selects (or returns) the
+//! [`USER_DATA_COLUMNS`](crate::requests::models::user_data_repo::USER_DATA_COLUMNS)
+//! and is parsed with
+//! [`get_user_data_from_row`](crate::requests::models::user_data_repo::get_user_data_from_row).
+//!
+//! Reads and updates are limited to records the user owns
+//! or was granted access to through the ``users_data_acl``
+//! table (reads also include ``users_data_shares`` user
+//! shares, see
+//! [`get_user_data_access_sql`](crate::requests::models::user_data_acl::get_user_data_access_sql)).
+//!
+use tokio_postgres::Client;
+use tokio_postgres::Row;
+
+use crate::monitoring::otel::trace_db_query;
+use crate::requests::models::api_error::ApiError;
+use crate::requests::models::user_data::ModelUserData;
+use crate::requests::models::user_data_acl::get_user_data_access_sql;
+use crate::requests::models::user_data_acl::get_user_data_archive_access_sql;
+use crate::requests::models::user_data_conflict::get_versioned_filename;
+use crate::requests::models::user_data_derivative::ModelUserDataDerivative;
+
+/// columns selected and returned for a
+/// [`ModelUserData`](crate::requests::models::user_data::ModelUserData)
+pub const USER_DATA_COLUMNS: &str = "\
+    users_data.id, \
+    users_data.user_id, \
+    users_data.filename, \
+    users_data.data_type, \
+    users_data.size_in_bytes, \
+    users_data.comments, \
+    users_data.encoding, \
+    users_data.sloc, \
+    users_data.status, \
+    users_data.created_at, \
+    users_data.updated_at, \
+    users_data.version, \
+    users_data.storage_class, \
+    users_data.expires_at, \
+    users_data.scan_status, \
+    users_data.scan_signature, \
+    users_data.content_type, \
+    users_data.derivatives_status, \
+    users_data.tags, \
+    users_data.folder, \
+    users_data.file_version, \
+    users_data.version_of, \
+    users_data.replaced_by";
+
+/// json array of the
+/// [`ModelUserDataDerivative`](crate::requests::models::user_data_derivative::ModelUserDataDerivative)
+/// records for each searched ``users_data`` record
+const USER_DATA_DERIVATIVES_SQL: &str = "\
+    COALESCE((\
+        SELECT \
+            json_agg(json_build_object(\
+                'kind', d.kind, \
+                'size', d.size, \
+                'width', d.width, \
+                'height', d.height, \
+                'content_type', d.content_type, \
+                'size_in_bytes', d.size_in_bytes, \
+                'sloc', d.sloc, \
+                'created_at', \
+                    to_char(d.created_at AT TIME ZONE 'UTC', \
+                        'YYYY-MM-DD\"T\"HH24:MI:SS\"Z\"')) \
+                ORDER BY d.kind, d.size) \
+        FROM \
+            users_data_derivatives AS d \
+        WHERE \
+            d.data_id = users_data.id), '[]'::json)";
+
+/// get_user_data_from_row
+///
+/// Parse a row with the
+/// [`USER_DATA_COLUMNS`](crate::requests::models::user_data_repo::USER_DATA_COLUMNS)
+/// (and optional `archived` and `derivatives` columns)
+///
+/// # Arguments
+///
+/// * `row` - [`Row`](tokio_postgres::Row) - db row
+///
+/// # Returns
+///
+/// [`ModelUserData`](crate::requests::models::user_data::ModelUserData)
+///
+pub fn get_user_data_from_row(row: &Row) -> ModelUserData {
+    let created_at: chrono::DateTime<chrono::Utc> =
+        row.try_get("created_at").unwrap();
+    let updated_at: Option<chrono::DateTime<chrono::Utc>> =
+        row.try_get("updated_at").unwrap();
+    ModelUserData {
+        user_id: row.try_get("user_id").unwrap(),
+        data_id: row.try_get("id").unwrap(),
+        filename: row.try_get("filename").unwrap(),
+        data_type: row.try_get("data_type").unwrap(),
+        size_in_bytes: row.try_get("size_in_bytes").unwrap(),
+        comments: row.try_get("comments").unwrap(),
+        encoding: row.try_get("encoding").unwrap(),
+        sloc: row.try_get("sloc").unwrap(),
+        created_at: format!("{}", created_at.format("%Y-%m-%dT%H:%M:%SZ")),
+        updated_at: updated_at
+            .map(|v| format!("{}", v.format("%Y-%m-%dT%H:%M:%SZ")))
+            .unwrap_or_default(),
+        version: row.try_get("version").unwrap(),
+        archived: row.try_get("archived").unwrap_or(false),
+        status: row.try_get("status").unwrap(),
+        storage_class: row
+            .try_get::<_, Option<String>>("storage_class")
+            .unwrap()
+            .unwrap_or_default(),
+        expires_at: row
+            .try_get::<_, Option<chrono::DateTime<chrono::Utc>>>("expires_at")
+            .unwrap()
+            .map(|v| format!("{}", v.format("%Y-%m-%dT%H:%M:%SZ")))
+            .unwrap_or_default(),
+        scan_status: row
+            .try_get::<_, Option<String>>("scan_status")
+            .unwrap()
+            .unwrap_or_default(),
+        scan_signature: row
+            .try_get::<_, Option<String>>("scan_signature")
+            .unwrap()
+            .unwrap_or_default(),
+        content_type: row
+            .try_get::<_, Option<String>>("content_type")
+            .unwrap()
+            .unwrap_or_default(),
+        derivatives_status: row
+            .try_get::<_, Option<String>>("derivatives_status")
+            .unwrap()
+            .unwrap_or_default(),
+        tags: row.try_get("tags").unwrap(),
+        folder: row
+            .try_get::<_, Option<String>>("folder")
+            .unwrap()
+            .unwrap_or_default(),
+        file_version: row.try_get("file_version").unwrap(),
+        version_of: row
+            .try_get::<_, Option<i32>>("version_of")
+            .unwrap()
+            .unwrap_or_default(),
+        replaced_by: row
+            .try_get::<_, Option<i32>>("replaced_by")
+            .unwrap()
+            .unwrap_or_default(),
+        derivatives: row
+            .try_get::<_, serde_json::Value>("derivatives")
+            .ok()
+            .and_then(|v| {
+                serde_json::from_value::<Vec<ModelUserDataDerivative>>(v).ok()
+            })
+            .unwrap_or_default(),
+        msg: "success".to_string(),
+    }
+}
+
+/// get_sql_string_or_null
+///
+/// Quote an optional string value for an insert
+///
+fn get_sql_string_or_null(value: &Option<String>) -> String {
+    match value {
+        Some(v) => format!("'{}'", v.replace('\'', "''")),
+        None => "NULL".to_string(),
+    }
+}
+
+/// get_sql_text_array
+///
+/// Build a deduped ``text[]`` literal for an insert, update
+/// or search
+///
+fn get_sql_text_array(values: &[String]) -> String {
+    let mut unique_values: Vec<&String> = Vec::with_capacity(values.len());
+    for v in values.iter() {
+        if !unique_values.contains(&v) {
+            unique_values.push(v);
+        }
+    }
+    match unique_values.is_empty() {
+        true => "'{}'::text[]".to_string(),
+        false => format!(
+            "ARRAY[{}]::text[]",
+            unique_values
+                .iter()
+                .map(|v| format!("'{}'", v.replace('\'', "''")))
+                .collect::<Vec<String>>()
+                .join(", ")
+        ),
+    }
+}
+
+/// get_sql_like_prefix
+///
+/// Escape a value for a ``LIKE 'value%'`` prefix filter
+///
+fn get_sql_like_prefix(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+        .replace('\'', "''")
+}
+
+/// NewUserData
+///
+/// Values for inserting a ``users_data`` record
+///
+/// # Arguments
+///
+/// * `user_id` - `i32` - owner user id
+/// * `filename` - `String` - data filename
+/// * `data_type` - `String` - data type
+/// * `size_in_bytes` - `i64` - file size
+/// * `comments` - `String` - file comments
+/// * `encoding` - `String` - file encoding
+/// * `sloc` - `String` - full s3 location path
+/// * `status` - `String` - upload status (``pending`` or
+///   ``ready``)
+/// * `checksum` - `String` - hex sha256 of the contents
+///   (empty = none)
+/// * `storage_class` - `Option<String>` - s3 storage class
+///   (`None` = ``S3_STORAGE_CLASS``)
+/// * `expire_days` - `Option<i64>` - days until the s3
+///   object expires (`None` = never)
+/// * `expire_storage_class` - `Option<String>` - storage
+///   class to move the s3 object to when it expires
+///   (`None` = delete it)
+/// * `scan_status` - `Option<String>` - upload scanner
+///   result (`None` = not scanned)
+/// * `scan_signature` - `Option<String>` - signature found
+///   in an infected file
+/// * `content_type` - `Option<String>` - upload
+///   ``Content-Type`` (`None` = unknown)
+/// * `derivatives_status` - `Option<String>` - ``pending``
+///   to queue the upload for the thumbnail task (`None` =
+///   no thumbnails)
+/// * `tags` - `Vec<String>` - user-defined tags
+/// * `folder` - `Option<String>` - folder path (`None` =
+///   not in a folder)
+/// * `file_version` - `Option<i32>` - version of the file
+///   (`None` = ``1``)
+/// * `version_of` - `Option<i32>` - ``users_data.id`` of the
+///   file's first version (`None` = not a newer version)
+///
+#[derive(Clone, Default)]
+pub struct NewUserData {
+    pub user_id: i32,
+    pub filename: String,
+    pub data_type: String,
+    pub size_in_bytes: i64,
+    pub comments: String,
+    pub encoding: String,
+    pub sloc: String,
+    pub status: String,
+    pub checksum: String,
+    pub storage_class: Option<String>,
+    pub expire_days: Option<i64>,
+    pub expire_storage_class: Option<String>,
+    pub scan_status: Option<String>,
+    pub scan_signature: Option<String>,
+    pub content_type: Option<String>,
+    pub derivatives_status: Option<String>,
+    pub tags: Vec<String>,
+    pub folder: Option<String>,
+    pub file_version: Option<i32>,
+    pub version_of: Option<i32>,
+}
+
+/// UserDataChanges
+///
+/// Optional values for updating a ``users_data`` record
+/// (`None` leaves the column unchanged)
+///
+/// # Arguments
+///
+/// * `filename` - `Option<String>` - data filename
+/// * `data_type` - `Option<String>` - data type
+/// * `comments` - `Option<String>` - file comments
+/// * `encoding` - `Option<String>` - file encoding
+/// * `sloc` - `Option<String>` - full s3 location path
+/// * `tags` - `Option<Vec<String>>` - replace the tags
+/// * `folder` - `Option<String>` - move the record to a
+///   folder (an empty string removes it from its folder)
+/// * `expected_version` - `Option<i32>` - only update the
+///   record if its `users_data.version` still matches
+///
+#[derive(Clone, Default)]
+pub struct UserDataChanges {
+    pub filename: Option<String>,
+    pub data_type: Option<String>,
+    pub comments: Option<String>,
+    pub encoding: Option<String>,
+    pub sloc: Option<String>,
+    pub tags: Option<Vec<String>>,
+    pub folder: Option<String>,
+    pub expected_version: Option<i32>,
+}
+
+/// UserDataSearch
+///
+/// Filters for searching ``users_data`` records ordered by
+/// newest first (max 100 records)
+///
+/// # Arguments
+///
+/// * `creator_user_id` - `Option<i32>` - owner user id
+/// * `data_id` - `Option<i32>` - ``users_data.id``
+/// * `filename` - `Option<String>` - ``ILIKE`` filter
+/// * `data_type` - `Option<String>` - ``ILIKE`` filter
+/// * `above_bytes` - `Option<i64>` - min size (exclusive)
+/// * `below_bytes` - `Option<i64>` - max size (exclusive)
+/// * `comments` - `Option<String>` - ``ILIKE`` filter
+/// * `encoding` - `Option<String>` - ``ILIKE`` filter
+/// * `sloc` - `Option<String>` - ``ILIKE`` filter
+/// * `status` - `Option<String>` - exact upload status
+/// * `tags` - `Option<Vec<String>>` - records with all of
+///   these tags
+/// * `folder` - `Option<String>` - records in this folder
+/// * `include_subfolders` - `bool` - also match records in
+///   the `folder`'s subfolders
+/// * `include_archived` - `bool` - also search the
+///   ``users_data_archive`` table
+/// * `versions_of` - `Option<i32>` - every version of the
+///   file with this ``users_data.id``
+/// * `include_versions` - `bool` - also match older file
+///   versions (only current versions are matched unless a
+///   `data_id` or `versions_of` filter is set)
+///
+#[derive(Clone, Default)]
+pub struct UserDataSearch {
+    pub creator_user_id: Option<i32>,
+    pub data_id: Option<i32>,
+    pub filename: Option<String>,
+    pub data_type: Option<String>,
+    pub above_bytes: Option<i64>,
+    pub below_bytes: Option<i64>,
+    pub comments: Option<String>,
+    pub encoding: Option<String>,
+    pub sloc: Option<String>,
+    pub status: Option<String>,
+    pub tags: Option<Vec<String>>,
+    pub folder: Option<String>,
+    pub include_subfolders: bool,
+    pub include_archived: bool,
+    pub versions_of: Option<i32>,
+    pub include_versions: bool,
+}
+
+impl UserDataSearch {
+    /// get_table_sql
+    ///
+    /// Build the filtered `SELECT` for one data table
+    /// (queried with the `users_data` alias)
+    ///
+    /// # Arguments
+    ///
+    /// * `table_from` - `&str` - table with the `users_data` alias
+    /// * `archived` - `bool` - value for the `archived` column
+    /// * `access_sql` - `&str` - access condition from
+    ///   [`get_user_data_access_sql`](crate::requests::models::user_data_acl::get_user_data_access_sql)
+    ///
+    fn get_table_sql(
+        &self,
+        table_from: &str,
+        archived: bool,
+        access_sql: &str,
+    ) -> String {
+        let mut conditions: Vec<String> = vec![access_sql.to_string()];
+        if let Some(v) = self.creator_user_id {
+            conditions.push(format!("users_data.user_id = {v}"));
+        }
+        if let Some(v) = self.data_id {
+            conditions.push(format!("users_data.id = {v}"));
+        }
+        match self.versions_of {
+            // the first version's id is shared by every version
+            // (older versions may be archived)
+            Some(v) => conditions.push(format!(
+                "(SELECT \
+                    COALESCE(first_version.version_of, first_version.id) \
+                FROM (\
+                    SELECT id, version_of FROM users_data \
+                    WHERE id = {v} \
+                    UNION ALL \
+                    SELECT id, version_of FROM users_data_archive \
+                    WHERE id = {v}) AS first_version \
+                LIMIT 1) IN (users_data.id, users_data.version_of)"
+            )),
+            None => {
+                if !self.include_versions && self.data_id.is_none() {
+                    conditions
+                        .push("users_data.replaced_by IS NULL".to_string());
+                }
+            }
+        }
+        if let Some(v) = &self.status {
+            conditions.push(format!(
+                "users_data.status = '{}'",
+                v.replace('\'', "''")
+            ));
+        }
+        if let Some(v) = &self.tags {
+            if !v.is_empty() {
+                conditions.push(format!(
+                    "users_data.tags @> {}",
+                    get_sql_text_array(v)
+                ));
+            }
+        }
+        if let Some(v) = &self.folder {
+            let folder_sql = format!("'{}'", v.replace('\'', "''"));
+            match (self.include_subfolders, v.as_str()) {
+                (false, _) => {
+                    conditions.push(format!("users_data.folder = {folder_sql}"))
+                }
+                (true, "/") => {
+                    conditions.push("users_data.folder LIKE '/%'".to_string())
+                }
+                (true, _) => conditions.push(format!(
+                    "(users_data.folder = {folder_sql} \
+                    OR users_data.folder LIKE '{}/%')",
+                    get_sql_like_prefix(v)
+                )),
+            }
+        }
+        // https://www.google.com/search?q=rust+bigint+postgres
+        // postgres size_in_bytes field is a BIGINT type
+        if let Some(v) = self.above_bytes {
+            conditions.push(format!("users_data.size_in_bytes > {v}"));
+        }
+        if let Some(v) = self.below_bytes {
+            conditions.push(format!("users_data.size_in_bytes < {v}"));
+        }
+        let ilike_filters = [
+            ("filename", &self.filename),
+            ("data_type", &self.data_type),
+            ("comments", &self.comments),
+            ("encoding", &self.encoding),
+            ("sloc", &self.sloc),
+        ];
+        for (column, value) in ilike_filters.iter() {
+            if let Some(v) = value {
+                conditions.push(format!(
+                    "users_data.{column} ILIKE '%{}%'",
+                    v.replace('\'', "''")
+                ));
+            }
+        }
+        format!(
+            "SELECT \
+                {USER_DATA_COLUMNS}, \
+                {USER_DATA_DERIVATIVES_SQL} AS derivatives, \
+                {archived} AS archived \
+            FROM \
+                {table_from} \
+            WHERE \
+                {}",
+            conditions.join(" AND ")
+        )
+    }
+}
+
+/// UserDataSloc
+///
+/// A ``users_data`` or ``users_data_archive`` record's s3
+/// location returned by
+/// [`find_by_sloc_prefix`](crate::requests::models::user_data_repo::UserDataRepo::find_by_sloc_prefix)
+///
+/// # Arguments
+///
+/// * `data_id` - `i32` - ``users_data.id``
+/// * `user_id` - `i32` - owner user id
+/// * `sloc` - `String` - full s3 location path
+/// * `status` - `String` - upload status
+/// * `archived` - `bool` - the record is in the
+///   ``users_data_archive`` table
+///
+#[derive(Clone, Debug, Default)]
+pub struct UserDataSloc {
+    pub data_id: i32,
+    pub user_id: i32,
+    pub sloc: String,
+    pub status: String,
+    pub archived: bool,
+}
+
+/// UserDataRepo
+///
+/// Typed queries for the ``users_data`` table on one db
+/// connection (a primary or read replica
+/// [`PooledConnection`](bb8::PooledConnection) derefs
+/// to the [`Client`](tokio_postgres::Client))
+///
+pub struct UserDataRepo<'a> {
+    client: &'a Client,
+}
+
+impl<'a> UserDataRepo<'a> {
+    /// new
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - [`Client`](tokio_postgres::Client) - db
+    ///   connection
+    ///
+    pub fn new(client: &'a Client) -> Self {
+        UserDataRepo { client }
+    }
+
+    /// query_one
+    ///
+    /// Run a query that returns at most one record
+    ///
+    async fn query_one(
+        &self,
+        tracking_label: &str,
+        action: &str,
+        query: &str,
+    ) -> Result<ModelUserData, ApiError> {
+        match trace_db_query(query, self.client.query(query, &[])).await {
+            Ok(query_result) => match query_result.first() {
+                Some(row) => Ok(get_user_data_from_row(row)),
+                None => Err(ApiError::NotFound(format!(
+                    "{tracking_label} - \
+                    failed to {action} - no user data found"
+                ))),
+            },
+            Err(e) => Err(ApiError::from_db_error(tracking_label, action, &e)),
+        }
+    }
+
+    /// insert
+    ///
+    /// Create a ``users_data`` record
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - caller logging label
+    /// * `new_data` - [`NewUserData`](crate::requests::models::user_data_repo::NewUserData)
+    ///
+    /// # Returns
+    ///
+    /// Ok([`ModelUserData`](crate::requests::models::user_data::ModelUserData)) -
+    /// the new record
+    ///
+    /// # Errors
+    ///
+    /// Err([`ApiError`](crate::requests::models::api_error::ApiError))
+    ///
+    pub async fn insert(
+        &self,
+        tracking_label: &str,
+        new_data: &NewUserData,
+    ) -> Result<ModelUserData, ApiError> {
+        let query = format!(
+            "INSERT INTO \
+                users_data (\
+                    user_id, \
+                    filename, \
+                    data_type, \
+                    size_in_bytes, \
+                    comments, \
+                    encoding, \
+                    sloc, \
+                    status, \
+                    checksum, \
+                    storage_class, \
+                    expires_at, \
+                    expire_storage_class, \
+                    scan_status, \
+                    scan_signature, \
+                    content_type, \
+                    derivatives_status, \
+                    tags, \
+                    folder, \
+                    file_version, \
+                    version_of, \
+                    tenant_id) \
+            VALUES (\
+                {}, \
+                '{}', \
+                '{}', \
+                {}, \
+                '{}', \
+                '{}', \
+                '{}', \
+                '{}', \
+                NULLIF('{}', ''), \
+                {}, \
+                {}, \
+                {}, \
+                {}, \
+                {}, \
+                {}, \
+                {}, \
+                {}, \
+                {}, \
+                {}, \
+                {}, \
+                (SELECT users.tenant_id FROM users \
+                    WHERE users.id = {})) \
+            RETURNING \
+                {USER_DATA_COLUMNS};",
+            new_data.user_id,
+            new_data.filename.replace('\'', "''"),
+            new_data.data_type.replace('\'', "''"),
+            new_data.size_in_bytes,
+            new_data.comments.replace('\'', "''"),
+            new_data.encoding.replace('\'', "''"),
+            new_data.sloc.replace('\'', "''"),
+            new_data.status.replace('\'', "''"),
+            new_data.checksum.replace('\'', "''"),
+            get_sql_string_or_null(&new_data.storage_class),
+            match new_data.expire_days {
+                Some(v) => format!(
+                    "timezone('UTC'::text, now()) + interval '{v} days'"
+                ),
+                None => "NULL".to_string(),
+            },
+            get_sql_string_or_null(&new_data.expire_storage_class),
+            get_sql_string_or_null(&new_data.scan_status),
+            get_sql_string_or_null(&new_data.scan_signature),
+            get_sql_string_or_null(&new_data.content_type),
+            get_sql_string_or_null(&new_data.derivatives_status),
+            get_sql_text_array(&new_data.tags),
+            get_sql_string_or_null(&new_data.folder),
+            new_data.file_version.unwrap_or(1),
+            match new_data.version_of {
+                Some(v) => format!("{v}"),
+                None => "NULL".to_string(),
+            },
+            new_data.user_id
+        );
+        self.query_one(
+            tracking_label,
+            &format!("insert user data for user_id={}", new_data.user_id),
+            &query,
+        )
+        .await
+    }
+
+    /// insert_with_conflict_policy
+    ///
+    /// Create a ``users_data`` record after applying a
+    /// filename collision policy (see
+    /// [`user_data_conflict`](crate::requests::models::user_data_conflict))
+    /// in one transaction. A transaction-level advisory lock
+    /// on the user, folder and filename keeps concurrent
+    /// uploads of the same file from both passing the check.
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - caller logging label
+    /// * `new_data` - [`NewUserData`](crate::requests::models::user_data_repo::NewUserData)
+    /// * `policy` - `&str` - ``allow``, ``reject``, ``suffix``
+    ///   or ``overwrite``
+    ///
+    /// # Returns
+    ///
+    /// Ok([`ModelUserData`](crate::requests::models::user_data::ModelUserData)) -
+    /// the new record (with a suffixed `filename` for
+    /// ``suffix`` and the next `file_version` for
+    /// ``overwrite``)
+    ///
+    /// # Errors
+    ///
+    /// Err([`ApiError`](crate::requests::models::api_error::ApiError)) -
+    /// `Conflict` if the policy is ``reject`` and the user
+    /// already has the filename in the folder
+    ///
+    pub async fn insert_with_conflict_policy(
+        &self,
+        tracking_label: &str,
+        new_data: &NewUserData,
+        policy: &str,
+    ) -> Result<ModelUserData, ApiError> {
+        if policy == "allow" {
+            return self.insert(tracking_label, new_data).await;
+        }
+        self.begin_transaction(tracking_label).await?;
+        let result = self
+            .insert_with_conflict_policy_in_transaction(
+                tracking_label,
+                new_data,
+                policy,
+            )
+            .await;
+        match result {
+            Ok(user_data) => {
+                self.commit_transaction(tracking_label).await?;
+                Ok(user_data)
+            }
+            Err(e) => {
+                if let Err(rollback_err) =
+                    self.rollback_transaction(tracking_label).await
+                {
+                    error!("{rollback_err}");
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// insert_with_conflict_policy_in_transaction
+    ///
+    /// Lock the filename and insert the record for
+    /// [`insert_with_conflict_policy`](crate::requests::models::user_data_repo::UserDataRepo::insert_with_conflict_policy)
+    ///
+    async fn insert_with_conflict_policy_in_transaction(
+        &self,
+        tracking_label: &str,
+        new_data: &NewUserData,
+        policy: &str,
+    ) -> Result<ModelUserData, ApiError> {
+        let folder_sql = get_sql_string_or_null(&new_data.folder);
+        let lock_query = format!(
+            "SELECT pg_advisory_xact_lock(\
+                hashtext({} || ':' || COALESCE({folder_sql}, '') \
+                    || ':' || '{}'));",
+            new_data.user_id,
+            new_data.filename.replace('\'', "''")
+        );
+        trace_db_query(
+            &lock_query,
+            self.client.query(lock_query.as_str(), &[]),
+        )
+        .await
+        .map_err(|e| {
+            ApiError::from_db_error(
+                tracking_label,
+                &format!("lock filename for user_id={}", new_data.user_id),
+                &e,
+            )
+        })?;
+        let current = self
+            .find_current_by_filename(
+                tracking_label,
+                new_data.user_id,
+                &new_data.folder,
+                &new_data.filename,
+            )
+            .await?;
+        let current = match current {
+            Some(current) => current,
+            None => return self.insert(tracking_label, new_data).await,
+        };
+        match policy {
+            "reject" => Err(ApiError::Conflict(format!(
+                "{tracking_label} - \
+                user_id={} already has the file {} in the folder \
+                (data_id={})",
+                new_data.user_id, new_data.filename, current.data_id
+            ))),
+            "suffix" => {
+                let filename = self
+                    .find_free_versioned_filename(
+                        tracking_label,
+                        new_data.user_id,
+                        &new_data.folder,
+                        &new_data.filename,
+                    )
+                    .await?;
+                self.insert(
+                    tracking_label,
+                    &NewUserData {
+                        filename,
+                        ..new_data.clone()
+                    },
+                )
+                .await
+            }
+            _ => {
+                let version_of = match current.version_of {
+                    0 => current.data_id,
+                    v => v,
+                };
+                let user_data = self
+                    .insert(
+                        tracking_label,
+                        &NewUserData {
+                            file_version: Some(current.file_version + 1),
+                            version_of: Some(version_of),
+                            ..new_data.clone()
+                        },
+                    )
+                    .await?;
+                let query = format!(
+                    "UPDATE \
+                        users_data \
+                    SET \
+                        replaced_by = {}, \
+                        updated_at = timezone('UTC'::text, now()) \
+                    WHERE \
+                        users_data.id = {} \
+                    AND \
+                        users_data.replaced_by IS NULL;",
+                    user_data.data_id, current.data_id
+                );
+                match trace_db_query(
+                    &query,
+                    self.client.execute(query.as_str(), &[]),
+                )
+                .await
+                {
+                    Ok(0) => Err(ApiError::Conflict(format!(
+                        "{tracking_label} - \
+                        data_id={} was already replaced",
+                        current.data_id
+                    ))),
+                    Ok(_) => Ok(user_data),
+                    Err(e) => Err(ApiError::from_db_error(
+                        tracking_label,
+                        &format!("replace user data id={}", current.data_id),
+                        &e,
+                    )),
+                }
+            }
+        }
+    }
+
+    /// find_current_by_filename
+    ///
+    /// Get the current version of the user's file with a
+    /// filename in a folder (older versions and ``expired``
+    /// records are skipped)
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - caller logging label
+    /// * `user_id` - `i32` - owner user id
+    /// * `folder` - `&Option<String>` - folder path (`None` =
+    ///   not in a folder)
+    /// * `filename` - `&str` - data filename
+    ///
+    /// # Returns
+    ///
+    /// Ok(`Option<`[`ModelUserData`](crate::requests::models::user_data::ModelUserData)`>`) -
+    /// `None` if the filename is free
+    ///
+    /// # Errors
+    ///
+    /// Err([`ApiError`](crate::requests::models::api_error::ApiError))
+    ///
+    pub async fn find_current_by_filename(
+        &self,
+        tracking_label: &str,
+        user_id: i32,
+        folder: &Option<String>,
+        filename: &str,
+    ) -> Result<Option<ModelUserData>, ApiError> {
+        let query = format!(
+            "SELECT \
+                {USER_DATA_COLUMNS} \
+            FROM \
+                users_data \
+            WHERE \
+                users_data.user_id = {user_id} \
+            AND \
+                users_data.filename = '{}' \
+            AND \
+                users_data.folder IS NOT DISTINCT FROM {} \
+            AND \
+                users_data.replaced_by IS NULL \
+            AND \
+                users_data.status <> 'expired' \
+            ORDER BY \
+                users_data.id DESC \
+            LIMIT 1;",
+            filename.replace('\'', "''"),
+            get_sql_string_or_null(folder)
+        );
+        match self
+            .query_one(
+                tracking_label,
+                &format!("find user data {filename} for user_id={user_id}"),
+                &query,
+            )
+            .await
+        {
+            Ok(user_data) => Ok(Some(user_data)),
+            Err(ApiError::NotFound(_)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// find_free_versioned_filename
+    ///
+    /// Get the first filename with a ``-vN`` suffix (starting
+    /// at ``-v2``) that is free in the user's folder
+    ///
+    async fn find_free_versioned_filename(
+        &self,
+        tracking_label: &str,
+        user_id: i32,
+        folder: &Option<String>,
+        filename: &str,
+    ) -> Result<String, ApiError> {
+        let stem = match filename.rfind('.') {
+            Some(idx) if idx > 0 => &filename[..idx],
+            _ => filename,
+        };
+        let query = format!(
+            "SELECT \
+                users_data.filename \
+            FROM \
+                users_data \
+            WHERE \
+                users_data.user_id = {user_id} \
+            AND \
+                users_data.filename LIKE '{}-v%' \
+            AND \
+                users_data.folder IS NOT DISTINCT FROM {} \
+            AND \
+                users_data.replaced_by IS NULL \
+            AND \
+                users_data.status <> 'expired';",
+            get_sql_like_prefix(stem),
+            get_sql_string_or_null(folder)
+        );
+        let used_filenames: Vec<String> = match trace_db_query(
+            &query,
+            self.client.query(query.as_str(), &[]),
+        )
+        .await
+        {
+            Ok(query_result) => query_result
+                .iter()
+                .map(|row| row.try_get("filename").unwrap())
+                .collect(),
+            Err(e) => {
+                return Err(ApiError::from_db_error(
+                    tracking_label,
+                    &format!("find versioned filenames for user_id={user_id}"),
+                    &e,
+                ))
+            }
+        };
+        let mut file_version: i32 = 2;
+        loop {
+            let versioned_filename =
+                get_versioned_filename(filename, file_version);
+            if !used_filenames.contains(&versioned_filename) {
+                return Ok(versioned_filename);
+            }
+            file_version += 1;
+        }
+    }
+
+    /// find_by_checksum
+    ///
+    /// Get the newest ``users_data`` record the user owns with
+    /// the same contents checksum whose s3 object can be
+    /// reused (``quarantined``, ``failed``, ``expired`` and
+    /// ``broken`` records and records with a storage class
+    /// or expiry are skipped)
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - caller logging label
+    /// * `user_id` - `i32` - owner user id
+    /// * `checksum` - `&str` - hex sha256 of the contents
+    ///
+    /// # Returns
+    ///
+    /// Ok(`Option<`[`ModelUserData`](crate::requests::models::user_data::ModelUserData)`>`) -
+    /// `None` if the user has no matching record
+    ///
+    /// # Errors
+    ///
+    /// Err([`ApiError`](crate::requests::models::api_error::ApiError))
+    ///
+    pub async fn find_by_checksum(
+        &self,
+        tracking_label: &str,
+        user_id: i32,
+        checksum: &str,
+    ) -> Result<Option<ModelUserData>, ApiError> {
+        let query = format!(
+            "SELECT \
+                {USER_DATA_COLUMNS} \
+            FROM \
+                users_data \
+            WHERE \
+                users_data.user_id = {user_id} \
+            AND \
+                users_data.checksum = '{}' \
+            AND \
+                users_data.status IN ('pending', 'scanning', 'ready') \
+            AND \
+                users_data.storage_class IS NULL \
+            AND \
+                users_data.expires_at IS NULL \
+            ORDER BY \
+                users_data.id DESC \
+            LIMIT 1;",
+            checksum.replace('\'', "''")
+        );
+        match self
+            .query_one(
+                tracking_label,
+                &format!("find user data by checksum for user_id={user_id}"),
+                &query,
+            )
+            .await
+        {
+            Ok(user_data) => Ok(Some(user_data)),
+            Err(ApiError::NotFound(_)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// find_by_id
+    ///
+    /// Get a ``users_data`` record the user can read
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - caller logging label
+    /// * `user_id` - `i32` - requesting user id
+    /// * `role` - `&str` - requesting user's role
+    /// * `data_id` - `i32` - ``users_data.id``
+    ///
+    /// # Returns
+    ///
+    /// Ok([`ModelUserData`](crate::requests::models::user_data::ModelUserData))
+    ///
+    /// # Errors
+    ///
+    /// Err([`ApiError`](crate::requests::models::api_error::ApiError)) -
+    /// `NotFound` if the record does not exist or is not
+    /// shared with the user
+    ///
+    pub async fn find_by_id(
+        &self,
+        tracking_label: &str,
+        user_id: i32,
+        role: &str,
+        data_id: i32,
+    ) -> Result<ModelUserData, ApiError> {
+        let query = format!(
+            "SELECT \
+                {USER_DATA_COLUMNS} \
+            FROM \
+                users_data \
+            WHERE \
+                users_data.id = {data_id} \
+            AND \
+                {} \
+            LIMIT 1;",
+            get_user_data_access_sql(user_id, role, false)
+        );
+        self.query_one(
+            tracking_label,
+            &format!("find user data id={data_id} for user_id={user_id}"),
+            &query,
+        )
+        .await
+    }
+
+    /// find_by_share_token
+    ///
+    /// Get the ``users_data`` record for an unexpired public
+    /// link share
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - caller logging label
+    /// * `token_hash` - `&str` - hash of the link token
+    ///   (see [`hash_token`](crate::utils::hash_token::hash_token))
+    ///
+    /// # Returns
+    ///
+    /// Ok([`ModelUserData`](crate::requests::models::user_data::ModelUserData))
+    ///
+    /// # Errors
+    ///
+    /// Err([`ApiError`](crate::requests::models::api_error::ApiError)) -
+    /// `NotFound` if there is no link share for the token or
+    /// it expired
+    ///
+    pub async fn find_by_share_token(
+        &self,
+        tracking_label: &str,
+        token_hash: &str,
+    ) -> Result<ModelUserData, ApiError> {
+        let query = format!(
+            "SELECT \
+                {USER_DATA_COLUMNS} \
+            FROM \
+                users_data \
+            INNER JOIN \
+                users_data_shares \
+            ON \
+                users_data_shares.data_id = users_data.id \
+            WHERE \
+                users_data_shares.token = '{}' \
+            AND (\
+                users_data_shares.expires_at IS NULL \
+                OR users_data_shares.expires_at \
+                    > timezone('UTC'::text, now())) \
+            LIMIT 1;",
+            token_hash.replace('\'', "''")
+        );
+        self.query_one(tracking_label, "find user data by share token", &query)
+            .await
+    }
+
+    /// update
+    ///
+    /// Update the changed columns on a ``users_data`` record
+    /// the user owns or can write and set
+    /// `users_data.updated_at` (the ``users_data_version``
+    /// trigger bumps `users_data.version`)
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - caller logging label
+    /// * `user_id` - `i32` - requesting user id
+    /// * `role` - `&str` - requesting user's role
+    /// * `data_id` - `i32` - ``users_data.id``
+    /// * `changes` - [`UserDataChanges`](crate::requests::models::user_data_repo::UserDataChanges)
+    ///
+    /// # Returns
+    ///
+    /// Ok([`ModelUserData`](crate::requests::models::user_data::ModelUserData)) -
+    /// the updated record
+    ///
+    /// # Errors
+    ///
+    /// Err([`ApiError`](crate::requests::models::api_error::ApiError)) -
+    /// `NotFound` if the record does not exist or the user
+    /// cannot write it, or `VersionConflict` if the
+    /// `expected_version` is stale
+    ///
+    pub async fn update(
+        &self,
+        tracking_label: &str,
+        user_id: i32,
+        role: &str,
+        data_id: i32,
+        changes: &UserDataChanges,
+    ) -> Result<ModelUserData, ApiError> {
+        let mut set_values: Vec<String> =
+            vec!["updated_at = timezone('UTC'::text, now())".to_string()];
+        let string_values = [
+            ("filename", &changes.filename),
+            ("data_type", &changes.data_type),
+            ("comments", &changes.comments),
+            ("encoding", &changes.encoding),
+            ("sloc", &changes.sloc),
+        ];
+        for (column, value) in string_values.iter() {
+            if let Some(v) = value {
+                set_values
+                    .push(format!("{column} = '{}'", v.replace('\'', "''")));
+            }
+        }
+        if let Some(v) = &changes.tags {
+            set_values.push(format!("tags = {}", get_sql_text_array(v)));
+        }
+        if let Some(v) = &changes.folder {
+            set_values.push(format!(
+                "folder = NULLIF('{}', '')",
+                v.replace('\'', "''")
+            ));
+        }
+        let access_sql = get_user_data_access_sql(user_id, role, true);
+        let version_sql = match changes.expected_version {
+            Some(v) => format!(" AND users_data.version = {v}"),
+            None => "".to_string(),
+        };
+        let query = format!(
+            "UPDATE \
+                users_data \
+            SET \
+                {} \
+            WHERE \
+                users_data.id = {data_id}{version_sql} \
+            AND \
+                {access_sql} \
+            RETURNING \
+                {USER_DATA_COLUMNS};",
+            set_values.join(", ")
+        );
+        let action =
+            format!("update user data id={data_id} for user_id={user_id}");
+        match self.query_one(tracking_label, &action, &query).await {
+            Err(ApiError::NotFound(err_msg))
+                if changes.expected_version.is_some() =>
+            {
+                // the user can write the record so another
+                // request changed it
+                let query = format!(
+                    "SELECT \
+                        {USER_DATA_COLUMNS} \
+                    FROM \
+                        users_data \
+                    WHERE \
+                        users_data.id = {data_id} \
+                    AND \
+                        {access_sql} \
+                    LIMIT 1;"
+                );
+                match self.query_one(tracking_label, &action, &query).await {
+                    Ok(user_data) => Err(ApiError::VersionConflict(format!(
+                        "{tracking_label} - failed to {action} - \
+                        expected version={} but the current \
+                        version={}",
+                        changes.expected_version.unwrap_or(-1),
+                        user_data.version
+                    ))),
+                    Err(_) => Err(ApiError::NotFound(err_msg)),
+                }
+            }
+            result => result,
+        }
+    }
+
+    /// delete
+    ///
+    /// Delete a ``ready``, ``quarantined``, ``failed`` or
+    /// ``broken`` ``users_data`` record the user owns by
+    /// marking it ``expired`` (so it can no longer be
+    /// downloaded) with an
+    /// ``expires_at`` of now. The
+    /// [`UserDataLifecycle`](crate::archive::user_data_lifecycle::UserDataLifecycle)
+    /// task deletes the s3 object on its next run.
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - caller logging label
+    /// * `user_id` - `i32` - requesting user id
+    /// * `data_id` - `i32` - ``users_data.id``
+    /// * `expected_version` - `Option<i32>` - reject the
+    ///   delete if the record changed since the client read it
+    ///
+    /// # Returns
+    ///
+    /// Ok([`ModelUserData`](crate::requests::models::user_data::ModelUserData)) -
+    /// the deleted record
+    ///
+    /// # Errors
+    ///
+    /// Err([`ApiError`](crate::requests::models::api_error::ApiError)) -
+    /// `NotFound` if the user does not own the record,
+    /// `VersionConflict` if the `expected_version` is stale
+    /// or `Conflict` if the record is still uploading or
+    /// processing or was already deleted
+    ///
+    pub async fn delete(
+        &self,
+        tracking_label: &str,
+        user_id: i32,
+        data_id: i32,
+        expected_version: Option<i32>,
+    ) -> Result<ModelUserData, ApiError> {
+        let version_sql = match expected_version {
+            Some(v) => format!(" AND users_data.version = {v}"),
+            None => "".to_string(),
+        };
+        let query = format!(
+            "UPDATE \
+                users_data \
+            SET \
+                status = 'expired', \
+                status_updated_at = timezone('UTC'::text, now()), \
+                expires_at = timezone('UTC'::text, now()), \
+                expire_storage_class = NULL, \
+                updated_at = timezone('UTC'::text, now()) \
+            WHERE \
+                users_data.id = {data_id}{version_sql} \
+            AND \
+                users_data.user_id = {user_id} \
+            AND \
+                users_data.status IN ('ready', 'quarantined', 'failed', 'broken') \
+            RETURNING \
+                {USER_DATA_COLUMNS};"
+        );
+        let action =
+            format!("delete user data id={data_id} for user_id={user_id}");
+        match self.query_one(tracking_label, &action, &query).await {
+            Err(ApiError::NotFound(err_msg)) => {
+                let query = format!(
+                    "SELECT \
+                        {USER_DATA_COLUMNS} \
+                    FROM \
+                        users_data \
+                    WHERE \
+                        users_data.id = {data_id} \
+                    AND \
+                        users_data.user_id = {user_id} \
+                    LIMIT 1;"
+                );
+                match self.query_one(tracking_label, &action, &query).await {
+                    Ok(user_data)
+                        if expected_version.is_some()
+                            && expected_version != Some(user_data.version) =>
+                    {
+                        Err(ApiError::VersionConflict(format!(
+                            "{tracking_label} - failed to {action} - \
+                            expected version={} but the current \
+                            version={}",
+                            expected_version.unwrap_or(-1),
+                            user_data.version
+                        )))
+                    }
+                    Ok(user_data) => Err(ApiError::Conflict(format!(
+                        "{tracking_label} - failed to {action} - \
+                        records with status={} cannot be deleted",
+                        user_data.status
+                    ))),
+                    Err(_) => Err(ApiError::NotFound(err_msg)),
+                }
+            }
+            result => result,
+        }
+    }
+
+    /// begin_transaction
+    ///
+    /// Start a transaction on the repository's connection.
+    /// Every path must end it with
+    /// [`commit_transaction`](crate::requests::models::user_data_repo::UserDataRepo::commit_transaction)
+    /// or
+    /// [`rollback_transaction`](crate::requests::models::user_data_repo::UserDataRepo::rollback_transaction)
+    /// before the connection goes back to the pool.
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - caller logging label
+    ///
+    /// # Errors
+    ///
+    /// Err([`ApiError`](crate::requests::models::api_error::ApiError))
+    ///
+    pub async fn begin_transaction(
+        &self,
+        tracking_label: &str,
+    ) -> Result<(), ApiError> {
+        self.batch_execute(tracking_label, "begin a transaction", "BEGIN;")
+            .await
+    }
+
+    /// commit_transaction
+    ///
+    /// Commit the transaction started with
+    /// [`begin_transaction`](crate::requests::models::user_data_repo::UserDataRepo::begin_transaction)
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - caller logging label
+    ///
+    /// # Errors
+    ///
+    /// Err([`ApiError`](crate::requests::models::api_error::ApiError))
+    ///
+    pub async fn commit_transaction(
+        &self,
+        tracking_label: &str,
+    ) -> Result<(), ApiError> {
+        self.batch_execute(tracking_label, "commit a transaction", "COMMIT;")
+            .await
+    }
+
+    /// rollback_transaction
+    ///
+    /// Roll back the transaction started with
+    /// [`begin_transaction`](crate::requests::models::user_data_repo::UserDataRepo::begin_transaction)
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - caller logging label
+    ///
+    /// # Errors
+    ///
+    /// Err([`ApiError`](crate::requests::models::api_error::ApiError))
+    ///
+    pub async fn rollback_transaction(
+        &self,
+        tracking_label: &str,
+    ) -> Result<(), ApiError> {
+        self.batch_execute(
+            tracking_label,
+            "roll back a transaction",
+            "ROLLBACK;",
+        )
+        .await
+    }
+
+    /// batch_execute
+    ///
+    /// Run a statement without parameters or returned rows
+    ///
+    async fn batch_execute(
+        &self,
+        tracking_label: &str,
+        action: &str,
+        query: &str,
+    ) -> Result<(), ApiError> {
+        trace_db_query(query, self.client.batch_execute(query))
+            .await
+            .map_err(|e| ApiError::from_db_error(tracking_label, action, &e))
+    }
+
+    /// get_search_query
+    ///
+    /// Build the ``SELECT`` query (without a trailing ``;``
+    /// or a ``LIMIT``) for every ``users_data`` record the
+    /// user can read matching every set filter, newest first
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - `i32` - requesting user id
+    /// * `role` - `&str` - requesting user's role
+    /// * `search` - [`UserDataSearch`](crate::requests::models::user_data_repo::UserDataSearch)
+    ///
+    pub fn get_search_query(
+        user_id: i32,
+        role: &str,
+        search: &UserDataSearch,
+    ) -> String {
+        let recent_query = search.get_table_sql(
+            "users_data",
+            false,
+            &get_user_data_access_sql(user_id, role, false),
+        );
+        match search.include_archived {
+            true => format!(
+                "{recent_query} \
+                UNION ALL \
+                {} \
+                ORDER BY id DESC",
+                search.get_table_sql(
+                    "users_data_archive AS users_data",
+                    true,
+                    &get_user_data_archive_access_sql(user_id, role),
+                )
+            ),
+            false => format!(
+                "{recent_query} \
+                ORDER BY id DESC"
+            ),
+        }
+    }
+
+    /// search
+    ///
+    /// Find up to 100 ``users_data`` records the user can
+    /// read matching every set filter
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - caller logging label
+    /// * `user_id` - `i32` - requesting user id
+    /// * `role` - `&str` - requesting user's role
+    /// * `search` - [`UserDataSearch`](crate::requests::models::user_data_repo::UserDataSearch)
+    ///
+    /// # Returns
+    ///
+    /// Ok(`Vec<`[`ModelUserData`](crate::requests::models::user_data::ModelUserData)`>`) -
+    /// newest first (empty when nothing matched)
+    ///
+    /// # Errors
+    ///
+    /// Err([`ApiError`](crate::requests::models::api_error::ApiError))
+    ///
+    pub async fn search(
+        &self,
+        tracking_label: &str,
+        user_id: i32,
+        role: &str,
+        search: &UserDataSearch,
+    ) -> Result<Vec<ModelUserData>, ApiError> {
+        let query = format!(
+            "{} LIMIT 100;",
+            UserDataRepo::get_search_query(user_id, role, search)
+        );
+        match trace_db_query(&query, self.client.query(query.as_str(), &[]))
+            .await
+        {
+            Ok(query_result) => {
+                Ok(query_result.iter().map(get_user_data_from_row).collect())
+            }
+            Err(e) => Err(ApiError::from_db_error(
+                tracking_label,
+                &format!("search user data for user_id={user_id}"),
+                &e,
+            )),
+        }
+    }
+
+    /// find_by_sloc_prefix
+    ///
+    /// Get every ``users_data`` and ``users_data_archive``
+    /// record with a ``sloc`` under a location (for
+    /// reconciling the records with the keys in s3)
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - caller logging label
+    /// * `sloc_prefix` - `&str` - location prefix
+    ///   (``s3://BUCKET/PREFIX/USER_ID/``)
+    ///
+    /// # Returns
+    ///
+    /// Ok(`Vec<`[`UserDataSloc`](crate::requests::models::user_data_repo::UserDataSloc)`>`) -
+    /// sorted by ``sloc``
+    ///
+    /// # Errors
+    ///
+    /// Err([`ApiError`](crate::requests::models::api_error::ApiError))
+    ///
+    pub async fn find_by_sloc_prefix(
+        &self,
+        tracking_label: &str,
+        sloc_prefix: &str,
+    ) -> Result<Vec<UserDataSloc>, ApiError> {
+        let sloc_like = format!(
+            "{}%",
+            sloc_prefix
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_")
+                .replace('\'', "''")
+        );
+        let query = format!(
+            "SELECT \
+                id, \
+                COALESCE(user_id, 0) AS user_id, \
+                sloc, \
+                status, \
+                false AS archived \
+            FROM \
+                users_data \
+            WHERE \
+                sloc LIKE '{sloc_like}' \
+            UNION ALL \
+            SELECT \
+                id, \
+                COALESCE(user_id, 0) AS user_id, \
+                sloc, \
+                status, \
+                true AS archived \
+            FROM \
+                users_data_archive \
+            WHERE \
+                sloc LIKE '{sloc_like}' \
+            ORDER BY sloc, id;"
+        );
+        match trace_db_query(&query, self.client.query(query.as_str(), &[]))
+            .await
+        {
+            Ok(query_result) => Ok(query_result
+                .iter()
+                .map(|row| UserDataSloc {
+                    data_id: row.try_get("id").unwrap(),
+                    user_id: row.try_get("user_id").unwrap(),
+                    sloc: row.try_get("sloc").unwrap(),
+                    status: row.try_get("status").unwrap(),
+                    archived: row.try_get("archived").unwrap(),
+                })
+                .collect()),
+            Err(e) => Err(ApiError::from_db_error(
+                tracking_label,
+                &format!("find user data under {sloc_prefix}"),
+                &e,
+            )),
+        }
+    }
+}
@@ -0,0 +1,88 @@
+//! Model for sharing a user's ``users_data`` record with
+//! another user id or through a public download link
+//!
+//! User shares give read access (search and download) like a
+//! ``users_data_acl`` share (see
+//! [`get_user_data_access_sql`](crate::requests::models::user_data_acl::get_user_data_access_sql)).
+//! Link shares store the hash of a random token and anyone
+//! with the token can download the file until the share
+//! expires (see
+//! [`download_shared_user_data`](crate::requests::user::download_shared_user_data::download_shared_user_data)).
+//!
+use serde::Deserialize;
+use serde::Serialize;
+
+/// columns returned for a
+/// [`ModelUserDataShare`](crate::requests::models::user_data_share::ModelUserDataShare)
+pub const USER_DATA_SHARE_COLUMNS: &str = "\
+    users_data_shares.id, \
+    users_data_shares.data_id, \
+    users_data_shares.owner_user_id, \
+    users_data_shares.shared_with_user_id, \
+    users_data_shares.expires_at, \
+    users_data_shares.created_at";
+
+/// ModelUserDataShare
+///
+/// Representation in the db for a share of a
+/// `users_data` record with a user or a public link
+///
+/// # DB table
+///
+/// `users_data_shares`
+///
+/// # Arguments
+///
+/// * `share_id` - `i32` - `users_data_shares.id` in the db
+/// * `data_id` - `i32` - `users_data.id` being shared
+/// * `owner_user_id` - `i32` - `users.id` that owns the
+///   `users_data` record
+/// * `shared_with_user_id` - `Option<i32>` - `users.id` the
+///   record is shared with (`None` for a public link)
+/// * `expires_at` - `String` - time the share stops working
+///   (empty if it never expires)
+/// * `created_at` - `String` - share creation time
+///
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ModelUserDataShare {
+    pub share_id: i32,
+    pub data_id: i32,
+    pub owner_user_id: i32,
+    pub shared_with_user_id: Option<i32>,
+    pub expires_at: String,
+    pub created_at: String,
+}
+
+/// get_user_data_share_from_row
+///
+/// Convert a row with the
+/// [`USER_DATA_SHARE_COLUMNS`](crate::requests::models::user_data_share::USER_DATA_SHARE_COLUMNS)
+/// into a
+/// [`ModelUserDataShare`](crate::requests::models::user_data_share::ModelUserDataShare)
+///
+/// # Arguments
+///
+/// * `row` - [`Row`](tokio_postgres::Row) - db row
+///
+/// # Returns
+///
+/// [`ModelUserDataShare`](crate::requests::models::user_data_share::ModelUserDataShare)
+///
+pub fn get_user_data_share_from_row(
+    row: &tokio_postgres::Row,
+) -> ModelUserDataShare {
+    let created_at_utc: chrono::DateTime<chrono::Utc> =
+        row.try_get("created_at").unwrap();
+    let expires_at: Option<chrono::DateTime<chrono::Utc>> =
+        row.try_get("expires_at").unwrap();
+    ModelUserDataShare {
+        share_id: row.try_get("id").unwrap(),
+        data_id: row.try_get("data_id").unwrap(),
+        owner_user_id: row.try_get("owner_user_id").unwrap(),
+        shared_with_user_id: row.try_get("shared_with_user_id").unwrap(),
+        expires_at: expires_at
+            .map(|v| format!("{}", v.format("%Y-%m-%dT%H:%M:%SZ")))
+            .unwrap_or_default(),
+        created_at: format!("{}", created_at_utc.format("%Y-%m-%dT%H:%M:%SZ")),
+    }
+}
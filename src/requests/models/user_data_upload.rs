@@ -0,0 +1,241 @@
+//! Model for tracking the s3 multipart upload of a
+//! resumable ``users_data`` upload
+//!
+//! Each ``users_data`` record in the ``uploading`` status
+//! has one ``users_data_uploads`` record with the parts
+//! received so far (see
+//! [`resumable_upload`](crate::requests::user::resumable_upload)).
+//!
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::PooledConnection;
+use bb8_postgres::PostgresConnectionManager;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::monitoring::otel::trace_db_query;
+
+/// ModelUserDataUploadPart
+///
+/// One stored chunk of a resumable upload
+///
+/// # Arguments
+///
+/// * `part_number` - `i64` - s3 multipart upload part number
+/// * `etag` - `String` - etag returned for the part
+/// * `size_in_bytes` - `i64` - size of the chunk
+///
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ModelUserDataUploadPart {
+    pub part_number: i64,
+    pub etag: String,
+    pub size_in_bytes: i64,
+}
+
+/// ModelUserDataUpload
+///
+/// Representation in the db for an in-progress
+/// resumable upload
+///
+/// # DB table
+///
+/// `users_data_uploads` (joined with `users_data`)
+///
+/// # Arguments
+///
+/// * `data_id` - `i32` - users_data.id in the db
+/// * `user_id` - `i32` - user id in the db
+/// * `filename` - `String` - data filename
+/// * `sloc` - `String` - full s3 location path
+/// * `status` - `String` - ``users_data.status``
+/// * `bucket` - `String` - s3 bucket
+/// * `key` - `String` - s3 key
+/// * `upload_id` - `String` - s3 multipart upload id
+/// * `total_bytes` - `i64` - size of the file
+/// * `received_bytes` - `i64` - bytes stored so far
+/// * `parts` - `Vec<`[`ModelUserDataUploadPart`](crate::requests::models::user_data_upload::ModelUserDataUploadPart)`>` -
+///   stored parts in order
+/// * `expires_at` - `String` - time the lifecycle task
+///   aborts the upload
+/// * `expired` - `bool` - ``expires_at`` has passed
+///
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ModelUserDataUpload {
+    pub data_id: i32,
+    pub user_id: i32,
+    pub filename: String,
+    pub sloc: String,
+    pub status: String,
+    pub bucket: String,
+    pub key: String,
+    pub upload_id: String,
+    pub total_bytes: i64,
+    pub received_bytes: i64,
+    pub parts: Vec<ModelUserDataUploadPart>,
+    pub expires_at: String,
+    pub expired: bool,
+}
+
+/// columns selected by
+/// [`get_user_data_upload_from_row`](crate::requests::models::user_data_upload::get_user_data_upload_from_row)
+const USER_DATA_UPLOAD_COLUMNS: &str = "\
+    users_data_uploads.data_id, \
+    users_data_uploads.user_id, \
+    users_data.filename, \
+    users_data.sloc, \
+    users_data.status, \
+    users_data_uploads.bucket, \
+    users_data_uploads.key, \
+    users_data_uploads.upload_id, \
+    users_data_uploads.total_bytes, \
+    users_data_uploads.received_bytes, \
+    users_data_uploads.parts, \
+    users_data_uploads.expires_at, \
+    (users_data_uploads.expires_at \
+        < timezone('UTC'::text, now())) AS expired";
+
+/// get_user_data_upload_from_row
+///
+/// Convert a row with the ``USER_DATA_UPLOAD_COLUMNS`` into a
+/// [`ModelUserDataUpload`](crate::requests::models::user_data_upload::ModelUserDataUpload)
+///
+fn get_user_data_upload_from_row(
+    row: &tokio_postgres::Row,
+) -> ModelUserDataUpload {
+    let expires_at: chrono::DateTime<chrono::Utc> =
+        row.try_get("expires_at").unwrap();
+    let parts: serde_json::Value = row.try_get("parts").unwrap();
+    ModelUserDataUpload {
+        data_id: row.try_get("data_id").unwrap(),
+        user_id: row.try_get("user_id").unwrap(),
+        filename: row.try_get("filename").unwrap(),
+        sloc: row.try_get("sloc").unwrap(),
+        status: row.try_get("status").unwrap(),
+        bucket: row.try_get("bucket").unwrap(),
+        key: row.try_get("key").unwrap(),
+        upload_id: row.try_get("upload_id").unwrap(),
+        total_bytes: row.try_get("total_bytes").unwrap(),
+        received_bytes: row.try_get("received_bytes").unwrap(),
+        parts: serde_json::from_value(parts).unwrap_or_default(),
+        expires_at: format!("{}", expires_at.format("%Y-%m-%dT%H:%M:%SZ")),
+        expired: row.try_get("expired").unwrap(),
+    }
+}
+
+/// get_user_data_upload
+///
+/// Find a user's in-progress resumable upload
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `user_id` - `i32` - owner user id
+/// * `data_id` - `i32` - ``users_data.id``
+/// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+///   an established db connection from the
+///   postgres client db threadpool
+///
+/// # Returns
+///
+/// Ok(`Option<`[`ModelUserDataUpload`](crate::requests::models::user_data_upload::ModelUserDataUpload)`>`) -
+/// `None` if the user has no upload for the ``data_id``
+///
+/// # Errors
+///
+/// Err(err_msg: `String`)
+///
+pub async fn get_user_data_upload(
+    tracking_label: &str,
+    user_id: i32,
+    data_id: i32,
+    conn: &PooledConnection<'_, PostgresConnectionManager<MakeTlsConnector>>,
+) -> Result<Option<ModelUserDataUpload>, String> {
+    let query = format!(
+        "SELECT \
+            {USER_DATA_UPLOAD_COLUMNS} \
+        FROM \
+            users_data_uploads \
+        INNER JOIN \
+            users_data \
+        ON \
+            users_data.id = users_data_uploads.data_id \
+        WHERE \
+            users_data_uploads.data_id = {data_id} \
+            AND \
+            users_data_uploads.user_id = {user_id} \
+        LIMIT 1;"
+    );
+    let stmt = conn.prepare(&query).await.unwrap();
+    match trace_db_query(&query, conn.query(&stmt, &[])).await {
+        Ok(query_result) => {
+            Ok(query_result.first().map(get_user_data_upload_from_row))
+        }
+        Err(e) => Err(format!(
+            "{tracking_label} - \
+            failed to find upload for data_id={data_id} with err='{e}'"
+        )),
+    }
+}
+
+/// add_user_data_upload_part
+///
+/// Store a received part if no other request stored a
+/// part at the same offset first
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `upload` - [`ModelUserDataUpload`](crate::requests::models::user_data_upload::ModelUserDataUpload) -
+///   the upload before the part was received
+/// * `part` - [`ModelUserDataUploadPart`](crate::requests::models::user_data_upload::ModelUserDataUploadPart) -
+///   the new part
+/// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+///   an established db connection from the
+///   postgres client db threadpool
+///
+/// # Returns
+///
+/// Ok(`Option<i64>`) - new ``received_bytes`` (`None` = the
+/// upload's ``received_bytes`` changed or the upload is gone)
+///
+/// # Errors
+///
+/// Err(err_msg: `String`)
+///
+pub async fn add_user_data_upload_part(
+    tracking_label: &str,
+    upload: &ModelUserDataUpload,
+    part: &ModelUserDataUploadPart,
+    conn: &PooledConnection<'_, PostgresConnectionManager<MakeTlsConnector>>,
+) -> Result<Option<i64>, String> {
+    let data_id = upload.data_id;
+    let query = format!(
+        "UPDATE \
+            users_data_uploads \
+        SET \
+            received_bytes = users_data_uploads.received_bytes + {}, \
+            parts = users_data_uploads.parts || '{}'::jsonb, \
+            updated_at = timezone('UTC'::text, now()) \
+        WHERE \
+            users_data_uploads.data_id = {data_id} \
+            AND \
+            users_data_uploads.received_bytes = {} \
+        RETURNING \
+            users_data_uploads.received_bytes;",
+        part.size_in_bytes,
+        serde_json::json!([part]).to_string().replace('\'', "''"),
+        upload.received_bytes
+    );
+    let stmt = conn.prepare(&query).await.unwrap();
+    match trace_db_query(&query, conn.query(&stmt, &[])).await {
+        Ok(query_result) => Ok(query_result
+            .first()
+            .map(|row| row.try_get("received_bytes").unwrap())),
+        Err(e) => Err(format!(
+            "{tracking_label} - \
+            failed to store part={} for data_id={data_id} with err='{e}'",
+            part.part_number
+        )),
+    }
+}
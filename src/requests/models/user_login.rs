@@ -0,0 +1,234 @@
+//! Model for a user's login attempts (the ``users_logins``
+//! table)
+//!
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::PooledConnection;
+use bb8_postgres::PostgresConnectionManager;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::monitoring::otel::trace_db_query;
+use crate::requests::models::user_session::ModelUserSessionMetadata;
+
+/// ModelUserLogin
+///
+/// Representation in the db for one login attempt
+///
+/// # DB table
+///
+/// `users_logins`
+///
+/// # Arguments
+///
+/// * `id` - `i64` - `users_logins.id` in the db
+/// * `user_id` - `i32` - user id
+/// * `method` - `String` - ``password`` or ``passkey``
+/// * `result` - `String` - ``success`` or the failure
+///   reason (``invalid_password``, ``invalid_otp``,
+///   ``invalid_passkey``, ``unverified``, ``challenged``,
+///   ``denied`` or ``reset_required``)
+/// * `success` - `bool` - the login created a token
+/// * `user_agent` - `String` - ``User-Agent`` header
+/// * `ip_address` - `String` - client address
+/// * `device` - `String` - ``device`` header
+/// * `country` - `String` - client country (empty = unknown)
+/// * `city` - `String` - client city (empty = unknown)
+/// * `created_at` - `String` - UTC-formatted date time string
+///
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ModelUserLogin {
+    pub id: i64,
+    pub user_id: i32,
+    pub method: String,
+    pub result: String,
+    pub success: bool,
+    pub user_agent: String,
+    pub ip_address: String,
+    pub device: String,
+    pub country: String,
+    pub city: String,
+    pub created_at: String,
+}
+
+/// insert_user_login
+///
+/// Record a login attempt and delete the user's attempts
+/// older than `retention_days`
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `user_id` - `i32` - user id in the db
+/// * `method` - `&str` - ``password`` or ``passkey``
+/// * `result` - `&str` - ``success`` or the failure reason
+/// * `session` - [`ModelUserSessionMetadata`](crate::requests::models::user_session::ModelUserSessionMetadata) -
+///   client details for the attempt
+/// * `retention_days` - `i64` - days to keep attempts
+///   (`0` = keep every attempt)
+/// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+///   an established db connection from the
+///   postgres client db threadpool
+///
+/// # Errors
+///
+/// Err(err_msg: `String`)
+///
+pub async fn insert_user_login(
+    tracking_label: &str,
+    user_id: i32,
+    method: &str,
+    result: &str,
+    session: &ModelUserSessionMetadata,
+    retention_days: i64,
+    conn: &PooledConnection<'_, PostgresConnectionManager<MakeTlsConnector>>,
+) -> Result<(), String> {
+    let prune_sql = match retention_days > 0 {
+        true => format!(
+            "DELETE FROM \
+                users_logins \
+            WHERE \
+                users_logins.user_id = {user_id} \
+            AND \
+                users_logins.created_at < \
+                    timezone('UTC'::text, now()) \
+                    - interval '{retention_days} days';"
+        ),
+        false => "".to_string(),
+    };
+    let query = format!(
+        "INSERT INTO \
+            users_logins (\
+                user_id, \
+                method, \
+                result, \
+                user_agent, \
+                ip_address, \
+                device, \
+                country, \
+                city) \
+        VALUES (\
+            {user_id}, \
+            '{}', \
+            '{}', \
+            '{}', \
+            '{}', \
+            '{}', \
+            '{}', \
+            '{}');{prune_sql}",
+        method.replace('\'', "''"),
+        result.replace('\'', "''"),
+        session.user_agent.replace('\'', "''"),
+        session.ip_address.replace('\'', "''"),
+        session.device.replace('\'', "''"),
+        session.country.replace('\'', "''"),
+        session.city.replace('\'', "''")
+    );
+    match trace_db_query(&query, conn.batch_execute(&query)).await {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!(
+            "{tracking_label} - \
+            failed to record {method} login result={result} \
+            for user_id={user_id} with err='{e}'"
+        )),
+    }
+}
+
+/// get_user_logins
+///
+/// Get a user's most recent login attempts ordered by
+/// newest first
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `user_id` - `i32` - user id in the db
+/// * `failed_only` - `bool` - only get failed attempts
+/// * `limit` - `i64` - max attempts
+/// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+///   an established db connection from the
+///   postgres client db threadpool
+///
+/// # Returns
+///
+/// Ok(`Vec<`[`ModelUserLogin`](crate::requests::models::user_login::ModelUserLogin)`>`)
+///
+/// # Errors
+///
+/// Err(err_msg: `String`)
+///
+pub async fn get_user_logins(
+    tracking_label: &str,
+    user_id: i32,
+    failed_only: bool,
+    limit: i64,
+    conn: &PooledConnection<'_, PostgresConnectionManager<MakeTlsConnector>>,
+) -> Result<Vec<ModelUserLogin>, String> {
+    let result_sql = match failed_only {
+        true => "AND users_logins.result <> 'success' ",
+        false => "",
+    };
+    let query = format!(
+        "SELECT \
+            users_logins.id, \
+            users_logins.user_id, \
+            users_logins.method, \
+            users_logins.result, \
+            users_logins.user_agent, \
+            users_logins.ip_address, \
+            users_logins.device, \
+            users_logins.country, \
+            users_logins.city, \
+            users_logins.created_at \
+        FROM \
+            users_logins \
+        WHERE \
+            users_logins.user_id = {user_id} \
+        {result_sql}\
+        ORDER BY \
+            users_logins.created_at DESC, \
+            users_logins.id DESC \
+        LIMIT {limit};"
+    );
+    let stmt = conn.prepare(&query).await.unwrap();
+    match trace_db_query(&query, conn.query(&stmt, &[])).await {
+        Ok(query_result) => {
+            Ok(query_result.iter().map(get_user_login_from_row).collect())
+        }
+        Err(e) => Err(format!(
+            "{tracking_label} - \
+            failed to get logins for user_id={user_id} \
+            with err='{e}'"
+        )),
+    }
+}
+
+/// get_user_login_from_row
+///
+/// Convert a ``users_logins`` row into a
+/// [`ModelUserLogin`](crate::requests::models::user_login::ModelUserLogin)
+///
+fn get_user_login_from_row(row: &tokio_postgres::Row) -> ModelUserLogin {
+    let created_at_utc: chrono::DateTime<chrono::Utc> =
+        row.try_get("created_at").unwrap();
+    let result: String = row.try_get("result").unwrap();
+    let user_agent: Option<String> = row.try_get("user_agent").unwrap();
+    let ip_address: Option<String> = row.try_get("ip_address").unwrap();
+    let device: Option<String> = row.try_get("device").unwrap();
+    let country: Option<String> = row.try_get("country").unwrap();
+    let city: Option<String> = row.try_get("city").unwrap();
+    ModelUserLogin {
+        id: row.try_get("id").unwrap(),
+        user_id: row.try_get("user_id").unwrap(),
+        method: row.try_get("method").unwrap(),
+        success: result == "success",
+        result,
+        user_agent: user_agent.unwrap_or_default(),
+        ip_address: ip_address.unwrap_or_default(),
+        device: device.unwrap_or_default(),
+        country: country.unwrap_or_default(),
+        city: city.unwrap_or_default(),
+        created_at: format!("{}", created_at_utc.format("%Y-%m-%dT%H:%M:%SZ")),
+    }
+}
@@ -0,0 +1,225 @@
+//! Module for a user's notifications
+//!
+//! Every user event published through the
+//! [`EventBus`](crate::kafka::event_bus::EventBus) is stored
+//! as a ``users_notifications`` record when
+//! ``USER_NOTIFICATIONS_ENABLED=1`` (see
+//! [`UserNotifications`](crate::notifications::user_notifications::UserNotifications))
+//!
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::PooledConnection;
+use bb8_postgres::PostgresConnectionManager;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::monitoring::otel::trace_db_query;
+
+/// ModelUserNotification
+///
+/// Representation of a user event in the db
+///
+/// # DB table
+///
+/// `users_notifications`
+///
+/// # Arguments
+///
+/// * `id` - `i64` - notification id (also the server-sent
+///   event id for resuming a stream)
+/// * `user_id` - `i32` - user id
+/// * `event` - `String` - event name (``USER_UPDATE``)
+/// * `details` - `String` - space-separated ``key=value`` pairs
+/// * `created_at` - `String` - utc timestamp
+///
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ModelUserNotification {
+    pub id: i64,
+    pub user_id: i32,
+    pub event: String,
+    pub details: String,
+    pub created_at: String,
+}
+
+/// insert_user_notification
+///
+/// Store a user event in the ``users_notifications`` table
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `user_id` - `i32` - user id
+/// * `event` - `&str` - event name
+/// * `details` - `&str` - space-separated ``key=value`` pairs
+/// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+///   an established db connection from the
+///   postgres client db threadpool
+///
+/// # Returns
+///
+/// Ok(notification_id: `i64`)
+///
+/// # Errors
+///
+/// Err(err_msg: `String`) if the record cannot be created
+///
+pub async fn insert_user_notification(
+    tracking_label: &str,
+    user_id: i32,
+    event: &str,
+    details: &str,
+    conn: &PooledConnection<'_, PostgresConnectionManager<MakeTlsConnector>>,
+) -> Result<i64, String> {
+    let query = format!(
+        "INSERT INTO \
+            users_notifications (\
+                user_id, \
+                event, \
+                details) \
+        VALUES (\
+            {user_id}, \
+            '{}', \
+            '{}') \
+        RETURNING \
+            users_notifications.id;",
+        event.replace('\'', "''"),
+        details.replace('\'', "''")
+    );
+    match trace_db_query(&query, conn.query(query.as_str(), &[])).await {
+        Ok(query_result) => match query_result.first() {
+            Some(row) => Ok(row.try_get("id").unwrap()),
+            None => Err(format!(
+                "{tracking_label} - \
+                failed to store {event} notification for user={user_id}"
+            )),
+        },
+        Err(e) => Err(format!(
+            "{tracking_label} - \
+            failed to store {event} notification for user={user_id} \
+            with err='{e}'"
+        )),
+    }
+}
+
+/// get_user_notifications_after
+///
+/// Get the user's notifications created after a
+/// notification id (oldest first)
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `user_id` - `i32` - user id
+/// * `last_id` - `i64` - only return notifications with a
+///   larger id
+/// * `limit` - `i64` - max notifications to return
+/// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+///   an established db connection from the
+///   postgres client db threadpool
+///
+/// # Returns
+///
+/// Ok(`Vec<`[`ModelUserNotification`](crate::requests::models::user_notification::ModelUserNotification)`>`)
+///
+/// # Errors
+///
+/// Err(err_msg: `String`) if the query fails
+///
+pub async fn get_user_notifications_after(
+    tracking_label: &str,
+    user_id: i32,
+    last_id: i64,
+    limit: i64,
+    conn: &PooledConnection<'_, PostgresConnectionManager<MakeTlsConnector>>,
+) -> Result<Vec<ModelUserNotification>, String> {
+    let query = format!(
+        "SELECT \
+            users_notifications.id, \
+            users_notifications.user_id, \
+            users_notifications.event, \
+            users_notifications.details, \
+            users_notifications.created_at \
+        FROM \
+            users_notifications \
+        WHERE \
+            users_notifications.user_id = {user_id} \
+            AND \
+            users_notifications.id > {last_id} \
+        ORDER BY \
+            users_notifications.id ASC \
+        LIMIT {limit};"
+    );
+    match trace_db_query(&query, conn.query(query.as_str(), &[])).await {
+        Ok(query_result) => Ok(query_result
+            .iter()
+            .map(|row| {
+                let created_at: chrono::DateTime<chrono::Utc> =
+                    row.try_get("created_at").unwrap();
+                ModelUserNotification {
+                    id: row.try_get("id").unwrap(),
+                    user_id: row.try_get("user_id").unwrap(),
+                    event: row.try_get("event").unwrap(),
+                    details: row.try_get("details").unwrap(),
+                    created_at: format!(
+                        "{}",
+                        created_at.format("%Y-%m-%dT%H:%M:%SZ")
+                    ),
+                }
+            })
+            .collect()),
+        Err(e) => Err(format!(
+            "{tracking_label} - \
+            failed to get notifications for user={user_id} \
+            after id={last_id} with err='{e}'"
+        )),
+    }
+}
+
+/// get_latest_user_notification_id
+///
+/// Get the user's newest notification id so a new stream
+/// only sends notifications created after it connects
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `user_id` - `i32` - user id
+/// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+///   an established db connection from the
+///   postgres client db threadpool
+///
+/// # Returns
+///
+/// Ok(notification_id: `i64`) - `0` if the user has no
+/// notifications
+///
+/// # Errors
+///
+/// Err(err_msg: `String`) if the query fails
+///
+pub async fn get_latest_user_notification_id(
+    tracking_label: &str,
+    user_id: i32,
+    conn: &PooledConnection<'_, PostgresConnectionManager<MakeTlsConnector>>,
+) -> Result<i64, String> {
+    let query = format!(
+        "SELECT \
+            COALESCE(MAX(users_notifications.id), 0) AS id \
+        FROM \
+            users_notifications \
+        WHERE \
+            users_notifications.user_id = {user_id};"
+    );
+    match trace_db_query(&query, conn.query(query.as_str(), &[])).await {
+        Ok(query_result) => Ok(query_result
+            .first()
+            .map(|row| row.try_get("id").unwrap())
+            .unwrap_or(0)),
+        Err(e) => Err(format!(
+            "{tracking_label} - \
+            failed to get the latest notification for user={user_id} \
+            with err='{e}'"
+        )),
+    }
+}
@@ -8,6 +8,8 @@ use bb8_postgres::PostgresConnectionManager;
 use serde::Deserialize;
 use serde::Serialize;
 
+use crate::requests::models::user_repo::UserRepo;
+
 /// ModelUserOtp
 ///
 /// Representation in the db for a
@@ -23,7 +25,8 @@ use serde::Serialize;
 ///
 /// * `id` - `i32` - `users_otp.id` in the db
 /// * `user_id` - `i32` - `users.id` in the db
-/// * `token` - `String` - one-time-use password token
+/// * `token` - `String` - hash of the one-time-use password token
+///   (see [`hash_token`](crate::utils::hash_token::hash_token))
 /// * `exp_date_utc` - [`chrono::DateTime`](chrono::DateTime) -
 ///   the one-time-use password's expiration date in `Utc`
 /// * `consumed_date_utc` -
@@ -46,14 +49,16 @@ pub struct ModelUserOtp {
 /// get_user_otp
 ///
 /// Get the user's one-time-use password record
-/// from the db
+/// from the db. The user's records are found by
+/// `user_id` and `email` and the `token` hash is
+/// compared with each stored hash in constant time.
 ///
 /// # Arguments
 ///
 /// * `tracking_label` - `&str` - caller logging label
 /// * `user_id` - `i32` - user id in the db
 /// * `email` - `&str` - user's email address
-/// * `token` - `&str` - user's one-time-use password token
+/// * `token` - `&str` - hash of the user's one-time-use password token
 /// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
 ///   an established db connection from the
 ///   postgres client db threadpool
@@ -76,64 +81,7 @@ pub async fn get_user_otp(
     token: &str,
     conn: &PooledConnection<'_, PostgresConnectionManager<MakeTlsConnector>>,
 ) -> Result<ModelUserOtp, String> {
-    // find all user by email and an active state where state == 0
-    let query = format!(
-        "SELECT \
-            users_otp.id, \
-            users_otp.user_id, \
-            users_otp.token, \
-            users_otp.email, \
-            users_otp.state, \
-            users_otp.exp_date, \
-            users_otp.consumed_date, \
-            users_otp.created_at \
-        FROM \
-            users_otp \
-        WHERE \
-            users_otp.user_id = {user_id} \
-            AND \
-            users_otp.token = '{token}' \
-            AND \
-            users_otp.email = '{email}' \
-        LIMIT 1;"
-    );
-    // println!("{}", query);
-    let stmt = conn.prepare(&query).await.unwrap();
-    match conn.query(&stmt, &[]).await {
-        Ok(query_result) => {
-            if let Some(row) = query_result.first() {
-                let found_db_id: i32 = row.try_get("id").unwrap();
-                let found_user_id: i32 = row.try_get("user_id").unwrap();
-                let found_token: String = row.try_get("token").unwrap();
-                let found_email: String = row.try_get("email").unwrap();
-                let found_state: i32 = row.try_get("state").unwrap();
-                let found_exp_date_utc: chrono::DateTime<chrono::Utc> =
-                    row.try_get("exp_date").unwrap();
-                let found_consumed_date_utc: Option<
-                    chrono::DateTime<chrono::Utc>,
-                > = row.try_get("consumed_date").unwrap();
-                return Ok(ModelUserOtp {
-                    id: found_db_id,
-                    user_id: found_user_id,
-                    token: found_token,
-                    email: found_email,
-                    state: found_state,
-                    exp_date_utc: found_exp_date_utc,
-                    consumed_date_utc: found_consumed_date_utc,
-                });
-            }
-            Err(format!(
-                "{tracking_label} - \
-                failed to find any user one-time-password \
-                by user_id={user_id} \
-                email={email}"
-            ))
-        }
-        Err(e) => Err(format!(
-            "{tracking_label} - \
-                failed to find user one-time-password \
-                by user_id={user_id} \
-                with err='{e}'"
-        )),
-    }
+    Ok(UserRepo::new(conn)
+        .find_otp(tracking_label, user_id, email, token)
+        .await?)
 }
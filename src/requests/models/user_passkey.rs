@@ -0,0 +1,133 @@
+//! Module for a user's registered webauthn passkeys
+//!
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::PooledConnection;
+use bb8_postgres::PostgresConnectionManager;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use webauthn_rs::prelude::Passkey;
+
+use crate::monitoring::otel::trace_db_query;
+
+/// ModelUserPasskey
+///
+/// Representation in the db for a
+/// user's registered webauthn passkey credential
+///
+/// A user can register many passkeys (one per device
+/// or authenticator)
+///
+/// # DB table
+///
+/// `users_passkeys`
+///
+/// # Arguments
+///
+/// * `id` - `i32` - `users_passkeys.id` in the db
+/// * `user_id` - `i32` - `users.id` in the db
+/// * `cred_id` - `String` - base64 url-safe encoded
+///   webauthn credential id
+/// * `name` - `String` - user-friendly name for the passkey
+/// * `passkey` - [`Passkey`](webauthn_rs::prelude::Passkey) -
+///   deserialized webauthn credential with the public key
+///   and signature counter
+/// * `state` - `i32` - is the passkey
+///   active (`0`) or inactive (`1`)
+///
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ModelUserPasskey {
+    pub id: i32,
+    pub user_id: i32,
+    pub cred_id: String,
+    pub name: String,
+    pub passkey: Passkey,
+    pub state: i32,
+}
+
+/// get_user_passkeys
+///
+/// Get all active passkeys for a user from the db
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `user_id` - `i32` - user id in the db
+/// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+///   an established db connection from the
+///   postgres client db threadpool
+///
+/// # Returns
+///
+/// ## get_user_passkeys on Success Returns
+///
+/// `Vec` of [`ModelUserPasskey`](crate::requests::models::user_passkey::ModelUserPasskey)
+/// (empty if the user has not registered any passkeys)
+///
+/// # Errors
+///
+/// Various `Err(String)` can be returned depending
+/// on what breaks
+///
+pub async fn get_user_passkeys(
+    tracking_label: &str,
+    user_id: i32,
+    conn: &PooledConnection<'_, PostgresConnectionManager<MakeTlsConnector>>,
+) -> Result<Vec<ModelUserPasskey>, String> {
+    let query = format!(
+        "SELECT \
+            users_passkeys.id, \
+            users_passkeys.user_id, \
+            users_passkeys.cred_id, \
+            users_passkeys.name, \
+            users_passkeys.passkey, \
+            users_passkeys.state \
+        FROM \
+            users_passkeys \
+        WHERE \
+            users_passkeys.user_id = {user_id} \
+        AND \
+            users_passkeys.state = 0 \
+        ORDER BY \
+            users_passkeys.id ASC;"
+    );
+    let stmt = conn.prepare(&query).await.unwrap();
+    match trace_db_query(&query, conn.query(&stmt, &[])).await {
+        Ok(query_result) => {
+            let mut passkeys: Vec<ModelUserPasskey> =
+                Vec::with_capacity(query_result.len());
+            for row in query_result.iter() {
+                let id: i32 = row.try_get("id").unwrap();
+                let passkey_str: String = row.try_get("passkey").unwrap();
+                let passkey: Passkey = match serde_json::from_str(&passkey_str)
+                {
+                    Ok(passkey) => passkey,
+                    Err(e) => {
+                        error!(
+                            "{tracking_label} - \
+                            skipping invalid passkey id={id} \
+                            for user_id={user_id} with err='{e}'"
+                        );
+                        continue;
+                    }
+                };
+                passkeys.push(ModelUserPasskey {
+                    id,
+                    user_id: row.try_get("user_id").unwrap(),
+                    cred_id: row.try_get("cred_id").unwrap(),
+                    name: row.try_get("name").unwrap(),
+                    passkey,
+                    state: row.try_get("state").unwrap(),
+                });
+            }
+            Ok(passkeys)
+        }
+        Err(e) => Err(format!(
+            "{tracking_label} - \
+                failed to find passkeys for user_id={user_id} \
+                with err='{e}'"
+        )),
+    }
+}
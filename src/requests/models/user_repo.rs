@@ -0,0 +1,754 @@
+//! Typed queries for the ``users``, ``users_otp`` and
+//! ``users_verified`` tables
+//!
+//! Handlers use a
+//! [`UserRepo`](crate::requests::models::user_repo::UserRepo)
+//! instead of building sql strings and parsing rows. Every
+//! query selects (or returns) the
+//! [`USER_COLUMNS`](crate::requests::models::user_repo::USER_COLUMNS)
+//! and is parsed with
+//! [`get_user_from_row`](crate::requests::models::user_repo::get_user_from_row).
+//!
+//! # Usage
+//!
+//! ```rust,ignore
+//! let user_repo = UserRepo::new(&conn);
+//! let user_model = user_repo.find_by_id(tracking_label, user_id).await?;
+//! ```
+//!
+use tokio_postgres::Client;
+use tokio_postgres::Row;
+
+use crate::monitoring::otel::trace_db_query;
+use crate::requests::models::api_error::ApiError;
+use crate::requests::models::user::ModelUser;
+use crate::requests::models::user_otp::ModelUserOtp;
+use crate::requests::models::user_verify::ModelUserVerify;
+use crate::utils::hash_token::is_token_hash_match;
+use crate::utils::password_migration::get_password_scheme;
+use crate::utils::password_migration::UNKNOWN_PASSWORD_SCHEME;
+
+/// columns selected and returned for a
+/// [`ModelUser`](crate::requests::models::user::ModelUser)
+pub const USER_COLUMNS: &str = "\
+    users.id, \
+    users.email, \
+    users.password, \
+    users.state, \
+    users.verified, \
+    users.role, \
+    users.tenant_id, \
+    users.version, \
+    users.locale";
+
+/// get_user_from_row
+///
+/// Parse a row with the
+/// [`USER_COLUMNS`](crate::requests::models::user_repo::USER_COLUMNS)
+///
+/// # Arguments
+///
+/// * `row` - [`Row`](tokio_postgres::Row) - db row
+///
+/// # Returns
+///
+/// [`ModelUser`](crate::requests::models::user::ModelUser)
+///
+pub fn get_user_from_row(row: &Row) -> ModelUser {
+    ModelUser {
+        id: row.try_get("id").unwrap(),
+        email: row.try_get("email").unwrap(),
+        password: row.try_get("password").unwrap(),
+        state: row.try_get("state").unwrap(),
+        verified: row.try_get("verified").unwrap(),
+        role: row.try_get("role").unwrap(),
+        tenant_id: row.try_get("tenant_id").unwrap(),
+        version: row.try_get("version").unwrap(),
+        locale: row.try_get("locale").unwrap(),
+    }
+}
+
+/// NewUser
+///
+/// Values for inserting a ``users`` record
+///
+/// # Arguments
+///
+/// * `email` - `String` - normalized email
+/// * `password_hash` - `String` - argon2-salted password
+/// * `state` - `i32` - active (`0`) or inactive (`1`)
+/// * `verified` - `i32` - unverified (`0`) or verified (`1`)
+/// * `role` - `String` - user's role
+/// * `tenant_id` - `i32` - tenant that owns the user
+/// * `locale` - `String` - locale for the user's emails
+///
+#[derive(Clone, Default)]
+pub struct NewUser {
+    pub email: String,
+    pub password_hash: String,
+    pub state: i32,
+    pub verified: i32,
+    pub role: String,
+    pub tenant_id: i32,
+    pub locale: String,
+}
+
+/// UserChanges
+///
+/// Optional values for updating a ``users`` record (`None`
+/// leaves the column unchanged)
+///
+/// # Arguments
+///
+/// * `email` - `Option<String>` - normalized email
+/// * `password_hash` - `Option<String>` - argon2-salted
+///   password (also sets `users.password_scheme`)
+/// * `state` - `Option<i32>` - active (`0`) or inactive (`1`)
+/// * `verified` - `Option<i32>` - unverified (`0`) or
+///   verified (`1`)
+/// * `role` - `Option<String>` - user's role
+/// * `locale` - `Option<String>` - locale for the user's
+///   emails
+/// * `expected_version` - `Option<i32>` - only update the
+///   record if its `users.version` still matches
+///
+#[derive(Clone, Default)]
+pub struct UserChanges {
+    pub email: Option<String>,
+    pub password_hash: Option<String>,
+    pub state: Option<i32>,
+    pub verified: Option<i32>,
+    pub role: Option<String>,
+    pub locale: Option<String>,
+    pub expected_version: Option<i32>,
+}
+
+/// PasswordSchemeCount
+///
+/// Number of users with a ``users.password_scheme``
+///
+/// # Arguments
+///
+/// * `scheme` - `String` - password hash scheme (``unknown``
+///   for hashes that are not recorded yet)
+/// * `users` - `i64` - users with the scheme
+///
+#[derive(Clone, Default)]
+pub struct PasswordSchemeCount {
+    pub scheme: String,
+    pub users: i64,
+}
+
+/// UserSearch
+///
+/// Filters for searching ``users`` records ordered by
+/// newest first
+///
+/// # Arguments
+///
+/// * `user_id` - `Option<i32>` - only match this user
+///   (for non-admin searches)
+/// * `tenant_id` - `Option<i32>` - only match users in
+///   this tenant
+/// * `email` - `Option<String>` - ``ILIKE`` email filter
+/// * `role` - `Option<String>` - exact role
+/// * `state` - `Option<i32>` - exact state
+/// * `verified` - `Option<i32>` - exact verified flag
+/// * `created_after` - `Option<chrono::DateTime<chrono::Utc>>` -
+///   created at or after this time
+/// * `created_before` - `Option<chrono::DateTime<chrono::Utc>>` -
+///   created before this time
+/// * `limit` - `i64` - max records returned (below `1`
+///   returns every matching record)
+/// * `offset` - `i64` - records skipped
+///
+#[derive(Clone, Default)]
+pub struct UserSearch {
+    pub user_id: Option<i32>,
+    pub tenant_id: Option<i32>,
+    pub email: Option<String>,
+    pub role: Option<String>,
+    pub state: Option<i32>,
+    pub verified: Option<i32>,
+    pub created_after: Option<chrono::DateTime<chrono::Utc>>,
+    pub created_before: Option<chrono::DateTime<chrono::Utc>>,
+    pub limit: i64,
+    pub offset: i64,
+}
+
+/// UserRepo
+///
+/// Typed queries for the ``users`` table on one db
+/// connection (a primary or read replica
+/// [`PooledConnection`](bb8::PooledConnection) derefs
+/// to the [`Client`](tokio_postgres::Client))
+///
+pub struct UserRepo<'a> {
+    client: &'a Client,
+}
+
+impl<'a> UserRepo<'a> {
+    /// new
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - [`Client`](tokio_postgres::Client) - db
+    ///   connection
+    ///
+    pub fn new(client: &'a Client) -> Self {
+        UserRepo { client }
+    }
+
+    /// query_one
+    ///
+    /// Run a query that returns at most one user
+    ///
+    async fn query_one(
+        &self,
+        tracking_label: &str,
+        action: &str,
+        query: &str,
+    ) -> Result<ModelUser, ApiError> {
+        match trace_db_query(query, self.client.query(query, &[])).await {
+            Ok(query_result) => match query_result.first() {
+                Some(row) => Ok(get_user_from_row(row)),
+                None => Err(ApiError::NotFound(format!(
+                    "{tracking_label} - \
+                    failed to {action} - no user found"
+                ))),
+            },
+            Err(e) => Err(ApiError::from_db_error(tracking_label, action, &e)),
+        }
+    }
+
+    /// find_by_id
+    ///
+    /// Get a user by `users.id`
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - caller logging label
+    /// * `id` - `i32` - user id
+    ///
+    /// # Returns
+    ///
+    /// Ok([`ModelUser`](crate::requests::models::user::ModelUser))
+    ///
+    /// # Errors
+    ///
+    /// Err([`ApiError`](crate::requests::models::api_error::ApiError)) -
+    /// `NotFound` if there is no user with the id
+    ///
+    pub async fn find_by_id(
+        &self,
+        tracking_label: &str,
+        id: i32,
+    ) -> Result<ModelUser, ApiError> {
+        let query = format!(
+            "SELECT \
+                {USER_COLUMNS} \
+            FROM \
+                users \
+            WHERE \
+                users.id = {id} \
+            LIMIT 1;"
+        );
+        self.query_one(tracking_label, &format!("find user by id={id}"), &query)
+            .await
+    }
+
+    /// find_active_by_email
+    ///
+    /// Get an active (`users.state = 0`) user by email
+    /// within a tenant
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - caller logging label
+    /// * `tenant_id` - `i32` - tenant that owns the user
+    /// * `email` - `&str` - user email
+    ///
+    /// # Returns
+    ///
+    /// Ok([`ModelUser`](crate::requests::models::user::ModelUser))
+    ///
+    /// # Errors
+    ///
+    /// Err([`ApiError`](crate::requests::models::api_error::ApiError)) -
+    /// `NotFound` if there is no active user with the email
+    ///
+    pub async fn find_active_by_email(
+        &self,
+        tracking_label: &str,
+        tenant_id: i32,
+        email: &str,
+    ) -> Result<ModelUser, ApiError> {
+        let query = format!(
+            "SELECT \
+                {USER_COLUMNS} \
+            FROM \
+                users \
+            WHERE \
+                users.email = '{}' \
+            AND \
+                users.tenant_id = {tenant_id} \
+            AND \
+                users.state = 0 \
+            LIMIT 1;",
+            email.replace('\'', "''")
+        );
+        self.query_one(
+            tracking_label,
+            &format!("find active user by email={email}"),
+            &query,
+        )
+        .await
+    }
+
+    /// find_otp
+    ///
+    /// Get the user's one-time-use password record
+    /// (``users_otp``) for a token hash. The records are
+    /// selected by ``user_id`` and ``email`` only and each
+    /// stored hash is compared with the presented hash in
+    /// constant time (with
+    /// [`is_token_hash_match`](crate::utils::hash_token::is_token_hash_match))
+    /// so the token is never used in the ``WHERE`` clause
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - caller logging label
+    /// * `user_id` - `i32` - user id
+    /// * `email` - `&str` - user email
+    /// * `token` - `&str` - hash of the one-time-use password token
+    ///
+    /// # Returns
+    ///
+    /// Ok([`ModelUserOtp`](crate::requests::models::user_otp::ModelUserOtp))
+    ///
+    /// # Errors
+    ///
+    /// Err([`ApiError`](crate::requests::models::api_error::ApiError)) -
+    /// `NotFound` if no record matches
+    ///
+    pub async fn find_otp(
+        &self,
+        tracking_label: &str,
+        user_id: i32,
+        email: &str,
+        token: &str,
+    ) -> Result<ModelUserOtp, ApiError> {
+        let query = format!(
+            "SELECT \
+                users_otp.id, \
+                users_otp.user_id, \
+                users_otp.token, \
+                users_otp.email, \
+                users_otp.state, \
+                users_otp.exp_date, \
+                users_otp.consumed_date \
+            FROM \
+                users_otp \
+            WHERE \
+                users_otp.user_id = {user_id} \
+                AND \
+                users_otp.email = '{}' \
+            ORDER BY users_otp.id DESC;",
+            email.replace('\'', "''")
+        );
+        let action =
+            format!("find user one-time-password by user_id={user_id}");
+        match trace_db_query(&query, self.client.query(query.as_str(), &[]))
+            .await
+        {
+            Ok(query_result) => match query_result.iter().find(|row| {
+                is_token_hash_match(
+                    token,
+                    &row.try_get::<&str, String>("token").unwrap(),
+                )
+            }) {
+                Some(row) => Ok(ModelUserOtp {
+                    id: row.try_get("id").unwrap(),
+                    user_id: row.try_get("user_id").unwrap(),
+                    token: row.try_get("token").unwrap(),
+                    email: row.try_get("email").unwrap(),
+                    state: row.try_get("state").unwrap(),
+                    exp_date_utc: row.try_get("exp_date").unwrap(),
+                    consumed_date_utc: row.try_get("consumed_date").unwrap(),
+                }),
+                None => Err(ApiError::NotFound(format!(
+                    "{tracking_label} - \
+                    failed to find any user one-time-password \
+                    by user_id={user_id} email={email}"
+                ))),
+            },
+            Err(e) => Err(ApiError::from_db_error(tracking_label, &action, &e)),
+        }
+    }
+
+    /// find_verify
+    ///
+    /// Get the user's email verification record
+    /// (``users_verified``)
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - caller logging label
+    /// * `user_id` - `i32` - user id
+    ///
+    /// # Returns
+    ///
+    /// Ok([`ModelUserVerify`](crate::requests::models::user_verify::ModelUserVerify))
+    ///
+    /// # Errors
+    ///
+    /// Err([`ApiError`](crate::requests::models::api_error::ApiError)) -
+    /// `NotFound` if the user has no verification record
+    ///
+    pub async fn find_verify(
+        &self,
+        tracking_label: &str,
+        user_id: i32,
+    ) -> Result<ModelUserVerify, ApiError> {
+        let query = format!(
+            "SELECT \
+                users_verified.id, \
+                users_verified.user_id, \
+                users_verified.token, \
+                users_verified.email, \
+                users_verified.state, \
+                users_verified.exp_date \
+            FROM \
+                users_verified \
+            WHERE \
+                users_verified.user_id = {user_id} \
+            LIMIT 1;"
+        );
+        let action = format!("find user verify by user_id={user_id}");
+        match trace_db_query(&query, self.client.query(query.as_str(), &[]))
+            .await
+        {
+            Ok(query_result) => match query_result.first() {
+                Some(row) => Ok(ModelUserVerify {
+                    id: row.try_get("id").unwrap(),
+                    user_id: row.try_get("user_id").unwrap(),
+                    token: row.try_get("token").unwrap(),
+                    email: row.try_get("email").unwrap(),
+                    state: row.try_get("state").unwrap(),
+                    exp_date_utc: row.try_get("exp_date").unwrap(),
+                }),
+                None => Err(ApiError::NotFound(format!(
+                    "{tracking_label} - \
+                    failed to find any user verify with user_id={user_id}"
+                ))),
+            },
+            Err(e) => Err(ApiError::from_db_error(tracking_label, &action, &e)),
+        }
+    }
+
+    /// insert
+    ///
+    /// Create a ``users`` record
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - caller logging label
+    /// * `new_user` - [`NewUser`](crate::requests::models::user_repo::NewUser)
+    ///
+    /// # Returns
+    ///
+    /// Ok([`ModelUser`](crate::requests::models::user::ModelUser)) -
+    /// the new record
+    ///
+    /// # Errors
+    ///
+    /// Err([`ApiError`](crate::requests::models::api_error::ApiError)) -
+    /// `Conflict` if the email is already registered
+    ///
+    pub async fn insert(
+        &self,
+        tracking_label: &str,
+        new_user: &NewUser,
+    ) -> Result<ModelUser, ApiError> {
+        let query = format!(
+            "INSERT INTO \
+                users (\
+                    email, \
+                    password, \
+                    state, \
+                    verified, \
+                    role, \
+                    tenant_id, \
+                    locale, \
+                    password_scheme) \
+            VALUES (\
+                '{}', \
+                '{}', \
+                {}, \
+                {}, \
+                '{}', \
+                {}, \
+                '{}', \
+                '{}') \
+            RETURNING \
+                {USER_COLUMNS};",
+            new_user.email.replace('\'', "''"),
+            new_user.password_hash.replace('\'', "''"),
+            new_user.state,
+            new_user.verified,
+            new_user.role.replace('\'', "''"),
+            new_user.tenant_id,
+            new_user.locale.replace('\'', "''"),
+            get_password_scheme(&new_user.password_hash).replace('\'', "''")
+        );
+        self.query_one(
+            tracking_label,
+            &format!("insert user email={}", new_user.email),
+            &query,
+        )
+        .await
+    }
+
+    /// update
+    ///
+    /// Update the changed columns on a ``users`` record and
+    /// set `users.updated_at` (the ``users_version`` trigger
+    /// bumps `users.version`)
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - caller logging label
+    /// * `id` - `i32` - user id
+    /// * `changes` - [`UserChanges`](crate::requests::models::user_repo::UserChanges)
+    ///
+    /// # Returns
+    ///
+    /// Ok([`ModelUser`](crate::requests::models::user::ModelUser)) -
+    /// the updated record
+    ///
+    /// # Errors
+    ///
+    /// Err([`ApiError`](crate::requests::models::api_error::ApiError)) -
+    /// `NotFound` if there is no user with the id,
+    /// `VersionConflict` if the `expected_version` is stale or
+    /// `Conflict` if the new email is already registered
+    ///
+    pub async fn update(
+        &self,
+        tracking_label: &str,
+        id: i32,
+        changes: &UserChanges,
+    ) -> Result<ModelUser, ApiError> {
+        let mut set_values: Vec<String> =
+            vec!["updated_at = timezone('UTC'::text, now())".to_string()];
+        let password_scheme =
+            changes.password_hash.as_deref().map(get_password_scheme);
+        let string_values = [
+            ("email", &changes.email),
+            ("password", &changes.password_hash),
+            ("password_scheme", &password_scheme),
+            ("role", &changes.role),
+            ("locale", &changes.locale),
+        ];
+        for (column, value) in string_values.iter() {
+            if let Some(v) = value {
+                set_values
+                    .push(format!("{column} = '{}'", v.replace('\'', "''")));
+            }
+        }
+        let int_values =
+            [("state", changes.state), ("verified", changes.verified)];
+        for (column, value) in int_values.iter() {
+            if let Some(v) = value {
+                set_values.push(format!("{column} = {v}"));
+            }
+        }
+        let version_sql = match changes.expected_version {
+            Some(v) => format!(" AND users.version = {v}"),
+            None => "".to_string(),
+        };
+        let query = format!(
+            "UPDATE \
+                users \
+            SET \
+                {} \
+            WHERE \
+                users.id = {id}{version_sql} \
+            RETURNING \
+                {USER_COLUMNS};",
+            set_values.join(", ")
+        );
+        let action = format!("update user id={id}");
+        match self.query_one(tracking_label, &action, &query).await {
+            Err(ApiError::NotFound(err_msg))
+                if changes.expected_version.is_some() =>
+            {
+                // the user exists so another request changed it
+                match self.find_by_id(tracking_label, id).await {
+                    Ok(user_model) => Err(ApiError::VersionConflict(format!(
+                        "{tracking_label} - failed to {action} - \
+                        expected version={} but the current \
+                        version={}",
+                        changes.expected_version.unwrap_or(-1),
+                        user_model.version
+                    ))),
+                    Err(_) => Err(ApiError::NotFound(err_msg)),
+                }
+            }
+            result => result,
+        }
+    }
+
+    /// get_search_query
+    ///
+    /// Build the ``SELECT`` query (without a trailing ``;``)
+    /// for ``users`` records matching every set filter,
+    /// newest first
+    ///
+    /// # Arguments
+    ///
+    /// * `search` - [`UserSearch`](crate::requests::models::user_repo::UserSearch) -
+    ///   filters (a `limit` below `1` returns every match)
+    ///
+    pub fn get_search_query(search: &UserSearch) -> String {
+        let mut conditions: Vec<String> = Vec::new();
+        if let Some(v) = search.user_id {
+            conditions.push(format!("users.id = {v}"));
+        }
+        if let Some(v) = search.tenant_id {
+            conditions.push(format!("users.tenant_id = {v}"));
+        }
+        if let Some(v) = &search.email {
+            conditions.push(format!(
+                "users.email ILIKE '%{}%'",
+                v.replace('\'', "''")
+            ));
+        }
+        if let Some(v) = &search.role {
+            conditions
+                .push(format!("users.role = '{}'", v.replace('\'', "''")));
+        }
+        if let Some(v) = search.state {
+            conditions.push(format!("users.state = {v}"));
+        }
+        if let Some(v) = search.verified {
+            conditions.push(format!("users.verified = {v}"));
+        }
+        let time_filters =
+            [(">=", &search.created_after), ("<", &search.created_before)];
+        for (op, value) in time_filters.iter() {
+            if let Some(v) = value {
+                conditions.push(format!(
+                    "users.created_at {op} '{}'",
+                    v.format("%Y-%m-%dT%H:%M:%S%.fZ")
+                ));
+            }
+        }
+        let where_sql = match conditions.is_empty() {
+            true => "".to_string(),
+            false => format!("WHERE {}", conditions.join(" AND ")),
+        };
+        let page_sql = match search.limit > 0 {
+            true => format!(" LIMIT {} OFFSET {}", search.limit, search.offset),
+            false => "".to_string(),
+        };
+        format!(
+            "SELECT \
+                {USER_COLUMNS} \
+            FROM \
+                users \
+            {where_sql} \
+            ORDER BY \
+                users.created_at DESC, \
+                users.id DESC{page_sql}"
+        )
+    }
+
+    /// search
+    ///
+    /// Find ``users`` records matching every set filter
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - caller logging label
+    /// * `search` - [`UserSearch`](crate::requests::models::user_repo::UserSearch)
+    ///
+    /// # Returns
+    ///
+    /// Ok(`Vec<`[`ModelUser`](crate::requests::models::user::ModelUser)`>`) -
+    /// newest first (empty when nothing matched)
+    ///
+    /// # Errors
+    ///
+    /// Err([`ApiError`](crate::requests::models::api_error::ApiError))
+    ///
+    pub async fn search(
+        &self,
+        tracking_label: &str,
+        search: &UserSearch,
+    ) -> Result<Vec<ModelUser>, ApiError> {
+        let query = format!("{};", UserRepo::get_search_query(search));
+        match trace_db_query(&query, self.client.query(query.as_str(), &[]))
+            .await
+        {
+            Ok(query_result) => {
+                Ok(query_result.iter().map(get_user_from_row).collect())
+            }
+            Err(e) => {
+                Err(ApiError::from_db_error(tracking_label, "search users", &e))
+            }
+        }
+    }
+    /// count_by_password_scheme
+    ///
+    /// Count the users on each ``users.password_scheme`` to
+    /// track a password hash migration
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - caller logging label
+    ///
+    /// # Returns
+    ///
+    /// Ok(`Vec<`[`PasswordSchemeCount`](crate::requests::models::user_repo::PasswordSchemeCount)`>`) -
+    /// most users first
+    ///
+    /// # Errors
+    ///
+    /// Err([`ApiError`](crate::requests::models::api_error::ApiError))
+    ///
+    pub async fn count_by_password_scheme(
+        &self,
+        tracking_label: &str,
+    ) -> Result<Vec<PasswordSchemeCount>, ApiError> {
+        let query = format!(
+            "SELECT \
+                COALESCE(users.password_scheme, '{UNKNOWN_PASSWORD_SCHEME}') \
+                    AS scheme, \
+                COUNT(*) AS users \
+            FROM \
+                users \
+            GROUP BY \
+                1 \
+            ORDER BY \
+                users DESC, \
+                scheme ASC;"
+        );
+        match trace_db_query(&query, self.client.query(query.as_str(), &[]))
+            .await
+        {
+            Ok(query_result) => Ok(query_result
+                .iter()
+                .map(|row| PasswordSchemeCount {
+                    scheme: row.try_get("scheme").unwrap_or_default(),
+                    users: row.try_get("users").unwrap_or(0),
+                })
+                .collect()),
+            Err(e) => Err(ApiError::from_db_error(
+                tracking_label,
+                "count users by password scheme",
+                &e,
+            )),
+        }
+    }
+}
@@ -0,0 +1,549 @@
+//! Model for a user's login sessions (issued tokens
+//! in the ``users_tokens`` table)
+//!
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::PooledConnection;
+use bb8_postgres::PostgresConnectionManager;
+
+use hyper::header::HeaderValue;
+use hyper::header::USER_AGENT;
+use hyper::HeaderMap;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::core::server::geo_ip::GeoIp;
+use crate::monitoring::otel::trace_db_query;
+
+/// ModelUserSessionMetadata
+///
+/// Client details stored with a new token in the
+/// `users_tokens` table so a user can tell their
+/// sessions apart
+///
+/// # Arguments
+///
+/// * `user_agent` - `String` - ``User-Agent`` header
+///   (up to 512 characters)
+/// * `ip_address` - `String` - client address (forwarded
+///   by a trusted proxy or the connection's remote address)
+/// * `device` - `String` - optional ``device`` header set
+///   by the client (up to 256 characters)
+/// * `country` - `String` - client country code from
+///   ``GEOIP_DB_PATH`` (empty = unknown)
+/// * `city` - `String` - client city from
+///   ``GEOIP_DB_PATH`` (empty = unknown)
+///
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ModelUserSessionMetadata {
+    pub user_agent: String,
+    pub ip_address: String,
+    pub device: String,
+    pub country: String,
+    pub city: String,
+}
+
+/// ModelUserSession
+///
+/// Representation in the db for a
+/// user's active session (the token value is never
+/// returned)
+///
+/// # DB table
+///
+/// `users_tokens`
+///
+/// # Arguments
+///
+/// * `session_id` - `i32` - `users_tokens.id` in the db
+/// * `user_id` - `i32` - `users.id` that owns the session
+/// * `user_agent` - `String` - client ``User-Agent``
+/// * `ip_address` - `String` - client ip address at login
+/// * `device` - `String` - client device name
+/// * `country` - `String` - client country code at login
+///   (empty = unknown)
+/// * `city` - `String` - client city at login
+///   (empty = unknown)
+/// * `created_at` - `String` - login time
+/// * `last_used_at` - `String` - most recent request
+///   with this session's token
+///
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ModelUserSession {
+    pub session_id: i32,
+    pub user_id: i32,
+    pub user_agent: String,
+    pub ip_address: String,
+    pub device: String,
+    pub country: String,
+    pub city: String,
+    pub created_at: String,
+    pub last_used_at: String,
+}
+
+/// get_user_session_metadata
+///
+/// Build the
+/// [`ModelUserSessionMetadata`](crate::requests::models::user_session::ModelUserSessionMetadata)
+/// for a new token from the request headers and the
+/// client's address (and its location with ``GEOIP_DB_PATH``)
+///
+/// # Arguments
+///
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+/// * `remote_addr` - `&std::net::SocketAddr` - client address
+///   (resolved with
+///   [`TrustedProxies`](crate::core::server::trusted_proxies::TrustedProxies))
+/// * `geo_ip` - [`GeoIp`](crate::core::server::geo_ip::GeoIp) -
+///   looks up the client's country and city
+///
+/// # Returns
+///
+/// [`ModelUserSessionMetadata`](crate::requests::models::user_session::ModelUserSessionMetadata)
+///
+pub fn get_user_session_metadata(
+    headers: &HeaderMap<HeaderValue>,
+    remote_addr: &std::net::SocketAddr,
+    geo_ip: &GeoIp,
+) -> ModelUserSessionMetadata {
+    let get_header = |key: &str, max_len: usize| -> String {
+        match headers.get(key) {
+            Some(v) => {
+                let v = v.to_str().unwrap_or("").trim();
+                v.chars().take(max_len).collect()
+            }
+            None => "".to_string(),
+        }
+    };
+    let ip_address = format!("{}", remote_addr.ip().to_canonical());
+    let location = geo_ip.get_location(&ip_address);
+    ModelUserSessionMetadata {
+        user_agent: get_header(USER_AGENT.as_str(), 512),
+        ip_address,
+        device: get_header("device", 256),
+        country: location.country,
+        city: location.city,
+    }
+}
+
+/// get_user_session_by_token
+///
+/// Find the active `users_tokens` record for a token
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `token` - `&str` - the client's jwt
+/// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+///   an established db connection from the
+///   postgres client db threadpool
+///
+/// # Returns
+///
+/// Ok((session_id: `i32`, user_id: `i32`))
+///
+/// # Errors
+///
+/// Err(err_msg: `String`) if the token is not an active session
+///
+pub async fn get_user_session_by_token(
+    tracking_label: &str,
+    token: &str,
+    conn: &PooledConnection<'_, PostgresConnectionManager<MakeTlsConnector>>,
+) -> Result<(i32, i32), String> {
+    let query = format!(
+        "SELECT \
+            users_tokens.id, \
+            users_tokens.user_id \
+        FROM \
+            users_tokens \
+        WHERE \
+            users_tokens.token = '{}' \
+            AND \
+            users_tokens.state = 0 \
+        LIMIT 1;",
+        token.replace('\'', "''")
+    );
+    let stmt = conn.prepare(&query).await.unwrap();
+    match trace_db_query(&query, conn.query(&stmt, &[])).await {
+        Ok(query_result) => match query_result.first() {
+            Some(row) => {
+                let session_id: i32 = row.try_get("id").unwrap();
+                let user_id: i32 = row.try_get("user_id").unwrap();
+                Ok((session_id, user_id))
+            }
+            None => Err(format!(
+                "{tracking_label} - \
+                failed to find an active session for the token"
+            )),
+        },
+        Err(e) => Err(format!(
+            "{tracking_label} - \
+            failed to find session for the token with err='{e}'"
+        )),
+    }
+}
+
+/// touch_user_session
+///
+/// Confirm a token is an active session for the user
+/// and set its `users_tokens.last_used_at` to now
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `user_id` - `i32` - user id in the db
+/// * `token` - `&str` - the client's jwt
+/// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+///   an established db connection from the
+///   postgres client db threadpool
+///
+/// # Returns
+///
+/// Ok(session_id: `i32`)
+///
+/// # Errors
+///
+/// Err(err_msg: `String`) if the token was revoked or
+/// was not issued to the user
+///
+pub async fn touch_user_session(
+    tracking_label: &str,
+    user_id: i32,
+    token: &str,
+    conn: &PooledConnection<'_, PostgresConnectionManager<MakeTlsConnector>>,
+) -> Result<i32, String> {
+    let query = format!(
+        "UPDATE \
+            users_tokens \
+        SET \
+            last_used_at = timezone('UTC'::text, now()) \
+        WHERE \
+            users_tokens.user_id = {user_id} \
+            AND \
+            users_tokens.token = '{}' \
+            AND \
+            users_tokens.state = 0 \
+        RETURNING \
+            users_tokens.id;",
+        token.replace('\'', "''")
+    );
+    let stmt = conn.prepare(&query).await.unwrap();
+    match trace_db_query(&query, conn.query(&stmt, &[])).await {
+        Ok(query_result) => match query_result.first() {
+            Some(row) => Ok(row.try_get("id").unwrap()),
+            None => Err(format!(
+                "{tracking_label} - \
+                token is not an active session for user_id={user_id}"
+            )),
+        },
+        Err(e) => Err(format!(
+            "{tracking_label} - \
+            failed to update session for user_id={user_id} \
+            with err='{e}'"
+        )),
+    }
+}
+
+/// get_active_user_sessions
+///
+/// Get all active sessions for a user ordered by
+/// most recently used
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `user_id` - `i32` - user id in the db
+/// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+///   an established db connection from the
+///   postgres client db threadpool
+///
+/// # Returns
+///
+/// Ok(`Vec<`[`ModelUserSession`](crate::requests::models::user_session::ModelUserSession)`>`)
+///
+/// # Errors
+///
+/// Err(err_msg: `String`)
+///
+pub async fn get_active_user_sessions(
+    tracking_label: &str,
+    user_id: i32,
+    conn: &PooledConnection<'_, PostgresConnectionManager<MakeTlsConnector>>,
+) -> Result<Vec<ModelUserSession>, String> {
+    let query = format!(
+        "SELECT \
+            users_tokens.id, \
+            users_tokens.user_id, \
+            users_tokens.user_agent, \
+            users_tokens.ip_address, \
+            users_tokens.device, \
+            users_tokens.country, \
+            users_tokens.city, \
+            users_tokens.created_at, \
+            users_tokens.last_used_at \
+        FROM \
+            users_tokens \
+        WHERE \
+            users_tokens.user_id = {user_id} \
+            AND \
+            users_tokens.state = 0 \
+        ORDER BY \
+            COALESCE(users_tokens.last_used_at, users_tokens.created_at) \
+            DESC;"
+    );
+    let stmt = conn.prepare(&query).await.unwrap();
+    let query_result =
+        match trace_db_query(&query, conn.query(&stmt, &[])).await {
+            Ok(query_result) => query_result,
+            Err(e) => {
+                return Err(format!(
+                    "{tracking_label} - \
+                failed to get sessions for user_id={user_id} \
+                with err='{e}'"
+                ));
+            }
+        };
+    Ok(query_result.iter().map(get_user_session_from_row).collect())
+}
+
+/// get_user_session_from_row
+///
+/// Convert a ``users_tokens`` row into a
+/// [`ModelUserSession`](crate::requests::models::user_session::ModelUserSession)
+///
+fn get_user_session_from_row(row: &tokio_postgres::Row) -> ModelUserSession {
+    let created_at_utc: chrono::DateTime<chrono::Utc> =
+        row.try_get("created_at").unwrap();
+    let last_used_at_utc: Option<chrono::DateTime<chrono::Utc>> =
+        row.try_get("last_used_at").unwrap();
+    let user_agent: Option<String> = row.try_get("user_agent").unwrap();
+    let ip_address: Option<String> = row.try_get("ip_address").unwrap();
+    let device: Option<String> = row.try_get("device").unwrap();
+    let country: Option<String> = row.try_get("country").unwrap();
+    let city: Option<String> = row.try_get("city").unwrap();
+    ModelUserSession {
+        session_id: row.try_get("id").unwrap(),
+        user_id: row.try_get("user_id").unwrap(),
+        user_agent: user_agent.unwrap_or_default(),
+        ip_address: ip_address.unwrap_or_default(),
+        device: device.unwrap_or_default(),
+        country: country.unwrap_or_default(),
+        city: city.unwrap_or_default(),
+        created_at: format!("{}", created_at_utc.format("%Y-%m-%dT%H:%M:%SZ")),
+        last_used_at: match last_used_at_utc {
+            Some(v) => format!("{}", v.format("%Y-%m-%dT%H:%M:%SZ")),
+            None => "".to_string(),
+        },
+    }
+}
+
+/// get_user_login_history
+///
+/// Get a user's most recent sessions (active, expired and
+/// revoked) ordered by newest first
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `user_id` - `i32` - user id in the db
+/// * `limit` - `i64` - max sessions
+/// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+///   an established db connection from the
+///   postgres client db threadpool
+///
+/// # Returns
+///
+/// Ok(`Vec<`[`ModelUserSession`](crate::requests::models::user_session::ModelUserSession)`>`)
+///
+/// # Errors
+///
+/// Err(err_msg: `String`)
+///
+pub async fn get_user_login_history(
+    tracking_label: &str,
+    user_id: i32,
+    limit: i64,
+    conn: &PooledConnection<'_, PostgresConnectionManager<MakeTlsConnector>>,
+) -> Result<Vec<ModelUserSession>, String> {
+    let query = format!(
+        "SELECT \
+            users_tokens.id, \
+            users_tokens.user_id, \
+            users_tokens.user_agent, \
+            users_tokens.ip_address, \
+            users_tokens.device, \
+            users_tokens.country, \
+            users_tokens.city, \
+            users_tokens.created_at, \
+            users_tokens.last_used_at \
+        FROM \
+            users_tokens \
+        WHERE \
+            users_tokens.user_id = {user_id} \
+        ORDER BY \
+            users_tokens.created_at DESC \
+        LIMIT {limit};"
+    );
+    let stmt = conn.prepare(&query).await.unwrap();
+    match trace_db_query(&query, conn.query(&stmt, &[])).await {
+        Ok(query_result) => {
+            Ok(query_result.iter().map(get_user_session_from_row).collect())
+        }
+        Err(e) => Err(format!(
+            "{tracking_label} - \
+            failed to get login history for user_id={user_id} \
+            with err='{e}'"
+        )),
+    }
+}
+
+/// ModelJwtKidUsage
+///
+/// Number of active sessions signed with a jwt kid
+///
+/// # DB table
+///
+/// `users_tokens`
+///
+/// # Arguments
+///
+/// * `kid` - `String` - `users_tokens.kid` that signed the tokens
+/// * `active_tokens` - `i64` - active tokens that have not expired
+/// * `last_used_at` - `String` - most recent request with
+///   one of the tokens
+///
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ModelJwtKidUsage {
+    pub kid: String,
+    pub active_tokens: i64,
+    pub last_used_at: String,
+}
+
+/// get_active_token_counts_by_kid
+///
+/// Count the active (`0`) `users_tokens` records that
+/// were created within the token expiration window
+/// for each signing kid
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `expiration_seconds` - `usize` - jwt lifetime in seconds
+/// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+///   an established db connection from the
+///   postgres client db threadpool
+///
+/// # Returns
+///
+/// Ok(`Vec<`[`ModelJwtKidUsage`](crate::requests::models::user_session::ModelJwtKidUsage)`>`)
+/// sorted by kid
+///
+/// # Errors
+///
+/// Err(err_msg: `String`)
+///
+pub async fn get_active_token_counts_by_kid(
+    tracking_label: &str,
+    expiration_seconds: usize,
+    conn: &PooledConnection<'_, PostgresConnectionManager<MakeTlsConnector>>,
+) -> Result<Vec<ModelJwtKidUsage>, String> {
+    let query = format!(
+        "SELECT \
+            users_tokens.kid, \
+            COUNT(*) AS active_tokens, \
+            MAX(users_tokens.last_used_at) AS last_used_at \
+        FROM \
+            users_tokens \
+        WHERE \
+            users_tokens.state = 0 \
+            AND \
+            users_tokens.created_at > \
+                timezone('UTC'::text, now()) \
+                - interval '{expiration_seconds} seconds' \
+        GROUP BY \
+            users_tokens.kid \
+        ORDER BY \
+            users_tokens.kid;"
+    );
+    let stmt = conn.prepare(&query).await.unwrap();
+    let query_result =
+        match trace_db_query(&query, conn.query(&stmt, &[])).await {
+            Ok(query_result) => query_result,
+            Err(e) => {
+                return Err(format!(
+                    "{tracking_label} - \
+                failed to count active tokens by kid with err='{e}'"
+                ));
+            }
+        };
+    let mut usage: Vec<ModelJwtKidUsage> = Vec::new();
+    for row in query_result.iter() {
+        let last_used_at_utc: Option<chrono::DateTime<chrono::Utc>> =
+            row.try_get("last_used_at").unwrap();
+        usage.push(ModelJwtKidUsage {
+            kid: row.try_get("kid").unwrap(),
+            active_tokens: row.try_get("active_tokens").unwrap(),
+            last_used_at: match last_used_at_utc {
+                Some(v) => format!("{}", v.format("%Y-%m-%dT%H:%M:%SZ")),
+                None => "".to_string(),
+            },
+        });
+    }
+    Ok(usage)
+}
+
+/// revoke_tokens_by_kid
+///
+/// Revoke (set `state = 1`) every active `users_tokens`
+/// record signed with a jwt kid
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `kid` - `&str` - signing kid
+/// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+///   an established db connection from the
+///   postgres client db threadpool
+///
+/// # Returns
+///
+/// Ok(user_ids: `Vec<i32>`) - owner of each revoked token
+///
+/// # Errors
+///
+/// Err(err_msg: `String`)
+///
+pub async fn revoke_tokens_by_kid(
+    tracking_label: &str,
+    kid: &str,
+    conn: &PooledConnection<'_, PostgresConnectionManager<MakeTlsConnector>>,
+) -> Result<Vec<i32>, String> {
+    let query = format!(
+        "UPDATE \
+            users_tokens \
+        SET \
+            state = 1, \
+            updated_at = timezone('UTC'::text, now()) \
+        WHERE \
+            users_tokens.kid = '{}' \
+            AND \
+            users_tokens.state = 0 \
+        RETURNING \
+            users_tokens.user_id;",
+        kid.replace('\'', "''")
+    );
+    let stmt = conn.prepare(&query).await.unwrap();
+    match trace_db_query(&query, conn.query(&stmt, &[])).await {
+        Ok(query_result) => Ok(query_result
+            .iter()
+            .map(|row| row.try_get("user_id").unwrap())
+            .collect()),
+        Err(e) => Err(format!(
+            "{tracking_label} - \
+            failed to revoke tokens for kid={kid} with err='{e}'"
+        )),
+    }
+}
@@ -0,0 +1,644 @@
+//! Module for webhook endpoints and their deliveries
+//!
+//! Admins register endpoints in the ``webhooks`` table and
+//! the [`EventBus`](crate::kafka::event_bus::EventBus) queues
+//! one ``webhooks_deliveries`` record per subscribed endpoint
+//! for the
+//! [`WebhookDispatcher`](crate::webhooks::webhook_dispatcher::WebhookDispatcher)
+//!
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::PooledConnection;
+use bb8_postgres::PostgresConnectionManager;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::monitoring::otel::trace_db_query;
+
+/// ModelWebhook
+///
+/// Representation of a webhook endpoint in the db (the
+/// secret is never returned)
+///
+/// # DB table
+///
+/// `webhooks`
+///
+/// # Arguments
+///
+/// * `id` - `i32` - webhook id
+/// * `url` - `String` - endpoint that receives the deliveries
+/// * `event_types` - `Vec<String>` - subscribed webhook event
+///   types (``user.created``)
+/// * `state` - `i32` - ``0`` = active, ``1`` = deleted
+/// * `created_by` - `i32` - admin ``users.id``
+/// * `created_at` - `String` - utc timestamp
+///
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ModelWebhook {
+    pub id: i32,
+    pub url: String,
+    pub event_types: Vec<String>,
+    pub state: i32,
+    pub created_by: i32,
+    pub created_at: String,
+}
+
+/// ModelWebhookDelivery
+///
+/// Delivery status for one event sent to one webhook
+///
+/// # DB table
+///
+/// `webhooks_deliveries`
+///
+/// # Arguments
+///
+/// * `id` - `i64` - delivery id (``X-Webhook-Id`` header)
+/// * `webhook_id` - `i32` - ``webhooks.id``
+/// * `event` - `String` - webhook event type (``user.created``)
+/// * `status` - `String` - ``pending``, ``delivered`` or ``failed``
+/// * `attempts` - `i32` - number of sent requests
+/// * `last_status_code` - `i32` - HTTP status code of the
+///   last attempt (``0`` = no response)
+/// * `last_error` - `String` - error for the last failed attempt
+/// * `next_attempt_at` - `String` - utc timestamp for the
+///   next retry of a ``pending`` delivery
+/// * `created_at` - `String` - utc timestamp
+/// * `delivered_at` - `String` - utc timestamp (empty until
+///   ``delivered``)
+///
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ModelWebhookDelivery {
+    pub id: i64,
+    pub webhook_id: i32,
+    pub event: String,
+    pub status: String,
+    pub attempts: i32,
+    pub last_status_code: i32,
+    pub last_error: String,
+    pub next_attempt_at: String,
+    pub created_at: String,
+    pub delivered_at: String,
+}
+
+/// ModelWebhookAttempt
+///
+/// A claimed delivery with the endpoint it is sent to
+///
+/// # Arguments
+///
+/// * `id` - `i64` - ``webhooks_deliveries.id``
+/// * `webhook_id` - `i32` - ``webhooks.id``
+/// * `event` - `String` - webhook event type
+/// * `payload` - `String` - json request body
+/// * `attempts` - `i32` - attempts including this one
+/// * `url` - `String` - ``webhooks.url``
+/// * `secret` - `String` - ``webhooks.secret`` for the signature
+///
+#[derive(Clone, Debug, Default)]
+pub struct ModelWebhookAttempt {
+    pub id: i64,
+    pub webhook_id: i32,
+    pub event: String,
+    pub payload: String,
+    pub attempts: i32,
+    pub url: String,
+    pub secret: String,
+}
+
+/// format_webhook_ts
+///
+/// Format an optional timestamp column (empty for `NULL`)
+///
+fn format_webhook_ts(value: Option<chrono::DateTime<chrono::Utc>>) -> String {
+    match value {
+        Some(value) => format!("{}", value.format("%Y-%m-%dT%H:%M:%SZ")),
+        None => "".to_string(),
+    }
+}
+
+/// insert_webhook
+///
+/// Register a webhook endpoint
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `url` - `&str` - endpoint url
+/// * `secret` - `&str` - shared secret for signing deliveries
+/// * `event_types` - `&[String]` - validated webhook event types
+/// * `created_by` - `i32` - admin ``users.id``
+/// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+///   an established db connection from the
+///   postgres client db threadpool
+///
+/// # Returns
+///
+/// Ok([`ModelWebhook`](crate::requests::models::webhook::ModelWebhook))
+///
+/// # Errors
+///
+/// Err(err_msg: `String`) if the record cannot be created
+///
+pub async fn insert_webhook(
+    tracking_label: &str,
+    url: &str,
+    secret: &str,
+    event_types: &[String],
+    created_by: i32,
+    conn: &PooledConnection<'_, PostgresConnectionManager<MakeTlsConnector>>,
+) -> Result<ModelWebhook, String> {
+    let query = format!(
+        "INSERT INTO \
+            webhooks (\
+                url, \
+                secret, \
+                event_types, \
+                created_by) \
+        VALUES (\
+            '{}', \
+            '{}', \
+            $1, \
+            {created_by}) \
+        RETURNING \
+            webhooks.id, \
+            webhooks.created_at;",
+        url.replace('\'', "''"),
+        secret.replace('\'', "''")
+    );
+    match trace_db_query(&query, conn.query(query.as_str(), &[&event_types]))
+        .await
+    {
+        Ok(query_result) => match query_result.first() {
+            Some(row) => Ok(ModelWebhook {
+                id: row.try_get("id").unwrap(),
+                url: url.to_string(),
+                event_types: event_types.to_vec(),
+                state: 0,
+                created_by,
+                created_at: format_webhook_ts(
+                    row.try_get("created_at").unwrap(),
+                ),
+            }),
+            None => Err(format!(
+                "{tracking_label} - failed to create webhook url={url}"
+            )),
+        },
+        Err(e) => Err(format!(
+            "{tracking_label} - \
+            failed to create webhook url={url} with err='{e}'"
+        )),
+    }
+}
+
+/// get_webhooks
+///
+/// Get the active webhook endpoints (oldest first)
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+///   an established db connection from the
+///   postgres client db threadpool
+///
+/// # Returns
+///
+/// Ok(`Vec<`[`ModelWebhook`](crate::requests::models::webhook::ModelWebhook)`>`)
+///
+/// # Errors
+///
+/// Err(err_msg: `String`) if the query fails
+///
+pub async fn get_webhooks(
+    tracking_label: &str,
+    conn: &PooledConnection<'_, PostgresConnectionManager<MakeTlsConnector>>,
+) -> Result<Vec<ModelWebhook>, String> {
+    let query = "SELECT \
+            webhooks.id, \
+            webhooks.url, \
+            webhooks.event_types, \
+            webhooks.state, \
+            webhooks.created_by, \
+            webhooks.created_at \
+        FROM \
+            webhooks \
+        WHERE \
+            webhooks.state = 0 \
+        ORDER BY \
+            webhooks.id ASC;";
+    match trace_db_query(query, conn.query(query, &[])).await {
+        Ok(query_result) => Ok(query_result
+            .iter()
+            .map(|row| ModelWebhook {
+                id: row.try_get("id").unwrap(),
+                url: row.try_get("url").unwrap(),
+                event_types: row.try_get("event_types").unwrap(),
+                state: row.try_get("state").unwrap(),
+                created_by: row.try_get("created_by").unwrap(),
+                created_at: format_webhook_ts(
+                    row.try_get("created_at").unwrap(),
+                ),
+            })
+            .collect()),
+        Err(e) => Err(format!(
+            "{tracking_label} - failed to get webhooks with err='{e}'"
+        )),
+    }
+}
+
+/// delete_webhook
+///
+/// Soft-delete a webhook endpoint (``webhooks.state = 1``)
+/// and fail its ``pending`` deliveries. Delivery records are
+/// kept for the delivery status api.
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `webhook_id` - `i32` - ``webhooks.id``
+/// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+///   an established db connection from the
+///   postgres client db threadpool
+///
+/// # Returns
+///
+/// Ok(`Option<i64>`) - number of failed ``pending``
+/// deliveries or `None` if there is no active webhook
+/// with the id
+///
+/// # Errors
+///
+/// Err(err_msg: `String`) if the query fails
+///
+pub async fn delete_webhook(
+    tracking_label: &str,
+    webhook_id: i32,
+    conn: &PooledConnection<'_, PostgresConnectionManager<MakeTlsConnector>>,
+) -> Result<Option<i64>, String> {
+    let query = format!(
+        "WITH deleted AS (\
+            UPDATE \
+                webhooks \
+            SET \
+                state = 1, \
+                deleted_at = timezone('UTC'::text, now()) \
+            WHERE \
+                webhooks.id = {webhook_id} \
+                AND \
+                webhooks.state = 0 \
+            RETURNING \
+                webhooks.id), \
+        cancelled AS (\
+            UPDATE \
+                webhooks_deliveries \
+            SET \
+                status = 'failed', \
+                last_error = 'webhook deleted' \
+            FROM \
+                deleted \
+            WHERE \
+                webhooks_deliveries.webhook_id = deleted.id \
+                AND \
+                webhooks_deliveries.status = 'pending' \
+            RETURNING \
+                webhooks_deliveries.id) \
+        SELECT \
+            deleted.id, \
+            (SELECT COUNT(*) FROM cancelled) AS cancelled \
+        FROM \
+            deleted;"
+    );
+    match trace_db_query(&query, conn.query(query.as_str(), &[])).await {
+        Ok(query_result) => Ok(query_result
+            .first()
+            .map(|row| row.try_get("cancelled").unwrap())),
+        Err(e) => Err(format!(
+            "{tracking_label} - \
+            failed to delete webhook={webhook_id} with err='{e}'"
+        )),
+    }
+}
+
+/// search_webhook_deliveries
+///
+/// Get the newest deliveries (newest first) filtered by
+/// webhook and/or status
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `webhook_id` - `Option<i32>` - only this ``webhooks.id``
+/// * `status` - `Option<&str>` - only this validated status
+/// * `limit` - `i64` - max deliveries to return
+/// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+///   an established db connection from the
+///   postgres client db threadpool
+///
+/// # Returns
+///
+/// Ok(`Vec<`[`ModelWebhookDelivery`](crate::requests::models::webhook::ModelWebhookDelivery)`>`)
+///
+/// # Errors
+///
+/// Err(err_msg: `String`) if the query fails
+///
+pub async fn search_webhook_deliveries(
+    tracking_label: &str,
+    webhook_id: Option<i32>,
+    status: Option<&str>,
+    limit: i64,
+    conn: &PooledConnection<'_, PostgresConnectionManager<MakeTlsConnector>>,
+) -> Result<Vec<ModelWebhookDelivery>, String> {
+    let mut filters = vec!["TRUE".to_string()];
+    if let Some(webhook_id) = webhook_id {
+        filters.push(format!("webhooks_deliveries.webhook_id = {webhook_id}"));
+    }
+    if let Some(status) = status {
+        filters.push(format!(
+            "webhooks_deliveries.status = '{}'",
+            status.replace('\'', "''")
+        ));
+    }
+    let query = format!(
+        "SELECT \
+            webhooks_deliveries.id, \
+            webhooks_deliveries.webhook_id, \
+            webhooks_deliveries.event, \
+            webhooks_deliveries.status, \
+            webhooks_deliveries.attempts, \
+            webhooks_deliveries.last_status_code, \
+            webhooks_deliveries.last_error, \
+            webhooks_deliveries.next_attempt_at, \
+            webhooks_deliveries.created_at, \
+            webhooks_deliveries.delivered_at \
+        FROM \
+            webhooks_deliveries \
+        WHERE \
+            {} \
+        ORDER BY \
+            webhooks_deliveries.id DESC \
+        LIMIT {limit};",
+        filters.join(" AND ")
+    );
+    match trace_db_query(&query, conn.query(query.as_str(), &[])).await {
+        Ok(query_result) => Ok(query_result
+            .iter()
+            .map(|row| {
+                let last_status_code: Option<i32> =
+                    row.try_get("last_status_code").unwrap();
+                let last_error: Option<String> =
+                    row.try_get("last_error").unwrap();
+                ModelWebhookDelivery {
+                    id: row.try_get("id").unwrap(),
+                    webhook_id: row.try_get("webhook_id").unwrap(),
+                    event: row.try_get("event").unwrap(),
+                    status: row.try_get("status").unwrap(),
+                    attempts: row.try_get("attempts").unwrap(),
+                    last_status_code: last_status_code.unwrap_or(0),
+                    last_error: last_error.unwrap_or_default(),
+                    next_attempt_at: format_webhook_ts(
+                        row.try_get("next_attempt_at").unwrap(),
+                    ),
+                    created_at: format_webhook_ts(
+                        row.try_get("created_at").unwrap(),
+                    ),
+                    delivered_at: format_webhook_ts(
+                        row.try_get("delivered_at").unwrap(),
+                    ),
+                }
+            })
+            .collect()),
+        Err(e) => Err(format!(
+            "{tracking_label} - \
+            failed to search webhook deliveries with err='{e}'"
+        )),
+    }
+}
+
+/// insert_webhook_deliveries
+///
+/// Queue a ``pending`` delivery of an event for every
+/// active webhook subscribed to the event type
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `event` - `&str` - webhook event type (``user.created``)
+/// * `payload` - `&str` - json request body
+/// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+///   an established db connection from the
+///   postgres client db threadpool
+///
+/// # Returns
+///
+/// Ok(num_queued: `u64`)
+///
+/// # Errors
+///
+/// Err(err_msg: `String`) if the insert fails
+///
+pub async fn insert_webhook_deliveries(
+    tracking_label: &str,
+    event: &str,
+    payload: &str,
+    conn: &PooledConnection<'_, PostgresConnectionManager<MakeTlsConnector>>,
+) -> Result<u64, String> {
+    let query = format!(
+        "INSERT INTO \
+            webhooks_deliveries (\
+                webhook_id, \
+                event, \
+                payload) \
+        SELECT \
+            webhooks.id, \
+            '{event}', \
+            '{}' \
+        FROM \
+            webhooks \
+        WHERE \
+            webhooks.state = 0 \
+            AND \
+            '{event}' = ANY(webhooks.event_types);",
+        payload.replace('\'', "''")
+    );
+    match trace_db_query(&query, conn.execute(query.as_str(), &[])).await {
+        Ok(num_queued) => Ok(num_queued),
+        Err(e) => Err(format!(
+            "{tracking_label} - \
+            failed to queue {event} webhook deliveries with err='{e}'"
+        )),
+    }
+}
+
+/// claim_webhook_deliveries
+///
+/// Claim a batch of due ``pending`` deliveries for active
+/// webhooks. Each claimed delivery counts an attempt and
+/// is not due again until `lease_seconds` pass, so api
+/// servers that claim at the same time skip each other's
+/// rows and a delivery is retried if its server stops
+/// before recording the result.
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `batch_size` - `i64` - max deliveries to claim
+/// * `lease_seconds` - `i64` - seconds before an unfinished
+///   attempt is due again
+/// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+///   an established db connection from the
+///   postgres client db threadpool
+///
+/// # Returns
+///
+/// Ok(`Vec<`[`ModelWebhookAttempt`](crate::requests::models::webhook::ModelWebhookAttempt)`>`)
+///
+/// # Errors
+///
+/// Err(err_msg: `String`) if the query fails
+///
+pub async fn claim_webhook_deliveries(
+    tracking_label: &str,
+    batch_size: i64,
+    lease_seconds: i64,
+    conn: &PooledConnection<'_, PostgresConnectionManager<MakeTlsConnector>>,
+) -> Result<Vec<ModelWebhookAttempt>, String> {
+    let query = format!(
+        "WITH claimed AS (\
+            UPDATE \
+                webhooks_deliveries \
+            SET \
+                attempts = webhooks_deliveries.attempts + 1, \
+                next_attempt_at = timezone('UTC'::text, now()) \
+                    + interval '{lease_seconds} seconds' \
+            WHERE \
+                webhooks_deliveries.id IN (\
+                    SELECT \
+                        webhooks_deliveries.id \
+                    FROM \
+                        webhooks_deliveries \
+                    INNER JOIN \
+                        webhooks \
+                    ON \
+                        webhooks.id = webhooks_deliveries.webhook_id \
+                    WHERE \
+                        webhooks_deliveries.status = 'pending' \
+                        AND \
+                        webhooks_deliveries.next_attempt_at <= \
+                            timezone('UTC'::text, now()) \
+                        AND \
+                        webhooks.state = 0 \
+                    ORDER BY \
+                        webhooks_deliveries.next_attempt_at ASC \
+                    LIMIT {batch_size} \
+                    FOR UPDATE OF webhooks_deliveries SKIP LOCKED) \
+            RETURNING \
+                webhooks_deliveries.id, \
+                webhooks_deliveries.webhook_id, \
+                webhooks_deliveries.event, \
+                webhooks_deliveries.payload, \
+                webhooks_deliveries.attempts) \
+        SELECT \
+            claimed.id, \
+            claimed.webhook_id, \
+            claimed.event, \
+            claimed.payload, \
+            claimed.attempts, \
+            webhooks.url, \
+            webhooks.secret \
+        FROM \
+            claimed \
+        INNER JOIN \
+            webhooks \
+        ON \
+            webhooks.id = claimed.webhook_id;"
+    );
+    match trace_db_query(&query, conn.query(query.as_str(), &[])).await {
+        Ok(query_result) => Ok(query_result
+            .iter()
+            .map(|row| ModelWebhookAttempt {
+                id: row.try_get("id").unwrap(),
+                webhook_id: row.try_get("webhook_id").unwrap(),
+                event: row.try_get("event").unwrap(),
+                payload: row.try_get("payload").unwrap(),
+                attempts: row.try_get("attempts").unwrap(),
+                url: row.try_get("url").unwrap(),
+                secret: row.try_get("secret").unwrap(),
+            })
+            .collect()),
+        Err(e) => Err(format!(
+            "{tracking_label} - \
+            failed to claim webhook deliveries with err='{e}'"
+        )),
+    }
+}
+
+/// update_webhook_delivery
+///
+/// Record the result of a delivery attempt
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `delivery_id` - `i64` - ``webhooks_deliveries.id``
+/// * `status` - `&str` - ``pending`` (retry),
+///   ``delivered`` or ``failed``
+/// * `status_code` - `i32` - HTTP status code
+///   (``0`` = no response)
+/// * `error` - `&str` - error message (empty on success)
+/// * `retry_in_seconds` - `i64` - seconds until the next
+///   attempt for a ``pending`` delivery
+/// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+///   an established db connection from the
+///   postgres client db threadpool
+///
+/// # Errors
+///
+/// Err(err_msg: `String`) if the update fails
+///
+pub async fn update_webhook_delivery(
+    tracking_label: &str,
+    delivery_id: i64,
+    status: &str,
+    status_code: i32,
+    error: &str,
+    retry_in_seconds: i64,
+    conn: &PooledConnection<'_, PostgresConnectionManager<MakeTlsConnector>>,
+) -> Result<(), String> {
+    let last_status_code = match status_code {
+        0 => "NULL".to_string(),
+        _ => format!("{status_code}"),
+    };
+    let last_error = match error.is_empty() {
+        true => "NULL".to_string(),
+        false => format!("'{}'", error.replace('\'', "''")),
+    };
+    let delivered_at = match status {
+        "delivered" => "timezone('UTC'::text, now())",
+        _ => "NULL",
+    };
+    let query = format!(
+        "UPDATE \
+            webhooks_deliveries \
+        SET \
+            status = '{status}', \
+            last_status_code = {last_status_code}, \
+            last_error = {last_error}, \
+            next_attempt_at = timezone('UTC'::text, now()) \
+                + interval '{retry_in_seconds} seconds', \
+            delivered_at = {delivered_at} \
+        WHERE \
+            webhooks_deliveries.id = {delivery_id};"
+    );
+    match trace_db_query(&query, conn.execute(query.as_str(), &[])).await {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!(
+            "{tracking_label} - \
+            failed to update webhook delivery={delivery_id} \
+            status={status} with err='{e}'"
+        )),
+    }
+}
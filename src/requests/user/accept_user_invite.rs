@@ -0,0 +1,369 @@
+//! Module for accepting a user invite
+//!
+//! ## Accept User Invite
+//!
+//! Consume a one-time-use invite token created by [`invite_user`](crate::requests::admin::invite_user::invite_user), set the user's ``users.password`` and activate the account (``users.state = 0``). Receiving the invite proves the user owns the email, so the email is also marked as verified. Returns a new jwt for the user.
+//!
+//! - URL path: ``/user/invite/accept``
+//! - Method: ``POST``
+//! - Handler: [`accept_user_invite`](crate::requests::user::accept_user_invite::accept_user_invite)
+//! - Request: [`ApiReqUserAcceptInvite`](crate::requests::user::accept_user_invite::ApiReqUserAcceptInvite)
+//! - Response: [`ApiResUserLogin`](crate::requests::auth::login_user::ApiResUserLogin)
+//!
+use std::convert::Infallible;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Response;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::core::core_config::CoreConfig;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::monitoring::otel::trace_db_query;
+use crate::requests::auth::create_user_token::create_user_token;
+use crate::requests::auth::login_user::ApiResUserLogin;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::models::user_session::get_user_session_metadata;
+use crate::requests::validation::field_rules::check_id;
+use crate::requests::validation::field_rules::check_length;
+use crate::requests::validation::field_rules::check_password;
+use crate::requests::validation::validate_api_req::validate_api_req;
+use crate::requests::validation::validate_api_req::ApiFieldError;
+use crate::requests::validation::validate_api_req::ApiReqValidate;
+use crate::requests::validation::validate_password_policy::validate_password_policy;
+use crate::utils::hash_token::hash_token;
+use crate::utils::password_migration::get_password_scheme;
+
+/// ApiReqUserAcceptInvite
+///
+/// # Request Type For accept_user_invite
+///
+/// Accept an invite and set the user's password
+///
+/// This type is the deserialized input for:
+/// [`accept_user_invite`](crate::requests::user::accept_user_invite::accept_user_invite)
+///
+/// # Usage
+///
+/// This type is constructed from the deserialized
+/// `bytes` (`&[u8]`) argument
+/// on the
+/// [`accept_user_invite`](crate::requests::user::accept_user_invite::accept_user_invite)
+/// function.
+///
+/// # Arguments
+///
+/// * `user_id` - `i32` - invited user id
+/// * `token` - `String` - one-time-use invite token
+/// * `password` - `String` - new user password
+///
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApiReqUserAcceptInvite {
+    pub user_id: i32,
+    pub token: String,
+    pub password: String,
+}
+
+impl ApiReqValidate for ApiReqUserAcceptInvite {
+    /// validate
+    ///
+    /// Require a positive `user_id`, a `token` between
+    /// 4 and 256 characters and a new `password` between
+    /// 4 and 1024 characters
+    ///
+    fn validate(&self) -> Vec<ApiFieldError> {
+        let mut errors = Vec::new();
+        check_id(&mut errors, "user_id", self.user_id);
+        check_length(&mut errors, "token", &self.token, 4, 256);
+        check_password(&mut errors, "password", &self.password);
+        errors
+    }
+}
+
+/// accept_user_invite
+///
+/// Handler for accepting a pending invite. The invite is
+/// consumed and the user is activated with one sql
+/// statement, so only one of many concurrent requests
+/// for the same token succeeds.
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
+///   db threadpool with required tls encryption
+/// * `kafka_pool` -
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+///   for asynchronously publishing messages to the connected kafka cluster
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+/// * `remote_addr` - `&std::net::SocketAddr` - client address
+/// * `bytes` - `&[u8]` - received bytes from the hyper
+///   [`Request`](hyper::Request)'s [`Body`](hyper::Body)
+///
+/// # Returns
+///
+/// ## accept_user_invite on Success Returns
+///
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResUserLogin`](crate::requests::auth::login_user::ApiResUserLogin)
+/// dictionary within the
+/// [`Body`](hyper::Body) and a
+/// `200` HTTP status code
+///
+/// Ok([`Response`](hyper::Response))
+///
+/// # Errors
+///
+/// ## accept_user_invite on Failure Returns
+///
+/// All errors return as a
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResUserLogin`](crate::requests::auth::login_user::ApiResUserLogin)
+/// dictionary with a
+/// `non-200` HTTP status code
+///
+/// Err([`Response`](hyper::Response))
+///
+pub async fn accept_user_invite(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    kafka_pool: &KafkaPublisher,
+    headers: &HeaderMap<HeaderValue>,
+    remote_addr: &std::net::SocketAddr,
+    bytes: &[u8],
+) -> std::result::Result<Response<Body>, Infallible> {
+    let req_object: ApiReqUserAcceptInvite = match serde_json::from_slice(bytes)
+    {
+        Ok(uo) => uo,
+        Err(_) => {
+            let response = Response::builder()
+                .status(400)
+                .body(Body::from(
+                    serde_json::to_string(&ApiResUserLogin {
+                        user_id: -1,
+                        email: "".to_string(),
+                        state: -1,
+                        verified: -1,
+                        role: "".to_string(),
+                        token: "".to_string(),
+                        msg: ("User accept invite failed - please \
+                                ensure user_id, token and password \
+                                are set in the request")
+                            .to_string(),
+                        error_code: Some(ApiErrorCode::InvalidRequest),
+                        challenge: Vec::new(),
+                    })
+                    .unwrap(),
+                ))
+                .unwrap();
+            return Ok(response);
+        }
+    };
+    if let Some(response) = validate_api_req(tracking_label, &req_object) {
+        return Ok(response);
+    }
+    if let Some(response) = validate_password_policy(
+        tracking_label,
+        &config.get_settings().password_policy,
+        "password",
+        &req_object.password,
+    ) {
+        return Ok(response);
+    }
+    let user_id = req_object.user_id;
+
+    // only the token hash is stored in the db
+    let token_hash =
+        hash_token(&req_object.token, &config.server_password_salt);
+
+    // salt the user's password
+    let new_password = config
+        .password_hashing
+        .hash_password(&req_object.password, &config.server_password_salt)
+        .unwrap();
+    let new_password_scheme = get_password_scheme(&new_password);
+
+    // accept the invite and activate the user in 1 statement.
+    // the conditional UPDATE row-locks the invite so concurrent
+    // requests for the same token wait and then match 0 rows
+    // after the first request sets state = 1
+    let now = chrono::Utc::now();
+    let query = format!(
+        "WITH accepted_invite AS (\
+            UPDATE \
+                users_invites \
+            SET \
+                state = 1, \
+                accepted_at = '{now}' \
+            WHERE \
+                users_invites.user_id = {user_id} \
+                AND \
+                users_invites.state = 0 \
+                AND \
+                users_invites.token = '{}' \
+                AND \
+                users_invites.exp_date > '{now}' \
+            RETURNING \
+                users_invites.user_id) \
+        UPDATE \
+            users \
+        SET \
+            password = '{new_password}', \
+            password_scheme = '{new_password_scheme}', \
+            state = 0, \
+            verified = 1, \
+            updated_at = '{now}' \
+        FROM \
+            accepted_invite \
+        WHERE \
+            users.id = accepted_invite.user_id \
+        RETURNING \
+            users.id, \
+            users.email, \
+            users.state, \
+            users.verified, \
+            users.role;",
+        token_hash.replace('\'', "''")
+    );
+    let conn = db_pool.get().await.unwrap();
+    let stmt = conn.prepare(&query).await.unwrap();
+    let query_result =
+        match trace_db_query(&query, conn.query(&stmt, &[])).await {
+            Ok(query_result) => query_result,
+            Err(e) => {
+                error!(
+                    "{tracking_label} - \
+                failed to accept invite for user {user_id} with err='{e}'"
+                );
+                let response = Response::builder()
+                    .status(500)
+                    .body(Body::from(
+                        serde_json::to_string(&ApiResUserLogin {
+                            user_id: -1,
+                            email: "".to_string(),
+                            state: -1,
+                            verified: -1,
+                            role: "".to_string(),
+                            token: "".to_string(),
+                            msg: format!(
+                            "User accept invite failed for user_id={user_id}"
+                        ),
+                            error_code: Some(ApiErrorCode::InternalError),
+                            challenge: Vec::new(),
+                        })
+                        .unwrap(),
+                    ))
+                    .unwrap();
+                return Ok(response);
+            }
+        };
+    config.user_cache.invalidate_user(user_id).await;
+    if query_result.is_empty() {
+        let response = Response::builder()
+            .status(400)
+            .body(Body::from(
+                serde_json::to_string(&ApiResUserLogin {
+                    user_id: -1,
+                    email: "".to_string(),
+                    state: -1,
+                    verified: -1,
+                    role: "".to_string(),
+                    token: "".to_string(),
+                    msg: ("User invite does not exist, was already \
+                        accepted or has expired")
+                        .to_string(),
+                    error_code: Some(ApiErrorCode::NotFound),
+                    challenge: Vec::new(),
+                })
+                .unwrap(),
+            ))
+            .unwrap();
+        return Ok(response);
+    }
+    let row = &query_result[0];
+    let user_email: String = row.try_get("email").unwrap();
+    let user_state: i32 = row.try_get("state").unwrap();
+    let user_verified: i32 = row.try_get("verified").unwrap();
+    let user_role: String = row.try_get("role").unwrap();
+
+    let user_token = match create_user_token(
+        tracking_label,
+        config,
+        &conn,
+        &user_email,
+        user_id,
+        &get_user_session_metadata(headers, remote_addr, &config.geo_ip),
+        "",
+    )
+    .await
+    {
+        Ok(user_token) => user_token,
+        Err(_) => {
+            let response = Response::builder()
+                .status(500)
+                .body(Body::from(
+                    serde_json::to_string(&ApiResUserLogin {
+                        user_id: -1,
+                        email: "".to_string(),
+                        state: -1,
+                        verified: -1,
+                        role: "".to_string(),
+                        token: "".to_string(),
+                        msg: format!(
+                            "User token creation failed - \
+                            {user_id} {user_email}"
+                        ),
+                        error_code: Some(ApiErrorCode::InternalError),
+                        challenge: Vec::new(),
+                    })
+                    .unwrap(),
+                ))
+                .unwrap();
+            return Ok(response);
+        }
+    };
+
+    info!("{tracking_label} - user {user_id} {user_email} accepted invite");
+    config
+        .events
+        .publish_user_event(
+            kafka_pool,
+            user_id,
+            "USER_INVITE_ACCEPT",
+            &format!("email={user_email}"),
+        )
+        .await;
+
+    let response = config
+        .token_cookie
+        .set_token_cookie(Response::builder().status(200), &user_token)
+        .body(Body::from(
+            serde_json::to_string(&ApiResUserLogin {
+                user_id,
+                email: user_email,
+                state: user_state,
+                verified: user_verified,
+                role: user_role,
+                token: config.token_cookie.get_response_token(user_token),
+                msg: "success".to_string(),
+                error_code: None,
+                challenge: Vec::new(),
+            })
+            .unwrap(),
+        ))
+        .unwrap();
+    Ok(response)
+}
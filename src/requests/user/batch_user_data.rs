@@ -0,0 +1,635 @@
+//! Module for updating, moving and deleting many of a
+//! user's ``users_data`` records in one request
+//!
+//! ## Run a batch of user data file operations
+//!
+//! Update the metadata, move to a folder or delete up to 100 ``users_data`` records in a single db transaction with a result for each operation. If any operation fails the whole batch is rolled back.
+//!
+//! - URL path: ``/user/data/batch``
+//! - Method: ``POST``
+//! - Handler: [`batch_user_data`](crate::requests::user::batch_user_data::batch_user_data)
+//! - Request: [`ApiReqUserDataBatch`](crate::requests::user::batch_user_data::ApiReqUserDataBatch)
+//! - Response: [`ApiResUserDataBatch`](crate::requests::user::batch_user_data::ApiResUserDataBatch)
+//!
+
+use std::convert::Infallible;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Response;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::core::core_config::CoreConfig;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::requests::auth::validate_user_token::validate_user_token;
+use crate::requests::models::api_error::ApiError;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::models::user::get_user_by_id;
+use crate::requests::models::user_data::ModelUserData;
+use crate::requests::models::user_data_repo::UserDataChanges;
+use crate::requests::models::user_data_repo::UserDataRepo;
+use crate::requests::user::update_user_data::ApiReqUserUpdateData;
+use crate::requests::validation::field_rules::add_field_error;
+use crate::requests::validation::field_rules::check_id;
+use crate::requests::validation::field_rules::check_one_of;
+use crate::requests::validation::validate_api_req::validate_api_req;
+use crate::requests::validation::validate_api_req::ApiFieldError;
+use crate::requests::validation::validate_api_req::ApiReqValidate;
+
+/// max operations in one
+/// [`ApiReqUserDataBatch`](crate::requests::user::batch_user_data::ApiReqUserDataBatch)
+pub const MAX_USER_DATA_BATCH_OPERATIONS: usize = 100;
+
+/// supported
+/// [`ApiReqUserDataBatchOperation`](crate::requests::user::batch_user_data::ApiReqUserDataBatchOperation)
+/// `op` values
+pub const USER_DATA_BATCH_OPS: [&str; 3] = ["update", "move", "delete"];
+
+/// ApiReqUserDataBatchOperation
+///
+/// # One operation in an ApiReqUserDataBatch
+///
+/// # Arguments
+///
+/// * `op` - `String` - ``update`` (change the metadata
+///   fields like
+///   [`ApiReqUserUpdateData`](crate::requests::user::update_user_data::ApiReqUserUpdateData)),
+///   ``move`` (change only the `folder`) or ``delete``
+///   (only records the user owns)
+/// * `data_id` - `i32` - `users_data.id` record to change
+/// * `filename` - `Option<String>` - ``update`` only
+/// * `data_type` - `Option<String>` - ``update`` only
+/// * `comments` - `Option<String>` - ``update`` only
+/// * `encoding` - `Option<String>` - ``update`` only
+/// * `sloc` - `Option<String>` - ``update`` only
+/// * `tags` - `Option<Vec<String>>` - ``update`` only
+/// * `folder` - `Option<String>` - required for ``move``
+///   (an empty string removes the record from its folder)
+/// * `version` - `Option<i32>` - required `users_data.version`
+///   from the last search or update response
+///
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApiReqUserDataBatchOperation {
+    pub op: String,
+    pub data_id: i32,
+    pub filename: Option<String>,
+    pub data_type: Option<String>,
+    pub comments: Option<String>,
+    pub encoding: Option<String>,
+    pub sloc: Option<String>,
+    pub tags: Option<Vec<String>>,
+    pub folder: Option<String>,
+    pub version: Option<i32>,
+}
+
+/// implementation for validating and converting an
+/// operation into repository changes
+impl ApiReqUserDataBatchOperation {
+    /// get_update_request
+    ///
+    /// Build the
+    /// [`ApiReqUserUpdateData`](crate::requests::user::update_user_data::ApiReqUserUpdateData)
+    /// this operation is validated and applied as
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - `i32` - user id from the batch
+    ///
+    pub fn get_update_request(&self, user_id: i32) -> ApiReqUserUpdateData {
+        ApiReqUserUpdateData {
+            user_id,
+            data_id: self.data_id,
+            filename: self.filename.clone(),
+            data_type: self.data_type.clone(),
+            comments: self.comments.clone(),
+            encoding: self.encoding.clone(),
+            sloc: self.sloc.clone(),
+            tags: self.tags.clone(),
+            folder: self.folder.clone(),
+            version: self.version,
+        }
+    }
+
+    /// validate
+    ///
+    /// Add errors for an unsupported `op`, an invalid
+    /// `data_id` or `version`, invalid metadata fields and
+    /// fields the `op` does not use
+    ///
+    /// # Arguments
+    ///
+    /// * `errors` - `&mut Vec<ApiFieldError>` - invalid fields
+    /// * `user_id` - `i32` - user id from the batch
+    /// * `index` - `usize` - position in the batch's
+    ///   `operations` for the field names
+    ///
+    pub fn validate(
+        &self,
+        errors: &mut Vec<ApiFieldError>,
+        user_id: i32,
+        index: usize,
+    ) {
+        let prefix = format!("operations[{index}]");
+        check_one_of(
+            errors,
+            &format!("{prefix}.op"),
+            self.op.as_str(),
+            &USER_DATA_BATCH_OPS,
+        );
+        for e in self.get_update_request(user_id).validate() {
+            if e.field != "user_id" {
+                add_field_error(
+                    errors,
+                    &format!("{prefix}.{}", e.field),
+                    &e.msg,
+                );
+            }
+        }
+        let update_only_fields = [
+            ("filename", self.filename.is_some()),
+            ("data_type", self.data_type.is_some()),
+            ("comments", self.comments.is_some()),
+            ("encoding", self.encoding.is_some()),
+            ("sloc", self.sloc.is_some()),
+            ("tags", self.tags.is_some()),
+        ];
+        if self.op != "update" {
+            for (field, is_set) in update_only_fields.iter() {
+                if *is_set {
+                    add_field_error(
+                        errors,
+                        &format!("{prefix}.{field}"),
+                        "is only supported by the update op",
+                    );
+                }
+            }
+        }
+        match (self.op.as_str(), &self.folder) {
+            ("move", None) => add_field_error(
+                errors,
+                &format!("{prefix}.folder"),
+                "is required for the move op",
+            ),
+            ("delete", Some(_)) => add_field_error(
+                errors,
+                &format!("{prefix}.folder"),
+                "is not supported by the delete op",
+            ),
+            _ => {}
+        }
+    }
+}
+
+/// ApiReqUserDataBatch
+///
+/// # Request Type For batch_user_data
+///
+/// Handles updating, moving and deleting many `users_data`
+/// records in one db transaction
+///
+/// This type is the deserialized input for:
+/// [`batch_user_data`](crate::requests::user::batch_user_data::batch_user_data)
+///
+/// # Usage
+///
+/// This type is constructed from the deserialized
+/// `bytes` (`&[u8]`) argument
+/// on the
+/// [`batch_user_data`](crate::requests::user::batch_user_data::batch_user_data)
+/// function.
+///
+/// # Arguments
+///
+/// * `user_id` - `i32` - user id
+/// * `operations` - `Vec<`[`ApiReqUserDataBatchOperation`](crate::requests::user::batch_user_data::ApiReqUserDataBatchOperation)`>` -
+///   1 to
+///   [`MAX_USER_DATA_BATCH_OPERATIONS`](crate::requests::user::batch_user_data::MAX_USER_DATA_BATCH_OPERATIONS)
+///   operations run in order
+///
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApiReqUserDataBatch {
+    pub user_id: i32,
+    pub operations: Vec<ApiReqUserDataBatchOperation>,
+}
+
+impl ApiReqValidate for ApiReqUserDataBatch {
+    /// validate
+    ///
+    /// Require a positive `user_id` and 1 to
+    /// [`MAX_USER_DATA_BATCH_OPERATIONS`](crate::requests::user::batch_user_data::MAX_USER_DATA_BATCH_OPERATIONS)
+    /// valid operations
+    ///
+    fn validate(&self) -> Vec<ApiFieldError> {
+        let mut errors = Vec::new();
+        check_id(&mut errors, "user_id", self.user_id);
+        if self.operations.is_empty()
+            || self.operations.len() > MAX_USER_DATA_BATCH_OPERATIONS
+        {
+            add_field_error(
+                &mut errors,
+                "operations",
+                &format!(
+                    "must have between 1 and \
+                    {MAX_USER_DATA_BATCH_OPERATIONS} operations"
+                ),
+            );
+        }
+        for (index, operation) in self.operations.iter().enumerate() {
+            operation.validate(&mut errors, self.user_id, index);
+        }
+        errors
+    }
+}
+
+/// ApiResUserDataBatchResult
+///
+/// # Result for one operation in an ApiResUserDataBatch
+///
+/// # Arguments
+///
+/// * `op` - `String` - requested operation
+/// * `data_id` - `i32` - `users_data.id` from the operation
+/// * `status_code` - `u16` - `200` if the operation
+///   succeeded, the failed operation's HTTP status code
+///   (`404` if the record does not exist or the user cannot
+///   change it, `409` for a stale `version` or a record that
+///   cannot be deleted) or `424` for every other operation
+///   in a rolled back batch
+/// * `data` - [`ModelUserData`](crate::requests::models::user_data::ModelUserData) -
+///   the changed record (empty if the batch was rolled back)
+/// * `msg` - `String` - help message
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   machine-readable failure code (omitted on success)
+///
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApiResUserDataBatchResult {
+    pub op: String,
+    pub data_id: i32,
+    pub status_code: u16,
+    pub data: ModelUserData,
+    pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ApiErrorCode>,
+}
+
+/// ApiResUserDataBatch
+///
+/// # Response type for batch_user_data
+///
+/// Return the result for each operation in the batch
+///
+/// # Usage
+///
+/// This type is the serialized output for the function:
+/// [`batch_user_data`](crate::requests::user::batch_user_data::batch_user_data)
+/// and contained within the
+/// hyper [`Body`](hyper::Body)
+/// of the
+/// hyper [`Response`](hyper::Response)
+/// sent back to the client.
+///
+/// # Arguments
+///
+/// * `user_id` - `i32` - user id
+/// * `results` - `Vec<`[`ApiResUserDataBatchResult`](crate::requests::user::batch_user_data::ApiResUserDataBatchResult)`>` -
+///   one result for each operation in the request's order
+/// * `msg` - `String` - help message
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   machine-readable failure code (omitted on success)
+///
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct ApiResUserDataBatch {
+    pub user_id: i32,
+    pub results: Vec<ApiResUserDataBatchResult>,
+    pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ApiErrorCode>,
+}
+
+/// get_batch_response
+///
+/// Build the hyper [`Response`](hyper::Response) for
+/// [`batch_user_data`](crate::requests::user::batch_user_data::batch_user_data)
+///
+/// # Arguments
+///
+/// * `status` - `u16` - HTTP status code
+/// * `res` - [`ApiResUserDataBatch`](crate::requests::user::batch_user_data::ApiResUserDataBatch)
+///
+fn get_batch_response(
+    status: u16,
+    res: &ApiResUserDataBatch,
+) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::from(serde_json::to_string(res).unwrap()))
+        .unwrap()
+}
+
+/// batch_user_data
+///
+/// Handles running a batch of ``update``, ``move`` and
+/// ``delete`` operations on the user's `users_data` records
+/// based off values in the POST-ed hyper
+/// [`Request`](hyper::Request)'s [`Body`](hyper::Body)
+///
+/// ## Overview Notes
+///
+/// The operations run in order in one db transaction.
+/// ``update`` and ``move`` change records the user owns or
+/// can write through a ``users_data_acl`` share (see
+/// [`UserDataRepo::update`](crate::requests::models::user_data_repo::UserDataRepo::update)).
+/// ``delete`` marks ``ready``, ``quarantined``, ``failed``
+/// and ``broken`` records the user owns ``expired`` (see
+/// [`UserDataRepo::delete`](crate::requests::models::user_data_repo::UserDataRepo::delete)),
+/// and the lifecycle task deletes their s3 objects.
+///
+/// The first failed operation rolls back the whole batch
+/// and the operations after it are not run.
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
+///   db threadpool with required tls encryption
+/// * `kafka_pool` -
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+///   for asynchronously publishing messages to the connected kafka cluster
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+/// * `bytes` - `&[u8]` - received bytes from the hyper
+///   [`Request`](hyper::Request)'s [`Body`](hyper::Body)
+///
+/// # Returns
+///
+/// ## batch_user_data on Success Returns
+///
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResUserDataBatch`](crate::requests::user::batch_user_data::ApiResUserDataBatch)
+/// dictionary within the
+/// [`Body`](hyper::Body) and a
+/// `200` HTTP status code
+///
+/// Ok([`Response`](hyper::Response))
+///
+/// # Errors
+///
+/// ## batch_user_data on Failure Returns
+///
+/// All errors return as a
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResUserDataBatch`](crate::requests::user::batch_user_data::ApiResUserDataBatch)
+/// dictionary with a
+/// `non-200` HTTP status code (the failed operation's
+/// `status_code` for a rolled back batch)
+///
+/// Err([`Response`](hyper::Response))
+///
+pub async fn batch_user_data(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    kafka_pool: &KafkaPublisher,
+    headers: &HeaderMap<HeaderValue>,
+    bytes: &[u8],
+) -> std::result::Result<Response<Body>, Infallible> {
+    let batch_object: ApiReqUserDataBatch = match serde_json::from_slice(bytes)
+    {
+        Ok(bo) => bo,
+        Err(_) => {
+            return Ok(get_batch_response(
+                400,
+                &ApiResUserDataBatch {
+                    user_id: -1,
+                    results: Vec::new(),
+                    msg: ("User data batch failed - please ensure \
+                        user_id and operations are set with an op \
+                        and data_id for each operation")
+                        .to_string(),
+                    error_code: Some(ApiErrorCode::InvalidRequest),
+                },
+            ));
+        }
+    };
+
+    if let Some(response) = validate_api_req(tracking_label, &batch_object) {
+        return Ok(response);
+    }
+    let user_id = batch_object.user_id;
+    let conn = db_pool.get().await.unwrap();
+    if let Err(error_code) =
+        validate_user_token(tracking_label, config, &conn, headers, user_id)
+            .await
+    {
+        return Ok(get_batch_response(
+            400,
+            &ApiResUserDataBatch {
+                user_id,
+                results: Vec::new(),
+                msg: ("User data batch failed due to invalid token")
+                    .to_string(),
+                error_code: Some(error_code),
+            },
+        ));
+    }
+
+    let user_model = match get_user_by_id(tracking_label, user_id, &conn).await
+    {
+        Ok(user_model) => user_model,
+        Err(err_msg) => {
+            error!(
+                "{tracking_label} - \
+                failed to find user {user_id} for data batch \
+                with err='{err_msg}'"
+            );
+            return Ok(get_batch_response(
+                400,
+                &ApiResUserDataBatch {
+                    user_id,
+                    results: Vec::new(),
+                    msg: format!(
+                        "User data batch failed - \
+                        unable to find user with id: {user_id}"
+                    ),
+                    error_code: Some(ApiErrorCode::UserNotFound),
+                },
+            ));
+        }
+    };
+
+    let repo = UserDataRepo::new(&conn);
+    if let Err(e) = repo.begin_transaction(tracking_label).await {
+        error!("{e}");
+        return Ok(get_batch_response(
+            e.status_code(),
+            &ApiResUserDataBatch {
+                user_id,
+                results: Vec::new(),
+                msg: format!(
+                    "User data batch failed for user_id={user_id} \
+                    with err='{e}'"
+                ),
+                error_code: Some(e.error_code()),
+            },
+        ));
+    }
+    let mut results: Vec<ApiResUserDataBatchResult> = Vec::new();
+    let mut failure: Option<(usize, ApiError)> = None;
+    for (index, operation) in batch_object.operations.iter().enumerate() {
+        let result = match operation.op.as_str() {
+            "delete" => {
+                repo.delete(
+                    tracking_label,
+                    user_id,
+                    operation.data_id,
+                    operation.version,
+                )
+                .await
+            }
+            "move" => {
+                repo.update(
+                    tracking_label,
+                    user_id,
+                    &user_model.role,
+                    operation.data_id,
+                    &UserDataChanges {
+                        folder: operation.folder.clone(),
+                        expected_version: operation.version,
+                        ..Default::default()
+                    },
+                )
+                .await
+            }
+            _ => {
+                repo.update(
+                    tracking_label,
+                    user_id,
+                    &user_model.role,
+                    operation.data_id,
+                    &operation.get_update_request(user_id).get_changes(),
+                )
+                .await
+            }
+        };
+        match result {
+            Ok(data) => results.push(ApiResUserDataBatchResult {
+                op: operation.op.clone(),
+                data_id: operation.data_id,
+                status_code: 200,
+                data,
+                msg: "success".to_string(),
+                error_code: None,
+            }),
+            Err(e) => {
+                failure = Some((index, e));
+                break;
+            }
+        }
+    }
+
+    let failure = match failure {
+        Some(failure) => Some(failure),
+        None => repo
+            .commit_transaction(tracking_label)
+            .await
+            .err()
+            .map(|e| (batch_object.operations.len(), e)),
+    };
+    if let Some((failed_index, e)) = failure {
+        error!("{e}");
+        if let Err(rollback_err) =
+            repo.rollback_transaction(tracking_label).await
+        {
+            error!("{rollback_err}");
+        }
+        let status = e.status_code();
+        let results = batch_object
+            .operations
+            .iter()
+            .enumerate()
+            .map(|(index, operation)| ApiResUserDataBatchResult {
+                op: operation.op.clone(),
+                data_id: operation.data_id,
+                status_code: match index == failed_index {
+                    true => status,
+                    false => 424,
+                },
+                data: ModelUserData::default(),
+                msg: match index.cmp(&failed_index) {
+                    std::cmp::Ordering::Equal => format!("{e}"),
+                    std::cmp::Ordering::Less => {
+                        "rolled back - another operation failed".to_string()
+                    }
+                    std::cmp::Ordering::Greater => {
+                        "not run - another operation failed".to_string()
+                    }
+                },
+                error_code: match index == failed_index {
+                    true => Some(e.error_code()),
+                    false => Some(ApiErrorCode::RolledBack),
+                },
+            })
+            .collect();
+        return Ok(get_batch_response(
+            status,
+            &ApiResUserDataBatch {
+                user_id,
+                results,
+                msg: match failed_index < batch_object.operations.len() {
+                    true => format!(
+                        "User data batch rolled back - \
+                        operations[{failed_index}] failed"
+                    ),
+                    false => format!(
+                        "User data batch rolled back - \
+                        failed to commit for user_id={user_id}"
+                    ),
+                },
+                error_code: Some(e.error_code()),
+            },
+        ));
+    }
+
+    let num_ops = |op: &str| {
+        batch_object
+            .operations
+            .iter()
+            .filter(|operation| operation.op == op)
+            .count()
+    };
+    config
+        .events
+        .publish_user_event(
+            kafka_pool,
+            user_id,
+            "USER_BATCH_DATA",
+            &format!(
+                "updated={} moved={} deleted={}",
+                num_ops("update"),
+                num_ops("move"),
+                num_ops("delete")
+            ),
+        )
+        .await;
+
+    Ok(get_batch_response(
+        200,
+        &ApiResUserDataBatch {
+            user_id,
+            results,
+            msg: "success".to_string(),
+            error_code: None,
+        },
+    ))
+}
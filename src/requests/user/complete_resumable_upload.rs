@@ -0,0 +1,378 @@
+//! Module for completing a resumable upload
+//!
+//! ## Complete a resumable upload
+//!
+//! Combine the stored chunks into the s3 object once all
+//! ``total_bytes`` were received and move the ``users_data``
+//! record out of the ``uploading`` status.
+//!
+//! - URL path: ``/user/data/DATAID/complete``
+//! - Method: ``POST``
+//! - Handler: [`complete_resumable_upload`](crate::requests::user::complete_resumable_upload::complete_resumable_upload)
+//! - Request: none (uses the token header)
+//! - Response: [`ApiResUserResumableUpload`](crate::requests::user::resumable_upload::ApiResUserResumableUpload)
+//!
+use std::convert::Infallible;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Response;
+
+use crate::core::core_config::CoreConfig;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::monitoring::otel::trace_client_span;
+use crate::monitoring::otel::trace_db_query;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::models::user_data_upload::get_user_data_upload;
+use crate::requests::user::resumable_upload::get_object_store_error_response;
+use crate::requests::user::resumable_upload::get_resumable_upload_data_id;
+use crate::requests::user::resumable_upload::get_resumable_upload_response;
+use crate::requests::user::resumable_upload::get_resumable_upload_state_response;
+use crate::requests::user::resumable_upload::get_resumable_upload_user_id;
+use crate::requests::user::resumable_upload::ApiResUserResumableUpload;
+
+/// complete_resumable_upload
+///
+/// Handles completing a resumable upload after all of its
+/// chunks were stored. The ``users_data`` record becomes
+/// ``pending`` for the
+/// [`UserDataPipeline`](crate::processing::user_data_pipeline::UserDataPipeline)
+/// or ``ready`` if the pipeline is disabled, and an
+/// ``UPLOAD_USER_DATA`` user event is published.
+///
+/// # Usage
+///
+/// Uploads missing chunks are rejected with a `409` and the
+/// `received_bytes` to resume from. Uploads past their
+/// `upload_expires_at` are rejected with a `410`. Completing
+/// an upload counts against the user's
+/// ``USER_DATA_MAX_CONCURRENT_UPLOADS`` (`429` with a
+/// ``Retry-After`` header over the limit).
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
+///   db threadpool with required tls encryption
+/// * `kafka_pool` -
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+///   for asynchronously publishing messages to the connected kafka cluster
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+/// * `request_uri` - `&str` - url path with the data id
+///
+/// # Returns
+///
+/// ## complete_resumable_upload on Success Returns
+///
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResUserResumableUpload`](crate::requests::user::resumable_upload::ApiResUserResumableUpload)
+/// dictionary within the
+/// [`Body`](hyper::Body) and a
+/// `200` HTTP status code
+///
+/// Ok([`Response`](hyper::Response))
+///
+/// # Errors
+///
+/// ## complete_resumable_upload on Failure Returns
+///
+/// All errors return as a
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResUserResumableUpload`](crate::requests::user::resumable_upload::ApiResUserResumableUpload)
+/// dictionary with a
+/// `non-200` HTTP status code
+///
+/// Err([`Response`](hyper::Response))
+///
+pub async fn complete_resumable_upload(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    kafka_pool: &KafkaPublisher,
+    headers: &HeaderMap<HeaderValue>,
+    request_uri: &str,
+) -> std::result::Result<Response<Body>, Infallible> {
+    let data_id = match get_resumable_upload_data_id(request_uri, "complete") {
+        Some(data_id) => data_id,
+        None => {
+            return Ok(get_resumable_upload_response(
+                400,
+                &ApiResUserResumableUpload {
+                    user_id: -1,
+                    data_id: -1,
+                    msg: ("Invalid data id must be a positive integer")
+                        .to_string(),
+                    error_code: Some(ApiErrorCode::InvalidRequest),
+                    ..Default::default()
+                },
+            ));
+        }
+    };
+
+    let conn = db_pool.get().await.unwrap();
+    let user_id = match get_resumable_upload_user_id(
+        tracking_label,
+        config,
+        &conn,
+        headers,
+    )
+    .await
+    {
+        Ok(user_id) => user_id,
+        Err(error_code) => {
+            return Ok(get_resumable_upload_response(
+                400,
+                &ApiResUserResumableUpload {
+                    user_id: -1,
+                    data_id,
+                    msg: ("User complete resumable upload failed due to invalid token")
+                        .to_string(),
+                    error_code: Some(error_code),
+                    ..Default::default()
+                },
+            ));
+        }
+    };
+
+    let upload = match get_user_data_upload(
+        tracking_label,
+        user_id,
+        data_id,
+        &conn,
+    )
+    .await
+    {
+        Ok(Some(upload)) => upload,
+        Ok(None) => {
+            return Ok(get_resumable_upload_response(
+                    404,
+                    &ApiResUserResumableUpload {
+                        user_id,
+                        data_id,
+                        msg: format!(
+                            "User complete resumable upload failed - no resumable upload \
+                            for data_id={data_id}"
+                        ),
+                        error_code: Some(ApiErrorCode::NotFound),
+                        ..Default::default()
+                    },
+                ));
+        }
+        Err(e) => {
+            error!("{e}");
+            return Ok(get_resumable_upload_response(
+                    500,
+                    &ApiResUserResumableUpload {
+                        user_id,
+                        data_id,
+                        msg: format!(
+                            "User complete resumable upload failed for data_id={data_id}"
+                        ),
+                        error_code: Some(ApiErrorCode::InternalError),
+                        ..Default::default()
+                    },
+                ));
+        }
+    };
+    let received_bytes = upload.received_bytes;
+    let num_parts = upload.parts.len() as i64;
+    if upload.expired {
+        return Ok(get_resumable_upload_state_response(
+            410,
+            &upload,
+            received_bytes,
+            num_parts,
+            format!(
+                "User complete resumable upload failed - the upload \
+                expired at {}",
+                upload.expires_at
+            ),
+            Some(ApiErrorCode::NotFound),
+        ));
+    }
+    if received_bytes != upload.total_bytes {
+        return Ok(get_resumable_upload_state_response(
+            409,
+            &upload,
+            received_bytes,
+            num_parts,
+            format!(
+                "User complete resumable upload failed - received \
+                {received_bytes} of {} bytes",
+                upload.total_bytes
+            ),
+            Some(ApiErrorCode::Conflict),
+        ));
+    }
+
+    // hold one of the user's upload permits until the upload is recorded
+    let _upload_permit = match config.user_upload_limit.try_acquire(user_id) {
+        Ok(upload_permit) => upload_permit,
+        Err((status, err_msg)) => {
+            info!(
+                "{tracking_label} - rejecting complete upload \
+                with err='{err_msg}'"
+            );
+            let mut response = get_resumable_upload_state_response(
+                status,
+                &upload,
+                received_bytes,
+                num_parts,
+                err_msg,
+                Some(ApiErrorCode::from_status(status)),
+            );
+            response.headers_mut().insert(
+                "Retry-After",
+                config.user_upload_limit.retry_after_seconds.into(),
+            );
+            return Ok(response);
+        }
+    };
+
+    let parts: Vec<(i64, String)> = upload
+        .parts
+        .iter()
+        .map(|part| (part.part_number, part.etag.clone()))
+        .collect();
+    if let Err(e) = trace_client_span(
+        "s3 complete multipart upload",
+        vec![
+            ("s3.bucket", upload.bucket.clone()),
+            ("s3.key", upload.key.clone()),
+            ("s3.parts", format!("{num_parts}")),
+        ],
+        config.object_store.complete_multipart_upload(
+            tracking_label,
+            &upload.bucket,
+            &upload.key,
+            &upload.upload_id,
+            &parts,
+        ),
+    )
+    .await
+    {
+        error!("{e}");
+        return Ok(get_object_store_error_response(
+            config,
+            &e,
+            |status, error_code| {
+                get_resumable_upload_state_response(
+                    status,
+                    &upload,
+                    received_bytes,
+                    num_parts,
+                    "User complete resumable upload failed - unable to \
+                    combine the chunks"
+                        .to_string(),
+                    Some(error_code),
+                )
+            },
+        ));
+    }
+
+    // skip uploads completed or aborted by another request
+    let status = config.user_data_pipeline.get_upload_status();
+    let query = format!(
+        "UPDATE \
+            users_data \
+        SET \
+            status = '{status}', \
+            status_updated_at = timezone('UTC'::text, now()) \
+        WHERE \
+            users_data.id = {data_id} \
+            AND \
+            users_data.user_id = {user_id} \
+            AND \
+            users_data.status = 'uploading' \
+        RETURNING \
+            users_data.id;"
+    );
+    let stmt = conn.prepare(&query).await.unwrap();
+    match trace_db_query(&query, conn.query(&stmt, &[])).await {
+        Ok(query_result) if query_result.is_empty() => {
+            return Ok(get_resumable_upload_state_response(
+                409,
+                &upload,
+                received_bytes,
+                num_parts,
+                "User complete resumable upload failed - the upload is \
+                no longer in progress"
+                    .to_string(),
+                Some(ApiErrorCode::Conflict),
+            ));
+        }
+        Ok(_) => {}
+        Err(e) => {
+            error!(
+                "{tracking_label} - failed to complete data_id={data_id} \
+                with err='{e}'"
+            );
+            return Ok(get_resumable_upload_state_response(
+                500,
+                &upload,
+                received_bytes,
+                num_parts,
+                format!(
+                    "User complete resumable upload failed for \
+                    data_id={data_id}"
+                ),
+                Some(ApiErrorCode::InternalError),
+            ));
+        }
+    }
+    let query = format!(
+        "DELETE FROM \
+            users_data_uploads \
+        WHERE \
+            users_data_uploads.data_id = {data_id};"
+    );
+    let stmt = conn.prepare(&query).await.unwrap();
+    if let Err(e) = trace_db_query(&query, conn.query(&stmt, &[])).await {
+        error!(
+            "{tracking_label} - failed to delete the completed upload \
+            for data_id={data_id} with err='{e}'"
+        );
+    }
+
+    info!(
+        "{tracking_label} - completed resumable upload for \
+        user_id={user_id} data_id={data_id} parts={num_parts} \
+        status={status} {}",
+        upload.sloc
+    );
+    config
+        .events
+        .publish_user_event(
+            kafka_pool,
+            user_id,
+            "UPLOAD_USER_DATA",
+            &format!("data={data_id}"),
+        )
+        .await;
+    Ok(get_resumable_upload_response(
+        200,
+        &ApiResUserResumableUpload {
+            user_id,
+            data_id,
+            filename: upload.filename,
+            sloc: upload.sloc,
+            status: status.to_string(),
+            total_bytes: upload.total_bytes,
+            received_bytes,
+            next_part: num_parts + 1,
+            upload_expires_at: upload.expires_at,
+            msg: "success".to_string(),
+            error_code: None,
+        },
+    ))
+}
@@ -0,0 +1,394 @@
+//! Module for completing direct-to-s3 uploads from s3 event
+//! notifications
+//!
+//! ## Complete uploads from s3 events
+//!
+//! Mark ``uploading`` ``users_data`` records as uploaded when an s3 ``ObjectCreated:*`` event notification for their ``sloc`` arrives, so direct-to-s3 uploads stay in sync without the client reporting completion. An SQS poller or a lambda forwards the s3 event json and signs it with ``S3_EVENTS_SECRET`` (see [`S3EventCallback`](crate::requests::user::s3_event_callback::S3EventCallback)).
+//!
+//! - URL path: ``/user/data/s3/events``
+//! - Method: ``POST``
+//! - Handler: [`complete_s3_event_uploads`](crate::requests::user::complete_s3_event_uploads::complete_s3_event_uploads)
+//! - Request: [`ApiReqS3EventNotification`](crate::requests::user::complete_s3_event_uploads::ApiReqS3EventNotification) (uses the ``X-S3-Event-Timestamp`` and ``X-S3-Event-Signature`` headers)
+//! - Response: [`ApiResS3EventNotification`](crate::requests::user::complete_s3_event_uploads::ApiResS3EventNotification)
+//!
+use std::convert::Infallible;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Response;
+
+use lazy_static::lazy_static;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::core::core_config::CoreConfig;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::monitoring::metric_types::register_int_counter_vec;
+use crate::monitoring::metric_types::IntCounterVec;
+use crate::monitoring::otel::trace_db_query;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::user::s3_event_callback::decode_s3_event_key;
+
+lazy_static! {
+    pub static ref USER_DATA_S3_EVENTS_COUNTER_VEC: IntCounterVec =
+        register_int_counter_vec!(
+            "user_data_s3_events_total",
+            "Number of s3 event notification records completed or \
+            skipped and callbacks rejected.",
+            &["result"]
+        )
+        .unwrap();
+}
+
+/// ApiReqS3EventBucket
+///
+/// ``s3.bucket`` in an s3 event notification record
+///
+/// # Arguments
+///
+/// * `name` - `String` - bucket name
+///
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ApiReqS3EventBucket {
+    #[serde(default)]
+    pub name: String,
+}
+
+/// ApiReqS3EventObject
+///
+/// ``s3.object`` in an s3 event notification record
+///
+/// # Arguments
+///
+/// * `key` - `String` - url-encoded s3 key
+/// * `size` - `i64` - object size in bytes
+///
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ApiReqS3EventObject {
+    #[serde(default)]
+    pub key: String,
+    #[serde(default)]
+    pub size: i64,
+}
+
+/// ApiReqS3EventEntity
+///
+/// ``s3`` in an s3 event notification record
+///
+/// # Arguments
+///
+/// * `bucket` - [`ApiReqS3EventBucket`](crate::requests::user::complete_s3_event_uploads::ApiReqS3EventBucket)
+/// * `object` - [`ApiReqS3EventObject`](crate::requests::user::complete_s3_event_uploads::ApiReqS3EventObject)
+///
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ApiReqS3EventEntity {
+    #[serde(default)]
+    pub bucket: ApiReqS3EventBucket,
+    #[serde(default)]
+    pub object: ApiReqS3EventObject,
+}
+
+/// ApiReqS3EventRecord
+///
+/// One record in an s3 event notification
+///
+/// # Arguments
+///
+/// * `event_name` - `String` - ``eventName`` like
+///   ``ObjectCreated:Put``
+/// * `s3` - [`ApiReqS3EventEntity`](crate::requests::user::complete_s3_event_uploads::ApiReqS3EventEntity)
+///
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ApiReqS3EventRecord {
+    #[serde(rename = "eventName", default)]
+    pub event_name: String,
+    #[serde(default)]
+    pub s3: ApiReqS3EventEntity,
+}
+
+/// ApiReqS3EventNotification
+///
+/// # Request body for complete_s3_event_uploads
+///
+/// The s3 event notification json (only the fields used by
+/// the handler are listed)
+///
+/// # Arguments
+///
+/// * `records` - `Vec<`[`ApiReqS3EventRecord`](crate::requests::user::complete_s3_event_uploads::ApiReqS3EventRecord)`>` -
+///   ``Records`` (s3 test events have none)
+///
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ApiReqS3EventNotification {
+    #[serde(rename = "Records", default)]
+    pub records: Vec<ApiReqS3EventRecord>,
+}
+
+/// ApiResS3EventNotification
+///
+/// # Response type for complete_s3_event_uploads
+///
+/// # Usage
+///
+/// This type is the serialized output for the function:
+/// [`complete_s3_event_uploads`](crate::requests::user::complete_s3_event_uploads::complete_s3_event_uploads]
+/// and contained within the
+/// hyper [`Body`](hyper::Body)
+/// of the
+/// hyper [`Response`](hyper::Response)
+/// sent back to the client.
+///
+/// # Arguments
+///
+/// * `completed` - `Vec<i32>` - `users_data.id` records
+///   that left the ``uploading`` status
+/// * `skipped` - `usize` - records that were not
+///   ``ObjectCreated`` events or did not match an
+///   ``uploading`` record (already completed, unknown key)
+/// * `msg` - `String` - help message
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   machine-readable failure code (omitted on success)
+///
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ApiResS3EventNotification {
+    pub completed: Vec<i32>,
+    pub skipped: usize,
+    pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ApiErrorCode>,
+}
+
+/// get_s3_event_response
+///
+/// Build a json response for an s3 event callback
+///
+fn get_s3_event_response(
+    status: u16,
+    res: &ApiResS3EventNotification,
+) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::from(serde_json::to_string(res).unwrap()))
+        .unwrap()
+}
+
+/// get_s3_event_error_response
+///
+/// Build a json error response for a rejected s3 event
+/// callback
+///
+fn get_s3_event_error_response(
+    status: u16,
+    msg: String,
+    error_code: ApiErrorCode,
+) -> Response<Body> {
+    USER_DATA_S3_EVENTS_COUNTER_VEC
+        .with_label_values(&["rejected"])
+        .inc();
+    get_s3_event_response(
+        status,
+        &ApiResS3EventNotification {
+            msg,
+            error_code: Some(error_code),
+            ..Default::default()
+        },
+    )
+}
+
+/// complete_s3_event_uploads
+///
+/// Handler for s3 event notification callbacks. Each
+/// ``ObjectCreated:*`` record moves the ``uploading``
+/// ``users_data`` record with the same ``sloc`` to
+/// ``pending`` for the
+/// [`UserDataPipeline`](crate::processing::user_data_pipeline::UserDataPipeline)
+/// (or ``ready`` if the pipeline is disabled), stores the
+/// object's size, removes any leftover resumable upload
+/// state and publishes an ``UPLOAD_USER_DATA`` user event
+/// for the owner. Other records are skipped, so s3 retries
+/// and duplicate deliveries are safe.
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
+///   db threadpool with required tls encryption
+/// * `kafka_pool` -
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+///   for asynchronously publishing messages to the connected kafka cluster
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+/// * `bytes` - `&[u8]` - received bytes from the hyper
+///   [`Request`](hyper::Request)'s [`Body`](hyper::Body)
+///
+/// # Returns
+///
+/// ## complete_s3_event_uploads on Success Returns
+///
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResS3EventNotification`](crate::requests::user::complete_s3_event_uploads::ApiResS3EventNotification)
+/// dictionary within the
+/// [`Body`](hyper::Body) and a
+/// `200` HTTP status code
+///
+/// Ok([`Response`](hyper::Response))
+///
+/// # Errors
+///
+/// ## complete_s3_event_uploads on Failure Returns
+///
+/// All errors return as a
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResS3EventNotification`](crate::requests::user::complete_s3_event_uploads::ApiResS3EventNotification)
+/// dictionary with a
+/// `non-200` HTTP status code (`404` if ``S3_EVENTS_SECRET``
+/// is not set, `401` for a missing, old or invalid signature
+/// and `500` if a record could not be updated so the caller
+/// retries the event)
+///
+/// Err([`Response`](hyper::Response))
+///
+pub async fn complete_s3_event_uploads(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    kafka_pool: &KafkaPublisher,
+    headers: &HeaderMap<HeaderValue>,
+    bytes: &[u8],
+) -> std::result::Result<Response<Body>, Infallible> {
+    if !config.s3_event_callback.is_enabled() {
+        return Ok(get_s3_event_error_response(
+            404,
+            ("S3 event callbacks are disabled on this api server").to_string(),
+            ApiErrorCode::FeatureDisabled,
+        ));
+    }
+    if let Err(err_msg) = config.s3_event_callback.verify_signature(
+        headers,
+        bytes,
+        chrono::Utc::now().timestamp(),
+    ) {
+        warn!("{tracking_label} - rejected s3 event callback - {err_msg}");
+        return Ok(get_s3_event_error_response(
+            401,
+            format!("S3 event callback failed - {err_msg}"),
+            ApiErrorCode::InvalidToken,
+        ));
+    }
+    let notification: ApiReqS3EventNotification =
+        match serde_json::from_slice(bytes) {
+            Ok(notification) => notification,
+            Err(_) => {
+                return Ok(get_s3_event_error_response(
+                    400,
+                    ("S3 event callback failed - invalid s3 event json")
+                        .to_string(),
+                    ApiErrorCode::InvalidRequest,
+                ));
+            }
+        };
+
+    let status = config.user_data_pipeline.get_upload_status();
+    let query = format!(
+        "WITH completed AS (\
+            UPDATE \
+                users_data \
+            SET \
+                status = '{status}', \
+                size_in_bytes = CASE WHEN $2::BIGINT > 0 \
+                    THEN $2::BIGINT ELSE users_data.size_in_bytes END, \
+                status_updated_at = timezone('UTC'::text, now()) \
+            WHERE \
+                users_data.sloc = $1 \
+                AND \
+                users_data.status = 'uploading' \
+            RETURNING \
+                users_data.id, \
+                users_data.user_id\
+        ), removed AS (\
+            DELETE FROM \
+                users_data_uploads \
+            WHERE \
+                users_data_uploads.data_id IN (SELECT id FROM completed)\
+        ) \
+        SELECT id, user_id FROM completed;"
+    );
+    let conn = db_pool.get().await.unwrap();
+    let stmt = conn.prepare(&query).await.unwrap();
+    let mut res = ApiResS3EventNotification::default();
+    for record in notification.records.iter() {
+        let key = decode_s3_event_key(&record.s3.object.key);
+        if !record.event_name.starts_with("ObjectCreated:")
+            || record.s3.bucket.name.is_empty()
+            || key.is_empty()
+        {
+            res.skipped += 1;
+            continue;
+        }
+        let sloc = format!("s3://{}/{key}", record.s3.bucket.name);
+        let query_result = match trace_db_query(
+            &query,
+            conn.query(&stmt, &[&sloc, &record.s3.object.size]),
+        )
+        .await
+        {
+            Ok(query_result) => query_result,
+            Err(e) => {
+                error!(
+                    "{tracking_label} - failed to complete the upload \
+                    for {sloc} with err='{e}'"
+                );
+                return Ok(get_s3_event_error_response(
+                    500,
+                    format!(
+                        "S3 event callback failed for {sloc} after \
+                        completing data_ids={:?}",
+                        res.completed
+                    ),
+                    ApiErrorCode::InternalError,
+                ));
+            }
+        };
+        if query_result.is_empty() {
+            res.skipped += 1;
+            continue;
+        }
+        for row in query_result.iter() {
+            let data_id: i32 = row.try_get("id").unwrap();
+            let user_id: i32 = row.try_get("user_id").unwrap();
+            info!(
+                "{tracking_label} - completed s3 event upload for \
+                user_id={user_id} data_id={data_id} \
+                status={status} {sloc}"
+            );
+            config
+                .events
+                .publish_user_event(
+                    kafka_pool,
+                    user_id,
+                    "UPLOAD_USER_DATA",
+                    &format!("data={data_id}"),
+                )
+                .await;
+            res.completed.push(data_id);
+        }
+    }
+    USER_DATA_S3_EVENTS_COUNTER_VEC
+        .with_label_values(&["completed"])
+        .inc_by(res.completed.len() as u64);
+    USER_DATA_S3_EVENTS_COUNTER_VEC
+        .with_label_values(&["skipped"])
+        .inc_by(res.skipped as u64);
+    res.msg = "success".to_string();
+    Ok(get_s3_event_response(200, &res))
+}
@@ -0,0 +1,357 @@
+//! Module for confirming a user's self-service account deletion
+//!
+//! ## Confirm Self-Service Account Deletion
+//!
+//! Consume the confirmation token from ``/user/delete/request`` to soft-delete the user (``users.state = 1``), revoke all of the user's tokens and schedule the user's uploads for deletion after ``USER_DELETE_PURGE_DAYS`` (see [`UserDeleteConfig`](crate::requests::user::user_delete_config::UserDeleteConfig)). Unlike ``DELETE /user``, the user must prove they can read the account's email first.
+//!
+//! - URL path: ``/user/delete/confirm``
+//! - Method: ``POST``
+//! - Handler: [`confirm_user_delete`](crate::requests::user::confirm_user_delete::confirm_user_delete)
+//! - Request: [`ApiReqUserDeleteConfirm`](crate::requests::user::confirm_user_delete::ApiReqUserDeleteConfirm)
+//! - Response: [`ApiResUserDeleteConfirm`](crate::requests::user::confirm_user_delete::ApiResUserDeleteConfirm)
+//!
+
+use std::convert::Infallible;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Response;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::core::core_config::CoreConfig;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::monitoring::otel::trace_db_query;
+use crate::requests::auth::validate_user_token::validate_user_token;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::validation::field_rules::check_id;
+use crate::requests::validation::field_rules::check_length;
+use crate::requests::validation::validate_api_req::validate_api_req;
+use crate::requests::validation::validate_api_req::ApiFieldError;
+use crate::requests::validation::validate_api_req::ApiReqValidate;
+use crate::utils::hash_token::hash_token;
+
+/// ApiReqUserDeleteConfirm
+///
+/// # Request Type For confirm_user_delete
+///
+/// Confirm deleting the user's own account with the
+/// emailed one-time-use token
+///
+/// This type is the deserialized input for:
+/// [`confirm_user_delete`](crate::requests::user::confirm_user_delete::confirm_user_delete]
+///
+/// # Arguments
+///
+/// * `user_id` - `i32` - user id
+/// * `token` - `String` - emailed confirmation token
+///
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApiReqUserDeleteConfirm {
+    // users.id
+    pub user_id: i32,
+    // users_delete_requests.token
+    pub token: String,
+}
+
+impl ApiReqValidate for ApiReqUserDeleteConfirm {
+    /// validate
+    ///
+    /// Require a positive `user_id` and a `token` between
+    /// 4 and 256 characters
+    ///
+    fn validate(&self) -> Vec<ApiFieldError> {
+        let mut errors = Vec::new();
+        check_id(&mut errors, "user_id", self.user_id);
+        check_length(&mut errors, "token", &self.token, 4, 256);
+        errors
+    }
+}
+
+/// ApiResUserDeleteConfirm
+///
+/// # Response type for confirm_user_delete
+///
+/// Notify the client that the account was deleted
+///
+/// # Arguments
+///
+/// * `user_id` - `i32` - user id
+/// * `revoked_tokens` - `i64` - number of revoked
+///   ``users_tokens`` records
+/// * `purge_files` - `i64` - number of ``users_data``
+///   uploads scheduled for deletion
+/// * `purge_date` - `String` - UTC-formatted date time string
+///   when the uploads are deleted
+/// * `msg` - `String` - help message
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   machine-readable failure code (omitted on success)
+///
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApiResUserDeleteConfirm {
+    pub user_id: i32,
+    pub revoked_tokens: i64,
+    pub purge_files: i64,
+    pub purge_date: String,
+    pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ApiErrorCode>,
+}
+
+/// confirm_user_delete
+///
+/// Consume a user's account deletion confirmation token.
+/// The token is consumed, the user is soft-deleted, the
+/// user's tokens are revoked and the user's uploads get a
+/// ``users_data.expires_at`` purge date in a single sql
+/// statement, so a token can only be used once.
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
+///   db threadpool with required tls encryption
+/// * `kafka_pool` -
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+///   for asynchronously publishing messages to the connected kafka cluster
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+/// * `bytes` - `&[u8]` - received bytes from the hyper
+///   [`Request`](hyper::Request)'s [`Body`](hyper::Body)
+///
+/// # Returns
+///
+/// ## Success
+///
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResUserDeleteConfirm`](crate::requests::user::confirm_user_delete::ApiResUserDeleteConfirm)
+/// dictionary within the
+/// [`Body`](hyper::Body) and a
+/// `200` HTTP status code
+///
+/// Ok([`Response`](hyper::Response))
+///
+/// # Errors
+///
+/// `400` for an invalid request or token, and when the
+/// confirmation token is unknown, expired or already used
+///
+/// Err([`Response`](hyper::Response))
+///
+pub async fn confirm_user_delete(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    kafka_pool: &KafkaPublisher,
+    headers: &HeaderMap<HeaderValue>,
+    bytes: &[u8],
+) -> std::result::Result<Response<Body>, Infallible> {
+    let req_object: ApiReqUserDeleteConfirm =
+        match serde_json::from_slice(bytes) {
+            Ok(uo) => uo,
+            Err(_) => {
+                return Ok(get_delete_confirm_response(
+                    400,
+                    -1,
+                    "User delete confirm failed - please ensure \
+                    user_id and token were set correctly in the request",
+                    ApiErrorCode::InvalidRequest,
+                ));
+            }
+        };
+
+    if let Some(response) = validate_api_req(tracking_label, &req_object) {
+        return Ok(response);
+    }
+
+    let user_id = req_object.user_id;
+    let conn = db_pool.get().await.unwrap();
+    if let Err(error_code) =
+        validate_user_token(tracking_label, config, &conn, headers, user_id)
+            .await
+    {
+        return Ok(get_delete_confirm_response(
+            400,
+            user_id,
+            "User delete confirm failed due to invalid token",
+            error_code,
+        ));
+    }
+
+    // only the token hash is stored in the db
+    let token_hash =
+        hash_token(&req_object.token, &config.server_password_salt);
+    let now = chrono::Utc::now();
+    let purge_date =
+        now + chrono::Duration::days(config.user_delete.purge_days);
+
+    // consume the request, soft-delete the user, revoke the
+    // user's tokens and schedule the uploads for the lifecycle
+    // task in 1 statement. the conditional UPDATE row-locks the
+    // request so concurrent confirmations match 0 rows after
+    // the first one sets state = 1
+    let query = format!(
+        "WITH confirmed AS (\
+            UPDATE \
+                users_delete_requests \
+            SET \
+                state = 1, \
+                confirmed_at = '{now}', \
+                purge_date = '{purge_date}' \
+            WHERE \
+                users_delete_requests.user_id = {user_id} \
+                AND \
+                users_delete_requests.state = 0 \
+                AND \
+                users_delete_requests.token = '{}' \
+                AND \
+                users_delete_requests.exp_date > '{now}' \
+            RETURNING \
+                users_delete_requests.user_id), \
+        deleted_user AS (\
+            UPDATE \
+                users \
+            SET \
+                state = 1 \
+            FROM \
+                confirmed \
+            WHERE \
+                users.id = confirmed.user_id \
+            RETURNING \
+                users.id), \
+        revoked_tokens AS (\
+            UPDATE \
+                users_tokens \
+            SET \
+                state = 1, \
+                updated_at = '{now}' \
+            FROM \
+                confirmed \
+            WHERE \
+                users_tokens.user_id = confirmed.user_id \
+                AND \
+                users_tokens.state = 0 \
+            RETURNING \
+                users_tokens.id), \
+        purge_files AS (\
+            UPDATE \
+                users_data \
+            SET \
+                expires_at = '{purge_date}', \
+                expire_storage_class = NULL \
+            FROM \
+                confirmed \
+            WHERE \
+                users_data.user_id = confirmed.user_id \
+            RETURNING \
+                users_data.id) \
+        SELECT \
+            deleted_user.id, \
+            (SELECT COUNT(*) FROM revoked_tokens) AS revoked_tokens, \
+            (SELECT COUNT(*) FROM purge_files) AS purge_files \
+        FROM \
+            deleted_user;",
+        token_hash.replace('\'', "''")
+    );
+    let stmt = conn.prepare(&query).await.unwrap();
+    let query_result =
+        match trace_db_query(&query, conn.query(&stmt, &[])).await {
+            Ok(query_result) => query_result,
+            Err(e) => {
+                return Ok(get_delete_confirm_response(
+                    400,
+                    user_id,
+                    &format!(
+                        "User delete confirm failed for user_id={user_id} \
+                        with err='{e}'"
+                    ),
+                    ApiErrorCode::InternalError,
+                ));
+            }
+        };
+    config.user_cache.invalidate_user(user_id).await;
+
+    // must match up with RETURNING
+    let row = match query_result.first() {
+        Some(row) => row,
+        None => {
+            config.auth_alerts.record_failure(
+                tracking_label,
+                "otp",
+                "delete_not_found",
+            );
+            return Ok(get_delete_confirm_response(
+                400,
+                user_id,
+                "User delete confirm failed - the confirmation token \
+                does not match, expired or was already used",
+                ApiErrorCode::VerifyInvalid,
+            ));
+        }
+    };
+    let revoked_tokens: i64 = row.try_get("revoked_tokens").unwrap();
+    let purge_files: i64 = row.try_get("purge_files").unwrap();
+
+    info!(
+        "{tracking_label} - \
+        user {user_id} confirmed deleting their account - \
+        revoked_tokens={revoked_tokens} purge_files={purge_files} \
+        purge_date={purge_date}"
+    );
+    config.events.user_deleted(kafka_pool, user_id).await;
+
+    let response = Response::builder()
+        .status(200)
+        .body(Body::from(
+            serde_json::to_string(&ApiResUserDeleteConfirm {
+                user_id,
+                revoked_tokens,
+                purge_files,
+                purge_date: format!(
+                    "{}",
+                    purge_date.format("%Y-%m-%dT%H:%M:%SZ")
+                ),
+                msg: "success".to_string(),
+                error_code: None,
+            })
+            .unwrap(),
+        ))
+        .unwrap();
+    Ok(response)
+}
+
+/// get_delete_confirm_response
+///
+/// Build an error response for
+/// [`confirm_user_delete`](crate::requests::user::confirm_user_delete::confirm_user_delete)
+///
+fn get_delete_confirm_response(
+    status: u16,
+    user_id: i32,
+    msg: &str,
+    error_code: ApiErrorCode,
+) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::from(
+            serde_json::to_string(&ApiResUserDeleteConfirm {
+                user_id,
+                revoked_tokens: 0,
+                purge_files: 0,
+                purge_date: "".to_string(),
+                msg: msg.to_string(),
+                error_code: Some(error_code),
+            })
+            .unwrap(),
+        ))
+        .unwrap()
+}
@@ -4,6 +4,8 @@
 //!
 //! Consume a one-time-use password and change the user's ``users.password`` value to the new argon2-salted password
 //!
+//! Logged-in users send their Bearer token with the one-time-use password from ``/user/password/reset``. Users that cannot log in send only the one-time-use password emailed by ``/user/password/forgot``. Each token can only be used once, and only the first of several concurrent requests with the same token changes the password (existing dbs need the ``0021_users_tokens_consumed.sql`` migration). A changed password also clears a password reset forced by an admin (see [`force_password_reset`](crate::requests::admin::force_password_reset::force_password_reset)) and sends a security notification to the user (see [`security_notifications`](crate::notifications::security_notifications)).
+//!
 //! - URL path: ``/user/password/change``
 //! - Method: ``POST``
 //! - Handler: [`consume_user_otp`](crate::requests::user::consume_user_otp::consume_user_otp)
@@ -16,6 +18,7 @@ use std::convert::Infallible;
 use postgres_native_tls::MakeTlsConnector;
 
 use bb8::Pool;
+use bb8::PooledConnection;
 use bb8_postgres::PostgresConnectionManager;
 
 use hyper::header::HeaderValue;
@@ -26,16 +29,29 @@ use hyper::Response;
 use serde::Deserialize;
 use serde::Serialize;
 
-use argon2::hash_encoded as argon_hash_encoded;
-use argon2::Config as argon_config;
-
-use kafka_threadpool::kafka_publisher::KafkaPublisher;
-
 use crate::core::core_config::CoreConfig;
-use crate::kafka::publish_msg::publish_msg;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::monitoring::otel::trace_db_query;
+use crate::notifications::security_notifications::send_security_notification;
 use crate::requests::auth::validate_user_token::validate_user_token;
+use crate::requests::models::api_error::ApiErrorCode;
 use crate::requests::models::user::get_user_by_id;
 use crate::requests::models::user_otp::get_user_otp;
+use crate::requests::validation::field_rules::check_email;
+use crate::requests::validation::field_rules::check_id;
+use crate::requests::validation::field_rules::check_length;
+use crate::requests::validation::field_rules::check_password;
+use crate::requests::validation::normalize_email::normalize_email;
+use crate::requests::validation::validate_api_req::validate_api_req;
+use crate::requests::validation::validate_api_req::ApiFieldError;
+use crate::requests::validation::validate_api_req::ApiReqValidate;
+use crate::requests::validation::validate_password_policy::validate_password_policy;
+use crate::utils::hash_token::hash_token;
+use crate::utils::password_migration::get_password_scheme;
+
+/// message for a locked one-time-password
+pub const OTP_LOCKED_MSG: &str = "User one-time-password was locked after too \
+    many failed attempts - please create a new one-time-password";
 
 /// ApiReqUserConsumeOtp
 ///
@@ -75,6 +91,23 @@ pub struct ApiReqUserConsumeOtp {
     pub password: String,
 }
 
+impl ApiReqValidate for ApiReqUserConsumeOtp {
+    /// validate
+    ///
+    /// Require a positive `user_id`, a valid `email`,
+    /// a `token` between 4 and 256 characters and a
+    /// new `password` between 4 and 1024 characters
+    ///
+    fn validate(&self) -> Vec<ApiFieldError> {
+        let mut errors = Vec::new();
+        check_id(&mut errors, "user_id", self.user_id);
+        check_email(&mut errors, "email", &self.email);
+        check_length(&mut errors, "token", &self.token, 4, 256);
+        check_password(&mut errors, "password", &self.password);
+        errors
+    }
+}
+
 /// ApiResUserConsumeOtp
 ///
 /// # Response type for consumer_user_otp
@@ -98,6 +131,8 @@ pub struct ApiReqUserConsumeOtp {
 /// * `user_id` - `i32` - user id
 /// * `otp_id` - `i32` - users_otp primary db key id
 /// * `msg` - `String` - help message
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   machine-readable failure code (omitted on success)
 ///
 #[derive(Serialize, Deserialize, Clone)]
 pub struct ApiResUserConsumeOtp {
@@ -106,6 +141,8 @@ pub struct ApiResUserConsumeOtp {
     // users_otp.id
     pub otp_id: i32,
     pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ApiErrorCode>,
 }
 
 /// consume_user_otp
@@ -120,6 +157,21 @@ pub struct ApiResUserConsumeOtp {
 /// New password is salted using `argon2`
 ///
 /// OTP tokens can only be used 1 time by a user.
+/// Presenting a wrong token counts a failed attempt on the
+/// user's active OTP, and the OTP is locked
+/// (``users_otp.state = 3``) after ``USER_OTP_MAX_ATTEMPTS``
+/// failed attempts. The user's OTP records are found by
+/// `user_id` and `email` and the token hashes are compared
+/// in constant time (the token is not used in the sql).
+/// The jwt in the ``TOKEN_HEADER`` header is optional
+/// (for ``/user/password/forgot``). When it is set it must
+/// be valid for the `user_id`, otherwise the user must
+/// be active and in the request's tenant.
+/// The otp is consumed and the password is changed in
+/// a single statement that also inserts the token hash into
+/// the ``users_tokens_consumed`` table, so concurrent
+/// requests with the same token cannot both succeed and a
+/// consumed token can never be used again.
 ///
 /// # Arguments
 ///
@@ -128,7 +180,7 @@ pub struct ApiResUserConsumeOtp {
 /// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
 ///   db threadpool with required tls encryption
 /// * `kafka_pool` -
-///   [`KafkaPublisher`](kafka_threadpool::kafka_publisher::KafkaPublisher)
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
 ///   for asynchronously publishing messages to the connected kafka cluster
 /// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
 ///   hashmap containing headers in key-value pairs
@@ -173,110 +225,59 @@ pub async fn consume_user_otp(
     headers: &HeaderMap<HeaderValue>,
     bytes: &[u8],
 ) -> std::result::Result<Response<Body>, Infallible> {
-    let req_object: ApiReqUserConsumeOtp = match serde_json::from_slice(bytes) {
-        Ok(uo) => uo,
-        Err(_) => {
-            let response = Response::builder()
-                .status(400)
-                .body(Body::from(
-                    serde_json::to_string(&ApiResUserConsumeOtp {
-                        user_id: -1,
-                        otp_id: -1,
-                        msg: ("User consume one-time-password failed - \
+    let mut req_object: ApiReqUserConsumeOtp =
+        match serde_json::from_slice(bytes) {
+            Ok(uo) => uo,
+            Err(_) => {
+                let response = Response::builder()
+                    .status(400)
+                    .body(Body::from(
+                        serde_json::to_string(&ApiResUserConsumeOtp {
+                            user_id: -1,
+                            otp_id: -1,
+                            msg: ("User consume one-time-password failed - \
                             please ensure \
                             user_id, email, token, and password \
                             were set correctly in the request")
-                            .to_string(),
-                    })
-                    .unwrap(),
-                ))
-                .unwrap();
-            return Ok(response);
-        }
-    };
+                                .to_string(),
+                            error_code: Some(ApiErrorCode::InvalidRequest),
+                        })
+                        .unwrap(),
+                    ))
+                    .unwrap();
+                return Ok(response);
+            }
+        };
 
-    // is this a waste of time because nothing changed
-    if req_object.user_id < 0 {
-        let response = Response::builder()
-            .status(400)
-            .body(Body::from(
-                serde_json::to_string(&ApiResUserConsumeOtp {
-                    user_id: req_object.user_id,
-                    otp_id: -1,
-                    msg: ("User consume one-time-password failed \
-                        please ensure the \
-                        user_id is a non-negative number")
-                        .to_string(),
-                })
-                .unwrap(),
-            ))
-            .unwrap();
-        return Ok(response);
-    } else if req_object.email.is_empty() {
-        let response = Response::builder()
-            .status(400)
-            .body(Body::from(
-                serde_json::to_string(&ApiResUserConsumeOtp {
-                    user_id: req_object.user_id,
-                    otp_id: -1,
-                    msg: ("User consume one-time-password failed \
-                        please ensure the \
-                        email is set to the user's email address")
-                        .to_string(),
-                })
-                .unwrap(),
-            ))
-            .unwrap();
-        return Ok(response);
-    } else if req_object.password.is_empty() {
-        let response = Response::builder()
-            .status(400)
-            .body(Body::from(
-                serde_json::to_string(&ApiResUserConsumeOtp {
-                    user_id: req_object.user_id,
-                    otp_id: -1,
-                    msg: ("User consume one-time-password failed \
-                        please ensure the \
-                        passsword is set")
-                        .to_string(),
-                })
-                .unwrap(),
-            ))
-            .unwrap();
-        return Ok(response);
-    } else if req_object.password.len() < 4 {
-        let response = Response::builder()
-            .status(400)
-            .body(Body::from(
-                serde_json::to_string(&ApiResUserConsumeOtp {
-                    user_id: req_object.user_id,
-                    otp_id: -1,
-                    msg: ("User consume one-time-password failed \
-                        please ensure the \
-                        passsword is longer than 4 characters")
-                        .to_string(),
-                })
-                .unwrap(),
-            ))
-            .unwrap();
+    req_object.email = normalize_email(&req_object.email);
+    if let Some(response) = validate_api_req(tracking_label, &req_object) {
         return Ok(response);
-    } else if req_object.token.len() < 4 {
-        let response = Response::builder()
-            .status(400)
-            .body(Body::from(
-                serde_json::to_string(&ApiResUserConsumeOtp {
-                    user_id: req_object.user_id,
-                    otp_id: -1,
-                    msg: ("User consume one-time-password failed \
-                        please ensure the \
-                        token is longer than 4 characters")
-                        .to_string(),
-                })
-                .unwrap(),
-            ))
-            .unwrap();
+    }
+    if let Some(response) = validate_password_policy(
+        tracking_label,
+        &config.get_settings().password_policy,
+        "password",
+        &req_object.password,
+    ) {
         return Ok(response);
-    } else if req_object.token.len() > 256 {
+    }
+
+    let conn = db_pool.get().await.unwrap();
+
+    let user_clone = req_object.clone();
+    let user_id = user_clone.user_id;
+    let user_email = user_clone.email;
+    // users from /user/password/forgot cannot log in so
+    // the one-time-password is the only credential
+    let has_token = !config.token_cookie.get_request_token(headers).is_empty();
+    let valid_token = match has_token {
+        true => {
+            validate_user_token(tracking_label, config, &conn, headers, user_id)
+                .await
+        }
+        false => Ok("".to_string()),
+    };
+    if let Err(error_code) = valid_token {
         let response = Response::builder()
             .status(400)
             .body(Body::from(
@@ -284,9 +285,9 @@ pub async fn consume_user_otp(
                     user_id: req_object.user_id,
                     otp_id: -1,
                     msg: ("User consume one-time-password failed \
-                        please ensure the \
-                        token is shorter than 256 characters")
+                            due to invalid token")
                         .to_string(),
+                    error_code: Some(error_code),
                 })
                 .unwrap(),
             ))
@@ -294,31 +295,32 @@ pub async fn consume_user_otp(
         return Ok(response);
     }
 
-    let conn = db_pool.get().await.unwrap();
-
-    let user_clone = req_object.clone();
-    let user_id = user_clone.user_id;
-    let user_email = user_clone.email;
-    let _token = match validate_user_token(
-        tracking_label,
-        config,
-        &conn,
-        headers,
-        user_id,
-    )
-    .await
+    // get the user and detect if the email is different
+    let user_model = match get_user_by_id(tracking_label, user_id, &conn).await
     {
-        Ok(_token) => _token,
-        Err(_) => {
+        Ok(user_model) => user_model,
+        Err(err_msg) => {
+            error!(
+                "{tracking_label} - \
+                failed to consume one-time-password user {user_id} \
+                with err='{err_msg}'"
+            );
+            config.auth_alerts.record_failure(
+                tracking_label,
+                "otp",
+                "unknown_user",
+            );
             let response = Response::builder()
                 .status(400)
                 .body(Body::from(
                     serde_json::to_string(&ApiResUserConsumeOtp {
                         user_id: req_object.user_id,
                         otp_id: -1,
-                        msg: ("User consume one-time-password failed \
-                            due to invalid token")
-                            .to_string(),
+                        msg: format!(
+                            "User consume one-time-password failed - \
+                            unable to find user with id: {user_id}"
+                        ),
+                        error_code: Some(ApiErrorCode::UserNotFound),
                     })
                     .unwrap(),
                 ))
@@ -327,15 +329,22 @@ pub async fn consume_user_otp(
         }
     };
 
-    // get the user and detect if the email is different
-    let user_model = match get_user_by_id(tracking_label, user_id, &conn).await
-    {
-        Ok(user_model) => user_model,
-        Err(err_msg) => {
-            error!(
-                "{tracking_label} - \
-                failed to consume one-time-password user {user_id} \
-                with err='{err_msg}'"
+    // without a jwt only active users in the request's
+    // tenant can change their password
+    if !has_token {
+        let tenant_id = match config.tenants.is_enabled() {
+            true => config
+                .tenants
+                .get_tenant_id(tracking_label, headers, &conn)
+                .await
+                .ok(),
+            false => Some(user_model.tenant_id),
+        };
+        if user_model.state != 0 || tenant_id != Some(user_model.tenant_id) {
+            config.auth_alerts.record_failure(
+                tracking_label,
+                "otp",
+                "inactive_user",
             );
             let response = Response::builder()
                 .status(400)
@@ -347,15 +356,21 @@ pub async fn consume_user_otp(
                             "User consume one-time-password failed - \
                             unable to find user with id: {user_id}"
                         ),
+                        error_code: Some(ApiErrorCode::UserNotFound),
                     })
                     .unwrap(),
                 ))
                 .unwrap();
             return Ok(response);
         }
-    };
+    }
 
     if user_model.email != req_object.email && user_email != user_model.email {
+        config.auth_alerts.record_failure(
+            tracking_label,
+            "otp",
+            "email_mismatch",
+        );
         let response = Response::builder()
             .status(400)
             .body(Body::from(
@@ -367,6 +382,7 @@ pub async fn consume_user_otp(
                         user_email does not match {}",
                         req_object.email
                     ),
+                    error_code: Some(ApiErrorCode::InvalidRequest),
                 })
                 .unwrap(),
             ))
@@ -374,26 +390,49 @@ pub async fn consume_user_otp(
         return Ok(response);
     }
 
+    // only the token hash is stored in the db
+    let token_hash =
+        hash_token(&req_object.token, &config.server_password_salt);
+
     // get the user one-time-password record
     let user_otp_model = match get_user_otp(
         tracking_label,
         user_id,
         &req_object.email,
-        &req_object.token,
+        &token_hash,
         &conn,
     )
     .await
     {
         Ok(rec) => rec,
         Err(_) => {
+            config.auth_alerts.record_failure(
+                tracking_label,
+                "otp",
+                "not_found",
+            );
+            let (msg, error_code) = match record_failed_otp_attempt(
+                tracking_label,
+                config,
+                &conn,
+                user_id,
+            )
+            .await
+            {
+                true => (OTP_LOCKED_MSG, ApiErrorCode::LoginLocked),
+                false => (
+                    "User one-time-password record does not exist",
+                    ApiErrorCode::OtpInvalid,
+                ),
+            };
             let response = Response::builder()
                 .status(400)
                 .body(Body::from(
                     serde_json::to_string(&ApiResUserConsumeOtp {
                         user_id: req_object.user_id,
                         otp_id: -1,
-                        msg: ("User one-time-password record does not exist")
-                            .to_string(),
+                        msg: msg.to_string(),
+                        error_code: Some(error_code),
                     })
                     .unwrap(),
                 ))
@@ -402,18 +441,41 @@ pub async fn consume_user_otp(
         }
     };
 
-    if req_object.token != user_otp_model.token {
+    // state 3 = locked after too many failed attempts
+    if user_otp_model.state == 3 {
+        config
+            .auth_alerts
+            .record_failure(tracking_label, "otp", "locked");
         let response = Response::builder()
             .status(400)
             .body(Body::from(
                 serde_json::to_string(&ApiResUserConsumeOtp {
                     user_id: req_object.user_id,
                     otp_id: -1,
-                    msg: format!(
-                        "User one-time-password token={} does not match \
-                        db otp_token={}",
-                        req_object.token, user_otp_model.token
-                    ),
+                    msg: OTP_LOCKED_MSG.to_string(),
+                    error_code: Some(ApiErrorCode::LoginLocked),
+                })
+                .unwrap(),
+            ))
+            .unwrap();
+        return Ok(response);
+    }
+
+    // state 2 = invalidated when the user created a newer otp
+    if user_otp_model.state == 2 {
+        config
+            .auth_alerts
+            .record_failure(tracking_label, "otp", "replaced");
+        let response = Response::builder()
+            .status(400)
+            .body(Body::from(
+                serde_json::to_string(&ApiResUserConsumeOtp {
+                    user_id: req_object.user_id,
+                    otp_id: -1,
+                    msg: ("User one-time-password was replaced by \
+                        a newer one-time-password")
+                        .to_string(),
+                    error_code: Some(ApiErrorCode::OtpInvalid),
                 })
                 .unwrap(),
             ))
@@ -431,13 +493,15 @@ pub async fn consume_user_otp(
     if exp_date_vs_now > 0 {
         let err_msg = format!(
             "{tracking_label} - user {user_id} \
-            one-time-password token {} \
-            expired on: \
+            one-time-password token expired on: \
             exp_date={} \
             duration_since={exp_date_vs_now}s",
-            req_object.token, user_otp_model.exp_date_utc
+            user_otp_model.exp_date_utc
         );
         error!("{err_msg}");
+        config
+            .auth_alerts
+            .record_failure(tracking_label, "otp", "expired");
         let response = Response::builder()
             .status(400)
             .body(Body::from(
@@ -445,6 +509,7 @@ pub async fn consume_user_otp(
                     user_id: req_object.user_id,
                     otp_id: -1,
                     msg: ("User one-time-password has expired").to_string(),
+                    error_code: Some(ApiErrorCode::OtpExpired),
                 })
                 .unwrap(),
             ))
@@ -457,74 +522,71 @@ pub async fn consume_user_otp(
         consuming user {user_id} otp"
     );
 
+    // salt the user's password
+    let new_password = config
+        .password_hashing
+        .hash_password(&req_object.password, &config.server_password_salt)
+        .unwrap();
+    let new_password_scheme = get_password_scheme(&new_password);
+
+    let token_hash_sql = token_hash.replace('\'', "''");
+    // consume the otp and change the password in 1 statement.
+    // the users_tokens_consumed row is unique for each token
+    // hash so concurrent requests for the same token wait for
+    // the first request and then insert nothing, and the
+    // conditional UPDATE only matches the verified otp's id
+    // while it is still unconsumed
     let cur_query = format!(
-        "UPDATE \
-            users_otp \
+        "WITH consumed_token AS (\
+            INSERT INTO \
+                users_tokens_consumed (kind, token, user_id) \
+            VALUES \
+                ('otp', '{token_hash_sql}', {user_id}) \
+            ON CONFLICT (kind, token) DO NOTHING \
+            RETURNING \
+                users_tokens_consumed.id), \
+        consumed_otp AS (\
+            UPDATE \
+                users_otp \
+            SET \
+                state = 1, \
+                consumed_date = '{now}' \
+            WHERE \
+                users_otp.id = {} \
+                AND \
+                users_otp.user_id = {user_id} \
+                AND \
+                users_otp.state = 0 \
+                AND \
+                users_otp.consumed_date IS NULL \
+                AND \
+                EXISTS (SELECT 1 FROM consumed_token) \
+                AND \
+                users_otp.email = '{}' \
+                AND \
+                users_otp.exp_date > '{now}' \
+            RETURNING \
+                users_otp.id, \
+                users_otp.user_id) \
+        UPDATE \
+            users \
         SET \
-            state = 1, \
-            consumed_date = '{now}' \
+            password = '{new_password}', \
+            password_scheme = '{new_password_scheme}', \
+            password_reset_required = 0 \
+        FROM \
+            consumed_otp \
         WHERE \
-            user_id = {user_id} \
-            AND \
-            state = 0 \
-            AND \
-            token = '{}' \
-            AND \
-            email = '{user_email}' \
+            users.id = consumed_otp.user_id \
         RETURNING \
-            users_otp.id, \
-            users_otp.user_id, \
-            users_otp.token, \
-            users_otp.email, \
-            users_otp.state, \
-            users_otp.exp_date;",
-        req_object.token
+            consumed_otp.id;",
+        user_otp_model.id,
+        user_email.replace('\'', "''")
     );
 
     let stmt = conn.prepare(&cur_query).await.unwrap();
-    let query_result = match conn.query(&stmt, &[]).await {
-        Ok(query_result) => query_result,
-        Err(e) => {
-            let response = Response::builder()
-                .status(400)
-                .body(Body::from(
-                    serde_json::to_string(&ApiResUserConsumeOtp {
-                        user_id: req_object.user_id,
-                        otp_id: -1,
-                        msg: format!(
-                            "User consume one-time-password failed \
-                            for user_id={user_id} {user_email} \
-                            with err='{e}'"
-                        ),
-                    })
-                    .unwrap(),
-                ))
-                .unwrap();
-            return Ok(response);
-        }
-    };
-
-    // must match up with RETURNING
-    if let Some(row) = query_result.first() {
-        // salt the user's password
-        let argon_config = argon_config::default();
-        let new_password = argon_hash_encoded(
-            req_object.password.as_bytes(),
-            &config.server_password_salt,
-            &argon_config,
-        )
-        .unwrap();
-
-        let update_user_query = format!(
-            "UPDATE \
-                users \
-            SET \
-                password = '{new_password}' \
-            WHERE \
-                users.id = {user_id};"
-        );
-        let stmt = conn.prepare(&update_user_query).await.unwrap();
-        let _ = match conn.query(&stmt, &[]).await {
+    let query_result =
+        match trace_db_query(&cur_query, conn.query(&stmt, &[])).await {
             Ok(query_result) => query_result,
             Err(e) => {
                 let response = Response::builder()
@@ -535,10 +597,10 @@ pub async fn consume_user_otp(
                             otp_id: -1,
                             msg: format!(
                                 "User consume one-time-password failed \
-                                to reset user's password for \
-                                user_id={user_id} {user_email} \
-                                with err='{e}'"
+                            for user_id={user_id} {user_email} \
+                            with err='{e}'"
                             ),
+                            error_code: Some(ApiErrorCode::InternalError),
                         })
                         .unwrap(),
                     ))
@@ -546,24 +608,25 @@ pub async fn consume_user_otp(
                 return Ok(response);
             }
         };
+    config.user_cache.invalidate_user(user_id).await;
 
+    // must match up with RETURNING
+    if let Some(row) = query_result.first() {
         let user_otp_id: i32 = row.try_get("id").unwrap();
 
-        // if enabled, publish to kafka
-        if config.kafka_publish_events {
-            publish_msg(
-                kafka_pool,
-                // topic
-                "user.events",
-                // partition key
-                &format!("user-{}", user_id),
-                // optional headers stored in: Option<HashMap<String, String>>
-                None,
-                // payload in the message
-                &format!("USER_CONSUME_OTP user={user_id}"),
-            )
+        config
+            .events
+            .publish_user_event(kafka_pool, user_id, "USER_CONSUME_OTP", "")
             .await;
-        }
+        send_security_notification(
+            tracking_label,
+            config,
+            kafka_pool,
+            &user_model,
+            "password",
+            "",
+        )
+        .await;
 
         let response = Response::builder()
             .status(200)
@@ -572,12 +635,16 @@ pub async fn consume_user_otp(
                     user_id,
                     otp_id: user_otp_id,
                     msg: "success".to_string(),
+                    error_code: None,
                 })
                 .unwrap(),
             ))
             .unwrap();
         return Ok(response);
     }
+    config
+        .auth_alerts
+        .record_failure(tracking_label, "otp", "already_used");
     let response = Response::builder()
         .status(400)
         .body(Body::from(
@@ -585,11 +652,86 @@ pub async fn consume_user_otp(
                 user_id: req_object.user_id,
                 otp_id: -1,
                 msg: ("User consume one-time-password failed - \
-                    no records found in db")
+                    the one-time-password was already used or expired")
                     .to_string(),
+                error_code: Some(ApiErrorCode::OtpExpired),
             })
             .unwrap(),
         ))
         .unwrap();
     Ok(response)
 }
+
+/// record_failed_otp_attempt
+///
+/// Count a wrong token against the user's active otp and
+/// lock it (``state = 3``) once it reaches
+/// ``USER_OTP_MAX_ATTEMPTS`` so short tokens (for example
+/// 6 digit ``numeric`` tokens) cannot be brute-forced
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+///   an established db connection from the
+///   postgres client db threadpool
+/// * `user_id` - `i32` - user id
+///
+/// # Returns
+///
+/// `bool` - `true` when the active otp is locked
+///
+pub async fn record_failed_otp_attempt(
+    tracking_label: &str,
+    config: &CoreConfig,
+    conn: &PooledConnection<'_, PostgresConnectionManager<MakeTlsConnector>>,
+    user_id: i32,
+) -> bool {
+    let max_attempts = config.otp.max_attempts;
+    if max_attempts == 0 {
+        return false;
+    }
+    let query = format!(
+        "UPDATE \
+            users_otp \
+        SET \
+            failed_attempts = users_otp.failed_attempts + 1, \
+            state = CASE \
+                WHEN users_otp.failed_attempts + 1 >= {max_attempts} \
+                THEN 3 \
+                ELSE users_otp.state END \
+        WHERE \
+            users_otp.user_id = {user_id} \
+            AND \
+            users_otp.state = 0 \
+        RETURNING \
+            users_otp.state, \
+            users_otp.failed_attempts;"
+    );
+    let stmt = conn.prepare(&query).await.unwrap();
+    match trace_db_query(&query, conn.query(&stmt, &[])).await {
+        Ok(query_result) => match query_result.first() {
+            Some(row) => {
+                let state: i32 = row.try_get("state").unwrap();
+                if state == 3 {
+                    warn!(
+                        "{tracking_label} - \
+                        locked one-time-password for user {user_id} \
+                        after {max_attempts} failed attempts"
+                    );
+                }
+                state == 3
+            }
+            None => false,
+        },
+        Err(e) => {
+            error!(
+                "{tracking_label} - \
+                failed to count a failed one-time-password attempt \
+                for user {user_id} with err='{e}'"
+            );
+            false
+        }
+    }
+}
@@ -2,7 +2,7 @@
 //!
 //! ## Create One-Time-Use Password Reset Token (OTP)
 //!
-//! Create a one-time-use password reset token that allows a user to change their ``users.password`` value by presenting the token
+//! Create a one-time-use password reset token that allows a user to change their ``users.password`` value by presenting the token. Creating a new token invalidates the user's previous unconsumed tokens, and token creation is rate limited per user (see [`OtpConfig`](crate::requests::user::otp_config::OtpConfig)).
 //!
 //! - URL path: ``/user/password/reset``
 //! - Method: ``POST``
@@ -16,6 +16,7 @@ use std::convert::Infallible;
 use postgres_native_tls::MakeTlsConnector;
 
 use bb8::Pool;
+use bb8::PooledConnection;
 use bb8_postgres::PostgresConnectionManager;
 
 use hyper::header::HeaderValue;
@@ -26,13 +27,20 @@ use hyper::Response;
 use serde::Deserialize;
 use serde::Serialize;
 
-use kafka_threadpool::kafka_publisher::KafkaPublisher;
-
 use crate::core::core_config::CoreConfig;
-use crate::kafka::publish_msg::publish_msg;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::monitoring::otel::trace_db_query;
 use crate::requests::auth::validate_user_token::validate_user_token;
+use crate::requests::models::api_error::ApiErrorCode;
 use crate::requests::models::user::get_user_by_id;
-use crate::utils::get_uuid::get_uuid;
+use crate::requests::models::user::ModelUser;
+use crate::requests::validation::field_rules::check_email;
+use crate::requests::validation::field_rules::check_id;
+use crate::requests::validation::normalize_email::normalize_email;
+use crate::requests::validation::validate_api_req::validate_api_req;
+use crate::requests::validation::validate_api_req::ApiFieldError;
+use crate::requests::validation::validate_api_req::ApiReqValidate;
+use crate::utils::hash_token::hash_token;
 
 /// ApiReqUserCreateOtp
 ///
@@ -66,6 +74,19 @@ pub struct ApiReqUserCreateOtp {
     pub email: String,
 }
 
+impl ApiReqValidate for ApiReqUserCreateOtp {
+    /// validate
+    ///
+    /// Require a positive `user_id` and a valid `email`
+    ///
+    fn validate(&self) -> Vec<ApiFieldError> {
+        let mut errors = Vec::new();
+        check_id(&mut errors, "user_id", self.user_id);
+        check_email(&mut errors, "email", &self.email);
+        errors
+    }
+}
+
 /// ApiResUserCreateOtp
 ///
 /// # Response type for create_otp
@@ -89,10 +110,12 @@ pub struct ApiReqUserCreateOtp {
 ///
 /// * `user_id` - `i32` - user id
 /// * `token` - `String` - user's new one-time-use token to reset
-///   their password
+///   their password (empty with ``USER_OTP_DELIVERY=email``)
 /// * `exp_date` - `String` - UTC-formatted date time string when
 ///   the `token` expires
 /// * `msg` - `String` - help message
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   machine-readable failure code (omitted on success)
 ///
 #[derive(Serialize, Deserialize, Clone)]
 pub struct ApiResUserCreateOtp {
@@ -102,11 +125,22 @@ pub struct ApiResUserCreateOtp {
     pub token: String,
     pub exp_date: String,
     pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ApiErrorCode>,
 }
 
 /// create_otp
 ///
 /// Creates a one-time-use token to reset a user's account password.
+/// The user's previous unconsumed tokens are invalidated
+/// (``users_otp.state = 2``) in the same sql statement.
+///
+/// Token charset, length, expiration, rate limiting and delivery
+/// are set with
+/// [`OtpConfig`](crate::requests::user::otp_config::OtpConfig).
+/// With ``USER_OTP_DELIVERY=email`` the token is published in the
+/// ``USER_CREATE_OTP`` user event for a mail service and the
+/// response `token` is empty.
 ///
 /// # Arguments
 ///
@@ -115,7 +149,7 @@ pub struct ApiResUserCreateOtp {
 /// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
 ///   db threadpool with required tls encryption
 /// * `kafka_pool` -
-///   [`KafkaPublisher`](kafka_threadpool::kafka_publisher::KafkaPublisher)
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
 ///   for asynchronously publishing messages to the connected kafka cluster
 /// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
 ///   hashmap containing headers in key-value pairs
@@ -143,7 +177,9 @@ pub struct ApiResUserCreateOtp {
 /// containing a json-serialized
 /// [`ApiResUserCreateOtp`](crate::requests::user::create_otp::ApiResUserCreateOtp)
 /// dictionary with a
-/// `non-201` HTTP status code
+/// `non-201` HTTP status code (`429` with a ``Retry-After``
+/// header when the user created too many tokens and `503`
+/// when email delivery is enabled without kafka events)
 ///
 /// Err([`Response`](hyper::Response))
 ///
@@ -155,63 +191,33 @@ pub async fn create_otp(
     headers: &HeaderMap<HeaderValue>,
     bytes: &[u8],
 ) -> std::result::Result<Response<Body>, Infallible> {
-    let req_object: ApiReqUserCreateOtp = match serde_json::from_slice(bytes) {
-        Ok(uo) => uo,
-        Err(_) => {
-            let response = Response::builder()
-                .status(400)
-                .body(Body::from(
-                    serde_json::to_string(&ApiResUserCreateOtp {
-                        user_id: -1,
-                        token: "".to_string(),
-                        exp_date: "".to_string(),
-                        msg: ("User create one-time-password failed - \
+    let mut req_object: ApiReqUserCreateOtp =
+        match serde_json::from_slice(bytes) {
+            Ok(uo) => uo,
+            Err(_) => {
+                let response = Response::builder()
+                    .status(400)
+                    .body(Body::from(
+                        serde_json::to_string(&ApiResUserCreateOtp {
+                            user_id: -1,
+                            token: "".to_string(),
+                            exp_date: "".to_string(),
+                            msg: ("User create one-time-password failed - \
                             please ensure \
                             user_id and email \
                             were set correctly in the request")
-                            .to_string(),
-                    })
-                    .unwrap(),
-                ))
-                .unwrap();
-            return Ok(response);
-        }
-    };
+                                .to_string(),
+                            error_code: Some(ApiErrorCode::InvalidRequest),
+                        })
+                        .unwrap(),
+                    ))
+                    .unwrap();
+                return Ok(response);
+            }
+        };
 
-    // is this a waste of time because nothing changed
-    if req_object.user_id <= 0 {
-        let response = Response::builder()
-            .status(400)
-            .body(Body::from(
-                serde_json::to_string(&ApiResUserCreateOtp {
-                    user_id: req_object.user_id,
-                    token: "".to_string(),
-                    exp_date: "".to_string(),
-                    msg: ("User create one-time-password failed \
-                        please ensure \
-                        user_id is a non-negative number")
-                        .to_string(),
-                })
-                .unwrap(),
-            ))
-            .unwrap();
-        return Ok(response);
-    } else if req_object.email.is_empty() {
-        let response = Response::builder()
-            .status(400)
-            .body(Body::from(
-                serde_json::to_string(&ApiResUserCreateOtp {
-                    user_id: req_object.user_id,
-                    token: "".to_string(),
-                    exp_date: "".to_string(),
-                    msg: ("User create one-time-password failed \
-                        please ensure \
-                        email is set to the user's email address")
-                        .to_string(),
-                })
-                .unwrap(),
-            ))
-            .unwrap();
+    req_object.email = normalize_email(&req_object.email);
+    if let Some(response) = validate_api_req(tracking_label, &req_object) {
         return Ok(response);
     }
 
@@ -230,7 +236,7 @@ pub async fn create_otp(
     .await
     {
         Ok(_token) => _token,
-        Err(_) => {
+        Err(error_code) => {
             let response = Response::builder()
                 .status(400)
                 .body(Body::from(
@@ -241,6 +247,7 @@ pub async fn create_otp(
                         msg: ("User create one-time-password failed \
                             due to invalid token")
                             .to_string(),
+                        error_code: Some(error_code),
                     })
                     .unwrap(),
                 ))
@@ -270,6 +277,7 @@ pub async fn create_otp(
                             "User create one-time-password failed - \
                             unable to find user with id: {user_id}"
                         ),
+                        error_code: Some(ApiErrorCode::UserNotFound),
                     })
                     .unwrap(),
                 ))
@@ -291,6 +299,35 @@ pub async fn create_otp(
                         user_email does not match {}",
                         req_object.email
                     ),
+                    error_code: Some(ApiErrorCode::InvalidRequest),
+                })
+                .unwrap(),
+            ))
+            .unwrap();
+        return Ok(response);
+    }
+
+    // the email delivery method needs the event bus
+    // to hand the token to a mail service
+    let otp_config = &config.otp;
+    let deliver_by_email = otp_config.delivery == "email";
+    if deliver_by_email && !config.events.enabled {
+        error!(
+            "{tracking_label} - \
+            unable to email one-time-password for user {user_id} - \
+            USER_OTP_DELIVERY=email requires KAFKA_PUBLISH_EVENTS=1"
+        );
+        let response = Response::builder()
+            .status(503)
+            .body(Body::from(
+                serde_json::to_string(&ApiResUserCreateOtp {
+                    user_id: req_object.user_id,
+                    token: "".to_string(),
+                    exp_date: "".to_string(),
+                    msg: ("User create one-time-password failed - \
+                        email delivery is not available")
+                        .to_string(),
+                    error_code: Some(ApiErrorCode::FeatureDisabled),
                 })
                 .unwrap(),
             ))
@@ -298,120 +335,415 @@ pub async fn create_otp(
         return Ok(response);
     }
 
-    let user_otp_expiration_in_seconds_str =
-        std::env::var("USER_OTP_EXP_IN_SECONDS")
-            .unwrap_or_else(|_| "2592000".to_string());
-    let user_otp_expiration_in_seconds: i64 =
-        user_otp_expiration_in_seconds_str.parse::<i64>().unwrap();
+    let user_otp = match insert_user_otp(
+        tracking_label,
+        config,
+        &conn,
+        user_id,
+        &user_email,
+    )
+    .await
+    {
+        Ok(user_otp) => user_otp,
+        Err(e) => {
+            let mut response = Response::builder().status(e.status);
+            if let Some(retry_after) = e.retry_after {
+                response =
+                    response.header("Retry-After", format!("{retry_after}"));
+            }
+            let response = response
+                .body(Body::from(
+                    serde_json::to_string(&ApiResUserCreateOtp {
+                        user_id: req_object.user_id,
+                        token: "".to_string(),
+                        exp_date: "".to_string(),
+                        msg: e.msg,
+                        error_code: Some(ApiErrorCode::from_status(e.status)),
+                    })
+                    .unwrap(),
+                ))
+                .unwrap();
+            return Ok(response);
+        }
+    };
+
+    // with email delivery the token is only sent to the
+    // mail service and not returned to the client
+    if deliver_by_email {
+        send_otp_email(
+            tracking_label,
+            config,
+            kafka_pool,
+            &user_model,
+            &user_otp,
+        )
+        .await;
+    }
+    let (event_details, response_token, msg) = match deliver_by_email {
+        true => (
+            get_otp_email_details(
+                config,
+                user_id,
+                &user_email,
+                &user_otp.token,
+            ),
+            "".to_string(),
+            "success - the one-time-password was sent to the user email"
+                .to_string(),
+        ),
+        false => ("".to_string(), user_otp.token, "success".to_string()),
+    };
+    config
+        .events
+        .publish_user_event(
+            kafka_pool,
+            user_id,
+            "USER_CREATE_OTP",
+            &event_details,
+        )
+        .await;
+
+    let response = Response::builder()
+        .status(201)
+        .body(Body::from(
+            serde_json::to_string(&ApiResUserCreateOtp {
+                user_id: user_otp.id,
+                token: response_token,
+                exp_date: user_otp.exp_date,
+                msg,
+                error_code: None,
+            })
+            .unwrap(),
+        ))
+        .unwrap();
+    Ok(response)
+}
+
+/// CreatedUserOtp
+///
+/// A new ``users_otp`` record from
+/// [`insert_user_otp`](crate::requests::user::create_otp::insert_user_otp)
+///
+/// # Arguments
+///
+/// * `id` - `i32` - ``users_otp.id``
+/// * `token` - `String` - the new (unhashed) token (only
+///   its hash is stored in the db)
+/// * `exp_date` - `String` - UTC-formatted date time string
+///   when the `token` expires
+///
+#[derive(Clone, Default)]
+pub struct CreatedUserOtp {
+    pub id: i32,
+    pub token: String,
+    pub exp_date: String,
+}
+
+/// UserOtpError
+///
+/// Why
+/// [`insert_user_otp`](crate::requests::user::create_otp::insert_user_otp)
+/// did not create a token
+///
+/// # Arguments
+///
+/// * `status` - `u16` - HTTP status code (`429` when rate
+///   limited)
+/// * `retry_after` - `Option<i64>` - seconds for the
+///   ``Retry-After`` header when rate limited
+/// * `msg` - `String` - error message for the client
+///
+#[derive(Clone, Debug)]
+pub struct UserOtpError {
+    pub status: u16,
+    pub retry_after: Option<i64>,
+    pub msg: String,
+}
+
+/// insert_user_otp
+///
+/// Create a one-time-use token for a user after checking
+/// the per-user rate limit. The user's previous unconsumed
+/// tokens are invalidated (``users_otp.state = 2``) in the
+/// same sql statement.
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+///   an established db connection from the
+///   postgres client db threadpool
+/// * `user_id` - `i32` - user id
+/// * `user_email` - `&str` - user email
+///
+/// # Returns
+///
+/// Ok([`CreatedUserOtp`](crate::requests::user::create_otp::CreatedUserOtp))
+///
+/// # Errors
+///
+/// Err([`UserOtpError`](crate::requests::user::create_otp::UserOtpError))
+/// when the user is rate limited or the insert fails
+///
+pub async fn insert_user_otp(
+    tracking_label: &str,
+    config: &CoreConfig,
+    conn: &PooledConnection<'_, PostgresConnectionManager<MakeTlsConnector>>,
+    user_id: i32,
+    user_email: &str,
+) -> Result<CreatedUserOtp, UserOtpError> {
+    let otp_config = &config.otp;
     let now = chrono::Utc::now();
+
+    // rate limit otp creation per user
+    if otp_config.rate_limit_max > 0 {
+        let window_start = now
+            - chrono::Duration::seconds(otp_config.rate_limit_window_seconds);
+        let rate_query = format!(
+            "SELECT \
+                COUNT(*) AS num_otps, \
+                MIN(users_otp.created_at) AS oldest_created_at \
+            FROM \
+                users_otp \
+            WHERE \
+                users_otp.user_id = {user_id} \
+                AND \
+                users_otp.created_at > '{window_start}';"
+        );
+        let stmt = conn.prepare(&rate_query).await.unwrap();
+        if let Ok(rows) =
+            trace_db_query(&rate_query, conn.query(&stmt, &[])).await
+        {
+            let num_otps: i64 = rows[0].try_get("num_otps").unwrap_or(0);
+            if num_otps >= otp_config.rate_limit_max {
+                let oldest_created_at: chrono::DateTime<chrono::Utc> =
+                    rows[0].try_get("oldest_created_at").unwrap_or(now);
+                let retry_after = (oldest_created_at
+                    + chrono::Duration::seconds(
+                        otp_config.rate_limit_window_seconds,
+                    )
+                    - now)
+                    .num_seconds()
+                    .max(1);
+                warn!(
+                    "{tracking_label} - \
+                    rate limited one-time-password for user {user_id} - \
+                    {num_otps} created in the last \
+                    {}s",
+                    otp_config.rate_limit_window_seconds
+                );
+                return Err(UserOtpError {
+                    status: 429,
+                    retry_after: Some(retry_after),
+                    msg: format!(
+                        "User create one-time-password failed - \
+                        too many requests, please retry in \
+                        {retry_after} seconds"
+                    ),
+                });
+            }
+        }
+    }
+
     // https://docs.rs/chrono/0.4.19/chrono/struct.Duration.html#method.seconds
     let otp_expiration_timestamp =
-        now + chrono::Duration::seconds(user_otp_expiration_in_seconds);
+        now + chrono::Duration::seconds(otp_config.exp_in_seconds);
 
-    let otp_token = format!("{}{}", get_uuid(), get_uuid());
+    let otp_token = otp_config.generate_token();
+    // only the token hash is stored in the db and the
+    // token is only returned to the client in this response
+    // (or published for the mail service)
+    let otp_token_hash = hash_token(&otp_token, &config.server_password_salt);
 
+    // invalidate the user's unconsumed otps (state = 2)
+    // and create the new otp in 1 statement so each user
+    // has at most one active otp. the INSERT selects from
+    // invalidated_otps so the UPDATE runs first and the
+    // idx_users_otp_user_id_active unique index only sees
+    // the new otp
     let cur_query = format!(
-        "INSERT INTO \
+        "WITH invalidated_otps AS (\
+            UPDATE \
+                users_otp \
+            SET \
+                state = 2 \
+            WHERE \
+                users_otp.user_id = {user_id} \
+                AND \
+                users_otp.state = 0 \
+            RETURNING \
+                users_otp.id), \
+        num_invalidated_otps AS (\
+            SELECT \
+                COUNT(*) AS num_invalidated \
+            FROM \
+                invalidated_otps) \
+        INSERT INTO \
             users_otp (\
                 user_id, \
                 token, \
                 email, \
                 state, \
-                exp_date) \
-        VALUES (\
+                exp_date, \
+                tenant_id) \
+        SELECT \
             {user_id}, \
-            '{otp_token}', \
-            '{user_email}', \
-            0,
-            '{otp_expiration_timestamp}') \
+            '{otp_token_hash}', \
+            '{}', \
+            0, \
+            '{otp_expiration_timestamp}', \
+            (SELECT users.tenant_id FROM users \
+                WHERE users.id = {user_id}) \
+        FROM \
+            num_invalidated_otps \
         RETURNING \
             users_otp.id, \
             users_otp.user_id, \
             users_otp.token, \
             users_otp.email, \
             users_otp.state, \
-            users_otp.exp_date;"
+            users_otp.exp_date, \
+            (SELECT num_invalidated FROM num_invalidated_otps);",
+        user_email.replace('\'', "''")
     );
 
     let stmt = conn.prepare(&cur_query).await.unwrap();
-    let query_result = match conn.query(&stmt, &[]).await {
+    let query_result = match trace_db_query(&cur_query, conn.query(&stmt, &[]))
+        .await
+    {
         Ok(query_result) => query_result,
         Err(e) => {
-            let response = Response::builder()
-                .status(400)
-                .body(Body::from(
-                    serde_json::to_string(&ApiResUserCreateOtp {
-                        user_id: req_object.user_id,
-                        token: "".to_string(),
-                        exp_date: "".to_string(),
-                        msg: format!(
-                            "User create one-time-password failed \
-                            for user_id={user_id} {user_email} \
-                            with err='{e}'"
-                        ),
-                    })
-                    .unwrap(),
-                ))
-                .unwrap();
-            return Ok(response);
+            let err_msg = format!("{e}");
+            // a concurrent request created the active otp first
+            let msg = match err_msg.contains("duplicate key value violates") {
+                true => format!(
+                    "User create one-time-password failed \
+                    for user_id={user_id} - another one-time-password \
+                    was created at the same time"
+                ),
+                false => format!(
+                    "User create one-time-password failed \
+                    for user_id={user_id} {user_email} \
+                    with err='{err_msg}'"
+                ),
+            };
+            return Err(UserOtpError {
+                status: 400,
+                retry_after: None,
+                msg,
+            });
         }
     };
 
     // must match up with RETURNING
-    if let Some(row) = query_result.first() {
-        let user_otp_id: i32 = row.try_get("id").unwrap();
-        let user_otp_token: String = row.try_get("token").unwrap();
-        let user_otp_exp_date_str: String = match row.try_get("exp_date") {
-            Ok(v) => {
-                let user_otp_exp_date: chrono::DateTime<chrono::Utc> = v;
-                format!("{}", user_otp_exp_date.format("%Y-%m-%dT%H:%M:%SZ"))
-            }
-            Err(_) => "".to_string(),
-        };
-
-        // if enabled, publish to kafka
-        if config.kafka_publish_events {
-            publish_msg(
-                kafka_pool,
-                // topic
-                "user.events",
-                // partition key
-                &format!("user-{}", user_id),
-                // optional headers stored in: Option<HashMap<String, String>>
-                None,
-                // payload in the message
-                &format!("USER_CREATE_OTP user={user_id}"),
-            )
-            .await;
+    match query_result.first() {
+        Some(row) => {
+            let user_otp_id: i32 = row.try_get("id").unwrap();
+            let exp_date = match row.try_get("exp_date") {
+                Ok(v) => {
+                    let user_otp_exp_date: chrono::DateTime<chrono::Utc> = v;
+                    format!(
+                        "{}",
+                        user_otp_exp_date.format("%Y-%m-%dT%H:%M:%SZ")
+                    )
+                }
+                Err(_) => "".to_string(),
+            };
+            let num_invalidated: i64 =
+                row.try_get("num_invalidated").unwrap_or(0);
+            info!(
+                "{tracking_label} - \
+                created one-time-password for user {user_id} \
+                invalidated={num_invalidated} \
+                delivery={}",
+                otp_config.delivery
+            );
+            Ok(CreatedUserOtp {
+                id: user_otp_id,
+                token: otp_token,
+                exp_date,
+            })
         }
+        None => Err(UserOtpError {
+            status: 400,
+            retry_after: None,
+            msg: ("User create one-time-password failed - \
+                no records found in db")
+                .to_string(),
+        }),
+    }
+}
 
-        let response = Response::builder()
-            .status(201)
-            .body(Body::from(
-                serde_json::to_string(&ApiResUserCreateOtp {
-                    user_id: user_otp_id,
-                    token: user_otp_token,
-                    exp_date: user_otp_exp_date_str,
-                    msg: "success".to_string(),
-                })
-                .unwrap(),
-            ))
-            .unwrap();
-        return Ok(response);
+/// get_otp_email_details
+///
+/// Build the ``USER_CREATE_OTP`` event details for the
+/// mail service (the email, token and the
+/// ``USER_OTP_RESET_URL`` link when it is set)
+///
+/// # Arguments
+///
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `user_id` - `i32` - user id
+/// * `user_email` - `&str` - user email
+/// * `token` - `&str` - the new (unhashed) otp token
+///
+pub fn get_otp_email_details(
+    config: &CoreConfig,
+    user_id: i32,
+    user_email: &str,
+    token: &str,
+) -> String {
+    match config.otp.get_reset_link(user_id, user_email, token) {
+        Some(link) => format!("email={user_email} token={token} link={link}"),
+        None => format!("email={user_email} token={token}"),
     }
-    let response = Response::builder()
-        .status(400)
-        .body(Body::from(
-            serde_json::to_string(&ApiResUserCreateOtp {
-                user_id: req_object.user_id,
-                token: "".to_string(),
-                exp_date: "".to_string(),
-                msg: ("User create one-time-password failed - \
-                    no records found in db")
-                    .to_string(),
-            })
-            .unwrap(),
-        ))
-        .unwrap();
-    Ok(response)
+}
+
+/// send_otp_email
+///
+/// Render the ``otp`` email in the user's locale with the
+/// token, expiration and the ``USER_OTP_RESET_URL`` link
+/// (when it is set) and publish it for the mail service
+/// (see
+/// [`EmailTemplates::send_user_email`](crate::notifications::email_templates::EmailTemplates::send_user_email))
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `kafka_pool` - initialized [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+/// * `user_model` - [`ModelUser`](crate::requests::models::user::ModelUser) -
+///   the token's user
+/// * `user_otp` - [`CreatedUserOtp`](crate::requests::user::create_otp::CreatedUserOtp) -
+///   the new token
+///
+pub async fn send_otp_email(
+    tracking_label: &str,
+    config: &CoreConfig,
+    kafka_pool: &KafkaPublisher,
+    user_model: &ModelUser,
+    user_otp: &CreatedUserOtp,
+) {
+    config
+        .email_templates
+        .send_user_email(
+            tracking_label,
+            &config.events,
+            kafka_pool,
+            user_model.id,
+            "otp",
+            &user_model.locale,
+            &user_model.email,
+            serde_json::json!({
+                "token": user_otp.token,
+                "exp_date": user_otp.exp_date,
+                "link": config.otp.get_reset_link(
+                    user_model.id,
+                    &user_model.email,
+                    &user_otp.token
+                ),
+            }),
+        )
+        .await;
 }
@@ -18,23 +18,37 @@ use postgres_native_tls::MakeTlsConnector;
 use bb8::Pool;
 use bb8_postgres::PostgresConnectionManager;
 
+use hyper::header::HeaderValue;
 use hyper::Body;
+use hyper::HeaderMap;
 use hyper::Response;
 
 use serde::Deserialize;
 use serde::Serialize;
 
-use argon2::hash_encoded as argon_hash_encoded;
-use argon2::Config as argon_config;
-
-use kafka_threadpool::kafka_publisher::KafkaPublisher;
-
 use crate::core::core_config::CoreConfig;
-use crate::kafka::publish_msg::publish_msg;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::notifications::email_templates::normalize_locale;
+use crate::notifications::email_templates::EmailTemplates;
 use crate::requests::auth::create_user_token::create_user_token;
 use crate::requests::auth::login_user::ApiResUserLogin;
+use crate::requests::models::api_error::ApiError;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::models::user::DEFAULT_USER_LOCALE;
+use crate::requests::models::user_repo::NewUser;
+use crate::requests::models::user_repo::UserRepo;
+use crate::requests::models::user_session::get_user_session_metadata;
 use crate::requests::user::is_verification_enabled::is_verification_enabled;
 use crate::requests::user::upsert_user_verification::upsert_user_verification;
+use crate::requests::validation::field_rules::check_email;
+use crate::requests::validation::field_rules::check_locale;
+use crate::requests::validation::field_rules::check_password;
+use crate::requests::validation::normalize_email::normalize_email;
+use crate::requests::validation::validate_api_req::validate_api_req;
+use crate::requests::validation::validate_api_req::ApiFieldError;
+use crate::requests::validation::validate_api_req::ApiReqValidate;
+use crate::requests::validation::validate_email_domain::validate_email_domain;
+use crate::requests::validation::validate_password_policy::validate_password_policy;
 use crate::utils::get_server_address::get_server_address;
 
 /// ApiReqUserCreate
@@ -58,11 +72,32 @@ use crate::utils::get_server_address::get_server_address;
 ///
 /// * `email` - `String` - user email
 /// * `password` - `String` - new user password
+/// * `locale` - `Option<String>` - locale for the user's
+///   emails (default ``en``)
 ///
 #[derive(Serialize, Deserialize, Clone)]
 pub struct ApiReqUserCreate {
     pub email: String,
     pub password: String,
+    #[serde(default)]
+    pub locale: Option<String>,
+}
+
+impl ApiReqValidate for ApiReqUserCreate {
+    /// validate
+    ///
+    /// Require a valid `email`, a `password` between
+    /// 4 and 1024 characters and a valid `locale` (if set)
+    ///
+    fn validate(&self) -> Vec<ApiFieldError> {
+        let mut errors = Vec::new();
+        check_email(&mut errors, "email", &self.email);
+        check_password(&mut errors, "password", &self.password);
+        if let Some(locale) = &self.locale {
+            check_locale(&mut errors, "locale", locale);
+        }
+        errors
+    }
 }
 
 /// ApiResUserCreate
@@ -90,6 +125,8 @@ pub struct ApiReqUserCreate {
 /// * `role` - `String` - user role
 /// * `token` - `String` - user jwt
 /// * `msg` - `String` - help message
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   machine-readable failure code (omitted on success)
 ///
 #[derive(Serialize, Deserialize, Default, Clone)]
 pub struct ApiResUserCreate {
@@ -99,6 +136,8 @@ pub struct ApiResUserCreate {
     pub role: String,
     pub token: String,
     pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ApiErrorCode>,
 }
 
 /// create_user
@@ -117,8 +156,13 @@ pub struct ApiResUserCreate {
 /// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
 ///   db threadpool with required tls encryption
 /// * `kafka_pool` -
-///   [`KafkaPublisher`](kafka_threadpool::kafka_publisher::KafkaPublisher)
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
 ///   for asynchronously publishing messages to the connected kafka cluster
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+///   (``User-Agent`` and ``device`` are stored with the session)
+/// * `remote_addr` - `&std::net::SocketAddr` - client address
+///   stored with the session
 /// * `bytes` - `&[u8]` - received bytes from the hyper
 ///   [`Request`](hyper::Request)'s [`Body`](hyper::Body)
 ///
@@ -158,26 +202,52 @@ pub async fn create_user(
     config: &CoreConfig,
     db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
     kafka_pool: &KafkaPublisher,
+    headers: &HeaderMap<HeaderValue>,
+    remote_addr: &std::net::SocketAddr,
     bytes: &[u8],
 ) -> std::result::Result<Response<Body>, Infallible> {
-    let user_object: ApiReqUserCreate = serde_json::from_slice(bytes).unwrap();
+    let mut user_object: ApiReqUserCreate = match serde_json::from_slice(bytes)
+    {
+        Ok(uo) => uo,
+        Err(_) => {
+            let response = Response::builder()
+                .status(400)
+                .body(Body::from(
+                    serde_json::to_string(&ApiResUserCreate {
+                        user_id: -1,
+                        email: "".to_string(),
+                        state: -1,
+                        role: "".to_string(),
+                        token: "".to_string(),
+                        msg: ("User creation failed - please ensure \
+                            email and password \
+                            were set correctly in the request")
+                            .to_string(),
+                        error_code: Some(ApiErrorCode::InvalidRequest),
+                    })
+                    .unwrap(),
+                ))
+                .unwrap();
+            return Ok(response);
+        }
+    };
 
-    if user_object.password.len() < 4 {
-        let response = Response::builder()
-            .status(400)
-            .body(Body::from(
-                serde_json::to_string(&ApiResUserCreate {
-                    user_id: -1,
-                    email: "".to_string(),
-                    state: -1,
-                    role: "".to_string(),
-                    token: "".to_string(),
-                    msg: ("User password must be more than 4 characters")
-                        .to_string(),
-                })
-                .unwrap(),
-            ))
-            .unwrap();
+    user_object.email = normalize_email(&user_object.email);
+    user_object.locale = user_object.locale.as_deref().map(normalize_locale);
+    if let Some(response) = validate_api_req(tracking_label, &user_object) {
+        return Ok(response);
+    }
+    if let Some(response) = validate_password_policy(
+        tracking_label,
+        &config.get_settings().password_policy,
+        "password",
+        &user_object.password,
+    ) {
+        return Ok(response);
+    }
+    if let Some(response) =
+        validate_email_domain(tracking_label, "email", &user_object.email).await
+    {
         return Ok(response);
     }
 
@@ -194,44 +264,55 @@ pub async fn create_user(
     };
 
     // salt the user's password
-    let argon_config = argon_config::default();
-    let hash = argon_hash_encoded(
-        user_object.password.as_bytes(),
-        &config.server_password_salt,
-        &argon_config,
-    )
-    .unwrap();
+    let hash = config
+        .password_hashing
+        .hash_password(&user_object.password, &config.server_password_salt)
+        .unwrap();
 
-    let insert_query = format!(
-        "INSERT INTO \
-            users (\
-                email, \
-                password, \
-                state, \
-                verified, \
-                role) \
-        VALUES (\
-            '{}', \
-            '{hash}', \
-            {user_start_state_value}, \
-            {user_verified_value}, \
-            '{user_role}') \
-        RETURNING \
-            users.id, \
-            users.email, \
-            users.password, \
-            users.state, \
-            users.verified, \
-            users.role;",
-        user_object.email
-    );
     let conn = db_pool.get().await.unwrap();
-    let stmt = conn.prepare(&insert_query).await.unwrap();
-    let query_result = match conn.query(&stmt, &[]).await {
-        Ok(query_result) => query_result,
-        Err(e) => {
-            let err_msg = format!("{e}");
-            if err_msg.contains("duplicate key value violates") {
+    let tenant_id = match config
+        .tenants
+        .get_tenant_id(tracking_label, headers, &conn)
+        .await
+    {
+        Ok(tenant_id) => tenant_id,
+        Err(err_msg) => {
+            error!("{err_msg}");
+            let response = Response::builder()
+                .status(400)
+                .body(Body::from(
+                    serde_json::to_string(&ApiResUserCreate {
+                        user_id: -1,
+                        email: "".to_string(),
+                        state: -1,
+                        role: "".to_string(),
+                        token: "".to_string(),
+                        msg: ("User creation failed - unknown tenant")
+                            .to_string(),
+                        error_code: Some(ApiErrorCode::UnknownTenant),
+                    })
+                    .unwrap(),
+                ))
+                .unwrap();
+            return Ok(response);
+        }
+    };
+    let new_user = NewUser {
+        email: user_object.email.clone(),
+        password_hash: hash.clone(),
+        state: user_start_state_value,
+        verified: user_verified_value,
+        role: user_role.to_string(),
+        tenant_id,
+        locale: user_object
+            .locale
+            .clone()
+            .unwrap_or_else(|| DEFAULT_USER_LOCALE.to_string()),
+    };
+    let created_user =
+        match UserRepo::new(&conn).insert(tracking_label, &new_user).await {
+            Ok(created_user) => created_user,
+            Err(ApiError::Conflict(_)) => {
                 let response = Response::builder()
                     .status(400)
                     .body(Body::from(
@@ -245,175 +326,179 @@ pub async fn create_user(
                                 "User email {} already registered",
                                 user_object.email
                             ),
+                            error_code: Some(ApiErrorCode::EmailInUse),
                         })
                         .unwrap(),
                     ))
                     .unwrap();
                 return Ok(response);
-            } else {
+            }
+            Err(e) => {
                 let response = Response::builder()
                     .status(500)
                     .body(Body::from(
-                        serde_json::to_string(
-                            &ApiResUserCreate {
-                                user_id: -1,
-                                email: "".to_string(),
-                                state: -1,
-                                role: "".to_string(),
-                                token: "".to_string(),
-                                msg: format!(
-                                    "User creation failed for email={} with err='{err_msg}'",
-                                        user_object.email)
-                        }).unwrap()))
+                        serde_json::to_string(&ApiResUserCreate {
+                            user_id: -1,
+                            email: "".to_string(),
+                            state: -1,
+                            role: "".to_string(),
+                            token: "".to_string(),
+                            msg: format!(
+                                "User creation failed for email={} \
+                                with err='{e}'",
+                                user_object.email
+                            ),
+                            error_code: Some(ApiErrorCode::InternalError),
+                        })
+                        .unwrap(),
+                    ))
                     .unwrap();
                 return Ok(response);
             }
-        }
-    };
-
-    let mut row_list: Vec<(i32, String, String, i32, i32, String)> =
-        Vec::with_capacity(1);
-    for row in query_result.iter() {
-        let id: i32 = row.try_get("id").unwrap();
-        let email: String = row.try_get("email").unwrap();
-        let password: String = row.try_get("password").unwrap();
-        if password != hash {
-            error!("BAD PASSWORD FOUND DURING USER CREATION:\npassword=\n{password}\n!=\nsalt=\n{hash}");
+        };
+    if created_user.password != hash {
+        error!(
+            "BAD PASSWORD FOUND DURING USER CREATION:\npassword=\n{}\n!=\n\
+            salt=\n{hash}",
+            created_user.password
+        );
+        let response = Response::builder()
+            .status(400)
+            .body(Body::from(
+                serde_json::to_string(&ApiResUserLogin {
+                    user_id: -1,
+                    email: "".to_string(),
+                    state: -1,
+                    verified: -1,
+                    role: "".to_string(),
+                    token: "".to_string(),
+                    msg: ("User login failed - invalid password").to_string(),
+                    error_code: Some(ApiErrorCode::InvalidCredentials),
+                    challenge: Vec::new(),
+                })
+                .unwrap(),
+            ))
+            .unwrap();
+        return Ok(response);
+    }
+    let user_id = created_user.id;
+    let user_email = created_user.email.clone();
+    let user_token = match create_user_token(
+        tracking_label,
+        config,
+        &conn,
+        &user_email,
+        user_id,
+        &get_user_session_metadata(headers, remote_addr, &config.geo_ip),
+        "",
+    )
+    .await
+    {
+        Ok(user_token) => user_token,
+        Err(_) => {
             let response = Response::builder()
-                .status(400)
+                .status(500)
                 .body(Body::from(
-                    serde_json::to_string(&ApiResUserLogin {
-                        user_id: -1,
-                        email: "".to_string(),
-                        state: -1,
-                        verified: -1,
-                        role: "".to_string(),
-                        token: "".to_string(),
-                        msg: ("User login failed - invalid password")
-                            .to_string(),
-                    })
-                    .unwrap(),
-                ))
+                    serde_json::to_string(
+                        &ApiResUserLogin {
+                            user_id: -1,
+                            email: "".to_string(),
+                            state: -1,
+                            verified: -1,
+                            role: "".to_string(),
+                            token: "".to_string(),
+                            msg: format!("User token creation failed - {user_id} {user_email}"),
+                            error_code: Some(ApiErrorCode::InternalError),
+                            challenge: Vec::new(),
+                        }
+                    ).unwrap()))
                 .unwrap();
             return Ok(response);
         }
-        let user_state: i32 = row.try_get("state").unwrap();
-        let user_verified_db: i32 = row.try_get("verified").unwrap();
-        let role: String = row.try_get("role").unwrap();
-        row_list.push((id, email, password, user_state, user_verified_db, role))
-    }
-    if row_list.is_empty() {
-        let response = Response::builder()
-            .status(400)
-            .body(Body::from(
-                serde_json::to_string(
-                    &ApiResUserLogin {
-                        user_id: -1,
-                        email: "".to_string(),
-                        state: -1,
-                        verified: -1,
-                        role: "".to_string(),
-                        token: "".to_string(),
-                        msg: format!(
-                            "User creation failed - user does not exist with email={}",
-                                user_object.email)
-                    }
-                ).unwrap()))
-            .unwrap();
-        Ok(response)
-    } else {
-        let user_id = row_list[0].0;
-        let user_email = row_list[0].1.clone();
-        let user_token = match create_user_token(
+    };
+    if user_verification_enabled {
+        match upsert_user_verification(
             tracking_label,
             config,
-            &conn,
-            &user_email,
             user_id,
+            &user_email,
+            true, // is new user flag
+            0,    // not verified
+            &conn,
         )
         .await
         {
-            Ok(user_token) => user_token,
-            Err(_) => {
-                let response = Response::builder()
-                    .status(500)
-                    .body(Body::from(
-                        serde_json::to_string(
-                            &ApiResUserLogin {
-                                user_id: -1,
-                                email: "".to_string(),
-                                state: -1,
-                                verified: -1,
-                                role: "".to_string(),
-                                token: "".to_string(),
-                                msg: format!("User token creation failed - {user_id} {user_email}"),
-                            }
-                        ).unwrap()))
-                    .unwrap();
-                return Ok(response);
-            }
-        };
-        if user_verification_enabled {
-            match upsert_user_verification(
-                tracking_label,
-                user_id,
-                &user_email,
-                true, // is new user flag
-                0,    // not verified
-                &conn,
-            )
-            .await
-            {
-                Ok(verification_token) => {
+            Ok(verification_token) => {
+                // only the token hash is stored so the verify
+                // url is only logged for local debugging
+                if std::env::var("DEBUG").unwrap_or_else(|_| "0".to_string())
+                    == *"1"
+                {
                     info!(
                         "{tracking_label} - verify token created user={user_id} \
                         {user_email} - verify url:\
                         curl -ks \
                         \"https://{}/user/verify?u={user_id}&t={verification_token}\" \
                         | jq",
-                            get_server_address("api"));
-                }
-                Err(e) => {
-                    error!(
-                        "{tracking_label} - \
-                        failed to generate verify token for user {user_id} \
-                        {user_email} with err='{e}'"
+                        get_server_address("api"));
+                } else {
+                    info!(
+                        "{tracking_label} - verify token created \
+                        user={user_id} {user_email}"
                     );
                 }
-            };
-        }
+                config
+                    .email_templates
+                    .send_user_email(
+                        tracking_label,
+                        &config.events,
+                        kafka_pool,
+                        user_id,
+                        "verify",
+                        &created_user.locale,
+                        &user_email,
+                        serde_json::json!({
+                            "link": EmailTemplates::get_link(
+                                &config.email_templates.verify_url,
+                                user_id,
+                                &verification_token
+                            ),
+                        }),
+                    )
+                    .await;
+            }
+            Err(e) => {
+                error!(
+                    "{tracking_label} - \
+                    failed to generate verify token for user {user_id} \
+                    {user_email} with err='{e}'"
+                );
+            }
+        };
+    }
 
-        // if enabled, publish to kafka
-        if config.kafka_publish_events {
-            publish_msg(
-                kafka_pool,
-                // topic
-                "user.events",
-                // partition key
-                &format!("user-{}", user_id),
-                // optional headers stored in: Option<HashMap<String, String>>
-                None,
-                // payload in the message
-                &format!("USER_CREATE user={user_id} email={user_email}"),
-            )
-            .await;
-        }
+    config
+        .events
+        .user_created(kafka_pool, user_id, &user_email)
+        .await;
 
-        let response = Response::builder()
-            .status(201)
-            .body(Body::from(
-                serde_json::to_string(&ApiResUserLogin {
-                    user_id,
-                    email: user_email,
-                    state: row_list[0].3,
-                    verified: row_list[0].4,
-                    role: row_list[0].5.clone(),
-                    token: user_token,
-                    msg: "success".to_string(),
-                })
-                .unwrap(),
-            ))
-            .unwrap();
-        Ok(response)
-    }
+    let response = config
+        .token_cookie
+        .set_token_cookie(Response::builder().status(201), &user_token)
+        .body(Body::from(
+            serde_json::to_string(&ApiResUserLogin {
+                user_id,
+                email: user_email,
+                state: created_user.state,
+                verified: created_user.verified,
+                role: created_user.role,
+                token: config.token_cookie.get_response_token(user_token),
+                msg: "success".to_string(),
+                error_code: None,
+                challenge: Vec::new(),
+            })
+            .unwrap(),
+        ))
+        .unwrap();
+    Ok(response)
 }
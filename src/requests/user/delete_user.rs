@@ -26,11 +26,17 @@ use hyper::Response;
 use serde::Deserialize;
 use serde::Serialize;
 
-use kafka_threadpool::kafka_publisher::KafkaPublisher;
-
 use crate::core::core_config::CoreConfig;
-use crate::kafka::publish_msg::publish_msg;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::monitoring::otel::trace_db_query;
 use crate::requests::auth::validate_user_token::validate_user_token;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::validation::field_rules::check_email;
+use crate::requests::validation::field_rules::check_id;
+use crate::requests::validation::normalize_email::normalize_email;
+use crate::requests::validation::validate_api_req::validate_api_req;
+use crate::requests::validation::validate_api_req::ApiFieldError;
+use crate::requests::validation::validate_api_req::ApiReqValidate;
 
 /// ApiReqUserDelete
 ///
@@ -68,6 +74,19 @@ pub struct ApiReqUserDelete {
     pub email: String,
 }
 
+impl ApiReqValidate for ApiReqUserDelete {
+    /// validate
+    ///
+    /// Require a positive `user_id` and a valid `email`
+    ///
+    fn validate(&self) -> Vec<ApiFieldError> {
+        let mut errors = Vec::new();
+        check_id(&mut errors, "user_id", self.user_id);
+        check_email(&mut errors, "email", &self.email);
+        errors
+    }
+}
+
 /// ApiResUserDelete
 ///
 /// # Response type for delete_user
@@ -95,6 +114,8 @@ pub struct ApiReqUserDelete {
 ///   (`0` - not-verified, `1` - verified)
 /// * `role` - `String` - user role
 /// * `msg` - `String` - help message
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   machine-readable failure code (omitted on success)
 ///
 #[derive(Serialize, Deserialize, Clone)]
 pub struct ApiResUserDelete {
@@ -104,6 +125,8 @@ pub struct ApiResUserDelete {
     pub verified: i32,
     pub role: String,
     pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ApiErrorCode>,
 }
 
 /// delete_user
@@ -127,7 +150,7 @@ pub struct ApiResUserDelete {
 /// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
 ///   db threadpool with required tls encryption
 /// * `kafka_pool` -
-///   [`KafkaPublisher`](kafka_threadpool::kafka_publisher::KafkaPublisher)
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
 ///   for asynchronously publishing messages to the connected kafka cluster
 /// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
 ///   hashmap containing headers in key-value pairs
@@ -169,7 +192,8 @@ pub async fn delete_user(
     headers: &HeaderMap<HeaderValue>,
     bytes: &[u8],
 ) -> std::result::Result<Response<Body>, Infallible> {
-    let user_object: ApiReqUserDelete = match serde_json::from_slice(bytes) {
+    let mut user_object: ApiReqUserDelete = match serde_json::from_slice(bytes)
+    {
         Ok(uo) => uo,
         Err(_) => {
             let response = Response::builder()
@@ -184,6 +208,7 @@ pub async fn delete_user(
                         msg: ("User delete failed - please ensure user_id \
                                 and user_email were set on the request")
                             .to_string(),
+                        error_code: Some(ApiErrorCode::InvalidRequest),
                     })
                     .unwrap(),
                 ))
@@ -192,6 +217,11 @@ pub async fn delete_user(
         }
     };
 
+    user_object.email = normalize_email(&user_object.email);
+    if let Some(response) = validate_api_req(tracking_label, &user_object) {
+        return Ok(response);
+    }
+
     let conn = db_pool.get().await.unwrap();
     let _token = match validate_user_token(
         tracking_label,
@@ -203,7 +233,7 @@ pub async fn delete_user(
     .await
     {
         Ok(_token) => _token,
-        Err(_) => {
+        Err(error_code) => {
             let response = Response::builder()
                 .status(400)
                 .body(Body::from(
@@ -215,6 +245,7 @@ pub async fn delete_user(
                         role: "".to_string(),
                         msg: ("User delete failed due to invalid token")
                             .to_string(),
+                        error_code: Some(error_code),
                     })
                     .unwrap(),
                 ))
@@ -239,31 +270,34 @@ pub async fn delete_user(
         user_object.email
     );
     let stmt = conn.prepare(&query).await.unwrap();
-    let query_result = match conn.query(&stmt, &[]).await {
-        Ok(query_result) => query_result,
-        Err(e) => {
-            let err_msg = format!("{}", e);
-            let response = Response::builder()
-                .status(500)
-                .body(Body::from(
-                    serde_json::to_string(&ApiResUserDelete {
-                        user_id: -1,
-                        email: "".to_string(),
-                        state: -1,
-                        verified: -1,
-                        role: "".to_string(),
-                        msg: format!(
-                            "User delete failed for email={} \
+    let query_result =
+        match trace_db_query(&query, conn.query(&stmt, &[])).await {
+            Ok(query_result) => query_result,
+            Err(e) => {
+                let err_msg = format!("{}", e);
+                let response = Response::builder()
+                    .status(500)
+                    .body(Body::from(
+                        serde_json::to_string(&ApiResUserDelete {
+                            user_id: -1,
+                            email: "".to_string(),
+                            state: -1,
+                            verified: -1,
+                            role: "".to_string(),
+                            msg: format!(
+                                "User delete failed for email={} \
                             with err='{err_msg}'",
-                            user_object.email
-                        ),
-                    })
-                    .unwrap(),
-                ))
-                .unwrap();
-            return Ok(response);
-        }
-    };
+                                user_object.email
+                            ),
+                            error_code: Some(ApiErrorCode::InternalError),
+                        })
+                        .unwrap(),
+                    ))
+                    .unwrap();
+                return Ok(response);
+            }
+        };
+    config.user_cache.invalidate_user(user_object.user_id).await;
     let mut row_list: Vec<(i32, String, i32, i32, String)> =
         Vec::with_capacity(1);
     if let Some(row) = query_result.first() {
@@ -287,27 +321,17 @@ pub async fn delete_user(
                         role: "".to_string(),
                         msg: format!(
                             "User creation failed - unable to find user by email={}",
-                                user_object.email)
+                                user_object.email),
+                        error_code: Some(ApiErrorCode::UserNotFound),
                     }
                 ).unwrap()))
             .unwrap();
         Ok(response)
     } else {
-        // if enabled, publish to kafka
-        if config.kafka_publish_events {
-            publish_msg(
-                kafka_pool,
-                // topic
-                "user.events",
-                // partition key
-                &format!("user-{}", user_object.user_id),
-                // optional headers stored in: Option<HashMap<String, String>>
-                None,
-                // payload in the message
-                &format!("USER_DELETE user={}", user_object.user_id),
-            )
+        config
+            .events
+            .user_deleted(kafka_pool, user_object.user_id)
             .await;
-        }
 
         let response = Response::builder()
             .status(204)
@@ -319,6 +343,7 @@ pub async fn delete_user(
                     verified: row_list[0].3,
                     role: row_list[0].4.clone(),
                     msg: "success".to_string(),
+                    error_code: None,
                 })
                 .unwrap(),
             ))
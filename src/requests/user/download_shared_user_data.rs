@@ -0,0 +1,131 @@
+//! Module for downloading a user's s3 data file through a
+//! public download link
+//!
+//! ## Download a shared user data file
+//!
+//! Download the file for a ``ready`` ``users_data`` record with the token from an unexpired public download link (no login required)
+//!
+//! - URL path: ``/user/data/shared/TOKEN``
+//! - Method: ``GET``
+//! - Handler: [`download_shared_user_data`](crate::requests::user::download_shared_user_data::download_shared_user_data)
+//! - Request: none (the link token is in the url path)
+//! - Response: the file contents (errors return an [`ApiResUserDownloadData`](crate::requests::user::download_user_data::ApiResUserDownloadData))
+//!
+use std::convert::Infallible;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use hyper::Body;
+use hyper::Response;
+
+use crate::core::core_config::CoreConfig;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::requests::models::api_error::ApiError;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::models::user_data_repo::UserDataRepo;
+use crate::requests::user::download_user_data::get_user_data_download_error_response;
+use crate::requests::user::download_user_data::get_user_data_download_response;
+use crate::utils::hash_token::hash_token;
+
+/// download_shared_user_data
+///
+/// Download the file for a `users_data` record shared
+/// through a public download link created with
+/// [`share_user_data`](crate::requests::user::share_user_data::share_user_data).
+/// Anyone with the link token can download the file until
+/// the link expires or the owner removes it with
+/// [`unshare_user_data`](crate::requests::user::unshare_user_data::unshare_user_data).
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
+///   db threadpool with required tls encryption
+/// * `kafka_pool` -
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+///   for asynchronously publishing messages to the connected kafka cluster
+/// * `request_uri` - `&str` - url path with the link token
+///
+/// # Returns
+///
+/// ## download_shared_user_data on Success Returns
+///
+/// hyper [`Response`](hyper::Response)
+/// containing the file contents within the
+/// [`Body`](hyper::Body) and a
+/// `200` HTTP status code
+///
+/// Ok([`Response`](hyper::Response))
+///
+/// # Errors
+///
+/// ## download_shared_user_data on Failure Returns
+///
+/// All errors return as a
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResUserDownloadData`](crate::requests::user::download_user_data::ApiResUserDownloadData)
+/// dictionary with a
+/// `non-200` HTTP status code (`404` if the link does not
+/// exist or expired)
+///
+/// Err([`Response`](hyper::Response))
+///
+pub async fn download_shared_user_data(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    kafka_pool: &KafkaPublisher,
+    request_uri: &str,
+) -> std::result::Result<Response<Body>, Infallible> {
+    let token = request_uri.strip_prefix("/user/data/shared/").unwrap_or("");
+    if token.is_empty() || token.contains('/') {
+        return Ok(get_user_data_download_error_response(
+            400,
+            -1,
+            ("Invalid download link").to_string(),
+            ApiErrorCode::InvalidRequest,
+        ));
+    }
+
+    let token_hash = hash_token(token, &config.server_password_salt);
+    let conn = db_pool.get().await.unwrap();
+    let user_data = match UserDataRepo::new(&conn)
+        .find_by_share_token(tracking_label, &token_hash)
+        .await
+    {
+        Ok(user_data) => user_data,
+        Err(ApiError::NotFound(_)) => {
+            return Ok(get_user_data_download_error_response(
+                404,
+                -1,
+                ("User data download failed - \
+                the download link does not exist or expired")
+                    .to_string(),
+                ApiErrorCode::NotFound,
+            ));
+        }
+        Err(e) => {
+            return Ok(get_user_data_download_error_response(
+                e.status_code(),
+                -1,
+                format!("User data download failed with err='{e}'"),
+                e.error_code(),
+            ));
+        }
+    };
+    Ok(get_user_data_download_response(
+        tracking_label,
+        config,
+        kafka_pool,
+        &conn,
+        user_data.user_id,
+        &user_data,
+        "share_token",
+    )
+    .await)
+}
@@ -0,0 +1,476 @@
+//! Module for downloading a user's s3 data file
+//!
+//! ## Download a user data file
+//!
+//! Download the file for a ``ready`` ``users_data`` record the user owns or can read through a ``users_data_acl`` share or ``users_data_shares`` user share
+//!
+//! - URL path: ``/user/data/DATAID/download``
+//! - Method: ``GET``
+//! - Handler: [`download_user_data`](crate::requests::user::download_user_data::download_user_data)
+//! - Request: none (uses the token header)
+//! - Response: the file contents (errors return an [`ApiResUserDownloadData`](crate::requests::user::download_user_data::ApiResUserDownloadData))
+//!
+//! ## Download metering
+//!
+//! Each download is stored in the ``users_data_access`` table
+//! (see [`insert_user_data_access`](crate::requests::models::user_data_access::insert_user_data_access))
+//! and adds to the ``user_data_downloads_total`` and
+//! ``user_data_egress_bytes_total`` prometheus counters. The
+//! counters are labeled with the file owner's ``user_id``
+//! (capped by ``METRICS_MAX_LABEL_VALUES``, see
+//! [`get_metric_label`](crate::monitoring::metric_labels::get_metric_label))
+//! and the ``access`` (``owner``, ``acl`` or ``share_token``).
+//!
+use std::convert::Infallible;
+
+use postgres_native_tls::MakeTlsConnector;
+use tokio_postgres::Client;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Response;
+
+use lazy_static::lazy_static;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::archive::user_data_lifecycle::get_bucket_and_key_from_sloc;
+use crate::core::core_config::CoreConfig;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::monitoring::metric_labels::get_metric_label;
+use crate::monitoring::metric_types::register_int_counter_vec;
+use crate::monitoring::metric_types::IntCounterVec;
+use crate::monitoring::otel::trace_client_span;
+use crate::requests::auth::validate_user_token::validate_user_token;
+use crate::requests::models::api_error::ApiError;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::models::user::get_user_by_id;
+use crate::requests::models::user_data::ModelUserData;
+use crate::requests::models::user_data_access::insert_user_data_access;
+use crate::requests::models::user_data_repo::UserDataRepo;
+use crate::requests::models::user_session::get_user_session_by_token;
+use crate::utils::circuit_breaker::is_circuit_open_error;
+
+lazy_static! {
+    pub static ref USER_DATA_DOWNLOADS_COUNTER_VEC: IntCounterVec =
+        register_int_counter_vec!(
+            "user_data_downloads_total",
+            "Number of users_data file downloads by the file owner's \
+            user id and access.",
+            &["user_id", "access"]
+        )
+        .unwrap();
+    pub static ref USER_DATA_EGRESS_BYTES_COUNTER_VEC: IntCounterVec =
+        register_int_counter_vec!(
+            "user_data_egress_bytes_total",
+            "Bytes sent for users_data file downloads by the file \
+            owner's user id and access.",
+            &["user_id", "access"]
+        )
+        .unwrap();
+}
+
+/// ApiResUserDownloadData
+///
+/// # Response type for download_user_data and download_shared_user_data
+///
+/// Successful downloads return the file contents. Failed
+/// downloads return this type.
+///
+/// # Usage
+///
+/// This type is the serialized output for the functions:
+/// [`download_user_data`](crate::requests::user::download_user_data::download_user_data]
+/// and
+/// [`download_shared_user_data`](crate::requests::user::download_shared_user_data::download_shared_user_data]
+/// and contained within the
+/// hyper [`Body`](hyper::Body)
+/// of the
+/// hyper [`Response`](hyper::Response)
+/// sent back to the client.
+///
+/// # Arguments
+///
+/// * `data_id` - `i32` - `users_data.id` (`-1` if unknown)
+/// * `msg` - `String` - help message
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   machine-readable failure code (omitted on success)
+///
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApiResUserDownloadData {
+    pub data_id: i32,
+    pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ApiErrorCode>,
+}
+
+/// get_user_data_download_error_response
+///
+/// Build a json error response for a failed download
+///
+/// # Arguments
+///
+/// * `status` - `u16` - HTTP status code
+/// * `data_id` - `i32` - `users_data.id` (`-1` if unknown)
+/// * `msg` - `String` - message for the client
+/// * `error_code` - [`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode) -
+///   machine-readable failure code
+///
+/// # Returns
+///
+/// [`Response`](hyper::Response) containing a json-serialized
+/// [`ApiResUserDownloadData`](crate::requests::user::download_user_data::ApiResUserDownloadData)
+///
+pub fn get_user_data_download_error_response(
+    status: u16,
+    data_id: i32,
+    msg: String,
+    error_code: ApiErrorCode,
+) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::from(
+            serde_json::to_string(&ApiResUserDownloadData {
+                data_id,
+                msg,
+                error_code: Some(error_code),
+            })
+            .unwrap(),
+        ))
+        .unwrap()
+}
+
+/// get_user_data_download_response
+///
+/// Send the s3 file for a `users_data` record the caller
+/// can access, store the download in ``users_data_access``,
+/// add it to the egress counters and publish a
+/// ``DATA_DOWNLOADED`` audit event. Only ``ready`` records
+/// can be downloaded.
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `kafka_pool` -
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+///   for asynchronously publishing messages to the connected kafka cluster
+/// * `client` - [`Client`](tokio_postgres::Client) - db
+///   connection
+/// * `user_id` - `i32` - user downloading the file (the
+///   owner's id for public link downloads)
+/// * `user_data` - [`ModelUserData`](crate::requests::models::user_data::ModelUserData) -
+///   the record to download
+/// * `access` - `&str` - how access was granted: ``owner``,
+///   ``acl`` or ``share_token``
+///
+/// # Returns
+///
+/// [`Response`](hyper::Response) with the file contents
+/// and a `200` HTTP status code or a json-serialized
+/// [`ApiResUserDownloadData`](crate::requests::user::download_user_data::ApiResUserDownloadData)
+/// with a `non-200` HTTP status code (`409` if the record is
+/// not ``ready`` and `503` with a ``Retry-After`` header
+/// while the s3 circuit breaker is open)
+///
+pub async fn get_user_data_download_response(
+    tracking_label: &str,
+    config: &CoreConfig,
+    kafka_pool: &KafkaPublisher,
+    client: &Client,
+    user_id: i32,
+    user_data: &ModelUserData,
+    access: &str,
+) -> Response<Body> {
+    let data_id = user_data.data_id;
+    if user_data.status != "ready" {
+        return get_user_data_download_error_response(
+            409,
+            data_id,
+            format!(
+                "User data download failed - data_id={data_id} \
+                has status={} and only ready files can be downloaded",
+                user_data.status
+            ),
+            ApiErrorCode::Conflict,
+        );
+    }
+    let (bucket, key) = match get_bucket_and_key_from_sloc(&user_data.sloc) {
+        Some((bucket, key)) => (bucket, key),
+        None => {
+            error!(
+                "{tracking_label} - \
+                invalid sloc={} for data_id={data_id}",
+                user_data.sloc
+            );
+            return get_user_data_download_error_response(
+                500,
+                data_id,
+                format!("User data download failed for data_id={data_id}"),
+                ApiErrorCode::InternalError,
+            );
+        }
+    };
+    let contents = match trace_client_span(
+        "s3 download",
+        vec![("s3.bucket", bucket.clone()), ("s3.key", key.clone())],
+        config.object_store.download_to_memory(&bucket, &key),
+    )
+    .await
+    {
+        Ok(contents) => contents,
+        Err(e) if is_circuit_open_error(&e) => {
+            warn!(
+                "{tracking_label} - \
+                rejected download data_id={data_id} \
+                with err='{e}'"
+            );
+            let mut response = get_user_data_download_error_response(
+                503,
+                data_id,
+                format!(
+                    "User data download failed for data_id={data_id} - \
+                    file storage is unavailable, please retry later"
+                ),
+                ApiErrorCode::ServiceUnavailable,
+            );
+            response.headers_mut().insert(
+                "Retry-After",
+                config
+                    .s3_circuit_breaker
+                    .get_retry_after_seconds()
+                    .max(1)
+                    .into(),
+            );
+            return response;
+        }
+        Err(e) => {
+            error!(
+                "{tracking_label} - \
+                failed to download data_id={data_id} \
+                with err='{e}'"
+            );
+            return get_user_data_download_error_response(
+                500,
+                data_id,
+                format!("User data download failed for data_id={data_id}"),
+                ApiErrorCode::InternalError,
+            );
+        }
+    };
+    let num_bytes = contents.len() as i64;
+    // public link downloads have no signed-in user
+    let downloader_user_id = match access {
+        "share_token" => None,
+        _ => Some(user_id),
+    };
+    // a failed audit insert should not fail the download
+    if let Err(e) = insert_user_data_access(
+        tracking_label,
+        data_id,
+        user_data.user_id,
+        downloader_user_id,
+        access,
+        num_bytes,
+        client,
+    )
+    .await
+    {
+        error!("{e}");
+    }
+    let owner_label = get_metric_label(
+        "user_data_egress_bytes_total",
+        &user_data.user_id.to_string(),
+    );
+    USER_DATA_DOWNLOADS_COUNTER_VEC
+        .with_label_values(&[&owner_label, access])
+        .inc();
+    USER_DATA_EGRESS_BYTES_COUNTER_VEC
+        .with_label_values(&[&owner_label, access])
+        .inc_by(num_bytes as u64);
+    config
+        .events
+        .data_downloaded(kafka_pool, user_id, data_id, num_bytes, "", access)
+        .await;
+
+    let content_type = match user_data.content_type.is_empty() {
+        true => "application/octet-stream",
+        false => user_data.content_type.as_str(),
+    };
+    // keep the header value ascii
+    let filename: String = user_data
+        .filename
+        .chars()
+        .filter(|c| (c.is_ascii_graphic() || *c == ' ') && *c != '"')
+        .collect();
+    Response::builder()
+        .status(200)
+        .header("Content-Type", content_type)
+        .header(
+            "Content-Disposition",
+            format!("attachment; filename=\"{filename}\""),
+        )
+        .header("Content-Length", num_bytes)
+        .body(Body::from(contents))
+        .unwrap()
+}
+
+/// download_user_data
+///
+/// Download the file for a `users_data` record the user
+/// owns or can read through a share
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
+///   db threadpool with required tls encryption
+/// * `kafka_pool` -
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+///   for asynchronously publishing messages to the connected kafka cluster
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+/// * `request_uri` - `&str` - url path with the data id
+///
+/// # Returns
+///
+/// ## download_user_data on Success Returns
+///
+/// hyper [`Response`](hyper::Response)
+/// containing the file contents within the
+/// [`Body`](hyper::Body) and a
+/// `200` HTTP status code
+///
+/// Ok([`Response`](hyper::Response))
+///
+/// # Errors
+///
+/// ## download_user_data on Failure Returns
+///
+/// All errors return as a
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResUserDownloadData`](crate::requests::user::download_user_data::ApiResUserDownloadData)
+/// dictionary with a
+/// `non-200` HTTP status code (`404` if the record does
+/// not exist or is not shared with the user)
+///
+/// Err([`Response`](hyper::Response))
+///
+pub async fn download_user_data(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    kafka_pool: &KafkaPublisher,
+    headers: &HeaderMap<HeaderValue>,
+    request_uri: &str,
+) -> std::result::Result<Response<Body>, Infallible> {
+    let data_id = request_uri
+        .strip_prefix("/user/data/")
+        .and_then(|v| v.strip_suffix("/download"))
+        .and_then(|v| v.parse::<i32>().ok())
+        .unwrap_or(-1);
+    if data_id <= 0 {
+        return Ok(get_user_data_download_error_response(
+            400,
+            -1,
+            ("Invalid data id must be a positive integer").to_string(),
+            ApiErrorCode::InvalidRequest,
+        ));
+    }
+
+    let token = &config.token_cookie.get_request_token(headers);
+
+    let conn = db_pool.get().await.unwrap();
+    let user_id =
+        match get_user_session_by_token(tracking_label, token, &conn).await {
+            Ok((_, user_id)) => user_id,
+            Err(_) => -1,
+        };
+    let valid_token = match user_id > 0 {
+        true => {
+            validate_user_token(tracking_label, config, &conn, headers, user_id)
+                .await
+        }
+        false => Err(ApiErrorCode::InvalidToken),
+    };
+    if let Err(error_code) = valid_token {
+        return Ok(get_user_data_download_error_response(
+            400,
+            data_id,
+            ("User data download failed due to invalid token").to_string(),
+            error_code,
+        ));
+    }
+
+    let user_model = match get_user_by_id(tracking_label, user_id, &conn).await
+    {
+        Ok(user_model) => user_model,
+        Err(err_msg) => {
+            error!(
+                "{tracking_label} - \
+                failed to find user {user_id} for data download \
+                with err='{err_msg}'"
+            );
+            return Ok(get_user_data_download_error_response(
+                400,
+                data_id,
+                format!(
+                    "User data download failed - \
+                    unable to find user with id: {user_id}"
+                ),
+                ApiErrorCode::UserNotFound,
+            ));
+        }
+    };
+
+    let user_data = match UserDataRepo::new(&conn)
+        .find_by_id(tracking_label, user_id, &user_model.role, data_id)
+        .await
+    {
+        Ok(user_data) => user_data,
+        Err(ApiError::NotFound(_)) => {
+            return Ok(get_user_data_download_error_response(
+                404,
+                data_id,
+                format!(
+                    "User data download failed - \
+                    unable to find data_id={data_id} \
+                    for user_id={user_id}"
+                ),
+                ApiErrorCode::NotFound,
+            ));
+        }
+        Err(e) => {
+            return Ok(get_user_data_download_error_response(
+                e.status_code(),
+                data_id,
+                format!(
+                    "User data download failed for data_id={data_id} \
+                    with err='{e}'"
+                ),
+                e.error_code(),
+            ));
+        }
+    };
+    let access = match user_data.user_id == user_id {
+        true => "owner",
+        false => "acl",
+    };
+    Ok(get_user_data_download_response(
+        tracking_label,
+        config,
+        kafka_pool,
+        &conn,
+        user_id,
+        &user_data,
+        access,
+    )
+    .await)
+}
@@ -0,0 +1,305 @@
+//! Module for users that forgot their password
+//!
+//! ## Email a One-Time-Use Password Reset Token (OTP) Without Logging In
+//!
+//! Create a one-time-use password reset token for the user with the email and publish it in a ``USER_CREATE_OTP`` user event for a mail service (with a ``USER_OTP_RESET_URL`` link when it is set). The response is always the same ``202`` so clients cannot find out which emails are registered. The user changes their password by sending the token to ``/user/password/change`` without a Bearer token. Logged-in users can keep using ``/user/password/reset``.
+//!
+//! - URL path: ``/user/password/forgot``
+//! - Method: ``POST``
+//! - Handler: [`forgot_password`](crate::requests::user::forgot_password::forgot_password)
+//! - Request: [`ApiReqUserForgotPassword`](crate::requests::user::forgot_password::ApiReqUserForgotPassword)
+//! - Response: [`ApiResUserForgotPassword`](crate::requests::user::forgot_password::ApiResUserForgotPassword)
+//!
+
+use std::convert::Infallible;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Response;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::core::core_config::CoreConfig;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::models::user::get_active_user_by_email;
+use crate::requests::user::create_otp::get_otp_email_details;
+use crate::requests::user::create_otp::insert_user_otp;
+use crate::requests::user::create_otp::send_otp_email;
+use crate::requests::validation::field_rules::check_email;
+use crate::requests::validation::normalize_email::normalize_email;
+use crate::requests::validation::validate_api_req::validate_api_req;
+use crate::requests::validation::validate_api_req::ApiFieldError;
+use crate::requests::validation::validate_api_req::ApiReqValidate;
+
+/// the response for every valid request
+pub const FORGOT_PASSWORD_MSG: &str = "If the email is registered, a \
+    one-time-password was sent to it";
+
+/// ApiReqUserForgotPassword
+///
+/// # Request Type For forgot_password
+///
+/// Email a one-time-use password reset token to a user
+/// that cannot log in
+///
+/// This type is the deserialized input for:
+/// [`forgot_password`](crate::requests::user::forgot_password::forgot_password]
+///
+/// # Arguments
+///
+/// * `email` - `String` - user email
+///
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApiReqUserForgotPassword {
+    // users.email
+    pub email: String,
+}
+
+impl ApiReqValidate for ApiReqUserForgotPassword {
+    /// validate
+    ///
+    /// Require a valid `email`
+    ///
+    fn validate(&self) -> Vec<ApiFieldError> {
+        let mut errors = Vec::new();
+        check_email(&mut errors, "email", &self.email);
+        errors
+    }
+}
+
+/// ApiResUserForgotPassword
+///
+/// # Response type for forgot_password
+///
+/// The same message for registered and unknown emails
+///
+/// # Arguments
+///
+/// * `msg` - `String` - help message
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   machine-readable failure code (omitted on success)
+///
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApiResUserForgotPassword {
+    pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ApiErrorCode>,
+}
+
+/// forgot_password
+///
+/// Email a one-time-use token for resetting the password
+/// of the active user with the email (in the request's
+/// tenant). Unknown emails, inactive users, rate limited
+/// users (see
+/// [`OtpConfig`](crate::requests::user::otp_config::OtpConfig))
+/// and db errors are only logged, so every valid request
+/// gets the same response. The token is never returned to
+/// the client (``USER_OTP_DELIVERY`` is ignored).
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
+///   db threadpool with required tls encryption
+/// * `kafka_pool` -
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+///   for asynchronously publishing messages to the connected kafka cluster
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+/// * `bytes` - `&[u8]` - received bytes from the hyper
+///   [`Request`](hyper::Request)'s [`Body`](hyper::Body)
+///
+/// # Returns
+///
+/// ## Success
+///
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResUserForgotPassword`](crate::requests::user::forgot_password::ApiResUserForgotPassword)
+/// dictionary within the
+/// [`Body`](hyper::Body) and a
+/// `202` HTTP status code
+///
+/// Ok([`Response`](hyper::Response))
+///
+/// # Errors
+///
+/// `400` for an invalid request and `503` when kafka user
+/// events are disabled (the token cannot be emailed)
+///
+/// Err([`Response`](hyper::Response))
+///
+pub async fn forgot_password(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    kafka_pool: &KafkaPublisher,
+    headers: &HeaderMap<HeaderValue>,
+    bytes: &[u8],
+) -> std::result::Result<Response<Body>, Infallible> {
+    let mut req_object: ApiReqUserForgotPassword =
+        match serde_json::from_slice(bytes) {
+            Ok(uo) => uo,
+            Err(_) => {
+                let response = Response::builder()
+                    .status(400)
+                    .body(Body::from(
+                        serde_json::to_string(&ApiResUserForgotPassword {
+                            msg: ("User forgot password failed - \
+                            please ensure \
+                            email was set correctly in the request")
+                                .to_string(),
+                            error_code: Some(ApiErrorCode::InvalidRequest),
+                        })
+                        .unwrap(),
+                    ))
+                    .unwrap();
+                return Ok(response);
+            }
+        };
+
+    req_object.email = normalize_email(&req_object.email);
+    if let Some(response) = validate_api_req(tracking_label, &req_object) {
+        return Ok(response);
+    }
+
+    // the token is only delivered by the mail service
+    if !config.events.enabled {
+        error!(
+            "{tracking_label} - \
+            unable to email a forgot password one-time-password - \
+            /user/password/forgot requires KAFKA_PUBLISH_EVENTS=1"
+        );
+        let response = Response::builder()
+            .status(503)
+            .body(Body::from(
+                serde_json::to_string(&ApiResUserForgotPassword {
+                    msg: ("User forgot password failed - \
+                        email delivery is not available")
+                        .to_string(),
+                    error_code: Some(ApiErrorCode::FeatureDisabled),
+                })
+                .unwrap(),
+            ))
+            .unwrap();
+        return Ok(response);
+    }
+
+    email_user_otp(
+        tracking_label,
+        config,
+        db_pool,
+        kafka_pool,
+        headers,
+        &req_object.email,
+    )
+    .await;
+
+    let response = Response::builder()
+        .status(202)
+        .body(Body::from(
+            serde_json::to_string(&ApiResUserForgotPassword {
+                msg: FORGOT_PASSWORD_MSG.to_string(),
+                error_code: None,
+            })
+            .unwrap(),
+        ))
+        .unwrap();
+    Ok(response)
+}
+
+/// email_user_otp
+///
+/// Create the one-time-use token for an active user and
+/// publish it for the mail service (errors are only logged)
+///
+async fn email_user_otp(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    kafka_pool: &KafkaPublisher,
+    headers: &HeaderMap<HeaderValue>,
+    email: &str,
+) {
+    let conn = match db_pool.get().await {
+        Ok(conn) => conn,
+        Err(e) => {
+            error!(
+                "{tracking_label} - \
+                forgot password failed to get a db connection \
+                with err='{e}'"
+            );
+            return;
+        }
+    };
+    let tenant_id = match config
+        .tenants
+        .get_tenant_id(tracking_label, headers, &conn)
+        .await
+    {
+        Ok(tenant_id) => tenant_id,
+        Err(err_msg) => {
+            warn!("{tracking_label} - forgot password - {err_msg}");
+            return;
+        }
+    };
+    let user_model =
+        match get_active_user_by_email(tracking_label, tenant_id, email, &conn)
+            .await
+        {
+            Ok(user_model) => user_model,
+            Err(_) => {
+                info!(
+                    "{tracking_label} - \
+                    forgot password - no active user with the email \
+                    in tenant_id={tenant_id}"
+                );
+                return;
+            }
+        };
+    let user_id = user_model.id;
+    match insert_user_otp(tracking_label, config, &conn, user_id, email).await {
+        Ok(user_otp) => {
+            config
+                .events
+                .publish_user_event(
+                    kafka_pool,
+                    user_id,
+                    "USER_CREATE_OTP",
+                    &get_otp_email_details(
+                        config,
+                        user_id,
+                        email,
+                        &user_otp.token,
+                    ),
+                )
+                .await;
+            send_otp_email(
+                tracking_label,
+                config,
+                kafka_pool,
+                &user_model,
+                &user_otp,
+            )
+            .await;
+        }
+        Err(e) => {
+            warn!(
+                "{tracking_label} - \
+                forgot password for user {user_id} failed \
+                status={} - {}",
+                e.status, e.msg
+            );
+        }
+    }
+}
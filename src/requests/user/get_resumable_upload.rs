@@ -0,0 +1,182 @@
+//! Module for getting the status of a resumable upload
+//!
+//! ## Get a resumable upload
+//!
+//! Return the ``received_bytes`` offset of an in-progress
+//! resumable upload so the client can resume it with the
+//! next ``Content-Range`` chunk.
+//!
+//! - URL path: ``/user/data/DATAID/chunks``
+//! - Method: ``GET``
+//! - Handler: [`get_resumable_upload`](crate::requests::user::get_resumable_upload::get_resumable_upload)
+//! - Request: none (uses the token header)
+//! - Response: [`ApiResUserResumableUpload`](crate::requests::user::resumable_upload::ApiResUserResumableUpload)
+//!
+use std::convert::Infallible;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Response;
+
+use crate::core::core_config::CoreConfig;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::models::user_data_upload::get_user_data_upload;
+use crate::requests::user::resumable_upload::get_resumable_upload_data_id;
+use crate::requests::user::resumable_upload::get_resumable_upload_response;
+use crate::requests::user::resumable_upload::get_resumable_upload_state_response;
+use crate::requests::user::resumable_upload::get_resumable_upload_user_id;
+use crate::requests::user::resumable_upload::ApiResUserResumableUpload;
+
+/// get_resumable_upload
+///
+/// Handles getting the stored offset of one of the
+/// user's in-progress resumable uploads. Completed and
+/// aborted uploads return a `404`.
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
+///   db threadpool with required tls encryption
+/// * `_kafka_pool` -
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+///   for asynchronously publishing messages to the connected kafka cluster
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+/// * `request_uri` - `&str` - url path with the data id
+///
+/// # Returns
+///
+/// ## get_resumable_upload on Success Returns
+///
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResUserResumableUpload`](crate::requests::user::resumable_upload::ApiResUserResumableUpload)
+/// dictionary within the
+/// [`Body`](hyper::Body) and a
+/// `200` HTTP status code
+///
+/// Ok([`Response`](hyper::Response))
+///
+/// # Errors
+///
+/// ## get_resumable_upload on Failure Returns
+///
+/// All errors return as a
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResUserResumableUpload`](crate::requests::user::resumable_upload::ApiResUserResumableUpload)
+/// dictionary with a
+/// `non-200` HTTP status code
+///
+/// Err([`Response`](hyper::Response))
+///
+pub async fn get_resumable_upload(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    _kafka_pool: &KafkaPublisher,
+    headers: &HeaderMap<HeaderValue>,
+    request_uri: &str,
+) -> std::result::Result<Response<Body>, Infallible> {
+    let data_id = match get_resumable_upload_data_id(request_uri, "chunks") {
+        Some(data_id) => data_id,
+        None => {
+            return Ok(get_resumable_upload_response(
+                400,
+                &ApiResUserResumableUpload {
+                    user_id: -1,
+                    data_id: -1,
+                    msg: ("Invalid data id must be a positive integer")
+                        .to_string(),
+                    error_code: Some(ApiErrorCode::InvalidRequest),
+                    ..Default::default()
+                },
+            ));
+        }
+    };
+
+    let conn = db_pool.get().await.unwrap();
+    let user_id = match get_resumable_upload_user_id(
+        tracking_label,
+        config,
+        &conn,
+        headers,
+    )
+    .await
+    {
+        Ok(user_id) => user_id,
+        Err(error_code) => {
+            return Ok(get_resumable_upload_response(
+                400,
+                &ApiResUserResumableUpload {
+                    user_id: -1,
+                    data_id,
+                    msg: ("User get resumable upload failed due to invalid token")
+                        .to_string(),
+                    error_code: Some(error_code),
+                    ..Default::default()
+                },
+            ));
+        }
+    };
+
+    let upload = match get_user_data_upload(
+        tracking_label,
+        user_id,
+        data_id,
+        &conn,
+    )
+    .await
+    {
+        Ok(Some(upload)) => upload,
+        Ok(None) => {
+            return Ok(get_resumable_upload_response(
+                    404,
+                    &ApiResUserResumableUpload {
+                        user_id,
+                        data_id,
+                        msg: format!(
+                            "User get resumable upload failed - no resumable upload \
+                            for data_id={data_id}"
+                        ),
+                        error_code: Some(ApiErrorCode::NotFound),
+                        ..Default::default()
+                    },
+                ));
+        }
+        Err(e) => {
+            error!("{e}");
+            return Ok(get_resumable_upload_response(
+                    500,
+                    &ApiResUserResumableUpload {
+                        user_id,
+                        data_id,
+                        msg: format!(
+                            "User get resumable upload failed for data_id={data_id}"
+                        ),
+                        error_code: Some(ApiErrorCode::InternalError),
+                        ..Default::default()
+                    },
+                ));
+        }
+    };
+    let received_bytes = upload.received_bytes;
+    let num_parts = upload.parts.len() as i64;
+    Ok(get_resumable_upload_state_response(
+        200,
+        &upload,
+        received_bytes,
+        num_parts,
+        "success".to_string(),
+        None,
+    ))
+}
@@ -0,0 +1,575 @@
+//! Module for reading a user data upload's metadata
+//! from either custom headers or a
+//! ``multipart/form-data`` body
+//!
+//! ## Upload with headers (raw body)
+//!
+//! Optional ``storage_class``, ``expire_days`` and
+//! ``expire_storage_class`` headers set the s3 storage class
+//! and lifecycle policy for the file. The ``Content-Type``
+//! header is stored as the file's content type. Optional
+//! ``tags`` (comma-delimited) and ``folder`` headers organize
+//! the file. An optional ``on_conflict`` header picks what
+//! happens when the folder already has the filename (see
+//! [`user_data_conflict`](crate::requests::models::user_data_conflict)).
+//!
+//! ```bash
+//! curl -X POST -H 'user_id: 1' -H 'filename: test.txt' \
+//!     --data-binary @test.txt "https://0.0.0.0:3000/user/data"
+//! ```
+//!
+//! ## Upload with multipart/form-data
+//!
+//! The ``metadata`` part is a json-serialized
+//! [`ApiReqUserUploadMetadata`](crate::requests::user::get_upload_metadata::ApiReqUserUploadMetadata)
+//! and the ``file`` part contains the file contents (its
+//! ``Content-Type`` is used unless the metadata sets a
+//! ``content_type``)
+//!
+//! ```bash
+//! curl -X POST \
+//!     -F 'metadata={"user_id":1,"filename":"test.txt"};type=application/json' \
+//!     -F 'file=@test.txt' "https://0.0.0.0:3000/user/data"
+//! ```
+//!
+use hyper::body::Bytes;
+use hyper::header::HeaderValue;
+use hyper::header::CONTENT_TYPE;
+use hyper::HeaderMap;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::is3::object_store::S3_STORAGE_CLASSES;
+use crate::requests::models::user_data_conflict::USER_DATA_CONFLICT_POLICIES;
+use crate::requests::user::read_upload_body::get_upload_too_large_msg;
+use crate::requests::user::validate_upload_header::validate_upload_header;
+use crate::requests::user::validate_upload_header::validate_upload_headers_size;
+use crate::requests::validation::field_rules::add_field_error;
+use crate::requests::validation::field_rules::check_data_filename;
+use crate::requests::validation::field_rules::check_data_folder;
+use crate::requests::validation::field_rules::check_data_tags;
+use crate::requests::validation::field_rules::check_id;
+use crate::requests::validation::field_rules::check_length;
+use crate::requests::validation::field_rules::check_one_of;
+use crate::requests::validation::field_rules::check_range;
+use crate::requests::validation::validate_api_req::ApiFieldError;
+use crate::requests::validation::validate_api_req::ApiReqValidate;
+
+/// max length for each metadata value
+/// (matches the ``users_data`` column sizes)
+pub const UPLOAD_METADATA_LIMITS: [(&str, usize); 13] = [
+    ("user_id", 11),
+    ("filename", 511),
+    ("data_type", 64),
+    ("encoding", 64),
+    ("comments", 512),
+    ("sloc", 1024),
+    ("s3_enable", 8),
+    ("storage_class", 32),
+    ("expire_days", 5),
+    ("expire_storage_class", 32),
+    ("tags", 1300),
+    ("folder", 1024),
+    ("on_conflict", 16),
+];
+
+/// max ``expire_days`` for an upload (about 100 years)
+pub const UPLOAD_MAX_EXPIRE_DAYS: i64 = 36500;
+
+/// max ``content_type`` length
+/// (matches the ``users_data.content_type`` column)
+pub const UPLOAD_MAX_CONTENT_TYPE_LEN: usize = 128;
+
+/// ApiReqUserUploadMetadata
+///
+/// # Request Type For upload_user_data metadata
+///
+/// Describes the uploaded file. This is read from the
+/// custom headers (raw body uploads) or from the json
+/// ``metadata`` part (``multipart/form-data`` uploads).
+///
+/// # Arguments
+///
+/// * `user_id` - `i32` - user id
+/// * `filename` - `String` - name of the file (1 to 511 characters
+///   without ``/`` or ``\\``)
+/// * `data_type` - `Option<String>` - data type (default `file`)
+/// * `encoding` - `Option<String>` - encoding (default `na`)
+/// * `comments` - `Option<String>` - notes or description
+///   (default `file`)
+/// * `sloc` - `Option<String>` - remote s3 location
+///   (default is generated)
+/// * `s3_enable` - `Option<bool>` - upload the file to s3
+///   (default is based off ``S3_DATA_UPLOAD_TO_S3``)
+/// * `storage_class` - `Option<String>` - s3 storage class
+///   (one of
+///   [`S3_STORAGE_CLASSES`](crate::is3::object_store::S3_STORAGE_CLASSES),
+///   default is ``S3_STORAGE_CLASS``)
+/// * `expire_days` - `Option<i64>` - days until the
+///   [`UserDataLifecycle`](crate::archive::user_data_lifecycle::UserDataLifecycle)
+///   deletes the s3 object (1 to 36500, default never)
+/// * `expire_storage_class` - `Option<String>` - move the s3
+///   object to this storage class after `expire_days`
+///   instead of deleting it
+/// * `content_type` - `Option<String>` - file content type
+///   without parameters (default is the ``Content-Type``
+///   of the raw body or ``file`` part)
+/// * `tags` - `Option<Vec<String>>` - up to 20 tags
+///   (see [`check_data_tags`](crate::requests::validation::field_rules::check_data_tags),
+///   the ``tags`` header is comma-delimited)
+/// * `folder` - `Option<String>` - folder path like
+///   ``/projects/2022``
+///   (see [`check_data_folder`](crate::requests::validation::field_rules::check_data_folder))
+/// * `on_conflict` - `Option<String>` - filename collision
+///   policy (one of
+///   [`USER_DATA_CONFLICT_POLICIES`](crate::requests::models::user_data_conflict::USER_DATA_CONFLICT_POLICIES),
+///   default is ``USER_DATA_FILENAME_CONFLICT``)
+///
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct ApiReqUserUploadMetadata {
+    pub user_id: i32,
+    pub filename: String,
+    pub data_type: Option<String>,
+    pub encoding: Option<String>,
+    pub comments: Option<String>,
+    pub sloc: Option<String>,
+    pub s3_enable: Option<bool>,
+    pub storage_class: Option<String>,
+    pub expire_days: Option<i64>,
+    pub expire_storage_class: Option<String>,
+    #[serde(default)]
+    pub content_type: Option<String>,
+    #[serde(default)]
+    pub tags: Option<Vec<String>>,
+    #[serde(default)]
+    pub folder: Option<String>,
+    #[serde(default)]
+    pub on_conflict: Option<String>,
+}
+
+impl ApiReqValidate for ApiReqUserUploadMetadata {
+    /// validate
+    ///
+    /// Require a positive `user_id`, a single segment
+    /// `filename` (see
+    /// [`check_data_filename`](crate::requests::validation::field_rules::check_data_filename)),
+    /// optional values that fit in the ``users_data`` columns
+    /// (see [`UPLOAD_METADATA_LIMITS`](crate::requests::user::get_upload_metadata::UPLOAD_METADATA_LIMITS))
+    /// supported storage classes and collision policies and
+    /// valid `tags` and `folder` values. An
+    /// `expire_storage_class` requires `expire_days`.
+    ///
+    fn validate(&self) -> Vec<ApiFieldError> {
+        let mut errors = Vec::new();
+        check_id(&mut errors, "user_id", self.user_id);
+        check_data_filename(&mut errors, "filename", &self.filename);
+        let optional_values = [
+            ("data_type", &self.data_type),
+            ("encoding", &self.encoding),
+            ("comments", &self.comments),
+            ("sloc", &self.sloc),
+        ];
+        for (key, value) in optional_values.iter() {
+            let max_len = UPLOAD_METADATA_LIMITS
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, max_len)| *max_len)
+                .unwrap();
+            if let Some(v) = value {
+                check_length(&mut errors, key, v, 0, max_len);
+            }
+        }
+        if let Some(v) = &self.content_type {
+            check_length(
+                &mut errors,
+                "content_type",
+                v,
+                1,
+                UPLOAD_MAX_CONTENT_TYPE_LEN,
+            );
+        }
+        let storage_classes = [
+            ("storage_class", &self.storage_class),
+            ("expire_storage_class", &self.expire_storage_class),
+        ];
+        for (key, value) in storage_classes.iter() {
+            if let Some(v) = value {
+                check_one_of(&mut errors, key, v.as_str(), &S3_STORAGE_CLASSES);
+            }
+        }
+        if let Some(v) = self.expire_days {
+            check_range(
+                &mut errors,
+                "expire_days",
+                v,
+                1,
+                UPLOAD_MAX_EXPIRE_DAYS,
+            );
+        }
+        if let Some(v) = &self.tags {
+            check_data_tags(&mut errors, "tags", v);
+        }
+        if let Some(v) = &self.folder {
+            check_data_folder(&mut errors, "folder", v);
+        }
+        if let Some(v) = &self.on_conflict {
+            check_one_of(
+                &mut errors,
+                "on_conflict",
+                v.as_str(),
+                &USER_DATA_CONFLICT_POLICIES,
+            );
+        }
+        if self.expire_storage_class.is_some() && self.expire_days.is_none() {
+            add_field_error(
+                &mut errors,
+                "expire_storage_class",
+                "requires expire_days",
+            );
+        }
+        errors
+    }
+}
+
+/// is_multipart_upload
+///
+/// Check if the request's ``Content-Type`` is
+/// ``multipart/form-data``
+///
+/// # Arguments
+///
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+///
+/// # Returns
+///
+/// `bool`
+///
+pub fn is_multipart_upload(headers: &HeaderMap<HeaderValue>) -> bool {
+    match headers.get(CONTENT_TYPE) {
+        Some(v) => v
+            .to_str()
+            .unwrap_or("")
+            .to_lowercase()
+            .starts_with("multipart/form-data"),
+        None => false,
+    }
+}
+
+/// get_content_type_essence
+///
+/// Strip the parameters from a ``Content-Type`` value
+///
+/// # Arguments
+///
+/// * `content_type` - `&str` - ``Content-Type`` value
+///
+/// # Returns
+///
+/// `Option<String>` - lowercase type without parameters
+/// (`None` if empty)
+///
+/// ```rust
+/// use restapi::requests::user::get_upload_metadata::get_content_type_essence;
+/// assert_eq!(
+///     get_content_type_essence("Image/PNG; charset=binary"),
+///     Some("image/png".to_string())
+/// );
+/// assert_eq!(get_content_type_essence(" ; q=1"), None);
+/// ```
+///
+pub fn get_content_type_essence(content_type: &str) -> Option<String> {
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_lowercase();
+    match essence.is_empty() {
+        true => None,
+        false => Some(essence),
+    }
+}
+
+/// get_upload_metadata_from_headers
+///
+/// Build an
+/// [`ApiReqUserUploadMetadata`](crate::requests::user::get_upload_metadata::ApiReqUserUploadMetadata)
+/// from the custom upload headers after validating them with
+/// [`validate_upload_header`](crate::requests::user::validate_upload_header::validate_upload_header)
+///
+/// # Arguments
+///
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+///
+/// # Returns
+///
+/// Ok([`ApiReqUserUploadMetadata`](crate::requests::user::get_upload_metadata::ApiReqUserUploadMetadata))
+///
+/// # Errors
+///
+/// `Err((u16, String))` - HTTP status code
+/// and an error message for the client
+///
+pub fn get_upload_metadata_from_headers(
+    headers: &HeaderMap<HeaderValue>,
+) -> Result<ApiReqUserUploadMetadata, (u16, String)> {
+    validate_upload_headers_size(headers)?;
+    let mut values: Vec<Option<String>> = Vec::new();
+    for (key, max_len) in UPLOAD_METADATA_LIMITS.iter() {
+        values.push(validate_upload_header(headers, key, *max_len)?);
+    }
+    let user_id = match &values[0] {
+        Some(v) => match v.parse::<i32>() {
+            Ok(user_id) => user_id,
+            Err(_) => {
+                return Err((
+                    400,
+                    "user_id must be a postive number that is \
+                    the actual user_id for the token"
+                        .to_string(),
+                ));
+            }
+        },
+        None => {
+            return Err((
+                400,
+                "Missing required header 'user_id' key \
+                (i.e. curl -H 'user_id: INT'"
+                    .to_string(),
+            ));
+        }
+    };
+    let filename = match &values[1] {
+        Some(v) => v.to_string(),
+        None => {
+            return Err((
+                400,
+                "Missing required header 'filename' key \
+                (i.e. curl -H 'filename: STRING'"
+                    .to_string(),
+            ));
+        }
+    };
+    let expire_days = match &values[8] {
+        Some(v) => match v.parse::<i64>() {
+            Ok(expire_days) => Some(expire_days),
+            Err(_) => {
+                return Err((
+                    400,
+                    "expire_days must be a number of days".to_string(),
+                ));
+            }
+        },
+        None => None,
+    };
+    let metadata = ApiReqUserUploadMetadata {
+        user_id,
+        filename,
+        data_type: values[2].clone(),
+        encoding: values[3].clone(),
+        comments: values[4].clone(),
+        sloc: values[5].clone(),
+        // any s3_enable header value enables the s3 upload
+        s3_enable: values[6].as_ref().map(|_| true),
+        storage_class: values[7].as_ref().map(|v| v.to_uppercase()),
+        expire_days,
+        expire_storage_class: values[9].as_ref().map(|v| v.to_uppercase()),
+        content_type: headers
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(get_content_type_essence),
+        tags: values[10].as_ref().map(|v| {
+            v.split(',')
+                .map(|tag| tag.trim().to_string())
+                .filter(|tag| !tag.is_empty())
+                .collect()
+        }),
+        folder: values[11].clone(),
+        on_conflict: values[12].as_ref().map(|v| v.to_lowercase()),
+    };
+    validate_upload_metadata(&metadata)?;
+    Ok(metadata)
+}
+
+/// get_upload_metadata_from_multipart
+///
+/// Read the json ``metadata`` part and the ``file`` part
+/// from a ``multipart/form-data`` body
+///
+/// # Arguments
+///
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+/// * `body` - `hyper::Body` - the hyper
+///   [`Request`](hyper::Request)'s [`Body`](hyper::Body)
+/// * `max_upload_size_bytes` - `i64` - max multipart body size
+///   in bytes (`0` = unlimited)
+///
+/// # Returns
+///
+/// Ok(([`ApiReqUserUploadMetadata`](crate::requests::user::get_upload_metadata::ApiReqUserUploadMetadata), `Bytes`))
+///
+/// # Errors
+///
+/// `Err((u16, String))` - HTTP status code (`413` for
+/// bodies over `max_upload_size_bytes`)
+/// and an error message for the client
+///
+pub async fn get_upload_metadata_from_multipart(
+    headers: &HeaderMap<HeaderValue>,
+    body: hyper::Body,
+    max_upload_size_bytes: i64,
+) -> Result<(ApiReqUserUploadMetadata, Bytes), (u16, String)> {
+    let content_type = headers
+        .get(CONTENT_TYPE)
+        .map(|v| v.to_str().unwrap_or(""))
+        .unwrap_or("");
+    let boundary = match multer::parse_boundary(content_type) {
+        Ok(boundary) => boundary,
+        Err(e) => {
+            return Err((
+                400,
+                format!("Invalid multipart/form-data boundary with err='{e}'"),
+            ));
+        }
+    };
+    // the json metadata part is small - do not buffer
+    // large non-file parts in memory
+    let mut size_limit =
+        multer::SizeLimit::new().for_field("metadata", 64 * 1024);
+    if max_upload_size_bytes > 0 {
+        size_limit = size_limit.whole_stream(max_upload_size_bytes as u64);
+    }
+    let constraints = multer::Constraints::new()
+        .allowed_fields(vec!["metadata", "file"])
+        .size_limit(size_limit);
+    let mut multipart =
+        multer::Multipart::with_constraints(body, boundary, constraints);
+    let mut metadata: Option<ApiReqUserUploadMetadata> = None;
+    let mut file_contents: Option<Bytes> = None;
+    let mut file_content_type: Option<String> = None;
+    loop {
+        let field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(multer::Error::StreamSizeExceeded { .. }) => {
+                return Err((
+                    413,
+                    get_upload_too_large_msg(max_upload_size_bytes),
+                ));
+            }
+            Err(e) => {
+                return Err((
+                    400,
+                    format!("Invalid multipart/form-data body with err='{e}'"),
+                ));
+            }
+        };
+        let field_name = field.name().unwrap_or("").to_string();
+        let field_content_type = field
+            .content_type()
+            .and_then(|v| get_content_type_essence(v.essence_str()));
+        let field_bytes = match field.bytes().await {
+            Ok(field_bytes) => field_bytes,
+            Err(multer::Error::StreamSizeExceeded { .. }) => {
+                return Err((
+                    413,
+                    get_upload_too_large_msg(max_upload_size_bytes),
+                ));
+            }
+            Err(e) => {
+                return Err((
+                    400,
+                    format!(
+                        "Failed to read multipart/form-data \
+                        part '{field_name}' with err='{e}'"
+                    ),
+                ));
+            }
+        };
+        match field_name.as_str() {
+            "metadata" => {
+                metadata = match serde_json::from_slice(&field_bytes) {
+                    Ok(v) => Some(v),
+                    Err(e) => {
+                        return Err((
+                            400,
+                            format!(
+                                "The multipart/form-data 'metadata' part \
+                                must be json with user_id and filename \
+                                - err='{e}'"
+                            ),
+                        ));
+                    }
+                };
+            }
+            _ => {
+                file_contents = Some(field_bytes);
+                file_content_type = field_content_type;
+            }
+        }
+    }
+    let mut metadata = match metadata {
+        Some(metadata) => metadata,
+        None => {
+            return Err((
+                400,
+                "Missing required multipart/form-data 'metadata' part"
+                    .to_string(),
+            ));
+        }
+    };
+    metadata.storage_class = metadata.storage_class.map(|v| v.to_uppercase());
+    metadata.expire_storage_class =
+        metadata.expire_storage_class.map(|v| v.to_uppercase());
+    metadata.on_conflict = metadata.on_conflict.map(|v| v.to_lowercase());
+    metadata.content_type = match &metadata.content_type {
+        Some(v) => get_content_type_essence(v),
+        None => file_content_type,
+    };
+    validate_upload_metadata(&metadata)?;
+    match file_contents {
+        Some(file_contents) => Ok((metadata, file_contents)),
+        None => Err((
+            400,
+            "Missing required multipart/form-data 'file' part".to_string(),
+        )),
+    }
+}
+
+/// validate_upload_metadata
+///
+/// Check the metadata values fit in the
+/// ``users_data`` columns with
+/// [`ApiReqValidate`](crate::requests::validation::validate_api_req::ApiReqValidate)
+///
+/// # Arguments
+///
+/// * `metadata` - [`ApiReqUserUploadMetadata`](crate::requests::user::get_upload_metadata::ApiReqUserUploadMetadata)
+///
+/// # Errors
+///
+/// `Err((422, String))` - invalid fields for the client
+///
+pub fn validate_upload_metadata(
+    metadata: &ApiReqUserUploadMetadata,
+) -> Result<(), (u16, String)> {
+    let errors = metadata.validate();
+    if errors.is_empty() {
+        return Ok(());
+    }
+    Err((
+        422,
+        format!(
+            "Request validation failed for fields: {}",
+            errors
+                .iter()
+                .map(|e| format!("{} {}", e.field, e.msg))
+                .collect::<Vec<String>>()
+                .join(", ")
+        ),
+    ))
+}
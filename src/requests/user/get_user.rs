@@ -26,12 +26,17 @@ use hyper::Response;
 use serde::Deserialize;
 use serde::Serialize;
 
-use kafka_threadpool::kafka_publisher::KafkaPublisher;
-
 use crate::core::core_config::CoreConfig;
-use crate::kafka::publish_msg::publish_msg;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::pools::db_read_pools::DbReadPools;
 use crate::requests::auth::validate_user_token::validate_user_token;
+use crate::requests::models::api_error::ApiErrorCode;
 use crate::requests::models::user::get_user_by_id;
+use crate::requests::validation::field_rules::check_id;
+use crate::requests::validation::validate_api_req::validate_api_req;
+use crate::requests::validation::validate_api_req::ApiFieldError;
+use crate::requests::validation::validate_api_req::ApiReqValidate;
+use crate::utils::etag::get_etag_response;
 
 /// ApiReqUserGet
 ///
@@ -59,6 +64,18 @@ pub struct ApiReqUserGet {
     pub user_id: i32,
 }
 
+impl ApiReqValidate for ApiReqUserGet {
+    /// validate
+    ///
+    /// Require a positive `user_id`
+    ///
+    fn validate(&self) -> Vec<ApiFieldError> {
+        let mut errors = Vec::new();
+        check_id(&mut errors, "user_id", self.user_id);
+        errors
+    }
+}
+
 /// ApiResUserGet
 ///
 /// # Response type for get_user
@@ -83,7 +100,10 @@ pub struct ApiReqUserGet {
 /// * `verified` - `i32` - user email verified
 ///   (`0` - not-verified, `1` - verified)
 /// * `role` - `String` - user role
+/// * `version` - `i32` - user version for the next update
 /// * `msg` - `String` - help message
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   machine-readable failure code (omitted on success)
 ///
 #[derive(Serialize, Deserialize, Clone)]
 pub struct ApiResUserGet {
@@ -92,7 +112,10 @@ pub struct ApiResUserGet {
     pub state: i32,
     pub verified: i32,
     pub role: String,
+    pub version: i32,
     pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ApiErrorCode>,
 }
 
 /// get_user
@@ -109,8 +132,10 @@ pub struct ApiResUserGet {
 /// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
 /// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
 ///   db threadpool with required tls encryption
+/// * `db_read_pools` - [`DbReadPools`](crate::pools::db_read_pools::DbReadPools) -
+///   postgres read replica pools for the read-only query
 /// * `kafka_pool` -
-///   [`KafkaPublisher`](kafka_threadpool::kafka_publisher::KafkaPublisher)
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
 ///   for asynchronously publishing messages to the connected kafka cluster
 /// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
 ///   hashmap containing headers in key-value pairs
@@ -131,8 +156,9 @@ pub struct ApiResUserGet {
 /// containing a json-serialized
 /// [`ApiResUserGet`](crate::requests::user::get_user::ApiResUserGet)
 /// dictionary within the
-/// [`Body`](hyper::Body) and a
-/// `200` HTTP status code
+/// [`Body`](hyper::Body), an ``ETag`` header and a
+/// `200` HTTP status code (an empty `304` when the
+/// ``If-None-Match`` header has the current ``ETag``)
 ///
 /// Ok([`Response`](hyper::Response))
 ///
@@ -153,6 +179,7 @@ pub async fn get_user(
     tracking_label: &str,
     config: &CoreConfig,
     db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    db_read_pools: &DbReadPools,
     kafka_pool: &KafkaPublisher,
     headers: &HeaderMap<HeaderValue>,
     request_uri: &str,
@@ -160,27 +187,12 @@ pub async fn get_user(
     let user_id = str::replace(request_uri, "/user/", "")
         .parse::<i32>()
         .unwrap_or(-1);
-    if user_id <= 0 {
-        let response = Response::builder()
-            .status(400)
-            .body(Body::from(
-                serde_json::to_string(&ApiResUserGet {
-                    user_id: -1,
-                    email: "".to_string(),
-                    state: -1,
-                    verified: -1,
-                    role: "".to_string(),
-                    msg: ("Invalid user_id must be a positive integer")
-                        .to_string(),
-                })
-                .unwrap(),
-            ))
-            .unwrap();
+    let user_object = ApiReqUserGet { user_id };
+    if let Some(response) = validate_api_req(tracking_label, &user_object) {
         return Ok(response);
     }
 
     info!("{tracking_label} - getting user_id={user_id}");
-    let user_object = ApiReqUserGet { user_id };
 
     let conn = db_pool.get().await.unwrap();
     let _token = match validate_user_token(
@@ -193,7 +205,7 @@ pub async fn get_user(
     .await
     {
         Ok(_token) => _token,
-        Err(_) => {
+        Err(error_code) => {
             let response = Response::builder()
                 .status(400)
                 .body(Body::from(
@@ -203,8 +215,10 @@ pub async fn get_user(
                         state: -1,
                         verified: -1,
                         role: "".to_string(),
+                        version: -1,
                         msg: ("User get failed due to invalid token")
                             .to_string(),
+                        error_code: Some(error_code),
                     })
                     .unwrap(),
                 ))
@@ -214,39 +228,30 @@ pub async fn get_user(
     };
 
     // find all user by email and an active state where state == 0
-    match get_user_by_id(tracking_label, user_id, &conn).await {
+    let read_conn = db_read_pools.get_read_conn(tracking_label).await;
+    let read_conn = read_conn.as_ref().unwrap_or(&conn);
+    match get_user_by_id(tracking_label, user_id, read_conn).await {
         Ok(user_model) => {
-            // if enabled, publish to kafka
-            if config.kafka_publish_events {
-                publish_msg(
-                    kafka_pool,
-                    // topic
-                    "user.events",
-                    // partition key
-                    &format!("user-{}", user_id),
-                    // optional headers stored in: Option<HashMap<String, String>>
-                    None,
-                    // payload in the message
-                    &format!("USER_GET user={user_id}"),
-                )
+            config
+                .events
+                .publish_user_event(kafka_pool, user_id, "USER_GET", "")
                 .await;
-            }
 
-            let response = Response::builder()
-                .status(200)
-                .body(Body::from(
-                    serde_json::to_string(&ApiResUserGet {
-                        user_id: user_model.id,
-                        email: user_model.email,
-                        state: user_model.state,
-                        verified: user_model.verified,
-                        role: user_model.role,
-                        msg: "success".to_string(),
-                    })
-                    .unwrap(),
-                ))
-                .unwrap();
-            Ok(response)
+            // a 304 when the client already has this version
+            Ok(get_etag_response(
+                headers,
+                serde_json::to_string(&ApiResUserGet {
+                    user_id: user_model.id,
+                    email: user_model.email,
+                    state: user_model.state,
+                    verified: user_model.verified,
+                    role: user_model.role,
+                    version: user_model.version,
+                    msg: "success".to_string(),
+                    error_code: None,
+                })
+                .unwrap(),
+            ))
         }
         Err(err_msg) => {
             error!(
@@ -262,11 +267,13 @@ pub async fn get_user(
                         state: -1,
                         verified: -1,
                         role: "".to_string(),
+                        version: -1,
                         msg: format!(
                             "User login failed - \
                                 user does not exist with user_id={}",
                             user_object.user_id
                         ),
+                        error_code: Some(ApiErrorCode::UserNotFound),
                     })
                     .unwrap(),
                 ))
@@ -0,0 +1,225 @@
+//! Module for getting the download stats for a user's s3 data file
+//!
+//! ## Get User Data Access Stats
+//!
+//! Get the download totals and the most recent downloads from the ``users_data_access`` audit table for a ``users_data`` (or archived) record the user owns (see [`get_user_data_access_stats`](crate::requests::models::user_data_access::get_user_data_access_stats))
+//!
+//! - URL path: ``/user/data/DATAID/access``
+//! - Method: ``GET``
+//! - Handler: [`get_user_data_access`](crate::requests::user::get_user_data_access::get_user_data_access)
+//! - Request: none (uses the token header)
+//! - Response: [`ApiResUserGetDataAccess`](crate::requests::user::get_user_data_access::ApiResUserGetDataAccess)
+//!
+use std::convert::Infallible;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Response;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::core::core_config::CoreConfig;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::requests::auth::validate_user_token::validate_user_token;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::models::user_data_access::get_user_data_access_stats;
+use crate::requests::models::user_data_access::ModelUserDataAccess;
+use crate::requests::models::user_data_access::ModelUserDataAccessStats;
+use crate::requests::models::user_session::get_user_session_by_token;
+
+/// ApiResUserGetDataAccess
+///
+/// # Response type for get_user_data_access
+///
+/// Return the download stats for a user's file
+///
+/// # Usage
+///
+/// This type is the serialized output for the function:
+/// [`get_user_data_access`](crate::requests::user::get_user_data_access::get_user_data_access]
+/// and contained within the
+/// hyper [`Body`](hyper::Body)
+/// of the
+/// hyper [`Response`](hyper::Response)
+/// sent back to the client.
+///
+/// # Arguments
+///
+/// * `stats` - [`ModelUserDataAccessStats`](crate::requests::models::user_data_access::ModelUserDataAccessStats) -
+///   the file's download totals
+/// * `accesses` - `Vec<`[`ModelUserDataAccess`](crate::requests::models::user_data_access::ModelUserDataAccess)`>` -
+///   most recent downloads (newest first)
+/// * `msg` - `String` - help message
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   machine-readable failure code (omitted on success)
+///
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ApiResUserGetDataAccess {
+    pub stats: ModelUserDataAccessStats,
+    pub accesses: Vec<ModelUserDataAccess>,
+    pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ApiErrorCode>,
+}
+
+/// get_user_data_access_error_response
+///
+/// Build a json error response for a failed access stats
+/// request
+///
+fn get_user_data_access_error_response(
+    status: u16,
+    msg: String,
+    error_code: ApiErrorCode,
+) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::from(
+            serde_json::to_string(&ApiResUserGetDataAccess {
+                msg,
+                error_code: Some(error_code),
+                ..Default::default()
+            })
+            .unwrap(),
+        ))
+        .unwrap()
+}
+
+/// get_user_data_access
+///
+/// Handler for getting the download stats for a
+/// `users_data` record the user owns
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
+///   db threadpool with required tls encryption
+/// * `kafka_pool` -
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+///   for asynchronously publishing messages to the connected kafka cluster
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+/// * `request_uri` - `&str` - url path with the data id
+///
+/// # Returns
+///
+/// ## get_user_data_access on Success Returns
+///
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResUserGetDataAccess`](crate::requests::user::get_user_data_access::ApiResUserGetDataAccess)
+/// dictionary within the
+/// [`Body`](hyper::Body) and a
+/// `200` HTTP status code
+///
+/// Ok([`Response`](hyper::Response))
+///
+/// # Errors
+///
+/// ## get_user_data_access on Failure Returns
+///
+/// All errors return as a
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResUserGetDataAccess`](crate::requests::user::get_user_data_access::ApiResUserGetDataAccess)
+/// dictionary with a
+/// `non-200` HTTP status code (`404` if the record does
+/// not exist or is owned by another user)
+///
+/// Err([`Response`](hyper::Response))
+///
+pub async fn get_user_data_access(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    kafka_pool: &KafkaPublisher,
+    headers: &HeaderMap<HeaderValue>,
+    request_uri: &str,
+) -> std::result::Result<Response<Body>, Infallible> {
+    let data_id = request_uri
+        .strip_prefix("/user/data/")
+        .and_then(|v| v.strip_suffix("/access"))
+        .and_then(|v| v.parse::<i32>().ok())
+        .unwrap_or(-1);
+    if data_id <= 0 {
+        return Ok(get_user_data_access_error_response(
+            400,
+            ("Invalid data id must be a positive integer").to_string(),
+            ApiErrorCode::InvalidRequest,
+        ));
+    }
+
+    let token = &config.token_cookie.get_request_token(headers);
+
+    let conn = db_pool.get().await.unwrap();
+    let user_id =
+        match get_user_session_by_token(tracking_label, token, &conn).await {
+            Ok((_, user_id)) => user_id,
+            Err(_) => -1,
+        };
+    let valid_token = match user_id > 0 {
+        true => {
+            validate_user_token(tracking_label, config, &conn, headers, user_id)
+                .await
+        }
+        false => Err(ApiErrorCode::InvalidToken),
+    };
+    if let Err(error_code) = valid_token {
+        return Ok(get_user_data_access_error_response(
+            400,
+            ("User get data access failed due to invalid token").to_string(),
+            error_code,
+        ));
+    }
+
+    // only the owner can see who downloaded the file
+    match get_user_data_access_stats(tracking_label, data_id, user_id, &conn)
+        .await
+    {
+        Ok((stats, accesses)) => {
+            config
+                .events
+                .publish_user_event(
+                    kafka_pool,
+                    user_id,
+                    "USER_GET_DATA_ACCESS",
+                    &format!("data={data_id}"),
+                )
+                .await;
+
+            let response = Response::builder()
+                .status(200)
+                .body(Body::from(
+                    serde_json::to_string(&ApiResUserGetDataAccess {
+                        stats,
+                        accesses,
+                        msg: "success".to_string(),
+                        error_code: None,
+                    })
+                    .unwrap(),
+                ))
+                .unwrap();
+            Ok(response)
+        }
+        Err(e) => {
+            error!("{e}");
+            Ok(get_user_data_access_error_response(
+                e.status_code(),
+                format!(
+                    "User get data access failed for data_id={data_id} \
+                    and user_id={user_id}"
+                ),
+                e.error_code(),
+            ))
+        }
+    }
+}
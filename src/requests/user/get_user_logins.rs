@@ -0,0 +1,238 @@
+//! Module for listing a user's recent login attempts
+//!
+//! ## Get User Logins
+//!
+//! List the most recent successful and failed password and passkey login attempts for the user that owns the request's token. Each attempt includes the time, result, client ip address, user agent and device.
+//!
+//! - URL path: ``/user/logins``
+//! - Method: ``GET``
+//! - Handler: [`get_user_logins`](crate::requests::user::get_user_logins::get_user_logins)
+//! - Request: optional query parameters ``limit`` (``1`` to ``100``, default ``20``) and ``failed`` (``1`` = only failed attempts)
+//! - Response: [`ApiResUserGetLogins`](crate::requests::user::get_user_logins::ApiResUserGetLogins)
+//!
+use std::collections::HashMap;
+use std::convert::Infallible;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Response;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::core::core_config::CoreConfig;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::requests::auth::validate_user_token::validate_user_token;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::models::user_login::get_user_logins as get_logins;
+use crate::requests::models::user_login::ModelUserLogin;
+use crate::requests::models::user_session::get_user_session_by_token;
+
+/// default number of login attempts returned
+pub const USER_LOGINS_DEFAULT_LIMIT: i64 = 20;
+
+/// max number of login attempts returned
+pub const USER_LOGINS_MAX_LIMIT: i64 = 100;
+
+/// ApiResUserGetLogins
+///
+/// # Response type for get_user_logins
+///
+/// Return the user's recent login attempts
+///
+/// # Usage
+///
+/// This type is the serialized output for the function:
+/// [`get_user_logins`](crate::requests::user::get_user_logins::get_user_logins]
+/// and contained within the
+/// hyper [`Body`](hyper::Body)
+/// of the
+/// hyper [`Response`](hyper::Response)
+/// sent back to the client.
+///
+/// # Arguments
+///
+/// * `user_id` - `i32` - user id
+/// * `logins` - `Vec<`[`ModelUserLogin`](crate::requests::models::user_login::ModelUserLogin)`>` -
+///   login attempts ordered by newest first
+/// * `msg` - `String` - help message
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   machine-readable failure code (omitted on success)
+///
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApiResUserGetLogins {
+    pub user_id: i32,
+    pub logins: Vec<ModelUserLogin>,
+    pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ApiErrorCode>,
+}
+
+/// get_user_logins
+///
+/// Handler for listing the recent login attempts for the
+/// user that owns the request's token
+///
+/// Attempts are recorded by
+/// [`LoginHistory`](crate::requests::auth::login_history::LoginHistory)
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
+///   db threadpool with required tls encryption
+/// * `kafka_pool` -
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+///   for asynchronously publishing messages to the connected kafka cluster
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+/// * `request_query_params` - `&str` - url query string
+///   with the optional ``limit`` and ``failed`` parameters
+///
+/// # Returns
+///
+/// ## get_user_logins on Success Returns
+///
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResUserGetLogins`](crate::requests::user::get_user_logins::ApiResUserGetLogins)
+/// dictionary within the
+/// [`Body`](hyper::Body) and a
+/// `200` HTTP status code
+///
+/// Ok([`Response`](hyper::Response))
+///
+/// # Errors
+///
+/// ## get_user_logins on Failure Returns
+///
+/// All errors return as a
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResUserGetLogins`](crate::requests::user::get_user_logins::ApiResUserGetLogins)
+/// dictionary with a
+/// `non-200` HTTP status code
+///
+/// Err([`Response`](hyper::Response))
+///
+pub async fn get_user_logins(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    kafka_pool: &KafkaPublisher,
+    headers: &HeaderMap<HeaderValue>,
+    request_query_params: &str,
+) -> std::result::Result<Response<Body>, Infallible> {
+    let token = &config.token_cookie.get_request_token(headers);
+
+    let conn = db_pool.get().await.unwrap();
+    let (_, user_id) = get_user_session_by_token(tracking_label, token, &conn)
+        .await
+        .unwrap_or((-1, -1));
+    let valid_token = match user_id > 0 {
+        true => {
+            validate_user_token(tracking_label, config, &conn, headers, user_id)
+                .await
+        }
+        false => Err(ApiErrorCode::InvalidToken),
+    };
+    if let Err(error_code) = valid_token {
+        return Ok(get_user_logins_response(
+            400,
+            -1,
+            "User get logins failed due to invalid token",
+            error_code,
+        ));
+    }
+
+    let query_params: HashMap<String, String> =
+        url::form_urlencoded::parse(request_query_params.as_bytes())
+            .into_owned()
+            .collect();
+    let limit = match query_params.get("limit") {
+        Some(v) => match v.parse::<i64>() {
+            Ok(v) if (1..=USER_LOGINS_MAX_LIMIT).contains(&v) => v,
+            _ => {
+                return Ok(get_user_logins_response(
+                    400,
+                    user_id,
+                    &format!(
+                        "User get logins failed - \
+                        limit must be between 1 and {USER_LOGINS_MAX_LIMIT}"
+                    ),
+                    ApiErrorCode::InvalidRequest,
+                ));
+            }
+        },
+        None => USER_LOGINS_DEFAULT_LIMIT,
+    };
+    let failed_only = matches!(
+        query_params.get("failed").map(|v| v.as_str()),
+        Some("1") | Some("true")
+    );
+
+    match get_logins(tracking_label, user_id, failed_only, limit, &conn).await {
+        Ok(logins) => {
+            config
+                .events
+                .publish_user_event(kafka_pool, user_id, "USER_GET_LOGINS", "")
+                .await;
+
+            let response = Response::builder()
+                .status(200)
+                .body(Body::from(
+                    serde_json::to_string(&ApiResUserGetLogins {
+                        user_id,
+                        logins,
+                        msg: "success".to_string(),
+                        error_code: None,
+                    })
+                    .unwrap(),
+                ))
+                .unwrap();
+            Ok(response)
+        }
+        Err(err_msg) => {
+            error!("{err_msg}");
+            Ok(get_user_logins_response(
+                500,
+                user_id,
+                &format!("User get logins failed for user_id={user_id}"),
+                ApiErrorCode::InternalError,
+            ))
+        }
+    }
+}
+
+/// get_user_logins_response
+///
+/// Build a failed
+/// [`ApiResUserGetLogins`](crate::requests::user::get_user_logins::ApiResUserGetLogins)
+/// response
+///
+fn get_user_logins_response(
+    status: u16,
+    user_id: i32,
+    msg: &str,
+    error_code: ApiErrorCode,
+) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::from(
+            serde_json::to_string(&ApiResUserGetLogins {
+                user_id,
+                logins: Vec::new(),
+                msg: msg.to_string(),
+                error_code: Some(error_code),
+            })
+            .unwrap(),
+        ))
+        .unwrap()
+}
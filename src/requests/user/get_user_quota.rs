@@ -0,0 +1,194 @@
+//! Module for getting a user's storage quota and usage
+//!
+//! ## Get User Quota
+//!
+//! Get the storage quota, used bytes and available bytes for the user that owns the request's token (see [`UserDataQuota`](crate::requests::user::user_data_quota::UserDataQuota))
+//!
+//! - URL path: ``/user/quota``
+//! - Method: ``GET``
+//! - Handler: [`get_user_quota`](crate::requests::user::get_user_quota::get_user_quota)
+//! - Request: none (uses the token header)
+//! - Response: [`ApiResUserGetQuota`](crate::requests::user::get_user_quota::ApiResUserGetQuota)
+//!
+use std::convert::Infallible;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Response;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::core::core_config::CoreConfig;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::requests::auth::validate_user_token::validate_user_token;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::models::user_data_quota::get_user_data_quota;
+use crate::requests::models::user_data_quota::ModelUserDataQuota;
+use crate::requests::models::user_session::get_user_session_by_token;
+
+/// ApiResUserGetQuota
+///
+/// # Response type for get_user_quota
+///
+/// Return the user's storage quota and usage
+///
+/// # Usage
+///
+/// This type is the serialized output for the function:
+/// [`get_user_quota`](crate::requests::user::get_user_quota::get_user_quota]
+/// and contained within the
+/// hyper [`Body`](hyper::Body)
+/// of the
+/// hyper [`Response`](hyper::Response)
+/// sent back to the client.
+///
+/// # Arguments
+///
+/// * `quota` - [`ModelUserDataQuota`](crate::requests::models::user_data_quota::ModelUserDataQuota) -
+///   the user's quota and usage
+/// * `msg` - `String` - help message
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   machine-readable failure code (omitted on success)
+///
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApiResUserGetQuota {
+    pub quota: ModelUserDataQuota,
+    pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ApiErrorCode>,
+}
+
+/// get_user_quota
+///
+/// Handler for getting the storage quota and usage for
+/// the user that owns the request's token
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
+///   db threadpool with required tls encryption
+/// * `kafka_pool` -
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+///   for asynchronously publishing messages to the connected kafka cluster
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+///
+/// # Returns
+///
+/// ## get_user_quota on Success Returns
+///
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResUserGetQuota`](crate::requests::user::get_user_quota::ApiResUserGetQuota)
+/// dictionary within the
+/// [`Body`](hyper::Body) and a
+/// `200` HTTP status code
+///
+/// Ok([`Response`](hyper::Response))
+///
+/// # Errors
+///
+/// ## get_user_quota on Failure Returns
+///
+/// All errors return as a
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResUserGetQuota`](crate::requests::user::get_user_quota::ApiResUserGetQuota)
+/// dictionary with a
+/// `non-200` HTTP status code
+///
+/// Err([`Response`](hyper::Response))
+///
+pub async fn get_user_quota(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    kafka_pool: &KafkaPublisher,
+    headers: &HeaderMap<HeaderValue>,
+) -> std::result::Result<Response<Body>, Infallible> {
+    let token = &config.token_cookie.get_request_token(headers);
+
+    let conn = db_pool.get().await.unwrap();
+    let user_id =
+        match get_user_session_by_token(tracking_label, token, &conn).await {
+            Ok((_, user_id)) => user_id,
+            Err(_) => -1,
+        };
+    let valid_token = match user_id > 0 {
+        true => {
+            validate_user_token(tracking_label, config, &conn, headers, user_id)
+                .await
+        }
+        false => Err(ApiErrorCode::InvalidToken),
+    };
+    if let Err(error_code) = valid_token {
+        let response = Response::builder()
+            .status(400)
+            .body(Body::from(
+                serde_json::to_string(&ApiResUserGetQuota {
+                    quota: ModelUserDataQuota::default(),
+                    msg: ("User get quota failed due to invalid token")
+                        .to_string(),
+                    error_code: Some(error_code),
+                })
+                .unwrap(),
+            ))
+            .unwrap();
+        return Ok(response);
+    }
+
+    match get_user_data_quota(
+        tracking_label,
+        user_id,
+        config.user_data_quota.default_quota_bytes,
+        &conn,
+    )
+    .await
+    {
+        Ok(quota) => {
+            config
+                .events
+                .publish_user_event(kafka_pool, user_id, "USER_GET_QUOTA", "")
+                .await;
+
+            let response = Response::builder()
+                .status(200)
+                .body(Body::from(
+                    serde_json::to_string(&ApiResUserGetQuota {
+                        quota,
+                        msg: "success".to_string(),
+                        error_code: None,
+                    })
+                    .unwrap(),
+                ))
+                .unwrap();
+            Ok(response)
+        }
+        Err(e) => {
+            error!("{e}");
+            let response = Response::builder()
+                .status(e.status_code())
+                .body(Body::from(
+                    serde_json::to_string(&ApiResUserGetQuota {
+                        quota: ModelUserDataQuota::default(),
+                        msg: format!(
+                            "User get quota failed for user_id={user_id}"
+                        ),
+                        error_code: Some(e.error_code()),
+                    })
+                    .unwrap(),
+                ))
+                .unwrap();
+            Ok(response)
+        }
+    }
+}
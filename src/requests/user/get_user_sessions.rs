@@ -0,0 +1,202 @@
+//! Module for listing a user's active sessions
+//!
+//! ## Get User Sessions
+//!
+//! List the active sessions (issued tokens) for the user that owns the request's token. Each session includes the client's user agent, ip address and device from login.
+//!
+//! - URL path: ``/user/sessions``
+//! - Method: ``GET``
+//! - Handler: [`get_user_sessions`](crate::requests::user::get_user_sessions::get_user_sessions)
+//! - Request: none (uses the token header)
+//! - Response: [`ApiResUserGetSessions`](crate::requests::user::get_user_sessions::ApiResUserGetSessions)
+//!
+use std::convert::Infallible;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Response;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::core::core_config::CoreConfig;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::requests::auth::validate_user_token::validate_user_token;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::models::user_session::get_active_user_sessions;
+use crate::requests::models::user_session::get_user_session_by_token;
+use crate::requests::models::user_session::ModelUserSession;
+
+/// ApiResUserGetSessions
+///
+/// # Response type for get_user_sessions
+///
+/// Return the user's active sessions
+///
+/// # Usage
+///
+/// This type is the serialized output for the function:
+/// [`get_user_sessions`](crate::requests::user::get_user_sessions::get_user_sessions]
+/// and contained within the
+/// hyper [`Body`](hyper::Body)
+/// of the
+/// hyper [`Response`](hyper::Response)
+/// sent back to the client.
+///
+/// # Arguments
+///
+/// * `user_id` - `i32` - user id
+/// * `current_session_id` - `i32` - session id for the
+///   request's token
+/// * `sessions` - `Vec<`[`ModelUserSession`](crate::requests::models::user_session::ModelUserSession)`>` -
+///   active sessions ordered by most recently used
+/// * `msg` - `String` - help message
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   machine-readable failure code (omitted on success)
+///
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApiResUserGetSessions {
+    pub user_id: i32,
+    pub current_session_id: i32,
+    pub sessions: Vec<ModelUserSession>,
+    pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ApiErrorCode>,
+}
+
+/// get_user_sessions
+///
+/// Handler for listing the active sessions for the
+/// user that owns the request's token
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
+///   db threadpool with required tls encryption
+/// * `kafka_pool` -
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+///   for asynchronously publishing messages to the connected kafka cluster
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+///
+/// # Returns
+///
+/// ## get_user_sessions on Success Returns
+///
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResUserGetSessions`](crate::requests::user::get_user_sessions::ApiResUserGetSessions)
+/// dictionary within the
+/// [`Body`](hyper::Body) and a
+/// `200` HTTP status code
+///
+/// Ok([`Response`](hyper::Response))
+///
+/// # Errors
+///
+/// ## get_user_sessions on Failure Returns
+///
+/// All errors return as a
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResUserGetSessions`](crate::requests::user::get_user_sessions::ApiResUserGetSessions)
+/// dictionary with a
+/// `non-200` HTTP status code
+///
+/// Err([`Response`](hyper::Response))
+///
+pub async fn get_user_sessions(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    kafka_pool: &KafkaPublisher,
+    headers: &HeaderMap<HeaderValue>,
+) -> std::result::Result<Response<Body>, Infallible> {
+    let token = &config.token_cookie.get_request_token(headers);
+
+    let conn = db_pool.get().await.unwrap();
+    let (current_session_id, user_id) =
+        get_user_session_by_token(tracking_label, token, &conn)
+            .await
+            .unwrap_or((-1, -1));
+    let valid_token = match user_id > 0 {
+        true => {
+            validate_user_token(tracking_label, config, &conn, headers, user_id)
+                .await
+        }
+        false => Err(ApiErrorCode::InvalidToken),
+    };
+    if let Err(error_code) = valid_token {
+        let response = Response::builder()
+            .status(400)
+            .body(Body::from(
+                serde_json::to_string(&ApiResUserGetSessions {
+                    user_id: -1,
+                    current_session_id: -1,
+                    sessions: Vec::new(),
+                    msg: ("User get sessions failed due to invalid token")
+                        .to_string(),
+                    error_code: Some(error_code),
+                })
+                .unwrap(),
+            ))
+            .unwrap();
+        return Ok(response);
+    }
+
+    match get_active_user_sessions(tracking_label, user_id, &conn).await {
+        Ok(sessions) => {
+            config
+                .events
+                .publish_user_event(
+                    kafka_pool,
+                    user_id,
+                    "USER_GET_SESSIONS",
+                    "",
+                )
+                .await;
+
+            let response = Response::builder()
+                .status(200)
+                .body(Body::from(
+                    serde_json::to_string(&ApiResUserGetSessions {
+                        user_id,
+                        current_session_id,
+                        sessions,
+                        msg: "success".to_string(),
+                        error_code: None,
+                    })
+                    .unwrap(),
+                ))
+                .unwrap();
+            Ok(response)
+        }
+        Err(err_msg) => {
+            error!("{err_msg}");
+            let response = Response::builder()
+                .status(500)
+                .body(Body::from(
+                    serde_json::to_string(&ApiResUserGetSessions {
+                        user_id,
+                        current_session_id,
+                        sessions: Vec::new(),
+                        msg: format!(
+                            "User get sessions failed for user_id={user_id}"
+                        ),
+                        error_code: Some(ApiErrorCode::InternalError),
+                    })
+                    .unwrap(),
+                ))
+                .unwrap();
+            Ok(response)
+        }
+    }
+}
@@ -0,0 +1,456 @@
+//! Module for sharing a user's s3 data record with another
+//! user or role
+//!
+//! ## Grant access to a user data file record
+//!
+//! Share a ``users_data`` record with another user id or role with ``read`` (search) or ``write`` (search and update) access
+//!
+//! - URL path: ``/user/data/acl``
+//! - Method: ``POST``
+//! - Handler: [`grant_user_data_access`](crate::requests::user::grant_user_data_access::grant_user_data_access)
+//! - Request: [`ApiReqUserGrantDataAccess`](crate::requests::user::grant_user_data_access::ApiReqUserGrantDataAccess)
+//! - Response: [`ApiResUserGrantDataAccess`](crate::requests::user::grant_user_data_access::ApiResUserGrantDataAccess)
+//!
+use std::convert::Infallible;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Response;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::core::core_config::CoreConfig;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::monitoring::otel::trace_db_query;
+use crate::requests::auth::validate_user_token::validate_user_token;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::models::user_data_acl::is_valid_user_data_access;
+use crate::requests::models::user_data_acl::ModelUserDataAcl;
+use crate::requests::validation::field_rules::add_field_error;
+use crate::requests::validation::field_rules::check_id;
+use crate::requests::validation::field_rules::check_length;
+use crate::requests::validation::validate_api_req::validate_api_req;
+use crate::requests::validation::validate_api_req::ApiFieldError;
+use crate::requests::validation::validate_api_req::ApiReqValidate;
+
+/// ApiReqUserGrantDataAccess
+///
+/// # Request Type For grant_user_data_access
+///
+/// Share a `users_data` record owned by `user_id` with
+/// either a user (`grantee_user_id`) or a role
+/// (`grantee_role`). Granting again to the same user or
+/// role changes the `access` level.
+///
+/// This type is the deserialized input for:
+/// [`grant_user_data_access`](crate::requests::user::grant_user_data_access::grant_user_data_access]
+///
+/// # Usage
+///
+/// This type is constructed from the deserialized
+/// `bytes` (`&[u8]`) argument
+/// on the
+/// [`grant_user_data_access`](crate::requests::user::grant_user_data_access::grant_user_data_access)
+/// function.
+///
+/// # Arguments
+///
+/// * `user_id` - `i32` - user id that owns the `users_data` record
+/// * `data_id` - `i32` - `users_data.id` to share
+/// * `grantee_user_id` - `Option<i32>` - share with this `users.id`
+/// * `grantee_role` - `Option<String>` - share with all users
+///   with this `users.role`
+/// * `access` - `Option<String>` - `read` (default) or `write`
+///
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApiReqUserGrantDataAccess {
+    pub user_id: i32,
+    pub data_id: i32,
+    pub grantee_user_id: Option<i32>,
+    pub grantee_role: Option<String>,
+    pub access: Option<String>,
+}
+
+impl ApiReqValidate for ApiReqUserGrantDataAccess {
+    /// validate
+    ///
+    /// Require a positive `user_id` and `data_id` with
+    /// either a positive `grantee_user_id` or a
+    /// `grantee_role` and an optional `access`
+    /// of `read` or `write`
+    ///
+    fn validate(&self) -> Vec<ApiFieldError> {
+        let mut errors = Vec::new();
+        check_id(&mut errors, "user_id", self.user_id);
+        check_id(&mut errors, "data_id", self.data_id);
+        match (self.grantee_user_id, &self.grantee_role) {
+            (Some(grantee_user_id), None) => {
+                check_id(&mut errors, "grantee_user_id", grantee_user_id);
+            }
+            (None, Some(grantee_role)) => {
+                check_length(&mut errors, "grantee_role", grantee_role, 1, 20);
+            }
+            _ => {
+                add_field_error(
+                    &mut errors,
+                    "grantee_user_id",
+                    "set either grantee_user_id or grantee_role (not both)",
+                );
+            }
+        }
+        if let Some(access) = &self.access {
+            if !is_valid_user_data_access(access) {
+                add_field_error(
+                    &mut errors,
+                    "access",
+                    "must be one of: read, write",
+                );
+            }
+        }
+        errors
+    }
+}
+
+/// ApiResUserGrantDataAccess
+///
+/// # Response type for grant_user_data_access
+///
+/// Return the stored share for the `users_data` record
+///
+/// # Usage
+///
+/// This type is the serialized output for the function:
+/// [`grant_user_data_access`](crate::requests::user::grant_user_data_access::grant_user_data_access]
+/// and contained within the
+/// hyper [`Body`](hyper::Body)
+/// of the
+/// hyper [`Response`](hyper::Response)
+/// sent back to the client.
+///
+/// # Arguments
+///
+/// * `acl` - [`ModelUserDataAcl`](crate::requests::models::user_data_acl::ModelUserDataAcl) -
+///   the stored share
+/// * `msg` - `String` - help message
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   machine-readable failure code (omitted on success)
+///
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApiResUserGrantDataAccess {
+    pub acl: ModelUserDataAcl,
+    pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ApiErrorCode>,
+}
+
+/// grant_user_data_access
+///
+/// Share a `users_data` record with another user or role.
+/// Only the owner of the record can grant access.
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
+///   db threadpool with required tls encryption
+/// * `kafka_pool` -
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+///   for asynchronously publishing messages to the connected kafka cluster
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+///   [`Request`](hyper::Request)'s [`Body`](hyper::Body)
+/// * `bytes` - `&[u8]` - received bytes from the hyper
+///   [`Request`](hyper::Request)'s [`Body`](hyper::Body)
+///
+/// # Returns
+///
+/// ## grant_user_data_access on Success Returns
+///
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResUserGrantDataAccess`](crate::requests::user::grant_user_data_access::ApiResUserGrantDataAccess)
+/// dictionary within the
+/// [`Body`](hyper::Body) and a
+/// `201` HTTP status code
+///
+/// Ok([`Response`](hyper::Response))
+///
+/// # Errors
+///
+/// ## grant_user_data_access on Failure Returns
+///
+/// All errors return as a
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResUserGrantDataAccess`](crate::requests::user::grant_user_data_access::ApiResUserGrantDataAccess)
+/// dictionary with a
+/// `non-201` HTTP status code
+///
+/// Err([`Response`](hyper::Response))
+///
+pub async fn grant_user_data_access(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    kafka_pool: &KafkaPublisher,
+    headers: &HeaderMap<HeaderValue>,
+    bytes: &[u8],
+) -> std::result::Result<Response<Body>, Infallible> {
+    let req_object: ApiReqUserGrantDataAccess =
+        match serde_json::from_slice(bytes) {
+            Ok(uo) => uo,
+            Err(_) => {
+                let response = Response::builder()
+                    .status(400)
+                    .body(Body::from(
+                        serde_json::to_string(&ApiResUserGrantDataAccess {
+                            acl: ModelUserDataAcl::default(),
+                            msg: ("User grant data access failed - \
+                                please ensure user_id and data_id are set \
+                                with either grantee_user_id or grantee_role \
+                                and optional access \
+                                were set correctly in the request")
+                                .to_string(),
+                            error_code: Some(ApiErrorCode::InvalidRequest),
+                        })
+                        .unwrap(),
+                    ))
+                    .unwrap();
+                return Ok(response);
+            }
+        };
+
+    if let Some(response) = validate_api_req(tracking_label, &req_object) {
+        return Ok(response);
+    }
+
+    let user_id = req_object.user_id;
+    let data_id = req_object.data_id;
+    let access = req_object
+        .access
+        .clone()
+        .unwrap_or_else(|| "read".to_string());
+    let (grantee_column, grantee_value) =
+        match (req_object.grantee_user_id, &req_object.grantee_role) {
+            (Some(grantee_user_id), None) => {
+                ("grantee_user_id", format!("{grantee_user_id}"))
+            }
+            (None, Some(grantee_role)) => (
+                "grantee_role",
+                format!("'{}'", grantee_role.replace('\'', "''")),
+            ),
+            _ => {
+                let response = Response::builder()
+                    .status(400)
+                    .body(Body::from(
+                        serde_json::to_string(&ApiResUserGrantDataAccess {
+                            acl: ModelUserDataAcl::default(),
+                            msg: ("User grant data access failed - \
+                            please set either grantee_user_id \
+                            or grantee_role (not both)")
+                                .to_string(),
+                            error_code: Some(ApiErrorCode::InvalidRequest),
+                        })
+                        .unwrap(),
+                    ))
+                    .unwrap();
+                return Ok(response);
+            }
+        };
+    if req_object.grantee_user_id == Some(user_id) {
+        let response = Response::builder()
+            .status(400)
+            .body(Body::from(
+                serde_json::to_string(&ApiResUserGrantDataAccess {
+                    acl: ModelUserDataAcl::default(),
+                    msg: ("User grant data access failed - \
+                        the owner already has access")
+                        .to_string(),
+                    error_code: Some(ApiErrorCode::Conflict),
+                })
+                .unwrap(),
+            ))
+            .unwrap();
+        return Ok(response);
+    }
+
+    let conn = db_pool.get().await.unwrap();
+    let _token = match validate_user_token(
+        tracking_label,
+        config,
+        &conn,
+        headers,
+        user_id,
+    )
+    .await
+    {
+        Ok(_token) => _token,
+        Err(error_code) => {
+            let response = Response::builder()
+                .status(400)
+                .body(Body::from(
+                    serde_json::to_string(&ApiResUserGrantDataAccess {
+                        acl: ModelUserDataAcl::default(),
+                        msg: ("User grant data access failed \
+                            due to invalid token")
+                            .to_string(),
+                        error_code: Some(error_code),
+                    })
+                    .unwrap(),
+                ))
+                .unwrap();
+            return Ok(response);
+        }
+    };
+
+    // users can only be granted access to records in their tenant
+    let tenant_filter = match req_object.grantee_user_id {
+        Some(grantee_user_id) => format!(
+            "AND EXISTS (\
+                SELECT 1 FROM \
+                    users \
+                WHERE \
+                    users.id = {grantee_user_id} \
+                AND \
+                    users.tenant_id = users_data.tenant_id) "
+        ),
+        None => "".to_string(),
+    };
+    // only the owner can share the record
+    let cur_query = format!(
+        "INSERT INTO \
+            users_data_acl (\
+                data_id, \
+                owner_user_id, \
+                {grantee_column}, \
+                access) \
+        SELECT \
+            users_data.id, \
+            users_data.user_id, \
+            {grantee_value}, \
+            '{access}' \
+        FROM \
+            users_data \
+        WHERE \
+            users_data.id = {data_id} \
+        AND \
+            users_data.user_id = {user_id} \
+        {tenant_filter}\
+        ON CONFLICT (data_id, {grantee_column}) \
+            WHERE {grantee_column} IS NOT NULL \
+        DO UPDATE SET \
+            access = EXCLUDED.access, \
+            updated_at = timezone('UTC'::text, now()) \
+        RETURNING \
+            users_data_acl.id, \
+            users_data_acl.data_id, \
+            users_data_acl.owner_user_id, \
+            users_data_acl.grantee_user_id, \
+            users_data_acl.grantee_role, \
+            users_data_acl.access, \
+            users_data_acl.created_at, \
+            users_data_acl.updated_at;"
+    );
+    let stmt = conn.prepare(&cur_query).await.unwrap();
+    let query_result =
+        match trace_db_query(&cur_query, conn.query(&stmt, &[])).await {
+            Ok(query_result) => query_result,
+            Err(e) => {
+                let response = Response::builder()
+                    .status(500)
+                    .body(Body::from(
+                        serde_json::to_string(&ApiResUserGrantDataAccess {
+                            acl: ModelUserDataAcl::default(),
+                            msg: format!(
+                                "User grant data access failed \
+                            for user_id={user_id} data_id={data_id} \
+                            with err='{e}'"
+                            ),
+                            error_code: Some(ApiErrorCode::InternalError),
+                        })
+                        .unwrap(),
+                    ))
+                    .unwrap();
+                return Ok(response);
+            }
+        };
+
+    // must match up with RETURNING
+    if let Some(row) = query_result.first() {
+        let created_at_utc: chrono::DateTime<chrono::Utc> =
+            row.try_get("created_at").unwrap();
+        let updated_at_str: String = match row.try_get("updated_at") {
+            Ok(v) => {
+                let updated_at_utc: chrono::DateTime<chrono::Utc> = v;
+                format!("{}", updated_at_utc.format("%Y-%m-%dT%H:%M:%SZ"))
+            }
+            Err(_) => "".to_string(),
+        };
+        let acl = ModelUserDataAcl {
+            acl_id: row.try_get("id").unwrap(),
+            data_id: row.try_get("data_id").unwrap(),
+            owner_user_id: row.try_get("owner_user_id").unwrap(),
+            grantee_user_id: row.try_get("grantee_user_id").unwrap(),
+            grantee_role: row.try_get("grantee_role").unwrap(),
+            access: row.try_get("access").unwrap(),
+            created_at: format!(
+                "{}",
+                created_at_utc.format("%Y-%m-%dT%H:%M:%SZ")
+            ),
+            updated_at: updated_at_str,
+        };
+
+        config
+            .events
+            .publish_user_event(
+                kafka_pool,
+                user_id,
+                "USER_GRANT_DATA_ACCESS",
+                // publish the role without its sql quotes
+                &format!(
+                    "data={data_id} \
+                    {grantee_column}={} \
+                    access={access}",
+                    req_object.grantee_role.clone().unwrap_or(grantee_value)
+                ),
+            )
+            .await;
+
+        let response = Response::builder()
+            .status(201)
+            .body(Body::from(
+                serde_json::to_string(&ApiResUserGrantDataAccess {
+                    acl,
+                    msg: "success".to_string(),
+                    error_code: None,
+                })
+                .unwrap(),
+            ))
+            .unwrap();
+        return Ok(response);
+    }
+    let response = Response::builder()
+        .status(404)
+        .body(Body::from(
+            serde_json::to_string(&ApiResUserGrantDataAccess {
+                acl: ModelUserDataAcl::default(),
+                msg: format!(
+                    "User grant data access failed - \
+                    unable to find data_id={data_id} \
+                    owned by user_id={user_id}"
+                ),
+                error_code: Some(ApiErrorCode::NotFound),
+            })
+            .unwrap(),
+        ))
+        .unwrap();
+    Ok(response)
+}
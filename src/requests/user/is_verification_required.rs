@@ -10,11 +10,10 @@
 /// email verification is required for
 /// login and access to resources
 ///
-/// ## Roadmap
-///
-/// This should move into the
-/// [`CoreConfig`](crate::core::core_config::CoreConfig)
-/// server statics.
+/// This is only the default. Handlers should use
+/// ``verification_required`` from
+/// [`CoreConfig::get_settings`](crate::core::core_config::CoreConfig::get_settings)
+/// which admins can override at runtime.
 ///
 /// # Returns
 ///
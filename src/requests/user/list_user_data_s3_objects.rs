@@ -0,0 +1,467 @@
+//! Module for listing a user's s3 keys and reconciling them
+//! with the ``users_data`` records
+//!
+//! ## List User S3 Objects
+//!
+//! List the keys under the user's ``S3_DATA_PREFIX/USER_ID/``
+//! prefix and match each key with the ``users_data`` and
+//! ``users_data_archive`` records that point to it. Keys
+//! without a record are flagged as ``orphan`` and records
+//! without a key are returned in ``missing`` (records that
+//! are still ``uploading`` or were ``expired`` are skipped),
+//! so operators can find drift between the db and the bucket.
+//!
+//! Admins in the ``default`` tenant can list another user's
+//! prefix with the ``user_id`` query parameter.
+//!
+//! - URL path: ``/user/data/s3/list``
+//! - Method: ``GET``
+//! - Handler: [`list_user_data_s3_objects`](crate::requests::user::list_user_data_s3_objects::list_user_data_s3_objects)
+//! - Request: optional query parameters ``user_id`` (admins
+//!   only) and ``max_keys`` (``1`` to ``10000``, default
+//!   ``1000``)
+//! - Response: [`ApiResUserListS3Objects`](crate::requests::user::list_user_data_s3_objects::ApiResUserListS3Objects)
+//!
+use std::collections::HashMap;
+use std::convert::Infallible;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Response;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::core::core_config::CoreConfig;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::requests::admin::is_admin_user::is_admin_user;
+use crate::requests::auth::validate_user_token::validate_user_token;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::models::user_data_repo::UserDataRepo;
+use crate::requests::models::user_session::get_user_session_by_token;
+use crate::utils::circuit_breaker::is_circuit_open_error;
+
+/// default number of keys listed per request
+pub const S3_LIST_DEFAULT_MAX_KEYS: usize = 1000;
+
+/// most keys listed per request
+pub const S3_LIST_MAX_KEYS: usize = 10000;
+
+/// ApiResUserS3Object
+///
+/// A key under the user's s3 prefix
+///
+/// # Arguments
+///
+/// * `key` - `String` - key location
+/// * `size_in_bytes` - `i64` - object size
+/// * `last_modified` - `String` - last modified time
+/// * `storage_class` - `String` - s3 storage class
+/// * `data_ids` - `Vec<i32>` - ``users_data.id`` values with
+///   a ``sloc`` pointing to the key
+/// * `orphan` - `bool` - no record points to the key
+///
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct ApiResUserS3Object {
+    pub key: String,
+    pub size_in_bytes: i64,
+    pub last_modified: String,
+    pub storage_class: String,
+    pub data_ids: Vec<i32>,
+    pub orphan: bool,
+}
+
+/// ApiResUserS3Missing
+///
+/// A record with a ``sloc`` under the user's s3 prefix that
+/// has no key in the bucket
+///
+/// # Arguments
+///
+/// * `data_id` - `i32` - ``users_data.id``
+/// * `sloc` - `String` - full s3 location path
+/// * `status` - `String` - upload status
+/// * `archived` - `bool` - the record is in the
+///   ``users_data_archive`` table
+///
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct ApiResUserS3Missing {
+    pub data_id: i32,
+    pub sloc: String,
+    pub status: String,
+    pub archived: bool,
+}
+
+/// ApiResUserListS3Objects
+///
+/// # Response type for list_user_data_s3_objects
+///
+/// Return the keys under the user's s3 prefix reconciled
+/// with the ``users_data`` records
+///
+/// # Usage
+///
+/// This type is the serialized output for the function:
+/// [`list_user_data_s3_objects`](crate::requests::user::list_user_data_s3_objects::list_user_data_s3_objects]
+/// and contained within the
+/// hyper [`Body`](hyper::Body)
+/// of the
+/// hyper [`Response`](hyper::Response)
+/// sent back to the client.
+///
+/// # Arguments
+///
+/// * `user_id` - `i32` - listed user id
+/// * `bucket` - `String` - ``S3_DATA_BUCKET``
+/// * `prefix` - `String` - listed key prefix
+/// * `objects` - `Vec<`[`ApiResUserS3Object`](crate::requests::user::list_user_data_s3_objects::ApiResUserS3Object)`>` -
+///   keys sorted by key
+/// * `missing` - `Vec<`[`ApiResUserS3Missing`](crate::requests::user::list_user_data_s3_objects::ApiResUserS3Missing)`>` -
+///   records without a key
+/// * `num_objects` - `usize` - number of listed keys
+/// * `num_orphans` - `usize` - number of keys without a
+///   record
+/// * `num_missing` - `usize` - number of records without a
+///   key
+/// * `truncated` - `bool` - the prefix has more than
+///   ``max_keys`` keys (``missing`` only checks records up to
+///   the last listed key)
+/// * `msg` - `String` - help message
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   machine-readable failure code (omitted on success)
+///
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct ApiResUserListS3Objects {
+    pub user_id: i32,
+    pub bucket: String,
+    pub prefix: String,
+    pub objects: Vec<ApiResUserS3Object>,
+    pub missing: Vec<ApiResUserS3Missing>,
+    pub num_objects: usize,
+    pub num_orphans: usize,
+    pub num_missing: usize,
+    pub truncated: bool,
+    pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ApiErrorCode>,
+}
+
+/// list_user_data_s3_objects
+///
+/// Handler for listing the keys under a user's s3 prefix
+/// and flagging keys and records that do not match
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
+///   db threadpool with required tls encryption
+/// * `kafka_pool` -
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+///   for asynchronously publishing messages to the connected kafka cluster
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+/// * `request_query_params` - `&str` - url query string
+///
+/// # Returns
+///
+/// ## list_user_data_s3_objects on Success Returns
+///
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResUserListS3Objects`](crate::requests::user::list_user_data_s3_objects::ApiResUserListS3Objects)
+/// dictionary within the
+/// [`Body`](hyper::Body) and a
+/// `200` HTTP status code
+///
+/// Ok([`Response`](hyper::Response))
+///
+/// # Errors
+///
+/// ## list_user_data_s3_objects on Failure Returns
+///
+/// All errors return as a
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResUserListS3Objects`](crate::requests::user::list_user_data_s3_objects::ApiResUserListS3Objects)
+/// dictionary with a
+/// `non-200` HTTP status code (``503`` with a ``Retry-After``
+/// header while the s3 circuit breaker is open)
+///
+/// Err([`Response`](hyper::Response))
+///
+pub async fn list_user_data_s3_objects(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    kafka_pool: &KafkaPublisher,
+    headers: &HeaderMap<HeaderValue>,
+    request_query_params: &str,
+) -> std::result::Result<Response<Body>, Infallible> {
+    let token = &config.token_cookie.get_request_token(headers);
+
+    let conn = db_pool.get().await.unwrap();
+    let (_, user_id) = get_user_session_by_token(tracking_label, token, &conn)
+        .await
+        .unwrap_or((-1, -1));
+    let valid_token = match user_id > 0 {
+        true => {
+            validate_user_token(tracking_label, config, &conn, headers, user_id)
+                .await
+        }
+        false => Err(ApiErrorCode::InvalidToken),
+    };
+    if let Err(error_code) = valid_token {
+        return Ok(get_list_s3_objects_response(
+            400,
+            "User list s3 objects failed due to invalid token",
+            error_code,
+        ));
+    }
+
+    let query_params: HashMap<String, String> =
+        url::form_urlencoded::parse(request_query_params.as_bytes())
+            .into_owned()
+            .collect();
+    let list_user_id = match query_params.get("user_id") {
+        Some(v) => match v.parse::<i32>() {
+            Ok(v) if v > 0 => v,
+            _ => {
+                return Ok(get_list_s3_objects_response(
+                    400,
+                    "User list s3 objects failed - \
+                    user_id must be a positive integer",
+                    ApiErrorCode::InvalidRequest,
+                ));
+            }
+        },
+        None => user_id,
+    };
+    let max_keys = match query_params.get("max_keys") {
+        Some(v) => match v.parse::<usize>() {
+            Ok(v) if (1..=S3_LIST_MAX_KEYS).contains(&v) => v,
+            _ => {
+                return Ok(get_list_s3_objects_response(
+                    400,
+                    &format!(
+                        "User list s3 objects failed - \
+                        max_keys must be between 1 and {S3_LIST_MAX_KEYS}"
+                    ),
+                    ApiErrorCode::InvalidRequest,
+                ));
+            }
+        },
+        None => S3_LIST_DEFAULT_MAX_KEYS,
+    };
+    if list_user_id != user_id
+        && !is_admin_user(tracking_label, user_id, &conn).await
+    {
+        warn!(
+            "{tracking_label} - \
+            rejected list s3 objects for user_id={list_user_id} \
+            from non-admin user {user_id}"
+        );
+        return Ok(get_list_s3_objects_response(
+            403,
+            "User list s3 objects failed - \
+            only admins can list another user's objects",
+            ApiErrorCode::Forbidden,
+        ));
+    }
+
+    let s3_bucket = std::env::var("S3_DATA_BUCKET")
+        .unwrap_or_else(|_| "BUCKET_NAME".to_string());
+    let s3_prefix = std::env::var("S3_DATA_PREFIX")
+        .unwrap_or_else(|_| "user/data/file".to_string());
+    let prefix = format!("{s3_prefix}/{list_user_id}/");
+
+    // list one extra key to know if there are more
+    let mut entries = match config
+        .object_store
+        .list_objects(tracking_label, &s3_bucket, &prefix, max_keys + 1)
+        .await
+    {
+        Ok(entries) => entries,
+        Err(e) if is_circuit_open_error(&e) => {
+            warn!(
+                "{tracking_label} - \
+                rejected list s3 objects for user_id={list_user_id} \
+                with err='{e}'"
+            );
+            let mut response = get_list_s3_objects_response(
+                503,
+                "User list s3 objects failed - \
+                file storage is unavailable, please retry later",
+                ApiErrorCode::ServiceUnavailable,
+            );
+            response.headers_mut().insert(
+                "Retry-After",
+                config
+                    .s3_circuit_breaker
+                    .get_retry_after_seconds()
+                    .max(1)
+                    .into(),
+            );
+            return Ok(response);
+        }
+        Err(e) => {
+            error!(
+                "{tracking_label} - \
+                failed to list s3 objects for user_id={list_user_id} \
+                with err='{e}'"
+            );
+            return Ok(get_list_s3_objects_response(
+                500,
+                &format!(
+                    "User list s3 objects failed for user_id={list_user_id}"
+                ),
+                ApiErrorCode::InternalError,
+            ));
+        }
+    };
+    let truncated = entries.len() > max_keys;
+    entries.truncate(max_keys);
+
+    let repo = UserDataRepo::new(&conn);
+    let records = match repo
+        .find_by_sloc_prefix(
+            tracking_label,
+            &format!("s3://{s3_bucket}/{prefix}"),
+        )
+        .await
+    {
+        Ok(records) => records,
+        Err(e) => {
+            error!("{e}");
+            return Ok(get_list_s3_objects_response(
+                e.status_code(),
+                &format!(
+                    "User list s3 objects failed for user_id={list_user_id}"
+                ),
+                e.error_code(),
+            ));
+        }
+    };
+
+    let mut data_ids_by_key: HashMap<String, Vec<i32>> = HashMap::new();
+    for record in records.iter() {
+        let key = record
+            .sloc
+            .strip_prefix(&format!("s3://{s3_bucket}/"))
+            .unwrap_or(&record.sloc)
+            .to_string();
+        data_ids_by_key.entry(key).or_default().push(record.data_id);
+    }
+    let objects: Vec<ApiResUserS3Object> = entries
+        .into_iter()
+        .map(|entry| {
+            let data_ids =
+                data_ids_by_key.get(&entry.key).cloned().unwrap_or_default();
+            ApiResUserS3Object {
+                orphan: data_ids.is_empty(),
+                key: entry.key,
+                size_in_bytes: entry.size_in_bytes,
+                last_modified: entry.last_modified,
+                storage_class: entry.storage_class,
+                data_ids,
+            }
+        })
+        .collect();
+    // a truncated listing only covers keys up to the last one
+    let last_key = match truncated {
+        true => objects.last().map(|object| object.key.clone()),
+        false => None,
+    };
+    let listed_keys: std::collections::HashSet<&str> =
+        objects.iter().map(|object| object.key.as_str()).collect();
+    let missing: Vec<ApiResUserS3Missing> = records
+        .iter()
+        .filter(|record| {
+            record.status != "uploading" && record.status != "expired"
+        })
+        .filter_map(|record| {
+            let key = record
+                .sloc
+                .strip_prefix(&format!("s3://{s3_bucket}/"))
+                .unwrap_or(&record.sloc);
+            let in_range = match &last_key {
+                Some(last_key) => key <= last_key.as_str(),
+                None => true,
+            };
+            match in_range && !listed_keys.contains(key) {
+                true => Some(ApiResUserS3Missing {
+                    data_id: record.data_id,
+                    sloc: record.sloc.clone(),
+                    status: record.status.clone(),
+                    archived: record.archived,
+                }),
+                false => None,
+            }
+        })
+        .collect();
+    let num_orphans = objects.iter().filter(|object| object.orphan).count();
+    if num_orphans > 0 || !missing.is_empty() {
+        warn!(
+            "{tracking_label} - \
+            s3://{s3_bucket}/{prefix} has {num_orphans} orphan keys \
+            and {} records without a key",
+            missing.len()
+        );
+    }
+
+    config
+        .events
+        .publish_user_event(kafka_pool, user_id, "USER_LIST_S3_OBJECTS", "")
+        .await;
+
+    let response = Response::builder()
+        .status(200)
+        .body(Body::from(
+            serde_json::to_string(&ApiResUserListS3Objects {
+                user_id: list_user_id,
+                bucket: s3_bucket,
+                prefix,
+                num_objects: objects.len(),
+                num_orphans,
+                num_missing: missing.len(),
+                objects,
+                missing,
+                truncated,
+                msg: "success".to_string(),
+                error_code: None,
+            })
+            .unwrap(),
+        ))
+        .unwrap();
+    Ok(response)
+}
+
+/// get_list_s3_objects_response
+///
+/// Build an error response for
+/// [`list_user_data_s3_objects`](crate::requests::user::list_user_data_s3_objects::list_user_data_s3_objects)
+///
+fn get_list_s3_objects_response(
+    status: u16,
+    msg: &str,
+    error_code: ApiErrorCode,
+) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::from(
+            serde_json::to_string(&ApiResUserListS3Objects {
+                msg: msg.to_string(),
+                error_code: Some(error_code),
+                ..Default::default()
+            })
+            .unwrap(),
+        ))
+        .unwrap()
+}
@@ -1,16 +1,49 @@
 //! Modules for managing all user activities and state
 //!
+pub mod accept_user_invite;
+pub mod batch_user_data;
+pub mod complete_resumable_upload;
+pub mod complete_s3_event_uploads;
+pub mod confirm_user_delete;
 pub mod consume_user_otp;
 pub mod create_otp;
 pub mod create_user;
 pub mod delete_user;
+pub mod download_shared_user_data;
+pub mod download_user_data;
+pub mod forgot_password;
+pub mod get_resumable_upload;
+pub mod get_upload_metadata;
 pub mod get_user;
+pub mod get_user_data_access;
+pub mod get_user_logins;
+pub mod get_user_quota;
+pub mod get_user_sessions;
+pub mod grant_user_data_access;
 pub mod is_verification_enabled;
 pub mod is_verification_required;
+pub mod list_user_data_s3_objects;
+pub mod otp_config;
+pub mod read_upload_body;
+pub mod request_user_delete;
+pub mod resumable_upload;
+pub mod revoke_user_data_access;
+pub mod revoke_user_session;
+pub mod s3_event_callback;
+pub mod search_export;
 pub mod search_user_data;
 pub mod search_users;
+pub mod share_user_data;
+pub mod start_resumable_upload;
+pub mod stream_user_notifications;
+pub mod unshare_user_data;
 pub mod update_user;
 pub mod update_user_data;
 pub mod upload_user_data;
+pub mod upload_user_data_chunk;
 pub mod upsert_user_verification;
+pub mod user_data_quota;
+pub mod user_delete_config;
+pub mod user_upload_limit;
+pub mod validate_upload_header;
 pub mod verify_user;
@@ -0,0 +1,217 @@
+//! Settings for creating one-time-use password
+//! reset tokens (OTP) with
+//! [`create_otp`](crate::requests::user::create_otp::create_otp)
+//!
+//! Each user has at most one active OTP. Creating a new
+//! OTP invalidates the user's previous unconsumed OTPs
+//! (``users_otp.state = 2``), and OTP creation is rate
+//! limited per user. Only argon2 hashes of the tokens are
+//! stored, and an OTP is locked (``users_otp.state = 3``)
+//! after too many wrong tokens.
+//!
+use crate::utils::get_uuid::get_uuid;
+
+/// supported ``USER_OTP_TOKEN_CHARSET`` values
+pub const OTP_TOKEN_CHARSETS: [&str; 4] =
+    ["uuid", "alphanumeric", "hex", "numeric"];
+
+/// supported ``USER_OTP_DELIVERY`` values
+pub const OTP_DELIVERY_METHODS: [&str; 2] = ["response", "email"];
+
+/// OtpConfig
+///
+/// Settings for creating one-time-use password tokens
+///
+/// # Supported Environment Variables
+///
+/// ```bash
+/// export USER_OTP_EXP_IN_SECONDS="2592000"
+/// # uuid, alphanumeric, hex or numeric
+/// export USER_OTP_TOKEN_CHARSET="uuid"
+/// # ignored for the uuid charset
+/// export USER_OTP_TOKEN_LENGTH="32"
+/// # max otps per user within the window (0 = unlimited)
+/// export USER_OTP_RATE_LIMIT_MAX="5"
+/// export USER_OTP_RATE_LIMIT_WINDOW_SECONDS="3600"
+/// # wrong tokens before the active otp is locked (0 = unlimited)
+/// export USER_OTP_MAX_ATTEMPTS="5"
+/// # response or email
+/// export USER_OTP_DELIVERY="response"
+/// # emailed reset link (the user_id, email and token are
+/// # added as query params - empty = no link)
+/// export USER_OTP_RESET_URL="https://app.example.com/reset-password"
+/// ```
+///
+/// # Arguments
+///
+/// * `exp_in_seconds` - `i64` - seconds until a new otp expires
+/// * `token_charset` - `String` - characters used in new tokens
+/// * `token_length` - `usize` - length of new tokens
+///   (`6` to `256`, ignored for the `uuid` charset)
+/// * `rate_limit_max` - `i64` - max otps created per user
+///   within `rate_limit_window_seconds` (`0` = unlimited)
+/// * `rate_limit_window_seconds` - `i64` - rate limit window
+/// * `max_attempts` - `i64` - wrong tokens presented to
+///   ``/user/password/change`` before the user's active
+///   otp is locked (`0` = unlimited)
+/// * `delivery` - `String` - `response` returns the token to
+///   the client and `email` publishes it in a
+///   ``USER_CREATE_OTP`` user event for a mail service
+/// * `reset_url` - `String` - page for the emailed reset
+///   link (empty = the event only has the token)
+///
+#[derive(Clone, Default)]
+pub struct OtpConfig {
+    pub exp_in_seconds: i64,
+    pub token_charset: String,
+    pub token_length: usize,
+    pub rate_limit_max: i64,
+    pub rate_limit_window_seconds: i64,
+    pub max_attempts: i64,
+    pub delivery: String,
+    pub reset_url: String,
+}
+
+impl OtpConfig {
+    /// build_otp_config
+    ///
+    /// Build an
+    /// [`OtpConfig`](crate::requests::user::otp_config::OtpConfig)
+    /// from environment variables. Unsupported charset and
+    /// delivery values fall back to the defaults.
+    ///
+    pub fn build_otp_config() -> Self {
+        let get_env = |key: &str, default: i64| -> i64 {
+            std::env::var(key)
+                .unwrap_or_else(|_| format!("{default}"))
+                .parse::<i64>()
+                .unwrap_or(default)
+        };
+        let get_one_of = |key: &str, allowed: &[&str]| -> String {
+            let value = std::env::var(key)
+                .unwrap_or_else(|_| allowed[0].to_string())
+                .to_lowercase();
+            match allowed.contains(&value.as_str()) {
+                true => value,
+                false => {
+                    warn!("unsupported {key}={value} - using {}", allowed[0]);
+                    allowed[0].to_string()
+                }
+            }
+        };
+        OtpConfig {
+            exp_in_seconds: get_env("USER_OTP_EXP_IN_SECONDS", 2592000),
+            token_charset: get_one_of(
+                "USER_OTP_TOKEN_CHARSET",
+                &OTP_TOKEN_CHARSETS,
+            ),
+            token_length: get_env("USER_OTP_TOKEN_LENGTH", 32).clamp(6, 256)
+                as usize,
+            rate_limit_max: get_env("USER_OTP_RATE_LIMIT_MAX", 5).max(0),
+            rate_limit_window_seconds: get_env(
+                "USER_OTP_RATE_LIMIT_WINDOW_SECONDS",
+                3600,
+            )
+            .max(1),
+            max_attempts: get_env("USER_OTP_MAX_ATTEMPTS", 5).max(0),
+            delivery: get_one_of("USER_OTP_DELIVERY", &OTP_DELIVERY_METHODS),
+            reset_url: std::env::var("USER_OTP_RESET_URL").unwrap_or_default(),
+        }
+    }
+
+    /// get_reset_link
+    ///
+    /// Build the emailed password reset link from
+    /// ``USER_OTP_RESET_URL``
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - `i32` - user id
+    /// * `email` - `&str` - user email
+    /// * `token` - `&str` - the new (unhashed) otp token
+    ///
+    /// # Returns
+    ///
+    /// `Option<String>` - `None` when ``USER_OTP_RESET_URL``
+    /// is not set or is not a valid url
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use restapi::requests::user::otp_config::OtpConfig;
+    /// let otp_config = OtpConfig {
+    ///     reset_url: "https://app.example.com/reset".to_string(),
+    ///     ..OtpConfig::default()
+    /// };
+    /// assert_eq!(
+    ///     otp_config.get_reset_link(2, "a+b@email.com", "abc"),
+    ///     Some(
+    ///         "https://app.example.com/reset?user_id=2&email=a%2Bb%40email.com&token=abc"
+    ///             .to_string()
+    ///     )
+    /// );
+    /// assert_eq!(OtpConfig::default().get_reset_link(2, "a@b.com", "abc"), None);
+    /// ```
+    ///
+    pub fn get_reset_link(
+        &self,
+        user_id: i32,
+        email: &str,
+        token: &str,
+    ) -> Option<String> {
+        if self.reset_url.is_empty() {
+            return None;
+        }
+        match url::Url::parse_with_params(
+            &self.reset_url,
+            &[
+                ("user_id", format!("{user_id}").as_str()),
+                ("email", email),
+                ("token", token),
+            ],
+        ) {
+            Ok(link) => Some(link.to_string()),
+            Err(e) => {
+                warn!(
+                    "invalid USER_OTP_RESET_URL={} with err='{e}'",
+                    self.reset_url
+                );
+                None
+            }
+        }
+    }
+
+    /// generate_token
+    ///
+    /// Create a new random otp token using the configured
+    /// `token_charset` and `token_length`
+    ///
+    /// # Returns
+    ///
+    /// `String` - the new (unhashed) token
+    ///
+    pub fn generate_token(&self) -> String {
+        let charset: &[u8] = match self.token_charset.as_str() {
+            "alphanumeric" => {
+                b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789"
+            }
+            "hex" => b"0123456789abcdef",
+            "numeric" => b"0123456789",
+            _ => return format!("{}{}", get_uuid(), get_uuid()),
+        };
+        // reject random bytes past the largest multiple of the
+        // charset length so every character is equally likely
+        let max_byte = 256 - (256 % charset.len());
+        let mut token = String::with_capacity(self.token_length);
+        let mut buf = [0u8; 64];
+        while token.len() < self.token_length {
+            openssl::rand::rand_bytes(&mut buf).unwrap();
+            for b in buf.iter() {
+                if (*b as usize) < max_byte && token.len() < self.token_length {
+                    token.push(charset[*b as usize % charset.len()] as char);
+                }
+            }
+        }
+        token
+    }
+}
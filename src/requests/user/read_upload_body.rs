@@ -0,0 +1,108 @@
+//! Enforce the ``max_upload_size_bytes`` runtime setting
+//! for user data uploads
+//!
+use futures::StreamExt;
+
+use hyper::body::Bytes;
+use hyper::header::HeaderValue;
+use hyper::header::CONTENT_LENGTH;
+use hyper::HeaderMap;
+
+/// get_upload_too_large_msg
+///
+/// Error message for uploads over the
+/// ``max_upload_size_bytes`` setting
+///
+/// # Arguments
+///
+/// * `max_upload_size_bytes` - `i64` - max upload size in bytes
+///
+pub fn get_upload_too_large_msg(max_upload_size_bytes: i64) -> String {
+    format!(
+        "User data upload rejected - the upload is larger than \
+        the max upload size of {max_upload_size_bytes} bytes"
+    )
+}
+
+/// validate_upload_content_length
+///
+/// Reject uploads with a ``Content-Length`` header over the
+/// ``max_upload_size_bytes`` setting before reading the body
+///
+/// # Arguments
+///
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+/// * `max_upload_size_bytes` - `i64` - max upload size in bytes
+///   (`0` = unlimited)
+///
+/// # Errors
+///
+/// `Err((413, String))` - HTTP status code
+/// and an error message for the client
+///
+pub fn validate_upload_content_length(
+    headers: &HeaderMap<HeaderValue>,
+    max_upload_size_bytes: i64,
+) -> Result<(), (u16, String)> {
+    if max_upload_size_bytes < 1 {
+        return Ok(());
+    }
+    let content_length = headers
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(0);
+    if content_length > max_upload_size_bytes as u64 {
+        return Err((413, get_upload_too_large_msg(max_upload_size_bytes)));
+    }
+    Ok(())
+}
+
+/// read_upload_body
+///
+/// Read a raw upload body and stop as soon as it is larger
+/// than the ``max_upload_size_bytes`` setting (chunked
+/// uploads do not send a ``Content-Length``)
+///
+/// # Arguments
+///
+/// * `body` - `hyper::Body` - the hyper
+///   [`Request`](hyper::Request)'s [`Body`](hyper::Body)
+/// * `max_upload_size_bytes` - `i64` - max upload size in bytes
+///   (`0` = unlimited)
+///
+/// # Returns
+///
+/// Ok(`Bytes`)
+///
+/// # Errors
+///
+/// `Err((u16, String))` - HTTP status code (`413` for
+/// uploads over the limit) and an error message for the client
+///
+pub async fn read_upload_body(
+    mut body: hyper::Body,
+    max_upload_size_bytes: i64,
+) -> Result<Bytes, (u16, String)> {
+    let mut contents: Vec<u8> = Vec::new();
+    while let Some(chunk) = body.next().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                return Err((
+                    400,
+                    format!("Failed to read the upload body with err='{e}'"),
+                ));
+            }
+        };
+        if max_upload_size_bytes > 0
+            && (contents.len() + chunk.len()) as u64
+                > max_upload_size_bytes as u64
+        {
+            return Err((413, get_upload_too_large_msg(max_upload_size_bytes)));
+        }
+        contents.extend_from_slice(&chunk);
+    }
+    Ok(Bytes::from(contents))
+}
@@ -0,0 +1,319 @@
+//! Module for users that want to delete their own account
+//!
+//! ## Request Self-Service Account Deletion
+//!
+//! Email a one-time-use confirmation token to the user that owns the request's token. The token is only published in a ``USER_DELETE_REQUEST`` user event for a mail service (with a ``USER_DELETE_CONFIRM_URL`` link when it is set) and the account is not changed until the token is sent to ``/user/delete/confirm``. A new request replaces the user's previous unconfirmed token.
+//!
+//! - URL path: ``/user/delete/request``
+//! - Method: ``POST``
+//! - Handler: [`request_user_delete`](crate::requests::user::request_user_delete::request_user_delete)
+//! - Request: [`ApiReqUserDeleteRequest`](crate::requests::user::request_user_delete::ApiReqUserDeleteRequest)
+//! - Response: [`ApiResUserDeleteRequest`](crate::requests::user::request_user_delete::ApiResUserDeleteRequest)
+//!
+
+use std::convert::Infallible;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Response;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::core::core_config::CoreConfig;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::monitoring::otel::trace_db_query;
+use crate::requests::auth::validate_user_token::validate_user_token;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::models::user::get_user_by_id;
+use crate::requests::validation::field_rules::check_id;
+use crate::requests::validation::validate_api_req::validate_api_req;
+use crate::requests::validation::validate_api_req::ApiFieldError;
+use crate::requests::validation::validate_api_req::ApiReqValidate;
+use crate::utils::hash_token::hash_token;
+
+/// ApiReqUserDeleteRequest
+///
+/// # Request Type For request_user_delete
+///
+/// Start deleting the user's own account
+///
+/// This type is the deserialized input for:
+/// [`request_user_delete`](crate::requests::user::request_user_delete::request_user_delete]
+///
+/// # Arguments
+///
+/// * `user_id` - `i32` - user id
+///
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApiReqUserDeleteRequest {
+    // users.id
+    pub user_id: i32,
+}
+
+impl ApiReqValidate for ApiReqUserDeleteRequest {
+    /// validate
+    ///
+    /// Require a positive `user_id`
+    ///
+    fn validate(&self) -> Vec<ApiFieldError> {
+        let mut errors = Vec::new();
+        check_id(&mut errors, "user_id", self.user_id);
+        errors
+    }
+}
+
+/// ApiResUserDeleteRequest
+///
+/// # Response type for request_user_delete
+///
+/// Notify the client that the confirmation token
+/// was sent to the user's email
+///
+/// # Arguments
+///
+/// * `user_id` - `i32` - user id
+/// * `exp_date` - `String` - UTC-formatted date time string
+///   when the confirmation token expires
+/// * `msg` - `String` - help message
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   machine-readable failure code (omitted on success)
+///
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApiResUserDeleteRequest {
+    pub user_id: i32,
+    pub exp_date: String,
+    pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ApiErrorCode>,
+}
+
+/// request_user_delete
+///
+/// Create a one-time-use account deletion confirmation
+/// token for the user that owns the request's token
+/// and publish it for the mail service. Only the token
+/// hash is stored in the ``users_delete_requests`` table.
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
+///   db threadpool with required tls encryption
+/// * `kafka_pool` -
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+///   for asynchronously publishing messages to the connected kafka cluster
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+/// * `bytes` - `&[u8]` - received bytes from the hyper
+///   [`Request`](hyper::Request)'s [`Body`](hyper::Body)
+///
+/// # Returns
+///
+/// ## Success
+///
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResUserDeleteRequest`](crate::requests::user::request_user_delete::ApiResUserDeleteRequest)
+/// dictionary within the
+/// [`Body`](hyper::Body) and a
+/// `202` HTTP status code
+///
+/// Ok([`Response`](hyper::Response))
+///
+/// # Errors
+///
+/// `400` for an invalid request or token, `503` when kafka
+/// user events are disabled (the token cannot be emailed)
+/// and `500` when the db insert fails
+///
+/// Err([`Response`](hyper::Response))
+///
+pub async fn request_user_delete(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    kafka_pool: &KafkaPublisher,
+    headers: &HeaderMap<HeaderValue>,
+    bytes: &[u8],
+) -> std::result::Result<Response<Body>, Infallible> {
+    let req_object: ApiReqUserDeleteRequest =
+        match serde_json::from_slice(bytes) {
+            Ok(uo) => uo,
+            Err(_) => {
+                return Ok(get_delete_request_response(
+                    400,
+                    -1,
+                    "User delete request failed - please ensure \
+                    user_id was set correctly in the request",
+                    ApiErrorCode::InvalidRequest,
+                ));
+            }
+        };
+
+    if let Some(response) = validate_api_req(tracking_label, &req_object) {
+        return Ok(response);
+    }
+
+    let user_id = req_object.user_id;
+    let conn = db_pool.get().await.unwrap();
+    if let Err(error_code) =
+        validate_user_token(tracking_label, config, &conn, headers, user_id)
+            .await
+    {
+        return Ok(get_delete_request_response(
+            400,
+            user_id,
+            "User delete request failed due to invalid token",
+            error_code,
+        ));
+    }
+
+    // the token is only delivered by the mail service
+    if !config.events.enabled {
+        error!(
+            "{tracking_label} - \
+            unable to email a user delete confirmation token - \
+            /user/delete/request requires KAFKA_PUBLISH_EVENTS=1"
+        );
+        return Ok(get_delete_request_response(
+            503,
+            user_id,
+            "User delete request failed - \
+            email delivery is not available",
+            ApiErrorCode::FeatureDisabled,
+        ));
+    }
+
+    let user_model = match get_user_by_id(tracking_label, user_id, &conn).await
+    {
+        Ok(user_model) => user_model,
+        Err(_) => {
+            return Ok(get_delete_request_response(
+                400,
+                user_id,
+                &format!(
+                    "User delete request failed - \
+                    unable to find user with id: {user_id}"
+                ),
+                ApiErrorCode::UserNotFound,
+            ));
+        }
+    };
+
+    let exp_date = chrono::Utc::now()
+        + chrono::Duration::seconds(config.user_delete.exp_in_seconds);
+    // reuse the otp token charset and length
+    let token = config.otp.generate_token();
+    let token_hash = hash_token(&token, &config.server_password_salt);
+
+    // each user has one deletion request and a new
+    // request replaces the previous token
+    let query = format!(
+        "INSERT INTO \
+            users_delete_requests (\
+                user_id, \
+                token, \
+                state, \
+                exp_date) \
+        VALUES (\
+            {user_id}, \
+            '{token_hash}', \
+            0, \
+            '{exp_date}') \
+        ON CONFLICT (user_id) DO UPDATE SET \
+            token = EXCLUDED.token, \
+            state = 0, \
+            exp_date = EXCLUDED.exp_date, \
+            created_at = timezone('UTC'::text, now()), \
+            confirmed_at = NULL, \
+            purge_date = NULL \
+        RETURNING \
+            users_delete_requests.id;"
+    );
+    let stmt = conn.prepare(&query).await.unwrap();
+    if let Err(e) = trace_db_query(&query, conn.query(&stmt, &[])).await {
+        error!(
+            "{tracking_label} - \
+            failed to create a user delete request for user {user_id} \
+            with err='{e}'"
+        );
+        return Ok(get_delete_request_response(
+            500,
+            user_id,
+            &format!(
+                "User delete request failed for user_id={user_id} \
+                with err='{e}'"
+            ),
+            ApiErrorCode::InternalError,
+        ));
+    }
+
+    let details = match config.user_delete.get_confirm_link(user_id, &token) {
+        Some(link) => {
+            format!("email={} token={token} link={link}", user_model.email)
+        }
+        None => format!("email={} token={token}", user_model.email),
+    };
+    config
+        .events
+        .publish_user_event(
+            kafka_pool,
+            user_id,
+            "USER_DELETE_REQUEST",
+            &details,
+        )
+        .await;
+
+    info!(
+        "{tracking_label} - \
+        created a delete confirmation token for user {user_id}"
+    );
+
+    let response = Response::builder()
+        .status(202)
+        .body(Body::from(
+            serde_json::to_string(&ApiResUserDeleteRequest {
+                user_id,
+                exp_date: format!("{}", exp_date.format("%Y-%m-%dT%H:%M:%SZ")),
+                msg: "a confirmation token was sent to the user's email"
+                    .to_string(),
+                error_code: None,
+            })
+            .unwrap(),
+        ))
+        .unwrap();
+    Ok(response)
+}
+
+/// get_delete_request_response
+///
+/// Build an error response for
+/// [`request_user_delete`](crate::requests::user::request_user_delete::request_user_delete)
+///
+fn get_delete_request_response(
+    status: u16,
+    user_id: i32,
+    msg: &str,
+    error_code: ApiErrorCode,
+) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::from(
+            serde_json::to_string(&ApiResUserDeleteRequest {
+                user_id,
+                exp_date: "".to_string(),
+                msg: msg.to_string(),
+                error_code: Some(error_code),
+            })
+            .unwrap(),
+        ))
+        .unwrap()
+}
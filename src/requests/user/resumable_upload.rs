@@ -0,0 +1,399 @@
+//! Settings and helpers for resumable uploads
+//!
+//! A resumable upload stores a large file in s3 as a
+//! multipart upload that receives one ``Content-Range``
+//! chunk per request, so a client can resume an
+//! interrupted upload from the last received byte:
+//!
+//! 1. [`start_resumable_upload`](crate::requests::user::start_resumable_upload::start_resumable_upload) -
+//!    ``POST /user/data/uploads`` creates a ``users_data``
+//!    record in the ``uploading`` status
+//! 2. [`upload_user_data_chunk`](crate::requests::user::upload_user_data_chunk::upload_user_data_chunk) -
+//!    ``PUT /user/data/DATAID/chunks`` stores each chunk as
+//!    the next multipart upload part
+//! 3. [`get_resumable_upload`](crate::requests::user::get_resumable_upload::get_resumable_upload) -
+//!    ``GET /user/data/DATAID/chunks`` returns the
+//!    ``received_bytes`` offset to resume from
+//! 4. [`complete_resumable_upload`](crate::requests::user::complete_resumable_upload::complete_resumable_upload) -
+//!    ``POST /user/data/DATAID/complete`` combines the parts
+//!    into the s3 object
+//!
+//! Uploads that are not completed within
+//! ``UPLOAD_RESUMABLE_EXPIRE_HOURS`` are aborted by the
+//! [`UserDataLifecycle`](crate::archive::user_data_lifecycle::UserDataLifecycle)
+//! task.
+//!
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::PooledConnection;
+use bb8_postgres::PostgresConnectionManager;
+
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Response;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::core::core_config::CoreConfig;
+use crate::requests::auth::validate_user_token::validate_user_token;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::models::user_data_upload::ModelUserDataUpload;
+use crate::requests::models::user_session::get_user_session_by_token;
+use crate::utils::circuit_breaker::is_circuit_open_error;
+
+/// max number of parts in an s3 multipart upload
+pub const RESUMABLE_UPLOAD_MAX_PARTS: i64 = 10000;
+
+/// ResumableUploadConfig
+///
+/// Settings for resumable uploads
+///
+/// # Supported Environment Variables
+///
+/// ```bash
+/// # every chunk except the last one must be at least
+/// # 5 MiB for s3 multipart uploads
+/// export UPLOAD_RESUMABLE_MIN_CHUNK_BYTES="5242880"
+/// export UPLOAD_RESUMABLE_MAX_CHUNK_BYTES="67108864"
+/// export UPLOAD_RESUMABLE_MAX_BYTES="53687091200"
+/// export UPLOAD_RESUMABLE_EXPIRE_HOURS="24"
+/// ```
+///
+/// # Arguments
+///
+/// * `min_chunk_bytes` - `i64` - min size of every chunk
+///   except the last one
+/// * `max_chunk_bytes` - `i64` - max size of a chunk
+/// * `max_bytes` - `i64` - max size of a resumable upload
+/// * `expire_hours` - `i64` - hours to complete an upload
+///   before the lifecycle task aborts it
+///
+#[derive(Clone, Default)]
+pub struct ResumableUploadConfig {
+    pub min_chunk_bytes: i64,
+    pub max_chunk_bytes: i64,
+    pub max_bytes: i64,
+    pub expire_hours: i64,
+}
+
+impl ResumableUploadConfig {
+    /// build_resumable_upload_config
+    ///
+    /// Build a
+    /// [`ResumableUploadConfig`](crate::requests::user::resumable_upload::ResumableUploadConfig)
+    /// from environment variables
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) -
+    /// ``UPLOAD_RESUMABLE_MIN_CHUNK_BYTES`` is larger than
+    /// ``UPLOAD_RESUMABLE_MAX_CHUNK_BYTES``
+    ///
+    pub fn build_resumable_upload_config() -> Result<Self, String> {
+        let get_env = |key: &str, default: i64| -> i64 {
+            std::env::var(key)
+                .unwrap_or_else(|_| format!("{default}"))
+                .parse::<i64>()
+                .unwrap_or(default)
+        };
+        let min_chunk_bytes =
+            get_env("UPLOAD_RESUMABLE_MIN_CHUNK_BYTES", 5242880).max(1);
+        let max_chunk_bytes =
+            get_env("UPLOAD_RESUMABLE_MAX_CHUNK_BYTES", 67108864).max(1);
+        if min_chunk_bytes > max_chunk_bytes {
+            return Err(format!(
+                "invalid UPLOAD_RESUMABLE_MIN_CHUNK_BYTES={min_chunk_bytes} \
+                - must not be larger than \
+                UPLOAD_RESUMABLE_MAX_CHUNK_BYTES={max_chunk_bytes}"
+            ));
+        }
+        Ok(ResumableUploadConfig {
+            min_chunk_bytes,
+            max_chunk_bytes,
+            max_bytes: get_env("UPLOAD_RESUMABLE_MAX_BYTES", 53687091200)
+                .max(1),
+            expire_hours: get_env("UPLOAD_RESUMABLE_EXPIRE_HOURS", 24)
+                .clamp(1, 24 * 30),
+        })
+    }
+
+    /// get_max_upload_bytes
+    ///
+    /// Largest upload that fits in ``UPLOAD_RESUMABLE_MAX_BYTES``
+    /// and in the max number of max-size parts
+    ///
+    /// # Returns
+    ///
+    /// `i64` - max ``total_bytes`` for a new upload
+    ///
+    pub fn get_max_upload_bytes(&self) -> i64 {
+        self.max_bytes.min(
+            self.max_chunk_bytes
+                .saturating_mul(RESUMABLE_UPLOAD_MAX_PARTS),
+        )
+    }
+}
+
+/// ApiResUserResumableUpload
+///
+/// # Response type for the resumable upload handlers
+///
+/// # Arguments
+///
+/// * `user_id` - `i32` - `users.id`
+/// * `data_id` - `i32` - `users_data.id`
+/// * `filename` - `String` - name of the file
+/// * `sloc` - `String` - remote s3 location
+/// * `status` - `String` - ``uploading`` until the upload
+///   is completed
+/// * `total_bytes` - `i64` - size of the file
+/// * `received_bytes` - `i64` - bytes stored so far (the
+///   start of the next chunk)
+/// * `next_part` - `i64` - part number of the next chunk
+/// * `upload_expires_at` - `String` - time the upload is
+///   aborted if it is not completed
+/// * `msg` - `String` - help message
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   machine-readable failure code (omitted on success)
+///
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct ApiResUserResumableUpload {
+    pub user_id: i32,
+    pub data_id: i32,
+    pub filename: String,
+    pub sloc: String,
+    pub status: String,
+    pub total_bytes: i64,
+    pub received_bytes: i64,
+    pub next_part: i64,
+    pub upload_expires_at: String,
+    pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ApiErrorCode>,
+}
+
+/// parse_content_range
+///
+/// Parse a ``Content-Range: bytes START-END/TOTAL`` header
+///
+/// # Arguments
+///
+/// * `content_range` - `&str` - header value
+///
+/// # Returns
+///
+/// `Option<(i64, i64, i64)>` - inclusive ``(start, end,
+/// total)`` (`None` = invalid or unknown total)
+///
+/// # Examples
+///
+/// ```
+/// use restapi::requests::user::resumable_upload::parse_content_range;
+/// assert_eq!(
+///     parse_content_range("bytes 0-1023/4096"),
+///     Some((0, 1023, 4096)));
+/// assert_eq!(parse_content_range("bytes 0-1023/*"), None);
+/// assert_eq!(parse_content_range("bytes 10-5/4096"), None);
+/// assert_eq!(parse_content_range("bytes 0-4096/4096"), None);
+/// ```
+///
+pub fn parse_content_range(content_range: &str) -> Option<(i64, i64, i64)> {
+    let range = content_range.trim().strip_prefix("bytes ")?;
+    let (start_end, total) = range.split_once('/')?;
+    let (start, end) = start_end.split_once('-')?;
+    let start = start.trim().parse::<i64>().ok()?;
+    let end = end.trim().parse::<i64>().ok()?;
+    let total = total.trim().parse::<i64>().ok()?;
+    match start >= 0 && start <= end && end < total {
+        true => Some((start, end, total)),
+        false => None,
+    }
+}
+
+/// get_resumable_upload_data_id
+///
+/// Get the ``users_data.id`` from a resumable upload url
+/// path (``/user/data/DATAID/ACTION``)
+///
+/// # Arguments
+///
+/// * `request_uri` - `&str` - url path
+/// * `action` - `&str` - last path segment (``chunks`` or
+///   ``complete``)
+///
+/// # Returns
+///
+/// `Option<i32>` - positive data id (`None` = the path
+/// does not match)
+///
+/// # Examples
+///
+/// ```
+/// use restapi::requests::user::resumable_upload::get_resumable_upload_data_id;
+/// assert_eq!(
+///     get_resumable_upload_data_id("/user/data/12/chunks", "chunks"),
+///     Some(12));
+/// assert_eq!(
+///     get_resumable_upload_data_id("/user/data/12/chunks", "complete"),
+///     None);
+/// assert_eq!(
+///     get_resumable_upload_data_id("/user/data/-1/chunks", "chunks"),
+///     None);
+/// ```
+///
+pub fn get_resumable_upload_data_id(
+    request_uri: &str,
+    action: &str,
+) -> Option<i32> {
+    request_uri
+        .strip_prefix("/user/data/")?
+        .strip_suffix(action)?
+        .strip_suffix('/')?
+        .parse::<i32>()
+        .ok()
+        .filter(|data_id| *data_id > 0)
+}
+
+/// get_resumable_upload_response
+///
+/// Build the hyper [`Response`](hyper::Response) for the
+/// resumable upload handlers
+///
+/// # Arguments
+///
+/// * `status` - `u16` - HTTP status code
+/// * `res` - [`ApiResUserResumableUpload`](crate::requests::user::resumable_upload::ApiResUserResumableUpload)
+///
+/// # Returns
+///
+/// [`Response`](hyper::Response) containing the
+/// json-serialized `res`
+///
+pub fn get_resumable_upload_response(
+    status: u16,
+    res: &ApiResUserResumableUpload,
+) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::from(serde_json::to_string(res).unwrap()))
+        .unwrap()
+}
+
+/// get_object_store_error_response
+///
+/// Build the response for a failed object store call with
+/// a ``503``, ``SERVICE_UNAVAILABLE`` and a ``Retry-After``
+/// header when the s3 circuit breaker rejected the call
+/// (otherwise a ``500`` and ``INTERNAL_ERROR``)
+///
+/// # Arguments
+///
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `err_msg` - `&str` - object store error
+/// * `build_response` - closure building the response for
+///   an HTTP status code and
+///   [`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)
+///
+pub fn get_object_store_error_response<F>(
+    config: &CoreConfig,
+    err_msg: &str,
+    build_response: F,
+) -> Response<Body>
+where
+    F: FnOnce(u16, ApiErrorCode) -> Response<Body>,
+{
+    if !is_circuit_open_error(err_msg) {
+        return build_response(500, ApiErrorCode::InternalError);
+    }
+    let mut response = build_response(503, ApiErrorCode::ServiceUnavailable);
+    response.headers_mut().insert(
+        "Retry-After",
+        config
+            .s3_circuit_breaker
+            .get_retry_after_seconds()
+            .max(1)
+            .into(),
+    );
+    response
+}
+
+/// get_resumable_upload_user_id
+///
+/// Get the user id for the request's token (the chunk,
+/// status and complete requests only send the token)
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+///   an established db connection from the
+///   postgres client db threadpool
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+///
+/// # Returns
+///
+/// `Result<i32, `[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+/// the error code if the token is not a valid active
+/// session
+///
+pub async fn get_resumable_upload_user_id(
+    tracking_label: &str,
+    config: &CoreConfig,
+    conn: &PooledConnection<'_, PostgresConnectionManager<MakeTlsConnector>>,
+    headers: &HeaderMap<HeaderValue>,
+) -> Result<i32, ApiErrorCode> {
+    let token = &config.token_cookie.get_request_token(headers);
+    let user_id =
+        match get_user_session_by_token(tracking_label, token, conn).await {
+            Ok((_, user_id)) => user_id,
+            Err(_) => return Err(ApiErrorCode::InvalidToken),
+        };
+    validate_user_token(tracking_label, config, conn, headers, user_id)
+        .await
+        .map(|_| user_id)
+}
+
+/// get_resumable_upload_state_response
+///
+/// Build the response for an in-progress upload with
+/// its current offset
+///
+/// # Arguments
+///
+/// * `status` - `u16` - HTTP status code
+/// * `upload` - [`ModelUserDataUpload`](crate::requests::models::user_data_upload::ModelUserDataUpload)
+/// * `received_bytes` - `i64` - bytes stored so far
+/// * `num_parts` - `i64` - parts stored so far
+/// * `msg` - `String` - message for the client
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   failure code (`None` on success)
+///
+pub fn get_resumable_upload_state_response(
+    status: u16,
+    upload: &ModelUserDataUpload,
+    received_bytes: i64,
+    num_parts: i64,
+    msg: String,
+    error_code: Option<ApiErrorCode>,
+) -> Response<Body> {
+    get_resumable_upload_response(
+        status,
+        &ApiResUserResumableUpload {
+            user_id: upload.user_id,
+            data_id: upload.data_id,
+            filename: upload.filename.clone(),
+            sloc: upload.sloc.clone(),
+            status: upload.status.clone(),
+            total_bytes: upload.total_bytes,
+            received_bytes,
+            next_part: num_parts + 1,
+            upload_expires_at: upload.expires_at.clone(),
+            msg,
+            error_code,
+        },
+    )
+}
@@ -0,0 +1,262 @@
+//! Module for revoking one of a user's sessions
+//!
+//! ## Revoke a User Session
+//!
+//! Revoke an active session (issued token) for the user that owns the request's token. Requests with a revoked session's token are rejected even if the jwt has not expired. Revoking the current session logs the client out.
+//!
+//! - URL path: ``/user/sessions/SESSIONID``
+//! - Method: ``DELETE``
+//! - Handler: [`revoke_user_session`](crate::requests::user::revoke_user_session::revoke_user_session)
+//! - Request: none (uses the token header)
+//! - Response: [`ApiResUserRevokeSession`](crate::requests::user::revoke_user_session::ApiResUserRevokeSession)
+//!
+use std::convert::Infallible;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Response;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::core::core_config::CoreConfig;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::monitoring::otel::trace_db_query;
+use crate::requests::auth::validate_user_token::validate_user_token;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::models::user_session::get_user_session_by_token;
+
+/// ApiResUserRevokeSession
+///
+/// # Response type for revoke_user_session
+///
+/// Notify the client the session was revoked
+///
+/// # Usage
+///
+/// This type is the serialized output for the function:
+/// [`revoke_user_session`](crate::requests::user::revoke_user_session::revoke_user_session]
+/// and contained within the
+/// hyper [`Body`](hyper::Body)
+/// of the
+/// hyper [`Response`](hyper::Response)
+/// sent back to the client.
+///
+/// # Arguments
+///
+/// * `user_id` - `i32` - user id
+/// * `session_id` - `i32` - revoked `users_tokens.id`
+/// * `msg` - `String` - help message
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   machine-readable failure code (omitted on success)
+///
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApiResUserRevokeSession {
+    pub user_id: i32,
+    pub session_id: i32,
+    pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ApiErrorCode>,
+}
+
+/// revoke_user_session
+///
+/// Handler for revoking one of the sessions for
+/// the user that owns the request's token
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
+///   db threadpool with required tls encryption
+/// * `kafka_pool` -
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+///   for asynchronously publishing messages to the connected kafka cluster
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+/// * `request_uri` - `&str` - url path with the session id
+///
+/// # Returns
+///
+/// ## revoke_user_session on Success Returns
+///
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResUserRevokeSession`](crate::requests::user::revoke_user_session::ApiResUserRevokeSession)
+/// dictionary within the
+/// [`Body`](hyper::Body) and a
+/// `200` HTTP status code
+///
+/// Ok([`Response`](hyper::Response))
+///
+/// # Errors
+///
+/// ## revoke_user_session on Failure Returns
+///
+/// All errors return as a
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResUserRevokeSession`](crate::requests::user::revoke_user_session::ApiResUserRevokeSession)
+/// dictionary with a
+/// `non-200` HTTP status code (`404` if the user
+/// has no active session with the id)
+///
+/// Err([`Response`](hyper::Response))
+///
+pub async fn revoke_user_session(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    kafka_pool: &KafkaPublisher,
+    headers: &HeaderMap<HeaderValue>,
+    request_uri: &str,
+) -> std::result::Result<Response<Body>, Infallible> {
+    let session_id = str::replace(request_uri, "/user/sessions/", "")
+        .parse::<i32>()
+        .unwrap_or(-1);
+    if session_id <= 0 {
+        let response = Response::builder()
+            .status(400)
+            .body(Body::from(
+                serde_json::to_string(&ApiResUserRevokeSession {
+                    user_id: -1,
+                    session_id: -1,
+                    msg: ("Invalid session id must be a positive integer")
+                        .to_string(),
+                    error_code: Some(ApiErrorCode::InvalidRequest),
+                })
+                .unwrap(),
+            ))
+            .unwrap();
+        return Ok(response);
+    }
+
+    let token = &config.token_cookie.get_request_token(headers);
+
+    let conn = db_pool.get().await.unwrap();
+    let user_id =
+        match get_user_session_by_token(tracking_label, token, &conn).await {
+            Ok((_, user_id)) => user_id,
+            Err(_) => -1,
+        };
+    let valid_token = match user_id > 0 {
+        true => {
+            validate_user_token(tracking_label, config, &conn, headers, user_id)
+                .await
+        }
+        false => Err(ApiErrorCode::InvalidToken),
+    };
+    if let Err(error_code) = valid_token {
+        let response = Response::builder()
+            .status(400)
+            .body(Body::from(
+                serde_json::to_string(&ApiResUserRevokeSession {
+                    user_id: -1,
+                    session_id,
+                    msg: ("User revoke session failed due to invalid token")
+                        .to_string(),
+                    error_code: Some(error_code),
+                })
+                .unwrap(),
+            ))
+            .unwrap();
+        return Ok(response);
+    }
+
+    info!(
+        "{tracking_label} - \
+        revoking user {user_id} session {session_id}"
+    );
+
+    let cur_query = format!(
+        "UPDATE \
+            users_tokens \
+        SET \
+            state = 1, \
+            updated_at = timezone('UTC'::text, now()) \
+        WHERE \
+            users_tokens.id = {session_id} \
+            AND \
+            users_tokens.user_id = {user_id} \
+            AND \
+            users_tokens.state = 0 \
+        RETURNING \
+            users_tokens.id;"
+    );
+    let stmt = conn.prepare(&cur_query).await.unwrap();
+    let query_result =
+        match trace_db_query(&cur_query, conn.query(&stmt, &[])).await {
+            Ok(query_result) => query_result,
+            Err(e) => {
+                let response = Response::builder()
+                    .status(500)
+                    .body(Body::from(
+                        serde_json::to_string(&ApiResUserRevokeSession {
+                            user_id,
+                            session_id,
+                            msg: format!(
+                                "User revoke session failed \
+                            for user_id={user_id} session_id={session_id} \
+                            with err='{e}'"
+                            ),
+                            error_code: Some(ApiErrorCode::InternalError),
+                        })
+                        .unwrap(),
+                    ))
+                    .unwrap();
+                return Ok(response);
+            }
+        };
+    // the revoked token may be cached as active
+    config.user_cache.invalidate_user(user_id).await;
+
+    if !query_result.is_empty() {
+        config
+            .events
+            .publish_user_event(
+                kafka_pool,
+                user_id,
+                "USER_REVOKE_SESSION",
+                &format!("session={session_id}"),
+            )
+            .await;
+
+        let response = Response::builder()
+            .status(200)
+            .body(Body::from(
+                serde_json::to_string(&ApiResUserRevokeSession {
+                    user_id,
+                    session_id,
+                    msg: "success".to_string(),
+                    error_code: None,
+                })
+                .unwrap(),
+            ))
+            .unwrap();
+        return Ok(response);
+    }
+    let response = Response::builder()
+        .status(404)
+        .body(Body::from(
+            serde_json::to_string(&ApiResUserRevokeSession {
+                user_id,
+                session_id,
+                msg: format!(
+                    "User revoke session failed - \
+                    no active session_id={session_id} \
+                    for user_id={user_id}"
+                ),
+                error_code: Some(ApiErrorCode::NotFound),
+            })
+            .unwrap(),
+        ))
+        .unwrap();
+    Ok(response)
+}
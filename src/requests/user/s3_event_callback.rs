@@ -0,0 +1,192 @@
+//! Verify s3 event notification callbacks
+//!
+//! S3 event notifications (forwarded by an SQS poller or a
+//! lambda) can call ``POST /user/data/s3/events`` to mark
+//! direct-to-s3 uploads as complete (see
+//! [`complete_s3_event_uploads`](crate::requests::user::complete_s3_event_uploads::complete_s3_event_uploads)).
+//! The callback is disabled until ``S3_EVENTS_SECRET`` is set,
+//! and each request needs the headers:
+//!
+//! - ``X-S3-Event-Timestamp`` - unix seconds when the request
+//!   was sent
+//! - ``X-S3-Event-Signature`` - ``v1=HEX`` where ``HEX`` is
+//!   the hex-encoded ``HMAC-SHA256`` of ``TIMESTAMP.BODY``
+//!   with ``S3_EVENTS_SECRET`` (the same scheme as outbound
+//!   webhooks, see
+//!   [`get_webhook_signature`](crate::webhooks::webhook_signature::get_webhook_signature))
+//!
+//! Requests older (or newer) than
+//! ``S3_EVENTS_MAX_AGE_SECONDS`` are rejected to stop
+//! replays.
+//!
+use hyper::header::HeaderValue;
+use hyper::HeaderMap;
+
+use crate::webhooks::webhook_signature::get_webhook_signature;
+
+/// header with the unix seconds the callback was signed
+pub const S3_EVENT_TIMESTAMP_HEADER: &str = "X-S3-Event-Timestamp";
+
+/// header with the callback's ``v1=HEX`` signature
+pub const S3_EVENT_SIGNATURE_HEADER: &str = "X-S3-Event-Signature";
+
+/// S3EventCallback
+///
+/// Settings for verifying s3 event notification callbacks
+///
+/// # Supported Environment Variables
+///
+/// ```bash
+/// # shared secret for the callback signatures
+/// # (unset = the callback is disabled)
+/// export S3_EVENTS_SECRET=""
+/// # max clock difference for the signed timestamp
+/// export S3_EVENTS_MAX_AGE_SECONDS="300"
+/// ```
+///
+/// # Arguments
+///
+/// * `secret` - `String` - shared secret for the
+///   ``X-S3-Event-Signature`` header (empty = disabled)
+/// * `max_age_seconds` - `i64` - max difference between the
+///   ``X-S3-Event-Timestamp`` header and the server's clock
+///
+#[derive(Clone, Default)]
+pub struct S3EventCallback {
+    pub secret: String,
+    pub max_age_seconds: i64,
+}
+
+impl S3EventCallback {
+    /// build_s3_event_callback
+    ///
+    /// Build a
+    /// [`S3EventCallback`](crate::requests::user::s3_event_callback::S3EventCallback)
+    /// from environment variables
+    ///
+    pub fn build_s3_event_callback() -> Self {
+        S3EventCallback {
+            secret: std::env::var("S3_EVENTS_SECRET").unwrap_or_default(),
+            max_age_seconds: std::env::var("S3_EVENTS_MAX_AGE_SECONDS")
+                .unwrap_or_else(|_| "300".to_string())
+                .parse::<i64>()
+                .unwrap_or(300),
+        }
+    }
+
+    /// is_enabled
+    ///
+    /// Is ``S3_EVENTS_SECRET`` set
+    ///
+    pub fn is_enabled(&self) -> bool {
+        !self.secret.is_empty()
+    }
+
+    /// verify_signature
+    ///
+    /// Check a callback's ``X-S3-Event-Timestamp`` and
+    /// ``X-S3-Event-Signature`` headers against its body
+    ///
+    /// # Arguments
+    ///
+    /// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+    ///   request headers
+    /// * `body` - `&[u8]` - request body
+    /// * `now` - `i64` - current unix seconds
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) if a header is missing, the
+    /// timestamp is too old or the signature does not match
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use hyper::HeaderMap;
+    /// use restapi::requests::user::s3_event_callback::S3EventCallback;
+    /// use restapi::webhooks::webhook_signature::get_webhook_signature;
+    /// let callback = S3EventCallback {
+    ///     secret: "secret".to_string(),
+    ///     max_age_seconds: 300,
+    /// };
+    /// let body = r#"{"Records":[]}"#;
+    /// let mut headers = HeaderMap::new();
+    /// headers.insert("X-S3-Event-Timestamp", "1700000000".parse().unwrap());
+    /// headers.insert(
+    ///     "X-S3-Event-Signature",
+    ///     get_webhook_signature("secret", 1700000000, body).parse().unwrap(),
+    /// );
+    /// assert!(callback.verify_signature(&headers, body.as_bytes(), 1700000010).is_ok());
+    /// assert!(callback.verify_signature(&headers, b"{}", 1700000010).is_err());
+    /// assert!(callback.verify_signature(&headers, body.as_bytes(), 1700001000).is_err());
+    /// // timestamps far outside the max age are rejected without overflowing
+    /// headers.insert("X-S3-Event-Timestamp", i64::MIN.to_string().parse().unwrap());
+    /// assert!(callback.verify_signature(&headers, body.as_bytes(), 1700000010).is_err());
+    /// ```
+    ///
+    pub fn verify_signature(
+        &self,
+        headers: &HeaderMap<HeaderValue>,
+        body: &[u8],
+        now: i64,
+    ) -> Result<(), String> {
+        let timestamp = headers
+            .get(S3_EVENT_TIMESTAMP_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse::<i64>().ok())
+            .ok_or_else(|| {
+                format!("missing or invalid {S3_EVENT_TIMESTAMP_HEADER}")
+            })?;
+        if now.abs_diff(timestamp) > self.max_age_seconds as u64 {
+            return Err(format!(
+                "{S3_EVENT_TIMESTAMP_HEADER}={timestamp} is more than \
+                {} seconds from the server time",
+                self.max_age_seconds
+            ));
+        }
+        let signature = headers
+            .get(S3_EVENT_SIGNATURE_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .ok_or_else(|| format!("missing {S3_EVENT_SIGNATURE_HEADER}"))?;
+        let payload = std::str::from_utf8(body)
+            .map_err(|_| "request body is not utf-8".to_string())?;
+        let expected = get_webhook_signature(&self.secret, timestamp, payload);
+        match expected.len() == signature.len()
+            && openssl::memcmp::eq(expected.as_bytes(), signature.as_bytes())
+        {
+            true => Ok(()),
+            false => Err(format!("invalid {S3_EVENT_SIGNATURE_HEADER}")),
+        }
+    }
+}
+
+/// decode_s3_event_key
+///
+/// Decode an s3 event notification's object key (s3 sends
+/// the key url-encoded with spaces as ``+``)
+///
+/// # Arguments
+///
+/// * `key` - `&str` - ``s3.object.key`` from the event
+///
+/// # Returns
+///
+/// `String` - s3 key
+///
+/// # Examples
+///
+/// ```rust
+/// use restapi::requests::user::s3_event_callback::decode_s3_event_key;
+/// assert_eq!(
+///     decode_s3_event_key("user/data/file/1/my+report%282%29.txt"),
+///     "user/data/file/1/my report(2).txt"
+/// );
+/// assert_eq!(decode_s3_event_key("a%3Db%26c"), "a=b&c");
+/// ```
+///
+pub fn decode_s3_event_key(key: &str) -> String {
+    url::form_urlencoded::parse(format!("k={key}").as_bytes())
+        .next()
+        .map(|(_, v)| v.into_owned())
+        .unwrap_or_default()
+}
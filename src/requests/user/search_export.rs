@@ -0,0 +1,465 @@
+//! Stream every matching search row as csv or ndjson
+//!
+//! ``POST /user/search`` and ``POST /user/data/search``
+//! requests with an ``Accept: text/csv`` or
+//! ``Accept: application/x-ndjson`` header skip the
+//! ``page_size`` and 100 record limits and stream every
+//! matching row for reporting and data pipelines. Rows are
+//! read in ``SEARCH_EXPORT_BATCH_ROWS`` batches with a
+//! postgres cursor inside a read-only transaction (on a read
+//! replica when ``POSTGRES_READ_ENDPOINTS`` is set), so large
+//! exports never hold every row in memory.
+//!
+//! The ``200`` status is sent before the first row is read,
+//! so a db failure during an export aborts the response body
+//! and clients see an incomplete transfer instead of a
+//! truncated file.
+//!
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use hyper::body::Bytes;
+use hyper::body::Sender;
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Response;
+
+use tokio_postgres::Client;
+use tokio_postgres::Row;
+
+use crate::monitoring::otel::trace_db_query;
+use crate::pools::db_read_pools::DbReadPools;
+
+/// SearchExportFormat
+///
+/// Supported export formats
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SearchExportFormat {
+    /// comma-separated values with a header row
+    Csv,
+    /// one json object per line
+    Ndjson,
+}
+
+impl SearchExportFormat {
+    /// from_headers
+    ///
+    /// Get the export format from the ``Accept`` header
+    ///
+    /// # Arguments
+    ///
+    /// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+    ///   request headers
+    ///
+    /// # Returns
+    ///
+    /// `None` for a json search
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use hyper::header::HeaderValue;
+    /// use hyper::HeaderMap;
+    /// use restapi::requests::user::search_export::SearchExportFormat;
+    /// let mut headers = HeaderMap::new();
+    /// assert_eq!(SearchExportFormat::from_headers(&headers), None);
+    /// headers.insert("Accept", HeaderValue::from_static("text/csv; q=0.9"));
+    /// assert_eq!(
+    ///     SearchExportFormat::from_headers(&headers),
+    ///     Some(SearchExportFormat::Csv)
+    /// );
+    /// headers.insert("Accept", HeaderValue::from_static("application/x-ndjson"));
+    /// assert_eq!(
+    ///     SearchExportFormat::from_headers(&headers),
+    ///     Some(SearchExportFormat::Ndjson)
+    /// );
+    /// ```
+    ///
+    pub fn from_headers(headers: &HeaderMap<HeaderValue>) -> Option<Self> {
+        headers
+            .get_all("Accept")
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .find_map(|v| match v.split(';').next().unwrap_or("").trim() {
+                "text/csv" => Some(SearchExportFormat::Csv),
+                "application/x-ndjson" | "application/ndjson" => {
+                    Some(SearchExportFormat::Ndjson)
+                }
+                _ => None,
+            })
+    }
+
+    /// get_content_type
+    ///
+    /// Get the response ``Content-Type``
+    ///
+    pub fn get_content_type(&self) -> &'static str {
+        match self {
+            SearchExportFormat::Csv => "text/csv; charset=utf-8",
+            SearchExportFormat::Ndjson => "application/x-ndjson",
+        }
+    }
+
+    /// get_extension
+    ///
+    /// Get the file extension for the ``Content-Disposition``
+    ///
+    pub fn get_extension(&self) -> &'static str {
+        match self {
+            SearchExportFormat::Csv => "csv",
+            SearchExportFormat::Ndjson => "ndjson",
+        }
+    }
+
+    /// format_row
+    ///
+    /// Format one exported row with only the export's columns
+    ///
+    /// # Arguments
+    ///
+    /// * `columns` - `&[&str]` - exported keys in order
+    /// * `value` - `&serde_json::Value` - json row
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use restapi::requests::user::search_export::SearchExportFormat;
+    /// let row = serde_json::json!({
+    ///     "user_id": 1,
+    ///     "email": "a@b.com",
+    ///     "tags": ["x", "y"],
+    ///     "password": "hidden",
+    /// });
+    /// let columns = ["user_id", "email", "tags"];
+    /// assert_eq!(
+    ///     SearchExportFormat::Csv.format_row(&columns, &row),
+    ///     "1,a@b.com,\"[\"\"x\"\",\"\"y\"\"]\"\n"
+    /// );
+    /// let line = SearchExportFormat::Ndjson.format_row(&columns, &row);
+    /// let parsed: serde_json::Value = serde_json::from_str(&line).unwrap();
+    /// assert!(line.ends_with('\n'));
+    /// assert_eq!(parsed["email"], "a@b.com");
+    /// assert!(parsed.get("password").is_none());
+    /// ```
+    ///
+    pub fn format_row(
+        &self,
+        columns: &[&str],
+        value: &serde_json::Value,
+    ) -> String {
+        match self {
+            SearchExportFormat::Csv => {
+                let values: Vec<String> = columns
+                    .iter()
+                    .map(|column| match value.get(column) {
+                        Some(serde_json::Value::String(v)) => get_csv_value(v),
+                        Some(serde_json::Value::Null) | None => "".to_string(),
+                        Some(v) => get_csv_value(&v.to_string()),
+                    })
+                    .collect();
+                format!("{}\n", values.join(","))
+            }
+            SearchExportFormat::Ndjson => {
+                let row: serde_json::Map<String, serde_json::Value> = columns
+                    .iter()
+                    .map(|column| {
+                        (
+                            column.to_string(),
+                            value
+                                .get(column)
+                                .cloned()
+                                .unwrap_or(serde_json::Value::Null),
+                        )
+                    })
+                    .collect();
+                format!("{}\n", serde_json::Value::Object(row))
+            }
+        }
+    }
+}
+
+/// get_csv_value
+///
+/// Quote a csv value that has a comma, quote or line break.
+/// Values that start with ``=``, ``+``, ``-``, ``@``, a tab
+/// or a carriage return are prefixed with ``'`` first so
+/// spreadsheets do not run them as formulas
+///
+/// # Arguments
+///
+/// * `value` - `&str` - raw value
+///
+/// # Examples
+///
+/// ```rust
+/// use restapi::requests::user::search_export::get_csv_value;
+/// assert_eq!(get_csv_value("report.csv"), "report.csv");
+/// assert_eq!(get_csv_value("a,b"), "\"a,b\"");
+/// assert_eq!(get_csv_value("say \"hi\""), "\"say \"\"hi\"\"\"");
+/// assert_eq!(get_csv_value("=1+2"), "'=1+2");
+/// assert_eq!(get_csv_value("+1"), "'+1");
+/// assert_eq!(get_csv_value("-1"), "'-1");
+/// assert_eq!(get_csv_value("@SUM(A1)"), "'@SUM(A1)");
+/// assert_eq!(get_csv_value("\tcmd"), "'\tcmd");
+/// assert_eq!(get_csv_value("\rcmd"), "\"'\rcmd\"");
+/// assert_eq!(
+///     get_csv_value("=HYPERLINK(\"x\",\"y\")"),
+///     "\"'=HYPERLINK(\"\"x\"\",\"\"y\"\")\""
+/// );
+/// ```
+///
+pub fn get_csv_value(value: &str) -> String {
+    let value = match value.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        true => format!("'{value}"),
+        false => value.to_string(),
+    };
+    match value.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", value.replace('"', "\"\"")),
+        false => value,
+    }
+}
+
+/// SearchExport
+///
+/// Settings for csv and ndjson search exports
+///
+/// # Supported Environment Variables
+///
+/// ```bash
+/// # 0 = Accept: text/csv and application/x-ndjson
+/// # searches return json pages
+/// export SEARCH_EXPORT_ENABLED="1"
+/// # rows read from the db cursor at a time
+/// export SEARCH_EXPORT_BATCH_ROWS="500"
+/// ```
+///
+/// # Arguments
+///
+/// * `enabled` - `bool` - support export searches
+/// * `batch_rows` - `i64` - rows fetched from the cursor
+///   at a time
+///
+#[derive(Clone, Default)]
+pub struct SearchExport {
+    pub enabled: bool,
+    pub batch_rows: i64,
+}
+
+impl SearchExport {
+    /// build_search_export
+    ///
+    /// Build a
+    /// [`SearchExport`](crate::requests::user::search_export::SearchExport)
+    /// from environment variables
+    ///
+    pub fn build_search_export() -> Self {
+        let enabled_s = std::env::var("SEARCH_EXPORT_ENABLED")
+            .unwrap_or_else(|_| "1".to_string());
+        SearchExport {
+            enabled: enabled_s == "1" || enabled_s == "true",
+            batch_rows: std::env::var("SEARCH_EXPORT_BATCH_ROWS")
+                .unwrap_or_else(|_| "500".to_string())
+                .parse::<i64>()
+                .unwrap_or(500)
+                .max(1),
+        }
+    }
+
+    /// get_format
+    ///
+    /// Get the requested export format when exports are
+    /// enabled
+    ///
+    /// # Arguments
+    ///
+    /// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+    ///   request headers
+    ///
+    pub fn get_format(
+        &self,
+        headers: &HeaderMap<HeaderValue>,
+    ) -> Option<SearchExportFormat> {
+        match self.enabled {
+            true => SearchExportFormat::from_headers(headers),
+            false => None,
+        }
+    }
+
+    /// stream_export
+    ///
+    /// Stream every row of a search query to the client
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - caller logging label
+    /// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
+    ///   db threadpool used when there is no read replica
+    /// * `db_read_pools` - [`DbReadPools`](crate::pools::db_read_pools::DbReadPools) -
+    ///   postgres read replica pools
+    /// * `format` - [`SearchExportFormat`](crate::requests::user::search_export::SearchExportFormat)
+    /// * `name` - `&str` - download file name without the
+    ///   extension
+    /// * `query` - `String` - ``SELECT`` query without a
+    ///   trailing ``;``
+    /// * `columns` - `&'static [&'static str]` - exported keys
+    /// * `get_row` - `fn(&Row) -> serde_json::Value` - convert
+    ///   a db row into a json row
+    ///
+    /// # Returns
+    ///
+    /// hyper [`Response`](hyper::Response) with a ``200``
+    /// status code and a streamed body
+    ///
+    #[allow(clippy::too_many_arguments)]
+    pub fn stream_export(
+        &self,
+        tracking_label: &str,
+        db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+        db_read_pools: &DbReadPools,
+        format: SearchExportFormat,
+        name: &str,
+        query: String,
+        columns: &'static [&'static str],
+        get_row: fn(&Row) -> serde_json::Value,
+    ) -> Response<Body> {
+        let (mut sender, body) = Body::channel();
+        let export_label = format!("{tracking_label} - {name} export");
+        let batch_rows = self.batch_rows;
+        let db_pool = db_pool.clone();
+        let db_read_pools = db_read_pools.clone();
+        tokio::spawn(async move {
+            let read_conn = db_read_pools.get_read_conn(&export_label).await;
+            let mut conn = match read_conn {
+                Some(conn) => conn,
+                None => match db_pool.get().await {
+                    Ok(conn) => conn,
+                    Err(e) => {
+                        error!(
+                            "{export_label} - \
+                            failed to get a db connection with err='{e}'"
+                        );
+                        sender.abort();
+                        return;
+                    }
+                },
+            };
+            match send_export_rows(
+                &mut conn,
+                &mut sender,
+                format,
+                &query,
+                columns,
+                get_row,
+                batch_rows,
+            )
+            .await
+            {
+                Ok(num_rows) => {
+                    info!("{export_label} - exported {num_rows} rows")
+                }
+                Err(err_msg) => {
+                    error!(
+                        "{export_label} - export failed with err='{err_msg}'"
+                    );
+                    sender.abort();
+                }
+            }
+        });
+        Response::builder()
+            .status(200)
+            .header("Content-Type", format.get_content_type())
+            .header(
+                "Content-Disposition",
+                format!(
+                    "attachment; filename=\"{name}.{}\"",
+                    format.get_extension()
+                ),
+            )
+            .header("X-Accel-Buffering", "no")
+            .body(body)
+            .unwrap()
+    }
+}
+
+/// send_export_rows
+///
+/// Read a query with a cursor and send the formatted rows
+/// in batches
+///
+/// # Returns
+///
+/// Ok(`u64`) - number of exported rows
+///
+/// # Errors
+///
+/// Err(err_msg: `String`) when a query fails or the client
+/// disconnects
+///
+async fn send_export_rows(
+    client: &mut Client,
+    sender: &mut Sender,
+    format: SearchExportFormat,
+    query: &str,
+    columns: &[&str],
+    get_row: fn(&Row) -> serde_json::Value,
+    batch_rows: i64,
+) -> Result<u64, String> {
+    let tx = client
+        .build_transaction()
+        .read_only(true)
+        .start()
+        .await
+        .map_err(|e| format!("failed to start the export with err='{e}'"))?;
+    let declare_query =
+        format!("DECLARE search_export NO SCROLL CURSOR FOR {query};");
+    trace_db_query(&declare_query, tx.batch_execute(declare_query.as_str()))
+        .await
+        .map_err(|e| {
+            format!("failed to run the export query with err='{e}'")
+        })?;
+    if format == SearchExportFormat::Csv {
+        let header = columns
+            .iter()
+            .map(|column| get_csv_value(column))
+            .collect::<Vec<String>>()
+            .join(",");
+        sender
+            .send_data(Bytes::from(format!("{header}\n")))
+            .await
+            .map_err(|_| "client disconnected".to_string())?;
+    }
+    let fetch_query = format!("FETCH {batch_rows} FROM search_export;");
+    let mut num_rows: u64 = 0;
+    loop {
+        let rows =
+            trace_db_query(&fetch_query, tx.query(fetch_query.as_str(), &[]))
+                .await
+                .map_err(|e| {
+                    format!("failed to fetch export rows with err='{e}'")
+                })?;
+        if rows.is_empty() {
+            break;
+        }
+        let chunk: String = rows
+            .iter()
+            .map(|row| format.format_row(columns, &get_row(row)))
+            .collect();
+        sender
+            .send_data(Bytes::from(chunk))
+            .await
+            .map_err(|_| "client disconnected".to_string())?;
+        num_rows += rows.len() as u64;
+        if (rows.len() as i64) < batch_rows {
+            break;
+        }
+    }
+    tx.commit()
+        .await
+        .map_err(|e| format!("failed to close the export with err='{e}'"))?;
+    Ok(num_rows)
+}
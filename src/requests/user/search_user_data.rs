@@ -22,15 +22,33 @@ use hyper::Body;
 use hyper::HeaderMap;
 use hyper::Response;
 
+use tokio_postgres::Row;
+
 use serde::Deserialize;
 use serde::Serialize;
 
-use kafka_threadpool::kafka_publisher::KafkaPublisher;
-
 use crate::core::core_config::CoreConfig;
-use crate::kafka::publish_msg::publish_msg;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::pools::db_read_pools::DbReadPools;
 use crate::requests::auth::validate_user_token::validate_user_token;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::models::user::get_user_by_id;
+use crate::requests::models::user_data::is_valid_user_data_status;
 use crate::requests::models::user_data::ModelUserData;
+use crate::requests::models::user_data::USER_DATA_STATUSES;
+use crate::requests::models::user_data_repo::get_user_data_from_row;
+use crate::requests::models::user_data_repo::UserDataRepo;
+use crate::requests::models::user_data_repo::UserDataSearch;
+use crate::requests::validation::field_rules::add_field_error;
+use crate::requests::validation::field_rules::check_data_folder;
+use crate::requests::validation::field_rules::check_data_tags;
+use crate::requests::validation::field_rules::check_id;
+use crate::requests::validation::field_rules::check_length;
+use crate::requests::validation::field_rules::check_range;
+use crate::requests::validation::validate_api_req::validate_api_req;
+use crate::requests::validation::validate_api_req::ApiFieldError;
+use crate::requests::validation::validate_api_req::ApiReqValidate;
+use crate::utils::etag::get_etag_response;
 
 /// ApiReqUserSearchData
 ///
@@ -75,6 +93,27 @@ use crate::requests::models::user_data::ModelUserData;
 ///   `users_data.encoding`
 /// * `sloc` - `Option<String>` - filter by
 ///   `users_data.sloc` the s3 storage location
+/// * `status` - `Option<String>` - filter by
+///   `users_data.status` (``pending``, ``scanning``,
+///   ``ready``, ``quarantined``, ``failed``, ``expired`` or
+///   ``broken``)
+/// * `tags` - `Option<Vec<String>>` - only return records
+///   with every one of these `users_data.tags`
+/// * `folder` - `Option<String>` - filter by
+///   `users_data.folder` (a path like ``/projects/2022``)
+/// * `include_subfolders` - `Option<bool>` - also return
+///   records in the `folder`'s subfolders (default `false`)
+/// * `include_archived` - `Option<bool>` - also search the
+///   `users_data_archive` table for records moved by the
+///   [`run_user_data_archiver`](crate::archive::run_user_data_archiver::run_user_data_archiver)
+///   (default `false`)
+/// * `versions_of` - `Option<i32>` - return every version
+///   of the file with this `users_data.id` (older versions
+///   are kept by ``on_conflict: overwrite`` uploads)
+/// * `include_versions` - `Option<bool>` - also return
+///   older file versions (default `false` returns only the
+///   current versions unless a `data_id` or `versions_of`
+///   filter is set)
 ///
 #[derive(Serialize, Deserialize, Clone)]
 pub struct ApiReqUserSearchData {
@@ -90,119 +129,173 @@ pub struct ApiReqUserSearchData {
     pub comments: Option<String>,
     pub encoding: Option<String>,
     pub sloc: Option<String>,
+    pub status: Option<String>,
+    pub tags: Option<Vec<String>>,
+    pub folder: Option<String>,
+    pub include_subfolders: Option<bool>,
+    pub include_archived: Option<bool>,
+    #[serde(default)]
+    pub versions_of: Option<i32>,
+    #[serde(default)]
+    pub include_versions: Option<bool>,
 }
 
-/// implementation for handling complex search filtering
-/// using sql
-impl ApiReqUserSearchData {
-    /// get_sql
+impl ApiReqValidate for ApiReqUserSearchData {
+    /// validate
     ///
-    /// Build the v1 search query string based on the
-    /// the requested values.
+    /// Require a positive `user_id` with optional positive
+    /// ids, non-negative byte sizes (`above_bytes` less
+    /// than `below_bytes`), strings that fit in the
+    /// ``users_data`` columns and valid `tags` and `folder`
+    /// values (`include_subfolders` requires a `folder`)
     ///
-    pub fn get_sql(&self) -> String {
-        let mut update_value: String = format!(
-            "SELECT \
-                users_data.id, \
-                users_data.user_id, \
-                users_data.filename, \
-                users_data.size_in_bytes, \
-                users_data.comments, \
-                users_data.data_type, \
-                users_data.encoding, \
-                users_data.sloc, \
-                users_data.created_at, \
-                users_data.updated_at \
-            FROM \
-                users_data \
-            WHERE \
-                users_data.user_id = {}",
-            self.user_id
-        );
-        match self.creator_user_id {
-            Some(_) => {
-                match update_value.len() {
-                    0 => {
-                        // update_value = format!("{update_value}, {creator_user_id_value}");
-                        0
-                    }
-                    // only one user_id supported for now
-                    _ => 1,
-                }
-            }
-            None => 1,
-        };
-        match self.data_id {
-            Some(v) => {
-                update_value = format!("{update_value}, id = {}", v);
-                0
-            }
-            None => 1,
-        };
-        match &self.filename {
-            Some(v) => {
-                update_value =
-                    format!("{update_value}, filename ILIKE '%{v}%'");
-                0
-            }
-            None => 1,
-        };
-        match &self.data_type {
-            Some(v) => {
-                update_value =
-                    format!("{update_value}, data_type ILIKE '%{v}%'");
-                0
-            }
-            None => 1,
-        };
-        // https://www.google.com/search?q=rust+bigint+postgres
-        // postgres size_in_bytes field is a BIGINT type
-        match self.above_bytes {
-            Some(v) => {
-                update_value = format!("{update_value}, size_in_bytes > {v}");
-                0
-            }
-            None => 1,
-        };
-        match self.below_bytes {
-            Some(v) => {
-                update_value = format!("{update_value}, size_in_bytes < {v}");
-                0
+    fn validate(&self) -> Vec<ApiFieldError> {
+        let mut errors = Vec::new();
+        check_id(&mut errors, "user_id", self.user_id);
+        if let Some(creator_user_id) = self.creator_user_id {
+            check_id(&mut errors, "creator_user_id", creator_user_id);
+        }
+        if let Some(data_id) = self.data_id {
+            check_id(&mut errors, "data_id", data_id);
+        }
+        if let Some(versions_of) = self.versions_of {
+            check_id(&mut errors, "versions_of", versions_of);
+        }
+        if let Some(above_bytes) = self.above_bytes {
+            check_range(&mut errors, "above_bytes", above_bytes, 0, i64::MAX);
+        }
+        if let Some(below_bytes) = self.below_bytes {
+            check_range(&mut errors, "below_bytes", below_bytes, 0, i64::MAX);
+        }
+        if let (Some(above_bytes), Some(below_bytes)) =
+            (self.above_bytes, self.below_bytes)
+        {
+            if above_bytes >= below_bytes {
+                add_field_error(
+                    &mut errors,
+                    "below_bytes",
+                    "must be larger than above_bytes",
+                );
             }
-            None => 1,
-        };
-        match &self.comments {
-            Some(v) => {
-                update_value =
-                    format!("{update_value}, comments ILIKE '%{v}%'");
-                0
+        }
+        let optional_values = [
+            ("filename", &self.filename, 1, 511),
+            ("data_type", &self.data_type, 0, 64),
+            ("comments", &self.comments, 0, 512),
+            ("encoding", &self.encoding, 0, 64),
+            ("sloc", &self.sloc, 0, 1024),
+        ];
+        for (field, value, min_len, max_len) in optional_values.iter() {
+            if let Some(v) = value {
+                check_length(&mut errors, field, v, *min_len, *max_len);
             }
-            None => 1,
-        };
-        match &self.encoding {
-            Some(v) => {
-                update_value =
-                    format!("{update_value}, encoding ILIKE '%{v}%'");
-                0
+        }
+        if let Some(status) = &self.status {
+            if !is_valid_user_data_status(status) {
+                add_field_error(
+                    &mut errors,
+                    "status",
+                    &format!(
+                        "must be one of: {}",
+                        USER_DATA_STATUSES.join(", ")
+                    ),
+                );
             }
-            None => 1,
-        };
-        match &self.sloc {
-            Some(v) => {
-                update_value = format!("{update_value}, sloc ILIKE '%{v}%'");
-                0
+        }
+        if let Some(v) = &self.tags {
+            check_data_tags(&mut errors, "tags", v);
+        }
+        match &self.folder {
+            Some(v) => check_data_folder(&mut errors, "folder", v),
+            None => {
+                if self.include_subfolders.unwrap_or(false) {
+                    add_field_error(
+                        &mut errors,
+                        "include_subfolders",
+                        "requires a folder",
+                    );
+                }
             }
-            None => 1,
-        };
-        // info!("ApiReqUserSearchData query: {cur_query}");
-        format!(
-            "{} ORDER BY users_data.id DESC \
-                LIMIT 100;",
-            update_value
-        )
+        }
+        errors
+    }
+}
+
+/// implementation for converting the request into
+/// repository search filters
+impl ApiReqUserSearchData {
+    /// get_search
+    ///
+    /// Build the
+    /// [`UserDataSearch`](crate::requests::models::user_data_repo::UserDataSearch)
+    /// filters for
+    /// [`UserDataRepo::search`](crate::requests::models::user_data_repo::UserDataRepo::search).
+    /// Records owned by the user or shared with the user's
+    /// id or `role` through the `users_data_acl` table are
+    /// included. Archived records are included with
+    /// `include_archived`. A `folder` filter only matches
+    /// that folder unless `include_subfolders` is set. Older
+    /// file versions are only included with `versions_of`,
+    /// `data_id` or `include_versions`.
+    ///
+    pub fn get_search(&self) -> UserDataSearch {
+        UserDataSearch {
+            creator_user_id: self.creator_user_id,
+            data_id: self.data_id,
+            filename: self.filename.clone(),
+            data_type: self.data_type.clone(),
+            above_bytes: self.above_bytes,
+            below_bytes: self.below_bytes,
+            comments: self.comments.clone(),
+            encoding: self.encoding.clone(),
+            sloc: self.sloc.clone(),
+            status: self.status.clone(),
+            tags: self.tags.clone(),
+            folder: self.folder.clone(),
+            include_subfolders: self.include_subfolders.unwrap_or(false),
+            include_archived: self.include_archived.unwrap_or(false),
+            versions_of: self.versions_of,
+            include_versions: self.include_versions.unwrap_or(false),
+        }
     }
 }
 
+/// `users_data` columns in csv and ndjson exports
+pub const USER_DATA_EXPORT_COLUMNS: [&str; 24] = [
+    "user_id",
+    "data_id",
+    "filename",
+    "data_type",
+    "size_in_bytes",
+    "comments",
+    "encoding",
+    "sloc",
+    "created_at",
+    "updated_at",
+    "version",
+    "archived",
+    "status",
+    "storage_class",
+    "expires_at",
+    "scan_status",
+    "scan_signature",
+    "content_type",
+    "derivatives_status",
+    "tags",
+    "folder",
+    "file_version",
+    "version_of",
+    "replaced_by",
+];
+
+/// get_user_data_export_row
+///
+/// Convert a ``users_data`` row into an export row
+///
+fn get_user_data_export_row(row: &Row) -> serde_json::Value {
+    serde_json::to_value(get_user_data_from_row(row)).unwrap()
+}
+
 /// ApiResUserSearchData
 ///
 /// # Response type for search_user_data
@@ -226,11 +319,15 @@ impl ApiReqUserSearchData {
 /// * `data` - Vec<[`ModelUserData`](crate::requests::models::user_data::ModelUserData)> -
 ///   list of matching `users_data` records
 /// * `msg` - `String` - help message
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   machine-readable failure code (omitted on success)
 ///
 #[derive(Serialize, Deserialize, Clone)]
 pub struct ApiResUserSearchData {
     pub data: Vec<ModelUserData>,
     pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ApiErrorCode>,
 }
 
 /// search_user_data
@@ -252,8 +349,10 @@ pub struct ApiResUserSearchData {
 /// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
 /// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
 ///   db threadpool with required tls encryption
+/// * `db_read_pools` - [`DbReadPools`](crate::pools::db_read_pools::DbReadPools) -
+///   postgres read replica pools for the read-only query
 /// * `kafka_pool` -
-///   [`KafkaPublisher`](kafka_threadpool::kafka_publisher::KafkaPublisher)
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
 ///   for asynchronously publishing messages to the connected kafka cluster
 /// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
 ///   hashmap containing headers in key-value pairs
@@ -271,8 +370,9 @@ pub struct ApiResUserSearchData {
 /// containing a json-serialized
 /// [`ApiResUserSearchData`](crate::requests::user::search_user_data::ApiResUserSearchData)
 /// dictionary within the
-/// [`Body`](hyper::Body) and a
-/// `200` HTTP status code
+/// [`Body`](hyper::Body), an ``ETag`` header and a
+/// `200` HTTP status code (an empty `304` when the
+/// ``If-None-Match`` header has the current ``ETag``)
 ///
 /// Ok([`Response`](hyper::Response))
 ///
@@ -293,6 +393,7 @@ pub async fn search_user_data(
     tracking_label: &str,
     config: &CoreConfig,
     db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    db_read_pools: &DbReadPools,
     kafka_pool: &KafkaPublisher,
     headers: &HeaderMap<HeaderValue>,
     bytes: &[u8],
@@ -315,6 +416,7 @@ pub async fn search_user_data(
                             comments, encoding, sloc \
                             were set correctly in the request")
                             .to_string(),
+                        error_code: Some(ApiErrorCode::InvalidRequest),
                     })
                     .unwrap(),
                 ))
@@ -322,6 +424,10 @@ pub async fn search_user_data(
             return Ok(response);
         }
     };
+
+    if let Some(response) = validate_api_req(tracking_label, &user_object) {
+        return Ok(response);
+    }
     let user_id = user_object.user_id;
     let conn = db_pool.get().await.unwrap();
     let _token = match validate_user_token(
@@ -334,7 +440,7 @@ pub async fn search_user_data(
     .await
     {
         Ok(_token) => _token,
-        Err(_) => {
+        Err(error_code) => {
             let response = Response::builder()
                 .status(400)
                 .body(Body::from(
@@ -342,6 +448,7 @@ pub async fn search_user_data(
                         data: Vec::new(),
                         msg: ("User search data failed due to invalid token")
                             .to_string(),
+                        error_code: Some(error_code),
                     })
                     .unwrap(),
                 ))
@@ -350,87 +457,89 @@ pub async fn search_user_data(
         }
     };
 
-    let cur_query = user_object.get_sql();
-    /*
-    if false {
-        println!(
-            "{tracking_label} - \
-            searching for user_id={user_id} data with \
-            query {cur_query}"
-        );
-    }
-    */
-
-    let stmt = conn.prepare(&cur_query).await.unwrap();
-    let query_result = match conn.query(&stmt, &[]).await {
-        Ok(query_result) => query_result,
-        Err(e) => {
-            let err_msg = format!("{e}");
+    let user_model = match get_user_by_id(tracking_label, user_id, &conn).await
+    {
+        Ok(user_model) => user_model,
+        Err(err_msg) => {
+            error!(
+                "{tracking_label} - \
+                failed to find user {user_id} for data search \
+                with err='{err_msg}'"
+            );
             let response = Response::builder()
-                .status(500)
+                .status(400)
                 .body(Body::from(
-                    serde_json::to_string(
-                        &ApiResUserSearchData {
-                            data: Vec::new(),
-                            msg: format!("User data search failed for user_id={user_id} with err='{err_msg}'")
-                        }
-                    ).unwrap()))
+                    serde_json::to_string(&ApiResUserSearchData {
+                        data: Vec::new(),
+                        msg: format!(
+                            "User data search failed - \
+                            unable to find user with id: {user_id}"
+                        ),
+                        error_code: Some(ApiErrorCode::UserNotFound),
+                    })
+                    .unwrap(),
+                ))
                 .unwrap();
             return Ok(response);
         }
     };
-    let mut row_list: Vec<ModelUserData> = Vec::with_capacity(1);
-    for row in query_result.iter() {
-        let found_data_id: i32 = row.try_get("id").unwrap();
-        let found_user_id: i32 = row.try_get("user_id").unwrap();
-        let found_filename: String = row.try_get("filename").unwrap();
-        let found_data_type: String = row.try_get("data_type").unwrap();
-        let found_size_in_bytes: i64 = row.try_get("size_in_bytes").unwrap();
-        let found_comments: String = row.try_get("comments").unwrap();
-        let found_encoding: String = row.try_get("encoding").unwrap();
-        let found_sloc: String = row.try_get("sloc").unwrap();
-        let created_at_utc: chrono::DateTime<chrono::Utc> =
-            row.try_get("created_at").unwrap();
-        let updated_at_str: String = match row.try_get("updated_at") {
-            Ok(v) => {
-                let updated_at_utc: chrono::DateTime<chrono::Utc> = v;
-                format!("{}", updated_at_utc.format("%Y-%m-%dT%H:%M:%SZ"))
-            }
-            Err(_) => "".to_string(),
-        };
-        row_list.push(ModelUserData {
-            user_id: found_user_id,
-            data_id: found_data_id,
-            filename: found_filename,
-            data_type: found_data_type,
-            size_in_bytes: found_size_in_bytes,
-            comments: found_comments,
-            encoding: found_encoding,
-            sloc: found_sloc,
-            created_at: format!(
-                "{}",
-                created_at_utc.format("%Y-%m-%dT%H:%M:%SZ")
+
+    // csv and ndjson exports stream every matching record
+    if let Some(format) = config.search_export.get_format(headers) {
+        config
+            .events
+            .publish_user_event(kafka_pool, user_id, "SEARCH_USER_DATA", "")
+            .await;
+        return Ok(config.search_export.stream_export(
+            tracking_label,
+            db_pool,
+            db_read_pools,
+            format,
+            "user_data",
+            UserDataRepo::get_search_query(
+                user_id,
+                &user_model.role,
+                &user_object.get_search(),
             ),
-            updated_at: updated_at_str,
-            msg: "success".to_string(),
-        });
+            &USER_DATA_EXPORT_COLUMNS,
+            get_user_data_export_row,
+        ));
     }
+    let read_conn = db_read_pools.get_read_conn(tracking_label).await;
+    let read_conn = read_conn.as_ref().unwrap_or(&conn);
+    let row_list = match UserDataRepo::new(read_conn)
+        .search(
+            tracking_label,
+            user_id,
+            &user_model.role,
+            &user_object.get_search(),
+        )
+        .await
+    {
+        Ok(row_list) => row_list,
+        Err(e) => {
+            let response = Response::builder()
+                .status(e.status_code())
+                .body(Body::from(
+                    serde_json::to_string(&ApiResUserSearchData {
+                        data: Vec::new(),
+                        msg: format!(
+                            "User data search failed for user_id={user_id} \
+                            with err='{e}'"
+                        ),
+                        error_code: Some(e.error_code()),
+                    })
+                    .unwrap(),
+                ))
+                .unwrap();
+            return Ok(response);
+        }
+    };
     if row_list.is_empty() {
-        // if enabled, publish to kafka
-        if config.kafka_publish_events {
-            publish_msg(
-                kafka_pool,
-                // topic
-                "user.events",
-                // partition key
-                &format!("user-{}", user_id),
-                // optional headers stored in: Option<HashMap<String, String>>
-                None,
-                // payload in the message
-                &format!("SEARCH_USER_DATA user={user_id}"),
-            )
+        config
+            .events
+            .publish_user_event(kafka_pool, user_id, "SEARCH_USER_DATA", "")
             .await;
-        }
 
         let response = Response::builder()
             .status(200)
@@ -438,22 +547,22 @@ pub async fn search_user_data(
                 serde_json::to_string(&ApiResUserSearchData {
                     data: Vec::new(),
                     msg: "no search data found".to_string(),
+                    error_code: None,
                 })
                 .unwrap(),
             ))
             .unwrap();
         Ok(response)
     } else {
-        let response = Response::builder()
-            .status(200)
-            .body(Body::from(
-                serde_json::to_string(&ApiResUserSearchData {
-                    data: row_list,
-                    msg: "success".to_string(),
-                })
-                .unwrap(),
-            ))
-            .unwrap();
-        Ok(response)
+        // a 304 when none of the records changed
+        Ok(get_etag_response(
+            headers,
+            serde_json::to_string(&ApiResUserSearchData {
+                data: row_list,
+                msg: "success".to_string(),
+                error_code: None,
+            })
+            .unwrap(),
+        ))
     }
 }
@@ -3,6 +3,8 @@
 //! ## Search Users in the Postgres DB
 //!
 //! Search for matching ``users`` records in the db
+//! with paging. Only users with the ``admin`` role
+//! can search across all users.
 //!
 //! - URL path: ``/user/search``
 //! - Method: ``POST``
@@ -23,15 +25,33 @@ use hyper::Body;
 use hyper::HeaderMap;
 use hyper::Response;
 
+use tokio_postgres::Row;
+
 use serde::Deserialize;
 use serde::Serialize;
 
-use kafka_threadpool::kafka_publisher::KafkaPublisher;
-
 use crate::core::core_config::CoreConfig;
-use crate::kafka::publish_msg::publish_msg;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::pools::db_read_pools::DbReadPools;
 use crate::requests::auth::validate_user_token::validate_user_token;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::models::user::get_user_by_id;
+use crate::requests::models::user_repo::get_user_from_row;
+use crate::requests::models::user_repo::UserRepo;
+use crate::requests::models::user_repo::UserSearch;
 use crate::requests::user::get_user::ApiResUserGet;
+use crate::requests::validation::field_rules::add_field_error;
+use crate::requests::validation::field_rules::check_id;
+use crate::requests::validation::field_rules::check_length;
+use crate::requests::validation::field_rules::check_one_of;
+use crate::requests::validation::field_rules::check_range;
+use crate::requests::validation::field_rules::MAX_EMAIL_LEN;
+use crate::requests::validation::field_rules::USER_ROLES;
+use crate::requests::validation::field_rules::USER_STATES;
+use crate::requests::validation::field_rules::USER_VERIFIED;
+use crate::requests::validation::validate_api_req::validate_api_req;
+use crate::requests::validation::validate_api_req::ApiFieldError;
+use crate::requests::validation::validate_api_req::ApiReqValidate;
 
 /// ApiReqUserSearch
 ///
@@ -40,8 +60,11 @@ use crate::requests::user::get_user::ApiResUserGet;
 /// Handles searching for many `users`
 /// record(s) from the db with optional filters
 ///
+/// Users with the `admin` role search across all users.
+/// All other users only match their own `users` record.
+///
 /// This type is the deserialized input for:
-/// [`search_users`](crate::requests::user::search_users::search_users]
+/// [`search_users`](crate::requests::user::search_users::search_users)
 ///
 /// # Usage
 ///
@@ -54,13 +77,207 @@ use crate::requests::user::get_user::ApiResUserGet;
 /// # Arguments
 ///
 /// * `user_id` - `i32` - user id
-/// * `email` - `String` - filter by
+/// * `email` - `Option<String>` - filter by
 ///   `users.email` with `ILIKE`
+/// * `role` - `Option<String>` - filter by `users.role`
+/// * `state` - `Option<i32>` - filter by `users.state`
+/// * `verified` - `Option<i32>` - filter by `users.verified`
+/// * `created_after` - `Option<String>` - filter by
+///   `users.created_at` on or after this RFC 3339 timestamp
+/// * `created_before` - `Option<String>` - filter by
+///   `users.created_at` before this RFC 3339 timestamp
+/// * `page` - `Option<i64>` - page number starting
+///   from `0` (default `0`)
+/// * `page_size` - `Option<i64>` - records per page
+///   (default and max `100`)
 ///
 #[derive(Serialize, Deserialize, Clone)]
 pub struct ApiReqUserSearch {
     pub user_id: i32,
-    pub email: String,
+    pub email: Option<String>,
+    pub role: Option<String>,
+    pub state: Option<i32>,
+    pub verified: Option<i32>,
+    pub created_after: Option<String>,
+    pub created_before: Option<String>,
+    pub page: Option<i64>,
+    pub page_size: Option<i64>,
+}
+
+impl ApiReqValidate for ApiReqUserSearch {
+    /// validate
+    ///
+    /// Require a positive `user_id`, an optional `email`
+    /// search value between 3 and 254 characters (partial
+    /// emails are supported), supported `role`, `state`
+    /// and `verified` values, RFC 3339 `created_*`
+    /// timestamps and a valid `page` and `page_size`
+    ///
+    fn validate(&self) -> Vec<ApiFieldError> {
+        let mut errors = Vec::new();
+        check_id(&mut errors, "user_id", self.user_id);
+        if let Some(v) = &self.email {
+            check_length(&mut errors, "email", v, 3, MAX_EMAIL_LEN);
+        }
+        if let Some(v) = &self.role {
+            check_one_of(&mut errors, "role", v.as_str(), &USER_ROLES);
+        }
+        if let Some(v) = self.state {
+            check_one_of(&mut errors, "state", v, &USER_STATES);
+        }
+        if let Some(v) = self.verified {
+            check_one_of(&mut errors, "verified", v, &USER_VERIFIED);
+        }
+        let created_after =
+            check_timestamp(&mut errors, "created_after", &self.created_after);
+        let created_before = check_timestamp(
+            &mut errors,
+            "created_before",
+            &self.created_before,
+        );
+        if let (Some(after), Some(before)) = (created_after, created_before) {
+            if after >= before {
+                add_field_error(
+                    &mut errors,
+                    "created_before",
+                    "must be after created_after",
+                );
+            }
+        }
+        if let Some(v) = self.page {
+            check_range(&mut errors, "page", v, 0, i32::MAX as i64);
+        }
+        if let Some(v) = self.page_size {
+            check_range(&mut errors, "page_size", v, 1, MAX_PAGE_SIZE);
+        }
+        errors
+    }
+}
+
+/// max number of `users` records returned per page
+pub const MAX_PAGE_SIZE: i64 = 100;
+
+/// `users` columns in csv and ndjson exports
+pub const USER_EXPORT_COLUMNS: [&str; 6] =
+    ["user_id", "email", "state", "verified", "role", "version"];
+
+/// get_user_export_row
+///
+/// Convert a ``users`` row into an export row without the
+/// password hash
+///
+fn get_user_export_row(row: &Row) -> serde_json::Value {
+    let user_model = get_user_from_row(row);
+    serde_json::json!({
+        "user_id": user_model.id,
+        "email": user_model.email,
+        "state": user_model.state,
+        "verified": user_model.verified,
+        "role": user_model.role,
+        "version": user_model.version,
+    })
+}
+
+/// check_timestamp
+///
+/// Require an optional RFC 3339 timestamp
+///
+/// # Arguments
+///
+/// * `errors` - `&mut Vec<ApiFieldError>` - invalid fields
+/// * `field` - `&str` - request field name
+/// * `value` - `&Option<String>` - timestamp to check
+///
+/// # Returns
+///
+/// `Option<chrono::DateTime<chrono::Utc>>` - the parsed
+/// timestamp if set and valid
+///
+fn check_timestamp(
+    errors: &mut Vec<ApiFieldError>,
+    field: &str,
+    value: &Option<String>,
+) -> Option<chrono::DateTime<chrono::Utc>> {
+    match value {
+        Some(v) => match chrono::DateTime::parse_from_rfc3339(v) {
+            Ok(dt) => Some(dt.with_timezone(&chrono::Utc)),
+            Err(_) => {
+                add_field_error(
+                    errors,
+                    field,
+                    "must be an RFC 3339 timestamp like \
+                    2022-01-31T00:00:00Z",
+                );
+                None
+            }
+        },
+        None => None,
+    }
+}
+
+/// ApiReqUserSearch
+///
+/// Implementation for building the search query
+/// using sql
+impl ApiReqUserSearch {
+    /// get_page
+    ///
+    /// Get the requested page number and page size
+    ///
+    /// # Returns
+    ///
+    /// `(page, page_size)` as `(i64, i64)`
+    ///
+    pub fn get_page(&self) -> (i64, i64) {
+        (
+            self.page.unwrap_or(0),
+            self.page_size.unwrap_or(MAX_PAGE_SIZE),
+        )
+    }
+
+    /// get_search
+    ///
+    /// Build the
+    /// [`UserSearch`](crate::requests::models::user_repo::UserSearch)
+    /// filters for
+    /// [`UserRepo::search`](crate::requests::models::user_repo::UserRepo::search).
+    /// Admins only match users in their tenant and
+    /// non-admin users only match their own `users`
+    /// record. One extra record is selected to detect if
+    /// there are more pages.
+    ///
+    /// Call this after validating the request.
+    ///
+    /// # Arguments
+    ///
+    /// * `is_admin` - `bool` - the requesting user has
+    ///   the `admin` role
+    /// * `tenant_id` - `i32` - the requesting user's tenant
+    ///
+    pub fn get_search(&self, is_admin: bool, tenant_id: i32) -> UserSearch {
+        let parse_time = |value: &Option<String>| {
+            value
+                .as_ref()
+                .and_then(|v| chrono::DateTime::parse_from_rfc3339(v).ok())
+                .map(|dt| dt.with_timezone(&chrono::Utc))
+        };
+        let (page, page_size) = self.get_page();
+        UserSearch {
+            user_id: match is_admin {
+                true => None,
+                false => Some(self.user_id),
+            },
+            tenant_id: Some(tenant_id),
+            email: self.email.clone(),
+            role: self.role.clone(),
+            state: self.state,
+            verified: self.verified,
+            created_after: parse_time(&self.created_after),
+            created_before: parse_time(&self.created_before),
+            limit: page_size + 1,
+            offset: page * page_size,
+        }
+    }
 }
 
 /// ApiResUserSearch
@@ -74,7 +291,7 @@ pub struct ApiReqUserSearch {
 /// # Usage
 ///
 /// This type is the serialized output for the function:
-/// [`search_users`](crate::requests::user::search_users::search_users]
+/// [`search_users`](crate::requests::user::search_users::search_users)
 /// and contained within the
 /// hyper [`Body`](hyper::Body)
 /// of the
@@ -85,17 +302,28 @@ pub struct ApiReqUserSearch {
 ///
 /// * `users` - Vec<[`ApiResUserGet`](crate::requests::user::get_user::ApiResUserGet)> -
 ///   list of matching `users` record(s)
+/// * `page` - `i64` - page number
+/// * `page_size` - `i64` - records per page
+/// * `has_more` - `bool` - there are more
+///   matching records on the next page
 /// * `msg` - `String` - help message
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   machine-readable failure code (omitted on success)
 ///
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, Default)]
 pub struct ApiResUserSearch {
     pub users: Vec<ApiResUserGet>,
+    pub page: i64,
+    pub page_size: i64,
+    pub has_more: bool,
     pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ApiErrorCode>,
 }
 
 /// search_users
 ///
-/// Search for matching `users_data` records by the POST-ed
+/// Search for matching `users` records by the POST-ed
 /// [`ApiReqUserSearch`](crate::requests::user::search_users::ApiReqUserSearch)
 /// (filters) and return a list of
 /// [`ApiResUserGet`](crate::requests::user::get_user::ApiResUserGet)
@@ -104,7 +332,10 @@ pub struct ApiResUserSearch {
 ///
 /// ## Overview Notes
 ///
-/// A user can have many records in the `users_data` table.
+/// Users with the `admin` role can search across all
+/// users in their tenant. All other users only match their own `users`
+/// record. Results are sorted by newest `users.created_at`
+/// first and paged with `page` and `page_size`.
 ///
 /// # Arguments
 ///
@@ -112,8 +343,10 @@ pub struct ApiResUserSearch {
 /// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
 /// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
 ///   db threadpool with required tls encryption
+/// * `db_read_pools` - [`DbReadPools`](crate::pools::db_read_pools::DbReadPools) -
+///   postgres read replica pools for the read-only query
 /// * `kafka_pool` -
-///   [`KafkaPublisher`](kafka_threadpool::kafka_publisher::KafkaPublisher)
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
 ///   for asynchronously publishing messages to the connected kafka cluster
 /// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
 ///   hashmap containing headers in key-value pairs
@@ -153,6 +386,7 @@ pub async fn search_users(
     tracking_label: &str,
     config: &CoreConfig,
     db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    db_read_pools: &DbReadPools,
     kafka_pool: &KafkaPublisher,
     headers: &HeaderMap<HeaderValue>,
     bytes: &[u8],
@@ -164,9 +398,9 @@ pub async fn search_users(
                 .status(400)
                 .body(Body::from(
                     serde_json::to_string(&ApiResUserSearch {
-                        users: Vec::new(),
-                        msg: ("Missing user_id and email to search")
-                            .to_string(),
+                        msg: ("Missing user_id to search").to_string(),
+                        error_code: Some(ApiErrorCode::InvalidRequest),
+                        ..Default::default()
                     })
                     .unwrap(),
                 ))
@@ -174,39 +408,17 @@ pub async fn search_users(
             return Ok(response);
         }
     };
-    let user_id: i32 = user_object.user_id;
-    let user_email: String = user_object.email.clone();
-
-    if user_id < 1 || user_email.is_empty() {
-        let response = Response::builder()
-            .status(400)
-            .body(Body::from(
-                serde_json::to_string(&ApiResUserSearch {
-                    users: Vec::new(),
-                    msg: ("Missing user_id and email to search").to_string(),
-                })
-                .unwrap(),
-            ))
-            .unwrap();
+    if let Some(response) = validate_api_req(tracking_label, &user_object) {
         return Ok(response);
     }
 
-    if user_email.len() < 3 {
-        let response = Response::builder()
-            .status(400)
-            .body(Body::from(
-                serde_json::to_string(&ApiResUserSearch {
-                    users: Vec::new(),
-                    msg: ("User search requires at least 3 characters")
-                        .to_string(),
-                })
-                .unwrap(),
-            ))
-            .unwrap();
-        return Ok(response);
-    }
+    let user_id: i32 = user_object.user_id;
+    let (page, page_size) = user_object.get_page();
 
-    info!("{tracking_label} - searching user_id={user_id} email={user_email}");
+    info!(
+        "{tracking_label} - searching user_id={user_id} \
+        page={page} page_size={page_size}"
+    );
 
     let conn = db_pool.get().await.unwrap();
     let _token = match validate_user_token(
@@ -219,14 +431,15 @@ pub async fn search_users(
     .await
     {
         Ok(_token) => _token,
-        Err(_) => {
+        Err(error_code) => {
             let response = Response::builder()
                 .status(400)
                 .body(Body::from(
                     serde_json::to_string(&ApiResUserSearch {
-                        users: Vec::new(),
                         msg: ("User search failed due to invalid token")
                             .to_string(),
+                        error_code: Some(error_code),
+                        ..Default::default()
                     })
                     .unwrap(),
                 ))
@@ -235,95 +448,106 @@ pub async fn search_users(
         }
     };
 
-    // find all user by email and an active state where state == 0
-    let get_query = format!(
-        "SELECT \
-            users.id, \
-            users.email, \
-            users.password, \
-            users.state, \
-            users.verified, \
-            users.role \
-        FROM \
-            users \
-        WHERE \
-            users.email \
-        ILIKE \
-            '%{}%' \
-        ORDER BY \
-            users.created_at \
-        DESC \
-        LIMIT 100",
-        user_email
-    );
-    let stmt = conn.prepare(&get_query).await.unwrap();
-    let query_result = match conn.query(&stmt, &[]).await {
-        Ok(query_result) => query_result,
+    // only admins can search across all users in their tenant
+    let (is_admin, tenant_id) =
+        match get_user_by_id(tracking_label, user_id, &conn).await {
+            Ok(user_model) => {
+                (user_model.role == "admin", user_model.tenant_id)
+            }
+            Err(_) => (false, -1),
+        };
+    // csv and ndjson exports stream every matching user
+    if let Some(format) = config.search_export.get_format(headers) {
+        config
+            .events
+            .publish_user_event(kafka_pool, user_id, "SEARCH_USERS", "")
+            .await;
+        let search = UserSearch {
+            limit: 0,
+            offset: 0,
+            ..user_object.get_search(is_admin, tenant_id)
+        };
+        return Ok(config.search_export.stream_export(
+            tracking_label,
+            db_pool,
+            db_read_pools,
+            format,
+            "users",
+            UserRepo::get_search_query(&search),
+            &USER_EXPORT_COLUMNS,
+            get_user_export_row,
+        ));
+    }
+    let read_conn = db_read_pools.get_read_conn(tracking_label).await;
+    let read_conn = read_conn.as_ref().unwrap_or(&conn);
+    let found_users = match UserRepo::new(read_conn)
+        .search(tracking_label, &user_object.get_search(is_admin, tenant_id))
+        .await
+    {
+        Ok(found_users) => found_users,
         Err(e) => {
-            let err_msg = format!("{}", e);
             let response = Response::builder()
-                .status(500)
+                .status(e.status_code())
                 .body(Body::from(
-                    serde_json::to_string(
-                        &ApiResUserSearch {
-                            users: Vec::new(),
-                            msg: format!("User search failed for user_id={user_id} email={user_email} with err='{err_msg}'")
-                        }
-                    ).unwrap()))
+                    serde_json::to_string(&ApiResUserSearch {
+                        msg: format!(
+                            "User search failed for user_id={user_id} \
+                            with err='{e}'"
+                        ),
+                        error_code: Some(e.error_code()),
+                        ..Default::default()
+                    })
+                    .unwrap(),
+                ))
                 .unwrap();
             return Ok(response);
         }
     };
-    let mut row_list: Vec<ApiResUserGet> = Vec::with_capacity(100);
-    for row in query_result.iter() {
-        let id: i32 = row.try_get("id").unwrap();
-        let email: String = row.try_get("email").unwrap();
-        let user_state: i32 = row.try_get("state").unwrap();
-        let user_verified: i32 = row.try_get("verified").unwrap();
-        let role: String = row.try_get("role").unwrap();
-        row_list.push(ApiResUserGet {
-            user_id: id,
-            email,
-            state: user_state,
-            verified: user_verified,
-            role,
+    let has_more = found_users.len() as i64 > page_size;
+    let row_list: Vec<ApiResUserGet> = found_users
+        .into_iter()
+        .take(page_size as usize)
+        .map(|user_model| ApiResUserGet {
+            user_id: user_model.id,
+            email: user_model.email,
+            state: user_model.state,
+            verified: user_model.verified,
+            role: user_model.role,
+            version: user_model.version,
             msg: "".to_string(),
-        });
-    }
+            error_code: None,
+        })
+        .collect();
     if row_list.is_empty() {
         let response = Response::builder()
             .status(400)
             .body(Body::from(
                 serde_json::to_string(&ApiResUserSearch {
-                    users: Vec::new(),
+                    page,
+                    page_size,
                     msg: ("no users found").to_string(),
+                    error_code: Some(ApiErrorCode::NotFound),
+                    ..Default::default()
                 })
                 .unwrap(),
             ))
             .unwrap();
         Ok(response)
     } else {
-        // if enabled, publish to kafka
-        if config.kafka_publish_events {
-            publish_msg(
-                kafka_pool,
-                // topic
-                "user.events",
-                // partition key
-                &format!("user-{}", user_id),
-                // optional headers stored in: Option<HashMap<String, String>>
-                None,
-                // payload in the message
-                &format!("SEARCH_USERS user={user_id}"),
-            )
+        config
+            .events
+            .publish_user_event(kafka_pool, user_id, "SEARCH_USERS", "")
             .await;
-        }
         let response = Response::builder()
             .status(200)
             .body(Body::from(
                 serde_json::to_string(&ApiResUserSearch {
                     users: row_list,
+                    page,
+                    page_size,
+                    has_more,
                     msg: "success".to_string(),
+                    error_code: None,
                 })
                 .unwrap(),
             ))
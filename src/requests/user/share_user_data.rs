@@ -0,0 +1,444 @@
+//! Module for sharing a user's s3 data record with another
+//! user or through a public download link
+//!
+//! ## Share a user data file record
+//!
+//! Share a ``users_data`` record with another user id (search and download access) or create a public download link with an expiry
+//!
+//! - URL path: ``/user/data/shares``
+//! - Method: ``POST``
+//! - Handler: [`share_user_data`](crate::requests::user::share_user_data::share_user_data)
+//! - Request: [`ApiReqUserShareData`](crate::requests::user::share_user_data::ApiReqUserShareData)
+//! - Response: [`ApiResUserShareData`](crate::requests::user::share_user_data::ApiResUserShareData)
+//!
+use std::convert::Infallible;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Response;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::core::core_config::CoreConfig;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::monitoring::otel::trace_db_query;
+use crate::requests::auth::validate_user_token::validate_user_token;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::models::user_data_share::get_user_data_share_from_row;
+use crate::requests::models::user_data_share::ModelUserDataShare;
+use crate::requests::models::user_data_share::USER_DATA_SHARE_COLUMNS;
+use crate::requests::validation::field_rules::add_field_error;
+use crate::requests::validation::field_rules::check_id;
+use crate::requests::validation::validate_api_req::validate_api_req;
+use crate::requests::validation::validate_api_req::ApiFieldError;
+use crate::requests::validation::validate_api_req::ApiReqValidate;
+use crate::utils::get_uuid::get_uuid;
+use crate::utils::hash_token::hash_token;
+
+/// public download links expire after this many hours
+/// unless the request sets `expire_hours`
+pub const USER_DATA_SHARE_LINK_EXPIRE_HOURS: i64 = 24;
+
+/// longest allowed `expire_hours` for a share (1 year)
+pub const USER_DATA_SHARE_MAX_EXPIRE_HOURS: i64 = 8760;
+
+/// ApiReqUserShareData
+///
+/// # Request Type For share_user_data
+///
+/// Share a `users_data` record owned by `user_id` with a
+/// user (`shared_with_user_id`) or create a public download
+/// link when `shared_with_user_id` is not set. Sharing
+/// again with the same user changes the expiry. Every link
+/// request creates a new link.
+///
+/// This type is the deserialized input for:
+/// [`share_user_data`](crate::requests::user::share_user_data::share_user_data]
+///
+/// # Usage
+///
+/// This type is constructed from the deserialized
+/// `bytes` (`&[u8]`) argument
+/// on the
+/// [`share_user_data`](crate::requests::user::share_user_data::share_user_data)
+/// function.
+///
+/// # Arguments
+///
+/// * `user_id` - `i32` - user id that owns the `users_data` record
+/// * `data_id` - `i32` - `users_data.id` to share
+/// * `shared_with_user_id` - `Option<i32>` - share with this
+///   `users.id` (`None` creates a public download link)
+/// * `expire_hours` - `Option<i64>` - hours until the share
+///   stops working (user shares never expire by default and
+///   links expire after
+///   [`USER_DATA_SHARE_LINK_EXPIRE_HOURS`](crate::requests::user::share_user_data::USER_DATA_SHARE_LINK_EXPIRE_HOURS))
+///
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApiReqUserShareData {
+    pub user_id: i32,
+    pub data_id: i32,
+    pub shared_with_user_id: Option<i32>,
+    pub expire_hours: Option<i64>,
+}
+
+impl ApiReqValidate for ApiReqUserShareData {
+    /// validate
+    ///
+    /// Require a positive `user_id` and `data_id` with an
+    /// optional positive `shared_with_user_id` and an optional
+    /// `expire_hours` between 1 and
+    /// [`USER_DATA_SHARE_MAX_EXPIRE_HOURS`](crate::requests::user::share_user_data::USER_DATA_SHARE_MAX_EXPIRE_HOURS)
+    ///
+    fn validate(&self) -> Vec<ApiFieldError> {
+        let mut errors = Vec::new();
+        check_id(&mut errors, "user_id", self.user_id);
+        check_id(&mut errors, "data_id", self.data_id);
+        if let Some(shared_with_user_id) = self.shared_with_user_id {
+            check_id(&mut errors, "shared_with_user_id", shared_with_user_id);
+        }
+        if let Some(expire_hours) = self.expire_hours {
+            if !(1..=USER_DATA_SHARE_MAX_EXPIRE_HOURS).contains(&expire_hours) {
+                add_field_error(
+                    &mut errors,
+                    "expire_hours",
+                    &format!(
+                        "must be between 1 and \
+                        {USER_DATA_SHARE_MAX_EXPIRE_HOURS}"
+                    ),
+                );
+            }
+        }
+        errors
+    }
+}
+
+/// ApiResUserShareData
+///
+/// # Response type for share_user_data
+///
+/// Return the stored share for the `users_data` record
+///
+/// # Usage
+///
+/// This type is the serialized output for the function:
+/// [`share_user_data`](crate::requests::user::share_user_data::share_user_data]
+/// and contained within the
+/// hyper [`Body`](hyper::Body)
+/// of the
+/// hyper [`Response`](hyper::Response)
+/// sent back to the client.
+///
+/// # Arguments
+///
+/// * `share` - [`ModelUserDataShare`](crate::requests::models::user_data_share::ModelUserDataShare) -
+///   the stored share
+/// * `token` - `String` - public link token for
+///   ``GET /user/data/shared/TOKEN`` (empty for user
+///   shares). Only the token's hash is stored so this is the
+///   only time it is returned.
+/// * `msg` - `String` - help message
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   machine-readable failure code (omitted on success)
+///
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApiResUserShareData {
+    pub share: ModelUserDataShare,
+    pub token: String,
+    pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ApiErrorCode>,
+}
+
+/// share_user_data
+///
+/// Share a `users_data` record with another user in the
+/// same tenant or create a public download link. Only the
+/// owner of the record can share it.
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
+///   db threadpool with required tls encryption
+/// * `kafka_pool` -
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+///   for asynchronously publishing messages to the connected kafka cluster
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+///   [`Request`](hyper::Request)'s [`Body`](hyper::Body)
+/// * `bytes` - `&[u8]` - received bytes from the hyper
+///   [`Request`](hyper::Request)'s [`Body`](hyper::Body)
+///
+/// # Returns
+///
+/// ## share_user_data on Success Returns
+///
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResUserShareData`](crate::requests::user::share_user_data::ApiResUserShareData)
+/// dictionary within the
+/// [`Body`](hyper::Body) and a
+/// `201` HTTP status code
+///
+/// Ok([`Response`](hyper::Response))
+///
+/// # Errors
+///
+/// ## share_user_data on Failure Returns
+///
+/// All errors return as a
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResUserShareData`](crate::requests::user::share_user_data::ApiResUserShareData)
+/// dictionary with a
+/// `non-201` HTTP status code
+///
+/// Err([`Response`](hyper::Response))
+///
+pub async fn share_user_data(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    kafka_pool: &KafkaPublisher,
+    headers: &HeaderMap<HeaderValue>,
+    bytes: &[u8],
+) -> std::result::Result<Response<Body>, Infallible> {
+    let req_object: ApiReqUserShareData = match serde_json::from_slice(bytes) {
+        Ok(uo) => uo,
+        Err(_) => {
+            let response = Response::builder()
+                .status(400)
+                .body(Body::from(
+                    serde_json::to_string(&ApiResUserShareData {
+                        share: ModelUserDataShare::default(),
+                        token: "".to_string(),
+                        msg: ("User share data failed - \
+                            please ensure user_id and data_id are set \
+                            with optional shared_with_user_id \
+                            and expire_hours \
+                            were set correctly in the request")
+                            .to_string(),
+                        error_code: Some(ApiErrorCode::InvalidRequest),
+                    })
+                    .unwrap(),
+                ))
+                .unwrap();
+            return Ok(response);
+        }
+    };
+
+    if let Some(response) = validate_api_req(tracking_label, &req_object) {
+        return Ok(response);
+    }
+
+    let user_id = req_object.user_id;
+    let data_id = req_object.data_id;
+    if req_object.shared_with_user_id == Some(user_id) {
+        let response = Response::builder()
+            .status(400)
+            .body(Body::from(
+                serde_json::to_string(&ApiResUserShareData {
+                    share: ModelUserDataShare::default(),
+                    token: "".to_string(),
+                    msg: ("User share data failed - \
+                        the owner already has access")
+                        .to_string(),
+                    error_code: Some(ApiErrorCode::Conflict),
+                })
+                .unwrap(),
+            ))
+            .unwrap();
+        return Ok(response);
+    }
+
+    let conn = db_pool.get().await.unwrap();
+    let _token = match validate_user_token(
+        tracking_label,
+        config,
+        &conn,
+        headers,
+        user_id,
+    )
+    .await
+    {
+        Ok(_token) => _token,
+        Err(error_code) => {
+            let response = Response::builder()
+                .status(400)
+                .body(Body::from(
+                    serde_json::to_string(&ApiResUserShareData {
+                        share: ModelUserDataShare::default(),
+                        token: "".to_string(),
+                        msg: ("User share data failed due to invalid token")
+                            .to_string(),
+                        error_code: Some(error_code),
+                    })
+                    .unwrap(),
+                ))
+                .unwrap();
+            return Ok(response);
+        }
+    };
+
+    let expire_hours = match req_object.shared_with_user_id {
+        Some(_) => req_object.expire_hours,
+        None => Some(
+            req_object
+                .expire_hours
+                .unwrap_or(USER_DATA_SHARE_LINK_EXPIRE_HOURS),
+        ),
+    };
+    let expires_at_value = match expire_hours {
+        Some(expire_hours) => format!(
+            "timezone('UTC'::text, now()) + interval '{expire_hours} hours'"
+        ),
+        None => "NULL".to_string(),
+    };
+    // only the token hash is stored in the db and the
+    // token is only returned to the owner in this response
+    let link_token = match req_object.shared_with_user_id {
+        Some(_) => "".to_string(),
+        None => format!("{}{}", get_uuid(), get_uuid()),
+    };
+    let (target_column, target_value, conflict_sql, tenant_filter) =
+        match req_object.shared_with_user_id {
+            // users can only be shared records in their tenant
+            Some(shared_with_user_id) => (
+                "shared_with_user_id",
+                format!("{shared_with_user_id}"),
+                "ON CONFLICT (data_id, shared_with_user_id) \
+                    WHERE shared_with_user_id IS NOT NULL \
+                DO UPDATE SET \
+                    expires_at = EXCLUDED.expires_at "
+                    .to_string(),
+                format!(
+                    "AND EXISTS (\
+                        SELECT 1 FROM \
+                            users \
+                        WHERE \
+                            users.id = {shared_with_user_id} \
+                        AND \
+                            users.tenant_id = users_data.tenant_id) "
+                ),
+            ),
+            None => (
+                "token",
+                format!(
+                    "'{}'",
+                    hash_token(&link_token, &config.server_password_salt)
+                ),
+                "".to_string(),
+                "".to_string(),
+            ),
+        };
+    // only the owner can share the record
+    let cur_query = format!(
+        "INSERT INTO \
+            users_data_shares (\
+                data_id, \
+                owner_user_id, \
+                {target_column}, \
+                expires_at) \
+        SELECT \
+            users_data.id, \
+            users_data.user_id, \
+            {target_value}, \
+            {expires_at_value} \
+        FROM \
+            users_data \
+        WHERE \
+            users_data.id = {data_id} \
+        AND \
+            users_data.user_id = {user_id} \
+        {tenant_filter}\
+        {conflict_sql}\
+        RETURNING \
+            {USER_DATA_SHARE_COLUMNS};"
+    );
+    let stmt = conn.prepare(&cur_query).await.unwrap();
+    let query_result =
+        match trace_db_query(&cur_query, conn.query(&stmt, &[])).await {
+            Ok(query_result) => query_result,
+            Err(e) => {
+                let response = Response::builder()
+                    .status(500)
+                    .body(Body::from(
+                        serde_json::to_string(&ApiResUserShareData {
+                            share: ModelUserDataShare::default(),
+                            token: "".to_string(),
+                            msg: format!(
+                                "User share data failed \
+                                for user_id={user_id} data_id={data_id} \
+                                with err='{e}'"
+                            ),
+                            error_code: Some(ApiErrorCode::InternalError),
+                        })
+                        .unwrap(),
+                    ))
+                    .unwrap();
+                return Ok(response);
+            }
+        };
+
+    // must match up with RETURNING
+    if let Some(row) = query_result.first() {
+        let share = get_user_data_share_from_row(row);
+        let details = match share.shared_with_user_id {
+            Some(shared_with_user_id) => format!(
+                "data={data_id} share={} \
+                shared_with_user_id={shared_with_user_id}",
+                share.share_id
+            ),
+            None => format!("data={data_id} share={}", share.share_id),
+        };
+        config
+            .events
+            .publish_user_event(
+                kafka_pool,
+                user_id,
+                "USER_SHARE_DATA",
+                &details,
+            )
+            .await;
+
+        let response = Response::builder()
+            .status(201)
+            .body(Body::from(
+                serde_json::to_string(&ApiResUserShareData {
+                    share,
+                    token: link_token,
+                    msg: "success".to_string(),
+                    error_code: None,
+                })
+                .unwrap(),
+            ))
+            .unwrap();
+        return Ok(response);
+    }
+    let response = Response::builder()
+        .status(404)
+        .body(Body::from(
+            serde_json::to_string(&ApiResUserShareData {
+                share: ModelUserDataShare::default(),
+                token: "".to_string(),
+                msg: format!(
+                    "User share data failed - \
+                    unable to find data_id={data_id} \
+                    owned by user_id={user_id}"
+                ),
+                error_code: Some(ApiErrorCode::NotFound),
+            })
+            .unwrap(),
+        ))
+        .unwrap();
+    Ok(response)
+}
@@ -0,0 +1,496 @@
+//! Module for starting a resumable upload
+//!
+//! ## Start a resumable upload
+//!
+//! Create a ``users_data`` record in the ``uploading``
+//! status and an s3 multipart upload for a file that is
+//! sent in ``Content-Range`` chunks (see
+//! [`resumable_upload`](crate::requests::user::resumable_upload)).
+//!
+//! - URL path: ``/user/data/uploads``
+//! - Method: ``POST``
+//! - Handler: [`start_resumable_upload`](crate::requests::user::start_resumable_upload::start_resumable_upload)
+//! - Request: [`ApiReqUserStartResumableUpload`](crate::requests::user::start_resumable_upload::ApiReqUserStartResumableUpload)
+//! - Response: [`ApiResUserResumableUpload`](crate::requests::user::resumable_upload::ApiResUserResumableUpload)
+//!
+use std::convert::Infallible;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Response;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::core::core_config::CoreConfig;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::monitoring::otel::trace_client_span;
+use crate::monitoring::otel::trace_db_query;
+use crate::requests::auth::validate_user_token::validate_user_token;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::models::user_data_repo::NewUserData;
+use crate::requests::models::user_data_repo::UserDataRepo;
+use crate::requests::user::get_upload_metadata::get_content_type_essence;
+use crate::requests::user::get_upload_metadata::ApiReqUserUploadMetadata;
+use crate::requests::user::resumable_upload::get_object_store_error_response;
+use crate::requests::user::resumable_upload::get_resumable_upload_response;
+use crate::requests::user::resumable_upload::ApiResUserResumableUpload;
+use crate::requests::validation::field_rules::add_field_error;
+use crate::requests::validation::field_rules::check_range;
+use crate::requests::validation::validate_api_req::validate_api_req;
+use crate::requests::validation::validate_api_req::ApiFieldError;
+use crate::requests::validation::validate_api_req::ApiReqValidate;
+use crate::utils::get_uuid::get_uuid;
+
+/// ApiReqUserStartResumableUpload
+///
+/// # Request Type For start_resumable_upload
+///
+/// The upload metadata
+/// ([`ApiReqUserUploadMetadata`](crate::requests::user::get_upload_metadata::ApiReqUserUploadMetadata)
+/// fields) with the size of the file
+///
+/// # Arguments
+///
+/// * `total_bytes` - `i64` - size of the file
+/// * all [`ApiReqUserUploadMetadata`](crate::requests::user::get_upload_metadata::ApiReqUserUploadMetadata)
+///   fields except `sloc` and `s3_enable` (resumable uploads
+///   are always stored in the ``S3_DATA_BUCKET``)
+///
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct ApiReqUserStartResumableUpload {
+    pub total_bytes: i64,
+    #[serde(flatten)]
+    pub metadata: ApiReqUserUploadMetadata,
+}
+
+impl ApiReqValidate for ApiReqUserStartResumableUpload {
+    /// validate
+    ///
+    /// Validate the metadata and require a positive
+    /// `total_bytes` without a client `sloc` or `s3_enable`
+    ///
+    fn validate(&self) -> Vec<ApiFieldError> {
+        let mut errors = self.metadata.validate();
+        check_range(&mut errors, "total_bytes", self.total_bytes, 1, i64::MAX);
+        if self.metadata.sloc.is_some() {
+            add_field_error(
+                &mut errors,
+                "sloc",
+                "is not supported for resumable uploads",
+            );
+        }
+        if self.metadata.s3_enable.is_some() {
+            add_field_error(
+                &mut errors,
+                "s3_enable",
+                "is not supported for resumable uploads",
+            );
+        }
+        errors
+    }
+}
+
+/// start_resumable_upload
+///
+/// Handles creating a ``users_data`` record in the
+/// ``uploading`` status and the s3 multipart upload that
+/// receives the file chunks from
+/// [`upload_user_data_chunk`](crate::requests::user::upload_user_data_chunk::upload_user_data_chunk)
+///
+/// # Usage
+///
+/// ## Environment variables
+///
+/// See
+/// [`ResumableUploadConfig`](crate::requests::user::resumable_upload::ResumableUploadConfig)
+///
+/// ## Upload Size Limit
+///
+/// Uploads larger than ``UPLOAD_RESUMABLE_MAX_BYTES`` (or
+/// 10000 chunks of ``UPLOAD_RESUMABLE_MAX_CHUNK_BYTES``) are
+/// rejected with a `413`.
+///
+/// ## Virus Scanning
+///
+/// Resumable uploads are stored before the whole file is
+/// received, so the
+/// [`UploadScan`](crate::processing::upload_scanner::UploadScan)
+/// scanner cannot check them. With a scanner they are
+/// rejected with a `400` unless the
+/// [`UserDataPipeline`](crate::processing::user_data_pipeline::UserDataPipeline)
+/// is enabled to process completed uploads before they can
+/// be downloaded.
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
+///   db threadpool with required tls encryption
+/// * `_kafka_pool` -
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+///   for asynchronously publishing messages to the connected kafka cluster
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+/// * `bytes` - `&[u8]` - received bytes from the hyper
+///   [`Request`](hyper::Request)'s [`Body`](hyper::Body)
+///
+/// # Returns
+///
+/// ## start_resumable_upload on Success Returns
+///
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResUserResumableUpload`](crate::requests::user::resumable_upload::ApiResUserResumableUpload)
+/// dictionary within the
+/// [`Body`](hyper::Body) and a
+/// `200` HTTP status code
+///
+/// Ok([`Response`](hyper::Response))
+///
+/// # Errors
+///
+/// ## start_resumable_upload on Failure Returns
+///
+/// All errors return as a
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResUserResumableUpload`](crate::requests::user::resumable_upload::ApiResUserResumableUpload)
+/// dictionary with a
+/// `non-200` HTTP status code
+///
+/// Err([`Response`](hyper::Response))
+///
+pub async fn start_resumable_upload(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    _kafka_pool: &KafkaPublisher,
+    headers: &HeaderMap<HeaderValue>,
+    bytes: &[u8],
+) -> std::result::Result<Response<Body>, Infallible> {
+    let req_object: ApiReqUserStartResumableUpload =
+        match serde_json::from_slice(bytes) {
+            Ok(uo) => uo,
+            Err(_) => {
+                return Ok(get_resumable_upload_response(
+                    400,
+                    &ApiResUserResumableUpload {
+                        user_id: -1,
+                        data_id: -1,
+                        msg: ("User start resumable upload failed - \
+                            please ensure user_id, filename and \
+                            total_bytes were set correctly in the request")
+                            .to_string(),
+                        error_code: Some(ApiErrorCode::InvalidRequest),
+                        ..Default::default()
+                    },
+                ));
+            }
+        };
+
+    if let Some(response) = validate_api_req(tracking_label, &req_object) {
+        return Ok(response);
+    }
+    let metadata = req_object.metadata;
+    let user_id = metadata.user_id;
+    let total_bytes = req_object.total_bytes;
+
+    let conn = db_pool.get().await.unwrap();
+    if let Err(error_code) =
+        validate_user_token(tracking_label, config, &conn, headers, user_id)
+            .await
+    {
+        return Ok(get_resumable_upload_response(
+            400,
+            &ApiResUserResumableUpload {
+                user_id: -1,
+                data_id: -1,
+                msg: ("User start resumable upload failed due to invalid \
+                    token")
+                    .to_string(),
+                error_code: Some(error_code),
+                ..Default::default()
+            },
+        ));
+    }
+
+    let max_upload_bytes = config.resumable_uploads.get_max_upload_bytes();
+    if total_bytes > max_upload_bytes {
+        return Ok(get_resumable_upload_response(
+            413,
+            &ApiResUserResumableUpload {
+                user_id,
+                data_id: -1,
+                total_bytes,
+                msg: format!(
+                    "User start resumable upload failed - \
+                    total_bytes={total_bytes} is larger than the max size \
+                    of {max_upload_bytes} bytes"
+                ),
+                error_code: Some(ApiErrorCode::PayloadTooLarge),
+                ..Default::default()
+            },
+        ));
+    }
+    if let Err((status, err_msg)) = config
+        .user_data_quota
+        .check_upload(tracking_label, user_id, total_bytes, &conn)
+        .await
+    {
+        return Ok(get_resumable_upload_response(
+            status,
+            &ApiResUserResumableUpload {
+                user_id,
+                data_id: -1,
+                total_bytes,
+                msg: format!("User start resumable upload failed - {err_msg}"),
+                error_code: Some(ApiErrorCode::QuotaExceeded),
+                ..Default::default()
+            },
+        ));
+    }
+    if config.upload_scan.scanner.is_some()
+        && !config.user_data_pipeline.enabled
+    {
+        return Ok(get_resumable_upload_response(
+            400,
+            &ApiResUserResumableUpload {
+                user_id,
+                data_id: -1,
+                total_bytes,
+                msg: ("User start resumable upload failed - \
+                    resumable uploads cannot be scanned before they \
+                    are stored - please use POST /user/data")
+                    .to_string(),
+                error_code: Some(ApiErrorCode::InvalidRequest),
+                ..Default::default()
+            },
+        ));
+    }
+
+    let file_name_str = metadata.filename.as_str();
+    let s3_bucket = std::env::var("S3_DATA_BUCKET")
+        .unwrap_or_else(|_| "BUCKET_NAME".to_string());
+    let s3_prefix = std::env::var("S3_DATA_PREFIX")
+        .unwrap_or_else(|_| "user/data/file".to_string());
+    let now = chrono::Utc::now();
+    let now_str = now.format("%Y/%m/%d");
+    let s3_uuid = get_uuid();
+    let s3_key_dst = format!(
+        "{s3_prefix}/\
+        {user_id}/\
+        {now_str}/\
+        {s3_uuid}.{file_name_str}"
+    );
+    let sloc = format!("s3://{s3_bucket}/{s3_key_dst}");
+    let content_type = metadata
+        .content_type
+        .as_deref()
+        .and_then(get_content_type_essence);
+
+    let upload_id = match trace_client_span(
+        "s3 create multipart upload",
+        vec![
+            ("s3.bucket", s3_bucket.clone()),
+            ("s3.key", s3_key_dst.clone()),
+        ],
+        config.object_store.create_multipart_upload(
+            tracking_label,
+            &s3_bucket,
+            &s3_key_dst,
+            metadata.storage_class.as_deref(),
+        ),
+    )
+    .await
+    {
+        Ok(upload_id) => upload_id,
+        Err(e) => {
+            error!("{e}");
+            return Ok(get_object_store_error_response(
+                config,
+                &e,
+                |status, error_code| {
+                    get_resumable_upload_response(
+                        status,
+                        &ApiResUserResumableUpload {
+                            user_id,
+                            data_id: -1,
+                            total_bytes,
+                            msg: format!(
+                                "User start resumable upload failed for \
+                                user_id={user_id} - unable to create the \
+                                upload"
+                            ),
+                            error_code: Some(error_code),
+                            ..Default::default()
+                        },
+                    )
+                },
+            ));
+        }
+    };
+
+    let new_data = NewUserData {
+        user_id,
+        filename: file_name_str.to_string(),
+        data_type: metadata
+            .data_type
+            .clone()
+            .unwrap_or_else(|| "file".to_string()),
+        size_in_bytes: total_bytes,
+        comments: metadata
+            .comments
+            .clone()
+            .unwrap_or_else(|| "file".to_string()),
+        encoding: metadata
+            .encoding
+            .clone()
+            .unwrap_or_else(|| "na".to_string()),
+        sloc,
+        status: "uploading".to_string(),
+        checksum: "".to_string(),
+        storage_class: metadata.storage_class.clone(),
+        expire_days: metadata.expire_days,
+        expire_storage_class: metadata.expire_storage_class.clone(),
+        scan_status: None,
+        scan_signature: None,
+        content_type: content_type.clone(),
+        derivatives_status: config
+            .user_data_thumbnails
+            .get_upload_derivatives_status(content_type.as_deref()),
+        tags: metadata.tags.clone().unwrap_or_default(),
+        folder: metadata.folder.clone(),
+        file_version: None,
+        version_of: None,
+    };
+    let user_data = match UserDataRepo::new(&conn)
+        .insert(tracking_label, &new_data)
+        .await
+    {
+        Ok(user_data) => user_data,
+        Err(e) => {
+            let _ = config
+                .object_store
+                .abort_multipart_upload(
+                    tracking_label,
+                    &s3_bucket,
+                    &s3_key_dst,
+                    &upload_id,
+                )
+                .await;
+            return Ok(get_resumable_upload_response(
+                e.status_code(),
+                &ApiResUserResumableUpload {
+                    user_id,
+                    data_id: -1,
+                    total_bytes,
+                    msg: format!(
+                        "User start resumable upload failed for \
+                        user_id={user_id} with err='{e}'"
+                    ),
+                    error_code: Some(e.error_code()),
+                    ..Default::default()
+                },
+            ));
+        }
+    };
+    let data_id = user_data.data_id;
+
+    let query = format!(
+        "INSERT INTO \
+            users_data_uploads (\
+                data_id, \
+                user_id, \
+                bucket, \
+                key, \
+                upload_id, \
+                total_bytes, \
+                expires_at) \
+        VALUES (\
+            {data_id}, \
+            {user_id}, \
+            '{}', \
+            '{}', \
+            '{}', \
+            {total_bytes}, \
+            timezone('UTC'::text, now()) + interval '{} hours') \
+        RETURNING \
+            users_data_uploads.expires_at;",
+        s3_bucket.replace('\'', "''"),
+        s3_key_dst.replace('\'', "''"),
+        upload_id.replace('\'', "''"),
+        config.resumable_uploads.expire_hours
+    );
+    let stmt = conn.prepare(&query).await.unwrap();
+    let upload_expires_at =
+        match trace_db_query(&query, conn.query(&stmt, &[])).await {
+            Ok(query_result) => {
+                let expires_at: chrono::DateTime<chrono::Utc> =
+                    query_result[0].try_get("expires_at").unwrap();
+                format!("{}", expires_at.format("%Y-%m-%dT%H:%M:%SZ"))
+            }
+            Err(e) => {
+                let _ = config
+                    .object_store
+                    .abort_multipart_upload(
+                        tracking_label,
+                        &s3_bucket,
+                        &s3_key_dst,
+                        &upload_id,
+                    )
+                    .await;
+                let query = format!(
+                    "DELETE FROM \
+                        users_data \
+                    WHERE \
+                        users_data.id = {data_id};"
+                );
+                let stmt = conn.prepare(&query).await.unwrap();
+                let _ = trace_db_query(&query, conn.query(&stmt, &[])).await;
+                return Ok(get_resumable_upload_response(
+                    500,
+                    &ApiResUserResumableUpload {
+                        user_id,
+                        data_id: -1,
+                        total_bytes,
+                        msg: format!(
+                            "User start resumable upload failed for \
+                            user_id={user_id} with err='{e}'"
+                        ),
+                        error_code: Some(ApiErrorCode::InternalError),
+                        ..Default::default()
+                    },
+                ));
+            }
+        };
+
+    info!(
+        "{tracking_label} - started resumable upload for user_id={user_id} \
+        data_id={data_id} total_bytes={total_bytes} {}",
+        user_data.sloc
+    );
+    Ok(get_resumable_upload_response(
+        200,
+        &ApiResUserResumableUpload {
+            user_id,
+            data_id,
+            filename: user_data.filename,
+            sloc: user_data.sloc,
+            status: user_data.status,
+            total_bytes,
+            received_bytes: 0,
+            next_part: 1,
+            upload_expires_at,
+            msg: "success".to_string(),
+            error_code: None,
+        },
+    ))
+}
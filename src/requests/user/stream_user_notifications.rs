@@ -0,0 +1,310 @@
+//! Module for streaming a user's notifications with server-sent events
+//!
+//! ## Stream User Notifications
+//!
+//! Stream the events for the user that owns the request's token as server-sent events (``text/event-stream``) for clients that cannot use websockets. Each event uses the ``users_notifications.id`` as the event ``id``, the event name (``USER_UPDATE``) as the ``event`` and a json-serialized [`ModelUserNotification`](crate::requests::models::user_notification::ModelUserNotification) as the ``data``. A ``: keep-alive`` comment is sent when there are no new events. Reconnect with the ``Last-Event-ID`` header to resume after the last received event. Without the header only new events are sent.
+//!
+//! - URL path: ``/user/notifications/stream``
+//! - Method: ``GET``
+//! - Handler: [`stream_user_notifications`](crate::requests::user::stream_user_notifications::stream_user_notifications)
+//! - Request: none (uses the token header and the optional ``Last-Event-ID`` header)
+//! - Response: ``text/event-stream`` or [`ApiResUserNotificationsStream`](crate::requests::user::stream_user_notifications::ApiResUserNotificationsStream) on failure
+//!
+use std::convert::Infallible;
+use std::time::Duration;
+use std::time::Instant;
+
+use lazy_static::lazy_static;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use hyper::body::Bytes;
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Response;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::core::core_config::CoreConfig;
+use crate::monitoring::metric_types::register_int_gauge;
+use crate::monitoring::metric_types::IntGauge;
+use crate::requests::auth::validate_user_token::validate_user_token;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::models::user_notification::get_latest_user_notification_id;
+use crate::requests::models::user_notification::get_user_notifications_after;
+use crate::requests::models::user_session::get_user_session_by_token;
+
+/// max notifications sent per poll
+pub const MAX_NOTIFICATIONS_PER_POLL: i64 = 100;
+
+lazy_static! {
+    pub static ref USER_NOTIFICATION_STREAMS_GAUGE: IntGauge =
+        register_int_gauge!(
+            "user_notification_streams",
+            "Number of open user notification streams."
+        )
+        .unwrap();
+}
+
+/// ApiResUserNotificationsStream
+///
+/// # Response type for stream_user_notifications failures
+///
+/// # Usage
+///
+/// This type is the serialized output for the function:
+/// [`stream_user_notifications`](crate::requests::user::stream_user_notifications::stream_user_notifications]
+/// when the stream cannot start and contained within the
+/// hyper [`Body`](hyper::Body)
+/// of the
+/// hyper [`Response`](hyper::Response)
+/// sent back to the client.
+///
+/// # Arguments
+///
+/// * `user_id` - `i32` - user id
+/// * `msg` - `String` - help message
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   machine-readable failure code (omitted on success)
+///
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApiResUserNotificationsStream {
+    pub user_id: i32,
+    pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ApiErrorCode>,
+}
+
+/// stream_user_notifications
+///
+/// Handler for streaming the notifications for the
+/// user that owns the request's token with server-sent
+/// events
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
+///   db threadpool with required tls encryption
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+///
+/// # Returns
+///
+/// ## stream_user_notifications on Success Returns
+///
+/// hyper [`Response`](hyper::Response)
+/// with a ``text/event-stream`` [`Body`](hyper::Body) that
+/// stays open until the client disconnects or
+/// ``USER_NOTIFICATIONS_MAX_STREAM_SECONDS`` pass and a
+/// `200` HTTP status code
+///
+/// Ok([`Response`](hyper::Response))
+///
+/// # Errors
+///
+/// ## stream_user_notifications on Failure Returns
+///
+/// All errors return as a
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResUserNotificationsStream`](crate::requests::user::stream_user_notifications::ApiResUserNotificationsStream)
+/// dictionary with a
+/// `non-200` HTTP status code
+///
+/// Err([`Response`](hyper::Response))
+///
+pub async fn stream_user_notifications(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    headers: &HeaderMap<HeaderValue>,
+) -> std::result::Result<Response<Body>, Infallible> {
+    let notifications = config.events.notifications.clone();
+    if !notifications.enabled {
+        let response = Response::builder()
+            .status(400)
+            .body(Body::from(
+                serde_json::to_string(&ApiResUserNotificationsStream {
+                    user_id: -1,
+                    msg: ("User notifications are disabled").to_string(),
+                    error_code: Some(ApiErrorCode::FeatureDisabled),
+                })
+                .unwrap(),
+            ))
+            .unwrap();
+        return Ok(response);
+    }
+    let token = &config.token_cookie.get_request_token(headers);
+
+    let conn = db_pool.get().await.unwrap();
+    let (_session_id, user_id) =
+        get_user_session_by_token(tracking_label, token, &conn)
+            .await
+            .unwrap_or((-1, -1));
+    let valid_token = match user_id > 0 {
+        true => {
+            validate_user_token(tracking_label, config, &conn, headers, user_id)
+                .await
+        }
+        false => Err(ApiErrorCode::InvalidToken),
+    };
+    if let Err(error_code) = valid_token {
+        let response = Response::builder()
+            .status(400)
+            .body(Body::from(
+                serde_json::to_string(&ApiResUserNotificationsStream {
+                    user_id: -1,
+                    msg: ("User notifications stream failed \
+                        due to invalid token")
+                        .to_string(),
+                    error_code: Some(error_code),
+                })
+                .unwrap(),
+            ))
+            .unwrap();
+        return Ok(response);
+    }
+
+    // resume after the last received event or only
+    // send events created after connecting
+    let last_event_id = match headers.get("Last-Event-ID") {
+        Some(v) => match v.to_str().unwrap_or("").trim().parse::<i64>() {
+            Ok(last_event_id) if last_event_id >= 0 => Ok(last_event_id),
+            _ => {
+                let response = Response::builder()
+                    .status(400)
+                    .body(Body::from(
+                        serde_json::to_string(&ApiResUserNotificationsStream {
+                            user_id,
+                            msg: ("User notifications stream failed - \
+                                Last-Event-ID must be a notification id")
+                                .to_string(),
+                            error_code: Some(ApiErrorCode::InvalidRequest),
+                        })
+                        .unwrap(),
+                    ))
+                    .unwrap();
+                return Ok(response);
+            }
+        },
+        None => {
+            get_latest_user_notification_id(tracking_label, user_id, &conn)
+                .await
+        }
+    };
+    let mut last_event_id = match last_event_id {
+        Ok(last_event_id) => last_event_id,
+        Err(err_msg) => {
+            error!("{err_msg}");
+            let response = Response::builder()
+                .status(500)
+                .body(Body::from(
+                    serde_json::to_string(&ApiResUserNotificationsStream {
+                        user_id,
+                        msg: format!(
+                            "User notifications stream failed \
+                            for user_id={user_id}"
+                        ),
+                        error_code: Some(ApiErrorCode::InternalError),
+                    })
+                    .unwrap(),
+                ))
+                .unwrap();
+            return Ok(response);
+        }
+    };
+    drop(conn);
+
+    let (mut sender, body) = Body::channel();
+    let stream_label = format!("{tracking_label} - notifications");
+    let stream_db_pool = db_pool.clone();
+    tokio::spawn(async move {
+        USER_NOTIFICATION_STREAMS_GAUGE.inc();
+        info!(
+            "{stream_label} - \
+            stream started for user={user_id} after id={last_event_id}"
+        );
+        let started_at = Instant::now();
+        let max_stream = Duration::from_secs(notifications.max_stream_seconds);
+        let keep_alive = Duration::from_secs(notifications.keep_alive_seconds);
+        let poll_interval =
+            Duration::from_millis(notifications.poll_interval_ms);
+        let mut last_sent_at = Instant::now();
+        // tell EventSource clients how long to wait before reconnecting
+        let mut connected = sender
+            .send_data(Bytes::from(format!(
+                "retry: {}\n\n",
+                notifications.poll_interval_ms
+            )))
+            .await
+            .is_ok();
+        while connected && started_at.elapsed() < max_stream {
+            let found = match stream_db_pool.get().await {
+                Ok(conn) => {
+                    get_user_notifications_after(
+                        &stream_label,
+                        user_id,
+                        last_event_id,
+                        MAX_NOTIFICATIONS_PER_POLL,
+                        &conn,
+                    )
+                    .await
+                }
+                Err(e) => Err(format!(
+                    "{stream_label} - \
+                    failed to get a db connection with err='{e}'"
+                )),
+            };
+            match found {
+                Ok(found) => {
+                    for notification in found.iter() {
+                        let event = format!(
+                            "id: {}\nevent: {}\ndata: {}\n\n",
+                            notification.id,
+                            notification.event,
+                            serde_json::to_string(notification).unwrap()
+                        );
+                        if sender.send_data(Bytes::from(event)).await.is_err() {
+                            connected = false;
+                            break;
+                        }
+                        last_event_id = notification.id;
+                        last_sent_at = Instant::now();
+                    }
+                }
+                Err(err_msg) => error!("{err_msg}"),
+            }
+            if connected && last_sent_at.elapsed() >= keep_alive {
+                connected = sender
+                    .send_data(Bytes::from(": keep-alive\n\n"))
+                    .await
+                    .is_ok();
+                last_sent_at = Instant::now();
+            }
+            if connected {
+                tokio::time::sleep(poll_interval).await;
+            }
+        }
+        USER_NOTIFICATION_STREAMS_GAUGE.dec();
+        info!(
+            "{stream_label} - \
+            stream closed for user={user_id} at id={last_event_id}"
+        );
+    });
+
+    let response = Response::builder()
+        .status(200)
+        .header("Content-Type", "text/event-stream")
+        .header("Cache-Control", "no-cache")
+        .header("X-Accel-Buffering", "no")
+        .body(body)
+        .unwrap();
+    Ok(response)
+}
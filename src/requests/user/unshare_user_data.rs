@@ -0,0 +1,303 @@
+//! Module for removing a user share or public download link
+//! from a user's s3 data record
+//!
+//! ## Unshare a user data file record
+//!
+//! Remove a ``users_data_shares`` user share or public download link by its ``share_id``
+//!
+//! - URL path: ``/user/data/shares``
+//! - Method: ``DELETE``
+//! - Handler: [`unshare_user_data`](crate::requests::user::unshare_user_data::unshare_user_data)
+//! - Request: [`ApiReqUserUnshareData`](crate::requests::user::unshare_user_data::ApiReqUserUnshareData)
+//! - Response: [`ApiResUserUnshareData`](crate::requests::user::unshare_user_data::ApiResUserUnshareData)
+//!
+use std::convert::Infallible;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Response;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::core::core_config::CoreConfig;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::monitoring::otel::trace_db_query;
+use crate::requests::auth::validate_user_token::validate_user_token;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::models::user_data_share::get_user_data_share_from_row;
+use crate::requests::models::user_data_share::ModelUserDataShare;
+use crate::requests::models::user_data_share::USER_DATA_SHARE_COLUMNS;
+use crate::requests::validation::field_rules::check_id;
+use crate::requests::validation::validate_api_req::validate_api_req;
+use crate::requests::validation::validate_api_req::ApiFieldError;
+use crate::requests::validation::validate_api_req::ApiReqValidate;
+
+/// ApiReqUserUnshareData
+///
+/// # Request Type For unshare_user_data
+///
+/// Remove a share (user share or public download link) on a
+/// `users_data` record owned by `user_id`
+///
+/// This type is the deserialized input for:
+/// [`unshare_user_data`](crate::requests::user::unshare_user_data::unshare_user_data]
+///
+/// # Usage
+///
+/// This type is constructed from the deserialized
+/// `bytes` (`&[u8]`) argument
+/// on the
+/// [`unshare_user_data`](crate::requests::user::unshare_user_data::unshare_user_data)
+/// function.
+///
+/// # Arguments
+///
+/// * `user_id` - `i32` - user id that owns the `users_data` record
+/// * `share_id` - `i32` - `users_data_shares.id` to remove
+///
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApiReqUserUnshareData {
+    pub user_id: i32,
+    pub share_id: i32,
+}
+
+impl ApiReqValidate for ApiReqUserUnshareData {
+    /// validate
+    ///
+    /// Require a positive `user_id` and `share_id`
+    ///
+    fn validate(&self) -> Vec<ApiFieldError> {
+        let mut errors = Vec::new();
+        check_id(&mut errors, "user_id", self.user_id);
+        check_id(&mut errors, "share_id", self.share_id);
+        errors
+    }
+}
+
+/// ApiResUserUnshareData
+///
+/// # Response type for unshare_user_data
+///
+/// Return the removed share for the `users_data` record
+///
+/// # Usage
+///
+/// This type is the serialized output for the function:
+/// [`unshare_user_data`](crate::requests::user::unshare_user_data::unshare_user_data]
+/// and contained within the
+/// hyper [`Body`](hyper::Body)
+/// of the
+/// hyper [`Response`](hyper::Response)
+/// sent back to the client.
+///
+/// # Arguments
+///
+/// * `share` - [`ModelUserDataShare`](crate::requests::models::user_data_share::ModelUserDataShare) -
+///   the removed share
+/// * `msg` - `String` - help message
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   machine-readable failure code (omitted on success)
+///
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApiResUserUnshareData {
+    pub share: ModelUserDataShare,
+    pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ApiErrorCode>,
+}
+
+/// unshare_user_data
+///
+/// Remove a user share or public download link from a
+/// `users_data` record. Only the owner of the record can
+/// remove its shares.
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
+///   db threadpool with required tls encryption
+/// * `kafka_pool` -
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+///   for asynchronously publishing messages to the connected kafka cluster
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+///   [`Request`](hyper::Request)'s [`Body`](hyper::Body)
+/// * `bytes` - `&[u8]` - received bytes from the hyper
+///   [`Request`](hyper::Request)'s [`Body`](hyper::Body)
+///
+/// # Returns
+///
+/// ## unshare_user_data on Success Returns
+///
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResUserUnshareData`](crate::requests::user::unshare_user_data::ApiResUserUnshareData)
+/// dictionary within the
+/// [`Body`](hyper::Body) and a
+/// `200` HTTP status code
+///
+/// Ok([`Response`](hyper::Response))
+///
+/// # Errors
+///
+/// ## unshare_user_data on Failure Returns
+///
+/// All errors return as a
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResUserUnshareData`](crate::requests::user::unshare_user_data::ApiResUserUnshareData)
+/// dictionary with a
+/// `non-200` HTTP status code
+///
+/// Err([`Response`](hyper::Response))
+///
+pub async fn unshare_user_data(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    kafka_pool: &KafkaPublisher,
+    headers: &HeaderMap<HeaderValue>,
+    bytes: &[u8],
+) -> std::result::Result<Response<Body>, Infallible> {
+    let req_object: ApiReqUserUnshareData = match serde_json::from_slice(bytes)
+    {
+        Ok(uo) => uo,
+        Err(_) => {
+            let response = Response::builder()
+                .status(400)
+                .body(Body::from(
+                    serde_json::to_string(&ApiResUserUnshareData {
+                        share: ModelUserDataShare::default(),
+                        msg: ("User unshare data failed - \
+                            please ensure user_id and share_id \
+                            were set correctly in the request")
+                            .to_string(),
+                        error_code: Some(ApiErrorCode::InvalidRequest),
+                    })
+                    .unwrap(),
+                ))
+                .unwrap();
+            return Ok(response);
+        }
+    };
+
+    if let Some(response) = validate_api_req(tracking_label, &req_object) {
+        return Ok(response);
+    }
+
+    let user_id = req_object.user_id;
+    let share_id = req_object.share_id;
+    let conn = db_pool.get().await.unwrap();
+    let _token = match validate_user_token(
+        tracking_label,
+        config,
+        &conn,
+        headers,
+        user_id,
+    )
+    .await
+    {
+        Ok(_token) => _token,
+        Err(error_code) => {
+            let response = Response::builder()
+                .status(400)
+                .body(Body::from(
+                    serde_json::to_string(&ApiResUserUnshareData {
+                        share: ModelUserDataShare::default(),
+                        msg: ("User unshare data failed due to invalid token")
+                            .to_string(),
+                        error_code: Some(error_code),
+                    })
+                    .unwrap(),
+                ))
+                .unwrap();
+            return Ok(response);
+        }
+    };
+
+    let cur_query = format!(
+        "DELETE FROM \
+            users_data_shares \
+        WHERE \
+            users_data_shares.id = {share_id} \
+        AND \
+            users_data_shares.owner_user_id = {user_id} \
+        RETURNING \
+            {USER_DATA_SHARE_COLUMNS};"
+    );
+    let stmt = conn.prepare(&cur_query).await.unwrap();
+    let query_result =
+        match trace_db_query(&cur_query, conn.query(&stmt, &[])).await {
+            Ok(query_result) => query_result,
+            Err(e) => {
+                let response = Response::builder()
+                    .status(500)
+                    .body(Body::from(
+                        serde_json::to_string(&ApiResUserUnshareData {
+                            share: ModelUserDataShare::default(),
+                            msg: format!(
+                                "User unshare data failed \
+                                for user_id={user_id} share_id={share_id} \
+                                with err='{e}'"
+                            ),
+                            error_code: Some(ApiErrorCode::InternalError),
+                        })
+                        .unwrap(),
+                    ))
+                    .unwrap();
+                return Ok(response);
+            }
+        };
+
+    // must match up with RETURNING
+    if let Some(row) = query_result.first() {
+        let share = get_user_data_share_from_row(row);
+        config
+            .events
+            .publish_user_event(
+                kafka_pool,
+                user_id,
+                "USER_UNSHARE_DATA",
+                &format!("data={} share={share_id}", share.data_id),
+            )
+            .await;
+
+        let response = Response::builder()
+            .status(200)
+            .body(Body::from(
+                serde_json::to_string(&ApiResUserUnshareData {
+                    share,
+                    msg: "success".to_string(),
+                    error_code: None,
+                })
+                .unwrap(),
+            ))
+            .unwrap();
+        return Ok(response);
+    }
+    let response = Response::builder()
+        .status(404)
+        .body(Body::from(
+            serde_json::to_string(&ApiResUserUnshareData {
+                share: ModelUserDataShare::default(),
+                msg: format!(
+                    "User unshare data failed - \
+                    no share_id={share_id} \
+                    owned by user_id={user_id}"
+                ),
+                error_code: Some(ApiErrorCode::NotFound),
+            })
+            .unwrap(),
+        ))
+        .unwrap();
+    Ok(response)
+}
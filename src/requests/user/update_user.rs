@@ -4,12 +4,24 @@
 //!
 //! Update supported ``users`` fields (including change user email and password)
 //!
+//! A changed password or email sends a security notification to the user's previous email (see [`security_notifications`](crate::notifications::security_notifications)).
+//!
 //! - URL path: ``/user``
 //! - Method: ``PUT``
 //! - Handler: [`update_user`](crate::requests::user::update_user::update_user)
 //! - Request: [`ApiReqUserUpdate`](crate::requests::user::update_user::ApiReqUserUpdate)
 //! - Response: [`ApiResUserUpdate`](crate::requests::user::update_user::ApiResUserUpdate)
 //!
+//! ## Patch User
+//!
+//! Update ``users`` fields with a json merge patch where a ``null`` ``locale`` resets it to the ``EMAIL_DEFAULT_LOCALE`` (see [`merge_patch`](crate::requests::validation::merge_patch)).
+//!
+//! - URL path: ``/user``
+//! - Method: ``PATCH``
+//! - Handler: [`patch_user`](crate::requests::user::update_user::patch_user)
+//! - Request: [`ApiReqUserUpdate`](crate::requests::user::update_user::ApiReqUserUpdate)
+//! - Response: [`ApiResUserUpdate`](crate::requests::user::update_user::ApiResUserUpdate)
+//!
 
 use std::convert::Infallible;
 
@@ -26,19 +38,39 @@ use hyper::Response;
 use serde::Deserialize;
 use serde::Serialize;
 
-use argon2::hash_encoded as argon_hash_encoded;
-use argon2::Config as argon_config;
-
-use kafka_threadpool::kafka_publisher::KafkaPublisher;
-
 use crate::core::core_config::CoreConfig;
-use crate::kafka::publish_msg::publish_msg;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::notifications::email_templates::normalize_locale;
+use crate::notifications::email_templates::EmailTemplates;
+use crate::notifications::security_notifications::send_security_notification;
 use crate::requests::auth::validate_user_token::validate_user_token;
+use crate::requests::models::api_error::ApiError;
+use crate::requests::models::api_error::ApiErrorCode;
 use crate::requests::models::user::get_user_by_id;
 use crate::requests::models::user::ModelUser;
+use crate::requests::models::user_repo::UserChanges;
+use crate::requests::models::user_repo::UserRepo;
 use crate::requests::user::is_verification_enabled::is_verification_enabled;
 use crate::requests::user::upsert_user_verification::upsert_user_verification;
+use crate::requests::validation::field_rules::check_email;
+use crate::requests::validation::field_rules::check_id;
+use crate::requests::validation::field_rules::check_locale;
+use crate::requests::validation::field_rules::check_one_of;
+use crate::requests::validation::field_rules::check_password;
+use crate::requests::validation::field_rules::check_version;
+use crate::requests::validation::field_rules::USER_ROLES;
+use crate::requests::validation::field_rules::USER_STATES;
+use crate::requests::validation::field_rules::USER_VERIFIED;
+use crate::requests::validation::merge_patch::parse_merge_patch;
+use crate::requests::validation::normalize_email::normalize_email;
+use crate::requests::validation::validate_api_req::get_validation_errors_response;
+use crate::requests::validation::validate_api_req::validate_api_req;
+use crate::requests::validation::validate_api_req::ApiFieldError;
+use crate::requests::validation::validate_api_req::ApiReqValidate;
+use crate::requests::validation::validate_email_domain::validate_email_domain;
+use crate::requests::validation::validate_password_policy::validate_password_policy;
 use crate::utils::get_server_address::get_server_address;
+use crate::utils::password_hashing::PasswordHashing;
 
 /// ApiReqUserUpdate
 ///
@@ -70,6 +102,11 @@ use crate::utils::get_server_address::get_server_address;
 ///   `users.verified` field
 /// * `role` - `Option<String>` - change the
 ///   `users.role` field
+/// * `locale` - `Option<String>` - change the
+///   `users.locale` field used for the user's emails
+/// * `version` - `Option<i32>` - required `users.version`
+///   from the last get, search or update response (a
+///   different current version is rejected with a ``409``)
 ///
 #[derive(Serialize, Deserialize, Clone)]
 pub struct ApiReqUserUpdate {
@@ -79,14 +116,55 @@ pub struct ApiReqUserUpdate {
     pub state: Option<i32>,
     pub verified: Option<i32>,
     pub role: Option<String>,
+    #[serde(default)]
+    pub locale: Option<String>,
+    pub version: Option<i32>,
+}
+
+impl ApiReqValidate for ApiReqUserUpdate {
+    /// validate
+    ///
+    /// Require a positive `user_id` with an optional
+    /// valid `email`, `password` between 4 and 1024
+    /// characters, `state` and `verified` of `0` or `1`,
+    /// a supported `role`, a valid `locale` and the expected
+    /// `version`
+    ///
+    fn validate(&self) -> Vec<ApiFieldError> {
+        let mut errors = Vec::new();
+        check_id(&mut errors, "user_id", self.user_id);
+        check_version(&mut errors, "version", self.version);
+        if let Some(email) = &self.email {
+            check_email(&mut errors, "email", email);
+        }
+        if let Some(password) = &self.password {
+            check_password(&mut errors, "password", password);
+        }
+        if let Some(state) = self.state {
+            check_one_of(&mut errors, "state", state, &USER_STATES);
+        }
+        if let Some(verified) = self.verified {
+            check_one_of(&mut errors, "verified", verified, &USER_VERIFIED);
+        }
+        if let Some(role) = &self.role {
+            check_one_of(&mut errors, "role", role.as_str(), &USER_ROLES);
+        }
+        if let Some(locale) = &self.locale {
+            check_locale(&mut errors, "locale", locale);
+        }
+        errors
+    }
 }
 
-/// implementation for wrapping complex sql statement creation
+/// implementation for building the user changes
 impl ApiReqUserUpdate {
-    /// get_sql
+    /// get_changes
     ///
-    /// Build the update sql statement based off the
-    /// object's values
+    /// Build the
+    /// [`UserChanges`](crate::requests::models::user_repo::UserChanges)
+    /// for
+    /// [`UserRepo::update`](crate::requests::models::user_repo::UserRepo::update)
+    /// based off the object's values
     ///
     /// # Password Salt Algorithm
     ///
@@ -94,97 +172,48 @@ impl ApiReqUserUpdate {
     /// uses `argon2` to salt the new password value
     /// stored in the db.
     ///
-    pub fn get_sql(
+    pub fn get_changes(
         &self,
         server_password_salt: &[u8],
+        password_hashing: &PasswordHashing,
         user_model: &ModelUser,
-    ) -> String {
-        let user_email = self.email.clone();
-        let email_value: String = match self.email.clone() {
-            Some(new_email) => {
-                if is_verification_enabled() {
-                    // if the email is different
-                    if !new_email.is_empty() && user_model.email != new_email {
-                        format!("email = '{new_email}', verified = 0")
-                    } else {
-                        // the email in the db matches the requested one
-                        "".to_string()
-                    }
-                } else {
-                    format!("email = '{new_email}', verified = 1")
-                }
-            }
-            None => "".to_string(),
-        };
-        let mut update_value = email_value;
-        let password_value: String = match self.password.clone() {
-            Some(cur_user_salted_password) => {
-                let config = argon_config::default();
-                let new_hashed_password = argon_hash_encoded(
-                    cur_user_salted_password.as_bytes(),
-                    server_password_salt,
-                    &config,
-                )
-                .unwrap();
-                if update_value.is_empty() {
-                    format!(", password = '{new_hashed_password}'")
-                } else {
-                    format!("password = '{new_hashed_password}'")
-                }
-            }
-            None => "".to_string(),
+    ) -> UserChanges {
+        let mut changes = UserChanges {
+            state: self.state,
+            locale: self.locale.clone(),
+            expected_version: self.version,
+            ..Default::default()
         };
-        update_value = format!("{update_value}{password_value}");
-        let state_value: String = match self.state {
-            Some(v) => {
-                if update_value.is_empty() {
-                    format!(", state = '{v}'")
-                } else {
-                    format!("state = '{v}'")
-                }
-            }
-            None => "".to_string(),
-        };
-        update_value = format!("{update_value}{state_value}");
-        let role_value: String = match self.role {
-            Some(_) => {
-                // for now role changing has no effect on purpose
-                if self.email.is_some()
-                    && &user_email.unwrap_or_else(|| "".to_string())
-                        == "admin@email.com"
-                {
-                    if update_value.is_empty() {
-                        ", role = 'admin' ".to_string()
-                    } else {
-                        "role = 'admin' ".to_string()
-                    }
-                } else if update_value.is_empty() {
-                    ", role = 'user' ".to_string()
-                } else {
-                    "role = 'user' ".to_string()
+        if let Some(new_email) = &self.email {
+            if is_verification_enabled() {
+                // only a different email needs to be verified again
+                if !new_email.is_empty() && user_model.email != *new_email {
+                    changes.email = Some(new_email.clone());
+                    changes.verified = Some(0);
                 }
+            } else {
+                changes.email = Some(new_email.clone());
+                changes.verified = Some(1);
             }
-            None => "".to_string(),
-        };
-        update_value = format!("{update_value}{role_value}");
-        let cur_query = format!(
-            "UPDATE \
-                users \
-            SET \
-                {update_value} \
-            WHERE \
-                users.id = {} \
-            RETURNING \
-                users.id, \
-                users.email, \
-                users.state, \
-                users.verified, \
-                users.role;",
-            self.user_id
-        );
-        // careful this can log the salted password!
-        // info!("ApiReqUserUpdate query: {cur_query}");
-        cur_query
+        }
+        if let Some(cur_user_salted_password) = &self.password {
+            changes.password_hash = Some(
+                password_hashing
+                    .hash_password(
+                        cur_user_salted_password,
+                        server_password_salt,
+                    )
+                    .unwrap(),
+            );
+        }
+        if self.role.is_some() {
+            // for now role changing has no effect on purpose
+            changes.role = match self.email.as_deref() {
+                Some("admin@email.com") => Some("admin".to_string()),
+                _ => Some("user".to_string()),
+            };
+        }
+        changes
     }
 }
 
@@ -212,7 +241,10 @@ impl ApiReqUserUpdate {
 /// * `verified` - `i32` - user email verified
 ///   (`0` - not-verified, `1` - verified)
 /// * `role` - `String` - user role
+/// * `version` - `i32` - user version for the next update
 /// * `msg` - `String` - help message
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   machine-readable failure code (omitted on success)
 ///
 #[derive(Serialize, Deserialize, Clone)]
 pub struct ApiResUserUpdate {
@@ -221,7 +253,10 @@ pub struct ApiResUserUpdate {
     pub state: i32,
     pub verified: i32,
     pub role: String,
+    pub version: i32,
     pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ApiErrorCode>,
 }
 
 /// update_user
@@ -241,7 +276,7 @@ pub struct ApiResUserUpdate {
 /// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
 ///   db threadpool with required tls encryption
 /// * `kafka_pool` -
-///   [`KafkaPublisher`](kafka_threadpool::kafka_publisher::KafkaPublisher)
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
 ///   for asynchronously publishing messages to the connected kafka cluster
 /// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
 ///   hashmap containing headers in key-value pairs
@@ -298,12 +333,14 @@ pub async fn update_user(
                         state: -1,
                         verified: -1,
                         role: "".to_string(),
+                        version: -1,
                         msg: ("User update failed - please ensure \
                             user_id is set \
                             with optional arguments \
                             email, password, state, role \
                             were set correctly in the request")
                             .to_string(),
+                        error_code: Some(ApiErrorCode::InvalidRequest),
                     })
                     .unwrap(),
                 ))
@@ -312,11 +349,149 @@ pub async fn update_user(
         }
     };
 
+    process_user_update(
+        tracking_label,
+        config,
+        db_pool,
+        kafka_pool,
+        headers,
+        user_object,
+    )
+    .await
+}
+
+/// patch_user
+///
+/// Handles updating a user record (in the `users` table)
+/// with a
+/// [json merge patch](crate::requests::validation::merge_patch)
+/// in the PATCH-ed hyper
+/// [`Request`](hyper::Request)'s [`Body`](hyper::Body)
+///
+/// ## Overview Notes
+///
+/// Missing fields are not changed and a ``null`` ``locale``
+/// resets the user's emails to the ``EMAIL_DEFAULT_LOCALE``.
+/// ``email``, ``password``, ``state``, ``verified`` and
+/// ``role`` cannot be ``null``.
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
+///   db threadpool with required tls encryption
+/// * `kafka_pool` -
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+///   for asynchronously publishing messages to the connected kafka cluster
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+///   [`Request`](hyper::Request)'s [`Body`](hyper::Body)
+/// * `bytes` - `&[u8]` - received bytes from the hyper
+///   [`Request`](hyper::Request)'s [`Body`](hyper::Body)
+///
+/// # Returns
+///
+/// ## patch_user on Success Returns
+///
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResUserUpdate`](crate::requests::user::update_user::ApiResUserUpdate)
+/// dictionary within the
+/// [`Body`](hyper::Body) and a
+/// `200` HTTP status code
+///
+/// Ok([`Response`](hyper::Response))
+///
+/// # Errors
+///
+/// ## patch_user on Failure Returns
+///
+/// All errors return as a
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResUserUpdate`](crate::requests::user::update_user::ApiResUserUpdate)
+/// dictionary with a
+/// `non-200` HTTP status code
+///
+/// Err([`Response`](hyper::Response))
+///
+pub async fn patch_user(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    kafka_pool: &KafkaPublisher,
+    headers: &HeaderMap<HeaderValue>,
+    bytes: &[u8],
+) -> std::result::Result<Response<Body>, Infallible> {
+    let patch = match parse_merge_patch::<ApiReqUserUpdate>(bytes) {
+        Ok(patch) => patch,
+        Err(err_msg) => {
+            let response = Response::builder()
+                .status(400)
+                .body(Body::from(
+                    serde_json::to_string(&ApiResUserUpdate {
+                        user_id: -1,
+                        email: "".to_string(),
+                        state: -1,
+                        verified: -1,
+                        role: "".to_string(),
+                        version: -1,
+                        msg: format!(
+                            "User patch failed - please ensure \
+                            user_id is set in a json object - {err_msg}"
+                        ),
+                        error_code: Some(ApiErrorCode::InvalidRequest),
+                    })
+                    .unwrap(),
+                ))
+                .unwrap();
+            return Ok(response);
+        }
+    };
+    let mut errors = Vec::new();
+    patch.check_not_cleared(
+        &mut errors,
+        &["email", "password", "state", "verified", "role"],
+    );
+    if !errors.is_empty() {
+        return Ok(get_validation_errors_response(errors));
+    }
+    let mut user_object = patch.req.clone();
+    if patch.is_cleared("locale") {
+        user_object.locale =
+            Some(config.email_templates.default_locale.clone());
+    }
+    process_user_update(
+        tracking_label,
+        config,
+        db_pool,
+        kafka_pool,
+        headers,
+        user_object,
+    )
+    .await
+}
+
+/// process_user_update
+///
+/// Validate and apply a ``PUT`` or ``PATCH`` update to a
+/// ``users`` record
+///
+async fn process_user_update(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    kafka_pool: &KafkaPublisher,
+    headers: &HeaderMap<HeaderValue>,
+    mut user_object: ApiReqUserUpdate,
+) -> std::result::Result<Response<Body>, Infallible> {
     // is this a waste of time because nothing changed
     if user_object.email.is_none()
         && user_object.password.is_none()
         && user_object.state.is_none()
         && user_object.role.is_none()
+        && user_object.locale.is_none()
     {
         let response = Response::builder()
             .status(400)
@@ -327,42 +502,43 @@ pub async fn update_user(
                     state: -1,
                     verified: -1,
                     role: "".to_string(),
+                    version: -1,
                     msg: ("User update detected no changes - please ensure \
                         the correct user_id for the TOKEN is set \
                         with optional arguments \
                         email, password, state, role \
                         were set correctly in the request")
                         .to_string(),
+                    error_code: Some(ApiErrorCode::NoChanges),
                 })
                 .unwrap(),
             ))
             .unwrap();
         return Ok(response);
-    } else if user_object
-        .password
-        .clone()
-        .unwrap_or_else(|| "NOT_SET_SO_SET_LARGER_THAN_MIN".to_string())
-        .len()
-        < 4
-    {
-        let response = Response::builder()
-            .status(400)
-            .body(Body::from(
-                serde_json::to_string(&ApiResUserUpdate {
-                    user_id: -1,
-                    email: "".to_string(),
-                    state: -1,
-                    verified: -1,
-                    role: "".to_string(),
-                    msg: ("User update failed - \
-                        password must be longer than 4 characters")
-                        .to_string(),
-                })
-                .unwrap(),
-            ))
-            .unwrap();
+    }
+
+    user_object.email = user_object.email.as_deref().map(normalize_email);
+    user_object.locale = user_object.locale.as_deref().map(normalize_locale);
+    if let Some(response) = validate_api_req(tracking_label, &user_object) {
         return Ok(response);
     }
+    if let Some(password) = &user_object.password {
+        if let Some(response) = validate_password_policy(
+            tracking_label,
+            &config.get_settings().password_policy,
+            "password",
+            password,
+        ) {
+            return Ok(response);
+        }
+    }
+    if let Some(email) = &user_object.email {
+        if let Some(response) =
+            validate_email_domain(tracking_label, "email", email).await
+        {
+            return Ok(response);
+        }
+    }
 
     let conn = db_pool.get().await.unwrap();
 
@@ -379,7 +555,7 @@ pub async fn update_user(
     .await
     {
         Ok(_token) => _token,
-        Err(_) => {
+        Err(error_code) => {
             let response = Response::builder()
                 .status(400)
                 .body(Body::from(
@@ -389,8 +565,10 @@ pub async fn update_user(
                         state: -1,
                         verified: -1,
                         role: "".to_string(),
+                        version: -1,
                         msg: ("User update failed due to invalid token")
                             .to_string(),
+                        error_code: Some(error_code),
                     })
                     .unwrap(),
                 ))
@@ -417,10 +595,12 @@ pub async fn update_user(
                         state: -1,
                         verified: -1,
                         role: "".to_string(),
+                        version: -1,
                         msg: format!(
                             "User update failed - \
                             unable to find user with id: {user_id}"
                         ),
+                        error_code: Some(ApiErrorCode::UserNotFound),
                     })
                     .unwrap(),
                 ))
@@ -429,156 +609,176 @@ pub async fn update_user(
         }
     };
 
-    let cur_query =
-        user_object.get_sql(&config.server_password_salt, &user_model);
-
-    let stmt = conn.prepare(&cur_query).await.unwrap();
-    let query_result = match conn.query(&stmt, &[]).await {
-        Ok(query_result) => query_result,
+    let changes = user_object.get_changes(
+        &config.server_password_salt,
+        &config.password_hashing,
+        &user_model,
+    );
+    let updated_user = match UserRepo::new(&conn)
+        .update(tracking_label, user_id, &changes)
+        .await
+    {
+        Ok(updated_user) => updated_user,
         Err(e) => {
-            let err_msg = format!("{e}");
-            if err_msg.contains(
-                "db error: ERROR: duplicate key value \
-                violates unique constraint",
-            ) && err_msg.contains("users_email_key")
-                && err_msg.contains("already exists")
-            {
-                let response = Response::builder()
-                    .status(400)
-                    .body(Body::from(
-                        serde_json::to_string(&ApiResUserUpdate {
-                            user_id: -1,
-                            email: "".to_string(),
-                            state: -1,
-                            verified: -1,
-                            role: "".to_string(),
-                            msg: format!(
-                                "User email is already \
-                                in use: {user_email}"
-                            ),
-                        })
-                        .unwrap(),
-                    ))
-                    .unwrap();
-                return Ok(response);
-            } else {
-                let response = Response::builder()
-                    .status(400)
-                    .body(Body::from(
-                        serde_json::to_string(
-                            &ApiResUserUpdate {
-                                user_id: -1,
-                                email: "".to_string(),
-                                state: -1,
-                                verified: -1,
-                                role: "".to_string(),
-                                msg: format!(
-                                    "User update failed for user_id={user_id} {user_email} \
-                                    with err='{err_msg}'")
-                            }
-                        ).unwrap()))
-                    .unwrap();
-                return Ok(response);
-            }
-        }
-    };
-
-    // must match up with RETURNING
-    let mut row_list: Vec<(i32, String, i32, i32, String)> =
-        Vec::with_capacity(1);
-    for row in query_result.iter() {
-        let id: i32 = row.try_get("id").unwrap();
-        let email: String = row.try_get("email").unwrap();
-        let user_state: i32 = row.try_get("state").unwrap();
-        let user_verified: i32 = row.try_get("verified").unwrap();
-        let role: String = row.try_get("role").unwrap();
-        row_list.push((id, email, user_state, user_verified, role))
-    }
-    if row_list.is_empty() {
-        let response = Response::builder()
-            .status(400)
-            .body(Body::from(
-                serde_json::to_string(
-                    &ApiResUserUpdate {
+            // a stale version is a 409 so clients know to retry
+            let status = match e {
+                ApiError::VersionConflict(_) => 409,
+                _ => 400,
+            };
+            let error_code = match e {
+                ApiError::Conflict(_) => ApiErrorCode::EmailInUse,
+                _ => e.error_code(),
+            };
+            let msg = match e {
+                ApiError::Conflict(_) => {
+                    format!("User email is already in use: {user_email}")
+                }
+                ApiError::VersionConflict(_) => format!(
+                    "User update failed - user_id={user_id} was changed \
+                    by another request - get the user and retry with \
+                    the current version"
+                ),
+                ApiError::NotFound(_) => format!(
+                    "User update failed - user does \
+                    not exist with user_id={user_id} email={user_email}"
+                ),
+                ApiError::Timeout(err_msg) | ApiError::Db(err_msg) => format!(
+                    "User update failed for user_id={user_id} {user_email} \
+                    with err='{err_msg}'"
+                ),
+            };
+            let response = Response::builder()
+                .status(status)
+                .body(Body::from(
+                    serde_json::to_string(&ApiResUserUpdate {
                         user_id: -1,
                         email: "".to_string(),
                         state: -1,
                         verified: -1,
                         role: "".to_string(),
-                        msg: format!(
-                            "User update failed - user does \
-                            not exist with user_id={user_id} email={user_email}")
-                    }
-                ).unwrap()))
-            .unwrap();
-        Ok(response)
-    } else {
-        // only update the verification table
-        // if it's enabled
-        // and the email changed
-        if is_verification_enabled()
-            && !user_email.is_empty()
-            && user_email != user_model.email
+                        version: -1,
+                        msg,
+                        error_code: Some(error_code),
+                    })
+                    .unwrap(),
+                ))
+                .unwrap();
+            return Ok(response);
+        }
+    };
+    config.user_cache.invalidate_user(user_id).await;
+
+    // only update the verification table
+    // if it's enabled
+    // and the email changed
+    if is_verification_enabled()
+        && !user_email.is_empty()
+        && user_email != user_model.email
+    {
+        let user_id = user_model.id;
+        match upsert_user_verification(
+            tracking_label,
+            config,
+            user_id,
+            &user_email,
+            false, // not first time creating the user
+            0,     // if the email changed it's not verified
+            &conn,
+        )
+        .await
         {
-            let user_id = user_model.id;
-            match upsert_user_verification(
-                tracking_label,
-                user_id,
-                &user_email,
-                false, // not first time creating the user
-                0,     // if the email changed it's not verified
-                &conn,
-            )
-            .await
-            {
-                Ok(verification_token) => {
+            Ok(verification_token) => {
+                // only the token hash is stored so the verify
+                // url is only logged for local debugging
+                if std::env::var("DEBUG").unwrap_or_else(|_| "0".to_string())
+                    == *"1"
+                {
                     info!(
                         "{tracking_label} - \
                         verify token updated for user={user_id} \
                         {user_email} verify url: \
                         curl -ks \
                         \"https://{}/user/verify?u={user_id}&t={verification_token}\"",
-                            get_server_address("api"));
-                }
-                Err(e) => {
-                    error!(
+                        get_server_address("api"));
+                } else {
+                    info!(
                         "{tracking_label} - \
-                        failed to generate verify token for user update \
-                        user_id={user_id} \
-                        {user_email} with err='{e}'"
+                        verify token updated for user={user_id} \
+                        {user_email}"
                     );
                 }
+                config
+                    .email_templates
+                    .send_user_email(
+                        tracking_label,
+                        &config.events,
+                        kafka_pool,
+                        user_id,
+                        "verify",
+                        &updated_user.locale,
+                        &user_email,
+                        serde_json::json!({
+                            "link": EmailTemplates::get_link(
+                                &config.email_templates.verify_url,
+                                user_id,
+                                &verification_token
+                            ),
+                        }),
+                    )
+                    .await;
+            }
+            Err(e) => {
+                error!(
+                    "{tracking_label} - \
+                    failed to generate verify token for user update \
+                    user_id={user_id} \
+                    {user_email} with err='{e}'"
+                );
             }
         }
-        // if enabled, publish to kafka
-        if config.kafka_publish_events {
-            publish_msg(
-                kafka_pool,
-                // topic
-                "user.events",
-                // partition key
-                &format!("user-{}", user_id),
-                // optional headers stored in: Option<HashMap<String, String>>
-                None,
-                // payload in the message
-                &format!("USER_UPDATE user={user_id} email={user_email}"),
-            )
-            .await;
-        }
-        let response = Response::builder()
-            .status(200)
-            .body(Body::from(
-                serde_json::to_string(&ApiResUserUpdate {
-                    user_id: row_list[0].0,
-                    email: row_list[0].1.clone(),
-                    state: row_list[0].2,
-                    verified: row_list[0].3,
-                    role: row_list[0].4.clone(),
-                    msg: "success".to_string(),
-                })
-                .unwrap(),
-            ))
-            .unwrap();
-        Ok(response)
     }
+    // notify the user's previous email about credential changes
+    if changes.password_hash.is_some() {
+        send_security_notification(
+            tracking_label,
+            config,
+            kafka_pool,
+            &user_model,
+            "password",
+            "",
+        )
+        .await;
+    }
+    if updated_user.email != user_model.email {
+        send_security_notification(
+            tracking_label,
+            config,
+            kafka_pool,
+            &user_model,
+            "email",
+            &updated_user.email,
+        )
+        .await;
+    }
+    config
+        .events
+        .user_updated(kafka_pool, user_id, &user_email)
+        .await;
+    let response = Response::builder()
+        .status(200)
+        .body(Body::from(
+            serde_json::to_string(&ApiResUserUpdate {
+                user_id: updated_user.id,
+                email: updated_user.email,
+                state: updated_user.state,
+                verified: updated_user.verified,
+                role: updated_user.role,
+                version: updated_user.version,
+                msg: "success".to_string(),
+                error_code: None,
+            })
+            .unwrap(),
+        ))
+        .unwrap();
+    Ok(response)
 }
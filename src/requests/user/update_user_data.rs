@@ -13,6 +13,18 @@
 //! - Request: [`ApiReqUserUpdateData`](crate::requests::user::update_user_data::ApiReqUserUpdateData)
 //! - Response: [`ApiResUserUpdateData`](crate::requests::user::update_user_data::ApiResUserUpdateData)
 //!
+//! ## Patch an existing user data file record with a json merge patch
+//!
+//! Clear ``comments``, ``data_type``, ``encoding``, ``tags`` or
+//! ``folder`` by setting them to ``null``
+//! (see [`merge_patch`](crate::requests::validation::merge_patch))
+//!
+//! - URL path: ``/user/data``
+//! - Method: ``PATCH``
+//! - Handler: [`patch_user_data`](crate::requests::user::update_user_data::patch_user_data)
+//! - Request: [`ApiReqUserUpdateData`](crate::requests::user::update_user_data::ApiReqUserUpdateData)
+//! - Response: [`ApiResUserUpdateData`](crate::requests::user::update_user_data::ApiResUserUpdateData)
+//!
 
 use std::convert::Infallible;
 
@@ -29,12 +41,25 @@ use hyper::Response;
 use serde::Deserialize;
 use serde::Serialize;
 
-use kafka_threadpool::kafka_publisher::KafkaPublisher;
-
 use crate::core::core_config::CoreConfig;
-use crate::kafka::publish_msg::publish_msg;
+use crate::kafka::kafka_publisher::KafkaPublisher;
 use crate::requests::auth::validate_user_token::validate_user_token;
+use crate::requests::models::api_error::ApiError;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::models::user::get_user_by_id;
 use crate::requests::models::user_data::ModelUserData;
+use crate::requests::models::user_data_repo::UserDataChanges;
+use crate::requests::models::user_data_repo::UserDataRepo;
+use crate::requests::validation::field_rules::check_data_folder;
+use crate::requests::validation::field_rules::check_data_tags;
+use crate::requests::validation::field_rules::check_id;
+use crate::requests::validation::field_rules::check_length;
+use crate::requests::validation::field_rules::check_version;
+use crate::requests::validation::merge_patch::parse_merge_patch;
+use crate::requests::validation::validate_api_req::get_validation_errors_response;
+use crate::requests::validation::validate_api_req::validate_api_req;
+use crate::requests::validation::validate_api_req::ApiFieldError;
+use crate::requests::validation::validate_api_req::ApiReqValidate;
 
 /// ApiReqUserUpdateData
 ///
@@ -67,6 +92,14 @@ use crate::requests::models::user_data::ModelUserData;
 ///   `users_data.encoding` field
 /// * `sloc` - `Option<String>` - change the
 ///   `users_data.sloc` field
+/// * `tags` - `Option<Vec<String>>` - replace the
+///   `users_data.tags` (an empty list removes every tag)
+/// * `folder` - `Option<String>` - move the record to a
+///   folder like ``/projects/2022`` (an empty string
+///   removes it from its folder)
+/// * `version` - `Option<i32>` - required `users_data.version`
+///   from the last search or update response (a different
+///   current version is rejected with a ``409``)
 ///
 #[derive(Serialize, Deserialize, Clone)]
 pub struct ApiReqUserUpdateData {
@@ -77,113 +110,72 @@ pub struct ApiReqUserUpdateData {
     pub comments: Option<String>,
     pub encoding: Option<String>,
     pub sloc: Option<String>,
+    pub tags: Option<Vec<String>>,
+    pub folder: Option<String>,
+    pub version: Option<i32>,
 }
 
-/// implementation for wrapping complex sql statement creation
-impl ApiReqUserUpdateData {
-    /// get_sql
+impl ApiReqValidate for ApiReqUserUpdateData {
+    /// validate
     ///
-    /// Build the update sql statement based off the
-    /// object's values
+    /// Require a positive `user_id`, `data_id` and expected
+    /// `version` with optional strings that fit in the
+    /// ``users_data`` columns and valid `tags` and `folder`
+    /// values
     ///
-    pub fn get_sql(&self) -> String {
-        let mut update_value = ("UPDATE \
-                users_data \
-            SET ")
-            .to_string();
-        let mut num_params = 0;
-        match &self.filename {
-            Some(v) => {
-                match num_params {
-                    0 => {
-                        update_value =
-                            format!("{update_value} filename = '{v}'")
-                    }
-                    _ => {
-                        update_value =
-                            format!("{update_value}, filename = '{v}'")
-                    }
-                }
-                num_params += 1;
-                0
-            }
-            None => 1,
-        };
-        match &self.data_type {
-            Some(v) => {
-                match num_params {
-                    0 => {
-                        update_value =
-                            format!("{update_value} data_type = '{v}'")
-                    }
-                    _ => {
-                        update_value =
-                            format!("{update_value}, data_type = '{v}'")
-                    }
-                }
-                num_params += 1;
-                0
-            }
-            None => 1,
-        };
-        match &self.comments {
-            Some(v) => {
-                match num_params {
-                    0 => {
-                        update_value =
-                            format!("{update_value} comments = '{v}'")
-                    }
-                    _ => {
-                        update_value =
-                            format!("{update_value}, comments = '{v}'")
-                    }
-                }
-                num_params += 1;
-                0
+    fn validate(&self) -> Vec<ApiFieldError> {
+        let mut errors = Vec::new();
+        check_id(&mut errors, "user_id", self.user_id);
+        check_id(&mut errors, "data_id", self.data_id);
+        check_version(&mut errors, "version", self.version);
+        let optional_values = [
+            ("filename", &self.filename, 1, 511),
+            ("data_type", &self.data_type, 0, 64),
+            ("comments", &self.comments, 0, 512),
+            ("encoding", &self.encoding, 0, 64),
+            ("sloc", &self.sloc, 0, 1024),
+        ];
+        for (field, value, min_len, max_len) in optional_values.iter() {
+            if let Some(v) = value {
+                check_length(&mut errors, field, v, *min_len, *max_len);
             }
-            None => 1,
-        };
-        match &self.encoding {
-            Some(v) => {
-                match num_params {
-                    0 => {
-                        update_value =
-                            format!("{update_value} encoding = '{v}'")
-                    }
-                    _ => {
-                        update_value =
-                            format!("{update_value}, encoding = '{v}'")
-                    }
-                }
-                num_params += 1;
-                0
+        }
+        if let Some(v) = &self.tags {
+            check_data_tags(&mut errors, "tags", v);
+        }
+        if let Some(v) = &self.folder {
+            if !v.is_empty() {
+                check_data_folder(&mut errors, "folder", v);
             }
-            None => 1,
-        };
-        if false {
-            println!(
-                "ApiReqUserUpdateData \
-                num_params={num_params}"
-            );
         }
-        // info!("ApiReqUserUpdateData query: {cur_query}");
-        format!(
-            "{} \
-                WHERE \
-                    users_data.id = {} \
-                RETURNING \
-                    users_data.id, \
-                    users_data.user_id, \
-                    users_data.filename, \
-                    users_data.data_type, \
-                    users_data.size_in_bytes, \
-                    users_data.comments, \
-                    users_data.encoding, \
-                    users_data.sloc, \
-                    users_data.created_at, \
-                    users_data.updated_at",
-            update_value, self.data_id
-        )
+        errors
+    }
+}
+
+/// implementation for converting the request into
+/// repository changes
+impl ApiReqUserUpdateData {
+    /// get_changes
+    ///
+    /// Build the
+    /// [`UserDataChanges`](crate::requests::models::user_data_repo::UserDataChanges)
+    /// for
+    /// [`UserDataRepo::update`](crate::requests::models::user_data_repo::UserDataRepo::update).
+    /// Only records owned by the user or shared with `write`
+    /// access to the user's id or `role` through the
+    /// `users_data_acl` table are updated.
+    ///
+    pub fn get_changes(&self) -> UserDataChanges {
+        UserDataChanges {
+            filename: self.filename.clone(),
+            data_type: self.data_type.clone(),
+            comments: self.comments.clone(),
+            encoding: self.encoding.clone(),
+            sloc: self.sloc.clone(),
+            tags: self.tags.clone(),
+            folder: self.folder.clone(),
+            expected_version: self.version,
+        }
     }
 }
 
@@ -208,11 +200,15 @@ impl ApiReqUserUpdateData {
 /// * `data` - [`ModelUserData`](crate::requests::models::user_data::ModelUserData) -
 ///   the newly-updated record from the `users_data` db table
 /// * `msg` - `String` - help message
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   machine-readable failure code (omitted on success)
 ///
 #[derive(Serialize, Deserialize, Clone)]
 pub struct ApiResUserUpdateData {
     pub data: ModelUserData,
     pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ApiErrorCode>,
 }
 
 /// update_user_data
@@ -232,7 +228,7 @@ pub struct ApiResUserUpdateData {
 /// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
 ///   db threadpool with required tls encryption
 /// * `kafka_pool` -
-///   [`KafkaPublisher`](kafka_threadpool::kafka_publisher::KafkaPublisher)
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
 ///   for asynchronously publishing messages to the connected kafka cluster
 /// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
 ///   hashmap containing headers in key-value pairs
@@ -293,6 +289,105 @@ pub async fn update_user_data(
                             comments, data_type, encoding \
                             were set correctly in the request")
                             .to_string(),
+                        error_code: Some(ApiErrorCode::InvalidRequest),
+                    })
+                    .unwrap(),
+                ))
+                .unwrap();
+            return Ok(response);
+        }
+    };
+
+    process_user_data_update(
+        tracking_label,
+        config,
+        db_pool,
+        kafka_pool,
+        headers,
+        user_object,
+    )
+    .await
+}
+
+/// patch_user_data
+///
+/// Handles updating a user data record (in the `users_data`
+/// table) with a
+/// [json merge patch](crate::requests::validation::merge_patch)
+/// in the PATCH-ed hyper
+/// [`Request`](hyper::Request)'s [`Body`](hyper::Body)
+///
+/// ## Overview Notes
+///
+/// Missing fields are not changed and fields set to ``null``
+/// are cleared: ``comments``, ``data_type`` and ``encoding``
+/// become empty, ``tags`` are removed and the record is
+/// moved out of its ``folder``. ``filename`` and ``sloc``
+/// cannot be ``null``.
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
+///   db threadpool with required tls encryption
+/// * `kafka_pool` -
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+///   for asynchronously publishing messages to the connected kafka cluster
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+///   [`Request`](hyper::Request)'s [`Body`](hyper::Body)
+/// * `bytes` - `&[u8]` - received bytes from the hyper
+///   [`Request`](hyper::Request)'s [`Body`](hyper::Body)
+///
+/// # Returns
+///
+/// ## patch_user_data on Success Returns
+///
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResUserUpdateData`](crate::requests::user::update_user_data::ApiResUserUpdateData)
+/// dictionary within the
+/// [`Body`](hyper::Body) and a
+/// `200` HTTP status code
+///
+/// Ok([`Response`](hyper::Response))
+///
+/// # Errors
+///
+/// ## patch_user_data on Failure Returns
+///
+/// All errors return as a
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResUserUpdateData`](crate::requests::user::update_user_data::ApiResUserUpdateData)
+/// dictionary with a
+/// `non-200` HTTP status code
+///
+/// Err([`Response`](hyper::Response))
+///
+pub async fn patch_user_data(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    kafka_pool: &KafkaPublisher,
+    headers: &HeaderMap<HeaderValue>,
+    bytes: &[u8],
+) -> std::result::Result<Response<Body>, Infallible> {
+    let patch = match parse_merge_patch::<ApiReqUserUpdateData>(bytes) {
+        Ok(patch) => patch,
+        Err(err_msg) => {
+            let response = Response::builder()
+                .status(400)
+                .body(Body::from(
+                    serde_json::to_string(&ApiResUserUpdateData {
+                        data: ModelUserData::default(),
+                        msg: format!(
+                            "User patch data failed - please ensure \
+                            user_id and data_id are set in a json \
+                            object - {err_msg}"
+                        ),
+                        error_code: Some(ApiErrorCode::InvalidRequest),
                     })
                     .unwrap(),
                 ))
@@ -300,6 +395,52 @@ pub async fn update_user_data(
             return Ok(response);
         }
     };
+    let mut errors = Vec::new();
+    patch.check_not_cleared(&mut errors, &["filename", "sloc"]);
+    if !errors.is_empty() {
+        return Ok(get_validation_errors_response(errors));
+    }
+    let mut user_object = patch.req.clone();
+    for (field, value) in [
+        ("comments", &mut user_object.comments),
+        ("data_type", &mut user_object.data_type),
+        ("encoding", &mut user_object.encoding),
+        ("folder", &mut user_object.folder),
+    ] {
+        if patch.is_cleared(field) {
+            *value = Some("".to_string());
+        }
+    }
+    if patch.is_cleared("tags") {
+        user_object.tags = Some(Vec::new());
+    }
+    process_user_data_update(
+        tracking_label,
+        config,
+        db_pool,
+        kafka_pool,
+        headers,
+        user_object,
+    )
+    .await
+}
+
+/// process_user_data_update
+///
+/// Validate and apply a ``PUT`` or ``PATCH`` update to a
+/// ``users_data`` record
+///
+async fn process_user_data_update(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    kafka_pool: &KafkaPublisher,
+    headers: &HeaderMap<HeaderValue>,
+    user_object: ApiReqUserUpdateData,
+) -> std::result::Result<Response<Body>, Infallible> {
+    if let Some(response) = validate_api_req(tracking_label, &user_object) {
+        return Ok(response);
+    }
     let user_id = user_object.user_id;
     let conn = db_pool.get().await.unwrap();
     let _token = match validate_user_token(
@@ -312,7 +453,7 @@ pub async fn update_user_data(
     .await
     {
         Ok(_token) => _token,
-        Err(_) => {
+        Err(error_code) => {
             let response = Response::builder()
                 .status(400)
                 .body(Body::from(
@@ -320,6 +461,7 @@ pub async fn update_user_data(
                         data: ModelUserData::default(),
                         msg: ("User update data failed due to invalid token")
                             .to_string(),
+                        error_code: Some(error_code),
                     })
                     .unwrap(),
                 ))
@@ -328,21 +470,69 @@ pub async fn update_user_data(
         }
     };
 
-    let cur_query = user_object.get_sql();
-    let stmt = conn.prepare(&cur_query).await.unwrap();
-    let query_result = match conn.query(&stmt, &[]).await {
-        Ok(query_result) => query_result,
+    let user_model = match get_user_by_id(tracking_label, user_id, &conn).await
+    {
+        Ok(user_model) => user_model,
+        Err(err_msg) => {
+            error!(
+                "{tracking_label} - \
+                failed to find user {user_id} for data update \
+                with err='{err_msg}'"
+            );
+            let response = Response::builder()
+                .status(400)
+                .body(Body::from(
+                    serde_json::to_string(&ApiResUserUpdateData {
+                        data: ModelUserData::default(),
+                        msg: format!(
+                            "User update data failed - \
+                            unable to find user with id: {user_id}"
+                        ),
+                        error_code: Some(ApiErrorCode::UserNotFound),
+                    })
+                    .unwrap(),
+                ))
+                .unwrap();
+            return Ok(response);
+        }
+    };
+
+    let updated_data = match UserDataRepo::new(&conn)
+        .update(
+            tracking_label,
+            user_id,
+            &user_model.role,
+            user_object.data_id,
+            &user_object.get_changes(),
+        )
+        .await
+    {
+        Ok(updated_data) => updated_data,
+        Err(ApiError::NotFound(_)) => {
+            let response = Response::builder()
+                .status(200)
+                .body(Body::from(
+                    serde_json::to_string(&ApiResUserUpdateData {
+                        data: ModelUserData::default(),
+                        msg: "no update data found".to_string(),
+                        error_code: None,
+                    })
+                    .unwrap(),
+                ))
+                .unwrap();
+            return Ok(response);
+        }
         Err(e) => {
-            let err_msg = format!("{e}");
             let response = Response::builder()
-                .status(500)
+                .status(e.status_code())
                 .body(Body::from(
                     serde_json::to_string(&ApiResUserUpdateData {
                         data: ModelUserData::default(),
                         msg: format!(
                             "User update data failed for user_id={user_id} \
-                                with err='{err_msg}'"
+                                with err='{e}'"
                         ),
+                        error_code: Some(e.error_code()),
                     })
                     .unwrap(),
                 ))
@@ -350,81 +540,21 @@ pub async fn update_user_data(
             return Ok(response);
         }
     };
-    let mut row_list: Vec<ModelUserData> = Vec::with_capacity(1);
-    for row in query_result.iter() {
-        let found_data_id: i32 = row.try_get("id").unwrap();
-        let found_user_id: i32 = row.try_get("user_id").unwrap();
-        let found_filename: String = row.try_get("filename").unwrap();
-        let found_data_type: String = row.try_get("data_type").unwrap();
-        let found_size_in_bytes: i64 = row.try_get("size_in_bytes").unwrap();
-        let found_comments: String = row.try_get("comments").unwrap();
-        let found_encoding: String = row.try_get("encoding").unwrap();
-        let found_sloc: String = row.try_get("sloc").unwrap();
-        let created_at_utc: chrono::DateTime<chrono::Utc> =
-            row.try_get("created_at").unwrap();
-        let updated_at_str: String = match row.try_get("updated_at") {
-            Ok(v) => {
-                let updated_at_utc: chrono::DateTime<chrono::Utc> = v;
-                format!("{}", updated_at_utc.format("%Y-%m-%dT%H:%M:%SZ"))
-            }
-            Err(_) => "".to_string(),
-        };
-        row_list.push(ModelUserData {
-            user_id: found_user_id,
-            data_id: found_data_id,
-            filename: found_filename,
-            data_type: found_data_type,
-            size_in_bytes: found_size_in_bytes,
-            comments: found_comments,
-            encoding: found_encoding,
-            sloc: found_sloc,
-            created_at: format!(
-                "{}",
-                created_at_utc.format("%Y-%m-%dT%H:%M:%SZ")
-            ),
-            updated_at: updated_at_str,
-            msg: "success".to_string(),
-        });
-    }
-    if row_list.is_empty() {
-        let response = Response::builder()
-            .status(200)
-            .body(Body::from(
-                serde_json::to_string(&ApiResUserUpdateData {
-                    data: ModelUserData::default(),
-                    msg: "no update data found".to_string(),
-                })
-                .unwrap(),
-            ))
-            .unwrap();
-        Ok(response)
-    } else {
-        // if enabled, publish to kafka
-        if config.kafka_publish_events {
-            publish_msg(
-                kafka_pool,
-                // topic
-                "user.events",
-                // partition key
-                &format!("user-{}", user_id),
-                // optional headers stored in: Option<HashMap<String, String>>
-                None,
-                // payload in the message
-                &format!("USER_UPDATE_DATA user={user_id}"),
-            )
-            .await;
-        }
+    config
+        .events
+        .publish_user_event(kafka_pool, user_id, "USER_UPDATE_DATA", "")
+        .await;
 
-        let response = Response::builder()
-            .status(200)
-            .body(Body::from(
-                serde_json::to_string(&ApiResUserUpdateData {
-                    data: row_list.remove(0),
-                    msg: "success".to_string(),
-                })
-                .unwrap(),
-            ))
-            .unwrap();
-        Ok(response)
-    }
+    let response = Response::builder()
+        .status(200)
+        .body(Body::from(
+            serde_json::to_string(&ApiResUserUpdateData {
+                data: updated_data,
+                msg: "success".to_string(),
+                error_code: None,
+            })
+            .unwrap(),
+        ))
+        .unwrap();
+    Ok(response)
 }
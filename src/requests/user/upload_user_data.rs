@@ -7,6 +7,10 @@
 //! tracking record in the ``users_data`` table. The documentation
 //! refers to this as a ``user data`` or ``user data file`` record.
 //!
+//! The file metadata can be sent in custom headers with the file as the
+//! raw body, or as a ``multipart/form-data`` body with a json
+//! ``metadata`` part and a ``file`` part.
+//!
 //! - URL path: ``/user/data``
 //! - Method: ``POST``
 //! - Handler: [`upload_user_data`](crate::requests::user::upload_user_data::upload_user_data)
@@ -21,7 +25,7 @@ use postgres_native_tls::MakeTlsConnector;
 use bb8::Pool;
 use bb8_postgres::PostgresConnectionManager;
 
-use hyper::body;
+use hyper::body::Bytes;
 use hyper::header::HeaderValue;
 use hyper::Body;
 use hyper::HeaderMap;
@@ -30,12 +34,23 @@ use hyper::Response;
 use serde::Deserialize;
 use serde::Serialize;
 
-use kafka_threadpool::kafka_publisher::KafkaPublisher;
+use sha2::Digest;
+use sha2::Sha256;
 
 use crate::core::core_config::CoreConfig;
-use crate::is3::s3_upload_buffer::s3_upload_buffer;
-use crate::kafka::publish_msg::publish_msg;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::monitoring::otel::trace_client_span;
 use crate::requests::auth::validate_user_token::validate_user_token;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::models::user_data_conflict::get_user_data_conflict_policy;
+use crate::requests::models::user_data_repo::NewUserData;
+use crate::requests::models::user_data_repo::UserDataRepo;
+use crate::requests::user::get_upload_metadata::get_upload_metadata_from_headers;
+use crate::requests::user::get_upload_metadata::get_upload_metadata_from_multipart;
+use crate::requests::user::get_upload_metadata::is_multipart_upload;
+use crate::requests::user::read_upload_body::read_upload_body;
+use crate::requests::user::read_upload_body::validate_upload_content_length;
+use crate::utils::circuit_breaker::is_circuit_open_error;
 use crate::utils::get_uuid::get_uuid;
 
 /// ApiReqUserUploadData
@@ -96,7 +111,40 @@ pub struct ApiReqUserUploadData {
 /// * `comments` - `String` - notes or description
 /// * `encoding` - `String` - encoding
 /// * `sloc` - `String` - remote s3 location
+/// * `status` - `String` - ``pending`` until the
+///   [`UserDataPipeline`](crate::processing::user_data_pipeline::UserDataPipeline)
+///   processes the file or ``ready`` if the pipeline is disabled
+/// * `storage_class` - `String` - s3 storage class (empty =
+///   the server's ``S3_STORAGE_CLASS``)
+/// * `expires_at` - `String` - time the
+///   [`UserDataLifecycle`](crate::archive::user_data_lifecycle::UserDataLifecycle)
+///   deletes or transitions the s3 object (empty = never)
+/// * `checksum` - `String` - hex sha256 of the file contents
+/// * `deduplicated` - `bool` - ``true`` if the user already
+///   uploaded the same contents and the new record reuses
+///   that record's `sloc` instead of uploading to s3 again
+/// * `scan_status` - `String` - upload scanner result
+///   (``clean``, ``infected`` or ``error``, empty = not
+///   scanned)
+/// * `scan_signature` - `String` - signature found in an
+///   ``infected`` (``quarantined``) file
+/// * `content_type` - `String` - file content type (empty =
+///   unknown)
+/// * `derivatives_status` - `String` - ``pending`` until the
+///   [`UserDataThumbnails`](crate::processing::user_data_thumbnails::UserDataThumbnails)
+///   task creates thumbnails for an image upload (empty = no
+///   thumbnails)
+/// * `tags` - `Vec<String>` - user-defined tags
+/// * `folder` - `String` - folder path (empty = not in a
+///   folder)
+/// * `file_version` - `i32` - version of the file in the
+///   folder (``on_conflict: overwrite`` uploads create the
+///   next version)
+/// * `version_of` - `i32` - `data_id` of the file's first
+///   version (``0`` = this is the first version)
 /// * `msg` - `String` - help message
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   machine-readable failure code (omitted on success)
 ///
 #[derive(Serialize, Deserialize, Clone)]
 pub struct ApiResUserUploadData {
@@ -110,7 +158,84 @@ pub struct ApiResUserUploadData {
     pub comments: String,
     pub encoding: String,
     pub sloc: String,
+    pub status: String,
+    pub storage_class: String,
+    pub expires_at: String,
+    pub checksum: String,
+    pub deduplicated: bool,
+    pub scan_status: String,
+    pub scan_signature: String,
+    pub content_type: String,
+    pub derivatives_status: String,
+    pub tags: Vec<String>,
+    pub folder: String,
+    pub file_version: i32,
+    pub version_of: i32,
     pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ApiErrorCode>,
+}
+
+/// get_upload_unavailable_response
+///
+/// Build the ``503`` response for uploads rejected while the
+/// s3 circuit breaker is open
+///
+/// # Arguments
+///
+/// * `user_id` - `i32` - uploading user
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+///
+/// # Returns
+///
+/// [`Response`](hyper::Response) with a ``Retry-After``
+/// header and a json-serialized
+/// [`ApiResUserUploadData`](crate::requests::user::upload_user_data::ApiResUserUploadData)
+///
+fn get_upload_unavailable_response(
+    user_id: i32,
+    config: &CoreConfig,
+) -> Response<Body> {
+    Response::builder()
+        .status(503)
+        .header(
+            "Retry-After",
+            format!(
+                "{}",
+                config.s3_circuit_breaker.get_retry_after_seconds().max(1)
+            ),
+        )
+        .body(Body::from(
+            serde_json::to_string(&ApiResUserUploadData {
+                user_id,
+                data_id: -1,
+                filename: "".to_string(),
+                data_type: "".to_string(),
+                size_in_bytes: 0,
+                comments: "".to_string(),
+                encoding: "".to_string(),
+                sloc: "".to_string(),
+                status: "".to_string(),
+                storage_class: "".to_string(),
+                expires_at: "".to_string(),
+                checksum: "".to_string(),
+                deduplicated: false,
+                scan_status: "".to_string(),
+                scan_signature: "".to_string(),
+                content_type: "".to_string(),
+                derivatives_status: "".to_string(),
+                tags: Vec::new(),
+                folder: "".to_string(),
+                file_version: 0,
+                version_of: 0,
+                msg: "User data upload failed - file storage is \
+                    unavailable, please retry later"
+                    .to_string(),
+                error_code: Some(ApiErrorCode::ServiceUnavailable),
+            })
+            .unwrap(),
+        ))
+        .unwrap()
 }
 
 /// upload_user_data
@@ -134,11 +259,117 @@ pub struct ApiResUserUploadData {
 /// export S3_DATA_PREFIX="user/data/file"
 /// ```
 ///
+/// ### Disable reusing the s3 object of identical uploads
+///
+/// ```bash
+/// export S3_DATA_DEDUPE="0"
+/// ```
+///
+/// ### Change the default filename collision policy
+///
+/// ```bash
+/// export USER_DATA_FILENAME_CONFLICT="allow"
+/// ```
+///
+/// ### Change the max combined size of all upload headers
+///
+/// ```bash
+/// export UPLOAD_MAX_HEADER_BYTES=8192
+/// ```
+///
+/// ## Header Validation
+///
+/// The metadata headers (`user_id`, `filename`, `data_type`,
+/// `encoding`, `comments`, `sloc` and `s3_enable`) must be
+/// printable ascii (`400` otherwise) and within their max length
+/// (`431` otherwise). Uploads with headers larger than
+/// `UPLOAD_MAX_HEADER_BYTES` are also rejected with a `431`.
+///
+/// ## Upload Size Limit
+///
+/// Uploads larger than the ``max_upload_size_bytes``
+/// runtime setting (see
+/// [`RuntimeSettings`](crate::settings::runtime_settings::RuntimeSettings))
+/// are rejected with a `413`.
+///
+/// ## Upload Formats
+///
+/// - raw body - the file contents are the POST-ed body and the
+///   metadata is passed in custom headers
+///   (`user_id`, `filename`, `data_type`, `encoding`,
+///   `comments`, `sloc` and `s3_enable`)
+/// - `multipart/form-data` - a json `metadata` part
+///   ([`ApiReqUserUploadMetadata`](crate::requests::user::get_upload_metadata::ApiReqUserUploadMetadata))
+///   and a `file` part with the file contents
+///
 /// The file contents must be passed in the `data` field of the
 /// [`ApiReqUserUploadData`](crate::requests::user::upload_user_data::ApiReqUserUploadData)
 /// type which is serialized within a POST-ed hyper
 /// [`Request`](hyper::Request)'s [`Body`](hyper::Body)
 ///
+/// ## Upload Dedupe
+///
+/// The sha256 checksum of the contents is stored in
+/// `users_data.checksum`. If the user already has a
+/// `pending`, `scanning` or `ready` record with the same
+/// checksum, the new record reuses its `sloc`, the s3 upload
+/// is skipped and the response has `deduplicated: true`.
+/// Uploads with a client `sloc`, `storage_class` or
+/// `expire_days` are always uploaded.
+///
+/// ## Filename Collisions
+///
+/// The `on_conflict` header (or metadata field) picks what
+/// happens when the user's folder already has a current
+/// record with the `filename` (see
+/// [`user_data_conflict`](crate::requests::models::user_data_conflict)):
+/// `allow` another record, `reject` with a `409`, store the
+/// upload with a `suffix` like ``report-v2.txt``, or
+/// `overwrite` the file with its next `file_version` (the
+/// replaced record is kept as an older version).
+///
+/// ## Storage Quota
+///
+/// Uploads that do not fit in the user's
+/// [`UserDataQuota`](crate::requests::user::user_data_quota::UserDataQuota)
+/// are rejected with a `413` (larger than the whole quota)
+/// or a `507` (larger than the remaining quota).
+///
+/// ## Storage Class and Lifecycle
+///
+/// The optional `storage_class` header (or metadata field)
+/// stores the s3 object in one of the
+/// [`S3_STORAGE_CLASSES`](crate::is3::object_store::S3_STORAGE_CLASSES)
+/// instead of the ``S3_STORAGE_CLASS``. With `expire_days`
+/// the
+/// [`UserDataLifecycle`](crate::archive::user_data_lifecycle::UserDataLifecycle)
+/// deletes the s3 object after that many days and marks the
+/// record ``expired``, or moves the object to the
+/// `expire_storage_class` if one is set.
+///
+/// ## Virus Scanning
+///
+/// With an
+/// [`UploadScan`](crate::processing::upload_scanner::UploadScan)
+/// scanner the contents are scanned before the s3 upload.
+/// Infected files are rejected with a `422` or stored as a
+/// ``quarantined`` record (``UPLOAD_SCAN_ACTION=quarantine``)
+/// that cannot be downloaded. Uploads the scanner could not
+/// scan are rejected with a `503` unless
+/// ``UPLOAD_SCAN_ON_ERROR=allow``.
+///
+/// ## Image Thumbnails
+///
+/// Uploads with one of the
+/// [`THUMBNAIL_CONTENT_TYPES`](crate::requests::models::user_data_derivative::THUMBNAIL_CONTENT_TYPES)
+/// (from the ``Content-Type`` header or the multipart
+/// ``file`` part) are created with a ``pending``
+/// `derivatives_status` when ``USERS_DATA_THUMBNAILS_ENABLED=1``
+/// for the
+/// [`UserDataThumbnails`](crate::processing::user_data_thumbnails::UserDataThumbnails)
+/// task. The thumbnails are returned in the `derivatives`
+/// of a data search.
+///
 /// ## Overview Notes
 ///
 /// This function only creates 1 `users_data` record at a time.
@@ -153,7 +384,7 @@ pub struct ApiResUserUploadData {
 /// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
 ///   db threadpool with required tls encryption
 /// * `kafka_pool` -
-///   [`KafkaPublisher`](kafka_threadpool::kafka_publisher::KafkaPublisher)
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
 ///   for asynchronously publishing messages to the connected kafka cluster
 /// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
 ///   hashmap containing headers in key-value pairs
@@ -188,7 +419,12 @@ pub struct ApiResUserUploadData {
 /// containing a json-serialized
 /// [`ApiResUserUploadData`](crate::requests::user::upload_user_data::ApiResUserUploadData)
 /// dictionary with a
-/// `non-200` HTTP status code
+/// `non-200` HTTP status code (``429`` with a ``Retry-After``
+/// header when the user already has
+/// ``USER_DATA_MAX_CONCURRENT_UPLOADS`` uploads in flight,
+/// see [`UserUploadLimit`](crate::requests::user::user_upload_limit::UserUploadLimit),
+/// and ``503`` with a ``Retry-After`` header while the s3
+/// circuit breaker is open)
 ///
 /// Err([`Response`](hyper::Response))
 ///
@@ -200,12 +436,38 @@ pub async fn upload_user_data(
     headers: &HeaderMap<HeaderValue>,
     body: hyper::Body,
 ) -> std::result::Result<Response<Body>, Infallible> {
-    if !headers.contains_key("user_id") {
-        let response = Response::builder()
-            .status(400)
-            .body(Body::from(
-                serde_json::to_string(
-                    &ApiResUserUploadData {
+    let max_upload_size_bytes = config.get_settings().max_upload_size_bytes;
+    // read the metadata from a multipart/form-data body or
+    // from the custom headers for raw body uploads
+    let mut raw_body: Option<hyper::Body> = None;
+    let upload_metadata =
+        match validate_upload_content_length(headers, max_upload_size_bytes) {
+            Err(e) => Err(e),
+            Ok(_) => match is_multipart_upload(headers) {
+                true => get_upload_metadata_from_multipart(
+                    headers,
+                    body,
+                    max_upload_size_bytes,
+                )
+                .await
+                .map(|(metadata, file_contents)| {
+                    (metadata, Some(file_contents))
+                }),
+                false => {
+                    raw_body = Some(body);
+                    get_upload_metadata_from_headers(headers)
+                        .map(|metadata| (metadata, None))
+                }
+            },
+        };
+    let (metadata, multipart_bytes) = match upload_metadata {
+        Ok(upload_metadata) => upload_metadata,
+        Err((status, err_msg)) => {
+            info!("{tracking_label} - rejecting upload with err='{err_msg}'");
+            let response = Response::builder()
+                .status(status)
+                .body(Body::from(
+                    serde_json::to_string(&ApiResUserUploadData {
                         user_id: -1,
                         data_id: -1,
                         filename: "".to_string(),
@@ -214,107 +476,46 @@ pub async fn upload_user_data(
                         comments: "".to_string(),
                         encoding: "".to_string(),
                         sloc: "".to_string(),
-                        msg: (
-                            "Missing required header 'user_id' key (i.e. curl -H 'user_id: INT'"
-                        ).to_string(),
-                    }
-                ).unwrap()))
-            .unwrap();
-        return Ok(response);
-    }
-    let user_id_str = headers.get("user_id").unwrap().to_str().unwrap();
-    let user_id: i32 = match user_id_str.parse::<i32>() {
-        Ok(user_id) => user_id,
-        Err(_) => {
-            let response = Response::builder()
-                .status(400)
-                .body(Body::from(
-                    serde_json::to_string(
-                        &ApiResUserUploadData {
-                            user_id: -1,
-                            data_id: -1,
-                            filename: "".to_string(),
-                            data_type: "".to_string(),
-                            size_in_bytes: 0,
-                            comments: "".to_string(),
-                            encoding: "".to_string(),
-                            sloc: "".to_string(),
-                            msg: (
-                                "user_id must be a postive number that is the actual user_id for the token"
-                            ).to_string(),
-                        }
-                    ).unwrap()))
+                        status: "".to_string(),
+                        storage_class: "".to_string(),
+                        expires_at: "".to_string(),
+                        checksum: "".to_string(),
+                        deduplicated: false,
+                        scan_status: "".to_string(),
+                        scan_signature: "".to_string(),
+                        content_type: "".to_string(),
+                        derivatives_status: "".to_string(),
+                        tags: Vec::new(),
+                        folder: "".to_string(),
+                        file_version: 0,
+                        version_of: 0,
+                        msg: err_msg,
+                        error_code: Some(ApiErrorCode::from_status(status)),
+                    })
+                    .unwrap(),
+                ))
                 .unwrap();
             return Ok(response);
         }
     };
-    if !headers.contains_key("filename") {
-        let response = Response::builder()
-            .status(400)
-            .body(Body::from(
-                serde_json::to_string(
-                    &ApiResUserUploadData {
-                        user_id: -1,
-                        data_id: -1,
-                        filename: "".to_string(),
-                        data_type: "".to_string(),
-                        size_in_bytes: 0,
-                        comments: "".to_string(),
-                        encoding: "".to_string(),
-                        sloc: "".to_string(),
-                        msg: (
-                            "Missing required header 'filename' key (i.e. curl -H 'user_id: INT'"
-                        ).to_string(),
-                    }
-                ).unwrap()))
-            .unwrap();
-        return Ok(response);
-    }
-    let file_name_str = headers.get("filename").unwrap().to_str().unwrap();
-    let file_name_len = file_name_str.len();
-
-    // between 1 and 511 chars
-    if !(1..=511).contains(&file_name_len) {
-        let response = Response::builder()
-            .status(400)
-            .body(Body::from(
-                serde_json::to_string(
-                    &ApiResUserUploadData {
-                        user_id: -1,
-                        data_id: -1,
-                        filename: "".to_string(),
-                        data_type: "".to_string(),
-                        size_in_bytes: 0,
-                        comments: "".to_string(),
-                        encoding: "".to_string(),
-                        sloc: "".to_string(),
-                        msg: (
-                            "The header value for 'filename' must be between 1 and 511 characters"
-                        ).to_string(),
-                    }
-                ).unwrap()))
-            .unwrap();
-        return Ok(response);
-    }
+    let user_id = metadata.user_id;
+    let file_name_str = metadata.filename.as_str();
     // -H 'filename: testfile.txt' -H 'data_type: file' -H 'encoding: na' -H 'comments: this is a test comment' -H 'sloc: s3://bucket/prefix'
-    let encoding = match headers.get("encoding") {
-        Some(v) => v.to_str().unwrap().to_string(),
-        None => "na".to_string(),
-    };
-    let comments = match headers.get("comments") {
-        Some(v) => v.to_str().unwrap().to_string(),
-        None => "file".to_string(),
-    };
-    let data_type = match headers.get("data_type") {
-        Some(v) => v.to_str().unwrap().to_string(),
-        None => "file".to_string(),
-    };
-    let sloc_start = match headers.get("sloc") {
-        Some(v) => v.to_str().unwrap().to_string(),
-        None => "".to_string(),
-    };
-    let should_upload_to_s3 = match headers.get("s3_enable") {
-        Some(_) => true,
+    let encoding = metadata
+        .encoding
+        .clone()
+        .unwrap_or_else(|| "na".to_string());
+    let comments = metadata
+        .comments
+        .clone()
+        .unwrap_or_else(|| "file".to_string());
+    let data_type = metadata
+        .data_type
+        .clone()
+        .unwrap_or_else(|| "file".to_string());
+    let sloc_start = metadata.sloc.clone().unwrap_or_default();
+    let should_upload_to_s3 = match metadata.s3_enable {
+        Some(s3_enable) => s3_enable,
         None => {
             std::env::var("S3_DATA_UPLOAD_TO_S3")
                 .unwrap_or_else(|_| "0".to_string())
@@ -354,7 +555,7 @@ pub async fn upload_user_data(
         .await
         {
             Ok(_token) => _token,
-            Err(_) => {
+            Err(error_code) => {
                 let response = Response::builder()
                     .status(400)
                     .body(Body::from(
@@ -368,9 +569,23 @@ pub async fn upload_user_data(
                                 comments: "".to_string(),
                                 encoding: "".to_string(),
                                 sloc: "".to_string(),
+                                status: "".to_string(),
+                                storage_class: "".to_string(),
+                                expires_at: "".to_string(),
+                                checksum: "".to_string(),
+                                deduplicated: false,
+                                scan_status: "".to_string(),
+                                scan_signature: "".to_string(),
+                                content_type: "".to_string(),
+                                derivatives_status: "".to_string(),
+                                tags: Vec::new(),
+                                folder: "".to_string(),
+                                file_version: 0,
+                                version_of: 0,
                                 msg: ("
                                     User data upload failed due to invalid token"
                                 ).to_string(),
+                                error_code: Some(error_code),
                             }
                         ).unwrap()))
                 .unwrap();
@@ -379,8 +594,166 @@ pub async fn upload_user_data(
         };
     }
 
+    // reject a taken filename before reading the body (the
+    // insert checks it again under a lock)
+    let conflict_policy = get_user_data_conflict_policy(&metadata.on_conflict);
+    if conflict_policy == "reject" {
+        let conn = db_pool.get().await.unwrap();
+        if let Ok(Some(current)) = UserDataRepo::new(&conn)
+            .find_current_by_filename(
+                tracking_label,
+                user_id,
+                &metadata.folder,
+                file_name_str,
+            )
+            .await
+        {
+            info!(
+                "{tracking_label} - rejecting upload for user_id={user_id} \
+                name={file_name_str} - the folder already has the \
+                filename in data_id={}",
+                current.data_id
+            );
+            let response = Response::builder()
+                .status(409)
+                .body(Body::from(
+                    serde_json::to_string(&ApiResUserUploadData {
+                        user_id,
+                        data_id: current.data_id,
+                        filename: current.filename,
+                        data_type: "".to_string(),
+                        size_in_bytes: 0,
+                        comments: "".to_string(),
+                        encoding: "".to_string(),
+                        sloc: "".to_string(),
+                        status: "".to_string(),
+                        storage_class: "".to_string(),
+                        expires_at: "".to_string(),
+                        checksum: "".to_string(),
+                        deduplicated: false,
+                        scan_status: "".to_string(),
+                        scan_signature: "".to_string(),
+                        content_type: "".to_string(),
+                        derivatives_status: "".to_string(),
+                        tags: Vec::new(),
+                        folder: current.folder,
+                        file_version: current.file_version,
+                        version_of: current.version_of,
+                        msg: "User data upload rejected - a file with \
+                            this filename already exists in the folder"
+                            .to_string(),
+                        error_code: Some(ApiErrorCode::Conflict),
+                    })
+                    .unwrap(),
+                ))
+                .unwrap();
+            return Ok(response);
+        }
+    }
+
+    // fail fast before reading the body while s3 keeps failing
+    if should_upload_to_s3 && config.s3_circuit_breaker.is_open() {
+        info!(
+            "{tracking_label} - rejecting upload for user_id={user_id} \
+            - s3 circuit breaker is open"
+        );
+        return Ok(get_upload_unavailable_response(user_id, config));
+    }
+
+    // hold one of the user's upload permits until the upload is done
+    let _upload_permit = match config.user_upload_limit.try_acquire(user_id) {
+        Ok(upload_permit) => upload_permit,
+        Err((status, err_msg)) => {
+            info!("{tracking_label} - rejecting upload with err='{err_msg}'");
+            let response = Response::builder()
+                .status(status)
+                .header(
+                    "Retry-After",
+                    format!("{}", config.user_upload_limit.retry_after_seconds),
+                )
+                .body(Body::from(
+                    serde_json::to_string(&ApiResUserUploadData {
+                        user_id,
+                        data_id: -1,
+                        filename: "".to_string(),
+                        data_type: "".to_string(),
+                        size_in_bytes: 0,
+                        comments: "".to_string(),
+                        encoding: "".to_string(),
+                        sloc: "".to_string(),
+                        status: "".to_string(),
+                        storage_class: "".to_string(),
+                        expires_at: "".to_string(),
+                        checksum: "".to_string(),
+                        deduplicated: false,
+                        scan_status: "".to_string(),
+                        scan_signature: "".to_string(),
+                        content_type: "".to_string(),
+                        derivatives_status: "".to_string(),
+                        tags: Vec::new(),
+                        folder: "".to_string(),
+                        file_version: 0,
+                        version_of: 0,
+                        msg: err_msg,
+                        error_code: Some(ApiErrorCode::from_status(status)),
+                    })
+                    .unwrap(),
+                ))
+                .unwrap();
+            return Ok(response);
+        }
+    };
+
     info!("{tracking_label} - receiving user_id={user_id} name={file_name_str} data");
-    let bytes = body::to_bytes(body).await.unwrap();
+    let bytes = match (multipart_bytes, raw_body) {
+        (Some(file_contents), _) => file_contents,
+        (None, Some(raw_body)) => {
+            match read_upload_body(raw_body, max_upload_size_bytes).await {
+                Ok(bytes) => bytes,
+                Err((status, err_msg)) => {
+                    info!(
+                        "{tracking_label} - \
+                        rejecting upload with err='{err_msg}'"
+                    );
+                    let response = Response::builder()
+                        .status(status)
+                        .body(Body::from(
+                            serde_json::to_string(&ApiResUserUploadData {
+                                user_id: -1,
+                                data_id: -1,
+                                filename: "".to_string(),
+                                data_type: "".to_string(),
+                                size_in_bytes: 0,
+                                comments: "".to_string(),
+                                encoding: "".to_string(),
+                                sloc: "".to_string(),
+                                status: "".to_string(),
+                                storage_class: "".to_string(),
+                                expires_at: "".to_string(),
+                                checksum: "".to_string(),
+                                deduplicated: false,
+                                scan_status: "".to_string(),
+                                scan_signature: "".to_string(),
+                                content_type: "".to_string(),
+                                derivatives_status: "".to_string(),
+                                tags: Vec::new(),
+                                folder: "".to_string(),
+                                file_version: 0,
+                                version_of: 0,
+                                msg: err_msg,
+                                error_code: Some(ApiErrorCode::from_status(
+                                    status,
+                                )),
+                            })
+                            .unwrap(),
+                        ))
+                        .unwrap();
+                    return Ok(response);
+                }
+            }
+        }
+        (None, None) => Bytes::new(),
+    };
     let file_contents_size: usize = bytes.len() as usize;
     if file_contents_size < 1 {
         let response = Response::builder()
@@ -395,7 +768,21 @@ pub async fn upload_user_data(
                     comments: "".to_string(),
                     encoding: "".to_string(),
                     sloc: "".to_string(),
+                    status: "".to_string(),
+                    storage_class: "".to_string(),
+                    expires_at: "".to_string(),
+                    checksum: "".to_string(),
+                    deduplicated: false,
+                    scan_status: "".to_string(),
+                    scan_signature: "".to_string(),
+                    content_type: "".to_string(),
+                    derivatives_status: "".to_string(),
+                    tags: Vec::new(),
+                    folder: "".to_string(),
+                    file_version: 0,
+                    version_of: 0,
                     msg: ("No data uploaded in the body").to_string(),
+                    error_code: Some(ApiErrorCode::InvalidRequest),
                 })
                 .unwrap(),
             ))
@@ -403,6 +790,54 @@ pub async fn upload_user_data(
         return Ok(response);
     }
 
+    {
+        let conn = db_pool.get().await.unwrap();
+        if let Err((status, err_msg)) = config
+            .user_data_quota
+            .check_upload(
+                tracking_label,
+                user_id,
+                file_contents_size as i64,
+                &conn,
+            )
+            .await
+        {
+            info!("{tracking_label} - rejecting upload with err='{err_msg}'");
+            let response = Response::builder()
+                .status(status)
+                .body(Body::from(
+                    serde_json::to_string(&ApiResUserUploadData {
+                        user_id,
+                        data_id: -1,
+                        filename: "".to_string(),
+                        data_type: "".to_string(),
+                        size_in_bytes: file_contents_size as i64,
+                        comments: "".to_string(),
+                        encoding: "".to_string(),
+                        sloc: "".to_string(),
+                        status: "".to_string(),
+                        storage_class: "".to_string(),
+                        expires_at: "".to_string(),
+                        checksum: "".to_string(),
+                        deduplicated: false,
+                        scan_status: "".to_string(),
+                        scan_signature: "".to_string(),
+                        content_type: "".to_string(),
+                        derivatives_status: "".to_string(),
+                        tags: Vec::new(),
+                        folder: "".to_string(),
+                        file_version: 0,
+                        version_of: 0,
+                        msg: err_msg,
+                        error_code: Some(ApiErrorCode::from_status(status)),
+                    })
+                    .unwrap(),
+                ))
+                .unwrap();
+            return Ok(response);
+        }
+    }
+
     let file_contents_size_in_mb: f32 =
         file_contents_size as f32 / 1024.0 / 1024.0;
 
@@ -414,57 +849,19 @@ pub async fn upload_user_data(
         {sloc}"
     );
 
-    if should_upload_to_s3 {
-        match s3_upload_buffer(tracking_label, &s3_bucket, &s3_key_dst, &bytes)
-            .await
-        {
-            Ok(good_msg) => {
-                info!("{good_msg} - done uploading - {sloc}")
-            }
-            Err(emsg) => {
-                info!("{emsg} - failed uploading {sloc}")
-            }
-        }
-    } else {
-        info!("{tracking_label} - not uploading to s3");
-    }
-
-    let conn = db_pool.get().await.unwrap();
-    let cur_query = format!(
-        "INSERT INTO \
-        users_data (\
-            user_id, \
-            filename, \
-            data_type, \
-            size_in_bytes, \
-            comments, \
-            encoding, \
-            sloc) \
-        VALUES (\
-            {user_id},
-            '{file_name_str}',
-            '{data_type}',
-            {file_contents_size},
-            '{comments}',
-            '{encoding}',
-            '{sloc}') \
-        RETURNING \
-            users_data.id,
-            users_data.user_id,
-            users_data.filename,
-            users_data.data_type,
-            users_data.size_in_bytes,
-            users_data.comments,
-            users_data.encoding,
-            users_data.sloc;"
-    );
-    let stmt = conn.prepare(&cur_query).await.unwrap();
-    let query_result = match conn.query(&stmt, &[]).await {
-        Ok(query_result) => query_result,
-        Err(e) => {
-            let err_msg = format!("{}", e);
+    let scan_result = match config
+        .upload_scan
+        .scan_upload(tracking_label, file_name_str, &bytes)
+        .await
+    {
+        Ok(scan_result) => scan_result,
+        Err(err_msg) => {
+            error!(
+                "{tracking_label} - rejecting upload for user_id={user_id} \
+                name={file_name_str} - scan failed with err='{err_msg}'"
+            );
             let response = Response::builder()
-                .status(500)
+                .status(503)
                 .body(Body::from(
                     serde_json::to_string(&ApiResUserUploadData {
                         user_id: -1,
@@ -475,10 +872,23 @@ pub async fn upload_user_data(
                         comments: "".to_string(),
                         encoding: "".to_string(),
                         sloc: "".to_string(),
-                        msg: format!(
-                            "User data upload failed for user_id={user_id} \
-                                with err='{err_msg}'"
-                        ),
+                        status: "".to_string(),
+                        storage_class: "".to_string(),
+                        expires_at: "".to_string(),
+                        checksum: "".to_string(),
+                        deduplicated: false,
+                        scan_status: "error".to_string(),
+                        scan_signature: "".to_string(),
+                        content_type: "".to_string(),
+                        derivatives_status: "".to_string(),
+                        tags: Vec::new(),
+                        folder: "".to_string(),
+                        file_version: 0,
+                        version_of: 0,
+                        msg: "User data upload failed - \
+                            the file could not be scanned"
+                            .to_string(),
+                        error_code: Some(ApiErrorCode::InternalError),
                     })
                     .unwrap(),
                 ))
@@ -486,31 +896,12 @@ pub async fn upload_user_data(
             return Ok(response);
         }
     };
-    let mut row_list: Vec<ApiResUserUploadData> = Vec::with_capacity(1);
-    for row in query_result.iter() {
-        let found_data_id: i32 = row.try_get("id").unwrap();
-        let found_user_id: i32 = row.try_get("user_id").unwrap();
-        let found_filename: String = row.try_get("filename").unwrap();
-        let found_data_type: String = row.try_get("data_type").unwrap();
-        let found_size_in_bytes: i64 = row.try_get("size_in_bytes").unwrap();
-        let found_comments: String = row.try_get("comments").unwrap();
-        let found_encoding: String = row.try_get("encoding").unwrap();
-        let found_sloc: String = row.try_get("sloc").unwrap();
-        row_list.push(ApiResUserUploadData {
-            user_id: found_user_id,
-            data_id: found_data_id,
-            filename: found_filename,
-            data_type: found_data_type,
-            size_in_bytes: found_size_in_bytes,
-            comments: found_comments,
-            encoding: found_encoding,
-            sloc: found_sloc,
-            msg: "success".to_string(),
-        });
-    }
-    if row_list.is_empty() {
+    if let Some(result) = scan_result
+        .as_ref()
+        .filter(|result| config.upload_scan.should_reject(result))
+    {
         let response = Response::builder()
-            .status(400)
+            .status(422)
             .body(Body::from(
                 serde_json::to_string(&ApiResUserUploadData {
                     user_id: -1,
@@ -521,32 +912,245 @@ pub async fn upload_user_data(
                     comments: "".to_string(),
                     encoding: "".to_string(),
                     sloc: "".to_string(),
-                    msg: ("no upload data found in db").to_string(),
+                    status: "".to_string(),
+                    storage_class: "".to_string(),
+                    expires_at: "".to_string(),
+                    checksum: "".to_string(),
+                    deduplicated: false,
+                    scan_status: result.status.clone(),
+                    scan_signature: result.signature.clone(),
+                    content_type: "".to_string(),
+                    derivatives_status: "".to_string(),
+                    tags: Vec::new(),
+                    folder: "".to_string(),
+                    file_version: 0,
+                    version_of: 0,
+                    msg: format!(
+                        "User data upload rejected - \
+                        infected file signature={}",
+                        result.signature
+                    ),
+                    error_code: Some(ApiErrorCode::FileRejected),
                 })
                 .unwrap(),
             ))
             .unwrap();
-        Ok(response)
-    } else {
-        // if enabled, publish to kafka
-        if config.kafka_publish_events {
-            publish_msg(
-                kafka_pool,
-                // topic
-                "user.events",
-                // partition key
-                &format!("user-{}", user_id),
-                // optional headers stored in: Option<HashMap<String, String>>
-                None,
-                // payload in the message
-                &format!("UPLOAD_USER_DATA user={user_id}"),
-            )
-            .await;
+        return Ok(response);
+    }
+    let quarantined = scan_result
+        .as_ref()
+        .map(|result| result.is_infected())
+        .unwrap_or(false);
+
+    let checksum = format!("{:x}", Sha256::digest(&bytes));
+    let dedupe_enabled = std::env::var("S3_DATA_DEDUPE")
+        .unwrap_or_else(|_| "1".to_string())
+        == *"1";
+    let dedupe_source = match should_upload_to_s3
+        && dedupe_enabled
+        && metadata.sloc.is_none()
+        && metadata.storage_class.is_none()
+        && metadata.expire_days.is_none()
+        && !quarantined
+    {
+        true => {
+            let conn = db_pool.get().await.unwrap();
+            match UserDataRepo::new(&conn)
+                .find_by_checksum(tracking_label, user_id, &checksum)
+                .await
+            {
+                Ok(dedupe_source) => dedupe_source,
+                Err(e) => {
+                    warn!(
+                        "{tracking_label} - uploading without dedupe \
+                        for user_id={user_id} - \
+                        failed to find a matching checksum with err='{e}'"
+                    );
+                    None
+                }
+            }
         }
-        let response = Response::builder()
-            .status(200)
-            .body(Body::from(serde_json::to_string(&row_list[0]).unwrap()))
-            .unwrap();
-        Ok(response)
+        false => None,
+    };
+    let deduplicated = dedupe_source.is_some();
+    let sloc = match dedupe_source {
+        Some(existing) => {
+            info!(
+                "{tracking_label} - skipping s3 upload for user_id={user_id} \
+                name={file_name_str} - same contents as \
+                data_id={} {}",
+                existing.data_id, existing.sloc
+            );
+            existing.sloc
+        }
+        None => sloc,
+    };
+
+    if should_upload_to_s3 && !deduplicated {
+        match trace_client_span(
+            "s3 upload",
+            vec![
+                ("s3.bucket", s3_bucket.clone()),
+                ("s3.key", s3_key_dst.clone()),
+                ("s3.bytes", format!("{}", bytes.len())),
+            ],
+            match &metadata.storage_class {
+                Some(storage_class) => {
+                    config.object_store.upload_buffer_with_storage_class(
+                        tracking_label,
+                        &s3_bucket,
+                        &s3_key_dst,
+                        &bytes,
+                        storage_class,
+                    )
+                }
+                None => config.object_store.upload_buffer(
+                    tracking_label,
+                    &s3_bucket,
+                    &s3_key_dst,
+                    &bytes,
+                ),
+            },
+        )
+        .await
+        {
+            Ok(good_msg) => {
+                info!("{good_msg} - done uploading - {sloc}")
+            }
+            Err(emsg) if is_circuit_open_error(&emsg) => {
+                info!("{tracking_label} - {emsg} - not uploading {sloc}");
+                return Ok(get_upload_unavailable_response(user_id, config));
+            }
+            Err(emsg) => {
+                info!("{emsg} - failed uploading {sloc}")
+            }
+        }
+    } else if !should_upload_to_s3 {
+        info!("{tracking_label} - not uploading to s3");
     }
+
+    let conn = db_pool.get().await.unwrap();
+    let new_data = NewUserData {
+        user_id,
+        filename: file_name_str.to_string(),
+        data_type,
+        size_in_bytes: file_contents_size as i64,
+        comments,
+        encoding,
+        sloc,
+        status: match quarantined {
+            true => "quarantined".to_string(),
+            false => config.user_data_pipeline.get_upload_status().to_string(),
+        },
+        checksum: checksum.clone(),
+        storage_class: metadata.storage_class.clone(),
+        expire_days: metadata.expire_days,
+        expire_storage_class: metadata.expire_storage_class.clone(),
+        scan_status: scan_result.as_ref().map(|v| v.status.clone()),
+        scan_signature: scan_result
+            .as_ref()
+            .map(|v| v.signature.clone())
+            .filter(|v| !v.is_empty()),
+        content_type: metadata.content_type.clone(),
+        derivatives_status: match quarantined {
+            true => None,
+            false => config.user_data_thumbnails.get_upload_derivatives_status(
+                metadata.content_type.as_deref(),
+            ),
+        },
+        tags: metadata.tags.clone().unwrap_or_default(),
+        folder: metadata.folder.clone(),
+        file_version: None,
+        version_of: None,
+    };
+    // an upload that lost a reject race leaves its s3 object
+    // for the users_data reconcile task
+    let user_data = match UserDataRepo::new(&conn)
+        .insert_with_conflict_policy(
+            tracking_label,
+            &new_data,
+            &conflict_policy,
+        )
+        .await
+    {
+        Ok(user_data) => user_data,
+        Err(e) => {
+            let response = Response::builder()
+                .status(e.status_code())
+                .body(Body::from(
+                    serde_json::to_string(&ApiResUserUploadData {
+                        user_id: -1,
+                        data_id: -1,
+                        filename: "".to_string(),
+                        data_type: "".to_string(),
+                        size_in_bytes: 0,
+                        comments: "".to_string(),
+                        encoding: "".to_string(),
+                        sloc: "".to_string(),
+                        status: "".to_string(),
+                        storage_class: "".to_string(),
+                        expires_at: "".to_string(),
+                        checksum: "".to_string(),
+                        deduplicated: false,
+                        scan_status: "".to_string(),
+                        scan_signature: "".to_string(),
+                        content_type: "".to_string(),
+                        derivatives_status: "".to_string(),
+                        tags: Vec::new(),
+                        folder: "".to_string(),
+                        file_version: 0,
+                        version_of: 0,
+                        msg: format!(
+                            "User data upload failed for user_id={user_id} \
+                                with err='{e}'"
+                        ),
+                        error_code: Some(e.error_code()),
+                    })
+                    .unwrap(),
+                ))
+                .unwrap();
+            return Ok(response);
+        }
+    };
+    config
+        .events
+        .publish_user_event(
+            kafka_pool,
+            user_id,
+            "UPLOAD_USER_DATA",
+            &format!("data={}", user_data.data_id),
+        )
+        .await;
+    let response = Response::builder()
+        .status(200)
+        .body(Body::from(
+            serde_json::to_string(&ApiResUserUploadData {
+                user_id: user_data.user_id,
+                data_id: user_data.data_id,
+                filename: user_data.filename,
+                data_type: user_data.data_type,
+                size_in_bytes: user_data.size_in_bytes,
+                comments: user_data.comments,
+                encoding: user_data.encoding,
+                sloc: user_data.sloc,
+                status: user_data.status,
+                storage_class: user_data.storage_class,
+                expires_at: user_data.expires_at,
+                checksum,
+                deduplicated,
+                scan_status: user_data.scan_status,
+                scan_signature: user_data.scan_signature,
+                content_type: user_data.content_type,
+                derivatives_status: user_data.derivatives_status,
+                tags: user_data.tags,
+                folder: user_data.folder,
+                file_version: user_data.file_version,
+                version_of: user_data.version_of,
+                msg: "success".to_string(),
+                error_code: None,
+            })
+            .unwrap(),
+        ))
+        .unwrap();
+    Ok(response)
 }
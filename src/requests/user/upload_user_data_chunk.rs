@@ -0,0 +1,473 @@
+//! Module for uploading a chunk of a resumable upload
+//!
+//! ## Upload a resumable upload chunk
+//!
+//! Store the PUT-ed body as the next part of the upload's s3
+//! multipart upload. The ``Content-Range`` header must start
+//! at the upload's ``received_bytes``.
+//!
+//! - URL path: ``/user/data/DATAID/chunks``
+//! - Method: ``PUT``
+//! - Handler: [`upload_user_data_chunk`](crate::requests::user::upload_user_data_chunk::upload_user_data_chunk)
+//! - Request: the chunk contents as the raw body with a
+//!   ``Content-Range: bytes START-END/TOTAL`` header
+//! - Response: [`ApiResUserResumableUpload`](crate::requests::user::resumable_upload::ApiResUserResumableUpload)
+//!
+use std::convert::Infallible;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use hyper::header::HeaderValue;
+use hyper::header::CONTENT_RANGE;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Response;
+
+use crate::core::core_config::CoreConfig;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::monitoring::otel::trace_client_span;
+use crate::requests::models::api_error::ApiErrorCode;
+use crate::requests::models::user_data_upload::add_user_data_upload_part;
+use crate::requests::models::user_data_upload::get_user_data_upload;
+use crate::requests::models::user_data_upload::ModelUserDataUploadPart;
+use crate::requests::user::read_upload_body::read_upload_body;
+use crate::requests::user::resumable_upload::get_object_store_error_response;
+use crate::requests::user::resumable_upload::get_resumable_upload_data_id;
+use crate::requests::user::resumable_upload::get_resumable_upload_response;
+use crate::requests::user::resumable_upload::get_resumable_upload_state_response;
+use crate::requests::user::resumable_upload::get_resumable_upload_user_id;
+use crate::requests::user::resumable_upload::parse_content_range;
+use crate::requests::user::resumable_upload::ApiResUserResumableUpload;
+use crate::requests::user::resumable_upload::RESUMABLE_UPLOAD_MAX_PARTS;
+use crate::utils::circuit_breaker::CIRCUIT_BREAKER_OPEN_MSG;
+
+/// upload_user_data_chunk
+///
+/// Handles storing one ``Content-Range`` chunk of a
+/// resumable upload as the next s3 multipart upload part
+///
+/// # Usage
+///
+/// ## Chunk Rules
+///
+/// - the ``Content-Range`` total must match the
+///   upload's `total_bytes`
+/// - every chunk except the last one must be between
+///   ``UPLOAD_RESUMABLE_MIN_CHUNK_BYTES`` and
+///   ``UPLOAD_RESUMABLE_MAX_CHUNK_BYTES`` (`413` for larger
+///   chunks)
+/// - a chunk must start at the upload's `received_bytes`
+///   (`409` otherwise with the `received_bytes` to resume
+///   from). Sending a chunk that was already stored
+///   returns a `200` without storing it again.
+/// - uploads past their `upload_expires_at` are rejected
+///   with a `410`
+/// - chunks count against the user's
+///   ``USER_DATA_MAX_CONCURRENT_UPLOADS`` (see
+///   [`UserUploadLimit`](crate::requests::user::user_upload_limit::UserUploadLimit))
+///   and are rejected with a `429` and a ``Retry-After``
+///   header over the limit
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
+///   db threadpool with required tls encryption
+/// * `_kafka_pool` -
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
+///   for asynchronously publishing messages to the connected kafka cluster
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+/// * `request_uri` - `&str` - url path with the data id
+/// * `body` - `hyper::Body` - the hyper
+///   [`Request`](hyper::Request)'s [`Body`](hyper::Body)
+///   containing the chunk contents
+///
+/// # Returns
+///
+/// ## upload_user_data_chunk on Success Returns
+///
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResUserResumableUpload`](crate::requests::user::resumable_upload::ApiResUserResumableUpload)
+/// dictionary within the
+/// [`Body`](hyper::Body) and a
+/// `200` HTTP status code
+///
+/// Ok([`Response`](hyper::Response))
+///
+/// # Errors
+///
+/// ## upload_user_data_chunk on Failure Returns
+///
+/// All errors return as a
+/// hyper [`Response`](hyper::Response)
+/// containing a json-serialized
+/// [`ApiResUserResumableUpload`](crate::requests::user::resumable_upload::ApiResUserResumableUpload)
+/// dictionary with a
+/// `non-200` HTTP status code
+///
+/// Err([`Response`](hyper::Response))
+///
+pub async fn upload_user_data_chunk(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    _kafka_pool: &KafkaPublisher,
+    headers: &HeaderMap<HeaderValue>,
+    request_uri: &str,
+    body: hyper::Body,
+) -> std::result::Result<Response<Body>, Infallible> {
+    let data_id = match get_resumable_upload_data_id(request_uri, "chunks") {
+        Some(data_id) => data_id,
+        None => {
+            return Ok(get_resumable_upload_response(
+                400,
+                &ApiResUserResumableUpload {
+                    user_id: -1,
+                    data_id: -1,
+                    msg: ("Invalid data id must be a positive integer")
+                        .to_string(),
+                    error_code: Some(ApiErrorCode::InvalidRequest),
+                    ..Default::default()
+                },
+            ));
+        }
+    };
+
+    let conn = db_pool.get().await.unwrap();
+    let user_id = match get_resumable_upload_user_id(
+        tracking_label,
+        config,
+        &conn,
+        headers,
+    )
+    .await
+    {
+        Ok(user_id) => user_id,
+        Err(error_code) => {
+            return Ok(get_resumable_upload_response(
+                400,
+                &ApiResUserResumableUpload {
+                    user_id: -1,
+                    data_id,
+                    msg: ("User upload chunk failed due to invalid token")
+                        .to_string(),
+                    error_code: Some(error_code),
+                    ..Default::default()
+                },
+            ));
+        }
+    };
+
+    let upload =
+        match get_user_data_upload(tracking_label, user_id, data_id, &conn)
+            .await
+        {
+            Ok(Some(upload)) => upload,
+            Ok(None) => {
+                return Ok(get_resumable_upload_response(
+                    404,
+                    &ApiResUserResumableUpload {
+                        user_id,
+                        data_id,
+                        msg: format!(
+                            "User upload chunk failed - no resumable upload \
+                            for data_id={data_id}"
+                        ),
+                        error_code: Some(ApiErrorCode::NotFound),
+                        ..Default::default()
+                    },
+                ));
+            }
+            Err(e) => {
+                error!("{e}");
+                return Ok(get_resumable_upload_response(
+                    500,
+                    &ApiResUserResumableUpload {
+                        user_id,
+                        data_id,
+                        msg: format!(
+                            "User upload chunk failed for data_id={data_id}"
+                        ),
+                        error_code: Some(ApiErrorCode::InternalError),
+                        ..Default::default()
+                    },
+                ));
+            }
+        };
+    let received_bytes = upload.received_bytes;
+    let num_parts = upload.parts.len() as i64;
+    if upload.expired {
+        return Ok(get_resumable_upload_state_response(
+            410,
+            &upload,
+            received_bytes,
+            num_parts,
+            format!(
+                "User upload chunk failed - the upload expired at {}",
+                upload.expires_at
+            ),
+            Some(ApiErrorCode::NotFound),
+        ));
+    }
+
+    let content_range = headers
+        .get(CONTENT_RANGE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("");
+    let (start, end) = match parse_content_range(content_range) {
+        Some((start, end, total)) if total == upload.total_bytes => {
+            (start, end)
+        }
+        _ => {
+            return Ok(get_resumable_upload_state_response(
+                400,
+                &upload,
+                received_bytes,
+                num_parts,
+                format!(
+                    "User upload chunk failed - the Content-Range header \
+                    must be 'bytes START-END/{}'",
+                    upload.total_bytes
+                ),
+                Some(ApiErrorCode::InvalidRequest),
+            ));
+        }
+    };
+    if end < received_bytes {
+        return Ok(get_resumable_upload_state_response(
+            200,
+            &upload,
+            received_bytes,
+            num_parts,
+            format!("chunk bytes {start}-{end} were already received"),
+            None,
+        ));
+    }
+    if start != received_bytes {
+        return Ok(get_resumable_upload_state_response(
+            409,
+            &upload,
+            received_bytes,
+            num_parts,
+            format!(
+                "User upload chunk failed - the next chunk must start at \
+                byte {received_bytes}"
+            ),
+            Some(ApiErrorCode::Conflict),
+        ));
+    }
+    let chunk_size = end - start + 1;
+    let max_chunk_bytes = config.resumable_uploads.max_chunk_bytes;
+    let min_chunk_bytes = config.resumable_uploads.min_chunk_bytes;
+    if chunk_size > max_chunk_bytes {
+        return Ok(get_resumable_upload_state_response(
+            413,
+            &upload,
+            received_bytes,
+            num_parts,
+            format!(
+                "User upload chunk failed - chunk of {chunk_size} bytes is \
+                larger than the max size of {max_chunk_bytes} bytes"
+            ),
+            Some(ApiErrorCode::PayloadTooLarge),
+        ));
+    }
+    if end + 1 < upload.total_bytes && chunk_size < min_chunk_bytes {
+        return Ok(get_resumable_upload_state_response(
+            400,
+            &upload,
+            received_bytes,
+            num_parts,
+            format!(
+                "User upload chunk failed - every chunk except the last \
+                one must be at least {min_chunk_bytes} bytes"
+            ),
+            Some(ApiErrorCode::InvalidRequest),
+        ));
+    }
+    let part_number = num_parts + 1;
+    if part_number > RESUMABLE_UPLOAD_MAX_PARTS {
+        return Ok(get_resumable_upload_state_response(
+            400,
+            &upload,
+            received_bytes,
+            num_parts,
+            format!(
+                "User upload chunk failed - uploads support at most \
+                {RESUMABLE_UPLOAD_MAX_PARTS} chunks"
+            ),
+            Some(ApiErrorCode::InvalidRequest),
+        ));
+    }
+
+    // fail fast before reading the chunk while s3 keeps failing
+    if config.s3_circuit_breaker.is_open() {
+        return Ok(get_object_store_error_response(
+            config,
+            &format!(
+                "{} {CIRCUIT_BREAKER_OPEN_MSG}",
+                config.s3_circuit_breaker.name
+            ),
+            |status, error_code| {
+                get_resumable_upload_state_response(
+                    status,
+                    &upload,
+                    received_bytes,
+                    num_parts,
+                    format!(
+                        "User upload chunk failed - file storage is \
+                        unavailable, please retry bytes {start}-{end} later"
+                    ),
+                    Some(error_code),
+                )
+            },
+        ));
+    }
+
+    // hold one of the user's upload permits until the chunk is stored
+    let _upload_permit = match config.user_upload_limit.try_acquire(user_id) {
+        Ok(upload_permit) => upload_permit,
+        Err((status, err_msg)) => {
+            info!("{tracking_label} - rejecting chunk with err='{err_msg}'");
+            let mut response = get_resumable_upload_state_response(
+                status,
+                &upload,
+                received_bytes,
+                num_parts,
+                err_msg,
+                Some(ApiErrorCode::from_status(status)),
+            );
+            response.headers_mut().insert(
+                "Retry-After",
+                config.user_upload_limit.retry_after_seconds.into(),
+            );
+            return Ok(response);
+        }
+    };
+
+    let bytes = match read_upload_body(body, chunk_size).await {
+        Ok(bytes) if bytes.len() as i64 == chunk_size => bytes,
+        Ok(bytes) => {
+            return Ok(get_resumable_upload_state_response(
+                400,
+                &upload,
+                received_bytes,
+                num_parts,
+                format!(
+                    "User upload chunk failed - received {} bytes for a \
+                    Content-Range of {chunk_size} bytes",
+                    bytes.len()
+                ),
+                Some(ApiErrorCode::InvalidRequest),
+            ));
+        }
+        Err((status, err_msg)) => {
+            return Ok(get_resumable_upload_state_response(
+                status,
+                &upload,
+                received_bytes,
+                num_parts,
+                err_msg,
+                Some(ApiErrorCode::from_status(status)),
+            ));
+        }
+    };
+
+    let etag = match trace_client_span(
+        "s3 upload part",
+        vec![
+            ("s3.bucket", upload.bucket.clone()),
+            ("s3.key", upload.key.clone()),
+            ("s3.part", format!("{part_number}")),
+            ("s3.bytes", format!("{chunk_size}")),
+        ],
+        config.object_store.upload_part(
+            tracking_label,
+            &upload.bucket,
+            &upload.key,
+            &upload.upload_id,
+            part_number,
+            &bytes,
+        ),
+    )
+    .await
+    {
+        Ok(etag) => etag,
+        Err(e) => {
+            error!("{e}");
+            return Ok(get_object_store_error_response(
+                config,
+                &e,
+                |status, error_code| {
+                    get_resumable_upload_state_response(
+                        status,
+                        &upload,
+                        received_bytes,
+                        num_parts,
+                        format!(
+                            "User upload chunk failed - unable to store \
+                            chunk bytes {start}-{end}"
+                        ),
+                        Some(error_code),
+                    )
+                },
+            ));
+        }
+    };
+
+    let part = ModelUserDataUploadPart {
+        part_number,
+        etag,
+        size_in_bytes: chunk_size,
+    };
+    match add_user_data_upload_part(tracking_label, &upload, &part, &conn).await
+    {
+        Ok(Some(received_bytes)) => {
+            info!(
+                "{tracking_label} - stored chunk {start}-{end} part={} \
+                for user_id={user_id} data_id={data_id} \
+                received={received_bytes}/{}",
+                part.part_number, upload.total_bytes
+            );
+            Ok(get_resumable_upload_state_response(
+                200,
+                &upload,
+                received_bytes,
+                part_number,
+                "success".to_string(),
+                None,
+            ))
+        }
+        Ok(None) => Ok(get_resumable_upload_state_response(
+            409,
+            &upload,
+            received_bytes,
+            num_parts,
+            format!(
+                "User upload chunk failed - another request stored \
+                bytes {start}-{end} first"
+            ),
+            Some(ApiErrorCode::Conflict),
+        )),
+        Err(e) => {
+            error!("{e}");
+            Ok(get_resumable_upload_state_response(
+                500,
+                &upload,
+                received_bytes,
+                num_parts,
+                format!(
+                    "User upload chunk failed - unable to store chunk \
+                    bytes {start}-{end}"
+                ),
+                Some(ApiErrorCode::InternalError),
+            ))
+        }
+    }
+}
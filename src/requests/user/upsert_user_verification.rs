@@ -9,8 +9,11 @@ use bb8_postgres::PostgresConnectionManager;
 use chrono::Duration;
 use chrono::Utc;
 
+use crate::core::core_config::CoreConfig;
+use crate::monitoring::otel::trace_db_query;
 use crate::requests::user::is_verification_enabled::is_verification_enabled;
 use crate::utils::get_uuid::get_uuid;
+use crate::utils::hash_token::hash_token;
 
 /// upsert_user_verification
 ///
@@ -22,6 +25,9 @@ use crate::utils::get_uuid::get_uuid;
 /// based off the environment variable
 /// `USER_EMAIL_VERIFICATION_EXP_IN_SECONDS`.
 ///
+/// Only the hash of the token is stored in the db
+/// (see [`hash_token`](crate::utils::hash_token::hash_token)).
+///
 /// # Usage
 ///
 /// ## Environment Variables
@@ -38,6 +44,8 @@ use crate::utils::get_uuid::get_uuid;
 /// # Arguments
 ///
 /// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig) -
+///   server config with the password salt for hashing the token
 /// * `user_id` - `i32` - user id
 /// * `email` - `&str` - email address
 /// * `is_new_user` - `bool` - flag to allow skipping
@@ -52,7 +60,8 @@ use crate::utils::get_uuid::get_uuid;
 ///
 /// ## upsert_user_verification on Success Returns
 ///
-/// Ok(`String`)
+/// Ok(`String`) - the new (unhashed) verification token
+/// to send to the user
 ///
 /// # Errors
 ///
@@ -62,6 +71,7 @@ use crate::utils::get_uuid::get_uuid;
 ///
 pub async fn upsert_user_verification(
     tracking_label: &str,
+    config: &CoreConfig,
     user_id: i32,
     email: &str,
     is_new_user: bool,
@@ -70,6 +80,7 @@ pub async fn upsert_user_verification(
 ) -> Result<String, String> {
     // create the new email verification token value
     let token = get_uuid();
+    let token_hash = hash_token(&token, &config.server_password_salt);
     let user_verified_value = match is_verification_enabled() {
         true => 0,
         false => 1,
@@ -114,7 +125,7 @@ pub async fn upsert_user_verification(
             with query='{query}'"
         );
         let stmt = conn.prepare(&query).await.unwrap();
-        let _ = match conn.query(&stmt, &[]).await {
+        let _ = match trace_db_query(&query, conn.query(&stmt, &[])).await {
             Ok(query_result) => query_result,
             Err(e) => {
                 let err_msg = format!("{e}");
@@ -128,6 +139,7 @@ pub async fn upsert_user_verification(
                 }
             }
         };
+        config.user_cache.invalidate_user(user_id).await;
     }
 
     let query = match is_new_user {
@@ -139,13 +151,16 @@ pub async fn upsert_user_verification(
                         token, \
                         email, \
                         state, \
-                        exp_date) \
+                        exp_date, \
+                        tenant_id) \
                 VALUES (\
                     {user_id}, \
-                    '{token}', \
+                    '{token_hash}', \
                     '{email}', \
                     {user_verified_value}, \
-                    '{verification_expiration_timestamp}');"
+                    '{verification_expiration_timestamp}', \
+                    (SELECT users.tenant_id FROM users \
+                        WHERE users.id = {user_id}));"
             )
         }
         false => {
@@ -155,7 +170,7 @@ pub async fn upsert_user_verification(
                 SET \
                     email = '{email}',
                     state = {user_verified_value}, \
-                    token = '{token}', \
+                    token = '{token_hash}', \
                     exp_date = '{verification_expiration_timestamp}', \
                     verify_date = NULL \
                 WHERE \
@@ -172,7 +187,7 @@ pub async fn upsert_user_verification(
         with query='{query}'"
     );
     let stmt = conn.prepare(&query).await.unwrap();
-    let _ = match conn.query(&stmt, &[]).await {
+    let _ = match trace_db_query(&query, conn.query(&stmt, &[])).await {
         Ok(query_result) => query_result,
         Err(e) => {
             let err_msg = format!("{e}");
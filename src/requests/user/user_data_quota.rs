@@ -0,0 +1,319 @@
+//! Per-user storage quotas for ``users_data`` uploads
+//!
+//! Each user can store up to ``USER_DATA_QUOTA_BYTES``
+//! (``0`` = unlimited) unless an admin sets a
+//! ``users.quota_bytes`` override in the db. Uploads with
+//! [`upload_user_data`](crate::requests::user::upload_user_data::upload_user_data)
+//! and
+//! [`start_resumable_upload`](crate::requests::user::start_resumable_upload::start_resumable_upload)
+//! are rejected with a ``413`` when the file is larger than
+//! the whole quota and with a ``507`` when it does not fit
+//! in the remaining quota. Users check their usage with
+//! [`get_user_quota`](crate::requests::user::get_user_quota::get_user_quota).
+//!
+//! Concurrent uploads from one user are checked
+//! independently, so they can go over the quota by up to
+//! the size of the in-flight uploads.
+//!
+use std::time::Duration;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use lazy_static::lazy_static;
+
+use tokio_postgres::Client;
+
+use crate::monitoring::metric_types::register_int_gauge;
+use crate::monitoring::metric_types::IntGauge;
+use crate::monitoring::otel::trace_db_query;
+use crate::requests::models::user_data_quota::get_user_data_quota;
+use crate::requests::models::user_data_quota::ModelUserDataQuota;
+use crate::requests::models::user_data_quota::USER_DATA_QUOTA_EXCLUDED_STATUSES;
+
+lazy_static! {
+    pub static ref USER_DATA_USED_BYTES_GAUGE: IntGauge = register_int_gauge!(
+        "user_data_used_bytes",
+        "Bytes stored by all users' users_data records that count \
+        towards their quotas."
+    )
+    .unwrap();
+    pub static ref USER_DATA_FILES_GAUGE: IntGauge = register_int_gauge!(
+        "user_data_files",
+        "Number of users_data records that count towards the \
+        users' quotas."
+    )
+    .unwrap();
+    pub static ref USER_DATA_USERS_OVER_QUOTA_GAUGE: IntGauge =
+        register_int_gauge!(
+            "user_data_users_over_quota",
+            "Number of users storing more bytes than their quota."
+        )
+        .unwrap();
+}
+
+/// UserDataQuota
+///
+/// Settings for per-user storage quotas
+///
+/// # Supported Environment Variables
+///
+/// ```bash
+/// # default quota for users without a users.quota_bytes
+/// # override (0 = unlimited)
+/// export USER_DATA_QUOTA_BYTES="0"
+/// # refresh the aggregate usage gauges (0 = disabled)
+/// export USER_DATA_QUOTA_METRICS_INTERVAL_SECONDS="300"
+/// ```
+///
+/// # Arguments
+///
+/// * `default_quota_bytes` - `i64` - quota for users without
+///   a ``users.quota_bytes`` override (`0` = unlimited)
+/// * `metrics_interval_seconds` - `u64` - seconds between
+///   refreshing the usage gauges (`0` = disabled)
+///
+#[derive(Clone, Default)]
+pub struct UserDataQuota {
+    pub default_quota_bytes: i64,
+    pub metrics_interval_seconds: u64,
+}
+
+impl UserDataQuota {
+    /// build_user_data_quota
+    ///
+    /// Build a
+    /// [`UserDataQuota`](crate::requests::user::user_data_quota::UserDataQuota)
+    /// from environment variables
+    ///
+    pub fn build_user_data_quota() -> Self {
+        let get_env = |key: &str, default: i64| -> i64 {
+            std::env::var(key)
+                .unwrap_or_else(|_| format!("{default}"))
+                .parse::<i64>()
+                .unwrap_or(default)
+        };
+        UserDataQuota {
+            default_quota_bytes: get_env("USER_DATA_QUOTA_BYTES", 0).max(0),
+            metrics_interval_seconds: get_env(
+                "USER_DATA_QUOTA_METRICS_INTERVAL_SECONDS",
+                300,
+            )
+            .max(0) as u64,
+        }
+    }
+
+    /// check_upload
+    ///
+    /// Check a new upload fits in the user's quota
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - caller logging label
+    /// * `user_id` - `i32` - uploading user id
+    /// * `upload_bytes` - `i64` - size of the new upload
+    /// * `client` - [`Client`](tokio_postgres::Client) - db
+    ///   connection
+    ///
+    /// # Returns
+    ///
+    /// Ok([`ModelUserDataQuota`](crate::requests::models::user_data_quota::ModelUserDataQuota)) -
+    /// the quota and usage before the upload
+    ///
+    /// # Errors
+    ///
+    /// `Err((u16, String))` - HTTP status code (``413`` if the
+    /// upload is larger than the quota or ``507`` if it does
+    /// not fit in the remaining quota) and an error message
+    /// for the client
+    ///
+    pub async fn check_upload(
+        &self,
+        tracking_label: &str,
+        user_id: i32,
+        upload_bytes: i64,
+        client: &Client,
+    ) -> Result<ModelUserDataQuota, (u16, String)> {
+        let quota = match get_user_data_quota(
+            tracking_label,
+            user_id,
+            self.default_quota_bytes,
+            client,
+        )
+        .await
+        {
+            Ok(quota) => quota,
+            Err(e) => {
+                error!("{e}");
+                return Err((
+                    e.status_code(),
+                    format!(
+                        "Unable to check the storage quota \
+                        for user_id={user_id}"
+                    ),
+                ));
+            }
+        };
+        if quota.quota_bytes == 0 {
+            return Ok(quota);
+        }
+        if upload_bytes > quota.quota_bytes {
+            return Err((
+                413,
+                format!(
+                    "Upload of {upload_bytes} bytes is larger than \
+                    the storage quota of {} bytes",
+                    quota.quota_bytes
+                ),
+            ));
+        }
+        if quota.used_bytes + upload_bytes > quota.quota_bytes {
+            return Err((
+                507,
+                format!(
+                    "Upload of {upload_bytes} bytes exceeds the \
+                    storage quota - {} of {} bytes are used and \
+                    {} bytes are available",
+                    quota.used_bytes, quota.quota_bytes, quota.available_bytes
+                ),
+            ));
+        }
+        Ok(quota)
+    }
+
+    /// update_usage_gauges
+    ///
+    /// Set the ``user_data_used_bytes``, ``user_data_files``
+    /// and ``user_data_users_over_quota`` prometheus gauges
+    /// from the usage of every user
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - caller logging label
+    /// * `client` - [`Client`](tokio_postgres::Client) - db
+    ///   connection
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`)
+    ///
+    pub async fn update_usage_gauges(
+        &self,
+        tracking_label: &str,
+        client: &Client,
+    ) -> Result<(), String> {
+        let query = format!(
+            "WITH usage AS (\
+                SELECT \
+                    d.user_id, \
+                    SUM(d.size_in_bytes)::BIGINT AS used_bytes, \
+                    COUNT(*) AS num_files \
+                FROM (\
+                    SELECT \
+                        users_data.user_id, \
+                        users_data.size_in_bytes \
+                    FROM \
+                        users_data \
+                    WHERE \
+                        users_data.status NOT IN \
+                            ({USER_DATA_QUOTA_EXCLUDED_STATUSES}) \
+                    UNION ALL \
+                    SELECT \
+                        users_data_archive.user_id, \
+                        users_data_archive.size_in_bytes \
+                    FROM \
+                        users_data_archive \
+                    WHERE \
+                        users_data_archive.status NOT IN \
+                            ({USER_DATA_QUOTA_EXCLUDED_STATUSES})\
+                ) AS d \
+                GROUP BY \
+                    d.user_id\
+            ) \
+            SELECT \
+                COALESCE(SUM(usage.used_bytes), 0)::BIGINT \
+                    AS used_bytes, \
+                COALESCE(SUM(usage.num_files), 0)::BIGINT \
+                    AS num_files, \
+                COUNT(*) FILTER (\
+                    WHERE \
+                        COALESCE(users.quota_bytes, {0}) > 0 \
+                    AND \
+                        usage.used_bytes \
+                            > COALESCE(users.quota_bytes, {0})) \
+                    AS num_over_quota \
+            FROM \
+                usage \
+            INNER JOIN \
+                users \
+            ON \
+                users.id = usage.user_id;",
+            self.default_quota_bytes
+        );
+        match trace_db_query(&query, client.query(query.as_str(), &[])).await {
+            Ok(query_result) => {
+                if let Some(row) = query_result.first() {
+                    USER_DATA_USED_BYTES_GAUGE
+                        .set(row.try_get("used_bytes").unwrap());
+                    USER_DATA_FILES_GAUGE
+                        .set(row.try_get("num_files").unwrap());
+                    USER_DATA_USERS_OVER_QUOTA_GAUGE
+                        .set(row.try_get("num_over_quota").unwrap());
+                }
+                Ok(())
+            }
+            Err(e) => Err(format!(
+                "{tracking_label} - \
+                failed to get the users_data usage with err='{e}'"
+            )),
+        }
+    }
+}
+
+/// run_user_data_quota_metrics
+///
+/// Refresh the usage gauges with
+/// [`update_usage_gauges`](crate::requests::user::user_data_quota::UserDataQuota::update_usage_gauges)
+/// every `metrics_interval_seconds`.
+/// The loop only reads ``users_data`` and sets this api
+/// server's own gauges, so it needs no lock (each server
+/// reports the same totals).
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `quota` - [`UserDataQuota`](crate::requests::user::user_data_quota::UserDataQuota)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client db
+///   threadpool with required tls encryption
+///
+pub async fn run_user_data_quota_metrics(
+    tracking_label: &str,
+    quota: UserDataQuota,
+    db_pool: Pool<PostgresConnectionManager<MakeTlsConnector>>,
+) {
+    if quota.metrics_interval_seconds == 0 {
+        return;
+    }
+    loop {
+        match db_pool.get().await {
+            Ok(conn) => {
+                if let Err(err_msg) =
+                    quota.update_usage_gauges(tracking_label, &conn).await
+                {
+                    error!("{err_msg}");
+                }
+            }
+            Err(e) => {
+                error!(
+                    "{tracking_label} - \
+                    failed to get a db connection for the \
+                    users_data usage gauges with err='{e}'"
+                );
+            }
+        }
+        tokio::time::sleep(Duration::from_secs(quota.metrics_interval_seconds))
+            .await;
+    }
+}
@@ -0,0 +1,125 @@
+//! Settings for the self-service account deletion flow
+//!
+//! 1. [`request_user_delete`](crate::requests::user::request_user_delete::request_user_delete) -
+//!    ``POST /user/delete/request`` emails a one-time-use
+//!    confirmation token
+//! 2. [`confirm_user_delete`](crate::requests::user::confirm_user_delete::confirm_user_delete) -
+//!    ``POST /user/delete/confirm`` consumes the token,
+//!    soft-deletes the user (``users.state = 1``), revokes the
+//!    user's tokens and schedules the user's uploads for
+//!    deletion after ``USER_DELETE_PURGE_DAYS``
+//!
+//! The scheduled uploads are deleted from s3 by the
+//! [`UserDataLifecycle`](crate::archive::user_data_lifecycle::UserDataLifecycle)
+//! task (``USERS_DATA_LIFECYCLE_ENABLED=1``).
+//!
+
+/// UserDeleteConfig
+///
+/// Settings for self-service account deletion
+///
+/// # Supported Environment Variables
+///
+/// ```bash
+/// # seconds until a deletion confirmation token expires
+/// export USER_DELETE_EXP_IN_SECONDS="86400"
+/// # days before a deleted user's uploads are purged
+/// export USER_DELETE_PURGE_DAYS="30"
+/// # emailed confirmation link (the user_id and token are
+/// # added as query params - empty = no link)
+/// export USER_DELETE_CONFIRM_URL="https://app.example.com/delete-account"
+/// ```
+///
+/// # Arguments
+///
+/// * `exp_in_seconds` - `i64` - seconds until a new
+///   confirmation token expires
+/// * `purge_days` - `i64` - days between the confirmation
+///   and the purge of the user's uploads (`0` = the next
+///   lifecycle run)
+/// * `confirm_url` - `String` - page for the emailed
+///   confirmation link (empty = the event only has the token)
+///
+#[derive(Clone, Default)]
+pub struct UserDeleteConfig {
+    pub exp_in_seconds: i64,
+    pub purge_days: i64,
+    pub confirm_url: String,
+}
+
+impl UserDeleteConfig {
+    /// build_user_delete_config
+    ///
+    /// Build a
+    /// [`UserDeleteConfig`](crate::requests::user::user_delete_config::UserDeleteConfig)
+    /// from environment variables
+    ///
+    pub fn build_user_delete_config() -> Self {
+        let get_env = |key: &str, default: i64| -> i64 {
+            std::env::var(key)
+                .unwrap_or_else(|_| format!("{default}"))
+                .parse::<i64>()
+                .unwrap_or(default)
+        };
+        UserDeleteConfig {
+            exp_in_seconds: get_env("USER_DELETE_EXP_IN_SECONDS", 86400)
+                .max(60),
+            purge_days: get_env("USER_DELETE_PURGE_DAYS", 30).max(0),
+            confirm_url: std::env::var("USER_DELETE_CONFIRM_URL")
+                .unwrap_or_default(),
+        }
+    }
+
+    /// get_confirm_link
+    ///
+    /// Build the emailed deletion confirmation link from
+    /// ``USER_DELETE_CONFIRM_URL``
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - `i32` - user id
+    /// * `token` - `&str` - the new (unhashed) confirmation token
+    ///
+    /// # Returns
+    ///
+    /// `Option<String>` - `None` when ``USER_DELETE_CONFIRM_URL``
+    /// is not set or is not a valid url
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use restapi::requests::user::user_delete_config::UserDeleteConfig;
+    /// let user_delete_config = UserDeleteConfig {
+    ///     confirm_url: "https://app.example.com/delete".to_string(),
+    ///     ..UserDeleteConfig::default()
+    /// };
+    /// assert_eq!(
+    ///     user_delete_config.get_confirm_link(2, "abc"),
+    ///     Some("https://app.example.com/delete?user_id=2&token=abc".to_string())
+    /// );
+    /// assert_eq!(UserDeleteConfig::default().get_confirm_link(2, "abc"), None);
+    /// ```
+    ///
+    pub fn get_confirm_link(
+        &self,
+        user_id: i32,
+        token: &str,
+    ) -> Option<String> {
+        if self.confirm_url.is_empty() {
+            return None;
+        }
+        match url::Url::parse_with_params(
+            &self.confirm_url,
+            &[("user_id", format!("{user_id}").as_str()), ("token", token)],
+        ) {
+            Ok(link) => Some(link.to_string()),
+            Err(e) => {
+                warn!(
+                    "invalid USER_DELETE_CONFIRM_URL={} with err='{e}'",
+                    self.confirm_url
+                );
+                None
+            }
+        }
+    }
+}
@@ -0,0 +1,182 @@
+//! Per-user concurrent upload limit
+//!
+//! [`upload_user_data`](crate::requests::user::upload_user_data::upload_user_data)
+//! takes a permit from the uploading user's semaphore after
+//! validating the user's token and holds it until the file is
+//! stored and recorded in the db.
+//! [`upload_user_data_chunk`](crate::requests::user::upload_user_data_chunk::upload_user_data_chunk)
+//! and
+//! [`complete_resumable_upload`](crate::requests::user::complete_resumable_upload::complete_resumable_upload)
+//! hold a permit while they store a chunk or combine the
+//! chunks, so resumable uploads share the same limit and one
+//! user cannot saturate the s3 uplink or the db pool with
+//! parallel uploads. Uploads over
+//! ``USER_DATA_MAX_CONCURRENT_UPLOADS`` are rejected with
+//! a ``429`` and a ``Retry-After`` header. The limit is per api
+//! server (it is not shared across replicas).
+//!
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+
+use tokio::sync::OwnedSemaphorePermit;
+use tokio::sync::Semaphore;
+
+use crate::monitoring::metric_types::register_int_counter;
+use crate::monitoring::metric_types::IntCounter;
+
+lazy_static! {
+    pub static ref USER_UPLOAD_LIMITED_COUNTER: IntCounter =
+        register_int_counter!(
+            "user_data_uploads_limited_total",
+            "Number of uploads rejected by \
+            USER_DATA_MAX_CONCURRENT_UPLOADS."
+        )
+        .unwrap();
+}
+
+/// semaphores for users with in-flight uploads
+type UserUploadSemaphores = Arc<Mutex<HashMap<i32, Arc<Semaphore>>>>;
+
+/// UserUploadPermit
+///
+/// Holds one of a user's upload permits until it is dropped
+/// (and forgets the user's semaphore once every permit is
+/// returned)
+///
+pub struct UserUploadPermit {
+    user_id: i32,
+    max_concurrent_uploads: usize,
+    permit: Option<OwnedSemaphorePermit>,
+    semaphores: UserUploadSemaphores,
+}
+
+impl Drop for UserUploadPermit {
+    fn drop(&mut self) {
+        let mut semaphores = self.semaphores.lock().unwrap();
+        self.permit.take();
+        if semaphores
+            .get(&self.user_id)
+            .map(|semaphore| {
+                semaphore.available_permits() == self.max_concurrent_uploads
+            })
+            .unwrap_or(false)
+        {
+            semaphores.remove(&self.user_id);
+        }
+    }
+}
+
+/// UserUploadLimit
+///
+/// Settings and per-user semaphores for limiting concurrent
+/// uploads
+///
+/// # Supported Environment Variables
+///
+/// ```bash
+/// # max parallel uploads for each user (0 = unlimited)
+/// export USER_DATA_MAX_CONCURRENT_UPLOADS="4"
+/// # Retry-After for rejected uploads
+/// export USER_DATA_UPLOAD_RETRY_AFTER_SECONDS="1"
+/// ```
+///
+/// # Arguments
+///
+/// * `max_concurrent_uploads` - `usize` - max parallel
+///   uploads for each user (`0` = unlimited)
+/// * `retry_after_seconds` - `u64` - ``Retry-After`` for
+///   rejected uploads
+/// * `semaphores` - `Arc<Mutex<HashMap<i32, Arc<Semaphore>>>>` -
+///   semaphores for users with in-flight uploads (shared
+///   across connections)
+///
+#[derive(Clone, Default)]
+pub struct UserUploadLimit {
+    pub max_concurrent_uploads: usize,
+    pub retry_after_seconds: u64,
+    pub semaphores: UserUploadSemaphores,
+}
+
+impl UserUploadLimit {
+    /// build_user_upload_limit
+    ///
+    /// Build a
+    /// [`UserUploadLimit`](crate::requests::user::user_upload_limit::UserUploadLimit)
+    /// from environment variables
+    ///
+    pub fn build_user_upload_limit() -> Self {
+        UserUploadLimit {
+            max_concurrent_uploads: std::env::var(
+                "USER_DATA_MAX_CONCURRENT_UPLOADS",
+            )
+            .unwrap_or_else(|_| "4".to_string())
+            .parse::<usize>()
+            .unwrap_or(4),
+            retry_after_seconds: std::env::var(
+                "USER_DATA_UPLOAD_RETRY_AFTER_SECONDS",
+            )
+            .unwrap_or_else(|_| "1".to_string())
+            .parse::<u64>()
+            .unwrap_or(1),
+            semaphores: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// try_acquire
+    ///
+    /// Take one of the user's upload permits without waiting
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - `i32` - uploading user id
+    ///
+    /// # Returns
+    ///
+    /// Ok(`Option<UserUploadPermit>`) - the permit to hold
+    /// until the upload is done (`None` when the limit is
+    /// disabled)
+    ///
+    /// # Errors
+    ///
+    /// `Err((u16, String))` - HTTP status code ``429`` and an
+    /// error message for the client when the user already has
+    /// ``USER_DATA_MAX_CONCURRENT_UPLOADS`` uploads in flight
+    ///
+    pub fn try_acquire(
+        &self,
+        user_id: i32,
+    ) -> Result<Option<UserUploadPermit>, (u16, String)> {
+        if self.max_concurrent_uploads == 0 {
+            return Ok(None);
+        }
+        let mut semaphores = self.semaphores.lock().unwrap();
+        let semaphore = semaphores
+            .entry(user_id)
+            .or_insert_with(|| {
+                Arc::new(Semaphore::new(self.max_concurrent_uploads))
+            })
+            .clone();
+        match semaphore.try_acquire_owned() {
+            Ok(permit) => Ok(Some(UserUploadPermit {
+                user_id,
+                max_concurrent_uploads: self.max_concurrent_uploads,
+                permit: Some(permit),
+                semaphores: self.semaphores.clone(),
+            })),
+            Err(_) => {
+                USER_UPLOAD_LIMITED_COUNTER.inc();
+                Err((
+                    429,
+                    format!(
+                        "User data upload failed - too many concurrent \
+                        uploads (max {}) - please retry after {} seconds",
+                        self.max_concurrent_uploads, self.retry_after_seconds
+                    ),
+                ))
+            }
+        }
+    }
+}
@@ -0,0 +1,151 @@
+//! Module for validating the custom headers sent
+//! with a user data upload
+//!
+//! Uploads pass the file's metadata (``user_id``,
+//! ``filename``, ``comments``, etc.) in request headers.
+//! Every header value is checked for a maximum length
+//! and for printable ascii before it is used, and each
+//! rejection is counted in the
+//! ``upload_header_rejections_total`` prometheus counter.
+//!
+use lazy_static::lazy_static;
+
+use hyper::header::HeaderValue;
+use hyper::HeaderMap;
+
+use crate::monitoring::metric_types::register_int_counter_vec;
+use crate::monitoring::metric_types::IntCounterVec;
+
+lazy_static! {
+    pub static ref UPLOAD_HEADER_REJECTIONS_COUNTER_VEC: IntCounterVec =
+        register_int_counter_vec!(
+            "upload_header_rejections_total",
+            "Number of user data uploads rejected for invalid headers.",
+            &["header", "reason"]
+        )
+        .unwrap();
+}
+
+/// get_upload_max_header_bytes
+///
+/// Get the maximum combined size (in bytes) of all
+/// header names and values allowed on an upload request
+///
+/// # Environment Variables
+///
+/// ```bash
+/// export UPLOAD_MAX_HEADER_BYTES=8192
+/// ```
+///
+/// ## Roadmap: should move into CoreConfig
+///
+pub fn get_upload_max_header_bytes() -> usize {
+    std::env::var("UPLOAD_MAX_HEADER_BYTES")
+        .unwrap_or_else(|_| "8192".to_string())
+        .parse::<usize>()
+        .unwrap_or(8192)
+}
+
+/// validate_upload_headers_size
+///
+/// Check the combined size of all header names and
+/// values is within
+/// [`get_upload_max_header_bytes`](crate::requests::user::validate_upload_header::get_upload_max_header_bytes)
+///
+/// # Arguments
+///
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+///
+/// # Returns
+///
+/// `Ok(())`
+///
+/// # Errors
+///
+/// `Err((431, String))` - HTTP status code
+/// and an error message for the client
+///
+pub fn validate_upload_headers_size(
+    headers: &HeaderMap<HeaderValue>,
+) -> Result<(), (u16, String)> {
+    let max_bytes = get_upload_max_header_bytes();
+    let total_bytes: usize = headers
+        .iter()
+        .map(|(k, v)| k.as_str().len() + v.len())
+        .sum();
+    if total_bytes > max_bytes {
+        UPLOAD_HEADER_REJECTIONS_COUNTER_VEC
+            .with_label_values(&["all", "too_large"])
+            .inc();
+        return Err((
+            431,
+            format!(
+                "Upload headers are too large - \
+                {total_bytes} bytes exceeds the max of {max_bytes} bytes"
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// validate_upload_header
+///
+/// Get an optional header value after checking it is
+/// printable ascii and not longer than `max_len` bytes
+///
+/// # Arguments
+///
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+/// * `key` - `&str` - header name
+/// * `max_len` - `usize` - maximum length of the value in bytes
+///
+/// # Returns
+///
+/// `Ok(Option<String>)` - `None` if the header was not set
+///
+/// # Errors
+///
+/// `Err((u16, String))` - HTTP status code
+/// (`431` for oversized values and `400` for
+/// non-ascii values) and an error message for the client
+///
+pub fn validate_upload_header(
+    headers: &HeaderMap<HeaderValue>,
+    key: &str,
+    max_len: usize,
+) -> Result<Option<String>, (u16, String)> {
+    let value = match headers.get(key) {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+    if value.len() > max_len {
+        UPLOAD_HEADER_REJECTIONS_COUNTER_VEC
+            .with_label_values(&[key, "too_large"])
+            .inc();
+        return Err((
+            431,
+            format!(
+                "The header value for '{key}' is too large - \
+                it must be {max_len} characters or less"
+            ),
+        ));
+    }
+    // to_str only accepts visible ascii characters
+    match value.to_str() {
+        Ok(v) => Ok(Some(v.to_string())),
+        Err(_) => {
+            UPLOAD_HEADER_REJECTIONS_COUNTER_VEC
+                .with_label_values(&[key, "invalid_charset"])
+                .inc();
+            Err((
+                400,
+                format!(
+                    "The header value for '{key}' must only contain \
+                    printable ascii characters"
+                ),
+            ))
+        }
+    }
+}
@@ -3,7 +3,7 @@
 //!
 //! ## Verify a User's email
 //!
-//! Consume a one-time-use verification token and change the user's ``users.verified`` value verified (``1``)
+//! Consume a one-time-use verification token and change the user's ``users.verified`` value verified (``1``). Each verify link works once - replaying the link or sending it in concurrent requests returns a ``400`` after the first success (existing dbs need the ``0021_users_tokens_consumed.sql`` migration).
 //!
 //! - URL path: ``/user/verify``
 //! - Method: ``GET``
@@ -25,14 +25,22 @@ use hyper::Response;
 use serde::Deserialize;
 use serde::Serialize;
 
-use kafka_threadpool::kafka_publisher::KafkaPublisher;
-
 use crate::core::core_config::CoreConfig;
-use crate::kafka::publish_msg::publish_msg;
+use crate::kafka::kafka_publisher::KafkaPublisher;
+use crate::monitoring::otel::trace_db_query;
+use crate::requests::models::api_error::ApiErrorCode;
 use crate::requests::models::user::get_user_by_id;
 use crate::requests::models::user_verify::get_user_verify_by_user_id;
 use crate::requests::user::is_verification_enabled::is_verification_enabled;
+use crate::requests::validation::field_rules::check_email;
+use crate::requests::validation::field_rules::check_id;
+use crate::requests::validation::field_rules::check_length;
+use crate::requests::validation::validate_api_req::validate_api_req;
+use crate::requests::validation::validate_api_req::ApiFieldError;
+use crate::requests::validation::validate_api_req::ApiReqValidate;
 use crate::utils::get_query_params_from_url::get_query_params_from_url;
+use crate::utils::hash_token::hash_token;
+use crate::utils::hash_token::is_token_hash_match;
 
 /// ApiReqUserVerify
 ///
@@ -74,6 +82,24 @@ pub struct ApiReqUserVerify {
     pub e: Option<String>,
 }
 
+impl ApiReqValidate for ApiReqUserVerify {
+    /// validate
+    ///
+    /// Require a positive user id (`u`), a verify token
+    /// (`t`) between 20 and 256 characters and an optional
+    /// valid email (`e`)
+    ///
+    fn validate(&self) -> Vec<ApiFieldError> {
+        let mut errors = Vec::new();
+        check_id(&mut errors, "u", self.u);
+        check_length(&mut errors, "t", &self.t, 20, 256);
+        if let Some(e) = &self.e {
+            check_email(&mut errors, "e", e);
+        }
+        errors
+    }
+}
+
 /// ApiResUserVerify
 ///
 /// # Response type for verify_user
@@ -100,6 +126,8 @@ pub struct ApiReqUserVerify {
 ///   (`1` - verified)
 /// * `role` - `String` - user role
 /// * `msg` - `String` - help message
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   machine-readable failure code (omitted on success)
 ///
 #[derive(Serialize, Deserialize, Clone)]
 pub struct ApiResUserVerify {
@@ -109,6 +137,8 @@ pub struct ApiResUserVerify {
     pub verified: i32,
     pub role: String,
     pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ApiErrorCode>,
 }
 
 /// verify_user
@@ -122,6 +152,11 @@ pub struct ApiResUserVerify {
 ///
 /// This function only updates 1 `users_verified` record at a time.
 ///
+/// Verify links can only be used once. The record is verified
+/// in a single statement that also inserts the token hash into
+/// the ``users_tokens_consumed`` table, so concurrent requests
+/// with the same link cannot both succeed.
+///
 /// # Arguments
 ///
 /// * `tracking_label` - `&str` - caller logging label
@@ -130,7 +165,7 @@ pub struct ApiResUserVerify {
 /// * `db_pool` - [`Pool`](bb8::Pool) - postgres client
 ///   db threadpool with required tls encryption
 /// * `kafka_pool` -
-///   [`KafkaPublisher`](kafka_threadpool::kafka_publisher::KafkaPublisher)
+///   [`KafkaPublisher`](crate::kafka::kafka_publisher::KafkaPublisher)
 ///   for asynchronously publishing messages to the connected kafka cluster
 /// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
 ///   hashmap containing headers in key-value pairs
@@ -192,6 +227,7 @@ pub async fn verify_user(
                             verified: -1,
                             role: "".to_string(),
                             msg: ("Missing required query params").to_string(),
+                            error_code: Some(ApiErrorCode::InvalidRequest),
                         })
                         .unwrap(),
                     ))
@@ -225,6 +261,7 @@ pub async fn verify_user(
                         role: "".to_string(),
                         msg: ("Missing required query param: user id")
                             .to_string(),
+                        error_code: Some(ApiErrorCode::InvalidRequest),
                     })
                     .unwrap(),
                 ))
@@ -250,6 +287,7 @@ pub async fn verify_user(
                             the verify token is correct and reach out \
                             to support for additional help")
                             .to_string(),
+                        error_code: Some(ApiErrorCode::InvalidRequest),
                     })
                     .unwrap(),
                 ))
@@ -258,69 +296,12 @@ pub async fn verify_user(
         }
     };
 
-    if user_id <= 0 {
-        let response = Response::builder()
-            .status(400)
-            .body(Body::from(
-                serde_json::to_string(&ApiResUserVerify {
-                    user_id: -1,
-                    email: "".to_string(),
-                    state: -1,
-                    verified: -1,
-                    role: "".to_string(),
-                    msg: ("User verify failed - please ensure \
-                        the user id must be a non-negative number \
-                        and reach out to support for additional help")
-                        .to_string(),
-                })
-                .unwrap(),
-            ))
-            .unwrap();
-        return Ok(response);
-    }
-
-    let verify_token_len = verify_token.len();
-    if verify_token_len < 20 {
-        let response = Response::builder()
-            .status(400)
-            .body(Body::from(
-                serde_json::to_string(&ApiResUserVerify {
-                    user_id: -1,
-                    email: "".to_string(),
-                    state: -1,
-                    verified: -1,
-                    role: "".to_string(),
-                    msg: format!(
-                        "User verify failed - please ensure \
-                        the verify token is valid \
-                        ({verify_token_len} is too short) \
-                        and reach out to support for additional help"
-                    ),
-                })
-                .unwrap(),
-            ))
-            .unwrap();
-        return Ok(response);
-    } else if verify_token_len > 256 {
-        let response = Response::builder()
-            .status(400)
-            .body(Body::from(
-                serde_json::to_string(&ApiResUserVerify {
-                    user_id: -1,
-                    email: "".to_string(),
-                    state: -1,
-                    verified: -1,
-                    role: "".to_string(),
-                    msg: format!(
-                        "User verify failed - please ensure \
-                        the verify token is valid \
-                        ({verify_token_len} is too long) \
-                        and reach out to support for additional help"
-                    ),
-                })
-                .unwrap(),
-            ))
-            .unwrap();
+    let req_object = ApiReqUserVerify {
+        u: user_id,
+        t: verify_token.clone(),
+        e: params_map.get("e").map(|e| e.to_string()),
+    };
+    if let Some(response) = validate_api_req(tracking_label, &req_object) {
         return Ok(response);
     }
 
@@ -344,6 +325,7 @@ pub async fn verify_user(
                             the parameters are correct and reach out \
                             to support for additional help")
                             .to_string(),
+                        error_code: Some(ApiErrorCode::UserNotFound),
                     })
                     .unwrap(),
                 ))
@@ -364,6 +346,7 @@ pub async fn verify_user(
                     verified: user_model.verified,
                     role: user_model.role,
                     msg: ("User verification success").to_string(),
+                    error_code: None,
                 })
                 .unwrap(),
             ))
@@ -388,6 +371,7 @@ pub async fn verify_user(
                         "User {user_id} is inactive - \
                         not able to verify {user_email}"
                     ),
+                    error_code: Some(ApiErrorCode::UserInactive),
                 })
                 .unwrap(),
             ))
@@ -408,6 +392,7 @@ pub async fn verify_user(
                     verified: user_model.verified,
                     role: user_model.role,
                     msg: ("User already verified").to_string(),
+                    error_code: Some(ApiErrorCode::AlreadyVerified),
                 })
                 .unwrap(),
             ))
@@ -436,6 +421,7 @@ pub async fn verify_user(
                         role: "".to_string(),
                         msg: ("User verification record does not exist")
                             .to_string(),
+                        error_code: Some(ApiErrorCode::NotFound),
                     })
                     .unwrap(),
                 ))
@@ -444,6 +430,35 @@ pub async fn verify_user(
         }
     };
 
+    // only the token hash is stored in the db
+    let verify_token_hash =
+        hash_token(&verify_token, &config.server_password_salt);
+    if !is_token_hash_match(&verify_token_hash, &user_verify_model.token) {
+        error!(
+            "{tracking_label} - user {user_id} \
+            verify token does not match"
+        );
+        let response = Response::builder()
+            .status(400)
+            .body(Body::from(
+                serde_json::to_string(&ApiResUserVerify {
+                    user_id: -1,
+                    email: "".to_string(),
+                    state: -1,
+                    verified: -1,
+                    role: "".to_string(),
+                    msg: ("User verify failed - please ensure \
+                        the verify token is correct and reach out \
+                        to support for additional help")
+                        .to_string(),
+                    error_code: Some(ApiErrorCode::VerifyInvalid),
+                })
+                .unwrap(),
+            ))
+            .unwrap();
+        return Ok(response);
+    }
+
     let now: chrono::DateTime<chrono::Utc> = chrono::Utc::now();
     let exp_vs_now_diff =
         now.signed_duration_since(user_verify_model.exp_date_utc);
@@ -462,8 +477,7 @@ pub async fn verify_user(
     if exp_date_vs_now > 0 {
         let err_msg = format!(
             "{tracking_label} - user {user_id} \
-            verify token {verify_token} \
-            expired on: \
+            verify token expired on: \
             exp_date={} \
             duration_since={exp_date_vs_now}s",
             user_verify_model.exp_date_utc
@@ -479,6 +493,7 @@ pub async fn verify_user(
                     verified: -1,
                     role: "".to_string(),
                     msg: format!("user {user_email} verification has expired"),
+                    error_code: Some(ApiErrorCode::VerifyExpired),
                 })
                 .unwrap(),
             ))
@@ -486,140 +501,125 @@ pub async fn verify_user(
         return Ok(response);
     }
 
+    // verify the user in 1 statement. the users_tokens_consumed
+    // row is unique for each token hash so concurrent requests
+    // for the same link wait for the first request and then
+    // insert nothing, and only an unverified and unexpired
+    // users_verified record is updated
     let query = format!(
-        "UPDATE \
-            users_verified \
+        "WITH consumed_token AS (\
+            INSERT INTO \
+                users_tokens_consumed (kind, token, user_id) \
+            VALUES \
+                ('verify', '{verify_token_hash}', {user_id}) \
+            ON CONFLICT (kind, token) DO NOTHING \
+            RETURNING \
+                users_tokens_consumed.id), \
+        verified_token AS (\
+            UPDATE \
+                users_verified \
+            SET \
+                email = '{}', \
+                state = 1, \
+                verify_date = '{now}' \
+            WHERE \
+                users_verified.user_id = {user_id} \
+                AND \
+                users_verified.token = '{verify_token_hash}' \
+                AND \
+                users_verified.state = 0 \
+                AND \
+                users_verified.exp_date > '{now}' \
+                AND \
+                EXISTS (SELECT 1 FROM consumed_token) \
+            RETURNING \
+                users_verified.user_id, \
+                users_verified.email, \
+                users_verified.state) \
+        UPDATE \
+            users \
         SET \
-            email = '{user_email}', \
-            state = 1, \
-            verify_date = '{now}' \
+            verified = 1 \
+        FROM \
+            verified_token \
         WHERE \
-            users_verified.user_id = {user_id} \
+            users.id = verified_token.user_id \
         RETURNING \
-            users_verified.user_id,
-            users_verified.token,
-            users_verified.email,
-            users_verified.state;"
+            verified_token.user_id, \
+            verified_token.email, \
+            verified_token.state;",
+        user_email.replace('\'', "''")
     );
     let stmt = conn.prepare(&query).await.unwrap();
-    let query_result = match conn.query(&stmt, &[]).await {
-        Ok(query_result) => {
-            info!(
-                "{tracking_label} - \
-                user {user_id} email {user_email} token verified"
-            );
-            query_result
-        }
-        Err(e) => {
-            let err_msg = format!("{e}");
-            if err_msg.contains(
-                "db error: ERROR: duplicate key value \
+    let query_result =
+        match trace_db_query(&query, conn.query(&stmt, &[])).await {
+            Ok(query_result) => query_result,
+            Err(e) => {
+                let err_msg = format!("{e}");
+                if err_msg.contains(
+                    "db error: ERROR: duplicate key value \
                 violates unique constraint",
-            ) && err_msg.contains("users_verified_email_key")
-                && err_msg.contains("already exists")
-            {
-                let response = Response::builder()
-                    .status(400)
-                    .body(Body::from(
-                        serde_json::to_string(&ApiResUserVerify {
-                            user_id: -1,
-                            email: "".to_string(),
-                            state: -1,
-                            verified: -1,
-                            role: "".to_string(),
-                            msg: format!(
-                                "User email is already \
+                ) && err_msg.contains("users_verified_tenant_id_email_key")
+                    && err_msg.contains("already exists")
+                {
+                    let response = Response::builder()
+                        .status(400)
+                        .body(Body::from(
+                            serde_json::to_string(&ApiResUserVerify {
+                                user_id: -1,
+                                email: "".to_string(),
+                                state: -1,
+                                verified: -1,
+                                role: "".to_string(),
+                                msg: format!(
+                                    "User email is already \
                                 in use: {user_email}"
-                            ),
-                        })
-                        .unwrap(),
-                    ))
-                    .unwrap();
-                return Ok(response);
-            } else {
-                let response = Response::builder()
-                    .status(400)
-                    .body(Body::from(
-                        serde_json::to_string(&ApiResUserVerify {
-                            user_id: -1,
-                            email: "".to_string(),
-                            state: -1,
-                            verified: -1,
-                            role: "".to_string(),
-                            msg: format!(
-                                "User update failed for user_id={user_id} \
+                                ),
+                                error_code: Some(ApiErrorCode::EmailInUse),
+                            })
+                            .unwrap(),
+                        ))
+                        .unwrap();
+                    return Ok(response);
+                } else {
+                    let response = Response::builder()
+                        .status(400)
+                        .body(Body::from(
+                            serde_json::to_string(&ApiResUserVerify {
+                                user_id: -1,
+                                email: "".to_string(),
+                                state: -1,
+                                verified: -1,
+                                role: "".to_string(),
+                                msg: format!(
+                                    "User update failed for user_id={user_id} \
                                     {user_email} \
                                     with err='{err_msg}'"
-                            ),
-                        })
-                        .unwrap(),
-                    ))
-                    .unwrap();
-                return Ok(response);
+                                ),
+                                error_code: Some(ApiErrorCode::InternalError),
+                            })
+                            .unwrap(),
+                        ))
+                        .unwrap();
+                    return Ok(response);
+                }
             }
-        }
-    };
-
-    let query = format!(
-        "UPDATE \
-            users \
-        SET \
-            verified = 1 \
-        WHERE \
-            users.id = {user_id};"
-    );
-    let stmt = conn.prepare(&query).await.unwrap();
-    match conn.query(&stmt, &[]).await {
-        Ok(_) => {
-            info!(
-                "{tracking_label} - \
-                user {user_id} email {user_email} account verified"
-            );
-        }
-        Err(e) => {
-            let err_msg = format!("{e}");
-            let response = Response::builder()
-                .status(400)
-                .body(Body::from(
-                    serde_json::to_string(&ApiResUserVerify {
-                        user_id: -1,
-                        email: "".to_string(),
-                        state: -1,
-                        verified: -1,
-                        role: "".to_string(),
-                        msg: format!(
-                            "User table update failed for user verification \
-                            user_id={user_id} {user_email}={verify_token} \
-                            with err='{err_msg}'"
-                        ),
-                    })
-                    .unwrap(),
-                ))
-                .unwrap();
-            return Ok(response);
-        }
-    };
+        };
+    config.user_cache.invalidate_user(user_id).await;
 
     // must match up with RETURNING
     if let Some(row) = query_result.first() {
+        info!(
+            "{tracking_label} - \
+            user {user_id} email {user_email} account verified"
+        );
         let found_user_id: i32 = row.try_get("user_id").unwrap();
         let email: String = row.try_get("email").unwrap();
         let user_verify_state: i32 = row.try_get("state").unwrap();
-        // if enabled, publish to kafka
-        if config.kafka_publish_events {
-            publish_msg(
-                kafka_pool,
-                // topic
-                "user.events",
-                // partition key
-                &format!("user-{}", user_id),
-                // optional headers stored in: Option<HashMap<String, String>>
-                None,
-                // payload in the message
-                &format!("USER_VERIFY user={user_id} email={email}"),
-            )
+        config
+            .events
+            .user_verified(kafka_pool, user_id, &email)
             .await;
-        }
         let response = Response::builder()
             .status(200)
             .body(Body::from(
@@ -630,6 +630,7 @@ pub async fn verify_user(
                     verified: user_verify_state,
                     role: user_model.role,
                     msg: format!("user {found_user_id} verified {email}"),
+                    error_code: None,
                 })
                 .unwrap(),
             ))
@@ -646,10 +647,10 @@ pub async fn verify_user(
                 state: -1,
                 verified: -1,
                 role: "".to_string(),
-                msg: format!(
-                    "User update failed - user does \
-                    not exist with user_id={user_id} email={user_email}"
-                ),
+                msg: ("User verify failed - the verify link was \
+                    already used or expired")
+                    .to_string(),
+                error_code: Some(ApiErrorCode::VerifyInvalid),
             })
             .unwrap(),
         ))
@@ -0,0 +1,527 @@
+//! Reusable field checks for
+//! [`ApiReqValidate`](crate::requests::validation::validate_api_req::ApiReqValidate)
+//! implementations
+//!
+//! Each check appends an
+//! [`ApiFieldError`](crate::requests::validation::validate_api_req::ApiFieldError)
+//! to `errors` when the value is invalid so one request
+//! can report all of its invalid fields at once.
+//!
+use std::fmt::Display;
+
+use crate::requests::validation::validate_api_req::ApiFieldError;
+
+/// max length for an email address
+pub const MAX_EMAIL_LEN: usize = 254;
+/// min length for a user password
+pub const MIN_PASSWORD_LEN: usize = 4;
+/// max length for a user password
+pub const MAX_PASSWORD_LEN: usize = 1024;
+/// supported ``users.role`` values
+pub const USER_ROLES: [&str; 2] = ["user", "admin"];
+/// supported ``users.state`` values (`0` - active, `1` - inactive)
+pub const USER_STATES: [i32; 2] = [0, 1];
+/// supported ``users.verified`` values
+/// (`0` - not verified, `1` - verified)
+pub const USER_VERIFIED: [i32; 2] = [0, 1];
+/// max tags on a ``users_data`` record
+pub const MAX_DATA_TAGS: usize = 20;
+/// max length for each ``users_data.tags`` value
+pub const MAX_DATA_TAG_LEN: usize = 64;
+/// max length for a ``users_data.folder`` path
+pub const MAX_DATA_FOLDER_LEN: usize = 1024;
+/// max length for a ``users_data.filename`` value
+pub const MAX_DATA_FILENAME_LEN: usize = 511;
+/// max length for a ``users.locale`` value
+pub const MAX_LOCALE_LEN: usize = 16;
+
+/// add_field_error
+///
+/// Append an invalid field
+///
+/// # Arguments
+///
+/// * `errors` - `&mut Vec<ApiFieldError>` - invalid fields
+/// * `field` - `&str` - request field name
+/// * `msg` - `&str` - what is wrong with the value
+///
+pub fn add_field_error(
+    errors: &mut Vec<ApiFieldError>,
+    field: &str,
+    msg: &str,
+) {
+    errors.push(ApiFieldError {
+        field: field.to_string(),
+        msg: msg.to_string(),
+    });
+}
+
+/// is_valid_email
+///
+/// Check an email address has a single ``@`` with a
+/// non-empty local part and a dotted domain. Quotes,
+/// backslashes and whitespace are not allowed.
+///
+/// # Arguments
+///
+/// * `email` - `&str` - email address
+///
+/// # Returns
+///
+/// `bool` where `true` - the email is valid
+///
+pub fn is_valid_email(email: &str) -> bool {
+    if email.len() < 3 || email.len() > MAX_EMAIL_LEN {
+        return false;
+    }
+    let (local, domain) = match email.split_once('@') {
+        Some(parts) => parts,
+        None => return false,
+    };
+    if local.is_empty()
+        || local.len() > 64
+        || local.starts_with('.')
+        || local.ends_with('.')
+        || local.contains("..")
+        || !local.chars().all(|c| {
+            c.is_ascii_alphanumeric() || "!#$%&*+/=?^_`{|}~.-".contains(c)
+        })
+    {
+        return false;
+    }
+    let labels: Vec<&str> = domain.split('.').collect();
+    labels.len() >= 2
+        && labels.iter().all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+/// check_email
+///
+/// Require a valid email address
+/// (see [`is_valid_email`](crate::requests::validation::field_rules::is_valid_email))
+///
+/// # Arguments
+///
+/// * `errors` - `&mut Vec<ApiFieldError>` - invalid fields
+/// * `field` - `&str` - request field name
+/// * `value` - `&str` - email address
+///
+pub fn check_email(errors: &mut Vec<ApiFieldError>, field: &str, value: &str) {
+    if !is_valid_email(value) {
+        add_field_error(errors, field, "must be a valid email address");
+    }
+}
+
+/// check_length
+///
+/// Require a string length between `min` and `max`
+/// characters (inclusive)
+///
+/// # Arguments
+///
+/// * `errors` - `&mut Vec<ApiFieldError>` - invalid fields
+/// * `field` - `&str` - request field name
+/// * `value` - `&str` - value to check
+/// * `min` - `usize` - min length
+/// * `max` - `usize` - max length
+///
+pub fn check_length(
+    errors: &mut Vec<ApiFieldError>,
+    field: &str,
+    value: &str,
+    min: usize,
+    max: usize,
+) {
+    let len = value.chars().count();
+    if len < min || len > max {
+        let msg = match min {
+            0 => format!("must be {max} characters or less"),
+            _ => format!("must be between {min} and {max} characters"),
+        };
+        add_field_error(errors, field, &msg);
+    }
+}
+
+/// check_password
+///
+/// Require a password between
+/// [`MIN_PASSWORD_LEN`](crate::requests::validation::field_rules::MIN_PASSWORD_LEN)
+/// and
+/// [`MAX_PASSWORD_LEN`](crate::requests::validation::field_rules::MAX_PASSWORD_LEN)
+/// characters
+///
+/// # Arguments
+///
+/// * `errors` - `&mut Vec<ApiFieldError>` - invalid fields
+/// * `field` - `&str` - request field name
+/// * `value` - `&str` - password
+///
+pub fn check_password(
+    errors: &mut Vec<ApiFieldError>,
+    field: &str,
+    value: &str,
+) {
+    check_length(errors, field, value, MIN_PASSWORD_LEN, MAX_PASSWORD_LEN);
+}
+
+/// check_range
+///
+/// Require a number between `min` and `max` (inclusive)
+///
+/// # Arguments
+///
+/// * `errors` - `&mut Vec<ApiFieldError>` - invalid fields
+/// * `field` - `&str` - request field name
+/// * `value` - `T` - value to check
+/// * `min` - `T` - min value
+/// * `max` - `T` - max value
+///
+pub fn check_range<T: PartialOrd + Display>(
+    errors: &mut Vec<ApiFieldError>,
+    field: &str,
+    value: T,
+    min: T,
+    max: T,
+) {
+    if value < min || value > max {
+        add_field_error(
+            errors,
+            field,
+            &format!("must be between {min} and {max}"),
+        );
+    }
+}
+
+/// check_id
+///
+/// Require a positive db id
+///
+/// # Arguments
+///
+/// * `errors` - `&mut Vec<ApiFieldError>` - invalid fields
+/// * `field` - `&str` - request field name
+/// * `value` - `i32` - db id
+///
+pub fn check_id(errors: &mut Vec<ApiFieldError>, field: &str, value: i32) {
+    if value < 1 {
+        add_field_error(errors, field, "must be a positive integer");
+    }
+}
+
+/// check_version
+///
+/// Require the row version the client last read
+/// (optimistic concurrency)
+///
+/// # Arguments
+///
+/// * `errors` - `&mut Vec<ApiFieldError>` - invalid fields
+/// * `field` - `&str` - request field name
+/// * `value` - `Option<i32>` - expected version
+///
+pub fn check_version(
+    errors: &mut Vec<ApiFieldError>,
+    field: &str,
+    value: Option<i32>,
+) {
+    match value {
+        Some(v) => check_id(errors, field, v),
+        None => add_field_error(
+            errors,
+            field,
+            "is required - send the version from the last read",
+        ),
+    }
+}
+
+/// check_one_of
+///
+/// Require one of the supported values
+///
+/// # Arguments
+///
+/// * `errors` - `&mut Vec<ApiFieldError>` - invalid fields
+/// * `field` - `&str` - request field name
+/// * `value` - `T` - value to check
+/// * `allowed` - `&[T]` - supported values
+///
+pub fn check_one_of<T: PartialEq + Display>(
+    errors: &mut Vec<ApiFieldError>,
+    field: &str,
+    value: T,
+    allowed: &[T],
+) {
+    if !allowed.contains(&value) {
+        let allowed_s = allowed
+            .iter()
+            .map(|v| format!("{v}"))
+            .collect::<Vec<String>>()
+            .join(", ");
+        add_field_error(errors, field, &format!("must be one of: {allowed_s}"));
+    }
+}
+
+/// is_valid_data_tag
+///
+/// Check a ``users_data`` tag is 1 to
+/// [`MAX_DATA_TAG_LEN`](crate::requests::validation::field_rules::MAX_DATA_TAG_LEN)
+/// characters without commas, control characters or
+/// leading and trailing whitespace
+///
+/// # Arguments
+///
+/// * `tag` - `&str` - tag value
+///
+/// # Returns
+///
+/// `bool` where `true` - the tag is valid
+///
+/// ```rust
+/// use restapi::requests::validation::field_rules::is_valid_data_tag;
+/// assert!(is_valid_data_tag("tax docs"));
+/// assert!(!is_valid_data_tag(" tax"));
+/// assert!(!is_valid_data_tag("a,b"));
+/// assert!(!is_valid_data_tag(""));
+/// ```
+///
+pub fn is_valid_data_tag(tag: &str) -> bool {
+    let len = tag.chars().count();
+    (1..=MAX_DATA_TAG_LEN).contains(&len)
+        && tag.trim() == tag
+        && !tag.chars().any(|c| c == ',' || c.is_control())
+}
+
+/// check_data_tags
+///
+/// Require at most
+/// [`MAX_DATA_TAGS`](crate::requests::validation::field_rules::MAX_DATA_TAGS)
+/// valid tags
+/// (see [`is_valid_data_tag`](crate::requests::validation::field_rules::is_valid_data_tag))
+///
+/// # Arguments
+///
+/// * `errors` - `&mut Vec<ApiFieldError>` - invalid fields
+/// * `field` - `&str` - request field name
+/// * `value` - `&[String]` - tags
+///
+pub fn check_data_tags(
+    errors: &mut Vec<ApiFieldError>,
+    field: &str,
+    value: &[String],
+) {
+    if value.len() > MAX_DATA_TAGS {
+        add_field_error(
+            errors,
+            field,
+            &format!("must have {MAX_DATA_TAGS} tags or less"),
+        );
+    } else if !value.iter().all(|v| is_valid_data_tag(v)) {
+        add_field_error(
+            errors,
+            field,
+            &format!(
+                "each tag must be between 1 and {MAX_DATA_TAG_LEN} \
+                characters without commas or surrounding whitespace"
+            ),
+        );
+    }
+}
+
+/// is_valid_data_folder
+///
+/// Check a ``users_data`` folder is an absolute path up to
+/// [`MAX_DATA_FOLDER_LEN`](crate::requests::validation::field_rules::MAX_DATA_FOLDER_LEN)
+/// characters like ``/`` or ``/projects/2022`` without a
+/// trailing slash, empty, ``.`` or ``..`` segments or
+/// control characters
+///
+/// # Arguments
+///
+/// * `folder` - `&str` - folder path
+///
+/// # Returns
+///
+/// `bool` where `true` - the folder is valid
+///
+/// ```rust
+/// use restapi::requests::validation::field_rules::is_valid_data_folder;
+/// assert!(is_valid_data_folder("/"));
+/// assert!(is_valid_data_folder("/projects/2022"));
+/// assert!(!is_valid_data_folder("projects"));
+/// assert!(!is_valid_data_folder("/projects/"));
+/// assert!(!is_valid_data_folder("/projects//2022"));
+/// assert!(!is_valid_data_folder("/projects/../secrets"));
+/// ```
+///
+pub fn is_valid_data_folder(folder: &str) -> bool {
+    if folder == "/" {
+        return true;
+    }
+    folder.starts_with('/')
+        && folder.chars().count() <= MAX_DATA_FOLDER_LEN
+        && !folder.chars().any(|c| c.is_control())
+        && folder[1..].split('/').all(|segment| {
+            !segment.is_empty() && segment != "." && segment != ".."
+        })
+}
+
+/// check_data_folder
+///
+/// Require a valid folder path
+/// (see [`is_valid_data_folder`](crate::requests::validation::field_rules::is_valid_data_folder))
+///
+/// # Arguments
+///
+/// * `errors` - `&mut Vec<ApiFieldError>` - invalid fields
+/// * `field` - `&str` - request field name
+/// * `value` - `&str` - folder path
+///
+pub fn check_data_folder(
+    errors: &mut Vec<ApiFieldError>,
+    field: &str,
+    value: &str,
+) {
+    if !is_valid_data_folder(value) {
+        add_field_error(
+            errors,
+            field,
+            &format!(
+                "must be a path like /projects/2022 up to \
+                {MAX_DATA_FOLDER_LEN} characters"
+            ),
+        );
+    }
+}
+
+/// is_valid_data_filename
+///
+/// Check an uploaded filename is 1 to
+/// [`MAX_DATA_FILENAME_LEN`](crate::requests::validation::field_rules::MAX_DATA_FILENAME_LEN)
+/// characters and is not ``.`` or ``..`` and has no ``/``,
+/// ``\`` or control characters. The filename is part of the
+/// s3 key so it must be a single path segment.
+///
+/// # Arguments
+///
+/// * `filename` - `&str` - uploaded filename
+///
+/// # Returns
+///
+/// `bool` where `true` - the filename is valid
+///
+/// ```rust
+/// use restapi::requests::validation::field_rules::is_valid_data_filename;
+/// assert!(is_valid_data_filename("report 2022.pdf"));
+/// assert!(is_valid_data_filename("..hidden"));
+/// assert!(!is_valid_data_filename(""));
+/// assert!(!is_valid_data_filename(".."));
+/// assert!(!is_valid_data_filename("../../etc/passwd"));
+/// assert!(!is_valid_data_filename("/etc/passwd"));
+/// assert!(!is_valid_data_filename("..\\secrets.txt"));
+/// ```
+///
+pub fn is_valid_data_filename(filename: &str) -> bool {
+    filename != "."
+        && filename != ".."
+        && (1..=MAX_DATA_FILENAME_LEN).contains(&filename.chars().count())
+        && !filename
+            .chars()
+            .any(|c| c == '/' || c == '\\' || c.is_control())
+}
+
+/// check_data_filename
+///
+/// Require a valid uploaded filename
+/// (see [`is_valid_data_filename`](crate::requests::validation::field_rules::is_valid_data_filename))
+///
+/// # Arguments
+///
+/// * `errors` - `&mut Vec<ApiFieldError>` - invalid fields
+/// * `field` - `&str` - request field name
+/// * `value` - `&str` - filename
+///
+pub fn check_data_filename(
+    errors: &mut Vec<ApiFieldError>,
+    field: &str,
+    value: &str,
+) {
+    if !is_valid_data_filename(value) {
+        add_field_error(
+            errors,
+            field,
+            &format!(
+                "must be 1 to {MAX_DATA_FILENAME_LEN} characters \
+                without /, \\ or control characters"
+            ),
+        );
+    }
+}
+
+/// is_valid_locale
+///
+/// Check a ``users.locale`` is up to
+/// [`MAX_LOCALE_LEN`](crate::requests::validation::field_rules::MAX_LOCALE_LEN)
+/// characters with a 2 or 3 letter language and optional
+/// ``-`` or ``_`` separated letter or digit subtags like
+/// ``en``, ``pt-BR`` or ``zh_Hant``
+///
+/// # Arguments
+///
+/// * `locale` - `&str` - locale
+///
+/// # Returns
+///
+/// `bool` where `true` - the locale is valid
+///
+/// ```rust
+/// use restapi::requests::validation::field_rules::is_valid_locale;
+/// assert!(is_valid_locale("en"));
+/// assert!(is_valid_locale("pt-BR"));
+/// assert!(is_valid_locale("es_419"));
+/// assert!(!is_valid_locale("english"));
+/// assert!(!is_valid_locale("en-"));
+/// assert!(!is_valid_locale("../en"));
+/// ```
+///
+pub fn is_valid_locale(locale: &str) -> bool {
+    if locale.len() > MAX_LOCALE_LEN {
+        return false;
+    }
+    let mut subtags = locale.split(['-', '_']);
+    let language = subtags.next().unwrap_or_default();
+    (2..=3).contains(&language.len())
+        && language.chars().all(|c| c.is_ascii_alphabetic())
+        && subtags.all(|subtag| {
+            (2..=8).contains(&subtag.len())
+                && subtag.chars().all(|c| c.is_ascii_alphanumeric())
+        })
+}
+
+/// check_locale
+///
+/// Require a valid locale
+/// (see [`is_valid_locale`](crate::requests::validation::field_rules::is_valid_locale))
+///
+/// # Arguments
+///
+/// * `errors` - `&mut Vec<ApiFieldError>` - invalid fields
+/// * `field` - `&str` - request field name
+/// * `value` - `&str` - locale
+///
+pub fn check_locale(errors: &mut Vec<ApiFieldError>, field: &str, value: &str) {
+    if !is_valid_locale(value) {
+        add_field_error(
+            errors,
+            field,
+            &format!(
+                "must be a locale like en or pt-BR up to \
+                {MAX_LOCALE_LEN} characters"
+            ),
+        );
+    }
+}
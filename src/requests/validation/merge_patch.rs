@@ -0,0 +1,150 @@
+//! Parse ``PATCH`` request bodies with
+//! [RFC 7396](https://www.rfc-editor.org/rfc/rfc7396) json
+//! merge patch semantics
+//!
+//! A merge patch is a json object where:
+//!
+//! - missing keys leave the field unchanged
+//! - keys with a ``null`` value clear the field
+//! - every other key replaces the field's value (arrays are
+//!   replaced, not merged)
+//!
+//! ``PUT`` requests cannot clear optional fields because
+//! ``None`` means "do not change", so the ``PATCH`` handlers
+//! use the cleared keys from
+//! [`parse_merge_patch`](crate::requests::validation::merge_patch::parse_merge_patch)
+//! to reset those fields.
+//!
+use serde::de::DeserializeOwned;
+
+use crate::requests::validation::field_rules::add_field_error;
+use crate::requests::validation::validate_api_req::ApiFieldError;
+
+/// MergePatch
+///
+/// A deserialized merge patch request
+///
+/// # Arguments
+///
+/// * `req` - `T` - the request type (``null`` values
+///   deserialize as ``None``)
+/// * `cleared` - `Vec<String>` - keys with a ``null`` value
+///
+#[derive(Clone, Debug)]
+pub struct MergePatch<T> {
+    pub req: T,
+    pub cleared: Vec<String>,
+}
+
+impl<T> MergePatch<T> {
+    /// is_cleared
+    ///
+    /// Check if the patch set a field to ``null``
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - `&str` - request field name
+    ///
+    pub fn is_cleared(&self, field: &str) -> bool {
+        self.cleared.iter().any(|v| v == field)
+    }
+
+    /// check_not_cleared
+    ///
+    /// Append an
+    /// [`ApiFieldError`](crate::requests::validation::validate_api_req::ApiFieldError)
+    /// for each required field the patch set to ``null``
+    ///
+    /// # Arguments
+    ///
+    /// * `errors` - `&mut Vec<ApiFieldError>` - invalid fields
+    /// * `fields` - `&[&str]` - fields that cannot be cleared
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use restapi::requests::validation::merge_patch::parse_merge_patch;
+    /// use restapi::requests::user::update_user_data::ApiReqUserUpdateData;
+    /// let patch = parse_merge_patch::<ApiReqUserUpdateData>(
+    ///     br#"{"user_id":1,"data_id":2,"filename":null}"#,
+    /// )
+    /// .unwrap();
+    /// let mut errors = Vec::new();
+    /// // absent fields are not cleared
+    /// patch.check_not_cleared(&mut errors, &["data_type", "folder"]);
+    /// assert!(errors.is_empty());
+    /// // null fields are cleared
+    /// patch.check_not_cleared(&mut errors, &["filename", "data_type"]);
+    /// assert_eq!(errors.len(), 1);
+    /// assert_eq!(errors[0].field, "filename");
+    /// assert_eq!(errors[0].msg, "cannot be null");
+    /// ```
+    ///
+    pub fn check_not_cleared(
+        &self,
+        errors: &mut Vec<ApiFieldError>,
+        fields: &[&str],
+    ) {
+        for field in fields.iter() {
+            if self.is_cleared(field) {
+                add_field_error(errors, field, "cannot be null");
+            }
+        }
+    }
+}
+
+/// parse_merge_patch
+///
+/// Deserialize a merge patch body and record which keys
+/// are ``null``
+///
+/// # Arguments
+///
+/// * `bytes` - `&[u8]` - request body
+///
+/// # Errors
+///
+/// Err(err_msg: `String`) if the body is not a json object
+/// or does not deserialize as `T`
+///
+/// # Examples
+///
+/// ```rust
+/// use restapi::requests::validation::merge_patch::parse_merge_patch;
+/// use restapi::requests::user::update_user_data::ApiReqUserUpdateData;
+/// let patch = parse_merge_patch::<ApiReqUserUpdateData>(
+///     br#"{"user_id":1,"data_id":2,"comments":null,"folder":"/a"}"#,
+/// )
+/// .unwrap();
+/// // null clears the field
+/// assert!(patch.is_cleared("comments"));
+/// assert_eq!(patch.req.comments, None);
+/// // a value replaces the field
+/// assert!(!patch.is_cleared("folder"));
+/// assert_eq!(patch.req.folder.as_deref(), Some("/a"));
+/// // an absent field is left untouched
+/// assert!(!patch.is_cleared("tags"));
+/// assert_eq!(patch.req.tags, None);
+/// assert_eq!(patch.cleared, vec!["comments".to_string()]);
+/// assert!(parse_merge_patch::<ApiReqUserUpdateData>(b"[]").is_err());
+/// ```
+///
+pub fn parse_merge_patch<T: DeserializeOwned>(
+    bytes: &[u8],
+) -> Result<MergePatch<T>, String> {
+    let value: serde_json::Value = serde_json::from_slice(bytes)
+        .map_err(|e| format!("invalid json merge patch with err='{e}'"))?;
+    let cleared = match value.as_object() {
+        Some(patch) => patch
+            .iter()
+            .filter(|(_, v)| v.is_null())
+            .map(|(k, _)| k.clone())
+            .collect(),
+        None => {
+            return Err("json merge patch must be a json object".to_string())
+        }
+    };
+    let req = serde_json::from_value(value)
+        .map_err(|e| format!("invalid json merge patch with err='{e}'"))?;
+    Ok(MergePatch { req, cleared })
+}
@@ -0,0 +1,8 @@
+//! Request body validation for the ``ApiReq*`` types
+//!
+pub mod field_rules;
+pub mod merge_patch;
+pub mod normalize_email;
+pub mod validate_api_req;
+pub mod validate_email_domain;
+pub mod validate_password_policy;
@@ -0,0 +1,29 @@
+//! Normalize email addresses so ``"User@Email.com "``
+//! and ``"user@email.com"`` map to the same account
+//!
+
+/// normalize_email
+///
+/// Trim surrounding whitespace and lowercase an email
+/// address. Handlers normalize request emails before
+/// validating them and before any db lookups, so
+/// ``users.email`` only holds normalized addresses.
+///
+/// # Arguments
+///
+/// * `email` - `&str` - email address from a request
+///
+/// # Returns
+///
+/// `String` - the normalized email address
+///
+/// # Examples
+///
+/// ```rust
+/// use restapi::requests::validation::normalize_email::normalize_email;
+/// assert_eq!(normalize_email(" User@Email.com "), "user@email.com");
+/// ```
+///
+pub fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
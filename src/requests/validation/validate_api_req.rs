@@ -0,0 +1,171 @@
+//! Validate deserialized ``ApiReq*`` request types and
+//! return field-level errors to the client
+//!
+//! Handlers call
+//! [`validate_api_req`](crate::requests::validation::validate_api_req::validate_api_req)
+//! right after deserializing the request. Invalid
+//! requests get a `422` HTTP status code with an
+//! [`ApiResValidationErrors`](crate::requests::validation::validate_api_req::ApiResValidationErrors)
+//! body listing every invalid field:
+//!
+//! ```json
+//! {
+//!     "errors": [
+//!         {"field": "email", "msg": "must be a valid email address"},
+//!         {"field": "password", "msg": "must be between 4 and 1024 characters"}
+//!     ],
+//!     "msg": "Request validation failed for fields: email, password",
+//!     "error_code": "VALIDATION_FAILED"
+//! }
+//! ```
+//!
+use hyper::Body;
+use hyper::Response;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::requests::models::api_error::ApiErrorCode;
+
+/// ApiFieldError
+///
+/// One invalid field in a request
+///
+/// # Arguments
+///
+/// * `field` - `String` - request field name
+/// * `msg` - `String` - what is wrong with the value
+///
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ApiFieldError {
+    pub field: String,
+    pub msg: String,
+}
+
+/// ApiResValidationErrors
+///
+/// # Response type for invalid requests
+///
+/// Return every invalid field in the request with
+/// a `422` HTTP status code
+///
+/// # Arguments
+///
+/// * `errors` - `Vec<`[`ApiFieldError`](crate::requests::validation::validate_api_req::ApiFieldError)`>` -
+///   invalid fields
+/// * `msg` - `String` - help message
+/// * `error_code` - `Option<`[`ApiErrorCode`](crate::requests::models::api_error::ApiErrorCode)`>` -
+///   ``VALIDATION_FAILED``
+///
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct ApiResValidationErrors {
+    pub errors: Vec<ApiFieldError>,
+    pub msg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ApiErrorCode>,
+}
+
+/// ApiReqValidate
+///
+/// Implemented by every ``ApiReq*`` type to check the
+/// deserialized values (email format, lengths, numeric
+/// ranges and supported enum values) before the handler
+/// touches the db
+///
+pub trait ApiReqValidate {
+    /// validate
+    ///
+    /// # Returns
+    ///
+    /// `Vec<`[`ApiFieldError`](crate::requests::validation::validate_api_req::ApiFieldError)`>` -
+    /// empty when the request is valid
+    ///
+    fn validate(&self) -> Vec<ApiFieldError>;
+}
+
+/// validate_api_req
+///
+/// Validate a deserialized request and build the
+/// `422` response for the client if any field
+/// is invalid
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `req` - `&T` where `T` implements
+///   [`ApiReqValidate`](crate::requests::validation::validate_api_req::ApiReqValidate)
+///
+/// # Returns
+///
+/// `None` if the request is valid
+///
+/// `Some(`[`Response`](hyper::Response)`)` with a `422` HTTP
+/// status code and a json-serialized
+/// [`ApiResValidationErrors`](crate::requests::validation::validate_api_req::ApiResValidationErrors)
+/// body
+///
+pub fn validate_api_req<T: ApiReqValidate>(
+    tracking_label: &str,
+    req: &T,
+) -> Option<Response<Body>> {
+    let errors = req.validate();
+    if errors.is_empty() {
+        return None;
+    }
+    info!(
+        "{tracking_label} - \
+        rejected {} with invalid fields: {}",
+        std::any::type_name::<T>().rsplit("::").next().unwrap_or(""),
+        get_error_fields(&errors)
+    );
+    Some(get_validation_errors_response(errors))
+}
+
+/// get_error_fields
+///
+/// Join the invalid field names for logging
+/// and the response `msg`
+///
+fn get_error_fields(errors: &[ApiFieldError]) -> String {
+    errors
+        .iter()
+        .map(|e| e.field.as_str())
+        .collect::<Vec<&str>>()
+        .join(", ")
+}
+
+/// get_validation_errors_response
+///
+/// Build the `422` response for invalid fields found
+/// outside of
+/// [`ApiReqValidate`](crate::requests::validation::validate_api_req::ApiReqValidate)
+/// (i.e. checks that need the network or the db)
+///
+/// # Arguments
+///
+/// * `errors` - `Vec<`[`ApiFieldError`](crate::requests::validation::validate_api_req::ApiFieldError)`>` -
+///   invalid fields
+///
+/// # Returns
+///
+/// [`Response`](hyper::Response) with a `422` HTTP
+/// status code and a json-serialized
+/// [`ApiResValidationErrors`](crate::requests::validation::validate_api_req::ApiResValidationErrors)
+/// body
+///
+pub fn get_validation_errors_response(
+    errors: Vec<ApiFieldError>,
+) -> Response<Body> {
+    let fields = get_error_fields(&errors);
+    Response::builder()
+        .status(422)
+        .body(Body::from(
+            serde_json::to_string(&ApiResValidationErrors {
+                errors,
+                msg: format!("Request validation failed for fields: {fields}"),
+                error_code: Some(ApiErrorCode::ValidationFailed),
+            })
+            .unwrap(),
+        ))
+        .unwrap()
+}
@@ -0,0 +1,95 @@
+//! Optional check that a new email address has a
+//! domain that can receive mail
+//!
+//! Enable with:
+//!
+//! ```bash
+//! export USER_EMAIL_CHECK_DOMAIN="1"
+//! export USER_EMAIL_CHECK_DOMAIN_TIMEOUT_MS="2000"
+//! ```
+//!
+//! The domain must resolve to an address. This is the
+//! mail server fallback used when a domain has no ``MX``
+//! record, so it rejects typo domains without needing a
+//! dedicated dns client. Lookups that time out are allowed
+//! so a slow resolver does not block signups.
+//!
+use std::time::Duration;
+
+use hyper::Body;
+use hyper::Response;
+
+use crate::requests::validation::field_rules::add_field_error;
+use crate::requests::validation::validate_api_req::get_validation_errors_response;
+
+/// is_email_domain_check_enabled
+///
+/// Check the ``USER_EMAIL_CHECK_DOMAIN`` environment
+/// variable (disabled by default)
+///
+pub fn is_email_domain_check_enabled() -> bool {
+    std::env::var("USER_EMAIL_CHECK_DOMAIN").unwrap_or_else(|_| "0".to_string())
+        == *"1"
+}
+
+/// validate_email_domain
+///
+/// Reject a normalized and already-validated email
+/// address whose domain does not resolve
+/// (only when ``USER_EMAIL_CHECK_DOMAIN="1"``)
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `field` - `&str` - request field name
+/// * `email` - `&str` - normalized email address
+///
+/// # Returns
+///
+/// `None` if the domain resolves (or the check is disabled)
+///
+/// `Some(`[`Response`](hyper::Response)`)` with a `422` HTTP
+/// status code and a json-serialized
+/// [`ApiResValidationErrors`](crate::requests::validation::validate_api_req::ApiResValidationErrors)
+/// body
+///
+pub async fn validate_email_domain(
+    tracking_label: &str,
+    field: &str,
+    email: &str,
+) -> Option<Response<Body>> {
+    if !is_email_domain_check_enabled() {
+        return None;
+    }
+    let domain = email.rsplit('@').next().unwrap_or("");
+    let timeout_ms = std::env::var("USER_EMAIL_CHECK_DOMAIN_TIMEOUT_MS")
+        .unwrap_or_else(|_| "2000".to_string())
+        .parse::<u64>()
+        .unwrap_or(2000);
+    let lookup = tokio::net::lookup_host(format!("{domain}:25"));
+    let resolves =
+        match tokio::time::timeout(Duration::from_millis(timeout_ms), lookup)
+            .await
+        {
+            Ok(Ok(mut addrs)) => addrs.next().is_some(),
+            Ok(Err(_)) => false,
+            Err(_) => {
+                warn!(
+                    "{tracking_label} - \
+                email domain lookup for {domain} timed out - allowing"
+                );
+                true
+            }
+        };
+    if resolves {
+        return None;
+    }
+    info!("{tracking_label} - rejected email with unknown domain {domain}");
+    let mut errors = Vec::new();
+    add_field_error(
+        &mut errors,
+        field,
+        "must be an email address with a domain that receives mail",
+    );
+    Some(get_validation_errors_response(errors))
+}
@@ -0,0 +1,177 @@
+//! Password policy for new and changed passwords
+//!
+//! Every password must be between
+//! [`MIN_PASSWORD_LEN`](crate::requests::validation::field_rules::MIN_PASSWORD_LEN)
+//! and
+//! [`MAX_PASSWORD_LEN`](crate::requests::validation::field_rules::MAX_PASSWORD_LEN)
+//! characters. The policy adds a longer minimum length and
+//! required character classes. It is part of the
+//! [`RuntimeSettings`](crate::settings::runtime_settings::RuntimeSettings)
+//! so admins can tighten it with ``PUT /admin/settings``
+//! without redeploying. Existing passwords keep working
+//! until they are changed.
+//!
+use hyper::Body;
+use hyper::Response;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::requests::validation::field_rules::add_field_error;
+use crate::requests::validation::field_rules::MAX_PASSWORD_LEN;
+use crate::requests::validation::field_rules::MIN_PASSWORD_LEN;
+use crate::requests::validation::validate_api_req::get_validation_errors_response;
+
+/// PasswordPolicy
+///
+/// Rules for new and changed passwords
+///
+/// # Supported Environment Variables
+///
+/// ```bash
+/// export PASSWORD_MIN_LENGTH="4"
+/// export PASSWORD_REQUIRE_DIGIT="0"
+/// export PASSWORD_REQUIRE_SYMBOL="0"
+/// export PASSWORD_REQUIRE_MIXED_CASE="0"
+/// ```
+///
+/// # Arguments
+///
+/// * `min_length` - `usize` - minimum number of characters
+/// * `require_digit` - `bool` - require a ``0-9`` digit
+/// * `require_symbol` - `bool` - require a character that
+///   is not a letter, digit or whitespace
+/// * `require_mixed_case` - `bool` - require an uppercase
+///   and a lowercase letter
+///
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub require_digit: bool,
+    pub require_symbol: bool,
+    pub require_mixed_case: bool,
+}
+
+impl PasswordPolicy {
+    /// build_password_policy
+    ///
+    /// Build a
+    /// [`PasswordPolicy`](crate::requests::validation::validate_password_policy::PasswordPolicy)
+    /// from environment variables
+    ///
+    pub fn build_password_policy() -> Self {
+        let get_flag = |key: &str| -> bool {
+            let value = std::env::var(key).unwrap_or_else(|_| "0".to_string());
+            value == "1" || value == "true"
+        };
+        PasswordPolicy {
+            min_length: std::env::var("PASSWORD_MIN_LENGTH")
+                .unwrap_or_default()
+                .parse::<usize>()
+                .unwrap_or(MIN_PASSWORD_LEN)
+                .clamp(MIN_PASSWORD_LEN, MAX_PASSWORD_LEN),
+            require_digit: get_flag("PASSWORD_REQUIRE_DIGIT"),
+            require_symbol: get_flag("PASSWORD_REQUIRE_SYMBOL"),
+            require_mixed_case: get_flag("PASSWORD_REQUIRE_MIXED_CASE"),
+        }
+    }
+
+    /// get_violations
+    ///
+    /// List the rules a password does not meet
+    ///
+    /// # Arguments
+    ///
+    /// * `password` - `&str` - new password
+    ///
+    /// # Returns
+    ///
+    /// `Vec<String>` - one message per unmet rule (empty when
+    /// the password meets the policy)
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use restapi::requests::validation::validate_password_policy::PasswordPolicy;
+    /// let policy = PasswordPolicy {
+    ///     min_length: 8,
+    ///     require_digit: true,
+    ///     require_symbol: false,
+    ///     require_mixed_case: true,
+    /// };
+    /// assert_eq!(policy.get_violations("Password123").len(), 0);
+    /// assert_eq!(policy.get_violations("password").len(), 2);
+    /// ```
+    ///
+    pub fn get_violations(&self, password: &str) -> Vec<String> {
+        let mut violations: Vec<String> = Vec::new();
+        if password.chars().count() < self.min_length {
+            violations.push(format!(
+                "must be at least {} characters",
+                self.min_length
+            ));
+        }
+        if self.require_digit && !password.chars().any(|c| c.is_ascii_digit()) {
+            violations.push("must contain a digit".to_string());
+        }
+        if self.require_symbol
+            && !password
+                .chars()
+                .any(|c| !c.is_alphanumeric() && !c.is_whitespace())
+        {
+            violations.push("must contain a symbol".to_string());
+        }
+        if self.require_mixed_case
+            && !(password.chars().any(|c| c.is_uppercase())
+                && password.chars().any(|c| c.is_lowercase()))
+        {
+            violations.push(
+                "must contain an uppercase and a lowercase letter".to_string(),
+            );
+        }
+        violations
+    }
+}
+
+/// validate_password_policy
+///
+/// Reject an already-validated new password that does not
+/// meet the current
+/// [`PasswordPolicy`](crate::requests::validation::validate_password_policy::PasswordPolicy)
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `policy` - [`PasswordPolicy`](crate::requests::validation::validate_password_policy::PasswordPolicy) -
+///   current policy from the
+///   [`RuntimeSettings`](crate::settings::runtime_settings::RuntimeSettings)
+/// * `field` - `&str` - request field name
+/// * `password` - `&str` - new password
+///
+/// # Returns
+///
+/// `None` if the password meets the policy
+///
+/// `Some(`[`Response`](hyper::Response)`)` with a `422` HTTP
+/// status code and a json-serialized
+/// [`ApiResValidationErrors`](crate::requests::validation::validate_api_req::ApiResValidationErrors)
+/// body
+///
+pub fn validate_password_policy(
+    tracking_label: &str,
+    policy: &PasswordPolicy,
+    field: &str,
+    password: &str,
+) -> Option<Response<Body>> {
+    let violations = policy.get_violations(password);
+    if violations.is_empty() {
+        return None;
+    }
+    info!(
+        "{tracking_label} - rejected {field} that does not meet \
+        the password policy"
+    );
+    let mut errors = Vec::new();
+    add_field_error(&mut errors, field, &violations.join(", "));
+    Some(get_validation_errors_response(errors))
+}
@@ -0,0 +1,8 @@
+//! Fetch the postgres password, password salt and jwt keys
+//! from a secrets manager instead of only environment
+//! variables and files on disk
+//!
+pub mod aws_secrets_manager;
+pub mod refresh_secrets;
+pub mod secrets_provider;
+pub mod vault;
@@ -0,0 +1,187 @@
+//! Fetch the secrets again while the server is running
+//!
+//! New ``TOKEN_ALGO_PRIVATE_KEY`` and ``TOKEN_ALGO_PUBLIC_KEY``
+//! secrets replace the `default` jwt keys without downtime.
+//! The postgres password and password salt are only used at
+//! startup (the db pools are built once and changing the
+//! salt would break every stored password hash), so a
+//! changed value is logged as a warning until the server is
+//! restarted.
+//!
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::core::core_config::CoreConfig;
+use crate::jwt::jwt_keys::decrypt_jwt_private_key;
+use crate::jwt::jwt_keys::load_jwt_keys_from_paths;
+use crate::jwt::jwt_keys::JwtKeyPaths;
+use crate::jwt::jwt_keys::JwtKeys;
+use crate::jwt::jwt_keys::DEFAULT_JWT_KID;
+use crate::secrets::secrets_provider::get_jwt_key_paths_with_secrets;
+use crate::secrets::secrets_provider::get_jwt_keys_from_secrets;
+use crate::secrets::secrets_provider::Secrets;
+use crate::secrets::secrets_provider::SECRET_JWT_PRIVATE_KEY;
+use crate::secrets::secrets_provider::SECRET_JWT_PUBLIC_KEY;
+use crate::secrets::secrets_provider::SECRET_SERVER_PASSWORD_SALT;
+
+/// load_jwt_keys_from_secrets_or_paths
+///
+/// Load the jwt key ring with the `default` keys from the
+/// secrets provider (if enabled and it has the keys) or
+/// from the key files
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `secrets` - [`Secrets`](crate::secrets::secrets_provider::Secrets) -
+///   secrets provider settings
+/// * `jwt_key_paths` - [`JwtKeyPaths`](crate::jwt::jwt_keys::JwtKeyPaths) -
+///   key directory and `default` key paths
+///
+/// # Errors
+///
+/// Err(err_msg: `String`) if the secrets could not be
+/// fetched or the keys are invalid
+///
+pub async fn load_jwt_keys_from_secrets_or_paths(
+    tracking_label: &str,
+    secrets: &Secrets,
+    jwt_key_paths: &JwtKeyPaths,
+) -> Result<JwtKeys, String> {
+    let values = secrets.fetch_secrets(tracking_label).await?;
+    let jwt_key_paths = &get_jwt_key_paths_with_secrets(jwt_key_paths, &values);
+    match get_jwt_keys_from_secrets(tracking_label, jwt_key_paths, &values)? {
+        Some(jwt_keys) => Ok(jwt_keys),
+        None => Ok(load_jwt_keys_from_paths(tracking_label, jwt_key_paths)?),
+    }
+}
+
+/// has_new_jwt_keys
+///
+/// Check if the fetched jwt key secrets are different from
+/// the `default` keys the server is using (encrypted private
+/// keys are compared after decrypting them)
+///
+fn has_new_jwt_keys(
+    config: &CoreConfig,
+    values: &HashMap<String, String>,
+) -> bool {
+    match (
+        values.get(SECRET_JWT_PRIVATE_KEY),
+        values.get(SECRET_JWT_PUBLIC_KEY),
+    ) {
+        (Some(private_key), Some(public_key)) => {
+            let passphrase =
+                get_jwt_key_paths_with_secrets(&config.jwt_key_paths, values)
+                    .private_key_passphrase;
+            let private_key = match decrypt_jwt_private_key(
+                private_key.as_bytes(),
+                &passphrase,
+            ) {
+                Ok(private_key) => private_key,
+                // reload to log the error
+                Err(_) => return true,
+            };
+            let jwt_keys = config.jwt_keys.read().unwrap();
+            jwt_keys.encoding_keys.get(DEFAULT_JWT_KID) != Some(&private_key)
+                || jwt_keys
+                    .decoding_keys
+                    .get(DEFAULT_JWT_KID)
+                    .map(|v| v.as_slice())
+                    != Some(public_key.as_bytes())
+        }
+        _ => false,
+    }
+}
+
+/// run_secrets_refresher
+///
+/// Fetch the secrets every
+/// ``SECRETS_REFRESH_INTERVAL_SECONDS`` and replace the
+/// shared
+/// [`JwtKeys`](crate::jwt::jwt_keys::JwtKeys)
+/// when the jwt key secrets change. The current keys are
+/// kept if a fetch or reload fails.
+///
+/// Returns right away if no secrets provider is set or the
+/// interval is ``0``.
+///
+/// # Arguments
+///
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+///
+pub async fn run_secrets_refresher(config: CoreConfig) {
+    let secrets = &config.secrets;
+    if !secrets.is_enabled() || secrets.refresh_interval_seconds == 0 {
+        return;
+    }
+    let tracking_label = format!("{} - secrets", config.label);
+    info!(
+        "{tracking_label} - refreshing secrets from SECRETS_PROVIDER={} \
+        every {} seconds",
+        secrets.provider_name, secrets.refresh_interval_seconds
+    );
+    // only warn once for each new restart-only value
+    let mut warned: HashMap<String, String> = HashMap::new();
+    loop {
+        tokio::time::sleep(Duration::from_secs(
+            secrets.refresh_interval_seconds,
+        ))
+        .await;
+        let values = match secrets.fetch_secrets(&tracking_label).await {
+            Ok(values) => values,
+            Err(err_msg) => {
+                error!(
+                    "{tracking_label} - keeping current secrets - {err_msg}"
+                );
+                continue;
+            }
+        };
+
+        if has_new_jwt_keys(&config, &values) {
+            match get_jwt_keys_from_secrets(
+                &tracking_label,
+                &config.jwt_key_paths,
+                &values,
+            ) {
+                Ok(Some(new_jwt_keys)) => {
+                    info!(
+                        "{tracking_label} - loaded new jwt keys \
+                        from SECRETS_PROVIDER={}",
+                        secrets.provider_name
+                    );
+                    *config.jwt_keys.write().unwrap() = new_jwt_keys;
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    error!(
+                        "{tracking_label} - \
+                        keeping current jwt keys - reload failed with \
+                        err='{e}'"
+                    );
+                }
+            }
+        }
+
+        for (name, current) in [
+            (&secrets.db_password_name, config.db_password.as_bytes()),
+            (
+                &SECRET_SERVER_PASSWORD_SALT.to_string(),
+                config.server_password_salt.as_slice(),
+            ),
+        ] {
+            if let Some(value) = values.get(name) {
+                if value.as_bytes() != current
+                    && warned.get(name) != Some(value)
+                {
+                    warn!(
+                        "{tracking_label} - {name} changed in \
+                        SECRETS_PROVIDER={} - restart the server to use it",
+                        secrets.provider_name
+                    );
+                    warned.insert(name.clone(), value.clone());
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,459 @@
+//! Fetch the server's secrets from HashiCorp Vault or AWS
+//! Secrets Manager
+//!
+//! With ``SECRETS_PROVIDER`` set, the server fetches one
+//! json object of secrets at startup and uses these keys
+//! before the matching environment variables and files:
+//!
+//! - ``POSTGRES_PASSWORD`` - postgres password (the key
+//!   follows the ``{DB_CERT_NAME}_PASSWORD`` environment
+//!   variable name)
+//! - ``SERVER_PASSWORD_SALT`` - argon2 password salt
+//! - ``TOKEN_ALGO_PRIVATE_KEY`` and ``TOKEN_ALGO_PUBLIC_KEY`` -
+//!   the `default` jwt pem keys (set both or neither)
+//! - ``TOKEN_ALGO_PRIVATE_KEY_PASSPHRASE`` - passphrase for
+//!   encrypted jwt private keys (from the secret or the key
+//!   files)
+//!
+//! Values set on the
+//! [`RestApiServerBuilder`](crate::core::server::rest_api_server::RestApiServerBuilder)
+//! are still used first. Missing keys fall back to the
+//! environment variables and files.
+//!
+//! Built-in providers:
+//!
+//! - ``SECRETS_PROVIDER=vault`` - a HashiCorp Vault kv
+//!   secret
+//!   ([`VaultSecretsProvider`](crate::secrets::vault::VaultSecretsProvider))
+//! - ``SECRETS_PROVIDER=aws`` - an AWS Secrets Manager json
+//!   secret (requires the ``s3`` feature for the aws
+//!   credentials and request signing, see
+//!   [`AwsSecretsManagerProvider`](crate::secrets::aws_secrets_manager::AwsSecretsManagerProvider))
+//!
+//! Set a
+//! [`SecretsProvider`](crate::secrets::secrets_provider::SecretsProvider)
+//! with
+//! [`RestApiServerBuilder::secrets_provider`](crate::core::server::rest_api_server::RestApiServerBuilder::secrets_provider)
+//! for other secrets managers. The secrets are fetched again
+//! every ``SECRETS_REFRESH_INTERVAL_SECONDS`` (see
+//! [`run_secrets_refresher`](crate::secrets::refresh_secrets::run_secrets_refresher)).
+//!
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+
+use crate::core::startup_error::StartupError;
+use crate::jwt::jwt_keys::load_jwt_keys_with_default_keys;
+use crate::jwt::jwt_keys::JwtKeyPaths;
+use crate::jwt::jwt_keys::JwtKeys;
+use crate::monitoring::metric_types::register_int_counter_vec;
+use crate::monitoring::metric_types::IntCounterVec;
+use crate::secrets::aws_secrets_manager::build_aws_secrets_manager_provider;
+use crate::secrets::vault::VaultSecretsProvider;
+
+lazy_static! {
+    pub static ref SECRETS_FETCHES_COUNTER_VEC: IntCounterVec =
+        register_int_counter_vec!(
+            "secrets_fetches_total",
+            "Number of secrets provider fetches by result (ok or error).",
+            &["result"]
+        )
+        .unwrap();
+}
+
+/// secret key for the argon2 password salt
+pub const SECRET_SERVER_PASSWORD_SALT: &str = "SERVER_PASSWORD_SALT";
+
+/// secret key for the `default` jwt private key contents
+pub const SECRET_JWT_PRIVATE_KEY: &str = "TOKEN_ALGO_PRIVATE_KEY";
+
+/// secret key for the `default` jwt public key contents
+pub const SECRET_JWT_PUBLIC_KEY: &str = "TOKEN_ALGO_PUBLIC_KEY";
+
+/// secret key for the encrypted jwt private key passphrase
+pub const SECRET_JWT_PRIVATE_KEY_PASSPHRASE: &str =
+    "TOKEN_ALGO_PRIVATE_KEY_PASSPHRASE";
+
+/// future returned by
+/// [`SecretsProvider::get_secrets`](crate::secrets::secrets_provider::SecretsProvider::get_secrets)
+pub type SecretsProviderFuture<'a> = Pin<
+    Box<
+        dyn Future<Output = Result<HashMap<String, String>, String>>
+            + Send
+            + 'a,
+    >,
+>;
+
+/// SecretsProvider
+///
+/// Hook for fetching the server's secrets from a secrets
+/// manager
+///
+pub trait SecretsProvider: Send + Sync {
+    /// get_secrets
+    ///
+    /// Fetch the current secrets
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - logging label for caller
+    ///
+    /// # Returns
+    ///
+    /// Ok(`HashMap<String, String>`) - secret values by key
+    /// (like ``POSTGRES_PASSWORD``)
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) - the secrets could not be
+    /// fetched
+    ///
+    fn get_secrets<'a>(
+        &'a self,
+        tracking_label: &'a str,
+    ) -> SecretsProviderFuture<'a>;
+}
+
+/// Secrets
+///
+/// Settings for fetching secrets from a secrets manager
+///
+/// # Supported Environment Variables
+///
+/// ```bash
+/// # vault or aws (empty = environment variables and files)
+/// export SECRETS_PROVIDER=""
+/// # fetch the secrets again (0 = only at startup)
+/// export SECRETS_REFRESH_INTERVAL_SECONDS="300"
+/// export SECRETS_TIMEOUT_MS="5000"
+/// # vault kv secret
+/// export VAULT_ADDR="https://vault:8200"
+/// export VAULT_TOKEN=""
+/// # or read the token from a file on each fetch
+/// export VAULT_TOKEN_FILE=""
+/// export VAULT_NAMESPACE=""
+/// export SECRETS_VAULT_PATH="secret/data/restapi"
+/// # aws secrets manager json secret
+/// export SECRETS_AWS_SECRET_ID="restapi"
+/// export SECRETS_AWS_REGION="us-east-2"
+/// ```
+///
+/// # Arguments
+///
+/// * `provider_name` - `String` - ``vault``, ``aws``,
+///   ``custom`` or empty when disabled
+/// * `refresh_interval_seconds` - `u64` - seconds between
+///   fetches after startup (``0`` = only at startup)
+/// * `timeout_ms` - `u64` - max milliseconds for each fetch
+/// * `db_password_name` - `String` - secret key for the
+///   postgres password (``{DB_CERT_NAME}_PASSWORD``)
+/// * `provider` - `Option<Arc<dyn `[`SecretsProvider`](crate::secrets::secrets_provider::SecretsProvider)`>>` -
+///   secrets manager (`None` = disabled)
+///
+#[derive(Clone, Default)]
+pub struct Secrets {
+    pub provider_name: String,
+    pub refresh_interval_seconds: u64,
+    pub timeout_ms: u64,
+    pub db_password_name: String,
+    pub provider: Option<Arc<dyn SecretsProvider>>,
+}
+
+impl Secrets {
+    /// build_secrets
+    ///
+    /// Build the
+    /// [`Secrets`](crate::secrets::secrets_provider::Secrets)
+    /// from environment variables
+    ///
+    /// # Arguments
+    ///
+    /// * `db_cert_name` - `&str` - postgres tls name
+    ///   (``POSTGRES`` by default)
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) for an unsupported
+    /// ``SECRETS_PROVIDER`` or if the provider is missing
+    /// required settings
+    ///
+    pub fn build_secrets(db_cert_name: &str) -> Result<Self, String> {
+        let provider_name = std::env::var("SECRETS_PROVIDER")
+            .unwrap_or_default()
+            .trim()
+            .to_lowercase();
+        let provider: Option<Arc<dyn SecretsProvider>> =
+            match provider_name.as_str() {
+                "" => None,
+                "vault" => Some(Arc::new(
+                    VaultSecretsProvider::build_vault_secrets_provider()?,
+                )),
+                "aws" => Some(build_aws_secrets_manager_provider(
+                    &std::env::var("SECRETS_AWS_SECRET_ID")
+                        .unwrap_or_else(|_| "restapi".to_string()),
+                    &std::env::var("SECRETS_AWS_REGION")
+                        .unwrap_or_else(|_| "us-east-2".to_string()),
+                )?),
+                _ => {
+                    return Err(format!(
+                        "invalid SECRETS_PROVIDER={provider_name} \
+                        must be vault or aws"
+                    ))
+                }
+            };
+        Ok(Secrets {
+            provider_name,
+            refresh_interval_seconds: std::env::var(
+                "SECRETS_REFRESH_INTERVAL_SECONDS",
+            )
+            .unwrap_or_else(|_| "300".to_string())
+            .parse::<u64>()
+            .unwrap_or(300),
+            timeout_ms: std::env::var("SECRETS_TIMEOUT_MS")
+                .unwrap_or_else(|_| "5000".to_string())
+                .parse::<u64>()
+                .unwrap_or(5000)
+                .max(1),
+            db_password_name: get_db_password_secret_name(db_cert_name),
+            provider,
+        })
+    }
+
+    /// is_enabled
+    ///
+    /// Is a secrets provider set
+    ///
+    pub fn is_enabled(&self) -> bool {
+        self.provider.is_some()
+    }
+
+    /// fetch_secrets
+    ///
+    /// Fetch the current secrets from the provider
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - caller logging label
+    ///
+    /// # Returns
+    ///
+    /// Ok(`HashMap<String, String>`) - secret values by key
+    /// (empty when disabled)
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) if the provider failed or did
+    /// not reply within ``SECRETS_TIMEOUT_MS``
+    ///
+    pub async fn fetch_secrets(
+        &self,
+        tracking_label: &str,
+    ) -> Result<HashMap<String, String>, String> {
+        let provider = match &self.provider {
+            Some(provider) => provider,
+            None => return Ok(HashMap::new()),
+        };
+        let result = match tokio::time::timeout(
+            Duration::from_millis(self.timeout_ms),
+            provider.get_secrets(tracking_label),
+        )
+        .await
+        {
+            Ok(result) => result,
+            Err(_) => Err(format!(
+                "timed out after SECRETS_TIMEOUT_MS={}",
+                self.timeout_ms
+            )),
+        };
+        match result {
+            Ok(values) => {
+                SECRETS_FETCHES_COUNTER_VEC.with_label_values(&["ok"]).inc();
+                info!(
+                    "{tracking_label} - fetched {} secrets from \
+                    SECRETS_PROVIDER={}",
+                    values.len(),
+                    self.provider_name
+                );
+                Ok(values)
+            }
+            Err(err_msg) => {
+                SECRETS_FETCHES_COUNTER_VEC
+                    .with_label_values(&["error"])
+                    .inc();
+                Err(format!(
+                    "failed to fetch secrets from SECRETS_PROVIDER={} \
+                    with err='{err_msg}'",
+                    self.provider_name
+                ))
+            }
+        }
+    }
+}
+
+/// get_db_password_secret_name
+///
+/// Get the secret key for the postgres password
+///
+/// # Arguments
+///
+/// * `db_cert_name` - `&str` - postgres tls name
+///
+/// # Examples
+///
+/// ```rust
+/// use restapi::secrets::secrets_provider::get_db_password_secret_name;
+/// assert_eq!(get_db_password_secret_name("postgres"), "POSTGRES_PASSWORD");
+/// ```
+///
+pub fn get_db_password_secret_name(db_cert_name: &str) -> String {
+    format!("{db_cert_name}_PASSWORD").to_uppercase()
+}
+
+/// get_secrets_from_json
+///
+/// Convert a json object of secrets into a map. Numbers and
+/// booleans are converted to strings and nulls are skipped.
+///
+/// # Arguments
+///
+/// * `value` - [`Value`](serde_json::Value) - json object
+///
+/// # Errors
+///
+/// Err(err_msg: `String`) if the value is not a json object
+///
+/// # Examples
+///
+/// ```rust
+/// use restapi::secrets::secrets_provider::get_secrets_from_json;
+/// let secrets = get_secrets_from_json(&serde_json::json!({
+///     "POSTGRES_PASSWORD": "pw",
+///     "PORT": 5432,
+///     "UNSET": null
+/// }))
+/// .unwrap();
+/// assert_eq!(secrets.get("POSTGRES_PASSWORD").unwrap(), "pw");
+/// assert_eq!(secrets.get("PORT").unwrap(), "5432");
+/// assert!(secrets.get("UNSET").is_none());
+/// assert!(get_secrets_from_json(&serde_json::json!("pw")).is_err());
+/// ```
+///
+pub fn get_secrets_from_json(
+    value: &serde_json::Value,
+) -> Result<HashMap<String, String>, String> {
+    let object = value
+        .as_object()
+        .ok_or_else(|| "secrets must be a json object".to_string())?;
+    Ok(object
+        .iter()
+        .filter(|(_, v)| !v.is_null())
+        .map(|(k, v)| {
+            let value = match v.as_str() {
+                Some(v) => v.to_string(),
+                None => v.to_string(),
+            };
+            (k.clone(), value)
+        })
+        .collect())
+}
+
+/// get_jwt_key_paths_with_secrets
+///
+/// Use the ``TOKEN_ALGO_PRIVATE_KEY_PASSPHRASE`` secret (if
+/// set) for encrypted jwt private keys
+///
+/// # Arguments
+///
+/// * `jwt_key_paths` - [`JwtKeyPaths`](crate::jwt::jwt_keys::JwtKeyPaths) -
+///   key directory, `default` key paths and passphrase
+/// * `secrets` - `&HashMap<String, String>` - fetched secrets
+///
+/// # Examples
+///
+/// ```rust
+/// use std::collections::HashMap;
+/// use restapi::jwt::jwt_keys::JwtKeyPaths;
+/// use restapi::secrets::secrets_provider::get_jwt_key_paths_with_secrets;
+/// let jwt_key_paths = JwtKeyPaths::default();
+/// let mut secrets = HashMap::new();
+/// assert_eq!(
+///     get_jwt_key_paths_with_secrets(&jwt_key_paths, &secrets)
+///         .private_key_passphrase,
+///     ""
+/// );
+/// secrets.insert(
+///     "TOKEN_ALGO_PRIVATE_KEY_PASSPHRASE".to_string(),
+///     "pw".to_string(),
+/// );
+/// assert_eq!(
+///     get_jwt_key_paths_with_secrets(&jwt_key_paths, &secrets)
+///         .private_key_passphrase,
+///     "pw"
+/// );
+/// ```
+///
+pub fn get_jwt_key_paths_with_secrets(
+    jwt_key_paths: &JwtKeyPaths,
+    secrets: &HashMap<String, String>,
+) -> JwtKeyPaths {
+    let mut jwt_key_paths = jwt_key_paths.clone();
+    if let Some(passphrase) = secrets.get(SECRET_JWT_PRIVATE_KEY_PASSPHRASE) {
+        jwt_key_paths.private_key_passphrase = passphrase.clone();
+    }
+    jwt_key_paths
+}
+
+/// get_jwt_keys_from_secrets
+///
+/// Load the jwt key ring with the `default` keys from the
+/// ``TOKEN_ALGO_PRIVATE_KEY`` and ``TOKEN_ALGO_PUBLIC_KEY``
+/// secrets and any rotated `<kid>.` prefixed key files
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `jwt_key_paths` - [`JwtKeyPaths`](crate::jwt::jwt_keys::JwtKeyPaths) -
+///   directory with any rotated keys
+/// * `secrets` - `&HashMap<String, String>` - fetched secrets
+///
+/// # Returns
+///
+/// Ok(`Option<`[`JwtKeys`](crate::jwt::jwt_keys::JwtKeys)`>`) -
+/// `None` when the secrets do not have the jwt keys
+///
+/// # Errors
+///
+/// Err([`StartupError`](crate::core::startup_error::StartupError))
+/// if only one of the keys is set or the keys are not valid
+/// ES256 pem keys
+///
+pub fn get_jwt_keys_from_secrets(
+    tracking_label: &str,
+    jwt_key_paths: &JwtKeyPaths,
+    secrets: &HashMap<String, String>,
+) -> Result<Option<JwtKeys>, StartupError> {
+    match (
+        secrets.get(SECRET_JWT_PRIVATE_KEY),
+        secrets.get(SECRET_JWT_PUBLIC_KEY),
+    ) {
+        (None, None) => Ok(None),
+        (Some(private_key), Some(public_key)) => {
+            load_jwt_keys_with_default_keys(
+                tracking_label,
+                &get_jwt_key_paths_with_secrets(jwt_key_paths, secrets),
+                private_key.as_bytes().to_vec(),
+                public_key.as_bytes().to_vec(),
+            )
+            .map(Some)
+        }
+        _ => Err(StartupError::Config(format!(
+            "the secrets provider must have both \
+            {SECRET_JWT_PRIVATE_KEY} and {SECRET_JWT_PUBLIC_KEY} \
+            or neither"
+        ))),
+    }
+}
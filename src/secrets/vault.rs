@@ -0,0 +1,245 @@
+//! Fetch secrets from a HashiCorp Vault kv secret
+//!
+//! ``SECRETS_PROVIDER=vault`` reads the secret at
+//! ``SECRETS_VAULT_PATH`` with ``GET VAULT_ADDR/v1/PATH``.
+//! Both kv version 2 (``secret/data/restapi``) and version 1
+//! (``kv/restapi``) engines are supported. Store the keys
+//! with:
+//!
+//! ```bash
+//! vault kv put secret/restapi \
+//!     POSTGRES_PASSWORD="..." \
+//!     SERVER_PASSWORD_SALT="..." \
+//!     TOKEN_ALGO_PRIVATE_KEY=@./jwt/private-key-pkcs8.pem \
+//!     TOKEN_ALGO_PUBLIC_KEY=@./jwt/public-key.pem
+//! ```
+//!
+//! Set ``VAULT_TOKEN_FILE`` (instead of ``VAULT_TOKEN``) to
+//! read a token that is renewed by a vault agent on each
+//! fetch.
+//!
+use std::collections::HashMap;
+
+use hyper::client::HttpConnector;
+use hyper::Body;
+use hyper::Client;
+use hyper::Method;
+use hyper::Request;
+
+use hyper_tls::HttpsConnector;
+
+use crate::secrets::secrets_provider::get_secrets_from_json;
+use crate::secrets::secrets_provider::SecretsProvider;
+use crate::secrets::secrets_provider::SecretsProviderFuture;
+
+/// VaultSecretsProvider
+///
+/// Fetch secrets from a HashiCorp Vault kv secret
+///
+/// # Arguments
+///
+/// * `address` - `String` - vault address
+///   (``VAULT_ADDR``)
+/// * `path` - `String` - kv secret path
+///   (``SECRETS_VAULT_PATH``)
+/// * `token` - `String` - vault token (``VAULT_TOKEN``)
+/// * `token_file` - `String` - file with the vault token
+///   read on each fetch (``VAULT_TOKEN_FILE``)
+/// * `namespace` - `String` - vault enterprise namespace
+///   (``VAULT_NAMESPACE``)
+///
+pub struct VaultSecretsProvider {
+    pub address: String,
+    pub path: String,
+    pub token: String,
+    pub token_file: String,
+    pub namespace: String,
+    client: Client<HttpsConnector<HttpConnector>>,
+}
+
+impl VaultSecretsProvider {
+    /// new
+    ///
+    /// Build a vault provider for a token
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - `&str` - vault address
+    /// * `path` - `&str` - kv secret path
+    /// * `token` - `&str` - vault token
+    ///
+    pub fn new(address: &str, path: &str, token: &str) -> Self {
+        VaultSecretsProvider {
+            address: address.to_string(),
+            path: path.to_string(),
+            token: token.to_string(),
+            token_file: "".to_string(),
+            namespace: "".to_string(),
+            client: Client::builder().build(HttpsConnector::new()),
+        }
+    }
+
+    /// build_vault_secrets_provider
+    ///
+    /// Build a
+    /// [`VaultSecretsProvider`](crate::secrets::vault::VaultSecretsProvider)
+    /// from environment variables
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) if ``VAULT_ADDR`` or both
+    /// ``VAULT_TOKEN`` and ``VAULT_TOKEN_FILE`` are not set
+    ///
+    pub fn build_vault_secrets_provider() -> Result<Self, String> {
+        let address = std::env::var("VAULT_ADDR")
+            .unwrap_or_default()
+            .trim()
+            .to_string();
+        if address.is_empty() {
+            return Err(
+                "SECRETS_PROVIDER=vault requires VAULT_ADDR".to_string()
+            );
+        }
+        let token = std::env::var("VAULT_TOKEN").unwrap_or_default();
+        let token_file = std::env::var("VAULT_TOKEN_FILE").unwrap_or_default();
+        if token.is_empty() && token_file.is_empty() {
+            return Err("SECRETS_PROVIDER=vault requires VAULT_TOKEN \
+                or VAULT_TOKEN_FILE"
+                .to_string());
+        }
+        let mut provider = VaultSecretsProvider::new(
+            &address,
+            &std::env::var("SECRETS_VAULT_PATH")
+                .unwrap_or_else(|_| "secret/data/restapi".to_string()),
+            token.trim(),
+        );
+        provider.token_file = token_file;
+        provider.namespace =
+            std::env::var("VAULT_NAMESPACE").unwrap_or_default();
+        Ok(provider)
+    }
+
+    /// get_token
+    ///
+    /// Get the vault token (reading ``VAULT_TOKEN_FILE`` if
+    /// set)
+    ///
+    async fn get_token(&self) -> Result<String, String> {
+        if self.token_file.is_empty() {
+            return Ok(self.token.clone());
+        }
+        tokio::fs::read_to_string(&self.token_file)
+            .await
+            .map(|v| v.trim().to_string())
+            .map_err(|e| {
+                format!(
+                    "failed to read VAULT_TOKEN_FILE={} with err='{e}'",
+                    self.token_file
+                )
+            })
+    }
+}
+
+impl SecretsProvider for VaultSecretsProvider {
+    fn get_secrets<'a>(
+        &'a self,
+        _tracking_label: &'a str,
+    ) -> SecretsProviderFuture<'a> {
+        Box::pin(async move {
+            let token = self.get_token().await?;
+            let mut req = Request::builder()
+                .method(Method::GET)
+                .uri(get_vault_secret_url(&self.address, &self.path))
+                .header("X-Vault-Token", token);
+            if !self.namespace.is_empty() {
+                req = req.header("X-Vault-Namespace", &self.namespace);
+            }
+            let req = req.body(Body::empty()).map_err(|e| {
+                format!(
+                    "invalid VAULT_ADDR or SECRETS_VAULT_PATH with err='{e}'"
+                )
+            })?;
+            let res =
+                self.client.request(req).await.map_err(|e| {
+                    format!("vault request failed with err='{e}'")
+                })?;
+            if !res.status().is_success() {
+                return Err(format!(
+                    "vault returned status={} for path={}",
+                    res.status(),
+                    self.path
+                ));
+            }
+            let bytes = hyper::body::to_bytes(res.into_body())
+                .await
+                .map_err(|e| format!("vault request failed with err='{e}'"))?;
+            parse_vault_secret(&bytes)
+        })
+    }
+}
+
+/// get_vault_secret_url
+///
+/// Build the vault api url for a kv secret path
+///
+/// # Arguments
+///
+/// * `address` - `&str` - vault address
+/// * `path` - `&str` - kv secret path
+///
+/// # Examples
+///
+/// ```rust
+/// use restapi::secrets::vault::get_vault_secret_url;
+/// assert_eq!(
+///     get_vault_secret_url("https://vault:8200/", "/secret/data/restapi"),
+///     "https://vault:8200/v1/secret/data/restapi"
+/// );
+/// ```
+///
+pub fn get_vault_secret_url(address: &str, path: &str) -> String {
+    format!(
+        "{}/v1/{}",
+        address.trim_end_matches('/'),
+        path.trim_start_matches('/')
+    )
+}
+
+/// parse_vault_secret
+///
+/// Get the secrets from a vault kv read response (kv version
+/// 2 nests the secret in ``data.data`` next to
+/// ``data.metadata``)
+///
+/// # Arguments
+///
+/// * `body` - `&[u8]` - vault json response
+///
+/// # Errors
+///
+/// Err(err_msg: `String`) if the response has no secret
+///
+/// # Examples
+///
+/// ```rust
+/// use restapi::secrets::vault::parse_vault_secret;
+/// let kv2 = br#"{"data":{"data":{"POSTGRES_PASSWORD":"pw"},"metadata":{"version":3}}}"#;
+/// assert_eq!(parse_vault_secret(kv2).unwrap().get("POSTGRES_PASSWORD").unwrap(), "pw");
+/// let kv1 = br#"{"data":{"POSTGRES_PASSWORD":"pw"}}"#;
+/// assert_eq!(parse_vault_secret(kv1).unwrap().get("POSTGRES_PASSWORD").unwrap(), "pw");
+/// assert!(parse_vault_secret(br#"{"errors":[]}"#).is_err());
+/// ```
+///
+pub fn parse_vault_secret(
+    body: &[u8],
+) -> Result<HashMap<String, String>, String> {
+    let value: serde_json::Value = serde_json::from_slice(body)
+        .map_err(|e| format!("vault returned invalid json with err='{e}'"))?;
+    let data = value
+        .get("data")
+        .ok_or_else(|| "vault response has no data".to_string())?;
+    match (data.get("data"), data.get("metadata")) {
+        (Some(secret), Some(_)) => get_secrets_from_json(secret),
+        _ => get_secrets_from_json(data),
+    }
+}
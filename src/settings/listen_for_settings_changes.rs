@@ -0,0 +1,277 @@
+//! Keep each api server's cached
+//! [`RuntimeSettings`](crate::settings::runtime_settings::RuntimeSettings)
+//! and ip rules in sync with the ``settings`` and ``ip_rules``
+//! tables
+//!
+//! The ``settings`` and ``ip_rules`` tables have triggers
+//! that send a ``settings_changed`` or ``ip_rules_changed``
+//! notification on every insert, update and delete. Each api
+//! server holds one dedicated (non-pooled) postgres connection
+//! that listens on both channels and reloads the changed
+//! table when a notification arrives.
+//!
+use std::time::Duration;
+
+use futures::channel::mpsc::unbounded;
+use futures::StreamExt;
+
+use postgres_native_tls::MakeTlsConnector;
+use tokio_postgres::AsyncMessage;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use crate::core::core_config::CoreConfig;
+use crate::core::server::ip_access::IpAccessRule;
+use crate::pools::get_db_pool::get_db_conn_str;
+use crate::pools::get_db_pool::get_db_tls_connector;
+use crate::requests::models::ip_rule::get_ip_rules;
+use crate::requests::models::setting::get_settings;
+use crate::settings::runtime_settings::RuntimeSettings;
+
+/// seconds to wait before reconnecting the listener
+const SETTINGS_LISTENER_RECONNECT_SECONDS: u64 = 5;
+
+/// reload_settings
+///
+/// Reload the
+/// [`RuntimeSettings`](crate::settings::runtime_settings::RuntimeSettings)
+/// from environment variables and the ``settings`` table
+/// and store them on the
+/// [`CoreConfig`](crate::core::core_config::CoreConfig).
+/// The current settings are kept if the reload fails.
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client db
+///   threadpool with required tls encryption
+///
+/// # Errors
+///
+/// Err(err_msg: `String`)
+///
+pub async fn reload_settings(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+) -> Result<(), String> {
+    let conn = match db_pool.get().await {
+        Ok(conn) => conn,
+        Err(e) => {
+            return Err(format!(
+                "{tracking_label} - \
+                failed to get a db connection for reloading settings \
+                with err='{e}'"
+            ));
+        }
+    };
+    let overrides = get_settings(tracking_label, &conn).await?;
+    let new_settings =
+        RuntimeSettings::build_from_overrides(tracking_label, &overrides);
+    *config.settings.write().unwrap() = new_settings;
+    info!(
+        "{tracking_label} - loaded settings with {} overrides",
+        overrides.len()
+    );
+    Ok(())
+}
+
+/// reload_ip_rules
+///
+/// Reload the ``ip_rules`` table rules on the
+/// [`IpAccessControl`](crate::core::server::ip_access::IpAccessControl).
+/// Invalid rows are logged and skipped, and the current
+/// rules are kept if the reload fails.
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client db
+///   threadpool with required tls encryption
+///
+/// # Errors
+///
+/// Err(err_msg: `String`)
+///
+pub async fn reload_ip_rules(
+    tracking_label: &str,
+    config: &CoreConfig,
+    db_pool: &Pool<PostgresConnectionManager<MakeTlsConnector>>,
+) -> Result<(), String> {
+    let conn = match db_pool.get().await {
+        Ok(conn) => conn,
+        Err(e) => {
+            return Err(format!(
+                "{tracking_label} - \
+                failed to get a db connection for reloading ip rules \
+                with err='{e}'"
+            ));
+        }
+    };
+    let mut rules: Vec<IpAccessRule> = Vec::new();
+    for ip_rule in get_ip_rules(tracking_label, &conn).await? {
+        match IpAccessRule::parse_rule(
+            &ip_rule.action,
+            &ip_rule.cidr,
+            &ip_rule.path_prefix,
+        ) {
+            Ok(rule) => rules.push(rule),
+            Err(err_msg) => {
+                error!(
+                    "{tracking_label} - \
+                    skipping ip rule={} - {err_msg}",
+                    ip_rule.id
+                );
+            }
+        }
+    }
+    info!("{tracking_label} - loaded {} ip rules", rules.len());
+    config.ip_access.set_db_rules(rules);
+    Ok(())
+}
+
+/// listen_for_settings_changes
+///
+/// Run ``LISTEN settings_changed`` and
+/// ``LISTEN ip_rules_changed`` on a dedicated postgres
+/// connection and call
+/// [`reload_settings`](crate::settings::listen_for_settings_changes::reload_settings)
+/// and
+/// [`reload_ip_rules`](crate::settings::listen_for_settings_changes::reload_ip_rules)
+/// on startup and after each table's notifications.
+/// Reconnects (and reloads) if the connection is lost so
+/// changes made while disconnected are not missed.
+///
+/// # Arguments
+///
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client db
+///   threadpool with required tls encryption
+///
+pub async fn listen_for_settings_changes(
+    config: CoreConfig,
+    db_pool: Pool<PostgresConnectionManager<MakeTlsConnector>>,
+) {
+    let tracking_label = format!("{} - settings", config.label);
+    loop {
+        let connector = get_db_tls_connector(&config);
+        let (db_conn_str, db_conn_no_password) = get_db_conn_str(&config);
+        match tokio_postgres::connect(&db_conn_str, connector).await {
+            Ok((client, mut connection)) => {
+                // drive the connection and forward notifications
+                let (tx, mut rx) = unbounded::<(String, String)>();
+                let conn_label = tracking_label.clone();
+                let mut messages = futures::stream::poll_fn(move |cx| {
+                    connection.poll_message(cx)
+                });
+                tokio::spawn(async move {
+                    while let Some(message) = messages.next().await {
+                        match message {
+                            Ok(AsyncMessage::Notification(notification)) => {
+                                if tx
+                                    .unbounded_send((
+                                        notification.channel().to_string(),
+                                        notification.payload().to_string(),
+                                    ))
+                                    .is_err()
+                                {
+                                    break;
+                                }
+                            }
+                            Ok(_) => {}
+                            Err(e) => {
+                                error!(
+                                    "{conn_label} - \
+                                    settings listener connection failed \
+                                    with err='{e}'"
+                                );
+                                break;
+                            }
+                        }
+                    }
+                });
+                match client
+                    .batch_execute(
+                        "LISTEN settings_changed; LISTEN ip_rules_changed;",
+                    )
+                    .await
+                {
+                    Ok(_) => {
+                        // load after LISTEN so no change is missed
+                        if let Err(err_msg) =
+                            reload_settings(&tracking_label, &config, &db_pool)
+                                .await
+                        {
+                            error!("{err_msg}");
+                        }
+                        if let Err(err_msg) =
+                            reload_ip_rules(&tracking_label, &config, &db_pool)
+                                .await
+                        {
+                            error!("{err_msg}");
+                        }
+                        while let Some((channel, key)) = rx.next().await {
+                            let result = match channel.as_str() {
+                                "ip_rules_changed" => {
+                                    info!(
+                                        "{tracking_label} - \
+                                        ip rule {key} changed - \
+                                        reloading ip rules"
+                                    );
+                                    reload_ip_rules(
+                                        &tracking_label,
+                                        &config,
+                                        &db_pool,
+                                    )
+                                    .await
+                                }
+                                _ => {
+                                    info!(
+                                        "{tracking_label} - \
+                                        setting {key} changed - \
+                                        reloading settings"
+                                    );
+                                    reload_settings(
+                                        &tracking_label,
+                                        &config,
+                                        &db_pool,
+                                    )
+                                    .await
+                                }
+                            };
+                            if let Err(err_msg) = result {
+                                error!("{err_msg}");
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        error!(
+                            "{tracking_label} - \
+                            failed to listen for settings changes \
+                            with err='{e}'"
+                        );
+                    }
+                }
+            }
+            Err(e) => {
+                error!(
+                    "{tracking_label} - \
+                    failed to connect settings listener to \
+                    {db_conn_no_password} with err='{e}'"
+                );
+            }
+        }
+        warn!(
+            "{tracking_label} - \
+            reconnecting settings listener in \
+            {SETTINGS_LISTENER_RECONNECT_SECONDS}s"
+        );
+        tokio::time::sleep(Duration::from_secs(
+            SETTINGS_LISTENER_RECONNECT_SECONDS,
+        ))
+        .await;
+    }
+}
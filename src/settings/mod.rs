@@ -0,0 +1,13 @@
+//! Cluster-wide runtime settings that admins can change
+//! without redeploying
+//!
+//! Values start from environment variables and are
+//! overridden by rows in the ``settings`` table. Each api
+//! server caches the values in
+//! [`RuntimeSettings`](crate::settings::runtime_settings::RuntimeSettings)
+//! and reloads them when postgres sends a
+//! ``settings_changed`` notification (see
+//! [`listen_for_settings_changes`](crate::settings::listen_for_settings_changes::listen_for_settings_changes))
+//!
+pub mod listen_for_settings_changes;
+pub mod runtime_settings;
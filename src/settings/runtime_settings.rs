@@ -0,0 +1,386 @@
+//! Runtime settings cached by each api server
+//!
+//! ## Supported Settings
+//!
+//! Key                               | Type   | Environment Variable
+//! --------------------------------- | ------ | --------------------
+//! login_throttle_enabled            | bool   | LOGIN_THROTTLE_ENABLED
+//! login_throttle_max_failures       | int    | LOGIN_THROTTLE_MAX_FAILURES
+//! login_throttle_base_delay_seconds | int    | LOGIN_THROTTLE_BASE_DELAY_SECONDS
+//! login_throttle_max_delay_seconds  | int    | LOGIN_THROTTLE_MAX_DELAY_SECONDS
+//! login_throttle_reset_seconds      | int    | LOGIN_THROTTLE_RESET_SECONDS
+//! verification_required             | bool   | USER_EMAIL_VERIFICATION_REQUIRED
+//! max_upload_size_bytes             | int    | MAX_UPLOAD_SIZE_BYTES
+//! maintenance_mode                  | bool   | MAINTENANCE_MODE
+//! maintenance_retry_after_seconds   | int    | MAINTENANCE_RETRY_AFTER_SECONDS
+//! jwt_signing_kid                   | string | TOKEN_SIGNING_KID
+//! jwt_retired_kids                  | list   | TOKEN_RETIRED_KIDS
+//! password_min_length               | int    | PASSWORD_MIN_LENGTH
+//! password_require_digit            | bool   | PASSWORD_REQUIRE_DIGIT
+//! password_require_symbol           | bool   | PASSWORD_REQUIRE_SYMBOL
+//! password_require_mixed_case       | bool   | PASSWORD_REQUIRE_MIXED_CASE
+//!
+use std::sync::Arc;
+use std::sync::RwLock;
+
+use hyper::Method;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::requests::auth::login_throttle::LoginThrottle;
+use crate::requests::models::setting::ModelSetting;
+use crate::requests::user::is_verification_required::is_verification_required;
+use crate::requests::validation::field_rules::MAX_PASSWORD_LEN;
+use crate::requests::validation::field_rules::MIN_PASSWORD_LEN;
+use crate::requests::validation::validate_password_policy::PasswordPolicy;
+
+/// supported ``settings.key`` values
+pub const SUPPORTED_SETTINGS: [&str; 15] = [
+    "login_throttle_enabled",
+    "login_throttle_max_failures",
+    "login_throttle_base_delay_seconds",
+    "login_throttle_max_delay_seconds",
+    "login_throttle_reset_seconds",
+    "verification_required",
+    "max_upload_size_bytes",
+    "maintenance_mode",
+    "maintenance_retry_after_seconds",
+    "jwt_signing_kid",
+    "jwt_retired_kids",
+    "password_min_length",
+    "password_require_digit",
+    "password_require_symbol",
+    "password_require_mixed_case",
+];
+
+/// RuntimeSettings
+///
+/// Settings that can change while the server is running
+///
+/// # Supported Environment Variables
+///
+/// ```bash
+/// # max upload size in bytes (0 = unlimited)
+/// export MAX_UPLOAD_SIZE_BYTES="0"
+/// # reject non-admin requests with a 503
+/// export MAINTENANCE_MODE="0"
+/// # Retry-After header for requests rejected in
+/// # maintenance mode
+/// export MAINTENANCE_RETRY_AFTER_SECONDS="60"
+/// # pin the jwt signing kid (empty = newest loaded key)
+/// export TOKEN_SIGNING_KID=""
+/// # comma-separated jwt kids that are no longer accepted
+/// export TOKEN_RETIRED_KIDS=""
+/// ```
+///
+/// # Arguments
+///
+/// * `login_throttle` - [`LoginThrottle`](crate::requests::auth::login_throttle::LoginThrottle) -
+///   failed login throttling
+/// * `verification_required` - `bool` - users must verify
+///   their email before logging in
+/// * `max_upload_size_bytes` - `i64` - max user data upload
+///   size (`0` = unlimited)
+/// * `maintenance_mode` - `bool` - reject requests
+///   with a `503` except for logins, admin apis, health
+///   checks and metrics
+/// * `maintenance_retry_after_seconds` - `i64` - seconds in
+///   the ``Retry-After`` header of rejected requests
+/// * `jwt_signing_kid` - `String` - sign new tokens with this
+///   jwt kid (empty = newest loaded key)
+/// * `jwt_retired_kids` - `Vec<String>` - reject tokens signed
+///   with these jwt kids
+/// * `password_policy` - [`PasswordPolicy`](crate::requests::validation::validate_password_policy::PasswordPolicy) -
+///   rules for new and changed passwords
+///
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct RuntimeSettings {
+    pub login_throttle: LoginThrottle,
+    pub verification_required: bool,
+    pub max_upload_size_bytes: i64,
+    pub maintenance_mode: bool,
+    pub maintenance_retry_after_seconds: i64,
+    pub jwt_signing_kid: String,
+    pub jwt_retired_kids: Vec<String>,
+    pub password_policy: PasswordPolicy,
+}
+
+/// shared runtime settings on the
+/// [`CoreConfig`](crate::core::core_config::CoreConfig)
+pub type SharedRuntimeSettings = Arc<RwLock<RuntimeSettings>>;
+
+/// parse_setting_bool
+///
+/// Parse a `bool` setting value
+/// (``1``/``true`` or ``0``/``false``)
+///
+fn parse_setting_bool(key: &str, value: &str) -> Result<bool, String> {
+    match value.trim() {
+        "1" | "true" => Ok(true),
+        "0" | "false" => Ok(false),
+        _ => Err(format!("{key} must be true or false")),
+    }
+}
+
+/// parse_setting_int
+///
+/// Parse an `i64` setting value between `min` and
+/// `max` (inclusive)
+///
+fn parse_setting_int(
+    key: &str,
+    value: &str,
+    min: i64,
+    max: i64,
+) -> Result<i64, String> {
+    match value.trim().parse::<i64>() {
+        Ok(v) if v >= min && v <= max => Ok(v),
+        _ => Err(format!("{key} must be an integer between {min} and {max}")),
+    }
+}
+
+/// parse_setting_kid
+///
+/// Parse a jwt kid setting value (empty or up to 128
+/// ``a-z``, ``A-Z``, ``0-9``, ``.``, ``_`` or ``-`` characters)
+///
+fn parse_setting_kid(key: &str, value: &str) -> Result<String, String> {
+    let kid = value.trim();
+    if kid.len() > 128
+        || !kid
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+    {
+        return Err(format!(
+            "{key} must be a jwt kid with letters, numbers, '.', '_' or '-'"
+        ));
+    }
+    Ok(kid.to_string())
+}
+
+/// parse_setting_kids
+///
+/// Parse a comma-separated list of jwt kids into a
+/// sorted list without duplicates
+///
+fn parse_setting_kids(key: &str, value: &str) -> Result<Vec<String>, String> {
+    let mut kids: Vec<String> = Vec::new();
+    for kid in value.split(',') {
+        let kid = parse_setting_kid(key, kid)?;
+        if !kid.is_empty() && !kids.contains(&kid) {
+            kids.push(kid);
+        }
+    }
+    kids.sort();
+    Ok(kids)
+}
+
+impl RuntimeSettings {
+    /// build_runtime_settings
+    ///
+    /// Build the
+    /// [`RuntimeSettings`](crate::settings::runtime_settings::RuntimeSettings)
+    /// from environment variables (before applying the
+    /// db overrides)
+    ///
+    pub fn build_runtime_settings() -> Self {
+        RuntimeSettings {
+            login_throttle: LoginThrottle::build_login_throttle(),
+            verification_required: is_verification_required(),
+            max_upload_size_bytes: std::env::var("MAX_UPLOAD_SIZE_BYTES")
+                .unwrap_or_else(|_| "0".to_string())
+                .parse::<i64>()
+                .unwrap_or(0)
+                .max(0),
+            maintenance_mode: parse_setting_bool(
+                "MAINTENANCE_MODE",
+                &std::env::var("MAINTENANCE_MODE")
+                    .unwrap_or_else(|_| "0".to_string()),
+            )
+            .unwrap_or(false),
+            maintenance_retry_after_seconds: parse_setting_int(
+                "MAINTENANCE_RETRY_AFTER_SECONDS",
+                &std::env::var("MAINTENANCE_RETRY_AFTER_SECONDS")
+                    .unwrap_or_else(|_| "60".to_string()),
+                1,
+                86400,
+            )
+            .unwrap_or(60),
+            jwt_signing_kid: parse_setting_kid(
+                "TOKEN_SIGNING_KID",
+                &std::env::var("TOKEN_SIGNING_KID").unwrap_or_default(),
+            )
+            .unwrap_or_default(),
+            jwt_retired_kids: parse_setting_kids(
+                "TOKEN_RETIRED_KIDS",
+                &std::env::var("TOKEN_RETIRED_KIDS").unwrap_or_default(),
+            )
+            .unwrap_or_default(),
+            password_policy: PasswordPolicy::build_password_policy(),
+        }
+    }
+
+    /// apply_setting
+    ///
+    /// Validate and apply one setting
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - `&str` - setting name from
+    ///   [`SUPPORTED_SETTINGS`](crate::settings::runtime_settings::SUPPORTED_SETTINGS)
+    /// * `value` - `&str` - setting value
+    ///
+    /// # Returns
+    ///
+    /// Ok(value: `String`) - normalized value for storing in the db
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) for unsupported keys or invalid values
+    ///
+    pub fn apply_setting(
+        &mut self,
+        key: &str,
+        value: &str,
+    ) -> Result<String, String> {
+        let throttle = &mut self.login_throttle;
+        let password_policy = &mut self.password_policy;
+        match key {
+            "login_throttle_enabled" => {
+                throttle.enabled = parse_setting_bool(key, value)?;
+                Ok(format!("{}", throttle.enabled))
+            }
+            "login_throttle_max_failures" => {
+                throttle.max_failures =
+                    parse_setting_int(key, value, 1, 1000)? as i32;
+                Ok(format!("{}", throttle.max_failures))
+            }
+            "login_throttle_base_delay_seconds" => {
+                throttle.base_delay_seconds =
+                    parse_setting_int(key, value, 1, 86400)?;
+                Ok(format!("{}", throttle.base_delay_seconds))
+            }
+            "login_throttle_max_delay_seconds" => {
+                throttle.max_delay_seconds =
+                    parse_setting_int(key, value, 1, 604800)?;
+                Ok(format!("{}", throttle.max_delay_seconds))
+            }
+            "login_throttle_reset_seconds" => {
+                throttle.reset_seconds =
+                    parse_setting_int(key, value, 1, 2592000)?;
+                Ok(format!("{}", throttle.reset_seconds))
+            }
+            "verification_required" => {
+                self.verification_required = parse_setting_bool(key, value)?;
+                Ok(format!("{}", self.verification_required))
+            }
+            "max_upload_size_bytes" => {
+                self.max_upload_size_bytes =
+                    parse_setting_int(key, value, 0, i64::MAX)?;
+                Ok(format!("{}", self.max_upload_size_bytes))
+            }
+            "maintenance_mode" => {
+                self.maintenance_mode = parse_setting_bool(key, value)?;
+                Ok(format!("{}", self.maintenance_mode))
+            }
+            "maintenance_retry_after_seconds" => {
+                self.maintenance_retry_after_seconds =
+                    parse_setting_int(key, value, 1, 86400)?;
+                Ok(format!("{}", self.maintenance_retry_after_seconds))
+            }
+            "jwt_signing_kid" => {
+                self.jwt_signing_kid = parse_setting_kid(key, value)?;
+                Ok(self.jwt_signing_kid.clone())
+            }
+            "jwt_retired_kids" => {
+                self.jwt_retired_kids = parse_setting_kids(key, value)?;
+                Ok(self.jwt_retired_kids.join(","))
+            }
+            "password_min_length" => {
+                password_policy.min_length = parse_setting_int(
+                    key,
+                    value,
+                    MIN_PASSWORD_LEN as i64,
+                    MAX_PASSWORD_LEN as i64,
+                )? as usize;
+                Ok(format!("{}", password_policy.min_length))
+            }
+            "password_require_digit" => {
+                password_policy.require_digit = parse_setting_bool(key, value)?;
+                Ok(format!("{}", password_policy.require_digit))
+            }
+            "password_require_symbol" => {
+                password_policy.require_symbol =
+                    parse_setting_bool(key, value)?;
+                Ok(format!("{}", password_policy.require_symbol))
+            }
+            "password_require_mixed_case" => {
+                password_policy.require_mixed_case =
+                    parse_setting_bool(key, value)?;
+                Ok(format!("{}", password_policy.require_mixed_case))
+            }
+            _ => Err(format!(
+                "unsupported setting {key} - supported settings: {}",
+                SUPPORTED_SETTINGS.join(", ")
+            )),
+        }
+    }
+
+    /// build_from_overrides
+    ///
+    /// Build the
+    /// [`RuntimeSettings`](crate::settings::runtime_settings::RuntimeSettings)
+    /// from environment variables and apply the db overrides.
+    /// Invalid overrides are logged and skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - caller logging label
+    /// * `overrides` - `&[`[`ModelSetting`](crate::requests::models::setting::ModelSetting)`]` -
+    ///   rows from the ``settings`` table
+    ///
+    pub fn build_from_overrides(
+        tracking_label: &str,
+        overrides: &[ModelSetting],
+    ) -> Self {
+        let mut settings = RuntimeSettings::build_runtime_settings();
+        for setting in overrides.iter() {
+            if let Err(err_msg) =
+                settings.apply_setting(&setting.key, &setting.value)
+            {
+                warn!(
+                    "{tracking_label} - \
+                    ignoring invalid setting override with err='{err_msg}'"
+                );
+            }
+        }
+        settings
+    }
+
+    /// is_allowed_during_maintenance
+    ///
+    /// Requests that are still served in maintenance mode
+    /// (logins and csrf tokens so admins can get a token,
+    /// admin apis, jwks, event schemas, health checks and
+    /// metrics)
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - [`Method`](hyper::Method) - request method
+    /// * `request_uri` - `&str` - url path
+    ///
+    pub fn is_allowed_during_maintenance(
+        method: &Method,
+        request_uri: &str,
+    ) -> bool {
+        request_uri.starts_with("/admin/")
+            || (method == Method::POST && request_uri == "/login")
+            || (method == Method::GET
+                && (request_uri == "/metrics"
+                    || request_uri == "/health"
+                    || request_uri == "/csrf"
+                    || request_uri == "/.well-known/jwks.json"
+                    || request_uri == "/openapi/events.json"))
+    }
+}
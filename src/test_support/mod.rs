@@ -0,0 +1,28 @@
+//! End-to-end test harness for crates that use the api server
+//!
+//! Requires the ``test-support`` feature and a running
+//! docker daemon for the postgres container.
+//!
+//! ```rust,no_run
+//! use hyper::Method;
+//! use restapi::test_support::test_server::TestServer;
+//!
+//! #[tokio::test]
+//! async fn create_and_get_user() {
+//!     let server = TestServer::start().await.unwrap();
+//!     let (user_id, token) = server
+//!         .create_user("user@email.com", "12345")
+//!         .await
+//!         .unwrap();
+//!     let (status, user) = server
+//!         .send_json(Method::GET, &format!("/user/{user_id}"), Some(&token), None)
+//!         .await
+//!         .unwrap();
+//!     assert_eq!(status, 200);
+//!     assert_eq!(user["email"], "user@email.com");
+//! }
+//! ```
+//!
+pub mod test_assets;
+pub mod test_postgres;
+pub mod test_server;
@@ -0,0 +1,255 @@
+//! Throwaway tls certificates and jwt keys for end-to-end tests
+//!
+//! [`build_test_assets`](crate::test_support::test_assets::build_test_assets)
+//! creates a private certificate authority, certificates for
+//! the api server and postgres, and ES256 jwt keys in a new
+//! temp directory with the same layout as the ``./tls`` and
+//! ``./jwt`` directories:
+//!
+//! ```bash
+//! ${DIR}/tls/ca/ca.pem
+//! ${DIR}/tls/api/server.pem
+//! ${DIR}/tls/api/server-key.pem
+//! ${DIR}/tls/postgres/server.pem
+//! ${DIR}/tls/postgres/server-key.pem
+//! ${DIR}/tls/postgres/client.pem
+//! ${DIR}/tls/postgres/client-key.pem
+//! ${DIR}/jwt/private-key-pkcs8.pem
+//! ${DIR}/jwt/public-key.pem
+//! ```
+//!
+//! The certificates are valid for ``localhost``,
+//! ``127.0.0.1`` and ``0.0.0.0``. The directory is removed
+//! when the [`TestAssets`](crate::test_support::test_assets::TestAssets)
+//! are dropped.
+//!
+use std::path::PathBuf;
+
+use openssl::asn1::Asn1Time;
+use openssl::bn::BigNum;
+use openssl::bn::MsbOption;
+use openssl::ec::EcGroup;
+use openssl::ec::EcKey;
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::PKey;
+use openssl::pkey::Private;
+use openssl::rsa::Rsa;
+use openssl::x509::extension::BasicConstraints;
+use openssl::x509::extension::ExtendedKeyUsage;
+use openssl::x509::extension::KeyUsage;
+use openssl::x509::extension::SubjectAlternativeName;
+use openssl::x509::X509NameBuilder;
+use openssl::x509::X509;
+
+use crate::tls::get_tls_config::TlsPaths;
+use crate::utils::get_uuid::get_uuid;
+
+/// TestAssets
+///
+/// Paths to the generated tls certificates and jwt keys
+///
+/// # Arguments
+///
+/// * `dir` - `PathBuf` - temp directory with every asset
+/// * `ca_path` - `String` - certificate authority for
+///   every certificate
+/// * `api_tls` - [`TlsPaths`](crate::tls::get_tls_config::TlsPaths) -
+///   api server certificate and key
+/// * `postgres_server_tls` - [`TlsPaths`](crate::tls::get_tls_config::TlsPaths) -
+///   postgres server certificate and key
+/// * `postgres_client_tls` - [`TlsPaths`](crate::tls::get_tls_config::TlsPaths) -
+///   postgres client certificate and key for the api server
+/// * `jwt_private_key` - `String` - ES256 private key
+///   (pkcs8)
+/// * `jwt_public_key` - `String` - ES256 public key
+///
+pub struct TestAssets {
+    pub dir: PathBuf,
+    pub ca_path: String,
+    pub api_tls: TlsPaths,
+    pub postgres_server_tls: TlsPaths,
+    pub postgres_client_tls: TlsPaths,
+    pub jwt_private_key: String,
+    pub jwt_public_key: String,
+}
+
+impl Drop for TestAssets {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// build_test_assets
+///
+/// Create the tls certificates and jwt keys in a new
+/// temp directory
+///
+/// # Returns
+///
+/// Ok([`TestAssets`](crate::test_support::test_assets::TestAssets))
+///
+/// # Errors
+///
+/// Err(err_msg: `String`) when a key or certificate cannot be
+/// created or written
+///
+pub fn build_test_assets() -> Result<TestAssets, String> {
+    let dir = std::env::temp_dir().join(format!("restapi-test-{}", get_uuid()));
+    for sub_dir in ["tls/ca", "tls/api", "tls/postgres", "jwt"] {
+        std::fs::create_dir_all(dir.join(sub_dir)).map_err(|e| {
+            format!(
+                "failed to create test dir {dir:?}/{sub_dir} with err='{e}'"
+            )
+        })?;
+    }
+    let path = |name: &str| dir.join(name).to_string_lossy().to_string();
+
+    let (ca_key, ca_cert) = build_ca()
+        .map_err(|e| format!("failed to build test ca with err='{e}'"))?;
+    write_pem(&path("tls/ca/ca.pem"), &ca_cert.to_pem())?;
+
+    let write_cert = |name: &str, cn: &str| -> Result<TlsPaths, String> {
+        let (key, cert) = build_cert(&ca_key, &ca_cert, cn).map_err(|e| {
+            format!("failed to build test certificate {name} with err='{e}'")
+        })?;
+        let tls_paths = TlsPaths {
+            ca: path("tls/ca/ca.pem"),
+            key: path(&format!("{name}-key.pem")),
+            cert: path(&format!("{name}.pem")),
+        };
+        write_pem(&tls_paths.key, &key.private_key_to_pem_pkcs8())?;
+        write_pem(&tls_paths.cert, &cert.to_pem())?;
+        Ok(tls_paths)
+    };
+    let api_tls = write_cert("tls/api/server", "api")?;
+    let postgres_server_tls = write_cert("tls/postgres/server", "postgres")?;
+    let postgres_client_tls = write_cert("tls/postgres/client", "postgres")?;
+
+    let jwt_key = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)
+        .and_then(|group| EcKey::generate(&group))
+        .and_then(PKey::from_ec_key)
+        .map_err(|e| format!("failed to build test jwt key with err='{e}'"))?;
+    let jwt_private_key = path("jwt/private-key-pkcs8.pem");
+    let jwt_public_key = path("jwt/public-key.pem");
+    write_pem(&jwt_private_key, &jwt_key.private_key_to_pem_pkcs8())?;
+    write_pem(&jwt_public_key, &jwt_key.public_key_to_pem())?;
+
+    Ok(TestAssets {
+        ca_path: path("tls/ca/ca.pem"),
+        dir,
+        api_tls,
+        postgres_server_tls,
+        postgres_client_tls,
+        jwt_private_key,
+        jwt_public_key,
+    })
+}
+
+/// write_pem
+///
+/// Write a pem file
+///
+/// # Arguments
+///
+/// * `path` - `&str` - file path
+/// * `pem` - `Result<Vec<u8>, ErrorStack>` - pem contents
+///
+fn write_pem(
+    path: &str,
+    pem: &Result<Vec<u8>, openssl::error::ErrorStack>,
+) -> Result<(), String> {
+    let pem = pem
+        .as_ref()
+        .map_err(|e| format!("failed to encode {path} with err='{e}'"))?;
+    std::fs::write(path, pem)
+        .map_err(|e| format!("failed to write {path} with err='{e}'"))
+}
+
+/// build_ca
+///
+/// Build a self-signed certificate authority
+///
+fn build_ca() -> Result<(PKey<Private>, X509), openssl::error::ErrorStack> {
+    let key = PKey::from_rsa(Rsa::generate(2048)?)?;
+    let mut name = X509NameBuilder::new()?;
+    name.append_entry_by_nid(Nid::COMMONNAME, "restapi-test-ca")?;
+    let name = name.build();
+    let mut builder = X509::builder()?;
+    builder.set_version(2)?;
+    let serial_number = get_serial_number()?.to_asn1_integer()?;
+    builder.set_serial_number(&serial_number)?;
+    builder.set_subject_name(&name)?;
+    builder.set_issuer_name(&name)?;
+    builder.set_pubkey(&key)?;
+    builder.set_not_before(Asn1Time::days_from_now(0)?.as_ref())?;
+    builder.set_not_after(Asn1Time::days_from_now(1)?.as_ref())?;
+    builder
+        .append_extension(BasicConstraints::new().critical().ca().build()?)?;
+    builder.append_extension(
+        KeyUsage::new()
+            .critical()
+            .key_cert_sign()
+            .crl_sign()
+            .build()?,
+    )?;
+    builder.sign(&key, MessageDigest::sha256())?;
+    Ok((key, builder.build()))
+}
+
+/// build_cert
+///
+/// Build a certificate signed by the test certificate
+/// authority for ``localhost``, ``127.0.0.1`` and ``0.0.0.0``
+///
+fn build_cert(
+    ca_key: &PKey<Private>,
+    ca_cert: &X509,
+    common_name: &str,
+) -> Result<(PKey<Private>, X509), openssl::error::ErrorStack> {
+    let key = PKey::from_rsa(Rsa::generate(2048)?)?;
+    let mut name = X509NameBuilder::new()?;
+    name.append_entry_by_nid(Nid::COMMONNAME, common_name)?;
+    let name = name.build();
+    let mut builder = X509::builder()?;
+    builder.set_version(2)?;
+    let serial_number = get_serial_number()?.to_asn1_integer()?;
+    builder.set_serial_number(&serial_number)?;
+    builder.set_subject_name(&name)?;
+    builder.set_issuer_name(ca_cert.subject_name())?;
+    builder.set_pubkey(&key)?;
+    builder.set_not_before(Asn1Time::days_from_now(0)?.as_ref())?;
+    builder.set_not_after(Asn1Time::days_from_now(1)?.as_ref())?;
+    builder.append_extension(
+        KeyUsage::new()
+            .critical()
+            .digital_signature()
+            .key_encipherment()
+            .build()?,
+    )?;
+    builder.append_extension(
+        ExtendedKeyUsage::new()
+            .server_auth()
+            .client_auth()
+            .build()?,
+    )?;
+    let san = SubjectAlternativeName::new()
+        .dns("localhost")
+        .dns(common_name)
+        .ip("127.0.0.1")
+        .ip("0.0.0.0")
+        .build(&builder.x509v3_context(Some(ca_cert), None))?;
+    builder.append_extension(san)?;
+    builder.sign(ca_key, MessageDigest::sha256())?;
+    Ok((key, builder.build()))
+}
+
+/// get_serial_number
+///
+/// Random certificate serial number
+///
+fn get_serial_number() -> Result<BigNum, openssl::error::ErrorStack> {
+    let mut serial = BigNum::new()?;
+    serial.rand(128, MsbOption::MAYBE_ZERO, false)?;
+    Ok(serial)
+}
@@ -0,0 +1,189 @@
+//! Start a throwaway postgres container for end-to-end tests
+//!
+//! [`start_test_postgres`](crate::test_support::test_postgres::start_test_postgres)
+//! starts ``postgres:14.5-alpine`` with testcontainers and the
+//! same tls setup as ``docker/db/compose.yml``, then applies
+//! the ``docker/db/sql/init.sql`` schema (which already has
+//! every change from ``docker/db/sql/migrations``) and any
+//! extra migrations to the ``mydb`` database. The container
+//! is removed when the
+//! [`TestPostgres`](crate::test_support::test_postgres::TestPostgres)
+//! is dropped.
+//!
+use std::time::Duration;
+
+use native_tls::Certificate as native_tls_cert;
+use native_tls::TlsConnector;
+use postgres_native_tls::MakeTlsConnector;
+
+use testcontainers::core::IntoContainerPort;
+use testcontainers::core::WaitFor;
+use testcontainers::runners::AsyncRunner;
+use testcontainers::ContainerAsync;
+use testcontainers::GenericImage;
+use testcontainers::ImageExt;
+
+use crate::pools::run_migrations::split_sql_statements;
+use crate::test_support::test_assets::TestAssets;
+
+/// postgres image for the test container
+pub const TEST_POSTGRES_IMAGE: (&str, &str) = ("postgres", "14.5-alpine");
+
+/// the schema applied to every test database
+pub const TEST_POSTGRES_SCHEMA: &str =
+    include_str!("../../docker/db/sql/init.sql");
+
+/// postgres superuser password in the test container
+const TEST_POSTGRES_PASSWORD: &str = "postgres";
+
+/// TestPostgres
+///
+/// A running postgres container with the api schema
+///
+/// # Arguments
+///
+/// * `container` - `ContainerAsync<GenericImage>` - the
+///   container (removed on drop)
+/// * `address` - `String` - ``localhost:PORT`` address for
+///   the api server's db endpoint
+///
+pub struct TestPostgres {
+    pub container: ContainerAsync<GenericImage>,
+    pub address: String,
+}
+
+/// start_test_postgres
+///
+/// Start the postgres container, wait for it to accept tls
+/// connections and apply the schema and migrations
+///
+/// # Arguments
+///
+/// * `assets` - [`TestAssets`](crate::test_support::test_assets::TestAssets) -
+///   certificates for the postgres server
+/// * `migrations` - `&[String]` - extra sql applied after
+///   the schema
+///
+/// # Returns
+///
+/// Ok([`TestPostgres`](crate::test_support::test_postgres::TestPostgres))
+///
+/// # Errors
+///
+/// Err(err_msg: `String`) when the container cannot start
+/// or a sql statement fails
+///
+pub async fn start_test_postgres(
+    assets: &TestAssets,
+    migrations: &[String],
+) -> Result<TestPostgres, String> {
+    let read = |path: &str| {
+        std::fs::read(path)
+            .map_err(|e| format!("failed to read {path} with err='{e}'"))
+    };
+    // postgres only loads a key owned by the postgres user
+    // so copy the tls assets before starting
+    // (same as docker/db/compose.yml)
+    let container = GenericImage::new(
+        TEST_POSTGRES_IMAGE.0,
+        TEST_POSTGRES_IMAGE.1,
+    )
+    .with_exposed_port(5432.tcp())
+    .with_wait_for(WaitFor::message_on_stdout(
+        "database system is ready to accept connections",
+    ))
+    .with_wait_for(WaitFor::message_on_stderr(
+        "database system is ready to accept connections",
+    ))
+    .with_env_var("POSTGRES_PASSWORD", TEST_POSTGRES_PASSWORD)
+    .with_env_var("POSTGRES_DB", "mydb")
+    .with_copy_to("/certs/ca.pem", read(&assets.ca_path)?)
+    .with_copy_to("/certs/server.pem", read(&assets.postgres_server_tls.cert)?)
+    .with_copy_to(
+        "/certs/server-key.pem",
+        read(&assets.postgres_server_tls.key)?,
+    )
+    .with_cmd([
+        "sh",
+        "-c",
+        "cp /certs/* / \
+            && chmod 400 /ca.pem /server.pem /server-key.pem \
+            && chown postgres:postgres /ca.pem /server.pem /server-key.pem \
+            && exec docker-entrypoint.sh postgres \
+            -c ssl=on \
+            -c ssl_cert_file=/server.pem \
+            -c ssl_key_file=/server-key.pem \
+            -c ssl_ca_file=/ca.pem",
+    ])
+    .with_startup_timeout(Duration::from_secs(120))
+    .start()
+    .await
+    .map_err(|e| {
+        format!("failed to start the test postgres container with err='{e}'")
+    })?;
+    let port = container
+        .get_host_port_ipv4(5432.tcp())
+        .await
+        .map_err(|e| {
+            format!("failed to find the test postgres port with err='{e}'")
+        })?;
+    let address = format!("localhost:{port}");
+
+    let ca_bytes = read(&assets.ca_path)?;
+    let connector = TlsConnector::builder()
+        .add_root_certificate(
+            native_tls_cert::from_pem(&ca_bytes)
+                .map_err(|e| format!("invalid test ca with err='{e}'"))?,
+        )
+        .build()
+        .map_err(|e| {
+            format!("failed to build the tls connector with err='{e}'")
+        })?;
+    let conn_str = format!(
+        "postgresql://postgres:{TEST_POSTGRES_PASSWORD}@{address}/mydb?sslmode=require"
+    );
+    // the port can be mapped before postgres accepts connections
+    let mut attempts = 0;
+    let client = loop {
+        match tokio_postgres::connect(
+            &conn_str,
+            MakeTlsConnector::new(connector.clone()),
+        )
+        .await
+        {
+            Ok((client, connection)) => {
+                tokio::spawn(connection);
+                break client;
+            }
+            Err(e) => {
+                attempts += 1;
+                if attempts >= 60 {
+                    return Err(format!(
+                        "failed to connect to the test postgres {address} \
+                        with err='{e}'"
+                    ));
+                }
+                tokio::time::sleep(Duration::from_millis(500)).await;
+            }
+        }
+    };
+
+    let migrations = std::iter::once(TEST_POSTGRES_SCHEMA)
+        .chain(migrations.iter().map(|v| v.as_str()));
+    for sql in migrations {
+        for statement in split_sql_statements(sql) {
+            // the container already created the db
+            if statement.to_uppercase().starts_with("CREATE DATABASE") {
+                continue;
+            }
+            client.batch_execute(&statement).await.map_err(|e| {
+                format!(
+                    "failed to apply test migration statement='{statement}' \
+                    with err='{e}'"
+                )
+            })?;
+        }
+    }
+
+    Ok(TestPostgres { container, address })
+}
@@ -0,0 +1,326 @@
+//! Boot the api server on a random port for end-to-end tests
+//!
+//! [`TestServer::start`](crate::test_support::test_server::TestServer::start)
+//! builds throwaway tls certificates and jwt keys with
+//! [`build_test_assets`](crate::test_support::test_assets::build_test_assets),
+//! starts postgres with
+//! [`start_test_postgres`](crate::test_support::test_postgres::start_test_postgres)
+//! and serves the api with a
+//! [`RestApiServerBuilder`](crate::core::server::rest_api_server::RestApiServerBuilder)
+//! on a free ``127.0.0.1`` port. Everything is stopped and
+//! removed when the
+//! [`TestServer`](crate::test_support::test_server::TestServer)
+//! is dropped.
+//!
+use std::time::Duration;
+
+use hyper::client::HttpConnector;
+use hyper::Body;
+use hyper::Client;
+use hyper::Method;
+use hyper::Request;
+use hyper_tls::HttpsConnector;
+
+use native_tls::Certificate as native_tls_cert;
+use native_tls::Identity;
+use native_tls::TlsConnector;
+
+use tokio::task::JoinHandle;
+
+use crate::core::core_config::CoreConfig;
+use crate::core::server::rest_api_server::RestApiServerBuilder;
+use crate::requests::user::create_user::ApiReqUserCreate;
+use crate::requests::user::create_user::ApiResUserCreate;
+use crate::test_support::test_assets::build_test_assets;
+use crate::test_support::test_assets::TestAssets;
+use crate::test_support::test_postgres::start_test_postgres;
+use crate::test_support::test_postgres::TestPostgres;
+
+/// db user created by ``docker/db/sql/init.sql``
+const TEST_DB_USERNAME: &str = "datawriter";
+/// db password created by ``docker/db/sql/init.sql``
+const TEST_DB_PASSWORD: &str = "123321";
+
+/// https client returned by
+/// [`TestServer::client`](crate::test_support::test_server::TestServer::client)
+pub type TestClient = Client<HttpsConnector<HttpConnector>>;
+
+/// TestServer
+///
+/// A running api server with its own postgres container,
+/// tls certificates and jwt keys
+///
+/// # Arguments
+///
+/// * `base_url` - `String` - ``https://localhost:PORT``
+/// * `config` - [`CoreConfig`](crate::core::core_config::CoreConfig)
+///   the server was started with
+/// * `postgres` - [`TestPostgres`](crate::test_support::test_postgres::TestPostgres) -
+///   the postgres container
+/// * `assets` - [`TestAssets`](crate::test_support::test_assets::TestAssets) -
+///   the tls certificates and jwt keys
+/// * `server_task` - `JoinHandle<bool>` - the server task
+///   (aborted on drop)
+///
+pub struct TestServer {
+    pub base_url: String,
+    pub config: CoreConfig,
+    pub postgres: TestPostgres,
+    pub assets: TestAssets,
+    pub server_task: JoinHandle<bool>,
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        self.server_task.abort();
+    }
+}
+
+impl TestServer {
+    /// start
+    ///
+    /// Start a test server with the default builder
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) when the assets, postgres or
+    /// the server cannot start
+    ///
+    pub async fn start() -> Result<Self, String> {
+        Self::start_with(RestApiServerBuilder::new("test-server"), &[]).await
+    }
+
+    /// start_with
+    ///
+    /// Start a test server from a builder with custom routes,
+    /// claims providers or other options. The listening
+    /// address, api and db tls, db endpoint, db credentials,
+    /// db name and jwt keys are always replaced with the test
+    /// values. Kafka events are disabled unless the builder
+    /// enables them.
+    ///
+    /// # Arguments
+    ///
+    /// * `builder` - [`RestApiServerBuilder`](crate::core::server::rest_api_server::RestApiServerBuilder)
+    /// * `migrations` - `&[String]` - extra sql applied after
+    ///   the ``docker/db/sql/init.sql`` schema
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) when the assets, postgres or
+    /// the server cannot start
+    ///
+    pub async fn start_with(
+        builder: RestApiServerBuilder,
+        migrations: &[String],
+    ) -> Result<Self, String> {
+        let assets = build_test_assets()?;
+        let postgres = start_test_postgres(&assets, migrations).await?;
+
+        // let the os pick a free port
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .map_err(|e| format!("failed to find a free port with err='{e}'"))?
+            .port();
+        let address = format!("127.0.0.1:{port}");
+
+        let kafka_publish_events =
+            builder.kafka_publish_events.unwrap_or(false);
+        let server = builder
+            .api_endpoint(&address)
+            .api_tls(
+                &assets.api_tls.ca,
+                &assets.api_tls.cert,
+                &assets.api_tls.key,
+            )
+            .db_endpoint(&postgres.address)
+            .db_read_endpoints(&[])
+            .db_credentials(TEST_DB_USERNAME, TEST_DB_PASSWORD)
+            .db_name("mydb")
+            .db_tls(
+                &assets.postgres_client_tls.ca,
+                &assets.postgres_client_tls.cert,
+                &assets.postgres_client_tls.key,
+            )
+            .jwt_key_files(&assets.jwt_private_key, &assets.jwt_public_key)
+            .kafka_publish_events(kafka_publish_events)
+            .build()
+            .await?;
+        let config = server.config.clone();
+        let server_task = tokio::spawn(server.serve());
+
+        // wait for the server to accept connections
+        let mut attempts = 0;
+        while tokio::net::TcpStream::connect(&address).await.is_err() {
+            attempts += 1;
+            if server_task.is_finished() || attempts >= 100 {
+                server_task.abort();
+                return Err(format!(
+                    "test server failed to start on {address}"
+                ));
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+
+        Ok(TestServer {
+            base_url: format!("https://localhost:{port}"),
+            config,
+            postgres,
+            assets,
+            server_task,
+        })
+    }
+
+    /// url
+    ///
+    /// Full url for a path (``/user``)
+    ///
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{path}", self.base_url)
+    }
+
+    /// client
+    ///
+    /// hyper https client that trusts the test certificate
+    /// authority and sends the api's client certificate
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) when the tls assets cannot be
+    /// loaded
+    ///
+    pub fn client(&self) -> Result<TestClient, String> {
+        let read = |path: &str| {
+            std::fs::read(path)
+                .map_err(|e| format!("failed to read {path} with err='{e}'"))
+        };
+        let ca = native_tls_cert::from_pem(&read(&self.assets.ca_path)?)
+            .map_err(|e| format!("invalid test ca with err='{e}'"))?;
+        let identity = Identity::from_pkcs8(
+            &read(&self.assets.api_tls.cert)?,
+            &read(&self.assets.api_tls.key)?,
+        )
+        .map_err(|e| format!("invalid test client identity with err='{e}'"))?;
+        let connector = TlsConnector::builder()
+            .add_root_certificate(ca)
+            .identity(identity)
+            .build()
+            .map_err(|e| {
+                format!("failed to build the tls connector with err='{e}'")
+            })?;
+        let mut http = HttpConnector::new();
+        http.enforce_http(false);
+        let https = HttpsConnector::from((
+            http,
+            tokio_native_tls::TlsConnector::from(connector),
+        ));
+        Ok(Client::builder().build::<_, Body>(https))
+    }
+
+    /// send_json
+    ///
+    /// Send a request with an optional json body and user
+    /// token, and parse the json response
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - [`Method`](hyper::Method)
+    /// * `path` - `&str` - url path (``/user/2``)
+    /// * `token` - `Option<&str>` - user jwt sent in the
+    ///   ``TOKEN_HEADER`` header
+    /// * `body` - `Option<serde_json::Value>` - json body
+    ///
+    /// # Returns
+    ///
+    /// Ok((`status`, `response_json`)) where an empty or
+    /// non-json response body is ``serde_json::Value::Null``
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) when the request fails
+    ///
+    pub async fn send_json(
+        &self,
+        method: Method,
+        path: &str,
+        token: Option<&str>,
+        body: Option<serde_json::Value>,
+    ) -> Result<(u16, serde_json::Value), String> {
+        let mut request = Request::builder()
+            .method(method)
+            .uri(self.url(path))
+            .header("Content-Type", "application/json");
+        if let Some(token) = token {
+            let token_header = std::env::var("TOKEN_HEADER")
+                .unwrap_or_else(|_| "Bearer".to_string());
+            request = request.header(token_header, token);
+        }
+        let request = request
+            .body(match body {
+                Some(body) => Body::from(body.to_string()),
+                None => Body::empty(),
+            })
+            .map_err(|e| format!("invalid request {path} with err='{e}'"))?;
+        let response = self
+            .client()?
+            .request(request)
+            .await
+            .map_err(|e| format!("request {path} failed with err='{e}'"))?;
+        let status = response.status().as_u16();
+        let bytes = hyper::body::to_bytes(response.into_body())
+            .await
+            .map_err(|e| format!("failed to read {path} with err='{e}'"))?;
+        Ok((
+            status,
+            serde_json::from_slice(&bytes).unwrap_or(serde_json::Value::Null),
+        ))
+    }
+
+    /// create_user
+    ///
+    /// Create a user with ``POST /user``
+    ///
+    /// # Arguments
+    ///
+    /// * `email` - `&str` - user email
+    /// * `password` - `&str` - user password
+    ///
+    /// # Returns
+    ///
+    /// Ok((`user_id`, `token`))
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) when the user is not created
+    ///
+    pub async fn create_user(
+        &self,
+        email: &str,
+        password: &str,
+    ) -> Result<(i32, String), String> {
+        let req = ApiReqUserCreate {
+            email: email.to_string(),
+            password: password.to_string(),
+            locale: None,
+        };
+        let (status, body) = self
+            .send_json(
+                Method::POST,
+                "/user",
+                None,
+                Some(serde_json::json!(req)),
+            )
+            .await?;
+        if status != 201 {
+            return Err(format!(
+                "failed to create user {email} status={status} \
+                response={body}"
+            ));
+        }
+        let res: ApiResUserCreate =
+            serde_json::from_value(body).map_err(|e| {
+                format!("invalid create user response with err='{e}'")
+            })?;
+        Ok((res.user_id, res.token))
+    }
+}
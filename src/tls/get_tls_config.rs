@@ -5,6 +5,7 @@ use rustls::Certificate;
 use rustls::PrivateKey;
 use rustls::ServerConfig;
 
+use crate::core::startup_error::StartupError;
 use crate::tls::tls_config::TlsConfig;
 
 /// get_tls_config
@@ -38,6 +39,12 @@ use crate::tls::tls_config::TlsConfig;
 ///   listening port with format: IP_ADDRESS:PORT
 /// * `mode` - `tls` for api's and `require` for postgres
 ///
+/// # Errors
+///
+/// Err([`StartupError`](crate::core::startup_error::StartupError))
+/// naming the ``{APP_NAME}_TLS_*`` environment variable and
+/// file that is missing or invalid
+///
 /// # Examples
 ///
 /// ```rust
@@ -75,7 +82,56 @@ pub async fn get_tls_config(
     app_name: &str,
     server_address: &str,
     mode: &str,
-) -> Result<TlsConfig, String> {
+) -> Result<TlsConfig, StartupError> {
+    get_tls_config_from_paths(
+        tracking_label,
+        app_name,
+        server_address,
+        mode,
+        get_tls_paths(app_name),
+    )
+    .await
+}
+
+/// TlsPaths
+///
+/// Paths to the tls certificate authority, key and
+/// certificate for an endpoint
+///
+/// # Arguments
+///
+/// * `ca` - `String` - path to the certificate authority
+/// * `key` - `String` - path to the private key
+/// * `cert` - `String` - path to the certificate
+///
+#[derive(Clone, Debug, Default)]
+pub struct TlsPaths {
+    pub ca: String,
+    pub key: String,
+    pub cert: String,
+}
+
+/// get_tls_paths
+///
+/// Get the [`TlsPaths`](crate::tls::get_tls_config::TlsPaths)
+/// for an endpoint from the ``{APP_NAME}_TLS_DIR``,
+/// ``{APP_NAME}_TLS_CA``, ``{APP_NAME}_TLS_KEY`` and
+/// ``{APP_NAME}_TLS_CERT`` environment variables
+///
+/// # Arguments
+///
+/// * `app_name` - &str - directory name for tls assets
+///
+/// # Examples
+///
+/// ```rust
+/// use restapi::tls::get_tls_config::get_tls_paths;
+/// let tls_paths = get_tls_paths("docs-example");
+/// assert_eq!(tls_paths.ca, "./tls/ca/ca.pem");
+/// assert_eq!(tls_paths.cert, "./tls/docs-example/server.pem");
+/// ```
+///
+pub fn get_tls_paths(app_name: &str) -> TlsPaths {
     let uppercase_app_name = app_name.to_uppercase();
     let mut conn_type = "server";
     if app_name.to_lowercase() == "postgres" {
@@ -83,121 +139,201 @@ pub async fn get_tls_config(
     }
     let tls_dir = std::env::var(format!("{uppercase_app_name}_TLS_DIR"))
         .unwrap_or_else(|_| "./tls".to_string());
-    let tls_ca = std::env::var(format!("{uppercase_app_name}_TLS_CA"))
-        .unwrap_or_else(|_| format!("{tls_dir}/ca/ca.pem"));
-    let tls_key = std::env::var(format!("{uppercase_app_name}_TLS_KEY"))
-        .unwrap_or_else(|_| {
-            format!("{tls_dir}/{app_name}/{conn_type}-key.pem")
-        });
-    let tls_cert = std::env::var(format!("{uppercase_app_name}_TLS_CERT"))
-        .unwrap_or_else(|_| format!("{tls_dir}/{app_name}/{conn_type}.pem"));
-
-    let mut tls_enabled = false;
-    if !&tls_ca.is_empty() && !&tls_key.is_empty() && !&tls_cert.is_empty() {
-        tls_enabled = true;
+    TlsPaths {
+        ca: std::env::var(format!("{uppercase_app_name}_TLS_CA"))
+            .unwrap_or_else(|_| format!("{tls_dir}/ca/ca.pem")),
+        key: std::env::var(format!("{uppercase_app_name}_TLS_KEY"))
+            .unwrap_or_else(|_| {
+                format!("{tls_dir}/{app_name}/{conn_type}-key.pem")
+            }),
+        cert: std::env::var(format!("{uppercase_app_name}_TLS_CERT"))
+            .unwrap_or_else(|_| {
+                format!("{tls_dir}/{app_name}/{conn_type}.pem")
+            }),
     }
+}
 
-    info!(
-        "{tracking_label} - start \
-        tls={tls_enabled} \
-        ca={tls_ca} \
-        key={tls_key} \
-        cert={tls_cert}"
-    );
-
-    if std::fs::metadata(&tls_ca).is_err() {
-        let err_msg = format!(
-            "{tracking_label} - \
-            failed to find {}_TLS_CA={tls_ca}",
-            uppercase_app_name
-        );
-        error!("{err_msg}");
-        tls_enabled = false;
+/// check_tls_files
+///
+/// Check the tls certificate authority, key and certificate
+/// files exist and have pem certificates or keys
+///
+/// # Arguments
+///
+/// * `app_name` - &str - name for the tls assets (the
+///   ``{APP_NAME}_TLS_*`` environment variables in errors)
+/// * `tls_paths` - [`TlsPaths`](crate::tls::get_tls_config::TlsPaths) -
+///   tls certificate authority, key and certificate paths
+///
+/// # Returns
+///
+/// `Vec<StartupError>` - one
+/// [`StartupError`](crate::core::startup_error::StartupError)
+/// for each invalid file (empty when every file is valid)
+///
+pub fn check_tls_files(
+    app_name: &str,
+    tls_paths: &TlsPaths,
+) -> Vec<StartupError> {
+    let uppercase_app_name = app_name.to_uppercase();
+    let mut errors = Vec::new();
+    for (suffix, path) in [
+        ("CA", &tls_paths.ca),
+        ("KEY", &tls_paths.key),
+        ("CERT", &tls_paths.cert),
+    ] {
+        let env_var = format!("{uppercase_app_name}_TLS_{suffix}");
+        let pem = match std::fs::read(path) {
+            Ok(pem) => pem,
+            Err(e) => {
+                errors.push(StartupError::MissingFile {
+                    env_var,
+                    path: path.to_string(),
+                    err: format!("{e}"),
+                });
+                continue;
+            }
+        };
+        let result = match suffix {
+            "KEY" => load_private_key(&pem).map(|_| ()),
+            _ => load_certs(&pem).map(|_| ()),
+        };
+        if let Err(reason) = result {
+            errors.push(StartupError::InvalidPem {
+                env_var,
+                path: path.to_string(),
+                reason,
+            });
+        }
     }
+    errors
+}
 
-    if std::fs::metadata(&tls_key).is_err() {
-        let err_msg = format!(
-            "{tracking_label} - \
-            failed to find {}_TLS_KEY={tls_key}",
-            uppercase_app_name
-        );
-        error!("{err_msg}");
-        tls_enabled = false;
+/// load_certs
+///
+/// Load the certificates from a pem file
+///
+fn load_certs(pem: &[u8]) -> Result<Vec<Certificate>, String> {
+    let certs: Vec<Certificate> = rustls_pemfile::certs(&mut &*pem)
+        .map(|mut certs| certs.drain(..).map(Certificate).collect())
+        .map_err(|e| {
+            format!("failed to parse pem certificates with err='{e}'")
+        })?;
+    if certs.is_empty() {
+        return Err("failed to find a pem certificate".to_string());
     }
+    Ok(certs)
+}
 
-    if std::fs::metadata(&tls_cert).is_err() {
-        let err_msg = format!(
-            "{tracking_label} - \
-            failed to find {}_TLS_CERT={tls_cert}",
-            uppercase_app_name
+/// load_private_key
+///
+/// Load an rsa or pkcs8 private key from a pem file
+///
+fn load_private_key(pem: &[u8]) -> Result<PrivateKey, String> {
+    // try loading the tls key using rsa then as pkcs8 before stopping
+    // https://docs.rs/rustls-pemfile/latest/rustls_pemfile/#functions
+    let mut keys: Vec<PrivateKey> =
+        rustls_pemfile::rsa_private_keys(&mut &*pem)
+            .map(|mut keys| keys.drain(..).map(PrivateKey).collect())
+            .map_err(|e| format!("unsupported rsa key with err='{e}'"))?;
+    // if rsa returns an empty vec, try as pkcs8
+    if keys.is_empty() {
+        keys = rustls_pemfile::pkcs8_private_keys(&mut &*pem)
+            .map(|mut keys| keys.drain(..).map(PrivateKey).collect())
+            .map_err(|e| format!("unsupported pkcs8 key with err='{e}'"))?;
+    }
+    if keys.is_empty() {
+        return Err(
+            "failed to find a valid key - please use an rsa or pkcs8 key"
+                .to_string(),
         );
-        error!("{err_msg}");
-        tls_enabled = false;
     }
+    Ok(keys.remove(0))
+}
 
-    // load api certificates
-    let cert_pem = std::fs::read(&*tls_cert).unwrap();
-    let key_pem = std::fs::read(&*tls_key).unwrap();
-
-    let server_config = {
-        let certs: Vec<Certificate> = rustls_pemfile::certs(&mut &*cert_pem)
-            .map(|mut certs| certs.drain(..).map(Certificate).collect())
-            .unwrap();
-
-        if certs.is_empty() {
-            panic!(
-                "get_tls_config - failed to find a valid tls cert={tls_cert}"
-            )
-        }
-
-        // try loading the tls key using rsa then as pkcs8 before stopping
-        // https://docs.rs/rustls-pemfile/latest/rustls_pemfile/#functions
-        let mut keys: Vec<PrivateKey> =
-            match rustls_pemfile::rsa_private_keys(&mut &*key_pem)
-                .map(|mut keys| keys.drain(..).map(PrivateKey).collect())
-            {
-                Ok(key_data) => key_data,
-                Err(_) => {
-                    panic!("get_tls_config - unsupported rsa tls key={tls_key}")
-                }
-            };
+/// get_tls_config_from_paths
+///
+/// Build a [`TlsConfig`](crate::tls::tls_config) for hosting
+/// an encrypted endpoint with tls assets that were not set
+/// with environment variables (see
+/// [`RestApiServerBuilder`](crate::core::server::rest_api_server::RestApiServerBuilder))
+///
+/// # Arguments
+///
+/// * `tracking_label` - &str - label from caller function
+/// * `app_name` - &str - name for the tls assets in logs
+/// * `server_address` - &str - address to host the server's
+///   listening port with format: IP_ADDRESS:PORT
+/// * `mode` - `tls` for api's and `require` for postgres
+/// * `tls_paths` - [`TlsPaths`](crate::tls::get_tls_config::TlsPaths) -
+///   tls certificate authority, key and certificate paths
+///
+/// # Errors
+///
+/// Err([`StartupError`](crate::core::startup_error::StartupError))
+/// for the first missing or invalid tls file, or when the
+/// certificate and key do not match
+///
+pub async fn get_tls_config_from_paths(
+    tracking_label: &str,
+    app_name: &str,
+    server_address: &str,
+    mode: &str,
+    tls_paths: TlsPaths,
+) -> Result<TlsConfig, StartupError> {
+    info!(
+        "{tracking_label} - start \
+        ca={} \
+        key={} \
+        cert={}",
+        tls_paths.ca, tls_paths.key, tls_paths.cert
+    );
+    if let Some(e) = check_tls_files(app_name, &tls_paths).into_iter().next() {
+        error!("{tracking_label} - {e}");
+        return Err(e);
+    }
+    let TlsPaths {
+        ca: tls_ca,
+        key: tls_key,
+        cert: tls_cert,
+    } = tls_paths;
 
-        // if rsa returns an empty vec, try as pkcs8
-        if keys.is_empty() {
-            trace!("trying to load tls key={tls_key} using pkcs8");
-            keys = match rustls_pemfile::pkcs8_private_keys(&mut &*key_pem)
-                .map(|mut keys| keys.drain(..).map(PrivateKey).collect())
-            {
-                Ok(key_data) => key_data,
-                Err(_) => {
-                    panic!(
-                        "get_tls_config - unsupported pkcs8 tls key={tls_key}"
-                    )
-                }
-            };
+    // load api certificates (already checked above)
+    let read_err = |env_suffix: &str, path: &str, e: std::io::Error| {
+        StartupError::MissingFile {
+            env_var: format!("{}_TLS_{env_suffix}", app_name.to_uppercase()),
+            path: path.to_string(),
+            err: format!("{e}"),
         }
-
-        if keys.is_empty() {
-            panic!(
-                "get_tls_config - failed to find a valid tls key={tls_key} \
-                please use an rsa or pkcs8 key and retry"
-            )
+    };
+    let cert_pem = std::fs::read(&*tls_cert)
+        .map_err(|e| read_err("CERT", &tls_cert, e))?;
+    let key_pem =
+        std::fs::read(&*tls_key).map_err(|e| read_err("KEY", &tls_key, e))?;
+    let invalid_pem = |env_suffix: &str, path: &str, reason: String| {
+        StartupError::InvalidPem {
+            env_var: format!("{}_TLS_{env_suffix}", app_name.to_uppercase()),
+            path: path.to_string(),
+            reason,
         }
-
-        let mut server_config = ServerConfig::builder()
-            .with_safe_defaults()
-            .with_no_client_auth()
-            .with_single_cert(certs, keys.remove(0))
-            .unwrap();
-
-        server_config.alpn_protocols =
-            vec![b"h2".to_vec(), b"http/1.1".to_vec()];
-
-        server_config
     };
+    let certs = load_certs(&cert_pem)
+        .map_err(|reason| invalid_pem("CERT", &tls_cert, reason))?;
+    let key = load_private_key(&key_pem)
+        .map_err(|reason| invalid_pem("KEY", &tls_key, reason))?;
+
+    let mut server_config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|e| StartupError::InvalidTls {
+            name: app_name.to_string(),
+            err: format!("{e}"),
+        })?;
+    server_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
 
     Ok(TlsConfig {
-        enabled: tls_enabled,
+        enabled: true,
         cert_path: tls_cert,
         key_path: tls_key,
         ca_path: tls_ca,
@@ -0,0 +1,416 @@
+//! Fail fast while a downstream service keeps failing
+//!
+//! A [`CircuitBreaker`] counts consecutive failed calls to a
+//! dependency (s3 or kafka). After
+//! ``failure_threshold`` failures the breaker opens and
+//! calls are rejected without waiting on the dependency.
+//! After ``open_seconds`` one trial call is let through
+//! (half open): a success closes the breaker and a failure
+//! opens it again.
+//!
+//! The state is exported as the ``circuit_breaker_state``
+//! prometheus gauge (``0`` = closed, ``1`` = open,
+//! ``2`` = half open) and rejected calls and state changes
+//! are counted in ``circuit_breaker_total``.
+//!
+use std::future::Future;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use lazy_static::lazy_static;
+
+use crate::monitoring::metric_types::register_int_counter_vec;
+use crate::monitoring::metric_types::register_int_gauge_vec;
+use crate::monitoring::metric_types::IntCounterVec;
+use crate::monitoring::metric_types::IntGaugeVec;
+
+lazy_static! {
+    pub static ref CIRCUIT_BREAKER_STATE_GAUGE: IntGaugeVec =
+        register_int_gauge_vec!(
+            "circuit_breaker_state",
+            "Circuit breaker state by name \
+            (0 = closed, 1 = open, 2 = half open).",
+            &["name"]
+        )
+        .unwrap();
+    pub static ref CIRCUIT_BREAKER_COUNTER_VEC: IntCounterVec =
+        register_int_counter_vec!(
+            "circuit_breaker_total",
+            "Number of circuit breaker state changes and rejected \
+            calls by name and result.",
+            &["name", "result"]
+        )
+        .unwrap();
+}
+
+/// error message suffix for calls rejected by an open
+/// [`CircuitBreaker`](crate::utils::circuit_breaker::CircuitBreaker)
+pub const CIRCUIT_BREAKER_OPEN_MSG: &str = "circuit breaker is open";
+
+/// CircuitState
+///
+/// # Variants
+///
+/// * `Closed` - calls are made
+/// * `Open` - calls are rejected
+/// * `HalfOpen` - one trial call was let through
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CircuitState {
+    #[default]
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+impl CircuitState {
+    /// as_str
+    ///
+    /// State name for logs and responses
+    ///
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CircuitState::Closed => "closed",
+            CircuitState::Open => "open",
+            CircuitState::HalfOpen => "half_open",
+        }
+    }
+
+    /// as_gauge
+    ///
+    /// Value for the ``circuit_breaker_state`` gauge
+    ///
+    pub fn as_gauge(&self) -> i64 {
+        match self {
+            CircuitState::Closed => 0,
+            CircuitState::Open => 1,
+            CircuitState::HalfOpen => 2,
+        }
+    }
+}
+
+/// CircuitBreakerState
+///
+/// Shared state for every clone of a
+/// [`CircuitBreaker`](crate::utils::circuit_breaker::CircuitBreaker)
+///
+/// # Arguments
+///
+/// * `state` - [`CircuitState`](crate::utils::circuit_breaker::CircuitState)
+/// * `failures` - `u32` - consecutive failed calls
+/// * `opened_at` - `Option<Instant>` - when the breaker
+///   opened or let the last trial call through
+///
+#[derive(Clone, Debug, Default)]
+pub struct CircuitBreakerState {
+    pub state: CircuitState,
+    pub failures: u32,
+    pub opened_at: Option<Instant>,
+}
+
+/// CircuitBreaker
+///
+/// Reject calls to a dependency after repeated failures
+/// (clones share the same state)
+///
+/// # Supported Environment Variables
+///
+/// ```bash
+/// # open after this many consecutive failures (0 = disabled)
+/// export S3_CIRCUIT_BREAKER_FAILURE_THRESHOLD="5"
+/// # reject calls for this long before a trial call
+/// export S3_CIRCUIT_BREAKER_OPEN_SECONDS="30"
+/// export KAFKA_CIRCUIT_BREAKER_FAILURE_THRESHOLD="5"
+/// export KAFKA_CIRCUIT_BREAKER_OPEN_SECONDS="30"
+/// ```
+///
+/// # Arguments
+///
+/// * `name` - `String` - dependency name for logs and
+///   metrics (``s3`` or ``kafka``)
+/// * `failure_threshold` - `u32` - consecutive failures that
+///   open the breaker (``0`` = disabled)
+/// * `open_seconds` - `u64` - how long calls are rejected
+///   before a trial call
+/// * `state` - `Arc<Mutex<`[`CircuitBreakerState`](crate::utils::circuit_breaker::CircuitBreakerState)`>>`
+///
+#[derive(Clone, Debug, Default)]
+pub struct CircuitBreaker {
+    pub name: String,
+    pub failure_threshold: u32,
+    pub open_seconds: u64,
+    pub state: Arc<Mutex<CircuitBreakerState>>,
+}
+
+impl CircuitBreaker {
+    /// new
+    ///
+    /// Create a closed breaker
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - `&str` - dependency name for logs and metrics
+    /// * `failure_threshold` - `u32` - consecutive failures
+    ///   that open the breaker (``0`` = disabled)
+    /// * `open_seconds` - `u64` - how long calls are rejected
+    ///   before a trial call
+    ///
+    pub fn new(name: &str, failure_threshold: u32, open_seconds: u64) -> Self {
+        CIRCUIT_BREAKER_STATE_GAUGE
+            .with_label_values(&[name])
+            .set(CircuitState::Closed.as_gauge());
+        CircuitBreaker {
+            name: name.to_string(),
+            failure_threshold,
+            open_seconds,
+            state: Arc::new(Mutex::new(CircuitBreakerState::default())),
+        }
+    }
+
+    /// build_circuit_breaker
+    ///
+    /// Build a
+    /// [`CircuitBreaker`](crate::utils::circuit_breaker::CircuitBreaker)
+    /// from the ``PREFIX_CIRCUIT_BREAKER_FAILURE_THRESHOLD``
+    /// and ``PREFIX_CIRCUIT_BREAKER_OPEN_SECONDS``
+    /// environment variables
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - `&str` - dependency name for logs and metrics
+    /// * `env_prefix` - `&str` - environment variable prefix
+    ///   (``S3`` or ``KAFKA``)
+    ///
+    pub fn build_circuit_breaker(name: &str, env_prefix: &str) -> Self {
+        let get_env = |key: &str, default: u64| -> u64 {
+            std::env::var(format!("{env_prefix}_{key}"))
+                .unwrap_or_else(|_| format!("{default}"))
+                .parse::<u64>()
+                .unwrap_or(default)
+        };
+        CircuitBreaker::new(
+            name,
+            get_env("CIRCUIT_BREAKER_FAILURE_THRESHOLD", 5).min(10000) as u32,
+            get_env("CIRCUIT_BREAKER_OPEN_SECONDS", 30).clamp(1, 86400),
+        )
+    }
+
+    /// is_enabled
+    ///
+    /// Check if the breaker can open
+    ///
+    pub fn is_enabled(&self) -> bool {
+        self.failure_threshold > 0
+    }
+
+    /// get_state
+    ///
+    /// Get the current
+    /// [`CircuitState`](crate::utils::circuit_breaker::CircuitState)
+    ///
+    pub fn get_state(&self) -> CircuitState {
+        self.state.lock().unwrap().state
+    }
+
+    /// is_open
+    ///
+    /// Check if calls are rejected right now without using
+    /// up the half open trial call (for handlers that fail
+    /// fast before reading a request body)
+    ///
+    pub fn is_open(&self) -> bool {
+        self.get_retry_after_seconds() > 0
+    }
+
+    /// get_retry_after_seconds
+    ///
+    /// Seconds until the next trial call is let through
+    ///
+    /// # Returns
+    ///
+    /// `u64` - ``0`` when calls are not rejected
+    ///
+    pub fn get_retry_after_seconds(&self) -> u64 {
+        let state = self.state.lock().unwrap();
+        match (state.state, state.opened_at) {
+            (CircuitState::Closed, _) | (_, None) => 0,
+            (_, Some(opened_at)) => {
+                let elapsed = opened_at.elapsed().as_secs();
+                match elapsed < self.open_seconds {
+                    true => self.open_seconds - elapsed,
+                    false => 0,
+                }
+            }
+        }
+    }
+
+    /// allow
+    ///
+    /// Check if a call can be made. Once ``open_seconds``
+    /// passed, an open breaker lets one trial call through
+    /// (and another one every ``open_seconds`` if the trial
+    /// never reports back).
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) ending with
+    /// [`CIRCUIT_BREAKER_OPEN_MSG`](crate::utils::circuit_breaker::CIRCUIT_BREAKER_OPEN_MSG)
+    /// when the call is rejected
+    ///
+    pub fn allow(&self) -> Result<(), String> {
+        if !self.is_enabled() {
+            return Ok(());
+        }
+        let mut state = self.state.lock().unwrap();
+        if state.state == CircuitState::Closed {
+            return Ok(());
+        }
+        let is_trial = match state.opened_at {
+            Some(opened_at) => {
+                opened_at.elapsed().as_secs() >= self.open_seconds
+            }
+            None => true,
+        };
+        if is_trial {
+            state.state = CircuitState::HalfOpen;
+            state.opened_at = Some(Instant::now());
+            self.set_gauge(CircuitState::HalfOpen);
+            info!("{} circuit breaker is half open - trying a call", self.name);
+            return Ok(());
+        }
+        CIRCUIT_BREAKER_COUNTER_VEC
+            .with_label_values(&[&self.name, "rejected"])
+            .inc();
+        Err(format!("{} {CIRCUIT_BREAKER_OPEN_MSG}", self.name))
+    }
+
+    /// record_success
+    ///
+    /// Reset the failures and close the breaker
+    ///
+    pub fn record_success(&self) {
+        if !self.is_enabled() {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        state.failures = 0;
+        if state.state != CircuitState::Closed {
+            state.state = CircuitState::Closed;
+            state.opened_at = None;
+            self.set_gauge(CircuitState::Closed);
+            CIRCUIT_BREAKER_COUNTER_VEC
+                .with_label_values(&[&self.name, "closed"])
+                .inc();
+            info!("{} circuit breaker is closed", self.name);
+        }
+    }
+
+    /// record_failure
+    ///
+    /// Count a failed call and open the breaker after
+    /// ``failure_threshold`` consecutive failures or a failed
+    /// trial call
+    ///
+    /// # Arguments
+    ///
+    /// * `err_msg` - `&str` - call error for the logs
+    ///
+    pub fn record_failure(&self, err_msg: &str) {
+        if !self.is_enabled() {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        state.failures = state.failures.saturating_add(1);
+        let should_open = match state.state {
+            CircuitState::Closed => state.failures >= self.failure_threshold,
+            CircuitState::HalfOpen => true,
+            CircuitState::Open => false,
+        };
+        if should_open {
+            state.state = CircuitState::Open;
+            state.opened_at = Some(Instant::now());
+            self.set_gauge(CircuitState::Open);
+            CIRCUIT_BREAKER_COUNTER_VEC
+                .with_label_values(&[&self.name, "opened"])
+                .inc();
+            warn!(
+                "{} circuit breaker is open for {}s after {} failures \
+                with err='{err_msg}'",
+                self.name, self.open_seconds, state.failures
+            );
+        }
+    }
+
+    /// call
+    ///
+    /// Run a call through the breaker and record its result
+    ///
+    /// # Arguments
+    ///
+    /// * `fut` - future for the call
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) from the call or when the
+    /// breaker rejects it
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use restapi::utils::circuit_breaker::CircuitBreaker;
+    /// use restapi::utils::circuit_breaker::CircuitState;
+    /// use restapi::utils::circuit_breaker::is_circuit_open_error;
+    /// let breaker = CircuitBreaker::new("doc", 2, 30);
+    /// let rt = tokio::runtime::Runtime::new().unwrap();
+    /// for _ in 0..2 {
+    ///     let res: Result<(), String> =
+    ///         rt.block_on(breaker.call(async { Err("down".to_string()) }));
+    ///     assert_eq!(res.unwrap_err(), "down");
+    /// }
+    /// assert_eq!(breaker.get_state(), CircuitState::Open);
+    /// let res = rt.block_on(breaker.call(async { Ok(()) }));
+    /// assert!(is_circuit_open_error(&res.unwrap_err()));
+    /// ```
+    ///
+    pub async fn call<T, Fut>(&self, fut: Fut) -> Result<T, String>
+    where
+        Fut: Future<Output = Result<T, String>>,
+    {
+        self.allow()?;
+        let res = fut.await;
+        match &res {
+            Ok(_) => self.record_success(),
+            Err(err_msg) => self.record_failure(err_msg),
+        }
+        res
+    }
+
+    /// set_gauge
+    ///
+    /// Export the state to the ``circuit_breaker_state`` gauge
+    ///
+    /// # Arguments
+    ///
+    /// * `state` - [`CircuitState`](crate::utils::circuit_breaker::CircuitState)
+    ///
+    fn set_gauge(&self, state: CircuitState) {
+        CIRCUIT_BREAKER_STATE_GAUGE
+            .with_label_values(&[&self.name])
+            .set(state.as_gauge());
+    }
+}
+
+/// is_circuit_open_error
+///
+/// Check if an error came from an open
+/// [`CircuitBreaker`](crate::utils::circuit_breaker::CircuitBreaker)
+/// so handlers can return a ``503`` instead of a ``500``
+///
+/// # Arguments
+///
+/// * `err_msg` - `&str` - call error
+///
+pub fn is_circuit_open_error(err_msg: &str) -> bool {
+    err_msg.ends_with(CIRCUIT_BREAKER_OPEN_MSG)
+}
@@ -0,0 +1,96 @@
+//! ETags and conditional ``If-None-Match`` responses so
+//! polling clients do not download unchanged records again
+//!
+use hyper::header::HeaderValue;
+use hyper::Body;
+use hyper::HeaderMap;
+use hyper::Response;
+
+use uuid::Uuid;
+
+/// get_etag
+///
+/// Build a strong ``ETag`` from a hash of the response
+/// contents. The hash is a name-based (``sha1``) uuid, so
+/// every api server returns the same ``ETag`` for the same
+/// record.
+///
+/// # Arguments
+///
+/// * `contents` - `&[u8]` - serialized record(s)
+///
+/// # Returns
+///
+/// `String` - quoted ``ETag`` header value
+///
+/// # Examples
+///
+/// ```rust
+/// use restapi::utils::etag::get_etag;
+/// assert_eq!(get_etag(b"{\"id\":1}"), get_etag(b"{\"id\":1}"));
+/// assert_ne!(get_etag(b"{\"id\":1}"), get_etag(b"{\"id\":2}"));
+/// assert!(get_etag(b"{}").starts_with('"'));
+/// ```
+///
+pub fn get_etag(contents: &[u8]) -> String {
+    format!(
+        "\"{}\"",
+        Uuid::new_v5(&Uuid::NAMESPACE_OID, contents).simple()
+    )
+}
+
+/// is_etag_match
+///
+/// Check if a request's ``If-None-Match`` header contains
+/// the ``ETag`` (weak ``W/`` tags and ``*`` match too)
+///
+/// # Arguments
+///
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+/// * `etag` - `&str` - current quoted ``ETag``
+///
+/// # Returns
+///
+/// `bool` - ``true`` when the client already has the
+/// current version
+///
+pub fn is_etag_match(headers: &HeaderMap<HeaderValue>, etag: &str) -> bool {
+    headers
+        .get_all("If-None-Match")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|v| v.trim())
+        .any(|v| v == "*" || v.trim_start_matches("W/") == etag)
+}
+
+/// get_etag_response
+///
+/// Build a ``200`` response with an ``ETag`` header, or
+/// an empty ``304`` when the request's ``If-None-Match``
+/// matches the ``ETag``
+///
+/// # Arguments
+///
+/// * `headers` - [`HeaderMap`](hyper::HeaderMap) -
+///   hashmap containing headers in key-value pairs
+/// * `body` - `String` - json-serialized response
+///
+/// # Returns
+///
+/// hyper [`Response`](hyper::Response)
+///
+pub fn get_etag_response(
+    headers: &HeaderMap<HeaderValue>,
+    body: String,
+) -> Response<Body> {
+    let etag = get_etag(body.as_bytes());
+    let builder = Response::builder()
+        .header("ETag", &etag)
+        .header("Cache-Control", "no-cache");
+    if is_etag_match(headers, &etag) {
+        return builder.status(304).body(Body::empty()).unwrap();
+    }
+    builder.status(200).body(Body::from(body)).unwrap()
+}
@@ -0,0 +1,63 @@
+//! Hash one-time-use tokens before storing them in the db
+//! and compare the hashes in constant time
+//!
+use argon2::hash_encoded as argon_hash_encoded;
+use argon2::Config as argon_config;
+
+/// hash_token
+///
+/// Hash a one-time-use token (email verification or
+/// password reset) with `argon2` and the server's
+/// password salt (``SERVER_PASSWORD_SALT``) so only the
+/// hash is stored in the db. The same token and salt
+/// always create the same hash so tokens can be found
+/// by their hash.
+///
+/// # Arguments
+///
+/// * `token` - `&str` - token sent to the user
+/// * `salt` - `&[u8]` - server password salt from the
+///   [`CoreConfig`](crate::core::core_config::CoreConfig)
+///
+/// # Returns
+///
+/// `String` - encoded `argon2` hash
+///
+pub fn hash_token(token: &str, salt: &[u8]) -> String {
+    argon_hash_encoded(token.as_bytes(), salt, &argon_config::default())
+        .unwrap()
+}
+
+/// is_token_hash_match
+///
+/// Compare a presented token's hash with the stored hash
+/// in constant time (with
+/// [`openssl::memcmp::eq`](openssl::memcmp::eq)) so the
+/// response time does not tell a client how many leading
+/// characters matched
+///
+/// # Arguments
+///
+/// * `token_hash` - `&str` - hash of the presented token from
+///   [`hash_token`](crate::utils::hash_token::hash_token)
+/// * `stored_hash` - `&str` - hash stored in the db
+///
+/// # Returns
+///
+/// `bool` - `true` when the hashes are equal
+///
+/// # Examples
+///
+/// ```rust
+/// use restapi::utils::hash_token::is_token_hash_match;
+/// assert!(is_token_hash_match("$argon2i$abc", "$argon2i$abc"));
+/// assert!(!is_token_hash_match("$argon2i$abc", "$argon2i$abd"));
+/// assert!(!is_token_hash_match("$argon2i$abc", "plaintext"));
+/// ```
+///
+pub fn is_token_hash_match(token_hash: &str, stored_hash: &str) -> bool {
+    // memcmp::eq panics on different lengths and the length
+    // of an argon2 hash is not a secret
+    token_hash.len() == stored_hash.len()
+        && openssl::memcmp::eq(token_hash.as_bytes(), stored_hash.as_bytes())
+}
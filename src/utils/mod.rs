@@ -1,7 +1,12 @@
 //! Utility modules for HTTP requests and debugging
 //!
+pub mod circuit_breaker;
+pub mod etag;
 pub mod file_io;
 pub mod get_query_params_from_url;
 pub mod get_server_address;
 pub mod get_uuid;
+pub mod hash_token;
+pub mod password_hashing;
+pub mod password_migration;
 pub mod path_exists;
@@ -0,0 +1,363 @@
+//! Hash user passwords with configurable argon2 parameters
+//!
+//! The memory, iterations and parallelism are stored in each
+//! encoded hash (``$argon2i$v=19$m=4096,t=3,p=1$...``), so
+//! passwords hashed before a parameter change still verify
+//! and are rehashed with the new parameters at the user's
+//! next successful login.
+//!
+//! One-time-use token hashes (see
+//! [`hash_token`](crate::utils::hash_token::hash_token)) keep
+//! the argon2 defaults because they are found by their hash.
+//!
+//! ```bash
+//! export PASSWORD_HASH_MEMORY_KIB="4096"
+//! export PASSWORD_HASH_ITERATIONS="3"
+//! export PASSWORD_HASH_PARALLELISM="1"
+//! # time one hash at startup and warn outside the target
+//! export PASSWORD_HASH_BENCHMARK="1"
+//! export PASSWORD_HASH_TARGET_MIN_MS="0"
+//! export PASSWORD_HASH_TARGET_MAX_MS="1000"
+//! ```
+//!
+use std::time::Duration;
+use std::time::Instant;
+
+use argon2::hash_encoded as argon_hash_encoded;
+use argon2::verify_encoded as argon_verify_encoded;
+use argon2::Config as argon_config;
+use argon2::ThreadMode;
+
+use crate::utils::hash_token::is_token_hash_match;
+
+/// PasswordHashing
+///
+/// argon2 parameters for user password hashes and the
+/// startup latency check
+///
+/// # Arguments
+///
+/// * `memory_kib` - `u32` - memory cost in KiB
+///   (``PASSWORD_HASH_MEMORY_KIB``)
+/// * `iterations` - `u32` - time cost
+///   (``PASSWORD_HASH_ITERATIONS``)
+/// * `parallelism` - `u32` - lanes hashed in parallel
+///   (``PASSWORD_HASH_PARALLELISM``)
+/// * `benchmark_enabled` - `bool` - time one hash at startup
+///   (``PASSWORD_HASH_BENCHMARK``)
+/// * `target_min_ms` - `u64` - warn if a hash is faster
+///   (``PASSWORD_HASH_TARGET_MIN_MS``, ``0`` disables)
+/// * `target_max_ms` - `u64` - warn if a hash is slower
+///   (``PASSWORD_HASH_TARGET_MAX_MS``, ``0`` disables)
+///
+#[derive(Clone, Debug)]
+pub struct PasswordHashing {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+    pub benchmark_enabled: bool,
+    pub target_min_ms: u64,
+    pub target_max_ms: u64,
+}
+
+impl Default for PasswordHashing {
+    fn default() -> Self {
+        let defaults = argon_config::default();
+        PasswordHashing {
+            memory_kib: defaults.mem_cost,
+            iterations: defaults.time_cost,
+            parallelism: defaults.lanes,
+            benchmark_enabled: true,
+            target_min_ms: 0,
+            target_max_ms: 1000,
+        }
+    }
+}
+
+impl PasswordHashing {
+    /// build_password_hashing
+    ///
+    /// Build a
+    /// [`PasswordHashing`](crate::utils::password_hashing::PasswordHashing)
+    /// from environment variables (unset values use the
+    /// argon2 defaults)
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) if a parameter is not a number
+    /// or argon2 does not support it
+    ///
+    pub fn build_password_hashing() -> Result<Self, String> {
+        let defaults = PasswordHashing::default();
+        let get_u32 = |name: &str, default: u32| -> Result<u32, String> {
+            match std::env::var(name) {
+                Ok(v) => v.trim().parse::<u32>().map_err(|_| {
+                    format!("{name}={v} must be a positive number")
+                }),
+                Err(_) => Ok(default),
+            }
+        };
+        let get_u64 = |name: &str, default: u64| -> Result<u64, String> {
+            match std::env::var(name) {
+                Ok(v) => v.trim().parse::<u64>().map_err(|_| {
+                    format!("{name}={v} must be a positive number")
+                }),
+                Err(_) => Ok(default),
+            }
+        };
+        let password_hashing = PasswordHashing {
+            memory_kib: get_u32(
+                "PASSWORD_HASH_MEMORY_KIB",
+                defaults.memory_kib,
+            )?,
+            iterations: get_u32(
+                "PASSWORD_HASH_ITERATIONS",
+                defaults.iterations,
+            )?,
+            parallelism: get_u32(
+                "PASSWORD_HASH_PARALLELISM",
+                defaults.parallelism,
+            )?,
+            benchmark_enabled: std::env::var("PASSWORD_HASH_BENCHMARK")
+                .unwrap_or_else(|_| "1".to_string())
+                == "1",
+            target_min_ms: get_u64(
+                "PASSWORD_HASH_TARGET_MIN_MS",
+                defaults.target_min_ms,
+            )?,
+            target_max_ms: get_u64(
+                "PASSWORD_HASH_TARGET_MAX_MS",
+                defaults.target_max_ms,
+            )?,
+        };
+        password_hashing.validate()?;
+        Ok(password_hashing)
+    }
+
+    /// validate
+    ///
+    /// Check the parameters are supported by argon2
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) describing the invalid parameter
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use restapi::utils::password_hashing::PasswordHashing;
+    /// assert!(PasswordHashing::default().validate().is_ok());
+    /// let mut password_hashing = PasswordHashing::default();
+    /// password_hashing.parallelism = 4;
+    /// password_hashing.memory_kib = 16;
+    /// assert!(password_hashing.validate().is_err());
+    /// ```
+    ///
+    pub fn validate(&self) -> Result<(), String> {
+        if self.iterations < 1 {
+            return Err(
+                "PASSWORD_HASH_ITERATIONS must be at least 1".to_string()
+            );
+        }
+        if self.parallelism < 1 || self.parallelism > 0x00ff_ffff {
+            return Err(format!(
+                "PASSWORD_HASH_PARALLELISM={} must be between 1 and {}",
+                self.parallelism, 0x00ff_ffff
+            ));
+        }
+        if self.memory_kib < 8 * self.parallelism {
+            return Err(format!(
+                "PASSWORD_HASH_MEMORY_KIB={} must be at least 8 times \
+                PASSWORD_HASH_PARALLELISM={}",
+                self.memory_kib, self.parallelism
+            ));
+        }
+        Ok(())
+    }
+
+    /// get_argon_config
+    ///
+    /// Get the argon2 config for these parameters
+    ///
+    /// # Returns
+    ///
+    /// [`argon2::Config`](argon2::Config)
+    ///
+    pub fn get_argon_config(&self) -> argon_config<'static> {
+        argon_config {
+            mem_cost: self.memory_kib,
+            time_cost: self.iterations,
+            lanes: self.parallelism,
+            thread_mode: ThreadMode::from_threads(self.parallelism),
+            ..argon_config::default()
+        }
+    }
+
+    /// hash_password
+    ///
+    /// Hash a password with the server's password salt
+    /// (``SERVER_PASSWORD_SALT``). The encoded hash stores
+    /// the parameters used.
+    ///
+    /// # Arguments
+    ///
+    /// * `password` - `&str` - plaintext password
+    /// * `salt` - `&[u8]` - server password salt from the
+    ///   [`CoreConfig`](crate::core::core_config::CoreConfig)
+    ///
+    /// # Returns
+    ///
+    /// Ok(`String`) - encoded `argon2` hash
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) if argon2 rejects the salt or
+    /// parameters
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use restapi::utils::password_hashing::PasswordHashing;
+    /// let mut password_hashing = PasswordHashing::default();
+    /// password_hashing.memory_kib = 64;
+    /// password_hashing.iterations = 2;
+    /// let hash = password_hashing
+    ///     .hash_password("123321", b"78197b60-c950-4339-a52c")
+    ///     .unwrap();
+    /// assert!(hash.starts_with("$argon2i$v=19$m=64,t=2,p=1$"));
+    /// ```
+    ///
+    pub fn hash_password(
+        &self,
+        password: &str,
+        salt: &[u8],
+    ) -> Result<String, String> {
+        argon_hash_encoded(
+            password.as_bytes(),
+            salt,
+            &self.get_argon_config(),
+        )
+        .map_err(|e| format!("failed to hash password with err='{e}'"))
+    }
+
+    /// benchmark
+    ///
+    /// Time one password hash with these parameters
+    ///
+    /// # Arguments
+    ///
+    /// * `salt` - `&[u8]` - server password salt
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) if the hash fails
+    ///
+    pub fn benchmark(&self, salt: &[u8]) -> Result<Duration, String> {
+        let start = Instant::now();
+        self.hash_password("restapi-password-hash-benchmark", salt)?;
+        Ok(start.elapsed())
+    }
+
+    /// check_latency
+    ///
+    /// Run the
+    /// [`benchmark`](crate::utils::password_hashing::PasswordHashing::benchmark)
+    /// (if ``PASSWORD_HASH_BENCHMARK=1``) and warn if one hash
+    /// is outside ``PASSWORD_HASH_TARGET_MIN_MS`` and
+    /// ``PASSWORD_HASH_TARGET_MAX_MS``. This blocks while
+    /// hashing, so call it from a blocking task
+    /// ([`spawn_blocking`](tokio::task::spawn_blocking)) and
+    /// not from an async task on the runtime's worker threads.
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - caller logging label
+    /// * `salt` - `&[u8]` - server password salt
+    ///
+    pub fn check_latency(&self, tracking_label: &str, salt: &[u8]) {
+        if !self.benchmark_enabled {
+            return;
+        }
+        let elapsed_ms = match self.benchmark(salt) {
+            Ok(elapsed) => elapsed.as_millis() as u64,
+            Err(err_msg) => {
+                error!(
+                    "{tracking_label} - password hash benchmark - {err_msg}"
+                );
+                return;
+            }
+        };
+        let params = format!(
+            "PASSWORD_HASH_MEMORY_KIB={} PASSWORD_HASH_ITERATIONS={} \
+            PASSWORD_HASH_PARALLELISM={}",
+            self.memory_kib, self.iterations, self.parallelism
+        );
+        if self.target_min_ms > 0 && elapsed_ms < self.target_min_ms {
+            warn!(
+                "{tracking_label} - password hashing took {elapsed_ms}ms \
+                which is faster than PASSWORD_HASH_TARGET_MIN_MS={} - \
+                increase {params}",
+                self.target_min_ms
+            );
+        } else if self.target_max_ms > 0 && elapsed_ms > self.target_max_ms {
+            warn!(
+                "{tracking_label} - password hashing took {elapsed_ms}ms \
+                which is slower than PASSWORD_HASH_TARGET_MAX_MS={} - \
+                decrease {params}",
+                self.target_max_ms
+            );
+        } else {
+            info!(
+                "{tracking_label} - password hashing takes {elapsed_ms}ms \
+                with {params}"
+            );
+        }
+    }
+}
+
+/// is_password_match
+///
+/// Check a login password against the stored hash. The
+/// presented password's hash (with the current parameters)
+/// is compared in constant time first, and hashes created
+/// with older parameters are verified with the parameters
+/// stored in them.
+///
+/// # Arguments
+///
+/// * `password` - `&str` - presented plaintext password
+/// * `password_hash` - `&str` - presented password's hash from
+///   [`hash_password`](crate::utils::password_hashing::PasswordHashing::hash_password)
+/// * `stored_hash` - `&str` - ``users.password`` hash
+///
+/// # Returns
+///
+/// `bool` - `true` when the password matches
+///
+/// # Examples
+///
+/// ```rust
+/// use restapi::utils::password_hashing::is_password_match;
+/// use restapi::utils::password_hashing::PasswordHashing;
+/// let salt = b"78197b60-c950-4339-a52c";
+/// let mut old = PasswordHashing::default();
+/// old.memory_kib = 32;
+/// old.iterations = 1;
+/// let stored_hash = old.hash_password("123321", salt).unwrap();
+/// let mut new = PasswordHashing::default();
+/// new.memory_kib = 64;
+/// let password_hash = new.hash_password("123321", salt).unwrap();
+/// assert!(is_password_match("123321", &password_hash, &stored_hash));
+/// let wrong_hash = new.hash_password("123322", salt).unwrap();
+/// assert!(!is_password_match("123322", &wrong_hash, &stored_hash));
+/// ```
+///
+pub fn is_password_match(
+    password: &str,
+    password_hash: &str,
+    stored_hash: &str,
+) -> bool {
+    is_token_hash_match(password_hash, stored_hash)
+        || argon_verify_encoded(stored_hash, password.as_bytes())
+            .unwrap_or(false)
+}
@@ -0,0 +1,376 @@
+//! Migrate stored password hashes to the current hashing
+//! scheme at login
+//!
+//! Each ``users.password_scheme`` records how the stored hash
+//! was created. Argon2 hashes use
+//! ``VARIANT$v=VERSION$m=MEMORY,t=ITERATIONS,p=PARALLELISM$salt=FINGERPRINT``
+//! where the fingerprint is the first 8 hex characters of the
+//! sha256 of the hash's base64 salt, so changing the
+//! ``PASSWORD_HASH_*`` parameters or ``SERVER_PASSWORD_SALT``
+//! changes the current scheme. After a user's password is
+//! verified with a legacy scheme, ``POST /login`` rehashes
+//! it with the current scheme (when
+//! ``PASSWORD_HASH_REHASH_ON_LOGIN=1``), and
+//! ``GET /admin/passwords/schemes`` reports how many users
+//! are left on each scheme.
+//!
+//! Hashes imported from another system (like bcrypt) can
+//! be verified by implementing a
+//! [`LegacyPasswordHasher`](crate::utils::password_migration::LegacyPasswordHasher)
+//! and setting it with
+//! ``RestApiServerBuilder::legacy_password_hasher`` (or on
+//! the [`CoreConfig`](crate::core::core_config::CoreConfig)
+//! ``password_migration.legacy_hashers``).
+//!
+use std::sync::Arc;
+
+use lazy_static::lazy_static;
+
+use sha2::Digest;
+use sha2::Sha256;
+
+use tokio_postgres::Client;
+
+use crate::monitoring::metric_types::register_int_counter_vec;
+use crate::monitoring::metric_types::IntCounterVec;
+use crate::requests::models::user_repo::UserChanges;
+use crate::requests::models::user_repo::UserRepo;
+use crate::utils::password_hashing::is_password_match;
+
+lazy_static! {
+    pub static ref PASSWORD_HASH_MIGRATIONS_COUNTER_VEC: IntCounterVec =
+        register_int_counter_vec!(
+            "password_hash_migrations_total",
+            "Number of passwords rehashed with the current scheme at login.",
+            &["result"]
+        )
+        .unwrap();
+}
+
+/// scheme for stored hashes that are not argon2 and not
+/// recognized by a
+/// [`LegacyPasswordHasher`](crate::utils::password_migration::LegacyPasswordHasher)
+pub const UNKNOWN_PASSWORD_SCHEME: &str = "unknown";
+
+/// LegacyPasswordHasher
+///
+/// Verify stored password hashes that were not created by
+/// this server's argon2 hashing
+///
+pub trait LegacyPasswordHasher: Send + Sync {
+    /// scheme
+    ///
+    /// Name stored in ``users.password_scheme`` for hashes
+    /// this hasher handles (like ``bcrypt``)
+    ///
+    fn scheme(&self) -> String;
+
+    /// is_scheme
+    ///
+    /// Return `true` if the stored hash uses this scheme
+    ///
+    /// # Arguments
+    ///
+    /// * `stored_hash` - `&str` - ``users.password`` hash
+    ///
+    fn is_scheme(&self, stored_hash: &str) -> bool;
+
+    /// verify
+    ///
+    /// Return `true` if the plaintext password matches the
+    /// stored hash
+    ///
+    /// # Arguments
+    ///
+    /// * `password` - `&str` - presented plaintext password
+    /// * `stored_hash` - `&str` - ``users.password`` hash
+    ///
+    fn verify(&self, password: &str, stored_hash: &str) -> bool;
+}
+
+/// PasswordCheck
+///
+/// Result of verifying a login password
+///
+/// # Arguments
+///
+/// * `matched` - `bool` - the password matches
+/// * `scheme` - `String` - scheme of the stored hash
+/// * `needs_rehash` - `bool` - the password matched with a
+///   scheme that is not the current scheme
+///
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PasswordCheck {
+    pub matched: bool,
+    pub scheme: String,
+    pub needs_rehash: bool,
+}
+
+/// PasswordMigration
+///
+/// Settings for rehashing legacy password hashes at login
+///
+/// # Supported Environment Variables
+///
+/// ```bash
+/// # rehash legacy password hashes after a successful login
+/// export PASSWORD_HASH_REHASH_ON_LOGIN="1"
+/// ```
+///
+/// # Arguments
+///
+/// * `legacy_hashers` - `Vec<Arc<dyn`[`LegacyPasswordHasher`](crate::utils::password_migration::LegacyPasswordHasher)`>>` -
+///   verifiers for hashes that are not argon2
+/// * `rehash_on_login` - `bool` - rehash legacy hashes with
+///   the current scheme after a successful login
+///
+#[derive(Clone)]
+pub struct PasswordMigration {
+    pub legacy_hashers: Vec<Arc<dyn LegacyPasswordHasher>>,
+    pub rehash_on_login: bool,
+}
+
+impl Default for PasswordMigration {
+    fn default() -> Self {
+        PasswordMigration {
+            legacy_hashers: Vec::new(),
+            rehash_on_login: true,
+        }
+    }
+}
+
+impl PasswordMigration {
+    /// build_password_migration
+    ///
+    /// Build a
+    /// [`PasswordMigration`](crate::utils::password_migration::PasswordMigration)
+    /// from environment variables (the legacy hashers are
+    /// set by the ``RestApiServerBuilder``)
+    ///
+    pub fn build_password_migration() -> Self {
+        PasswordMigration {
+            rehash_on_login: std::env::var("PASSWORD_HASH_REHASH_ON_LOGIN")
+                .unwrap_or_else(|_| "1".to_string())
+                == "1",
+            ..Default::default()
+        }
+    }
+
+    /// get_scheme
+    ///
+    /// Get the scheme of a stored hash, checking the
+    /// [`LegacyPasswordHasher`](crate::utils::password_migration::LegacyPasswordHasher)s
+    /// after the argon2 schemes
+    ///
+    /// # Arguments
+    ///
+    /// * `stored_hash` - `&str` - ``users.password`` hash
+    ///
+    /// # Returns
+    ///
+    /// `String` - scheme or
+    /// [`UNKNOWN_PASSWORD_SCHEME`](crate::utils::password_migration::UNKNOWN_PASSWORD_SCHEME)
+    ///
+    pub fn get_scheme(&self, stored_hash: &str) -> String {
+        let scheme = get_password_scheme(stored_hash);
+        if scheme != UNKNOWN_PASSWORD_SCHEME {
+            return scheme;
+        }
+        self.legacy_hashers
+            .iter()
+            .find(|hasher| hasher.is_scheme(stored_hash))
+            .map(|hasher| hasher.scheme())
+            .unwrap_or(scheme)
+    }
+
+    /// check_password
+    ///
+    /// Verify a login password against the stored hash with
+    /// the stored hash's scheme
+    ///
+    /// # Arguments
+    ///
+    /// * `password` - `&str` - presented plaintext password
+    /// * `password_hash` - `&str` - presented password's hash
+    ///   with the current scheme from
+    ///   [`hash_password`](crate::utils::password_hashing::PasswordHashing::hash_password)
+    /// * `stored_hash` - `&str` - ``users.password`` hash
+    ///
+    /// # Returns
+    ///
+    /// [`PasswordCheck`](crate::utils::password_migration::PasswordCheck)
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use std::sync::Arc;
+    /// use restapi::utils::password_hashing::PasswordHashing;
+    /// use restapi::utils::password_migration::LegacyPasswordHasher;
+    /// use restapi::utils::password_migration::PasswordMigration;
+    /// struct PlainHasher;
+    /// impl LegacyPasswordHasher for PlainHasher {
+    ///     fn scheme(&self) -> String {
+    ///         "plain".to_string()
+    ///     }
+    ///     fn is_scheme(&self, stored_hash: &str) -> bool {
+    ///         stored_hash.starts_with("plain:")
+    ///     }
+    ///     fn verify(&self, password: &str, stored_hash: &str) -> bool {
+    ///         stored_hash == format!("plain:{password}")
+    ///     }
+    /// }
+    /// let mut password_hashing = PasswordHashing::default();
+    /// password_hashing.memory_kib = 64;
+    /// let salt = b"78197b60-c950-4339-a52c";
+    /// let hash = password_hashing.hash_password("123321", salt).unwrap();
+    /// let mut migration = PasswordMigration::default();
+    /// migration.legacy_hashers.push(Arc::new(PlainHasher));
+    /// let check = migration.check_password("123321", &hash, &hash);
+    /// assert!(check.matched && !check.needs_rehash);
+    /// let check = migration.check_password("123321", &hash, "plain:123321");
+    /// assert!(check.matched && check.needs_rehash);
+    /// assert_eq!(check.scheme, "plain");
+    /// let check = migration.check_password("123322", &hash, "plain:123321");
+    /// assert!(!check.matched);
+    /// ```
+    ///
+    pub fn check_password(
+        &self,
+        password: &str,
+        password_hash: &str,
+        stored_hash: &str,
+    ) -> PasswordCheck {
+        let scheme = self.get_scheme(stored_hash);
+        let matched = match self
+            .legacy_hashers
+            .iter()
+            .find(|hasher| hasher.scheme() == scheme)
+        {
+            Some(hasher) => hasher.verify(password, stored_hash),
+            None => is_password_match(password, password_hash, stored_hash),
+        };
+        PasswordCheck {
+            matched,
+            needs_rehash: matched && stored_hash != password_hash,
+            scheme,
+        }
+    }
+
+    /// migrate_password
+    ///
+    /// Replace a user's verified legacy hash with the hash
+    /// from the current scheme (if
+    /// ``PASSWORD_HASH_REHASH_ON_LOGIN=1``). Failures are
+    /// logged and do not fail the login.
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - caller logging label
+    /// * `conn` - [`Client`](tokio_postgres::Client) - db
+    ///   connection
+    /// * `user_id` - `i32` - user id
+    /// * `check` - [`PasswordCheck`](crate::utils::password_migration::PasswordCheck) -
+    ///   result of
+    ///   [`check_password`](crate::utils::password_migration::PasswordMigration::check_password)
+    /// * `password_hash` - `&str` - the password's hash with
+    ///   the current scheme
+    ///
+    /// # Returns
+    ///
+    /// `bool` - `true` when the hash was replaced
+    ///
+    pub async fn migrate_password(
+        &self,
+        tracking_label: &str,
+        conn: &Client,
+        user_id: i32,
+        check: &PasswordCheck,
+        password_hash: &str,
+    ) -> bool {
+        if !self.rehash_on_login || !check.matched || !check.needs_rehash {
+            return false;
+        }
+        let changes = UserChanges {
+            password_hash: Some(password_hash.to_string()),
+            ..Default::default()
+        };
+        match UserRepo::new(conn)
+            .update(tracking_label, user_id, &changes)
+            .await
+        {
+            Ok(_) => {
+                info!(
+                    "{tracking_label} - rehashed user {user_id} password \
+                    from scheme={} to scheme={}",
+                    check.scheme,
+                    get_password_scheme(password_hash)
+                );
+                PASSWORD_HASH_MIGRATIONS_COUNTER_VEC
+                    .with_label_values(&["rehashed"])
+                    .inc();
+                true
+            }
+            Err(e) => {
+                warn!(
+                    "{tracking_label} - \
+                    failed to rehash user {user_id} password - {e}"
+                );
+                PASSWORD_HASH_MIGRATIONS_COUNTER_VEC
+                    .with_label_values(&["failed"])
+                    .inc();
+                false
+            }
+        }
+    }
+}
+
+/// get_password_scheme
+///
+/// Get the scheme of an argon2 encoded hash (the
+/// ``users.password_scheme`` value)
+///
+/// # Arguments
+///
+/// * `password_hash` - `&str` - encoded argon2 hash
+///
+/// # Returns
+///
+/// `String` - ``VARIANT$v=VERSION$PARAMS$salt=FINGERPRINT`` or
+/// [`UNKNOWN_PASSWORD_SCHEME`](crate::utils::password_migration::UNKNOWN_PASSWORD_SCHEME)
+///
+/// # Examples
+///
+/// ```rust
+/// use restapi::utils::password_hashing::PasswordHashing;
+/// use restapi::utils::password_migration::get_password_scheme;
+/// let mut password_hashing = PasswordHashing::default();
+/// password_hashing.memory_kib = 64;
+/// let salt = b"78197b60-c950-4339-a52c";
+/// let hash = password_hashing.hash_password("123321", salt).unwrap();
+/// let scheme = get_password_scheme(&hash);
+/// assert!(scheme.starts_with("argon2i$v=19$m=64,t=3,p=1$salt="));
+/// assert_eq!(scheme, get_password_scheme(
+///     &password_hashing.hash_password("123322", salt).unwrap()));
+/// assert_ne!(scheme, get_password_scheme(
+///     &password_hashing.hash_password("123321", b"another-salt").unwrap()));
+/// assert_eq!(get_password_scheme("plaintext"), "unknown");
+/// ```
+///
+pub fn get_password_scheme(password_hash: &str) -> String {
+    let parts: Vec<&str> = password_hash.split('$').collect();
+    if parts.len() != 6
+        || !parts[0].is_empty()
+        || !parts[1].starts_with("argon2")
+        || !parts[2].starts_with("v=")
+    {
+        return UNKNOWN_PASSWORD_SCHEME.to_string();
+    }
+    let salt_fingerprint = format!("{:x}", Sha256::digest(parts[4]));
+    format!(
+        "{}${}${}$salt={}",
+        parts[1],
+        parts[2],
+        parts[3],
+        &salt_fingerprint[..8]
+    )
+}
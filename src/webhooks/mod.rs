@@ -0,0 +1,12 @@
+//! Deliver user events to admin-registered webhook
+//! endpoints without kafka
+//!
+//! See
+//! [`WebhookDispatcher`](crate::webhooks::webhook_dispatcher::WebhookDispatcher)
+//! for the supported environment variables and
+//! [`get_webhook_signature`](crate::webhooks::webhook_signature::get_webhook_signature)
+//! for verifying a delivery
+//!
+pub mod run_webhook_dispatcher;
+pub mod webhook_dispatcher;
+pub mod webhook_signature;
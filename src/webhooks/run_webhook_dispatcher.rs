@@ -0,0 +1,76 @@
+//! Background task that sends the queued webhook deliveries
+//!
+use std::time::Duration;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+
+use hyper::Client;
+
+use hyper_tls::HttpsConnector;
+
+use crate::webhooks::webhook_dispatcher::WebhookDispatcher;
+
+/// run_webhook_dispatcher
+///
+/// Send batches of due deliveries with
+/// [`deliver_webhooks_batch`](crate::webhooks::webhook_dispatcher::WebhookDispatcher::deliver_webhooks_batch)
+/// until there is nothing left to send and then
+/// wait `interval_ms` before checking again.
+/// Due deliveries are leased with
+/// ``FOR UPDATE OF webhooks_deliveries SKIP LOCKED`` for the
+/// request timeout plus 60 seconds, so each attempt is sent
+/// by one api server.
+///
+/// # Arguments
+///
+/// * `tracking_label` - `&str` - caller logging label
+/// * `webhooks` - [`WebhookDispatcher`](crate::webhooks::webhook_dispatcher::WebhookDispatcher)
+/// * `db_pool` - [`Pool`](bb8::Pool) - postgres client db
+///   threadpool with required tls encryption
+///
+pub async fn run_webhook_dispatcher(
+    tracking_label: &str,
+    webhooks: WebhookDispatcher,
+    db_pool: Pool<PostgresConnectionManager<MakeTlsConnector>>,
+) {
+    if !webhooks.enabled {
+        return;
+    }
+    info!(
+        "{tracking_label} - \
+        sending webhook deliveries every {}ms",
+        webhooks.interval_ms
+    );
+    let client = Client::builder().build(HttpsConnector::new());
+    loop {
+        match db_pool.get().await {
+            Ok(conn) => loop {
+                match webhooks
+                    .deliver_webhooks_batch(tracking_label, &client, &conn)
+                    .await
+                {
+                    Ok(num_sent) => {
+                        if (num_sent as i64) < webhooks.batch_size {
+                            break;
+                        }
+                    }
+                    Err(err_msg) => {
+                        error!("{err_msg}");
+                        break;
+                    }
+                }
+            },
+            Err(e) => {
+                error!(
+                    "{tracking_label} - \
+                    failed to get a db connection for sending \
+                    webhook deliveries with err='{e}'"
+                );
+            }
+        }
+        tokio::time::sleep(Duration::from_millis(webhooks.interval_ms)).await;
+    }
+}
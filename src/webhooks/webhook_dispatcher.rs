@@ -0,0 +1,493 @@
+//! Settings for queueing user events as webhook deliveries
+//! and sending them to the registered endpoints
+//!
+//! The [`EventBus`](crate::kafka::event_bus::EventBus) queues
+//! a ``webhooks_deliveries`` record for each active webhook
+//! subscribed to the event in a background task (with or
+//! without kafka), and
+//! [`run_webhook_dispatcher`](crate::webhooks::run_webhook_dispatcher::run_webhook_dispatcher)
+//! ``POST``s the signed json payloads and retries failed
+//! deliveries with an exponential backoff.
+//!
+//! Supported webhook event types:
+//!
+//! | Webhook event type | User event           |
+//! | ------------------ | -------------------- |
+//! | ``user.created``   | ``USER_CREATE``      |
+//! | ``user.verified``  | ``USER_VERIFY``      |
+//! | ``data.uploaded``  | ``UPLOAD_USER_DATA`` |
+//!
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+
+use postgres_native_tls::MakeTlsConnector;
+
+use bb8::Pool;
+use bb8::PooledConnection;
+use bb8_postgres::PostgresConnectionManager;
+
+use hyper::client::HttpConnector;
+use hyper::Body;
+use hyper::Client;
+use hyper::Method;
+use hyper::Request;
+
+use hyper_tls::HttpsConnector;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::monitoring::metric_types::register_int_counter_vec;
+use crate::monitoring::metric_types::IntCounterVec;
+use crate::requests::models::webhook::claim_webhook_deliveries;
+use crate::requests::models::webhook::insert_webhook_deliveries;
+use crate::requests::models::webhook::update_webhook_delivery;
+use crate::requests::models::webhook::ModelWebhookAttempt;
+use crate::utils::get_uuid::get_uuid;
+use crate::webhooks::webhook_signature::get_webhook_signature;
+
+lazy_static! {
+    pub static ref WEBHOOK_DELIVERIES_COUNTER_VEC: IntCounterVec =
+        register_int_counter_vec!(
+            "webhook_deliveries_total",
+            "Number of webhook delivery attempts by result.",
+            &["event", "result"]
+        )
+        .unwrap();
+}
+
+/// list of `(webhook event type, user event)` pairs that
+/// can be delivered to webhooks
+pub const WEBHOOK_EVENT_TYPES: [(&str, &str); 3] = [
+    ("user.created", "USER_CREATE"),
+    ("user.verified", "USER_VERIFY"),
+    ("data.uploaded", "UPLOAD_USER_DATA"),
+];
+
+/// longest wait between retries of a failed delivery
+const WEBHOOK_MAX_RETRY_SECONDS: i64 = 86400;
+
+/// get_webhook_event_type
+///
+/// Get the webhook event type for a user event
+///
+/// # Arguments
+///
+/// * `event` - `&str` - user event name (``USER_CREATE``)
+///
+/// # Returns
+///
+/// `Option<&str>` - `None` when the user event is not
+/// delivered to webhooks
+///
+/// # Examples
+///
+/// ```rust
+/// use restapi::webhooks::webhook_dispatcher::get_webhook_event_type;
+/// assert_eq!(get_webhook_event_type("USER_CREATE"), Some("user.created"));
+/// assert_eq!(get_webhook_event_type("USER_GET"), None);
+/// ```
+///
+pub fn get_webhook_event_type(event: &str) -> Option<&'static str> {
+    WEBHOOK_EVENT_TYPES
+        .iter()
+        .find(|(_, user_event)| *user_event == event)
+        .map(|(event_type, _)| *event_type)
+}
+
+/// WebhookPayload
+///
+/// Json body sent to a webhook endpoint
+///
+/// # Arguments
+///
+/// * `id` - `String` - unique event id (the same for every
+///   webhook that receives the event)
+/// * `event` - `String` - webhook event type (``user.created``)
+/// * `created_at` - `String` - utc timestamp of the event
+/// * `user_id` - `i32` - user id for the event
+/// * `data` - `BTreeMap<String, String>` - the user event's
+///   ``key=value`` details (``email`` or ``data``)
+///
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct WebhookPayload {
+    pub id: String,
+    pub event: String,
+    pub created_at: String,
+    pub user_id: i32,
+    pub data: BTreeMap<String, String>,
+}
+
+/// WebhookDispatcher
+///
+/// Settings for delivering user events to webhooks
+///
+/// # Supported Environment Variables
+///
+/// ```bash
+/// # queue and send webhook deliveries
+/// export WEBHOOKS_ENABLED="0"
+/// export WEBHOOKS_INTERVAL_MS="1000"
+/// export WEBHOOKS_BATCH_SIZE="50"
+/// # failed deliveries are retried after 30s, 60s, 120s, ...
+/// export WEBHOOKS_MAX_ATTEMPTS="8"
+/// export WEBHOOKS_RETRY_SECONDS="30"
+/// export WEBHOOKS_TIMEOUT_MS="5000"
+/// # allow registering http:// urls (https only by default)
+/// export WEBHOOKS_ALLOW_HTTP="0"
+/// ```
+///
+/// # Arguments
+///
+/// * `enabled` - `bool` - queue and send deliveries
+/// * `interval_ms` - `u64` - milliseconds between checks for
+///   due deliveries
+/// * `batch_size` - `i64` - max deliveries sent at once
+/// * `max_attempts` - `i32` - attempts before a delivery is
+///   ``failed``
+/// * `retry_seconds` - `i64` - wait after the first failed
+///   attempt (doubled after each failure up to a day)
+/// * `timeout_ms` - `u64` - max milliseconds for each request
+/// * `allow_http` - `bool` - allow ``http://`` webhook urls
+/// * `db_pool` - `Option<`[`Pool`](bb8::Pool)`>` - postgres
+///   client db threadpool for queueing deliveries (set
+///   when the api server starts)
+///
+#[derive(Clone, Default)]
+pub struct WebhookDispatcher {
+    pub enabled: bool,
+    pub interval_ms: u64,
+    pub batch_size: i64,
+    pub max_attempts: i32,
+    pub retry_seconds: i64,
+    pub timeout_ms: u64,
+    pub allow_http: bool,
+    pub db_pool: Option<Pool<PostgresConnectionManager<MakeTlsConnector>>>,
+}
+
+impl WebhookDispatcher {
+    /// build_webhook_dispatcher
+    ///
+    /// Build a
+    /// [`WebhookDispatcher`](crate::webhooks::webhook_dispatcher::WebhookDispatcher)
+    /// from environment variables (without a db pool)
+    ///
+    pub fn build_webhook_dispatcher() -> Self {
+        let get_env = |key: &str, default: u64| -> u64 {
+            std::env::var(key)
+                .unwrap_or_else(|_| format!("{default}"))
+                .parse::<u64>()
+                .unwrap_or(default)
+        };
+        let get_flag = |key: &str| -> bool {
+            let value = std::env::var(key).unwrap_or_else(|_| "0".to_string());
+            value == "1" || value == "true"
+        };
+        WebhookDispatcher {
+            enabled: get_flag("WEBHOOKS_ENABLED"),
+            interval_ms: get_env("WEBHOOKS_INTERVAL_MS", 1000).max(100),
+            batch_size: get_env("WEBHOOKS_BATCH_SIZE", 50).clamp(1, 1000)
+                as i64,
+            max_attempts: get_env("WEBHOOKS_MAX_ATTEMPTS", 8).clamp(1, 100)
+                as i32,
+            retry_seconds: get_env("WEBHOOKS_RETRY_SECONDS", 30).clamp(1, 3600)
+                as i64,
+            timeout_ms: get_env("WEBHOOKS_TIMEOUT_MS", 5000).max(1),
+            allow_http: get_flag("WEBHOOKS_ALLOW_HTTP"),
+            db_pool: None,
+        }
+    }
+
+    /// validate_webhook_url
+    ///
+    /// Check a url before it is registered as a webhook
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - `&str` - webhook url
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) when the url is invalid, has no
+    /// host or is not ``https`` (``http`` is allowed with
+    /// ``WEBHOOKS_ALLOW_HTTP=1``)
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use restapi::webhooks::webhook_dispatcher::WebhookDispatcher;
+    /// let webhooks = WebhookDispatcher::default();
+    /// assert!(webhooks.validate_webhook_url("https://hooks.example.com/a").is_ok());
+    /// assert!(webhooks.validate_webhook_url("http://hooks.example.com/a").is_err());
+    /// assert!(webhooks.validate_webhook_url("not a url").is_err());
+    /// ```
+    ///
+    pub fn validate_webhook_url(&self, url: &str) -> Result<(), String> {
+        let parsed_url = match url::Url::parse(url) {
+            Ok(parsed_url) => parsed_url,
+            Err(e) => return Err(format!("invalid url with err='{e}'")),
+        };
+        if parsed_url.host_str().unwrap_or("").is_empty() {
+            return Err("url is missing a host".to_string());
+        }
+        match parsed_url.scheme() {
+            "https" => Ok(()),
+            "http" if self.allow_http => Ok(()),
+            "http" => Err("url must use https (or set \
+                WEBHOOKS_ALLOW_HTTP=1)"
+                .to_string()),
+            scheme => Err(format!("unsupported url scheme={scheme}")),
+        }
+    }
+
+    /// get_retry_in_seconds
+    ///
+    /// Get the wait before the next attempt of a failed
+    /// delivery (``WEBHOOKS_RETRY_SECONDS`` doubled after
+    /// each failed attempt up to a day)
+    ///
+    /// # Arguments
+    ///
+    /// * `attempts` - `i32` - failed attempts so far
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use restapi::webhooks::webhook_dispatcher::WebhookDispatcher;
+    /// let webhooks = WebhookDispatcher {
+    ///     retry_seconds: 30,
+    ///     ..WebhookDispatcher::default()
+    /// };
+    /// assert_eq!(webhooks.get_retry_in_seconds(1), 30);
+    /// assert_eq!(webhooks.get_retry_in_seconds(3), 120);
+    /// assert_eq!(webhooks.get_retry_in_seconds(40), 86400);
+    /// ```
+    ///
+    pub fn get_retry_in_seconds(&self, attempts: i32) -> i64 {
+        let exponent = (attempts.max(1) - 1).min(20) as u32;
+        self.retry_seconds
+            .saturating_mul(2_i64.pow(exponent))
+            .min(WEBHOOK_MAX_RETRY_SECONDS)
+    }
+
+    /// queue_user_event
+    ///
+    /// Queue a delivery for each webhook subscribed to the
+    /// user event in the background when webhooks are
+    /// enabled and the api server set the `db_pool`.
+    /// User events without a webhook event type are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - `i32` - user id for the event
+    /// * `event` - `&str` - user event name (``USER_CREATE``)
+    /// * `details` - `&str` - space-separated ``key=value``
+    ///   pairs (use ``""`` for none)
+    ///
+    pub fn queue_user_event(&self, user_id: i32, event: &str, details: &str) {
+        let event_type = match get_webhook_event_type(event) {
+            Some(event_type) => event_type,
+            None => return,
+        };
+        let db_pool = match (self.enabled, &self.db_pool) {
+            (true, Some(db_pool)) => db_pool.clone(),
+            _ => return,
+        };
+        let payload = serde_json::to_string(&WebhookPayload {
+            id: get_uuid(),
+            event: event_type.to_string(),
+            created_at: format!(
+                "{}",
+                chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ")
+            ),
+            user_id,
+            data: details
+                .split_whitespace()
+                .filter_map(|part| part.split_once('='))
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+        })
+        .unwrap();
+        tokio::spawn(async move {
+            let tracking_label = "webhooks";
+            let conn = match db_pool.get().await {
+                Ok(conn) => conn,
+                Err(e) => {
+                    error!(
+                        "{tracking_label} - \
+                        failed to queue {event_type} webhooks \
+                        for user={user_id} - \
+                        unable to get a db connection with err='{e}'"
+                    );
+                    return;
+                }
+            };
+            if let Err(err_msg) = insert_webhook_deliveries(
+                tracking_label,
+                event_type,
+                &payload,
+                &conn,
+            )
+            .await
+            {
+                error!("{err_msg}");
+            }
+        });
+    }
+
+    /// deliver_webhooks_batch
+    ///
+    /// Claim up to ``WEBHOOKS_BATCH_SIZE`` due deliveries,
+    /// send them concurrently and record each result
+    ///
+    /// # Arguments
+    ///
+    /// * `tracking_label` - `&str` - caller logging label
+    /// * `client` - hyper [`Client`](hyper::Client) for
+    ///   ``http`` and ``https`` requests
+    /// * `conn` - [`PooledConnection`](bb8::PooledConnection) -
+    ///   an established db connection from the
+    ///   postgres client db threadpool
+    ///
+    /// # Returns
+    ///
+    /// Ok(num_claimed: `usize`)
+    ///
+    /// # Errors
+    ///
+    /// Err(err_msg: `String`) if the deliveries cannot be claimed
+    ///
+    pub async fn deliver_webhooks_batch(
+        &self,
+        tracking_label: &str,
+        client: &Client<HttpsConnector<HttpConnector>>,
+        conn: &PooledConnection<
+            '_,
+            PostgresConnectionManager<MakeTlsConnector>,
+        >,
+    ) -> Result<usize, String> {
+        // unfinished attempts are retried after the lease
+        let lease_seconds = (self.timeout_ms / 1000) as i64 + 60;
+        let attempts = claim_webhook_deliveries(
+            tracking_label,
+            self.batch_size,
+            lease_seconds,
+            conn,
+        )
+        .await?;
+        let results = futures::future::join_all(
+            attempts
+                .iter()
+                .map(|attempt| self.send_webhook(client, attempt)),
+        )
+        .await;
+        for (attempt, result) in attempts.iter().zip(results) {
+            let (status, status_code, error, retry_in_seconds) = match result {
+                Ok(status_code) => {
+                    ("delivered", status_code, "".to_string(), 0)
+                }
+                Err((status_code, err_msg)) => {
+                    if attempt.attempts >= self.max_attempts {
+                        warn!(
+                            "{tracking_label} - \
+                            webhook delivery={} to webhook={} failed \
+                            after {} attempts with err='{err_msg}'",
+                            attempt.id, attempt.webhook_id, attempt.attempts
+                        );
+                        ("failed", status_code, err_msg, 0)
+                    } else {
+                        (
+                            "pending",
+                            status_code,
+                            err_msg,
+                            self.get_retry_in_seconds(attempt.attempts),
+                        )
+                    }
+                }
+            };
+            WEBHOOK_DELIVERIES_COUNTER_VEC
+                .with_label_values(&[
+                    &attempt.event,
+                    match status {
+                        "pending" => "retry",
+                        _ => status,
+                    },
+                ])
+                .inc();
+            if let Err(err_msg) = update_webhook_delivery(
+                tracking_label,
+                attempt.id,
+                status,
+                status_code,
+                &error,
+                retry_in_seconds,
+                conn,
+            )
+            .await
+            {
+                error!("{err_msg}");
+            }
+        }
+        Ok(attempts.len())
+    }
+
+    /// send_webhook
+    ///
+    /// ``POST`` one signed delivery
+    ///
+    /// # Returns
+    ///
+    /// Ok(status_code: `i32`) for a ``2xx`` response
+    ///
+    /// # Errors
+    ///
+    /// Err((status_code: `i32`, err_msg: `String`)) with
+    /// status code ``0`` when there was no response
+    ///
+    async fn send_webhook(
+        &self,
+        client: &Client<HttpsConnector<HttpConnector>>,
+        attempt: &ModelWebhookAttempt,
+    ) -> Result<i32, (i32, String)> {
+        let timestamp = chrono::Utc::now().timestamp();
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri(&attempt.url)
+            .header("Content-Type", "application/json")
+            .header("User-Agent", "restapi-webhooks")
+            .header("X-Webhook-Id", format!("{}", attempt.id))
+            .header("X-Webhook-Event", &attempt.event)
+            .header("X-Webhook-Timestamp", format!("{timestamp}"))
+            .header(
+                "X-Webhook-Signature",
+                get_webhook_signature(
+                    &attempt.secret,
+                    timestamp,
+                    &attempt.payload,
+                ),
+            )
+            .body(Body::from(attempt.payload.clone()))
+            .map_err(|e| (0, format!("invalid webhook request err='{e}'")))?;
+        match tokio::time::timeout(
+            Duration::from_millis(self.timeout_ms),
+            client.request(req),
+        )
+        .await
+        {
+            Ok(Ok(res)) if res.status().is_success() => {
+                Ok(res.status().as_u16() as i32)
+            }
+            Ok(Ok(res)) => Err((
+                res.status().as_u16() as i32,
+                format!("webhook returned status={}", res.status()),
+            )),
+            Ok(Err(e)) => Err((0, format!("webhook failed with err='{e}'"))),
+            Err(_) => Err((
+                0,
+                format!("webhook timed out after {}ms", self.timeout_ms),
+            )),
+        }
+    }
+}
@@ -0,0 +1,63 @@
+//! Sign webhook deliveries so receivers can verify the
+//! payload came from this api server
+//!
+//! Each delivery has the headers:
+//!
+//! - ``X-Webhook-Id`` - ``webhooks_deliveries.id`` (the same
+//!   for every retry, so receivers can skip duplicates)
+//! - ``X-Webhook-Event`` - webhook event type
+//!   (``user.created``)
+//! - ``X-Webhook-Timestamp`` - unix seconds when the
+//!   attempt was sent
+//! - ``X-Webhook-Signature`` - ``v1=HEX`` where ``HEX`` is
+//!   the hex-encoded ``HMAC-SHA256`` of
+//!   ``TIMESTAMP.PAYLOAD`` with the webhook's secret
+//!
+//! Receivers should recompute the signature, compare it in
+//! constant time and reject old timestamps to stop replays.
+//!
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+
+/// get_webhook_signature
+///
+/// Sign a webhook payload with the webhook's secret
+///
+/// # Arguments
+///
+/// * `secret` - `&str` - ``webhooks.secret``
+/// * `timestamp` - `i64` - unix seconds in the
+///   ``X-Webhook-Timestamp`` header
+/// * `payload` - `&str` - json request body
+///
+/// # Returns
+///
+/// `String` - ``X-Webhook-Signature`` header value
+/// (``v1=HEX``)
+///
+/// # Examples
+///
+/// ```rust
+/// use restapi::webhooks::webhook_signature::get_webhook_signature;
+/// let signature = get_webhook_signature("secret", 1700000000, "{}");
+/// assert!(signature.starts_with("v1="));
+/// assert_eq!(signature.len(), 3 + 64);
+/// assert_eq!(signature, get_webhook_signature("secret", 1700000000, "{}"));
+/// assert_ne!(signature, get_webhook_signature("secret", 1700000001, "{}"));
+/// ```
+///
+pub fn get_webhook_signature(
+    secret: &str,
+    timestamp: i64,
+    payload: &str,
+) -> String {
+    // hmac keys cannot fail and sha256 is always available
+    let key = PKey::hmac(secret.as_bytes()).unwrap();
+    let mut signer = Signer::new(MessageDigest::sha256(), &key).unwrap();
+    signer.update(format!("{timestamp}.").as_bytes()).unwrap();
+    signer.update(payload.as_bytes()).unwrap();
+    let digest = signer.sign_to_vec().unwrap();
+    let hex: String = digest.iter().map(|b| format!("{b:02x}")).collect();
+    format!("v1={hex}")
+}
@@ -28,6 +28,7 @@ function user_create() {
     create_user_out=$(curl -s "${TLS_ARGS}" \
         "https://${API_ENDPOINT}/user" \
         -XPOST \
+        -H "Content-Type: application/json" \
         -d "{\"email\":\"${username}\",\"password\":\"${password}\"}")
     last_status="$?"
     if [[ "${last_status}" -ne 0 ]]; then
@@ -67,6 +68,7 @@ function user_login() {
     login_user_out=$(curl -s "${TLS_ARGS}" \
         "https://${API_ENDPOINT}/login" \
         -XPOST \
+        -H "Content-Type: application/json" \
         -d "{\"email\":\"${username}\",\"password\":\"${password}\"}")
     last_status="$?"
     if [[ "${last_status}" -ne 0 ]]; then
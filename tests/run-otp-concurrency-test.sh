@@ -0,0 +1,116 @@
+#!/bin/bash
+
+# regression test for consuming a one-time-use password (otp)
+# with many concurrent requests - only 1 request can succeed
+# and the consumed otp cannot be replayed
+
+function yellow() { printf "\x1b[38;5;227m%s\e[0m " "${@}"; printf "\n"; }
+function warn() { printf "\x1b[38;5;208m%s\e[0m " "${@}"; printf "\n"; }
+function green() { printf "\x1b[38;5;048m%s\e[0m " "${@}"; printf "\n"; }
+function red() { printf "\x1b[38;5;196m%s\e[0m " "${@}"; printf "\n"; }
+
+export TLS_ARGS=""
+if [[ "${API_ENDPOINT}" == "" ]]; then
+    red "please set the environment variable: API_ENDPOINT to something like: export API_ENDPOINT=api.yourdomain.com"
+    exit 1
+fi
+
+max_concurrent=20
+if [[ "${1}" != "" ]]; then
+    max_concurrent="${1}"
+fi
+
+function build_auth_header() {
+    export API_AUTH_HEADER="Bearer: ${API_TOKEN}"
+} # build_auth_header - end
+
+function user_create() {
+    username="${1}"
+    password="${2}"
+    echo "creating user: ${username}"
+    create_user_out=$(curl -s ${TLS_ARGS} \
+        "https://${API_ENDPOINT}/user" \
+        -XPOST \
+        -H "Content-Type: application/json" \
+        -d "{\"email\":\"${username}\",\"password\":\"${password}\"}")
+    last_status="$?"
+    if [[ "${last_status}" -ne 0 ]]; then
+        red "failed to create user: ${username} - stopping"
+        exit 1
+    fi
+    export API_TOKEN=$(echo "${create_user_out}" | jq -r '.token')
+    export API_USER_ID=$(echo "${create_user_out}" | jq -r '.user_id')
+    export API_USERNAME="${username}"
+    build_auth_header
+    if [[ "${API_USER_ID}" == "" ]] || [[ "${API_USER_ID}" == "null" ]]; then
+        red "failed to get token on new user ${username} - stopping"
+        echo -e "\nuser out: ${create_user_out}\n"
+        exit 1
+    fi
+} # user_create - end
+
+function user_create_otp() {
+    echo "creating otp for user: ${API_USER_ID}"
+    create_otp_out=$(curl -s ${TLS_ARGS} \
+        "https://${API_ENDPOINT}/user/password/reset" \
+        -H "${API_AUTH_HEADER}" \
+        -XPOST \
+        -H "Content-Type: application/json" \
+        -d "{\"user_id\":${API_USER_ID},\"email\":\"${API_USERNAME}\"}")
+    export API_OTP_TOKEN=$(echo "${create_otp_out}" | jq -r '.token')
+    if [[ "${API_OTP_TOKEN}" == "" ]] || [[ "${API_OTP_TOKEN}" == "null" ]]; then
+        red "failed to create otp for user ${API_USER_ID} - stopping"
+        echo -e "\notp out: ${create_otp_out}\n"
+        exit 1
+    fi
+} # user_create_otp - end
+
+function run_test() {
+    new_user_suffix=$(date -u +'%Y%m%d%H%M%S%N')
+    test_username="testotp${new_user_suffix}@email.com"
+    user_create "${test_username}" "testotp123"
+    user_create_otp
+
+    yellow "consuming the same otp with ${max_concurrent} concurrent requests on ${API_ENDPOINT}"
+    out_dir=$(mktemp -d)
+    for (( c=0; c<"$max_concurrent"; c++ ))
+    do
+        curl -s ${TLS_ARGS} \
+            -o /dev/null \
+            -w "%{http_code}" \
+            "https://${API_ENDPOINT}/user/password/change" \
+            -H "${API_AUTH_HEADER}" \
+            -XPOST \
+            -H "Content-Type: application/json" \
+            -d "{\"user_id\":${API_USER_ID},\"email\":\"${API_USERNAME}\",\"token\":\"${API_OTP_TOKEN}\",\"password\":\"newpass${c}\"}" \
+            > "${out_dir}/${c}" &
+    done
+    wait
+
+    num_success=$(cat "${out_dir}"/* | grep -o "200" | wc -l)
+    rm -rf "${out_dir}"
+    if [[ "${num_success}" -ne 1 ]]; then
+        red "failed - ${num_success} of ${max_concurrent} concurrent requests consumed the same otp (expected 1)"
+        exit 1
+    fi
+    green "passed - 1 of ${max_concurrent} concurrent requests consumed the otp"
+
+    yellow "replaying the consumed otp on ${API_ENDPOINT}"
+    replay_status=$(curl -s ${TLS_ARGS} \
+        -o /dev/null \
+        -w "%{http_code}" \
+        "https://${API_ENDPOINT}/user/password/change" \
+        -H "${API_AUTH_HEADER}" \
+        -XPOST \
+        -H "Content-Type: application/json" \
+        -d "{\"user_id\":${API_USER_ID},\"email\":\"${API_USERNAME}\",\"token\":\"${API_OTP_TOKEN}\",\"password\":\"replaypass\"}")
+    if [[ "${replay_status}" != "400" ]]; then
+        red "failed - replaying the consumed otp returned ${replay_status} (expected 400)"
+        exit 1
+    fi
+    green "passed - the consumed otp cannot be replayed"
+} # run_test - end
+
+run_test
+
+exit 0
@@ -0,0 +1,75 @@
+//! End-to-end tests with the
+//! [`TestServer`](restapi::test_support::test_server::TestServer)
+//!
+//! Requires the ``test-support`` feature and a running
+//! docker daemon for the postgres container:
+//!
+//! ```bash
+//! cargo test --features test-support --test test_server
+//! ```
+//!
+#![cfg(feature = "test-support")]
+
+use hyper::Method;
+
+use restapi::requests::auth::login_user::ApiReqUserLogin;
+use restapi::requests::auth::login_user::ApiResUserLogin;
+use restapi::test_support::test_server::TestServer;
+
+const TEST_EMAIL: &str = "test-server@email.com";
+const TEST_PASSWORD: &str = "Test-Server-Password-123";
+
+#[tokio::test]
+async fn create_user_and_login() {
+    let server = TestServer::start().await.unwrap();
+    let (user_id, _) =
+        server.create_user(TEST_EMAIL, TEST_PASSWORD).await.unwrap();
+    assert!(user_id > 0);
+
+    let req = ApiReqUserLogin {
+        email: TEST_EMAIL.to_string(),
+        password: TEST_PASSWORD.to_string(),
+        scopes: vec![],
+        otp: String::new(),
+    };
+    let (status, body) = server
+        .send_json(Method::POST, "/login", None, Some(serde_json::json!(req)))
+        .await
+        .unwrap();
+    assert_eq!(status, 201, "login failed with response={body}");
+    let res: ApiResUserLogin = serde_json::from_value(body).unwrap();
+    assert_eq!(res.user_id, user_id);
+    assert_eq!(res.email, TEST_EMAIL);
+    assert!(!res.token.is_empty());
+
+    // the login token is valid for the user's own routes
+    let (status, user) = server
+        .send_json(
+            Method::GET,
+            &format!("/user/{user_id}"),
+            Some(&res.token),
+            None,
+        )
+        .await
+        .unwrap();
+    assert_eq!(status, 200, "get user failed with response={user}");
+    assert_eq!(user["email"], TEST_EMAIL);
+}
+
+#[tokio::test]
+async fn login_with_wrong_password_fails() {
+    let server = TestServer::start().await.unwrap();
+    server.create_user(TEST_EMAIL, TEST_PASSWORD).await.unwrap();
+
+    let req = ApiReqUserLogin {
+        email: TEST_EMAIL.to_string(),
+        password: "wrong-password".to_string(),
+        scopes: vec![],
+        otp: String::new(),
+    };
+    let (status, _) = server
+        .send_json(Method::POST, "/login", None, Some(serde_json::json!(req)))
+        .await
+        .unwrap();
+    assert_ne!(status, 201);
+}